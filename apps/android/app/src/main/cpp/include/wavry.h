@@ -2,6 +2,7 @@
 #define WAVRY_H
 
 #include <stdint.h>
+#include <stdbool.h>
 
 #ifdef __cplusplus
 extern "C" {
@@ -20,11 +21,24 @@ typedef struct {
   uint32_t bitrate_kbps;
   uint32_t keyframe_interval_ms;
   uint32_t display_id;
+  uint32_t max_session_secs; // 0 for no limit
+  uint32_t idle_timeout_secs; // 0 to never pause
+  bool lock_on_disconnect;
 } WavryHostConfig;
 int wavry_start_host_with_config(uint16_t port, const WavryHostConfig *config);
 int wavry_start_client(const char *host_ip, uint16_t port);
 int wavry_stop(void);
 
+// Host Permissions
+#define WAVRY_PERMISSION_GRANTED 0
+#define WAVRY_PERMISSION_DENIED 1
+#define WAVRY_PERMISSION_NOT_DETERMINED 2
+#define WAVRY_PERMISSION_NOT_REQUIRED 3
+
+int wavry_host_permission_status(void);
+int wavry_host_request_permission(void);
+void wavry_host_set_permission_result(int granted);
+
 // Identity Management
 int32_t wavry_init_identity(const char *storage_path);
 int32_t wavry_get_public_key(uint8_t *out_buffer_32);
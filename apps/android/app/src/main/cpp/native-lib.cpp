@@ -69,6 +69,20 @@ Java_com_wavry_android_core_NativeBridge_nativeStartHost(JNIEnv *, jobject, jint
     return wavry_start_host(static_cast<uint16_t>(port));
 }
 
+extern "C" JNIEXPORT jint JNICALL
+Java_com_wavry_android_core_NativeBridge_nativeHostPermissionStatus(JNIEnv *, jobject) {
+    return wavry_host_permission_status();
+}
+
+extern "C" JNIEXPORT void JNICALL
+Java_com_wavry_android_core_NativeBridge_nativeSetHostPermissionResult(
+    JNIEnv *,
+    jobject,
+    jboolean granted
+) {
+    wavry_host_set_permission_result(granted ? 1 : 0);
+}
+
 extern "C" JNIEXPORT jint JNICALL
 Java_com_wavry_android_core_NativeBridge_nativeStartClient(
     JNIEnv *env,
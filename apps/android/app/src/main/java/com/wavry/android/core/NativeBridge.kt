@@ -7,6 +7,8 @@ class NativeBridge {
     external fun nativeGetPublicKeyHex(): String
     external fun nativeVersion(): String
     external fun nativeStartHost(port: Int): Int
+    external fun nativeHostPermissionStatus(): Int
+    external fun nativeSetHostPermissionResult(granted: Boolean)
     external fun nativeStartClient(host: String, port: Int): Int
     external fun nativeConnectSignaling(url: String, token: String): Int
     external fun nativeSendConnectRequest(username: String): Int
@@ -18,6 +18,10 @@ class WavryCore(
 
     fun startHost(port: Int): Int = native.nativeStartHost(port)
 
+    fun hostPermissionGranted(): Boolean = native.nativeHostPermissionStatus() == 0
+
+    fun setHostPermissionResult(granted: Boolean) = native.nativeSetHostPermissionResult(granted)
+
     fun startClient(host: String, port: Int): Int = native.nativeStartClient(host, port)
 
     fun connectSignaling(url: String, token: String): Int = native.nativeConnectSignaling(url, token)
@@ -68,6 +72,7 @@ class WavryCore(
                 -3 -> "Runtime or channel failure"
                 -4 -> "Session startup failed"
                 -5 -> "Startup failed"
+                -6 -> "Screen capture permission not granted"
                 -10 -> "Invalid host/port values"
                 -11 -> "String conversion failure"
                 else -> "Operation failed (code $code)"
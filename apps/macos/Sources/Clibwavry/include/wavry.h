@@ -2,6 +2,7 @@
 #define WAVRY_H
 
 #include <stdint.h>
+#include <stdbool.h>
 
 int wavry_init(void);
 const char *wavry_version(void);
@@ -15,6 +16,9 @@ typedef struct {
   uint32_t bitrate_kbps;
   uint32_t keyframe_interval_ms;
   uint32_t display_id;
+  uint32_t max_session_secs; // 0 for no limit
+  uint32_t idle_timeout_secs; // 0 to never pause
+  bool lock_on_disconnect;
 } WavryHostConfig;
 int wavry_start_host_with_config(uint16_t port, const WavryHostConfig *config);
 int wavry_start_client(const char *host_ip, uint16_t port);
@@ -266,7 +266,10 @@ class AppState: ObservableObject {
                 fps: UInt16(max(15, min(hostFps, 240))),
                 bitrate_kbps: UInt32(max(1, bitrateMbps) * 1000),
                 keyframe_interval_ms: UInt32(max(250, min(keyframeIntervalMs, 10000))),
-                display_id: chosenDisplay
+                display_id: chosenDisplay,
+                max_session_secs: 0,
+                idle_timeout_secs: 0,
+                lock_on_disconnect: false
             )
 
             isStartingHost = true
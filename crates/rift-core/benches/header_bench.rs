@@ -0,0 +1,50 @@
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rift_core::compact::{CompactDecoder, CompactEncoder};
+use rift_core::{Channel, PhysicalPacket, RIFT_VERSION};
+
+/// Encrypted payload sizes: a mouse move, a key event and a video chunk.
+const PAYLOADS: [(&str, usize); 3] = [("mouse", 28), ("key", 36), ("video", 1200)];
+
+fn packet(packet_id: u64, payload_len: usize) -> PhysicalPacket {
+    PhysicalPacket {
+        version: RIFT_VERSION,
+        session_id: None,
+        session_alias: Some(0x1234_5678),
+        packet_id,
+        payload: Bytes::from(vec![0xAB; payload_len]),
+    }
+}
+
+/// Throughput is the bytes on the wire per packet, so the two header forms
+/// can be compared per payload size straight from the report.
+fn bench_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transport_header");
+    for (name, payload_len) in PAYLOADS {
+        let full = packet(10_000, payload_len);
+        group.throughput(Throughput::Bytes(full.encode().len() as u64));
+        group.bench_with_input(BenchmarkId::new("full", name), &full, |b, packet| {
+            b.iter(|| PhysicalPacket::decode(packet.encode()).unwrap())
+        });
+
+        let mut encoder = CompactEncoder::default();
+        let mut decoder = CompactDecoder::default();
+        decoder.accept(10_000);
+        let _ = encoder.encode(&packet(10_000, payload_len), Channel::Input);
+        let compact = packet(10_001, payload_len);
+        group.throughput(Throughput::Bytes(
+            encoder.encode(&compact, Channel::Input).len() as u64,
+        ));
+        group.bench_with_input(BenchmarkId::new("compact", name), &compact, |b, packet| {
+            b.iter(|| {
+                decoder
+                    .decode(encoder.encode(packet, Channel::Input))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_headers);
+criterion_main!(benches);
@@ -1,6 +1,21 @@
 use std::time::{Duration, Instant};
 use tracing::{debug, info};
 
+use crate::feedback::{DelayGradient, DelaySignal, PacketTiming};
+use crate::probe::{self, ProbeBurst};
+use crate::ProbeResult;
+
+/// Re-probe interval while stable and below the ceiling.
+const REPROBE_INTERVAL: Duration = Duration::from_secs(10);
+/// A probe with no result after this long is abandoned.
+const PROBE_RESULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Probes losing more than this are treated as having found no headroom.
+const PROBE_MAX_LOSS: f32 = 0.02;
+/// Fraction of the probed capacity the bitrate is allowed to jump to.
+const PROBE_HEADROOM_FACTOR: f64 = 0.9;
+/// Floor on the spacing of delay-gradient back-offs, for very short RTTs.
+const MIN_GRADIENT_BACKOFF_INTERVAL: Duration = Duration::from_millis(100);
+
 /// States for the DELTA Congestion Control algorithm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeltaState {
@@ -71,6 +86,23 @@ pub struct DeltaCC {
     current_bitrate_kbps: u32,
     current_fps: u32,
     fec_ratio: f32, // 0.0 to 1.0
+
+    // Padded bandwidth probing
+    probe_pending: bool,
+    probe_in_flight: Option<InFlightProbe>,
+    last_probe: Option<Instant>,
+    next_probe_id: u32,
+
+    // Transport-wide feedback
+    delay_gradient: DelayGradient,
+    last_gradient_backoff: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InFlightProbe {
+    burst: ProbeBurst,
+    base_bitrate_kbps: u32,
+    sent_at: Instant,
 }
 
 impl DeltaCC {
@@ -89,6 +121,133 @@ impl DeltaCC {
             current_bitrate_kbps: initial_bitrate,
             current_fps: initial_fps,
             fec_ratio: 0.05, // Start with 5% baseline
+            probe_pending: true,
+            probe_in_flight: None,
+            last_probe: None,
+            next_probe_id: 1,
+            delay_gradient: DelayGradient::new(),
+            last_gradient_backoff: None,
+        }
+    }
+
+    /// The path changed (new address, interface or relay): forget the delay
+    /// baseline and probe again once stable.
+    pub fn on_network_change(&mut self) {
+        self.window_samples.clear();
+        self.rtt_min_us = u64::MAX;
+        self.rtt_smooth_us = 0.0;
+        self.last_d_q_us = 0.0;
+        self.probe_pending = true;
+        self.probe_in_flight = None;
+        self.delay_gradient = DelayGradient::new();
+        self.last_gradient_backoff = None;
+    }
+
+    /// Burst the host should send now, if any. Probes run after the first RTT
+    /// sample, after a network change, and periodically while stable.
+    pub fn next_probe(&mut self, now: Instant) -> Option<ProbeBurst> {
+        if let Some(in_flight) = self.probe_in_flight {
+            if now.duration_since(in_flight.sent_at) < PROBE_RESULT_TIMEOUT {
+                return None;
+            }
+            debug!("DELTA: probe {} timed out", in_flight.burst.probe_id);
+            self.probe_in_flight = None;
+        }
+        if self.state != DeltaState::Stable || self.rtt_smooth_us == 0.0 {
+            return None;
+        }
+        if self.current_bitrate_kbps >= self.config.max_bitrate_kbps {
+            self.probe_pending = false;
+            return None;
+        }
+        let reprobe_due = self
+            .last_probe
+            .is_none_or(|last| now.duration_since(last) >= REPROBE_INTERVAL);
+        if !self.probe_pending && !reprobe_due {
+            return None;
+        }
+
+        // Pad up to twice the current rate, or to the ceiling.
+        let target = self
+            .current_bitrate_kbps
+            .saturating_mul(2)
+            .min(self.config.max_bitrate_kbps);
+        let burst = ProbeBurst::new(self.next_probe_id, target - self.current_bitrate_kbps);
+        self.next_probe_id = self.next_probe_id.wrapping_add(1).max(1);
+        self.probe_pending = false;
+        self.last_probe = Some(now);
+        self.probe_in_flight = Some(InFlightProbe {
+            burst,
+            base_bitrate_kbps: self.current_bitrate_kbps,
+            sent_at: now,
+        });
+        Some(burst)
+    }
+
+    /// Raises the bitrate toward the capacity a clean probe demonstrated.
+    pub fn on_probe_result(&mut self, result: &ProbeResult) {
+        let Some(in_flight) = self.probe_in_flight else {
+            return;
+        };
+        if in_flight.burst.probe_id != result.probe_id {
+            return;
+        }
+        self.probe_in_flight = None;
+
+        let loss = probe::loss_fraction(result);
+        let Some(delivered) = probe::delivered_kbps(result) else {
+            return;
+        };
+        if loss > PROBE_MAX_LOSS || self.state != DeltaState::Stable {
+            debug!(
+                "DELTA: probe {} found no headroom (loss {:.1}%, {}kbps delivered)",
+                result.probe_id,
+                loss * 100.0,
+                delivered
+            );
+            return;
+        }
+
+        let capacity =
+            in_flight.base_bitrate_kbps as u64 + delivered.min(in_flight.burst.rate_kbps) as u64;
+        let probed =
+            ((capacity as f64 * PROBE_HEADROOM_FACTOR) as u32).min(self.config.max_bitrate_kbps);
+        if probed > self.current_bitrate_kbps {
+            info!(
+                "DELTA: probe {} raised bitrate {} -> {}kbps",
+                result.probe_id, self.current_bitrate_kbps, probed
+            );
+            self.current_bitrate_kbps = probed;
+        }
+    }
+
+    /// Reacts to per-packet arrival times from the client. A building queue
+    /// backs the bitrate off at most once per RTT and holds it there until
+    /// the RTT path reports the delay flat again.
+    pub fn on_transport_feedback(&mut self, packets: &[PacketTiming], now: Instant) {
+        if self.delay_gradient.update(packets) != DelaySignal::Overuse {
+            return;
+        }
+        let interval =
+            Duration::from_micros(self.rtt_smooth_us as u64).max(MIN_GRADIENT_BACKOFF_INTERVAL);
+        if self
+            .last_gradient_backoff
+            .is_some_and(|last| now.duration_since(last) < interval)
+        {
+            return;
+        }
+        self.last_gradient_backoff = Some(now);
+
+        let reduced = ((self.current_bitrate_kbps as f64 * self.config.beta) as u32)
+            .max(self.config.min_bitrate_kbps);
+        info!(
+            "DELTA: delay gradient overuse, bitrate {} -> {}kbps",
+            self.current_bitrate_kbps, reduced
+        );
+        self.current_bitrate_kbps = reduced;
+        if self.state == DeltaState::Stable {
+            self.state = DeltaState::Rising;
+            self.stable_count = 0;
         }
     }
 
@@ -492,6 +651,78 @@ impl FecController {
 mod tests {
     use super::*;
 
+    fn clean_probe_result(burst: ProbeBurst) -> ProbeResult {
+        ProbeResult {
+            probe_id: burst.probe_id,
+            count: burst.count,
+            received: burst.count,
+            span_bytes: (burst.count as u64 - 1) * probe::PROBE_PACKET_BYTES as u64,
+            span_us: probe::PROBE_BURST_DURATION.as_micros() as u64 * (burst.count as u64 - 1)
+                / burst.count as u64,
+        }
+    }
+
+    #[test]
+    fn test_delta_probes_after_first_sample_and_raises_bitrate() {
+        let mut cc = DeltaCC::new(DeltaConfig::default(), 5_000, 60);
+        let now = Instant::now();
+        assert!(cc.next_probe(now).is_none(), "no RTT baseline yet");
+
+        cc.on_rtt_sample(5000, 0.0, 0);
+        let before = cc.target_bitrate_kbps();
+        let burst = cc.next_probe(now).expect("startup probe");
+        assert_eq!(burst.rate_kbps, before);
+        assert!(cc.next_probe(now).is_none(), "one probe in flight");
+
+        cc.on_probe_result(&clean_probe_result(burst));
+        assert!(cc.target_bitrate_kbps() > before);
+        assert!(cc.next_probe(now).is_none(), "re-probe waits for interval");
+        assert!(cc.next_probe(now + REPROBE_INTERVAL).is_some());
+    }
+
+    #[test]
+    fn test_delta_lossy_probe_keeps_bitrate() {
+        let mut cc = DeltaCC::new(DeltaConfig::default(), 5_000, 60);
+        cc.on_rtt_sample(5000, 0.0, 0);
+        let before = cc.target_bitrate_kbps();
+        let burst = cc.next_probe(Instant::now()).unwrap();
+
+        let mut result = clean_probe_result(burst);
+        result.received = burst.count / 2;
+        cc.on_probe_result(&result);
+        assert_eq!(cc.target_bitrate_kbps(), before);
+    }
+
+    #[test]
+    fn test_delta_backs_off_on_delay_gradient_once_per_rtt() {
+        let mut cc = DeltaCC::new(DeltaConfig::default(), 10_000, 60);
+        cc.on_rtt_sample(20_000, 0.0, 0);
+        let before = cc.target_bitrate_kbps();
+
+        // One packet a millisecond through a link that drains one every 1.5ms.
+        let mut link_free_us = 0;
+        let packets: Vec<PacketTiming> = (0..500u64)
+            .map(|packet_id| {
+                let send_us = packet_id * 1_000;
+                link_free_us = link_free_us.max(send_us) + 1_500;
+                PacketTiming {
+                    packet_id,
+                    send_us,
+                    arrival_us: link_free_us,
+                    bytes: 1_200,
+                }
+            })
+            .collect();
+        let now = Instant::now();
+        cc.on_transport_feedback(&packets[..250], now);
+        assert_eq!(cc.state(), DeltaState::Rising);
+        let reduced = cc.target_bitrate_kbps();
+        assert!(reduced < before);
+
+        cc.on_transport_feedback(&packets[250..], now + Duration::from_millis(50));
+        assert_eq!(cc.target_bitrate_kbps(), reduced, "one back-off per RTT");
+    }
+
     #[test]
     fn test_delta_rtt_min_tracking() {
         let mut cc = DeltaCC::new(DeltaConfig::default(), 10000, 60);
@@ -0,0 +1,554 @@
+//! Forward error correction for media packets.
+//!
+//! Consecutive media packets are grouped into data shards and protected by
+//! parity shards sent as `FecPacket`s. Two schemes are supported:
+//!
+//! - XOR: a single parity shard per group, recovers one lost packet.
+//! - Reed-Solomon: a systematic Cauchy code over GF(2^8) with a configurable
+//!   number of parity shards, recovers as many lost packets as there are
+//!   parity shards received.
+//!
+//! XOR is the Reed-Solomon construction with every coefficient fixed to 1, so
+//! one decoder handles both.
+
+use std::collections::HashMap;
+
+use crate::{FecPacket, FecScheme};
+
+/// Data plus parity shards per group; bounded by the GF(2^8) field size.
+pub const MAX_RS_SHARDS: u32 = 256;
+/// Data shards per Reed-Solomon group when sized from a parity ratio.
+pub const RS_DATA_SHARDS: u32 = 16;
+/// Upper bound on parity shards when sized from a parity ratio.
+pub const RS_MAX_PARITY_SHARDS: u32 = 8;
+/// Largest parity payload accepted from the network.
+pub const MAX_FEC_SHARD_BYTES: usize = 64 * 1024;
+/// Parity groups retained by the receiver while waiting for more shards.
+const MAX_PENDING_GROUPS: usize = 64;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum FecError {
+    #[error("shard count must be at least 2")]
+    InvalidShardCount,
+    #[error("at most {MAX_RS_SHARDS} shards per group")]
+    TooManyShards,
+}
+
+/// Picks the scheme to use for a peer's advertised `Hello::fec_schemes`.
+pub fn negotiate_scheme(offered: &[i32]) -> FecScheme {
+    if offered.contains(&(FecScheme::ReedSolomon as i32)) {
+        FecScheme::ReedSolomon
+    } else {
+        FecScheme::Xor
+    }
+}
+
+/// Schemes this build can decode, for `Hello::fec_schemes`.
+pub fn supported_schemes() -> Vec<i32> {
+    vec![FecScheme::Xor as i32, FecScheme::ReedSolomon as i32]
+}
+
+#[derive(Debug, Clone)]
+pub struct FecBuilder {
+    scheme: FecScheme,
+    data_shards: u32,
+    parity_shards: u32,
+    group_id: u64,
+    first_packet_id: Option<u64>,
+    payloads: Vec<Vec<u8>>,
+    shard_lengths: Vec<u32>,
+    max_payload_len: usize,
+}
+
+impl FecBuilder {
+    /// XOR parity: one parity shard for every `shard_count - 1` data packets.
+    pub fn new(shard_count: u32) -> Result<Self, FecError> {
+        if shard_count < 2 {
+            return Err(FecError::InvalidShardCount);
+        }
+        Ok(Self::with_layout(FecScheme::Xor, shard_count - 1, 1))
+    }
+
+    /// Reed-Solomon parity: `parity_shards` parity packets for every
+    /// `data_shards` data packets.
+    pub fn reed_solomon(data_shards: u32, parity_shards: u32) -> Result<Self, FecError> {
+        if data_shards < 1 || parity_shards < 1 {
+            return Err(FecError::InvalidShardCount);
+        }
+        if data_shards.saturating_add(parity_shards) > MAX_RS_SHARDS {
+            return Err(FecError::TooManyShards);
+        }
+        Ok(Self::with_layout(
+            FecScheme::ReedSolomon,
+            data_shards,
+            parity_shards,
+        ))
+    }
+
+    /// Builder for `scheme` adding roughly `ratio` parity overhead.
+    pub fn for_ratio(scheme: FecScheme, ratio: f32) -> Self {
+        let ratio = if ratio.is_finite() {
+            ratio.max(0.0)
+        } else {
+            0.0
+        };
+        match scheme {
+            FecScheme::Xor => {
+                let shards = (1.0 / ratio).clamp(4.0, 30.0) as u32;
+                Self::with_layout(FecScheme::Xor, shards - 1, 1)
+            }
+            FecScheme::ReedSolomon => {
+                let parity =
+                    ((RS_DATA_SHARDS as f32 * ratio).ceil() as u32).clamp(1, RS_MAX_PARITY_SHARDS);
+                Self::with_layout(FecScheme::ReedSolomon, RS_DATA_SHARDS, parity)
+            }
+        }
+    }
+
+    fn with_layout(scheme: FecScheme, data_shards: u32, parity_shards: u32) -> Self {
+        Self {
+            scheme,
+            data_shards,
+            parity_shards,
+            group_id: 0,
+            first_packet_id: None,
+            payloads: Vec::with_capacity(data_shards as usize),
+            shard_lengths: Vec::with_capacity(data_shards as usize),
+            max_payload_len: 0,
+        }
+    }
+
+    pub fn scheme(&self) -> FecScheme {
+        self.scheme
+    }
+
+    /// Adds a data packet. Returns the group's parity packets once it is full.
+    pub fn push(&mut self, packet_id: u64, payload: &[u8]) -> Vec<FecPacket> {
+        if self.payloads.is_empty() {
+            self.first_packet_id = Some(packet_id);
+        }
+
+        self.max_payload_len = self.max_payload_len.max(payload.len());
+        self.shard_lengths.push(payload.len() as u32);
+        self.payloads.push(payload.to_vec());
+
+        if self.payloads.len() < self.data_shards as usize {
+            return Vec::new();
+        }
+
+        let shard_count = self.data_shards + self.parity_shards;
+        let packets = (0..self.parity_shards)
+            .map(|row| {
+                let mut parity = vec![0u8; self.max_payload_len];
+                for (col, payload) in self.payloads.iter().enumerate() {
+                    let coefficient =
+                        parity_coefficient(self.scheme, self.data_shards, row, col as u32);
+                    mul_add(&mut parity, payload, coefficient);
+                }
+                FecPacket {
+                    group_id: self.group_id,
+                    first_packet_id: self.first_packet_id.unwrap_or(packet_id),
+                    shard_count,
+                    parity_index: self.data_shards + row,
+                    payload: parity,
+                    shard_lengths: self.shard_lengths.clone(),
+                    scheme: self.scheme as i32,
+                    data_shards: self.data_shards,
+                }
+            })
+            .collect();
+        self.group_id = self.group_id.wrapping_add(1);
+        self.reset();
+        packets
+    }
+
+    fn reset(&mut self) {
+        self.payloads.clear();
+        self.shard_lengths.clear();
+        self.max_payload_len = 0;
+        self.first_packet_id = None;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ParityGroup {
+    shard_count: u32,
+    data_shards: u32,
+    scheme: i32,
+    parity: Vec<FecPacket>,
+    done: bool,
+}
+
+/// Receive-side counterpart of `FecBuilder`: collects parity shards per group
+/// and rebuilds missing data packets once enough shards have arrived.
+#[derive(Debug, Default)]
+pub struct FecReassembler {
+    /// Keyed by `first_packet_id`; group ids restart when a sender rebuilds
+    /// its `FecBuilder`, packet ids don't.
+    groups: HashMap<u64, ParityGroup>,
+}
+
+impl FecReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a parity packet and returns any data packets it lets us
+    /// recover, as `(packet_id, payload)`. `data` looks up received packets.
+    pub fn on_parity<'a, F>(&mut self, fec: FecPacket, data: F) -> Vec<(u64, Vec<u8>)>
+    where
+        F: Fn(u64) -> Option<&'a [u8]>,
+    {
+        let Some((scheme, data_shards)) = validate(&fec) else {
+            return Vec::new();
+        };
+
+        if !self.groups.contains_key(&fec.first_packet_id)
+            && self.groups.len() >= MAX_PENDING_GROUPS
+        {
+            if let Some(oldest) = self.groups.keys().min().copied() {
+                self.groups.remove(&oldest);
+            }
+        }
+        let first_packet_id = fec.first_packet_id;
+        let group = self
+            .groups
+            .entry(first_packet_id)
+            .or_insert_with(|| ParityGroup {
+                shard_count: fec.shard_count,
+                data_shards,
+                scheme: fec.scheme,
+                ..Default::default()
+            });
+        if group.done
+            || group.shard_count != fec.shard_count
+            || group.data_shards != data_shards
+            || group.scheme != fec.scheme
+            || group
+                .parity
+                .iter()
+                .any(|p| p.parity_index == fec.parity_index)
+        {
+            return Vec::new();
+        }
+        group.parity.push(fec);
+
+        let packet_id = |col: u32| first_packet_id.wrapping_add(col as u64);
+        let missing: Vec<u32> = (0..data_shards)
+            .filter(|&col| data(packet_id(col)).is_none())
+            .collect();
+        if missing.is_empty() {
+            group.done = true;
+            return Vec::new();
+        }
+        if group.parity.len() < missing.len() {
+            return Vec::new();
+        }
+
+        let rows = &group.parity[..missing.len()];
+        let len = rows.iter().map(|p| p.payload.len()).max().unwrap_or(0);
+
+        // Strip the known data shards out of each parity shard, leaving a
+        // combination of the missing shards only.
+        let mut rhs: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|parity| {
+                let row = parity.parity_index - data_shards;
+                let mut acc = parity.payload.clone();
+                acc.resize(len, 0);
+                for col in 0..data_shards {
+                    if let Some(payload) = data(packet_id(col)) {
+                        mul_add(
+                            &mut acc,
+                            payload,
+                            parity_coefficient(scheme, data_shards, row, col),
+                        );
+                    }
+                }
+                acc
+            })
+            .collect();
+        let mut matrix: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|parity| {
+                let row = parity.parity_index - data_shards;
+                missing
+                    .iter()
+                    .map(|&col| parity_coefficient(scheme, data_shards, row, col))
+                    .collect()
+            })
+            .collect();
+        let shard_lengths = rows[0].shard_lengths.clone();
+        if !solve(&mut matrix, &mut rhs) {
+            return Vec::new();
+        }
+        group.done = true;
+
+        missing
+            .into_iter()
+            .zip(rhs)
+            .map(|(col, mut payload)| {
+                // Parity covers the longest shard; trim the zero padding so the
+                // recovered ciphertext authenticates.
+                if let Some(&actual_len) = shard_lengths.get(col as usize) {
+                    payload.truncate(actual_len as usize);
+                }
+                (packet_id(col), payload)
+            })
+            .collect()
+    }
+}
+
+/// Checks a parity packet from the network and returns its scheme and data
+/// shard count.
+fn validate(fec: &FecPacket) -> Option<(FecScheme, u32)> {
+    let scheme = FecScheme::try_from(fec.scheme).ok()?;
+    if fec.shard_count < 2 || fec.shard_count > MAX_RS_SHARDS {
+        return None;
+    }
+    let data_shards = match (scheme, fec.data_shards) {
+        (FecScheme::Xor, 0) => fec.shard_count - 1,
+        (_, n) => n,
+    };
+    if data_shards == 0 || data_shards >= fec.shard_count {
+        return None;
+    }
+    if scheme == FecScheme::Xor && data_shards != fec.shard_count - 1 {
+        return None;
+    }
+    if fec.parity_index < data_shards || fec.parity_index >= fec.shard_count {
+        return None;
+    }
+    if fec.payload.len() > MAX_FEC_SHARD_BYTES {
+        return None;
+    }
+    Some((scheme, data_shards))
+}
+
+/// Coefficient of data shard `col` in parity shard `row`. Reed-Solomon uses a
+/// Cauchy matrix `1 / (x_row + y_col)` with `x_row = data_shards + row` and
+/// `y_col = col`, so every square submatrix is invertible.
+fn parity_coefficient(scheme: FecScheme, data_shards: u32, row: u32, col: u32) -> u8 {
+    match scheme {
+        FecScheme::Xor => 1,
+        FecScheme::ReedSolomon => gf_inv(((data_shards + row) as u8) ^ (col as u8)),
+    }
+}
+
+/// Gauss-Jordan elimination over GF(2^8); leaves the solution in `rhs`.
+fn solve(matrix: &mut [Vec<u8>], rhs: &mut [Vec<u8>]) -> bool {
+    let n = matrix.len();
+    for col in 0..n {
+        let Some(pivot) = (col..n).find(|&row| matrix[row][col] != 0) else {
+            return false;
+        };
+        matrix.swap(col, pivot);
+        rhs.swap(col, pivot);
+
+        let inv = gf_inv(matrix[col][col]);
+        for value in matrix[col].iter_mut() {
+            *value = gf_mul(*value, inv);
+        }
+        for byte in rhs[col].iter_mut() {
+            *byte = gf_mul(*byte, inv);
+        }
+
+        let pivot_row = matrix[col].clone();
+        let pivot_rhs = rhs[col].clone();
+        for row in 0..n {
+            let factor = matrix[row][col];
+            if row == col || factor == 0 {
+                continue;
+            }
+            for (value, &p) in matrix[row].iter_mut().zip(&pivot_row) {
+                *value ^= gf_mul(factor, p);
+            }
+            mul_add(&mut rhs[row], &pivot_rhs, factor);
+        }
+    }
+    true
+}
+
+/// `target ^= coefficient * source`, over the shorter of the two.
+fn mul_add(target: &mut [u8], source: &[u8], coefficient: u8) {
+    match coefficient {
+        0 => {}
+        1 => {
+            for (t, s) in target.iter_mut().zip(source) {
+                *t ^= s;
+            }
+        }
+        _ => {
+            for (t, &s) in target.iter_mut().zip(source) {
+                *t ^= gf_mul(coefficient, s);
+            }
+        }
+    }
+}
+
+/// exp/log tables for GF(2^8) with the 0x11d reduction polynomial.
+const GF_TABLES: ([u8; 512], [u8; 256]) = build_gf_tables();
+
+const fn build_gf_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (exp, log) = &GF_TABLES;
+    exp[log[a as usize] as usize + log[b as usize] as usize]
+}
+
+/// Multiplicative inverse; callers never pass zero.
+fn gf_inv(a: u8) -> u8 {
+    debug_assert!(a != 0);
+    let (exp, log) = &GF_TABLES;
+    exp[255 - log[a as usize] as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_packets(count: u64) -> Vec<(u64, Vec<u8>)> {
+        (0..count)
+            .map(|i| {
+                let len = 40 + (i as usize * 7) % 23;
+                (
+                    100 + i,
+                    (0..len).map(|b| (b as u64 * 31 + i) as u8).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn encode(builder: &mut FecBuilder, packets: &[(u64, Vec<u8>)]) -> Vec<FecPacket> {
+        let mut parity = Vec::new();
+        for (id, payload) in packets {
+            parity.extend(builder.push(*id, payload));
+        }
+        parity
+    }
+
+    fn recover(
+        packets: &[(u64, Vec<u8>)],
+        parity: Vec<FecPacket>,
+        lost: &[u64],
+    ) -> Vec<(u64, Vec<u8>)> {
+        let received: HashMap<u64, Vec<u8>> = packets
+            .iter()
+            .filter(|(id, _)| !lost.contains(id))
+            .cloned()
+            .collect();
+        let mut reassembler = FecReassembler::new();
+        let mut recovered = Vec::new();
+        for fec in parity {
+            recovered.extend(reassembler.on_parity(fec, |id| received.get(&id).map(Vec::as_slice)));
+        }
+        recovered.sort();
+        recovered
+    }
+
+    #[test]
+    fn gf_inverse_round_trips() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1, "a = {a}");
+        }
+    }
+
+    #[test]
+    fn builder_rejects_bad_layouts() {
+        assert!(FecBuilder::new(2).is_ok());
+        assert!(matches!(
+            FecBuilder::reed_solomon(0, 2),
+            Err(FecError::InvalidShardCount)
+        ));
+        assert!(matches!(
+            FecBuilder::reed_solomon(250, 10),
+            Err(FecError::TooManyShards)
+        ));
+    }
+
+    #[test]
+    fn xor_recovers_single_loss() {
+        let packets = data_packets(4);
+        let parity = encode(&mut FecBuilder::new(5).unwrap(), &packets);
+        assert_eq!(parity.len(), 1);
+
+        let recovered = recover(&packets, parity.clone(), &[102]);
+        assert_eq!(recovered, vec![packets[2].clone()]);
+        assert!(recover(&packets, parity, &[101, 102]).is_empty());
+    }
+
+    #[test]
+    fn xor_accepts_packets_without_scheme_fields() {
+        let packets = data_packets(3);
+        let mut parity = encode(&mut FecBuilder::new(4).unwrap(), &packets);
+        parity[0].data_shards = 0;
+        assert_eq!(recover(&packets, parity, &[100]), vec![packets[0].clone()]);
+    }
+
+    #[test]
+    fn reed_solomon_recovers_up_to_parity_count() {
+        let packets = data_packets(10);
+        let parity = encode(&mut FecBuilder::reed_solomon(10, 3).unwrap(), &packets);
+        assert_eq!(parity.len(), 3);
+
+        let lost = [100, 104, 109];
+        let expected: Vec<_> = packets
+            .iter()
+            .filter(|(id, _)| lost.contains(id))
+            .cloned()
+            .collect();
+        assert_eq!(recover(&packets, parity.clone(), &lost), expected);
+        assert!(recover(&packets, parity, &[100, 101, 102, 103]).is_empty());
+    }
+
+    #[test]
+    fn reed_solomon_uses_any_subset_of_parity() {
+        let packets = data_packets(6);
+        let mut parity = encode(&mut FecBuilder::reed_solomon(6, 3).unwrap(), &packets);
+        parity.remove(0);
+
+        let recovered = recover(&packets, parity, &[101, 103]);
+        assert_eq!(recovered, vec![packets[1].clone(), packets[3].clone()]);
+    }
+
+    #[test]
+    fn ratio_sizes_groups() {
+        let xor = FecBuilder::for_ratio(FecScheme::Xor, 0.05);
+        assert_eq!((xor.data_shards, xor.parity_shards), (19, 1));
+        let rs = FecBuilder::for_ratio(FecScheme::ReedSolomon, 0.2);
+        assert_eq!((rs.data_shards, rs.parity_shards), (16, 4));
+        let rs = FecBuilder::for_ratio(FecScheme::ReedSolomon, f32::NAN);
+        assert_eq!(rs.parity_shards, 1);
+    }
+
+    #[test]
+    fn negotiation_prefers_reed_solomon() {
+        assert_eq!(negotiate_scheme(&[]), FecScheme::Xor);
+        assert_eq!(
+            negotiate_scheme(&supported_schemes()),
+            FecScheme::ReedSolomon
+        );
+    }
+}
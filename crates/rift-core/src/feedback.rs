@@ -0,0 +1,420 @@
+//! Transport-wide congestion feedback.
+//!
+//! The client notes when each of the host's packets arrives and reports the
+//! arrival times every [`FEEDBACK_INTERVAL`] in a `TransportFeedback`. The
+//! host pairs them with its own send times. Once a queue starts to build on
+//! the path, packets spread out on arrival compared with how they were sent,
+//! so the delay gradient turns positive well before the queue overflows into
+//! loss or shows up in the smoothed RTT.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::TransportFeedback;
+
+/// How often the client reports arrivals.
+pub const FEEDBACK_INTERVAL: Duration = Duration::from_millis(50);
+/// Arrivals a [`FeedbackRecorder`] holds before it reports early.
+const MAX_FEEDBACK_PACKETS: usize = 400;
+/// Send times kept for matching; several feedback intervals at high rates.
+const SEND_HISTORY: usize = 8192;
+
+/// Packets sent within this span form one group for the delay gradient,
+/// which keeps pacing bursts from reading as queueing.
+const GROUP_SPAN_US: u64 = 5_000;
+/// Group deltas the trendline is fitted over.
+const TRENDLINE_WINDOW: usize = 20;
+const TRENDLINE_SMOOTHING: f64 = 0.9;
+const TRENDLINE_GAIN: f64 = 4.0;
+const INITIAL_THRESHOLD_MS: f64 = 12.5;
+const THRESHOLD_UP: f64 = 0.0087;
+const THRESHOLD_DOWN: f64 = 0.039;
+/// A trend must stay over the threshold this long to count as overuse.
+const OVERUSE_TIME_MS: f64 = 10.0;
+
+/// Client-side log of arrivals since the last feedback.
+#[derive(Debug, Default)]
+pub struct FeedbackRecorder {
+    arrivals: Vec<(u64, u64)>,
+    last_report: Option<Instant>,
+}
+
+impl FeedbackRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_packet(&mut self, packet_id: u64, arrival_us: u64) {
+        self.arrivals.push((packet_id, arrival_us));
+    }
+
+    /// Feedback for every arrival since the previous report, once the
+    /// interval has passed or the batch is full.
+    pub fn poll(&mut self, now: Instant) -> Option<TransportFeedback> {
+        if self.arrivals.is_empty() {
+            return None;
+        }
+        let due = self.arrivals.len() >= MAX_FEEDBACK_PACKETS
+            || self
+                .last_report
+                .is_none_or(|last| now.duration_since(last) >= FEEDBACK_INTERVAL);
+        if !due {
+            return None;
+        }
+        self.last_report = Some(now);
+
+        let mut arrivals = std::mem::take(&mut self.arrivals);
+        arrivals.sort_unstable_by_key(|&(packet_id, _)| packet_id);
+        arrivals.dedup_by_key(|&mut (packet_id, _)| packet_id);
+        let (base_packet_id, reference_time_us) = arrivals[0];
+        let mut feedback = TransportFeedback {
+            base_packet_id,
+            reference_time_us,
+            packet_offsets: Vec::with_capacity(arrivals.len()),
+            arrival_deltas_us: Vec::with_capacity(arrivals.len()),
+        };
+        let mut previous_us = reference_time_us;
+        for (packet_id, arrival_us) in arrivals {
+            let Ok(offset) = u32::try_from(packet_id - base_packet_id) else {
+                break;
+            };
+            let delta = (arrival_us as i64 - previous_us as i64)
+                .clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            previous_us = (previous_us as i64 + delta as i64) as u64;
+            feedback.packet_offsets.push(offset);
+            feedback.arrival_deltas_us.push(delta);
+        }
+        Some(feedback)
+    }
+}
+
+/// `(packet_id, arrival_us)` pairs in `feedback`, in packet id order.
+pub fn feedback_arrivals(feedback: &TransportFeedback) -> Vec<(u64, u64)> {
+    let mut arrival_us = feedback.reference_time_us as i64;
+    feedback
+        .packet_offsets
+        .iter()
+        .zip(&feedback.arrival_deltas_us)
+        .map(|(&offset, &delta)| {
+            arrival_us += i64::from(delta);
+            (
+                feedback.base_packet_id.wrapping_add(u64::from(offset)),
+                arrival_us.max(0) as u64,
+            )
+        })
+        .collect()
+}
+
+/// A packet the client reported, with both ends' clocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketTiming {
+    pub packet_id: u64,
+    pub send_us: u64,
+    pub arrival_us: u64,
+    pub bytes: usize,
+}
+
+/// Host-side send times, matched against feedback as it arrives.
+#[derive(Debug)]
+pub struct SendTimes {
+    epoch: Instant,
+    sent: VecDeque<(u64, u64, usize)>,
+}
+
+impl SendTimes {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            epoch: now,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Records a packet as it leaves. Ids must increase, as sealed ids do.
+    pub fn on_sent(&mut self, packet_id: u64, bytes: usize, now: Instant) {
+        if self.sent.len() >= SEND_HISTORY {
+            self.sent.pop_front();
+        }
+        let send_us = now.saturating_duration_since(self.epoch).as_micros() as u64;
+        self.sent.push_back((packet_id, send_us, bytes));
+    }
+
+    /// Reported packets in send order. Send times up to the newest reported
+    /// id are forgotten, so late feedback for them is ignored.
+    pub fn on_feedback(&mut self, feedback: &TransportFeedback) -> Vec<PacketTiming> {
+        let arrivals = feedback_arrivals(feedback);
+        let Some(&(newest, _)) = arrivals.last() else {
+            return Vec::new();
+        };
+        let timings = arrivals
+            .into_iter()
+            .filter_map(|(packet_id, arrival_us)| {
+                let index = self
+                    .sent
+                    .binary_search_by_key(&packet_id, |&(id, _, _)| id)
+                    .ok()?;
+                let (_, send_us, bytes) = self.sent[index];
+                Some(PacketTiming {
+                    packet_id,
+                    send_us,
+                    arrival_us,
+                    bytes,
+                })
+            })
+            .collect();
+        while self.sent.front().is_some_and(|&(id, _, _)| id <= newest) {
+            self.sent.pop_front();
+        }
+        timings
+    }
+}
+
+/// Queue trend the delay gradient points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelaySignal {
+    /// Queues are draining.
+    Underuse,
+    Normal,
+    /// A queue is building.
+    Overuse,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PacketGroup {
+    first_send_us: u64,
+    last_send_us: u64,
+    last_arrival_us: u64,
+}
+
+/// Trendline estimate of the one-way delay gradient between packet groups,
+/// compared against an adaptive threshold.
+#[derive(Debug)]
+pub struct DelayGradient {
+    current: Option<PacketGroup>,
+    previous: Option<PacketGroup>,
+    first_arrival_us: Option<u64>,
+    accumulated_ms: f64,
+    smoothed_ms: f64,
+    /// `(arrival ms, smoothed accumulated delay ms)` per group delta.
+    samples: VecDeque<(f64, f64)>,
+    deltas: u32,
+    threshold_ms: f64,
+    last_threshold_update_ms: Option<f64>,
+    overuse_since_ms: Option<f64>,
+    previous_trend: f64,
+    signal: DelaySignal,
+}
+
+impl Default for DelayGradient {
+    fn default() -> Self {
+        Self {
+            current: None,
+            previous: None,
+            first_arrival_us: None,
+            accumulated_ms: 0.0,
+            smoothed_ms: 0.0,
+            samples: VecDeque::with_capacity(TRENDLINE_WINDOW + 1),
+            deltas: 0,
+            threshold_ms: INITIAL_THRESHOLD_MS,
+            last_threshold_update_ms: None,
+            overuse_since_ms: None,
+            previous_trend: 0.0,
+            signal: DelaySignal::Normal,
+        }
+    }
+}
+
+impl DelayGradient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn signal(&self) -> DelaySignal {
+        self.signal
+    }
+
+    /// Feeds packets in send order and returns the resulting signal.
+    pub fn update(&mut self, packets: &[PacketTiming]) -> DelaySignal {
+        for packet in packets {
+            self.on_packet(packet);
+        }
+        self.signal
+    }
+
+    fn on_packet(&mut self, packet: &PacketTiming) {
+        if let Some(group) = self.current.as_mut() {
+            if packet.send_us.saturating_sub(group.first_send_us) <= GROUP_SPAN_US {
+                group.last_send_us = group.last_send_us.max(packet.send_us);
+                group.last_arrival_us = group.last_arrival_us.max(packet.arrival_us);
+                return;
+            }
+        }
+        let finished = self.current.replace(PacketGroup {
+            first_send_us: packet.send_us,
+            last_send_us: packet.send_us,
+            last_arrival_us: packet.arrival_us,
+        });
+        if let Some(finished) = finished {
+            if let Some(previous) = self.previous {
+                self.on_group_delta(previous, finished);
+            }
+            self.previous = Some(finished);
+        }
+    }
+
+    fn on_group_delta(&mut self, previous: PacketGroup, current: PacketGroup) {
+        let send_delta_ms = (current.last_send_us as f64 - previous.last_send_us as f64) / 1000.0;
+        let arrival_delta_ms =
+            (current.last_arrival_us as f64 - previous.last_arrival_us as f64) / 1000.0;
+        let first = *self.first_arrival_us.get_or_insert(current.last_arrival_us);
+        let arrival_ms = current.last_arrival_us.saturating_sub(first) as f64 / 1000.0;
+
+        self.deltas = self.deltas.saturating_add(1);
+        self.accumulated_ms += arrival_delta_ms - send_delta_ms;
+        self.smoothed_ms = TRENDLINE_SMOOTHING * self.smoothed_ms
+            + (1.0 - TRENDLINE_SMOOTHING) * self.accumulated_ms;
+        self.samples.push_back((arrival_ms, self.smoothed_ms));
+        if self.samples.len() > TRENDLINE_WINDOW {
+            self.samples.pop_front();
+        }
+        if self.samples.len() < TRENDLINE_WINDOW {
+            return;
+        }
+        if let Some(slope) = linear_slope(&self.samples) {
+            let trend = slope * f64::from(self.deltas.min(60)) * TRENDLINE_GAIN;
+            self.detect(trend, arrival_ms);
+        }
+    }
+
+    fn detect(&mut self, trend: f64, now_ms: f64) {
+        if trend > self.threshold_ms {
+            let since = *self.overuse_since_ms.get_or_insert(now_ms);
+            if now_ms - since >= OVERUSE_TIME_MS && trend >= self.previous_trend {
+                self.signal = DelaySignal::Overuse;
+            }
+        } else {
+            self.overuse_since_ms = None;
+            self.signal = if trend < -self.threshold_ms {
+                DelaySignal::Underuse
+            } else {
+                DelaySignal::Normal
+            };
+        }
+        self.previous_trend = trend;
+        self.adapt_threshold(trend, now_ms);
+    }
+
+    /// Tracks the trend's magnitude so steady cross traffic doesn't starve
+    /// the stream, while spikes such as a route change leave it alone.
+    fn adapt_threshold(&mut self, trend: f64, now_ms: f64) {
+        let last = self.last_threshold_update_ms.replace(now_ms);
+        if trend.abs() > self.threshold_ms + 15.0 {
+            return;
+        }
+        let k = if trend.abs() < self.threshold_ms {
+            THRESHOLD_DOWN
+        } else {
+            THRESHOLD_UP
+        };
+        let elapsed_ms = last.map_or(0.0, |last| (now_ms - last).min(100.0));
+        self.threshold_ms = (self.threshold_ms
+            + k * (trend.abs() - self.threshold_ms) * elapsed_ms)
+            .clamp(6.0, 600.0);
+    }
+}
+
+/// Least-squares slope of `y` over `x`.
+fn linear_slope(samples: &VecDeque<(f64, f64)>) -> Option<f64> {
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let (numerator, denominator) =
+        samples
+            .iter()
+            .fold((0.0, 0.0), |(numerator, denominator), &(x, y)| {
+                (
+                    numerator + (x - mean_x) * (y - mean_y),
+                    denominator + (x - mean_x) * (x - mean_x),
+                )
+            });
+    (denominator > 0.0).then(|| numerator / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packets sent every millisecond through a bottleneck that needs
+    /// `service_us` for each, behind a fixed 10 ms of propagation delay.
+    fn bottleneck(packets: u64, service_us: u64) -> Vec<PacketTiming> {
+        let mut link_free_us = 0;
+        (0..packets)
+            .map(|packet_id| {
+                let send_us = packet_id * 1_000;
+                link_free_us = link_free_us.max(send_us) + service_us;
+                PacketTiming {
+                    packet_id,
+                    send_us,
+                    arrival_us: link_free_us + 10_000,
+                    bytes: 1_200,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn feedback_round_trips_arrivals_in_id_order() {
+        let mut recorder = FeedbackRecorder::new();
+        let start = Instant::now();
+        for (packet_id, arrival_us) in [(12, 5_000), (10, 4_100), (11, 4_000), (10, 4_100)] {
+            recorder.on_packet(packet_id, arrival_us);
+        }
+        let feedback = recorder.poll(start).expect("first report is due");
+        assert_eq!(
+            feedback_arrivals(&feedback),
+            vec![(10, 4_100), (11, 4_000), (12, 5_000)]
+        );
+
+        recorder.on_packet(13, 6_000);
+        assert!(recorder.poll(start + FEEDBACK_INTERVAL / 2).is_none());
+        assert!(recorder.poll(start + FEEDBACK_INTERVAL).is_some());
+    }
+
+    #[test]
+    fn send_times_pair_with_feedback_and_skip_lost_packets() {
+        let start = Instant::now();
+        let mut sent = SendTimes::new(start);
+        for packet_id in 1..=4 {
+            sent.on_sent(packet_id, 1_000, start + Duration::from_millis(packet_id));
+        }
+        let feedback = TransportFeedback {
+            base_packet_id: 1,
+            reference_time_us: 50_000,
+            packet_offsets: vec![0, 2],
+            arrival_deltas_us: vec![0, 2_500],
+        };
+        let timings = sent.on_feedback(&feedback);
+        assert_eq!(timings.len(), 2);
+        assert_eq!(
+            (
+                timings[1].packet_id,
+                timings[1].send_us,
+                timings[1].arrival_us
+            ),
+            (3, 3_000, 52_500)
+        );
+        // Packet 2 was lost and is forgotten along with everything reported.
+        assert!(sent.on_feedback(&feedback).is_empty());
+    }
+
+    #[test]
+    fn delay_gradient_flags_a_building_queue() {
+        let mut clear = DelayGradient::new();
+        assert_eq!(clear.update(&bottleneck(500, 600)), DelaySignal::Normal);
+
+        let mut congested = DelayGradient::new();
+        assert_eq!(
+            congested.update(&bottleneck(500, 1_200)),
+            DelaySignal::Overuse
+        );
+    }
+}
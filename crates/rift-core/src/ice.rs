@@ -0,0 +1,428 @@
+//! Candidate pairing and connectivity checks before the Noise handshake.
+//!
+//! A trimmed RFC 8445 for one UDP socket per peer. Each side gathers its
+//! candidates (interface addresses, the address STUN or a router mapping
+//! reports, a relay) and sends them in `OFFER_RIFT`/`ANSWER_RIFT`. The client
+//! controls: it pairs its socket with every direct remote candidate and
+//! sends each pair a STUN binding request, highest priority first, paced and
+//! retransmitted. The host answers checks on its RIFT socket and sends its
+//! own towards the client's candidates, which opens its side of the NAT.
+//!
+//! Answered pairs then carry the handshake in priority order. Unanswered
+//! ones follow, since hosts predating checks still complete the handshake,
+//! and relay candidates are left to the relay lease, which is raced last.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::stun::{StunMessage, BINDING_REQUEST, BINDING_RESPONSE};
+pub use wavry_common::protocol::{CandidateKind, IceCandidate};
+
+/// Gap between new checks (RFC 8445's Ta).
+pub const CHECK_PACING: Duration = Duration::from_millis(20);
+/// First retransmission timeout; doubles on each retry.
+pub const CHECK_RTO: Duration = Duration::from_millis(100);
+/// Checks sent per pair before it fails.
+pub const MAX_CHECK_ATTEMPTS: u32 = 4;
+/// How long higher-priority pairs still pending may keep the first
+/// successful one from being nominated.
+pub const NOMINATION_GRACE: Duration = Duration::from_millis(150);
+/// Most candidates a peer advertises or a checklist accepts.
+pub const MAX_CANDIDATES: usize = 16;
+
+/// RFC 8445 §5.1.2.2 type preferences.
+fn type_preference(kind: CandidateKind) -> u32 {
+    match kind {
+        CandidateKind::Host => 126,
+        CandidateKind::Prflx => 110,
+        CandidateKind::Srflx => 100,
+        CandidateKind::Relay => 0,
+    }
+}
+
+/// RFC 8445 §5.1.2.1 priority for a single-component candidate. IPv6 gets
+/// the higher local preference, as RFC 8421 recommends.
+pub fn candidate_priority(kind: CandidateKind, addr: SocketAddr) -> u32 {
+    let local_preference: u32 = if addr.is_ipv6() { 65535 } else { 65534 };
+    (type_preference(kind) << 24) | (local_preference << 8) | 255
+}
+
+pub fn candidate(kind: CandidateKind, addr: SocketAddr) -> IceCandidate {
+    IceCandidate {
+        kind,
+        addr: addr.to_string(),
+        priority: candidate_priority(kind, addr),
+    }
+}
+
+/// RFC 8445 §6.1.2.3 pair priority from the controlling (`g`) and
+/// controlled (`d`) candidate priorities.
+pub fn pair_priority(g: u32, d: u32) -> u64 {
+    let (g, d) = (u64::from(g), u64::from(d));
+    (g.min(d) << 32) + 2 * g.max(d) + u64::from(g > d)
+}
+
+/// Whether a datagram is a connectivity check or its answer rather than
+/// RIFT or relay traffic.
+pub fn is_check(datagram: &[u8]) -> bool {
+    StunMessage::is_stun(datagram)
+}
+
+/// The answer to a connectivity check that arrived from `from`, or `None`
+/// when `datagram` is not one.
+pub fn check_response(datagram: &[u8], from: SocketAddr) -> Option<Vec<u8>> {
+    let request = StunMessage::decode(datagram).ok()?;
+    if request.msg_type != BINDING_REQUEST {
+        return None;
+    }
+    let from = SocketAddr::new(from.ip().to_canonical(), from.port());
+    Some(request.encode_binding_response(from))
+}
+
+/// A connectivity check the controlled side sends towards the controlling
+/// side's candidates, opening its own NAT for their checks.
+pub fn check_request() -> Vec<u8> {
+    StunMessage::new_binding_request().encode()
+}
+
+/// Remote candidates worth checking: parsable, direct, not link-local (the
+/// zone of a link-local address means nothing to the other side), and at
+/// most [`MAX_CANDIDATES`].
+pub fn direct_candidates(remote: &[IceCandidate]) -> Vec<(SocketAddr, &IceCandidate)> {
+    let mut direct: Vec<(SocketAddr, &IceCandidate)> = Vec::new();
+    for candidate in remote.iter().take(MAX_CANDIDATES) {
+        if candidate.kind == CandidateKind::Relay {
+            continue;
+        }
+        let Ok(addr) = candidate.addr.parse::<SocketAddr>() else {
+            continue;
+        };
+        let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+        if is_link_local(addr.ip()) || addr.ip().is_unspecified() || addr.port() == 0 {
+            continue;
+        }
+        if !direct.iter().any(|(seen, _)| *seen == addr) {
+            direct.push((addr, candidate));
+        }
+    }
+    direct
+}
+
+fn is_link_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_link_local(),
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairState {
+    Waiting,
+    InProgress,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct CandidatePair {
+    pub remote: SocketAddr,
+    pub remote_kind: CandidateKind,
+    pub priority: u64,
+    pub state: PairState,
+    /// This socket's address as the peer saw the check arrive.
+    pub mapped: Option<SocketAddr>,
+    pub rtt: Option<Duration>,
+    transaction_id: [u8; 12],
+    attempts: u32,
+    first_sent: Option<Instant>,
+    retransmit_at: Option<Instant>,
+}
+
+/// The controlling side's pairs and their check state.
+#[derive(Debug, Clone)]
+pub struct Checklist {
+    /// Highest priority first.
+    pairs: Vec<CandidatePair>,
+    next_check: Instant,
+    first_success: Option<Instant>,
+}
+
+impl Checklist {
+    /// Pairs this socket with each direct remote candidate. Local candidates
+    /// only set the pair priorities; a remote candidate in a family none of
+    /// them has is left out, as this socket cannot reach it.
+    pub fn new(local: &[IceCandidate], remote: &[IceCandidate], now: Instant) -> Self {
+        let local: Vec<(SocketAddr, u32)> = local
+            .iter()
+            .filter_map(|c| Some((c.addr.parse::<SocketAddr>().ok()?, c.priority)))
+            .collect();
+        let mut pairs: Vec<CandidatePair> = direct_candidates(remote)
+            .into_iter()
+            .filter_map(|(addr, remote)| {
+                let local_priority = if local.is_empty() {
+                    candidate_priority(CandidateKind::Host, addr)
+                } else {
+                    local
+                        .iter()
+                        .filter(|(local, _)| local.is_ipv6() == addr.is_ipv6())
+                        .map(|(_, priority)| *priority)
+                        .max()?
+                };
+                Some(CandidatePair {
+                    remote: addr,
+                    remote_kind: remote.kind,
+                    priority: pair_priority(local_priority, remote.priority),
+                    state: PairState::Waiting,
+                    mapped: None,
+                    rtt: None,
+                    transaction_id: [0; 12],
+                    attempts: 0,
+                    first_sent: None,
+                    retransmit_at: None,
+                })
+            })
+            .collect();
+        pairs.sort_by_key(|pair| std::cmp::Reverse(pair.priority));
+        Self {
+            pairs,
+            next_check: now,
+            first_success: None,
+        }
+    }
+
+    pub fn pairs(&self) -> &[CandidatePair] {
+        &self.pairs
+    }
+
+    /// The next check due at `now`, as a destination and datagram:
+    /// retransmissions first, then the best waiting pair once the pacing
+    /// interval has passed. Call until it returns `None`.
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<(SocketAddr, Vec<u8>)> {
+        for pair in &mut self.pairs {
+            if pair.state != PairState::InProgress || pair.retransmit_at.is_some_and(|at| at > now)
+            {
+                continue;
+            }
+            if pair.attempts >= MAX_CHECK_ATTEMPTS {
+                pair.state = PairState::Failed;
+                pair.retransmit_at = None;
+                continue;
+            }
+            return Some(Self::send(pair, now));
+        }
+
+        if now < self.next_check {
+            return None;
+        }
+        let pair = self
+            .pairs
+            .iter_mut()
+            .find(|pair| pair.state == PairState::Waiting)?;
+        pair.state = PairState::InProgress;
+        pair.transaction_id = StunMessage::new_binding_request().transaction_id;
+        pair.first_sent = Some(now);
+        self.next_check = now + CHECK_PACING;
+        Some(Self::send(pair, now))
+    }
+
+    fn send(pair: &mut CandidatePair, now: Instant) -> (SocketAddr, Vec<u8>) {
+        pair.retransmit_at = Some(now + CHECK_RTO * 2u32.pow(pair.attempts));
+        pair.attempts += 1;
+        let request = StunMessage {
+            msg_type: BINDING_REQUEST,
+            transaction_id: pair.transaction_id,
+        };
+        (pair.remote, request.encode())
+    }
+
+    /// Matches a datagram from `from` against the outstanding checks.
+    /// Returns whether it answered one.
+    pub fn handle_response(&mut self, from: SocketAddr, datagram: &[u8], now: Instant) -> bool {
+        let Ok(response) = StunMessage::decode(datagram) else {
+            return false;
+        };
+        if response.msg_type != BINDING_RESPONSE {
+            return false;
+        }
+        let from = SocketAddr::new(from.ip().to_canonical(), from.port());
+        let Some(pair) = self.pairs.iter_mut().find(|pair| {
+            pair.state == PairState::InProgress
+                && pair.remote == from
+                && pair.transaction_id == response.transaction_id
+        }) else {
+            return false;
+        };
+        pair.state = PairState::Succeeded;
+        pair.retransmit_at = None;
+        pair.mapped = StunMessage::decode_address(datagram).ok();
+        pair.rtt = pair
+            .first_sent
+            .map(|sent| now.saturating_duration_since(sent));
+        self.first_success.get_or_insert(now);
+        true
+    }
+
+    /// The highest-priority pair that answered.
+    pub fn nominated(&self) -> Option<&CandidatePair> {
+        self.pairs
+            .iter()
+            .find(|pair| pair.state == PairState::Succeeded)
+    }
+
+    /// Whether checking can stop: every pair has an outcome, or one
+    /// succeeded and no better pair is pending or the grace period is over.
+    pub fn is_complete(&self, now: Instant) -> bool {
+        let pending =
+            |pair: &CandidatePair| matches!(pair.state, PairState::Waiting | PairState::InProgress);
+        match self
+            .pairs
+            .iter()
+            .position(|pair| pair.state == PairState::Succeeded)
+        {
+            Some(best) => {
+                !self.pairs[..best].iter().any(pending)
+                    || self
+                        .first_success
+                        .is_some_and(|at| now >= at + NOMINATION_GRACE)
+            }
+            None => !self.pairs.iter().any(pending),
+        }
+    }
+
+    /// When [`Self::poll_transmit`] or [`Self::is_complete`] next has
+    /// something new to say.
+    pub fn next_timeout(&self) -> Option<Instant> {
+        let retransmit = self
+            .pairs
+            .iter()
+            .filter_map(|pair| pair.retransmit_at)
+            .min();
+        let new_check = self
+            .pairs
+            .iter()
+            .any(|pair| pair.state == PairState::Waiting)
+            .then_some(self.next_check);
+        let grace = self.first_success.map(|at| at + NOMINATION_GRACE);
+        [retransmit, new_check, grace].into_iter().flatten().min()
+    }
+
+    /// Remote addresses in the order to try the handshake: answered pairs by
+    /// priority, then the rest by priority.
+    pub fn ranked_addrs(&self) -> Vec<SocketAddr> {
+        let answered = self
+            .pairs
+            .iter()
+            .filter(|pair| pair.state == PairState::Succeeded);
+        let unanswered = self
+            .pairs
+            .iter()
+            .filter(|pair| pair.state != PairState::Succeeded);
+        answered.chain(unanswered).map(|pair| pair.remote).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn priorities_prefer_host_then_ipv6() {
+        let host_v4 = candidate_priority(CandidateKind::Host, addr("192.168.1.2:5000"));
+        let host_v6 = candidate_priority(CandidateKind::Host, addr("[2001:db8::2]:5000"));
+        let srflx = candidate_priority(CandidateKind::Srflx, addr("203.0.113.7:5000"));
+        let relay = candidate_priority(CandidateKind::Relay, addr("198.51.100.1:3478"));
+        assert!(host_v6 > host_v4 && host_v4 > srflx && srflx > relay);
+        assert_eq!(host_v4, 2_130_706_175);
+
+        assert!(pair_priority(host_v4, srflx) > pair_priority(srflx, srflx));
+        // The tie-break bit keeps both sides' orderings identical.
+        assert_eq!(
+            pair_priority(host_v4, srflx) - 1,
+            pair_priority(srflx, host_v4)
+        );
+    }
+
+    #[test]
+    fn checks_are_answered_with_the_mapped_address() {
+        let request = check_request();
+        assert!(is_check(&request));
+        let reply = check_response(&request, addr("[::ffff:203.0.113.7]:40000")).unwrap();
+        assert_eq!(
+            StunMessage::decode_address(&reply).unwrap(),
+            addr("203.0.113.7:40000")
+        );
+        let reply_v6 = check_response(&request, addr("[2001:db8::7]:40000")).unwrap();
+        assert_eq!(
+            StunMessage::decode_address(&reply_v6).unwrap(),
+            addr("[2001:db8::7]:40000")
+        );
+        // Answers are not answered again.
+        assert_eq!(check_response(&reply, addr("203.0.113.7:40000")), None);
+
+        let rift = crate::PhysicalPacket {
+            version: crate::RIFT_VERSION,
+            session_id: Some(0),
+            session_alias: None,
+            packet_id: 0,
+            payload: bytes::Bytes::from_static(&[0u8; 32]),
+        }
+        .encode();
+        assert!(!is_check(&rift));
+    }
+
+    #[test]
+    fn checklist_nominates_the_best_answering_pair() {
+        let start = Instant::now();
+        let local = [
+            candidate(CandidateKind::Host, addr("192.168.1.2:6000")),
+            candidate(CandidateKind::Srflx, addr("198.51.100.2:6000")),
+        ];
+        let remote = [
+            candidate(CandidateKind::Srflx, addr("203.0.113.7:5000")),
+            candidate(CandidateKind::Host, addr("192.168.1.20:5000")),
+            candidate(CandidateKind::Host, addr("[2001:db8::20]:5000")),
+            candidate(CandidateKind::Relay, addr("198.51.100.9:3478")),
+        ];
+        let mut checklist = Checklist::new(&local, &remote, start);
+        // No local IPv6 candidate, and relays are not checked.
+        assert_eq!(checklist.pairs().len(), 2);
+        assert_eq!(checklist.pairs()[0].remote, addr("192.168.1.20:5000"));
+
+        let (first, request) = checklist.poll_transmit(start).unwrap();
+        assert_eq!(first, addr("192.168.1.20:5000"));
+        // Paced: the second check waits for the next slot.
+        assert!(checklist.poll_transmit(start).is_none());
+        let (second, second_request) = checklist.poll_transmit(start + CHECK_PACING).unwrap();
+        assert_eq!(second, addr("203.0.113.7:5000"));
+
+        // Only the server-reflexive pair answers.
+        let reply = check_response(&second_request, addr("198.51.100.2:6001")).unwrap();
+        assert!(!checklist.handle_response(first, &reply, start + CHECK_PACING));
+        assert!(checklist.handle_response(second, &reply, start + CHECK_PACING * 2));
+        let nominated = checklist.nominated().unwrap();
+        assert_eq!(nominated.remote, second);
+        assert_eq!(nominated.mapped, Some(addr("198.51.100.2:6001")));
+        // The better host pair is still in flight.
+        assert!(!checklist.is_complete(start + CHECK_PACING * 2));
+        assert!(checklist.is_complete(start + CHECK_PACING * 2 + NOMINATION_GRACE));
+        assert_eq!(checklist.ranked_addrs(), vec![second, first]);
+
+        // The host pair retransmits with backoff, then fails.
+        let mut retries = 0;
+        for ms in (0..2000).step_by(10) {
+            while let Some((dest, retry)) =
+                checklist.poll_transmit(start + Duration::from_millis(ms))
+            {
+                assert_eq!(dest, first);
+                assert_eq!(retry, request);
+                retries += 1;
+            }
+        }
+        assert_eq!(retries, MAX_CHECK_ATTEMPTS - 1);
+        assert_eq!(checklist.pairs()[0].state, PairState::Failed);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::input_message::Event;
+use crate::{
+    GamepadAxis, GamepadButton, GamepadMessage, InputMessage, MouseMove, MouseRelative, Scroll,
+    TextInput, Touch, TouchPhase,
+};
+
+/// Largest scroll step accepted per event, in wheel notches.
+pub const MAX_SCROLL_NOTCHES: f32 = 32.0;
+/// Largest relative pointer step accepted per event, in pixels per axis.
+pub const MAX_POINTER_DELTA: i32 = 2048;
+/// Upper bound on axes or buttons carried in a single gamepad event.
+pub const MAX_GAMEPAD_ENTRIES: usize = 32;
+/// Highest gamepad slot a peer may address.
+pub const MAX_GAMEPAD_ID: u32 = 15;
+/// Longest text accepted per event, in UTF-8 bytes.
+pub const MAX_TEXT_INPUT_BYTES: usize = 256;
+/// Most events a host takes from one `InputBatch`.
+pub const MAX_INPUT_BATCH_EVENTS: usize = 64;
+/// Stick deadzone used when none, or a non-finite one, is configured.
+pub const DEFAULT_GAMEPAD_DEADZONE: f32 = 0.1;
+
+pub fn normalize_gamepad_deadzone(deadzone: f32) -> f32 {
+    if deadzone.is_finite() {
+        deadzone.clamp(0.0, 0.95)
+    } else {
+        DEFAULT_GAMEPAD_DEADZONE
+    }
+}
+
+pub fn apply_gamepad_deadzone(value: f32, deadzone: f32) -> f32 {
+    let deadzone = normalize_gamepad_deadzone(deadzone);
+    let abs = value.abs();
+    if abs <= deadzone {
+        0.0
+    } else {
+        let scaled = (abs - deadzone) / (1.0 - deadzone);
+        scaled.copysign(value).clamp(-1.0, 1.0)
+    }
+}
+
+/// The events `msg` carries, oldest first: its own, or those of its batch.
+/// Batches inside a batch are dropped, as is anything past
+/// [`MAX_INPUT_BATCH_EVENTS`].
+pub fn unbundle_input(msg: InputMessage) -> Vec<Event> {
+    match msg.event {
+        Some(Event::Batch(batch)) => batch
+            .events
+            .into_iter()
+            .take(MAX_INPUT_BATCH_EVENTS)
+            .filter_map(|msg| msg.event)
+            .filter(|event| !matches!(event, Event::Batch(_)))
+            .collect(),
+        Some(event) => vec![event],
+        None => Vec::new(),
+    }
+}
+
+/// Apply the host-side input policy shared by native and web peers.
+///
+/// Non-finite values drop the event; positions, scroll steps and axes are
+/// clamped to the ranges injectors expect.
+pub fn sanitize_input_event(event: Event) -> Option<Event> {
+    match event {
+        Event::MouseMove(MouseMove { x, y }) => {
+            if !x.is_finite() || !y.is_finite() {
+                return None;
+            }
+            Some(Event::MouseMove(MouseMove {
+                x: x.clamp(0.0, 1.0),
+                y: y.clamp(0.0, 1.0),
+            }))
+        }
+        Event::MouseRelative(MouseRelative { dx, dy }) => {
+            Some(Event::MouseRelative(MouseRelative {
+                dx: dx.clamp(-MAX_POINTER_DELTA, MAX_POINTER_DELTA),
+                dy: dy.clamp(-MAX_POINTER_DELTA, MAX_POINTER_DELTA),
+            }))
+        }
+        Event::Scroll(Scroll { dx, dy }) => {
+            if !dx.is_finite() || !dy.is_finite() {
+                return None;
+            }
+            Some(Event::Scroll(Scroll {
+                dx: dx.clamp(-MAX_SCROLL_NOTCHES, MAX_SCROLL_NOTCHES),
+                dy: dy.clamp(-MAX_SCROLL_NOTCHES, MAX_SCROLL_NOTCHES),
+            }))
+        }
+        Event::Gamepad(gamepad) => {
+            if gamepad.gamepad_id > MAX_GAMEPAD_ID {
+                return None;
+            }
+            let axes: Vec<GamepadAxis> = gamepad
+                .axes
+                .into_iter()
+                .filter(|a| a.value.is_finite())
+                .take(MAX_GAMEPAD_ENTRIES)
+                .map(|a| GamepadAxis {
+                    axis: a.axis,
+                    value: a.value.clamp(-1.0, 1.0),
+                })
+                .collect();
+            let buttons: Vec<GamepadButton> = gamepad
+                .buttons
+                .into_iter()
+                .take(MAX_GAMEPAD_ENTRIES)
+                .collect();
+            if axes.is_empty() && buttons.is_empty() {
+                return None;
+            }
+            Some(Event::Gamepad(GamepadMessage {
+                gamepad_id: gamepad.gamepad_id,
+                axes,
+                buttons,
+            }))
+        }
+        Event::Touch(touch) => {
+            TouchPhase::try_from(touch.phase).ok()?;
+            if !touch.x.is_finite() || !touch.y.is_finite() {
+                return None;
+            }
+            Some(Event::Touch(Touch {
+                x: touch.x.clamp(0.0, 1.0),
+                y: touch.y.clamp(0.0, 1.0),
+                ..touch
+            }))
+        }
+        Event::Text(TextInput { text }) => {
+            // Control characters other than newline and tab would reach the
+            // host as shortcuts.
+            let mut clean = String::with_capacity(text.len().min(MAX_TEXT_INPUT_BYTES));
+            for c in text
+                .chars()
+                .filter(|&c| !c.is_control() || c == '\n' || c == '\t')
+            {
+                if clean.len() + c.len_utf8() > MAX_TEXT_INPUT_BYTES {
+                    break;
+                }
+                clean.push(c);
+            }
+            if clean.is_empty() {
+                return None;
+            }
+            Some(Event::Text(TextInput { text: clean }))
+        }
+        other @ (Event::Key(_) | Event::MouseButton(_)) => Some(other),
+        // Batches are unbundled before their events are sanitized.
+        Event::Batch(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_rescales_remaining_range() {
+        assert_eq!(apply_gamepad_deadzone(0.05, 0.1), 0.0);
+        assert!((apply_gamepad_deadzone(0.55, 0.1) - 0.5).abs() < 1e-6);
+        assert_eq!(apply_gamepad_deadzone(-2.0, 0.1), -1.0);
+        assert_eq!(normalize_gamepad_deadzone(2.0), 0.95);
+        assert_eq!(normalize_gamepad_deadzone(-1.0), 0.0);
+        assert_eq!(
+            normalize_gamepad_deadzone(f32::NAN),
+            DEFAULT_GAMEPAD_DEADZONE
+        );
+        assert_eq!(apply_gamepad_deadzone(0.05, f32::INFINITY), 0.0);
+    }
+
+    #[test]
+    fn sanitize_clamps_and_drops_invalid_events() {
+        let clamped = sanitize_input_event(Event::MouseMove(MouseMove { x: 1.5, y: -0.2 }));
+        assert_eq!(
+            clamped,
+            Some(Event::MouseMove(MouseMove { x: 1.0, y: 0.0 }))
+        );
+        assert_eq!(
+            sanitize_input_event(Event::MouseRelative(MouseRelative {
+                dx: i32::MIN,
+                dy: 12
+            })),
+            Some(Event::MouseRelative(MouseRelative {
+                dx: -MAX_POINTER_DELTA,
+                dy: 12
+            }))
+        );
+        assert!(sanitize_input_event(Event::Scroll(Scroll {
+            dx: f32::NAN,
+            dy: 0.0
+        }))
+        .is_none());
+
+        let gamepad = sanitize_input_event(Event::Gamepad(GamepadMessage {
+            gamepad_id: 0,
+            axes: vec![
+                GamepadAxis {
+                    axis: 0,
+                    value: 3.0,
+                },
+                GamepadAxis {
+                    axis: 1,
+                    value: f32::INFINITY,
+                },
+            ],
+            buttons: vec![],
+        }));
+        let Some(Event::Gamepad(gamepad)) = gamepad else {
+            panic!("gamepad event dropped");
+        };
+        assert_eq!(gamepad.axes.len(), 1);
+        assert_eq!(gamepad.axes[0].value, 1.0);
+
+        assert!(sanitize_input_event(Event::Gamepad(GamepadMessage {
+            gamepad_id: MAX_GAMEPAD_ID + 1,
+            axes: vec![],
+            buttons: vec![GamepadButton {
+                button: 0,
+                pressed: true
+            }],
+        }))
+        .is_none());
+
+        let touch = Touch {
+            contact_id: 3,
+            phase: TouchPhase::Move as i32,
+            x: 0.25,
+            y: 1.2,
+        };
+        assert_eq!(
+            sanitize_input_event(Event::Touch(touch)),
+            Some(Event::Touch(Touch { y: 1.0, ..touch }))
+        );
+        assert!(sanitize_input_event(Event::Touch(Touch { phase: 9, ..touch })).is_none());
+        assert!(sanitize_input_event(Event::Touch(Touch {
+            x: f32::NAN,
+            ..touch
+        }))
+        .is_none());
+    }
+
+    #[test]
+    fn batches_unbundle_in_order_without_nesting() {
+        let input = |event| InputMessage {
+            timestamp_us: 1,
+            event: Some(event),
+        };
+        let key = Event::Key(crate::Key {
+            keycode: 30,
+            pressed: true,
+        });
+        let motion = Event::MouseRelative(MouseRelative { dx: 3, dy: -1 });
+        let nested = Event::Batch(crate::InputBatch {
+            events: vec![input(key.clone())],
+        });
+        let batch = input(Event::Batch(crate::InputBatch {
+            events: vec![input(motion.clone()), input(nested), input(key.clone())],
+        }));
+        assert_eq!(unbundle_input(batch), vec![motion.clone(), key.clone()]);
+        assert_eq!(unbundle_input(input(key.clone())), vec![key.clone()]);
+
+        let flood = input(Event::Batch(crate::InputBatch {
+            events: vec![input(motion); MAX_INPUT_BATCH_EVENTS + 10],
+        }));
+        assert_eq!(unbundle_input(flood).len(), MAX_INPUT_BATCH_EVENTS);
+        assert!(sanitize_input_event(Event::Batch(crate::InputBatch { events: vec![] })).is_none());
+    }
+
+    #[test]
+    fn sanitize_strips_control_characters_from_text() {
+        let text = |text: &str| {
+            sanitize_input_event(Event::Text(TextInput {
+                text: text.to_string(),
+            }))
+        };
+        assert_eq!(
+            text("é\u{1b}ñ\n"),
+            Some(Event::Text(TextInput {
+                text: "éñ\n".to_string()
+            }))
+        );
+        assert!(text("\u{7f}\u{3}").is_none());
+
+        // Truncation keeps whole characters.
+        let Some(Event::Text(long)) = text(&"ü".repeat(MAX_TEXT_INPUT_BYTES)) else {
+            panic!("text dropped");
+        };
+        assert_eq!(long.text.len(), MAX_TEXT_INPUT_BYTES);
+        assert_eq!(long.text.chars().count(), MAX_TEXT_INPUT_BYTES / 2);
+    }
+}
@@ -0,0 +1,394 @@
+//! Physical keys and their codes on each platform.
+//!
+//! Protocol keycodes are Linux evdev codes whatever the client runs on, so
+//! injectors look the key up here instead of passing the number through, and
+//! clients that only know a platform or DOM code translate it here first.
+
+macro_rules! physical_keys {
+    ($($key:ident => $evdev:expr, $vk:expr, $mac:expr;)*) => {
+        /// A key by position on a US layout, named after the W3C `code` values.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum PhysicalKey {
+            $($key,)*
+        }
+
+        /// Evdev code, Windows virtual-key code and macOS CGKeyCode per key.
+        const KEYS: &[(PhysicalKey, u16, Option<u16>, Option<u16>)] = &[
+            $((PhysicalKey::$key, $evdev, $vk, $mac),)*
+        ];
+
+        impl PhysicalKey {
+            /// Reads a browser `KeyboardEvent.code`.
+            pub fn from_dom_code(code: &str) -> Option<Self> {
+                match code {
+                    $(stringify!($key) => Some(Self::$key),)*
+                    // Older Firefox releases name the Meta keys after the OS key.
+                    "OSLeft" => Some(Self::MetaLeft),
+                    "OSRight" => Some(Self::MetaRight),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+physical_keys! {
+    Escape => 1, Some(0x1B), Some(0x35);
+    Digit1 => 2, Some(0x31), Some(0x12);
+    Digit2 => 3, Some(0x32), Some(0x13);
+    Digit3 => 4, Some(0x33), Some(0x14);
+    Digit4 => 5, Some(0x34), Some(0x15);
+    Digit5 => 6, Some(0x35), Some(0x17);
+    Digit6 => 7, Some(0x36), Some(0x16);
+    Digit7 => 8, Some(0x37), Some(0x1A);
+    Digit8 => 9, Some(0x38), Some(0x1C);
+    Digit9 => 10, Some(0x39), Some(0x19);
+    Digit0 => 11, Some(0x30), Some(0x1D);
+    Minus => 12, Some(0xBD), Some(0x1B);
+    Equal => 13, Some(0xBB), Some(0x18);
+    Backspace => 14, Some(0x08), Some(0x33);
+    Tab => 15, Some(0x09), Some(0x30);
+    KeyQ => 16, Some(0x51), Some(0x0C);
+    KeyW => 17, Some(0x57), Some(0x0D);
+    KeyE => 18, Some(0x45), Some(0x0E);
+    KeyR => 19, Some(0x52), Some(0x0F);
+    KeyT => 20, Some(0x54), Some(0x11);
+    KeyY => 21, Some(0x59), Some(0x10);
+    KeyU => 22, Some(0x55), Some(0x20);
+    KeyI => 23, Some(0x49), Some(0x22);
+    KeyO => 24, Some(0x4F), Some(0x1F);
+    KeyP => 25, Some(0x50), Some(0x23);
+    BracketLeft => 26, Some(0xDB), Some(0x21);
+    BracketRight => 27, Some(0xDD), Some(0x1E);
+    Enter => 28, Some(0x0D), Some(0x24);
+    ControlLeft => 29, Some(0xA2), Some(0x3B);
+    KeyA => 30, Some(0x41), Some(0x00);
+    KeyS => 31, Some(0x53), Some(0x01);
+    KeyD => 32, Some(0x44), Some(0x02);
+    KeyF => 33, Some(0x46), Some(0x03);
+    KeyG => 34, Some(0x47), Some(0x05);
+    KeyH => 35, Some(0x48), Some(0x04);
+    KeyJ => 36, Some(0x4A), Some(0x26);
+    KeyK => 37, Some(0x4B), Some(0x28);
+    KeyL => 38, Some(0x4C), Some(0x25);
+    Semicolon => 39, Some(0xBA), Some(0x29);
+    Quote => 40, Some(0xDE), Some(0x27);
+    Backquote => 41, Some(0xC0), Some(0x32);
+    ShiftLeft => 42, Some(0xA0), Some(0x38);
+    Backslash => 43, Some(0xDC), Some(0x2A);
+    KeyZ => 44, Some(0x5A), Some(0x06);
+    KeyX => 45, Some(0x58), Some(0x07);
+    KeyC => 46, Some(0x43), Some(0x08);
+    KeyV => 47, Some(0x56), Some(0x09);
+    KeyB => 48, Some(0x42), Some(0x0B);
+    KeyN => 49, Some(0x4E), Some(0x2D);
+    KeyM => 50, Some(0x4D), Some(0x2E);
+    Comma => 51, Some(0xBC), Some(0x2B);
+    Period => 52, Some(0xBE), Some(0x2F);
+    Slash => 53, Some(0xBF), Some(0x2C);
+    ShiftRight => 54, Some(0xA1), Some(0x3C);
+    NumpadMultiply => 55, Some(0x6A), Some(0x43);
+    AltLeft => 56, Some(0xA4), Some(0x3A);
+    Space => 57, Some(0x20), Some(0x31);
+    CapsLock => 58, Some(0x14), Some(0x39);
+    F1 => 59, Some(0x70), Some(0x7A);
+    F2 => 60, Some(0x71), Some(0x78);
+    F3 => 61, Some(0x72), Some(0x63);
+    F4 => 62, Some(0x73), Some(0x76);
+    F5 => 63, Some(0x74), Some(0x60);
+    F6 => 64, Some(0x75), Some(0x61);
+    F7 => 65, Some(0x76), Some(0x62);
+    F8 => 66, Some(0x77), Some(0x64);
+    F9 => 67, Some(0x78), Some(0x65);
+    F10 => 68, Some(0x79), Some(0x6D);
+    NumLock => 69, Some(0x90), Some(0x47);
+    ScrollLock => 70, Some(0x91), None;
+    Numpad7 => 71, Some(0x67), Some(0x59);
+    Numpad8 => 72, Some(0x68), Some(0x5B);
+    Numpad9 => 73, Some(0x69), Some(0x5C);
+    NumpadSubtract => 74, Some(0x6D), Some(0x4E);
+    Numpad4 => 75, Some(0x64), Some(0x56);
+    Numpad5 => 76, Some(0x65), Some(0x57);
+    Numpad6 => 77, Some(0x66), Some(0x58);
+    NumpadAdd => 78, Some(0x6B), Some(0x45);
+    Numpad1 => 79, Some(0x61), Some(0x53);
+    Numpad2 => 80, Some(0x62), Some(0x54);
+    Numpad3 => 81, Some(0x63), Some(0x55);
+    Numpad0 => 82, Some(0x60), Some(0x52);
+    NumpadDecimal => 83, Some(0x6E), Some(0x41);
+    IntlBackslash => 86, Some(0xE2), Some(0x0A);
+    F11 => 87, Some(0x7A), Some(0x67);
+    F12 => 88, Some(0x7B), Some(0x6F);
+    // Windows has no virtual key of its own for numpad Enter; the extended
+    // flag tells it apart from Enter.
+    NumpadEnter => 96, Some(0x0D), Some(0x4C);
+    ControlRight => 97, Some(0xA3), Some(0x3E);
+    NumpadDivide => 98, Some(0x6F), Some(0x4B);
+    PrintScreen => 99, Some(0x2C), None;
+    AltRight => 100, Some(0xA5), Some(0x3D);
+    Home => 102, Some(0x24), Some(0x73);
+    ArrowUp => 103, Some(0x26), Some(0x7E);
+    PageUp => 104, Some(0x21), Some(0x74);
+    ArrowLeft => 105, Some(0x25), Some(0x7B);
+    ArrowRight => 106, Some(0x27), Some(0x7C);
+    End => 107, Some(0x23), Some(0x77);
+    ArrowDown => 108, Some(0x28), Some(0x7D);
+    PageDown => 109, Some(0x22), Some(0x79);
+    // Mac keyboards put Help where Insert is.
+    Insert => 110, Some(0x2D), Some(0x72);
+    Delete => 111, Some(0x2E), Some(0x75);
+    AudioVolumeMute => 113, Some(0xAD), Some(0x4A);
+    AudioVolumeDown => 114, Some(0xAE), Some(0x49);
+    AudioVolumeUp => 115, Some(0xAF), Some(0x48);
+    NumpadEqual => 117, None, Some(0x51);
+    Pause => 119, Some(0x13), None;
+    MetaLeft => 125, Some(0x5B), Some(0x37);
+    MetaRight => 126, Some(0x5C), Some(0x36);
+    ContextMenu => 127, Some(0x5D), Some(0x6E);
+    MediaTrackNext => 163, Some(0xB0), None;
+    MediaPlayPause => 164, Some(0xB3), None;
+    MediaTrackPrevious => 165, Some(0xB1), None;
+    MediaStop => 166, Some(0xB2), None;
+    F13 => 183, Some(0x7C), Some(0x69);
+    F14 => 184, Some(0x7D), Some(0x6B);
+    F15 => 185, Some(0x7E), Some(0x71);
+    F16 => 186, Some(0x7F), Some(0x6A);
+    F17 => 187, Some(0x80), Some(0x40);
+    F18 => 188, Some(0x81), Some(0x4F);
+    F19 => 189, Some(0x82), Some(0x50);
+    F20 => 190, Some(0x83), Some(0x5A);
+    F21 => 191, Some(0x84), None;
+    F22 => 192, Some(0x85), None;
+    F23 => 193, Some(0x86), None;
+    F24 => 194, Some(0x87), None;
+    Fn => 464, None, Some(0x3F);
+}
+
+impl PhysicalKey {
+    pub fn from_evdev(code: u16) -> Option<Self> {
+        KEYS.iter()
+            .find(|&&(_, evdev, _, _)| evdev == code)
+            .map(|&(key, ..)| key)
+    }
+
+    /// Reads a protocol keycode; `None` for codes outside the table.
+    pub fn from_protocol(keycode: u32) -> Option<Self> {
+        u16::try_from(keycode).ok().and_then(Self::from_evdev)
+    }
+
+    /// Numpad Enter comes back as Enter, since both share a virtual key.
+    pub fn from_windows_vk(vk: u16) -> Option<Self> {
+        KEYS.iter()
+            .find(|&&(_, _, key_vk, _)| key_vk == Some(vk))
+            .map(|&(key, ..)| key)
+    }
+
+    pub fn from_mac_keycode(code: u16) -> Option<Self> {
+        KEYS.iter()
+            .find(|&&(_, _, _, mac)| mac == Some(code))
+            .map(|&(key, ..)| key)
+    }
+
+    pub fn evdev(self) -> u16 {
+        self.row().1
+    }
+
+    /// The protocol keycode clients send for this key.
+    pub fn protocol(self) -> u32 {
+        u32::from(self.evdev())
+    }
+
+    pub fn windows_vk(self) -> Option<u16> {
+        self.row().2
+    }
+
+    /// Keys SendInput must flag `KEYEVENTF_EXTENDEDKEY`; without it Windows
+    /// reads the arrows and navigation block as the numpad's.
+    pub fn is_windows_extended(self) -> bool {
+        matches!(
+            self,
+            Self::NumpadEnter
+                | Self::ControlRight
+                | Self::NumpadDivide
+                | Self::PrintScreen
+                | Self::AltRight
+                | Self::NumLock
+                | Self::Home
+                | Self::ArrowUp
+                | Self::PageUp
+                | Self::ArrowLeft
+                | Self::ArrowRight
+                | Self::End
+                | Self::ArrowDown
+                | Self::PageDown
+                | Self::Insert
+                | Self::Delete
+                | Self::MetaLeft
+                | Self::MetaRight
+                | Self::ContextMenu
+        )
+    }
+
+    pub fn mac_keycode(self) -> Option<u16> {
+        self.row().3
+    }
+
+    /// The key that types `c` on a US layout, and whether it needs Shift.
+    /// Backends that cannot inject Unicode use this for text input.
+    pub fn for_us_char(c: char) -> Option<(Self, bool)> {
+        const DIGITS: [PhysicalKey; 10] = [
+            PhysicalKey::Digit0,
+            PhysicalKey::Digit1,
+            PhysicalKey::Digit2,
+            PhysicalKey::Digit3,
+            PhysicalKey::Digit4,
+            PhysicalKey::Digit5,
+            PhysicalKey::Digit6,
+            PhysicalKey::Digit7,
+            PhysicalKey::Digit8,
+            PhysicalKey::Digit9,
+        ];
+        const SHIFTED_DIGITS: &str = ")!@#$%^&*(";
+        const LETTERS: [PhysicalKey; 26] = [
+            PhysicalKey::KeyA,
+            PhysicalKey::KeyB,
+            PhysicalKey::KeyC,
+            PhysicalKey::KeyD,
+            PhysicalKey::KeyE,
+            PhysicalKey::KeyF,
+            PhysicalKey::KeyG,
+            PhysicalKey::KeyH,
+            PhysicalKey::KeyI,
+            PhysicalKey::KeyJ,
+            PhysicalKey::KeyK,
+            PhysicalKey::KeyL,
+            PhysicalKey::KeyM,
+            PhysicalKey::KeyN,
+            PhysicalKey::KeyO,
+            PhysicalKey::KeyP,
+            PhysicalKey::KeyQ,
+            PhysicalKey::KeyR,
+            PhysicalKey::KeyS,
+            PhysicalKey::KeyT,
+            PhysicalKey::KeyU,
+            PhysicalKey::KeyV,
+            PhysicalKey::KeyW,
+            PhysicalKey::KeyX,
+            PhysicalKey::KeyY,
+            PhysicalKey::KeyZ,
+        ];
+
+        if c.is_ascii_lowercase() {
+            return Some((LETTERS[(c as u8 - b'a') as usize], false));
+        }
+        if c.is_ascii_uppercase() {
+            return Some((LETTERS[(c as u8 - b'A') as usize], true));
+        }
+        if c.is_ascii_digit() {
+            return Some((DIGITS[(c as u8 - b'0') as usize], false));
+        }
+        if let Some(digit) = SHIFTED_DIGITS.find(c) {
+            return Some((DIGITS[digit], true));
+        }
+        Some(match c {
+            ' ' => (Self::Space, false),
+            '\n' => (Self::Enter, false),
+            '\t' => (Self::Tab, false),
+            '-' => (Self::Minus, false),
+            '_' => (Self::Minus, true),
+            '=' => (Self::Equal, false),
+            '+' => (Self::Equal, true),
+            '[' => (Self::BracketLeft, false),
+            '{' => (Self::BracketLeft, true),
+            ']' => (Self::BracketRight, false),
+            '}' => (Self::BracketRight, true),
+            '\\' => (Self::Backslash, false),
+            '|' => (Self::Backslash, true),
+            ';' => (Self::Semicolon, false),
+            ':' => (Self::Semicolon, true),
+            '\'' => (Self::Quote, false),
+            '"' => (Self::Quote, true),
+            '`' => (Self::Backquote, false),
+            '~' => (Self::Backquote, true),
+            ',' => (Self::Comma, false),
+            '<' => (Self::Comma, true),
+            '.' => (Self::Period, false),
+            '>' => (Self::Period, true),
+            '/' => (Self::Slash, false),
+            '?' => (Self::Slash, true),
+            _ => return None,
+        })
+    }
+
+    fn row(self) -> &'static (PhysicalKey, u16, Option<u16>, Option<u16>) {
+        KEYS.iter()
+            .find(|&&(key, ..)| key == self)
+            .expect("every key has a table row")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique_per_platform() {
+        let evdev: HashSet<u16> = KEYS.iter().map(|row| row.1).collect();
+        assert_eq!(evdev.len(), KEYS.len());
+
+        let macs: Vec<u16> = KEYS.iter().filter_map(|row| row.3).collect();
+        assert_eq!(macs.iter().collect::<HashSet<_>>().len(), macs.len());
+
+        // Numpad Enter reuses Enter's virtual key and nothing else repeats.
+        let vks: Vec<u16> = KEYS.iter().filter_map(|row| row.2).collect();
+        assert_eq!(vks.iter().collect::<HashSet<_>>().len(), vks.len() - 1);
+    }
+
+    #[test]
+    fn translates_between_platforms() {
+        let a = PhysicalKey::from_protocol(30).unwrap();
+        assert_eq!(a, PhysicalKey::KeyA);
+        assert_eq!(a.windows_vk(), Some(0x41));
+        assert_eq!(a.mac_keycode(), Some(0x00));
+        assert_eq!(PhysicalKey::from_windows_vk(0x41), Some(a));
+        assert_eq!(PhysicalKey::from_mac_keycode(0x00), Some(a));
+
+        assert_eq!(PhysicalKey::from_windows_vk(0x0D), Some(PhysicalKey::Enter));
+        assert!(PhysicalKey::NumpadEnter.is_windows_extended());
+        assert!(!PhysicalKey::Enter.is_windows_extended());
+        assert_eq!(PhysicalKey::from_protocol(0x1_0000), None);
+        assert_eq!(PhysicalKey::Escape.protocol(), 1);
+    }
+
+    #[test]
+    fn dom_codes_name_their_keys() {
+        assert_eq!(PhysicalKey::from_dom_code("KeyA"), Some(PhysicalKey::KeyA));
+        assert_eq!(
+            PhysicalKey::from_dom_code("NumpadEnter").map(PhysicalKey::protocol),
+            Some(96)
+        );
+        assert_eq!(
+            PhysicalKey::from_dom_code("OSLeft"),
+            Some(PhysicalKey::MetaLeft)
+        );
+        assert_eq!(PhysicalKey::from_dom_code("keya"), None);
+        assert_eq!(PhysicalKey::from_dom_code(""), None);
+    }
+
+    #[test]
+    fn us_layout_types_printable_ascii() {
+        for c in (' '..='~').chain(['\n', '\t']) {
+            assert!(PhysicalKey::for_us_char(c).is_some(), "{:?} has no key", c);
+        }
+        assert_eq!(
+            PhysicalKey::for_us_char('Q'),
+            Some((PhysicalKey::KeyQ, true))
+        );
+        assert_eq!(
+            PhysicalKey::for_us_char('('),
+            Some((PhysicalKey::Digit9, true))
+        );
+        assert_eq!(PhysicalKey::for_us_char('é'), None);
+    }
+}
@@ -18,6 +18,9 @@ pub mod rift {
 
 pub use rift::*;
 
+pub use fec::{FecBuilder, FecError, FecReassembler};
+pub use permissions::PermissionSet;
+
 pub const RIFT_VERSION: u16 = 1;
 pub const UNASSIGNED_SESSION_ID: u128 = 0;
 
@@ -34,10 +37,27 @@ pub const RIFT_MAGIC: [u8; 2] = [0x52, 0x49]; // 'RI'
 /// Maximum clipboard text size accepted from the network (1 MiB).
 /// Prevents memory exhaustion from malformed or malicious ClipboardMessage payloads.
 pub const MAX_CLIPBOARD_TEXT_BYTES: usize = 1024 * 1024;
+
+impl ClipboardSync {
+    /// What a Hello without the field gets.
+    pub const BOTH: Self = Self {
+        to_host: true,
+        from_host: true,
+    };
+
+    /// The directions `hello` agreed to.
+    pub fn negotiated(hello: &Hello) -> Self {
+        hello.clipboard_sync.unwrap_or(Self::BOTH)
+    }
+}
+
 /// Default maximum file size accepted over file-transfer messages (1 GiB).
 pub const MAX_FILE_TRANSFER_BYTES: u64 = 1024 * 1024 * 1024;
 /// Default chunk payload size for file transfer.
 pub const DEFAULT_FILE_CHUNK_BYTES: usize = 900;
+/// Largest cursor bitmap edge sent in a `CursorUpdate`; bigger cursors are
+/// sent as `SYSTEM_CURSOR_ARROW` so the update fits in one datagram.
+pub const MAX_CURSOR_SIZE: u32 = 96;
 
 #[derive(Debug, thiserror::Error)]
 pub enum RiftError {
@@ -53,11 +73,37 @@ pub enum RiftError {
     ProtoEncode(String),
     #[error("protobuf decode error: {0}")]
     ProtoDecode(String),
+    #[error("compact header before any full transport header")]
+    MissingReference,
+}
+
+impl From<&RiftError> for ErrorCode {
+    fn from(err: &RiftError) -> Self {
+        match err {
+            RiftError::UnsupportedVersion(_) => ErrorCode::UnsupportedVersion,
+            RiftError::ProtoEncode(_) => ErrorCode::Serialization,
+            RiftError::TooShort(_)
+            | RiftError::InvalidMagic(_)
+            | RiftError::ChecksumMismatch
+            | RiftError::ProtoDecode(_)
+            | RiftError::MissingReference => ErrorCode::MalformedPacket,
+        }
+    }
 }
 pub mod cc;
+pub mod compact;
+pub mod fec;
+pub mod feedback;
+pub mod ice;
+pub mod input;
+pub mod keymap;
+pub mod permissions;
+pub mod probe;
+pub mod sim;
 pub mod stun;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use wavry_common::error::ErrorCode;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PhysicalPacket {
@@ -189,6 +235,15 @@ pub fn packet_priority(channel: Channel) -> PacketPriority {
     }
 }
 
+/// Channel a message travels on, as tagged in compact headers.
+pub fn message_channel(msg: &Message) -> Channel {
+    match msg.content {
+        Some(message::Content::Input(_)) => Channel::Input,
+        Some(message::Content::Media(_)) => Channel::Media,
+        Some(message::Content::Control(_)) | None => Channel::Control,
+    }
+}
+
 pub fn chunk_video_payload(
     frame_id: u64,
     timestamp_us: u64,
@@ -216,98 +271,50 @@ pub fn chunk_video_payload(
             payload: chunk.to_vec(),
             capture_us,
             encode_us,
+            stream_id: VIDEO_STREAM_PRIMARY,
+            eye_view: None,
         });
     }
     Ok(chunks)
 }
 
-#[derive(Debug, thiserror::Error, PartialEq, Eq)]
-pub enum ChunkError {
-    #[error("max payload must be non-zero")]
-    InvalidMaxPayload,
-    #[error("too many chunks for a single frame")]
-    TooManyChunks,
-}
+/// Video stream carrying mono frames, packed stereo, or the left eye.
+pub const VIDEO_STREAM_PRIMARY: u32 = 0;
+/// Right-eye video stream under `StereoMode::StereoDualStream`.
+pub const VIDEO_STREAM_RIGHT_EYE: u32 = 1;
+/// Most displays a flat session streams besides the primary one.
+pub const MAX_ADDITIONAL_MONITORS: usize = 3;
 
-#[derive(Debug, thiserror::Error, PartialEq, Eq)]
-pub enum FecError {
-    #[error("shard count must be at least 2")]
-    InvalidShardCount,
+/// Video stream carrying `SelectMonitor.additional_monitor_ids[index]`.
+pub fn monitor_stream_id(index: usize) -> u32 {
+    VIDEO_STREAM_PRIMARY + 1 + index as u32
 }
 
-#[derive(Debug, Clone)]
-pub struct FecBuilder {
-    shard_count: u32,
-    group_id: u64,
-    first_packet_id: Option<u64>,
-    payloads: Vec<Vec<u8>>,
-    shard_lengths: Vec<u32>,
-    max_payload_len: usize,
+/// Index into `SelectMonitor.additional_monitor_ids` of a flat session's
+/// stream, or `None` for the primary stream.
+pub fn monitor_stream_index(stream_id: u32) -> Option<usize> {
+    stream_id
+        .checked_sub(VIDEO_STREAM_PRIMARY + 1)
+        .map(|index| index as usize)
 }
 
-impl FecBuilder {
-    pub fn new(shard_count: u32) -> Result<Self, FecError> {
-        if shard_count < 2 {
-            return Err(FecError::InvalidShardCount);
-        }
-        Ok(Self {
-            shard_count,
-            group_id: 0,
-            first_packet_id: None,
-            payloads: Vec::with_capacity(shard_count as usize),
-            shard_lengths: Vec::with_capacity(shard_count as usize),
-            max_payload_len: 0,
-        })
-    }
-
-    pub fn push(&mut self, packet_id: u64, payload: &[u8]) -> Option<FecPacket> {
-        if self.payloads.is_empty() {
-            self.first_packet_id = Some(packet_id);
-        }
-
-        self.max_payload_len = self.max_payload_len.max(payload.len());
-        self.shard_lengths.push(payload.len() as u32);
-        self.payloads.push(payload.to_vec());
-
-        if self.payloads.len() == (self.shard_count - 1) as usize {
-            let parity_payload = xor_parity(&self.payloads, self.max_payload_len);
-            let packet = FecPacket {
-                group_id: self.group_id,
-                first_packet_id: self.first_packet_id.unwrap_or(packet_id),
-                shard_count: self.shard_count,
-                parity_index: self.shard_count - 1,
-                payload: parity_payload,
-                shard_lengths: self.shard_lengths.clone(),
-            };
-            self.group_id = self.group_id.wrapping_add(1);
-            self.reset();
-            Some(packet)
-        } else {
-            None
-        }
+/// Move one frame's chunks onto `stream_id`, attaching the eye view to the first chunk.
+pub fn tag_stereo_chunks(chunks: &mut [VideoChunk], stream_id: u32, eye_view: Option<EyeView>) {
+    for chunk in chunks.iter_mut() {
+        chunk.stream_id = stream_id;
+        chunk.eye_view = None;
     }
-
-    fn reset(&mut self) {
-        self.payloads.clear();
-        self.shard_lengths.clear();
-        self.max_payload_len = 0;
-        self.first_packet_id = None;
-    }
-}
-
-fn xor_parity(payloads: &[Vec<u8>], max_len: usize) -> Vec<u8> {
-    let mut parity = vec![0u8; max_len];
-    for payload in payloads {
-        xor_in_place(&mut parity, payload, max_len);
+    if let Some(first) = chunks.first_mut() {
+        first.eye_view = eye_view;
     }
-    parity
 }
 
-fn xor_in_place(target: &mut [u8], payload: &[u8], max_len: usize) {
-    let len = payload.len().min(max_len);
-    for i in 0..len {
-        target[i] ^= payload[i];
-    }
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ChunkError {
+    #[error("max payload must be non-zero")]
+    InvalidMaxPayload,
+    #[error("too many chunks for a single frame")]
+    TooManyChunks,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -351,6 +358,12 @@ pub enum HandshakeError {
     InvalidSessionId,
 }
 
+impl From<&HandshakeError> for ErrorCode {
+    fn from(_: &HandshakeError) -> Self {
+        ErrorCode::Protocol
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Handshake {
     role: Role,
@@ -488,6 +501,16 @@ mod tests {
             input_caps: 1, // Keyboard
             protocol_version: 1,
             public_addr: "".to_string(),
+            candidate_addrs: vec![],
+            stereo_modes: vec![],
+            audio_layouts: vec![],
+            fec_schemes: vec![],
+            cursor_channel: false,
+            audio_params: None,
+            compact_header: false,
+            transport_feedback: false,
+            microphone: false,
+            clipboard_sync: None,
         }
     }
 
@@ -505,6 +528,17 @@ mod tests {
             session_id: vec![0u8; 16],
             session_alias: 42,
             public_addr: "".to_string(),
+            candidate_addrs: vec![],
+            stereo_mode: StereoMode::StereoAuto as i32,
+            audio_layout: AudioLayout::AudioStereo as i32,
+            fec_scheme: FecScheme::Xor as i32,
+            cursor_channel: false,
+            audio_params: None,
+            compact_header: false,
+            transport_feedback: false,
+            microphone: false,
+            permissions: 0,
+            input_batch: false,
         }
     }
 
@@ -521,6 +555,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn hellos_without_clipboard_sync_sync_both_ways() {
+        let mut hello = sample_hello();
+        assert_eq!(ClipboardSync::negotiated(&hello), ClipboardSync::BOTH);
+        hello.clipboard_sync = Some(ClipboardSync {
+            to_host: false,
+            from_host: true,
+        });
+        assert!(!ClipboardSync::negotiated(&hello).to_host);
+    }
+
     #[test]
     fn physical_packet_roundtrip() {
         let packet = PhysicalPacket {
@@ -630,6 +675,33 @@ mod tests {
         assert!(matches!(result, Err(ChunkError::InvalidMaxPayload)));
     }
 
+    #[test]
+    fn tag_stereo_chunks_moves_frame_to_eye_stream() {
+        let mut chunks = chunk_video_payload(7, 1000, true, &[0; 700], 300, 0, 0).unwrap();
+        assert!(chunks.iter().all(|c| c.stream_id == VIDEO_STREAM_PRIMARY));
+        let view = EyeView {
+            orientation_w: 1.0,
+            angle_left: -0.8,
+            angle_right: 0.7,
+            ..Default::default()
+        };
+        tag_stereo_chunks(&mut chunks, VIDEO_STREAM_RIGHT_EYE, Some(view));
+        assert!(chunks.iter().all(|c| c.stream_id == VIDEO_STREAM_RIGHT_EYE));
+        assert_eq!(
+            chunks[0].eye_view.as_ref().map(|v| v.angle_left),
+            Some(-0.8)
+        );
+        assert!(chunks[1..].iter().all(|c| c.eye_view.is_none()));
+    }
+
+    #[test]
+    fn monitor_streams_follow_the_primary() {
+        assert_eq!(monitor_stream_index(VIDEO_STREAM_PRIMARY), None);
+        for index in 0..MAX_ADDITIONAL_MONITORS {
+            assert_eq!(monitor_stream_index(monitor_stream_id(index)), Some(index));
+        }
+    }
+
     #[test]
     fn fec_builder_new() {
         let builder = FecBuilder::new(4);
@@ -0,0 +1,133 @@
+//! What a client may do in a session besides watching.
+//!
+//! The host grants a [`PermissionSet`] in `HelloAck.permissions` and may
+//! change it mid-session with a `PermissionUpdate`. The host enforces the set
+//! by dropping messages it does not cover; clients use it to disable the
+//! matching features instead of sending messages that would be dropped.
+
+use std::fmt;
+use std::str::FromStr;
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct PermissionSet: u32 {
+        /// Watch and listen to the stream. Always set on the wire, so a set
+        /// from a host that knows about permissions is never zero.
+        const VIEW = 1;
+        /// Keyboard, mouse, touch and gamepad input.
+        const INPUT = 1 << 1;
+        /// Clipboard sync in both directions.
+        const CLIPBOARD = 1 << 2;
+        /// Sending files to the host.
+        const FILE_TRANSFER = 1 << 3;
+    }
+}
+
+/// Names used on the command line, in display order.
+const NAMES: [(PermissionSet, &str); 3] = [
+    (PermissionSet::INPUT, "input"),
+    (PermissionSet::CLIPBOARD, "clipboard"),
+    (PermissionSet::FILE_TRANSFER, "files"),
+];
+
+impl PermissionSet {
+    /// Reads `HelloAck.permissions` or `PermissionUpdate.permissions`. Hosts
+    /// that predate permissions send 0 and enforce nothing.
+    pub fn from_wire(bits: u32) -> Self {
+        if bits == 0 {
+            Self::all()
+        } else {
+            Self::from_bits_truncate(bits) | Self::VIEW
+        }
+    }
+
+    pub fn to_wire(self) -> u32 {
+        (self | Self::VIEW).bits()
+    }
+}
+
+impl Default for PermissionSet {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromStr for PermissionSet {
+    type Err = String;
+
+    /// Parses `all`, `view-only`, or a comma-separated list such as
+    /// `input,clipboard`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.as_str() {
+            "all" | "full" => return Ok(Self::all()),
+            "view-only" | "view" | "none" => return Ok(Self::VIEW),
+            _ => {}
+        }
+        let mut set = Self::VIEW;
+        for name in s.split(',').map(str::trim) {
+            let Some((flag, _)) = NAMES.iter().find(|(_, known)| *known == name) else {
+                return Err(format!(
+                    "unknown permission '{}', expected all, view-only, or a list of input, clipboard and files",
+                    name
+                ));
+            };
+            set |= *flag;
+        }
+        Ok(set)
+    }
+}
+
+impl fmt::Display for PermissionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.contains(Self::all()) {
+            return f.write_str("all");
+        }
+        let granted: Vec<&str> = NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        if granted.is_empty() {
+            f.write_str("view-only")
+        } else {
+            f.write_str(&granted.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_zero_means_a_host_without_permissions() {
+        assert_eq!(PermissionSet::from_wire(0), PermissionSet::all());
+        assert_eq!(PermissionSet::VIEW.to_wire(), 1);
+        assert_eq!(PermissionSet::from_wire(1), PermissionSet::VIEW);
+        let input = PermissionSet::INPUT;
+        assert_eq!(
+            PermissionSet::from_wire(input.to_wire()),
+            PermissionSet::VIEW | PermissionSet::INPUT
+        );
+    }
+
+    #[test]
+    fn test_parse_and_display_round_trip() {
+        for text in [
+            "all",
+            "view-only",
+            "input",
+            "input,clipboard",
+            "clipboard,files",
+        ] {
+            let set: PermissionSet = text.parse().unwrap();
+            assert_eq!(set.to_string(), text);
+        }
+        assert_eq!(
+            " Input , FILES ".parse::<PermissionSet>().unwrap(),
+            PermissionSet::VIEW | PermissionSet::INPUT | PermissionSet::FILE_TRANSFER
+        );
+        assert!("input,mouse".parse::<PermissionSet>().is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Padded bandwidth probes.
+//!
+//! The host sends a short burst of padding packets paced at a chosen rate on
+//! top of the media stream. The client reports how much of the burst arrived
+//! and over what span, which tells the congestion controller whether the path
+//! has headroom beyond what video traffic currently reveals.
+
+use std::time::{Duration, Instant};
+
+use crate::{ProbePacket, ProbeResult};
+
+/// Padding bytes per probe packet; keeps the datagram under a typical MTU.
+pub const PROBE_PACKET_BYTES: usize = 1200;
+/// Time a burst is spread over at its target rate.
+pub const PROBE_BURST_DURATION: Duration = Duration::from_millis(50);
+/// The receiver closes a burst if no further packet arrives within this window.
+pub const PROBE_RECEIVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+const MIN_BURST_PACKETS: u32 = 5;
+const MAX_BURST_PACKETS: u32 = 250;
+
+/// One probe burst: `count` packets paced to add `rate_kbps` for the burst duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeBurst {
+    pub probe_id: u32,
+    pub rate_kbps: u32,
+    pub count: u32,
+}
+
+impl ProbeBurst {
+    pub fn new(probe_id: u32, rate_kbps: u32) -> Self {
+        let burst_bytes =
+            rate_kbps as u64 * 1000 / 8 * PROBE_BURST_DURATION.as_millis() as u64 / 1000;
+        let count = (burst_bytes / PROBE_PACKET_BYTES as u64)
+            .clamp(MIN_BURST_PACKETS as u64, MAX_BURST_PACKETS as u64) as u32;
+        Self {
+            probe_id,
+            rate_kbps,
+            count,
+        }
+    }
+
+    /// Send offset of packet `seq` from the start of the burst.
+    fn offset(&self, seq: u32) -> Duration {
+        PROBE_BURST_DURATION * seq / self.count
+    }
+
+    fn packet(&self, seq: u32) -> ProbePacket {
+        ProbePacket {
+            probe_id: self.probe_id,
+            seq,
+            count: self.count,
+            rate_kbps: self.rate_kbps,
+            padding: vec![0u8; PROBE_PACKET_BYTES],
+        }
+    }
+}
+
+/// Paces a burst from the host's send loop.
+#[derive(Debug)]
+pub struct ProbeSender {
+    burst: ProbeBurst,
+    started: Instant,
+    next_seq: u32,
+}
+
+impl ProbeSender {
+    pub fn new(burst: ProbeBurst, now: Instant) -> Self {
+        Self {
+            burst,
+            started: now,
+            next_seq: 0,
+        }
+    }
+
+    /// Packets whose scheduled send time has passed. Call once per loop iteration.
+    pub fn poll(&mut self, now: Instant) -> Vec<ProbePacket> {
+        let elapsed = now.saturating_duration_since(self.started);
+        let mut due = Vec::new();
+        while self.next_seq < self.burst.count && self.burst.offset(self.next_seq) <= elapsed {
+            due.push(self.burst.packet(self.next_seq));
+            self.next_seq += 1;
+        }
+        due
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_seq >= self.burst.count
+    }
+}
+
+#[derive(Debug)]
+struct ActiveProbe {
+    probe_id: u32,
+    count: u32,
+    received: u32,
+    span_bytes: u64,
+    first_at: Instant,
+    last_at: Instant,
+}
+
+impl ActiveProbe {
+    fn result(&self) -> ProbeResult {
+        ProbeResult {
+            probe_id: self.probe_id,
+            count: self.count,
+            received: self.received,
+            span_bytes: self.span_bytes,
+            span_us: self.last_at.duration_since(self.first_at).as_micros() as u64,
+        }
+    }
+}
+
+/// Client-side accounting for the burst currently arriving.
+#[derive(Debug, Default)]
+pub struct ProbeReceiver {
+    active: Option<ActiveProbe>,
+}
+
+impl ProbeReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a probe packet. Returns a finished report when this packet
+    /// completes its burst or starts a new one. A packet that does both
+    /// reports its own burst, which supersedes the one it cut short.
+    pub fn on_packet(&mut self, packet: &ProbePacket, now: Instant) -> Option<ProbeResult> {
+        let mut finished = None;
+        if self
+            .active
+            .as_ref()
+            .is_some_and(|active| active.probe_id != packet.probe_id)
+        {
+            finished = self.active.take().map(|active| active.result());
+        }
+
+        let active = self.active.get_or_insert(ActiveProbe {
+            probe_id: packet.probe_id,
+            count: packet.count,
+            received: 0,
+            span_bytes: 0,
+            first_at: now,
+            last_at: now,
+        });
+        if active.received > 0 {
+            active.span_bytes += packet.padding.len() as u64;
+        }
+        active.received = active.received.saturating_add(1);
+        active.last_at = now;
+
+        if packet.seq.saturating_add(1) >= packet.count {
+            return self.active.take().map(|active| active.result());
+        }
+        finished
+    }
+
+    /// Closes a burst whose tail was lost.
+    pub fn poll_timeout(&mut self, now: Instant) -> Option<ProbeResult> {
+        let expired = self.active.as_ref().is_some_and(|active| {
+            now.saturating_duration_since(active.last_at) >= PROBE_RECEIVE_TIMEOUT
+        });
+        if expired {
+            self.active.take().map(|active| active.result())
+        } else {
+            None
+        }
+    }
+}
+
+/// Rate the receiver observed across the burst, if the span is measurable.
+pub fn delivered_kbps(result: &ProbeResult) -> Option<u32> {
+    if result.received < 2 || result.span_us == 0 {
+        return None;
+    }
+    Some((result.span_bytes * 8 * 1000 / result.span_us).min(u32::MAX as u64) as u32)
+}
+
+pub fn loss_fraction(result: &ProbeResult) -> f32 {
+    if result.count == 0 {
+        return 0.0;
+    }
+    result.count.saturating_sub(result.received) as f32 / result.count as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_size_scales_with_rate() {
+        assert_eq!(ProbeBurst::new(1, 100).count, MIN_BURST_PACKETS);
+        // 10 Mbps for 50 ms is 62.5 kB, about 52 packets.
+        assert_eq!(ProbeBurst::new(1, 10_000).count, 52);
+        assert_eq!(ProbeBurst::new(1, 1_000_000).count, MAX_BURST_PACKETS);
+    }
+
+    #[test]
+    fn sender_paces_packets_across_burst() {
+        let start = Instant::now();
+        let mut sender = ProbeSender::new(ProbeBurst::new(7, 10_000), start);
+        assert_eq!(sender.poll(start).len(), 1);
+        let halfway = sender.poll(start + PROBE_BURST_DURATION / 2).len();
+        assert!((24..=27).contains(&halfway), "sent {halfway} by halfway");
+        sender.poll(start + PROBE_BURST_DURATION);
+        assert!(sender.is_done());
+    }
+
+    #[test]
+    fn receiver_reports_rate_over_burst() {
+        let burst = ProbeBurst::new(3, 10_000);
+        let start = Instant::now();
+        let mut receiver = ProbeReceiver::new();
+        let mut report = None;
+        for seq in 0..burst.count {
+            let at = start + burst.offset(seq);
+            report = receiver.on_packet(&burst.packet(seq), at);
+        }
+        let report = report.expect("burst complete");
+        assert_eq!(report.received, burst.count);
+        assert_eq!(loss_fraction(&report), 0.0);
+        let kbps = delivered_kbps(&report).unwrap();
+        assert!((9_500..=10_500).contains(&kbps), "measured {kbps} kbps");
+    }
+
+    #[test]
+    fn receiver_closes_burst_on_timeout_or_next_probe() {
+        let first = ProbeBurst::new(1, 5_000);
+        let second = ProbeBurst::new(2, 5_000);
+        let start = Instant::now();
+        let mut receiver = ProbeReceiver::new();
+
+        assert!(receiver.on_packet(&first.packet(0), start).is_none());
+        let report = receiver
+            .on_packet(&second.packet(0), start + Duration::from_millis(5))
+            .expect("first burst closed");
+        assert_eq!((report.probe_id, report.received), (1, 1));
+        assert!(delivered_kbps(&report).is_none());
+
+        assert!(receiver.poll_timeout(start).is_none());
+        let report = receiver
+            .poll_timeout(start + Duration::from_secs(1))
+            .expect("second burst timed out");
+        assert_eq!(report.probe_id, 2);
+        assert!(loss_fraction(&report) > 0.5);
+    }
+
+    #[test]
+    fn last_packet_of_a_new_burst_reports_that_burst() {
+        let first = ProbeBurst::new(1, 5_000);
+        let second = ProbeBurst::new(2, 5_000);
+        let start = Instant::now();
+        let mut receiver = ProbeReceiver::new();
+
+        assert!(receiver.on_packet(&first.packet(0), start).is_none());
+        let report = receiver
+            .on_packet(&second.packet(second.count - 1), start)
+            .expect("second burst complete");
+        assert_eq!((report.probe_id, report.received), (2, 1));
+        assert!(receiver
+            .poll_timeout(start + Duration::from_secs(1))
+            .is_none());
+    }
+}
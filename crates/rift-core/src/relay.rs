@@ -26,6 +26,7 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
+use wavry_common::error::ErrorCode;
 
 /// Magic byte identifying Wavry relay protocol packets.
 pub const RELAY_MAGIC: u8 = 0x57; // 'W' for Wavry
@@ -111,6 +112,8 @@ pub enum LeaseRejectReason {
     Banned = 0x0005,
     /// Too many requests from this source.
     RateLimited = 0x0006,
+    /// The peer's user is out of daily or monthly relay quota.
+    QuotaExceeded = 0x0007,
 }
 
 impl TryFrom<u16> for LeaseRejectReason {
@@ -124,6 +127,7 @@ impl TryFrom<u16> for LeaseRejectReason {
             0x0004 => Ok(Self::SessionFull),
             0x0005 => Ok(Self::Banned),
             0x0006 => Ok(Self::RateLimited),
+            0x0007 => Ok(Self::QuotaExceeded),
             _ => Err(RelayError::UnknownRejectReason(value)),
         }
     }
@@ -154,6 +158,15 @@ pub enum RelayError {
     Malformed(String),
 }
 
+impl From<&RelayError> for ErrorCode {
+    fn from(err: &RelayError) -> Self {
+        match err {
+            RelayError::UnsupportedVersion(..) => ErrorCode::UnsupportedVersion,
+            _ => ErrorCode::MalformedPacket,
+        }
+    }
+}
+
 /// Relay packet header (20 bytes).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RelayHeader {
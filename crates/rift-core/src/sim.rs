@@ -0,0 +1,500 @@
+//! In-process datagram network for deterministic tests.
+//!
+//! [`SimulatedTransport`] offers the non-blocking `send_to`/`recv_from` shape
+//! of a UDP socket, but datagrams travel through a [`SimulatedNetwork`] that
+//! applies loss, delay, jitter, duplication, reordering and a bandwidth cap
+//! from a seeded RNG or an explicit per-packet script. Time is virtual and
+//! only moves on [`SimulatedNetwork::advance`], so a test replays identically
+//! on every run. [`proxy::ImpairmentProxy`] puts the same network between
+//! real UDP sockets.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub mod proxy;
+
+/// Impairments applied to each datagram on a link.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    /// Probability in `0.0..=1.0` that a datagram is dropped.
+    pub loss: f64,
+    /// One-way delay every datagram sees.
+    pub delay: Duration,
+    /// Extra delay drawn uniformly from `0..=jitter`.
+    pub jitter: Duration,
+    /// Probability that a delivered datagram arrives twice.
+    pub duplicate: f64,
+    /// Probability that a datagram is held back by `reorder_delay`, letting
+    /// later ones overtake it.
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Bottleneck rate in kbit/s; 0 is unlimited. Datagrams queue behind one
+    /// another at this rate before `delay` applies.
+    pub bandwidth_kbps: u32,
+    /// Longest backlog the bottleneck holds; datagrams that would wait
+    /// longer are tail-dropped. Zero is unbounded.
+    pub queue_limit: Duration,
+}
+
+impl LinkConditions {
+    /// A perfect link with a fixed one-way delay.
+    pub fn with_delay(delay: Duration) -> Self {
+        Self {
+            delay,
+            ..Self::default()
+        }
+    }
+
+    /// Time to clock `len` bytes through the bottleneck.
+    fn serialization_delay(&self, len: usize) -> Duration {
+        if self.bandwidth_kbps == 0 {
+            return Duration::ZERO;
+        }
+        // bits / (kbit/s) = ms; scale to microseconds.
+        Duration::from_micros(len as u64 * 8 * 1_000 / self.bandwidth_kbps as u64)
+    }
+}
+
+/// Scripted outcome for the next datagram on a link, overriding the
+/// random draw from [`LinkConditions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fate {
+    Deliver,
+    Drop,
+    Duplicate,
+    /// Deliver after this one-way delay instead of the link's.
+    Delay(Duration),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub sent: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    /// Drops caused by a full bottleneck queue; included in `dropped`.
+    pub overflowed: u64,
+}
+
+/// Shared medium that [`SimulatedTransport`]s are bound to.
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    now: Duration,
+    rng: StdRng,
+    conditions: LinkConditions,
+    links: HashMap<(SocketAddr, SocketAddr), LinkConditions>,
+    scripts: HashMap<(SocketAddr, SocketAddr), VecDeque<Fate>>,
+    /// When each link's bottleneck finishes sending its backlog.
+    busy_until: HashMap<(SocketAddr, SocketAddr), Duration>,
+    /// In-flight datagrams per bound destination.
+    queues: HashMap<SocketAddr, BinaryHeap<Reverse<Datagram>>>,
+    next_seq: u64,
+    stats: NetworkStats,
+}
+
+struct Datagram {
+    due: Duration,
+    seq: u64,
+    from: SocketAddr,
+    payload: Vec<u8>,
+}
+
+impl PartialEq for Datagram {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Datagram {}
+
+impl PartialOrd for Datagram {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Datagram {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+impl SimulatedNetwork {
+    /// A lossless, zero-delay network whose random draws come from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                now: Duration::ZERO,
+                rng: StdRng::seed_from_u64(seed),
+                conditions: LinkConditions::default(),
+                links: HashMap::new(),
+                scripts: HashMap::new(),
+                busy_until: HashMap::new(),
+                queues: HashMap::new(),
+                next_seq: 0,
+                stats: NetworkStats::default(),
+            })),
+        }
+    }
+
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<SimulatedTransport> {
+        let mut inner = lock(&self.inner);
+        if inner.queues.contains_key(&addr) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already bound", addr),
+            ));
+        }
+        inner.queues.insert(addr, BinaryHeap::new());
+        Ok(SimulatedTransport {
+            addr,
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Conditions for every link without its own [`Self::set_link`].
+    pub fn set_conditions(&self, conditions: LinkConditions) {
+        lock(&self.inner).conditions = conditions;
+    }
+
+    /// Conditions for datagrams sent from `from` to `to` only.
+    pub fn set_link(&self, from: SocketAddr, to: SocketAddr, conditions: LinkConditions) {
+        lock(&self.inner).links.insert((from, to), conditions);
+    }
+
+    /// Queues fates for the next datagrams from `from` to `to`.
+    pub fn script(&self, from: SocketAddr, to: SocketAddr, fates: impl IntoIterator<Item = Fate>) {
+        lock(&self.inner)
+            .scripts
+            .entry((from, to))
+            .or_default()
+            .extend(fates);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        lock(&self.inner).now += by;
+    }
+
+    /// Virtual time since the network was created.
+    pub fn now(&self) -> Duration {
+        lock(&self.inner).now
+    }
+
+    /// Time until the next in-flight datagram is due, if any.
+    pub fn next_due(&self) -> Option<Duration> {
+        let inner = lock(&self.inner);
+        inner
+            .queues
+            .values()
+            .filter_map(|queue| queue.peek().map(|Reverse(datagram)| datagram.due))
+            .min()
+            .map(|due| due.saturating_sub(inner.now))
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        lock(&self.inner).stats
+    }
+}
+
+impl Inner {
+    fn send(&mut self, from: SocketAddr, to: SocketAddr, payload: &[u8]) {
+        self.stats.sent += 1;
+        if !self.queues.contains_key(&to) {
+            self.stats.dropped += 1;
+            return;
+        }
+        let conditions = self
+            .links
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(self.conditions);
+        let scripted = self
+            .scripts
+            .get_mut(&(from, to))
+            .and_then(|script| script.pop_front());
+
+        let (copies, delay) = match scripted {
+            Some(Fate::Deliver) => (1, conditions.delay),
+            Some(Fate::Drop) => (0, Duration::ZERO),
+            Some(Fate::Duplicate) => (2, conditions.delay),
+            Some(Fate::Delay(delay)) => (1, delay),
+            None => {
+                if self.rng.gen_bool(conditions.loss.clamp(0.0, 1.0)) {
+                    (0, Duration::ZERO)
+                } else {
+                    let copies = if self.rng.gen_bool(conditions.duplicate.clamp(0.0, 1.0)) {
+                        2
+                    } else {
+                        1
+                    };
+                    (copies, self.draw_delay(&conditions))
+                }
+            }
+        };
+        if copies == 0 {
+            self.stats.dropped += 1;
+            return;
+        }
+        let Some(queued) = self.enqueue(from, to, &conditions, payload.len()) else {
+            self.stats.dropped += 1;
+            self.stats.overflowed += 1;
+            return;
+        };
+        if copies > 1 {
+            self.stats.duplicated += 1;
+        }
+
+        for copy in 0..copies {
+            // A duplicate trails the original by a fresh jitter draw.
+            let extra = if copy == 0 {
+                Duration::ZERO
+            } else {
+                self.draw_jitter(&conditions)
+            };
+            let datagram = Datagram {
+                due: self.now + queued + delay + extra,
+                seq: self.next_seq,
+                from,
+                payload: payload.to_vec(),
+            };
+            self.next_seq += 1;
+            if let Some(queue) = self.queues.get_mut(&to) {
+                queue.push(Reverse(datagram));
+            }
+        }
+    }
+
+    /// Queues `len` bytes on the link's bottleneck and returns how long until
+    /// they have left it, or `None` when the queue is full.
+    fn enqueue(
+        &mut self,
+        from: SocketAddr,
+        to: SocketAddr,
+        conditions: &LinkConditions,
+        len: usize,
+    ) -> Option<Duration> {
+        if conditions.bandwidth_kbps == 0 {
+            return Some(Duration::ZERO);
+        }
+        let busy_until = self.busy_until.entry((from, to)).or_default();
+        let backlog = busy_until.saturating_sub(self.now);
+        if !conditions.queue_limit.is_zero() && backlog > conditions.queue_limit {
+            return None;
+        }
+        let done = backlog + conditions.serialization_delay(len);
+        *busy_until = self.now + done;
+        Some(done)
+    }
+
+    fn draw_delay(&mut self, conditions: &LinkConditions) -> Duration {
+        let mut delay = conditions.delay + self.draw_jitter(conditions);
+        if self.rng.gen_bool(conditions.reorder.clamp(0.0, 1.0)) {
+            delay += conditions.reorder_delay;
+        }
+        delay
+    }
+
+    fn draw_jitter(&mut self, conditions: &LinkConditions) -> Duration {
+        if conditions.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        let micros = conditions.jitter.as_micros() as u64;
+        Duration::from_micros(self.rng.gen_range(0..=micros))
+    }
+
+    fn recv(&mut self, addr: SocketAddr) -> Option<Datagram> {
+        let now = self.now;
+        let queue = self.queues.get_mut(&addr)?;
+        if queue
+            .peek()
+            .is_some_and(|Reverse(datagram)| datagram.due <= now)
+        {
+            let Reverse(datagram) = queue.pop()?;
+            self.stats.delivered += 1;
+            return Some(datagram);
+        }
+        None
+    }
+}
+
+/// One endpoint on a [`SimulatedNetwork`], behaving like a non-blocking
+/// UDP socket.
+pub struct SimulatedTransport {
+    addr: SocketAddr,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl SimulatedTransport {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    /// Never blocks. Datagrams to unbound addresses vanish, as with UDP.
+    pub fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        lock(&self.inner).send(self.addr, target, buf);
+        Ok(buf.len())
+    }
+
+    /// Returns the next datagram that is due, or `WouldBlock`. Datagrams
+    /// longer than `buf` are truncated.
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let datagram = lock(&self.inner)
+            .recv(self.addr)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+        let len = datagram.payload.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram.payload[..len]);
+        Ok((len, datagram.from))
+    }
+}
+
+impl Drop for SimulatedTransport {
+    fn drop(&mut self) {
+        lock(&self.inner).queues.remove(&self.addr);
+    }
+}
+
+fn lock(inner: &Mutex<Inner>) -> MutexGuard<'_, Inner> {
+    inner
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn drain(transport: &SimulatedTransport) -> Vec<u8> {
+        let mut buf = [0u8; 16];
+        let mut received = Vec::new();
+        while let Ok((len, _)) = transport.recv_from(&mut buf) {
+            assert_eq!(len, 1);
+            received.push(buf[0]);
+        }
+        received
+    }
+
+    #[test]
+    fn delivers_after_delay_and_follows_script() {
+        let net = SimulatedNetwork::new(1);
+        let a = net.bind(addr(1)).unwrap();
+        let b = net.bind(addr(2)).unwrap();
+        assert!(net.bind(addr(2)).is_err());
+        net.set_conditions(LinkConditions::with_delay(Duration::from_millis(10)));
+        net.script(
+            addr(1),
+            addr(2),
+            [
+                Fate::Delay(Duration::from_millis(30)),
+                Fate::Drop,
+                Fate::Duplicate,
+            ],
+        );
+
+        for byte in 0..4u8 {
+            a.send_to(&[byte], addr(2)).unwrap();
+        }
+        assert!(drain(&b).is_empty());
+
+        net.advance(Duration::from_millis(10));
+        assert_eq!(drain(&b), vec![2, 2, 3]);
+        assert_eq!(net.next_due(), Some(Duration::from_millis(20)));
+
+        net.advance(Duration::from_millis(20));
+        assert_eq!(drain(&b), vec![0]);
+        assert_eq!(
+            net.stats(),
+            NetworkStats {
+                sent: 4,
+                delivered: 4,
+                dropped: 1,
+                duplicated: 1,
+                overflowed: 0,
+            }
+        );
+
+        drop(b);
+        a.send_to(&[9], addr(2)).unwrap();
+        assert_eq!(net.stats().dropped, 2);
+    }
+
+    #[test]
+    fn random_impairments_replay_from_seed() {
+        let run = |seed| {
+            let net = SimulatedNetwork::new(seed);
+            let a = net.bind(addr(1)).unwrap();
+            let b = net.bind(addr(2)).unwrap();
+            net.set_conditions(LinkConditions {
+                loss: 0.2,
+                delay: Duration::from_millis(5),
+                jitter: Duration::from_millis(5),
+                duplicate: 0.1,
+                reorder: 0.1,
+                reorder_delay: Duration::from_millis(20),
+                ..LinkConditions::default()
+            });
+            for byte in 0..100u8 {
+                a.send_to(&[byte], addr(2)).unwrap();
+                net.advance(Duration::from_millis(1));
+            }
+            net.advance(Duration::from_millis(50));
+            (drain(&b), net.stats())
+        };
+
+        let (received, stats) = run(7);
+        assert_eq!(run(7), (received.clone(), stats));
+        assert!(stats.dropped > 0 && stats.duplicated > 0);
+        assert_eq!(
+            received.len() as u64,
+            stats.sent - stats.dropped + stats.duplicated
+        );
+        assert!(received.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn bandwidth_cap_queues_and_tail_drops() {
+        let net = SimulatedNetwork::new(1);
+        let a = net.bind(addr(1)).unwrap();
+        let b = net.bind(addr(2)).unwrap();
+        // 1000-byte datagrams take 8ms each at 1 Mbit/s.
+        net.set_conditions(LinkConditions {
+            delay: Duration::from_millis(5),
+            bandwidth_kbps: 1_000,
+            queue_limit: Duration::from_millis(20),
+            ..LinkConditions::default()
+        });
+
+        let payload = [0u8; 1000];
+        for _ in 0..4 {
+            a.send_to(&payload, addr(2)).unwrap();
+        }
+        // Backlogs of 0, 8, 16 and 24ms: the fourth overflows the 20ms queue.
+        assert_eq!(net.stats().overflowed, 1);
+        assert_eq!(net.next_due(), Some(Duration::from_millis(13)));
+
+        let mut buf = [0u8; 1000];
+        let mut arrivals = Vec::new();
+        for _ in 0..40 {
+            net.advance(Duration::from_millis(1));
+            while b.recv_from(&mut buf).is_ok() {
+                arrivals.push(net.now().as_millis());
+            }
+        }
+        assert_eq!(arrivals, vec![13, 21, 29]);
+        assert_eq!(net.stats().dropped, 1);
+    }
+}
@@ -0,0 +1,197 @@
+//! Real UDP front end for a [`SimulatedNetwork`].
+//!
+//! [`ImpairmentProxy`] sits between a client and a server that use ordinary
+//! UDP sockets: the client sends to the proxy instead of the server, and
+//! traffic in both directions crosses a simulated link on the way. Virtual
+//! time follows the wall clock, so runs are not bit-for-bit repeatable, but
+//! the impairments are drawn from a seeded RNG.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{LinkConditions, NetworkStats, SimulatedNetwork, SimulatedTransport};
+
+/// Virtual endpoints the two directions travel between.
+const CLIENT_SIDE: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1));
+const SERVER_SIDE: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 1));
+/// Sleep between polls of the sockets and the simulated link.
+const POLL_INTERVAL: Duration = Duration::from_micros(250);
+const MAX_DATAGRAM: usize = 64 * 1024;
+
+/// Forwards datagrams from clients to one upstream server, and the server's
+/// replies back to whichever client sent most recently.
+pub struct ImpairmentProxy {
+    addr: SocketAddr,
+    network: SimulatedNetwork,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ImpairmentProxy {
+    /// Listens on `listen` and forwards to `upstream` over a lossless link
+    /// until conditions are set.
+    pub fn spawn(listen: SocketAddr, upstream: SocketAddr, seed: u64) -> io::Result<Self> {
+        let front = UdpSocket::bind(listen)?;
+        let back = match upstream {
+            SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+            SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+        };
+        back.connect(upstream)?;
+        front.set_nonblocking(true)?;
+        back.set_nonblocking(true)?;
+
+        let network = SimulatedNetwork::new(seed);
+        let link = Link {
+            front,
+            back,
+            client_side: network.bind(CLIENT_SIDE)?,
+            server_side: network.bind(SERVER_SIDE)?,
+            network: network.clone(),
+            client: None,
+        };
+        let addr = link.front.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = thread::Builder::new()
+            .name("rift-impairment-proxy".into())
+            .spawn({
+                let stop = stop.clone();
+                move || link.run(&stop)
+            })?;
+
+        Ok(Self {
+            addr,
+            network,
+            stop,
+            worker: Some(worker),
+        })
+    }
+
+    /// Address clients should send to in place of the server.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Conditions for client-to-server traffic.
+    pub fn set_uplink(&self, conditions: LinkConditions) {
+        self.network.set_link(CLIENT_SIDE, SERVER_SIDE, conditions);
+    }
+
+    /// Conditions for server-to-client traffic.
+    pub fn set_downlink(&self, conditions: LinkConditions) {
+        self.network.set_link(SERVER_SIDE, CLIENT_SIDE, conditions);
+    }
+
+    /// Counters for both directions combined.
+    pub fn stats(&self) -> NetworkStats {
+        self.network.stats()
+    }
+}
+
+impl Drop for ImpairmentProxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Link {
+    front: UdpSocket,
+    back: UdpSocket,
+    client_side: SimulatedTransport,
+    server_side: SimulatedTransport,
+    network: SimulatedNetwork,
+    client: Option<SocketAddr>,
+}
+
+impl Link {
+    fn run(mut self, stop: &AtomicBool) {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        let mut last = Instant::now();
+        while !stop.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            self.network.advance(now - last);
+            last = now;
+            self.pump(&mut buf);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Moves arrivals onto the simulated link and due datagrams off it.
+    /// Socket errors, such as ICMP unreachable reports, are ignored.
+    fn pump(&mut self, buf: &mut [u8]) {
+        while let Ok((len, from)) = self.front.recv_from(buf) {
+            self.client = Some(from);
+            let _ = self.client_side.send_to(&buf[..len], SERVER_SIDE);
+        }
+        while let Ok(len) = self.back.recv(buf) {
+            let _ = self.server_side.send_to(&buf[..len], CLIENT_SIDE);
+        }
+        while let Ok((len, _)) = self.server_side.recv_from(buf) {
+            let _ = self.back.send(&buf[..len]);
+        }
+        while let Ok((len, _)) = self.client_side.recv_from(buf) {
+            if let Some(client) = self.client {
+                let _ = self.front.send_to(&buf[..len], client);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localhost() -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 0))
+    }
+
+    #[test]
+    fn forwards_both_ways_with_link_delay() {
+        let server = UdpSocket::bind(localhost()).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let proxy = ImpairmentProxy::spawn(localhost(), server.local_addr().unwrap(), 1).unwrap();
+        proxy.set_uplink(LinkConditions::with_delay(Duration::from_millis(30)));
+
+        let client = UdpSocket::bind(localhost()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let sent_at = Instant::now();
+        client.send_to(b"ping", proxy.local_addr()).unwrap();
+
+        let mut buf = [0u8; 16];
+        let (len, from) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ping");
+        assert!(sent_at.elapsed() >= Duration::from_millis(30));
+
+        server.send_to(b"pong", from).unwrap();
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"pong");
+    }
+
+    #[test]
+    fn lossy_uplink_drops_datagrams() {
+        let server = UdpSocket::bind(localhost()).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let proxy = ImpairmentProxy::spawn(localhost(), server.local_addr().unwrap(), 1).unwrap();
+        proxy.set_uplink(LinkConditions {
+            loss: 1.0,
+            ..LinkConditions::default()
+        });
+
+        let client = UdpSocket::bind(localhost()).unwrap();
+        client.send_to(b"lost", proxy.local_addr()).unwrap();
+        assert!(server.recv_from(&mut [0u8; 16]).is_err());
+        assert_eq!(proxy.stats().dropped, 1);
+    }
+}
@@ -1,10 +1,17 @@
 use anyhow::{anyhow, Result};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 pub const STUN_MAGIC_COOKIE: u32 = 0x2112A442;
 pub const BINDING_REQUEST: u16 = 0x0001;
 pub const BINDING_RESPONSE: u16 = 0x0101;
+pub const STUN_HEADER_SIZE: usize = 20;
 
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const FAMILY_IPV4: u8 = 0x01;
+const FAMILY_IPV6: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct StunMessage {
     pub msg_type: u16,
     pub transaction_id: [u8; 12],
@@ -21,6 +28,27 @@ impl StunMessage {
         }
     }
 
+    /// Whether `buf` carries a STUN header, as opposed to RIFT, compact or
+    /// relay framing (none of which start with two zero bits and the cookie).
+    pub fn is_stun(buf: &[u8]) -> bool {
+        buf.len() >= STUN_HEADER_SIZE
+            && buf[0] & 0xC0 == 0
+            && buf[4..8] == STUN_MAGIC_COOKIE.to_be_bytes()
+    }
+
+    /// Reads the header of a STUN message.
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if !Self::is_stun(buf) {
+            return Err(anyhow!("not a STUN message"));
+        }
+        let mut transaction_id = [0u8; 12];
+        transaction_id.copy_from_slice(&buf[8..20]);
+        Ok(Self {
+            msg_type: u16::from_be_bytes([buf[0], buf[1]]),
+            transaction_id,
+        })
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(20);
         buf.extend_from_slice(&self.msg_type.to_be_bytes());
@@ -30,6 +58,43 @@ impl StunMessage {
         buf
     }
 
+    /// The success response to this request, telling the sender it was seen
+    /// from `mapped`.
+    pub fn encode_binding_response(&self, mapped: SocketAddr) -> Vec<u8> {
+        let mut value = vec![0, 0];
+        value.extend_from_slice(&(mapped.port() ^ (STUN_MAGIC_COOKIE >> 16) as u16).to_be_bytes());
+        match mapped.ip() {
+            IpAddr::V4(ip) => {
+                value[1] = FAMILY_IPV4;
+                let xored = u32::from(ip) ^ STUN_MAGIC_COOKIE;
+                value.extend_from_slice(&xored.to_be_bytes());
+            }
+            IpAddr::V6(ip) => {
+                value[1] = FAMILY_IPV6;
+                let mask = self.v6_mask();
+                value.extend(ip.octets().iter().zip(mask).map(|(byte, mask)| byte ^ mask));
+            }
+        }
+
+        let mut buf = Vec::with_capacity(STUN_HEADER_SIZE + 4 + value.len());
+        buf.extend_from_slice(&BINDING_RESPONSE.to_be_bytes());
+        buf.extend_from_slice(&((4 + value.len()) as u16).to_be_bytes());
+        buf.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+        buf.extend_from_slice(&self.transaction_id);
+        buf.extend_from_slice(&ATTR_XOR_MAPPED_ADDRESS.to_be_bytes());
+        buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&value);
+        buf
+    }
+
+    /// IPv6 XOR-MAPPED-ADDRESS mask: the cookie followed by the transaction id.
+    fn v6_mask(&self) -> [u8; 16] {
+        let mut mask = [0u8; 16];
+        mask[..4].copy_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+        mask[4..].copy_from_slice(&self.transaction_id);
+        mask
+    }
+
     pub fn decode_address(buf: &[u8]) -> Result<SocketAddr> {
         if buf.len() < 20 {
             return Err(anyhow!("STUN message too short"));
@@ -44,6 +109,7 @@ impl StunMessage {
         if cookie != STUN_MAGIC_COOKIE {
             return Err(anyhow!("Invalid magic cookie"));
         }
+        let header = Self::decode(buf)?;
 
         let mut pos = 20;
         let end = buf.len();
@@ -56,36 +122,49 @@ impl StunMessage {
             if pos + attr_len > end {
                 break;
             }
+            let value = &buf[pos..pos + attr_len];
 
-            // XOR-MAPPED-ADDRESS is 0x0020
-            if attr_type == 0x0020 {
+            if attr_type == ATTR_XOR_MAPPED_ADDRESS {
                 if attr_len < 8 {
                     return Err(anyhow!("Invalid XOR-MAPPED-ADDRESS length"));
                 }
-                let port = u16::from_be_bytes([buf[pos + 2], buf[pos + 3]])
-                    ^ (STUN_MAGIC_COOKIE >> 16) as u16;
-                let family = buf[pos + 1];
-                if family == 0x01 {
-                    // IPv4
-                    let a = buf[pos + 4] ^ (STUN_MAGIC_COOKIE >> 24) as u8;
-                    let b = buf[pos + 5] ^ (STUN_MAGIC_COOKIE >> 16) as u8;
-                    let c = buf[pos + 6] ^ (STUN_MAGIC_COOKIE >> 8) as u8;
-                    let d = buf[pos + 7] ^ (STUN_MAGIC_COOKIE) as u8;
-                    return Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(a, b, c, d)), port));
+                let port =
+                    u16::from_be_bytes([value[2], value[3]]) ^ (STUN_MAGIC_COOKIE >> 16) as u16;
+                match value[1] {
+                    FAMILY_IPV4 => {
+                        let raw = u32::from_be_bytes([value[4], value[5], value[6], value[7]]);
+                        let ip = Ipv4Addr::from(raw ^ STUN_MAGIC_COOKIE);
+                        return Ok(SocketAddr::new(IpAddr::V4(ip), port));
+                    }
+                    FAMILY_IPV6 if attr_len >= 20 => {
+                        let mut octets = [0u8; 16];
+                        for ((out, byte), mask) in
+                            octets.iter_mut().zip(&value[4..20]).zip(header.v6_mask())
+                        {
+                            *out = byte ^ mask;
+                        }
+                        return Ok(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port));
+                    }
+                    _ => {}
                 }
             }
 
-            // MAPPED-ADDRESS is 0x0001
-            if attr_type == 0x0001 {
+            if attr_type == ATTR_MAPPED_ADDRESS {
                 if attr_len < 8 {
                     return Err(anyhow!("Invalid MAPPED-ADDRESS length"));
                 }
-                let port = u16::from_be_bytes([buf[pos + 2], buf[pos + 3]]);
-                let family = buf[pos + 1];
-                if family == 0x01 {
-                    // IPv4
-                    let ip = Ipv4Addr::new(buf[pos + 4], buf[pos + 5], buf[pos + 6], buf[pos + 7]);
-                    return Ok(SocketAddr::new(IpAddr::V4(ip), port));
+                let port = u16::from_be_bytes([value[2], value[3]]);
+                match value[1] {
+                    FAMILY_IPV4 => {
+                        let ip = Ipv4Addr::new(value[4], value[5], value[6], value[7]);
+                        return Ok(SocketAddr::new(IpAddr::V4(ip), port));
+                    }
+                    FAMILY_IPV6 if attr_len >= 20 => {
+                        let mut octets = [0u8; 16];
+                        octets.copy_from_slice(&value[4..20]);
+                        return Ok(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port));
+                    }
+                    _ => {}
                 }
             }
 
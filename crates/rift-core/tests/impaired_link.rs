@@ -0,0 +1,158 @@
+//! Congestion control and FEC exercised over simulated impaired links.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use prost::Message;
+use rift_core::cc::{DeltaCC, DeltaConfig};
+use rift_core::sim::{LinkConditions, SimulatedNetwork};
+use rift_core::{FecBuilder, FecPacket, FecReassembler, FecScheme};
+
+const PACKET_BYTES: usize = 1200;
+
+fn addr(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+#[test]
+fn delta_cc_settles_near_bottleneck_capacity() {
+    const CAPACITY_KBPS: u32 = 8_000;
+    const ONE_WAY: Duration = Duration::from_millis(10);
+    const FEEDBACK_INTERVAL_MS: u64 = 20;
+
+    let net = SimulatedNetwork::new(3);
+    let sender = net.bind(addr(1)).unwrap();
+    let receiver = net.bind(addr(2)).unwrap();
+    net.set_conditions(LinkConditions {
+        delay: ONE_WAY,
+        bandwidth_kbps: CAPACITY_KBPS,
+        queue_limit: Duration::from_millis(300),
+        ..LinkConditions::default()
+    });
+
+    let mut cc = DeltaCC::new(DeltaConfig::default(), 20_000, 60);
+    let mut budget_bytes = 0.0;
+    let mut delays_us = Vec::new();
+    let mut last_stats = net.stats();
+    let mut bitrates = Vec::new();
+    let mut queue_delays_us = Vec::new();
+    let mut buf = [0u8; PACKET_BYTES];
+
+    for ms in 1..=10_000u64 {
+        budget_bytes += cc.target_bitrate_kbps() as f64 / 8.0;
+        while budget_bytes >= PACKET_BYTES as f64 {
+            budget_bytes -= PACKET_BYTES as f64;
+            let mut packet = [0u8; PACKET_BYTES];
+            packet[..8].copy_from_slice(&(net.now().as_micros() as u64).to_le_bytes());
+            sender.send_to(&packet, addr(2)).unwrap();
+        }
+
+        net.advance(Duration::from_millis(1));
+        while let Ok((len, _)) = receiver.recv_from(&mut buf) {
+            assert_eq!(len, PACKET_BYTES);
+            let sent_us = u64::from_le_bytes(buf[..8].try_into().unwrap());
+            delays_us.push(net.now().as_micros() as u64 - sent_us);
+        }
+
+        if ms % FEEDBACK_INTERVAL_MS == 0 && !delays_us.is_empty() {
+            let one_way_us = delays_us.iter().sum::<u64>() / delays_us.len() as u64;
+            delays_us.clear();
+            let stats = net.stats();
+            let sent = stats.sent - last_stats.sent;
+            let dropped = stats.dropped - last_stats.dropped;
+            last_stats = stats;
+            let loss = if sent == 0 {
+                0.0
+            } else {
+                dropped as f32 / sent as f32
+            };
+            // The feedback path is uncongested.
+            cc.on_rtt_sample(one_way_us + ONE_WAY.as_micros() as u64, loss, 0);
+
+            if ms > 5_000 {
+                bitrates.push(cc.target_bitrate_kbps());
+                queue_delays_us.push(one_way_us - ONE_WAY.as_micros() as u64);
+            }
+        }
+    }
+
+    let mean_kbps = bitrates.iter().map(|&b| b as u64).sum::<u64>() / bitrates.len() as u64;
+    let mean_queue_us = queue_delays_us.iter().sum::<u64>() / queue_delays_us.len() as u64;
+    assert!(
+        (CAPACITY_KBPS as u64 / 2..=CAPACITY_KBPS as u64 * 13 / 10).contains(&mean_kbps),
+        "mean target {} kbps against {} kbps capacity",
+        mean_kbps,
+        CAPACITY_KBPS
+    );
+    assert!(
+        mean_queue_us < 100_000,
+        "mean queueing delay {} us",
+        mean_queue_us
+    );
+}
+
+#[test]
+fn reed_solomon_fec_repairs_random_loss() {
+    const PACKETS: u64 = 1_600;
+
+    let net = SimulatedNetwork::new(11);
+    let sender = net.bind(addr(1)).unwrap();
+    let receiver = net.bind(addr(2)).unwrap();
+    net.set_conditions(LinkConditions {
+        loss: 0.05,
+        delay: Duration::from_millis(5),
+        ..LinkConditions::default()
+    });
+
+    // Tag byte, then a packet id and payload for data or an encoded FecPacket.
+    let mut builder = FecBuilder::for_ratio(FecScheme::ReedSolomon, 0.25);
+    for packet_id in 0..PACKETS {
+        let payload = vec![packet_id as u8; 100 + (packet_id % 400) as usize];
+        let mut datagram = vec![0u8];
+        datagram.extend_from_slice(&packet_id.to_le_bytes());
+        datagram.extend_from_slice(&payload);
+        sender.send_to(&datagram, addr(2)).unwrap();
+        for parity in builder.push(packet_id, &payload) {
+            let mut datagram = vec![1u8];
+            datagram.extend_from_slice(&parity.encode_to_vec());
+            sender.send_to(&datagram, addr(2)).unwrap();
+        }
+    }
+    net.advance(Duration::from_millis(5));
+
+    let mut received: HashMap<u64, Vec<u8>> = HashMap::new();
+    let mut reassembler = FecReassembler::new();
+    let mut delivered = 0;
+    let mut buf = vec![0u8; 2048];
+    while let Ok((len, _)) = receiver.recv_from(&mut buf) {
+        match buf[0] {
+            0 => {
+                let packet_id = u64::from_le_bytes(buf[1..9].try_into().unwrap());
+                received.insert(packet_id, buf[9..len].to_vec());
+                delivered += 1;
+            }
+            _ => {
+                let parity = FecPacket::decode(&buf[1..len]).unwrap();
+                let recovered =
+                    reassembler.on_parity(parity, |id| received.get(&id).map(Vec::as_slice));
+                for (packet_id, payload) in recovered {
+                    assert_eq!(
+                        payload,
+                        vec![packet_id as u8; 100 + (packet_id % 400) as usize]
+                    );
+                    received.insert(packet_id, payload);
+                }
+            }
+        }
+    }
+
+    assert!(delivered < PACKETS, "the link should have lost packets");
+    assert!(
+        received.len() as u64 >= PACKETS * 99 / 100,
+        "{} of {} packets after repair ({} delivered)",
+        received.len(),
+        PACKETS,
+        delivered
+    );
+}
@@ -0,0 +1,261 @@
+//! Host-side allow/deny decisions for client devices.
+//!
+//! A client that completes the Noise handshake has only proven which
+//! [`WavryId`] it holds. Hosts ask their user the first time an identity
+//! connects and keep the answer here, so later sessions from the same device
+//! are admitted or refused without asking again.
+//!
+//! # File Format
+//!
+//! One device per line, `#` starts a comment:
+//!
+//! ```text
+//! <allow|deny> <wavry-id> <decided-at unix seconds> <client name>
+//! ```
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::identity::WavryId;
+
+/// File name of the store inside [`wavry_common::helpers::config_dir`].
+pub const AUTHORIZED_CLIENTS_FILE: &str = "authorized_clients";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientDecision {
+    Allow,
+    Deny,
+}
+
+impl ClientDecision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "allow" => Some(Self::Allow),
+            "deny" => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A remembered decision about one client device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizedClient {
+    pub wavry_id: WavryId,
+    pub decision: ClientDecision,
+    /// Client name from the Hello that was decided on, for display only.
+    pub name: String,
+    /// Unix seconds when the decision was made.
+    pub decided_at: u64,
+}
+
+/// Decisions about client devices, optionally backed by a file.
+#[derive(Debug, Default)]
+pub struct AuthorizedClients {
+    path: Option<PathBuf>,
+    clients: Vec<AuthorizedClient>,
+}
+
+impl AuthorizedClients {
+    /// A store that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Default location of the host's store.
+    pub fn default_path() -> Option<PathBuf> {
+        wavry_common::helpers::config_dir().map(|dir| dir.join(AUTHORIZED_CLIENTS_FILE))
+    }
+
+    /// Loads the store at `path`; a missing file is an empty store.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let clients = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                parse(&contents).with_context(|| format!("failed to parse {}", path.display()))?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            clients,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Remembered devices in the order they were first decided on.
+    pub fn clients(&self) -> &[AuthorizedClient] {
+        &self.clients
+    }
+
+    /// The remembered decision for `wavry_id`, if the user made one.
+    pub fn decision(&self, wavry_id: &WavryId) -> Option<ClientDecision> {
+        self.clients
+            .iter()
+            .find(|client| client.wavry_id == *wavry_id)
+            .map(|client| client.decision)
+    }
+
+    /// Records `decision` for `wavry_id`, replacing any earlier one.
+    pub fn decide(
+        &mut self,
+        wavry_id: &WavryId,
+        decision: ClientDecision,
+        name: &str,
+    ) -> Result<()> {
+        let decided_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        // Names come from the peer; keep them to one line of the file.
+        let name = name
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect::<String>()
+            .trim()
+            .to_string();
+        let client = AuthorizedClient {
+            wavry_id: wavry_id.clone(),
+            decision,
+            name,
+            decided_at,
+        };
+        match self
+            .clients
+            .iter_mut()
+            .find(|existing| existing.wavry_id == *wavry_id)
+        {
+            Some(existing) => *existing = client,
+            None => self.clients.push(client),
+        }
+        self.save()
+    }
+
+    /// Forgets `wavry_id` so the user is asked again; returns whether it was
+    /// remembered.
+    pub fn remove(&mut self, wavry_id: &WavryId) -> Result<bool> {
+        let before = self.clients.len();
+        self.clients.retain(|client| client.wavry_id != *wavry_id);
+        if self.clients.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut contents =
+            String::from("# Wavry client devices: <allow|deny> <wavry-id> <decided-at> <name>\n");
+        for client in &self.clients {
+            contents.push_str(&format!(
+                "{} {} {} {}\n",
+                client.decision, client.wavry_id, client.decided_at, client.name
+            ));
+        }
+        // Write-then-rename so a crash never leaves a truncated store.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents).with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+fn parse(contents: &str) -> Result<Vec<AuthorizedClient>> {
+    let mut clients = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(4, ' ');
+        let (Some(decision), Some(wavry_id)) = (fields.next(), fields.next()) else {
+            bail!("line {}: expected `<allow|deny> <wavry-id>`", index + 1);
+        };
+        let Some(decision) = ClientDecision::parse(decision) else {
+            bail!("line {}: unknown decision {:?}", index + 1, decision);
+        };
+        let wavry_id = WavryId::parse(wavry_id).with_context(|| format!("line {}", index + 1))?;
+        let decided_at = fields
+            .next()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        clients.push(AuthorizedClient {
+            wavry_id,
+            decision,
+            name: fields.next().unwrap_or_default().to_string(),
+            decided_at,
+        });
+    }
+    Ok(clients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityKeypair;
+
+    #[test]
+    fn test_decisions_round_trip_through_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "wavry-authorized-clients-{}",
+            rand::random::<u64>()
+        ));
+        let path = dir.join(AUTHORIZED_CLIENTS_FILE);
+        let laptop = IdentityKeypair::generate().wavry_id();
+        let stranger = IdentityKeypair::generate().wavry_id();
+
+        let mut store = AuthorizedClients::open(&path).unwrap();
+        assert_eq!(store.decision(&laptop), None);
+        store
+            .decide(&laptop, ClientDecision::Allow, "Living room\nPC")
+            .unwrap();
+        store
+            .decide(&stranger, ClientDecision::Deny, "unknown")
+            .unwrap();
+
+        let mut reopened = AuthorizedClients::open(&path).unwrap();
+        assert_eq!(reopened.clients(), store.clients());
+        assert_eq!(reopened.clients()[0].name, "Living room PC");
+        assert_eq!(reopened.decision(&laptop), Some(ClientDecision::Allow));
+        assert_eq!(reopened.decision(&stranger), Some(ClientDecision::Deny));
+
+        reopened
+            .decide(&stranger, ClientDecision::Allow, "unknown")
+            .unwrap();
+        assert_eq!(reopened.decision(&stranger), Some(ClientDecision::Allow));
+        assert!(reopened.remove(&laptop).unwrap());
+        assert!(!reopened.remove(&laptop).unwrap());
+        assert_eq!(
+            AuthorizedClients::open(&path).unwrap().decision(&laptop),
+            None
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,526 @@
+//! Encrypted RIFT datagrams to one peer.
+//!
+//! [`SecureChannel`] runs the Noise XX handshake on top of
+//! [`SecureClient`]/[`SecureServer`], retransmitting lost handshake messages,
+//! and frames every message in a [`PhysicalPacket`] under the next packet id.
+//! Handshake packets carry `session_id = 0`; message 3 and transport packets
+//! carry a session alias.
+//!
+//! The channel borrows a [`DatagramTransport`] rather than owning a socket,
+//! so a host can keep one `Arc<UdpSocket>` for every peer. Callers that read
+//! the socket themselves hand each packet to [`SecureChannel::handle_packet`];
+//! the others use [`SecureChannel::recv_msg`].
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use rift_core::{PhysicalPacket, RIFT_VERSION};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::time::{self, Instant};
+use tracing::debug;
+use wavry_common::error::ErrorCode;
+
+use crate::connection::{ConnectionError, SecureClient, SecureServer};
+use crate::identity::WavryId;
+
+/// Largest datagram [`SecureChannel`] reads.
+const MAX_DATAGRAM: usize = 65_535;
+
+/// Alias the client puts on message 3, before the host assigns a real one.
+const HANDSHAKE_ALIAS: u32 = 1;
+
+/// Unconnected datagram socket a channel sends and receives through.
+pub trait DatagramTransport: Send + Sync {
+    fn send_to(
+        &self,
+        datagram: &[u8],
+        peer: SocketAddr,
+    ) -> impl Future<Output = io::Result<usize>> + Send;
+
+    fn recv_from(
+        &self,
+        buf: &mut [u8],
+    ) -> impl Future<Output = io::Result<(usize, SocketAddr)>> + Send;
+}
+
+impl DatagramTransport for UdpSocket {
+    fn send_to(
+        &self,
+        datagram: &[u8],
+        peer: SocketAddr,
+    ) -> impl Future<Output = io::Result<usize>> + Send {
+        UdpSocket::send_to(self, datagram, peer)
+    }
+
+    fn recv_from(
+        &self,
+        buf: &mut [u8],
+    ) -> impl Future<Output = io::Result<(usize, SocketAddr)>> + Send {
+        UdpSocket::recv_from(self, buf)
+    }
+}
+
+impl<T: DatagramTransport + ?Sized> DatagramTransport for Arc<T> {
+    fn send_to(
+        &self,
+        datagram: &[u8],
+        peer: SocketAddr,
+    ) -> impl Future<Output = io::Result<usize>> + Send {
+        (**self).send_to(datagram, peer)
+    }
+
+    fn recv_from(
+        &self,
+        buf: &mut [u8],
+    ) -> impl Future<Output = io::Result<(usize, SocketAddr)>> + Send {
+        (**self).recv_from(buf)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ChannelError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("handshake with {peer} timed out after {attempts} attempts")]
+    HandshakeTimeout { peer: String, attempts: u32 },
+
+    #[error(transparent)]
+    Crypto(#[from] ConnectionError),
+}
+
+impl From<&ChannelError> for ErrorCode {
+    fn from(err: &ChannelError) -> Self {
+        match err {
+            ChannelError::Io(_) => ErrorCode::Io,
+            ChannelError::HandshakeTimeout { .. } => ErrorCode::Timeout,
+            ChannelError::Crypto(err) => err.into(),
+        }
+    }
+}
+
+/// Handshake retransmission policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelConfig {
+    /// Times message 1 is sent before the client gives up.
+    pub handshake_attempts: u32,
+    /// How long each attempt waits for the next handshake message.
+    pub step_timeout: Duration,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            handshake_attempts: 6,
+            step_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+enum Endpoint {
+    Client {
+        secure: SecureClient,
+        /// Resent if the host repeats message 2 because message 3 was lost.
+        msg3: Bytes,
+    },
+    Server {
+        secure: SecureServer,
+        /// Resent while the client repeats message 1.
+        msg2: Option<Bytes>,
+    },
+}
+
+/// An encrypted conversation with a single peer.
+pub struct SecureChannel<T> {
+    transport: T,
+    peer: SocketAddr,
+    endpoint: Endpoint,
+    session_alias: Option<u32>,
+    next_packet_id: u64,
+    buf: Vec<u8>,
+}
+
+impl<T: DatagramTransport> SecureChannel<T> {
+    /// Runs the initiator side against `peer`, resending message 1 until
+    /// message 2 arrives or the attempts run out.
+    pub async fn connect(
+        transport: T,
+        peer: SocketAddr,
+        mut secure: SecureClient,
+        config: ChannelConfig,
+    ) -> Result<Self, ChannelError> {
+        let msg1 = handshake_packet(Bytes::from(secure.start_handshake()?));
+
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        for attempt in 1..=config.handshake_attempts {
+            transport.send_to(&msg1, peer).await?;
+            debug!(
+                "sent crypto msg1 to {} (attempt {}/{})",
+                peer, attempt, config.handshake_attempts
+            );
+
+            let deadline = Instant::now() + config.step_timeout;
+            while let Some(packet) = recv_packet(&transport, peer, &mut buf, deadline).await? {
+                if packet.session_id != Some(0) {
+                    continue;
+                }
+                let msg3 = PhysicalPacket {
+                    version: RIFT_VERSION,
+                    session_id: None,
+                    session_alias: Some(HANDSHAKE_ALIAS),
+                    packet_id: 0,
+                    payload: Bytes::from(secure.process_server_response(&packet.payload)?),
+                }
+                .encode();
+                transport.send_to(&msg3, peer).await?;
+                debug!("sent crypto msg3 to {}", peer);
+                return Ok(Self {
+                    transport,
+                    peer,
+                    endpoint: Endpoint::Client { secure, msg3 },
+                    // Alias 0 would read as a handshake header.
+                    session_alias: Some(HANDSHAKE_ALIAS),
+                    next_packet_id: 1,
+                    buf,
+                });
+            }
+        }
+
+        Err(ChannelError::HandshakeTimeout {
+            peer: peer.to_string(),
+            attempts: config.handshake_attempts,
+        })
+    }
+
+    /// Waits for a client's message 1 from any address and completes the
+    /// responder side with that peer.
+    pub async fn accept(
+        transport: T,
+        secure: SecureServer,
+        config: ChannelConfig,
+    ) -> Result<Self, ChannelError> {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        let (msg1, peer) = loop {
+            let (len, src) = transport.recv_from(&mut buf).await?;
+            match PhysicalPacket::decode(Bytes::copy_from_slice(&buf[..len])) {
+                Ok(packet) if packet.session_id == Some(0) => break (packet, src),
+                _ => debug!("ignoring non-handshake packet from {}", src),
+            }
+        };
+
+        let mut channel = Self::responder(transport, peer, secure);
+        channel.handle_packet(&msg1).await?;
+        let deadline = Instant::now()
+            + config
+                .step_timeout
+                .saturating_mul(config.handshake_attempts.max(1));
+        while !channel.is_established() {
+            let Some(packet) = recv_packet(&channel.transport, peer, &mut buf, deadline).await?
+            else {
+                return Err(ChannelError::HandshakeTimeout {
+                    peer: peer.to_string(),
+                    attempts: config.handshake_attempts,
+                });
+            };
+            channel.handle_packet(&packet).await?;
+        }
+        Ok(channel)
+    }
+
+    /// Responder side for a host that reads `transport` itself and passes
+    /// `peer`'s packets to [`Self::handle_packet`], handshake included.
+    pub fn responder(transport: T, peer: SocketAddr, secure: SecureServer) -> Self {
+        Self {
+            transport,
+            peer,
+            endpoint: Endpoint::Server { secure, msg2: None },
+            session_alias: None,
+            next_packet_id: 1,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn is_established(&self) -> bool {
+        match &self.endpoint {
+            Endpoint::Client { secure, .. } => secure.is_established(),
+            Endpoint::Server { secure, .. } => secure.is_established(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// The peer's Wavry ID, if it proved one during the handshake.
+    pub fn remote_identity(&self) -> Option<&WavryId> {
+        match &self.endpoint {
+            Endpoint::Client { secure, .. } => secure.remote_identity(),
+            Endpoint::Server { secure, .. } => secure.remote_identity(),
+        }
+    }
+
+    /// Alias stamped on outgoing packets. Clients use the message 3 alias
+    /// until the host assigns one.
+    pub fn set_session_alias(&mut self, alias: u32) {
+        self.session_alias = Some(alias);
+    }
+
+    /// Takes one packet from the peer. Handshake messages are answered, and
+    /// repeated ones get the earlier reply again; transport packets come
+    /// back decrypted.
+    pub async fn handle_packet(
+        &mut self,
+        packet: &PhysicalPacket,
+    ) -> Result<Option<Vec<u8>>, ChannelError> {
+        let established = self.is_established();
+        match &mut self.endpoint {
+            Endpoint::Client { msg3, .. } if packet.session_id == Some(0) => {
+                self.transport.send_to(msg3, self.peer).await?;
+                debug!("resent crypto msg3 to {}", self.peer);
+                Ok(None)
+            }
+            Endpoint::Server { secure, msg2 } if !established => {
+                if packet.session_id == Some(0) {
+                    let reply = match msg2 {
+                        Some(cached) => {
+                            debug!("resending crypto msg2 to {}", self.peer);
+                            cached.clone()
+                        }
+                        None => msg2
+                            .insert(handshake_packet(Bytes::from(
+                                secure.process_client_hello(&packet.payload)?,
+                            )))
+                            .clone(),
+                    };
+                    self.transport.send_to(&reply, self.peer).await?;
+                } else if packet.session_alias.is_some() {
+                    secure.process_client_finish(&packet.payload)?;
+                    *msg2 = None;
+                    debug!("crypto established with {}", self.peer);
+                }
+                Ok(None)
+            }
+            Endpoint::Server { .. } if packet.session_id.is_some() => Ok(None),
+            Endpoint::Client { secure, .. } => {
+                Ok(Some(secure.decrypt(packet.packet_id, &packet.payload)?))
+            }
+            Endpoint::Server { secure, .. } => {
+                Ok(Some(secure.decrypt(packet.packet_id, &packet.payload)?))
+            }
+        }
+    }
+
+    /// Encrypts `plaintext` under the next packet id, ready to encode.
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<PhysicalPacket, ChannelError> {
+        let packet_id = self.next_packet_id;
+        let ciphertext = match &mut self.endpoint {
+            Endpoint::Client { secure, .. } => secure.encrypt(packet_id, plaintext)?,
+            Endpoint::Server { secure, .. } => secure.encrypt(packet_id, plaintext)?,
+        };
+        self.next_packet_id = packet_id.wrapping_add(1);
+        Ok(PhysicalPacket {
+            version: RIFT_VERSION,
+            session_id: None,
+            session_alias: self.session_alias,
+            packet_id,
+            payload: Bytes::from(ciphertext),
+        })
+    }
+
+    /// Encrypts and sends one message, returning its packet id. Delivery is
+    /// not guaranteed.
+    pub async fn send_msg(&mut self, plaintext: &[u8]) -> Result<u64, ChannelError> {
+        let packet = self.seal(plaintext)?;
+        self.transport.send_to(&packet.encode(), self.peer).await?;
+        Ok(packet.packet_id)
+    }
+
+    /// Receives the next message from the peer. Packets from other
+    /// addresses, repeated handshake messages, replays and packets that fail
+    /// to decrypt are skipped.
+    pub async fn recv_msg(&mut self) -> Result<Vec<u8>, ChannelError> {
+        if self.buf.is_empty() {
+            self.buf = vec![0u8; MAX_DATAGRAM];
+        }
+        loop {
+            let (len, src) = self.transport.recv_from(&mut self.buf).await?;
+            if src != self.peer {
+                continue;
+            }
+            let Ok(packet) = PhysicalPacket::decode(Bytes::copy_from_slice(&self.buf[..len]))
+            else {
+                continue;
+            };
+            match self.handle_packet(&packet).await {
+                Ok(Some(plaintext)) => return Ok(plaintext),
+                Ok(None) => {}
+                Err(ChannelError::Crypto(err)) => {
+                    debug!("dropping packet {} from {}: {}", packet.packet_id, src, err)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+fn handshake_packet(payload: Bytes) -> Bytes {
+    PhysicalPacket {
+        version: RIFT_VERSION,
+        session_id: Some(0),
+        session_alias: None,
+        packet_id: 0,
+        payload,
+    }
+    .encode()
+}
+
+/// Next decodable packet from `peer`, or `None` once `deadline` passes.
+async fn recv_packet<T: DatagramTransport>(
+    transport: &T,
+    peer: SocketAddr,
+    buf: &mut [u8],
+    deadline: Instant,
+) -> Result<Option<PhysicalPacket>, ChannelError> {
+    loop {
+        let (len, src) = match time::timeout_at(deadline, transport.recv_from(buf)).await {
+            Ok(received) => received?,
+            Err(_) => return Ok(None),
+        };
+        if src != peer {
+            debug!("ignoring handshake packet from unexpected peer {}", src);
+            continue;
+        }
+        match PhysicalPacket::decode(Bytes::copy_from_slice(&buf[..len])) {
+            Ok(packet) => return Ok(Some(packet)),
+            Err(err) => debug!("RIFT decode error in handshake from {}: {}", src, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn bind() -> Arc<UdpSocket> {
+        Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn handshake_and_exchange_messages() {
+        let host_socket = bind().await;
+        let host_addr = host_socket.local_addr().unwrap();
+        let host = tokio::spawn(async move {
+            let mut channel = SecureChannel::accept(
+                host_socket,
+                SecureServer::new().unwrap(),
+                ChannelConfig::default(),
+            )
+            .await
+            .unwrap();
+            let request = channel.recv_msg().await.unwrap();
+            channel.set_session_alias(7);
+            channel
+                .send_msg(&[request, b" pong".to_vec()].concat())
+                .await
+                .unwrap();
+        });
+
+        let mut client = SecureChannel::connect(
+            bind().await,
+            host_addr,
+            SecureClient::new().unwrap(),
+            ChannelConfig::default(),
+        )
+        .await
+        .unwrap();
+        client.send_msg(b"ping").await.unwrap();
+        assert_eq!(client.recv_msg().await.unwrap(), b"ping pong");
+        host.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn responder_resends_msg2_for_a_repeated_msg1() {
+        let host_socket = bind().await;
+        let client_socket = bind().await;
+        let client_addr = client_socket.local_addr().unwrap();
+        let mut host = SecureChannel::responder(
+            host_socket.clone(),
+            client_addr,
+            SecureServer::new().unwrap(),
+        );
+
+        let mut client = SecureClient::new().unwrap();
+        let msg1 = PhysicalPacket::decode(handshake_packet(Bytes::from(
+            client.start_handshake().unwrap(),
+        )))
+        .unwrap();
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            assert_eq!(host.handle_packet(&msg1).await.unwrap(), None);
+            let (len, _) = client_socket.recv_from(&mut buf).await.unwrap();
+            replies.push(buf[..len].to_vec());
+        }
+        assert_eq!(replies[0], replies[1]);
+        assert!(!host.is_established());
+
+        let msg2 = PhysicalPacket::decode(Bytes::from(replies.remove(0))).unwrap();
+        let msg3 = PhysicalPacket {
+            version: RIFT_VERSION,
+            session_id: None,
+            session_alias: Some(HANDSHAKE_ALIAS),
+            packet_id: 0,
+            payload: Bytes::from(client.process_server_response(&msg2.payload).unwrap()),
+        };
+        host.handle_packet(&msg3).await.unwrap();
+        assert!(host.is_established());
+
+        let data = PhysicalPacket {
+            version: RIFT_VERSION,
+            session_id: None,
+            session_alias: Some(HANDSHAKE_ALIAS),
+            packet_id: 1,
+            payload: Bytes::from(client.encrypt(1, b"hello").unwrap()),
+        };
+        assert_eq!(
+            host.handle_packet(&data).await.unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+        assert!(matches!(
+            host.handle_packet(&data).await,
+            Err(ChannelError::Crypto(ConnectionError::ReplayDetected(1)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn connect_times_out_without_a_host() {
+        let silent = bind().await;
+        let config = ChannelConfig {
+            handshake_attempts: 2,
+            step_timeout: Duration::from_millis(20),
+        };
+        let err = SecureChannel::connect(
+            bind().await,
+            silent.local_addr().unwrap(),
+            SecureClient::new().unwrap(),
+            config,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            ChannelError::HandshakeTimeout { attempts: 2, .. }
+        ));
+        assert_eq!(ErrorCode::from(&err), ErrorCode::Timeout);
+    }
+}
@@ -31,13 +31,21 @@
 //! ```
 
 use anyhow::Result;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chacha20poly1305::{
     aead::{Aead, KeyInit},
     ChaCha20Poly1305, Nonce,
 };
 use thiserror::Error;
+use tracing::debug;
+use wavry_common::error::ErrorCode;
 
-use crate::noise::{generate_noise_keypair, NoiseError, NoiseInitiator, NoiseResponder};
+use crate::identity::{verify_noise_key_proof, IdentityKeypair, WavryId};
+use crate::noise::{
+    generate_noise_keypair, NoiseError, NoiseInitiator, NoiseResponder, NoiseSession,
+};
+use crate::resume::ResumeTicket;
 use crate::seq_window::SequenceWindow;
 
 /// Handshake message types
@@ -73,6 +81,20 @@ pub enum ConnectionError {
     Noise(#[from] NoiseError),
 }
 
+impl From<&ConnectionError> for ErrorCode {
+    fn from(err: &ConnectionError) -> Self {
+        match err {
+            ConnectionError::HandshakeFailed(_) => ErrorCode::HandshakeFailed,
+            ConnectionError::EncryptionFailed(_) => ErrorCode::EncryptionFailed,
+            ConnectionError::DecryptionFailed(_) => ErrorCode::DecryptionFailed,
+            ConnectionError::ReplayDetected(_) => ErrorCode::ReplayDetected,
+            ConnectionError::InvalidPacket => ErrorCode::MalformedPacket,
+            ConnectionError::NotEstablished => ErrorCode::SessionNotEstablished,
+            ConnectionError::Noise(noise) => noise.into(),
+        }
+    }
+}
+
 /// Connection state during handshake.
 pub enum ClientHandshakeState {
     /// Waiting to send message 1
@@ -90,8 +112,12 @@ pub struct SecureClient {
     initiator: Option<NoiseInitiator>,
     state: ClientHandshakeState,
     cipher: Option<PacketCipher>,
+    resume_ticket: Option<ResumeTicket>,
     recv_window: SequenceWindow,
     local_keypair: ([u8; 32], [u8; 32]),
+    /// Sent in our last handshake message when we have a Wavry identity.
+    identity_proof: Option<Vec<u8>>,
+    remote_identity: Option<WavryId>,
 }
 
 impl SecureClient {
@@ -104,8 +130,11 @@ impl SecureClient {
             initiator: Some(initiator),
             state: ClientHandshakeState::Init,
             cipher: None,
+            resume_ticket: None,
             recv_window: SequenceWindow::new(),
             local_keypair: keypair,
+            identity_proof: None,
+            remote_identity: None,
         })
     }
 
@@ -121,11 +150,22 @@ impl SecureClient {
             initiator: Some(initiator),
             state: ClientHandshakeState::Init,
             cipher: None,
+            resume_ticket: None,
             recv_window: SequenceWindow::new(),
             local_keypair: (private_key, *public_key.as_bytes()),
+            identity_proof: None,
+            remote_identity: None,
         })
     }
 
+    /// Create a client that proves `identity` to the server, keyed so the
+    /// server sees the same static key every session.
+    pub fn with_identity(identity: &IdentityKeypair) -> Result<Self> {
+        let mut client = Self::with_keypair(identity.private_key_bytes())?;
+        client.identity_proof = Some(identity.noise_key_proof(&client.local_keypair.1));
+        Ok(client)
+    }
+
     /// Generate the first handshake message.
     ///
     /// Returns bytes to send to the server.
@@ -136,6 +176,7 @@ impl SecureClient {
             .ok_or(ConnectionError::NotEstablished)?;
         let msg = initiator.write_message_1()?;
         self.state = ClientHandshakeState::SentMsg1;
+        debug!("noise handshake started");
         Ok(msg)
     }
 
@@ -149,11 +190,11 @@ impl SecureClient {
             .ok_or(ConnectionError::NotEstablished)?;
 
         // Read message 2
-        let _payload = initiator.read_message_2(data)?;
+        let payload = initiator.read_message_2(data)?;
         self.state = ClientHandshakeState::ReceivedMsg2;
 
         // Write message 3 and transition to transport
-        let msg3 = initiator.write_message_3(&[])?;
+        let msg3 = initiator.write_message_3(self.identity_proof.as_deref().unwrap_or(&[]))?;
 
         // Extract the session and create cipher
         let initiator = self
@@ -161,8 +202,11 @@ impl SecureClient {
             .take()
             .ok_or(ConnectionError::NotEstablished)?;
         let session = initiator.into_session()?;
+        log_established(&session);
+        self.remote_identity = remote_identity(&session, &payload)?;
 
         // Create cipher from established session (client = initiator)
+        self.resume_ticket = Some(session.resume_ticket().clone());
         self.cipher = Some(PacketCipher::from_session(session, true)?);
         self.recv_window.reset();
         self.state = ClientHandshakeState::Complete;
@@ -195,6 +239,7 @@ impl SecureClient {
         ciphertext: &[u8],
     ) -> Result<Vec<u8>, ConnectionError> {
         if !self.recv_window.check(packet_id) {
+            debug!("dropping replayed packet {}", packet_id);
             return Err(ConnectionError::ReplayDetected(packet_id));
         }
 
@@ -211,6 +256,17 @@ impl SecureClient {
     pub fn local_public_key(&self) -> &[u8; 32] {
         &self.local_keypair.1
     }
+
+    /// Ticket for resuming this session after a path change, once established.
+    pub fn resume_ticket(&self) -> Option<&ResumeTicket> {
+        self.resume_ticket.as_ref()
+    }
+
+    /// Wavry identity the peer proved during the handshake; `None` for
+    /// peers without one.
+    pub fn remote_identity(&self) -> Option<&WavryId> {
+        self.remote_identity.as_ref()
+    }
 }
 
 impl Default for SecureClient {
@@ -219,6 +275,31 @@ impl Default for SecureClient {
     }
 }
 
+/// Logged from the caller's span, so the peer key lands next to its session ids.
+fn log_established(session: &NoiseSession) {
+    let peer_key = session
+        .remote_static()
+        .map(|key| URL_SAFE_NO_PAD.encode(key))
+        .unwrap_or_default();
+    debug!("noise handshake complete (peer_key={})", peer_key);
+}
+
+/// Identity vouched for by the peer's handshake payload. An empty payload
+/// means an anonymous peer; a proof for some other key fails the handshake.
+fn remote_identity(
+    session: &NoiseSession,
+    payload: &[u8],
+) -> Result<Option<WavryId>, ConnectionError> {
+    if payload.is_empty() {
+        return Ok(None);
+    }
+    session
+        .remote_static()
+        .and_then(|key| verify_noise_key_proof(payload, &key))
+        .map(Some)
+        .ok_or_else(|| ConnectionError::HandshakeFailed("invalid identity proof".into()))
+}
+
 /// Connection state during server-side handshake.
 pub enum ServerHandshakeState {
     /// Waiting for message 1
@@ -234,8 +315,12 @@ pub struct SecureServer {
     responder: Option<NoiseResponder>,
     state: ServerHandshakeState,
     cipher: Option<PacketCipher>,
+    resume_ticket: Option<ResumeTicket>,
     recv_window: SequenceWindow,
     local_keypair: ([u8; 32], [u8; 32]),
+    /// Sent in our last handshake message when we have a Wavry identity.
+    identity_proof: Option<Vec<u8>>,
+    remote_identity: Option<WavryId>,
 }
 
 impl SecureServer {
@@ -248,8 +333,11 @@ impl SecureServer {
             responder: Some(responder),
             state: ServerHandshakeState::Init,
             cipher: None,
+            resume_ticket: None,
             recv_window: SequenceWindow::new(),
             local_keypair: keypair,
+            identity_proof: None,
+            remote_identity: None,
         })
     }
 
@@ -264,11 +352,22 @@ impl SecureServer {
             responder: Some(responder),
             state: ServerHandshakeState::Init,
             cipher: None,
+            resume_ticket: None,
             recv_window: SequenceWindow::new(),
             local_keypair: (private_key, *public_key.as_bytes()),
+            identity_proof: None,
+            remote_identity: None,
         })
     }
 
+    /// Create a server that proves `identity` to clients, keyed so clients
+    /// see the same static key every session and can pin it.
+    pub fn with_identity(identity: &IdentityKeypair) -> Result<Self> {
+        let mut server = Self::with_keypair(identity.private_key_bytes())?;
+        server.identity_proof = Some(identity.noise_key_proof(&server.local_keypair.1));
+        Ok(server)
+    }
+
     /// Process client message 1 and generate message 2.
     ///
     /// Returns bytes to send back to client.
@@ -282,8 +381,9 @@ impl SecureServer {
         let _payload = responder.read_message_1(data)?;
 
         // Write message 2
-        let msg2 = responder.write_message_2(&[])?;
+        let msg2 = responder.write_message_2(self.identity_proof.as_deref().unwrap_or(&[]))?;
         self.state = ServerHandshakeState::SentMsg2;
+        debug!("noise handshake message 1 accepted");
 
         Ok(msg2)
     }
@@ -296,7 +396,7 @@ impl SecureServer {
             .ok_or(ConnectionError::NotEstablished)?;
 
         // Read message 3 and transition
-        let _payload = responder.read_message_3(data)?;
+        let payload = responder.read_message_3(data)?;
 
         // Extract session and create cipher (server = responder)
         let responder = self
@@ -304,7 +404,10 @@ impl SecureServer {
             .take()
             .ok_or(ConnectionError::NotEstablished)?;
         let session = responder.into_session()?;
+        log_established(&session);
+        self.remote_identity = remote_identity(&session, &payload)?;
 
+        self.resume_ticket = Some(session.resume_ticket().clone());
         self.cipher = Some(PacketCipher::from_session(session, false)?);
         self.recv_window.reset();
         self.state = ServerHandshakeState::Complete;
@@ -337,6 +440,7 @@ impl SecureServer {
         ciphertext: &[u8],
     ) -> Result<Vec<u8>, ConnectionError> {
         if !self.recv_window.check(packet_id) {
+            debug!("dropping replayed packet {}", packet_id);
             return Err(ConnectionError::ReplayDetected(packet_id));
         }
 
@@ -353,6 +457,17 @@ impl SecureServer {
     pub fn local_public_key(&self) -> &[u8; 32] {
         &self.local_keypair.1
     }
+
+    /// Ticket for resuming this session after a path change, once established.
+    pub fn resume_ticket(&self) -> Option<&ResumeTicket> {
+        self.resume_ticket.as_ref()
+    }
+
+    /// Wavry identity the peer proved during the handshake; `None` for
+    /// peers without one.
+    pub fn remote_identity(&self) -> Option<&WavryId> {
+        self.remote_identity.as_ref()
+    }
 }
 
 impl Default for SecureServer {
@@ -475,6 +590,32 @@ mod tests {
         assert!(server.is_established());
     }
 
+    #[test]
+    fn test_identities_are_proven_in_handshake() {
+        let host = IdentityKeypair::generate();
+        let user = IdentityKeypair::generate();
+        let mut client = SecureClient::with_identity(&user).unwrap();
+        let mut server = SecureServer::with_identity(&host).unwrap();
+
+        let msg1 = client.start_handshake().unwrap();
+        let msg2 = server.process_client_hello(&msg1).unwrap();
+        let msg3 = client.process_server_response(&msg2).unwrap();
+        server.process_client_finish(&msg3).unwrap();
+
+        assert_eq!(client.remote_identity(), Some(&host.wavry_id()));
+        assert_eq!(server.remote_identity(), Some(&user.wavry_id()));
+
+        // Anonymous peers stay anonymous.
+        let mut client = SecureClient::new().unwrap();
+        let mut server = SecureServer::with_identity(&host).unwrap();
+        let msg1 = client.start_handshake().unwrap();
+        let msg2 = server.process_client_hello(&msg1).unwrap();
+        let msg3 = client.process_server_response(&msg2).unwrap();
+        server.process_client_finish(&msg3).unwrap();
+        assert_eq!(client.remote_identity(), Some(&host.wavry_id()));
+        assert_eq!(server.remote_identity(), None);
+    }
+
     #[test]
     fn test_encrypted_communication() {
         // Setup
@@ -31,8 +31,15 @@ use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fs;
+use std::path::Path;
 use zeroize::Zeroize;
 
+/// Domain separation for signatures over a Noise static key.
+const NOISE_KEY_PROOF_CONTEXT: &[u8] = b"wavry-noise-static-v1";
+
+/// Length of a Noise key proof: the Ed25519 public key, then the signature.
+pub const NOISE_KEY_PROOF_LEN: usize = 32 + 64;
+
 /// Wavry ID: base64url-encoded Ed25519 public key.
 ///
 /// This is the primary user identifier in the Wavry system.
@@ -152,6 +159,17 @@ impl IdentityKeypair {
             .is_ok()
     }
 
+    /// Vouches for a Noise static key, so a peer that completes a handshake
+    /// against that key also learns this identity. Sent as a handshake payload.
+    pub fn noise_key_proof(&self, noise_public: &[u8; 32]) -> Vec<u8> {
+        let mut message = NOISE_KEY_PROOF_CONTEXT.to_vec();
+        message.extend_from_slice(noise_public);
+        let mut proof = Vec::with_capacity(NOISE_KEY_PROOF_LEN);
+        proof.extend_from_slice(&self.public_key_bytes());
+        proof.extend_from_slice(&self.sign(&message));
+        proof
+    }
+
     /// Save keypair to files.
     ///
     /// Private key is saved with restricted permissions (0600 on Unix).
@@ -194,6 +212,22 @@ impl IdentityKeypair {
         Ok(keypair)
     }
 
+    /// Load the keypair at `private_path`, or generate and save one (with
+    /// the public key next to it as `<private_path>.pub`) if it is missing.
+    ///
+    /// For long-term device identities that peers remember across sessions.
+    pub fn load_or_generate(private_path: &str) -> Result<Self> {
+        if Path::new(private_path).exists() {
+            return Self::load(private_path);
+        }
+        if let Some(dir) = Path::new(private_path).parent() {
+            fs::create_dir_all(dir).context("failed to create key directory")?;
+        }
+        let keypair = Self::generate();
+        keypair.save(private_path, &format!("{}.pub", private_path))?;
+        Ok(keypair)
+    }
+
     /// Load only the public key (for verification).
     pub fn load_public(public_path: &str) -> Result<PublicIdentity> {
         let bytes = fs::read(public_path).context("failed to read public key")?;
@@ -245,6 +279,22 @@ impl PublicIdentity {
     }
 }
 
+/// Checks a [`IdentityKeypair::noise_key_proof`] against the static key the
+/// handshake authenticated and returns the identity it proves.
+pub fn verify_noise_key_proof(proof: &[u8], noise_public: &[u8; 32]) -> Option<WavryId> {
+    if proof.len() != NOISE_KEY_PROOF_LEN {
+        return None;
+    }
+    let public: [u8; 32] = proof[..32].try_into().ok()?;
+    let signature: [u8; 64] = proof[32..].try_into().ok()?;
+    let identity = PublicIdentity::from_bytes(&public).ok()?;
+    let mut message = NOISE_KEY_PROOF_CONTEXT.to_vec();
+    message.extend_from_slice(noise_public);
+    identity
+        .verify(&message, &signature)
+        .then(|| identity.wavry_id())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -279,6 +329,20 @@ mod tests {
         assert_eq!(wavry_id, parsed);
     }
 
+    #[test]
+    fn test_noise_key_proof_binds_identity_to_key() {
+        let keypair = IdentityKeypair::generate();
+        let noise_public = [7u8; 32];
+        let proof = keypair.noise_key_proof(&noise_public);
+
+        assert_eq!(
+            verify_noise_key_proof(&proof, &noise_public),
+            Some(keypair.wavry_id())
+        );
+        assert_eq!(verify_noise_key_proof(&proof, &[8u8; 32]), None);
+        assert_eq!(verify_noise_key_proof(&proof[..64], &noise_public), None);
+    }
+
     #[test]
     fn test_keypair_bytes_roundtrip() {
         let keypair = IdentityKeypair::generate();
@@ -0,0 +1,254 @@
+//! Trust-on-first-use pinning of host identities.
+//!
+//! The Noise handshake proves a host holds the identity it presents, not
+//! that it is the host the user reached last time. [`PeerStore`] remembers
+//! the [`WavryId`] each host proved on first connection and reports any
+//! later change, like SSH's `known_hosts`.
+//!
+//! # File Format
+//!
+//! One host per line, `#` starts a comment:
+//!
+//! ```text
+//! <host> <wavry-id> <first-seen unix seconds>
+//! ```
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::identity::WavryId;
+
+/// File name of the store inside [`wavry_common::helpers::config_dir`].
+pub const KNOWN_HOSTS_FILE: &str = "known_hosts";
+
+/// A pinned host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownHost {
+    /// Name the user connects by: a host name, `ip:port`, or a signaling
+    /// username.
+    pub host: String,
+    pub wavry_id: WavryId,
+    /// Unix seconds when the identity was first pinned.
+    pub first_seen: u64,
+}
+
+/// Outcome of checking a host's identity against the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostTrust {
+    /// Matches the pinned identity.
+    Trusted,
+    /// Nothing pinned for this host yet.
+    FirstUse,
+    /// The host proved a different identity than the one pinned. Either it
+    /// was reinstalled or someone is impersonating it.
+    Changed { pinned: WavryId },
+}
+
+/// Hosts pinned on first connection, optionally backed by a file.
+#[derive(Debug, Default)]
+pub struct PeerStore {
+    path: Option<PathBuf>,
+    hosts: Vec<KnownHost>,
+}
+
+impl PeerStore {
+    /// A store that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Default location of the user's store.
+    pub fn default_path() -> Option<PathBuf> {
+        wavry_common::helpers::config_dir().map(|dir| dir.join(KNOWN_HOSTS_FILE))
+    }
+
+    /// Loads the store at `path`; a missing file is an empty store.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let hosts = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                parse(&contents).with_context(|| format!("failed to parse {}", path.display()))?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            hosts,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Pinned hosts in the order they were first seen.
+    pub fn hosts(&self) -> &[KnownHost] {
+        &self.hosts
+    }
+
+    pub fn get(&self, host: &str) -> Option<&KnownHost> {
+        self.hosts.iter().find(|known| known.host == host)
+    }
+
+    /// Compares `wavry_id` with what is pinned for `host` without changing
+    /// the store.
+    pub fn check(&self, host: &str, wavry_id: &WavryId) -> HostTrust {
+        match self.get(host) {
+            None => HostTrust::FirstUse,
+            Some(known) if known.wavry_id == *wavry_id => HostTrust::Trusted,
+            Some(known) => HostTrust::Changed {
+                pinned: known.wavry_id.clone(),
+            },
+        }
+    }
+
+    /// Checks `wavry_id` and pins it if `host` is new. A changed identity is
+    /// reported but not replaced; call [`trust`](Self::trust) once the user
+    /// has confirmed it.
+    pub fn verify(&mut self, host: &str, wavry_id: &WavryId) -> Result<HostTrust> {
+        let trust = self.check(host, wavry_id);
+        if trust == HostTrust::FirstUse {
+            self.trust(host, wavry_id)?;
+        }
+        Ok(trust)
+    }
+
+    /// Pins `wavry_id` for `host`, replacing any earlier identity.
+    pub fn trust(&mut self, host: &str, wavry_id: &WavryId) -> Result<()> {
+        if host.is_empty() || host.chars().any(char::is_whitespace) {
+            bail!("invalid host name {:?}", host);
+        }
+        let first_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let known = KnownHost {
+            host: host.to_string(),
+            wavry_id: wavry_id.clone(),
+            first_seen,
+        };
+        match self.hosts.iter_mut().find(|existing| existing.host == host) {
+            Some(existing) => *existing = known,
+            None => self.hosts.push(known),
+        }
+        self.save()
+    }
+
+    /// Forgets `host`; returns whether it was pinned.
+    pub fn remove(&mut self, host: &str) -> Result<bool> {
+        let before = self.hosts.len();
+        self.hosts.retain(|known| known.host != host);
+        if self.hosts.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut contents = String::from("# Wavry known hosts: <host> <wavry-id> <first-seen>\n");
+        for known in &self.hosts {
+            contents.push_str(&format!(
+                "{} {} {}\n",
+                known.host, known.wavry_id, known.first_seen
+            ));
+        }
+        // Write-then-rename so a crash never leaves a truncated store.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents).with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+fn parse(contents: &str) -> Result<Vec<KnownHost>> {
+    let mut hosts = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(host), Some(wavry_id)) = (fields.next(), fields.next()) else {
+            bail!("line {}: expected `<host> <wavry-id>`", index + 1);
+        };
+        let wavry_id = WavryId::parse(wavry_id).with_context(|| format!("line {}", index + 1))?;
+        let first_seen = fields
+            .next()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        hosts.push(KnownHost {
+            host: host.to_string(),
+            wavry_id,
+            first_seen,
+        });
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityKeypair;
+
+    #[test]
+    fn test_first_use_pins_and_change_is_reported() {
+        let original = IdentityKeypair::generate().wavry_id();
+        let imposter = IdentityKeypair::generate().wavry_id();
+        let mut store = PeerStore::in_memory();
+
+        assert_eq!(
+            store.verify("desk:5000", &original).unwrap(),
+            HostTrust::FirstUse
+        );
+        assert_eq!(
+            store.verify("desk:5000", &original).unwrap(),
+            HostTrust::Trusted
+        );
+        assert_eq!(
+            store.verify("desk:5000", &imposter).unwrap(),
+            HostTrust::Changed {
+                pinned: original.clone()
+            }
+        );
+        // A change is not pinned until trusted explicitly.
+        assert_eq!(store.get("desk:5000").unwrap().wavry_id, original);
+
+        store.trust("desk:5000", &imposter).unwrap();
+        assert_eq!(store.check("desk:5000", &imposter), HostTrust::Trusted);
+        assert!(store.remove("desk:5000").unwrap());
+        assert!(!store.remove("desk:5000").unwrap());
+        assert!(store.hosts().is_empty());
+    }
+
+    #[test]
+    fn test_store_round_trips_through_file() {
+        let dir = std::env::temp_dir().join(format!("wavry-known-hosts-{}", rand::random::<u64>()));
+        let path = dir.join(KNOWN_HOSTS_FILE);
+        let id = IdentityKeypair::generate().wavry_id();
+
+        let mut store = PeerStore::open(&path).unwrap();
+        assert!(store.hosts().is_empty());
+        store.trust("192.168.1.20:5000", &id).unwrap();
+        assert!(store.trust("two words", &id).is_err());
+
+        let reopened = PeerStore::open(&path).unwrap();
+        assert_eq!(reopened.hosts(), store.hosts());
+        assert_eq!(reopened.check("192.168.1.20:5000", &id), HostTrust::Trusted);
+
+        fs::write(&path, "host not-a-key\n").unwrap();
+        assert!(PeerStore::open(&path).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
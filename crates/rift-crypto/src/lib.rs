@@ -2,9 +2,13 @@
 //!
 //! This crate provides:
 //! - Ed25519 identity keys and Wavry IDs
+//! - Trust-on-first-use pinning of host identities
+//! - Remembered allow/deny decisions about client devices
 //! - Noise XX handshake for secure session establishment
 //! - Encrypted session management with replay protection
+//! - Resumption tickets for re-binding a session after a path change
 //! - Secure connection abstraction for UDP transport
+//! - [`SecureChannel`], which runs the handshake and framing over a shared socket
 //!
 //! # Design
 //!
@@ -18,13 +22,21 @@
 
 #![forbid(unsafe_code)]
 
+pub mod authorized_clients;
+pub mod channel;
 pub mod connection;
 pub mod identity;
+pub mod known_hosts;
 pub mod noise;
+pub mod resume;
 pub mod seq_window;
 pub mod session;
 
+pub use authorized_clients::{AuthorizedClient, AuthorizedClients, ClientDecision};
+pub use channel::{ChannelConfig, ChannelError, DatagramTransport, SecureChannel};
 pub use identity::{IdentityKeypair, WavryId};
+pub use known_hosts::{HostTrust, KnownHost, PeerStore};
 pub use noise::{NoiseInitiator, NoiseResponder, NoiseSession};
+pub use resume::ResumeTicket;
 pub use seq_window::SequenceWindow;
 pub use session::EncryptedSession;
@@ -35,6 +35,10 @@
 use anyhow::{Context, Result};
 use snow::{Builder, HandshakeState, TransportState};
 use thiserror::Error;
+use wavry_common::error::ErrorCode;
+use zeroize::Zeroize;
+
+use crate::resume::ResumeTicket;
 
 /// Noise protocol pattern (XX with X25519, ChaCha20-Poly1305, BLAKE2s)
 const NOISE_PATTERN: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
@@ -64,10 +68,24 @@ pub enum NoiseError {
     Snow(#[from] snow::Error),
 }
 
+impl From<&NoiseError> for ErrorCode {
+    fn from(err: &NoiseError) -> Self {
+        match err {
+            NoiseError::EncryptionFailed(_) => ErrorCode::EncryptionFailed,
+            NoiseError::DecryptionFailed(_) => ErrorCode::DecryptionFailed,
+            NoiseError::HandshakeNotComplete => ErrorCode::SessionNotEstablished,
+            NoiseError::HandshakeAlreadyComplete
+            | NoiseError::InvalidMessage
+            | NoiseError::Snow(_) => ErrorCode::HandshakeFailed,
+        }
+    }
+}
+
 /// Noise handshake initiator (client side).
 pub struct NoiseInitiator {
     state: InitiatorState,
     handshake_hash: Option<[u8; 32]>,
+    resume_ticket: Option<ResumeTicket>,
 }
 
 enum InitiatorState {
@@ -92,6 +110,7 @@ impl NoiseInitiator {
         Ok(Self {
             state: InitiatorState::Handshake(Box::new(state)),
             handshake_hash: None,
+            resume_ticket: None,
         })
     }
 
@@ -151,10 +170,13 @@ impl NoiseInitiator {
             .try_into()
             .map_err(|_| NoiseError::InvalidMessage)?;
 
+        let ticket = resume_ticket(&mut handshake, &handshake_hash);
+
         // Transition to transport mode
         let transport = handshake.into_transport_mode()?;
         self.state = InitiatorState::Transport(transport);
         self.handshake_hash = Some(handshake_hash);
+        self.resume_ticket = Some(ticket);
 
         Ok(buf)
     }
@@ -181,10 +203,12 @@ impl NoiseInitiator {
         let hash = self
             .handshake_hash
             .ok_or(NoiseError::HandshakeNotComplete)?;
+        let resume_ticket = self.resume_ticket.ok_or(NoiseError::HandshakeNotComplete)?;
         match self.state {
             InitiatorState::Transport(t) => Ok(NoiseSession {
                 transport: t,
                 handshake_hash: hash,
+                resume_ticket,
             }),
             _ => Err(NoiseError::HandshakeNotComplete),
         }
@@ -195,6 +219,7 @@ impl NoiseInitiator {
 pub struct NoiseResponder {
     state: ResponderState,
     handshake_hash: Option<[u8; 32]>,
+    resume_ticket: Option<ResumeTicket>,
 }
 
 enum ResponderState {
@@ -219,6 +244,7 @@ impl NoiseResponder {
         Ok(Self {
             state: ResponderState::Handshake(Box::new(state)),
             handshake_hash: None,
+            resume_ticket: None,
         })
     }
 
@@ -278,10 +304,13 @@ impl NoiseResponder {
             .try_into()
             .map_err(|_| NoiseError::InvalidMessage)?;
 
+        let ticket = resume_ticket(&mut handshake, &handshake_hash);
+
         // Transition to transport mode
         let transport = handshake.into_transport_mode()?;
         self.state = ResponderState::Transport(transport);
         self.handshake_hash = Some(handshake_hash);
+        self.resume_ticket = Some(ticket);
 
         Ok(buf)
     }
@@ -308,22 +337,35 @@ impl NoiseResponder {
         let hash = self
             .handshake_hash
             .ok_or(NoiseError::HandshakeNotComplete)?;
+        let resume_ticket = self.resume_ticket.ok_or(NoiseError::HandshakeNotComplete)?;
         match self.state {
             ResponderState::Transport(t) => Ok(NoiseSession {
                 transport: t,
                 handshake_hash: hash,
+                resume_ticket,
             }),
             _ => Err(NoiseError::HandshakeNotComplete),
         }
     }
 }
 
+/// Resumption ticket from the keys Noise splits off the final chaining key,
+/// which unlike the handshake hash never leave the two peers.
+fn resume_ticket(handshake: &mut HandshakeState, handshake_hash: &[u8; 32]) -> ResumeTicket {
+    let (mut initiator_key, mut responder_key) = handshake.dangerously_get_raw_split();
+    let ticket = ResumeTicket::derive(&initiator_key, &responder_key, handshake_hash);
+    initiator_key.zeroize();
+    responder_key.zeroize();
+    ticket
+}
+
 /// Established Noise session for encrypted transport.
 ///
 /// After handshake completion, use this to encrypt/decrypt messages.
 pub struct NoiseSession {
     transport: TransportState,
     handshake_hash: [u8; 32],
+    resume_ticket: ResumeTicket,
 }
 
 impl NoiseSession {
@@ -331,6 +373,11 @@ impl NoiseSession {
     pub fn handshake_hash(&self) -> &[u8; 32] {
         &self.handshake_hash
     }
+
+    /// Ticket for resuming this session after a path change.
+    pub fn resume_ticket(&self) -> &ResumeTicket {
+        &self.resume_ticket
+    }
     /// Encrypt a message.
     ///
     /// Returns ciphertext (plaintext + 16-byte auth tag).
@@ -7,6 +7,7 @@ use crate::noise::{NoiseError, NoiseSession};
 use crate::seq_window::SequenceWindow;
 use anyhow::Result;
 use thiserror::Error;
+use wavry_common::error::ErrorCode;
 
 /// Session encryption errors.
 #[derive(Debug, Error)]
@@ -27,6 +28,18 @@ pub enum SessionError {
     Noise(#[from] NoiseError),
 }
 
+impl From<&SessionError> for ErrorCode {
+    fn from(err: &SessionError) -> Self {
+        match err {
+            SessionError::Encryption(_) => ErrorCode::EncryptionFailed,
+            SessionError::Decryption(_) => ErrorCode::DecryptionFailed,
+            SessionError::Replay(_) => ErrorCode::ReplayDetected,
+            SessionError::NotEstablished => ErrorCode::SessionNotEstablished,
+            SessionError::Noise(noise) => noise.into(),
+        }
+    }
+}
+
 /// Encrypted RIFT session with replay protection.
 ///
 /// Wraps a Noise session with:
@@ -4,6 +4,7 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(name = "wavry")]
@@ -36,10 +37,31 @@ enum Command {
         server: String,
     },
 
+    /// List or forget hosts pinned on first connection
+    KnownHosts {
+        /// Store to use (defaults to `known_hosts` in the Wavry config dir)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        #[command(subcommand)]
+        action: KnownHostsAction,
+    },
+
     /// Show version information
     Version,
 }
 
+#[derive(Subcommand, Debug)]
+enum KnownHostsAction {
+    /// Print each pinned host and its Wavry ID
+    List,
+    /// Forget a host so its next identity is pinned afresh
+    Remove {
+        /// Host name or address as listed
+        host: String,
+    },
+}
+
 fn main() -> Result<()> {
     wavry_common::init_tracing();
 
@@ -140,6 +162,26 @@ fn main() -> Result<()> {
                 Ok::<(), anyhow::Error>(())
             })?;
         }
+        Command::KnownHosts { file, action } => {
+            let path = file
+                .or_else(rift_crypto::PeerStore::default_path)
+                .ok_or_else(|| anyhow::anyhow!("no config directory; pass --file"))?;
+            let mut store = rift_crypto::PeerStore::open(&path)?;
+            match action {
+                KnownHostsAction::List => {
+                    for known in store.hosts() {
+                        println!("{}  {}", known.host, known.wavry_id);
+                    }
+                }
+                KnownHostsAction::Remove { host } => {
+                    if store.remove(&host)? {
+                        println!("Removed {} from {}", host, path.display());
+                    } else {
+                        println!("{} is not in {}", host, path.display());
+                    }
+                }
+            }
+        }
         Command::Version => {
             println!("wavry {}", env!("CARGO_PKG_VERSION"));
         }
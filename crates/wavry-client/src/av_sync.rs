@@ -0,0 +1,184 @@
+//! Audio/video lip-sync.
+//!
+//! Audio goes straight to the sound card while video waits in the jitter
+//! buffer and the decoder, so without correction sound runs ahead of the
+//! picture. The host stamps both with capture times on one clock; every
+//! presented video frame ties that clock to local time, and audio is held
+//! until it will be heard at the same offset. Audio that falls more than
+//! [`AV_SYNC_TOLERANCE_US`] behind with more queued after it is dropped so
+//! playback catches up instead of lagging for the rest of the session.
+
+use rift_core::AudioPacket;
+use std::collections::VecDeque;
+use tracing::debug;
+
+/// Largest audio/video offset left uncorrected.
+pub const AV_SYNC_TOLERANCE_US: i64 = 40_000;
+/// Time from handing audio to the renderer until it is heard.
+pub const DEFAULT_AUDIO_OUTPUT_LATENCY_US: u64 = 20_000;
+/// Audio is never held longer than this after arriving.
+const MAX_AUDIO_HOLD_US: u64 = 500_000;
+/// A video offset jump beyond this means the host restarted its encoder
+/// with a new timestamp base, so the offset is relearned from scratch.
+const VIDEO_CLOCK_RESET_US: f64 = 1_000_000.0;
+const MAX_QUEUED_PACKETS: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AvSyncStats {
+    /// When the last released packet will be heard relative to its video;
+    /// positive when audio is behind.
+    pub skew_us: i64,
+    /// Packets dropped to catch audio up with video.
+    pub dropped: u64,
+}
+
+struct QueuedAudio {
+    packet: AudioPacket,
+    arrival_us: u64,
+}
+
+pub struct AvSync {
+    output_latency_us: u64,
+    /// Local presentation time minus host timestamp for recent video frames.
+    video_offset_us: Option<f64>,
+    queue: VecDeque<QueuedAudio>,
+    stats: AvSyncStats,
+}
+
+impl Default for AvSync {
+    fn default() -> Self {
+        Self::new(DEFAULT_AUDIO_OUTPUT_LATENCY_US)
+    }
+}
+
+impl AvSync {
+    pub fn new(output_latency_us: u64) -> Self {
+        Self {
+            output_latency_us,
+            video_offset_us: None,
+            queue: VecDeque::new(),
+            stats: AvSyncStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> AvSyncStats {
+        self.stats
+    }
+
+    /// Records that the video frame captured at `timestamp_us` reached the
+    /// screen at `now_us`.
+    pub fn on_video_presented(&mut self, timestamp_us: u64, now_us: u64) {
+        if timestamp_us == 0 {
+            return;
+        }
+        let offset = now_us as f64 - timestamp_us as f64;
+        self.video_offset_us = Some(match self.video_offset_us {
+            Some(current) if (offset - current).abs() < VIDEO_CLOCK_RESET_US => {
+                current + (offset - current) / 8.0
+            }
+            _ => offset,
+        });
+    }
+
+    pub fn push_audio(&mut self, packet: AudioPacket, arrival_us: u64) {
+        if self.queue.len() >= MAX_QUEUED_PACKETS {
+            self.queue.pop_front();
+            self.stats.dropped += 1;
+        }
+        self.queue.push_back(QueuedAudio { packet, arrival_us });
+    }
+
+    /// Next packet to hand to the audio renderer. Without video to follow,
+    /// audio passes straight through.
+    pub fn pop_ready(&mut self, now_us: u64) -> Option<AudioPacket> {
+        loop {
+            let front = self.queue.front()?;
+            let Some(offset) = self.video_offset_us else {
+                return self.release(None);
+            };
+            // When the front packet must start playing to be heard in sync.
+            let due_us = front.packet.timestamp_us as f64 + offset - self.output_latency_us as f64;
+            let held_us = now_us.saturating_sub(front.arrival_us);
+            if due_us > now_us as f64 && held_us < MAX_AUDIO_HOLD_US {
+                // Unrelated clocks would hold everything; pass through then.
+                if due_us - now_us as f64 > MAX_AUDIO_HOLD_US as f64 {
+                    return self.release(None);
+                }
+                return None;
+            }
+
+            let skew_us = (now_us as f64 - due_us) as i64;
+            if skew_us > AV_SYNC_TOLERANCE_US && self.queue.len() > 1 {
+                debug!(
+                    "dropping audio {}us behind video at ts {}",
+                    skew_us, front.packet.timestamp_us
+                );
+                self.queue.pop_front();
+                self.stats.dropped += 1;
+                continue;
+            }
+            return self.release(Some(skew_us));
+        }
+    }
+
+    fn release(&mut self, skew_us: Option<i64>) -> Option<AudioPacket> {
+        if let Some(skew_us) = skew_us {
+            self.stats.skew_us = skew_us;
+        }
+        self.queue.pop_front().map(|queued| queued.packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio(timestamp_us: u64) -> AudioPacket {
+        AudioPacket {
+            timestamp_us,
+            payload: Vec::new(),
+            layout: 0,
+            direction: 0,
+        }
+    }
+
+    #[test]
+    fn audio_waits_for_matching_video() {
+        let mut sync = AvSync::new(10_000);
+        sync.push_audio(audio(1_000_000), 0);
+        assert!(sync.pop_ready(0).is_some(), "no video yet");
+
+        // Video captured at 1.0s is on screen at local 0.2s.
+        sync.on_video_presented(1_000_000, 200_000);
+        sync.push_audio(audio(1_050_000), 210_000);
+        assert!(sync.pop_ready(230_000).is_none());
+        let packet = sync.pop_ready(240_000).expect("due 10ms before playback");
+        assert_eq!(packet.timestamp_us, 1_050_000);
+        assert_eq!(sync.stats().skew_us, 0);
+    }
+
+    #[test]
+    fn late_audio_backlog_is_dropped_to_catch_up() {
+        let mut sync = AvSync::new(0);
+        sync.on_video_presented(1_000_000, 100_000);
+        for i in 0..5 {
+            sync.push_audio(audio(1_000_000 + i * 20_000), 250_000);
+        }
+
+        // At 250ms the first packets are 150ms and 130ms late.
+        let packet = sync.pop_ready(250_000).unwrap();
+        assert_eq!(packet.timestamp_us, 1_080_000);
+        assert_eq!(sync.stats().dropped, 4);
+        assert_eq!(sync.stats().skew_us, 70_000);
+    }
+
+    #[test]
+    fn video_timestamp_rebase_relearns_offset() {
+        let mut sync = AvSync::new(0);
+        sync.on_video_presented(50_000_000, 100_000);
+        sync.on_video_presented(10_000, 200_000);
+        sync.push_audio(audio(20_000), 200_000);
+        assert!(sync.pop_ready(205_000).is_none());
+        assert!(sync.pop_ready(210_000).is_some());
+    }
+}
@@ -4,8 +4,12 @@ use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast;
-use wavry_client::{run_client, ClientConfig, FileTransferAction, FileTransferCommand};
+use wavry_client::{
+    run_client, ClientConfig, FileTransferAction, FileTransferCommand, JitterBufferConfig,
+};
 use wavry_vr::VrAdapter;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+use wavry_vr::VrAdapterManager;
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 use wavry_vr_alvr::AlvrAdapter;
@@ -15,6 +19,9 @@ use wavry_vr_alvr::AlvrAdapter;
 struct Args {
     #[arg(long)]
     connect: Option<SocketAddr>,
+    /// Another address of the same host, e.g. its IPv6 address, raced against --connect
+    #[arg(long = "also-connect")]
+    also_connect: Vec<SocketAddr>,
     #[arg(long, default_value = "wavry-client")]
     name: String,
     /// Disable encryption (for testing/debugging)
@@ -23,12 +30,30 @@ struct Args {
     /// Enable PCVR adapter (Linux/Windows only)
     #[arg(long, default_value_t = false)]
     vr: bool,
-    /// Enable local recording to MP4
+    /// Show the stream as a floating screen inside the running VR app (implies --vr)
+    #[arg(long, default_value_t = false)]
+    vr_overlay: bool,
+    /// Overlay screen width in meters
+    #[arg(long, default_value_t = 2.0)]
+    overlay_width: f32,
+    /// Overlay screen distance from the viewer in meters
+    #[arg(long, default_value_t = 2.0)]
+    overlay_distance: f32,
+    /// Overlay screen curvature, from 0.0 (flat) to 1.0 (wrapped around the viewer)
+    #[arg(long, default_value_t = 0.0)]
+    overlay_curvature: f32,
+    /// Read VR commands from stdin during the session: `reconnect`, `overlay` or `immersive`
+    #[arg(long, default_value_t = false, conflicts_with = "file_control_stdin")]
+    vr_control_stdin: bool,
+    /// Record the received stream locally
     #[arg(long, default_value_t = false)]
     record: bool,
     /// Directory to store recordings
     #[arg(long, default_value = "recordings")]
     record_dir: String,
+    /// Recording container: mkv (video and audio) or mp4 (video only)
+    #[arg(long, default_value = "mkv")]
+    record_format: String,
     /// Send file to host after session establishment (repeatable)
     #[arg(long = "send-file", value_name = "PATH")]
     send_files: Vec<PathBuf>,
@@ -36,11 +61,41 @@ struct Args {
     #[arg(long, default_value = "received-files")]
     file_out_dir: PathBuf,
     /// Maximum inbound file size in bytes
-    #[arg(long, default_value_t = wavry_common::file_transfer::DEFAULT_MAX_FILE_BYTES)]
+    #[arg(long, default_value_t = rift_core::MAX_FILE_TRANSFER_BYTES)]
     file_max_bytes: u64,
     /// Read file-transfer commands from stdin as: `<file_id> <pause|resume|cancel|retry>`
     #[arg(long, default_value_t = false)]
     file_control_stdin: bool,
+    /// Minimum playout delay for received video, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    jitter_target_ms: u64,
+    /// Maximum playout delay the jitter buffer may grow to, in milliseconds
+    #[arg(long, default_value_t = wavry_client::media::JITTER_MAX_BUFFER_US / 1_000)]
+    jitter_max_ms: u64,
+    /// Hold the playout delay at --jitter-target-ms instead of adapting to jitter
+    #[arg(long, default_value_t = false)]
+    fixed_jitter_delay: bool,
+    /// Input packets per second to hosts that take batches; 0 sends each event on its own
+    #[arg(long, default_value_t = wavry_client::input::DEFAULT_INPUT_RATE_HZ)]
+    input_rate_hz: u32,
+    /// Send this machine's microphone to the host for in-game voice chat
+    #[arg(long, default_value_t = false)]
+    microphone: bool,
+    /// Store of pinned host identities (defaults to `known_hosts` in the Wavry config dir)
+    #[arg(long)]
+    known_hosts: Option<PathBuf>,
+    /// Name to pin the host under instead of its address
+    #[arg(long)]
+    host_name: Option<String>,
+    /// Warn instead of refusing to connect when a host's identity has changed
+    #[arg(long, default_value_t = false)]
+    allow_host_key_change: bool,
+    /// Ed25519 key hosts recognise this device by (created if missing; defaults to the Wavry config dir)
+    #[arg(long)]
+    identity_key: Option<PathBuf>,
+    /// Invite link from a host (`https://<gateway>/invite/<token>`); used instead of --connect
+    #[arg(long, conflicts_with = "connect")]
+    invite: Option<String>,
 }
 
 fn parse_file_control_line(line: &str) -> Result<FileTransferCommand, String> {
@@ -68,15 +123,78 @@ fn parse_file_control_line(line: &str) -> Result<FileTransferCommand, String> {
     Ok(FileTransferCommand { file_id, action })
 }
 
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn alvr_adapter(overlay: Option<wavry_vr::OverlayConfig>) -> AlvrAdapter {
+    match overlay {
+        Some(overlay) => AlvrAdapter::new().with_overlay(overlay),
+        None => AlvrAdapter::new(),
+    }
+}
+
+/// Switches between overlay and immersive mode, or restarts the adapter after
+/// the headset reconnects, without leaving the session.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn spawn_vr_control(manager: Arc<Mutex<VrAdapterManager>>, overlay: wavry_vr::OverlayConfig) {
+    std::thread::spawn(move || {
+        eprintln!("VR control stdin enabled: use `reconnect`, `overlay` or `immersive`");
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let Ok(mut manager) = manager.lock() else {
+                break;
+            };
+            let result = match line.trim() {
+                "reconnect" => manager.restart(),
+                "overlay" => manager.swap(Box::new(alvr_adapter(Some(overlay)))),
+                "immersive" => manager.swap(Box::new(alvr_adapter(None))),
+                other => {
+                    eprintln!("unknown VR command `{}`", other);
+                    continue;
+                }
+            };
+            if let Err(err) = result {
+                eprintln!("VR adapter switch failed: {}", err);
+            }
+        }
+    });
+}
+
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt().with_env_filter("info").init();
 
     let args = Args::parse();
 
-    let vr_adapter: Option<Arc<Mutex<dyn VrAdapter>>> = if args.vr {
+    // Hosts remember which devices they approved by this key.
+    let identity_key = match args
+        .identity_key
+        .clone()
+        .or_else(|| wavry_common::helpers::config_dir().map(|dir| dir.join("identity.key")))
+    {
+        Some(path) => {
+            let path = path
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("identity key path is not valid UTF-8"))?
+                .to_string();
+            Some(rift_crypto::IdentityKeypair::load_or_generate(&path)?.private_key_bytes())
+        }
+        None => None,
+    };
+
+    let vr_adapter: Option<Arc<Mutex<dyn VrAdapter>>> = if args.vr || args.vr_overlay {
         #[cfg(any(target_os = "linux", target_os = "windows"))]
         {
-            Some(Arc::new(Mutex::new(AlvrAdapter::new())))
+            let overlay = wavry_vr::OverlayConfig {
+                width_m: args.overlay_width,
+                distance_m: args.overlay_distance,
+                curvature: args.overlay_curvature.clamp(0.0, 1.0),
+            };
+            let initial = alvr_adapter(args.vr_overlay.then_some(overlay));
+            let manager = Arc::new(Mutex::new(VrAdapterManager::new(Box::new(initial))));
+            if args.vr_control_stdin {
+                spawn_vr_control(Arc::clone(&manager), overlay);
+            }
+            Some(manager)
         }
         #[cfg(not(any(target_os = "linux", target_os = "windows")))]
         {
@@ -92,6 +210,10 @@ fn main() -> anyhow::Result<()> {
         Some(wavry_media::RecorderConfig {
             enabled: true,
             output_dir: std::path::PathBuf::from(args.record_dir),
+            container: match args.record_format.to_lowercase().as_str() {
+                "mp4" => wavry_media::Container::Mp4,
+                _ => wavry_media::Container::Matroska,
+            },
             ..Default::default()
         })
     } else {
@@ -131,14 +253,16 @@ fn main() -> anyhow::Result<()> {
 
     let config = ClientConfig {
         connect_addr: args.connect,
+        alternate_addrs: args.also_connect,
         client_name: args.name,
         no_encrypt: args.no_encrypt,
-        identity_key: None,
+        identity_key,
         relay_info: None,
         master_url: None,
         max_resolution: None,
         gamepad_enabled: true,
         gamepad_deadzone: 0.1,
+        input_rate_hz: args.input_rate_hz,
         vr_adapter,
         runtime_stats: None,
         recorder_config,
@@ -146,6 +270,29 @@ fn main() -> anyhow::Result<()> {
         file_out_dir: args.file_out_dir,
         file_max_bytes: args.file_max_bytes,
         file_command_bus,
+        file_send_bus: None,
+        file_event_bus: None,
+        clipboard_sync: None,
+        bandwidth_limit_bus: None,
+        host_recording_bus: None,
+        local_recording_bus: None,
+        pointer_mode_bus: None,
+        text_input_bus: None,
+        jitter_buffer: JitterBufferConfig {
+            target_delay_us: args.jitter_target_ms * 1_000,
+            max_delay_us: args.jitter_max_ms.max(args.jitter_target_ms) * 1_000,
+            adaptive: !args.fixed_jitter_delay,
+        },
+        microphone: args.microphone,
+        known_hosts: args
+            .known_hosts
+            .or_else(rift_crypto::PeerStore::default_path),
+        host_name: args.host_name,
+        strict_host_key: !args.allow_host_key_change,
+        socket: None,
+        remote_candidates: Vec::new(),
+        invite: args.invite,
+        relay_lease_source: None,
     };
 
     tokio::runtime::Builder::new_multi_thread()
@@ -1,40 +1,63 @@
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
-use rand::Rng as _;
+use futures::StreamExt;
 use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicU64, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Mutex,
 };
 use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::time;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, warn, Instrument, Span};
 
 use rift_core::{
+    compact::{CompactDecoder, CompactEncoder},
     decode_msg, encode_msg,
-    relay::{LeasePresentPayload, PeerRole, RelayHeader, RelayPacketType, RELAY_HEADER_SIZE},
+    feedback::{FeedbackRecorder, FEEDBACK_INTERVAL},
+    message_channel,
+    probe::ProbeReceiver,
+    relay::{
+        LeaseAckPayload, LeasePresentPayload, LeaseRejectPayload, PeerRole, RelayHeader,
+        RelayPacketType, RELAY_HEADER_SIZE,
+    },
     Codec as RiftCodec, ControlMessage as ProtoControl, Hello as ProtoHello,
-    Message as ProtoMessage, PhysicalPacket, Ping as ProtoPing, Resolution as ProtoResolution,
-    StatsReport as ProtoStatsReport, RIFT_VERSION,
+    Message as ProtoMessage, PermissionSet, PhysicalPacket, Ping as ProtoPing,
+    Resolution as ProtoResolution, StatsReport as ProtoStatsReport, DEFAULT_FILE_CHUNK_BYTES,
+    RIFT_VERSION,
 };
-use socket2::SockRef;
-
-use crate::helpers::{env_bool, local_platform, now_us};
-use crate::input::spawn_input_threads;
+use rift_crypto::{HostTrust, PeerStore, WavryId};
+
+use crate::av_sync::AvSync;
+use crate::discovery;
+use crate::helpers::{
+    apply_cursor_update, audio_layout_from_proto, audio_layout_to_proto, env_bool, local_platform,
+    now_us, pose_to_proto, random_file_id, stereo_mode_from_proto, stereo_mode_to_proto,
+    vr_video_frame,
+};
+use crate::ice;
+use crate::input::{spawn_input_threads, InputSender};
+use crate::invite;
+use crate::lease::{self, LeaseAction, RelayLeaseManager, RelayLeaseSource, SignalingAuth};
 use crate::media::{
-    ArrivalJitter, FecCache, FrameAssembler, JitterBuffer, NackWindow, RttTracker,
-    FRAME_TIMEOUT_US, NACK_WINDOW_SIZE,
+    ArrivalJitter, AssembledFrame, FecCache, FrameAssembler, JitterBuffer, RttTracker,
+    FRAME_TIMEOUT_US,
 };
+use crate::nack::{NackTracker, NACK_WINDOW_SIZE};
+use crate::net;
+use crate::path::PathSet;
+use crate::telemetry::LatencyTelemetry;
 use crate::types::{
-    ClientConfig, ClientRuntimeStats, CryptoState, FileTransferCommand, RelayInfo, RendererFactory,
-    VrOutbound,
+    ClientConfig, ClientRuntimeStats, ClipboardSyncDirection, CryptoState, FileSendRequest,
+    FileTransferCommand, FileTransferDirection, FileTransferEvent, LatencyBreakdown,
+    MonitorSelection, RelayInfo, RendererFactory, VrOutbound,
 };
 
-use wavry_common::file_transfer::{FileOffer, IncomingFile, OutgoingFile, DEFAULT_CHUNK_SIZE};
+use wavry_common::file_transfer::{FileOffer, IncomingFile, OutgoingFile};
+use wavry_common::{session_span, SessionSpanExt};
 #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
 use wavry_media::CapabilityProbe;
 #[cfg(not(target_os = "linux"))]
@@ -43,18 +66,28 @@ use wavry_media::DummyRenderer as VideoRenderer;
 use wavry_media::DummyRenderer as LinuxFallbackRenderer;
 #[cfg(target_os = "linux")]
 use wavry_media::GstVideoRenderer as VideoRenderer;
-use wavry_media::{Codec, DecodeConfig, Renderer, Resolution as MediaResolution};
+use wavry_media::{
+    AudioChannelLayout, Codec, CursorState, DecodeConfig, HeadOrientation, Renderer,
+    Resolution as MediaResolution, SpatialAudioRenderer,
+};
 use wavry_platform::{ArboardClipboard, Clipboard};
 use wavry_vr::types::{
     EncoderControl as VrEncoderControl, HandPose as VrHandPose, NetworkStats as VrNetworkStats,
-    Pose as VrPose, StreamConfig as VrStreamConfig, VideoCodec as VrVideoCodec,
-    VideoFrame as VrVideoFrame, VrTiming,
+    Pose as VrPose, StereoMode as VrStereoMode, StreamConfig as VrStreamConfig,
+    VideoCodec as VrVideoCodec, VideoFrame as VrVideoFrame, VrTiming,
 };
-use wavry_vr::{VrAdapter, VrAdapterCallbacks};
+use wavry_vr::{VrAdapter, VrAdapterCallbacks, DEFAULT_REFRESH_HZ};
 
 const CRYPTO_HANDSHAKE_ATTEMPTS: u32 = 6;
 const CRYPTO_HANDSHAKE_STEP_TIMEOUT: Duration = Duration::from_secs(2);
 const DSCP_EF: u32 = 0x2E;
+/// Silence after which the client starts asking the host to resume the session.
+const RESUME_AFTER_SILENCE: Duration = Duration::from_millis(1_500);
+/// Give up on resuming after this long; matches the host's default peer idle timeout.
+const SESSION_RESUME_GRACE: Duration = Duration::from_secs(30);
+/// Hello is resent until answered, covering loss and hosts that hold the
+/// answer while their user approves a new device.
+const HELLO_RETRY_INTERVAL: Duration = Duration::from_secs(1);
 const FILE_TRANSFER_TICK_MS: u64 = 2;
 const FILE_TRANSFER_PROGRESS_CHUNK_INTERVAL: u32 = 64;
 const FILE_TRANSFER_SHARE_PERCENT: f32 = 15.0;
@@ -92,8 +125,120 @@ fn linux_has_display() -> bool {
     std::env::var_os("WAYLAND_DISPLAY").is_some() || std::env::var_os("DISPLAY").is_some()
 }
 
+/// Surfaces for the additional monitors of a flat session, opened on each
+/// stream's first frame at the size the host's monitor list reports.
+#[derive(Default)]
+struct MonitorStreams {
+    displays: Vec<rift_core::MonitorInfo>,
+    additional: Vec<u32>,
+    /// `None` marks a stream whose renderer failed to open.
+    renderers: HashMap<u32, Option<Box<dyn Renderer + Send>>>,
+}
+
+impl MonitorStreams {
+    fn select(&mut self, additional: Vec<u32>) {
+        self.additional = additional;
+        self.renderers.clear();
+    }
+
+    fn renderer(
+        &mut self,
+        stream_id: u32,
+        codec: Option<Codec>,
+        factory: Option<&RendererFactory>,
+    ) -> Option<&mut Box<dyn Renderer + Send>> {
+        let display_id = *self
+            .additional
+            .get(rift_core::monitor_stream_index(stream_id)?)?;
+        if !self.renderers.contains_key(&stream_id) {
+            let display = self.displays.iter().find(|d| d.id == display_id)?;
+            let config = DecodeConfig {
+                codec: codec?,
+                resolution: MediaResolution {
+                    width: display.width as u16,
+                    height: display.height as u16,
+                },
+                enable_10bit: false,
+                enable_hdr: false,
+            };
+            let opened = match factory {
+                Some(factory) => factory(config),
+                None => VideoRenderer::new(config).map(|r| Box::new(r) as Box<dyn Renderer + Send>),
+            };
+            let opened = match opened {
+                Ok(r) => {
+                    info!(
+                        "opened surface for display {} on stream {}",
+                        display_id, stream_id
+                    );
+                    Some(r)
+                }
+                Err(e) => {
+                    warn!("renderer for display {} failed: {}", display_id, e);
+                    None
+                }
+            };
+            self.renderers.insert(stream_id, opened);
+        }
+        self.renderers.get_mut(&stream_id)?.as_mut()
+    }
+}
+
+/// Renderer for a frame of a flat session: the negotiated one for the
+/// primary stream, or the surface of the additional monitor it carries.
+fn flat_renderer<'a>(
+    primary: &'a mut Option<Box<dyn Renderer + Send>>,
+    monitors: &'a mut MonitorStreams,
+    stream_id: u32,
+    codec: Option<Codec>,
+    factory: Option<&RendererFactory>,
+) -> Option<&'a mut Box<dyn Renderer + Send>> {
+    if stream_id == rift_core::VIDEO_STREAM_PRIMARY {
+        primary.as_mut()
+    } else {
+        monitors.renderer(stream_id, codec, factory)
+    }
+}
+
+/// Plays one audio packet through the headset's spatial renderer or the
+/// default output. A renderer that fails is dropped and audio stays off.
+fn play_audio(
+    packet: &rift_core::AudioPacket,
+    spatial_audio: &mut Option<SpatialAudioRenderer>,
+    audio_renderer: &mut Option<Box<dyn Renderer + Send>>,
+    audio_disabled: &mut bool,
+) {
+    if let Some(ar) = spatial_audio.as_mut() {
+        if let Err(e) = ar.push(&packet.payload, audio_layout_from_proto(packet.layout)) {
+            if !*audio_disabled {
+                warn!("spatial audio failed, disabling audio: {}", e);
+            }
+            *spatial_audio = None;
+            *audio_disabled = true;
+        }
+    } else if let Some(ar) = audio_renderer.as_mut() {
+        if let Err(e) = ar.render(&packet.payload, packet.timestamp_us) {
+            if !*audio_disabled {
+                warn!("audio render failed, disabling audio: {}", e);
+            }
+            *audio_renderer = None;
+            *audio_disabled = true;
+        }
+    }
+}
+
+/// Audio layouts a headset asks for, best first. Everything is rendered
+/// binaurally against the head pose, so discrete channels beat a downmix.
+const VR_AUDIO_LAYOUTS: [AudioChannelLayout; 4] = [
+    AudioChannelLayout::Surround71,
+    AudioChannelLayout::Surround51,
+    AudioChannelLayout::AmbisonicFoa,
+    AudioChannelLayout::Stereo,
+];
+
 struct ClientVrCallbacks {
     tx: mpsc::Sender<VrOutbound>,
+    head: HeadOrientation,
 }
 
 impl VrAdapterCallbacks for ClientVrCallbacks {
@@ -102,17 +247,10 @@ impl VrAdapterCallbacks for ClientVrCallbacks {
     }
 
     fn on_pose_update(&self, pose: VrPose, timestamp_us: u64) {
-        let msg = rift_core::PoseUpdate {
-            timestamp_us,
-            position_x: pose.position[0],
-            position_y: pose.position[1],
-            position_z: pose.position[2],
-            orientation_x: pose.orientation[0],
-            orientation_y: pose.orientation[1],
-            orientation_z: pose.orientation[2],
-            orientation_w: pose.orientation[3],
-        };
-        let _ = self.tx.try_send(VrOutbound::Pose(msg));
+        self.head.set(pose.orientation);
+        let _ = self
+            .tx
+            .try_send(VrOutbound::Pose(pose_to_proto(&pose, timestamp_us)));
     }
 
     fn on_hand_pose_update(&self, hand_pose: VrHandPose, timestamp_us: u64) {
@@ -140,6 +278,13 @@ impl VrAdapterCallbacks for ClientVrCallbacks {
         let msg = rift_core::VrTiming {
             refresh_hz: timing.refresh_hz,
             vsync_offset_us: timing.vsync_offset_us,
+            predicted_display_time_us: timing.predicted_display_time_us,
+            render_pose: timing
+                .render_pose
+                .map(|pose| pose_to_proto(&pose, timing.predicted_display_time_us)),
+            late_latch: timing
+                .late_latch
+                .map(|pose| pose_to_proto(&pose, timing.predicted_display_time_us)),
         };
         let _ = self.tx.try_send(VrOutbound::Timing(msg));
     }
@@ -173,6 +318,37 @@ impl VrAdapterCallbacks for ClientVrCallbacks {
         };
         let _ = self.tx.try_send(VrOutbound::Gamepad(msg));
     }
+
+    fn on_foveation_update(&self, hint: wavry_vr::types::FoveationHint) {
+        let msg = rift_core::FoveationUpdate {
+            timestamp_us: hint.timestamp_us,
+            gaze_x: hint.gaze[0],
+            gaze_y: hint.gaze[1],
+            radius: hint.radius,
+        };
+        let _ = self.tx.try_send(VrOutbound::Foveation(msg));
+    }
+
+    fn on_haptic_feedback(&self, haptic: wavry_vr::types::HapticFeedback) {
+        let msg = rift_core::HapticFeedback {
+            controller_id: haptic.controller_id,
+            amplitude: haptic.amplitude,
+            frequency_hz: haptic.frequency_hz,
+            duration_us: haptic.duration_us,
+        };
+        let _ = self.tx.try_send(VrOutbound::Haptic(msg));
+    }
+
+    fn on_renegotiate(&self, stereo_modes: Vec<VrStereoMode>, refresh_hz: Option<u32>) {
+        let msg = rift_core::StreamReconfigure {
+            stereo_modes: stereo_modes
+                .into_iter()
+                .map(|mode| stereo_mode_to_proto(mode) as i32)
+                .collect(),
+            max_fps: refresh_hz.unwrap_or(DEFAULT_REFRESH_HZ),
+        };
+        let _ = self.tx.try_send(VrOutbound::Reconfigure(msg));
+    }
 }
 
 struct RuntimeStatsGuard {
@@ -184,6 +360,7 @@ impl RuntimeStatsGuard {
         if let Some(s) = stats.as_ref() {
             s.connected.store(false, Ordering::Relaxed);
             s.frames_decoded.store(0, Ordering::Relaxed);
+            s.permissions.store(0, Ordering::Relaxed);
         }
         Self { stats }
     }
@@ -206,35 +383,44 @@ struct FileTransferState {
 
 impl FileTransferState {
     fn new(send_files: &[PathBuf], output_dir: PathBuf, max_file_bytes: u64) -> Self {
-        let mut outgoing = VecDeque::new();
-        for path in send_files {
-            let file_id = random_file_id();
-            match OutgoingFile::from_path(path, file_id, DEFAULT_CHUNK_SIZE, max_file_bytes) {
-                Ok(file) => {
-                    info!("queued file for transfer to host: {}", path.display());
-                    outgoing.push_back(file);
-                }
-                Err(err) => warn!("skipping file {}: {}", path.display(), err),
-            }
-        }
-        Self {
-            outgoing,
+        let mut state = Self {
+            outgoing: VecDeque::new(),
             incoming: HashMap::new(),
             output_dir,
             max_file_bytes,
+        };
+        for path in send_files {
+            if let Err(err) = state.queue_outgoing(random_file_id(), path) {
+                warn!("skipping file {}: {}", path.display(), err);
+            }
         }
+        state
     }
-}
 
-fn random_file_id() -> u64 {
-    loop {
-        let id = rand::thread_rng().gen::<u64>();
-        if id != 0 {
-            return id;
+    fn queue_outgoing(&mut self, file_id: u64, path: &Path) -> Result<()> {
+        if self.outgoing.iter().any(|f| f.offer().file_id == file_id) {
+            return Err(anyhow!("file_id {} is already queued", file_id));
         }
+        let file =
+            OutgoingFile::from_path(path, file_id, DEFAULT_FILE_CHUNK_BYTES, self.max_file_bytes)?;
+        info!("queued file for transfer to host: {}", path.display());
+        self.outgoing.push_back(file);
+        Ok(())
     }
 }
 
+fn emit_file_event(bus: Option<&broadcast::Sender<FileTransferEvent>>, event: FileTransferEvent) {
+    if let Some(bus) = bus {
+        // No subscribers is fine; the UI may not be listening.
+        let _ = bus.send(event);
+    }
+}
+
+fn file_progress_due(transferred_chunks: u32, total_chunks: u32) -> bool {
+    transferred_chunks % FILE_TRANSFER_PROGRESS_CHUNK_INTERVAL == 0
+        || transferred_chunks >= total_chunks
+}
+
 fn offer_to_proto(offer: &FileOffer) -> rift_core::FileHeader {
     rift_core::FileHeader {
         file_id: offer.file_id,
@@ -293,7 +479,7 @@ impl FileTransferLimiter {
 
     fn capacity_for(rate_kbps: u32) -> f64 {
         let bytes_per_second = rate_kbps as f64 * 1000.0 / 8.0;
-        (bytes_per_second * 0.5).max((DEFAULT_CHUNK_SIZE as f64) * 4.0)
+        (bytes_per_second * 0.5).max((DEFAULT_FILE_CHUNK_BYTES as f64) * 4.0)
     }
 
     fn set_rate_kbps(&mut self, rate_kbps: u32) {
@@ -387,6 +573,7 @@ fn rotate_to_next_ready_transfer(outgoing: &mut VecDeque<OutgoingFile>) -> bool
 fn apply_file_status_to_outgoing(
     outgoing: &mut VecDeque<OutgoingFile>,
     status: &rift_core::FileStatus,
+    events: Option<&broadcast::Sender<FileTransferEvent>>,
 ) {
     let Some(idx) = outgoing
         .iter()
@@ -400,6 +587,7 @@ fn apply_file_status_to_outgoing(
     let message = message.as_str();
 
     let mut remove_file = false;
+    let mut terminal_event = None;
     {
         let file = outgoing
             .get_mut(idx)
@@ -439,6 +627,11 @@ fn apply_file_status_to_outgoing(
                 }
                 "cancel" => {
                     remove_file = true;
+                    terminal_event = Some(FileTransferEvent::Failed {
+                        file_id: status.file_id,
+                        direction: FileTransferDirection::Outgoing,
+                        error: "cancelled".to_string(),
+                    });
                 }
                 _ => {}
             }
@@ -451,6 +644,11 @@ fn apply_file_status_to_outgoing(
             }
             Some(rift_core::file_status::Status::Complete) => {
                 remove_file = true;
+                terminal_event = Some(FileTransferEvent::Completed {
+                    file_id: status.file_id,
+                    direction: FileTransferDirection::Outgoing,
+                    path: None,
+                });
             }
             Some(rift_core::file_status::Status::Error) => {
                 if message.contains("no matching file offer") {
@@ -466,6 +664,11 @@ fn apply_file_status_to_outgoing(
                         status.file_id, message
                     );
                     remove_file = true;
+                    terminal_event = Some(FileTransferEvent::Failed {
+                        file_id: status.file_id,
+                        direction: FileTransferDirection::Outgoing,
+                        error: message.to_string(),
+                    });
                 }
             }
             None => {}
@@ -475,11 +678,15 @@ fn apply_file_status_to_outgoing(
     if remove_file {
         let _ = outgoing.remove(idx);
     }
+    if let Some(event) = terminal_event {
+        emit_file_event(events, event);
+    }
 }
 
 fn apply_file_status_to_incoming(
     incoming: &mut HashMap<u64, IncomingFile>,
     status: &rift_core::FileStatus,
+    events: Option<&broadcast::Sender<FileTransferEvent>>,
 ) {
     let message = sanitize_file_status_message(&status.message);
     let message = message.as_str();
@@ -493,6 +700,16 @@ fn apply_file_status_to_incoming(
                         status.file_id, cmd, err
                     );
                 }
+                if cmd == "cancel" {
+                    emit_file_event(
+                        events,
+                        FileTransferEvent::Failed {
+                            file_id: status.file_id,
+                            direction: FileTransferDirection::Incoming,
+                            error: "cancelled".to_string(),
+                        },
+                    );
+                }
             }
         }
     }
@@ -508,14 +725,45 @@ fn apply_file_status_to_incoming(
                     status.file_id, err
                 );
             }
+            emit_file_event(
+                events,
+                FileTransferEvent::Failed {
+                    file_id: status.file_id,
+                    direction: FileTransferDirection::Incoming,
+                    error: if message.is_empty() {
+                        "transfer aborted by host".to_string()
+                    } else {
+                        message.to_string()
+                    },
+                },
+            );
         }
     }
 }
 
+/// Breakdown for a frame whose decode started at `decode_start` and that
+/// was presented just now. Renderers decode and present in one call, so the
+/// whole call counts as decode and `render_us` stays zero.
+fn presented_latency(
+    frame: &AssembledFrame,
+    rtt_us: u64,
+    decode_start: Instant,
+) -> LatencyBreakdown {
+    LatencyBreakdown {
+        frame_id: frame.frame_id,
+        capture_us: frame.capture_duration_us,
+        encode_us: frame.encode_duration_us,
+        pacing_us: frame.pacing_us,
+        network_us: (rtt_us / 2) as u32,
+        decode_us: decode_start.elapsed().as_micros() as u32,
+        render_us: 0,
+    }
+}
+
 async fn punch_hole(socket: &UdpSocket, target: SocketAddr) -> Result<()> {
     debug!("attempting UDP hole punch to {}", target);
     for _ in 0..3 {
-        socket.send_to(&[0u8; 1], target).await?;
+        net::send_to(socket, &[0u8; 1], target).await?;
         tokio::time::sleep(Duration::from_millis(50)).await;
     }
     Ok(())
@@ -536,38 +784,110 @@ async fn present_relay_lease(socket: &UdpSocket, relay: &RelayInfo) -> Result<()
         .encode(&mut buf[RELAY_HEADER_SIZE..])
         .map_err(|e| anyhow!("payload encode: {}", e))?;
 
-    socket
-        .send_to(&buf[..RELAY_HEADER_SIZE + p_len], relay.addr)
-        .await?;
+    net::send_to(socket, &buf[..RELAY_HEADER_SIZE + p_len], relay.addr).await?;
     info!("presented lease to relay at {}", relay.addr);
     Ok(())
 }
 
+async fn send_lease_renew(socket: &UdpSocket, relay: &RelayInfo) -> Result<()> {
+    let mut buf = [0u8; RELAY_HEADER_SIZE];
+    RelayHeader::new(RelayPacketType::LeaseRenew, relay.session_id)
+        .encode(&mut buf)
+        .map_err(|e| anyhow!("header encode: {}", e))?;
+    net::send_to(socket, &buf, relay.addr).await?;
+    debug!("renewing relay lease at {}", relay.addr);
+    Ok(())
+}
+
 pub async fn run_client(
     config: ClientConfig,
     renderer_factory: Option<RendererFactory>,
-    monitor_rx: Option<mpsc::UnboundedReceiver<u32>>,
+    monitor_rx: Option<mpsc::UnboundedReceiver<MonitorSelection>>,
 ) -> Result<()> {
-    run_client_inner(config, renderer_factory, None, monitor_rx).await
+    run_client_inner(config, renderer_factory, None, monitor_rx)
+        .instrument(session_span("client"))
+        .await
 }
 
 pub async fn run_client_with_shutdown(
     config: ClientConfig,
     renderer_factory: Option<RendererFactory>,
     shutdown_rx: oneshot::Receiver<()>,
-    monitor_rx: Option<mpsc::UnboundedReceiver<u32>>,
+    monitor_rx: Option<mpsc::UnboundedReceiver<MonitorSelection>>,
 ) -> Result<()> {
-    run_client_inner(config, renderer_factory, Some(shutdown_rx), monitor_rx).await
+    run_client_inner(config, renderer_factory, Some(shutdown_rx), monitor_rx)
+        .instrument(session_span("client"))
+        .await
+}
+
+/// Checks the identity a host proved against its pin in the store at
+/// `known_hosts`, pinning it on first use. A pinned host that proves no
+/// identity counts as changed.
+fn verify_host_identity(
+    known_hosts: &Path,
+    host: &str,
+    identity: Option<&WavryId>,
+    strict: bool,
+) -> Result<()> {
+    let mut store = PeerStore::open(known_hosts)?;
+    let Some(identity) = identity else {
+        // A pinned host that stops proving its identity is treated like one
+        // that changed it: dropping the proof must not get around the pin.
+        match store.get(host) {
+            Some(known) if strict => {
+                return Err(anyhow!(
+                    "host {} did not prove an identity but {} is pinned; if the host was reinstalled, remove it from {}",
+                    host,
+                    known.wavry_id,
+                    known_hosts.display()
+                ));
+            }
+            Some(known) => warn!(
+                "host {} did not prove an identity but {} is pinned; continuing unverified",
+                host, known.wavry_id
+            ),
+            None => warn!(
+                "host {} did not prove an identity; it cannot be pinned",
+                host
+            ),
+        }
+        return Ok(());
+    };
+    match store.verify(host, identity)? {
+        HostTrust::Trusted => debug!("host {} matches its pinned identity", host),
+        HostTrust::FirstUse => info!("pinned host {} as {}", host, identity),
+        HostTrust::Changed { pinned } if strict => {
+            return Err(anyhow!(
+                "host {} presented identity {} but {} is pinned; if the host was reinstalled, remove it from {}",
+                host,
+                identity,
+                pinned,
+                known_hosts.display()
+            ));
+        }
+        HostTrust::Changed { pinned } => warn!(
+            "host {} presented identity {} but {} is pinned; continuing without updating the pin",
+            host, identity, pinned
+        ),
+    }
+    Ok(())
 }
 
 async fn run_client_inner(
-    config: ClientConfig,
+    mut config: ClientConfig,
     renderer_factory: Option<RendererFactory>,
     mut shutdown_rx: Option<oneshot::Receiver<()>>,
-    mut monitor_rx: Option<mpsc::UnboundedReceiver<u32>>,
+    mut monitor_rx: Option<mpsc::UnboundedReceiver<MonitorSelection>>,
 ) -> Result<()> {
     let runtime_stats = config.runtime_stats.clone();
     let _runtime_stats_guard = RuntimeStatsGuard::new(runtime_stats.clone());
+    let span = Span::current();
+    if let Some(key) = config.identity_key {
+        span.record_wavry_id(&rift_crypto::identity::IdentityKeypair::from_bytes(&key).wavry_id());
+    }
+    if let Some(relay) = &config.relay_info {
+        span.record_relay_session(relay.session_id);
+    }
 
     if config.no_encrypt {
         if !env_bool("WAVRY_ALLOW_INSECURE_NO_ENCRYPT", false) {
@@ -578,37 +898,84 @@ async fn run_client_inner(
         warn!("ENCRYPTION DISABLED - not for production use");
     }
 
-    let socket = UdpSocket::bind("0.0.0.0:0").await?;
-    if let Err(e) = SockRef::from(&socket).set_tos_v4(DSCP_EF) {
+    let socket = match &config.socket {
+        Some(socket) => UdpSocket::from_std(socket.try_clone()?)?,
+        None => net::bind_udp(net::dual_stack_any(0))?,
+    };
+    if let Err(e) = net::set_tos(&socket, DSCP_EF) {
         debug!("failed to set DSCP/TOS: {}", e);
     }
 
-    // 1. Determine connection strategy
-    let p2p_target = match config.connect_addr {
-        Some(addr) => Some(addr),
-        None => discover_host(Duration::from_secs(1)).await.ok(),
-    };
+    // An invite names the host; where to reach it comes back over signaling.
+    if let Some(link) = config.invite.take() {
+        let link = invite::parse_invite_link(&link)?;
+        let route = invite::redeem(&link, &socket, &config.client_name).await?;
+        let mut direct_addrs = route.direct_addrs.into_iter();
+        config.connect_addr = direct_addrs.next();
+        config.alternate_addrs = direct_addrs.collect();
+        config.remote_candidates = route.candidates;
+        if config.relay_info.is_none() {
+            config.relay_info = route.relay;
+        }
+        if config.relay_lease_source.is_none() {
+            config.relay_lease_source = Some(RelayLeaseSource {
+                signaling_url: link.signaling_url,
+                auth: SignalingAuth::Invite(link.token),
+                target_username: route.host.clone(),
+                region: None,
+            });
+        }
+        config.host_name.get_or_insert(route.host);
+    }
 
-    let (connect_addr, relay_info) = if let Some(target) = p2p_target {
-        info!("direct P2P target: {}", target);
-        punch_hole(&socket, target).await.ok();
-        (target, None)
-    } else if let Some(ref relay) = config.relay_info {
-        info!("no direct address, using relay: {}", relay.addr);
-        present_relay_lease(&socket, relay).await?;
-        (relay.addr, Some(relay))
+    // 1. Gather path candidates: the direct addresses, given or found on the
+    // LAN, and the relay. The handshake picks whichever answers first.
+    let configured_addrs = config
+        .connect_addr
+        .into_iter()
+        .chain(config.alternate_addrs.iter().copied());
+    let paths = if config.remote_candidates.is_empty() {
+        let direct_addrs: Vec<SocketAddr> = match config.connect_addr {
+            Some(_) => configured_addrs.collect(),
+            None => discover_host(Duration::from_secs(1))
+                .await
+                .unwrap_or_default(),
+        };
+        PathSet::new(&direct_addrs, config.relay_info.clone())
     } else {
-        return Err(anyhow!("no connection targets available"));
+        // The host's candidates came over signaling: check them first and
+        // race the ones that answered ahead of the rest.
+        let local = ice::host_candidates(socket.local_addr()?.port());
+        let checklist = ice::run_checks(&socket, &local, &config.remote_candidates).await;
+        let direct_addrs: Vec<SocketAddr> = checklist
+            .ranked_addrs()
+            .into_iter()
+            .chain(configured_addrs)
+            .collect();
+        PathSet::ranked(&direct_addrs, config.relay_info.clone())
     };
+    let mut paths = paths.ok_or_else(|| anyhow!("no connection targets available"))?;
+    for path in paths.candidates() {
+        match &path.relay {
+            Some(relay) => {
+                info!("relay candidate: {}", relay.addr);
+                present_relay_lease(&socket, relay).await?;
+            }
+            None => {
+                info!("direct P2P candidate: {}", path.addr);
+                punch_hole(&socket, path.addr).await.ok();
+            }
+        }
+    }
+    let targets: Vec<SocketAddr> = paths.candidates().iter().map(|path| path.addr).collect();
 
-    if config.no_encrypt
-        && !connect_addr.ip().is_loopback()
-        && !env_bool("WAVRY_CLIENT_ALLOW_PUBLIC_CONNECT", false)
-    {
-        return Err(anyhow!(
-            "refusing to connect to non-loopback address {} in --no-encrypt mode without WAVRY_CLIENT_ALLOW_PUBLIC_CONNECT=1",
-            connect_addr
-        ));
+    if config.no_encrypt && !env_bool("WAVRY_CLIENT_ALLOW_PUBLIC_CONNECT", false) {
+        if let Some(addr) = targets.iter().find(|addr| !addr.ip().is_loopback()) {
+            return Err(anyhow!(
+                "refusing to connect to non-loopback address {} in --no-encrypt mode without WAVRY_CLIENT_ALLOW_PUBLIC_CONNECT=1",
+                addr
+            ));
+        }
     }
 
     // Initialize crypto state
@@ -617,7 +984,8 @@ async fn run_client_inner(
         false => {
             use rift_crypto::connection::SecureClient;
             if let Some(key) = config.identity_key {
-                CryptoState::Handshaking(SecureClient::with_keypair(key)?)
+                let identity = rift_crypto::identity::IdentityKeypair::from_bytes(&key);
+                CryptoState::Handshaking(SecureClient::with_identity(&identity)?)
             } else {
                 CryptoState::Handshaking(SecureClient::new()?)
             }
@@ -626,13 +994,47 @@ async fn run_client_inner(
 
     // Create input channel
     let (input_tx, mut input_rx) = mpsc::channel::<rift_core::InputMessage>(128);
-    spawn_input_threads(input_tx, config.gamepad_enabled, config.gamepad_deadzone)?;
+    let relative_mouse = Arc::new(AtomicBool::new(false));
+    if let Some(mut text_rx) = config.text_input_bus.as_ref().map(|bus| bus.subscribe()) {
+        let tx = input_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let text = match text_rx.recv().await {
+                    Ok(text) => text,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("dropped {} text inputs", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let input = rift_core::InputMessage {
+                    timestamp_us: now_us(),
+                    event: Some(rift_core::input_message::Event::Text(
+                        rift_core::TextInput { text },
+                    )),
+                };
+                if tx.send(input).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    spawn_input_threads(
+        input_tx,
+        config.gamepad_enabled,
+        config.gamepad_deadzone,
+        relative_mouse.clone(),
+    )?;
 
     // VR adapter wiring (optional)
     let (vr_tx, mut vr_rx) = mpsc::channel::<VrOutbound>(64);
+    let head_orientation = HeadOrientation::default();
     let vr_adapter: Option<Arc<Mutex<dyn VrAdapter>>> =
         if let Some(adapter) = config.vr_adapter.clone() {
-            let cb = Arc::new(ClientVrCallbacks { tx: vr_tx });
+            let cb = Arc::new(ClientVrCallbacks {
+                tx: vr_tx,
+                head: head_orientation.clone(),
+            });
             let start_ok = match adapter.lock() {
                 Ok(mut guard) => match guard.start(cb) {
                     Ok(()) => true,
@@ -657,7 +1059,7 @@ async fn run_client_inner(
 
     // Perform crypto handshake if enabled
     if let CryptoState::Handshaking(ref mut client) = crypto {
-        info!("starting crypto handshake with {}", connect_addr);
+        info!("starting crypto handshake with {:?}", targets);
 
         // Send msg1
         let msg1_payload = client
@@ -678,33 +1080,72 @@ async fn run_client_inner(
         let mut msg2_payload: Option<Bytes> = None;
         let mut last_msg2_decode_err: Option<String> = None;
 
+        // Every candidate gets msg1 once its delay has passed, direct ones
+        // staggered IPv6 first and relays last, and the first path to answer
+        // with msg2 carries the session.
         for attempt in 1..=CRYPTO_HANDSHAKE_ATTEMPTS {
-            socket.send_to(&phys1_wire, connect_addr).await?;
+            let attempt_start = time::Instant::now();
             debug!(
-                "sent crypto msg1 (attempt {}/{})",
+                "sending crypto msg1 (attempt {}/{})",
                 attempt, CRYPTO_HANDSHAKE_ATTEMPTS
             );
 
-            let deadline = time::Instant::now() + CRYPTO_HANDSHAKE_STEP_TIMEOUT;
+            let deadline = attempt_start + CRYPTO_HANDSHAKE_STEP_TIMEOUT;
+            // Candidates are ordered by delay; this many have had msg1.
+            let mut sent = 0;
             loop {
                 let now = time::Instant::now();
                 if now >= deadline {
                     break;
                 }
+                while let Some(path) = paths
+                    .candidates()
+                    .get(sent)
+                    .filter(|path| now >= attempt_start + path.delay)
+                {
+                    // One unreachable family must not stop the others.
+                    if let Err(e) =
+                        send_physical(&socket, &phys1_wire, path.addr, path.relay.as_ref()).await
+                    {
+                        debug!("crypto msg1 to {} failed: {}", path.addr, e);
+                    }
+                    sent += 1;
+                }
 
-                let remaining = deadline - now;
-                let recv = match time::timeout(remaining, socket.recv_from(&mut buf_arr)).await {
+                let wake = paths
+                    .candidates()
+                    .get(sent)
+                    .map_or(deadline, |path| (attempt_start + path.delay).min(deadline));
+                let recv = match time::timeout(wake - now, socket.recv_from(&mut buf_arr)).await {
                     Ok(v) => v?,
-                    Err(_) => break,
+                    Err(_) => continue,
                 };
 
                 let (len, src) = recv;
-                if src != connect_addr {
+                let src = net::canonical(src);
+                let Some(path) = paths.candidates().iter().find(|path| path.addr == src) else {
                     debug!("ignoring handshake packet from unexpected peer {}", src);
                     continue;
+                };
+                let mut raw = &buf_arr[..len];
+                if path.is_relayed() {
+                    match RelayHeader::decode(raw) {
+                        Ok(header) if header.packet_type == RelayPacketType::Forward => {
+                            raw = &raw[RELAY_HEADER_SIZE..];
+                        }
+                        Ok(header) if header.packet_type == RelayPacketType::LeaseReject => {
+                            warn!("relay lease rejected");
+                            continue;
+                        }
+                        _ => continue,
+                    }
                 }
 
-                let phys2 = match PhysicalPacket::decode(Bytes::copy_from_slice(&buf_arr[..len])) {
+                // Late connectivity checks and their answers.
+                if ice::is_check(raw) {
+                    continue;
+                }
+                let phys2 = match PhysicalPacket::decode(Bytes::copy_from_slice(raw)) {
                     Ok(p) => p,
                     Err(e) => {
                         last_msg2_decode_err =
@@ -719,7 +1160,8 @@ async fn run_client_inner(
                 }
 
                 msg2_payload = Some(phys2.payload);
-                debug!("received crypto msg2 on attempt {}", attempt);
+                paths.select_by_source(src);
+                debug!("received crypto msg2 from {} on attempt {}", src, attempt);
                 break;
             }
 
@@ -731,16 +1173,16 @@ async fn run_client_inner(
         let msg2_payload = msg2_payload.ok_or_else(|| {
             if let Some(detail) = last_msg2_decode_err {
                 anyhow!(
-                    "crypto handshake timeout after {} attempts with {}: {}",
+                    "crypto handshake timeout after {} attempts with {:?}: {}",
                     CRYPTO_HANDSHAKE_ATTEMPTS,
-                    connect_addr,
+                    targets,
                     detail
                 )
             } else {
                 anyhow!(
-                    "crypto handshake timeout after {} attempts waiting for host response from {}; verify host is running and port is correct",
+                    "crypto handshake timeout after {} attempts waiting for host response from {:?}; verify host is running and port is correct",
                     CRYPTO_HANDSHAKE_ATTEMPTS,
-                    connect_addr
+                    targets
                 )
             }
         })?;
@@ -750,6 +1192,20 @@ async fn run_client_inner(
             .process_server_response(&msg2_payload)
             .map_err(|e| anyhow!("crypto handshake error in msg3: {}", e))?;
 
+        // Pin before msg3 so a refused host never sees us complete the handshake.
+        let pin_name = config
+            .host_name
+            .clone()
+            .or_else(|| (!paths.active().is_relayed()).then(|| paths.active().addr.to_string()));
+        if let (Some(store), Some(host)) = (&config.known_hosts, pin_name) {
+            verify_host_identity(
+                store,
+                &host,
+                client.remote_identity(),
+                config.strict_host_key,
+            )?;
+        }
+
         let phys3 = PhysicalPacket {
             version: RIFT_VERSION,
             session_id: None,
@@ -757,11 +1213,18 @@ async fn run_client_inner(
             packet_id: 0,
             payload: Bytes::copy_from_slice(&msg3_payload),
         };
-        socket.send_to(&phys3.encode(), connect_addr).await?;
+        let path = paths.active();
+        send_physical(&socket, &phys3.encode(), path.addr, path.relay.as_ref()).await?;
         debug!("sent crypto msg3");
 
-        info!("crypto handshake complete");
+        info!(
+            "crypto handshake complete via {}{}",
+            path.addr,
+            if path.is_relayed() { " (relay)" } else { "" }
+        );
     }
+    let mut connect_addr = paths.active().addr;
+    let mut relay_info = paths.active().relay.clone();
 
     // Transition to established
     if let CryptoState::Handshaking(client) = crypto {
@@ -780,6 +1243,30 @@ async fn run_client_inner(
         })
         .collect();
 
+    let stereo_modes = vr_adapter
+        .as_ref()
+        .and_then(|adapter| adapter.lock().ok().map(|adapter| adapter.stereo_modes()))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|mode| stereo_mode_to_proto(mode) as i32)
+        .collect();
+    // Headsets ask for their display rate; 60 fps pacing stutters in VR.
+    let max_fps = match vr_adapter.as_ref() {
+        Some(adapter) => adapter
+            .lock()
+            .ok()
+            .and_then(|adapter| adapter.refresh_hz())
+            .unwrap_or(DEFAULT_REFRESH_HZ),
+        None => 60,
+    };
+    let audio_layouts = if vr_adapter.is_some() {
+        VR_AUDIO_LAYOUTS
+            .iter()
+            .map(|layout| audio_layout_to_proto(*layout) as i32)
+            .collect()
+    } else {
+        Vec::new()
+    };
     let hello = ProtoHello {
         client_name: config.client_name,
         platform: local_platform() as i32,
@@ -788,10 +1275,33 @@ async fn run_client_inner(
             width: r.width as u32,
             height: r.height as u32,
         }),
-        max_fps: 60,
+        max_fps,
         input_caps: 0xF, // All caps
         protocol_version: 1,
         public_addr: "".to_string(),
+        candidate_addrs: vec![],
+        stereo_modes,
+        audio_layouts,
+        fec_schemes: rift_core::fec::supported_schemes(),
+        // Only the GStreamer renderer composites a remote cursor.
+        cursor_channel: cfg!(target_os = "linux")
+            && renderer_factory.is_none()
+            && vr_adapter.is_none(),
+        // Host keeps its bitrate and frame size; FEC rebuilds single lost packets.
+        audio_params: Some(rift_core::AudioParams {
+            bitrate_kbps: 0,
+            frame_duration_us: 0,
+            inband_fec: true,
+        }),
+        compact_header: true,
+        transport_feedback: true,
+        microphone: config.microphone,
+        // Without a policy the client syncs both ways, which is what hosts
+        // assume when the field is missing.
+        clipboard_sync: config
+            .clipboard_sync
+            .as_ref()
+            .map(|control| control.direction().into()),
     };
 
     let msg = ProtoMessage {
@@ -802,28 +1312,38 @@ async fn run_client_inner(
 
     let packet_counter = Arc::new(AtomicU64::new(1));
     let next_packet_id = || packet_counter.fetch_add(1, Ordering::Relaxed);
+    // Full headers until the HelloAck agrees to compact ones.
+    let mut compact_tx: Option<CompactEncoder> = None;
+    let mut compact_rx = CompactDecoder::default();
 
     // Alias 0 is reserved for physical handshake framing in rift-core decode.
     // Use a non-zero bootstrap alias until HelloAck provides the negotiated alias.
+    let hello_msg = msg.clone();
     send_rift_msg(
         &socket,
         &mut crypto,
+        &mut compact_tx,
         connect_addr,
         msg,
         Some(1),
         next_packet_id(),
-        relay_info,
+        relay_info.as_ref(),
     )
     .await?;
     info!("sent RIFT hello to {}", connect_addr);
+    let mut hello_sent_at = Instant::now();
+    let mut hello_rejected = false;
 
     // Main recv loop
     let mut buf = vec![0u8; 64 * 1024];
     let mut ping_interval = time::interval(Duration::from_millis(500));
     let mut stats_interval = time::interval(Duration::from_millis(1000));
+    let mut feedback_interval = time::interval(FEEDBACK_INTERVAL);
     let mut jitter_interval = time::interval(Duration::from_millis(1));
 
-    let mut _session_id: Option<Vec<u8>> = None;
+    let mut session_id: Option<u128> = None;
+    let mut last_rx = Instant::now();
+    let mut resume_attempt: u64 = 0;
     let mut session_alias: Option<u32> = None;
 
     let mut last_packet_id: Option<u64> = None;
@@ -832,31 +1352,69 @@ async fn run_client_inner(
     let mut last_rtt_us: u64 = 0;
     let mut rtt_tracker = RttTracker::new();
     let mut arrival_jitter = ArrivalJitter::new();
-    let mut nack_window = NackWindow::new(NACK_WINDOW_SIZE);
-    let mut jitter_buffer = JitterBuffer::new();
+    let mut nack_tracker = NackTracker::new(NACK_WINDOW_SIZE);
+    // Arrival log for the host's delay-based congestion control, once agreed.
+    let mut transport_feedback: Option<FeedbackRecorder> = None;
+    // Encoded microphone audio, once the host has agreed to take it.
+    let mut mic_rx: Option<mpsc::Receiver<wavry_media::EncodedFrame>> = None;
+    // What the host lets this client do; hosts without permission control allow everything.
+    let mut permissions = PermissionSet::all();
+    let mut nack_recovered: u64 = 0;
+    let mut jitter_buffer = JitterBuffer::with_config(config.jitter_buffer);
+    let mut latency_telemetry = LatencyTelemetry::default();
     let mut last_skip_sent = Instant::now()
         .checked_sub(Duration::from_secs(1))
         .unwrap_or_else(Instant::now);
 
     let mut renderer: Option<Box<dyn Renderer + Send>> = None;
+    let mut monitor_streams = MonitorStreams::default();
+    let mut cursor: Option<CursorState> = None;
     let mut audio_renderer: Option<Box<dyn Renderer + Send>> = None;
+    let mut spatial_audio: Option<SpatialAudioRenderer> = None;
+    let mut av_sync = AvSync::default();
     let mut audio_disabled = false;
     #[cfg(target_os = "linux")]
     let mut video_disabled = false;
     #[cfg(not(target_os = "linux"))]
     let _video_disabled = false;
     let mut frames = FrameAssembler::new(FRAME_TIMEOUT_US);
+    let mut vr_stereo_mode = VrStereoMode::Auto;
+    let mut vr_stream: Option<VrStreamConfig> = None;
     let mut fec_cache = FecCache::new();
+    let mut probe_receiver = ProbeReceiver::new();
 
     let mut clipboard = ArboardClipboard::new().ok();
     let mut last_clipboard_text = clipboard.as_mut().and_then(|c| c.get_text().ok()).flatten();
     let mut clipboard_poll_interval = time::interval(Duration::from_millis(500));
-
-    let mut recorder = if let Some(config) = config.recorder_config {
-        Some(wavry_media::VideoRecorder::new(config)?)
+    let clipboard_sync = config.clipboard_sync.clone();
+    let clipboard_direction = || {
+        clipboard_sync
+            .as_ref()
+            .map(|control| control.direction())
+            .unwrap_or(ClipboardSyncDirection::Bidirectional)
+    };
+    // What the host was last told; it enforces the opt-in on its side too.
+    let mut announced_clipboard = clipboard_direction();
+
+    // Recordings started mid-session begin at the next keyframe, so they may
+    // miss up to one keyframe interval.
+    let recorder_config =
+        config
+            .recorder_config
+            .clone()
+            .unwrap_or_else(|| wavry_media::RecorderConfig {
+                container: wavry_media::Container::Matroska,
+                ..Default::default()
+            });
+    let mut recorder = if config.recorder_config.as_ref().is_some_and(|c| c.enabled) {
+        Some(wavry_media::VideoRecorder::new(recorder_config.clone())?)
     } else {
         None
     };
+    let mut local_recording_rx = config
+        .local_recording_bus
+        .as_ref()
+        .map(|bus| bus.subscribe());
 
     let mut stream_codec: Option<Codec> = None;
     let mut stream_resolution: Option<MediaResolution> = None;
@@ -866,9 +1424,32 @@ async fn run_client_inner(
         config.file_max_bytes.max(1),
     );
     let mut file_command_rx = config.file_command_bus.as_ref().map(|bus| bus.subscribe());
+    let mut file_send_rx = config.file_send_bus.as_ref().map(|bus| bus.subscribe());
+    let file_event_bus = config.file_event_bus.clone();
+    let mut bandwidth_limit_rx = config
+        .bandwidth_limit_bus
+        .as_ref()
+        .map(|bus| bus.subscribe());
+    let mut host_recording_rx = config
+        .host_recording_bus
+        .as_ref()
+        .map(|bus| bus.subscribe());
+    let mut pointer_mode_rx = config.pointer_mode_bus.as_ref().map(|bus| bus.subscribe());
     let mut transfer_budget_kbps = FILE_TRANSFER_MAX_KBPS;
     let mut file_transfer_limiter = FileTransferLimiter::new(FILE_TRANSFER_MIN_KBPS);
     let mut file_transfer_tick = time::interval(Duration::from_millis(FILE_TRANSFER_TICK_MS));
+    let mut relay_lease = config
+        .relay_info
+        .clone()
+        .map(|relay| RelayLeaseManager::new(relay, Instant::now()));
+    let (fresh_lease_tx, mut fresh_lease_rx) = mpsc::unbounded_channel::<RelayInfo>();
+    let mut lease_interval = time::interval(Duration::from_millis(250));
+    // Input waits for the next tick only once the host has said it unbundles
+    // batches; until then every event goes out as it arrives.
+    let mut input_batching = false;
+    let mut input_sender = InputSender::default();
+    let mut input_tick = time::interval(Duration::from_secs(1) / config.input_rate_hz.max(1));
+    input_tick.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
 
     let use_experimental_transport = env_bool("WAVRY_TRANSPORT_EXPERIMENTAL", false);
     if use_experimental_transport {
@@ -890,18 +1471,58 @@ async fn run_client_inner(
 
             // Handle input from capture threads
             Some(input) = input_rx.recv() => {
-                if let Some(alias) = session_alias {
+                if input_batching {
+                    input_sender.push(input);
+                } else if let Some(alias) = session_alias {
+                    let msg = ProtoMessage {
+                        content: Some(rift_core::message::Content::Input(input)),
+                    };
+                    if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                        debug!("input send error: {}", e);
+                    }
+                }
+            }
+
+            // Input gathered since the last tick, coalesced and batched
+            _ = input_tick.tick(), if !input_sender.is_empty() => {
+                while let Some(input) = input_sender.flush() {
+                    let Some(alias) = session_alias else { break };
                     let msg = ProtoMessage {
                         content: Some(rift_core::message::Content::Input(input)),
                     };
-                    if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                    if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                         debug!("input send error: {}", e);
                     }
                 }
             }
 
+            // Microphone passthrough for voice chat on the host
+            Some(packet) = async {
+                if let Some(rx) = mic_rx.as_mut() {
+                    rx.recv().await
+                } else {
+                    None
+                }
+            } => {
+                if let Some(alias) = session_alias {
+                    let msg = ProtoMessage {
+                        content: Some(rift_core::message::Content::Media(rift_core::MediaMessage {
+                            content: Some(rift_core::media_message::Content::Audio(rift_core::AudioPacket {
+                                timestamp_us: packet.timestamp_us,
+                                payload: packet.data,
+                                layout: rift_core::AudioLayout::AudioStereo as i32,
+                                direction: rift_core::AudioDirection::AudioFromClientMic as i32,
+                            })),
+                        })),
+                    };
+                    if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                        debug!("microphone send error: {}", e);
+                    }
+                }
+            }
+
             // Handle monitor selection from UI
-            Some(monitor_id) = async {
+            Some(selection) = async {
                 if let Some(rx) = monitor_rx.as_mut() {
                     rx.recv().await
                 } else {
@@ -909,15 +1530,30 @@ async fn run_client_inner(
                 }
             } => {
                 if let Some(alias) = session_alias {
-                    info!("Sending SelectMonitor request for display {}", monitor_id);
+                    let mut additional: Vec<u32> = Vec::new();
+                    for id in selection.additional {
+                        if id != selection.primary && !additional.contains(&id) {
+                            additional.push(id);
+                        }
+                    }
+                    additional.truncate(rift_core::MAX_ADDITIONAL_MONITORS);
+                    info!(
+                        "Sending SelectMonitor request for display {} with {} additional",
+                        selection.primary,
+                        additional.len()
+                    );
+                    monitor_streams.select(additional.clone());
                     let msg = ProtoMessage {
                         content: Some(rift_core::message::Content::Control(ProtoControl {
                             content: Some(rift_core::control_message::Content::SelectMonitor(
-                                rift_core::SelectMonitor { monitor_id },
+                                rift_core::SelectMonitor {
+                                    monitor_id: selection.primary,
+                                    additional_monitor_ids: additional,
+                                },
                             )),
                         })),
                     };
-                    if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                    if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                         warn!("SelectMonitor send error: {}", e);
                     }
                 }
@@ -948,8 +1584,8 @@ async fn run_client_inner(
                     );
 
                     // Apply immediately for local-outgoing/local-incoming state.
-                    apply_file_status_to_outgoing(&mut file_transfer.outgoing, &status);
-                    apply_file_status_to_incoming(&mut file_transfer.incoming, &status);
+                    apply_file_status_to_outgoing(&mut file_transfer.outgoing, &status, file_event_bus.as_ref());
+                    apply_file_status_to_incoming(&mut file_transfer.incoming, &status, file_event_bus.as_ref());
 
                     if let Some(alias) = session_alias {
                         let msg = ProtoMessage {
@@ -960,11 +1596,12 @@ async fn run_client_inner(
                         if let Err(e) = send_rift_msg(
                             &socket,
                             &mut crypto,
+                            &mut compact_tx,
                             connect_addr,
                             msg,
                             Some(alias),
                             next_packet_id(),
-                            relay_info,
+                            relay_info.as_ref(),
                         ).await {
                             warn!("failed to send file transfer command: {}", e);
                         }
@@ -974,6 +1611,175 @@ async fn run_client_inner(
                 }
             }
 
+            // Files queued for sending after the session started.
+            maybe_send = async {
+                if let Some(rx) = file_send_rx.as_mut() {
+                    match rx.recv().await {
+                        Ok(request) => Some(request),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("dropped {} queued file send request(s)", skipped);
+                            None
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            std::future::pending::<Option<FileSendRequest>>().await
+                        }
+                    }
+                } else {
+                    std::future::pending::<Option<FileSendRequest>>().await
+                }
+            } => {
+                if let Some(request) = maybe_send {
+                    if let Err(err) = file_transfer.queue_outgoing(request.file_id, &request.path) {
+                        warn!("cannot send file {}: {}", request.path.display(), err);
+                        emit_file_event(
+                            file_event_bus.as_ref(),
+                            FileTransferEvent::Failed {
+                                file_id: request.file_id,
+                                direction: FileTransferDirection::Outgoing,
+                                error: err.to_string(),
+                            },
+                        );
+                    }
+                }
+            }
+
+            // User-requested bandwidth cap.
+            maybe_limit = async {
+                if let Some(rx) = bandwidth_limit_rx.as_mut() {
+                    match rx.recv().await {
+                        Ok(kbps) => Some(kbps),
+                        Err(broadcast::error::RecvError::Lagged(_)) => None,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            std::future::pending::<Option<u32>>().await
+                        }
+                    }
+                } else {
+                    std::future::pending::<Option<u32>>().await
+                }
+            } => {
+                if let Some(kbps) = maybe_limit {
+                    if let Some(alias) = session_alias {
+                        info!("Requesting bandwidth limit of {} kbps", kbps);
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::Congestion(
+                                    rift_core::CongestionControl {
+                                        target_bitrate_kbps: 0,
+                                        target_fps: 0,
+                                        max_bitrate_kbps: kbps,
+                                    },
+                                )),
+                            })),
+                        };
+                        if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                            warn!("bandwidth limit send error: {}", e);
+                        }
+                    } else {
+                        warn!("bandwidth limit ignored: session not established yet");
+                    }
+                }
+            }
+
+            // User asked the host to start or stop recording.
+            maybe_record = async {
+                if let Some(rx) = host_recording_rx.as_mut() {
+                    match rx.recv().await {
+                        Ok(start) => Some(start),
+                        Err(broadcast::error::RecvError::Lagged(_)) => None,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            std::future::pending::<Option<bool>>().await
+                        }
+                    }
+                } else {
+                    std::future::pending::<Option<bool>>().await
+                }
+            } => {
+                if let Some(start) = maybe_record {
+                    if let Some(alias) = session_alias {
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::RecordingControl(
+                                    rift_core::RecordingControl { start },
+                                )),
+                            })),
+                        };
+                        if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                            warn!("recording request send error: {}", e);
+                        }
+                    } else {
+                        warn!("recording request ignored: session not established yet");
+                    }
+                }
+            }
+
+            // User locked or released the pointer.
+            maybe_relative = async {
+                if let Some(rx) = pointer_mode_rx.as_mut() {
+                    match rx.recv().await {
+                        Ok(relative) => Some(relative),
+                        Err(broadcast::error::RecvError::Lagged(_)) => None,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            std::future::pending::<Option<bool>>().await
+                        }
+                    }
+                } else {
+                    std::future::pending::<Option<bool>>().await
+                }
+            } => {
+                if let Some(relative) = maybe_relative {
+                    relative_mouse.store(relative, Ordering::Relaxed);
+                    info!("Pointer mode: {}", if relative { "relative" } else { "absolute" });
+                    if let Some(alias) = session_alias {
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::PointerMode(
+                                    rift_core::PointerModeChange { relative },
+                                )),
+                            })),
+                        };
+                        if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                            warn!("pointer mode send error: {}", e);
+                        }
+                    }
+                }
+            }
+
+            // User switched local recording of the stream on or off.
+            maybe_record_local = async {
+                if let Some(rx) = local_recording_rx.as_mut() {
+                    match rx.recv().await {
+                        Ok(start) => Some(start),
+                        Err(broadcast::error::RecvError::Lagged(_)) => None,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            std::future::pending::<Option<bool>>().await
+                        }
+                    }
+                } else {
+                    std::future::pending::<Option<bool>>().await
+                }
+            } => {
+                match maybe_record_local {
+                    Some(true) if recorder.is_none() => {
+                        match wavry_media::VideoRecorder::new(recorder_config.clone()) {
+                            Ok(rec) => {
+                                info!("Local recording started in {}", recorder_config.output_dir.display());
+                                recorder = Some(rec);
+                            }
+                            Err(e) => warn!("local recording failed to start: {}", e),
+                        }
+                    }
+                    Some(false) => {
+                        if let Some(mut rec) = recorder.take() {
+                            match rec.finalize() {
+                                Ok(()) => info!("Local recording stopped"),
+                                Err(e) => warn!("local recording finalize error: {}", e),
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
             // VR outbound (pose/timing)
             Some(out) = vr_rx.recv() => {
                 if let Some(alias) = session_alias {
@@ -984,7 +1790,7 @@ async fn run_client_inner(
                                     content: Some(rift_core::control_message::Content::PoseUpdate(pose)),
                                 })),
                             };
-                            if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                                 debug!("vr control send error: {}", e);
                             }
                         }
@@ -994,7 +1800,7 @@ async fn run_client_inner(
                                     content: Some(rift_core::control_message::Content::HandPoseUpdate(hand_pose)),
                                 })),
                             };
-                            if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                                 debug!("vr control send error: {}", e);
                             }
                         }
@@ -1004,7 +1810,7 @@ async fn run_client_inner(
                                     content: Some(rift_core::control_message::Content::VrTiming(timing)),
                                 })),
                             };
-                            if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                                 debug!("vr control send error: {}", e);
                             }
                         }
@@ -1012,30 +1818,159 @@ async fn run_client_inner(
                             let msg = ProtoMessage {
                                 content: Some(rift_core::message::Content::Input(input)),
                             };
-                            if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                                 debug!("vr input send error: {}", e);
                             }
                         }
+                        VrOutbound::Foveation(foveation) => {
+                            let msg = ProtoMessage {
+                                content: Some(rift_core::message::Content::Control(ProtoControl {
+                                    content: Some(rift_core::control_message::Content::Foveation(foveation)),
+                                })),
+                            };
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                                debug!("vr control send error: {}", e);
+                            }
+                        }
+                        VrOutbound::Haptic(haptic) => {
+                            let msg = ProtoMessage {
+                                content: Some(rift_core::message::Content::Control(ProtoControl {
+                                    content: Some(rift_core::control_message::Content::Haptic(haptic)),
+                                })),
+                            };
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                                debug!("vr control send error: {}", e);
+                            }
+                        }
+                        VrOutbound::Reconfigure(reconfigure) => {
+                            info!(
+                                "vr display changed, renegotiating (modes={:?}, max_fps={})",
+                                reconfigure.stereo_modes, reconfigure.max_fps
+                            );
+                            let msg = ProtoMessage {
+                                content: Some(rift_core::message::Content::Control(ProtoControl {
+                                    content: Some(rift_core::control_message::Content::StreamReconfigure(reconfigure)),
+                                })),
+                            };
+                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                                debug!("vr control send error: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Relay lease renewal, and moving to a replacement lease
+            _ = lease_interval.tick(), if relay_lease.is_some() => {
+                let Some(lease) = relay_lease.as_mut() else { continue };
+                while let Some(action) = lease.poll(Instant::now()) {
+                    match action {
+                        LeaseAction::Present => {
+                            if let Err(e) = present_relay_lease(&socket, lease.relay()).await {
+                                debug!("relay lease send error: {}", e);
+                            }
+                        }
+                        LeaseAction::Renew => {
+                            if let Err(e) = send_lease_renew(&socket, lease.relay()).await {
+                                debug!("relay lease renew error: {}", e);
+                            }
+                        }
+                        LeaseAction::RequestFresh => match config.relay_lease_source.clone() {
+                            Some(source) => {
+                                info!("requesting a replacement relay lease from {}", source.signaling_url);
+                                let tx = fresh_lease_tx.clone();
+                                tokio::spawn(async move {
+                                    match lease::fetch_relay_lease(&source).await {
+                                        Ok(relay) => {
+                                            let _ = tx.send(relay);
+                                        }
+                                        Err(e) => warn!("replacement relay lease request failed: {}", e),
+                                    }
+                                }.in_current_span());
+                            }
+                            None => debug!("relay lease needs replacing, but there is no signaling to ask"),
+                        },
+                        LeaseAction::Migrate(relay) => {
+                            info!("moving relay session to {} via {}", relay.session_id, relay.addr);
+                            span.record_relay_session(relay.session_id);
+                            paths.replace_relay(relay);
+                            if paths.active().is_relayed() {
+                                connect_addr = paths.active().addr;
+                                relay_info = paths.active().relay.clone();
+                            }
+                        }
                     }
                 }
             }
 
+            Some(relay) = fresh_lease_rx.recv() => {
+                if let Some(lease) = relay_lease.as_mut() {
+                    lease.on_fresh_lease(relay);
+                }
+            }
+
             // Ping interval
             _ = ping_interval.tick() => {
+                let silent = last_rx.elapsed();
+                if session_alias.is_some() && silent >= SESSION_RESUME_GRACE {
+                    return Err(anyhow!("no traffic from {} for {:?}; session lost", connect_addr, silent));
+                }
+                let resume_ticket = match &crypto {
+                    CryptoState::Established(client) if silent >= RESUME_AFTER_SILENCE => client.resume_ticket().cloned(),
+                    _ => None,
+                };
+                if let (Some(alias), Some(id), Some(ticket)) = (session_alias, session_id, resume_ticket) {
+                    if let Some(path) = paths.on_resume() {
+                        warn!("no traffic via {} for {:?}; failing over to {}{}", connect_addr, silent, path.addr, if path.is_relayed() { " (relay)" } else { "" });
+                        if let Some(relay) = &path.relay {
+                            if let Err(e) = present_relay_lease(&socket, relay).await {
+                                debug!("relay lease send error: {}", e);
+                            }
+                        }
+                        connect_addr = path.addr;
+                        relay_info = path.relay.clone();
+                    }
+                    resume_attempt += 1;
+                    let resume = rift_core::Resume {
+                        session_alias: alias,
+                        attempt: resume_attempt,
+                        proof: ticket.proof(id, alias, resume_attempt),
+                    };
+                    debug!("no traffic for {:?}; sending resume attempt {}", silent, resume_attempt);
+                    if let Err(e) = send_resume(&socket, &mut crypto, connect_addr, resume, next_packet_id(), relay_info.as_ref()).await {
+                        debug!("resume send error: {}", e);
+                    }
+                }
+                if session_alias.is_none() && !hello_rejected && hello_sent_at.elapsed() >= HELLO_RETRY_INTERVAL {
+                    debug!("no HelloAck from {} yet; resending hello", connect_addr);
+                    send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, hello_msg.clone(), Some(1), next_packet_id(), relay_info.as_ref()).await?;
+                    hello_sent_at = Instant::now();
+                }
                 if let Some(alias) = session_alias {
                     let ping = ProtoMessage {
                         content: Some(rift_core::message::Content::Control(ProtoControl {
                             content: Some(rift_core::control_message::Content::Ping(ProtoPing { timestamp_us: now_us() })),
                         })),
                     };
-                    send_rift_msg(&socket, &mut crypto, connect_addr, ping, Some(alias), next_packet_id(), relay_info).await?;
+                    send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, ping, Some(alias), next_packet_id(), relay_info.as_ref()).await?;
                 }
             }
 
             // Stats interval
             _ = stats_interval.tick() => {
+                // Hold frames roughly one RTT while resends are landing so a
+                // recovered frame isn't dropped behind its successors.
+                let nack_stats = nack_tracker.stats();
+                let retransmitting = nack_stats.recovered > nack_recovered;
+                nack_recovered = nack_stats.recovered;
+                jitter_buffer.set_min_delay_us(if retransmitting { last_rtt_us } else { 0 });
+
                 let stats_received = received_packets;
                 let stats_lost = lost_packets;
+                let latency_summary = latency_telemetry.take_summary();
+                if let Some(stats) = runtime_stats.as_ref() {
+                    stats.record_latency_summary(latency_summary);
+                }
                 if let Some(alias) = session_alias {
                     let stats = ProtoStatsReport {
                         period_ms: 1000,
@@ -1043,6 +1978,7 @@ async fn run_client_inner(
                         lost_packets: stats_lost,
                         rtt_us: last_rtt_us,
                         jitter_us: arrival_jitter.jitter_us(),
+                        latency: latency_summary.map(|summary| summary.to_proto()),
                     };
                     let msg = ProtoMessage {
                         content: Some(rift_core::message::Content::Control(ProtoControl {
@@ -1051,7 +1987,16 @@ async fn run_client_inner(
                     };
                     received_packets = 0;
                     lost_packets = 0;
-                    send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await?;
+                    send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await?;
+
+                    if let Some(result) = probe_receiver.poll_timeout(Instant::now()) {
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::ProbeResult(result)),
+                            })),
+                        };
+                        send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await?;
+                    }
                 }
                 if let Some(adapter) = vr_adapter.as_ref() {
                     if let Ok(mut adapter) = adapter.lock() {
@@ -1070,10 +2015,29 @@ async fn run_client_inner(
 
             // Clipboard polling
             _ = clipboard_poll_interval.tick() => {
+                let direction = clipboard_direction();
+                if let Some(alias) = session_alias.filter(|_| direction != announced_clipboard) {
+                    let msg = ProtoMessage {
+                        content: Some(rift_core::message::Content::Control(ProtoControl {
+                            content: Some(rift_core::control_message::Content::ClipboardSync(
+                                direction.into(),
+                            )),
+                        })),
+                    };
+                    match send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                        Ok(_) => announced_clipboard = direction,
+                        Err(e) => debug!("clipboard sync update error: {}", e),
+                    }
+                }
                 if let Some(ref mut c) = clipboard {
                     if let Ok(Some(current_text)) = c.get_text() {
                         if Some(current_text.clone()) != last_clipboard_text {
                             last_clipboard_text = Some(current_text.clone());
+                            if !clipboard_direction().sends()
+                                || !permissions.contains(PermissionSet::CLIPBOARD)
+                            {
+                                continue;
+                            }
                             if let Some(alias) = session_alias {
                                 let msg = ProtoMessage {
                                     content: Some(rift_core::message::Content::Control(ProtoControl {
@@ -1082,8 +2046,10 @@ async fn run_client_inner(
                                         )),
                                     })),
                                 };
-                                if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                                if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                                     debug!("clipboard send error: {}", e);
+                                } else if let Some(control) = clipboard_sync.as_ref() {
+                                    control.sent_updates.fetch_add(1, Ordering::Relaxed);
                                 }
                             }
                         }
@@ -1096,77 +2062,112 @@ async fn run_client_inner(
                     if let Err(e) = send_next_file_chunk(
                         &socket,
                         &mut crypto,
+                        &mut compact_tx,
                         connect_addr,
                         alias,
                         next_packet_id(),
-                        relay_info,
+                        relay_info.as_ref(),
                         transfer_budget_kbps,
                         &mut file_transfer_limiter,
                         &mut file_transfer.outgoing,
+                        file_event_bus.as_ref(),
                     ).await {
                         warn!("file transfer send error: {}", e);
                     }
                 }
             }
 
+            _ = feedback_interval.tick(), if transport_feedback.is_some() => {
+                let feedback = transport_feedback
+                    .as_mut()
+                    .and_then(|recorder| recorder.poll(Instant::now()));
+                if let (Some(alias), Some(feedback)) = (session_alias, feedback) {
+                    let msg = ProtoMessage {
+                        content: Some(rift_core::message::Content::Control(ProtoControl {
+                            content: Some(rift_core::control_message::Content::TransportFeedback(feedback)),
+                        })),
+                    };
+                    if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                        debug!("transport feedback send error: {}", e);
+                    }
+                }
+            }
+
             // Jitter buffer drain
             _ = jitter_interval.tick() => {
-                while let Some(ready) = jitter_buffer.pop_ready(now_us()) {
+                if let Some(alias) = session_alias {
+                    let packet_ids = nack_tracker.poll(now_us(), last_rtt_us);
+                    if !packet_ids.is_empty() {
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::Nack(rift_core::Nack { packet_ids })),
+                            })),
+                        };
+                        if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                            debug!("nack send error: {}", e);
+                        }
+                    }
+                }
+
+                while let Some(mut ready) = jitter_buffer.pop_ready(now_us()) {
                     let mut rendered = false;
-                    let render_start = Instant::now();
 
                     if let Some(ref mut rec) = recorder {
-                        if let (Some(codec), Some(res)) = (stream_codec, stream_resolution) {
-                            let _ = rec.write_frame(&ready.data, ready.keyframe, codec, res, 60);
+                        if let (Some(codec), Some(res), rift_core::VIDEO_STREAM_PRIMARY) = (stream_codec, stream_resolution, ready.stream_id) {
+                            let _ = rec.write_frame(&ready.data, ready.keyframe, codec, res, 60, ready.timestamp_us);
                         }
                     }
 
+                    let decode_start = Instant::now();
                     if let Some(adapter) = vr_adapter.as_ref() {
                         if let Ok(mut adapter) = adapter.lock() {
-                            let frame = VrVideoFrame {
-                                timestamp_us: ready.timestamp_us,
-                                frame_id: ready.frame_id,
-                                keyframe: ready.keyframe,
-                                data: Bytes::from(ready.data),
-                            };
+                            let frame = vr_video_frame(&mut ready, vr_stereo_mode);
                             let _ = adapter.submit_video(frame);
                             rendered = true;
                         }
-                    } else if let Some(r) = renderer.as_mut() {
+                    } else if let Some(r) = flat_renderer(&mut renderer, &mut monitor_streams, ready.stream_id, stream_codec, renderer_factory.as_ref()) {
                         r.render(&ready.data, ready.timestamp_us)?;
                         rendered = true;
                     }
 
+                    if rendered && ready.stream_id == rift_core::VIDEO_STREAM_PRIMARY {
+                        av_sync.on_video_presented(ready.timestamp_us, now_us());
+                    }
                     if rendered {
-                        let render_duration_us = render_start.elapsed().as_micros() as u32;
+                        let latency = presented_latency(&ready, last_rtt_us, decode_start);
+                        latency_telemetry.record(&latency);
                         if let Some(stats) = runtime_stats.as_ref() {
                             stats.frames_decoded.fetch_add(1, Ordering::Relaxed);
+                            stats.record_latency(latency);
                         }
 
                         if let Some(alias) = session_alias {
-                            let latency = rift_core::LatencyStats {
-                                frame_id: ready.frame_id,
-                                capture_us: ready.capture_duration_us,
-                                encode_us: ready.encode_duration_us,
-                                network_us: (last_rtt_us / 2) as u32,
-                                decode_us: render_duration_us, // Simplified: decode+render
-                                render_us: 0,
-                                total_us: 0,
-                            };
                             let msg = ProtoMessage {
                                 content: Some(rift_core::message::Content::Control(ProtoControl {
-                                    content: Some(rift_core::control_message::Content::Latency(latency)),
+                                    content: Some(rift_core::control_message::Content::Latency(latency.to_proto())),
                                 })),
                             };
-                            let _ = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await;
+                            let _ = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await;
                         }
                     }
                 }
+                while let Some(packet) = av_sync.pop_ready(now_us()) {
+                    play_audio(&packet, &mut spatial_audio, &mut audio_renderer, &mut audio_disabled);
+                }
+                if let Some(stats) = runtime_stats.as_ref() {
+                    let sync = av_sync.stats();
+                    stats.av_sync_skew_us.store(sync.skew_us, Ordering::Relaxed);
+                    stats.audio_packets_dropped.store(sync.dropped, Ordering::Relaxed);
+                    stats.jitter_buffer_delay_us.store(jitter_buffer.delay_us(), Ordering::Relaxed);
+                    stats.jitter_buffer_frames.store(jitter_buffer.len() as u64, Ordering::Relaxed);
+                    stats.late_frames_dropped.store(jitter_buffer.late_dropped(), Ordering::Relaxed);
+                }
             }
 
             // Receive packets
             recv = socket.recv_from(&mut buf) => {
                 let (len, peer) = recv?;
+                let peer = net::canonical(peer);
                 let mut raw = &buf[..len];
 
                 if RelayHeader::quick_check(raw) {
@@ -1176,11 +2177,29 @@ async fn run_client_inner(
                                 raw = &raw[RELAY_HEADER_SIZE..];
                             }
                             RelayPacketType::LeaseAck => {
-                                info!("relay lease accepted");
+                                let Some(lease) = relay_lease.as_mut().filter(|lease| lease.is_current(peer, relay_header.session_id)) else {
+                                    continue;
+                                };
+                                match LeaseAckPayload::decode(&raw[RELAY_HEADER_SIZE..]) {
+                                    Ok(ack) => {
+                                        let now_ms = now_us() / 1000;
+                                        lease.on_ack(ack.expires_ms, Instant::now(), now_ms);
+                                        debug!("relay lease good for {}s", ack.expires_ms.saturating_sub(now_ms) / 1000);
+                                    }
+                                    Err(e) => debug!("malformed relay lease ack: {}", e),
+                                }
                                 continue;
                             }
                             RelayPacketType::LeaseReject => {
-                                warn!("relay lease rejected");
+                                match LeaseRejectPayload::decode(&raw[RELAY_HEADER_SIZE..]) {
+                                    Ok(reject) => {
+                                        warn!("relay lease rejected: {:?}", reject.reason);
+                                        if let Some(lease) = relay_lease.as_mut().filter(|lease| lease.is_current(peer, relay_header.session_id)) {
+                                            lease.on_reject(reject.reason, Instant::now());
+                                        }
+                                    }
+                                    Err(e) => warn!("relay lease rejected: {}", e),
+                                }
                                 continue;
                             }
                             _ => continue,
@@ -1188,7 +2207,7 @@ async fn run_client_inner(
                     }
                 }
 
-                let phys = match PhysicalPacket::decode(Bytes::copy_from_slice(raw)) {
+                let phys = match compact_rx.decode(Bytes::copy_from_slice(raw)) {
                     Ok(p) => p,
                     Err(e) => {
                         debug!("RIFT decode error from {}: {}", peer, e);
@@ -1199,21 +2218,6 @@ async fn run_client_inner(
                 let arrival_us = now_us();
                 arrival_jitter.on_arrival(arrival_us);
 
-                if let Some(alias) = session_alias {
-                    let missing = nack_window.on_packet(phys.packet_id);
-                    if !missing.is_empty() {
-                        let nack = rift_core::Nack { packet_ids: missing };
-                        let msg = ProtoMessage {
-                            content: Some(rift_core::message::Content::Control(ProtoControl {
-                                content: Some(rift_core::control_message::Content::Nack(nack)),
-                            })),
-                        };
-                        if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
-                            debug!("nack send error: {}", e);
-                        }
-                    }
-                }
-
                 // Decrypt if needed
                 let plaintext = match decrypt_packet(&mut crypto, &phys) {
                     Ok(p) => p,
@@ -1222,13 +2226,37 @@ async fn run_client_inner(
                         continue;
                     }
                 };
+                compact_rx.accept(phys.packet_id);
+                last_rx = Instant::now();
+                if peer == connect_addr {
+                    paths.on_traffic();
+                } else if paths.select_by_source(peer) {
+                    // The host answered on another candidate; follow it there.
+                    info!("host now reachable via {}", peer);
+                    connect_addr = paths.active().addr;
+                    relay_info = paths.active().relay.clone();
+                }
+
+                // Handshake packets use id 0 and aren't part of the NACK sequence.
+                if session_alias.is_some() && phys.packet_id != 0 {
+                    nack_tracker.on_packet(phys.packet_id, arrival_us);
+                    if let Some(recorder) = transport_feedback.as_mut() {
+                        recorder.on_packet(phys.packet_id, arrival_us);
+                    }
+                }
 
-                if let Some(last_id) = last_packet_id {
-                    if phys.packet_id > last_id + 1 {
-                        lost_packets = lost_packets.saturating_add((phys.packet_id - last_id - 1) as u32);
+                // Retransmissions arrive behind the newest id; they neither
+                // count as new loss nor rewind the sequence.
+                match last_packet_id {
+                    Some(last_id) if phys.packet_id <= last_id => {}
+                    Some(last_id) => {
+                        if phys.packet_id > last_id + 1 {
+                            lost_packets = lost_packets.saturating_add((phys.packet_id - last_id - 1) as u32);
+                        }
+                        last_packet_id = Some(phys.packet_id);
                     }
+                    None => last_packet_id = Some(phys.packet_id),
                 }
-                last_packet_id = Some(phys.packet_id);
                 received_packets = received_packets.saturating_add(1);
 
                 let msg = match decode_msg(&plaintext) {
@@ -1251,11 +2279,45 @@ async fn run_client_inner(
                                 rift_core::control_message::Content::HelloAck(ack) => {
                                     if !ack.accepted {
                                         warn!("session rejected by {}", peer);
+                                        hello_rejected = true;
                                         continue;
                                     }
-                                    info!("session established with {}", peer);
-                                    _session_id = Some(ack.session_id.clone());
+                                    span.record_session_id(&ack.session_id)
+                                        .record_session_alias(ack.session_alias);
+                                    info!(
+                                        "session established with {} (fec={:?})",
+                                        peer,
+                                        ack.fec_scheme()
+                                    );
+                                    session_id = <[u8; 16]>::try_from(ack.session_id.as_slice())
+                                        .ok()
+                                        .map(u128::from_be_bytes);
                                     session_alias = Some(ack.session_alias);
+                                    if ack.compact_header && compact_tx.is_none() {
+                                        compact_tx = Some(CompactEncoder::default());
+                                    }
+                                    if ack.transport_feedback && transport_feedback.is_none() {
+                                        transport_feedback = Some(FeedbackRecorder::new());
+                                    }
+                                    input_batching = ack.input_batch && config.input_rate_hz > 0;
+                                    permissions = PermissionSet::from_wire(ack.permissions);
+                                    if permissions != PermissionSet::all() {
+                                        info!("host granted {}", permissions);
+                                    }
+                                    if let Some(stats) = runtime_stats.as_ref() {
+                                        stats.set_permissions(permissions);
+                                    }
+                                    if ack.microphone && config.microphone && mic_rx.is_none() {
+                                        match crate::mic::start_microphone(crate::mic::voice_opus_config()).await {
+                                            Ok(rx) => {
+                                                info!("sending microphone to host");
+                                                mic_rx = Some(rx);
+                                            }
+                                            Err(e) => warn!("microphone capture unavailable: {}", e),
+                                        }
+                                    } else if config.microphone && !ack.microphone {
+                                        info!("host declined microphone passthrough");
+                                    }
                                     transfer_budget_kbps =
                                         file_transfer_budget_kbps(ack.initial_bitrate_kbps.max(1));
                                     file_transfer_limiter.set_rate_kbps(transfer_budget_kbps);
@@ -1363,17 +2425,42 @@ async fn run_client_inner(
                                         } else {
                                             (1280, 720)
                                         };
+                                        vr_stereo_mode = stereo_mode_from_proto(ack.stereo_mode);
+                                        let audio_layout = audio_layout_from_proto(ack.audio_layout);
+                                        match SpatialAudioRenderer::new(audio_layout, head_orientation.clone()) {
+                                            Ok(ar) => spatial_audio = Some(ar),
+                                            Err(e) => warn!("spatial audio init failed: {}", e),
+                                        }
+                                        let stream = VrStreamConfig {
+                                            codec,
+                                            width,
+                                            height,
+                                            stereo: vr_stereo_mode,
+                                            refresh_hz: ack.fps,
+                                        };
+                                        vr_stream = Some(stream);
                                         if let Ok(mut adapter) = adapter.lock() {
-                                            adapter.configure_stream(VrStreamConfig {
-                                                codec,
-                                                width,
-                                                height,
-                                            });
+                                            adapter.configure_stream(stream);
                                         }
                                     }
                                 }
+                                rift_core::control_message::Content::ResumeAck(ack) => {
+                                    info!("session resumed with {} (attempt {})", peer, ack.attempt);
+                                }
+                                rift_core::control_message::Content::PermissionUpdate(update) => {
+                                    permissions = PermissionSet::from_wire(update.permissions);
+                                    info!("host changed permissions to {}", permissions);
+                                    if let Some(stats) = runtime_stats.as_ref() {
+                                        stats.set_permissions(permissions);
+                                    }
+                                }
+                                rift_core::control_message::Content::SessionEnd(end) => {
+                                    info!("host {} ended the session ({:?})", peer, end.reason());
+                                    break;
+                                }
                                 rift_core::control_message::Content::MonitorList(list) => {
                                     info!("Received monitor list: {} displays", list.monitors.len());
+                                    monitor_streams.displays = list.monitors.clone();
                                     if let Some(stats) = runtime_stats.as_ref() {
                                         if let Ok(mut monitors) = stats.monitors.lock() {
                                             *monitors = list.monitors;
@@ -1396,7 +2483,7 @@ async fn run_client_inner(
                                                     )),
                                                 })),
                                             };
-                                            if let Err(e) = send_rift_msg(&socket, &mut crypto, connect_addr, msg, Some(alias), next_packet_id(), relay_info).await {
+                                            if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
                                                 debug!("encoder control send error: {}", e);
                                             } else {
                                                 last_skip_sent = Instant::now();
@@ -1412,11 +2499,16 @@ async fn run_client_inner(
                                 rift_core::control_message::Content::Clipboard(clip) => {
                                     if clip.text.len() > rift_core::MAX_CLIPBOARD_TEXT_BYTES {
                                         warn!("Received clipboard message exceeds size limit ({} bytes), ignoring", clip.text.len());
+                                    } else if !clipboard_direction().receives() {
+                                        debug!("Ignoring clipboard update from host: sync is {}", clipboard_direction());
                                     } else {
                                         debug!("Received clipboard update from host");
                                         if let Some(ref mut c) = clipboard {
                                             let _ = c.set_text(clip.text.clone());
                                             last_clipboard_text = Some(clip.text);
+                                            if let Some(control) = clipboard_sync.as_ref() {
+                                                control.received_updates.fetch_add(1, Ordering::Relaxed);
+                                            }
                                         }
                                     }
                                 }
@@ -1442,11 +2534,12 @@ async fn run_client_inner(
                                                         let _ = send_rift_msg(
                                                             &socket,
                                                             &mut crypto,
+                                                            &mut compact_tx,
                                                             connect_addr,
                                                             status_msg,
                                                             Some(alias),
                                                             next_packet_id(),
-                                                            relay_info,
+                                                            relay_info.as_ref(),
                                                         )
                                                         .await;
                                                     }
@@ -1468,11 +2561,12 @@ async fn run_client_inner(
                                                     let _ = send_rift_msg(
                                                         &socket,
                                                         &mut crypto,
+                                                        &mut compact_tx,
                                                         connect_addr,
                                                         status_msg,
                                                         Some(alias),
                                                         next_packet_id(),
-                                                        relay_info,
+                                                        relay_info.as_ref(),
                                                     )
                                                     .await;
                                                 }
@@ -1486,6 +2580,15 @@ async fn run_client_inner(
                                             ) {
                                                 Ok(incoming) => {
                                                     info!("receiving file {} from host", incoming.offer().filename);
+                                                    emit_file_event(
+                                                        file_event_bus.as_ref(),
+                                                        FileTransferEvent::Started {
+                                                            file_id,
+                                                            direction: FileTransferDirection::Incoming,
+                                                            filename: incoming.offer().filename.clone(),
+                                                            file_size: incoming.offer().file_size,
+                                                        },
+                                                    );
                                                     file_transfer.incoming.insert(file_id, incoming);
                                                     let status_msg = ProtoMessage {
                                                         content: Some(rift_core::message::Content::Control(ProtoControl {
@@ -1502,11 +2605,12 @@ async fn run_client_inner(
                                                         let _ = send_rift_msg(
                                                             &socket,
                                                             &mut crypto,
+                                                            &mut compact_tx,
                                                             connect_addr,
                                                             status_msg,
                                                             Some(alias),
                                                             next_packet_id(),
-                                                            relay_info,
+                                                            relay_info.as_ref(),
                                                         )
                                                         .await;
                                                     }
@@ -1519,6 +2623,39 @@ async fn run_client_inner(
                                         Err(err) => warn!("invalid file offer {}: {}", file_id, err),
                                     }
                                 }
+                                rift_core::control_message::Content::Haptic(haptic) => {
+                                    if let Some(adapter) = vr_adapter.as_ref() {
+                                        if let Ok(mut adapter) = adapter.lock() {
+                                            let feedback = wavry_vr::types::HapticFeedback {
+                                                controller_id: haptic.controller_id,
+                                                amplitude: haptic.amplitude.clamp(0.0, 1.0),
+                                                frequency_hz: haptic.frequency_hz.max(0.0),
+                                                duration_us: haptic.duration_us,
+                                            };
+                                            if let Err(e) = adapter.submit_haptic(feedback) {
+                                                debug!("vr haptic submit failed: {}", e);
+                                            }
+                                        }
+                                    }
+                                }
+                                rift_core::control_message::Content::StreamReconfigured(update) => {
+                                    if let (Some(adapter), Some(stream)) = (vr_adapter.as_ref(), vr_stream.as_mut()) {
+                                        vr_stereo_mode = stereo_mode_from_proto(update.stereo_mode);
+                                        stream.stereo = vr_stereo_mode;
+                                        stream.refresh_hz = update.fps;
+                                        info!("vr stream reconfigured: {:?} at {} fps", vr_stereo_mode, update.fps);
+                                        if let Ok(mut adapter) = adapter.lock() {
+                                            adapter.configure_stream(*stream);
+                                        }
+                                    }
+                                }
+                                rift_core::control_message::Content::RecordingStatus(status) => {
+                                    if status.error.is_empty() {
+                                        info!("host recording {}", if status.recording { "on" } else { "off" });
+                                    } else {
+                                        warn!("host recording request refused: {}", status.error);
+                                    }
+                                }
                                 rift_core::control_message::Content::FileStatus(status) => {
                                     let status_name = rift_core::file_status::Status::try_from(status.status)
                                         .map(|s| format!("{:?}", s))
@@ -1528,8 +2665,8 @@ async fn run_client_inner(
                                         "host file transfer status file_id={} status={} message={}",
                                         status.file_id, status_name, message
                                     );
-                                    apply_file_status_to_outgoing(&mut file_transfer.outgoing, &status);
-                                    apply_file_status_to_incoming(&mut file_transfer.incoming, &status);
+                                    apply_file_status_to_outgoing(&mut file_transfer.outgoing, &status, file_event_bus.as_ref());
+                                    apply_file_status_to_incoming(&mut file_transfer.incoming, &status, file_event_bus.as_ref());
                                 }
                                 _ => {}
                             }
@@ -1542,19 +2679,19 @@ async fn run_client_inner(
                                 if let Some(frame) = frames.push(chunk) {
                                     jitter_buffer.update(arrival_jitter.jitter_us_f64());
                                     jitter_buffer.push(frame, arrival_us);
-                                    while let Some(ready) = jitter_buffer.pop_ready(now_us()) {
+                                    while let Some(mut ready) = jitter_buffer.pop_ready(now_us()) {
                                         if let Some(adapter) = vr_adapter.as_ref() {
                                             if let Ok(mut adapter) = adapter.lock() {
-                                                let frame = VrVideoFrame {
-                                                    timestamp_us: ready.timestamp_us,
-                                                    frame_id: ready.frame_id,
-                                                    keyframe: ready.keyframe,
-                                                    data: Bytes::from(ready.data),
-                                                };
+                                                let frame = vr_video_frame(&mut ready, vr_stereo_mode);
                                                 let _ = adapter.submit_video(frame);
                                             }
-                                        } else if let Some(r) = renderer.as_mut() {
+                                        } else if let Some(r) = flat_renderer(&mut renderer, &mut monitor_streams, ready.stream_id, stream_codec, renderer_factory.as_ref()) {
+                                            let decode_start = Instant::now();
                                             r.render(&ready.data, ready.timestamp_us)?;
+                                            latency_telemetry.record(&presented_latency(&ready, last_rtt_us, decode_start));
+                                        }
+                                        if ready.stream_id == rift_core::VIDEO_STREAM_PRIMARY {
+                                            av_sync.on_video_presented(ready.timestamp_us, now_us());
                                         }
                                     }
                                 }
@@ -1562,22 +2699,16 @@ async fn run_client_inner(
                             Some(rift_core::media_message::Content::Audio(packet)) => {
                                 fec_cache.insert(phys.packet_id, plaintext.clone());
 
-                                if let Some(ref mut rec) = recorder {
+                                // The recorder muxes plain stereo Opus only.
+                                if let (Some(rec), AudioChannelLayout::Stereo) = (recorder.as_mut(), audio_layout_from_proto(packet.layout)) {
                                     let _ = rec.write_audio(&packet.payload, packet.timestamp_us);
                                 }
 
-                                if let Some(ar) = audio_renderer.as_mut() {
-                                    if let Err(e) = ar.render(&packet.payload, packet.timestamp_us) {
-                                        if !audio_disabled {
-                                            warn!("audio render failed, disabling audio: {}", e);
-                                        }
-                                        audio_renderer = None;
-                                        audio_disabled = true;
-                                    }
-                                }
+                                av_sync.push_audio(packet, arrival_us);
                             }
                             Some(rift_core::media_message::Content::Fec(fec)) => {
-                                if let Some(recovered_plaintext) = fec_cache.try_recover(&fec) {
+                                for (recovered_id, recovered_plaintext) in fec_cache.try_recover(fec) {
+                                    nack_tracker.on_recovered(recovered_id);
                                     if let Ok(recovered_msg) = decode_msg(&recovered_plaintext) {
                                         if let Some(rift_core::message::Content::Media(recovered_media)) = recovered_msg.content {
                                             match recovered_media.content {
@@ -1585,55 +2716,49 @@ async fn run_client_inner(
                                                     if let Some(frame) = frames.push(chunk) {
                                                         jitter_buffer.update(arrival_jitter.jitter_us_f64());
                                                         jitter_buffer.push(frame, now_us());
-                                                        while let Some(ready) = jitter_buffer.pop_ready(now_us()) {
+                                                        while let Some(mut ready) = jitter_buffer.pop_ready(now_us()) {
                                                             if let Some(ref mut rec) = recorder {
-                                                                if let (Some(codec), Some(res)) = (stream_codec, stream_resolution) {
-                                                                    let _ = rec.write_frame(&ready.data, ready.keyframe, codec, res, 60);
+                                                                if let (Some(codec), Some(res), rift_core::VIDEO_STREAM_PRIMARY) = (stream_codec, stream_resolution, ready.stream_id) {
+                                                                    let _ = rec.write_frame(&ready.data, ready.keyframe, codec, res, 60, ready.timestamp_us);
                                                                 }
                                                             }
 
                                                             if let Some(adapter) = vr_adapter.as_ref() {
                                                                 if let Ok(mut adapter) = adapter.lock() {
-                                                                    let frame = VrVideoFrame {
-                                                                        timestamp_us: ready.timestamp_us,
-                                                                        frame_id: ready.frame_id,
-                                                                        keyframe: ready.keyframe,
-                                                                        data: Bytes::from(ready.data),
-                                                                    };
+                                                                    let frame = vr_video_frame(&mut ready, vr_stereo_mode);
                                                                     let _ = adapter.submit_video(frame);
                                                                 }
-                                                            } else if let Some(r) = renderer.as_mut() {
+                                                            } else if let Some(r) = flat_renderer(&mut renderer, &mut monitor_streams, ready.stream_id, stream_codec, renderer_factory.as_ref()) {
+                                                                let decode_start = Instant::now();
                                                                 r.render(&ready.data, ready.timestamp_us)?;
+                                                                latency_telemetry.record(&presented_latency(&ready, last_rtt_us, decode_start));
+                                                            }
+                                                            if ready.stream_id == rift_core::VIDEO_STREAM_PRIMARY {
+                                                                av_sync.on_video_presented(ready.timestamp_us, now_us());
                                                             }
                                                         }
                                                     }
                                                 }
                                                 Some(rift_core::media_message::Content::Audio(packet)) => {
-                                                    if let Some(ref mut rec) = recorder {
+                                                    if let (Some(rec), AudioChannelLayout::Stereo) = (recorder.as_mut(), audio_layout_from_proto(packet.layout)) {
                                                         let _ = rec.write_audio(&packet.payload, packet.timestamp_us);
                                                     }
 
-                                                    if let Some(ar) = audio_renderer.as_mut() {
-                                                        if let Err(e) = ar.render(&packet.payload, packet.timestamp_us) {
-                                                            if !audio_disabled {
-                                                                warn!("audio render failed, disabling audio: {}", e);
-                                                            }
-                                                            audio_renderer = None;
-                                                            audio_disabled = true;
-                                                        }
-                                                    }
+                                                    av_sync.push_audio(packet, now_us());
                                                 }
                                                 Some(rift_core::media_message::Content::FileChunk(chunk)) => {
                                                     if let Some(alias) = session_alias {
                                                         if let Err(err) = handle_incoming_file_chunk(
                                                             &socket,
                                                             &mut crypto,
+                                                            &mut compact_tx,
                                                             connect_addr,
                                                             alias,
                                                             next_packet_id(),
-                                                            relay_info,
+                                                            relay_info.as_ref(),
                                                             &mut file_transfer.incoming,
                                                             chunk,
+                                                            file_event_bus.as_ref(),
                                                         ).await {
                                                             warn!("file chunk handling error: {}", err);
                                                         }
@@ -1650,17 +2775,39 @@ async fn run_client_inner(
                                     if let Err(err) = handle_incoming_file_chunk(
                                         &socket,
                                         &mut crypto,
+                                        &mut compact_tx,
                                         connect_addr,
                                         alias,
                                         next_packet_id(),
-                                        relay_info,
+                                        relay_info.as_ref(),
                                         &mut file_transfer.incoming,
                                         chunk,
+                                        file_event_bus.as_ref(),
                                     ).await {
                                         warn!("file chunk handling error: {}", err);
                                     }
                                 }
                             }
+                            Some(rift_core::media_message::Content::Cursor(update)) => {
+                                cursor = apply_cursor_update(cursor.as_ref(), &update);
+                                if let (Some(state), Some(r)) = (cursor.as_ref(), renderer.as_mut()) {
+                                    if let Err(e) = r.update_cursor(state) {
+                                        debug!("cursor update failed: {}", e);
+                                    }
+                                }
+                            }
+                            Some(rift_core::media_message::Content::Probe(probe)) => {
+                                if let (Some(result), Some(alias)) = (probe_receiver.on_packet(&probe, Instant::now()), session_alias) {
+                                    let msg = ProtoMessage {
+                                        content: Some(rift_core::message::Content::Control(ProtoControl {
+                                            content: Some(rift_core::control_message::Content::ProbeResult(result)),
+                                        })),
+                                    };
+                                    if let Err(e) = send_rift_msg(&socket, &mut crypto, &mut compact_tx, connect_addr, msg, Some(alias), next_packet_id(), relay_info.as_ref()).await {
+                                        debug!("probe result send error: {}", e);
+                                    }
+                                }
+                            }
                             _ => {}
                         }
                     }
@@ -1739,6 +2886,7 @@ async fn run_client_inner(
 async fn send_next_file_chunk(
     socket: &UdpSocket,
     crypto: &mut CryptoState,
+    compact_tx: &mut Option<CompactEncoder>,
     connect_addr: SocketAddr,
     alias: u32,
     packet_id: u64,
@@ -1746,6 +2894,7 @@ async fn send_next_file_chunk(
     budget_kbps: u32,
     limiter: &mut FileTransferLimiter,
     outgoing: &mut VecDeque<OutgoingFile>,
+    events: Option<&broadcast::Sender<FileTransferEvent>>,
 ) -> Result<()> {
     if !rotate_to_next_ready_transfer(outgoing) {
         return Ok(());
@@ -1768,6 +2917,7 @@ async fn send_next_file_chunk(
             send_rift_msg(
                 socket,
                 crypto,
+                compact_tx,
                 connect_addr,
                 msg,
                 Some(alias),
@@ -1781,6 +2931,15 @@ async fn send_next_file_chunk(
                 front.offer().filename,
                 front.offer().file_size
             );
+            emit_file_event(
+                events,
+                FileTransferEvent::Started {
+                    file_id: front.offer().file_id,
+                    direction: FileTransferDirection::Outgoing,
+                    filename: front.offer().filename.clone(),
+                    file_size: front.offer().file_size,
+                },
+            );
             progressed = true;
         } else {
             let current_chunk = front.next_chunk_index();
@@ -1805,6 +2964,7 @@ async fn send_next_file_chunk(
                         send_rift_msg(
                             socket,
                             crypto,
+                            compact_tx,
                             connect_addr,
                             msg,
                             Some(alias),
@@ -1812,6 +2972,19 @@ async fn send_next_file_chunk(
                             relay_info,
                         )
                         .await?;
+                        let sent_chunks = current_chunk + 1;
+                        let total_chunks = front.offer().total_chunks;
+                        if file_progress_due(sent_chunks, total_chunks) {
+                            emit_file_event(
+                                events,
+                                FileTransferEvent::Progress {
+                                    file_id: front.offer().file_id,
+                                    direction: FileTransferDirection::Outgoing,
+                                    transferred_chunks: sent_chunks,
+                                    total_chunks,
+                                },
+                            );
+                        }
                         progressed = true;
                     }
                 }
@@ -1833,12 +3006,14 @@ async fn send_next_file_chunk(
 async fn handle_incoming_file_chunk(
     socket: &UdpSocket,
     crypto: &mut CryptoState,
+    compact_tx: &mut Option<CompactEncoder>,
     connect_addr: SocketAddr,
     alias: u32,
     packet_id: u64,
     relay_info: Option<&RelayInfo>,
     incoming: &mut HashMap<u64, IncomingFile>,
     chunk: rift_core::FileChunk,
+    events: Option<&broadcast::Sender<FileTransferEvent>>,
 ) -> Result<()> {
     let file_id = chunk.file_id;
     let mut progress_update: Option<(u32, u32, u32)> = None;
@@ -1850,7 +3025,18 @@ async fn handle_incoming_file_chunk(
             let total = entry.offer().total_chunks;
             let remaining = total.saturating_sub(received);
             let gap_detected = resume_chunk < chunk.chunk_index;
-            let progress_due = received % FILE_TRANSFER_PROGRESS_CHUNK_INTERVAL == 0;
+            let progress_due = file_progress_due(received, total);
+            if progress_due {
+                emit_file_event(
+                    events,
+                    FileTransferEvent::Progress {
+                        file_id,
+                        direction: FileTransferDirection::Incoming,
+                        transferred_chunks: received,
+                        total_chunks: total,
+                    },
+                );
+            }
             if gap_detected || progress_due || remaining <= 2 {
                 progress_update = Some((resume_chunk, received, total));
             }
@@ -1871,6 +3057,7 @@ async fn handle_incoming_file_chunk(
         let _ = send_rift_msg(
             socket,
             crypto,
+            compact_tx,
             connect_addr,
             msg,
             Some(alias),
@@ -1897,6 +3084,7 @@ async fn handle_incoming_file_chunk(
             let _ = send_rift_msg(
                 socket,
                 crypto,
+                compact_tx,
                 connect_addr,
                 msg,
                 Some(alias),
@@ -1916,6 +3104,14 @@ async fn handle_incoming_file_chunk(
                     file_id,
                     path.display()
                 );
+                emit_file_event(
+                    events,
+                    FileTransferEvent::Completed {
+                        file_id,
+                        direction: FileTransferDirection::Incoming,
+                        path: Some(path.clone()),
+                    },
+                );
                 let msg = ProtoMessage {
                     content: Some(rift_core::message::Content::Control(ProtoControl {
                         content: Some(rift_core::control_message::Content::FileStatus(
@@ -1930,6 +3126,7 @@ async fn handle_incoming_file_chunk(
                 let _ = send_rift_msg(
                     socket,
                     crypto,
+                    compact_tx,
                     connect_addr,
                     msg,
                     Some(alias),
@@ -1940,6 +3137,14 @@ async fn handle_incoming_file_chunk(
             }
             Err(err) => {
                 warn!("failed to finalize incoming file {}: {}", file_id, err);
+                emit_file_event(
+                    events,
+                    FileTransferEvent::Failed {
+                        file_id,
+                        direction: FileTransferDirection::Incoming,
+                        error: err.to_string(),
+                    },
+                );
                 let msg = ProtoMessage {
                     content: Some(rift_core::message::Content::Control(ProtoControl {
                         content: Some(rift_core::control_message::Content::FileStatus(
@@ -1954,6 +3159,7 @@ async fn handle_incoming_file_chunk(
                 let _ = send_rift_msg(
                     socket,
                     crypto,
+                    compact_tx,
                     connect_addr,
                     msg,
                     Some(alias),
@@ -1968,9 +3174,11 @@ async fn handle_incoming_file_chunk(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn send_rift_msg(
     socket: &UdpSocket,
     crypto: &mut CryptoState,
+    compact_tx: &mut Option<CompactEncoder>,
     dest: SocketAddr,
     msg: ProtoMessage,
     alias: Option<u32>,
@@ -1994,19 +3202,61 @@ async fn send_rift_msg(
         packet_id,
         payload: Bytes::copy_from_slice(&payload),
     };
+    let bytes = match compact_tx {
+        Some(encoder) => encoder.encode(&phys, message_channel(&msg)),
+        None => phys.encode(),
+    };
 
-    let rift_bytes = phys.encode();
+    send_physical(socket, &bytes, dest, relay).await
+}
 
+/// Asks the host to re-bind this session to our current address. The
+/// message is encrypted under the session's keys like any other, but always
+/// with the full header: a host that sees it from a new address has no
+/// compact-header state for it yet.
+async fn send_resume(
+    socket: &UdpSocket,
+    crypto: &mut CryptoState,
+    dest: SocketAddr,
+    resume: rift_core::Resume,
+    packet_id: u64,
+    relay: Option<&RelayInfo>,
+) -> Result<()> {
+    let alias = resume.session_alias;
+    let msg = ProtoMessage {
+        content: Some(rift_core::message::Content::Control(ProtoControl {
+            content: Some(rift_core::control_message::Content::Resume(resume)),
+        })),
+    };
+    send_rift_msg(
+        socket,
+        crypto,
+        &mut None,
+        dest,
+        msg,
+        Some(alias),
+        packet_id,
+        relay,
+    )
+    .await
+}
+
+async fn send_physical(
+    socket: &UdpSocket,
+    rift_bytes: &[u8],
+    dest: SocketAddr,
+    relay: Option<&RelayInfo>,
+) -> Result<()> {
     if let Some(info) = relay {
         let header = RelayHeader::new(RelayPacketType::Forward, info.session_id);
         let mut buf = vec![0u8; RELAY_HEADER_SIZE + rift_bytes.len()];
         header
             .encode(&mut buf)
             .map_err(|e| anyhow!("relay header encode: {}", e))?;
-        buf[RELAY_HEADER_SIZE..].copy_from_slice(&rift_bytes);
-        socket.send_to(&buf, info.addr).await?;
+        buf[RELAY_HEADER_SIZE..].copy_from_slice(rift_bytes);
+        net::send_to(socket, &buf, info.addr).await?;
     } else {
-        socket.send_to(&rift_bytes, dest).await?;
+        net::send_to(socket, rift_bytes, dest).await?;
     }
     Ok(())
 }
@@ -2021,19 +3271,12 @@ fn decrypt_packet(crypto: &mut CryptoState, phys: &PhysicalPacket) -> Result<Vec
     }
 }
 
-async fn discover_host(timeout: Duration) -> Result<SocketAddr> {
-    use mdns_sd::ServiceEvent;
-    let handle = tokio::task::spawn_blocking(move || {
-        let daemon = mdns_sd::ServiceDaemon::new()?;
-        let receiver = daemon.browse("_wavry._udp.local.")?;
-        for event in receiver {
-            if let ServiceEvent::ServiceResolved(info) = event {
-                if let Some(addr) = info.get_addresses().iter().next() {
-                    return Ok(SocketAddr::new(*addr, info.get_port()));
-                }
-            }
-        }
-        Err(anyhow!("no wavry hosts discovered"))
-    });
-    time::timeout(timeout, handle).await??
+/// Addresses of the first host found on the LAN.
+async fn discover_host(timeout: Duration) -> Result<Vec<SocketAddr>> {
+    let mut browser = discovery::discover_hosts()?;
+    let host = time::timeout(timeout, browser.next())
+        .await?
+        .ok_or_else(|| anyhow!("no wavry hosts discovered"))?;
+    info!("discovered host {} on the LAN", host.name);
+    Ok(host.addrs)
 }
@@ -0,0 +1,165 @@
+//! Finding hosts on the local network.
+//!
+//! Hosts advertise `_wavry._udp` over mDNS with their protocol version in the
+//! `v` TXT record. [`discover_hosts`] browses for them and yields each host as
+//! it resolves, again whenever its advertisement changes.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::net;
+
+pub const SERVICE_TYPE: &str = "_wavry._udp.local.";
+/// TXT record carrying the host's protocol version.
+pub const VERSION_TXT_KEY: &str = "v";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscoveredHost {
+    /// Instance name, usually the host's machine name.
+    pub name: String,
+    /// Full mDNS service name, unique per host on the network.
+    pub fullname: String,
+    /// Addresses to connect to, IPv6 first.
+    pub addrs: Vec<SocketAddr>,
+    pub version: Option<String>,
+    /// Every TXT record the host advertises, `v` included.
+    pub txt: BTreeMap<String, String>,
+}
+
+impl DiscoveredHost {
+    /// `None` when the host only advertised addresses a client cannot use.
+    fn from_service(info: &ServiceInfo) -> Option<Self> {
+        let addrs = net::happy_eyeballs_order(
+            info.get_addresses()
+                .iter()
+                // Link-local IPv6 needs a zone, which mDNS answers lack.
+                .filter(|ip| !net::is_ipv6_link_local(**ip))
+                .map(|ip| SocketAddr::new(*ip, info.get_port())),
+        );
+        if addrs.is_empty() {
+            return None;
+        }
+        let fullname = info.get_fullname().to_string();
+        let name = fullname
+            .strip_suffix(SERVICE_TYPE)
+            .map(|name| name.trim_end_matches('.'))
+            .unwrap_or(&fullname)
+            .to_string();
+        let txt: BTreeMap<String, String> = info
+            .get_properties()
+            .iter()
+            .map(|property| (property.key().to_string(), property.val_str().to_string()))
+            .collect();
+        Some(Self {
+            name,
+            fullname,
+            addrs,
+            version: txt.get(VERSION_TXT_KEY).cloned(),
+            txt,
+        })
+    }
+}
+
+/// Stream of hosts found by [`discover_hosts`]. Browsing stops when it is
+/// dropped.
+pub struct HostBrowser {
+    daemon: ServiceDaemon,
+    hosts: mpsc::UnboundedReceiver<DiscoveredHost>,
+}
+
+impl Stream for HostBrowser {
+    type Item = DiscoveredHost;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().hosts.poll_recv(cx)
+    }
+}
+
+impl Drop for HostBrowser {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Starts browsing the LAN for hosts.
+pub fn discover_hosts() -> Result<HostBrowser> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let (tx, hosts) = mpsc::unbounded_channel();
+    std::thread::Builder::new()
+        .name("wavry-mdns-browse".into())
+        .spawn(move || {
+            // Ends once the daemon shuts down or the browser is gone.
+            while let Ok(event) = events.recv() {
+                let ServiceEvent::ServiceResolved(info) = event else {
+                    continue;
+                };
+                if let Some(host) = DiscoveredHost::from_service(&info) {
+                    if tx.send(host).is_err() {
+                        return;
+                    }
+                }
+            }
+        })?;
+    Ok(HostBrowser { daemon, hosts })
+}
+
+/// Hosts that answered within `window`, one entry each, sorted by name.
+pub async fn list_hosts(window: Duration) -> Result<Vec<DiscoveredHost>> {
+    let mut browser = discover_hosts()?;
+    let mut hosts = BTreeMap::new();
+    let _ = time::timeout(window, async {
+        while let Some(host) = browser.next().await {
+            hosts.insert(host.fullname.clone(), host);
+        }
+    })
+    .await;
+    let mut hosts: Vec<DiscoveredHost> = hosts.into_values().collect();
+    hosts.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolved_service_becomes_a_host() {
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "living-room",
+            "living-room.local.",
+            "192.168.1.20",
+            4820,
+            &[("v", "1"), ("codec", "hevc")][..],
+        )
+        .unwrap();
+        let host = DiscoveredHost::from_service(&info).unwrap();
+        assert_eq!(host.name, "living-room");
+        assert_eq!(host.fullname, "living-room._wavry._udp.local.");
+        assert_eq!(host.addrs, vec!["192.168.1.20:4820".parse().unwrap()]);
+        assert_eq!(host.version.as_deref(), Some("1"));
+        assert_eq!(host.txt.get("codec").map(String::as_str), Some("hevc"));
+
+        let link_local = ServiceInfo::new(
+            SERVICE_TYPE,
+            "laptop",
+            "laptop.local.",
+            "fe80::1",
+            4820,
+            &[("v", "1")][..],
+        )
+        .unwrap();
+        assert_eq!(DiscoveredHost::from_service(&link_local), None);
+    }
+}
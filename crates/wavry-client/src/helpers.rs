@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use rand::Rng as _;
 use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::net::UdpSocket;
@@ -6,9 +7,18 @@ use tokio::time;
 
 use base64::{engine::general_purpose, Engine as _};
 use rift_core::{
-    decode_msg, encode_msg, Codec as RiftCodec, ControlMessage as ProtoControl,
-    Hello as ProtoHello, Message as ProtoMessage, Resolution as ProtoResolution, RIFT_VERSION,
+    decode_msg, encode_msg, AudioLayout as RiftAudioLayout, Codec as RiftCodec,
+    ControlMessage as ProtoControl, Hello as ProtoHello, Message as ProtoMessage,
+    Resolution as ProtoResolution, StereoMode as RiftStereoMode, SystemCursor as RiftSystemCursor,
+    MAX_CURSOR_SIZE, RIFT_VERSION,
 };
+use wavry_media::{AudioChannelLayout, CursorShape, CursorState, SystemCursor};
+use wavry_vr::types::{
+    Eye as VrEye, EyeView as VrEyeView, Fov as VrFov, Pose as VrPose, StereoMode as VrStereoMode,
+    VideoFrame as VrVideoFrame,
+};
+
+use crate::media::AssembledFrame;
 
 pub fn env_bool(name: &str, default: bool) -> bool {
     match std::env::var(name) {
@@ -27,6 +37,16 @@ pub fn now_us() -> u64 {
         .as_micros() as u64
 }
 
+/// Random non-zero identifier for a file transfer.
+pub fn random_file_id() -> u64 {
+    loop {
+        let id = rand::thread_rng().gen::<u64>();
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
 pub fn local_platform() -> rift_core::Platform {
     if cfg!(target_os = "windows") {
         rift_core::Platform::Windows
@@ -43,7 +63,12 @@ pub async fn discover_public_addr(socket: &UdpSocket) -> Result<SocketAddr> {
     let stun_msg = StunMessage::new_binding_request();
     let encoded = stun_msg.encode();
 
-    socket.send_to(&encoded, stun_server).await?;
+    // The public IPv4 mapping is what NAT traversal needs; IPv6 is not translated.
+    let stun_addr = tokio::net::lookup_host(stun_server)
+        .await?
+        .find(SocketAddr::is_ipv4)
+        .ok_or_else(|| anyhow!("no IPv4 address for {}", stun_server))?;
+    crate::net::send_to(socket, &encoded, stun_addr).await?;
 
     let mut buf = [0u8; 1024];
     let (len, _) = time::timeout(Duration::from_secs(2), socket.recv_from(&mut buf)).await??;
@@ -51,7 +76,13 @@ pub async fn discover_public_addr(socket: &UdpSocket) -> Result<SocketAddr> {
     StunMessage::decode_address(&buf[..len])
 }
 
-pub fn create_hello_base64(client_name: String, public_addr: Option<String>) -> Result<String> {
+/// `public_addr` is the STUN-mapped address older hosts understand;
+/// `candidate_addrs` lists any others, such as a global IPv6 address.
+pub fn create_hello_base64(
+    client_name: String,
+    public_addr: Option<String>,
+    candidate_addrs: Vec<String>,
+) -> Result<String> {
     // Note: this should ideally use a codec probe, but for CLI/minimal use we can default
     let hello = ProtoHello {
         client_name,
@@ -65,6 +96,16 @@ pub fn create_hello_base64(client_name: String, public_addr: Option<String>) ->
         input_caps: 0xF,
         protocol_version: RIFT_VERSION as u32,
         public_addr: public_addr.unwrap_or_default(),
+        candidate_addrs,
+        stereo_modes: vec![],
+        audio_layouts: vec![],
+        fec_schemes: rift_core::fec::supported_schemes(),
+        cursor_channel: false,
+        audio_params: None,
+        compact_header: false,
+        transport_feedback: false,
+        microphone: false,
+        clipboard_sync: None,
     };
     let msg = ProtoMessage {
         content: Some(rift_core::message::Content::Control(ProtoControl {
@@ -75,14 +116,17 @@ pub fn create_hello_base64(client_name: String, public_addr: Option<String>) ->
     Ok(general_purpose::STANDARD.encode(bytes))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_hello_ack_base64(
     accepted: bool,
     session_id: [u8; 16],
     session_alias: u32,
     public_addr: Option<String>,
+    candidate_addrs: Vec<String>,
     width: u32,
     height: u32,
     selected_codec: RiftCodec,
+    fec_scheme: rift_core::FecScheme,
 ) -> Result<String> {
     let ack = rift_core::HelloAck {
         accepted,
@@ -94,6 +138,17 @@ pub fn create_hello_ack_base64(
         session_id: session_id.to_vec(),
         session_alias,
         public_addr: public_addr.unwrap_or_default(),
+        candidate_addrs,
+        stereo_mode: rift_core::StereoMode::StereoAuto as i32,
+        audio_layout: rift_core::AudioLayout::AudioStereo as i32,
+        fec_scheme: fec_scheme as i32,
+        cursor_channel: false,
+        audio_params: None,
+        compact_header: false,
+        transport_feedback: false,
+        microphone: false,
+        permissions: 0,
+        input_batch: false,
     };
     let msg = ProtoMessage {
         content: Some(rift_core::message::Content::Control(ProtoControl {
@@ -128,10 +183,183 @@ pub fn decode_hello_ack_base64(b64: &str) -> Result<rift_core::HelloAck> {
     }
 }
 
+pub fn stereo_mode_to_proto(mode: VrStereoMode) -> RiftStereoMode {
+    match mode {
+        VrStereoMode::Auto => RiftStereoMode::StereoAuto,
+        VrStereoMode::Mono => RiftStereoMode::StereoMono,
+        VrStereoMode::SideBySide => RiftStereoMode::StereoSideBySide,
+        VrStereoMode::DualStream => RiftStereoMode::StereoDualStream,
+    }
+}
+
+pub fn stereo_mode_from_proto(mode: i32) -> VrStereoMode {
+    match RiftStereoMode::try_from(mode) {
+        Ok(RiftStereoMode::StereoMono) => VrStereoMode::Mono,
+        Ok(RiftStereoMode::StereoSideBySide) => VrStereoMode::SideBySide,
+        Ok(RiftStereoMode::StereoDualStream) => VrStereoMode::DualStream,
+        Ok(RiftStereoMode::StereoAuto) | Err(_) => VrStereoMode::Auto,
+    }
+}
+
+pub fn audio_layout_to_proto(layout: AudioChannelLayout) -> RiftAudioLayout {
+    match layout {
+        AudioChannelLayout::Stereo => RiftAudioLayout::AudioStereo,
+        AudioChannelLayout::Surround51 => RiftAudioLayout::AudioSurround51,
+        AudioChannelLayout::Surround71 => RiftAudioLayout::AudioSurround71,
+        AudioChannelLayout::AmbisonicFoa => RiftAudioLayout::AudioAmbisonicFoa,
+    }
+}
+
+pub fn audio_layout_from_proto(layout: i32) -> AudioChannelLayout {
+    match RiftAudioLayout::try_from(layout) {
+        Ok(RiftAudioLayout::AudioSurround51) => AudioChannelLayout::Surround51,
+        Ok(RiftAudioLayout::AudioSurround71) => AudioChannelLayout::Surround71,
+        Ok(RiftAudioLayout::AudioAmbisonicFoa) => AudioChannelLayout::AmbisonicFoa,
+        Ok(RiftAudioLayout::AudioStereo) | Err(_) => AudioChannelLayout::Stereo,
+    }
+}
+
+/// Applies a cursor update to the last known cursor. Updates without a shape
+/// keep the previous one; `None` until the first shape arrives.
+pub fn apply_cursor_update(
+    last: Option<&CursorState>,
+    update: &rift_core::CursorUpdate,
+) -> Option<CursorState> {
+    let (serial, shape) = match (cursor_shape_from_proto(update), last) {
+        (Some(shape), _) => (update.shape_serial, shape),
+        // A lost shape is resent shortly; keep the old serial until then so
+        // renderers pick up the new shape when it lands.
+        (None, Some(last)) => (last.serial, last.shape.clone()),
+        (None, None) => return None,
+    };
+    Some(CursorState {
+        x: update.x.clamp(0.0, 1.0),
+        y: update.y.clamp(0.0, 1.0),
+        visible: update.visible,
+        serial,
+        shape,
+    })
+}
+
+fn cursor_shape_from_proto(update: &rift_core::CursorUpdate) -> Option<CursorShape> {
+    let system = match update.system_cursor() {
+        RiftSystemCursor::None => {
+            let (width, height) = (update.width, update.height);
+            if !(1..=MAX_CURSOR_SIZE).contains(&width)
+                || !(1..=MAX_CURSOR_SIZE).contains(&height)
+                || update.bgra.len() != (width * height * 4) as usize
+            {
+                return None;
+            }
+            return Some(CursorShape::Bitmap {
+                width,
+                height,
+                hotspot_x: update.hotspot_x.min(width - 1),
+                hotspot_y: update.hotspot_y.min(height - 1),
+                bgra: update.bgra.clone(),
+            });
+        }
+        RiftSystemCursor::Arrow => SystemCursor::Arrow,
+        RiftSystemCursor::Ibeam => SystemCursor::IBeam,
+        RiftSystemCursor::Wait => SystemCursor::Wait,
+        RiftSystemCursor::Crosshair => SystemCursor::Crosshair,
+        RiftSystemCursor::Hand => SystemCursor::Hand,
+        RiftSystemCursor::ResizeEw => SystemCursor::ResizeEw,
+        RiftSystemCursor::ResizeNs => SystemCursor::ResizeNs,
+        RiftSystemCursor::ResizeNwse => SystemCursor::ResizeNwse,
+        RiftSystemCursor::ResizeNesw => SystemCursor::ResizeNesw,
+        RiftSystemCursor::Move => SystemCursor::Move,
+        RiftSystemCursor::NotAllowed => SystemCursor::NotAllowed,
+    };
+    Some(CursorShape::System(system))
+}
+
+pub fn pose_to_proto(pose: &VrPose, timestamp_us: u64) -> rift_core::PoseUpdate {
+    rift_core::PoseUpdate {
+        timestamp_us,
+        position_x: pose.position[0],
+        position_y: pose.position[1],
+        position_z: pose.position[2],
+        orientation_x: pose.orientation[0],
+        orientation_y: pose.orientation[1],
+        orientation_z: pose.orientation[2],
+        orientation_w: pose.orientation[3],
+    }
+}
+
+/// Hand an assembled frame to a VR adapter, tagging its eye when each eye has a stream.
+pub fn vr_video_frame(ready: &mut AssembledFrame, stereo: VrStereoMode) -> VrVideoFrame {
+    let eye = (stereo == VrStereoMode::DualStream).then(|| {
+        if ready.stream_id == rift_core::VIDEO_STREAM_RIGHT_EYE {
+            VrEye::Right
+        } else {
+            VrEye::Left
+        }
+    });
+    VrVideoFrame {
+        timestamp_us: ready.timestamp_us,
+        frame_id: ready.frame_id,
+        keyframe: ready.keyframe,
+        data: std::mem::take(&mut ready.data).into(),
+        eye,
+        view: ready.eye_view.as_ref().map(|view| VrEyeView {
+            pose: VrPose {
+                position: [view.position_x, view.position_y, view.position_z],
+                orientation: [
+                    view.orientation_x,
+                    view.orientation_y,
+                    view.orientation_z,
+                    view.orientation_w,
+                ],
+            },
+            fov: VrFov {
+                angle_left: view.angle_left,
+                angle_right: view.angle_right,
+                angle_up: view.angle_up,
+                angle_down: view.angle_down,
+            },
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn cursor_updates_keep_the_last_shape() {
+        let shaped = rift_core::CursorUpdate {
+            x: 0.5,
+            y: 2.0,
+            visible: true,
+            shape_serial: 3,
+            system_cursor: RiftSystemCursor::Hand as i32,
+            ..Default::default()
+        };
+        let cursor = apply_cursor_update(None, &shaped).expect("shape present");
+        assert_eq!(cursor.shape, CursorShape::System(SystemCursor::Hand));
+        assert_eq!(cursor.y, 1.0);
+
+        // The shape for serial 4 was lost: move the old one until it is resent.
+        let moved = rift_core::CursorUpdate {
+            x: 0.25,
+            shape_serial: 4,
+            ..Default::default()
+        };
+        let cursor = apply_cursor_update(Some(&cursor), &moved).expect("last shape");
+        assert_eq!((cursor.x, cursor.serial), (0.25, 3));
+        assert!(!cursor.visible);
+        assert!(apply_cursor_update(None, &moved).is_none());
+
+        let truncated = rift_core::CursorUpdate {
+            width: 2,
+            height: 2,
+            bgra: vec![0; 8],
+            ..Default::default()
+        };
+        assert!(apply_cursor_update(None, &truncated).is_none());
+    }
+
     #[test]
     fn test_env_bool_true_values() {
         std::env::set_var("TEST_ENV_TRUE", "true");
@@ -223,7 +451,7 @@ mod tests {
 
     #[test]
     fn test_create_hello_base64_valid_encoding() {
-        let result = create_hello_base64("TestClient".to_string(), None);
+        let result = create_hello_base64("TestClient".to_string(), None, vec![]);
         assert!(result.is_ok(), "Should create valid Hello message");
 
         let b64 = result.unwrap();
@@ -241,6 +469,7 @@ mod tests {
         let result = create_hello_base64(
             "TestClient".to_string(),
             Some("192.168.1.1:5000".to_string()),
+            vec![],
         );
         assert!(result.is_ok());
 
@@ -252,8 +481,17 @@ mod tests {
     #[test]
     fn test_create_hello_ack_base64_accepted() {
         let session_id = [42u8; 16];
-        let result =
-            create_hello_ack_base64(true, session_id, 999, None, 1920, 1080, RiftCodec::H264);
+        let result = create_hello_ack_base64(
+            true,
+            session_id,
+            999,
+            None,
+            vec![],
+            1920,
+            1080,
+            RiftCodec::H264,
+            rift_core::FecScheme::Xor,
+        );
         assert!(result.is_ok());
 
         let b64 = result.unwrap();
@@ -269,9 +507,11 @@ mod tests {
             session_id,
             0,
             Some("10.0.0.1:5000".to_string()),
+            vec![],
             0,
             0,
             RiftCodec::H264,
+            rift_core::FecScheme::Xor,
         );
         assert!(result.is_ok());
     }
@@ -281,7 +521,7 @@ mod tests {
         let original_name = "MyClient".to_string();
         let public_addr = Some("203.0.113.1:5000".to_string());
 
-        let b64 = create_hello_base64(original_name.clone(), public_addr.clone()).unwrap();
+        let b64 = create_hello_base64(original_name.clone(), public_addr.clone(), vec![]).unwrap();
         let decoded = decode_hello_base64(&b64).unwrap();
 
         assert_eq!(decoded.client_name, original_name);
@@ -306,9 +546,11 @@ mod tests {
             session_id,
             session_alias,
             public_addr.clone(),
+            vec!["[2001:db8::1]:5000".to_string()],
             1920,
             1080,
             RiftCodec::H264,
+            rift_core::FecScheme::ReedSolomon,
         )
         .unwrap();
 
@@ -318,8 +560,10 @@ mod tests {
         assert_eq!(decoded.session_id, session_id.to_vec());
         assert_eq!(decoded.session_alias, session_alias);
         assert_eq!(decoded.public_addr, "198.51.100.1:5000");
+        assert_eq!(decoded.candidate_addrs, vec!["[2001:db8::1]:5000"]);
         assert_eq!(decoded.stream_resolution.unwrap().width, 1920);
         assert_eq!(decoded.stream_resolution.unwrap().height, 1080);
+        assert_eq!(decoded.fec_scheme(), rift_core::FecScheme::ReedSolomon);
     }
 
     #[test]
@@ -330,7 +574,7 @@ mod tests {
 
     #[test]
     fn test_hello_message_contains_expected_fields() {
-        let b64 = create_hello_base64("TestClient".to_string(), None).unwrap();
+        let b64 = create_hello_base64("TestClient".to_string(), None, vec![]).unwrap();
         let hello = decode_hello_base64(&b64).unwrap();
 
         assert_eq!(hello.client_name, "TestClient");
@@ -343,8 +587,18 @@ mod tests {
 
     #[test]
     fn test_hello_ack_message_contains_expected_fields() {
-        let b64 =
-            create_hello_ack_base64(true, [1u8; 16], 1, None, 3840, 2160, RiftCodec::Hevc).unwrap();
+        let b64 = create_hello_ack_base64(
+            true,
+            [1u8; 16],
+            1,
+            None,
+            vec![],
+            3840,
+            2160,
+            RiftCodec::Hevc,
+            rift_core::FecScheme::Xor,
+        )
+        .unwrap();
         let ack = decode_hello_ack_base64(&b64).unwrap();
 
         assert_eq!(ack.fps, 60);
@@ -353,4 +607,60 @@ mod tests {
         assert_eq!(ack.stream_resolution.unwrap().width, 3840);
         assert_eq!(ack.stream_resolution.unwrap().height, 2160);
     }
+
+    #[test]
+    fn stereo_modes_round_trip_and_unknown_is_auto() {
+        for mode in [
+            VrStereoMode::Auto,
+            VrStereoMode::Mono,
+            VrStereoMode::SideBySide,
+            VrStereoMode::DualStream,
+        ] {
+            assert_eq!(
+                stereo_mode_from_proto(stereo_mode_to_proto(mode) as i32),
+                mode
+            );
+        }
+        assert_eq!(stereo_mode_from_proto(42), VrStereoMode::Auto);
+    }
+
+    #[test]
+    fn audio_layouts_round_trip_and_unknown_is_stereo() {
+        for layout in AudioChannelLayout::ALL {
+            assert_eq!(
+                audio_layout_from_proto(audio_layout_to_proto(layout) as i32),
+                layout
+            );
+        }
+        assert_eq!(audio_layout_from_proto(42), AudioChannelLayout::Stereo);
+    }
+
+    #[test]
+    fn dual_stream_frames_are_tagged_by_eye() {
+        let mut ready = AssembledFrame {
+            stream_id: rift_core::VIDEO_STREAM_RIGHT_EYE,
+            frame_id: 3,
+            timestamp_us: 1_000,
+            keyframe: true,
+            data: vec![1, 2, 3],
+            capture_duration_us: 0,
+            encode_duration_us: 0,
+            pacing_us: 0,
+            eye_view: Some(rift_core::EyeView {
+                orientation_w: 1.0,
+                angle_up: 0.9,
+                ..Default::default()
+            }),
+        };
+        let frame = vr_video_frame(&mut ready, VrStereoMode::DualStream);
+        assert_eq!(frame.eye, Some(VrEye::Right));
+        assert_eq!(frame.data.as_ref(), &[1, 2, 3]);
+        assert_eq!(frame.view.map(|v| v.fov.angle_up), Some(0.9));
+
+        ready.stream_id = rift_core::VIDEO_STREAM_PRIMARY;
+        assert_eq!(
+            vr_video_frame(&mut ready, VrStereoMode::SideBySide).eye,
+            None
+        );
+    }
 }
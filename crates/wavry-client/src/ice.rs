@@ -0,0 +1,125 @@
+//! Candidate gathering and connectivity checks on a session socket.
+//!
+//! [`gather_candidates`] runs before signaling, on the socket the session
+//! will use, so the advertised ports are the ones the NAT mapped for it. The
+//! controlling side then calls [`run_checks`] with the peer's candidates; the
+//! controlled side calls [`punch`] so its NAT lets those checks in. Pairing,
+//! pacing and nomination live in [`rift_core::ice`].
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use rift_core::ice::{self, CandidateKind, Checklist, IceCandidate};
+use tokio::net::UdpSocket;
+use tokio::time;
+use tracing::{debug, info};
+
+use crate::helpers::discover_public_addr;
+use crate::net;
+
+/// Longest the checks may hold up the handshake; unanswered pairs are still
+/// raced afterwards.
+pub const CHECK_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Interface addresses a socket bound to `port` on every interface is
+/// reachable at.
+pub fn host_candidates(port: u16) -> Vec<IceCandidate> {
+    let v6 = net::global_ipv6_addr().map(|ip| SocketAddr::new(ip.into(), port));
+    let v4 = net::lan_ipv4_addr().map(|ip| SocketAddr::new(ip.into(), port));
+    v6.into_iter()
+        .chain(v4)
+        .map(|addr| ice::candidate(CandidateKind::Host, addr))
+        .collect()
+}
+
+/// Host candidates plus the address STUN sees `socket` at. Call before
+/// anything else reads from `socket`, since the STUN answer arrives on it.
+pub async fn gather_candidates(socket: &UdpSocket) -> Vec<IceCandidate> {
+    let port = socket.local_addr().map(|addr| addr.port()).unwrap_or(0);
+    let mut candidates = host_candidates(port);
+    match discover_public_addr(socket).await {
+        Ok(addr) => {
+            let addr = net::canonical(addr);
+            if !candidates.iter().any(|c| c.addr == addr.to_string()) {
+                candidates.push(ice::candidate(CandidateKind::Srflx, addr));
+            }
+        }
+        Err(e) => debug!("no server-reflexive candidate: {}", e),
+    }
+    debug!("gathered candidates: {:?}", candidates);
+    candidates
+}
+
+/// Checks the pairs of `local` and `remote` from `socket`, answering the
+/// peer's checks meanwhile, until the checklist completes or
+/// [`CHECK_TIMEOUT`] passes.
+pub async fn run_checks(
+    socket: &UdpSocket,
+    local: &[IceCandidate],
+    remote: &[IceCandidate],
+) -> Checklist {
+    let start = Instant::now();
+    let deadline = start + CHECK_TIMEOUT;
+    let mut checklist = Checklist::new(local, remote, start);
+    let mut buf = [0u8; 1500];
+    loop {
+        let now = Instant::now();
+        while let Some((dest, request)) = checklist.poll_transmit(now) {
+            if let Err(e) = net::send_to(socket, &request, dest).await {
+                debug!("connectivity check to {} failed: {}", dest, e);
+            }
+        }
+        if checklist.is_complete(now) || now >= deadline {
+            break;
+        }
+
+        let wake = checklist
+            .next_timeout()
+            .map_or(deadline, |at| at.min(deadline))
+            .max(now + Duration::from_millis(1));
+        let Ok(received) =
+            time::timeout_at(time::Instant::from_std(wake), socket.recv_from(&mut buf)).await
+        else {
+            continue;
+        };
+        let (len, from) = match received {
+            Ok(received) => received,
+            Err(e) => {
+                // ICMP unreachable surfaces here on some platforms.
+                debug!("recv during connectivity checks: {}", e);
+                continue;
+            }
+        };
+        let datagram = &buf[..len];
+        if let Some(response) = ice::check_response(datagram, from) {
+            net::send_to(socket, &response, net::canonical(from))
+                .await
+                .ok();
+        } else {
+            checklist.handle_response(from, datagram, Instant::now());
+        }
+    }
+
+    match checklist.nominated() {
+        Some(pair) => info!(
+            "connectivity checks nominated {} ({:?}, rtt {:?})",
+            pair.remote, pair.remote_kind, pair.rtt
+        ),
+        None => info!(
+            "none of {} candidate pairs answered connectivity checks",
+            checklist.pairs().len()
+        ),
+    }
+    checklist
+}
+
+/// Sends one check to each of the peer's direct candidates, opening this
+/// side's NAT for the checks the peer is about to send.
+pub async fn punch(socket: &UdpSocket, remote: &[IceCandidate]) {
+    let request = ice::check_request();
+    for (addr, _) in ice::direct_candidates(remote) {
+        if let Err(e) = net::send_to(socket, &request, addr).await {
+            debug!("punch towards {} failed: {}", addr, e);
+        }
+    }
+}
@@ -1,7 +1,12 @@
 use crate::helpers::now_us;
 use anyhow::Result;
 use gilrs::{Event, EventType as GilrsEventType, Gilrs};
-use rift_core::InputMessage as ProtoInputMessage;
+use rift_core::input::{MAX_INPUT_BATCH_EVENTS, MAX_POINTER_DELTA};
+use rift_core::input_message::Event as InputEvent;
+use rift_core::{InputBatch, InputMessage as ProtoInputMessage};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tokio::sync::mpsc;
@@ -10,26 +15,96 @@ use tracing::warn;
 #[cfg(target_os = "linux")]
 use evdev::{Device, EventType, Key, RelativeAxisType};
 
-pub fn normalize_gamepad_deadzone(deadzone: f32) -> f32 {
-    deadzone.clamp(0.0, 0.95)
+pub use rift_core::input::{apply_gamepad_deadzone, normalize_gamepad_deadzone};
+
+/// Input send ticks per second when the host takes batches.
+pub const DEFAULT_INPUT_RATE_HZ: u32 = 250;
+/// Encoded size a batch stays under, so it fits one datagram with the
+/// packet header and AEAD overhead.
+const MAX_INPUT_BATCH_BYTES: usize = 900;
+
+/// Gathers input between send ticks. Consecutive relative moves are summed
+/// and consecutive absolute moves keep the latest position, so a 1 kHz mouse
+/// costs one message per tick instead of one packet per report.
+#[derive(Debug, Default)]
+pub struct InputSender {
+    pending: VecDeque<ProtoInputMessage>,
 }
 
-pub fn apply_gamepad_deadzone(value: f32, deadzone: f32) -> f32 {
-    let deadzone = normalize_gamepad_deadzone(deadzone);
-    let abs = value.abs();
-    if abs <= deadzone {
-        0.0
-    } else {
-        let scaled = (abs - deadzone) / (1.0 - deadzone);
-        scaled.copysign(value).clamp(-1.0, 1.0)
+impl InputSender {
+    pub fn push(&mut self, msg: ProtoInputMessage) {
+        let Some(event) = msg.event.as_ref() else {
+            return;
+        };
+        if let Some(last) = self.pending.back_mut() {
+            match (last.event.as_mut(), event) {
+                (Some(InputEvent::MouseRelative(sum)), InputEvent::MouseRelative(motion)) => {
+                    let dx = sum.dx.saturating_add(motion.dx);
+                    let dy = sum.dy.saturating_add(motion.dy);
+                    // Past the host's per-event clamp the sum would be cut
+                    // short, so start a new event instead.
+                    if dx.abs() <= MAX_POINTER_DELTA && dy.abs() <= MAX_POINTER_DELTA {
+                        (sum.dx, sum.dy) = (dx, dy);
+                        return;
+                    }
+                }
+                (Some(InputEvent::MouseMove(_)), InputEvent::MouseMove(_)) => {
+                    *last = msg;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.pending.push_back(msg);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// The next message to send: a lone event as it is, or as many pending
+    /// events as fit in one batch. Call until it returns `None`.
+    pub fn flush(&mut self) -> Option<ProtoInputMessage> {
+        let first = self.pending.pop_front()?;
+        if self.pending.is_empty() {
+            return Some(first);
+        }
+        let timestamp_us = first.timestamp_us;
+        let mut size = batch_entry_len(&first);
+        let mut events = vec![first];
+        while let Some(next) = self.pending.front() {
+            let len = batch_entry_len(next);
+            if events.len() >= MAX_INPUT_BATCH_EVENTS || size + len > MAX_INPUT_BATCH_BYTES {
+                break;
+            }
+            size += len;
+            events.extend(self.pending.pop_front());
+        }
+        if events.len() == 1 {
+            return events.pop();
+        }
+        Some(ProtoInputMessage {
+            timestamp_us,
+            event: Some(InputEvent::Batch(InputBatch { events })),
+        })
     }
 }
 
+/// Bytes `msg` adds to an `InputBatch`: field tag, length and body.
+fn batch_entry_len(msg: &ProtoInputMessage) -> usize {
+    let len = prost::Message::encoded_len(msg);
+    1 + prost::length_delimiter_len(len) + len
+}
+
+/// Starts the capture threads. Mouse motion, buttons and wheel are only
+/// forwarded as raw relative input while `relative_mouse` is set, i.e. while
+/// the pointer is locked to the stream.
 #[cfg(target_os = "linux")]
 pub fn spawn_input_threads(
     input_tx: mpsc::Sender<ProtoInputMessage>,
     gamepad_enabled: bool,
     gamepad_deadzone: f32,
+    relative_mouse: Arc<AtomicBool>,
 ) -> Result<()> {
     if gamepad_enabled {
         let tx_gamepad = input_tx.clone();
@@ -136,14 +211,69 @@ pub fn spawn_input_threads(
     }
 
     if let Some(mut mouse) = mouse {
-        let _tx = input_tx;
+        let tx = input_tx;
         thread::spawn(move || {
+            // Deltas are summed up to each SYN_REPORT so the host injects one
+            // motion per device report rather than one per axis.
+            let (mut dx, mut dy) = (0i32, 0i32);
             loop {
                 let mut had_events = false;
                 if let Ok(events) = mouse.fetch_events() {
-                    for _event in events {
+                    for event in events {
                         had_events = true;
-                        // ... simple mouse handling ...
+                        if !relative_mouse.load(Ordering::Relaxed) {
+                            (dx, dy) = (0, 0);
+                            continue;
+                        }
+                        let value = event.value();
+                        let input = match event.event_type() {
+                            EventType::RELATIVE => match RelativeAxisType(event.code()) {
+                                RelativeAxisType::REL_X => {
+                                    dx = dx.saturating_add(value);
+                                    None
+                                }
+                                RelativeAxisType::REL_Y => {
+                                    dy = dy.saturating_add(value);
+                                    None
+                                }
+                                RelativeAxisType::REL_WHEEL => Some(
+                                    rift_core::input_message::Event::Scroll(rift_core::Scroll {
+                                        dx: 0.0,
+                                        dy: value as f32,
+                                    }),
+                                ),
+                                RelativeAxisType::REL_HWHEEL => Some(
+                                    rift_core::input_message::Event::Scroll(rift_core::Scroll {
+                                        dx: value as f32,
+                                        dy: 0.0,
+                                    }),
+                                ),
+                                _ => None,
+                            },
+                            EventType::KEY => mouse_button(Key::new(event.code())).map(|button| {
+                                rift_core::input_message::Event::MouseButton(
+                                    rift_core::MouseButton {
+                                        button,
+                                        pressed: value != 0,
+                                    },
+                                )
+                            }),
+                            EventType::SYNCHRONIZATION if (dx, dy) != (0, 0) => {
+                                let motion = rift_core::MouseRelative { dx, dy };
+                                (dx, dy) = (0, 0);
+                                Some(rift_core::input_message::Event::MouseRelative(motion))
+                            }
+                            _ => None,
+                        };
+                        if let Some(event) = input {
+                            let input = ProtoInputMessage {
+                                event: Some(event),
+                                timestamp_us: now_us(),
+                            };
+                            if tx.blocking_send(input).is_err() {
+                                return;
+                            }
+                        }
                     }
                 }
                 if !had_events {
@@ -160,6 +290,7 @@ pub fn spawn_input_threads(
     input_tx: mpsc::Sender<ProtoInputMessage>,
     gamepad_enabled: bool,
     gamepad_deadzone: f32,
+    _relative_mouse: Arc<AtomicBool>,
 ) -> Result<()> {
     if gamepad_enabled {
         let tx_gamepad = input_tx.clone();
@@ -254,6 +385,17 @@ pub fn spawn_input_threads(
     Ok(())
 }
 
+/// Host button numbering: 1 left, 2 middle, 3 right.
+#[cfg(target_os = "linux")]
+fn mouse_button(key: Key) -> Option<u32> {
+    match key {
+        Key::BTN_LEFT => Some(1),
+        Key::BTN_MIDDLE => Some(2),
+        Key::BTN_RIGHT => Some(3),
+        _ => None,
+    }
+}
+
 #[cfg(target_os = "linux")]
 enum DeviceKind {
     Keyboard,
@@ -311,3 +453,97 @@ fn find_device(kind: DeviceKind) -> Result<Option<Device>> {
     }
     Ok(fallback)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(timestamp_us: u64, event: InputEvent) -> ProtoInputMessage {
+        ProtoInputMessage {
+            timestamp_us,
+            event: Some(event),
+        }
+    }
+
+    fn relative(dx: i32, dy: i32) -> InputEvent {
+        InputEvent::MouseRelative(rift_core::MouseRelative { dx, dy })
+    }
+
+    fn key(pressed: bool) -> InputEvent {
+        InputEvent::Key(rift_core::Key {
+            keycode: 30,
+            pressed,
+        })
+    }
+
+    #[test]
+    fn consecutive_moves_coalesce_around_other_events() {
+        let mut sender = InputSender::default();
+        sender.push(input(1, relative(3, 4)));
+        sender.push(input(2, relative(-1, 2)));
+        sender.push(input(3, key(true)));
+        sender.push(input(4, relative(5, 0)));
+        sender.push(input(5, relative(MAX_POINTER_DELTA, 0)));
+
+        let batch = sender.flush().unwrap();
+        assert!(sender.flush().is_none());
+        assert_eq!(batch.timestamp_us, 1);
+        let Some(InputEvent::Batch(batch)) = batch.event else {
+            panic!("expected a batch");
+        };
+        let events: Vec<_> = batch.events.into_iter().filter_map(|m| m.event).collect();
+        assert_eq!(
+            events,
+            vec![
+                relative(2, 6),
+                key(true),
+                relative(5, 0),
+                relative(MAX_POINTER_DELTA, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn absolute_moves_keep_the_latest_position() {
+        let mut sender = InputSender::default();
+        for i in 0..10 {
+            sender.push(input(
+                i,
+                InputEvent::MouseMove(rift_core::MouseMove {
+                    x: i as f32,
+                    y: 0.5,
+                }),
+            ));
+        }
+        assert_eq!(
+            sender.flush().unwrap().event,
+            Some(InputEvent::MouseMove(rift_core::MouseMove {
+                x: 9.0,
+                y: 0.5
+            }))
+        );
+        assert!(sender.is_empty());
+    }
+
+    #[test]
+    fn batches_stay_within_one_datagram() {
+        let mut sender = InputSender::default();
+        for _ in 0..8 {
+            sender.push(input(
+                1,
+                InputEvent::Text(rift_core::TextInput {
+                    text: "x".repeat(200),
+                }),
+            ));
+        }
+        let mut sent = 0;
+        while let Some(msg) = sender.flush() {
+            assert!(prost::Message::encoded_len(&msg) <= MAX_INPUT_BATCH_BYTES + 8);
+            sent += match msg.event {
+                Some(InputEvent::Batch(batch)) => batch.events.len(),
+                _ => 1,
+            };
+        }
+        assert_eq!(sent, 8);
+    }
+}
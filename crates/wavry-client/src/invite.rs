@@ -0,0 +1,178 @@
+//! Invite links. A host mints an invite on its gateway and shares the link;
+//! a guest without an account binds to signaling with it, which only lets it
+//! reach that host, and offers a session the way a signed-in client would.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use rift_core::ice::{CandidateKind, IceCandidate};
+use tokio::net::UdpSocket;
+use tokio::time;
+use tracing::{debug, info};
+use wavry_common::invite::peek_invite;
+
+use crate::helpers::{create_hello_base64, decode_hello_ack_base64};
+use crate::ice;
+use crate::signaling::{SignalMessage, SignalingClient};
+use crate::types::RelayInfo;
+
+/// Long enough for the host to approve a device it has not seen before.
+pub const INVITE_ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InviteLink {
+    /// Signaling endpoint of the gateway that minted the invite.
+    pub signaling_url: String,
+    pub token: String,
+    /// Username of the invited-to host, read from the token.
+    pub host: String,
+}
+
+/// Where the host answered an invite from.
+#[derive(Debug, Clone)]
+pub struct InviteRoute {
+    pub host: String,
+    pub direct_addrs: Vec<SocketAddr>,
+    pub candidates: Vec<IceCandidate>,
+    pub relay: Option<RelayInfo>,
+}
+
+/// Parse `https://<gateway>/invite/<token>`. `http`, `ws` and `wss` links
+/// work too; the gateway's signaling endpoint is `/ws` next to `/invite`.
+pub fn parse_invite_link(link: &str) -> Result<InviteLink> {
+    let link = link.trim();
+    let (scheme, rest) = link
+        .split_once("://")
+        .ok_or_else(|| anyhow!("invite link must include the gateway address"))?;
+    let scheme = match scheme.to_ascii_lowercase().as_str() {
+        "https" | "wss" => "wss",
+        "http" | "ws" => "ws",
+        other => bail!("unsupported invite link scheme: {}", other),
+    };
+    let (base, token) = rest
+        .split_once("/invite/")
+        .ok_or_else(|| anyhow!("not an invite link: {}", link))?;
+    let token = token
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    if base.is_empty() || token.is_empty() {
+        bail!("not an invite link: {}", link);
+    }
+    let claims = peek_invite(token)?;
+    Ok(InviteLink {
+        signaling_url: format!("{}://{}/ws", scheme, base.trim_end_matches('/')),
+        token: token.to_string(),
+        host: claims.host,
+    })
+}
+
+/// Binds to signaling with the invite and offers a session to its host from
+/// `socket`, which the session must then run on. Returns once the host
+/// answers.
+pub async fn redeem(
+    link: &InviteLink,
+    socket: &UdpSocket,
+    client_name: &str,
+) -> Result<InviteRoute> {
+    info!(
+        "redeeming invite to {} via {}",
+        link.host, link.signaling_url
+    );
+    let mut signaling = SignalingClient::connect_invite(&link.signaling_url, &link.token).await?;
+
+    let local_candidates = ice::gather_candidates(socket).await;
+    let public_addr = local_candidates
+        .iter()
+        .find(|candidate| candidate.kind == CandidateKind::Srflx)
+        .map(|candidate| candidate.addr.clone());
+    let hello_base64 = create_hello_base64(client_name.to_string(), public_addr, Vec::new())?;
+    signaling
+        .send(SignalMessage::OFFER_RIFT {
+            target_username: link.host.clone(),
+            hello_base64,
+            candidates: local_candidates,
+        })
+        .await?;
+
+    let route = time::timeout(INVITE_ANSWER_TIMEOUT, async {
+        let mut relay = None;
+        loop {
+            match signaling.recv().await? {
+                SignalMessage::ANSWER_RIFT {
+                    ack_base64,
+                    candidates,
+                    ..
+                } => {
+                    let ack = decode_hello_ack_base64(&ack_base64)?;
+                    if !ack.accepted {
+                        bail!("{} declined the invite", link.host);
+                    }
+                    let direct_addrs = std::iter::once(&ack.public_addr)
+                        .chain(&ack.candidate_addrs)
+                        .filter_map(|addr| addr.parse().ok())
+                        .collect();
+                    return Ok(InviteRoute {
+                        host: link.host.clone(),
+                        direct_addrs,
+                        candidates,
+                        relay,
+                    });
+                }
+                SignalMessage::RELAY_CREDENTIALS {
+                    relay_id,
+                    token,
+                    addr,
+                    session_id,
+                } => match addr.parse() {
+                    Ok(addr) => {
+                        relay = Some(RelayInfo {
+                            relay_id,
+                            addr,
+                            token,
+                            session_id,
+                        })
+                    }
+                    Err(_) => debug!("ignoring relay credentials with address {}", addr),
+                },
+                SignalMessage::ERROR { message, .. } => bail!("Invite rejected: {}", message),
+                other => debug!("ignoring {:?} while waiting for {}", other, link.host),
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("{} did not answer the invite", link.host))??;
+
+    if route.direct_addrs.is_empty() && route.candidates.is_empty() && route.relay.is_none() {
+        bail!("{} answered without any address to reach it at", link.host);
+    }
+    Ok(route)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wavry_common::invite::mint_invite;
+
+    #[test]
+    fn invite_links_map_to_the_gateway_signaling_endpoint() {
+        let token = mint_invite(b"secret", "alice", Duration::from_secs(60), 1_700_000_000);
+
+        let link =
+            parse_invite_link(&format!("https://gw.example/invite/{token}?via=chat")).unwrap();
+        assert_eq!(link.signaling_url, "wss://gw.example/ws");
+        assert_eq!(link.token, token);
+        assert_eq!(link.host, "alice");
+
+        let link =
+            parse_invite_link(&format!("http://127.0.0.1:3000/api/invite/{token}/")).unwrap();
+        assert_eq!(link.signaling_url, "ws://127.0.0.1:3000/api/ws");
+        assert_eq!(link.token, token);
+
+        assert!(parse_invite_link(&token).is_err());
+        assert!(parse_invite_link("https://gw.example/invite/").is_err());
+        assert!(parse_invite_link("ftp://gw.example/invite/abc.def").is_err());
+    }
+}
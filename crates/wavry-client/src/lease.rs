@@ -0,0 +1,406 @@
+//! Keeping a relay lease alive for the length of a session.
+//!
+//! The relay only forwards for a lease it has acknowledged, and each
+//! `LeaseAck` says when that lease runs out. [`RelayLeaseManager`] renews
+//! with a `LeaseRenew` once a third of the remaining time is left, and when
+//! renewals go unanswered it fetches a replacement lease over signaling in
+//! advance. If the relay then refuses to renew, or the lease runs out, the
+//! session moves to the replacement.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use rift_core::relay::LeaseRejectReason;
+use tokio::time;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::net;
+use crate::signaling::{SignalMessage, SignalingClient};
+use crate::types::RelayInfo;
+
+/// How often an unanswered present or renewal is sent again.
+pub const LEASE_RESEND_INTERVAL: Duration = Duration::from_secs(1);
+/// Renewals start at least this long before the lease runs out, so a few of
+/// them can be lost.
+pub const MIN_RENEW_MARGIN: Duration = Duration::from_secs(15);
+/// Unanswered presents or renewals before a replacement lease is fetched.
+pub const PREFETCH_AFTER_UNANSWERED: u32 = 3;
+/// Gap between requests for a replacement lease; longer than
+/// [`FRESH_LEASE_TIMEOUT`] so only one is in flight.
+pub const FRESH_LEASE_RETRY: Duration = Duration::from_secs(10);
+/// How long signaling gets to answer `REQUEST_RELAY`.
+pub const FRESH_LEASE_TIMEOUT: Duration = Duration::from_secs(8);
+/// Back-off after the relay rate limits a present or renewal.
+const RATE_LIMITED_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How the session asks signaling for a replacement lease.
+#[derive(Debug, Clone)]
+pub struct RelayLeaseSource {
+    pub signaling_url: String,
+    pub auth: SignalingAuth,
+    /// The host the relay session leads to.
+    pub target_username: String,
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SignalingAuth {
+    Token(String),
+    /// Guests bind with the invite they connected with.
+    Invite(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LeaseState {
+    /// Presented; waiting for the first ack.
+    Presenting,
+    /// Acknowledged until `expires`; renewal starts at `renew_at`.
+    Held { expires: Instant, renew_at: Instant },
+    /// Renewal sent; the lease is good until `expires` either way.
+    Renewing { expires: Instant },
+    /// Refused or run out; only a replacement helps.
+    Lost,
+    /// Refused for good (banned, or out of quota).
+    Refused,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeaseAction {
+    /// Send `LeasePresent` for [`RelayLeaseManager::relay`].
+    Present,
+    /// Send `LeaseRenew` for [`RelayLeaseManager::relay`].
+    Renew,
+    /// Ask signaling for a replacement lease and hand it to
+    /// [`RelayLeaseManager::on_fresh_lease`].
+    RequestFresh,
+    /// The lease moved to this relay session; the relayed path has to
+    /// follow it.
+    Migrate(RelayInfo),
+}
+
+#[derive(Debug)]
+pub struct RelayLeaseManager {
+    relay: RelayInfo,
+    state: LeaseState,
+    unanswered: u32,
+    next_send: Instant,
+    /// A replacement lease fetched ahead of need.
+    standby: Option<RelayInfo>,
+    next_fresh_request: Instant,
+}
+
+impl RelayLeaseManager {
+    /// Starts by presenting `relay`'s lease.
+    pub fn new(relay: RelayInfo, now: Instant) -> Self {
+        Self {
+            relay,
+            state: LeaseState::Presenting,
+            unanswered: 0,
+            next_send: now,
+            standby: None,
+            next_fresh_request: now,
+        }
+    }
+
+    pub fn relay(&self) -> &RelayInfo {
+        &self.relay
+    }
+
+    /// Whether `src` and `session_id` are the current lease's relay.
+    pub fn is_current(&self, src: SocketAddr, session_id: Uuid) -> bool {
+        session_id == self.relay.session_id && src == net::canonical(self.relay.addr)
+    }
+
+    /// When the lease runs out, once the relay has said.
+    pub fn expires(&self) -> Option<Instant> {
+        match self.state {
+            LeaseState::Held { expires, .. } | LeaseState::Renewing { expires } => Some(expires),
+            _ => None,
+        }
+    }
+
+    /// The relay acknowledged the lease until `expires_unix_ms`.
+    pub fn on_ack(&mut self, expires_unix_ms: u64, now: Instant, now_unix_ms: u64) {
+        let remaining = Duration::from_millis(expires_unix_ms.saturating_sub(now_unix_ms));
+        let margin = (remaining / 3).max(MIN_RENEW_MARGIN).min(remaining / 2);
+        self.state = LeaseState::Held {
+            expires: now + remaining,
+            renew_at: now + (remaining - margin),
+        };
+        self.unanswered = 0;
+        // A lease fetched in advance would only go stale now.
+        self.standby = None;
+    }
+
+    pub fn on_reject(&mut self, reason: LeaseRejectReason, now: Instant) {
+        match reason {
+            LeaseRejectReason::Banned | LeaseRejectReason::QuotaExceeded => {
+                self.state = LeaseState::Refused;
+                self.standby = None;
+            }
+            LeaseRejectReason::RateLimited => {
+                self.next_send = now + RATE_LIMITED_BACKOFF;
+            }
+            _ => self.state = LeaseState::Lost,
+        }
+    }
+
+    /// A replacement lease arrived from signaling.
+    pub fn on_fresh_lease(&mut self, relay: RelayInfo) {
+        if relay.session_id != self.relay.session_id && self.state != LeaseState::Refused {
+            self.standby = Some(relay);
+        }
+    }
+
+    /// The next thing to do, if anything; call until it returns `None`.
+    pub fn poll(&mut self, now: Instant) -> Option<LeaseAction> {
+        if let Some(expires) = self.expires() {
+            if now >= expires {
+                self.state = LeaseState::Lost;
+            }
+        }
+
+        // Nothing known to be good is given up for the replacement.
+        if matches!(self.state, LeaseState::Lost | LeaseState::Presenting) {
+            if let Some(relay) = self.standby.take() {
+                self.relay = relay.clone();
+                self.state = LeaseState::Presenting;
+                self.unanswered = 0;
+                self.next_send = now;
+                return Some(LeaseAction::Migrate(relay));
+            }
+        }
+
+        let wants_fresh = match self.state {
+            LeaseState::Lost => true,
+            LeaseState::Presenting | LeaseState::Renewing { .. } => {
+                self.unanswered >= PREFETCH_AFTER_UNANSWERED
+            }
+            LeaseState::Held { .. } | LeaseState::Refused => false,
+        };
+        if wants_fresh && self.standby.is_none() && now >= self.next_fresh_request {
+            self.next_fresh_request = now + FRESH_LEASE_RETRY;
+            return Some(LeaseAction::RequestFresh);
+        }
+
+        let action = match self.state {
+            LeaseState::Presenting if now >= self.next_send => LeaseAction::Present,
+            LeaseState::Held { expires, renew_at } if now >= renew_at => {
+                self.state = LeaseState::Renewing { expires };
+                LeaseAction::Renew
+            }
+            LeaseState::Renewing { .. } if now >= self.next_send => LeaseAction::Renew,
+            _ => return None,
+        };
+        self.unanswered += 1;
+        self.next_send = now + LEASE_RESEND_INTERVAL;
+        Some(action)
+    }
+}
+
+/// Ask signaling for a new lease to `source.target_username`, on a
+/// connection of its own.
+pub async fn fetch_relay_lease(source: &RelayLeaseSource) -> Result<RelayInfo> {
+    let mut signaling = match &source.auth {
+        SignalingAuth::Token(token) => {
+            SignalingClient::connect(&source.signaling_url, token).await?
+        }
+        SignalingAuth::Invite(invite) => {
+            SignalingClient::connect_invite(&source.signaling_url, invite).await?
+        }
+    };
+    signaling
+        .send(SignalMessage::REQUEST_RELAY {
+            target_username: source.target_username.clone(),
+            region: source.region.clone(),
+        })
+        .await?;
+
+    time::timeout(FRESH_LEASE_TIMEOUT, async {
+        loop {
+            match signaling.recv().await? {
+                SignalMessage::RELAY_CREDENTIALS {
+                    relay_id,
+                    token,
+                    addr,
+                    session_id,
+                } => {
+                    let addr = addr
+                        .parse()
+                        .map_err(|_| anyhow!("relay credentials with invalid address {}", addr))?;
+                    return Ok(RelayInfo {
+                        relay_id,
+                        addr,
+                        token,
+                        session_id,
+                    });
+                }
+                SignalMessage::ERROR { message, .. } => bail!("relay request refused: {}", message),
+                other => debug!("ignoring {:?} while waiting for relay credentials", other),
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("timed out waiting for relay credentials"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIX_MS: u64 = 1_700_000_000_000;
+
+    fn relay(n: u128) -> RelayInfo {
+        RelayInfo {
+            relay_id: format!("relay-{n}"),
+            addr: format!("198.51.100.{n}:4000").parse().unwrap(),
+            token: format!("lease-{n}"),
+            session_id: Uuid::from_u128(n),
+        }
+    }
+
+    fn drain(lease: &mut RelayLeaseManager, now: Instant) -> Vec<LeaseAction> {
+        std::iter::from_fn(|| lease.poll(now)).collect()
+    }
+
+    #[test]
+    fn renewal_starts_with_a_third_of_the_lease_left() {
+        let start = Instant::now();
+        let mut lease = RelayLeaseManager::new(relay(1), start);
+        assert_eq!(drain(&mut lease, start), vec![LeaseAction::Present]);
+
+        lease.on_ack(UNIX_MS + 300_000, start, UNIX_MS);
+        assert_eq!(lease.expires(), Some(start + Duration::from_secs(300)));
+        assert!(drain(&mut lease, start + Duration::from_secs(199)).is_empty());
+
+        let renew_at = start + Duration::from_secs(200);
+        assert_eq!(drain(&mut lease, renew_at), vec![LeaseAction::Renew]);
+        assert!(drain(&mut lease, renew_at + LEASE_RESEND_INTERVAL / 2).is_empty());
+        assert_eq!(
+            drain(&mut lease, renew_at + LEASE_RESEND_INTERVAL),
+            vec![LeaseAction::Renew]
+        );
+
+        // Short leases still renew before they run out.
+        lease.on_ack(UNIX_MS + 20_000, renew_at, UNIX_MS);
+        assert!(drain(&mut lease, renew_at + Duration::from_secs(9)).is_empty());
+        assert_eq!(
+            drain(&mut lease, renew_at + Duration::from_secs(10)),
+            vec![LeaseAction::Renew]
+        );
+    }
+
+    #[test]
+    fn unanswered_renewals_prefetch_and_a_refusal_migrates() {
+        let start = Instant::now();
+        let mut lease = RelayLeaseManager::new(relay(1), start);
+        drain(&mut lease, start);
+        lease.on_ack(UNIX_MS + 60_000, start, UNIX_MS);
+
+        let mut now = start + Duration::from_secs(40);
+        for _ in 1..PREFETCH_AFTER_UNANSWERED {
+            assert_eq!(drain(&mut lease, now), vec![LeaseAction::Renew]);
+            now += LEASE_RESEND_INTERVAL;
+        }
+        assert_eq!(
+            drain(&mut lease, now),
+            vec![LeaseAction::Renew, LeaseAction::RequestFresh]
+        );
+
+        // The old lease is still good, so the new one waits.
+        lease.on_fresh_lease(relay(2));
+        now += LEASE_RESEND_INTERVAL;
+        assert_eq!(drain(&mut lease, now), vec![LeaseAction::Renew]);
+        assert_eq!(lease.relay().session_id, Uuid::from_u128(1));
+
+        lease.on_reject(LeaseRejectReason::Expired, now);
+        assert_eq!(
+            drain(&mut lease, now),
+            vec![LeaseAction::Migrate(relay(2)), LeaseAction::Present]
+        );
+        assert!(lease.is_current(relay(2).addr, Uuid::from_u128(2)));
+        assert!(!lease.is_current(relay(1).addr, Uuid::from_u128(1)));
+    }
+
+    #[test]
+    fn a_lapsed_lease_requests_a_replacement_until_one_arrives() {
+        let start = Instant::now();
+        let mut lease = RelayLeaseManager::new(relay(1), start);
+        drain(&mut lease, start);
+        lease.on_ack(UNIX_MS + 30_000, start, UNIX_MS);
+
+        let lapsed = start + Duration::from_secs(30);
+        assert_eq!(drain(&mut lease, lapsed), vec![LeaseAction::RequestFresh]);
+        assert!(drain(&mut lease, lapsed + FRESH_LEASE_RETRY / 2).is_empty());
+        assert_eq!(
+            drain(&mut lease, lapsed + FRESH_LEASE_RETRY),
+            vec![LeaseAction::RequestFresh]
+        );
+
+        lease.on_fresh_lease(relay(2));
+        assert_eq!(
+            drain(&mut lease, lapsed + FRESH_LEASE_RETRY),
+            vec![LeaseAction::Migrate(relay(2)), LeaseAction::Present]
+        );
+        lease.on_ack(UNIX_MS + 30_000, lapsed + FRESH_LEASE_RETRY, UNIX_MS);
+        assert!(drain(&mut lease, lapsed + FRESH_LEASE_RETRY).is_empty());
+    }
+
+    #[tokio::test]
+    async fn replacement_leases_come_from_request_relay() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let gateway = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let mut seen = Vec::new();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                seen.push(text.to_string());
+                if text.contains("REQUEST_RELAY") {
+                    let reply = format!(
+                        r#"{{"type":"RELAY_CREDENTIALS","relay_id":"relay-2","token":"lease-2","addr":"198.51.100.2:4000","session_id":"{}"}}"#,
+                        Uuid::from_u128(2)
+                    );
+                    ws.send(Message::Text(reply.into())).await.unwrap();
+                    return seen;
+                }
+            }
+            seen
+        });
+
+        let source = RelayLeaseSource {
+            signaling_url: url,
+            auth: SignalingAuth::Invite("invite".to_string()),
+            target_username: "desk".to_string(),
+            region: Some("eu".to_string()),
+        };
+        assert_eq!(fetch_relay_lease(&source).await.unwrap(), relay(2));
+        let seen = gateway.await.unwrap();
+        assert!(seen[0].contains("BIND_INVITE"), "{:?}", seen);
+        assert!(seen[1].contains(r#""target_username":"desk""#));
+    }
+
+    #[test]
+    fn banned_or_out_of_quota_stops_asking() {
+        let start = Instant::now();
+        let mut lease = RelayLeaseManager::new(relay(1), start);
+        drain(&mut lease, start);
+        lease.on_reject(LeaseRejectReason::QuotaExceeded, start);
+        lease.on_fresh_lease(relay(2));
+        assert!(drain(&mut lease, start + Duration::from_secs(60)).is_empty());
+
+        let mut limited = RelayLeaseManager::new(relay(1), start);
+        drain(&mut limited, start);
+        limited.on_reject(LeaseRejectReason::RateLimited, start);
+        assert!(drain(&mut limited, start + LEASE_RESEND_INTERVAL).is_empty());
+        assert_eq!(
+            drain(&mut limited, start + RATE_LIMITED_BACKOFF),
+            vec![LeaseAction::Present]
+        );
+    }
+}
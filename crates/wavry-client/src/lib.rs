@@ -1,18 +1,33 @@
+pub mod av_sync;
 pub mod client;
+pub mod discovery;
 pub mod helpers;
+pub mod ice;
 pub mod input;
+pub mod invite;
+pub mod lease;
 pub mod media;
+pub mod mic;
+pub mod nack;
+pub mod net;
+pub mod path;
 pub mod signaling;
+pub mod telemetry;
 pub mod types;
+pub mod wake;
 
 pub use client::{run_client, run_client_with_shutdown};
+pub use discovery::{discover_hosts, DiscoveredHost};
 pub use helpers::{
     create_hello_ack_base64, create_hello_base64, decode_hello_ack_base64, decode_hello_base64,
-    discover_public_addr, env_bool, local_platform, now_us,
+    discover_public_addr, env_bool, local_platform, now_us, random_file_id,
 };
+pub use media::JitterBufferConfig;
+pub use telemetry::{LatencySummary, LatencyTelemetry, StageLatency};
 pub use types::{
-    ClientConfig, ClientRuntimeStats, CryptoState, FileTransferAction, FileTransferCommand,
-    RelayInfo, RendererFactory,
+    ClientConfig, ClientRuntimeStats, ClipboardSyncControl, ClipboardSyncDirection, CryptoState,
+    FileSendRequest, FileTransferAction, FileTransferCommand, FileTransferDirection,
+    FileTransferEvent, LatencyBreakdown, MonitorSelection, RelayInfo, RendererFactory,
 };
 
 pub fn pcvr_status() -> String {
@@ -1,18 +1,23 @@
 use crate::helpers::now_us;
-use rift_core::{FecPacket, VideoChunk};
-use std::collections::{BTreeSet, HashMap, VecDeque};
+use rift_core::{EyeView, FecPacket, FecReassembler, VideoChunk};
+use std::collections::{HashMap, VecDeque};
 use tracing::debug;
 
 pub const FRAME_TIMEOUT_US: u64 = 50_000;
 pub const MAX_FEC_CACHE: usize = 256;
-pub const JITTER_GROW_THRESHOLD_US: f64 = 2_000.0;
-pub const JITTER_SHRINK_THRESHOLD_US: f64 = 500.0;
 pub const JITTER_MAX_BUFFER_US: u64 = 10_000;
-pub const NACK_WINDOW_SIZE: u64 = 128;
+/// Playout delay per microsecond of measured jitter. Jitter is a mean
+/// deviation, so a few multiples of it cover nearly all late arrivals.
+pub const JITTER_TARGET_MULTIPLIER: f64 = 3.0;
+/// How far the adaptive delay shrinks per update once jitter eases.
+const JITTER_SHRINK_STEP_US: u64 = 500;
+/// Window in which the fastest frame transit sets the playout schedule.
+const PLAYOUT_BASE_WINDOW_US: u64 = 2_000_000;
 
 pub struct FrameAssembler {
     timeout_us: u64,
-    frames: HashMap<u64, FrameBuffer>,
+    /// Keyed by `(stream_id, frame_id)`; per-eye streams reuse frame ids.
+    frames: HashMap<(u32, u64), FrameBuffer>,
 }
 
 pub struct FrameBuffer {
@@ -24,15 +29,20 @@ pub struct FrameBuffer {
     pub chunks: Vec<Option<Vec<u8>>>,
     pub capture_duration_us: u32,
     pub encode_duration_us: u32,
+    pub eye_view: Option<EyeView>,
 }
 
 pub struct AssembledFrame {
+    pub stream_id: u32,
     pub frame_id: u64,
     pub timestamp_us: u64,
     pub keyframe: bool,
     pub data: Vec<u8>,
     pub capture_duration_us: u32,
     pub encode_duration_us: u32,
+    /// Time spent in the jitter buffer, filled in when the frame is released.
+    pub pacing_us: u32,
+    pub eye_view: Option<EyeView>,
 }
 
 impl FrameAssembler {
@@ -43,23 +53,26 @@ impl FrameAssembler {
         }
     }
 
-    pub fn push(&mut self, chunk: VideoChunk) -> Option<AssembledFrame> {
+    pub fn push(&mut self, mut chunk: VideoChunk) -> Option<AssembledFrame> {
         let now = now_us();
         self.frames
             .retain(|_, frame| now.saturating_sub(frame.first_seen_us) < self.timeout_us);
 
-        let entry = self
-            .frames
-            .entry(chunk.frame_id)
-            .or_insert_with(|| FrameBuffer {
-                first_seen_us: now,
-                timestamp_us: chunk.timestamp_us,
-                keyframe: chunk.keyframe,
-                chunk_count: chunk.chunk_count,
-                chunks: vec![None; chunk.chunk_count as usize],
-                capture_duration_us: chunk.capture_us,
-                encode_duration_us: chunk.encode_us,
-            });
+        let key = (chunk.stream_id, chunk.frame_id);
+        let entry = self.frames.entry(key).or_insert_with(|| FrameBuffer {
+            first_seen_us: now,
+            timestamp_us: chunk.timestamp_us,
+            keyframe: chunk.keyframe,
+            chunk_count: chunk.chunk_count,
+            chunks: vec![None; chunk.chunk_count as usize],
+            capture_duration_us: chunk.capture_us,
+            encode_duration_us: chunk.encode_us,
+            eye_view: None,
+        });
+
+        if chunk.eye_view.is_some() {
+            entry.eye_view = chunk.eye_view.take();
+        }
 
         if chunk.chunk_index < entry.chunk_count {
             entry.chunks[chunk.chunk_index as usize] = Some(chunk.payload);
@@ -77,22 +90,29 @@ impl FrameAssembler {
             let frame_id = chunk.frame_id;
             let capture_duration_us = entry.capture_duration_us;
             let encode_duration_us = entry.encode_duration_us;
-            self.frames.remove(&chunk.frame_id);
+            let eye_view = entry.eye_view.take();
+            self.frames.remove(&key);
             return Some(AssembledFrame {
+                stream_id: chunk.stream_id,
                 frame_id,
                 timestamp_us,
                 keyframe,
                 data: assembled,
                 capture_duration_us,
                 encode_duration_us,
+                pacing_us: 0,
+                eye_view,
             });
         }
         None
     }
 }
 
+/// Recently received media plaintexts, kept so FEC parity can rebuild the
+/// packets that went missing around them.
 pub struct FecCache {
     packets: HashMap<u64, Vec<u8>>,
+    reassembler: FecReassembler,
 }
 
 impl Default for FecCache {
@@ -105,6 +125,7 @@ impl FecCache {
     pub fn new() -> Self {
         Self {
             packets: HashMap::new(),
+            reassembler: FecReassembler::new(),
         }
     }
 
@@ -117,49 +138,17 @@ impl FecCache {
         self.packets.insert(packet_id, data);
     }
 
-    pub fn try_recover(&self, fec: &FecPacket) -> Option<Vec<u8>> {
-        let mut missing_id = None;
-        let mut missing_index: Option<usize> = None;
-        let mut recovered_payload = fec.payload.clone();
-        let mut present_count = 0;
-
-        for offset in 0..(fec.shard_count - 1) {
-            let pid = fec.first_packet_id + offset as u64;
-            if let Some(p) = self.packets.get(&pid) {
-                // XOR in the present packets
-                for (i, b) in p.iter().enumerate() {
-                    if i < recovered_payload.len() {
-                        recovered_payload[i] ^= b;
-                    }
-                }
-                present_count += 1;
-            } else {
-                if missing_id.is_some() {
-                    // More than one missing, can't recover
-                    return None;
-                }
-                missing_id = Some(pid);
-                missing_index = Some(offset as usize);
-            }
-        }
-
-        if present_count == (fec.shard_count - 2) {
-            // Exactly one missing, we've XORed everything else into the parity.
-            if let Some(id) = missing_id {
-                debug!("FEC: Recovered packet {}", id);
-            }
-            // Trim trailing XOR padding: the parity was computed over max_payload_len
-            // bytes, so shorter shards get zero-padded. Without trimming, the extra
-            // zeros corrupt the AEAD authentication tag and decryption fails.
-            if let Some(idx) = missing_index {
-                if let Some(&actual_len) = fec.shard_lengths.get(idx) {
-                    recovered_payload.truncate(actual_len as usize);
-                }
-            }
-            Some(recovered_payload)
-        } else {
-            None
+    /// Feeds a parity packet and returns the `(packet_id, plaintext)` pairs it
+    /// recovered, if any.
+    pub fn try_recover(&mut self, fec: FecPacket) -> Vec<(u64, Vec<u8>)> {
+        let packets = &self.packets;
+        let recovered = self
+            .reassembler
+            .on_parity(fec, |packet_id| packets.get(&packet_id).map(Vec::as_slice));
+        for (packet_id, _) in &recovered {
+            debug!("FEC: Recovered packet {}", packet_id);
         }
+        recovered
     }
 }
 
@@ -232,62 +221,45 @@ impl RttTracker {
     }
 }
 
-pub struct NackWindow {
-    window: u64,
-    highest: Option<u64>,
-    received: BTreeSet<u64>,
-    missing: BTreeSet<u64>,
+/// Playout delay settings for [`JitterBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterBufferConfig {
+    /// Delay held even on a clean link; the adaptive delay never drops below it.
+    pub target_delay_us: u64,
+    /// Longest a frame is held after it arrives.
+    pub max_delay_us: u64,
+    /// Grow the delay with measured arrival jitter.
+    pub adaptive: bool,
 }
 
-impl NackWindow {
-    pub fn new(window: u64) -> Self {
+impl Default for JitterBufferConfig {
+    fn default() -> Self {
         Self {
-            window,
-            highest: None,
-            received: BTreeSet::new(),
-            missing: BTreeSet::new(),
-        }
-    }
-
-    pub fn on_packet(&mut self, packet_id: u64) -> Vec<u64> {
-        let mut newly_missing = Vec::new();
-        if let Some(highest) = self.highest {
-            if packet_id > highest + 1 {
-                let gap_start = highest + 1;
-                let gap_end = packet_id - 1;
-                let min_start = packet_id.saturating_sub(self.window).max(gap_start);
-                for id in min_start..=gap_end {
-                    if !self.received.contains(&id) && !self.missing.contains(&id) {
-                        self.missing.insert(id);
-                        newly_missing.push(id);
-                    }
-                }
-                self.highest = Some(packet_id);
-            } else if packet_id > highest {
-                self.highest = Some(packet_id);
-            }
-        } else {
-            self.highest = Some(packet_id);
-        }
-
-        self.received.insert(packet_id);
-        self.missing.remove(&packet_id);
-        self.evict_old();
-        newly_missing
-    }
-
-    fn evict_old(&mut self) {
-        if let Some(highest) = self.highest {
-            let cutoff = highest.saturating_sub(self.window);
-            self.received = self.received.split_off(&cutoff);
-            self.missing = self.missing.split_off(&cutoff);
+            target_delay_us: 0,
+            max_delay_us: JITTER_MAX_BUFFER_US,
+            adaptive: true,
         }
     }
 }
 
+/// Holds assembled frames so they play out on the host's capture schedule
+/// rather than whenever the network delivered them.
+///
+/// Each frame is due at its capture timestamp plus the fastest transit seen
+/// recently plus the playout delay, so a frame held up in the network waits
+/// less and an early one waits more.
 pub struct JitterBuffer {
+    config: JitterBufferConfig,
     target_delay_us: u64,
+    /// Lower bound on `target_delay_us` while NACK retransmissions are landing,
+    /// so a frame completed by a resend can still slot in ahead of newer ones.
+    min_delay_us: u64,
     queue: VecDeque<BufferedFrame>,
+    /// Last frame id released per stream; anything older arrived too late.
+    released: HashMap<u32, u64>,
+    /// `(arrival, arrival - capture timestamp)` of recent frames.
+    transits: VecDeque<(u64, i64)>,
+    late_dropped: u64,
 }
 
 impl Default for JitterBuffer {
@@ -298,35 +270,276 @@ impl Default for JitterBuffer {
 
 pub struct BufferedFrame {
     pub arrival_us: u64,
+    /// When the frame would have arrived with the fastest recent transit;
+    /// the arrival time for frames without a capture timestamp.
+    pub playout_us: u64,
     pub frame: AssembledFrame,
 }
 
 impl JitterBuffer {
     pub fn new() -> Self {
+        Self::with_config(JitterBufferConfig::default())
+    }
+
+    pub fn with_config(config: JitterBufferConfig) -> Self {
         Self {
-            target_delay_us: 0,
+            config,
+            target_delay_us: config.target_delay_us.min(config.max_delay_us),
+            min_delay_us: 0,
             queue: VecDeque::new(),
+            released: HashMap::new(),
+            transits: VecDeque::new(),
+            late_dropped: 0,
         }
     }
 
+    /// Moves the playout delay toward a multiple of `jitter_us`: straight up
+    /// when jitter rises, and back down in small steps.
     pub fn update(&mut self, jitter_us: f64) {
-        if jitter_us > JITTER_GROW_THRESHOLD_US {
-            self.target_delay_us = (self.target_delay_us + 1_000).min(JITTER_MAX_BUFFER_US);
-        } else if jitter_us < JITTER_SHRINK_THRESHOLD_US {
-            self.target_delay_us = self.target_delay_us.saturating_sub(500);
+        let floor = self.config.target_delay_us.min(self.config.max_delay_us);
+        if !self.config.adaptive {
+            self.target_delay_us = floor;
+            return;
         }
+        let wanted = ((jitter_us.max(0.0) * JITTER_TARGET_MULTIPLIER) as u64)
+            .clamp(floor, self.config.max_delay_us);
+        self.target_delay_us = if wanted >= self.target_delay_us {
+            wanted
+        } else {
+            self.target_delay_us
+                .saturating_sub(JITTER_SHRINK_STEP_US)
+                .max(wanted)
+        };
+    }
+
+    pub fn set_min_delay_us(&mut self, delay_us: u64) {
+        self.min_delay_us = delay_us.min(self.config.max_delay_us);
+    }
+
+    /// Current playout delay.
+    pub fn delay_us(&self) -> u64 {
+        self.target_delay_us.max(self.min_delay_us)
+    }
+
+    /// Frames waiting for playout.
+    pub fn len(&self) -> usize {
+        self.queue.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Frames dropped so far for arriving too late to be shown.
+    pub fn late_dropped(&self) -> u64 {
+        self.late_dropped
+    }
+
+    /// Queues a frame in frame-id order within its stream, so a frame finished
+    /// late by a retransmission still reaches the decoder before its successors.
+    ///
+    /// Frames behind one already released are dropped, as are delta frames
+    /// that a queued keyframe has made unnecessary. A keyframe also drops the
+    /// overdue frames ahead of it so the picture jumps straight to it.
     pub fn push(&mut self, frame: AssembledFrame, arrival_us: u64) {
-        self.queue.push_back(BufferedFrame { arrival_us, frame });
+        let stream_id = frame.stream_id;
+        let superseded = !frame.keyframe
+            && self.queue.iter().any(|queued| {
+                queued.frame.stream_id == stream_id
+                    && queued.frame.keyframe
+                    && queued.frame.frame_id > frame.frame_id
+            });
+        if superseded
+            || self
+                .released
+                .get(&stream_id)
+                .is_some_and(|&last| frame.frame_id <= last)
+        {
+            debug!(
+                "dropping late frame {} on stream {}",
+                frame.frame_id, stream_id
+            );
+            self.late_dropped += 1;
+            return;
+        }
+
+        let playout_us = self.playout_time(&frame, arrival_us);
+        if frame.keyframe {
+            let delay_us = self.delay_us();
+            let before = self.queue.len();
+            self.queue.retain(|queued| {
+                queued.frame.stream_id != stream_id
+                    || queued.frame.frame_id > frame.frame_id
+                    || queued.playout_us + delay_us > arrival_us
+            });
+            let dropped = before - self.queue.len();
+            if dropped > 0 {
+                debug!(
+                    "keyframe {} on stream {} skips {} overdue frames",
+                    frame.frame_id, stream_id, dropped
+                );
+                self.late_dropped += dropped as u64;
+            }
+        }
+
+        let position = self
+            .queue
+            .iter()
+            .position(|queued| {
+                queued.frame.stream_id == stream_id && queued.frame.frame_id > frame.frame_id
+            })
+            .unwrap_or(self.queue.len());
+        self.queue.insert(
+            position,
+            BufferedFrame {
+                arrival_us,
+                playout_us,
+                frame,
+            },
+        );
     }
 
     pub fn pop_ready(&mut self, now_us: u64) -> Option<AssembledFrame> {
-        if let Some(front) = self.queue.front() {
-            if now_us.saturating_sub(front.arrival_us) >= self.target_delay_us {
-                return self.queue.pop_front().map(|f| f.frame);
-            }
+        let delay_us = self.delay_us();
+        let front = self.queue.front()?;
+        let due_us = (front.playout_us + delay_us)
+            .min(front.arrival_us + self.config.max_delay_us.max(delay_us));
+        if now_us < due_us {
+            return None;
         }
-        None
+        let held_us = now_us.saturating_sub(front.arrival_us);
+        self.queue.pop_front().map(|mut f| {
+            f.frame.pacing_us = held_us.min(u32::MAX as u64) as u32;
+            self.released.insert(f.frame.stream_id, f.frame.frame_id);
+            f.frame
+        })
+    }
+
+    /// Arrival time `frame` would have had with the fastest transit in the
+    /// recent window. Host and client clocks differ, so only the difference
+    /// between transits is meaningful.
+    fn playout_time(&mut self, frame: &AssembledFrame, arrival_us: u64) -> u64 {
+        if frame.timestamp_us == 0 {
+            return arrival_us;
+        }
+        let transit = arrival_us as i64 - frame.timestamp_us as i64;
+        while self
+            .transits
+            .front()
+            .is_some_and(|&(at, _)| arrival_us.saturating_sub(at) > PLAYOUT_BASE_WINDOW_US)
+        {
+            self.transits.pop_front();
+        }
+        // Only the minimum matters, so entries slower than the newest go.
+        while self.transits.back().is_some_and(|&(_, t)| t >= transit) {
+            self.transits.pop_back();
+        }
+        self.transits.push_back((arrival_us, transit));
+        let base = self.transits.front().map_or(transit, |&(_, t)| t);
+        arrival_us.saturating_sub((transit - base) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_id: u64) -> AssembledFrame {
+        AssembledFrame {
+            stream_id: 0,
+            frame_id,
+            timestamp_us: 0,
+            keyframe: false,
+            data: Vec::new(),
+            capture_duration_us: 0,
+            encode_duration_us: 0,
+            pacing_us: 0,
+            eye_view: None,
+        }
+    }
+
+    #[test]
+    fn jitter_buffer_reorders_late_frames_and_drops_stale_ones() {
+        let mut buffer = JitterBuffer::new();
+        buffer.set_min_delay_us(5_000);
+        buffer.push(frame(2), 0);
+        buffer.push(frame(1), 1_000);
+
+        assert!(buffer.pop_ready(4_000).is_none());
+        assert_eq!(buffer.pop_ready(6_000).map(|f| f.frame_id), Some(1));
+        assert_eq!(buffer.pop_ready(6_000).map(|f| f.frame_id), Some(2));
+
+        buffer.push(frame(1), 7_000);
+        assert!(buffer.pop_ready(20_000).is_none());
+    }
+
+    fn fixed_delay(delay_us: u64) -> JitterBuffer {
+        JitterBuffer::with_config(JitterBufferConfig {
+            target_delay_us: delay_us,
+            adaptive: false,
+            ..JitterBufferConfig::default()
+        })
+    }
+
+    #[test]
+    fn jitter_buffer_plays_out_on_the_capture_schedule() {
+        let mut buffer = fixed_delay(5_000);
+        buffer.push(
+            AssembledFrame {
+                timestamp_us: 1_000,
+                ..frame(1)
+            },
+            11_000,
+        );
+        // Captured 1ms later but held up 4ms longer in the network.
+        buffer.push(
+            AssembledFrame {
+                timestamp_us: 2_000,
+                ..frame(2)
+            },
+            16_000,
+        );
+
+        assert_eq!(buffer.pop_ready(16_000).map(|f| f.frame_id), Some(1));
+        assert!(buffer.pop_ready(16_500).is_none());
+        let late = buffer.pop_ready(17_000).expect("due 1ms after arrival");
+        assert_eq!((late.frame_id, late.pacing_us), (2, 1_000));
+    }
+
+    #[test]
+    fn jitter_buffer_skips_frames_a_keyframe_made_stale() {
+        let mut buffer = fixed_delay(5_000);
+        buffer.push(frame(5), 0);
+        buffer.push(
+            AssembledFrame {
+                keyframe: true,
+                ..frame(6)
+            },
+            8_000,
+        );
+        buffer.push(frame(4), 8_500);
+
+        assert_eq!(buffer.late_dropped(), 2);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.pop_ready(13_000).map(|f| f.frame_id), Some(6));
+    }
+
+    #[test]
+    fn jitter_buffer_delay_tracks_jitter_within_bounds() {
+        let mut buffer = JitterBuffer::with_config(JitterBufferConfig {
+            target_delay_us: 1_000,
+            max_delay_us: 8_000,
+            adaptive: true,
+        });
+        buffer.update(2_000.0);
+        assert_eq!(buffer.delay_us(), 6_000);
+        buffer.update(10_000.0);
+        assert_eq!(buffer.delay_us(), 8_000);
+        buffer.update(0.0);
+        assert_eq!(buffer.delay_us(), 7_500);
+        for _ in 0..20 {
+            buffer.update(0.0);
+        }
+        assert_eq!(buffer.delay_us(), 1_000);
     }
 }
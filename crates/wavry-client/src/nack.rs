@@ -0,0 +1,219 @@
+//! Loss detection and NACK scheduling.
+//!
+//! Gaps in the host's packet-id sequence are NACKed after a short reordering
+//! grace period, then re-requested with RTT-based exponential backoff until
+//! the packet arrives, FEC rebuilds it, or the retry budget runs out. The host
+//! answers NACKs by resending the original datagram, so a retransmission
+//! follows the normal receive path into frame reassembly.
+
+use std::collections::BTreeMap;
+
+/// Packet ids behind the newest one that are still worth requesting.
+pub const NACK_WINDOW_SIZE: u64 = 128;
+/// Ids per NACK message; the host ignores anything past this.
+pub const MAX_NACK_IDS: usize = 16;
+/// Wait this long after a gap appears before treating it as loss, not reordering.
+const NACK_REORDER_DELAY_US: u64 = 3_000;
+/// Floor for the retry timeout when RTT is unknown or tiny.
+const NACK_MIN_RETRY_US: u64 = 10_000;
+const NACK_MAX_RETRY_US: u64 = 500_000;
+const NACK_MAX_ATTEMPTS: u32 = 3;
+/// Minimum spacing between NACK messages so bursts don't flood the uplink.
+const NACK_MIN_INTERVAL_US: u64 = 5_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NackStats {
+    /// Ids included in a NACK, counting each retry.
+    pub requested: u64,
+    /// Gaps later filled by a retransmission or late arrival.
+    pub recovered: u64,
+    /// Gaps given up on after the retry budget or window ran out.
+    pub abandoned: u64,
+}
+
+#[derive(Debug)]
+struct MissingPacket {
+    next_nack_us: u64,
+    attempts: u32,
+}
+
+#[derive(Debug)]
+pub struct NackTracker {
+    window: u64,
+    highest: Option<u64>,
+    missing: BTreeMap<u64, MissingPacket>,
+    last_sent_us: Option<u64>,
+    stats: NackStats,
+}
+
+impl NackTracker {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            highest: None,
+            missing: BTreeMap::new(),
+            last_sent_us: None,
+            stats: NackStats::default(),
+        }
+    }
+
+    /// Records an arriving packet. Returns true if it fills a known gap.
+    pub fn on_packet(&mut self, packet_id: u64, now_us: u64) -> bool {
+        let Some(highest) = self.highest else {
+            self.highest = Some(packet_id);
+            return false;
+        };
+
+        if packet_id > highest {
+            let gap_start = (highest + 1).max(packet_id.saturating_sub(self.window));
+            for id in gap_start..packet_id {
+                self.missing.entry(id).or_insert(MissingPacket {
+                    next_nack_us: now_us + NACK_REORDER_DELAY_US,
+                    attempts: 0,
+                });
+            }
+            self.highest = Some(packet_id);
+            self.evict_old();
+            return false;
+        }
+
+        if self.missing.remove(&packet_id).is_some() {
+            self.stats.recovered += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Marks a packet rebuilt from FEC parity so it is no longer requested.
+    pub fn on_recovered(&mut self, packet_id: u64) {
+        self.missing.remove(&packet_id);
+    }
+
+    /// Ids due for a NACK now, oldest first. `rtt_us` scales the retry backoff.
+    pub fn poll(&mut self, now_us: u64, rtt_us: u64) -> Vec<u64> {
+        if self
+            .last_sent_us
+            .is_some_and(|last| now_us.saturating_sub(last) < NACK_MIN_INTERVAL_US)
+        {
+            return Vec::new();
+        }
+
+        let base_retry_us = (rtt_us + rtt_us / 2).clamp(NACK_MIN_RETRY_US, NACK_MAX_RETRY_US);
+        let mut due = Vec::new();
+        let mut exhausted = Vec::new();
+        for (&id, packet) in self.missing.iter_mut() {
+            if packet.next_nack_us > now_us {
+                continue;
+            }
+            if packet.attempts >= NACK_MAX_ATTEMPTS {
+                exhausted.push(id);
+                continue;
+            }
+            if due.len() == MAX_NACK_IDS {
+                continue;
+            }
+            packet.attempts += 1;
+            packet.next_nack_us = now_us + (base_retry_us << (packet.attempts - 1));
+            due.push(id);
+        }
+
+        for id in exhausted {
+            self.missing.remove(&id);
+            self.stats.abandoned += 1;
+        }
+        if !due.is_empty() {
+            self.last_sent_us = Some(now_us);
+            self.stats.requested += due.len() as u64;
+        }
+        due
+    }
+
+    pub fn stats(&self) -> NackStats {
+        self.stats
+    }
+
+    fn evict_old(&mut self) {
+        let Some(highest) = self.highest else {
+            return;
+        };
+        let cutoff = highest.saturating_sub(self.window);
+        let kept = self.missing.split_off(&cutoff);
+        self.stats.abandoned += self.missing.len() as u64;
+        self.missing = kept;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gap_is_nacked_after_reorder_delay() {
+        let mut tracker = NackTracker::new(NACK_WINDOW_SIZE);
+        tracker.on_packet(1, 0);
+        tracker.on_packet(4, 0);
+
+        assert!(tracker.poll(1_000, 20_000).is_empty());
+        assert_eq!(tracker.poll(NACK_REORDER_DELAY_US, 20_000), vec![2, 3]);
+    }
+
+    #[test]
+    fn reordered_packet_is_never_nacked() {
+        let mut tracker = NackTracker::new(NACK_WINDOW_SIZE);
+        tracker.on_packet(1, 0);
+        tracker.on_packet(3, 0);
+        assert!(tracker.on_packet(2, 500));
+        assert!(tracker.poll(NACK_REORDER_DELAY_US, 20_000).is_empty());
+        assert_eq!(tracker.stats().recovered, 1);
+    }
+
+    #[test]
+    fn retries_back_off_then_give_up() {
+        let mut tracker = NackTracker::new(NACK_WINDOW_SIZE);
+        tracker.on_packet(1, 0);
+        tracker.on_packet(3, 0);
+
+        let rtt = 20_000;
+        let retry = rtt + rtt / 2;
+        let mut now = NACK_REORDER_DELAY_US;
+        assert_eq!(tracker.poll(now, rtt), vec![2]);
+        assert!(tracker.poll(now + retry - 1, rtt).is_empty());
+        now += retry;
+        assert_eq!(tracker.poll(now, rtt), vec![2]);
+        now += retry * 2;
+        assert_eq!(tracker.poll(now, rtt), vec![2]);
+        now += retry * 4;
+        assert!(tracker.poll(now, rtt).is_empty());
+
+        let stats = tracker.stats();
+        assert_eq!((stats.requested, stats.abandoned), (3, 1));
+    }
+
+    #[test]
+    fn nacks_are_paced_and_capped() {
+        let mut tracker = NackTracker::new(NACK_WINDOW_SIZE);
+        tracker.on_packet(1, 0);
+        tracker.on_packet(40, 0);
+
+        let now = NACK_REORDER_DELAY_US;
+        let first = tracker.poll(now, 0);
+        assert_eq!(first.len(), MAX_NACK_IDS);
+        assert_eq!(first[0], 2);
+        assert!(tracker.poll(now + 1, 0).is_empty());
+        assert_eq!(tracker.poll(now + NACK_MIN_INTERVAL_US, 0)[0], 18);
+    }
+
+    #[test]
+    fn fec_recovery_and_window_stop_requests() {
+        let mut tracker = NackTracker::new(8);
+        tracker.on_packet(1, 0);
+        tracker.on_packet(4, 0);
+        tracker.on_recovered(2);
+        tracker.on_packet(20, 0);
+
+        // 3 fell out of the window; only the newest gap remains.
+        let due = tracker.poll(NACK_REORDER_DELAY_US, 0);
+        assert_eq!(due, (12..20).collect::<Vec<_>>());
+        assert_eq!(tracker.stats().abandoned, 1);
+    }
+}
@@ -0,0 +1,253 @@
+//! Dual-stack UDP sockets and host address handling.
+//!
+//! Sockets bind `[::]` with `IPV6_V6ONLY` off, so one socket reaches IPv4 and
+//! IPv6 peers alike, and fall back to `0.0.0.0` on hosts without IPv6. On a
+//! dual-stack socket IPv4 peers show up as IPv4-mapped addresses
+//! (`::ffff:a.b.c.d`): [`canonical`] turns those back into plain IPv4 so they
+//! compare equal to configured addresses, and [`send_to`] maps the other way
+//! for platforms that refuse IPv4 destinations on an IPv6 socket.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use anyhow::{anyhow, Result};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+/// Public resolver used only to ask the OS which IPv6 source address it
+/// would pick; nothing is sent to it.
+const IPV6_ROUTE_PROBE: SocketAddr = SocketAddr::V6(SocketAddrV6::new(
+    Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888),
+    53,
+    0,
+    0,
+));
+/// The IPv4 counterpart of [`IPV6_ROUTE_PROBE`].
+const IPV4_ROUTE_PROBE: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(8, 8, 8, 8), 53));
+
+/// Any address on `port`, for both IPv4 and IPv6.
+pub fn dual_stack_any(port: u16) -> SocketAddr {
+    SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port)
+}
+
+/// Binds a UDP socket to `addr`. `[::]` also accepts IPv4 and becomes
+/// `0.0.0.0` when the host has no IPv6.
+pub fn bind_udp(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = match bind_exact(addr) {
+        Err(err)
+            if addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                && !matches!(
+                    err.kind(),
+                    io::ErrorKind::AddrInUse | io::ErrorKind::PermissionDenied
+                ) =>
+        {
+            debug!("IPv6 unavailable ({}); binding IPv4 only", err);
+            bind_exact(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), addr.port()))?
+        }
+        result => result?,
+    };
+    UdpSocket::from_std(socket.into())
+}
+
+fn bind_exact(addr: SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
+}
+
+/// Marks outgoing packets with `tos` (DSCP and ECN bits) for either family.
+pub fn set_tos(socket: &UdpSocket, tos: u32) -> io::Result<()> {
+    let sock = SockRef::from(socket);
+    if socket.local_addr()?.is_ipv4() {
+        return sock.set_tos_v4(tos);
+    }
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    sock.set_tclass_v6(tos)?;
+    // Covers IPv4 peers of a dual-stack socket where the platform allows it.
+    if let Err(err) = sock.set_tos_v4(tos) {
+        debug!("no IPv4 TOS on dual-stack socket: {}", err);
+    }
+    Ok(())
+}
+
+/// `addr` with IPv4-mapped IPv6 turned back into IPv4.
+pub fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+/// Sends to `dest`, written as IPv4-mapped IPv6 when `socket` is IPv6.
+pub async fn send_to(socket: &UdpSocket, buf: &[u8], dest: SocketAddr) -> io::Result<usize> {
+    let dest = match dest {
+        SocketAddr::V4(v4) if socket.local_addr()?.is_ipv6() => {
+            SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
+        }
+        dest => dest,
+    };
+    socket.send_to(buf, dest).await
+}
+
+/// Orders host addresses for a Happy Eyeballs race (RFC 8305): duplicates
+/// dropped, IPv6 first, then alternating between families.
+pub fn happy_eyeballs_order(addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let mut v6 = Vec::new();
+    let mut v4 = Vec::new();
+    for addr in addrs.into_iter().map(canonical) {
+        let family = if addr.is_ipv6() { &mut v6 } else { &mut v4 };
+        if !family.contains(&addr) {
+            family.push(addr);
+        }
+    }
+    let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Parses a host address as typed by a user: `192.0.2.1:5000`,
+/// `[2001:db8::1]:5000`, or a link-local `[fe80::1%3]:5000` with its
+/// numeric zone index.
+pub fn parse_host_addr(input: &str) -> Result<SocketAddr> {
+    let input = input.trim();
+    if let Ok(addr) = input.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Some((host, port)) = input
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("]:"))
+    {
+        let port: u16 = port
+            .parse()
+            .map_err(|_| anyhow!("invalid port in {}", input))?;
+        let (ip, zone) = host.split_once('%').unwrap_or((host, ""));
+        let ip: Ipv6Addr = ip
+            .parse()
+            .map_err(|_| anyhow!("invalid IPv6 address in {}", input))?;
+        let scope_id = if zone.is_empty() {
+            0
+        } else {
+            zone.parse().map_err(|_| {
+                anyhow!(
+                    "zone {} in {} must be the numeric interface index",
+                    zone,
+                    input
+                )
+            })?
+        };
+        return Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)));
+    }
+    if input.parse::<Ipv6Addr>().is_ok() {
+        return Err(anyhow!(
+            "put IPv6 addresses in brackets with a port, e.g. [{}]:5000",
+            input
+        ));
+    }
+    if input.parse::<IpAddr>().is_ok() {
+        return Err(anyhow!("missing port, e.g. {}:5000", input));
+    }
+    Err(anyhow!("invalid address {}", input))
+}
+
+/// The global IPv6 address this host would reach the internet from, if it
+/// has one. Global IPv6 addresses are not translated, so together with the
+/// listening port it is an address peers can try directly.
+pub fn global_ipv6_addr() -> Option<Ipv6Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(IPV6_ROUTE_PROBE).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V6(ip) if is_global_unicast(ip) => Some(ip),
+        _ => None,
+    }
+}
+
+/// The IPv4 address of the interface this host would reach the internet
+/// from, usually a LAN address behind NAT.
+pub fn lan_ipv4_addr() -> Option<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(IPV4_ROUTE_PROBE).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() && !ip.is_loopback() => Some(ip),
+        _ => None,
+    }
+}
+
+/// 2000::/3, the only IPv6 range allocated for global unicast.
+fn is_global_unicast(ip: Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xe000 == 0x2000
+}
+
+/// Link-local IPv6 addresses need a zone, which mDNS answers do not carry.
+pub fn is_ipv6_link_local(ip: IpAddr) -> bool {
+    matches!(ip, IpAddr::V6(v6) if v6.segments()[0] & 0xffc0 == 0xfe80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_bracketed_and_zoned_ipv6() {
+        assert_eq!(
+            parse_host_addr(" 192.0.2.1:5000 ").unwrap(),
+            addr("192.0.2.1:5000")
+        );
+        assert_eq!(
+            parse_host_addr("[2001:db8::1]:5000").unwrap(),
+            addr("[2001:db8::1]:5000")
+        );
+        let SocketAddr::V6(zoned) = parse_host_addr("[fe80::1%3]:5000").unwrap() else {
+            panic!("expected IPv6");
+        };
+        assert_eq!(zoned.scope_id(), 3);
+        assert_eq!(zoned.port(), 5000);
+
+        let err = parse_host_addr("2001:db8::1").unwrap_err().to_string();
+        assert!(err.contains("[2001:db8::1]:5000"), "{}", err);
+        assert!(parse_host_addr("192.0.2.1").is_err());
+        assert!(parse_host_addr("[fe80::1%eth0]:5000").is_err());
+        assert!(parse_host_addr("[2001:db8::1]:99999").is_err());
+    }
+
+    #[test]
+    fn mapped_addresses_compare_as_ipv4() {
+        assert_eq!(
+            canonical(addr("[::ffff:192.0.2.1]:5000")),
+            addr("192.0.2.1:5000")
+        );
+        assert_eq!(
+            canonical(addr("[2001:db8::1]:5000")),
+            addr("[2001:db8::1]:5000")
+        );
+    }
+
+    #[test]
+    fn happy_eyeballs_puts_ipv6_first_and_interleaves() {
+        let ordered = happy_eyeballs_order([
+            addr("192.0.2.1:5000"),
+            addr("192.0.2.2:5000"),
+            addr("[::ffff:192.0.2.1]:5000"),
+            addr("[2001:db8::1]:5000"),
+        ]);
+        assert_eq!(
+            ordered,
+            vec![
+                addr("[2001:db8::1]:5000"),
+                addr("192.0.2.1:5000"),
+                addr("192.0.2.2:5000"),
+            ]
+        );
+    }
+}
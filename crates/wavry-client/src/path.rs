@@ -0,0 +1,296 @@
+//! Choosing between a direct and a relayed path to the host.
+//!
+//! Candidates are tried ICE-lite style: the crypto handshake doubles as the
+//! connectivity check, sent down every candidate with the direct paths given
+//! a short head start, and whichever path answers first carries the session.
+//! A host may have several direct addresses, typically IPv6 and IPv4; they
+//! race Happy Eyeballs style, IPv6 first and each next one a little later. When the active path goes silent mid-session, resume attempts
+//! move to the next candidate after a few unanswered tries; the host re-binds
+//! to whichever address the ticket proof arrives from. When the host sent
+//! candidates over signaling, connectivity checks rank them first and the
+//! race follows that order instead.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::net;
+use crate::types::RelayInfo;
+
+/// Relayed handshakes start this long after the last direct one, so a
+/// working direct path wins even when the relay answers slightly faster.
+pub const DIRECT_HEAD_START: Duration = Duration::from_millis(150);
+/// Gap between direct candidates (RFC 8305's connection attempt delay, kept
+/// short because the handshake is a single datagram round trip).
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(50);
+/// Unanswered resume attempts on one path before moving to the next.
+pub const FAILOVER_AFTER_RESUMES: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct PathCandidate {
+    /// Where datagrams are sent, and where replies arrive from.
+    pub addr: SocketAddr,
+    pub relay: Option<RelayInfo>,
+    /// When the handshake goes out on this path, from the start of each
+    /// attempt.
+    pub delay: Duration,
+}
+
+impl PathCandidate {
+    pub fn direct(addr: SocketAddr) -> Self {
+        Self {
+            addr: net::canonical(addr),
+            relay: None,
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn relayed(relay: RelayInfo) -> Self {
+        Self {
+            addr: net::canonical(relay.addr),
+            relay: Some(relay),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn is_relayed(&self) -> bool {
+        self.relay.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub struct PathSet {
+    /// Direct first, then relayed; ordered by `delay`.
+    candidates: Vec<PathCandidate>,
+    active: usize,
+    unanswered_resumes: u32,
+}
+
+impl PathSet {
+    /// `None` when there is nothing to connect to.
+    pub fn new(direct: &[SocketAddr], relay: Option<RelayInfo>) -> Option<Self> {
+        Self::ranked(&net::happy_eyeballs_order(direct.iter().copied()), relay)
+    }
+
+    /// Like [`Self::new`], but the direct addresses keep the given order,
+    /// such as connectivity check results; repeats are dropped.
+    pub fn ranked(direct: &[SocketAddr], relay: Option<RelayInfo>) -> Option<Self> {
+        let mut ordered: Vec<SocketAddr> = Vec::with_capacity(direct.len());
+        for addr in direct.iter().copied().map(net::canonical) {
+            if !ordered.contains(&addr) {
+                ordered.push(addr);
+            }
+        }
+        let mut candidates: Vec<_> = ordered
+            .into_iter()
+            .zip(0u32..)
+            .map(|(addr, index)| PathCandidate {
+                delay: ATTEMPT_DELAY * index,
+                ..PathCandidate::direct(addr)
+            })
+            .collect();
+        let relay_delay = candidates
+            .last()
+            .map_or(Duration::ZERO, |last| last.delay + DIRECT_HEAD_START);
+        candidates.extend(relay.map(|relay| PathCandidate {
+            delay: relay_delay,
+            ..PathCandidate::relayed(relay)
+        }));
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Self {
+            candidates,
+            active: 0,
+            unanswered_resumes: 0,
+        })
+    }
+
+    pub fn candidates(&self) -> &[PathCandidate] {
+        &self.candidates
+    }
+
+    pub fn active(&self) -> &PathCandidate {
+        &self.candidates[self.active]
+    }
+
+    /// Makes the candidate that `src` belongs to active. Returns false for
+    /// packets from anywhere else.
+    pub fn select_by_source(&mut self, src: SocketAddr) -> bool {
+        match self.candidates.iter().position(|path| path.addr == src) {
+            Some(index) => {
+                self.active = index;
+                self.unanswered_resumes = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Traffic arrived on the active path.
+    pub fn on_traffic(&mut self) {
+        self.unanswered_resumes = 0;
+    }
+
+    /// Records a resume attempt about to be sent. Returns the new active
+    /// path when the current one has had its share of tries and there is
+    /// another candidate to move to.
+    pub fn on_resume(&mut self) -> Option<&PathCandidate> {
+        self.unanswered_resumes += 1;
+        if self.unanswered_resumes <= FAILOVER_AFTER_RESUMES || self.candidates.len() < 2 {
+            return None;
+        }
+        self.active = (self.active + 1) % self.candidates.len();
+        self.unanswered_resumes = 1;
+        Some(self.active())
+    }
+
+    /// Points the relayed candidate at `relay`, such as when the lease moved
+    /// to a new relay session; a session without one gains it as the last
+    /// candidate.
+    pub fn replace_relay(&mut self, relay: RelayInfo) {
+        match self.candidates.iter_mut().find(|path| path.is_relayed()) {
+            Some(path) => {
+                *path = PathCandidate {
+                    delay: path.delay,
+                    ..PathCandidate::relayed(relay)
+                }
+            }
+            None => {
+                let delay = self
+                    .candidates
+                    .last()
+                    .map_or(Duration::ZERO, |last| last.delay + DIRECT_HEAD_START);
+                self.candidates.push(PathCandidate {
+                    delay,
+                    ..PathCandidate::relayed(relay)
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn relay() -> RelayInfo {
+        RelayInfo {
+            relay_id: "relay-1".to_string(),
+            addr: "198.51.100.7:4000".parse().unwrap(),
+            token: "token".to_string(),
+            session_id: Uuid::nil(),
+        }
+    }
+
+    #[test]
+    fn direct_is_preferred_and_any_answering_candidate_is_selected() {
+        let relay = relay();
+        let direct: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        assert!(PathSet::new(&[], None).is_none());
+
+        let mut paths = PathSet::new(&[direct], Some(relay.clone())).unwrap();
+        assert_eq!(paths.candidates().len(), 2);
+        assert!(!paths.active().is_relayed());
+
+        assert!(!paths.select_by_source("203.0.113.9:1".parse().unwrap()));
+        assert!(paths.select_by_source(relay.addr));
+        assert!(paths.active().is_relayed());
+    }
+
+    #[test]
+    fn unanswered_resumes_fail_over_and_rotate_back() {
+        let relay = relay();
+        let direct: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let mut paths = PathSet::new(&[direct], Some(relay.clone())).unwrap();
+
+        for _ in 0..FAILOVER_AFTER_RESUMES {
+            assert!(paths.on_resume().is_none());
+        }
+        let moved = paths.on_resume().unwrap();
+        assert!(moved.is_relayed());
+
+        // Traffic on the new path resets the count.
+        paths.on_traffic();
+        for _ in 0..FAILOVER_AFTER_RESUMES {
+            assert!(paths.on_resume().is_none());
+        }
+        assert_eq!(paths.on_resume().unwrap().addr, direct);
+
+        let mut single = PathSet::new(&[direct], None).unwrap();
+        for _ in 0..10 {
+            assert!(single.on_resume().is_none());
+        }
+    }
+
+    #[test]
+    fn direct_addresses_race_ipv6_first_and_relay_waits_for_the_last() {
+        let relay = relay();
+        let v4: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:5000".parse().unwrap();
+        let paths = PathSet::new(&[v4, v6], Some(relay.clone())).unwrap();
+        let order: Vec<_> = paths
+            .candidates()
+            .iter()
+            .map(|path| (path.addr, path.delay))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (v6, Duration::ZERO),
+                (v4, ATTEMPT_DELAY),
+                (relay.addr, ATTEMPT_DELAY + DIRECT_HEAD_START),
+            ]
+        );
+
+        // Replies from IPv4 peers arrive mapped on dual-stack sockets.
+        let mut paths = PathSet::new(&[v4, v6], None).unwrap();
+        assert!(paths.select_by_source(net::canonical("[::ffff:192.0.2.1]:5000".parse().unwrap())));
+        assert_eq!(paths.active().addr, v4);
+
+        let relayed_only = PathSet::new(&[], Some(relay.clone())).unwrap();
+        assert_eq!(relayed_only.active().delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn replaced_relay_keeps_its_place_in_the_race() {
+        let relay = relay();
+        let direct: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let mut paths = PathSet::new(&[direct], Some(relay.clone())).unwrap();
+        assert!(paths.select_by_source(relay.addr));
+
+        let moved = RelayInfo {
+            addr: "198.51.100.8:4000".parse().unwrap(),
+            session_id: Uuid::from_u128(2),
+            ..relay
+        };
+        paths.replace_relay(moved.clone());
+        assert_eq!(paths.candidates().len(), 2);
+        assert_eq!(paths.active().addr, moved.addr);
+        assert_eq!(
+            paths.active().relay.as_ref().unwrap().session_id,
+            moved.session_id
+        );
+        assert_eq!(paths.active().delay, DIRECT_HEAD_START);
+
+        let mut direct_only = PathSet::new(&[direct], None).unwrap();
+        direct_only.replace_relay(moved);
+        assert_eq!(direct_only.candidates()[1].delay, DIRECT_HEAD_START);
+        assert!(!direct_only.active().is_relayed());
+    }
+
+    #[test]
+    fn ranked_addresses_keep_their_order() {
+        let relay = relay();
+        let checked: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:5000".parse().unwrap();
+        let paths = PathSet::ranked(
+            &[checked, v6, "[::ffff:203.0.113.7]:40000".parse().unwrap()],
+            Some(relay.clone()),
+        )
+        .unwrap();
+        let order: Vec<_> = paths.candidates().iter().map(|path| path.addr).collect();
+        assert_eq!(order, vec![checked, v6, relay.addr]);
+        assert_eq!(paths.candidates()[1].delay, ATTEMPT_DELAY);
+    }
+}
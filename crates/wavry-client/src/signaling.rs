@@ -1,16 +1,48 @@
+//! WebSocket connection to the signaling gateway.
+//!
+//! [`SignalingClient`] keeps the connection up for as long as it is held: a
+//! background task pings the gateway, reconnects with exponential backoff
+//! and jitter when the socket drops or goes quiet, binds again with the same
+//! token or invite, and sends messages queued while it was down. Callers only
+//! see the gap as a delay.
+
 use anyhow::{anyhow, Result};
 use futures::{SinkExt, StreamExt};
+use rand::Rng as _;
 use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{self, Instant};
+use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
 
 pub use wavry_common::protocol::SignalMessage;
 
 const SIGNALING_TLS_PINS_ENV: &str = "WAVRY_SIGNALING_TLS_PINS_SHA256";
 
+/// How often the gateway is pinged.
+pub const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// Silence, pongs included, after which the connection counts as dead.
+pub const DEAD_AFTER: Duration = Duration::from_secs(40);
+/// Longest a connection attempt, TLS and bind included, may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_BASE: Duration = Duration::from_millis(500);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+/// A connection that lasted this long resets the backoff.
+const STABLE_CONNECTION: Duration = Duration::from_secs(30);
+/// Messages kept while disconnected; the oldest are dropped beyond this.
+pub const MAX_QUEUED_MESSAGES: usize = 256;
+const INBOUND_CAPACITY: usize = 64;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
 pub struct SignalingClient {
-    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    outbound: mpsc::UnboundedSender<SignalMessage>,
+    inbound: mpsc::Receiver<SignalMessage>,
+    connected: watch::Receiver<bool>,
 }
 
 fn env_bool(name: &str, default: bool) -> bool {
@@ -107,7 +139,7 @@ fn fingerprint_sha256(bytes: &[u8]) -> String {
 
 fn validate_peer_certificate_pin(
     url: &str,
-    ws: &WebSocketStream<MaybeTlsStream<TcpStream>>,
+    ws: &WsStream,
     tls_pin_set: &HashSet<String>,
 ) -> Result<()> {
     if !is_secure_signaling_url(url) {
@@ -156,53 +188,247 @@ fn validate_peer_certificate_pin(
 }
 
 impl SignalingClient {
+    /// Connects and binds with `token`. Only this first attempt can fail;
+    /// afterwards the connection is re-established in the background.
     pub async fn connect(url: &str, token: &str) -> Result<Self> {
+        Self::connect_with(
+            url,
+            SignalMessage::BIND {
+                token: token.to_string(),
+            },
+        )
+        .await
+    }
+
+    /// Connects as a guest of the host `invite` names. The gateway only
+    /// routes this connection's messages to that host.
+    pub async fn connect_invite(url: &str, invite: &str) -> Result<Self> {
+        Self::connect_with(
+            url,
+            SignalMessage::BIND_INVITE {
+                invite: invite.to_string(),
+            },
+        )
+        .await
+    }
+
+    async fn connect_with(url: &str, bind: SignalMessage) -> Result<Self> {
         let tls_pin_set = configured_tls_pin_set()?;
         validate_signaling_url(url, tls_pin_set.as_ref())?;
-        let (mut ws_stream, _) = connect_async(url).await?;
-        if let Some(tls_pin_set) = tls_pin_set.as_ref() {
-            validate_peer_certificate_pin(url, &ws_stream, tls_pin_set)?;
-        }
-
-        // Auth
-        let bind_msg = SignalMessage::BIND {
-            token: token.to_string(),
+        let connection = Connection {
+            url: url.to_string(),
+            bind,
+            tls_pin_set,
         };
-        ws_stream
-            .send(tokio_tungstenite::tungstenite::Message::Text(
-                serde_json::to_string(&bind_msg)?.into(),
-            ))
-            .await?;
-
-        // Expect OK? Gateway might send something back or just be silent until error.
-        // Assuming silent success for now based on gateway impl.
+        let ws = connection.open().await?;
 
-        Ok(Self { ws: ws_stream })
+        let (outbound, outbound_rx) = mpsc::unbounded_channel();
+        let (inbound_tx, inbound) = mpsc::channel(INBOUND_CAPACITY);
+        let (connected_tx, connected) = watch::channel(true);
+        tokio::spawn(connection.run(ws, outbound_rx, inbound_tx, connected_tx));
+        Ok(Self {
+            outbound,
+            inbound,
+            connected,
+        })
     }
 
+    /// Queues `msg`; it goes out now, or once the connection is back.
     pub async fn send(&mut self, msg: SignalMessage) -> Result<()> {
-        let text = serde_json::to_string(&msg)?;
-        self.ws
-            .send(tokio_tungstenite::tungstenite::Message::Text(text.into()))
-            .await?;
-        Ok(())
+        self.outbound
+            .send(msg)
+            .map_err(|_| anyhow!("Signaling connection closed"))
     }
 
+    /// The next message from the gateway. Waits across reconnects; messages
+    /// that fail to parse are skipped.
     pub async fn recv(&mut self) -> Result<SignalMessage> {
-        while let Some(msg) = self.ws.next().await {
-            let msg = msg?;
-            if let tokio_tungstenite::tungstenite::Message::Text(text) = msg {
-                let signal: SignalMessage = serde_json::from_str(&text)?;
-                return Ok(signal);
+        self.inbound
+            .recv()
+            .await
+            .ok_or_else(|| anyhow!("Signaling connection closed"))
+    }
+
+    /// Whether the gateway connection is currently up.
+    pub fn connected(&self) -> watch::Receiver<bool> {
+        self.connected.clone()
+    }
+}
+
+struct Connection {
+    url: String,
+    /// `BIND` or `BIND_INVITE`, sent first on every connection.
+    bind: SignalMessage,
+    tls_pin_set: Option<HashSet<String>>,
+}
+
+impl Connection {
+    /// Opens the socket and binds.
+    async fn open(&self) -> Result<WsStream> {
+        time::timeout(CONNECT_TIMEOUT, async {
+            let (mut ws, _) = connect_async(self.url.as_str()).await?;
+            if let Some(tls_pin_set) = self.tls_pin_set.as_ref() {
+                validate_peer_certificate_pin(&self.url, &ws, tls_pin_set)?;
+            }
+            ws.send(Message::Text(serde_json::to_string(&self.bind)?.into()))
+                .await?;
+            Ok(ws)
+        })
+        .await
+        .map_err(|_| anyhow!("timed out connecting to signaling"))?
+    }
+
+    async fn run(
+        self,
+        ws: WsStream,
+        mut outbound: mpsc::UnboundedReceiver<SignalMessage>,
+        inbound: mpsc::Sender<SignalMessage>,
+        connected: watch::Sender<bool>,
+    ) {
+        let mut queue = VecDeque::new();
+        let mut backoff = Backoff::default();
+        let mut ws = Some(ws);
+        loop {
+            let stream = match ws.take() {
+                Some(stream) => stream,
+                None => {
+                    connected.send_replace(false);
+                    let delay = backoff.next_delay();
+                    info!("reconnecting to signaling in {:?}", delay);
+                    let sleep = time::sleep(delay);
+                    tokio::pin!(sleep);
+                    loop {
+                        tokio::select! {
+                            _ = &mut sleep => break,
+                            msg = outbound.recv() => match msg {
+                                Some(msg) => enqueue(&mut queue, msg),
+                                None => return,
+                            },
+                        }
+                    }
+                    match self.open().await {
+                        Ok(stream) => {
+                            info!("signaling reconnected; {} queued messages", queue.len());
+                            stream
+                        }
+                        Err(e) => {
+                            warn!("signaling reconnect failed: {:#}", e);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            connected.send_replace(true);
+            let connected_at = Instant::now();
+            match serve(stream, &mut queue, &mut outbound, &inbound).await {
+                Ok(()) => return,
+                Err(e) => warn!("signaling connection lost: {:#}", e),
+            }
+            if connected_at.elapsed() >= STABLE_CONNECTION {
+                backoff.reset();
+            }
+        }
+    }
+}
+
+/// Relays messages both ways until the connection fails, or returns `Ok`
+/// once the [`SignalingClient`] is dropped.
+async fn serve(
+    mut ws: WsStream,
+    queue: &mut VecDeque<SignalMessage>,
+    outbound: &mut mpsc::UnboundedReceiver<SignalMessage>,
+    inbound: &mpsc::Sender<SignalMessage>,
+) -> Result<()> {
+    flush(&mut ws, queue).await?;
+    let mut ping = time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let mut last_heard = Instant::now();
+    loop {
+        tokio::select! {
+            msg = outbound.recv() => {
+                let Some(msg) = msg else {
+                    let _ = ws.close(None).await;
+                    return Ok(());
+                };
+                enqueue(queue, msg);
+                flush(&mut ws, queue).await?;
+            }
+            frame = ws.next() => {
+                let frame = match frame {
+                    Some(frame) => frame?,
+                    None => return Err(anyhow!("closed by the gateway")),
+                };
+                last_heard = Instant::now();
+                match frame {
+                    Message::Text(text) => match serde_json::from_str(&text) {
+                        Ok(signal) => {
+                            if inbound.send(signal).await.is_err() {
+                                return Ok(());
+                            }
+                        }
+                        Err(e) => debug!("ignoring signaling message {}: {}", text, e),
+                    },
+                    Message::Close(frame) => {
+                        return Err(anyhow!("closed by the gateway: {:?}", frame));
+                    }
+                    _ => {}
+                }
+            }
+            _ = ping.tick() => {
+                if last_heard.elapsed() >= DEAD_AFTER {
+                    return Err(anyhow!("no reply from the gateway in {:?}", DEAD_AFTER));
+                }
+                ws.send(Message::Ping(Vec::new().into())).await?;
             }
         }
-        Err(anyhow!("Signaling connection closed"))
+    }
+}
+
+/// Sends queued messages in order. A message leaves the queue only once
+/// written, so a failure keeps it for the next connection.
+async fn flush(ws: &mut WsStream, queue: &mut VecDeque<SignalMessage>) -> Result<()> {
+    while let Some(msg) = queue.front() {
+        let text = serde_json::to_string(msg)?;
+        ws.send(Message::Text(text.into())).await?;
+        queue.pop_front();
+    }
+    Ok(())
+}
+
+fn enqueue(queue: &mut VecDeque<SignalMessage>, msg: SignalMessage) {
+    if queue.len() >= MAX_QUEUED_MESSAGES {
+        warn!("signaling queue full; dropping the oldest message");
+        queue.pop_front();
+    }
+    queue.push_back(msg);
+}
+
+/// Exponential reconnect delays with jitter, so clients cut off together do
+/// not all return at once.
+#[derive(Debug, Default)]
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    /// A random delay between half and all of the current step, which
+    /// doubles from [`RECONNECT_BASE`] up to [`RECONNECT_MAX`].
+    fn next_delay(&mut self) -> Duration {
+        let step = RECONNECT_BASE
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(RECONNECT_MAX);
+        self.attempt = self.attempt.saturating_add(1);
+        rand::thread_rng().gen_range(step / 2..=step)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{normalize_fingerprint, parse_tls_pin_set};
+    use super::*;
 
     #[test]
     fn test_normalize_fingerprint_accepts_colons_and_case() {
@@ -224,4 +450,92 @@ mod tests {
     fn test_parse_tls_pin_set_rejects_invalid_length() {
         assert!(parse_tls_pin_set("abcd").is_err());
     }
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_cap() {
+        let mut backoff = Backoff::default();
+        let first = backoff.next_delay();
+        assert!(first >= RECONNECT_BASE / 2 && first <= RECONNECT_BASE);
+        let second = backoff.next_delay();
+        assert!(second >= RECONNECT_BASE && second <= RECONNECT_BASE * 2);
+        for _ in 0..8 {
+            backoff.next_delay();
+        }
+        for _ in 0..20 {
+            let delay = backoff.next_delay();
+            assert!(delay >= RECONNECT_MAX / 2 && delay <= RECONNECT_MAX);
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= RECONNECT_BASE);
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest() {
+        let mut queue = VecDeque::new();
+        for i in 0..=MAX_QUEUED_MESSAGES {
+            enqueue(
+                &mut queue,
+                SignalMessage::BIND {
+                    token: i.to_string(),
+                },
+            );
+        }
+        assert_eq!(queue.len(), MAX_QUEUED_MESSAGES);
+        assert!(matches!(queue.front(), Some(SignalMessage::BIND { token }) if token == "1"));
+    }
+
+    async fn next_text(ws: &mut WebSocketStream<TcpStream>) -> String {
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => text.to_string(),
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnects_rebinds_and_flushes_the_queue() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let accept = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let gateway = tokio_tungstenite::accept_async(stream).await.unwrap();
+            (gateway, listener)
+        });
+        let mut client = SignalingClient::connect(&url, "token-1").await.unwrap();
+        let (mut gateway, listener) = accept.await.unwrap();
+        assert!(next_text(&mut gateway).await.contains("token-1"));
+
+        // The gateway goes away; what the caller sends meanwhile is queued.
+        drop(gateway);
+        let mut connected = client.connected();
+        connected.wait_for(|up| !*up).await.unwrap();
+        client
+            .send(SignalMessage::REQUEST_RELAY {
+                target_username: "host".into(),
+                region: None,
+            })
+            .await
+            .unwrap();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut gateway = tokio_tungstenite::accept_async(stream).await.unwrap();
+        assert!(next_text(&mut gateway).await.contains("token-1"));
+        assert!(next_text(&mut gateway).await.contains("REQUEST_RELAY"));
+
+        gateway
+            .send(Message::Text(r#"{"type":"Bound"}"#.into()))
+            .await
+            .unwrap();
+        gateway
+            .send(Message::Text(
+                r#"{"type":"ERROR","code":null,"message":"nope"}"#.into(),
+            ))
+            .await
+            .unwrap();
+        // Unknown messages are skipped instead of ending the stream.
+        assert!(matches!(
+            client.recv().await.unwrap(),
+            SignalMessage::ERROR { message, .. } if message == "nope"
+        ));
+    }
 }
@@ -0,0 +1,239 @@
+//! Per-stage latency histograms for presented frames.
+//!
+//! The host stamps capture and encode durations on each `VideoChunk`; the
+//! client adds jitter-buffer, network and decode/present times. Every stage
+//! lands in a log-bucketed histogram that is summarised and reset once per
+//! stats period, so a single slow frame shows up in `max_us` without being
+//! averaged away.
+
+use serde::Serialize;
+
+use crate::types::LatencyBreakdown;
+
+/// Buckets per power of two, which bounds the error of a reported
+/// percentile to 1/8 of its value.
+const SUB_BUCKETS: u32 = 8;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+const BUCKETS: usize = ((32 - SUB_BUCKET_BITS + 1) * SUB_BUCKETS) as usize;
+
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: [u32; BUCKETS],
+    count: u32,
+    max_us: u32,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; BUCKETS],
+            count: 0,
+            max_us: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, us: u32) {
+        self.counts[bucket_index(us)] += 1;
+        self.count = self.count.saturating_add(1);
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn max_us(&self) -> u32 {
+        self.max_us
+    }
+
+    /// Upper edge of the bucket holding the `q` quantile (0.0..=1.0),
+    /// never more than the largest sample.
+    pub fn percentile_us(&self, q: f64) -> u32 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u32).max(1);
+        let mut seen = 0u32;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_upper_us(index).min(self.max_us);
+            }
+        }
+        self.max_us
+    }
+
+    pub fn summary(&self) -> StageLatency {
+        StageLatency {
+            p50_us: self.percentile_us(0.5),
+            p95_us: self.percentile_us(0.95),
+            max_us: self.max_us,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn bucket_index(us: u32) -> usize {
+    if us < SUB_BUCKETS {
+        return us as usize;
+    }
+    let exponent = us.ilog2();
+    let mantissa = (us >> (exponent - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+    ((exponent - SUB_BUCKET_BITS + 1) * SUB_BUCKETS + mantissa) as usize
+}
+
+fn bucket_upper_us(index: usize) -> u32 {
+    let index = index as u32;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let lower = (SUB_BUCKETS + index % SUB_BUCKETS) << shift;
+    lower.saturating_add((1 << shift) - 1)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StageLatency {
+    pub p50_us: u32,
+    pub p95_us: u32,
+    pub max_us: u32,
+}
+
+impl StageLatency {
+    pub fn to_proto(&self) -> rift_core::StageLatency {
+        rift_core::StageLatency {
+            p50_us: self.p50_us,
+            p95_us: self.p95_us,
+            max_us: self.max_us,
+        }
+    }
+}
+
+/// Latency of the frames presented during one stats period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencySummary {
+    pub frames: u32,
+    pub capture: StageLatency,
+    pub encode: StageLatency,
+    pub pacing: StageLatency,
+    pub network: StageLatency,
+    pub decode: StageLatency,
+    pub render: StageLatency,
+    pub total: StageLatency,
+}
+
+impl LatencySummary {
+    pub fn to_proto(&self) -> rift_core::LatencySummary {
+        rift_core::LatencySummary {
+            frames: self.frames,
+            capture: Some(self.capture.to_proto()),
+            encode: Some(self.encode.to_proto()),
+            pacing: Some(self.pacing.to_proto()),
+            network: Some(self.network.to_proto()),
+            decode: Some(self.decode.to_proto()),
+            render: Some(self.render.to_proto()),
+            total: Some(self.total.to_proto()),
+        }
+    }
+}
+
+/// One histogram per pipeline stage plus the end-to-end total.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyTelemetry {
+    capture: LatencyHistogram,
+    encode: LatencyHistogram,
+    pacing: LatencyHistogram,
+    network: LatencyHistogram,
+    decode: LatencyHistogram,
+    render: LatencyHistogram,
+    total: LatencyHistogram,
+}
+
+impl LatencyTelemetry {
+    pub fn record(&mut self, latency: &LatencyBreakdown) {
+        self.capture.record(latency.capture_us);
+        self.encode.record(latency.encode_us);
+        self.pacing.record(latency.pacing_us);
+        self.network.record(latency.network_us);
+        self.decode.record(latency.decode_us);
+        self.render.record(latency.render_us);
+        self.total.record(latency.total_us());
+    }
+
+    /// Summary of everything recorded since the last call, or `None` if no
+    /// frame was presented.
+    pub fn take_summary(&mut self) -> Option<LatencySummary> {
+        if self.total.count() == 0 {
+            return None;
+        }
+        let summary = LatencySummary {
+            frames: self.total.count(),
+            capture: self.capture.summary(),
+            encode: self.encode.summary(),
+            pacing: self.pacing.summary(),
+            network: self.network.summary(),
+            decode: self.decode.summary(),
+            render: self.render.summary(),
+            total: self.total.summary(),
+        };
+        *self = Self::default();
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_the_u32_range_in_order() {
+        let mut last = 0;
+        for us in [0, 1, 7, 8, 9, 15, 16, 1_000, 16_667, 1 << 31, u32::MAX] {
+            let index = bucket_index(us);
+            assert!(index >= last, "{} went backwards", us);
+            assert!(index < BUCKETS);
+            assert!(bucket_upper_us(index) >= us);
+            last = index;
+        }
+        assert_eq!(bucket_upper_us(bucket_index(u32::MAX)), u32::MAX);
+    }
+
+    #[test]
+    fn percentiles_stay_within_a_bucket_of_the_sample() {
+        let mut histogram = LatencyHistogram::default();
+        for us in 1..=100 {
+            histogram.record(us * 1_000);
+        }
+        let p50 = histogram.percentile_us(0.5);
+        let p95 = histogram.percentile_us(0.95);
+        assert!((50_000..=50_000 * 9 / 8).contains(&p50), "p50 {}", p50);
+        assert!((95_000..=100_000).contains(&p95), "p95 {}", p95);
+        assert_eq!(histogram.percentile_us(1.0), 100_000);
+        assert_eq!(histogram.max_us(), 100_000);
+    }
+
+    #[test]
+    fn summary_resets_after_each_period() {
+        let mut telemetry = LatencyTelemetry::default();
+        assert_eq!(telemetry.take_summary(), None);
+
+        telemetry.record(&LatencyBreakdown {
+            capture_us: 2_000,
+            encode_us: 4_000,
+            network_us: 10_000,
+            decode_us: 3_000,
+            ..LatencyBreakdown::default()
+        });
+        let summary = telemetry.take_summary().unwrap();
+        assert_eq!(summary.frames, 1);
+        assert_eq!(summary.total.max_us, 19_000);
+        assert_eq!(summary.network.p50_us, 10_000);
+        assert_eq!(summary.render, StageLatency::default());
+        assert_eq!(telemetry.take_summary(), None);
+    }
+}
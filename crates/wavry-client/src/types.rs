@@ -1,20 +1,29 @@
 use anyhow::Result;
+use rift_core::PermissionSet;
 use rift_crypto::connection::SecureClient;
+use serde::Serialize;
 use std::fmt;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::{
-    atomic::{AtomicBool, AtomicU64},
+    atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicU8, Ordering},
     Arc, Mutex,
 };
 use uuid::Uuid;
 use wavry_media::{DecodeConfig, Renderer, Resolution as MediaResolution};
 use wavry_vr::VrAdapter;
 
+use crate::lease::RelayLeaseSource;
+use crate::media::JitterBufferConfig;
+use crate::telemetry::LatencySummary;
+
 #[derive(Clone)]
 pub struct ClientConfig {
     pub connect_addr: Option<SocketAddr>,
+    /// Other addresses of the same host, such as its IPv6 address next to an
+    /// IPv4 `connect_addr`; all direct addresses are raced, IPv6 first.
+    pub alternate_addrs: Vec<SocketAddr>,
     pub client_name: String,
     pub no_encrypt: bool,
     pub identity_key: Option<[u8; 32]>,
@@ -23,13 +32,59 @@ pub struct ClientConfig {
     pub max_resolution: Option<MediaResolution>,
     pub gamepad_enabled: bool,
     pub gamepad_deadzone: f32,
+    /// Input send ticks per second once the host takes batches; `0` sends
+    /// every event as it arrives.
+    pub input_rate_hz: u32,
     pub vr_adapter: Option<Arc<Mutex<dyn VrAdapter>>>,
     pub runtime_stats: Option<Arc<ClientRuntimeStats>>,
+    /// Local recording settings; recording starts with the session when `enabled` is set.
     pub recorder_config: Option<wavry_media::RecorderConfig>,
     pub send_files: Vec<PathBuf>,
     pub file_out_dir: PathBuf,
     pub file_max_bytes: u64,
     pub file_command_bus: Option<tokio::sync::broadcast::Sender<FileTransferCommand>>,
+    pub file_send_bus: Option<tokio::sync::broadcast::Sender<FileSendRequest>>,
+    pub file_event_bus: Option<tokio::sync::broadcast::Sender<FileTransferEvent>>,
+    /// Clipboard sync policy; `None` keeps the legacy bidirectional behaviour.
+    pub clipboard_sync: Option<Arc<ClipboardSyncControl>>,
+    /// Bandwidth caps in kbps requested mid-session; forwarded to the host as congestion targets.
+    pub bandwidth_limit_bus: Option<tokio::sync::broadcast::Sender<u32>>,
+    /// Requests for the host to start (`true`) or stop recording the session.
+    pub host_recording_bus: Option<tokio::sync::broadcast::Sender<bool>>,
+    /// Starts (`true`) or stops local recording of the received stream.
+    pub local_recording_bus: Option<tokio::sync::broadcast::Sender<bool>>,
+    /// Switches mouse capture to raw relative deltas (`true`) or back to
+    /// absolute input; the host is told so it can change injection modes.
+    pub pointer_mode_bus: Option<tokio::sync::broadcast::Sender<bool>>,
+    /// Committed text (IME or dead-key compositions) for the host to type
+    /// with its own keyboard layout.
+    pub text_input_bus: Option<tokio::sync::broadcast::Sender<String>>,
+    /// Playout delay for received video.
+    pub jitter_buffer: JitterBufferConfig,
+    /// User consent to send their microphone to the host, which plays it
+    /// into a virtual input for in-game voice chat.
+    pub microphone: bool,
+    /// Store of host identities pinned on first connection; `None` skips pinning.
+    pub known_hosts: Option<PathBuf>,
+    /// Name the host is pinned under. Defaults to the direct address, so
+    /// relayed sessions without a name are not pinned.
+    pub host_name: Option<String>,
+    /// Refuse to connect when a host's identity differs from its pin, instead
+    /// of warning.
+    pub strict_host_key: bool,
+    /// Socket to run the session on, already used to gather this side's
+    /// candidates so the addresses advertised to the host stay valid.
+    /// `None` binds a fresh one.
+    pub socket: Option<Arc<std::net::UdpSocket>>,
+    /// The host's candidates from signaling; when present they are checked
+    /// for connectivity and the answering ones raced first.
+    pub remote_candidates: Vec<rift_core::ice::IceCandidate>,
+    /// Invite link to redeem through signaling before connecting; the
+    /// answering host's addresses replace `connect_addr`.
+    pub invite: Option<String>,
+    /// Where to fetch a replacement relay lease when the relay stops
+    /// renewing `relay_info`'s; `None` lets the session end with the lease.
+    pub relay_lease_source: Option<RelayLeaseSource>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -77,7 +132,47 @@ pub struct FileTransferCommand {
     pub action: FileTransferAction,
 }
 
-#[derive(Debug, Clone)]
+/// Request to queue a local file for sending during a live session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSendRequest {
+    pub file_id: u64,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileTransferDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// File transfer lifecycle notifications for UI consumers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileTransferEvent {
+    Started {
+        file_id: u64,
+        direction: FileTransferDirection,
+        filename: String,
+        file_size: u64,
+    },
+    Progress {
+        file_id: u64,
+        direction: FileTransferDirection,
+        transferred_chunks: u32,
+        total_chunks: u32,
+    },
+    Completed {
+        file_id: u64,
+        direction: FileTransferDirection,
+        path: Option<PathBuf>,
+    },
+    Failed {
+        file_id: u64,
+        direction: FileTransferDirection,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RelayInfo {
     pub relay_id: String,
     pub addr: SocketAddr,
@@ -85,11 +180,219 @@ pub struct RelayInfo {
     pub session_id: Uuid,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardSyncDirection {
+    Disabled,
+    ToHost,
+    FromHost,
+    Bidirectional,
+}
+
+impl ClipboardSyncDirection {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::ToHost => "to_host",
+            Self::FromHost => "from_host",
+            Self::Bidirectional => "bidirectional",
+        }
+    }
+
+    pub const fn sends(self) -> bool {
+        matches!(self, Self::ToHost | Self::Bidirectional)
+    }
+
+    pub const fn receives(self) -> bool {
+        matches!(self, Self::FromHost | Self::Bidirectional)
+    }
+
+    const fn to_u8(self) -> u8 {
+        match self {
+            Self::Disabled => 0,
+            Self::ToHost => 1,
+            Self::FromHost => 2,
+            Self::Bidirectional => 3,
+        }
+    }
+
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::ToHost,
+            2 => Self::FromHost,
+            3 => Self::Bidirectional,
+            _ => Self::Disabled,
+        }
+    }
+}
+
+impl fmt::Display for ClipboardSyncDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<ClipboardSyncDirection> for rift_core::ClipboardSync {
+    fn from(direction: ClipboardSyncDirection) -> Self {
+        Self {
+            to_host: direction.sends(),
+            from_host: direction.receives(),
+        }
+    }
+}
+
+impl FromStr for ClipboardSyncDirection {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "disabled" | "off" | "none" => Ok(Self::Disabled),
+            "to_host" | "send" | "outgoing" => Ok(Self::ToHost),
+            "from_host" | "receive" | "incoming" => Ok(Self::FromHost),
+            "bidirectional" | "both" | "on" => Ok(Self::Bidirectional),
+            _ => Err("expected one of: disabled, to_host, from_host, bidirectional"),
+        }
+    }
+}
+
+/// Clipboard sync policy shared between the UI and a running session.
+#[derive(Debug)]
+pub struct ClipboardSyncControl {
+    direction: AtomicU8,
+    pub sent_updates: AtomicU64,
+    pub received_updates: AtomicU64,
+}
+
+impl ClipboardSyncControl {
+    pub fn new(direction: ClipboardSyncDirection) -> Self {
+        Self {
+            direction: AtomicU8::new(direction.to_u8()),
+            sent_updates: AtomicU64::new(0),
+            received_updates: AtomicU64::new(0),
+        }
+    }
+
+    pub fn direction(&self) -> ClipboardSyncDirection {
+        ClipboardSyncDirection::from_u8(self.direction.load(Ordering::Relaxed))
+    }
+
+    pub fn set_direction(&self, direction: ClipboardSyncDirection) {
+        self.direction.store(direction.to_u8(), Ordering::Relaxed);
+    }
+}
+
+/// Where one presented frame spent its time, end to end, in microseconds.
+///
+/// Capture and encode are measured on the host and carried in the video
+/// chunks; pacing is how long the frame sat in the client jitter buffer;
+/// network is half the last measured RTT. Decode and render are timed
+/// together around the renderer call, so `render_us` stays zero until a
+/// renderer reports them separately.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencyBreakdown {
+    pub frame_id: u64,
+    pub capture_us: u32,
+    pub encode_us: u32,
+    pub pacing_us: u32,
+    pub network_us: u32,
+    pub decode_us: u32,
+    pub render_us: u32,
+}
+
+impl LatencyBreakdown {
+    pub fn total_us(&self) -> u32 {
+        self.capture_us
+            .saturating_add(self.encode_us)
+            .saturating_add(self.pacing_us)
+            .saturating_add(self.network_us)
+            .saturating_add(self.decode_us)
+            .saturating_add(self.render_us)
+    }
+
+    pub fn to_proto(&self) -> rift_core::LatencyStats {
+        rift_core::LatencyStats {
+            frame_id: self.frame_id,
+            capture_us: self.capture_us,
+            encode_us: self.encode_us,
+            network_us: self.network_us,
+            decode_us: self.decode_us,
+            render_us: self.render_us,
+            total_us: self.total_us(),
+            pacing_us: self.pacing_us,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ClientRuntimeStats {
     pub connected: AtomicBool,
     pub frames_decoded: AtomicU64,
     pub monitors: Mutex<Vec<rift_core::MonitorInfo>>,
+    /// Breakdown for the most recently presented frame.
+    pub last_latency: Mutex<Option<LatencyBreakdown>>,
+    /// Per-stage percentiles over the last stats period.
+    pub latency_summary: Mutex<Option<LatencySummary>>,
+    /// Current playout delay of the jitter buffer.
+    pub jitter_buffer_delay_us: AtomicU64,
+    /// Frames waiting in the jitter buffer.
+    pub jitter_buffer_frames: AtomicU64,
+    /// Frames dropped for arriving too late to be shown.
+    pub late_frames_dropped: AtomicU64,
+    /// How far the last audio played behind its video; negative when ahead.
+    pub av_sync_skew_us: AtomicI64,
+    /// Audio packets dropped to catch up with video.
+    pub audio_packets_dropped: AtomicU64,
+    /// `PermissionSet` bits the host granted, as sent on the wire.
+    pub permissions: AtomicU32,
+}
+
+impl ClientRuntimeStats {
+    pub fn record_latency(&self, latency: LatencyBreakdown) {
+        if let Ok(mut last) = self.last_latency.lock() {
+            *last = Some(latency);
+        }
+    }
+
+    pub fn latency(&self) -> Option<LatencyBreakdown> {
+        self.last_latency.lock().ok().and_then(|last| *last)
+    }
+
+    pub fn record_latency_summary(&self, summary: Option<LatencySummary>) {
+        if let Ok(mut last) = self.latency_summary.lock() {
+            *last = summary;
+        }
+    }
+
+    pub fn latency_summary(&self) -> Option<LatencySummary> {
+        self.latency_summary.lock().ok().and_then(|last| *last)
+    }
+
+    /// What the host currently lets this client do, so disabled features
+    /// can be shown as such.
+    pub fn permissions(&self) -> PermissionSet {
+        PermissionSet::from_wire(self.permissions.load(Ordering::Relaxed))
+    }
+
+    pub fn set_permissions(&self, permissions: PermissionSet) {
+        self.permissions
+            .store(permissions.to_wire(), Ordering::Relaxed);
+    }
+}
+
+/// Displays to stream: `primary` replaces the current capture, and each of
+/// `additional` arrives on its own video stream for a separate surface.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MonitorSelection {
+    pub primary: u32,
+    pub additional: Vec<u32>,
+}
+
+impl From<u32> for MonitorSelection {
+    fn from(primary: u32) -> Self {
+        Self {
+            primary,
+            additional: Vec::new(),
+        }
+    }
 }
 
 pub type RendererFactory = Box<dyn Fn(DecodeConfig) -> Result<Box<dyn Renderer + Send>> + Send>;
@@ -119,6 +422,9 @@ pub enum VrOutbound {
     HandPose(rift_core::HandPoseUpdate),
     Timing(rift_core::VrTiming),
     Gamepad(rift_core::InputMessage),
+    Foveation(rift_core::FoveationUpdate),
+    Haptic(rift_core::HapticFeedback),
+    Reconfigure(rift_core::StreamReconfigure),
 }
 
 #[cfg(test)]
@@ -129,6 +435,7 @@ mod tests {
     fn test_client_config_creation() {
         let config = ClientConfig {
             connect_addr: Some("192.168.1.1:5000".parse().unwrap()),
+            alternate_addrs: Vec::new(),
             client_name: "TestClient".to_string(),
             no_encrypt: false,
             identity_key: Some([42u8; 32]),
@@ -137,13 +444,31 @@ mod tests {
             max_resolution: None,
             gamepad_enabled: true,
             gamepad_deadzone: 0.15,
+            input_rate_hz: 250,
             vr_adapter: None,
             runtime_stats: None,
             recorder_config: None,
             send_files: Vec::new(),
             file_out_dir: PathBuf::from("received-files"),
-            file_max_bytes: wavry_common::file_transfer::DEFAULT_MAX_FILE_BYTES,
+            file_max_bytes: rift_core::MAX_FILE_TRANSFER_BYTES,
             file_command_bus: None,
+            file_send_bus: None,
+            file_event_bus: None,
+            clipboard_sync: None,
+            bandwidth_limit_bus: None,
+            host_recording_bus: None,
+            local_recording_bus: None,
+            pointer_mode_bus: None,
+            text_input_bus: None,
+            jitter_buffer: JitterBufferConfig::default(),
+            microphone: false,
+            known_hosts: None,
+            host_name: None,
+            strict_host_key: true,
+            socket: None,
+            remote_candidates: Vec::new(),
+            invite: None,
+            relay_lease_source: None,
         };
 
         assert_eq!(config.client_name, "TestClient");
@@ -156,6 +481,7 @@ mod tests {
     fn test_client_config_clone() {
         let config1 = ClientConfig {
             connect_addr: Some("127.0.0.1:5000".parse().unwrap()),
+            alternate_addrs: Vec::new(),
             client_name: "Clone Test".to_string(),
             no_encrypt: true,
             identity_key: None,
@@ -167,13 +493,31 @@ mod tests {
             }),
             gamepad_enabled: false,
             gamepad_deadzone: 0.0,
+            input_rate_hz: 0,
             vr_adapter: None,
             runtime_stats: None,
             recorder_config: None,
             send_files: Vec::new(),
             file_out_dir: PathBuf::from("received-files"),
-            file_max_bytes: wavry_common::file_transfer::DEFAULT_MAX_FILE_BYTES,
+            file_max_bytes: rift_core::MAX_FILE_TRANSFER_BYTES,
             file_command_bus: None,
+            file_send_bus: None,
+            file_event_bus: None,
+            clipboard_sync: None,
+            bandwidth_limit_bus: None,
+            host_recording_bus: None,
+            local_recording_bus: None,
+            pointer_mode_bus: None,
+            text_input_bus: None,
+            jitter_buffer: JitterBufferConfig::default(),
+            microphone: false,
+            known_hosts: None,
+            host_name: None,
+            strict_host_key: true,
+            socket: None,
+            remote_candidates: Vec::new(),
+            invite: None,
+            relay_lease_source: None,
         };
 
         let config2 = config1.clone();
@@ -224,6 +568,27 @@ mod tests {
             0
         );
         assert!(stats.monitors.lock().unwrap().is_empty());
+        assert_eq!(stats.latency(), None);
+    }
+
+    #[test]
+    fn test_latency_breakdown_totals_and_records() {
+        let latency = LatencyBreakdown {
+            frame_id: 7,
+            capture_us: 1_000,
+            encode_us: 2_500,
+            pacing_us: 500,
+            network_us: 4_000,
+            decode_us: 1_500,
+            render_us: 0,
+        };
+        assert_eq!(latency.total_us(), 9_500);
+        assert_eq!(latency.to_proto().total_us, 9_500);
+        assert_eq!(latency.to_proto().pacing_us, 500);
+
+        let stats = ClientRuntimeStats::default();
+        stats.record_latency(latency);
+        assert_eq!(stats.latency(), Some(latency));
     }
 
     #[test]
@@ -257,6 +622,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_clipboard_sync_direction_parsing() {
+        assert_eq!(
+            "both".parse::<ClipboardSyncDirection>(),
+            Ok(ClipboardSyncDirection::Bidirectional)
+        );
+        assert_eq!(
+            " To_Host ".parse::<ClipboardSyncDirection>(),
+            Ok(ClipboardSyncDirection::ToHost)
+        );
+        assert!("sideways".parse::<ClipboardSyncDirection>().is_err());
+        assert!(ClipboardSyncDirection::ToHost.sends());
+        assert!(!ClipboardSyncDirection::ToHost.receives());
+        assert!(!ClipboardSyncDirection::Disabled.sends());
+        let sync = rift_core::ClipboardSync::from(ClipboardSyncDirection::FromHost);
+        assert!(sync.from_host && !sync.to_host);
+    }
+
+    #[test]
+    fn test_clipboard_sync_control_updates_direction() {
+        let control = ClipboardSyncControl::new(ClipboardSyncDirection::Disabled);
+        assert_eq!(control.direction(), ClipboardSyncDirection::Disabled);
+        control.set_direction(ClipboardSyncDirection::FromHost);
+        assert_eq!(control.direction(), ClipboardSyncDirection::FromHost);
+    }
+
     #[test]
     fn test_crypto_state_debug() {
         let disabled = CryptoState::Disabled;
@@ -0,0 +1,255 @@
+//! Wake-on-LAN. Besides sending magic packets directly, a client can ask the
+//! gateway to wake a host registered there: the gateway hands the MAC to a
+//! wake agent on the host's LAN (`WAKE_HOST`), and [`wake_and_wait`] polls
+//! until the host binds to signaling again.
+
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use tokio::time::{self, Instant};
+use tracing::{debug, info};
+
+use crate::signaling::{SignalMessage, SignalingClient};
+
+/// Limited broadcast on the conventional WoL discard port.
+pub const DEFAULT_BROADCAST_ADDR: &str = "255.255.255.255:9";
+/// How often [`wake_and_wait`] asks whether the host is back.
+pub const WAKE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Long enough for a desktop to resume from sleep or boot and sign in.
+pub const WAKE_TIMEOUT: Duration = Duration::from_secs(120);
+
+const MAGIC_PACKET_LEN: usize = 6 + 16 * 6;
+
+/// Parse `aa:bb:cc:dd:ee:ff`, `aa-bb-cc-dd-ee-ff` or `aabbccddeeff`.
+pub fn parse_mac(mac: &str) -> Result<[u8; 6]> {
+    let hex: String = mac
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | '.'))
+        .collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Invalid MAC address: {}", mac);
+    }
+
+    let mut out = [0u8; 6];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| anyhow!("Invalid MAC address: {}", mac))?;
+    }
+    Ok(out)
+}
+
+/// Canonical lowercase, colon-separated form.
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub fn magic_packet(mac: &[u8; 6]) -> [u8; MAGIC_PACKET_LEN] {
+    let mut packet = [0xFFu8; MAGIC_PACKET_LEN];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(mac);
+    }
+    packet
+}
+
+/// Accepts `host:port` or a bare IP, which defaults to port 9.
+pub fn parse_broadcast_addr(addr: Option<&str>) -> Result<SocketAddr> {
+    let addr = addr
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .unwrap_or(DEFAULT_BROADCAST_ADDR);
+    if let Ok(socket_addr) = addr.parse::<SocketAddr>() {
+        return Ok(socket_addr);
+    }
+    addr.parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 9))
+        .map_err(|_| anyhow!("Invalid broadcast address: {}", addr))
+}
+
+pub fn send_magic_packet(mac: &[u8; 6], target: SocketAddr) -> Result<()> {
+    let bind_addr = if target.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket =
+        UdpSocket::bind(bind_addr).map_err(|e| anyhow!("Failed to bind UDP socket: {}", e))?;
+    socket
+        .set_broadcast(true)
+        .map_err(|e| anyhow!("Failed to enable broadcast: {}", e))?;
+    socket
+        .send_to(&magic_packet(mac), target)
+        .map_err(|e| anyhow!("Failed to send wake packet to {}: {}", target, e))?;
+    Ok(())
+}
+
+/// Carries out a gateway's `WAKE_HOST` on this device's LAN.
+pub fn handle_wake_host(mac_address: &str, broadcast_addr: Option<&str>) -> Result<()> {
+    let mac = parse_mac(mac_address)?;
+    let target = parse_broadcast_addr(broadcast_addr)?;
+    send_magic_packet(&mac, target)?;
+    info!(
+        "sent Wake-on-LAN packet for {} to {} on behalf of the gateway",
+        format_mac(&mac),
+        target
+    );
+    Ok(())
+}
+
+/// Asks the gateway to wake `target`'s registered hosts, then polls until
+/// `target` is online or `timeout` passes. Other messages that arrive
+/// meanwhile are dropped, so call this before offering a session.
+pub async fn wake_and_wait(
+    signaling: &mut SignalingClient,
+    target: &str,
+    timeout: Duration,
+) -> Result<()> {
+    signaling
+        .send(SignalMessage::REQUEST_WAKE {
+            target_username: target.to_string(),
+            host_name: None,
+        })
+        .await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut next_poll = Instant::now() + WAKE_POLL_INTERVAL;
+    let mut requested = false;
+    loop {
+        let msg = match time::timeout_at(next_poll.min(deadline), signaling.recv()).await {
+            Ok(msg) => msg?,
+            Err(_) if Instant::now() >= deadline => {
+                bail!("{} did not come online within {:?}", target, timeout)
+            }
+            Err(_) => {
+                signaling
+                    .send(SignalMessage::QUERY_HOST {
+                        target_username: target.to_string(),
+                    })
+                    .await?;
+                next_poll = Instant::now() + WAKE_POLL_INTERVAL;
+                continue;
+            }
+        };
+
+        match msg {
+            SignalMessage::HOST_STATUS {
+                target_username,
+                online,
+                wake_agents,
+            } if target_username == target => {
+                if online {
+                    info!("{} is online", target);
+                    return Ok(());
+                }
+                if !requested {
+                    // The first answer is to REQUEST_WAKE.
+                    if wake_agents == 0 {
+                        bail!("no device on {}'s network is available to wake it", target);
+                    }
+                    info!("waking {} through {} agent(s)", target, wake_agents);
+                    requested = true;
+                }
+            }
+            SignalMessage::ERROR { message, .. } => bail!("Wake failed: {}", message),
+            other => debug!("ignoring {:?} while waiting for {}", other, target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mac_accepts_common_separators() {
+        let expected = [0xaa, 0xbb, 0xcc, 0x01, 0x02, 0x03];
+        assert_eq!(parse_mac("AA:BB:CC:01:02:03").unwrap(), expected);
+        assert_eq!(parse_mac("aa-bb-cc-01-02-03").unwrap(), expected);
+        assert_eq!(parse_mac("aabbcc010203").unwrap(), expected);
+        assert!(parse_mac("aa:bb:cc:01:02").is_err());
+        assert!(parse_mac("zz:bb:cc:01:02:03").is_err());
+    }
+
+    #[test]
+    fn magic_packet_repeats_mac_after_sync_stream() {
+        let mac = [1, 2, 3, 4, 5, 6];
+        let packet = magic_packet(&mac);
+        assert_eq!(&packet[..6], &[0xFF; 6]);
+        assert!(packet[6..].chunks(6).all(|chunk| chunk == mac));
+        assert_eq!(format_mac(&mac), "01:02:03:04:05:06");
+    }
+
+    #[test]
+    fn parse_broadcast_addr_defaults_port() {
+        assert_eq!(
+            parse_broadcast_addr(Some("192.168.1.255")).unwrap(),
+            "192.168.1.255:9".parse().unwrap()
+        );
+        assert_eq!(
+            parse_broadcast_addr(None).unwrap(),
+            DEFAULT_BROADCAST_ADDR.parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn wake_host_delivers_a_magic_packet() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        handle_wake_host("aa-bb-cc-01-02-03", Some(&addr)).unwrap();
+        let mut buf = [0u8; 256];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(
+            buf[..len],
+            magic_packet(&[0xaa, 0xbb, 0xcc, 0x01, 0x02, 0x03])
+        );
+
+        assert!(handle_wake_host("not-a-mac", Some(&addr)).is_err());
+    }
+
+    #[tokio::test]
+    async fn wake_and_wait_polls_until_the_host_binds() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let gateway = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let mut seen = Vec::new();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let reply = if text.contains("REQUEST_WAKE") {
+                    r#"{"type":"HOST_STATUS","target_username":"desk","online":false,"wake_agents":1}"#
+                } else if text.contains("QUERY_HOST") {
+                    r#"{"type":"HOST_STATUS","target_username":"desk","online":true}"#
+                } else {
+                    ""
+                };
+                seen.push(text.to_string());
+                if !reply.is_empty() {
+                    ws.send(Message::Text(reply.into())).await.unwrap();
+                }
+                if reply.contains("true") {
+                    return seen;
+                }
+            }
+            seen
+        });
+
+        let mut signaling = SignalingClient::connect(&url, "token").await.unwrap();
+        wake_and_wait(&mut signaling, "desk", Duration::from_secs(10))
+            .await
+            .unwrap();
+        let seen = gateway.await.unwrap();
+        assert_eq!(seen.len(), 3, "{:?}", seen);
+        assert!(seen[1].contains(r#""target_username":"desk""#));
+    }
+}
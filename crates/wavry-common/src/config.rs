@@ -0,0 +1,477 @@
+//! Layered TOML configuration shared by the Wavry binaries.
+//!
+//! Values are resolved in order, later layers winning:
+//! built-in defaults, the config file, `WAVRY_<SECTION>__<KEY>` environment
+//! variables, then overrides set by the binary from its command line.
+//!
+//! ```toml
+//! [server]
+//! listen = "0.0.0.0:5000"
+//! fps = 120
+//!
+//! [relay]
+//! region = "eu-west-1"
+//! ```
+
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::error::{Error, Result};
+
+/// Environment variable naming the config file when none is given.
+pub const CONFIG_PATH_ENV: &str = "WAVRY_CONFIG";
+
+const ENV_PREFIX: &str = "WAVRY_";
+const ENV_SEPARATOR: &str = "__";
+
+/// Every section; each binary reads the one it runs as.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WavryConfig {
+    pub server: ServerConfig,
+    pub relay: RelayConfig,
+    pub master: MasterConfig,
+    pub client: ClientConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// UDP listen address; port 0 picks a free port and `[::]` serves IPv4 and IPv6.
+    pub listen: SocketAddr,
+    pub no_encrypt: bool,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub bitrate_kbps: u32,
+    pub keyframe_interval_ms: u32,
+    pub display_id: Option<u32>,
+    pub disable_mdns: bool,
+    pub max_peers: usize,
+    pub peer_idle_timeout_secs: u64,
+    pub gateway_url: String,
+    pub enable_webrtc: bool,
+    pub record: bool,
+    pub record_dir: PathBuf,
+    /// `system`, `microphone`, `app:<name>` or `disabled`.
+    pub audio_source: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            no_encrypt: false,
+            width: 1280,
+            height: 720,
+            fps: 60,
+            bitrate_kbps: 20_000,
+            keyframe_interval_ms: 1_000,
+            display_id: None,
+            disable_mdns: false,
+            max_peers: 64,
+            peer_idle_timeout_secs: 30,
+            gateway_url: "ws://127.0.0.1:3000/ws".into(),
+            enable_webrtc: false,
+            record: false,
+            record_dir: PathBuf::from("recordings"),
+            audio_source: "system".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    pub listen: SocketAddr,
+    pub master_url: String,
+    pub max_sessions: usize,
+    pub idle_timeout_secs: u64,
+    /// Hex-encoded Ed25519 key that signs leases.
+    pub master_public_key: Option<String>,
+    pub allow_insecure_dev: bool,
+    pub ip_rate_limit_pps: u64,
+    pub packet_queue_capacity: usize,
+    pub lease_duration_secs: u64,
+    /// Percent of `max_sessions` above which new sessions are shed.
+    pub load_shed_threshold_pct: u8,
+    pub health_listen: SocketAddr,
+    pub region: Option<String>,
+    pub max_bitrate_kbps: u32,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from((Ipv6Addr::UNSPECIFIED, 4000)),
+            master_url: "http://localhost:8080".into(),
+            max_sessions: 100,
+            idle_timeout_secs: 60,
+            master_public_key: None,
+            allow_insecure_dev: false,
+            ip_rate_limit_pps: 1000,
+            packet_queue_capacity: 2048,
+            lease_duration_secs: 300,
+            load_shed_threshold_pct: 95,
+            health_listen: SocketAddr::from(([127, 0, 0, 1], 9091)),
+            region: None,
+            max_bitrate_kbps: 20_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MasterConfig {
+    pub listen: SocketAddr,
+    pub log_level: String,
+    pub insecure_dev: bool,
+}
+
+impl Default for MasterConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            log_level: "info".into(),
+            insecure_dev: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub name: String,
+    pub connect: Option<SocketAddr>,
+    pub master_url: Option<String>,
+    pub no_encrypt: bool,
+    pub gamepad_enabled: bool,
+    pub gamepad_deadzone: f32,
+    pub file_out_dir: PathBuf,
+    pub file_max_bytes: u64,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            name: "wavry-client".into(),
+            connect: None,
+            master_url: None,
+            no_encrypt: false,
+            gamepad_enabled: true,
+            gamepad_deadzone: 0.1,
+            file_out_dir: PathBuf::from("received-files"),
+            file_max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl WavryConfig {
+    /// Checks ranges the types cannot express, reporting every problem at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, key: &str, why: &str| {
+            if !ok {
+                problems.push(format!("{}: {}", key, why));
+            }
+        };
+
+        let server = &self.server;
+        check(
+            (1..=7680).contains(&server.width) && (1..=4320).contains(&server.height),
+            "server.width/height",
+            "must be between 1x1 and 7680x4320",
+        );
+        check(
+            (1..=240).contains(&server.fps),
+            "server.fps",
+            "must be between 1 and 240",
+        );
+        check(
+            server.bitrate_kbps >= 100,
+            "server.bitrate_kbps",
+            "must be at least 100",
+        );
+        check(
+            server.keyframe_interval_ms > 0,
+            "server.keyframe_interval_ms",
+            "must be positive",
+        );
+        check(server.max_peers > 0, "server.max_peers", "must be positive");
+        check(
+            is_url(&server.gateway_url, &["ws", "wss"]),
+            "server.gateway_url",
+            "must be a ws:// or wss:// URL",
+        );
+
+        let relay = &self.relay;
+        check(
+            is_url(&relay.master_url, &["http", "https"]),
+            "relay.master_url",
+            "must be an http:// or https:// URL",
+        );
+        check(
+            relay.max_sessions > 0,
+            "relay.max_sessions",
+            "must be positive",
+        );
+        check(
+            relay.packet_queue_capacity > 0,
+            "relay.packet_queue_capacity",
+            "must be positive",
+        );
+        check(
+            (1..=100).contains(&relay.load_shed_threshold_pct),
+            "relay.load_shed_threshold_pct",
+            "must be between 1 and 100",
+        );
+        check(
+            relay.max_bitrate_kbps >= 10_000,
+            "relay.max_bitrate_kbps",
+            "must be at least 10000",
+        );
+
+        check(
+            matches!(
+                self.master.log_level.as_str(),
+                "error" | "warn" | "info" | "debug" | "trace"
+            ),
+            "master.log_level",
+            "must be one of error, warn, info, debug, trace",
+        );
+
+        let client = &self.client;
+        check(
+            !client.name.trim().is_empty(),
+            "client.name",
+            "must not be empty",
+        );
+        check(
+            client
+                .master_url
+                .as_deref()
+                .is_none_or(|url| is_url(url, &["http", "https"])),
+            "client.master_url",
+            "must be an http:// or https:// URL",
+        );
+        check(
+            (0.0..=0.95).contains(&client.gamepad_deadzone),
+            "client.gamepad_deadzone",
+            "must be between 0.0 and 0.95",
+        );
+        check(
+            client.file_max_bytes > 0,
+            "client.file_max_bytes",
+            "must be positive",
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::config(problems.join("; ")))
+        }
+    }
+}
+
+fn is_url(value: &str, schemes: &[&str]) -> bool {
+    value
+        .split_once("://")
+        .is_some_and(|(scheme, rest)| schemes.contains(&scheme) && !rest.is_empty())
+}
+
+/// Collects the layers and resolves them into a validated [`WavryConfig`].
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    file: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    overrides: Vec<(String, String)>,
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigLoader {
+    /// Reads the process environment, including [`CONFIG_PATH_ENV`].
+    pub fn new() -> Self {
+        Self {
+            file: std::env::var_os(CONFIG_PATH_ENV).map(PathBuf::from),
+            env: std::env::vars().collect(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Uses `path` as the config file; it must exist.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Replaces the environment the loader reads.
+    pub fn env<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = vars
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self
+    }
+
+    /// Overrides `key` (`section.field`) with a command-line value. The value
+    /// is read as TOML when it parses and as a plain string otherwise.
+    pub fn set(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.overrides.push((key.into(), value.to_string()));
+        self
+    }
+
+    pub fn load(self) -> Result<WavryConfig> {
+        let mut merged = Table::new();
+        if let Some(path) = &self.file {
+            merge(&mut merged, read_file(path)?);
+        }
+        for (name, raw) in &self.env {
+            let Some(key) = env_key(name) else {
+                continue;
+            };
+            insert(&mut merged, &key, parse_value(raw))
+                .map_err(|e| Error::config(format!("{}: {}", name, e)))?;
+        }
+        for (key, raw) in &self.overrides {
+            insert(&mut merged, key, parse_value(raw))?;
+        }
+
+        let config: WavryConfig = Value::Table(merged)
+            .try_into()
+            .map_err(|e: toml::de::Error| Error::config(e.message()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn read_file(path: &Path) -> Result<Table> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::config(format!("cannot read {}: {}", path.display(), e)))?;
+    text.parse::<Table>()
+        .map_err(|e| Error::config(format!("{}: {}", path.display(), e.message())))
+}
+
+/// `WAVRY_SERVER__BITRATE_KBPS` becomes `server.bitrate_kbps`. Single-underscore
+/// variables such as `WAVRY_RELAY_REGION` belong to the binaries' own flags.
+fn env_key(name: &str) -> Option<String> {
+    let rest = name.strip_prefix(ENV_PREFIX)?;
+    let (section, field) = rest.split_once(ENV_SEPARATOR)?;
+    if section.is_empty() || field.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{}.{}",
+        section.to_ascii_lowercase(),
+        field.to_ascii_lowercase()
+    ))
+}
+
+fn parse_value(raw: &str) -> Value {
+    format!("value = {}", raw)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn insert(root: &mut Table, key: &str, value: Value) -> Result<()> {
+    let Some((section, field)) = key.split_once('.') else {
+        return Err(Error::config(format!(
+            "{}: expected a section.field key",
+            key
+        )));
+    };
+    match root.entry(section).or_insert(Value::Table(Table::new())) {
+        Value::Table(table) => {
+            table.insert(field.to_string(), value);
+            Ok(())
+        }
+        _ => Err(Error::config(format!("{}: is not a table", section))),
+    }
+}
+
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(layer)) => merge(existing, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loader() -> ConfigLoader {
+        ConfigLoader {
+            file: None,
+            env: Vec::new(),
+            overrides: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = loader().load().unwrap();
+        assert_eq!(config, WavryConfig::default());
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let path = std::env::temp_dir().join(format!("wavry-config-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "[server]\nfps = 30\nwidth = 1920\n\n[relay]\nregion = \"eu\"\n",
+        )
+        .unwrap();
+
+        let config = loader()
+            .file(&path)
+            .env([
+                ("WAVRY_SERVER__FPS", "90"),
+                ("WAVRY_SERVER__LISTEN", "0.0.0.0:5000"),
+                ("WAVRY_RELAY_REGION", "ignored"),
+            ])
+            .set("server.fps", 120)
+            .load();
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.server.fps, 120);
+        assert_eq!(config.server.width, 1920);
+        assert_eq!(config.server.listen, "0.0.0.0:5000".parse().unwrap());
+        assert_eq!(config.relay.region.as_deref(), Some("eu"));
+    }
+
+    #[test]
+    fn reports_unknown_keys_and_bad_values() {
+        let err = loader().set("server.frame_rate", 60).load().unwrap_err();
+        assert!(err.to_string().contains("frame_rate"), "{}", err);
+
+        let err = loader()
+            .set("server.fps", 0)
+            .set("client.gamepad_deadzone", 2.0)
+            .load()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("server.fps"), "{}", err);
+        assert!(err.contains("client.gamepad_deadzone"), "{}", err);
+
+        assert!(loader().set("fps", 60).load().is_err());
+    }
+}
@@ -1,5 +1,7 @@
-//! Common error types for Wavry.
+//! Common error types for Wavry, and the numeric codes every crate maps its
+//! errors onto.
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Result type alias using Wavry's error type.
@@ -90,3 +92,227 @@ impl Error {
         Self::Internal(msg.to_string())
     }
 }
+
+/// Family of an [`ErrorCode`], given by its thousands digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    General,
+    Network,
+    Crypto,
+    Codec,
+    Auth,
+    Platform,
+}
+
+/// Stable failure codes for the FFI, the desktop frontend and logs.
+///
+/// Codes are never renumbered or reused; new ones go at the end of their
+/// category's range. Serialized as the bare number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "u16", try_from = "u16")]
+#[repr(u16)]
+pub enum ErrorCode {
+    Internal = 0,
+    Config = 1,
+    NotFound = 2,
+    Serialization = 3,
+    Io = 4,
+
+    NetworkUnreachable = 1000,
+    Timeout = 1001,
+    MalformedPacket = 1002,
+    UnsupportedVersion = 1003,
+    Protocol = 1004,
+    RateLimited = 1005,
+    RelayRejected = 1006,
+    SessionFull = 1007,
+
+    HandshakeFailed = 2000,
+    EncryptionFailed = 2001,
+    DecryptionFailed = 2002,
+    ReplayDetected = 2003,
+    SessionNotEstablished = 2004,
+
+    CodecUnsupported = 3000,
+    EncoderFailed = 3001,
+    DecoderFailed = 3002,
+    MediaPipeline = 3003,
+
+    AuthFailed = 4000,
+    LeaseInvalid = 4001,
+    LeaseExpired = 4002,
+
+    PlatformUnsupported = 5000,
+    PermissionDenied = 5001,
+    CaptureLost = 5002,
+    Compositor = 5003,
+    DeviceUnavailable = 5004,
+}
+
+impl ErrorCode {
+    const ALL: &'static [ErrorCode] = &[
+        Self::Internal,
+        Self::Config,
+        Self::NotFound,
+        Self::Serialization,
+        Self::Io,
+        Self::NetworkUnreachable,
+        Self::Timeout,
+        Self::MalformedPacket,
+        Self::UnsupportedVersion,
+        Self::Protocol,
+        Self::RateLimited,
+        Self::RelayRejected,
+        Self::SessionFull,
+        Self::HandshakeFailed,
+        Self::EncryptionFailed,
+        Self::DecryptionFailed,
+        Self::ReplayDetected,
+        Self::SessionNotEstablished,
+        Self::CodecUnsupported,
+        Self::EncoderFailed,
+        Self::DecoderFailed,
+        Self::MediaPipeline,
+        Self::AuthFailed,
+        Self::LeaseInvalid,
+        Self::LeaseExpired,
+        Self::PlatformUnsupported,
+        Self::PermissionDenied,
+        Self::CaptureLost,
+        Self::Compositor,
+        Self::DeviceUnavailable,
+    ];
+
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        Self::ALL.iter().copied().find(|known| known.code() == code)
+    }
+
+    pub fn category(self) -> ErrorCategory {
+        match self.code() / 1000 {
+            1 => ErrorCategory::Network,
+            2 => ErrorCategory::Crypto,
+            3 => ErrorCategory::Codec,
+            4 => ErrorCategory::Auth,
+            5 => ErrorCategory::Platform,
+            _ => ErrorCategory::General,
+        }
+    }
+
+    /// Short explanation suitable for showing to users.
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::Internal => "Something went wrong inside Wavry.",
+            Self::Config => "The configuration is invalid.",
+            Self::NotFound => "The requested item was not found.",
+            Self::Serialization => "Data could not be read or written.",
+            Self::Io => "A file or network operation failed.",
+            Self::NetworkUnreachable => "The host could not be reached.",
+            Self::Timeout => "The connection timed out.",
+            Self::MalformedPacket => "Received a corrupted packet.",
+            Self::UnsupportedVersion => "The other side runs an incompatible Wavry version.",
+            Self::Protocol => "The other side sent an unexpected message.",
+            Self::RateLimited => "Too many requests; try again shortly.",
+            Self::RelayRejected => "The relay refused the session.",
+            Self::SessionFull => "The host or relay has no room for another session.",
+            Self::HandshakeFailed => "The secure connection could not be set up.",
+            Self::EncryptionFailed => "Data could not be encrypted.",
+            Self::DecryptionFailed => "Received data that could not be decrypted.",
+            Self::ReplayDetected => "Rejected a replayed packet.",
+            Self::SessionNotEstablished => "The secure session is not ready yet.",
+            Self::CodecUnsupported => "No supported video codec is available.",
+            Self::EncoderFailed => "The video encoder failed.",
+            Self::DecoderFailed => "The video decoder failed.",
+            Self::MediaPipeline => "The media pipeline stopped unexpectedly.",
+            Self::AuthFailed => "Sign-in or authorization failed.",
+            Self::LeaseInvalid => "The relay lease is invalid.",
+            Self::LeaseExpired => "The relay lease has expired.",
+            Self::PlatformUnsupported => "This feature is not supported on this system.",
+            Self::PermissionDenied => "Wavry was denied a permission it needs.",
+            Self::CaptureLost => "Screen capture stopped.",
+            Self::Compositor => "Lost the connection to the display server.",
+            Self::DeviceUnavailable => "A required device is unavailable.",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "W{:04}", self.code())
+    }
+}
+
+impl From<ErrorCode> for u16 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl TryFrom<u16> for ErrorCode {
+    type Error = String;
+
+    fn try_from(code: u16) -> std::result::Result<Self, Self::Error> {
+        Self::from_code(code).ok_or_else(|| format!("unknown error code {}", code))
+    }
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::from(self)
+    }
+}
+
+impl From<&Error> for ErrorCode {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Io(_) => ErrorCode::Io,
+            Error::Serialization(_) => ErrorCode::Serialization,
+            Error::Config(_) => ErrorCode::Config,
+            Error::Crypto(_) => ErrorCode::HandshakeFailed,
+            Error::Protocol(_) => ErrorCode::Protocol,
+            Error::Auth(_) => ErrorCode::AuthFailed,
+            Error::NotFound(_) => ErrorCode::NotFound,
+            Error::Timeout(_) => ErrorCode::Timeout,
+            Error::RateLimited(_) => ErrorCode::RateLimited,
+            Error::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip_and_sit_in_their_category() {
+        for &code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_code(code.code()), Some(code));
+            assert!(!code.message().is_empty());
+        }
+        assert_eq!(ErrorCode::ReplayDetected.category(), ErrorCategory::Crypto);
+        assert_eq!(ErrorCode::Config.category(), ErrorCategory::General);
+        assert_eq!(ErrorCode::from_code(999), None);
+        assert_eq!(ErrorCode::LeaseExpired.to_string(), "W4002");
+    }
+
+    #[test]
+    fn serializes_as_number() {
+        let json = serde_json::to_string(&ErrorCode::DecryptionFailed).unwrap();
+        assert_eq!(json, "2002");
+        assert_eq!(
+            serde_json::from_str::<ErrorCode>("5001").unwrap(),
+            ErrorCode::PermissionDenied
+        );
+        assert!(serde_json::from_str::<ErrorCode>("7").is_err());
+    }
+
+    #[test]
+    fn common_errors_map_to_codes() {
+        assert_eq!(Error::config("bad").code(), ErrorCode::Config);
+        assert_eq!(Error::timeout("slow").code(), ErrorCode::Timeout);
+    }
+}
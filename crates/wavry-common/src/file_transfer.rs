@@ -4,8 +4,6 @@ use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-pub const DEFAULT_MAX_FILE_BYTES: u64 = 1024 * 1024 * 1024;
-pub const DEFAULT_CHUNK_SIZE: usize = 900;
 pub const MAX_FILENAME_BYTES: usize = 255;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -458,6 +456,8 @@ mod tests {
     use super::*;
     use std::time::{SystemTime, UNIX_EPOCH};
 
+    const MAX_FILE_BYTES: u64 = 1024 * 1024 * 1024;
+
     fn temp_dir(name: &str) -> PathBuf {
         let unique = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -484,7 +484,7 @@ mod tests {
         let file_path = dir.join("hello.txt");
         fs::write(&file_path, b"hello wavry").unwrap();
 
-        let out = OutgoingFile::from_path(&file_path, 42, 4, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let out = OutgoingFile::from_path(&file_path, 42, 4, MAX_FILE_BYTES).unwrap();
         assert_eq!(out.offer().file_id, 42);
         assert_eq!(out.offer().filename, "hello.txt");
         assert_eq!(out.offer().file_size, 11);
@@ -510,10 +510,9 @@ mod tests {
         let payload = vec![7u8; 10_000];
         fs::write(&send_path, &payload).unwrap();
 
-        let mut outgoing =
-            OutgoingFile::from_path(&send_path, 7, 900, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut outgoing = OutgoingFile::from_path(&send_path, 7, 900, MAX_FILE_BYTES).unwrap();
         let offer = outgoing.offer().clone();
-        let mut incoming = IncomingFile::new(&recv_dir, offer, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut incoming = IncomingFile::new(&recv_dir, offer, MAX_FILE_BYTES).unwrap();
 
         let mut chunks = Vec::new();
         while let Some(chunk) = outgoing.next_chunk().unwrap() {
@@ -542,10 +541,9 @@ mod tests {
         let payload = vec![3u8; 8_192];
         fs::write(&send_path, &payload).unwrap();
 
-        let mut outgoing =
-            OutgoingFile::from_path(&send_path, 17, 700, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut outgoing = OutgoingFile::from_path(&send_path, 17, 700, MAX_FILE_BYTES).unwrap();
         let offer = outgoing.offer().clone();
-        let mut incoming = IncomingFile::new(&recv_dir, offer, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut incoming = IncomingFile::new(&recv_dir, offer, MAX_FILE_BYTES).unwrap();
 
         let mut dropped: Option<FileChunkData> = None;
         while let Some(chunk) = outgoing.next_chunk().unwrap() {
@@ -577,10 +575,9 @@ mod tests {
         let recv_dir = dir.join("recv");
 
         fs::write(&send_path, b"abcdef").unwrap();
-        let mut outgoing =
-            OutgoingFile::from_path(&send_path, 99, 2, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut outgoing = OutgoingFile::from_path(&send_path, 99, 2, MAX_FILE_BYTES).unwrap();
         let offer = outgoing.offer().clone();
-        let mut incoming = IncomingFile::new(&recv_dir, offer, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut incoming = IncomingFile::new(&recv_dir, offer, MAX_FILE_BYTES).unwrap();
 
         while let Some(mut chunk) = outgoing.next_chunk().unwrap() {
             if chunk.chunk_index == 1 {
@@ -600,12 +597,12 @@ mod tests {
         let offer = FileOffer {
             file_id: 1,
             filename: "big.bin".to_string(),
-            file_size: DEFAULT_MAX_FILE_BYTES + 1,
+            file_size: MAX_FILE_BYTES + 1,
             checksum_sha256: "0".repeat(64),
             chunk_size: 1024,
             total_chunks: 2,
         };
-        assert!(validate_offer(&offer, DEFAULT_MAX_FILE_BYTES).is_err());
+        assert!(validate_offer(&offer, MAX_FILE_BYTES).is_err());
     }
 
     #[test]
@@ -614,8 +611,7 @@ mod tests {
         let file_path = dir.join("payload.bin");
         fs::write(&file_path, vec![5u8; 5_000]).unwrap();
 
-        let mut outgoing =
-            OutgoingFile::from_path(&file_path, 123, 700, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut outgoing = OutgoingFile::from_path(&file_path, 123, 700, MAX_FILE_BYTES).unwrap();
         assert_eq!(outgoing.next_chunk_index(), 0);
 
         outgoing.set_next_chunk(3).unwrap();
@@ -642,10 +638,9 @@ mod tests {
         let payload = (0..15_000u32).map(|v| (v % 251) as u8).collect::<Vec<_>>();
         fs::write(&send_path, &payload).unwrap();
 
-        let mut outgoing =
-            OutgoingFile::from_path(&send_path, 777, 900, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut outgoing = OutgoingFile::from_path(&send_path, 777, 900, MAX_FILE_BYTES).unwrap();
         let offer = outgoing.offer().clone();
-        let mut incoming = IncomingFile::new(&recv_dir, offer, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut incoming = IncomingFile::new(&recv_dir, offer, MAX_FILE_BYTES).unwrap();
 
         for _ in 0..4 {
             let chunk = outgoing
@@ -694,7 +689,7 @@ mod tests {
             total_chunks: 4,
         };
 
-        let mut incoming = IncomingFile::new(&recv_dir, offer, DEFAULT_MAX_FILE_BYTES).unwrap();
+        let mut incoming = IncomingFile::new(&recv_dir, offer, MAX_FILE_BYTES).unwrap();
         assert_eq!(incoming.received_count(), 0);
         assert_eq!(incoming.next_missing_chunk(), 0);
 
@@ -1,5 +1,7 @@
 //! Common helper functions for Wavry.
 
+use std::path::{Path, PathBuf};
+
 /// Performs a constant-time comparison of two strings.
 /// This is used to prevent timing attacks when comparing security tokens.
 pub fn constant_time_eq(a: &str, b: &str) -> bool {
@@ -17,6 +19,24 @@ pub fn constant_time_eq(a: &str, b: &str) -> bool {
     diff == 0
 }
 
+/// Per-user directory for Wavry state such as identity keys and pinned
+/// hosts: `$XDG_CONFIG_HOME/wavry` (or `~/.config/wavry`) on Linux,
+/// `~/Library/Application Support/wavry` on macOS and `%APPDATA%\wavry` on
+/// Windows.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME")
+            .map(|home| Path::new(&home).join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(base.join("wavry"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
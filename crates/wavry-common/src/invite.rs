@@ -0,0 +1,164 @@
+//! Invite tokens that let someone without an account connect to one host.
+//!
+//! A token is `base64url(claims).base64url(HMAC-SHA256(secret, claims))`.
+//! Gateways and masters sharing [`INVITE_SECRET_ENV`] verify each other's
+//! invites without a database, the same way TURN servers check credentials
+//! minted by [`crate::turn`]. Claims are readable without the secret, so a
+//! guest learns which host it is about to reach.
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Secret shared by every server that mints or accepts invites.
+pub const INVITE_SECRET_ENV: &str = "WAVRY_INVITE_SECRET";
+pub const DEFAULT_INVITE_TTL: Duration = Duration::from_secs(24 * 3600);
+pub const MIN_INVITE_TTL: Duration = Duration::from_secs(60);
+pub const MAX_INVITE_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+/// Signaling names handed to guests start with this; account registration
+/// refuses it so a guest can never be mistaken for a user.
+pub const GUEST_USERNAME_PREFIX: &str = "guest-";
+/// Longer tokens are rejected before any decoding.
+pub const MAX_INVITE_TOKEN_LEN: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InviteClaims {
+    /// Username of the host the invite reaches.
+    pub host: String,
+    /// Expiry, in Unix seconds.
+    pub exp: u64,
+    /// Distinguishes invites minted in the same second, for logs.
+    pub id: String,
+}
+
+/// Mint an invite to `host`, valid until `now_unix + ttl`.
+pub fn mint_invite(secret: &[u8], host: &str, ttl: Duration, now_unix: u64) -> String {
+    let claims = InviteClaims {
+        host: host.to_string(),
+        exp: now_unix.saturating_add(ttl.as_secs()),
+        id: uuid::Uuid::new_v4().simple().to_string(),
+    };
+    let payload =
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).expect("claims always serialize"));
+    let signature = URL_SAFE_NO_PAD.encode(invite_mac(secret, &payload).finalize().into_bytes());
+    format!("{payload}.{signature}")
+}
+
+/// Check the signature and expiry of `token`.
+pub fn verify_invite(secret: &[u8], token: &str, now_unix: u64) -> Result<InviteClaims> {
+    let (payload, signature) = split_token(token)?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| anyhow!("malformed invite signature"))?;
+    invite_mac(secret, payload)
+        .verify_slice(&signature)
+        .map_err(|_| anyhow!("invite signature mismatch"))?;
+    let claims = decode_claims(payload)?;
+    if claims.exp <= now_unix {
+        bail!("invite expired");
+    }
+    Ok(claims)
+}
+
+/// Read the claims of `token` without checking them; the server still has to
+/// accept the invite.
+pub fn peek_invite(token: &str) -> Result<InviteClaims> {
+    let (payload, _) = split_token(token)?;
+    decode_claims(payload)
+}
+
+/// Clamp a requested lifetime into [`MIN_INVITE_TTL`]..=[`MAX_INVITE_TTL`].
+pub fn invite_ttl(requested_secs: Option<u64>) -> Duration {
+    requested_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_INVITE_TTL)
+        .clamp(MIN_INVITE_TTL, MAX_INVITE_TTL)
+}
+
+/// A fresh signaling name for a guest connection.
+pub fn guest_username() -> String {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    format!("{GUEST_USERNAME_PREFIX}{}", &id[..12])
+}
+
+pub fn is_guest_username(username: &str) -> bool {
+    username.starts_with(GUEST_USERNAME_PREFIX)
+}
+
+/// Link form of an invite on a server reachable at `base_url`.
+pub fn invite_url(base_url: &str, token: &str) -> String {
+    format!("{}/invite/{token}", base_url.trim_end_matches('/'))
+}
+
+fn invite_mac(secret: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn split_token(token: &str) -> Result<(&str, &str)> {
+    if token.len() > MAX_INVITE_TOKEN_LEN {
+        bail!("invite token too long");
+    }
+    token
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| anyhow!("malformed invite token"))
+}
+
+fn decode_claims(payload: &str) -> Result<InviteClaims> {
+    let json = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| anyhow!("malformed invite token"))?;
+    serde_json::from_slice(&json).map_err(|_| anyhow!("malformed invite claims"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn minted_invite_verifies_until_it_expires() {
+        let token = mint_invite(b"secret", "alice", Duration::from_secs(3600), NOW);
+        let claims = verify_invite(b"secret", &token, NOW + 10).unwrap();
+        assert_eq!(claims.host, "alice");
+        assert_eq!(claims.exp, NOW + 3600);
+        assert_eq!(peek_invite(&token).unwrap(), claims);
+
+        assert!(verify_invite(b"secret", &token, NOW + 3600).is_err());
+        assert!(verify_invite(b"other", &token, NOW).is_err());
+    }
+
+    #[test]
+    fn tampered_claims_are_rejected() {
+        let token = mint_invite(b"secret", "alice", Duration::from_secs(3600), NOW);
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(format!(
+            r#"{{"host":"mallory","exp":{},"id":"x"}}"#,
+            NOW + 3600
+        ));
+        assert!(verify_invite(b"secret", &format!("{forged}.{signature}"), NOW).is_err());
+        assert!(verify_invite(b"secret", "not-a-token", NOW).is_err());
+    }
+
+    #[test]
+    fn ttl_is_clamped_and_guests_are_recognisable() {
+        assert_eq!(invite_ttl(None), DEFAULT_INVITE_TTL);
+        assert_eq!(invite_ttl(Some(1)), MIN_INVITE_TTL);
+        assert_eq!(invite_ttl(Some(u64::MAX)), MAX_INVITE_TTL);
+
+        let guest = guest_username();
+        assert!(is_guest_username(&guest));
+        assert_eq!(guest.len(), GUEST_USERNAME_PREFIX.len() + 12);
+        assert!(!is_guest_username("alice"));
+        assert_eq!(
+            invite_url("https://gw.example/", "abc.def"),
+            "https://gw.example/invite/abc.def"
+        );
+    }
+}
@@ -1,15 +1,22 @@
-//! Shared utilities for Wavry: configuration, logging, error types.
+//! Shared utilities for Wavry: configuration, logging, error types, metrics.
 //!
 //! This crate provides common infrastructure used across all Wavry components.
 
 #![forbid(unsafe_code)]
 
+pub mod config;
 pub mod error;
 pub mod file_transfer;
 pub mod helpers;
+pub mod invite;
+pub mod logging;
+pub mod metrics;
 pub mod protocol;
+pub mod turn;
 
+pub use config::{ConfigLoader, WavryConfig};
 pub use error::{Error, Result};
+pub use logging::{init_logging, session_span, LogHandle, LogOptions, SessionSpanExt};
 pub use protocol::*;
 
 /// Initialize tracing with sensible defaults.
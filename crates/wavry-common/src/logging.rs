@@ -0,0 +1,409 @@
+//! Log output options beyond [`crate::init_tracing`]: JSON lines, rotating
+//! log files and changing the level while running, plus the `session` span
+//! shared by every component.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{field, Span};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Never,
+    /// At the first write after midnight UTC.
+    Daily,
+    /// Once the active file reaches this many bytes.
+    Size(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+    pub dir: PathBuf,
+    /// The active file is `<prefix>.log`; rotated ones get a UTC timestamp.
+    pub prefix: String,
+    pub rotation: Rotation,
+    /// Rotated files kept besides the active one.
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogOptions {
+    /// Filter used when `RUST_LOG` is unset, e.g. `info,hyper=warn`.
+    pub default_level: String,
+    pub format: LogFormat,
+    pub file: Option<LogFile>,
+    /// Also log to stderr when writing to a file.
+    pub stderr: bool,
+}
+
+impl LogOptions {
+    pub fn new(default_level: impl Into<String>) -> Self {
+        Self {
+            default_level: default_level.into(),
+            format: LogFormat::Text,
+            file: None,
+            stderr: true,
+        }
+    }
+
+    /// Reads `WAVRY_LOG_FORMAT` (`text` or `json`), `WAVRY_LOG_DIR`,
+    /// `WAVRY_LOG_ROTATION` (`never`, `daily` or a size such as `50M`) and
+    /// `WAVRY_LOG_MAX_FILES`.
+    pub fn from_env(default_level: impl Into<String>, prefix: &str) -> Result<Self> {
+        let mut options = Self::new(default_level);
+        if let Ok(format) = std::env::var("WAVRY_LOG_FORMAT") {
+            options.format = match format.to_ascii_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                other => {
+                    return Err(Error::config(format!(
+                        "WAVRY_LOG_FORMAT: unknown format {}",
+                        other
+                    )))
+                }
+            };
+        }
+        if let Some(dir) = std::env::var_os("WAVRY_LOG_DIR") {
+            let rotation = match std::env::var("WAVRY_LOG_ROTATION") {
+                Ok(value) => parse_rotation(&value).ok_or_else(|| {
+                    Error::config(format!("WAVRY_LOG_ROTATION: invalid {}", value))
+                })?,
+                Err(_) => Rotation::Daily,
+            };
+            let max_files = match std::env::var("WAVRY_LOG_MAX_FILES") {
+                Ok(value) => value.parse().map_err(|_| {
+                    Error::config(format!("WAVRY_LOG_MAX_FILES: invalid {}", value))
+                })?,
+                Err(_) => 7,
+            };
+            options.file = Some(LogFile {
+                dir: dir.into(),
+                prefix: prefix.to_string(),
+                rotation,
+                max_files,
+            });
+        }
+        Ok(options)
+    }
+}
+
+fn parse_rotation(value: &str) -> Option<Rotation> {
+    let value = value.trim().to_ascii_lowercase();
+    match value.as_str() {
+        "never" => return Some(Rotation::Never),
+        "daily" => return Some(Rotation::Daily),
+        _ => {}
+    }
+    let (digits, scale) = match value.as_bytes().last()? {
+        b'k' => (&value[..value.len() - 1], 1 << 10),
+        b'm' => (&value[..value.len() - 1], 1 << 20),
+        b'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value.as_str(), 1),
+    };
+    let bytes = digits.parse::<u64>().ok()?.checked_mul(scale)?;
+    (bytes > 0).then_some(Rotation::Size(bytes))
+}
+
+/// Changes the active filter after startup.
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogHandle {
+    /// Takes `RUST_LOG`-style directives, e.g. `debug` or `info,rift_core=trace`.
+    pub fn set_filter(&self, directives: &str) -> Result<()> {
+        let filter = EnvFilter::try_new(directives).map_err(Error::config)?;
+        self.filter.reload(filter).map_err(Error::internal)
+    }
+}
+
+/// Installs the global subscriber. Fails if one is already set.
+pub fn init_logging(options: LogOptions) -> Result<LogHandle> {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&options.default_level))
+        .map_err(Error::config)?;
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let mut layers = Vec::new();
+    if let Some(file) = &options.file {
+        let writer = RollingFile::open(file.clone())?;
+        layers.push(fmt_layer(options.format, Mutex::new(writer), false));
+    }
+    if options.file.is_none() || options.stderr {
+        layers.push(fmt_layer(options.format, io::stderr, true));
+    }
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layers)
+        .try_init()
+        .map_err(Error::internal)?;
+    Ok(LogHandle { filter: handle })
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_target(true)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).boxed(),
+    }
+}
+
+/// Opens the span one peer's session runs in. Fields start empty and are
+/// filled in through [`SessionSpanExt`] as the handshake learns them, so
+/// grepping any one of them finds that session in client, server and relay
+/// logs alike.
+pub fn session_span(component: &'static str) -> Span {
+    tracing::info_span!(
+        "session",
+        component,
+        session_id = field::Empty,
+        session_alias = field::Empty,
+        relay_session = field::Empty,
+        wavry_id = field::Empty,
+    )
+}
+
+/// Records `session` span fields in the one format every component logs.
+pub trait SessionSpanExt {
+    /// The RIFT session id from the HelloAck, as lowercase hex.
+    fn record_session_id(&self, session_id: &[u8]) -> &Self;
+    fn record_session_alias(&self, alias: u32) -> &Self;
+    /// The relay lease session, as 32 hex digits without dashes.
+    fn record_relay_session(&self, session: Uuid) -> &Self;
+    fn record_wavry_id(&self, wavry_id: &dyn fmt::Display) -> &Self;
+}
+
+impl SessionSpanExt for Span {
+    fn record_session_id(&self, session_id: &[u8]) -> &Self {
+        self.record("session_id", hex::encode(session_id).as_str())
+    }
+
+    fn record_session_alias(&self, alias: u32) -> &Self {
+        self.record("session_alias", alias)
+    }
+
+    fn record_relay_session(&self, session: Uuid) -> &Self {
+        self.record("relay_session", field::display(session.simple()))
+    }
+
+    fn record_wavry_id(&self, wavry_id: &dyn fmt::Display) -> &Self {
+        self.record("wavry_id", field::display(wavry_id))
+    }
+}
+
+/// Log file writer that rotates by day or size and prunes old files.
+pub struct RollingFile {
+    config: LogFile,
+    file: File,
+    written: u64,
+    opened_day: u64,
+}
+
+impl RollingFile {
+    pub fn open(config: LogFile) -> Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let path = active_path(&config);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        let opened_day = modified_day(&path).unwrap_or_else(|| now_secs() / SECS_PER_DAY);
+        Ok(Self {
+            config,
+            file,
+            written,
+            opened_day,
+        })
+    }
+
+    fn needs_rotation(&self, incoming: usize) -> bool {
+        match self.config.rotation {
+            Rotation::Never => false,
+            Rotation::Daily => now_secs() / SECS_PER_DAY != self.opened_day,
+            Rotation::Size(limit) => self.written > 0 && self.written + incoming as u64 > limit,
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let active = active_path(&self.config);
+        let stamp = timestamp(now_secs());
+        let mut rotated = self
+            .config
+            .dir
+            .join(format!("{}.{}.log", self.config.prefix, stamp));
+        let mut seq = 1;
+        while rotated.exists() {
+            rotated = self
+                .config
+                .dir
+                .join(format!("{}.{}-{}.log", self.config.prefix, stamp, seq));
+            seq += 1;
+        }
+        fs::rename(&active, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&active)?;
+        self.written = 0;
+        self.opened_day = now_secs() / SECS_PER_DAY;
+        self.prune()
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let active = format!("{}.log", self.config.prefix);
+        let rotated_prefix = format!("{}.", self.config.prefix);
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&self.config.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name != active
+                            && name.starts_with(&rotated_prefix)
+                            && name.ends_with(".log")
+                    })
+            })
+            .collect();
+        // Timestamps sort chronologically, so the oldest files come first.
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.config.max_files);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn active_path(config: &LogFile) -> PathBuf {
+    config.dir.join(format!("{}.log", config.prefix))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+fn modified_day(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() / SECS_PER_DAY)
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let time = secs % SECS_PER_DAY;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(timestamp(0), "19700101-000000");
+        assert_eq!(timestamp(951_782_400 + 3_723), "20000229-010203");
+        assert_eq!(timestamp(1_767_225_599), "20251231-235959");
+    }
+
+    #[test]
+    fn parses_rotation_settings() {
+        assert_eq!(parse_rotation("daily"), Some(Rotation::Daily));
+        assert_eq!(parse_rotation("50M"), Some(Rotation::Size(50 << 20)));
+        assert_eq!(parse_rotation("4096"), Some(Rotation::Size(4096)));
+        assert_eq!(parse_rotation("0"), None);
+        assert_eq!(parse_rotation("weekly"), None);
+    }
+
+    #[test]
+    fn rotates_by_size_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("wavry-logs-{}", uuid::Uuid::new_v4()));
+        let mut writer = RollingFile::open(LogFile {
+            dir: dir.clone(),
+            prefix: "relay".into(),
+            rotation: Rotation::Size(10),
+            max_files: 2,
+        })
+        .unwrap();
+        for _ in 0..5 {
+            writer.write_all(b"0123456789").unwrap();
+        }
+        writer.flush().unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert!(names.contains(&"relay.log".to_string()));
+    }
+}
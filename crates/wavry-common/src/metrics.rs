@@ -0,0 +1,570 @@
+//! Process-wide counters, gauges and histograms with pluggable exporters.
+//!
+//! Record through the macros, which cache the handle at each call site:
+//!
+//! ```
+//! wavry_common::counter!("relay.packets_rx");
+//! wavry_common::counter!("relay.bytes_rx", 1200);
+//! wavry_common::gauge!("relay.active_sessions", 12.0);
+//! wavry_common::histogram!("server.encode_ms", 4.2);
+//! ```
+//!
+//! A [`Reporter`] pushes [`snapshot`]s to exporters on an interval;
+//! Prometheus endpoints can instead call [`render_prometheus`] per scrape.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Upper bounds of histogram buckets; values above the last land in `+Inf`.
+pub const HISTOGRAM_BUCKETS: &[f64] = &[
+    0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// Monotonic count of events.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn increment(&self, by: u64) {
+        self.0.fetch_add(by, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value that can go up and down.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: f64) {
+        update_f64(&self.0, |value| value + delta);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Distribution of observed values over [`HISTOGRAM_BUCKETS`].
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramCells>);
+
+#[derive(Debug)]
+struct HistogramCells {
+    /// One per bucket plus the `+Inf` overflow.
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self(Arc::new(HistogramCells {
+            buckets: (0..=HISTOGRAM_BUCKETS.len())
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }))
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        let bucket = HISTOGRAM_BUCKETS
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(HISTOGRAM_BUCKETS.len());
+        self.0.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.0.count.fetch_add(1, Ordering::Relaxed);
+        update_f64(&self.0.sum, |sum| sum + value);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: self
+                .0
+                .buckets
+                .iter()
+                .map(|bucket| bucket.load(Ordering::Relaxed))
+                .collect(),
+            count: self.0.count.load(Ordering::Relaxed),
+            sum: f64::from_bits(self.0.sum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+fn update_f64(cell: &AtomicU64, f: impl Fn(f64) -> f64) {
+    let _ = cell.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some(f(f64::from_bits(bits)).to_bits())
+    });
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+fn registry() -> &'static Mutex<BTreeMap<String, Metric>> {
+    static REGISTRY: OnceLock<Mutex<BTreeMap<String, Metric>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Looks up or registers `name`. A name already registered as another kind
+/// gets a detached handle, so the clash shows up in logs instead of panicking.
+fn register<T>(
+    name: &str,
+    get: impl Fn(&Metric) -> Option<T>,
+    create: impl FnOnce() -> (T, Metric),
+) -> T {
+    let mut metrics = registry().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(existing) = metrics.get(name) {
+        return get(existing).unwrap_or_else(|| {
+            tracing::warn!("metric {} is already registered as another kind", name);
+            create().0
+        });
+    }
+    let (handle, metric) = create();
+    metrics.insert(name.to_string(), metric);
+    handle
+}
+
+pub fn counter(name: &str) -> Counter {
+    register(
+        name,
+        |metric| match metric {
+            Metric::Counter(counter) => Some(counter.clone()),
+            _ => None,
+        },
+        || {
+            let counter = Counter::default();
+            (counter.clone(), Metric::Counter(counter))
+        },
+    )
+}
+
+pub fn gauge(name: &str) -> Gauge {
+    register(
+        name,
+        |metric| match metric {
+            Metric::Gauge(gauge) => Some(gauge.clone()),
+            _ => None,
+        },
+        || {
+            let gauge = Gauge::default();
+            (gauge.clone(), Metric::Gauge(gauge))
+        },
+    )
+}
+
+pub fn histogram(name: &str) -> Histogram {
+    register(
+        name,
+        |metric| match metric {
+            Metric::Histogram(histogram) => Some(histogram.clone()),
+            _ => None,
+        },
+        || {
+            let histogram = Histogram::default();
+            (histogram.clone(), Metric::Histogram(histogram))
+        },
+    )
+}
+
+/// Adds to a counter, by 1 when no amount is given. The name must be the
+/// same on every call from a given site, as the handle is cached there.
+#[macro_export]
+macro_rules! counter {
+    ($name:expr) => {
+        $crate::counter!($name, 1)
+    };
+    ($name:expr, $value:expr) => {{
+        static HANDLE: ::std::sync::OnceLock<$crate::metrics::Counter> =
+            ::std::sync::OnceLock::new();
+        HANDLE
+            .get_or_init(|| $crate::metrics::counter($name))
+            .increment($value);
+    }};
+}
+
+/// Sets a gauge.
+#[macro_export]
+macro_rules! gauge {
+    ($name:expr, $value:expr) => {{
+        static HANDLE: ::std::sync::OnceLock<$crate::metrics::Gauge> = ::std::sync::OnceLock::new();
+        HANDLE
+            .get_or_init(|| $crate::metrics::gauge($name))
+            .set($value);
+    }};
+}
+
+/// Records one histogram observation.
+#[macro_export]
+macro_rules! histogram {
+    ($name:expr, $value:expr) => {{
+        static HANDLE: ::std::sync::OnceLock<$crate::metrics::Histogram> =
+            ::std::sync::OnceLock::new();
+        HANDLE
+            .get_or_init(|| $crate::metrics::histogram($name))
+            .record($value);
+    }};
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// Per-bucket counts, not cumulative; the last entry is `+Inf`.
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+/// Values of every registered metric at one moment, sorted by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub counters: Vec<(String, u64)>,
+    pub gauges: Vec<(String, f64)>,
+    pub histograms: Vec<(String, HistogramSnapshot)>,
+}
+
+pub fn snapshot() -> Snapshot {
+    let metrics = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let mut snapshot = Snapshot::default();
+    for (name, metric) in metrics.iter() {
+        match metric {
+            Metric::Counter(counter) => snapshot.counters.push((name.clone(), counter.get())),
+            Metric::Gauge(gauge) => snapshot.gauges.push((name.clone(), gauge.get())),
+            Metric::Histogram(histogram) => snapshot
+                .histograms
+                .push((name.clone(), histogram.snapshot())),
+        }
+    }
+    snapshot
+}
+
+/// Destination for periodic snapshots.
+pub trait Exporter: Send {
+    fn export(&mut self, snapshot: &Snapshot) -> std::io::Result<()>;
+}
+
+/// Writes each snapshot as one `info` log line.
+#[derive(Debug, Default)]
+pub struct LogExporter;
+
+impl Exporter for LogExporter {
+    fn export(&mut self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let mut line = String::new();
+        for (name, value) in &snapshot.counters {
+            let _ = write!(line, " {}={}", name, value);
+        }
+        for (name, value) in &snapshot.gauges {
+            let _ = write!(line, " {}={}", name, value);
+        }
+        for (name, histogram) in &snapshot.histograms {
+            let mean = if histogram.count == 0 {
+                0.0
+            } else {
+                histogram.sum / histogram.count as f64
+            };
+            let _ = write!(
+                line,
+                " {}.count={} {}.mean={:.3}",
+                name, histogram.count, name, mean
+            );
+        }
+        tracing::info!("metrics{}", line);
+        Ok(())
+    }
+}
+
+/// Sends snapshots to a statsd daemon over UDP. Counters go out as the
+/// change since the previous export, as statsd expects.
+#[derive(Debug)]
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    prefix: String,
+    last_counters: HashMap<String, u64>,
+}
+
+impl StatsdExporter {
+    /// `prefix` is prepended to every name, e.g. `wavry.relay.`.
+    pub fn new(addr: impl ToSocketAddrs, prefix: impl Into<String>) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.into(),
+            last_counters: HashMap::new(),
+        })
+    }
+
+    fn lines(&mut self, snapshot: &Snapshot) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (name, value) in &snapshot.counters {
+            let last = self.last_counters.insert(name.clone(), *value).unwrap_or(0);
+            let delta = value.saturating_sub(last);
+            if delta > 0 {
+                lines.push(format!("{}{}:{}|c", self.prefix, name, delta));
+            }
+        }
+        for (name, value) in &snapshot.gauges {
+            lines.push(format!("{}{}:{}|g", self.prefix, name, value));
+        }
+        // Statsd only takes raw samples, so histograms report their mean.
+        for (name, histogram) in &snapshot.histograms {
+            if histogram.count > 0 {
+                let mean = histogram.sum / histogram.count as f64;
+                lines.push(format!("{}{}:{}|ms", self.prefix, name, mean));
+            }
+        }
+        lines
+    }
+}
+
+impl Exporter for StatsdExporter {
+    fn export(&mut self, snapshot: &Snapshot) -> std::io::Result<()> {
+        // Keep datagrams under a typical MTU.
+        let mut packet = String::new();
+        for line in self.lines(snapshot) {
+            if !packet.is_empty() && packet.len() + line.len() + 1 > 1400 {
+                self.socket.send(packet.as_bytes())?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        if !packet.is_empty() {
+            self.socket.send(packet.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the latest Prometheus text rendering for an HTTP handler to serve.
+#[derive(Debug, Clone, Default)]
+pub struct PrometheusExporter {
+    latest: Arc<Mutex<String>>,
+}
+
+impl PrometheusExporter {
+    pub fn text(&self) -> String {
+        self.latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl Exporter for PrometheusExporter {
+    fn export(&mut self, snapshot: &Snapshot) -> std::io::Result<()> {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = render_prometheus(snapshot);
+        Ok(())
+    }
+}
+
+/// Renders a snapshot in the Prometheus text exposition format. Dots and
+/// other characters Prometheus rejects in names become underscores.
+pub fn render_prometheus(snapshot: &Snapshot) -> String {
+    render_prometheus_with_labels(snapshot, &[])
+}
+
+/// [`render_prometheus`] with `labels` on every sample, e.g. the id of the
+/// relay that exported them.
+pub fn render_prometheus_with_labels(snapshot: &Snapshot, labels: &[(&str, &str)]) -> String {
+    let mut constant = String::new();
+    for (key, value) in labels {
+        let _ = write!(
+            constant,
+            "{}=\"{}\",",
+            prometheus_name(key),
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+    }
+    let plain = if constant.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", constant.trim_end_matches(','))
+    };
+
+    let mut out = String::new();
+    for (name, value) in &snapshot.counters {
+        let name = prometheus_name(name);
+        let _ = writeln!(out, "# TYPE {} counter\n{}{} {}", name, name, plain, value);
+    }
+    for (name, value) in &snapshot.gauges {
+        let name = prometheus_name(name);
+        let _ = writeln!(out, "# TYPE {} gauge\n{}{} {}", name, name, plain, value);
+    }
+    for (name, histogram) in &snapshot.histograms {
+        let name = prometheus_name(name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, count) in HISTOGRAM_BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}le=\"{}\"}} {}",
+                name, constant, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}le=\"+Inf\"}} {}",
+            name, constant, histogram.count
+        );
+        let _ = writeln!(out, "{}_sum{} {}", name, plain, histogram.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, plain, histogram.count);
+    }
+    out
+}
+
+fn prometheus_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Exports a snapshot to every exporter on an interval until dropped.
+pub struct Reporter {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    pub fn start(interval: Duration, mut exporters: Vec<Box<dyn Exporter>>) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let snapshot = snapshot();
+                for exporter in &mut exporters {
+                    if let Err(e) = exporter.export(&snapshot) {
+                        tracing::debug!("metrics export failed: {}", e);
+                    }
+                }
+            }
+        });
+        Self {
+            stop: Some(stop_tx),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macros_share_registered_handles() {
+        for _ in 0..3 {
+            crate::counter!("test.macro_counter");
+        }
+        crate::counter!("test.macro_counter", 7);
+        crate::gauge!("test.macro_gauge", 2.5);
+        assert_eq!(counter("test.macro_counter").get(), 10);
+        assert_eq!(gauge("test.macro_gauge").get(), 2.5);
+
+        // A clashing kind gets a handle that is not exported.
+        histogram("test.macro_counter").record(1.0);
+        let snapshot = snapshot();
+        assert!(snapshot
+            .counters
+            .contains(&("test.macro_counter".to_string(), 10)));
+        assert!(!snapshot
+            .histograms
+            .iter()
+            .any(|(name, _)| name == "test.macro_counter"));
+    }
+
+    #[test]
+    fn histogram_renders_cumulative_buckets() {
+        let histogram = histogram("test.render_ms");
+        histogram.record(0.2);
+        histogram.record(3.0);
+        histogram.record(9_000.0);
+        histogram.record(f64::NAN);
+
+        let snapshot = Snapshot {
+            histograms: vec![("test.render_ms".into(), histogram.snapshot())],
+            ..Snapshot::default()
+        };
+        let text = render_prometheus(&snapshot);
+        assert!(text.contains("# TYPE test_render_ms histogram"));
+        assert!(text.contains("test_render_ms_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("test_render_ms_bucket{le=\"5\"} 2\n"));
+        assert!(text.contains("test_render_ms_bucket{le=\"5000\"} 2\n"));
+        assert!(text.contains("test_render_ms_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("test_render_ms_count 3\n"));
+    }
+
+    #[test]
+    fn constant_labels_go_on_every_sample() {
+        let snapshot = Snapshot {
+            counters: vec![("relay.packets_rx".into(), 4)],
+            histograms: vec![(
+                "relay.forward_ms".into(),
+                HistogramSnapshot {
+                    buckets: vec![0; HISTOGRAM_BUCKETS.len() + 1],
+                    count: 0,
+                    sum: 0.0,
+                },
+            )],
+            ..Snapshot::default()
+        };
+        let text = render_prometheus_with_labels(&snapshot, &[("relay_id", "eu-1")]);
+        assert!(text.contains("relay_packets_rx{relay_id=\"eu-1\"} 4\n"));
+        assert!(text.contains("relay_forward_ms_bucket{relay_id=\"eu-1\",le=\"0.5\"} 0\n"));
+        assert!(text.contains("relay_forward_ms_count{relay_id=\"eu-1\"} 0\n"));
+        assert!(render_prometheus(&snapshot).contains("relay_packets_rx 4\n"));
+    }
+
+    #[test]
+    fn statsd_sends_counter_deltas() {
+        let mut exporter = StatsdExporter::new("127.0.0.1:9", "wavry.").unwrap();
+        let mut snapshot = Snapshot {
+            counters: vec![("packets".into(), 5)],
+            gauges: vec![("sessions".into(), 2.0)],
+            ..Snapshot::default()
+        };
+        assert_eq!(
+            exporter.lines(&snapshot),
+            vec!["wavry.packets:5|c", "wavry.sessions:2|g"]
+        );
+        snapshot.counters[0].1 = 8;
+        assert_eq!(exporter.lines(&snapshot)[0], "wavry.packets:3|c");
+    }
+}
@@ -8,16 +8,40 @@ pub enum SignalMessage {
     /// Initial binding of a connection to a specific session/token.
     BIND { token: String },
 
+    /// Bind as a guest of the host an invite names; the connection may then
+    /// only signal that host.
+    BIND_INVITE { invite: String },
+
+    /// Ask the server to mint an invite to this connection's user.
+    CREATE_INVITE {
+        #[serde(default)]
+        ttl_secs: Option<u64>,
+    },
+
+    /// A minted invite. `url` is set when the server knows its public address.
+    INVITE {
+        token: String,
+        #[serde(default)]
+        url: Option<String>,
+        expires_at: u64,
+    },
+
     /// RIFT-v1 SDP Exchange: OFFER (base64 encoded rift::Hello)
     OFFER_RIFT {
         target_username: String,
         hello_base64: String,
+        /// Where the client's session socket can be reached.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        candidates: Vec<IceCandidate>,
     },
 
     /// RIFT-v1 SDP Exchange: ANSWER (base64 encoded rift::HelloAck)
     ANSWER_RIFT {
         target_username: String,
         ack_base64: String,
+        /// Where the host's socket can be reached.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        candidates: Vec<IceCandidate>,
     },
 
     /// WebRTC-style OFFER (legacy/fallback)
@@ -55,10 +79,65 @@ pub enum SignalMessage {
         session_id: uuid::Uuid,
     },
 
+    /// Ask the gateway to wake `target_username`'s hosts registered for
+    /// wake-on-LAN; all of them unless `host_name` picks one.
+    REQUEST_WAKE {
+        target_username: String,
+        #[serde(default)]
+        host_name: Option<String>,
+    },
+
+    /// Offer to send magic packets on this connection's LAN.
+    WAKE_AGENT,
+
+    /// Sent to a wake agent: broadcast a magic packet for `mac_address`.
+    WAKE_HOST {
+        mac_address: String,
+        #[serde(default)]
+        broadcast_addr: Option<String>,
+    },
+
+    /// Ask whether `target_username` holds a signaling connection.
+    QUERY_HOST { target_username: String },
+
+    /// Answer to `QUERY_HOST` and `REQUEST_WAKE`; `wake_agents` counts the
+    /// agents a wake request went out through.
+    HOST_STATUS {
+        target_username: String,
+        online: bool,
+        #[serde(default)]
+        wake_agents: u32,
+    },
+
     /// Generic error message from the signaling server.
     ERROR { code: Option<u16>, message: String },
 }
 
+/// How a peer learned one of its candidate addresses, in ICE terms.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum CandidateKind {
+    /// An address of a local interface.
+    Host,
+    /// The address a STUN server or router port mapping reported.
+    Srflx,
+    /// The address a peer saw a connectivity check arrive from.
+    Prflx,
+    /// A relay allocated for the session.
+    Relay,
+}
+
+/// A transport address a peer may be reachable on, exchanged in
+/// `OFFER_RIFT`/`ANSWER_RIFT` before connectivity checks pick a path.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct IceCandidate {
+    pub kind: CandidateKind,
+    /// `ip:port`, IPv6 in brackets.
+    pub addr: String,
+    /// RFC 8445 candidate priority; higher is preferred.
+    pub priority: u32,
+}
+
 /// Request for a relay to register with the Master server.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RelayRegisterRequest {
@@ -92,6 +171,20 @@ pub struct RelayHeartbeatRequest {
     pub load_pct: f32,
 }
 
+/// Bytes a relay forwarded for one user since its previous usage report.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RelayUsageEntry {
+    pub wavry_id: String,
+    pub bytes: u64,
+}
+
+/// Periodic per-user traffic report from a relay to the Master server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RelayUsageReport {
+    pub relay_id: String,
+    pub entries: Vec<RelayUsageEntry>,
+}
+
 /// Request for a user to register with a display name.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RegisterRequest {
@@ -115,3 +208,30 @@ pub struct RelayFeedbackRequest {
     pub issues: Vec<String>,
     pub signature: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_without_candidates_still_parse() {
+        let legacy = r#"{"type":"OFFER_RIFT","target_username":"host","hello_base64":"AA=="}"#;
+        let SignalMessage::OFFER_RIFT { candidates, .. } = serde_json::from_str(legacy).unwrap()
+        else {
+            panic!("expected OFFER_RIFT");
+        };
+        assert!(candidates.is_empty());
+
+        let answer = SignalMessage::ANSWER_RIFT {
+            target_username: "client".into(),
+            ack_base64: "AA==".into(),
+            candidates: vec![IceCandidate {
+                kind: CandidateKind::Srflx,
+                addr: "203.0.113.7:40000".into(),
+                priority: 1_694_498_815,
+            }],
+        };
+        let json = serde_json::to_string(&answer).unwrap();
+        assert!(json.contains(r#""kind":"srflx""#), "{}", json);
+    }
+}
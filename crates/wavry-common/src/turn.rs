@@ -0,0 +1,78 @@
+//! Ephemeral TURN credentials using the TURN REST API scheme.
+//!
+//! The master and the TURN server share a secret; the username embeds an
+//! expiry and the password is `base64(HMAC-SHA1(secret, username))`, so the
+//! TURN server (coturn `use-auth-secret`) can verify it without a database.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use std::time::Duration;
+
+/// Default lifetime of minted TURN credentials.
+pub const DEFAULT_TURN_CREDENTIAL_TTL: Duration = Duration::from_secs(3600);
+
+/// Credentials for one or more TURN URLs, shaped like a WebRTC `RTCIceServer`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnCredentials {
+    pub urls: Vec<String>,
+    pub username: String,
+    pub credential: String,
+    pub ttl_secs: u64,
+}
+
+/// Mint credentials for `user`, valid until `now_unix + ttl`.
+pub fn mint_turn_credentials(
+    shared_secret: &str,
+    user: &str,
+    urls: Vec<String>,
+    ttl: Duration,
+    now_unix: u64,
+) -> TurnCredentials {
+    let expires_at = now_unix.saturating_add(ttl.as_secs());
+    let username = format!("{expires_at}:{user}");
+    let mut mac = Hmac::<Sha1>::new_from_slice(shared_secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    TurnCredentials {
+        urls,
+        username,
+        credential: STANDARD.encode(mac.finalize().into_bytes()),
+        ttl_secs: ttl.as_secs(),
+    }
+}
+
+/// Parse a comma-separated list of `turn:`/`turns:` URLs, dropping anything else.
+pub fn parse_turn_urls(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|url| url.starts_with("turn:") || url.starts_with("turns:"))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_follow_turn_rest_scheme() {
+        let creds = mint_turn_credentials(
+            "turn-secret",
+            "alice",
+            vec!["turn:turn.example.com:3478".into()],
+            Duration::from_secs(3600),
+            1_700_000_000,
+        );
+        assert_eq!(creds.username, "1700003600:alice");
+        assert_eq!(creds.credential, "E4vPY/Y6LWBxrSVVafd9LKs2lHA=");
+        assert_eq!(creds.ttl_secs, 3600);
+    }
+
+    #[test]
+    fn parse_turn_urls_keeps_only_turn_schemes() {
+        let urls = parse_turn_urls(" turn:a:3478, stun:b:3478 ,turns:c:5349,,");
+        assert_eq!(urls, vec!["turn:a:3478", "turns:c:5349"]);
+    }
+}
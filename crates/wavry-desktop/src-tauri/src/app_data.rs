@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+/// Path of a file stored directly under the app data directory.
+pub fn app_data_file(app_handle: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    let app_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?;
+    Ok(app_dir.join(name))
+}
+
+/// Write to a sibling file first so a crash never leaves a truncated store behind.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    std::fs::write(&tmp_path, contents)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
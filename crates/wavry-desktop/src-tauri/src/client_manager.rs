@@ -1,43 +1,254 @@
-use crate::state::{ClientSessionState, CLIENT_SESSION_STATE};
-use tokio::sync::{broadcast, mpsc, oneshot};
-use wavry_client::{run_client_with_shutdown, ClientConfig, FileTransferCommand};
-
-pub fn register_client_session(
-    stop_tx: oneshot::Sender<()>,
-    monitor_tx: mpsc::UnboundedSender<u32>,
-    file_command_tx: broadcast::Sender<FileTransferCommand>,
-) -> Result<(), String> {
-    let mut state = CLIENT_SESSION_STATE.lock().unwrap();
-    if state.is_some() {
-        return Err("Client session already active".into());
-    }
-    *state = Some(ClientSessionState {
-        stop_tx: Some(stop_tx),
-        monitor_tx: Some(monitor_tx),
-        file_command_tx: Some(file_command_tx),
-    });
-    Ok(())
+use crate::app_data;
+use crate::file_transfer;
+use crate::history::{self, ConnectionRecord, ConnectionTarget, QualitySummary};
+use crate::state::{ClientSessionState, CLIENT_SESSIONS};
+use rift_core::PermissionSet;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use wavry_client::{ClientRuntimeStats, ClipboardSyncDirection, LatencyBreakdown, LatencySummary};
+use wavry_sdk::{ClientSessionBuilder, SessionEvent};
+
+pub const CLIENT_SESSION_ENDED_EVENT: &str = "client-session-ended";
+
+/// Serializable view of a running client session.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientSessionInfo {
+    pub session_id: String,
+    pub target: ConnectionTarget,
+    pub started_at_unix_ms: u64,
+    pub connected: bool,
+    pub frames_decoded: u64,
+    pub latency: Option<LatencyBreakdown>,
+    pub latency_summary: Option<LatencySummary>,
+    pub permissions: SessionPermissions,
+}
+
+/// Features the host granted, so the UI can disable the rest.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SessionPermissions {
+    pub input: bool,
+    pub clipboard: bool,
+    pub file_transfer: bool,
+}
+
+impl From<PermissionSet> for SessionPermissions {
+    fn from(set: PermissionSet) -> Self {
+        Self {
+            input: set.contains(PermissionSet::INPUT),
+            clipboard: set.contains(PermissionSet::CLIPBOARD),
+            file_transfer: set.contains(PermissionSet::FILE_TRANSFER),
+        }
+    }
+}
+
+impl ClientSessionInfo {
+    fn new(session_id: &str, session: &ClientSessionState) -> Self {
+        let stats = session.session.stats();
+        Self {
+            session_id: session_id.to_string(),
+            target: session.target.clone(),
+            started_at_unix_ms: session.started_at_unix_ms,
+            connected: stats.connected,
+            frames_decoded: stats.frames_decoded,
+            latency: stats.latency,
+            latency_summary: stats.latency_summary,
+            permissions: session.session.permissions().into(),
+        }
+    }
+}
+
+pub fn register_client_session(session_id: String, session: ClientSessionState) {
+    CLIENT_SESSIONS.lock().unwrap().insert(session_id, session);
 }
 
-pub fn clear_client_session() {
-    if let Ok(mut state) = CLIENT_SESSION_STATE.lock() {
-        *state = None;
+pub fn clear_client_session(session_id: &str) {
+    if let Ok(mut sessions) = CLIENT_SESSIONS.lock() {
+        sessions.remove(session_id);
     }
 }
 
-pub fn spawn_client_session(mut config: ClientConfig) -> Result<(), String> {
-    let (stop_tx, stop_rx) = oneshot::channel::<()>();
-    let (monitor_tx, monitor_rx) = mpsc::unbounded_channel::<u32>();
-    let (file_command_tx, _file_command_rx) = broadcast::channel::<FileTransferCommand>(64);
-    config.file_command_bus = Some(file_command_tx.clone());
-    register_client_session(stop_tx, monitor_tx, file_command_tx)?;
+/// Pick the session a command applies to. Without an explicit id the command
+/// only resolves when exactly one session is running.
+fn resolve_session_id<'a>(
+    mut ids: impl ExactSizeIterator<Item = &'a String>,
+    requested: Option<&str>,
+) -> Result<String, String> {
+    if let Some(requested) = requested {
+        return ids
+            .find(|id| id.as_str() == requested)
+            .cloned()
+            .ok_or_else(|| format!("Unknown client session {}", requested));
+    }
+    match ids.len() {
+        0 => Err("No active client session".into()),
+        1 => Ok(ids.next().cloned().unwrap_or_default()),
+        _ => Err("Multiple client sessions active; specify a session id".into()),
+    }
+}
+
+/// Run `f` against the session selected by `session_id`.
+pub fn with_client_session<T>(
+    session_id: Option<&str>,
+    f: impl FnOnce(&str, &mut ClientSessionState) -> T,
+) -> Result<T, String> {
+    let mut sessions = CLIENT_SESSIONS.lock().unwrap();
+    let id = resolve_session_id(sessions.keys(), session_id)?;
+    let session = sessions
+        .get_mut(&id)
+        .ok_or_else(|| format!("Unknown client session {}", id))?;
+    Ok(f(&id, session))
+}
 
+pub fn list_client_sessions() -> Vec<ClientSessionInfo> {
+    CLIENT_SESSIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, session)| ClientSessionInfo::new(id, session))
+        .collect()
+}
+
+pub fn client_session_info(session_id: Option<&str>) -> Result<ClientSessionInfo, String> {
+    with_client_session(session_id, |id, session| {
+        ClientSessionInfo::new(id, session)
+    })
+}
+
+fn quality_summary(stats: &ClientRuntimeStats, elapsed_ms: u64) -> QualitySummary {
+    let frames_decoded = stats.frames_decoded.load(Ordering::Relaxed);
+    let average_fps = if elapsed_ms > 0 {
+        frames_decoded as f32 * 1000.0 / elapsed_ms as f32
+    } else {
+        0.0
+    };
+    QualitySummary {
+        connected: frames_decoded > 0 || stats.connected.load(Ordering::Relaxed),
+        frames_decoded,
+        average_fps,
+    }
+}
+
+/// Client recordings go to the user's videos folder when there is one.
+fn recordings_dir(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    match app_handle.path().video_dir() {
+        Ok(dir) => Ok(dir.join("Wavry")),
+        Err(_) => app_data::app_data_file(app_handle, "recordings"),
+    }
+}
+
+/// Start a client session and return its id.
+pub fn spawn_client_session(
+    app_handle: &tauri::AppHandle,
+    builder: ClientSessionBuilder,
+    target: ConnectionTarget,
+) -> Result<String, String> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    // Clipboard contents only leave the machine once the user opts in for this
+    // session, and local recording stays off until the user starts it.
+    let mut session = builder
+        .clipboard_sync(ClipboardSyncDirection::Disabled)
+        .recorder(wavry_media::RecorderConfig {
+            enabled: false,
+            output_dir: recordings_dir(app_handle)?,
+            container: wavry_media::Container::Matroska,
+            ..Default::default()
+        })
+        .start()
+        .map_err(|e| e.to_string())?;
+    let mut events = session
+        .take_events()
+        .ok_or_else(|| "Client session events unavailable".to_string())?;
+    let runtime_stats = session.runtime_stats().clone();
+    file_transfer::spawn_event_forwarder(
+        app_handle.clone(),
+        session_id.clone(),
+        session.file_events(),
+    );
+    let started_at_unix_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let started = Instant::now();
+    register_client_session(
+        session_id.clone(),
+        ClientSessionState {
+            target: target.clone(),
+            started_at_unix_ms,
+            session,
+        },
+    );
+
+    let history_path = history::history_path(app_handle)
+        .map_err(|e| log::warn!("Connection history unavailable: {}", e))
+        .ok();
+
+    let client_session_id = session_id.clone();
+    let app_handle = app_handle.clone();
     tauri::async_runtime::spawn(async move {
-        if let Err(e) = run_client_with_shutdown(config, None, stop_rx, Some(monitor_rx)).await {
-            log::error!("Client error: {}", e);
+        while let Some(event) = events.recv().await {
+            match event {
+                SessionEvent::Connected => {
+                    log::info!("Client session {} connected", client_session_id)
+                }
+                SessionEvent::Disconnected => {
+                    log::warn!("Client session {} lost the host", client_session_id)
+                }
+                // Only hosts hold devices for approval.
+                SessionEvent::PendingApproval { .. } => {}
+                SessionEvent::Ended { error } => {
+                    if let Some(e) = error {
+                        log::error!("Client error: {}", e);
+                    }
+                    break;
+                }
+            }
+        }
+        clear_client_session(&client_session_id);
+        let _ = tauri::Emitter::emit(
+            &app_handle,
+            CLIENT_SESSION_ENDED_EVENT,
+            client_session_id.as_str(),
+        );
+
+        if let Some(path) = history_path {
+            let duration_ms = started.elapsed().as_millis() as u64;
+            let record = ConnectionRecord {
+                id: uuid::Uuid::new_v4().to_string(),
+                target,
+                started_at_unix_ms,
+                duration_ms,
+                quality: quality_summary(&runtime_stats, duration_ms),
+                pinned: false,
+                mac_address: None,
+            };
+            if let Err(e) = history::update(&path, |h| {
+                h.record(record);
+                Ok(())
+            }) {
+                log::warn!("Failed to record connection history: {}", e);
+            }
         }
-        clear_client_session();
     });
 
-    Ok(())
+    Ok(session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_session_id;
+
+    #[test]
+    fn resolve_session_id_requires_id_when_ambiguous() {
+        let none: Vec<String> = Vec::new();
+        let one = vec!["a".to_string()];
+        let two = vec!["a".to_string(), "b".to_string()];
+
+        assert!(resolve_session_id(none.iter(), None).is_err());
+        assert_eq!(resolve_session_id(one.iter(), None).unwrap(), "a");
+        assert!(resolve_session_id(two.iter(), None).is_err());
+        assert_eq!(resolve_session_id(two.iter(), Some("b")).unwrap(), "b");
+        assert!(resolve_session_id(two.iter(), Some("c")).is_err());
+    }
 }
@@ -1,26 +1,34 @@
 use crate::auth::{
     get_or_create_identity, normalize_auth_server, parse_login_payload, signaling_ws_url_for_server,
 };
-use crate::client_manager::spawn_client_session;
+use crate::client_manager::{self, spawn_client_session, with_client_session, ClientSessionInfo};
+use crate::file_transfer;
+use crate::history::{self, ConnectionRecord, ConnectionTarget};
+use crate::host_config::HostConfig;
+use crate::monitor_watch;
+use crate::offer_approval::{send_device_decision, send_offer_decision};
+use crate::relay_fallback::{self, emit_progress, ConnectStage};
 use crate::secure_storage;
-use crate::state::{AuthState, AUTH_STATE, CLIENT_SESSION_STATE, SESSION_STATE};
+use crate::settings::{self, DesktopSettings};
+use crate::state::{AuthState, AUTH_STATE, BACKGROUND_HOSTING, CLIENT_SESSIONS, SESSION_STATE};
+use crate::tray::{self, HostStatus};
+use rift_core::ice::CandidateKind;
 use std::net::SocketAddr;
-use std::str::FromStr;
-use wavry_client::{ClientConfig, FileTransferAction, FileTransferCommand};
-use wavry_media::CapabilityProbe;
-
-#[cfg(target_os = "macos")]
-use wavry_media::MacProbe;
-#[cfg(target_os = "windows")]
-use wavry_media::WindowsProbe;
+use wavry_client::lease::{RelayLeaseSource, SignalingAuth};
+use wavry_client::{
+    wake, ClientRuntimeStats, ClipboardSyncDirection, FileTransferAction, FileTransferCommand,
+};
+use wavry_sdk::ClientSession;
+
 #[cfg(target_os = "linux")]
-use wavry_media::{linux_runtime_diagnostics, LinuxProbe, PipewireAudioCapturer, PipewireEncoder};
+use wavry_media::{linux_runtime_diagnostics, CapabilityProbe, LinuxProbe};
 
 #[cfg(target_os = "linux")]
 use serde::Serialize;
 use serde_json::json;
 use std::sync::atomic::Ordering;
-#[cfg(target_os = "linux")]
+use std::sync::Arc;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
 use tokio::sync::{mpsc, oneshot};
 
 #[cfg(target_os = "linux")]
@@ -34,7 +42,7 @@ pub struct LinuxHostPreflight {
 }
 
 #[cfg(target_os = "linux")]
-fn sanitize_linux_capture_resolution(
+pub(crate) fn sanitize_linux_capture_resolution(
     resolution: wavry_media::Resolution,
 ) -> wavry_media::Resolution {
     // H.264 paths generally expect even dimensions; clamp to safe non-zero values.
@@ -79,7 +87,7 @@ fn select_linux_display(
 }
 
 #[cfg(target_os = "linux")]
-fn linux_host_preflight_impl(
+pub(crate) fn linux_host_preflight_impl(
     requested_display_id: Option<u32>,
 ) -> Result<LinuxHostPreflight, String> {
     let diagnostics = linux_runtime_diagnostics().map_err(|e| {
@@ -155,16 +163,101 @@ pub async fn set_cc_config(config: rift_core::cc::DeltaConfig) -> Result<(), Str
     Ok(())
 }
 
+const MIN_BANDWIDTH_LIMIT_KBPS: u32 = 500;
+const MAX_BANDWIDTH_LIMIT_KBPS: u32 = 200_000;
+/// Largest file a desktop client session accepts from the host.
+const DESKTOP_FILE_MAX_BYTES: u64 = 1_073_741_824;
+/// How long a LAN scan listens for mDNS answers by default.
+const LAN_SCAN_MS: u64 = 2_000;
+
+/// Throttle running sessions: caps the DeltaCC ceiling when hosting and asks
+/// the host to lower its target for client sessions. Without a session id the
+/// limit applies to the host and every client session.
+#[tauri::command]
+pub async fn set_bandwidth_limit(kbps: u32, session_id: Option<String>) -> Result<(), String> {
+    if !(MIN_BANDWIDTH_LIMIT_KBPS..=MAX_BANDWIDTH_LIMIT_KBPS).contains(&kbps) {
+        return Err(format!(
+            "Bandwidth limit must be between {} and {} kbps",
+            MIN_BANDWIDTH_LIMIT_KBPS, MAX_BANDWIDTH_LIMIT_KBPS
+        ));
+    }
+
+    let host_tx = if session_id.is_some() {
+        None
+    } else {
+        SESSION_STATE
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|s| s.bandwidth_limit_tx.clone())
+    };
+    let client_results = match session_id.as_deref() {
+        Some(id) => vec![with_client_session(Some(id), |_, s| {
+            s.session.set_bandwidth_limit(kbps)
+        })?],
+        None => CLIENT_SESSIONS
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.session.set_bandwidth_limit(kbps))
+            .collect(),
+    };
+    if host_tx.is_none() && client_results.is_empty() {
+        return Err("No active session".into());
+    }
+
+    if let Some(tx) = host_tx {
+        tx.send(kbps)
+            .map_err(|_| "Host session is no longer running".to_string())?;
+    }
+    for result in client_results {
+        result.map_err(|_| "Client session is no longer running".to_string())?;
+    }
+    Ok(())
+}
+
+/// Start or stop recording the received stream of client sessions. Without
+/// a session id every client session is switched.
+#[tauri::command]
+pub async fn set_local_recording(enabled: bool, session_id: Option<String>) -> Result<(), String> {
+    let results: Vec<_> = match session_id.as_deref() {
+        Some(id) => vec![with_client_session(Some(id), |_, s| {
+            s.session.set_local_recording(enabled)
+        })?],
+        None => CLIENT_SESSIONS
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.session.set_local_recording(enabled))
+            .collect(),
+    };
+    if results.is_empty() {
+        return Err("No active client session".into());
+    }
+
+    for result in results {
+        result.map_err(|_| "Client session is no longer running".to_string())?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_cc_stats() -> Result<serde_json::Value, String> {
     if let Ok(state) = SESSION_STATE.lock() {
         if let Some(ref s) = *state {
             return Ok(json!({
-                "bitrate_kbps": s.current_bitrate.load(Ordering::Relaxed),
-                "state": s.cc_state.lock().unwrap().clone(),
+                "bitrate_kbps": s.counters.target_bitrate_kbps(),
+                "state": s.cc_state_label(),
             }));
         }
     }
+    // Viewers have no congestion controller; report where frames spend their time.
+    if let Ok(info) = client_manager::client_session_info(None) {
+        return Ok(json!({
+            "latency": info.latency,
+            "latency_summary": info.latency_summary,
+        }));
+    }
     Err("No active session".into())
 }
 
@@ -300,6 +393,138 @@ pub async fn login_full(
     }
 }
 
+/// Prefix search over the gateway's public user directory for connect autocomplete.
+#[tauri::command]
+pub async fn search_directory(
+    query: String,
+    cursor: Option<String>,
+    server: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let token = AUTH_STATE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|auth| auth.token.clone())
+        .ok_or("Not signed in")?;
+    let auth_server = normalize_auth_server(server);
+
+    let mut params = vec![("q", query)];
+    if let Some(cursor) = cursor {
+        params.push(("cursor", cursor));
+    }
+    let res = reqwest::Client::new()
+        .get(format!("{}/v1/directory/search", auth_server))
+        .bearer_auth(token)
+        .query(&params)
+        .send()
+        .await
+        .map_err(|e: reqwest::Error| e.to_string())?;
+
+    if res.status().is_success() {
+        res.json().await.map_err(|e: reqwest::Error| e.to_string())
+    } else {
+        let body: serde_json::Value = res.json().await.unwrap_or_default();
+        let err = body
+            .get("error")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Directory search failed");
+        Err(err.to_string())
+    }
+}
+
+/// Registers this machine's MAC with the gateway so signed-in clients can
+/// wake it through a wake agent on its LAN.
+#[tauri::command]
+pub async fn register_wake_host(
+    name: String,
+    mac: String,
+    broadcast_addr: Option<String>,
+    server: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let mac = wake::format_mac(&wake::parse_mac(&mac).map_err(|e| e.to_string())?);
+    let res = gateway_request(reqwest::Method::PUT, "/v1/hosts", server)?
+        .json(&json!({
+            "name": name,
+            "mac_address": mac,
+            "broadcast_addr": broadcast_addr,
+        }))
+        .send()
+        .await
+        .map_err(|e: reqwest::Error| e.to_string())?;
+    gateway_json(res, "Host registration failed").await
+}
+
+/// Hosts this account registered for wake-on-LAN.
+#[tauri::command]
+pub async fn list_wake_hosts(server: Option<String>) -> Result<serde_json::Value, String> {
+    let res = gateway_request(reqwest::Method::GET, "/v1/hosts", server)?
+        .send()
+        .await
+        .map_err(|e: reqwest::Error| e.to_string())?;
+    gateway_json(res, "Listing registered hosts failed").await
+}
+
+#[tauri::command]
+pub async fn unregister_wake_host(name: String, server: Option<String>) -> Result<(), String> {
+    let res = gateway_request(reqwest::Method::DELETE, "/v1/hosts", server)?
+        .query(&[("name", name)])
+        .send()
+        .await
+        .map_err(|e: reqwest::Error| e.to_string())?;
+    if res.status().is_success() {
+        Ok(())
+    } else {
+        gateway_json(res, "Host removal failed").await.map(|_| ())
+    }
+}
+
+/// Mint an invite link to this account's host for someone without an account.
+#[tauri::command]
+pub async fn create_invite(
+    ttl_secs: Option<u64>,
+    server: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let res = gateway_request(reqwest::Method::POST, "/v1/invites", server)?
+        .json(&json!({ "ttl_secs": ttl_secs }))
+        .send()
+        .await
+        .map_err(|e: reqwest::Error| e.to_string())?;
+    gateway_json(res, "Creating the invite failed").await
+}
+
+fn gateway_request(
+    method: reqwest::Method,
+    path: &str,
+    server: Option<String>,
+) -> Result<reqwest::RequestBuilder, String> {
+    let token = AUTH_STATE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|auth| auth.token.clone())
+        .ok_or("Not signed in")?;
+    let auth_server = normalize_auth_server(server);
+    Ok(reqwest::Client::new()
+        .request(method, format!("{}{}", auth_server, path))
+        .bearer_auth(token))
+}
+
+async fn gateway_json(
+    res: reqwest::Response,
+    fallback_error: &str,
+) -> Result<serde_json::Value, String> {
+    if res.status().is_success() {
+        res.json().await.map_err(|e: reqwest::Error| e.to_string())
+    } else {
+        let body: serde_json::Value = res.json().await.unwrap_or_default();
+        let err = body
+            .get("error")
+            .and_then(|v| v.as_str())
+            .unwrap_or(fallback_error);
+        Err(err.to_string())
+    }
+}
+
 #[tauri::command]
 pub async fn set_signaling_token(
     token: Option<String>,
@@ -352,21 +577,116 @@ pub fn delete_secure_data(key: String) -> Result<(), String> {
     secure_storage::delete_data(&key)
 }
 
+#[tauri::command]
+pub fn load_settings(app_handle: tauri::AppHandle) -> Result<DesktopSettings, String> {
+    settings::load_from(&settings::settings_path(&app_handle)?)
+}
+
+#[tauri::command]
+pub fn save_settings(
+    app_handle: tauri::AppHandle,
+    settings: DesktopSettings,
+) -> Result<DesktopSettings, String> {
+    let settings = settings.sanitized();
+    settings::save_to(&settings::settings_path(&app_handle)?, &settings)?;
+    BACKGROUND_HOSTING.store(settings.background_hosting, Ordering::Relaxed);
+    Ok(settings)
+}
+
+#[tauri::command]
+pub fn list_connection_history(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<ConnectionRecord>, String> {
+    let path = history::history_path(&app_handle)?;
+    let _guard = history::HISTORY_LOCK.lock().unwrap();
+    Ok(history::load_from(&path)?.sorted())
+}
+
+#[tauri::command]
+pub fn pin_connection(
+    app_handle: tauri::AppHandle,
+    id: String,
+    pinned: bool,
+) -> Result<(), String> {
+    history::update(&history::history_path(&app_handle)?, |h| {
+        h.set_pinned(&id, pinned)
+    })
+}
+
+#[tauri::command]
+pub fn delete_connection(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    history::update(&history::history_path(&app_handle)?, |h| h.remove(&id))
+}
+
+#[tauri::command]
+pub fn set_connection_mac(
+    app_handle: tauri::AppHandle,
+    id: String,
+    mac: Option<String>,
+) -> Result<(), String> {
+    let mac = match mac.as_deref().map(str::trim).filter(|m| !m.is_empty()) {
+        Some(mac) => Some(wake::format_mac(
+            &wake::parse_mac(mac).map_err(|e| e.to_string())?,
+        )),
+        None => None,
+    };
+    history::update(&history::history_path(&app_handle)?, |h| {
+        h.set_mac_address(&id, mac)
+    })
+}
+
+/// Send a Wake-on-LAN magic packet so a sleeping host can be reached.
+#[tauri::command]
+pub async fn wake_host(mac: String, broadcast_addr: Option<String>) -> Result<(), String> {
+    let mac_bytes = wake::parse_mac(&mac).map_err(|e| e.to_string())?;
+    let target =
+        wake::parse_broadcast_addr(broadcast_addr.as_deref()).map_err(|e| e.to_string())?;
+    wake::send_magic_packet(&mac_bytes, target).map_err(|e| e.to_string())?;
+    log::info!(
+        "Sent Wake-on-LAN packet for {} to {}",
+        wake::format_mac(&mac_bytes),
+        target
+    );
+    Ok(())
+}
+
+/// Hosts advertising themselves on the LAN, gathered for `timeout_ms`.
+#[tauri::command]
+pub async fn list_lan_hosts(
+    timeout_ms: Option<u64>,
+) -> Result<Vec<wavry_client::DiscoveredHost>, String> {
+    let window = std::time::Duration::from_millis(timeout_ms.unwrap_or(LAN_SCAN_MS).min(10_000));
+    let hosts = wavry_client::discovery::list_hosts(window)
+        .await
+        .map_err(|e| format!("LAN discovery failed: {}", e))?;
+    log::info!("Found {} host(s) on the LAN", hosts.len());
+    Ok(hosts)
+}
+
 #[tauri::command]
 pub async fn start_session(
+    app_handle: tauri::AppHandle,
     addr: String,
     resolution_mode: String,
     width: Option<u32>,
     height: Option<u32>,
     gamepad_enabled: Option<bool>,
     gamepad_deadzone: Option<f32>,
+    invite: Option<String>,
 ) -> Result<String, String> {
-    let socket_addr = if let Ok(s) = SocketAddr::from_str(&addr) {
-        Some(s)
-    } else if addr.is_empty() {
+    let invite = invite
+        .map(|link| link.trim().to_string())
+        .filter(|link| !link.is_empty())
+        .map(|link| {
+            wavry_client::invite::parse_invite_link(&link)
+                .map(|parsed| (link, parsed.host))
+                .map_err(|e| e.to_string())
+        })
+        .transpose()?;
+    let socket_addr = if addr.trim().is_empty() || invite.is_some() {
         None
     } else {
-        return Err("Invalid IP address".into());
+        Some(wavry_client::net::parse_host_addr(&addr).map_err(|e| e.to_string())?)
     };
 
     let max_resolution = match resolution_mode.as_str() {
@@ -384,61 +704,89 @@ pub async fn start_session(
         _ => None,
     };
 
-    let config = ClientConfig {
-        connect_addr: socket_addr,
-        client_name: "wavry-desktop".to_string(),
-        no_encrypt: false,
-        identity_key: None,
-        relay_info: None,
-        master_url: None, // Direct IP sessions don't usually need master feedback
-        max_resolution,
-        gamepad_enabled: gamepad_enabled.unwrap_or(true),
-        gamepad_deadzone: gamepad_deadzone.unwrap_or(0.1).clamp(0.0, 0.95),
-        vr_adapter: None,
-        runtime_stats: None,
-        recorder_config: None,
-        send_files: Vec::new(),
-        file_out_dir: std::path::PathBuf::from("received-files"),
-        file_max_bytes: 1_073_741_824,
-        file_command_bus: None,
-    };
+    // Hosts that approve devices recognise this machine by its identity.
+    let identity = get_or_create_identity(&app_handle)?;
 
-    spawn_client_session(config)?;
+    let saved_gamepad = settings::settings_path(&app_handle)
+        .and_then(|path| settings::load_from(&path))
+        .unwrap_or_default()
+        .gamepad;
+
+    // Direct IP sessions don't usually need master feedback.
+    let mut builder = ClientSession::builder("wavry-desktop")
+        .identity_key(identity.private_key_bytes())
+        .gamepad(
+            gamepad_enabled.unwrap_or(saved_gamepad.enabled),
+            gamepad_deadzone.unwrap_or(saved_gamepad.deadzone),
+        )
+        .file_max_bytes(DESKTOP_FILE_MAX_BYTES);
+    if let Some(addr) = socket_addr {
+        builder = builder.connect_addr(addr);
+    }
+    if let Some(resolution) = max_resolution {
+        builder = builder.max_resolution(resolution);
+    }
 
-    Ok("Session started".into())
+    let target = match invite {
+        Some((link, host)) => {
+            builder = builder.invite(link);
+            ConnectionTarget::Username(host)
+        }
+        None => ConnectionTarget::Address(addr),
+    };
+    spawn_client_session(&app_handle, builder, target)
 }
 
+/// Stop one client session, or all of them when no id is given.
 #[tauri::command]
-pub async fn stop_session() -> Result<String, String> {
-    let stop_tx = {
-        let mut state = CLIENT_SESSION_STATE.lock().unwrap();
-        state.as_mut().and_then(|s| s.stop_tx.take())
+pub async fn stop_session(session_id: Option<String>) -> Result<String, String> {
+    let count = {
+        let mut sessions = CLIENT_SESSIONS.lock().unwrap();
+        match session_id.as_deref() {
+            Some(id) => {
+                let session = sessions
+                    .get_mut(id)
+                    .ok_or_else(|| format!("Unknown client session {}", id))?;
+                usize::from(session.session.stop())
+            }
+            None => sessions.values_mut().filter(|s| s.session.stop()).count(),
+        }
     };
 
-    if let Some(tx) = stop_tx {
-        let _ = tx.send(());
-        Ok("Stopping client session".into())
-    } else {
-        Err("No active client session".into())
+    if count == 0 {
+        return Err("No active client session".into());
     }
+    Ok(if count == 1 {
+        "Stopping client session".into()
+    } else {
+        format!("Stopping {} client sessions", count)
+    })
+}
+
+#[tauri::command]
+pub fn list_client_sessions() -> Vec<ClientSessionInfo> {
+    client_manager::list_client_sessions()
+}
+
+#[tauri::command]
+pub fn get_session_stats(session_id: Option<String>) -> Result<ClientSessionInfo, String> {
+    client_manager::client_session_info(session_id.as_deref())
 }
 
 #[tauri::command]
-pub fn send_file_transfer_command(file_id: u64, action: String) -> Result<String, String> {
+pub fn send_file_transfer_command(
+    file_id: u64,
+    action: String,
+    session_id: Option<String>,
+) -> Result<String, String> {
     let action = action
         .parse::<FileTransferAction>()
         .map_err(|e| e.to_string())?;
-    let tx = {
-        let state = CLIENT_SESSION_STATE.lock().unwrap();
-        state.as_ref().and_then(|s| s.file_command_tx.clone())
-    };
-
-    let Some(tx) = tx else {
-        return Err("No active client session".into());
-    };
-
-    tx.send(FileTransferCommand { file_id, action })
-        .map_err(|e| format!("failed to enqueue file transfer command: {}", e))?;
+    with_client_session(session_id.as_deref(), |_, s| {
+        s.session
+            .file_command(FileTransferCommand { file_id, action })
+    })?
+    .map_err(|e| format!("failed to enqueue file transfer command: {}", e))?;
 
     Ok(format!(
         "queued file transfer command: file_id={} action={}",
@@ -447,14 +795,76 @@ pub fn send_file_transfer_command(file_id: u64, action: String) -> Result<String
 }
 
 #[tauri::command]
-pub async fn stop_host() -> Result<String, String> {
-    let stop_tx = {
-        let mut state = SESSION_STATE.lock().unwrap();
-        state.as_mut().and_then(|s| s.stop_tx.take())
-    };
+pub fn send_file(path: String, session_id: Option<String>) -> Result<u64, String> {
+    let path = std::path::PathBuf::from(path);
+    if !path.is_file() {
+        return Err(format!("Not a file: {}", path.display()));
+    }
+    let file_id = file_transfer::new_file_id();
+    with_client_session(session_id.as_deref(), |_, s| {
+        s.session.send_file(file_id, path)
+    })?
+    .map_err(|e| format!("failed to queue file for sending: {}", e))?;
+    Ok(file_id)
+}
+
+#[tauri::command]
+pub fn cancel_transfer(file_id: u64, session_id: Option<String>) -> Result<String, String> {
+    send_file_transfer_command(file_id, FileTransferAction::Cancel.to_string(), session_id)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClipboardSyncStatus {
+    pub session_id: String,
+    pub direction: String,
+    pub sent_updates: u64,
+    pub received_updates: u64,
+}
 
-    if let Some(tx) = stop_tx {
-        let _ = tx.send(());
+fn clipboard_sync_status(
+    session_id: &str,
+    control: &wavry_client::ClipboardSyncControl,
+) -> ClipboardSyncStatus {
+    ClipboardSyncStatus {
+        session_id: session_id.to_string(),
+        direction: control.direction().to_string(),
+        sent_updates: control.sent_updates.load(Ordering::Relaxed),
+        received_updates: control.received_updates.load(Ordering::Relaxed),
+    }
+}
+
+#[tauri::command]
+pub fn set_clipboard_sync(
+    app_handle: tauri::AppHandle,
+    direction: String,
+    session_id: Option<String>,
+) -> Result<ClipboardSyncStatus, String> {
+    let direction = direction
+        .parse::<ClipboardSyncDirection>()
+        .map_err(|e| e.to_string())?;
+    let (id, control) = with_client_session(session_id.as_deref(), |id, s| {
+        (id.to_string(), s.session.clipboard_sync().clone())
+    })?;
+
+    control.set_direction(direction);
+    log::info!("Clipboard sync for session {} set to {}", id, direction);
+    let status = clipboard_sync_status(&id, &control);
+    let _ = tauri::Emitter::emit(&app_handle, "clipboard-sync-changed", status.clone());
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn get_clipboard_sync_status(
+    session_id: Option<String>,
+) -> Result<ClipboardSyncStatus, String> {
+    with_client_session(session_id.as_deref(), |id, s| {
+        clipboard_sync_status(id, s.session.clipboard_sync())
+    })
+}
+
+#[tauri::command]
+pub async fn stop_host() -> Result<String, String> {
+    if tray::request_host_stop() {
         Ok("Stopping host".into())
     } else {
         Err("No active host session".into())
@@ -462,18 +872,42 @@ pub async fn stop_host() -> Result<String, String> {
 }
 
 #[tauri::command]
-pub async fn list_monitors() -> Result<Vec<wavry_media::DisplayInfo>, String> {
-    #[cfg(target_os = "macos")]
-    let probe = MacProbe;
-    #[cfg(target_os = "windows")]
-    let probe = WindowsProbe;
-    #[cfg(target_os = "linux")]
-    let probe = LinuxProbe;
+pub fn get_host_status() -> HostStatus {
+    tray::host_status()
+}
 
-    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
-    probe
-        .enumerate_displays()
-        .map_err(|e: anyhow::Error| e.to_string())
+#[tauri::command]
+pub fn set_background_hosting(enabled: bool) {
+    BACKGROUND_HOSTING.store(enabled, Ordering::Relaxed);
+}
+
+#[tauri::command]
+pub fn accept_offer(offer_id: String) -> Result<(), String> {
+    send_offer_decision(offer_id, true)
+}
+
+#[tauri::command]
+pub fn reject_offer(offer_id: String) -> Result<(), String> {
+    send_offer_decision(offer_id, false)
+}
+
+#[tauri::command]
+pub fn approve_device(wavry_id: String) -> Result<(), String> {
+    send_device_decision(wavry_id, true)
+}
+
+#[tauri::command]
+pub fn deny_device(wavry_id: String) -> Result<(), String> {
+    send_device_decision(wavry_id, false)
+}
+
+#[tauri::command]
+pub async fn list_monitors(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<wavry_media::DisplayInfo>, String> {
+    let displays = monitor_watch::enumerate_monitors()?;
+    monitor_watch::publish_monitors(&app_handle, &displays);
+    Ok(displays)
 }
 
 #[cfg(target_os = "linux")]
@@ -501,7 +935,11 @@ pub fn linux_host_preflight(_display_id: Option<u32>) -> Result<serde_json::Valu
 }
 
 #[tauri::command]
-pub async fn connect_via_id(target_username: String) -> Result<String, String> {
+pub async fn connect_via_id(
+    app_handle: tauri::AppHandle,
+    target_username: String,
+    wake_first: Option<bool>,
+) -> Result<String, String> {
     use wavry_client::signaling::{SignalMessage, SignalingClient};
 
     let (token, signaling_url) = {
@@ -519,39 +957,59 @@ pub async fn connect_via_id(target_username: String) -> Result<String, String> {
         .await
         .map_err(|e: anyhow::Error| format!("Signaling error: {}", e))?;
 
-    let udp = std::net::UdpSocket::bind("0.0.0.0:0").ok();
-    let public_addr = if let Some(ref s) = udp {
-        let tokio_u = tokio::net::UdpSocket::from_std(s.try_clone().unwrap()).ok();
-        if let Some(tu) = tokio_u {
-            wavry_client::discover_public_addr(&tu)
-                .await
-                .ok()
-                .map(|a: SocketAddr| a.to_string())
-        } else {
-            None
+    if wake_first.unwrap_or(false) {
+        emit_progress(&app_handle, &target_username, ConnectStage::Waking, None);
+        if let Err(e) = wake::wake_and_wait(&mut sig, &target_username, wake::WAKE_TIMEOUT).await {
+            let e = e.to_string();
+            emit_progress(
+                &app_handle,
+                &target_username,
+                ConnectStage::Failed,
+                Some(e.clone()),
+            );
+            return Err(e);
         }
-    } else {
-        None
-    };
+    }
+
+    // The session runs on this socket, so the candidates advertised to the
+    // host are the addresses it will actually be reached at.
+    let udp = wavry_client::net::bind_udp(wavry_client::net::dual_stack_any(0))
+        .map_err(|e| format!("Failed to bind UDP socket: {}", e))?;
+    let local_candidates = wavry_client::ice::gather_candidates(&udp).await;
+    let public_addr = local_candidates
+        .iter()
+        .find(|candidate| candidate.kind == CandidateKind::Srflx)
+        .map(|candidate| candidate.addr.clone());
 
     log::info!("Discovered public addr: {:?}", public_addr);
 
-    let hello_b64 = wavry_client::create_hello_base64("wavry-desktop".into(), public_addr)
+    let hello_b64 = wavry_client::create_hello_base64("wavry-desktop".into(), public_addr, vec![])
         .map_err(|e: anyhow::Error| e.to_string())?;
+    emit_progress(&app_handle, &target_username, ConnectStage::Signaling, None);
     sig.send(SignalMessage::OFFER_RIFT {
         target_username: target_username.clone(),
         hello_base64: hello_b64,
+        candidates: local_candidates,
     })
     .await
     .map_err(|e: anyhow::Error| e.to_string())?;
 
+    let relay_settings = settings::settings_path(&app_handle)
+        .and_then(|path| settings::load_from(&path))
+        .unwrap_or_default()
+        .relay;
+
     let wait_target = target_username.clone();
-    tokio::time::timeout(std::time::Duration::from_secs(20), async {
+    let answer = tokio::time::timeout(std::time::Duration::from_secs(20), async {
         let mut relay_info: Option<wavry_client::RelayInfo> = None;
 
         loop {
             match sig.recv().await {
-                Ok(SignalMessage::ANSWER_RIFT { ack_base64, .. }) => {
+                Ok(SignalMessage::ANSWER_RIFT {
+                    ack_base64,
+                    candidates,
+                    ..
+                }) => {
                     let ack = wavry_client::decode_hello_ack_base64(&ack_base64)
                         .map_err(|e: anyhow::Error| e.to_string())?;
                     log::info!(
@@ -561,99 +1019,14 @@ pub async fn connect_via_id(target_username: String) -> Result<String, String> {
                     );
 
                     if !ack.accepted {
-                        return Err("Connection rejected by host".into());
-                    }
-
-                    let connect_addr = if !ack.public_addr.is_empty() {
-                        ack.public_addr.parse::<std::net::SocketAddr>().ok()
-                    } else {
-                        None
-                    };
-
-                    if connect_addr.is_none() && relay_info.is_none() {
-                        log::info!(
-                            "Host {} did not provide direct endpoint; requesting relay fallback",
-                            target_username
-                        );
-                        sig.send(SignalMessage::REQUEST_RELAY {
-                            target_username: target_username.clone(),
-                            region: None,
-                        })
-                        .await
-                        .map_err(|e: anyhow::Error| format!("Failed to request relay: {}", e))?;
-
-                        let relay =
-                            tokio::time::timeout(std::time::Duration::from_secs(8), async {
-                                loop {
-                                    match sig.recv().await {
-                                        Ok(SignalMessage::RELAY_CREDENTIALS {
-                                            relay_id,
-                                            token,
-                                            addr,
-                                            session_id,
-                                        }) => {
-                                            let relay_addr = addr
-                                                .parse::<std::net::SocketAddr>()
-                                                .map_err(|_| {
-                                                "Relay credentials contained invalid relay address"
-                                                    .to_string()
-                                            })?;
-                                            break Ok(wavry_client::RelayInfo {
-                                                relay_id,
-                                                addr: relay_addr,
-                                                token,
-                                                session_id,
-                                            });
-                                        }
-                                        Ok(SignalMessage::ERROR { message, .. }) => {
-                                            break Err(message)
-                                        }
-                                        Ok(_) => continue,
-                                        Err(e) => break Err(e.to_string()),
-                                    }
-                                }
-                            })
-                            .await
-                            .map_err(|_| "Timed out waiting for relay credentials".to_string())??;
-
-                        relay_info = Some(relay);
+                        return Err("Connection rejected by host".to_string());
                     }
 
-                    if connect_addr.is_none() && relay_info.is_none() {
-                        return Err(
-                            "Host acknowledged request but no direct or relay route was provided"
-                                .into(),
-                        );
-                    }
-
-                    let master_url = if signaling_url.contains("/ws") {
-                        Some(signaling_url.replace("/ws", ""))
-                    } else {
-                        None
-                    };
-
-                    let config = wavry_client::ClientConfig {
-                        connect_addr,
-                        client_name: "wavry-desktop".into(),
-                        no_encrypt: false,
-                        identity_key: None,
-                        relay_info,
-                        master_url,
-                        max_resolution: None,
-                        gamepad_enabled: true,
-                        gamepad_deadzone: 0.1,
-                        vr_adapter: None,
-                        runtime_stats: None,
-                        recorder_config: None,
-                        send_files: Vec::new(),
-                        file_out_dir: std::path::PathBuf::from("received-files"),
-                        file_max_bytes: 1_073_741_824,
-                        file_command_bus: None,
-                    };
-
-                    spawn_client_session(config)?;
-
-                    return Ok("Connected".into());
+                    let direct_addrs: Vec<SocketAddr> = std::iter::once(&ack.public_addr)
+                        .chain(&ack.candidate_addrs)
+                        .filter_map(|addr| addr.parse().ok())
+                        .collect();
+                    break Ok((direct_addrs, candidates, relay_info));
                 }
                 Ok(SignalMessage::RELAY_CREDENTIALS {
                     relay_id,
@@ -662,13 +1035,10 @@ pub async fn connect_via_id(target_username: String) -> Result<String, String> {
                     session_id,
                 }) => {
                     log::info!("Received relay credentials: {} (id={})", addr, relay_id);
-                    if let Ok(relay_addr) = addr.parse::<std::net::SocketAddr>() {
-                        relay_info = Some(wavry_client::RelayInfo {
-                            relay_id,
-                            addr: relay_addr,
-                            token,
-                            session_id,
-                        });
+                    if let Ok(relay) = relay_fallback::relay_info_from_credentials(
+                        relay_id, token, &addr, session_id,
+                    ) {
+                        relay_info = Some(relay);
                     }
                 }
                 Ok(SignalMessage::ERROR { message, .. }) => return Err(message),
@@ -678,24 +1048,165 @@ pub async fn connect_via_id(target_username: String) -> Result<String, String> {
         }
     })
     .await
-    .map_err(|_| format!("Timed out waiting for {} to respond", wait_target))?
+    .map_err(|_| format!("Timed out waiting for {} to respond", wait_target));
+    let (direct_addrs, remote_candidates, mut relay_info) = match answer {
+        Ok(Ok(route)) => route,
+        Ok(Err(e)) | Err(e) => {
+            emit_progress(
+                &app_handle,
+                &target_username,
+                ConnectStage::Failed,
+                Some(e.clone()),
+            );
+            return Err(e);
+        }
+    };
+    emit_progress(
+        &app_handle,
+        &target_username,
+        ConnectStage::AnswerReceived,
+        None,
+    );
+
+    let master_url = if signaling_url.contains("/ws") {
+        Some(signaling_url.replace("/ws", ""))
+    } else {
+        None
+    };
+    let identity_key = get_or_create_identity(&app_handle)?.private_key_bytes();
+    let make_builder = |direct_addrs: &[SocketAddr],
+                        relay_info: Option<wavry_client::RelayInfo>,
+                        runtime_stats: Arc<ClientRuntimeStats>| {
+        let mut builder = ClientSession::builder("wavry-desktop")
+            .identity_key(identity_key)
+            .runtime_stats(runtime_stats)
+            .file_max_bytes(DESKTOP_FILE_MAX_BYTES);
+        if let Some((first, rest)) = direct_addrs.split_first() {
+            builder = builder.connect_addr(*first);
+            for addr in rest {
+                builder = builder.alternate_addr(*addr);
+            }
+        }
+        if let Some(relay) = relay_info {
+            builder = builder.relay(relay).relay_lease_source(RelayLeaseSource {
+                signaling_url: signaling_url.clone(),
+                auth: SignalingAuth::Token(token.clone()),
+                target_username: target_username.clone(),
+                region: relay_settings.preferred_region.clone(),
+            });
+        }
+        if let Some(url) = master_url.clone() {
+            builder = builder.master_url(url);
+        }
+        builder
+    };
+    let target = ConnectionTarget::Username(target_username.clone());
+
+    // Direct candidates and the relay race inside one session; the direct
+    // paths get a head start, so the relay only carries it when they fail.
+    let (direct_addrs, remote_candidates) = if relay_settings.force_relay {
+        (Vec::new(), Vec::new())
+    } else {
+        (direct_addrs, remote_candidates)
+    };
+    let has_direct = !direct_addrs.is_empty() || !remote_candidates.is_empty();
+    let use_relay = relay_settings.allow_relay || relay_settings.force_relay;
+    if !has_direct && !use_relay {
+        let message = "Host did not provide a direct endpoint and relay fallback is disabled";
+        emit_progress(
+            &app_handle,
+            &target_username,
+            ConnectStage::Failed,
+            Some(message.into()),
+        );
+        return Err(message.into());
+    }
+
+    if use_relay && relay_info.is_none() {
+        emit_progress(
+            &app_handle,
+            &target_username,
+            ConnectStage::RequestingRelay,
+            None,
+        );
+        match relay_fallback::request_relay_credentials(
+            &mut sig,
+            &target_username,
+            relay_settings.preferred_region.clone(),
+        )
+        .await
+        {
+            Ok(relay) => relay_info = Some(relay),
+            Err(e) if has_direct => {
+                log::warn!(
+                    "No relay for {}, trying direct only: {}",
+                    target_username,
+                    e
+                );
+            }
+            Err(e) => {
+                emit_progress(
+                    &app_handle,
+                    &target_username,
+                    ConnectStage::Failed,
+                    Some(e.clone()),
+                );
+                return Err(e);
+            }
+        }
+    }
+    let relay_info = relay_info.filter(|_| use_relay);
+
+    let (stage, detail) = if has_direct {
+        let first = direct_addrs
+            .first()
+            .map(SocketAddr::to_string)
+            .or_else(|| remote_candidates.first().map(|c| c.addr.clone()));
+        (ConnectStage::ProbingDirect, first)
+    } else {
+        (
+            ConnectStage::ConnectingRelay,
+            relay_info.as_ref().map(|r| r.addr.to_string()),
+        )
+    };
+    emit_progress(&app_handle, &target_username, stage, detail);
+
+    let stats = Arc::new(ClientRuntimeStats::default());
+    // Checked from the socket the candidates were gathered on.
+    let mut builder =
+        make_builder(&direct_addrs, relay_info, stats.clone()).remote_candidates(remote_candidates);
+    match udp.into_std() {
+        Ok(socket) => builder = builder.socket(socket),
+        Err(e) => log::warn!("Falling back to a fresh socket: {}", e),
+    }
+    let session_id = spawn_client_session(&app_handle, builder, target)?;
+    if relay_fallback::wait_for_connection(&stats, relay_fallback::CONNECT_PROGRESS_TIMEOUT).await {
+        emit_progress(&app_handle, &target_username, ConnectStage::Connected, None);
+    }
+    // Otherwise the session keeps retrying every path; the last stage stands.
+    Ok(session_id)
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
 #[tauri::command]
 pub async fn start_host(
     app_handle: tauri::AppHandle,
     port: u16,
-    display_id: Option<u32>,
+    config: Option<HostConfig>,
 ) -> Result<String, String> {
-    use crate::media_utils::choose_rift_codec;
-    use crate::state::SessionState;
-    use bytes::Bytes;
-    use std::net::UdpSocket;
-    use std::sync::atomic::AtomicU32;
-    use std::sync::{Arc, Mutex};
+    use crate::host_capture::{self, PlatformAudio, PlatformVideo};
+    use crate::host_config::rift_codec;
+    use crate::media_utils::local_supported_encoders;
+    use crate::offer_approval::{
+        DeviceApprovalEvent, IncomingOfferEvent, PendingOffer, PendingOffers,
+        DEVICE_APPROVAL_EVENT, INCOMING_OFFER_EVENT, OFFER_EXPIRED_EVENT,
+    };
+    use crate::state::{OfferDecision, SessionState};
+    use rift_crypto::authorized_clients::{AuthorizedClients, AUTHORIZED_CLIENTS_FILE};
+    use std::time::{Duration, Instant};
     use wavry_client::signaling::{SignalMessage, SignalingClient};
-    use wavry_media::{Codec, EncodeConfig, MediaError};
+    use wavry_sdk::host::{HostCounters, HostLoop};
+    use wavry_sdk::SessionEvent;
 
     {
         let state = SESSION_STATE.lock().unwrap();
@@ -704,42 +1215,71 @@ pub async fn start_host(
         }
     }
 
-    let preflight = linux_host_preflight_impl(display_id)?;
+    // Without a config from the UI, host with the saved preferences.
+    let host_config = config.unwrap_or_else(|| {
+        let saved = settings::settings_path(&app_handle)
+            .and_then(|path| settings::load_from(&path))
+            .unwrap_or_default();
+        HostConfig::from(&saved)
+    });
+    let codec = host_config.validate(&local_supported_encoders())?;
+    let display = host_capture::select_display(host_config.display_id)?;
 
     log::info!(
-        "Linux host capture using display id {} '{}' at {}x{}",
-        preflight.selected_display_id,
-        preflight.selected_display_name,
-        preflight.selected_resolution.width,
-        preflight.selected_resolution.height
+        "Host capture using display id {} '{}' at {}x{}",
+        display.id,
+        display.name,
+        display.resolution.width,
+        display.resolution.height
     );
 
-    let (cc_tx, mut cc_rx) = mpsc::unbounded_channel::<rift_core::cc::DeltaConfig>();
-    let current_bitrate = Arc::new(AtomicU32::new(8000));
-    let cc_state_shared = Arc::new(Mutex::new("Stable".to_string()));
+    let (cc_tx, cc_rx) = mpsc::unbounded_channel::<rift_core::cc::DeltaConfig>();
+    let counters = Arc::new(HostCounters::default());
 
     let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+    let (offer_decision_tx, mut offer_decision_rx) = mpsc::unbounded_channel::<OfferDecision>();
+    let (display_switch_tx, mut display_switch_rx) =
+        mpsc::unbounded_channel::<wavry_media::DisplayInfo>();
+    let (bandwidth_limit_tx, bandwidth_limit_rx) = mpsc::unbounded_channel::<u32>();
+    let (device_decision_tx, device_decision_rx) = mpsc::unbounded_channel();
+
+    // Devices the user already allowed or denied on this machine.
+    let authorized_clients = tauri::Manager::path(&app_handle)
+        .app_data_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            AuthorizedClients::open(dir.join(AUTHORIZED_CLIENTS_FILE))
+                .map_err(|e| format!("{:#}", e))
+        })
+        .map_err(|e| format!("Failed to load approved devices: {}", e))?;
 
     {
         let mut state = SESSION_STATE.lock().unwrap();
         *state = Some(SessionState {
             stop_tx: Some(stop_tx),
             cc_config_tx: Some(cc_tx),
-            current_bitrate: current_bitrate.clone(),
-            cc_state: cc_state_shared.clone(),
+            counters: counters.clone(),
+            offer_decision_tx: Some(offer_decision_tx),
+            device_decision_tx: Some(device_decision_tx),
+            display_id: Some(display.id),
+            display_switch_tx: Some(display_switch_tx),
+            bandwidth_limit_tx: Some(bandwidth_limit_tx),
         });
     }
 
-    let config = EncodeConfig {
-        codec: Codec::H264,
-        resolution: preflight.selected_resolution,
-        fps: 60,
-        bitrate_kbps: 8000,
-        keyframe_interval_ms: 2000,
-        display_id: Some(preflight.selected_display_id),
-        enable_10bit: false,
-        enable_hdr: false,
-    };
+    let capture_resolution =
+        host_capture::capture_resolution(host_config.resolution, display.resolution);
+    let mut config = host_config.encode_config(codec, capture_resolution, display.id);
+    let stream_codec = rift_codec(codec);
+    log::info!(
+        "Host stream config: {:?} {}x{}@{} {} kbps, keyframe every {} ms",
+        config.codec,
+        config.resolution.width,
+        config.resolution.height,
+        config.fps,
+        config.bitrate_kbps,
+        config.keyframe_interval_ms
+    );
 
     let mut signaling_token: Option<String> = None;
     let mut signaling_url = "wss://auth.wavry.dev/ws".to_string();
@@ -751,8 +1291,8 @@ pub async fn start_host(
         }
     }
 
-    let socket = match UdpSocket::bind(format!("0.0.0.0:{}", port)) {
-        Ok(socket) => socket,
+    let socket = match wavry_client::net::bind_udp(wavry_client::net::dual_stack_any(port)) {
+        Ok(socket) => Arc::new(socket),
         Err(e) => {
             if let Ok(mut state) = SESSION_STATE.lock() {
                 *state = None;
@@ -760,14 +1300,12 @@ pub async fn start_host(
             return Err(format!("Failed to bind UDP socket: {}", e));
         }
     };
-    socket.set_nonblocking(true).ok();
     let bound_port = socket.local_addr().map(|addr| addr.port()).unwrap_or(port);
 
     let app_handle_clone = app_handle.clone();
     tokio::spawn(async move {
         let app_handle = app_handle_clone;
         let mut retry_count = 0;
-        const MAX_RETRIES: u32 = 10;
 
         log::info!(
             "Host task started (requested port {}, bound port {})",
@@ -775,64 +1313,195 @@ pub async fn start_host(
             bound_port
         );
 
-        let shared_client_addr = Arc::new(std::sync::Mutex::new(None));
+        // Dropping the mapper also removes the router mapping.
+        let port_mapping = host_config
+            .port_mapping
+            .then(|| wavry_platform::PortMapper::spawn(bound_port));
+        let mapped_addr = port_mapping.as_ref().map(|(_, external)| external.clone());
 
         if let Some(token) = signaling_token {
+            // Gathered before the host loop owns the socket, since the STUN
+            // answer arrives on it.
+            let local_candidates = wavry_client::ice::gather_candidates(&socket).await;
+            let punch_socket = socket.clone();
             let signaling_url = signaling_url.clone();
+            let app_handle = app_handle.clone();
             tokio::spawn(async move {
                 if let Ok(mut sig) = SignalingClient::connect(&signaling_url, &token).await {
                     log::info!("Host registered with signaling gateway");
-                    while let Ok(msg) = sig.recv().await {
-                        if let SignalMessage::OFFER_RIFT {
-                            target_username,
-                            hello_base64,
-                        } = msg
-                        {
-                            if let Ok(hello) = wavry_client::decode_hello_base64(&hello_base64) {
-                                let session_id = uuid::Uuid::new_v4().into_bytes();
-                                let session_alias = 1;
-
-                                let udp = std::net::UdpSocket::bind("0.0.0.0:0").ok();
-                                let my_public_addr = if let Some(ref s) = udp {
-                                    let tokio_u =
-                                        tokio::net::UdpSocket::from_std(s.try_clone().unwrap())
-                                            .ok();
-                                    if let Some(tu) = tokio_u {
-                                        wavry_client::discover_public_addr(&tu)
-                                            .await
-                                            .ok()
-                                            .map(|a: SocketAddr| a.to_string())
-                                    } else {
-                                        None
+                    let mut pending_offers = PendingOffers::default();
+                    let mut offer_expiry = tokio::time::interval(Duration::from_secs(5));
+                    let emit_expired = |expired: Vec<String>| {
+                        for offer_id in expired {
+                            log::info!("Offer {} expired without a decision", offer_id);
+                            let _ =
+                                tauri::Emitter::emit(&app_handle, OFFER_EXPIRED_EVENT, offer_id);
+                        }
+                    };
+                    // A running host is on its LAN around the clock, so it
+                    // offers to wake the others; the offer is per connection.
+                    let mut connected = sig.connected();
+                    let _ = sig.send(SignalMessage::WAKE_AGENT).await;
+                    loop {
+                        tokio::select! {
+                            Ok(()) = connected.changed() => {
+                                if *connected.borrow_and_update() {
+                                    let _ = sig.send(SignalMessage::WAKE_AGENT).await;
+                                }
+                            }
+                            _ = offer_expiry.tick(), if !pending_offers.is_empty() => {
+                                emit_expired(pending_offers.expire(Instant::now()));
+                            }
+                            msg = sig.recv() => {
+                                let Ok(msg) = msg else {
+                                    break;
+                                };
+                                if let SignalMessage::WAKE_HOST {
+                                    mac_address,
+                                    broadcast_addr,
+                                } = &msg
+                                {
+                                    let broadcast_addr = broadcast_addr.as_deref();
+                                    if let Err(e) = wake::handle_wake_host(mac_address, broadcast_addr) {
+                                        log::warn!("Failed to wake {}: {}", mac_address, e);
                                     }
-                                } else {
-                                    None
+                                    continue;
+                                }
+                                let SignalMessage::OFFER_RIFT {
+                                    target_username,
+                                    hello_base64,
+                                    candidates,
+                                } = msg
+                                else {
+                                    continue;
+                                };
+                                let Ok(hello) = wavry_client::decode_hello_base64(&hello_base64)
+                                else {
+                                    log::warn!("Ignoring malformed offer from {}", target_username);
+                                    continue;
                                 };
 
-                                let (w, h) = if let Some(res) = hello.max_resolution {
-                                    (res.width, res.height)
-                                } else {
-                                    (1920, 1080)
+                                // Defer the answer until the user accepts or rejects the offer.
+                                let offer_id = uuid::Uuid::new_v4().to_string();
+                                log::info!(
+                                    "Incoming offer {} from {} ({})",
+                                    offer_id,
+                                    target_username,
+                                    hello.client_name
+                                );
+                                let _ = tauri::Emitter::emit(
+                                    &app_handle,
+                                    INCOMING_OFFER_EVENT,
+                                    IncomingOfferEvent::new(&offer_id, &target_username, &hello),
+                                );
+                                let dropped = pending_offers.insert(
+                                    offer_id,
+                                    PendingOffer {
+                                        username: target_username,
+                                        hello,
+                                        candidates,
+                                    },
+                                    Instant::now(),
+                                );
+                                emit_expired(dropped);
+                            }
+                            Some(decision) = offer_decision_rx.recv() => {
+                                let Some(offer) = pending_offers.take(&decision.offer_id, Instant::now()) else {
+                                    log::warn!(
+                                        "Ignoring decision for unknown or expired offer {}",
+                                        decision.offer_id
+                                    );
+                                    continue;
                                 };
 
-                                let selected_codec = choose_rift_codec(&hello);
-                                let ack_b64 = wavry_client::create_hello_ack_base64(
-                                    true,
-                                    session_id,
-                                    session_alias,
-                                    my_public_addr,
-                                    w,
-                                    h,
-                                    selected_codec,
-                                )
-                                .unwrap_or_default();
+                                let client_supports_codec = offer
+                                    .hello
+                                    .supported_codecs
+                                    .contains(&(stream_codec as i32));
+                                if decision.accept && !client_supports_codec {
+                                    log::warn!(
+                                        "Rejecting offer from {}: client cannot decode {}",
+                                        offer.username,
+                                        stream_codec.as_str_name()
+                                    );
+                                }
+
+                                let accepted = decision.accept && client_supports_codec;
+                                let (ack_b64, answer_candidates) = if accepted {
+                                    let session_id = uuid::Uuid::new_v4().into_bytes();
+                                    let negotiated_fec =
+                                        rift_core::fec::negotiate_scheme(&offer.hello.fec_schemes);
+                                    let session_alias = 1;
+
+                                    // A router mapping beats the STUN address,
+                                    // which only holds behind cone NATs.
+                                    let mapped = mapped_addr
+                                        .as_ref()
+                                        .and_then(|external| *external.borrow());
+                                    let mut candidates = local_candidates.clone();
+                                    candidates.extend(mapped.map(|addr| {
+                                        rift_core::ice::candidate(CandidateKind::Srflx, addr)
+                                    }));
+                                    let my_public_addr =
+                                        mapped.map(|addr| addr.to_string()).or_else(|| {
+                                            local_candidates
+                                                .iter()
+                                                .find(|c| c.kind == CandidateKind::Srflx)
+                                                .map(|c| c.addr.clone())
+                                        });
+
+                                    // The host socket is dual-stack, so peers
+                                    // with IPv6 can reach it without a NAT.
+                                    let ipv6_candidates =
+                                        wavry_client::net::global_ipv6_addr()
+                                            .map(|ip| {
+                                                SocketAddr::new(ip.into(), bound_port)
+                                                    .to_string()
+                                            })
+                                            .into_iter()
+                                            .collect();
+
+                                    let ack = wavry_client::create_hello_ack_base64(
+                                        true,
+                                        session_id,
+                                        session_alias,
+                                        my_public_addr,
+                                        ipv6_candidates,
+                                        capture_resolution.width as u32,
+                                        capture_resolution.height as u32,
+                                        stream_codec,
+                                        negotiated_fec,
+                                    )
+                                    .unwrap_or_default();
+                                    (ack, candidates)
+                                } else {
+                                    log::info!("Rejected offer from {}", offer.username);
+                                    let ack = wavry_client::create_hello_ack_base64(
+                                        false,
+                                        [0u8; 16],
+                                        0,
+                                        None,
+                                        vec![],
+                                        0,
+                                        0,
+                                        rift_core::Codec::H264,
+                                        rift_core::FecScheme::Xor,
+                                    )
+                                    .unwrap_or_default();
+                                    (ack, Vec::new())
+                                };
 
                                 let _ = sig
                                     .send(SignalMessage::ANSWER_RIFT {
-                                        target_username,
+                                        target_username: offer.username,
                                         ack_base64: ack_b64,
+                                        candidates: answer_candidates,
                                     })
                                     .await;
+                                if accepted {
+                                    // Opens this side's NAT for the client's checks.
+                                    wavry_client::ice::punch(&punch_socket, &offer.candidates).await;
+                                }
                             }
                         }
                     }
@@ -840,352 +1509,115 @@ pub async fn start_host(
             });
         }
 
-        'outer: loop {
-            let mut video_encoder = match PipewireEncoder::new(config).await {
-                Ok(e) => {
-                    retry_count = 0;
-                    e
-                }
-                Err(e) => {
-                    log::error!("Failed to initialize video encoder: {}", e);
-                    let can_retry = retry_count < MAX_RETRIES;
-
-                    #[derive(Clone, serde::Serialize)]
-                    struct HostErrorEvent {
-                        error_type: String,
-                        message: String,
-                        can_retry: bool,
+        // Keep the display awake while a client is connected, and ask the
+        // user about devices they have not decided on yet.
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel::<SessionEvent>();
+        let events_app = app_handle.clone();
+        tokio::spawn(async move {
+            let mut wake_lock = wavry_platform::WakeLock::new("Hosting a Wavry session");
+            while let Some(event) = events_rx.recv().await {
+                let active = match event {
+                    SessionEvent::Connected => true,
+                    SessionEvent::PendingApproval {
+                        wavry_id,
+                        client_name,
+                        addr,
+                    } => {
+                        let _ = tauri::Emitter::emit(
+                            &events_app,
+                            DEVICE_APPROVAL_EVENT,
+                            DeviceApprovalEvent::new(&wavry_id, &client_name, addr),
+                        );
+                        continue;
                     }
+                    _ => false,
+                };
+                if let Err(e) = wake_lock.set_active(active) {
+                    log::warn!("Failed to keep the display awake: {}", e);
+                }
+            }
+        });
 
-                    let error_type = match &e {
-                        MediaError::ProtocolViolation(_) => "ProtocolViolation",
-                        MediaError::PortalUnavailable(_) => "PortalUnavailable",
-                        MediaError::StreamNodeLoss(_) => "StreamNodeLoss",
-                        MediaError::CompositorDisconnect(_) => "CompositorDisconnect",
-                        _ => "Other",
-                    };
-
-                    let _ = tauri::Emitter::emit(
-                        &app_handle,
-                        "host-error",
-                        HostErrorEvent {
-                            error_type: error_type.to_string(),
-                            message: e.to_string(),
-                            can_retry,
-                        },
-                    );
+        let Some(video) =
+            open_host_video(&app_handle, config, &mut retry_count, &mut stop_rx).await
+        else {
+            if let Ok(mut state) = SESSION_STATE.lock() {
+                *state = None;
+            }
+            return;
+        };
+        let mut host_loop: HostLoop<PlatformVideo, PlatformAudio> =
+            HostLoop::new(socket, config, video)
+                .shared_counters(counters)
+                .cc_config_updates(cc_rx)
+                .bandwidth_limits(bandwidth_limit_rx)
+                .authorization(authorized_clients, device_decision_rx)
+                .session_policy(host_config.session_policy())
+                .events(events_tx);
+        match wavry_platform::ArboardClipboard::new() {
+            Ok(clipboard) => host_loop = host_loop.clipboard(Box::new(clipboard)),
+            Err(e) => log::warn!("Clipboard sync unavailable: {}", e),
+        }
+        match host_capture::open_audio().await {
+            Ok(audio) => host_loop = host_loop.audio(audio),
+            Err(e) => {
+                // Stream video alone rather than refusing to host.
+                log::error!("Failed to initialize audio capturer: {:#}", e);
+                host_capture::emit_host_error(
+                    &app_handle,
+                    "AudioCaptureFailure",
+                    e.to_string(),
+                    true,
+                );
+            }
+        }
 
+        loop {
+            tokio::select! {
+                result = host_loop.run(&mut stop_rx) => {
+                    let Err(e) = result else {
+                        break;
+                    };
+                    log::error!("Video capture error: {:#}", e);
+                    let (error_type, retryable) = host_capture::classify_error(&e);
+                    let can_retry = retryable && retry_count < MAX_HOST_RETRIES;
+                    host_capture::emit_host_error(&app_handle, error_type, e.to_string(), can_retry);
                     if !can_retry {
-                        break 'outer;
+                        break;
                     }
 
                     retry_count += 1;
-                    let delay = std::time::Duration::from_millis(1000 * (1 << retry_count).min(30));
-                    log::info!("Retrying video encoder initialization in {:?}", delay);
-
+                    // Cool down before retry
+                    let delay = std::time::Duration::from_millis(2000);
+                    log::info!("Retrying capture in {:?}", delay);
                     tokio::select! {
-                        _ = tokio::time::sleep(delay) => continue 'outer,
-                        _ = &mut stop_rx => break 'outer,
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = &mut stop_rx => break,
                     }
                 }
-            };
-
-            let audio_capturer = match PipewireAudioCapturer::new().await {
-                Ok(a) => a,
-                Err(e) => {
-                    log::error!("Failed to initialize audio capturer: {}", e);
-                    // Audio failure might be non-fatal but let's report it
-                    #[derive(Clone, serde::Serialize)]
-                    struct HostErrorEvent {
-                        error_type: String,
-                        message: String,
-                        can_retry: bool,
-                    }
-                    let _ = tauri::Emitter::emit(
-                        &app_handle,
-                        "host-error",
-                        HostErrorEvent {
-                            error_type: "AudioCaptureFailure".to_string(),
-                            message: e.to_string(),
-                            can_retry: true,
-                        },
-                    );
-                    // Fallback or just continue without audio
-                    // For now, we'll try to continue.
-                    // We need a dummy or option for audio_capturer.
-                    // Let's just break for now to avoid complexity in this step.
-                    break 'outer;
-                }
-            };
-
-            let socket_clone = socket.try_clone().expect("Failed to clone socket");
-            let shared_client_addr_audio = shared_client_addr.clone();
-
-            // Audio loop in a separate task
-            let (audio_stop_tx, audio_stop_rx) = oneshot::channel::<()>();
-            let mut audio_task_stop_rx = audio_stop_rx;
-            let audio_handle = tokio::spawn(async move {
-                let mut packet_id_counter: u64 = 1;
-                let mut audio_capturer = audio_capturer;
-                loop {
-                    if audio_task_stop_rx.try_recv().is_ok() {
-                        break;
-                    }
-
-                    // PipewireAudioCapturer::next_packet is blocking, so we use spawn_blocking if needed,
-                    // but since this is a dedicated task it's okay for now if we don't have many sessions.
-                    // Better: use a thread for the blocking parts.
-                    match audio_capturer.next_packet() {
-                        Ok(frame) => {
-                            let addr = {
-                                let addr_lock = shared_client_addr_audio.lock().unwrap();
-                                *addr_lock
-                            };
-
-                            if let Some(addr) = addr {
-                                let audio = rift_core::AudioPacket {
-                                    timestamp_us: frame.timestamp_us,
-                                    payload: frame.data,
-                                };
-
-                                let msg = rift_core::Message {
-                                    content: Some(rift_core::message::Content::Media(
-                                        rift_core::MediaMessage {
-                                            content: Some(
-                                                rift_core::media_message::Content::Audio(audio),
-                                            ),
-                                        },
-                                    )),
-                                };
-
-                                let phys = rift_core::PhysicalPacket {
-                                    version: rift_core::RIFT_VERSION,
-                                    session_id: None,
-                                    session_alias: None,
-                                    packet_id: {
-                                        let id = packet_id_counter;
-                                        packet_id_counter += 1;
-                                        id
-                                    },
-                                    payload: Bytes::from(rift_core::encode_msg(&msg)),
-                                };
-
-                                let _ = socket_clone.send_to(&phys.encode(), addr);
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Audio capture error: {}", e);
-                            break;
-                        }
-                    }
-                }
-                log::info!("Audio task exiting");
-            });
-
-            let mut sequence: u64 = 0;
-            let mut packet_id_counter: u64 = 1;
-            let mut delta_cc = rift_core::cc::DeltaCC::new(
-                rift_core::cc::DeltaConfig::default(),
-                config.bitrate_kbps,
-                config.fps as u32,
-            );
-            let mut fec_builder = rift_core::FecBuilder::new(20).unwrap();
-            let mut last_fec_ratio = 0.05f32;
-
-            loop {
-                if stop_rx.try_recv().is_ok() {
-                    let _ = audio_stop_tx.send(());
-                    audio_handle.abort();
-                    break 'outer;
-                }
-
-                if let Ok(new_config) = cc_rx.try_recv() {
-                    delta_cc = rift_core::cc::DeltaCC::new(
-                        new_config,
-                        delta_cc.target_bitrate_kbps(),
-                        delta_cc.target_fps(),
+                Some(display) = display_switch_rx.recv() => {
+                    log::info!(
+                        "Restarting capture on display {} '{}'",
+                        display.id,
+                        display.name
                     );
-                }
-
-                let mut buf = [0u8; 2048];
-                if let Ok((len, src)) = socket.recv_from(&mut buf) {
-                    let mut addr_lock = shared_client_addr.lock().unwrap();
-                    if addr_lock.is_none() {
-                        log::info!("Client connected from {}", src);
-                        *addr_lock = Some(src);
-                    }
-
-                    if let Ok(phys) =
-                        rift_core::PhysicalPacket::decode(Bytes::copy_from_slice(&buf[..len]))
-                    {
-                        if let Ok(msg) = rift_core::decode_msg(&phys.payload) {
-                            if let Some(rift_core::message::Content::Control(ctrl)) = msg.content {
-                                if let Some(rift_core::control_message::Content::Stats(stats)) =
-                                    ctrl.content
-                                {
-                                    let loss = if stats.received_packets > 0 {
-                                        stats.lost_packets as f32
-                                            / (stats.received_packets + stats.lost_packets) as f32
-                                    } else {
-                                        0.0
-                                    };
-                                    delta_cc.on_rtt_sample(stats.rtt_us, loss, stats.jitter_us);
-
-                                    let new_bitrate = delta_cc.target_bitrate_kbps();
-                                    if let Err(e) = video_encoder.set_bitrate(new_bitrate) {
-                                        log::error!("Failed to update bitrate: {}", e);
-                                    }
-
-                                    current_bitrate.store(new_bitrate, Ordering::Relaxed);
-                                    let state_str = format!("{:?}", delta_cc.state());
-                                    *cc_state_shared.lock().unwrap() = state_str;
-                                }
-                            }
-                        }
-                    }
-                }
-
-                match video_encoder.next_frame() {
-                    Ok(frame) => {
-                        let addr = {
-                            let addr_lock = shared_client_addr.lock().unwrap();
-                            *addr_lock
-                        };
-
-                        if let Some(addr) = addr {
-                            let max_payload = 1300;
-                            let data = frame.data;
-                            let total_chunks = data.len().div_ceil(max_payload) as u32;
-                            let frame_id = sequence;
-
-                            for i in 0..total_chunks {
-                                let start = (i as usize) * max_payload;
-                                let end = std::cmp::min(start + max_payload, data.len());
-                                let chunk_data = data[start..end].to_vec();
-
-                                let chunk = rift_core::VideoChunk {
-                                    frame_id,
-                                    chunk_index: i,
-                                    chunk_count: total_chunks,
-                                    timestamp_us: frame.timestamp_us,
-                                    keyframe: frame.keyframe,
-                                    payload: chunk_data,
-                                    capture_us: 0,
-                                    encode_us: 0,
-                                };
-
-                                let msg = rift_core::Message {
-                                    content: Some(rift_core::message::Content::Media(
-                                        rift_core::MediaMessage {
-                                            content: Some(
-                                                rift_core::media_message::Content::Video(chunk),
-                                            ),
-                                        },
-                                    )),
-                                };
-
-                                let phys = rift_core::PhysicalPacket {
-                                    version: rift_core::RIFT_VERSION,
-                                    session_id: None,
-                                    session_alias: None,
-                                    packet_id: {
-                                        let id = packet_id_counter;
-                                        packet_id_counter += 1;
-                                        id
-                                    },
-                                    payload: Bytes::from(rift_core::encode_msg(&msg)),
-                                };
-
-                                let _ = socket.send_to(&phys.encode(), addr);
-                                if let Some(fec) =
-                                    fec_builder.push(packet_id_counter - 1, &phys.payload)
-                                {
-                                    let fec_msg = rift_core::Message {
-                                        content: Some(rift_core::message::Content::Media(
-                                            rift_core::MediaMessage {
-                                                content: Some(
-                                                    rift_core::media_message::Content::Fec(fec),
-                                                ),
-                                            },
-                                        )),
-                                    };
-                                    let fec_phys = rift_core::PhysicalPacket {
-                                        version: rift_core::RIFT_VERSION,
-                                        session_id: None,
-                                        session_alias: None,
-                                        packet_id: 0,
-                                        payload: bytes::Bytes::from(rift_core::encode_msg(
-                                            &fec_msg,
-                                        )),
-                                    };
-                                    let _ = socket.send_to(&fec_phys.encode(), addr);
-                                }
-                            }
-                            sequence = sequence.wrapping_add(1);
-
-                            let current_fec = delta_cc.fec_ratio();
-                            if (current_fec - last_fec_ratio).abs() > 0.01 {
-                                let shards = (1.0 / current_fec).clamp(4.0, 30.0) as u32;
-                                if let Ok(new_fec) = rift_core::FecBuilder::new(shards) {
-                                    fec_builder = new_fec;
-                                    last_fec_ratio = current_fec;
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Video capture error: {}", e);
-
-                        // If it's a protocol error or compositor disconnect, we might want to retry
-                        let can_retry = matches!(
-                            e,
-                            MediaError::ProtocolViolation(_)
-                                | MediaError::CompositorDisconnect(_)
-                                | MediaError::StreamNodeLoss(_)
-                        );
-
-                        #[derive(Clone, serde::Serialize)]
-                        struct HostErrorEvent {
-                            error_type: String,
-                            message: String,
-                            can_retry: bool,
-                        }
-
-                        let error_type = match &e {
-                            MediaError::ProtocolViolation(_) => "ProtocolViolation",
-                            MediaError::PortalUnavailable(_) => "PortalUnavailable",
-                            MediaError::StreamNodeLoss(_) => "StreamNodeLoss",
-                            MediaError::CompositorDisconnect(_) => "CompositorDisconnect",
-                            _ => "Other",
-                        };
-
-                        let _ = tauri::Emitter::emit(
-                            &app_handle,
-                            "host-error",
-                            HostErrorEvent {
-                                error_type: error_type.to_string(),
-                                message: e.to_string(),
-                                can_retry,
-                            },
-                        );
-
-                        if can_retry && retry_count < MAX_RETRIES {
-                            retry_count += 1;
-                            // Cool down before retry
-                            let delay = std::time::Duration::from_millis(2000);
-                            log::info!("Retrying capture in {:?}", delay);
-                            audio_handle.abort();
-                            tokio::time::sleep(delay).await;
-                            continue 'outer;
-                        } else {
-                            audio_handle.abort();
-                            break 'outer;
-                        }
-                    }
+                    config.display_id = Some(display.id);
+                    config.resolution =
+                        host_capture::capture_resolution(host_config.resolution, display.resolution);
                 }
             }
+
+            let Some(video) =
+                open_host_video(&app_handle, config, &mut retry_count, &mut stop_rx).await
+            else {
+                break;
+            };
+            host_loop.replace_video(video, config);
         }
 
+        if let Some((mapper, _)) = port_mapping {
+            mapper.shutdown().await;
+        }
         if let Ok(mut state) = SESSION_STATE.lock() {
             *state = None;
         }
@@ -1194,10 +1626,50 @@ pub async fn start_host(
     Ok(format!("Hosting on UDP {}", bound_port))
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows"))]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const MAX_HOST_RETRIES: u32 = 10;
+
+/// Opens the platform encoder, backing off between failed attempts. Returns
+/// `None` once retries run out or the host is stopped.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+async fn open_host_video(
+    app_handle: &tauri::AppHandle,
+    config: wavry_media::EncodeConfig,
+    retry_count: &mut u32,
+    stop_rx: &mut oneshot::Receiver<()>,
+) -> Option<crate::host_capture::PlatformVideo> {
+    use crate::host_capture;
+
+    loop {
+        let e = match host_capture::open_video(config).await {
+            Ok(video) => {
+                *retry_count = 0;
+                return Some(video);
+            }
+            Err(e) => e,
+        };
+        log::error!("Failed to initialize video encoder: {:#}", e);
+        let can_retry = *retry_count < MAX_HOST_RETRIES;
+        let (error_type, _) = host_capture::classify_error(&e);
+        host_capture::emit_host_error(app_handle, error_type, e.to_string(), can_retry);
+        if !can_retry {
+            return None;
+        }
+
+        *retry_count += 1;
+        let delay = std::time::Duration::from_millis(1000 * (1 << *retry_count).min(30));
+        log::info!("Retrying video encoder initialization in {:?}", delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut *stop_rx => return None,
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
 #[tauri::command]
-pub async fn start_host(_port: u16, _display_id: Option<u32>) -> Result<String, String> {
-    Err("Host not fully implemented for this platform in refactored version yet".into())
+pub async fn start_host(_port: u16, _config: Option<HostConfig>) -> Result<String, String> {
+    Err("Hosting is not supported on this platform yet".into())
 }
 
 #[cfg(all(test, target_os = "linux"))]
@@ -0,0 +1,169 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+use wavry_client::{FileTransferDirection, FileTransferEvent};
+
+pub const FILE_TRANSFER_PROGRESS_EVENT: &str = "file-transfer-progress";
+pub const FILE_TRANSFER_COMPLETED_EVENT: &str = "file-transfer-completed";
+pub const FILE_TRANSFER_FAILED_EVENT: &str = "file-transfer-failed";
+
+/// Largest integer the webview can represent exactly (`Number.MAX_SAFE_INTEGER`).
+const JS_SAFE_INTEGER_MASK: u64 = (1 << 53) - 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileTransferPayload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub file_id: u64,
+    pub direction: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transferred_chunks: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_chunks: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FileTransferPayload {
+    fn new(file_id: u64, direction: FileTransferDirection) -> Self {
+        Self {
+            session_id: None,
+            file_id,
+            direction: match direction {
+                FileTransferDirection::Outgoing => "outgoing",
+                FileTransferDirection::Incoming => "incoming",
+            },
+            filename: None,
+            file_size: None,
+            transferred_chunks: None,
+            total_chunks: None,
+            path: None,
+            error: None,
+        }
+    }
+}
+
+/// File ids handed to the frontend must survive a round trip through a JS number.
+pub fn new_file_id() -> u64 {
+    loop {
+        let id = wavry_client::random_file_id() & JS_SAFE_INTEGER_MASK;
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+/// Map a client event to the Tauri event name and payload it is emitted as.
+pub fn to_frontend_event(event: FileTransferEvent) -> (&'static str, FileTransferPayload) {
+    match event {
+        FileTransferEvent::Started {
+            file_id,
+            direction,
+            filename,
+            file_size,
+        } => {
+            let mut payload = FileTransferPayload::new(file_id, direction);
+            payload.filename = Some(filename);
+            payload.file_size = Some(file_size);
+            payload.transferred_chunks = Some(0);
+            (FILE_TRANSFER_PROGRESS_EVENT, payload)
+        }
+        FileTransferEvent::Progress {
+            file_id,
+            direction,
+            transferred_chunks,
+            total_chunks,
+        } => {
+            let mut payload = FileTransferPayload::new(file_id, direction);
+            payload.transferred_chunks = Some(transferred_chunks);
+            payload.total_chunks = Some(total_chunks);
+            (FILE_TRANSFER_PROGRESS_EVENT, payload)
+        }
+        FileTransferEvent::Completed {
+            file_id,
+            direction,
+            path,
+        } => {
+            let mut payload = FileTransferPayload::new(file_id, direction);
+            payload.path = path.map(|p| p.display().to_string());
+            (FILE_TRANSFER_COMPLETED_EVENT, payload)
+        }
+        FileTransferEvent::Failed {
+            file_id,
+            direction,
+            error,
+        } => {
+            let mut payload = FileTransferPayload::new(file_id, direction);
+            payload.error = Some(error);
+            (FILE_TRANSFER_FAILED_EVENT, payload)
+        }
+    }
+}
+
+/// Forward client file transfer events to the frontend until the session ends.
+pub fn spawn_event_forwarder(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    mut events: broadcast::Receiver<FileTransferEvent>,
+) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let (name, mut payload) = to_frontend_event(event);
+                    payload.session_id = Some(session_id.clone());
+                    let _ = tauri::Emitter::emit(&app_handle, name, payload);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Dropped {} file transfer event(s)", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_file_id_is_js_safe_and_non_zero() {
+        for _ in 0..1000 {
+            let id = new_file_id();
+            assert!(id != 0);
+            assert!(id <= JS_SAFE_INTEGER_MASK);
+        }
+    }
+
+    #[test]
+    fn failed_event_maps_to_failed_payload() {
+        let (name, payload) = to_frontend_event(FileTransferEvent::Failed {
+            file_id: 7,
+            direction: FileTransferDirection::Outgoing,
+            error: "cancelled".into(),
+        });
+        assert_eq!(name, FILE_TRANSFER_FAILED_EVENT);
+        assert_eq!(payload.file_id, 7);
+        assert_eq!(payload.direction, "outgoing");
+        assert_eq!(payload.error.as_deref(), Some("cancelled"));
+    }
+
+    #[test]
+    fn started_event_reports_zero_progress() {
+        let (name, payload) = to_frontend_event(FileTransferEvent::Started {
+            file_id: 1,
+            direction: FileTransferDirection::Incoming,
+            filename: "notes.txt".into(),
+            file_size: 42,
+        });
+        assert_eq!(name, FILE_TRANSFER_PROGRESS_EVENT);
+        assert_eq!(payload.transferred_chunks, Some(0));
+        assert_eq!(payload.filename.as_deref(), Some("notes.txt"));
+    }
+}
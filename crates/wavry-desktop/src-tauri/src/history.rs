@@ -0,0 +1,243 @@
+use crate::app_data::{app_data_file, write_atomic};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const HISTORY_FILE: &str = "connection_history.json";
+
+/// Unpinned entries beyond this count are dropped, oldest first.
+pub const MAX_UNPINNED_ENTRIES: usize = 50;
+
+/// Serializes read-modify-write cycles on the history file.
+pub static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum ConnectionTarget {
+    Address(String),
+    Username(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QualitySummary {
+    pub connected: bool,
+    pub frames_decoded: u64,
+    pub average_fps: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionRecord {
+    pub id: String,
+    pub target: ConnectionTarget,
+    pub started_at_unix_ms: u64,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub quality: QualitySummary,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Host MAC address for Wake-on-LAN, in `aa:bb:cc:dd:ee:ff` form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionHistory {
+    #[serde(default)]
+    pub records: Vec<ConnectionRecord>,
+}
+
+impl ConnectionHistory {
+    /// Add a finished session, keeping at most one pinned entry per target.
+    pub fn record(&mut self, mut record: ConnectionRecord) {
+        if record.mac_address.is_none() {
+            record.mac_address = self.mac_for(&record.target);
+        }
+        if let Some(existing) = self
+            .records
+            .iter()
+            .position(|r| r.pinned && r.target == record.target)
+        {
+            record.pinned = true;
+            self.records.remove(existing);
+        }
+        self.records.insert(0, record);
+        self.trim();
+    }
+
+    pub fn set_pinned(&mut self, id: &str, pinned: bool) -> Result<(), String> {
+        let record = self
+            .records
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| format!("Unknown connection history entry {}", id))?;
+        record.pinned = pinned;
+        self.trim();
+        Ok(())
+    }
+
+    /// Store the MAC on every entry for the same target so it survives trimming.
+    pub fn set_mac_address(&mut self, id: &str, mac_address: Option<String>) -> Result<(), String> {
+        let target = self
+            .records
+            .iter()
+            .find(|r| r.id == id)
+            .map(|r| r.target.clone())
+            .ok_or_else(|| format!("Unknown connection history entry {}", id))?;
+        for record in self.records.iter_mut().filter(|r| r.target == target) {
+            record.mac_address = mac_address.clone();
+        }
+        Ok(())
+    }
+
+    pub fn mac_for(&self, target: &ConnectionTarget) -> Option<String> {
+        self.records
+            .iter()
+            .find(|r| &r.target == target && r.mac_address.is_some())
+            .and_then(|r| r.mac_address.clone())
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<(), String> {
+        let before = self.records.len();
+        self.records.retain(|r| r.id != id);
+        if self.records.len() == before {
+            return Err(format!("Unknown connection history entry {}", id));
+        }
+        Ok(())
+    }
+
+    /// Pinned entries first, then most recent.
+    pub fn sorted(&self) -> Vec<ConnectionRecord> {
+        let mut records = self.records.clone();
+        records.sort_by(|a, b| {
+            b.pinned
+                .cmp(&a.pinned)
+                .then(b.started_at_unix_ms.cmp(&a.started_at_unix_ms))
+        });
+        records
+    }
+
+    fn trim(&mut self) {
+        let mut unpinned = 0usize;
+        self.records.retain(|r| {
+            if r.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= MAX_UNPINNED_ENTRIES
+        });
+    }
+}
+
+pub fn history_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_data_file(app_handle, HISTORY_FILE)
+}
+
+pub fn load_from(path: &Path) -> Result<ConnectionHistory, String> {
+    match std::fs::read_to_string(path) {
+        Ok(raw) => serde_json::from_str(&raw).or_else(|e| {
+            log::warn!(
+                "Ignoring unreadable connection history {}: {}",
+                path.display(),
+                e
+            );
+            Ok(ConnectionHistory::default())
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ConnectionHistory::default()),
+        Err(e) => Err(format!("Failed to read connection history: {}", e)),
+    }
+}
+
+pub fn save_to(path: &Path, history: &ConnectionHistory) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(history).map_err(|e| e.to_string())?;
+    write_atomic(path, &json)
+}
+
+/// Load, modify and persist the history file under [`HISTORY_LOCK`].
+pub fn update<T>(
+    path: &Path,
+    f: impl FnOnce(&mut ConnectionHistory) -> Result<T, String>,
+) -> Result<T, String> {
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let mut history = load_from(path)?;
+    let out = f(&mut history)?;
+    save_to(path, &history)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, target: &str, started_at_unix_ms: u64) -> ConnectionRecord {
+        ConnectionRecord {
+            id: id.to_string(),
+            target: ConnectionTarget::Username(target.to_string()),
+            started_at_unix_ms,
+            duration_ms: 1000,
+            quality: QualitySummary::default(),
+            pinned: false,
+            mac_address: None,
+        }
+    }
+
+    #[test]
+    fn sorted_puts_pinned_first_then_newest() {
+        let mut history = ConnectionHistory::default();
+        history.record(record("a", "alice", 1));
+        history.record(record("b", "bob", 2));
+        history.record(record("c", "carol", 3));
+        history.set_pinned("a", true).unwrap();
+
+        let ids: Vec<_> = history.sorted().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn record_replaces_pinned_entry_for_same_target() {
+        let mut history = ConnectionHistory::default();
+        history.record(record("a", "alice", 1));
+        history.set_pinned("a", true).unwrap();
+        history.record(record("b", "alice", 2));
+
+        assert_eq!(history.records.len(), 1);
+        assert_eq!(history.records[0].id, "b");
+        assert!(history.records[0].pinned);
+    }
+
+    #[test]
+    fn trim_keeps_pinned_entries() {
+        let mut history = ConnectionHistory::default();
+        history.record(record("pinned", "alice", 0));
+        history.set_pinned("pinned", true).unwrap();
+        for i in 0..(MAX_UNPINNED_ENTRIES + 5) {
+            history.record(record(&format!("r{i}"), &format!("peer{i}"), i as u64 + 1));
+        }
+
+        assert_eq!(history.records.len(), MAX_UNPINNED_ENTRIES + 1);
+        assert!(history.records.iter().any(|r| r.id == "pinned"));
+    }
+
+    #[test]
+    fn new_records_inherit_mac_for_target() {
+        let mut history = ConnectionHistory::default();
+        history.record(record("a", "alice", 1));
+        history
+            .set_mac_address("a", Some("aa:bb:cc:dd:ee:ff".into()))
+            .unwrap();
+        history.record(record("b", "alice", 2));
+        history.record(record("c", "bob", 3));
+
+        assert_eq!(
+            history.mac_for(&ConnectionTarget::Username("alice".into())),
+            Some("aa:bb:cc:dd:ee:ff".into())
+        );
+        let bob = history.records.iter().find(|r| r.id == "c").unwrap();
+        assert!(bob.mac_address.is_none());
+    }
+
+    #[test]
+    fn remove_unknown_entry_fails() {
+        let mut history = ConnectionHistory::default();
+        assert!(history.remove("missing").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Platform capture for the shared host loop: which display to capture and
+//! which encoder and audio capturer feed `wavry_sdk::host::HostLoop`.
+
+use serde::Serialize;
+use wavry_media::{DisplayInfo, EncodeConfig, Resolution};
+
+#[cfg(target_os = "macos")]
+pub type PlatformVideo = wavry_media::MacScreenEncoder;
+#[cfg(target_os = "macos")]
+pub type PlatformAudio = wavry_media::MacAudioCapturer;
+
+#[cfg(target_os = "linux")]
+pub type PlatformVideo = wavry_media::PipewireEncoder;
+#[cfg(target_os = "linux")]
+pub type PlatformAudio = wavry_sdk::host::ThreadedAudioSource;
+
+pub const HOST_ERROR_EVENT: &str = "host-error";
+
+#[derive(Clone, Serialize)]
+struct HostErrorEvent {
+    error_type: String,
+    message: String,
+    can_retry: bool,
+}
+
+pub fn emit_host_error(
+    app_handle: &tauri::AppHandle,
+    error_type: &str,
+    message: String,
+    can_retry: bool,
+) {
+    let _ = tauri::Emitter::emit(
+        app_handle,
+        HOST_ERROR_EVENT,
+        HostErrorEvent {
+            error_type: error_type.to_string(),
+            message,
+            can_retry,
+        },
+    );
+}
+
+/// Display to capture: the requested one if attached, else the first.
+#[cfg(target_os = "linux")]
+pub fn select_display(requested_id: Option<u32>) -> Result<DisplayInfo, String> {
+    let preflight = crate::commands::linux_host_preflight_impl(requested_id)?;
+    Ok(DisplayInfo {
+        id: preflight.selected_display_id,
+        name: preflight.selected_display_name,
+        resolution: preflight.selected_resolution,
+    })
+}
+
+/// Display to capture: the requested one if attached, else the first.
+#[cfg(target_os = "macos")]
+pub fn select_display(requested_id: Option<u32>) -> Result<DisplayInfo, String> {
+    use wavry_media::{CapabilityProbe, MacProbe};
+
+    let displays = MacProbe
+        .enumerate_displays()
+        .map_err(|e| format!("Display enumeration failed: {}", e))?;
+    requested_id
+        .and_then(|id| displays.iter().find(|d| d.id == id))
+        .or_else(|| displays.first())
+        .cloned()
+        .ok_or_else(|| "No displays are available for host capture".to_string())
+}
+
+/// Capture size for `display`, honouring a resolution picked in the UI.
+pub fn capture_resolution(requested: Option<Resolution>, display: Resolution) -> Resolution {
+    let resolution = requested.unwrap_or(display);
+    #[cfg(target_os = "linux")]
+    let resolution = crate::commands::sanitize_linux_capture_resolution(resolution);
+    resolution
+}
+
+pub async fn open_video(config: EncodeConfig) -> anyhow::Result<PlatformVideo> {
+    #[cfg(target_os = "macos")]
+    {
+        wavry_media::MacScreenEncoder::new(config).await
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Ok(wavry_media::PipewireEncoder::new(config).await?)
+    }
+}
+
+pub async fn open_audio() -> anyhow::Result<PlatformAudio> {
+    #[cfg(target_os = "macos")]
+    {
+        wavry_media::MacAudioCapturer::new().await
+    }
+    #[cfg(target_os = "linux")]
+    {
+        // Pulling audio blocks; keep it on its own thread.
+        let mut capturer = wavry_media::PipewireAudioCapturer::new().await?;
+        wavry_sdk::host::ThreadedAudioSource::spawn(move || Ok(capturer.next_packet()?))
+    }
+}
+
+/// `host-error` category for a capture failure and whether restarting
+/// capture may fix it.
+pub fn classify_error(error: &anyhow::Error) -> (&'static str, bool) {
+    use wavry_media::MediaError;
+
+    match error.downcast_ref::<MediaError>() {
+        Some(MediaError::ProtocolViolation(_)) => ("ProtocolViolation", true),
+        Some(MediaError::PortalUnavailable(_)) => ("PortalUnavailable", false),
+        Some(MediaError::StreamNodeLoss(_)) => ("StreamNodeLoss", true),
+        Some(MediaError::CompositorDisconnect(_)) => ("CompositorDisconnect", true),
+        _ => ("Other", false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wavry_media::MediaError;
+
+    #[test]
+    fn classify_error_sees_through_anyhow() {
+        let error = anyhow::Error::from(MediaError::StreamNodeLoss("node gone".into()));
+        assert_eq!(classify_error(&error), ("StreamNodeLoss", true));
+        assert_eq!(
+            classify_error(&anyhow::anyhow!("encoder exploded")),
+            ("Other", false)
+        );
+    }
+}
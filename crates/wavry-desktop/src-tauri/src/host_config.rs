@@ -0,0 +1,222 @@
+use crate::settings::{DesktopSettings, PreferredCodec, ResolutionMode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use wavry_media::{CaptureMode, Codec, ContentType, EncodeConfig, EncoderTuning, Resolution};
+use wavry_sdk::SessionPolicy;
+
+/// Host stream parameters chosen in the UI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostConfig {
+    pub codec: PreferredCodec,
+    /// Capture resolution; `None` uses the selected display's native mode.
+    pub resolution: Option<Resolution>,
+    pub fps: u16,
+    pub bitrate_kbps: u32,
+    pub keyframe_interval_ms: u32,
+    pub display_id: Option<u32>,
+    /// Ask the router to forward the host UDP port via NAT-PMP or UPnP.
+    pub port_mapping: bool,
+    /// Desktop sharing or gaming; tunes the encoder for it.
+    pub content: ContentType,
+    /// Fixed rate, or encode only when the screen changes.
+    pub capture_mode: CaptureMode,
+    /// End a client's session after this many seconds; 0 for no limit.
+    pub max_session_secs: u32,
+    /// Pause streaming after this many seconds without client input; 0 to
+    /// never pause.
+    pub idle_timeout_secs: u32,
+    /// Lock this machine when the client disconnects.
+    pub lock_on_disconnect: bool,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            codec: PreferredCodec::H264,
+            resolution: None,
+            fps: 60,
+            bitrate_kbps: 8000,
+            keyframe_interval_ms: 2000,
+            display_id: None,
+            port_mapping: true,
+            content: ContentType::Game,
+            capture_mode: CaptureMode::Fixed,
+            max_session_secs: 0,
+            idle_timeout_secs: 0,
+            lock_on_disconnect: false,
+        }
+    }
+}
+
+impl From<&DesktopSettings> for HostConfig {
+    /// Host stream parameters from the saved preferences.
+    fn from(settings: &DesktopSettings) -> Self {
+        let resolution = (settings.resolution_mode == ResolutionMode::Custom).then(|| Resolution {
+            width: settings.custom_width as u16,
+            height: settings.custom_height as u16,
+        });
+        Self {
+            codec: settings.default_codec,
+            resolution,
+            bitrate_kbps: settings.bitrate_kbps,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<PreferredCodec> for Codec {
+    fn from(codec: PreferredCodec) -> Self {
+        match codec {
+            PreferredCodec::H264 => Codec::H264,
+            PreferredCodec::Hevc => Codec::Hevc,
+            PreferredCodec::Av1 => Codec::Av1,
+        }
+    }
+}
+
+pub fn rift_codec(codec: Codec) -> rift_core::Codec {
+    match codec {
+        Codec::H264 => rift_core::Codec::H264,
+        Codec::Hevc => rift_core::Codec::Hevc,
+        Codec::Av1 => rift_core::Codec::Av1,
+    }
+}
+
+impl HostConfig {
+    /// Check ranges and codec availability against the local encoder probe.
+    pub fn validate(&self, supported_encoders: &[Codec]) -> Result<Codec, String> {
+        if !(1..=240).contains(&self.fps) {
+            return Err(format!(
+                "Frame rate must be between 1 and 240 (got {})",
+                self.fps
+            ));
+        }
+        if !(500..=200_000).contains(&self.bitrate_kbps) {
+            return Err(format!(
+                "Bitrate must be between 500 and 200000 kbps (got {})",
+                self.bitrate_kbps
+            ));
+        }
+        if !(250..=10_000).contains(&self.keyframe_interval_ms) {
+            return Err(format!(
+                "Keyframe interval must be between 250 and 10000 ms (got {})",
+                self.keyframe_interval_ms
+            ));
+        }
+        if let Some(res) = self.resolution {
+            if !(320..=7680).contains(&res.width) || !(240..=4320).contains(&res.height) {
+                return Err(format!(
+                    "Resolution {}x{} is outside the supported range",
+                    res.width, res.height
+                ));
+            }
+        }
+
+        let codec = Codec::from(self.codec);
+        if !supported_encoders.contains(&codec) {
+            return Err(format!(
+                "{:?} encoding is not available on this machine (available: {:?})",
+                codec, supported_encoders
+            ));
+        }
+        Ok(codec)
+    }
+
+    pub fn session_policy(&self) -> SessionPolicy {
+        let secs = |value: u32| (value > 0).then(|| Duration::from_secs(value.into()));
+        SessionPolicy {
+            max_session: secs(self.max_session_secs),
+            idle_timeout: secs(self.idle_timeout_secs),
+            lock_on_disconnect: self.lock_on_disconnect,
+        }
+    }
+
+    pub fn encode_config(
+        &self,
+        codec: Codec,
+        resolution: Resolution,
+        display_id: u32,
+    ) -> EncodeConfig {
+        EncodeConfig {
+            codec,
+            resolution,
+            fps: self.fps,
+            bitrate_kbps: self.bitrate_kbps,
+            keyframe_interval_ms: self.keyframe_interval_ms,
+            display_id: Some(display_id),
+            enable_10bit: false,
+            enable_hdr: false,
+            hide_cursor: false,
+            capture_mode: self.capture_mode,
+            tuning: EncoderTuning::for_codec(codec, self.content),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_validates_with_h264() {
+        assert_eq!(
+            HostConfig::default().validate(&[Codec::H264]),
+            Ok(Codec::H264)
+        );
+    }
+
+    #[test]
+    fn unsupported_codec_is_rejected() {
+        let config = HostConfig {
+            codec: PreferredCodec::Av1,
+            ..HostConfig::default()
+        };
+        assert!(config.validate(&[Codec::H264, Codec::Hevc]).is_err());
+    }
+
+    #[test]
+    fn saved_settings_pick_codec_bitrate_and_resolution() {
+        let settings = DesktopSettings {
+            default_codec: PreferredCodec::Hevc,
+            bitrate_kbps: 20_000,
+            resolution_mode: ResolutionMode::Custom,
+            custom_width: 2560,
+            custom_height: 1440,
+            ..DesktopSettings::default()
+        };
+        let config = HostConfig::from(&settings);
+        assert_eq!(config.codec, PreferredCodec::Hevc);
+        assert_eq!(config.bitrate_kbps, 20_000);
+        assert_eq!(
+            config.resolution,
+            Some(Resolution {
+                width: 2560,
+                height: 1440
+            })
+        );
+        assert_eq!(
+            HostConfig::from(&DesktopSettings::default()).resolution,
+            None
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let supported = [Codec::H264];
+        let fps = HostConfig {
+            fps: 0,
+            ..HostConfig::default()
+        };
+        assert!(fps.validate(&supported).is_err());
+
+        let resolution = HostConfig {
+            resolution: Some(Resolution {
+                width: 100,
+                height: 100,
+            }),
+            ..HostConfig::default()
+        };
+        assert!(resolution.validate(&supported).is_err());
+    }
+}
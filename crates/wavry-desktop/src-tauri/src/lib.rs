@@ -1,9 +1,20 @@
+pub mod app_data;
 pub mod auth;
 pub mod client_manager;
 pub mod commands;
+pub mod file_transfer;
+pub mod history;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub mod host_capture;
+pub mod host_config;
 pub mod media_utils;
+pub mod monitor_watch;
+pub mod offer_approval;
+pub mod relay_fallback;
 pub mod secure_storage;
+pub mod settings;
 pub mod state;
+pub mod tray;
 
 #[cfg(target_os = "linux")]
 fn is_wayland_session() -> bool {
@@ -105,29 +116,70 @@ pub fn run() {
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let handle = app.handle();
+            match settings::settings_path(handle).and_then(|path| settings::load_from(&path)) {
+                Ok(settings) => state::BACKGROUND_HOSTING.store(
+                    settings.background_hosting,
+                    std::sync::atomic::Ordering::Relaxed,
+                ),
+                Err(e) => log::warn!("Failed to load settings at startup: {}", e),
+            }
+            tray::init(handle)?;
+            monitor_watch::spawn(handle.clone());
+            Ok(())
+        })
+        .on_window_event(tray::handle_window_event)
         .invoke_handler(tauri::generate_handler![
             commands::greet,
             commands::get_pcvr_status,
             commands::set_cc_config,
+            commands::set_bandwidth_limit,
+            commands::set_local_recording,
             commands::get_cc_stats,
             commands::register,
             commands::login_full,
             commands::set_signaling_token,
+            commands::search_directory,
             commands::start_session,
             commands::stop_session,
+            commands::list_client_sessions,
+            commands::get_session_stats,
             commands::send_file_transfer_command,
+            commands::send_file,
+            commands::cancel_transfer,
+            commands::set_clipboard_sync,
+            commands::get_clipboard_sync_status,
             commands::list_monitors,
             commands::linux_runtime_health,
             commands::linux_host_preflight,
             commands::connect_via_id,
             commands::start_host,
             commands::stop_host,
+            commands::get_host_status,
+            commands::set_background_hosting,
+            commands::accept_offer,
+            commands::reject_offer,
+            commands::approve_device,
+            commands::deny_device,
             commands::save_secure_token,
             commands::load_secure_token,
             commands::delete_secure_token,
             commands::save_secure_data,
             commands::load_secure_data,
             commands::delete_secure_data,
+            commands::load_settings,
+            commands::save_settings,
+            commands::list_connection_history,
+            commands::pin_connection,
+            commands::delete_connection,
+            commands::set_connection_mac,
+            commands::wake_host,
+            commands::register_wake_host,
+            commands::list_wake_hosts,
+            commands::unregister_wake_host,
+            commands::create_invite,
+            commands::list_lan_hosts,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
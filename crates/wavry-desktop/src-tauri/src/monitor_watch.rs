@@ -0,0 +1,134 @@
+use crate::state::SESSION_STATE;
+use std::sync::Mutex;
+use wavry_media::{replacement_display, DisplayInfo, DisplayWatch};
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+use wavry_media::CapabilityProbe;
+#[cfg(target_os = "linux")]
+use wavry_media::LinuxProbe;
+#[cfg(target_os = "macos")]
+use wavry_media::MacProbe;
+#[cfg(target_os = "windows")]
+use wavry_media::WindowsProbe;
+
+pub const MONITORS_CHANGED_EVENT: &str = "monitors_changed";
+
+/// Last display list pushed to the frontend.
+static LAST_MONITORS: Mutex<Option<Vec<DisplayInfo>>> = Mutex::new(None);
+
+pub fn enumerate_monitors() -> Result<Vec<DisplayInfo>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        MacProbe
+            .enumerate_displays()
+            .map_err(|e: anyhow::Error| e.to_string())
+    }
+    #[cfg(target_os = "windows")]
+    {
+        WindowsProbe
+            .enumerate_displays()
+            .map_err(|e: anyhow::Error| e.to_string())
+    }
+    #[cfg(target_os = "linux")]
+    {
+        LinuxProbe
+            .enumerate_displays()
+            .map_err(|e: anyhow::Error| e.to_string())
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        Ok(Vec::new())
+    }
+}
+
+fn watch_displays() -> anyhow::Result<DisplayWatch> {
+    #[cfg(target_os = "macos")]
+    {
+        MacProbe.watch_displays()
+    }
+    #[cfg(target_os = "windows")]
+    {
+        WindowsProbe.watch_displays()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        LinuxProbe.watch_displays()
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        Err(anyhow::anyhow!(
+            "display change notifications are not supported on this platform"
+        ))
+    }
+}
+
+/// Record a fresh enumeration, emitting `monitors_changed` and retargeting an
+/// active host session if its display disappeared.
+pub fn publish_monitors(app_handle: &tauri::AppHandle, displays: &[DisplayInfo]) {
+    {
+        let mut last = LAST_MONITORS.lock().unwrap();
+        if last.as_deref() == Some(displays) {
+            return;
+        }
+        *last = Some(displays.to_vec());
+    }
+
+    log::info!(
+        "Monitor configuration changed: {} display(s)",
+        displays.len()
+    );
+    let _ = tauri::Emitter::emit(app_handle, MONITORS_CHANGED_EVENT, displays);
+
+    let mut state = SESSION_STATE.lock().unwrap();
+    let Some(session) = state.as_mut() else {
+        return;
+    };
+    let Some(current) = session.display_id else {
+        return;
+    };
+    let Some(replacement) = replacement_display(displays, current) else {
+        return;
+    };
+    if let Some(tx) = session.display_switch_tx.as_ref() {
+        log::warn!(
+            "Host display {} disappeared; switching capture to {} ({})",
+            current,
+            replacement.id,
+            replacement.name
+        );
+        if tx.send(replacement.clone()).is_ok() {
+            session.display_id = Some(replacement.id);
+        }
+    }
+}
+
+/// Re-enumerate displays in the background whenever the platform reports a
+/// hotplug or layout change.
+pub fn spawn(app_handle: tauri::AppHandle) {
+    #[cfg(target_os = "linux")]
+    if crate::is_wayland_session() {
+        // Wayland enumeration goes through a portal session; only refresh on explicit requests.
+        log::info!(
+            "Monitor hotplug detection disabled on Wayland; relying on list_monitors refreshes"
+        );
+        return;
+    }
+
+    let mut watch = match watch_displays() {
+        Ok(watch) => watch,
+        Err(e) => {
+            log::info!("Monitor hotplug detection unavailable: {}", e);
+            return;
+        }
+    };
+    tauri::async_runtime::spawn(async move {
+        while watch.changed().await.is_some() {
+            match tauri::async_runtime::spawn_blocking(enumerate_monitors).await {
+                Ok(Ok(displays)) => publish_monitors(&app_handle, &displays),
+                Ok(Err(e)) => log::debug!("Monitor enumeration failed: {}", e),
+                Err(e) => log::debug!("Monitor enumeration task failed: {}", e),
+            }
+        }
+        log::info!("Monitor hotplug detection stopped");
+    });
+}
@@ -0,0 +1,267 @@
+use crate::state::{OfferDecision, SESSION_STATE};
+use rift_core::{Codec as RiftCodec, Platform as RiftPlatform};
+use rift_crypto::{ClientDecision, WavryId};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Event emitted to the frontend when a remote peer sends an `OFFER_RIFT`.
+pub const INCOMING_OFFER_EVENT: &str = "incoming_offer";
+
+/// Event emitted to the frontend, with the offer id, when an offer is dropped
+/// before the user decided on it.
+pub const OFFER_EXPIRED_EVENT: &str = "offer_expired";
+
+/// How long an offer waits for the user; the client has stopped waiting
+/// for the answer by then.
+pub const OFFER_TTL: Duration = Duration::from_secs(30);
+/// Offers held at once; the oldest is dropped to make room for a new one.
+pub const MAX_PENDING_OFFERS: usize = 16;
+
+/// Event emitted to the frontend when a device the user has not decided on
+/// connects.
+pub const DEVICE_APPROVAL_EVENT: &str = "device_approval";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IncomingOfferEvent {
+    pub offer_id: String,
+    pub username: String,
+    pub client_name: String,
+    pub platform: String,
+    pub supported_codecs: Vec<String>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub max_fps: u32,
+    pub input_caps: u32,
+    /// The peer wants to send its microphone into this machine.
+    pub microphone: bool,
+}
+
+/// Offer received from signaling that is waiting for the user's decision.
+pub struct PendingOffer {
+    pub username: String,
+    pub hello: rift_core::Hello,
+    /// The client's candidates, punched towards when the offer is accepted.
+    pub candidates: Vec<rift_core::ice::IceCandidate>,
+}
+
+/// Offers waiting for the user's decision, dropped after [`OFFER_TTL`] and
+/// capped at [`MAX_PENDING_OFFERS`].
+#[derive(Default)]
+pub struct PendingOffers {
+    offers: HashMap<String, (Instant, PendingOffer)>,
+}
+
+impl PendingOffers {
+    /// Holds `offer` and returns the ids dropped for age or to make room.
+    pub fn insert(&mut self, offer_id: String, offer: PendingOffer, now: Instant) -> Vec<String> {
+        let mut dropped = self.expire(now);
+        while self.offers.len() >= MAX_PENDING_OFFERS {
+            let Some(oldest) = self
+                .offers
+                .iter()
+                .min_by_key(|(_, (received, _))| *received)
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+            self.offers.remove(&oldest);
+            dropped.push(oldest);
+        }
+        self.offers.insert(offer_id, (now, offer));
+        dropped
+    }
+
+    /// The offer `offer_id`, unless it is unknown or has expired.
+    pub fn take(&mut self, offer_id: &str, now: Instant) -> Option<PendingOffer> {
+        let (received, offer) = self.offers.remove(offer_id)?;
+        (now.saturating_duration_since(received) < OFFER_TTL).then_some(offer)
+    }
+
+    /// Drops offers older than [`OFFER_TTL`] and returns their ids.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let expired: Vec<String> = self
+            .offers
+            .iter()
+            .filter(|(_, (received, _))| now.saturating_duration_since(*received) >= OFFER_TTL)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.offers.remove(id);
+        }
+        expired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offers.is_empty()
+    }
+}
+
+impl IncomingOfferEvent {
+    pub fn new(offer_id: &str, username: &str, hello: &rift_core::Hello) -> Self {
+        let platform = RiftPlatform::try_from(hello.platform)
+            .map(|p| p.as_str_name().to_string())
+            .unwrap_or_else(|_| "UNKNOWN".to_string());
+        let supported_codecs = hello
+            .supported_codecs
+            .iter()
+            .filter_map(|c| RiftCodec::try_from(*c).ok())
+            .map(|c| c.as_str_name().to_string())
+            .collect();
+
+        Self {
+            offer_id: offer_id.to_string(),
+            username: username.to_string(),
+            client_name: hello.client_name.clone(),
+            platform,
+            supported_codecs,
+            max_width: hello.max_resolution.as_ref().map(|r| r.width),
+            max_height: hello.max_resolution.as_ref().map(|r| r.height),
+            max_fps: hello.max_fps,
+            input_caps: hello.input_caps,
+            microphone: hello.microphone,
+        }
+    }
+}
+
+pub fn send_offer_decision(offer_id: String, accept: bool) -> Result<(), String> {
+    let tx = {
+        let state = SESSION_STATE.lock().unwrap();
+        state.as_ref().and_then(|s| s.offer_decision_tx.clone())
+    };
+
+    let Some(tx) = tx else {
+        return Err("No active host session".into());
+    };
+
+    tx.send(OfferDecision { offer_id, accept })
+        .map_err(|_| "Host is not connected to signaling".to_string())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceApprovalEvent {
+    pub wavry_id: String,
+    pub client_name: String,
+    pub addr: String,
+}
+
+impl DeviceApprovalEvent {
+    pub fn new(wavry_id: &WavryId, client_name: &str, addr: std::net::SocketAddr) -> Self {
+        Self {
+            wavry_id: wavry_id.to_string(),
+            client_name: client_name.to_string(),
+            addr: addr.to_string(),
+        }
+    }
+}
+
+pub fn send_device_decision(wavry_id: String, allow: bool) -> Result<(), String> {
+    let wavry_id = WavryId::parse(&wavry_id).map_err(|e| e.to_string())?;
+    let tx = {
+        let state = SESSION_STATE.lock().unwrap();
+        state.as_ref().and_then(|s| s.device_decision_tx.clone())
+    };
+
+    let Some(tx) = tx else {
+        return Err("No active host session".into());
+    };
+
+    let decision = if allow {
+        ClientDecision::Allow
+    } else {
+        ClientDecision::Deny
+    };
+    tx.send((wavry_id, decision))
+        .map_err(|_| "Host session has ended".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(username: &str) -> PendingOffer {
+        PendingOffer {
+            username: username.to_string(),
+            hello: rift_core::Hello::default(),
+            candidates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pending_offers_expire_and_stay_bounded() {
+        let start = Instant::now();
+        let mut offers = PendingOffers::default();
+        assert!(offers.insert("a".into(), offer("alice"), start).is_empty());
+        assert!(offers.take("a", start + OFFER_TTL).is_none());
+
+        offers.insert("b".into(), offer("bob"), start);
+        assert_eq!(offers.expire(start + OFFER_TTL), vec!["b".to_string()]);
+        assert!(offers.is_empty());
+
+        for i in 0..MAX_PENDING_OFFERS {
+            let at = start + Duration::from_millis(i as u64);
+            assert!(offers.insert(i.to_string(), offer("carol"), at).is_empty());
+        }
+        let at = start + Duration::from_secs(1);
+        assert_eq!(offers.insert("new".into(), offer("dave"), at), vec!["0"]);
+        assert_eq!(offers.take("new", at).unwrap().username, "dave");
+        assert!(offers.take("0", at).is_none());
+    }
+
+    #[test]
+    fn incoming_offer_event_maps_hello_capabilities() {
+        let hello = rift_core::Hello {
+            client_name: "wavry-desktop".to_string(),
+            platform: rift_core::Platform::Windows as i32,
+            supported_codecs: vec![rift_core::Codec::H264 as i32, rift_core::Codec::Av1 as i32],
+            max_resolution: Some(rift_core::Resolution {
+                width: 2560,
+                height: 1440,
+            }),
+            max_fps: 120,
+            input_caps: 0xF,
+            protocol_version: 1,
+            public_addr: String::new(),
+            candidate_addrs: vec![],
+            stereo_modes: vec![],
+            audio_layouts: vec![],
+            fec_schemes: vec![],
+            cursor_channel: false,
+            audio_params: None,
+            compact_header: false,
+            transport_feedback: false,
+            microphone: true,
+            clipboard_sync: None,
+        };
+
+        let event = IncomingOfferEvent::new("offer-1", "alice", &hello);
+        assert_eq!(event.username, "alice");
+        assert_eq!(event.platform, "WINDOWS");
+        assert_eq!(event.supported_codecs, vec!["H264", "AV1"]);
+        assert_eq!(event.max_width, Some(2560));
+        assert_eq!(event.max_fps, 120);
+        assert!(event.microphone);
+    }
+
+    #[test]
+    fn incoming_offer_event_tolerates_unknown_platform() {
+        let hello = rift_core::Hello {
+            platform: 99,
+            ..Default::default()
+        };
+        let event = IncomingOfferEvent::new("offer-2", "bob", &hello);
+        assert_eq!(event.platform, "UNKNOWN");
+        assert!(event.supported_codecs.is_empty());
+        assert_eq!(event.max_width, None);
+    }
+
+    #[test]
+    fn device_approval_event_formats_identity_and_address() {
+        let wavry_id = rift_crypto::IdentityKeypair::generate().wavry_id();
+        let addr = "192.168.1.30:41000".parse().unwrap();
+        let event = DeviceApprovalEvent::new(&wavry_id, "Laptop", addr);
+        assert_eq!(event.wavry_id, wavry_id.to_string());
+        assert_eq!(event.client_name, "Laptop");
+        assert_eq!(event.addr, "192.168.1.30:41000");
+    }
+}
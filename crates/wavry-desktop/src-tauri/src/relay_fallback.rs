@@ -0,0 +1,137 @@
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use wavry_client::signaling::{SignalMessage, SignalingClient};
+use wavry_client::{ClientRuntimeStats, RelayInfo};
+
+pub const CONNECT_PROGRESS_EVENT: &str = "connect-progress";
+
+/// How long connect_via_id waits for the handshake before returning with the
+/// session still retrying.
+pub const CONNECT_PROGRESS_TIMEOUT: Duration = Duration::from_secs(5);
+const RELAY_CREDENTIALS_TIMEOUT: Duration = Duration::from_secs(8);
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectStage {
+    Waking,
+    Signaling,
+    AnswerReceived,
+    ProbingDirect,
+    RequestingRelay,
+    ConnectingRelay,
+    Connected,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectProgress {
+    pub target: String,
+    pub stage: ConnectStage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+pub fn emit_progress(
+    app_handle: &tauri::AppHandle,
+    target: &str,
+    stage: ConnectStage,
+    detail: Option<String>,
+) {
+    let _ = tauri::Emitter::emit(
+        app_handle,
+        CONNECT_PROGRESS_EVENT,
+        ConnectProgress {
+            target: target.to_string(),
+            stage,
+            detail,
+        },
+    );
+}
+
+pub fn relay_info_from_credentials(
+    relay_id: String,
+    token: String,
+    addr: &str,
+    session_id: uuid::Uuid,
+) -> Result<RelayInfo, String> {
+    let addr = addr
+        .parse::<SocketAddr>()
+        .map_err(|_| "Relay credentials contained invalid relay address".to_string())?;
+    Ok(RelayInfo {
+        relay_id,
+        addr,
+        token,
+        session_id,
+    })
+}
+
+/// Ask the gateway for a relay towards `target_username` and wait for credentials.
+pub async fn request_relay_credentials(
+    sig: &mut SignalingClient,
+    target_username: &str,
+    region: Option<String>,
+) -> Result<RelayInfo, String> {
+    sig.send(SignalMessage::REQUEST_RELAY {
+        target_username: target_username.to_string(),
+        region,
+    })
+    .await
+    .map_err(|e: anyhow::Error| format!("Failed to request relay: {}", e))?;
+
+    tokio::time::timeout(RELAY_CREDENTIALS_TIMEOUT, async {
+        loop {
+            match sig.recv().await {
+                Ok(SignalMessage::RELAY_CREDENTIALS {
+                    relay_id,
+                    token,
+                    addr,
+                    session_id,
+                }) => break relay_info_from_credentials(relay_id, token, &addr, session_id),
+                Ok(SignalMessage::ERROR { message, .. }) => break Err(message),
+                Ok(_) => continue,
+                Err(e) => break Err(e.to_string()),
+            }
+        }
+    })
+    .await
+    .map_err(|_| "Timed out waiting for relay credentials".to_string())?
+}
+
+/// Wait until the client reports an established session or `timeout` expires.
+pub async fn wait_for_connection(stats: &ClientRuntimeStats, timeout: Duration) -> bool {
+    tokio::time::timeout(timeout, async {
+        while !stats.connected.load(Ordering::Relaxed) {
+            tokio::time::sleep(PROBE_POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay_info_rejects_invalid_address() {
+        let session_id = uuid::Uuid::new_v4();
+        assert!(
+            relay_info_from_credentials("r".into(), "t".into(), "not-an-addr", session_id).is_err()
+        );
+        let relay =
+            relay_info_from_credentials("r".into(), "t".into(), "10.0.0.1:4000", session_id)
+                .unwrap();
+        assert_eq!(relay.addr, "10.0.0.1:4000".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn wait_for_connection_times_out_when_never_connected() {
+        let stats = ClientRuntimeStats::default();
+        assert!(!wait_for_connection(&stats, Duration::from_millis(150)).await);
+        stats.connected.store(true, Ordering::Relaxed);
+        assert!(wait_for_connection(&stats, Duration::from_millis(150)).await);
+    }
+}
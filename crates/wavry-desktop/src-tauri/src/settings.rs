@@ -0,0 +1,209 @@
+use crate::app_data::{app_data_file, write_atomic};
+use rift_core::input::{normalize_gamepad_deadzone, DEFAULT_GAMEPAD_DEADZONE};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreferredCodec {
+    H264,
+    Hevc,
+    Av1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolutionMode {
+    Native,
+    Client,
+    Custom,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    pub enabled: bool,
+    pub deadzone: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            deadzone: DEFAULT_GAMEPAD_DEADZONE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelaySettings {
+    /// Allow falling back to a relay when no direct route is available.
+    pub allow_relay: bool,
+    /// Skip direct connectivity and always request a relay.
+    pub force_relay: bool,
+    pub preferred_region: Option<String>,
+}
+
+impl Default for RelaySettings {
+    fn default() -> Self {
+        Self {
+            allow_relay: true,
+            force_relay: false,
+            preferred_region: None,
+        }
+    }
+}
+
+/// Desktop preferences persisted in the app data directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DesktopSettings {
+    /// Host stream codec and bitrate; see `HostConfig::from`.
+    pub default_codec: PreferredCodec,
+    pub bitrate_kbps: u32,
+    pub resolution_mode: ResolutionMode,
+    pub custom_width: u32,
+    pub custom_height: u32,
+    pub gamepad: GamepadSettings,
+    pub relay: RelaySettings,
+    /// Keep hosting from the system tray after the window is closed.
+    pub background_hosting: bool,
+}
+
+impl Default for DesktopSettings {
+    fn default() -> Self {
+        Self {
+            default_codec: PreferredCodec::H264,
+            bitrate_kbps: 8000,
+            resolution_mode: ResolutionMode::Native,
+            custom_width: 1920,
+            custom_height: 1080,
+            gamepad: GamepadSettings::default(),
+            relay: RelaySettings::default(),
+            background_hosting: true,
+        }
+    }
+}
+
+impl DesktopSettings {
+    /// Clamp values that the frontend may have left out of range.
+    pub fn sanitized(mut self) -> Self {
+        self.bitrate_kbps = self.bitrate_kbps.clamp(500, 200_000);
+        self.custom_width = self.custom_width.clamp(320, 7680);
+        self.custom_height = self.custom_height.clamp(240, 4320);
+        self.gamepad.deadzone = normalize_gamepad_deadzone(self.gamepad.deadzone);
+        self.relay.preferred_region = self
+            .relay
+            .preferred_region
+            .map(|region| region.trim().to_string())
+            .filter(|region| !region.is_empty());
+        self
+    }
+}
+
+pub fn settings_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_data_file(app_handle, SETTINGS_FILE)
+}
+
+pub fn load_from(path: &Path) -> Result<DesktopSettings, String> {
+    match std::fs::read_to_string(path) {
+        Ok(raw) => match serde_json::from_str::<DesktopSettings>(&raw) {
+            Ok(settings) => Ok(settings.sanitized()),
+            Err(e) => {
+                log::warn!(
+                    "Ignoring unreadable settings file {}: {}",
+                    path.display(),
+                    e
+                );
+                Ok(DesktopSettings::default())
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DesktopSettings::default()),
+        Err(e) => Err(format!("Failed to read settings: {}", e)),
+    }
+}
+
+pub fn save_to(path: &Path, settings: &DesktopSettings) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(settings).map_err(|e| e.to_string())?;
+    write_atomic(path, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_settings_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("wavry-settings-{}-{}", name, uuid::Uuid::new_v4()))
+            .join(SETTINGS_FILE)
+    }
+
+    #[test]
+    fn load_missing_file_returns_defaults() {
+        let path = temp_settings_path("missing");
+        assert_eq!(load_from(&path).unwrap(), DesktopSettings::default());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = temp_settings_path("roundtrip");
+        let settings = DesktopSettings {
+            default_codec: PreferredCodec::Av1,
+            bitrate_kbps: 25_000,
+            resolution_mode: ResolutionMode::Custom,
+            custom_width: 2560,
+            custom_height: 1440,
+            gamepad: GamepadSettings {
+                enabled: false,
+                deadzone: 0.2,
+            },
+            relay: RelaySettings {
+                allow_relay: true,
+                force_relay: true,
+                preferred_region: Some("eu-west".into()),
+            },
+            background_hosting: false,
+        };
+        save_to(&path, &settings).unwrap();
+        assert_eq!(load_from(&path).unwrap(), settings);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn partial_settings_fill_defaults_and_clamp() {
+        let parsed: DesktopSettings =
+            serde_json::from_str(r#"{"bitrate_kbps": 1, "gamepad": {"deadzone": 3.0}}"#).unwrap();
+        let parsed = parsed.sanitized();
+        assert_eq!(parsed.bitrate_kbps, 500);
+        assert_eq!(parsed.gamepad.deadzone, 0.95);
+        assert!(parsed.gamepad.enabled);
+        assert_eq!(parsed.default_codec, PreferredCodec::H264);
+    }
+
+    #[test]
+    fn non_finite_deadzone_falls_back_to_default() {
+        for deadzone in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let settings = DesktopSettings {
+                gamepad: GamepadSettings {
+                    enabled: true,
+                    deadzone,
+                },
+                ..DesktopSettings::default()
+            }
+            .sanitized();
+            assert_eq!(settings.gamepad.deadzone, DEFAULT_GAMEPAD_DEADZONE);
+        }
+        let negative = DesktopSettings {
+            gamepad: GamepadSettings {
+                enabled: true,
+                deadzone: -0.5,
+            },
+            ..DesktopSettings::default()
+        }
+        .sanitized();
+        assert_eq!(negative.gamepad.deadzone, 0.0);
+    }
+}
@@ -1,19 +1,45 @@
-use std::sync::{atomic::AtomicU32, Arc, Mutex};
-use tokio::sync::{broadcast, mpsc, oneshot};
-use wavry_client::FileTransferCommand;
+use crate::history::ConnectionTarget;
+use std::collections::BTreeMap;
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use wavry_sdk::host::HostCounters;
 
 /// Global session state for the desktop app
 pub struct SessionState {
     pub stop_tx: Option<oneshot::Sender<()>>,
     pub cc_config_tx: Option<mpsc::UnboundedSender<rift_core::cc::DeltaConfig>>,
-    pub current_bitrate: Arc<AtomicU32>,
-    pub cc_state: Arc<Mutex<String>>,
+    pub counters: Arc<HostCounters>,
+    pub offer_decision_tx: Option<mpsc::UnboundedSender<OfferDecision>>,
+    /// Answers for devices waiting in [`wavry_sdk::SessionEvent::PendingApproval`].
+    pub device_decision_tx:
+        Option<mpsc::UnboundedSender<(rift_crypto::WavryId, rift_crypto::ClientDecision)>>,
+    /// Display currently being captured, if the host targets a specific one.
+    pub display_id: Option<u32>,
+    pub display_switch_tx: Option<mpsc::UnboundedSender<wavry_media::DisplayInfo>>,
+    /// Caps the DeltaCC ceiling of the running host session, in kbps.
+    pub bandwidth_limit_tx: Option<mpsc::UnboundedSender<u32>>,
+}
+
+impl SessionState {
+    /// DELTA state for display; `Stable` until the first congestion signal.
+    pub fn cc_state_label(&self) -> String {
+        self.counters
+            .cc_state()
+            .map(|state| format!("{:?}", state))
+            .unwrap_or_else(|| "Stable".to_string())
+    }
+}
+
+/// User decision for a pending incoming offer, routed to the host signaling task.
+pub struct OfferDecision {
+    pub offer_id: String,
+    pub accept: bool,
 }
 
 pub struct ClientSessionState {
-    pub stop_tx: Option<oneshot::Sender<()>>,
-    pub monitor_tx: Option<mpsc::UnboundedSender<u32>>,
-    pub file_command_tx: Option<broadcast::Sender<FileTransferCommand>>,
+    pub target: ConnectionTarget,
+    pub started_at_unix_ms: u64,
+    pub session: wavry_sdk::ClientSession,
 }
 
 pub struct AuthState {
@@ -22,6 +48,10 @@ pub struct AuthState {
 }
 
 pub static SESSION_STATE: Mutex<Option<SessionState>> = Mutex::new(None);
-pub static CLIENT_SESSION_STATE: Mutex<Option<ClientSessionState>> = Mutex::new(None);
+/// Active client sessions keyed by session id.
+pub static CLIENT_SESSIONS: Mutex<BTreeMap<String, ClientSessionState>> =
+    Mutex::new(BTreeMap::new());
 pub static AUTH_STATE: Mutex<Option<AuthState>> = Mutex::new(None);
 pub static IDENTITY_KEY: Mutex<Option<rift_crypto::IdentityKeypair>> = Mutex::new(None);
+/// Keep hosting when the main window is closed; mirrors `DesktopSettings::background_hosting`.
+pub static BACKGROUND_HOSTING: AtomicBool = AtomicBool::new(true);
@@ -0,0 +1,114 @@
+use crate::state::{BACKGROUND_HOSTING, SESSION_STATE};
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Emitter, Manager, Window, WindowEvent};
+
+const TRAY_ID: &str = "wavry-tray";
+const MAIN_WINDOW: &str = "main";
+
+const MENU_SHOW: &str = "show";
+const MENU_STATUS: &str = "status";
+const MENU_STOP_HOST: &str = "stop_host";
+const MENU_QUIT: &str = "quit";
+
+/// Emitted when the user picks "Host Status" from the tray menu.
+pub const TRAY_STATUS_EVENT: &str = "tray-host-status";
+/// Emitted when hosting is stopped from the tray rather than the window.
+pub const TRAY_HOST_STOPPED_EVENT: &str = "tray-host-stopped";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostStatus {
+    pub hosting: bool,
+    pub background_hosting: bool,
+    pub bitrate_kbps: Option<u32>,
+    pub cc_state: Option<String>,
+}
+
+pub fn host_status() -> HostStatus {
+    let state = SESSION_STATE.lock().unwrap();
+    let session = state.as_ref();
+    HostStatus {
+        hosting: session.is_some(),
+        background_hosting: BACKGROUND_HOSTING.load(Ordering::Relaxed),
+        bitrate_kbps: session.map(|s| s.counters.target_bitrate_kbps()),
+        cc_state: session.map(|s| s.cc_state_label()),
+    }
+}
+
+/// Signal the host task to stop. Returns false when no host is running.
+pub fn request_host_stop() -> bool {
+    let stop_tx = {
+        let mut state = SESSION_STATE.lock().unwrap();
+        state.as_mut().and_then(|s| s.stop_tx.take())
+    };
+
+    match stop_tx {
+        Some(tx) => {
+            let _ = tx.send(());
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let show = MenuItem::with_id(app, MENU_SHOW, "Show Wavry", true, None::<&str>)?;
+    let status = MenuItem::with_id(app, MENU_STATUS, "Host Status", true, None::<&str>)?;
+    let stop_host = MenuItem::with_id(app, MENU_STOP_HOST, "Stop Hosting", true, None::<&str>)?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let quit = MenuItem::with_id(app, MENU_QUIT, "Quit Wavry", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show, &status, &stop_host, &separator, &quit])?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Wavry")
+        .menu(&menu)
+        .on_menu_event(|app, event| handle_menu_event(app, event.id().as_ref()));
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+fn handle_menu_event(app: &AppHandle, id: &str) {
+    match id {
+        MENU_SHOW => show_main_window(app),
+        MENU_STATUS => {
+            show_main_window(app);
+            let _ = app.emit(TRAY_STATUS_EVENT, host_status());
+        }
+        MENU_STOP_HOST => {
+            if request_host_stop() {
+                log::info!("Hosting stopped from tray");
+                let _ = app.emit(TRAY_HOST_STOPPED_EVENT, ());
+            }
+        }
+        MENU_QUIT => {
+            request_host_stop();
+            app.exit(0);
+        }
+        _ => {}
+    }
+}
+
+/// Hide the window instead of closing it while a host session is running,
+/// so the host task keeps streaming in the background.
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::CloseRequested { api, .. } = event {
+        if BACKGROUND_HOSTING.load(Ordering::Relaxed) && host_status().hosting {
+            log::info!("Window closed while hosting; continuing in the background");
+            api.prevent_close();
+            let _ = window.hide();
+        }
+    }
+}
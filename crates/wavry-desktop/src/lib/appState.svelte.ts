@@ -37,6 +37,158 @@ interface LinuxHostPreflight {
     diagnostics: LinuxRuntimeDiagnostics;
 }
 
+export interface DesktopSettings {
+    default_codec: "h264" | "hevc" | "av1";
+    bitrate_kbps: number;
+    resolution_mode: "native" | "client" | "custom";
+    custom_width: number;
+    custom_height: number;
+    gamepad: { enabled: boolean; deadzone: number };
+    relay: { allow_relay: boolean; force_relay: boolean; preferred_region: string | null };
+    background_hosting: boolean;
+}
+
+export interface HostConfig {
+    codec: DesktopSettings["default_codec"];
+    resolution: { width: number; height: number } | null;
+    fps: number;
+    bitrate_kbps: number;
+    keyframe_interval_ms: number;
+    display_id: number | null;
+    port_mapping: boolean;
+    max_session_secs: number;
+    idle_timeout_secs: number;
+    lock_on_disconnect: boolean;
+    capture_mode: "fixed" | "damage";
+}
+
+export interface ConnectionRecord {
+    id: string;
+    target: { kind: "address" | "username"; value: string };
+    started_at_unix_ms: number;
+    duration_ms: number;
+    quality: { connected: boolean; frames_decoded: number; average_fps: number };
+    pinned: boolean;
+    mac_address?: string;
+}
+
+export interface LanHost {
+    name: string;
+    fullname: string;
+    addrs: string[];
+    version: string | null;
+    txt: Record<string, string>;
+}
+
+export interface FileTransferUpdate {
+    session_id?: string;
+    file_id: number;
+    direction: "outgoing" | "incoming";
+    filename?: string;
+    file_size?: number;
+    transferred_chunks?: number;
+    total_chunks?: number;
+    path?: string;
+    error?: string;
+}
+
+export type ClipboardSyncDirection = "disabled" | "to_host" | "from_host" | "bidirectional";
+
+export interface ConnectProgress {
+    target: string;
+    stage:
+        | "waking"
+        | "signaling"
+        | "answer_received"
+        | "probing_direct"
+        | "requesting_relay"
+        | "connecting_relay"
+        | "connected"
+        | "failed";
+    detail?: string;
+}
+
+export interface ClientSessionInfo {
+    session_id: string;
+    target: ConnectionRecord["target"];
+    started_at_unix_ms: number;
+    connected: boolean;
+    frames_decoded: number;
+    latency: LatencyBreakdown | null;
+    permissions: SessionPermissions;
+}
+
+export interface SessionPermissions {
+    input: boolean;
+    clipboard: boolean;
+    file_transfer: boolean;
+}
+
+export interface LatencyBreakdown {
+    frame_id: number;
+    capture_us: number;
+    encode_us: number;
+    pacing_us: number;
+    network_us: number;
+    decode_us: number;
+    render_us: number;
+}
+
+export interface ClipboardSyncStatus {
+    session_id: string;
+    direction: ClipboardSyncDirection;
+    sent_updates: number;
+    received_updates: number;
+}
+
+export interface DirectoryEntry {
+    username: string;
+    display_name: string;
+}
+
+export interface DirectorySearchResult {
+    results: DirectoryEntry[];
+    next_cursor: string | null;
+}
+
+export interface WakeHost {
+    name: string;
+    mac_address: string;
+    broadcast_addr: string | null;
+    updated_at: string;
+}
+
+export interface WakeHostList {
+    hosts: WakeHost[];
+    online: boolean;
+}
+
+export interface Invite {
+    token: string;
+    url: string;
+    /** Unix seconds. */
+    expires_at: number;
+}
+
+export interface IncomingOffer {
+    offer_id: string;
+    username: string;
+    client_name: string;
+    platform: string;
+    supported_codecs: string[];
+    max_width: number | null;
+    max_height: number | null;
+    max_fps: number;
+    input_caps: number;
+    microphone: boolean;
+}
+
+export interface DeviceApproval {
+    wavry_id: string;
+    client_name: string;
+    addr: string;
+}
+
 export class AppState {
     displayName = $state("");
     connectivityMode = $state<"wavry" | "direct" | "custom">("wavry");
@@ -50,6 +202,7 @@ export class AppState {
     // CC Stats
     ccBitrate = $state(0);
     ccState = $state("Stable");
+    latencySummary = $state<any>(null);
     ccConfig = $state<DeltaConfig>({
         target_delay_us: 15000,
         alpha: 0.125,
@@ -69,6 +222,15 @@ export class AppState {
     hostStatusMessage = $state("");
     hostErrorMessage = $state("");
     pcvrStatus = $state("PCVR: Unknown");
+    pendingOffers = $state<IncomingOffer[]>([]);
+    pendingDevices = $state<DeviceApproval[]>([]);
+    connectionHistory = $state<ConnectionRecord[]>([]);
+    lanHosts = $state<LanHost[]>([]);
+    isScanningLan = $state(false);
+    clipboardSync = $state<ClipboardSyncStatus | null>(null);
+    clientSessions = $state<ClientSessionInfo[]>([]);
+    activeSessionId = $state<string | null>(null);
+    fileTransfers = $state<Record<number, FileTransferUpdate & { state: "active" | "completed" | "failed" }>>({});
 
     // Monitor state
     monitors = $state<{ id: number, name: string, resolution: { width: number, height: number } }[]>([]);
@@ -86,9 +248,27 @@ export class AppState {
     gamepadDeadzone = $state(0.1);
 
     // Settings
+    defaultCodec = $state<DesktopSettings["default_codec"]>("h264");
+    bitrateKbps = $state(8000);
+    hostFps = $state(60);
+    keyframeIntervalMs = $state(2000);
+    portMapping = $state(true);
+    backgroundHosting = $state(true);
+    bandwidthLimitKbps = $state<number | null>(null);
+    relaySettings = $state<DesktopSettings["relay"]>({
+        allow_relay: true,
+        force_relay: false,
+        preferred_region: null,
+    });
     authServer = $state("https://auth.wavry.dev");
     hostPort = $state(0);
     upnpEnabled = $state(true);
+    // Host session policy; 0 minutes turns a limit off.
+    maxSessionMinutes = $state(0);
+    idleTimeoutMinutes = $state(0);
+    lockOnDisconnect = $state(false);
+    // "damage" encodes only when the screen changes.
+    captureMode = $state<HostConfig["capture_mode"]>("fixed");
 
     private parseStoredNumber(key: string, fallback: number): number {
         const raw = localStorage.getItem(key);
@@ -121,6 +301,8 @@ export class AppState {
     private sanitizeSettings() {
         this.hostPort = Math.trunc(this.clamp(this.hostPort, 0, 65535));
         this.gamepadDeadzone = this.clamp(this.gamepadDeadzone, 0, 0.5);
+        this.maxSessionMinutes = Math.trunc(this.clamp(this.maxSessionMinutes, 0, 24 * 60));
+        this.idleTimeoutMinutes = Math.trunc(this.clamp(this.idleTimeoutMinutes, 0, 24 * 60));
         this.customResolution = {
             width: Math.trunc(this.clamp(this.customResolution.width, 640, 8192)),
             height: Math.trunc(this.clamp(this.customResolution.height, 480, 8192)),
@@ -215,6 +397,10 @@ export class AppState {
         localStorage.setItem("authServer", this.authServer);
         localStorage.setItem("hostPort", String(this.hostPort));
         localStorage.setItem("upnpEnabled", this.upnpEnabled ? "true" : "false");
+        localStorage.setItem("maxSessionMinutes", String(this.maxSessionMinutes));
+        localStorage.setItem("idleTimeoutMinutes", String(this.idleTimeoutMinutes));
+        localStorage.setItem("lockOnDisconnect", this.lockOnDisconnect ? "true" : "false");
+        localStorage.setItem("captureMode", this.captureMode);
         localStorage.setItem("resolutionMode", this.resolutionMode);
         localStorage.setItem("customResolutionWidth", String(this.customResolution.width));
         localStorage.setItem("customResolutionHeight", String(this.customResolution.height));
@@ -225,6 +411,39 @@ export class AppState {
         } else {
             localStorage.removeItem("selectedMonitorId");
         }
+        this.persistBackendSettings();
+    }
+
+    private toBackendSettings(): DesktopSettings {
+        return {
+            default_codec: this.defaultCodec,
+            bitrate_kbps: this.bitrateKbps,
+            resolution_mode: this.resolutionMode,
+            custom_width: this.customResolution.width,
+            custom_height: this.customResolution.height,
+            gamepad: { enabled: this.gamepadEnabled, deadzone: this.gamepadDeadzone },
+            relay: { ...this.relaySettings },
+            background_hosting: this.backgroundHosting,
+        };
+    }
+
+    private applyBackendSettings(settings: DesktopSettings) {
+        this.defaultCodec = settings.default_codec;
+        this.bitrateKbps = settings.bitrate_kbps;
+        this.resolutionMode = settings.resolution_mode;
+        this.customResolution = { width: settings.custom_width, height: settings.custom_height };
+        this.gamepadEnabled = settings.gamepad.enabled;
+        this.gamepadDeadzone = settings.gamepad.deadzone;
+        this.relaySettings = { ...settings.relay };
+        this.backgroundHosting = settings.background_hosting;
+    }
+
+    private async persistBackendSettings() {
+        try {
+            await invoke("save_settings", { settings: this.toBackendSettings() });
+        } catch (e) {
+            console.error("Failed to persist settings:", e);
+        }
     }
 
     resetSettingsToDefaults() {
@@ -278,6 +497,10 @@ export class AppState {
         this.authServer = localStorage.getItem("authServer") || "https://auth.wavry.dev";
         this.hostPort = this.parseStoredNumber("hostPort", 0);
         this.upnpEnabled = localStorage.getItem("upnpEnabled") !== "false";
+        this.maxSessionMinutes = this.parseStoredNumber("maxSessionMinutes", 0);
+        this.idleTimeoutMinutes = this.parseStoredNumber("idleTimeoutMinutes", 0);
+        this.lockOnDisconnect = localStorage.getItem("lockOnDisconnect") === "true";
+        this.captureMode = localStorage.getItem("captureMode") === "damage" ? "damage" : "fixed";
         const resolutionMode = localStorage.getItem("resolutionMode");
         this.resolutionMode =
             resolutionMode === "native" || resolutionMode === "client" || resolutionMode === "custom"
@@ -289,6 +512,11 @@ export class AppState {
         };
         this.gamepadEnabled = localStorage.getItem("gamepadEnabled") !== "false";
         this.gamepadDeadzone = this.parseStoredNumber("gamepadDeadzone", 0.1);
+        try {
+            this.applyBackendSettings(await invoke<DesktopSettings>("load_settings"));
+        } catch (e) {
+            console.error("Failed to load persisted settings:", e);
+        }
         const storedMonitor = localStorage.getItem("selectedMonitorId");
         const parsedMonitor = storedMonitor == null ? null : Number(storedMonitor);
         this.selectedMonitorId = parsedMonitor != null && Number.isFinite(parsedMonitor) ? parsedMonitor : null;
@@ -308,6 +536,237 @@ export class AppState {
                 this.hostStatusMessage = "Host error occurred. Retrying automatically...";
             }
         });
+
+        listen<any[]>("monitors_changed", (event) => {
+            this.applyMonitorList(event.payload);
+        });
+
+        listen("tray-host-stopped", () => {
+            this.pendingOffers = [];
+            this.pendingDevices = [];
+            this.isHosting = false;
+            this.isConnected = false;
+            this.connectionStatus = "offline";
+            this.hostStatusMessage = "Hosting stopped from tray";
+            this.stopCCStatsPolling();
+        });
+
+        listen<FileTransferUpdate>("file-transfer-progress", (event) => {
+            this.updateFileTransfer(event.payload, "active");
+        });
+        listen<FileTransferUpdate>("file-transfer-completed", (event) => {
+            this.updateFileTransfer(event.payload, "completed");
+        });
+        listen<FileTransferUpdate>("file-transfer-failed", (event) => {
+            this.updateFileTransfer(event.payload, "failed");
+        });
+        listen<{ paths: string[] }>("tauri://drag-drop", async (event) => {
+            if (!this.isConnected || this.isHosting) return;
+            for (const path of event.payload.paths) {
+                try {
+                    await this.sendFile(path);
+                } catch (e) {
+                    console.error("Failed to send dropped file:", e);
+                }
+            }
+        });
+
+        listen<ClipboardSyncStatus>("clipboard-sync-changed", (event) => {
+            if (event.payload.session_id === this.activeSessionId) {
+                this.clipboardSync = event.payload;
+            }
+        });
+
+        listen<ConnectProgress>("connect-progress", (event) => {
+            const { target, stage, detail } = event.payload;
+            const label: Record<ConnectProgress["stage"], string> = {
+                waking: `Waking ${target} and waiting for it to come online...`,
+                signaling: `Sending cloud request to ${target}...`,
+                answer_received: `${target} accepted the request`,
+                probing_direct: `Trying direct connection to ${target}...`,
+                requesting_relay: "Requesting relay...",
+                connecting_relay: `Connecting to ${target} via relay...`,
+                connected: `Connected to ${target}`,
+                failed: `Connection to ${target} failed`,
+            };
+            this.hostStatusMessage = detail ? `${label[stage]} (${detail})` : label[stage];
+        });
+
+        listen<string>("client-session-ended", (event) => {
+            this.handleClientSessionEnded(event.payload);
+        });
+
+        listen<IncomingOffer>("incoming_offer", (event) => {
+            this.pendingOffers = [...this.pendingOffers, event.payload];
+        });
+
+        listen<string>("offer_expired", (event) => {
+            this.pendingOffers = this.pendingOffers.filter((offer) => offer.offer_id !== event.payload);
+        });
+
+        listen<DeviceApproval>("device_approval", (event) => {
+            this.pendingDevices = [
+                ...this.pendingDevices.filter((device) => device.wavry_id !== event.payload.wavry_id),
+                event.payload,
+            ];
+        });
+    }
+
+    private updateFileTransfer(update: FileTransferUpdate, state: "active" | "completed" | "failed") {
+        const previous = this.fileTransfers[update.file_id];
+        this.fileTransfers = {
+            ...this.fileTransfers,
+            [update.file_id]: { ...previous, ...update, state },
+        };
+    }
+
+    /** What the active session's host allows; controls for the rest should be disabled. */
+    get activePermissions(): SessionPermissions {
+        const session = this.clientSessions.find((s) => s.session_id === this.activeSessionId);
+        return session?.permissions ?? { input: true, clipboard: true, file_transfer: true };
+    }
+
+    async setClipboardSync(direction: ClipboardSyncDirection) {
+        this.clipboardSync = await invoke<ClipboardSyncStatus>("set_clipboard_sync", {
+            direction,
+            sessionId: this.activeSessionId,
+        });
+    }
+
+    async sendFile(path: string) {
+        const fileId = await invoke<number>("send_file", { path, sessionId: this.activeSessionId });
+        this.updateFileTransfer({ file_id: fileId, direction: "outgoing", filename: path.split(/[\\/]/).pop() }, "active");
+        return fileId;
+    }
+
+    async cancelTransfer(fileId: number) {
+        await invoke("cancel_transfer", { fileId, sessionId: this.activeSessionId });
+    }
+
+    async refreshClientSessions() {
+        try {
+            this.clientSessions = await invoke<ClientSessionInfo[]>("list_client_sessions");
+        } catch (e) {
+            console.error("Failed to list client sessions:", e);
+        }
+    }
+
+    /** Track a newly started session and make it the active tab. */
+    async addClientSession(sessionId: string) {
+        this.activeSessionId = sessionId;
+        await this.refreshClientSessions();
+    }
+
+    private handleClientSessionEnded(sessionId: string) {
+        this.clientSessions = this.clientSessions.filter((s) => s.session_id !== sessionId);
+        if (this.activeSessionId === sessionId) {
+            this.activeSessionId = this.clientSessions[0]?.session_id ?? null;
+        }
+        if (this.clientSessions.length === 0 && !this.isHosting) {
+            this.isConnected = false;
+            this.connectionStatus = "offline";
+            this.stopCCStatsPolling();
+        }
+    }
+
+    async stopClientSession(sessionId: string) {
+        await invoke("stop_session", { sessionId });
+    }
+
+    async refreshConnectionHistory() {
+        try {
+            this.connectionHistory = await invoke<ConnectionRecord[]>("list_connection_history");
+        } catch (e) {
+            console.error("Failed to load connection history:", e);
+        }
+    }
+
+    async pinConnection(id: string, pinned: boolean) {
+        await invoke("pin_connection", { id, pinned });
+        await this.refreshConnectionHistory();
+    }
+
+    async deleteConnection(id: string) {
+        await invoke("delete_connection", { id });
+        await this.refreshConnectionHistory();
+    }
+
+    async setConnectionMac(id: string, mac: string | null) {
+        await invoke("set_connection_mac", { id, mac });
+        await this.refreshConnectionHistory();
+    }
+
+    async scanLanHosts(timeoutMs: number | null = null) {
+        this.isScanningLan = true;
+        try {
+            this.lanHosts = await invoke<LanHost[]>("list_lan_hosts", { timeoutMs });
+        } catch (e) {
+            console.error("Failed to scan for LAN hosts:", e);
+        } finally {
+            this.isScanningLan = false;
+        }
+    }
+
+    async wakeHost(mac: string, broadcastAddr: string | null = null) {
+        try {
+            await invoke("wake_host", { mac, broadcastAddr });
+            this.hostStatusMessage = `Wake packet sent to ${mac}`;
+        } catch (e: unknown) {
+            const message = this.normalizeError(e);
+            this.hostErrorMessage = `Wake-on-LAN failed: ${message}`;
+            throw new Error(message);
+        }
+    }
+
+    async respondToOffer(offerId: string, accept: boolean) {
+        this.pendingOffers = this.pendingOffers.filter((offer) => offer.offer_id !== offerId);
+        try {
+            await invoke(accept ? "accept_offer" : "reject_offer", { offerId });
+        } catch (e: unknown) {
+            const message = this.normalizeError(e);
+            this.hostErrorMessage = `Failed to respond to connection request: ${message}`;
+            throw new Error(message);
+        }
+    }
+
+    async respondToDevice(wavryId: string, allow: boolean) {
+        this.pendingDevices = this.pendingDevices.filter((device) => device.wavry_id !== wavryId);
+        try {
+            await invoke(allow ? "approve_device" : "deny_device", { wavryId });
+        } catch (e: unknown) {
+            const message = this.normalizeError(e);
+            this.hostErrorMessage = `Failed to respond to device approval: ${message}`;
+            throw new Error(message);
+        }
+    }
+
+    async registerWakeHost(name: string, mac: string, broadcastAddr: string | null = null) {
+        return invoke<WakeHost>("register_wake_host", {
+            name,
+            mac,
+            broadcastAddr,
+            server: this.authServer,
+        });
+    }
+
+    async listWakeHosts() {
+        return invoke<WakeHostList>("list_wake_hosts", { server: this.authServer });
+    }
+
+    async unregisterWakeHost(name: string) {
+        await invoke("unregister_wake_host", { name, server: this.authServer });
+    }
+
+    async createInvite(ttlSecs: number | null = null) {
+        return invoke<Invite>("create_invite", { ttlSecs, server: this.authServer });
+    }
+
+    async searchDirectory(query: string, cursor: string | null = null) {
+        return invoke<DirectorySearchResult>("search_directory", {
+            query,
+            cursor,
+            server: this.authServer,
+        });
     }
 
     async register(details: any) {
@@ -349,6 +808,22 @@ export class AppState {
         return this.username || this.displayName || "Local Host";
     }
 
+    private hostConfig(): HostConfig {
+        return {
+            codec: this.defaultCodec,
+            resolution: this.resolutionMode === "custom" ? this.customResolution : null,
+            fps: this.hostFps,
+            bitrate_kbps: this.bitrateKbps,
+            keyframe_interval_ms: this.keyframeIntervalMs,
+            display_id: this.selectedMonitorId,
+            port_mapping: this.portMapping,
+            max_session_secs: this.maxSessionMinutes * 60,
+            idle_timeout_secs: this.idleTimeoutMinutes * 60,
+            lock_on_disconnect: this.lockOnDisconnect,
+            capture_mode: this.captureMode,
+        };
+    }
+
     async updateCCConfig() {
         try {
             await invoke("set_cc_config", { config: this.ccConfig });
@@ -357,6 +832,25 @@ export class AppState {
         }
     }
 
+    async setBandwidthLimit(kbps: number) {
+        try {
+            await invoke("set_bandwidth_limit", { kbps });
+            this.bandwidthLimitKbps = kbps;
+        } catch (e: unknown) {
+            console.error("Failed to set bandwidth limit:", e);
+            throw new Error(this.normalizeError(e));
+        }
+    }
+
+    async setLocalRecording(enabled: boolean, sessionId?: string) {
+        try {
+            await invoke("set_local_recording", { enabled, sessionId });
+        } catch (e: unknown) {
+            console.error("Failed to toggle local recording:", e);
+            throw new Error(this.normalizeError(e));
+        }
+    }
+
     async refreshPcvrStatus() {
         try {
             const status = await invoke<string>("get_pcvr_status");
@@ -377,8 +871,11 @@ export class AppState {
             }
             try {
                 const stats: any = await invoke("get_cc_stats");
-                this.ccBitrate = stats.bitrate_kbps;
-                this.ccState = stats.state;
+                if (stats.bitrate_kbps !== undefined) {
+                    this.ccBitrate = stats.bitrate_kbps;
+                    this.ccState = stats.state;
+                }
+                this.latencySummary = stats.latency_summary ?? null;
             } catch (e) {
                 // Silently fail if session ended
             }
@@ -392,24 +889,32 @@ export class AppState {
         }
     }
 
+    /** Returns false when no monitors are attached. */
+    private applyMonitorList(list: any[]): boolean {
+        this.monitors = list;
+        if (list.length === 0) {
+            this.selectedMonitorId = null;
+            localStorage.removeItem("selectedMonitorId");
+            return false;
+        }
+
+        if (
+            this.selectedMonitorId === null ||
+            !list.some((monitor) => monitor.id === this.selectedMonitorId)
+        ) {
+            this.selectedMonitorId = list[0].id;
+        }
+        this.saveToStorage();
+        return true;
+    }
+
     async loadMonitors() {
         this.isLoadingMonitors = true;
         try {
             const list: any[] = await invoke("list_monitors");
-            this.monitors = list;
-            if (list.length === 0) {
-                this.selectedMonitorId = null;
-                localStorage.removeItem("selectedMonitorId");
+            if (!this.applyMonitorList(list)) {
                 return;
             }
-
-            if (
-                this.selectedMonitorId === null ||
-                !list.some((monitor) => monitor.id === this.selectedMonitorId)
-            ) {
-                this.selectedMonitorId = list[0].id;
-            }
-            this.saveToStorage();
             await this.refreshLinuxRuntimeHealth();
         } catch (e: unknown) {
             console.error("Failed to list monitors:", e);
@@ -447,7 +952,9 @@ export class AppState {
 
     async connect(ip: string) {
         const target = ip.trim();
-        const addressError = this.validateConnectTarget(target);
+        // Invite links are redeemed by the client through the gateway.
+        const invite = target.includes("/invite/") ? target : null;
+        const addressError = invite ? null : this.validateConnectTarget(target);
         if (addressError) throw new Error(addressError);
         const settingsError = this.validateSettingsInputs();
         if (settingsError) throw new Error(settingsError);
@@ -465,8 +972,9 @@ export class AppState {
         }
 
         try {
-            const result = await invoke("start_session", {
-                addr: target,
+            const sessionId = await invoke<string>("start_session", {
+                addr: invite ? "" : target,
+                invite,
                 resolution_mode: this.resolutionMode,
                 width: resolution?.width,
                 height: resolution?.height,
@@ -477,7 +985,8 @@ export class AppState {
             this.isConnected = true;
             this.hostStatusMessage = `Session started with ${target}`;
             this.startCCStatsPolling();
-            return result;
+            await this.addClientSession(sessionId);
+            return sessionId;
         } catch (e: unknown) {
             this.connectionStatus = "offline";
             this.isConnected = false;
@@ -508,7 +1017,7 @@ export class AppState {
 
             const backendMessage = await invoke<string>("start_host", {
                 port: this.hostPort,
-                display_id: this.selectedMonitorId,
+                config: this.hostConfig(),
             });
             this.isHosting = true;
             this.isConnected = true;
@@ -537,10 +1046,13 @@ export class AppState {
         this.hostStatusMessage = "Stopping host...";
         try {
             await invoke("stop_host");
+            this.pendingOffers = [];
+            this.pendingDevices = [];
             this.isHosting = false;
             this.isConnected = false;
             this.connectionStatus = "offline";
             this.hostStatusMessage = "Hosting stopped";
+            this.bandwidthLimitKbps = null;
             this.stopCCStatsPolling();
         } catch (e: unknown) {
             console.error("Failed to stop host:", e);
@@ -569,6 +1081,9 @@ export class AppState {
             }
             this.isConnected = false;
             this.connectionStatus = "offline";
+            this.bandwidthLimitKbps = null;
+            this.clientSessions = [];
+            this.activeSessionId = null;
             this.stopCCStatsPolling();
             if (stopErrorMessage) {
                 this.hostStatusMessage = "";
@@ -587,6 +1102,7 @@ export class AppState {
         const response = await invoke<string>("send_file_transfer_command", {
             file_id: fileId,
             action,
+            sessionId: this.activeSessionId,
         });
         return response;
     }
@@ -15,6 +15,9 @@ typedef struct {
     uint32_t bitrate_kbps;
     uint32_t keyframe_interval_ms;
     uint32_t display_id; // u32::MAX for None
+    uint32_t max_session_secs; // 0 for no limit
+    uint32_t idle_timeout_secs; // 0 to never pause
+    bool lock_on_disconnect;
 } WavryHostConfig;
 
 typedef struct {
@@ -40,6 +43,16 @@ int32_t wavry_start_host(uint16_t port);
 int32_t wavry_start_host_with_config(uint16_t port, const WavryHostConfig *config);
 int32_t wavry_start_client(const char *host_ip, uint16_t port);
 
+// Host Permissions
+#define WAVRY_PERMISSION_GRANTED 0
+#define WAVRY_PERMISSION_DENIED 1
+#define WAVRY_PERMISSION_NOT_DETERMINED 2
+#define WAVRY_PERMISSION_NOT_REQUIRED 3
+
+int32_t wavry_host_permission_status(void);
+int32_t wavry_host_request_permission(void);
+void wavry_host_set_permission_result(bool granted);
+
 // Signaling / Cloud
 int32_t wavry_connect_signaling(const char *token);
 int32_t wavry_connect_signaling_with_url(const char *url, const char *token);
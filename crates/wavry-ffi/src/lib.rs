@@ -3,6 +3,7 @@
 use once_cell::sync::Lazy;
 use std::ffi::{c_char, CStr, CString};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::runtime::Runtime;
 use wavry_client::RelayInfo;
 
@@ -45,10 +46,12 @@ use wavry_media::{InputInjector, MacInputInjector};
 
 mod session;
 use session::{
-    run_client, run_host, ClientSessionParams, HostRuntimeConfig, SessionHandle, SessionStats,
+    wait_until_connected, HostRuntimeConfig, SessionHandle, SharedRenderer, CLIENT_STARTUP_TIMEOUT,
 };
+use wavry_sdk::{ClientSession, SessionEvent, SessionPolicy};
 
 mod identity;
+mod permission;
 mod signaling_ffi;
 
 // Global State
@@ -182,6 +185,11 @@ pub struct WavryHostConfig {
     pub bitrate_kbps: u32,
     pub keyframe_interval_ms: u32,
     pub display_id: u32,
+    /// 0 for no limit.
+    pub max_session_secs: u32,
+    /// 0 to never pause.
+    pub idle_timeout_secs: u32,
+    pub lock_on_disconnect: bool,
 }
 
 fn normalize_host_config(raw: &WavryHostConfig) -> HostRuntimeConfig {
@@ -196,6 +204,8 @@ fn normalize_host_config(raw: &WavryHostConfig) -> HostRuntimeConfig {
         Some(raw.display_id)
     };
 
+    let secs = |value: u32| (value > 0).then(|| Duration::from_secs(value.into()));
+
     HostRuntimeConfig {
         codec: wavry_media::Codec::H264,
         width,
@@ -204,6 +214,11 @@ fn normalize_host_config(raw: &WavryHostConfig) -> HostRuntimeConfig {
         bitrate_kbps,
         keyframe_interval_ms,
         display_id,
+        policy: SessionPolicy {
+            max_session: secs(raw.max_session_secs),
+            idle_timeout: secs(raw.idle_timeout_secs),
+            lock_on_disconnect: raw.lock_on_disconnect,
+        },
     }
 }
 
@@ -215,52 +230,53 @@ fn start_host_internal(port: u16, host_config: HostRuntimeConfig) -> i32 {
         return -1;
     }
 
-    clear_cloud_status();
-
-    let stats = Arc::new(SessionStats::default());
-    let (tx, rx) = tokio::sync::oneshot::channel();
-    let (init_tx, init_rx) = tokio::sync::oneshot::channel::<anyhow::Result<u16>>();
+    if let Some(reason) = permission::host_start_blocker() {
+        log::warn!("Refusing to start host: {}", reason);
+        set_last_error(&format!("Host start failed: {}", reason));
+        return -6;
+    }
 
-    let stats_clone = stats.clone();
-    RUNTIME.spawn(async move {
-        if let Err(e) = run_host(port, host_config, stats_clone, rx, init_tx).await {
-            log::error!("Host error: {}", e);
-        }
-    });
+    clear_cloud_status();
 
-    match init_rx.blocking_recv() {
-        Ok(Ok(bound_port)) => {
-            *guard = Some(SessionHandle {
-                stop_tx: Some(tx),
-                monitor_tx: None, // Host mode doesn't currently use monitor_tx
-                stats,
-            });
-            clear_last_error();
-            set_cloud_status(&format!("Hosting on UDP {}", bound_port));
-            log::info!(
-                "Started Host (requested port {}, bound port {}) ({}x{} @ {}fps, {} kbps, keyframe {}ms, display {:?})",
-                port,
-                bound_port,
-                host_config.width,
-                host_config.height,
-                host_config.fps,
-                host_config.bitrate_kbps,
-                host_config.keyframe_interval_ms,
-                host_config.display_id
-            );
-            0
-        }
-        Ok(Err(e)) => {
+    let mut session = match RUNTIME.block_on(host_config.builder(port).start()) {
+        Ok(session) => session,
+        Err(e) => {
             log::error!("Failed to start host: {}", e);
             set_last_error(&format!("Host start failed: {}", e));
-            -2
-        }
-        Err(_) => {
-            log::error!("Host init channel closed unexpectedly");
-            set_last_error("Host start failed: initialization channel closed");
-            -3
+            return -2;
         }
+    };
+    let bound_port = session.port();
+    signaling_ffi::set_hosting(bound_port);
+    if let Some(mut events) = session.take_events() {
+        RUNTIME.spawn(async move {
+            while let Some(event) = events.recv().await {
+                if let SessionEvent::Ended { error } = event {
+                    if let Some(e) = error {
+                        log::error!("Host error: {}", e);
+                    }
+                    signaling_ffi::clear_hosting();
+                    break;
+                }
+            }
+        });
     }
+
+    *guard = Some(SessionHandle::Host(session));
+    clear_last_error();
+    set_cloud_status(&format!("Hosting on UDP {}", bound_port));
+    log::info!(
+        "Started Host (requested port {}, bound port {}) ({}x{} @ {}fps, {} kbps, keyframe {}ms, display {:?})",
+        port,
+        bound_port,
+        host_config.width,
+        host_config.height,
+        host_config.fps,
+        host_config.bitrate_kbps,
+        host_config.keyframe_interval_ms,
+        host_config.display_id
+    );
+    0
 }
 
 /// Start Host Mode (Screen Capture -> UDP Stream)
@@ -285,6 +301,27 @@ pub unsafe extern "C" fn wavry_start_host_with_config(
     start_host_internal(port, config)
 }
 
+/// Screen-recording permission for host mode: one of the `WAVRY_PERMISSION_*`
+/// values (0 granted, 1 denied, 2 not determined, 3 not required).
+#[no_mangle]
+pub extern "C" fn wavry_host_permission_status() -> i32 {
+    permission::status()
+}
+
+/// Triggers the platform permission flow where native code can, and returns
+/// the resulting status. On Android the shell must show the MediaProjection
+/// consent itself and report it with `wavry_host_set_permission_result`.
+#[no_mangle]
+pub extern "C" fn wavry_host_request_permission() -> i32 {
+    permission::request()
+}
+
+/// Records the outcome of a permission flow run by the shell.
+#[no_mangle]
+pub extern "C" fn wavry_host_set_permission_result(granted: bool) {
+    permission::set_result(granted);
+}
+
 /// Start Client Mode (UDP Stream -> Remote Display)
 fn start_client_internal(
     direct_target: Option<(String, u16)>,
@@ -311,55 +348,71 @@ fn start_client_internal(
         "unknown target".to_string()
     };
 
-    let stats = Arc::new(SessionStats::default());
-    let (tx, rx) = tokio::sync::oneshot::channel();
-    let (init_tx, init_rx) = tokio::sync::oneshot::channel();
-    let (monitor_tx, monitor_rx) = tokio::sync::mpsc::unbounded_channel::<u32>();
-
-    let stats_clone = stats.clone();
-    let renderer = VIDEO_RENDERER.clone(); // Shared Reference
-
-    RUNTIME.spawn(async move {
-        if let Err(e) = run_client(ClientSessionParams {
-            direct_target,
-            relay_info,
-            client_name,
-            renderer_handle: renderer,
-            stats: stats_clone,
-            stop_rx: rx,
-            init_tx,
-            monitor_rx,
-        })
-        .await
-        {
-            set_last_error(&format!("Client runtime error: {}", e));
-            log::error!("Client error: {}", e);
+    let mut builder = ClientSession::builder(client_name);
+    if let Some((host, port)) = direct_target {
+        match format!("{}:{}", host, port).parse() {
+            Ok(addr) => builder = builder.connect_addr(addr),
+            Err(e) => {
+                set_last_error(&format!("Client start failed: Invalid address: {}", e));
+                return -4;
+            }
         }
-    });
+    }
+    if let Some(relay) = relay_info {
+        builder = builder.relay(relay);
+    }
+    if let Some(key) = identity::get_private_key() {
+        builder = builder.identity_key(key);
+    }
+    let renderer = VIDEO_RENDERER.clone(); // Shared Reference
+    builder = builder.renderer_factory(Box::new(move |_config| {
+        Ok(Box::new(SharedRenderer(renderer.clone())))
+    }));
 
-    // Wait for initialization
-    match init_rx.blocking_recv() {
-        Ok(Ok(())) => {
-            *guard = Some(SessionHandle {
-                stop_tx: Some(tx),
-                monitor_tx: Some(monitor_tx),
-                stats,
-            });
-            clear_last_error();
-            log::info!("Started Client connecting to {}", target_label);
-            0
-        }
-        Ok(Err(e)) => {
+    let started = {
+        let _runtime = RUNTIME.enter();
+        builder.start()
+    };
+    let mut session = match started {
+        Ok(session) => session,
+        Err(e) => {
             log::error!("Failed to start client: {}", e);
             set_last_error(&format!("Client start failed: {}", e));
-            -4
-        }
-        Err(_) => {
-            log::error!("Client init channel closed");
-            set_last_error("Client start failed: initialization channel closed");
-            -5
+            return -5;
         }
+    };
+    let Some(mut events) = session.take_events() else {
+        set_last_error("Client start failed: session events unavailable");
+        return -5;
+    };
+
+    // Wait for initialization
+    if let Err(e) = RUNTIME.block_on(wait_until_connected(&mut events, CLIENT_STARTUP_TIMEOUT)) {
+        session.stop();
+        log::error!("Failed to start client: {} ({})", e, target_label);
+        set_last_error(&format!("Client start failed: {}", e));
+        return -4;
     }
+
+    RUNTIME.spawn(async move {
+        while let Some(event) = events.recv().await {
+            if let SessionEvent::Ended { error } = event {
+                match error {
+                    Some(e) => {
+                        set_last_error(&format!("Client runtime error: {}", e));
+                        log::error!("Client error: {}", e);
+                    }
+                    None => log::info!("Client finished normally"),
+                }
+                break;
+            }
+        }
+    });
+
+    *guard = Some(SessionHandle::Client(session));
+    clear_last_error();
+    log::info!("Started Client connecting to {}", target_label);
+    0
 }
 
 pub(crate) fn start_client_with_targets(
@@ -424,14 +477,14 @@ pub unsafe extern "C" fn wavry_get_stats(out: *mut WavryStats) -> i32 {
 
     let guard = SESSION.lock().unwrap();
     if let Some(handle) = guard.as_ref() {
-        let s = &handle.stats;
+        let s = handle.stats();
         let stats = WavryStats {
-            connected: s.connected.load(std::sync::atomic::Ordering::Relaxed),
-            fps: s.fps.load(std::sync::atomic::Ordering::Relaxed),
-            rtt_ms: s.rtt_ms.load(std::sync::atomic::Ordering::Relaxed),
-            bitrate_kbps: s.bitrate_kbps.load(std::sync::atomic::Ordering::Relaxed),
-            frames_encoded: s.frames_encoded.load(std::sync::atomic::Ordering::Relaxed),
-            frames_decoded: s.frames_decoded.load(std::sync::atomic::Ordering::Relaxed),
+            connected: s.connected,
+            fps: s.fps,
+            rtt_ms: s.rtt_ms,
+            bitrate_kbps: s.bitrate_kbps,
+            frames_encoded: s.frames_encoded,
+            frames_decoded: s.frames_decoded,
         };
         *out = stats;
         clear_last_error();
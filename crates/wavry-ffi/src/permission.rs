@@ -0,0 +1,96 @@
+//! Screen-recording permission status for host mode.
+//!
+//! macOS gates capture behind TCC, which we can query and prompt for directly.
+//! Android's MediaProjection consent has to be launched from an Activity and
+//! its result stays with the shell, so the shell reports the outcome back via
+//! `wavry_host_set_permission_result`. Other platforms have no OS-level gate.
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub const WAVRY_PERMISSION_GRANTED: i32 = 0;
+pub const WAVRY_PERMISSION_DENIED: i32 = 1;
+/// The user has not been asked yet, or the answer is not visible to us.
+pub const WAVRY_PERMISSION_NOT_DETERMINED: i32 = 2;
+/// The platform has no screen-recording permission to grant.
+pub const WAVRY_PERMISSION_NOT_REQUIRED: i32 = 3;
+
+/// Last answer observed in this process; TCC and MediaProjection don't expose
+/// "denied" separately from "never asked".
+static LAST_ANSWER: AtomicI32 = AtomicI32::new(WAVRY_PERMISSION_NOT_DETERMINED);
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+#[cfg(target_os = "macos")]
+pub fn status() -> i32 {
+    if unsafe { CGPreflightScreenCaptureAccess() } {
+        WAVRY_PERMISSION_GRANTED
+    } else {
+        LAST_ANSWER.load(Ordering::Relaxed)
+    }
+}
+
+/// Shows the TCC prompt the first time; afterwards macOS only grants access
+/// from System Settings, usually followed by an app restart.
+#[cfg(target_os = "macos")]
+pub fn request() -> i32 {
+    let answer = if unsafe { CGRequestScreenCaptureAccess() } {
+        WAVRY_PERMISSION_GRANTED
+    } else {
+        WAVRY_PERMISSION_DENIED
+    };
+    LAST_ANSWER.store(answer, Ordering::Relaxed);
+    answer
+}
+
+#[cfg(target_os = "android")]
+pub fn status() -> i32 {
+    LAST_ANSWER.load(Ordering::Relaxed)
+}
+
+/// Native code can't receive the consent Activity result, so this only reports
+/// whether the shell still has to launch `createScreenCaptureIntent()`.
+#[cfg(target_os = "android")]
+pub fn request() -> i32 {
+    let current = status();
+    if current != WAVRY_PERMISSION_GRANTED {
+        crate::set_last_error(
+            "Screen capture needs MediaProjection consent: launch \
+             MediaProjectionManager.createScreenCaptureIntent() and report the result",
+        );
+    }
+    current
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "android")))]
+pub fn status() -> i32 {
+    WAVRY_PERMISSION_NOT_REQUIRED
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "android")))]
+pub fn request() -> i32 {
+    WAVRY_PERMISSION_NOT_REQUIRED
+}
+
+pub fn set_result(granted: bool) {
+    let answer = if granted {
+        WAVRY_PERMISSION_GRANTED
+    } else {
+        WAVRY_PERMISSION_DENIED
+    };
+    LAST_ANSWER.store(answer, Ordering::Relaxed);
+}
+
+/// Why host start would fail on permissions, if it would.
+pub fn host_start_blocker() -> Option<&'static str> {
+    match status() {
+        WAVRY_PERMISSION_DENIED => Some("screen recording permission denied"),
+        // TCC's preflight is authoritative: capture fails until it's granted.
+        #[cfg(target_os = "macos")]
+        WAVRY_PERMISSION_NOT_DETERMINED => Some("screen recording permission not granted"),
+        _ => None,
+    }
+}
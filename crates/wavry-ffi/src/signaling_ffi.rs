@@ -234,9 +234,20 @@ pub async fn start_signaling_bg(url: String, token: String) {
 
             // Spawn sender task
             let mut send_half = client; // SignalingClient owns the stream
+            let mut connected = send_half.connected();
 
             loop {
                 tokio::select! {
+                    // The client reconnects on its own; mirror its state.
+                    Ok(()) = connected.changed() => {
+                        let up = *connected.borrow_and_update();
+                        SIGNALING.is_connected.store(up, Ordering::SeqCst);
+                        if up {
+                            info!("Signaling reconnected");
+                        } else {
+                            warn!("Signaling connection lost, reconnecting");
+                        }
+                    }
                     // Outgoing messages
                     Some(msg) = rx.recv() => {
                         if let Err(e) = send_half.send(msg).await {
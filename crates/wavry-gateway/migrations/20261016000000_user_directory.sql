@@ -0,0 +1,5 @@
+-- Public user directory: opt-out flag and prefix-search index
+
+ALTER TABLE users ADD COLUMN directory_visible INTEGER NOT NULL DEFAULT 1;
+
+CREATE INDEX IF NOT EXISTS idx_users_display_name_nocase ON users(display_name COLLATE NOCASE);
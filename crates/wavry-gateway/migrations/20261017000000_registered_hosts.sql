@@ -0,0 +1,14 @@
+-- Hosts registered for wake-on-LAN, one row per machine of an account
+
+CREATE TABLE IF NOT EXISTS registered_hosts (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    mac_address TEXT NOT NULL,
+    broadcast_addr TEXT,
+    public_ip TEXT, -- where the host registered from; wake agents must share it
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(user_id, name),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
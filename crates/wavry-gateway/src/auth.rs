@@ -233,7 +233,7 @@ fn totp_from_secret(secret: &str) -> Result<TOTP, &'static str> {
     .map_err(|_| "Failed to initialize TOTP")
 }
 
-fn extract_session_token(headers: &HeaderMap) -> Option<String> {
+pub(crate) fn extract_session_token(headers: &HeaderMap) -> Option<String> {
     if let Some(value) = headers.get("x-session-token") {
         if let Ok(token) = value.to_str() {
             let trimmed = token.trim();
@@ -276,6 +276,7 @@ pub async fn register(
         || !security::is_valid_password(&payload.password)
         || !security::is_valid_display_name(&display_name)
         || !security::is_valid_username(&username)
+        || wavry_common::invite::is_guest_username(&username)
         || !security::is_valid_public_key_hex(&public_key)
     {
         AUTH_METRICS
@@ -249,6 +249,153 @@ pub async fn get_username_by_session_token(
     Ok(row.map(|v| v.0))
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DirectoryEntry {
+    pub username: String,
+    pub display_name: String,
+}
+
+/// Prefix search over visible, unbanned users, ordered by username.
+/// `after` is the last username of the previous page.
+pub async fn search_directory(
+    pool: &SqlitePool,
+    prefix: &str,
+    after: Option<&str>,
+    exclude_username: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<DirectoryEntry>> {
+    let pattern = format!("{}%", escape_like(prefix));
+    let rows = sqlx::query_as::<_, DirectoryEntry>(
+        r#"
+        SELECT u.username, u.display_name
+        FROM users u
+        WHERE u.directory_visible = 1
+            AND u.username != ''
+            AND u.username != ?
+            AND (u.username LIKE ? ESCAPE '\' OR u.display_name LIKE ? ESCAPE '\')
+            AND u.username > ?
+            AND NOT EXISTS (
+                SELECT 1 FROM user_bans b
+                WHERE b.user_id = u.id
+                    AND (b.expires_at IS NULL OR b.expires_at > CURRENT_TIMESTAMP)
+            )
+        ORDER BY u.username
+        LIMIT ?
+        "#,
+    )
+    .bind(exclude_username)
+    .bind(&pattern)
+    .bind(&pattern)
+    .bind(after.unwrap_or(""))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+pub async fn set_directory_visible(
+    pool: &SqlitePool,
+    username: &str,
+    visible: bool,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query("UPDATE users SET directory_visible = ? WHERE username = ?")
+        .bind(visible)
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RegisteredHost {
+    pub name: String,
+    pub mac_address: String,
+    pub broadcast_addr: Option<String>,
+    #[serde(skip)]
+    pub public_ip: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Registers `name` for `username`, or refreshes its MAC and addresses.
+/// Returns `false` if the user does not exist.
+pub async fn upsert_registered_host(
+    pool: &SqlitePool,
+    username: &str,
+    name: &str,
+    mac_address: &str,
+    broadcast_addr: Option<&str>,
+    public_ip: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO registered_hosts (id, user_id, name, mac_address, broadcast_addr, public_ip)
+        SELECT ?, id, ?, ?, ?, ? FROM users WHERE username = ?
+        ON CONFLICT(user_id, name) DO UPDATE SET
+            mac_address = excluded.mac_address,
+            broadcast_addr = excluded.broadcast_addr,
+            public_ip = excluded.public_ip,
+            updated_at = CURRENT_TIMESTAMP
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(name)
+    .bind(mac_address)
+    .bind(broadcast_addr)
+    .bind(public_ip)
+    .bind(username)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_registered_hosts(
+    pool: &SqlitePool,
+    username: &str,
+) -> anyhow::Result<Vec<RegisteredHost>> {
+    let rows = sqlx::query_as::<_, RegisteredHost>(
+        r#"
+        SELECT h.name, h.mac_address, h.broadcast_addr, h.public_ip, h.updated_at
+        FROM registered_hosts h
+        JOIN users u ON h.user_id = u.id
+        WHERE u.username = ?
+        ORDER BY h.name
+        "#,
+    )
+    .bind(username)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+pub async fn delete_registered_host(
+    pool: &SqlitePool,
+    username: &str,
+    name: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM registered_hosts
+        WHERE name = ? AND user_id = (SELECT id FROM users WHERE username = ?)
+        "#,
+    )
+    .bind(name)
+    .bind(username)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 pub async fn delete_expired_sessions(pool: &SqlitePool) -> anyhow::Result<u64> {
     let result = sqlx::query("DELETE FROM sessions WHERE expires_at <= datetime('now')")
         .execute(pool)
@@ -0,0 +1,249 @@
+//! Public user directory: username/display-name prefix search for connect
+//! autocomplete. Users can opt out, which hides them from every search.
+
+use axum::{
+    extract::{ConnectInfo, Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::SocketAddr;
+
+use crate::auth::extract_session_token;
+use crate::db::{self, DirectoryEntry};
+use crate::security;
+
+/// Shorter queries would let a caller page through the whole user table.
+pub const MIN_QUERY_LEN: usize = 2;
+pub const MAX_QUERY_LEN: usize = 64;
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+pub const MAX_PAGE_SIZE: usize = 25;
+
+#[derive(Debug, Deserialize)]
+pub struct DirectorySearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectorySearchResponse {
+    pub results: Vec<DirectoryEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DirectoryVisibilityRequest {
+    pub visible: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectoryVisibilityResponse {
+    pub visible: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+pub(crate) fn error_response(
+    status: StatusCode,
+    message: impl Into<String>,
+) -> axum::response::Response {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+        .into_response()
+}
+
+pub fn normalize_query(raw: &str) -> Result<String, &'static str> {
+    let query = raw.trim();
+    let len = query.chars().count();
+    if len < MIN_QUERY_LEN {
+        return Err("Search query too short");
+    }
+    if len > MAX_QUERY_LEN || query.chars().any(char::is_control) {
+        return Err("Invalid search query");
+    }
+    Ok(query.to_string())
+}
+
+pub fn page_size(requested: Option<usize>) -> usize {
+    requested
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Trims the one-row lookahead and returns the cursor for the next page.
+pub fn split_page(
+    mut rows: Vec<DirectoryEntry>,
+    page_size: usize,
+) -> (Vec<DirectoryEntry>, Option<String>) {
+    if rows.len() <= page_size {
+        return (rows, None);
+    }
+    rows.truncate(page_size);
+    let next_cursor = rows.last().map(|entry| entry.username.clone());
+    (rows, next_cursor)
+}
+
+/// Resolves the caller's session and applies the per-session rate limit.
+pub(crate) async fn authorize(
+    pool: &SqlitePool,
+    headers: &HeaderMap,
+    addr: SocketAddr,
+    scope: &str,
+) -> Result<String, axum::response::Response> {
+    let Some(token) = extract_session_token(headers) else {
+        return Err(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Missing bearer token",
+        ));
+    };
+    if !security::is_valid_session_token(&token) {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "Invalid session token",
+        ));
+    }
+
+    let client_ip = security::effective_client_ip(headers, addr);
+    let key = format!("{scope}:{}:{}", client_ip, security::hash_token(&token));
+    if !security::allow_directory_request(&key) {
+        return Err(error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many requests",
+        ));
+    }
+
+    match db::get_username_by_session_token(pool, &token).await {
+        Ok(Some(username)) => Ok(username),
+        Ok(None) => Err(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired session token",
+        )),
+        Err(err) => {
+            tracing::error!("session token lookup failed: {}", err);
+            Err(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Session lookup failed",
+            ))
+        }
+    }
+}
+
+pub async fn search(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(params): Query<DirectorySearchQuery>,
+) -> impl IntoResponse {
+    let username = match authorize(&pool, &headers, addr, "directory-search").await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let query = match normalize_query(&params.q) {
+        Ok(query) => query,
+        Err(msg) => return error_response(StatusCode::BAD_REQUEST, msg),
+    };
+    if let Some(cursor) = &params.cursor {
+        if !security::is_valid_username(cursor) {
+            return error_response(StatusCode::BAD_REQUEST, "Invalid cursor");
+        }
+    }
+
+    let page_size = page_size(params.limit);
+    match db::search_directory(
+        &pool,
+        &query,
+        params.cursor.as_deref(),
+        &username,
+        page_size as i64 + 1,
+    )
+    .await
+    {
+        Ok(rows) => {
+            let (results, next_cursor) = split_page(rows, page_size);
+            Json(DirectorySearchResponse {
+                results,
+                next_cursor,
+            })
+            .into_response()
+        }
+        Err(err) => {
+            tracing::error!("directory search failed: {}", err);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Directory search failed")
+        }
+    }
+}
+
+pub async fn set_visibility(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<DirectoryVisibilityRequest>,
+) -> impl IntoResponse {
+    let username = match authorize(&pool, &headers, addr, "directory-visibility").await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+
+    match db::set_directory_visible(&pool, &username, payload.visible).await {
+        Ok(true) => Json(DirectoryVisibilityResponse {
+            visible: payload.visible,
+        })
+        .into_response(),
+        Ok(false) => error_response(StatusCode::NOT_FOUND, "User not found"),
+        Err(err) => {
+            tracing::error!("directory visibility update failed: {}", err);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update directory visibility",
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(username: &str) -> DirectoryEntry {
+        DirectoryEntry {
+            username: username.to_string(),
+            display_name: username.to_uppercase(),
+        }
+    }
+
+    #[test]
+    fn query_rejects_short_and_control_input() {
+        assert_eq!(normalize_query("  al "), Ok("al".to_string()));
+        assert!(normalize_query("a").is_err());
+        assert!(normalize_query("ab\u{0}").is_err());
+        assert!(normalize_query(&"x".repeat(MAX_QUERY_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn page_size_is_clamped() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(1_000)), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn split_page_uses_lookahead_row_for_cursor() {
+        let (rows, cursor) = split_page(vec![entry("alice"), entry("alina")], 2);
+        assert_eq!(rows.len(), 2);
+        assert!(cursor.is_none());
+
+        let (rows, cursor) = split_page(vec![entry("alice"), entry("alina"), entry("alvin")], 2);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(cursor.as_deref(), Some("alina"));
+    }
+}
@@ -0,0 +1,276 @@
+//! Hosts registered for wake-on-LAN. A host registers its MAC from its own
+//! network, so the gateway knows which public address a wake agent must share
+//! before it is asked to broadcast the magic packet (see `REQUEST_WAKE` in
+//! [`crate::signal`]).
+
+use axum::{
+    extract::{ConnectInfo, Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::db::{self, RegisteredHost};
+use crate::directory::{authorize, error_response};
+use crate::security;
+use crate::signal::ConnectionMap;
+
+pub const MAX_HOST_NAME_LEN: usize = 64;
+pub const MAX_HOSTS_PER_USER: usize = 16;
+/// Port magic packets go to when the registration names none.
+pub const DEFAULT_WAKE_PORT: u16 = 9;
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterHostRequest {
+    pub name: String,
+    pub mac_address: String,
+    /// Directed broadcast address of the host's LAN; agents fall back to the
+    /// limited broadcast.
+    pub broadcast_addr: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveHostQuery {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostView {
+    pub name: String,
+    pub mac_address: String,
+    pub broadcast_addr: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostListResponse {
+    pub hosts: Vec<HostView>,
+    /// Whether the account currently holds a signaling connection.
+    pub online: bool,
+}
+
+impl From<RegisteredHost> for HostView {
+    fn from(host: RegisteredHost) -> Self {
+        Self {
+            name: host.name,
+            mac_address: host.mac_address,
+            broadcast_addr: host.broadcast_addr,
+            updated_at: host.updated_at,
+        }
+    }
+}
+
+pub fn normalize_host_name(raw: &str) -> Result<String, &'static str> {
+    let name = raw.trim();
+    if name.is_empty()
+        || name.chars().count() > MAX_HOST_NAME_LEN
+        || name.chars().any(char::is_control)
+    {
+        return Err("Invalid host name");
+    }
+    Ok(name.to_string())
+}
+
+/// Canonical lowercase, colon-separated form of `aa:bb:cc:dd:ee:ff`,
+/// `aa-bb-cc-dd-ee-ff` or `aabbccddeeff`. Group addresses are rejected since
+/// no single interface wakes for them.
+pub fn normalize_mac(raw: &str) -> Result<String, &'static str> {
+    let hex: String = raw
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | '.'))
+        .collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Invalid MAC address");
+    }
+    let first = u8::from_str_radix(&hex[..2], 16).map_err(|_| "Invalid MAC address")?;
+    if first & 0x01 != 0 || hex.chars().all(|c| c == '0') {
+        return Err("MAC address must be a unicast interface address");
+    }
+    let hex = hex.to_ascii_lowercase();
+    Ok((0..6)
+        .map(|i| &hex[i * 2..i * 2 + 2])
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+/// `ip:port` or a bare IPv4 address, which gets [`DEFAULT_WAKE_PORT`]. Magic
+/// packets are IPv4 broadcasts, so IPv6 is refused.
+pub fn normalize_broadcast_addr(raw: Option<&str>) -> Result<Option<String>, &'static str> {
+    let Some(addr) = raw.map(str::trim).filter(|addr| !addr.is_empty()) else {
+        return Ok(None);
+    };
+    let addr = addr
+        .parse::<SocketAddr>()
+        .or_else(|_| {
+            addr.parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, DEFAULT_WAKE_PORT))
+        })
+        .map_err(|_| "Invalid broadcast address")?;
+    if !addr.is_ipv4() || addr.port() == 0 {
+        return Err("Broadcast address must be an IPv4 address");
+    }
+    Ok(Some(addr.to_string()))
+}
+
+pub async fn list(
+    State(pool): State<SqlitePool>,
+    State(connections): State<ConnectionMap>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let username = match authorize(&pool, &headers, addr, "hosts-list").await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+
+    match db::list_registered_hosts(&pool, &username).await {
+        Ok(hosts) => {
+            let online = connections.read().await.contains_key(&username);
+            Json(HostListResponse {
+                hosts: hosts.into_iter().map(HostView::from).collect(),
+                online,
+            })
+            .into_response()
+        }
+        Err(err) => {
+            tracing::error!("listing registered hosts failed: {}", err);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list hosts")
+        }
+    }
+}
+
+pub async fn register(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<RegisterHostRequest>,
+) -> impl IntoResponse {
+    let username = match authorize(&pool, &headers, addr, "hosts-register").await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let (name, mac_address, broadcast_addr) = match (
+        normalize_host_name(&payload.name),
+        normalize_mac(&payload.mac_address),
+        normalize_broadcast_addr(payload.broadcast_addr.as_deref()),
+    ) {
+        (Ok(name), Ok(mac), Ok(broadcast)) => (name, mac, broadcast),
+        (Err(msg), _, _) | (_, Err(msg), _) | (_, _, Err(msg)) => {
+            return error_response(StatusCode::BAD_REQUEST, msg)
+        }
+    };
+
+    match db::list_registered_hosts(&pool, &username).await {
+        Ok(hosts) if hosts.len() >= MAX_HOSTS_PER_USER && !hosts.iter().any(|h| h.name == name) => {
+            return error_response(StatusCode::CONFLICT, "Too many registered hosts");
+        }
+        Ok(_) => {}
+        Err(err) => {
+            tracing::error!("listing registered hosts failed: {}", err);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to register host");
+        }
+    }
+
+    let public_ip = security::effective_client_ip(&headers, addr).to_string();
+    match db::upsert_registered_host(
+        &pool,
+        &username,
+        &name,
+        &mac_address,
+        broadcast_addr.as_deref(),
+        &public_ip,
+    )
+    .await
+    {
+        Ok(true) => {
+            tracing::info!("registered host {} for {}", name, username);
+            Json(HostView {
+                name,
+                mac_address,
+                broadcast_addr,
+                updated_at: Utc::now(),
+            })
+            .into_response()
+        }
+        Ok(false) => error_response(StatusCode::NOT_FOUND, "User not found"),
+        Err(err) => {
+            tracing::error!("host registration failed: {}", err);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to register host")
+        }
+    }
+}
+
+pub async fn remove(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(params): Query<RemoveHostQuery>,
+) -> impl IntoResponse {
+    let username = match authorize(&pool, &headers, addr, "hosts-remove").await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let name = match normalize_host_name(&params.name) {
+        Ok(name) => name,
+        Err(msg) => return error_response(StatusCode::BAD_REQUEST, msg),
+    };
+
+    match db::delete_registered_host(&pool, &username, &name).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error_response(StatusCode::NOT_FOUND, "Host not found"),
+        Err(err) => {
+            tracing::error!("host removal failed: {}", err);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove host")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mac_is_canonicalized_and_must_be_unicast() {
+        assert_eq!(
+            normalize_mac(" AA-BB-CC-01-02-03 ").as_deref(),
+            Ok("aa:bb:cc:01:02:03")
+        );
+        assert_eq!(
+            normalize_mac("a8bbcc010203").as_deref(),
+            Ok("a8:bb:cc:01:02:03")
+        );
+        assert!(normalize_mac("aa:bb:cc:01:02").is_err());
+        assert!(normalize_mac("zz:bb:cc:01:02:03").is_err());
+        assert!(normalize_mac("01:00:5e:00:00:01").is_err());
+        assert!(normalize_mac("ff:ff:ff:ff:ff:ff").is_err());
+        assert!(normalize_mac("00:00:00:00:00:00").is_err());
+    }
+
+    #[test]
+    fn broadcast_addr_defaults_port_and_requires_ipv4() {
+        assert_eq!(normalize_broadcast_addr(None), Ok(None));
+        assert_eq!(normalize_broadcast_addr(Some("  ")), Ok(None));
+        assert_eq!(
+            normalize_broadcast_addr(Some("192.168.1.255")),
+            Ok(Some("192.168.1.255:9".to_string()))
+        );
+        assert_eq!(
+            normalize_broadcast_addr(Some("10.0.0.255:7")),
+            Ok(Some("10.0.0.255:7".to_string()))
+        );
+        assert!(normalize_broadcast_addr(Some("ff02::1")).is_err());
+        assert!(normalize_broadcast_addr(Some("lan")).is_err());
+    }
+
+    #[test]
+    fn host_name_is_trimmed_and_bounded() {
+        assert_eq!(normalize_host_name(" Desk PC ").as_deref(), Ok("Desk PC"));
+        assert!(normalize_host_name("").is_err());
+        assert!(normalize_host_name("p\u{7}c").is_err());
+        assert!(normalize_host_name(&"x".repeat(MAX_HOST_NAME_LEN + 1)).is_err());
+    }
+}
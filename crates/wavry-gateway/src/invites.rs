@@ -0,0 +1,119 @@
+//! Invite links. A signed-in host mints an invite here and shares the link;
+//! whoever holds it binds to signaling as a guest (`BIND_INVITE` in
+//! [`crate::signal`]) and can reach that host, and only that host, until the
+//! invite expires. Invites are stateless, so they cannot be revoked early.
+
+use axum::{
+    extract::{ConnectInfo, Json, State},
+    http::{header, HeaderMap},
+    response::IntoResponse,
+};
+use once_cell::sync::Lazy;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::SocketAddr;
+use wavry_common::invite::{self, INVITE_SECRET_ENV};
+
+use crate::directory::authorize;
+
+/// Public base URL invite links point at, e.g. `https://gateway.example`.
+const PUBLIC_URL_ENV: &str = "WAVRY_GATEWAY_PUBLIC_URL";
+
+static INVITE_SECRET: Lazy<Vec<u8>> = Lazy::new(|| {
+    match std::env::var(INVITE_SECRET_ENV)
+        .ok()
+        .map(|secret| secret.trim().to_string())
+        .filter(|secret| !secret.is_empty())
+    {
+        Some(secret) => secret.into_bytes(),
+        None => {
+            tracing::warn!(
+                "{} not set; invites only work on this gateway until it restarts",
+                INVITE_SECRET_ENV
+            );
+            let mut secret = vec![0u8; 32];
+            OsRng.fill_bytes(&mut secret);
+            secret
+        }
+    }
+});
+
+pub(crate) fn invite_secret() -> &'static [u8] {
+    &INVITE_SECRET
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateInviteRequest {
+    /// Clamped to one minute..seven days; a day when omitted.
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteResponse {
+    pub token: String,
+    pub url: String,
+    /// Unix seconds.
+    pub expires_at: u64,
+}
+
+/// `WAVRY_GATEWAY_PUBLIC_URL`, or the `Host` the request arrived on over HTTPS.
+fn public_base_url(headers: &HeaderMap) -> String {
+    if let Some(url) = std::env::var(PUBLIC_URL_ENV)
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+    {
+        return url;
+    }
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .filter(|host| {
+            !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+        })
+        .unwrap_or("localhost");
+    format!("https://{host}")
+}
+
+pub async fn create(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    payload: Option<Json<CreateInviteRequest>>,
+) -> impl IntoResponse {
+    let username = match authorize(&pool, &headers, addr, "invites-create").await {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let Json(payload) = payload.unwrap_or_default();
+
+    let ttl = invite::invite_ttl(payload.ttl_secs);
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let token = invite::mint_invite(invite_secret(), &username, ttl, now);
+    tracing::info!("minted invite to {} valid for {}s", username, ttl.as_secs());
+    Json(InviteResponse {
+        url: invite::invite_url(&public_base_url(&headers), &token),
+        token,
+        expires_at: now + ttl.as_secs(),
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_url_falls_back_to_a_sane_host_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "gw.example:8443".parse().unwrap());
+        assert_eq!(public_base_url(&headers), "https://gw.example:8443");
+
+        headers.insert(header::HOST, "evil.example/phish?".parse().unwrap());
+        assert_eq!(public_base_url(&headers), "https://localhost");
+    }
+}
@@ -2,6 +2,9 @@ pub mod admin;
 pub mod audit;
 pub mod auth;
 pub mod db;
+pub mod directory;
+pub mod hosts;
+pub mod invites;
 pub mod relay;
 pub mod security;
 pub mod signal;
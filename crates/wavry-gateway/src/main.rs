@@ -19,6 +19,9 @@ mod admin;
 mod audit;
 mod auth;
 mod db;
+mod directory;
+mod hosts;
+mod invites;
 mod relay;
 mod security;
 mod signal;
@@ -337,10 +340,19 @@ async fn main() -> anyhow::Result<()> {
         .route("/auth/logout", post(auth::logout))
         .route("/auth/2fa/setup", post(auth::setup_totp))
         .route("/auth/2fa/enable", post(auth::enable_totp))
+        .route("/webtransport/token", post(web::webtransport_token))
         .route("/webrtc/config", get(web::webrtc_config))
         .route("/webrtc/offer", post(web::webrtc_offer))
         .route("/webrtc/answer", post(web::webrtc_answer))
         .route("/webrtc/candidate", post(web::webrtc_candidate))
+        .route("/webrtc/ice-servers", post(web::webrtc_ice_servers))
+        .route("/v1/directory/search", get(directory::search))
+        .route("/v1/directory/visibility", post(directory::set_visibility))
+        .route(
+            "/v1/hosts",
+            get(hosts::list).put(hosts::register).delete(hosts::remove),
+        )
+        .route("/v1/invites", post(invites::create))
         .route("/v1/relays/report", post(web::handle_relay_report))
         .route("/v1/relays/reputation", get(web::handle_relay_reputation))
         .route("/ws", get(signal::ws_handler))
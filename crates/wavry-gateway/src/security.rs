@@ -112,6 +112,7 @@ static POST_AUTH_LIMITER: OnceLock<FixedWindowRateLimiter> = OnceLock::new();
 static WEBRTC_LIMITER: OnceLock<FixedWindowRateLimiter> = OnceLock::new();
 static WS_BIND_LIMITER: OnceLock<FixedWindowRateLimiter> = OnceLock::new();
 static GLOBAL_API_LIMITER: OnceLock<FixedWindowRateLimiter> = OnceLock::new();
+static DIRECTORY_LIMITER: OnceLock<FixedWindowRateLimiter> = OnceLock::new();
 static ALLOWED_ORIGINS: OnceLock<HashSet<String>> = OnceLock::new();
 
 fn env_bool(name: &str, default: bool) -> bool {
@@ -253,6 +254,18 @@ pub fn allow_global_api_request(key: &str) -> bool {
         .allow(key)
 }
 
+pub fn allow_directory_request(key: &str) -> bool {
+    DIRECTORY_LIMITER
+        .get_or_init(|| {
+            FixedWindowRateLimiter::new(
+                env_u32("WAVRY_DIRECTORY_RATE_LIMIT", 30),
+                Duration::from_secs(env_u32("WAVRY_DIRECTORY_RATE_WINDOW_SECS", 60).max(1) as u64),
+                env_usize("WAVRY_DIRECTORY_RATE_MAX_KEYS", 50_000),
+            )
+        })
+        .allow(key)
+}
+
 fn parse_proxy_ip(headers: &HeaderMap) -> Option<IpAddr> {
     if let Some(forwarded_for) = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
         if let Some(first_ip) = forwarded_for.split(',').next() {
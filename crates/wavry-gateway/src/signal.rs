@@ -10,8 +10,8 @@ use futures::{stream::StreamExt, SinkExt};
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -19,10 +19,14 @@ use tokio::sync::{mpsc, RwLock};
 use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::db;
+use crate::db::{self, RegisteredHost};
+use crate::hosts;
+use crate::invites;
 use crate::relay::{RelayMap, RelaySession};
 use crate::security;
+use rift_core::ice::IceCandidate;
 use rift_crypto::seq_window::SequenceWindow;
+use wavry_common::invite;
 
 #[cfg(feature = "webtransport-runtime")]
 use wavry_web as web_transport;
@@ -32,12 +36,21 @@ const WS_MAX_TEXT_BYTES: usize = 64 * 1024;
 const WS_MAX_MESSAGES_PER_MINUTE: u32 = 600;
 const MAX_SIGNAL_SDP_BYTES: usize = 32 * 1024;
 const MAX_SIGNAL_CANDIDATE_BYTES: usize = 4096;
+/// Longest `ip:port` a RIFT candidate can hold, IPv6 in brackets.
+const MAX_RIFT_CANDIDATE_ADDR_BYTES: usize = 64;
 const WS_BIND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Minimum spacing between wake broadcasts for one account's hosts.
+const WAKE_COOLDOWN: Duration = Duration::from_secs(10);
 
 static ACTIVE_WS_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
-static IP_CONNECTIONS: Lazy<Mutex<HashMap<std::net::IpAddr, usize>>> =
+static IP_CONNECTIONS: Lazy<Mutex<HashMap<IpAddr, usize>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Connections that offered to wake hosts on their LAN, by the public IP the
+/// gateway sees them at.
+static WAKE_AGENTS: Lazy<Mutex<HashMap<String, IpAddr>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static LAST_WAKE: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
 use once_cell::sync::Lazy;
 use std::sync::Mutex;
 
@@ -115,16 +128,24 @@ pub enum SignalMessage {
     Bind {
         token: String,
     },
+    #[serde(rename = "BIND_INVITE")]
+    BindInvite {
+        invite: String,
+    },
 
     #[serde(rename = "OFFER_RIFT")]
     OfferRift {
         target_username: String,
         hello_base64: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        candidates: Vec<IceCandidate>,
     },
     #[serde(rename = "ANSWER_RIFT")]
     AnswerRift {
         target_username: String,
         ack_base64: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        candidates: Vec<IceCandidate>,
     },
 
     Offer {
@@ -151,12 +172,93 @@ pub enum SignalMessage {
         session_id: Uuid,
     },
 
+    #[serde(rename = "REQUEST_WAKE")]
+    RequestWake {
+        target_username: String,
+        #[serde(default)]
+        host_name: Option<String>,
+    },
+    #[serde(rename = "WAKE_AGENT")]
+    WakeAgent,
+    #[serde(rename = "WAKE_HOST")]
+    WakeHost {
+        mac_address: String,
+        broadcast_addr: Option<String>,
+    },
+    #[serde(rename = "QUERY_HOST")]
+    QueryHost {
+        target_username: String,
+    },
+    #[serde(rename = "HOST_STATUS")]
+    HostStatus {
+        target_username: String,
+        online: bool,
+        wake_agents: u32,
+    },
+
     Error {
         message: String,
     },
     Bound,
 }
 
+/// Agents sharing a public IP with each host, paired with the host they should
+/// wake. A host behind the same NAT as an agent is assumed to share its LAN.
+fn wake_assignments<'a>(
+    hosts: &'a [RegisteredHost],
+    agents: &HashMap<String, IpAddr>,
+) -> Vec<(String, &'a RegisteredHost)> {
+    let mut assignments = Vec::new();
+    for host in hosts {
+        let Some(host_ip) = host
+            .public_ip
+            .as_deref()
+            .and_then(|ip| ip.parse::<IpAddr>().ok())
+        else {
+            continue;
+        };
+        for (agent, agent_ip) in agents {
+            if agent_ip.to_canonical() == host_ip.to_canonical() {
+                assignments.push((agent.clone(), host));
+            }
+        }
+    }
+    assignments
+}
+
+/// Guests may only signal the host their invite names, and only to set up a
+/// session with it.
+fn guest_may_send(signal: &SignalMessage, host: &str) -> bool {
+    match signal {
+        SignalMessage::OfferRift {
+            target_username, ..
+        }
+        | SignalMessage::AnswerRift {
+            target_username, ..
+        }
+        | SignalMessage::Offer {
+            target_username, ..
+        }
+        | SignalMessage::Answer {
+            target_username, ..
+        }
+        | SignalMessage::Candidate {
+            target_username, ..
+        }
+        | SignalMessage::RequestRelay { target_username }
+        | SignalMessage::QueryHost { target_username } => target_username == host,
+        _ => false,
+    }
+}
+
+/// Candidates are forwarded untouched; only their number and size are bounded.
+fn valid_rift_candidates(candidates: &[IceCandidate]) -> bool {
+    candidates.len() <= rift_core::ice::MAX_CANDIDATES
+        && candidates
+            .iter()
+            .all(|candidate| candidate.addr.len() <= MAX_RIFT_CANDIDATE_ADDR_BYTES)
+}
+
 fn to_ws_message(signal: &SignalMessage) -> Option<Message> {
     serde_json::to_string(signal).ok().map(Message::Text)
 }
@@ -240,9 +342,12 @@ pub async fn ws_handler(
         return StatusCode::SERVICE_UNAVAILABLE.into_response();
     }
 
+    let client_ip = security::effective_client_ip(&headers, addr);
     ws.max_message_size(WS_MAX_TEXT_BYTES)
         .max_frame_size(WS_MAX_TEXT_BYTES)
-        .on_upgrade(move |socket| handle_socket(socket, connections, relay_sessions, pool, addr))
+        .on_upgrade(move |socket| {
+            handle_socket(socket, connections, relay_sessions, pool, addr, client_ip)
+        })
         .into_response()
 }
 
@@ -252,6 +357,7 @@ async fn handle_socket(
     relay_sessions: RelayMap,
     pool: SqlitePool,
     addr: SocketAddr,
+    client_ip: IpAddr,
 ) {
     ACTIVE_WS_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
     info!("client connecting from {}", addr);
@@ -270,6 +376,9 @@ async fn handle_socket(
     });
 
     let mut authenticated_username: Option<String> = None;
+    // Host named by the invite a guest connection bound with.
+    let mut invited_to: Option<String> = None;
+    let mut wake_agent = false;
     let mut message_window_start = Instant::now();
     let mut message_count: u32 = 0;
     let connection_start = Instant::now();
@@ -349,6 +458,19 @@ async fn handle_socket(
                     }
                 };
 
+                if let Some(host) = &invited_to {
+                    if !guest_may_send(&signal, host) {
+                        let _ = send_signal(
+                            &tx,
+                            &SignalMessage::Error {
+                                message: "Invite only allows signaling its host".into(),
+                            },
+                        )
+                        .await;
+                        continue;
+                    }
+                }
+
                 match signal {
                     SignalMessage::Bind { token } => {
                         if authenticated_username.is_some() {
@@ -423,9 +545,62 @@ async fn handle_socket(
                         let _ = send_signal(&tx, &SignalMessage::Bound).await;
                         info!("bound signaling session for user {}", username);
                     }
+                    SignalMessage::BindInvite { invite: token } => {
+                        if authenticated_username.is_some() {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Already bound".into(),
+                                },
+                            )
+                            .await;
+                            break;
+                        }
+
+                        if !security::allow_ws_bind_request(&format!("bind:{}", addr.ip())) {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Bind rate limit exceeded".into(),
+                                },
+                            )
+                            .await;
+                            break;
+                        }
+
+                        let now = chrono::Utc::now().timestamp().max(0) as u64;
+                        let claims = match invite::verify_invite(invites::invite_secret(), &token, now) {
+                            Ok(claims) => claims,
+                            Err(err) => {
+                                info!("rejected invite from {}: {}", addr, err);
+                                let _ = send_signal(
+                                    &tx,
+                                    &SignalMessage::Error {
+                                        message: "Invalid or expired invite".into(),
+                                    },
+                                )
+                                .await;
+                                break;
+                            }
+                        };
+
+                        let guest = invite::guest_username();
+                        connections
+                            .write()
+                            .await
+                            .insert(guest.clone(), Signaler::WebSocket(tx.clone()));
+                        info!(
+                            "bound guest {} invited to {} (invite {})",
+                            guest, claims.host, claims.id
+                        );
+                        authenticated_username = Some(guest);
+                        invited_to = Some(claims.host);
+                        let _ = send_signal(&tx, &SignalMessage::Bound).await;
+                    }
                     SignalMessage::OfferRift {
                         target_username,
                         hello_base64,
+                        candidates,
                     } => {
                         let Some(src) = &authenticated_username else {
                             let _ = send_signal(
@@ -437,7 +612,10 @@ async fn handle_socket(
                             .await;
                             break;
                         };
-                        if !security::is_valid_username(&target_username) || hello_base64.len() > 8192 {
+                        if !security::is_valid_username(&target_username)
+                            || hello_base64.len() > 8192
+                            || !valid_rift_candidates(&candidates)
+                        {
                             let _ = send_signal(
                                 &tx,
                                 &SignalMessage::Error {
@@ -453,6 +631,7 @@ async fn handle_socket(
                             SignalMessage::OfferRift {
                                 target_username: src.clone(),
                                 hello_base64,
+                                candidates,
                             },
                         )
                         .await;
@@ -460,6 +639,7 @@ async fn handle_socket(
                     SignalMessage::AnswerRift {
                         target_username,
                         ack_base64,
+                        candidates,
                     } => {
                         let Some(src) = &authenticated_username else {
                             let _ = send_signal(
@@ -471,7 +651,10 @@ async fn handle_socket(
                             .await;
                             break;
                         };
-                        if !security::is_valid_username(&target_username) || ack_base64.len() > 8192 {
+                        if !security::is_valid_username(&target_username)
+                            || ack_base64.len() > 8192
+                            || !valid_rift_candidates(&candidates)
+                        {
                             let _ = send_signal(
                                 &tx,
                                 &SignalMessage::Error {
@@ -487,6 +670,7 @@ async fn handle_socket(
                             SignalMessage::AnswerRift {
                                 target_username: src.clone(),
                                 ack_base64,
+                                candidates,
                             },
                         )
                         .await;
@@ -667,7 +851,178 @@ async fn handle_socket(
                         let _ = send_signal(&tx, &resp).await;
                         relay_message(&connections, &target_username, resp).await;
                     }
+                    SignalMessage::RequestWake {
+                        target_username,
+                        host_name,
+                    } => {
+                        if authenticated_username.is_none() {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Bind required before signaling".into(),
+                                },
+                            )
+                            .await;
+                            break;
+                        }
+                        let host_name = host_name
+                            .as_deref()
+                            .map(hosts::normalize_host_name)
+                            .transpose();
+                        if !security::is_valid_username(&target_username) || host_name.is_err() {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Invalid REQUEST_WAKE payload".into(),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+                        let host_name = host_name.unwrap_or_default();
+
+                        if connections.read().await.contains_key(&target_username) {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::HostStatus {
+                                    target_username,
+                                    online: true,
+                                    wake_agents: 0,
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        let recently_woken = {
+                            let now = Instant::now();
+                            let mut last_wake = LAST_WAKE.lock().unwrap();
+                            last_wake.retain(|_, at| now.duration_since(*at) < WAKE_COOLDOWN);
+                            last_wake.insert(target_username.clone(), now).is_some()
+                        };
+                        if recently_woken {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Wake already requested, try again shortly".into(),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        let lookup = db::list_registered_hosts(&pool, &target_username).await;
+                        let registered = match lookup {
+                            Ok(registered) => registered,
+                            Err(err) => {
+                                warn!(
+                                    "registered host lookup failed for {}: {}",
+                                    target_username, err
+                                );
+                                let _ = send_signal(
+                                    &tx,
+                                    &SignalMessage::Error {
+                                        message: "Host lookup failed".into(),
+                                    },
+                                )
+                                .await;
+                                continue;
+                            }
+                        };
+                        let registered: Vec<RegisteredHost> = registered
+                            .into_iter()
+                            .filter(|host| host_name.as_ref().is_none_or(|name| &host.name == name))
+                            .collect();
+                        if registered.is_empty() {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Host is not registered for wake-on-LAN".into(),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        let agents = WAKE_AGENTS.lock().unwrap().clone();
+                        let mut woken_via = HashSet::new();
+                        for (agent, host) in wake_assignments(&registered, &agents) {
+                            let signaler = connections.read().await.get(&agent).cloned();
+                            let sent = signaler.is_some_and(|signaler| {
+                                signaler.try_send(SignalMessage::WakeHost {
+                                    mac_address: host.mac_address.clone(),
+                                    broadcast_addr: host.broadcast_addr.clone(),
+                                })
+                            });
+                            if sent {
+                                woken_via.insert(agent);
+                            }
+                        }
+                        info!(
+                            "wake requested for {}: {} host(s), {} agent(s)",
+                            target_username,
+                            registered.len(),
+                            woken_via.len()
+                        );
+                        let _ = send_signal(
+                            &tx,
+                            &SignalMessage::HostStatus {
+                                target_username,
+                                online: false,
+                                wake_agents: woken_via.len() as u32,
+                            },
+                        )
+                        .await;
+                    }
+                    SignalMessage::WakeAgent => {
+                        let Some(src) = &authenticated_username else {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Bind required before signaling".into(),
+                                },
+                            )
+                            .await;
+                            break;
+                        };
+                        WAKE_AGENTS.lock().unwrap().insert(src.clone(), client_ip);
+                        wake_agent = true;
+                    }
+                    SignalMessage::QueryHost { target_username } => {
+                        if authenticated_username.is_none() {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Bind required before signaling".into(),
+                                },
+                            )
+                            .await;
+                            break;
+                        }
+                        if !security::is_valid_username(&target_username) {
+                            let _ = send_signal(
+                                &tx,
+                                &SignalMessage::Error {
+                                    message: "Invalid target username".into(),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+                        let online = connections.read().await.contains_key(&target_username);
+                        let _ = send_signal(
+                            &tx,
+                            &SignalMessage::HostStatus {
+                                target_username,
+                                online,
+                                wake_agents: 0,
+                            },
+                        )
+                        .await;
+                    }
                     SignalMessage::RelayCredentials { .. }
+                    | SignalMessage::WakeHost { .. }
+                    | SignalMessage::HostStatus { .. }
                     | SignalMessage::Error { .. }
                     | SignalMessage::Bound => {
                         let _ = send_signal(
@@ -685,6 +1040,9 @@ async fn handle_socket(
 
     if let Some(user) = authenticated_username {
         info!("client disconnected: {}", user);
+        if wake_agent {
+            WAKE_AGENTS.lock().unwrap().remove(&user);
+        }
         connections.write().await.remove(&user);
     }
     ACTIVE_WS_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
@@ -713,3 +1071,80 @@ async fn relay_message(connections: &ConnectionMap, target_username: &str, msg:
         warn!("target user not connected: {}", target_username);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(name: &str, public_ip: Option<&str>) -> RegisteredHost {
+        RegisteredHost {
+            name: name.to_string(),
+            mac_address: "aa:bb:cc:01:02:03".to_string(),
+            broadcast_addr: None,
+            public_ip: public_ip.map(str::to_string),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn wake_goes_only_to_agents_behind_the_hosts_address() {
+        let hosts = vec![
+            host("desk", Some("203.0.113.7")),
+            host("htpc", Some("198.51.100.2")),
+            host("legacy", None),
+        ];
+        let agents = HashMap::from([
+            ("phone".to_string(), "::ffff:203.0.113.7".parse().unwrap()),
+            ("stranger".to_string(), "192.0.2.1".parse().unwrap()),
+        ]);
+
+        let assignments = wake_assignments(&hosts, &agents);
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[0].0, "phone");
+        assert_eq!(assignments[0].1.name, "desk");
+    }
+
+    #[test]
+    fn guests_only_reach_their_host() {
+        let offer = |target: &str| SignalMessage::OfferRift {
+            target_username: target.into(),
+            hello_base64: String::new(),
+            candidates: Vec::new(),
+        };
+        assert!(guest_may_send(&offer("alice"), "alice"));
+        assert!(!guest_may_send(&offer("bob"), "alice"));
+        assert!(guest_may_send(
+            &SignalMessage::RequestRelay {
+                target_username: "alice".into()
+            },
+            "alice"
+        ));
+        assert!(!guest_may_send(&SignalMessage::WakeAgent, "alice"));
+        assert!(!guest_may_send(
+            &SignalMessage::RequestWake {
+                target_username: "alice".into(),
+                host_name: None,
+            },
+            "alice"
+        ));
+
+        let json = serde_json::to_value(SignalMessage::BindInvite {
+            invite: "abc.def".into(),
+        })
+        .unwrap();
+        assert_eq!(json["type"], "BIND_INVITE");
+    }
+
+    #[test]
+    fn wake_messages_use_screaming_case_tags() {
+        let json = serde_json::to_value(SignalMessage::WakeHost {
+            mac_address: "aa:bb:cc:01:02:03".into(),
+            broadcast_addr: None,
+        })
+        .unwrap();
+        assert_eq!(json["type"], "WAKE_HOST");
+
+        let json = serde_json::to_value(SignalMessage::WakeAgent).unwrap();
+        assert_eq!(json["type"], "WAKE_AGENT");
+    }
+}
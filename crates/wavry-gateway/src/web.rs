@@ -10,12 +10,16 @@ use std::net::SocketAddr;
 use crate::signal::{ConnectionMap, SignalMessage};
 use crate::{db, security};
 
+#[cfg(feature = "webtransport-runtime")]
+use once_cell::sync::Lazy;
 #[cfg(feature = "webtransport-runtime")]
 use serde_json;
 #[cfg(feature = "webtransport-runtime")]
 use std::collections::HashMap;
 #[cfg(feature = "webtransport-runtime")]
-use std::sync::Arc;
+use std::sync::{Arc, RwLock as SyncRwLock};
+#[cfg(feature = "webtransport-runtime")]
+use std::time::Duration;
 #[cfg(feature = "webtransport-runtime")]
 use tokio::sync::{mpsc, RwLock};
 #[cfg(feature = "webtransport-runtime")]
@@ -62,6 +66,97 @@ pub struct WebRtcConfigResponse {
     pub candidate_endpoint: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct WebTransportTokenRequest {
+    pub session_token: String,
+}
+
+#[cfg(feature = "webtransport-runtime")]
+#[derive(Debug, Serialize)]
+pub struct WebTransportTokenResponse {
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+/// Signs WebTransport session tokens. `WAVRY_WEB_TOKEN_KEY` (hex Ed25519 secret
+/// key) keeps tokens valid across restarts; otherwise a per-process key is used.
+#[cfg(feature = "webtransport-runtime")]
+static WEB_TOKEN_SIGNER: Lazy<Option<web_transport::WebTokenSigner>> = Lazy::new(|| {
+    let signer = match std::env::var("WAVRY_WEB_TOKEN_KEY") {
+        Ok(key_hex) => hex::decode(key_hex.trim())
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| web_transport::WebTokenSigner::from_secret_bytes(&bytes)),
+        Err(_) => web_transport::WebTokenSigner::generate(),
+    };
+    signer
+        .map_err(|err| tracing::error!("web token signer unavailable: {}", err))
+        .ok()
+});
+
+#[derive(Debug, Deserialize)]
+pub struct WebRtcIceServersRequest {
+    pub session_token: String,
+}
+
+#[cfg(feature = "webtransport-runtime")]
+#[derive(Debug, Serialize)]
+pub struct WebRtcIceServersResponse {
+    pub ice_servers: Vec<web_transport::WebIceServer>,
+}
+
+/// ICE settings offered to browsers. `WAVRY_TURN_URLS` replaces the default TURN
+/// relays and `WAVRY_MASTER_URL` selects the master that mints their credentials.
+#[cfg(feature = "webtransport-runtime")]
+static WEB_GATEWAY_CONFIG: Lazy<web_transport::WebGatewayConfig> = Lazy::new(|| {
+    let mut config = web_transport::WebGatewayConfig::default();
+    if let Ok(raw) = std::env::var("WAVRY_TURN_URLS") {
+        config.turn_urls = common::turn::parse_turn_urls(&raw);
+    }
+    if let Ok(master_url) = std::env::var("WAVRY_MASTER_URL") {
+        config.turn_credentials_url = format!(
+            "{}/v1/turn/credentials",
+            master_url.trim().trim_end_matches('/')
+        );
+    }
+    config
+});
+
+#[cfg(feature = "webtransport-runtime")]
+static MASTER_HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()
+        .unwrap_or_default()
+});
+
+#[cfg(feature = "webtransport-runtime")]
+async fn fetch_turn_credentials(username: &str) -> anyhow::Result<common::turn::TurnCredentials> {
+    let mut request = MASTER_HTTP_CLIENT
+        .post(&WEB_GATEWAY_CONFIG.turn_credentials_url)
+        .json(&serde_json::json!({ "username": username }));
+    if let Ok(token) = std::env::var("WAVRY_MASTER_TURN_AUTH_TOKEN") {
+        request = request.bearer_auth(token.trim());
+    }
+    Ok(request.send().await?.error_for_status()?.json().await?)
+}
+
+/// STUN plus, when the master can mint credentials, TURN for `username`.
+#[cfg(feature = "webtransport-runtime")]
+async fn ice_servers_for(username: &str) -> Vec<web_transport::WebIceServer> {
+    let credentials = match fetch_turn_credentials(username).await {
+        Ok(credentials) => Some(credentials),
+        Err(err) => {
+            tracing::debug!("TURN credentials unavailable for {}: {}", username, err);
+            None
+        }
+    };
+    WEB_GATEWAY_CONFIG.ice_servers(
+        credentials
+            .as_ref()
+            .map(|c| (c.username.as_str(), c.credential.as_str())),
+    )
+}
+
 #[derive(Debug, Serialize)]
 struct ErrorResponse {
     error: String,
@@ -116,6 +211,96 @@ pub async fn webrtc_config() -> impl IntoResponse {
     })
 }
 
+/// Mint a short-lived token the browser presents when opening WebTransport.
+pub async fn webtransport_token(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(payload): Json<WebTransportTokenRequest>,
+) -> impl IntoResponse {
+    if !ensure_webrtc_rate_limit("wt-token", addr) {
+        return error_response(StatusCode::TOO_MANY_REQUESTS, "Too many token requests");
+    }
+    if !security::is_valid_session_token(&payload.session_token) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid session token");
+    }
+
+    #[cfg(feature = "webtransport-runtime")]
+    {
+        let Some(signer) = WEB_TOKEN_SIGNER.as_ref() else {
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, "Token signing unavailable");
+        };
+        let username = match db::get_username_by_session_token(&pool, &payload.session_token).await
+        {
+            Ok(Some(username)) => username,
+            Ok(None) => {
+                return error_response(StatusCode::UNAUTHORIZED, "Invalid or expired session token")
+            }
+            Err(err) => {
+                tracing::error!("session token lookup failed: {}", err);
+                return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Session lookup failed");
+            }
+        };
+        let ttl = web_transport::DEFAULT_WEB_TOKEN_TTL;
+        match signer.mint(&username, &payload.session_token, ttl) {
+            Ok(token) => Json(WebTransportTokenResponse {
+                token,
+                expires_in_secs: ttl.as_secs(),
+            })
+            .into_response(),
+            Err(err) => {
+                tracing::error!("failed to mint web token: {}", err);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, "Token signing failed")
+            }
+        }
+    }
+
+    #[cfg(not(feature = "webtransport-runtime"))]
+    {
+        let _ = pool;
+        error_response(StatusCode::NOT_FOUND, "WebTransport runtime disabled")
+    }
+}
+
+/// ICE servers for a browser's `RTCPeerConnection`, with ephemeral TURN credentials.
+pub async fn webrtc_ice_servers(
+    State(pool): State<SqlitePool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(payload): Json<WebRtcIceServersRequest>,
+) -> impl IntoResponse {
+    if !ensure_webrtc_rate_limit("ice-servers", addr) {
+        return error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many ICE server requests",
+        );
+    }
+    if !security::is_valid_session_token(&payload.session_token) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid session token");
+    }
+
+    #[cfg(feature = "webtransport-runtime")]
+    {
+        match db::get_username_by_session_token(&pool, &payload.session_token).await {
+            Ok(Some(username)) => Json(WebRtcIceServersResponse {
+                ice_servers: ice_servers_for(&username).await,
+            })
+            .into_response(),
+            Ok(None) => {
+                error_response(StatusCode::UNAUTHORIZED, "Invalid or expired session token")
+            }
+            Err(err) => {
+                tracing::error!("session token lookup failed: {}", err);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, "Session lookup failed")
+            }
+        }
+    }
+
+    #[cfg(not(feature = "webtransport-runtime"))]
+    {
+        let _ = pool;
+        error_response(StatusCode::NOT_FOUND, "WebTransport runtime disabled")
+    }
+}
+
 pub async fn webrtc_offer(
     State(pool): State<SqlitePool>,
     State(connections): State<ConnectionMap>,
@@ -266,18 +451,27 @@ pub struct GatewayWebTransportHandler {
     pub connections: ConnectionMap,
     pub active_sessions: Arc<RwLock<HashMap<String, String>>>, // peer_addr -> username
     pub active_targets: Arc<RwLock<HashMap<String, String>>>,  // session_id -> target_username
+    /// Filled in synchronously when a session starts, so a control frame
+    /// handled right after always finds its session's sender and claims.
     pub session_senders:
-        Arc<RwLock<HashMap<String, mpsc::Sender<web_transport::ControlStreamFrame>>>>,
+        Arc<SyncRwLock<HashMap<String, mpsc::Sender<web_transport::ControlStreamFrame>>>>,
+    pub session_claims: Arc<SyncRwLock<HashMap<String, web_transport::WebSessionClaims>>>,
 }
 
 #[cfg(feature = "webtransport-runtime")]
 impl web_transport::WebTransportSessionHandler for GatewayWebTransportHandler {
     fn on_session_started(&self, session: web_transport::WebTransportSession) {
         tracing::info!("webtransport session started: {}", session.session_id);
-        let senders = self.session_senders.clone();
-        tokio::spawn(async move {
-            senders.write().await.insert(session.session_id, session.tx);
-        });
+        if let Some(claims) = session.claims {
+            self.session_claims
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(session.session_id.clone(), claims);
+        }
+        self.session_senders
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(session.session_id, session.tx);
     }
 
     fn on_input_datagram(&self, session_id: &str, datagram: web_transport::InputDatagram) {
@@ -304,20 +498,41 @@ impl web_transport::WebTransportSessionHandler for GatewayWebTransportHandler {
         let connections = self.connections.clone();
         let active_sessions = self.active_sessions.clone();
         let active_targets = self.active_targets.clone();
-        let session_senders = self.session_senders.clone();
         let session_id = session_id.to_string();
+        let session_tx = self
+            .session_senders
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&session_id)
+            .cloned();
+        let session_claims = self
+            .session_claims
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&session_id)
+            .cloned();
 
         tokio::spawn(async move {
             match frame {
                 web_transport::ControlStreamFrame::Control(msg) => {
                     match msg {
                         web_transport::ControlMessage::Connect { session_token, .. } => {
+                            // The transport token must have been minted for this gateway session.
+                            let bound = session_claims
+                                .as_ref()
+                                .map(|claims| claims.is_bound_to(&session_token))
+                                .unwrap_or(false);
+                            if !bound {
+                                tracing::warn!(
+                                    "WebTransport client {} presented an unbound session token",
+                                    session_id
+                                );
+                                return;
+                            }
                             if let Ok(Some(username)) =
                                 db::get_username_by_session_token(&pool, &session_token).await
                             {
-                                let maybe_tx =
-                                    session_senders.read().await.get(&session_id).cloned();
-                                if let Some(tx) = maybe_tx {
+                                if let Some(tx) = session_tx {
                                     tracing::info!(
                                         "WebTransport client {} bound to user {}",
                                         session_id,
@@ -328,9 +543,17 @@ impl web_transport::WebTransportSessionHandler for GatewayWebTransportHandler {
                                         .await
                                         .insert(session_id.clone(), username.clone());
                                     connections.write().await.insert(
-                                        username,
-                                        crate::signal::Signaler::WebTransport(tx),
+                                        username.clone(),
+                                        crate::signal::Signaler::WebTransport(tx.clone()),
                                     );
+                                    let ice_servers = ice_servers_for(&username).await;
+                                    let _ = tx
+                                        .send(web_transport::ControlStreamFrame::Response(
+                                            web_transport::WebControlResponse::IceServers {
+                                                ice_servers,
+                                            },
+                                        ))
+                                        .await;
                                 }
                             }
                         }
@@ -393,6 +616,34 @@ impl web_transport::WebTransportSessionHandler for GatewayWebTransportHandler {
             }
         });
     }
+
+    fn on_session_closed(&self, session_id: &str) {
+        let connections = self.connections.clone();
+        let active_sessions = self.active_sessions.clone();
+        let active_targets = self.active_targets.clone();
+        let session_id = session_id.to_string();
+        self.session_senders
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&session_id);
+        self.session_claims
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&session_id);
+
+        tokio::spawn(async move {
+            active_targets.write().await.remove(&session_id);
+            if let Some(username) = active_sessions.write().await.remove(&session_id) {
+                let mut guard = connections.write().await;
+                if matches!(
+                    guard.get(&username),
+                    Some(crate::signal::Signaler::WebTransport(_))
+                ) {
+                    guard.remove(&username);
+                }
+            }
+        });
+    }
 }
 
 #[cfg(feature = "webtransport-runtime")]
@@ -401,14 +652,21 @@ pub async fn run_webtransport_runtime(
     pool: sqlx::SqlitePool,
     connections: ConnectionMap,
 ) -> anyhow::Result<()> {
-    let server = web_transport::WebTransportServer::bind(bind_addr).await?;
+    let verifier = WEB_TOKEN_SIGNER
+        .as_ref()
+        .map(|signer| signer.verifier())
+        .ok_or_else(|| anyhow::anyhow!("web token signer unavailable"))?;
+    let server = web_transport::WebTransportServer::bind(bind_addr)
+        .await?
+        .with_token_verifier(verifier);
     server
         .run(GatewayWebTransportHandler {
             pool,
             connections,
             active_sessions: Arc::new(RwLock::new(HashMap::new())),
             active_targets: Arc::new(RwLock::new(HashMap::new())),
-            session_senders: Arc::new(RwLock::new(HashMap::new())),
+            session_senders: Arc::new(SyncRwLock::new(HashMap::new())),
+            session_claims: Arc::new(SyncRwLock::new(HashMap::new())),
         })
         .await
 }
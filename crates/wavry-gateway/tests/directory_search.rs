@@ -0,0 +1,101 @@
+//! Integration tests for the user directory queries.
+
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use wavry_gateway::db;
+
+async fn setup_test_db() -> SqlitePool {
+    // One connection so every query sees the same in-memory database.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to create test database");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Failed to run migrations");
+    pool
+}
+
+async fn create_user(pool: &SqlitePool, username: &str, display_name: &str) -> String {
+    db::create_user(
+        pool,
+        &format!("{username}@test.local"),
+        "hashed_password",
+        display_name,
+        username,
+        "public_key",
+    )
+    .await
+    .expect("Failed to create user")
+    .id
+}
+
+fn usernames(rows: &[db::DirectoryEntry]) -> Vec<&str> {
+    rows.iter().map(|row| row.username.as_str()).collect()
+}
+
+#[tokio::test]
+async fn search_matches_username_and_display_name_prefixes() {
+    let pool = setup_test_db().await;
+    create_user(&pool, "alice", "Alice Liddell").await;
+    create_user(&pool, "bob", "Alfred Bob").await;
+    create_user(&pool, "carol", "Carol").await;
+
+    let rows = db::search_directory(&pool, "al", None, "", 10)
+        .await
+        .expect("search");
+    assert_eq!(usernames(&rows), vec!["alice", "bob"]);
+}
+
+#[tokio::test]
+async fn search_pages_by_username_cursor() {
+    let pool = setup_test_db().await;
+    for name in ["ala", "alb", "alc"] {
+        create_user(&pool, name, name).await;
+    }
+
+    let first = db::search_directory(&pool, "al", None, "", 2)
+        .await
+        .expect("first page");
+    assert_eq!(usernames(&first), vec!["ala", "alb"]);
+
+    let second = db::search_directory(&pool, "al", Some("alb"), "", 2)
+        .await
+        .expect("second page");
+    assert_eq!(usernames(&second), vec!["alc"]);
+}
+
+#[tokio::test]
+async fn search_hides_opted_out_banned_and_self() {
+    let pool = setup_test_db().await;
+    create_user(&pool, "alice", "Alice").await;
+    create_user(&pool, "alvin", "Alvin").await;
+    let banned = create_user(&pool, "alma", "Alma").await;
+    create_user(&pool, "alex", "Alex").await;
+
+    assert!(db::set_directory_visible(&pool, "alvin", false)
+        .await
+        .expect("opt out"));
+    sqlx::query("INSERT INTO user_bans (user_id, reason) VALUES (?, 'abuse')")
+        .bind(&banned)
+        .execute(&pool)
+        .await
+        .expect("ban");
+
+    let rows = db::search_directory(&pool, "al", None, "alex", 10)
+        .await
+        .expect("search");
+    assert_eq!(usernames(&rows), vec!["alice"]);
+}
+
+#[tokio::test]
+async fn search_treats_wildcards_literally() {
+    let pool = setup_test_db().await;
+    create_user(&pool, "alice", "Alice").await;
+
+    let rows = db::search_directory(&pool, "%_", None, "", 10)
+        .await
+        .expect("search");
+    assert!(rows.is_empty());
+}
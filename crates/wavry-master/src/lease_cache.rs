@@ -0,0 +1,109 @@
+//! Pre-issued relay leases
+//!
+//! A requester's lease is minted ahead of time (at BIND and again after each relay request)
+//! so `REQUEST_RELAY` only has to sign the peer's lease. Pre-issued leases use a shorter TTL
+//! than inline ones because they sit idle before use.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// A signed lease plus the relay assignment it was minted for.
+#[derive(Debug, Clone)]
+pub struct PreissuedLease {
+    pub relay_id: String,
+    pub addr: String,
+    pub session_id: Uuid,
+    pub token: String,
+    /// Region hint the relay was selected for.
+    pub region: Option<String>,
+    pub expires_at: Instant,
+}
+
+/// At most one pre-issued lease per user; taking it always consumes the entry.
+pub struct LeaseCache {
+    entries: HashMap<String, PreissuedLease>,
+    min_remaining: Duration,
+}
+
+impl LeaseCache {
+    pub fn new(min_remaining: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            min_remaining,
+        }
+    }
+
+    pub fn insert(&mut self, wavry_id: &str, lease: PreissuedLease) {
+        self.entries.insert(wavry_id.to_string(), lease);
+    }
+
+    /// Returns the user's lease if it was selected for `region` and still has
+    /// enough validity left to reach the relay.
+    pub fn take(
+        &mut self,
+        wavry_id: &str,
+        region: Option<&str>,
+        now: Instant,
+    ) -> Option<PreissuedLease> {
+        let lease = self.entries.remove(wavry_id)?;
+        if lease.region.as_deref() != region {
+            return None;
+        }
+        if lease.expires_at.saturating_duration_since(now) < self.min_remaining {
+            return None;
+        }
+        Some(lease)
+    }
+
+    pub fn remove(&mut self, wavry_id: &str) {
+        self.entries.remove(wavry_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lease(region: Option<&str>, expires_at: Instant) -> PreissuedLease {
+        PreissuedLease {
+            relay_id: "relay-a".into(),
+            addr: "127.0.0.1:4000".into(),
+            session_id: Uuid::new_v4(),
+            token: "v4.public.test".into(),
+            region: region.map(str::to_string),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn take_consumes_matching_lease_once() {
+        let now = Instant::now();
+        let mut cache = LeaseCache::new(Duration::from_secs(30));
+        cache.insert("user-a", lease(None, now + Duration::from_secs(300)));
+
+        assert!(cache.take("user-a", None, now).is_some());
+        assert!(cache.take("user-a", None, now).is_none());
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn take_rejects_region_mismatch_and_near_expiry() {
+        let now = Instant::now();
+        let mut cache = LeaseCache::new(Duration::from_secs(30));
+
+        cache.insert(
+            "user-a",
+            lease(Some("us-east-1"), now + Duration::from_secs(300)),
+        );
+        assert!(cache.take("user-a", Some("eu-west-1"), now).is_none());
+        assert_eq!(cache.len(), 0);
+
+        cache.insert("user-a", lease(None, now + Duration::from_secs(10)));
+        assert!(cache.take("user-a", None, now).is_none());
+    }
+}
@@ -26,16 +26,24 @@ use serde::{Deserialize, Serialize};
 use std::io::ErrorKind;
 use std::sync::Mutex;
 use std::time::Duration;
-use std::time::{Instant, SystemTime};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, RwLock};
 
+mod lease_cache;
 mod selection;
+mod usage;
+use lease_cache::{LeaseCache, PreissuedLease};
 use selection::{RelayCandidate, RelayMetrics, RelayState};
+use usage::{LeaseQuota, QuotaLimits, UsageTotals};
 
+use wavry_common::invite::{
+    guest_username, invite_ttl, invite_url, mint_invite, verify_invite, INVITE_SECRET_ENV,
+};
 use wavry_common::protocol::{
     RegisterRequest, RelayFeedbackRequest, RelayHeartbeatRequest, RelayRegisterRequest,
-    RelayRegisterResponse, SignalMessage, VerifyRequest,
+    RelayRegisterResponse, RelayUsageReport, SignalMessage, VerifyRequest,
 };
+use wavry_common::turn::{mint_turn_credentials, parse_turn_urls, DEFAULT_TURN_CREDENTIAL_TTL};
 
 /// Lease claims in PASETO token
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,8 +68,17 @@ struct LeaseClaims {
     soft_limit_kbps: Option<u32>,
     #[serde(rename = "hlimit")]
     hard_limit_kbps: Option<u32>,
+    #[serde(rename = "dquota", default)]
+    daily_quota_bytes: Option<u64>,
+    #[serde(rename = "mquota", default)]
+    monthly_quota_bytes: Option<u64>,
+    #[serde(rename = "dused", default)]
+    daily_used_bytes: Option<u64>,
+    #[serde(rename = "mused", default)]
+    monthly_used_bytes: Option<u64>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn generate_lease(
     wavry_id: &str,
     session_id: Uuid,
@@ -69,6 +86,7 @@ fn generate_lease(
     relay_id: &str,
     signing_key_id: &str,
     lease_ttl: Duration,
+    quota: Option<&LeaseQuota>,
     key: &pasetors::keys::AsymmetricSecretKey<pasetors::version4::V4>,
 ) -> Result<String> {
     use pasetors::claims::Claims;
@@ -117,6 +135,23 @@ fn generate_lease(
         .add_additional("hlimit", 100_000)
         .map_err(|e| anyhow!("pasetors error: {}", e))?;
 
+    // Relay quotas, with the usage on record so relays can enforce them
+    if let Some(quota) = quota {
+        let claims_to_add = [
+            ("dquota", quota.daily_limit_bytes),
+            ("mquota", quota.monthly_limit_bytes),
+            ("dused", Some(quota.daily_used_bytes)),
+            ("mused", Some(quota.monthly_used_bytes)),
+        ];
+        for (claim, value) in claims_to_add {
+            if let Some(value) = value {
+                claims
+                    .add_additional(claim, value)
+                    .map_err(|e| anyhow!("pasetors error: {}", e))?;
+            }
+        }
+    }
+
     let token = pasetors::public::sign(key, &claims, None, None)
         .map_err(|e| anyhow!("pasetors error: {}", e))?;
     Ok(token)
@@ -165,19 +200,65 @@ struct AppState {
     relays: RelayMap,
     reputations: Arc<RwLock<HashMap<String, RelayReputation>>>,
     lease_rate_limiter: Mutex<HashMap<String, Vec<Instant>>>,
+    lease_cache: Mutex<LeaseCache>,
     banned_users: Arc<RwLock<HashSet<String>>>,
+    /// Relay traffic per user, from relay usage reports.
+    usage: Mutex<UsageTotals>,
+    quota_limits: QuotaLimits,
     relay_auth_token: Option<String>,
     #[cfg(feature = "insecure-dev-auth")]
     insecure_dev: bool,
     signing_key: pasetors::keys::AsymmetricSecretKey<pasetors::version4::V4>,
     signing_key_id: String,
     lease_ttl: Duration,
+    /// TTL for leases minted ahead of `REQUEST_RELAY`; `None` disables pre-issuance.
+    preissued_lease_ttl: Option<Duration>,
     provisioned_signing_key: bool,
+    turn: Option<TurnSettings>,
+    turn_auth_token: Option<String>,
+    /// Shared with gateways so either accepts the other's invites.
+    invite_secret: Vec<u8>,
+    /// Base URL invite links point at; without it only tokens are handed out.
+    public_url: Option<String>,
     started_at: Instant,
 }
 
+/// TURN servers sharing a REST-API secret with the master.
+struct TurnSettings {
+    urls: Vec<String>,
+    shared_secret: String,
+    credential_ttl: Duration,
+}
+
+impl TurnSettings {
+    fn from_env() -> Option<Self> {
+        let urls = parse_turn_urls(&std::env::var("WAVRY_TURN_URLS").unwrap_or_default());
+        let shared_secret = std::env::var("WAVRY_TURN_SHARED_SECRET")
+            .ok()
+            .map(|secret| secret.trim().to_string())
+            .filter(|secret| !secret.is_empty())?;
+        if urls.is_empty() {
+            return None;
+        }
+        let ttl_secs = env_u64(
+            "WAVRY_TURN_CREDENTIAL_TTL_SECS",
+            DEFAULT_TURN_CREDENTIAL_TTL.as_secs(),
+        );
+        Some(Self {
+            urls,
+            shared_secret,
+            credential_ttl: Duration::from_secs(ttl_secs.clamp(60, 86_400)),
+        })
+    }
+}
+
 const LEASE_LIMIT_PER_MINUTE: usize = 10;
 const DEFAULT_LEASE_TTL_SECS: u64 = 900;
+const DEFAULT_PREISSUED_LEASE_TTL_SECS: u64 = 300;
+/// A pre-issued lease with less validity left than this is re-minted instead.
+const PREISSUED_LEASE_MIN_REMAINING: Duration = Duration::from_secs(30);
+/// Users a single relay usage report may cover.
+const MAX_USAGE_REPORT_ENTRIES: usize = 10_000;
 
 fn check_lease_rate_limit(state: &AppState, username: &str) -> bool {
     let mut guard = state.lease_rate_limiter.lock().unwrap();
@@ -332,7 +413,10 @@ fn running_in_container() -> bool {
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    wavry_common::init_tracing_with_default(&args.log_level);
+    let _log_handle = wavry_common::init_logging(wavry_common::LogOptions::from_env(
+        args.log_level.as_str(),
+        "wavry-master",
+    )?)?;
 
     let listen_addr: std::net::SocketAddr = args
         .listen
@@ -394,6 +478,12 @@ async fn main() -> anyhow::Result<()> {
         .unwrap_or_else(|| derive_default_key_id(&signing_key));
     let lease_ttl_secs = env_u64("WAVRY_MASTER_LEASE_TTL_SECS", DEFAULT_LEASE_TTL_SECS);
     let lease_ttl = Duration::from_secs(lease_ttl_secs.clamp(60, 3600));
+    let preissued_lease_ttl_secs = env_u64(
+        "WAVRY_MASTER_PREISSUED_LEASE_TTL_SECS",
+        DEFAULT_PREISSUED_LEASE_TTL_SECS,
+    );
+    let preissued_lease_ttl = (preissued_lease_ttl_secs > 0)
+        .then(|| Duration::from_secs(preissued_lease_ttl_secs.clamp(60, lease_ttl.as_secs())));
     let relay_auth_token = std::env::var("WAVRY_MASTER_RELAY_AUTH_TOKEN")
         .ok()
         .map(|token| token.trim().to_string())
@@ -406,12 +496,63 @@ async fn main() -> anyhow::Result<()> {
         );
     }
     info!(
-        "master signing key id={} lease_ttl_secs={} provisioned_key={}",
+        "master signing key id={} lease_ttl_secs={} preissued_lease_ttl_secs={} provisioned_key={}",
         signing_key_id,
         lease_ttl.as_secs(),
+        preissued_lease_ttl.map_or(0, |ttl| ttl.as_secs()),
         provisioned_signing_key
     );
 
+    let turn = TurnSettings::from_env();
+    match &turn {
+        Some(turn) => info!(
+            "issuing TURN credentials for {} url(s), ttl_secs={}",
+            turn.urls.len(),
+            turn.credential_ttl.as_secs()
+        ),
+        None => {
+            info!("TURN credentials disabled; set WAVRY_TURN_URLS and WAVRY_TURN_SHARED_SECRET")
+        }
+    }
+    let quota_bytes =
+        |name| Some(env_u64(name, 0).saturating_mul(1_000_000)).filter(|bytes| *bytes > 0);
+    let quota_limits = QuotaLimits {
+        daily_bytes: quota_bytes("WAVRY_MASTER_DAILY_QUOTA_MB"),
+        monthly_bytes: quota_bytes("WAVRY_MASTER_MONTHLY_QUOTA_MB"),
+    };
+    if quota_limits.is_unlimited() {
+        info!("relay quotas disabled; set WAVRY_MASTER_DAILY_QUOTA_MB or WAVRY_MASTER_MONTHLY_QUOTA_MB");
+    } else {
+        info!(
+            "relay quotas daily_bytes={:?} monthly_bytes={:?}",
+            quota_limits.daily_bytes, quota_limits.monthly_bytes
+        );
+    }
+    let turn_auth_token = std::env::var("WAVRY_MASTER_TURN_AUTH_TOKEN")
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty());
+    let invite_secret = match std::env::var(INVITE_SECRET_ENV)
+        .ok()
+        .map(|secret| secret.trim().to_string())
+        .filter(|secret| !secret.is_empty())
+    {
+        Some(secret) => secret.into_bytes(),
+        None => {
+            warn!(
+                "{} not set; invites only work on this master until it restarts",
+                INVITE_SECRET_ENV
+            );
+            let mut secret = vec![0u8; 32];
+            rand::thread_rng().fill(&mut secret[..]);
+            secret
+        }
+    };
+    let public_url = std::env::var("WAVRY_MASTER_PUBLIC_URL")
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty());
+
     let state = Arc::new(AppState {
         #[cfg(feature = "insecure-dev-auth")]
         challenges: Mutex::new(HashMap::new()),
@@ -419,18 +560,27 @@ async fn main() -> anyhow::Result<()> {
         relays: Arc::new(RwLock::new(HashMap::new())),
         reputations: Arc::new(RwLock::new(HashMap::new())),
         lease_rate_limiter: Mutex::new(HashMap::new()),
+        lease_cache: Mutex::new(LeaseCache::new(PREISSUED_LEASE_MIN_REMAINING)),
         banned_users: Arc::new(RwLock::new(HashSet::new())),
+        usage: Mutex::new(UsageTotals::new()),
+        quota_limits,
         relay_auth_token,
         #[cfg(feature = "insecure-dev-auth")]
         insecure_dev,
         signing_key,
         signing_key_id,
         lease_ttl,
+        preissued_lease_ttl,
         provisioned_signing_key,
+        turn,
+        turn_auth_token,
+        invite_secret,
+        public_url,
         started_at: Instant::now(),
     });
 
     let relay_registry = state.relays.clone();
+    let usage_state = state.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
         let quarantine_after = std::time::Duration::from_secs(120);
@@ -448,6 +598,7 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
             relays.retain(|_, relay| now.duration_since(relay.last_seen) <= purge_after);
+            usage_state.usage.lock().unwrap().prune(chrono::Utc::now());
         }
     });
 
@@ -457,8 +608,10 @@ async fn main() -> anyhow::Result<()> {
         .route("/.well-known/wavry-id", get(handle_well_known_id))
         .route("/v1/relays/register", post(handle_relay_register))
         .route("/v1/relays/heartbeat", post(handle_relay_heartbeat))
+        .route("/v1/relays/usage", post(handle_relay_usage))
         .route("/v1/relays", get(handle_relay_list))
         .route("/v1/feedback", post(handle_feedback))
+        .route("/v1/turn/credentials", post(handle_turn_credentials))
         .route("/admin/api/sessions/revoke", post(handle_revoke_session))
         .route(
             "/admin/api/relays/update_state",
@@ -504,6 +657,8 @@ struct MasterHealthResponse {
     signing_key_id: String,
     provisioned_signing_key: bool,
     lease_ttl_secs: u64,
+    preissued_lease_ttl_secs: u64,
+    preissued_leases: usize,
 }
 
 async fn health_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
@@ -528,6 +683,8 @@ async fn health_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
             signing_key_id: state.signing_key_id.clone(),
             provisioned_signing_key: state.provisioned_signing_key,
             lease_ttl_secs: state.lease_ttl.as_secs(),
+            preissued_lease_ttl_secs: state.preissued_lease_ttl.map_or(0, |ttl| ttl.as_secs()),
+            preissued_leases: state.lease_cache.lock().unwrap().len(),
         }),
     )
         .into_response()
@@ -577,6 +734,15 @@ async fn handle_well_known_id(State(state): State<Arc<AppState>>) -> impl IntoRe
     .into_response()
 }
 
+/// Host part of a relay endpoint such as `203.0.113.5:4000` or
+/// `[2001:db8::5]:4000`.
+fn endpoint_ip(endpoint: &str) -> Option<std::net::IpAddr> {
+    endpoint
+        .parse::<std::net::SocketAddr>()
+        .ok()
+        .map(|addr| addr.ip().to_canonical())
+}
+
 async fn handle_relay_register(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -605,11 +771,11 @@ async fn handle_relay_register(
     }
 
     // Sybil Check: Max 5 relays per IP
-    if let Some(ip) = payload.endpoints.first().and_then(|e| e.split(':').next()) {
+    if let Some(ip) = payload.endpoints.first().and_then(|e| endpoint_ip(e)) {
         let relays = state.relays.read().await;
         let count = relays
             .values()
-            .filter(|r| r.endpoints.iter().any(|e| e.starts_with(ip)))
+            .filter(|r| r.endpoints.iter().any(|e| endpoint_ip(e) == Some(ip)))
             .count();
         if count >= 5 {
             warn!("Sybil check failed for IP {}: {} relays", ip, count);
@@ -673,6 +839,32 @@ async fn handle_relay_heartbeat(
     Json(serde_json::json!({ "ok": true })).into_response()
 }
 
+async fn handle_relay_usage(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<RelayUsageReport>,
+) -> impl IntoResponse {
+    if !assert_relay_service_identity(&headers, state.relay_auth_token.as_deref()) {
+        warn!("relay usage report rejected: missing/invalid service token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if payload.entries.len() > MAX_USAGE_REPORT_ENTRIES {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    if !state.relays.read().await.contains_key(&payload.relay_id) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let now = chrono::Utc::now();
+    let mut usage = state.usage.lock().unwrap();
+    for entry in &payload.entries {
+        if !entry.wavry_id.trim().is_empty() {
+            usage.add(&entry.wavry_id, entry.bytes, now);
+        }
+    }
+    Json(serde_json::json!({ "ok": true })).into_response()
+}
+
 async fn handle_relay_list(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     let now = Instant::now();
     let relays = state.relays.read().await;
@@ -733,6 +925,43 @@ async fn handle_feedback(
     Json(serde_json::json!({ "accepted": true })).into_response()
 }
 
+#[derive(Debug, Deserialize)]
+struct TurnCredentialsRequest {
+    username: String,
+}
+
+/// Mint short-lived TURN credentials for a signaling gateway to hand to a browser.
+async fn handle_turn_credentials(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<TurnCredentialsRequest>,
+) -> impl IntoResponse {
+    if !assert_relay_service_identity(&headers, state.turn_auth_token.as_deref()) {
+        warn!("TURN credential request rejected: missing/invalid service token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let Some(turn) = state.turn.as_ref() else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    let username = payload.username.trim();
+    // The TURN REST username is `<expiry>:<user>`, so the user part cannot contain ':'.
+    if username.is_empty() || username.len() > 64 || username.contains(':') {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    if state.banned_users.read().await.contains(username) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Json(mint_turn_credentials(
+        &turn.shared_secret,
+        username,
+        turn.urls.clone(),
+        turn.credential_ttl,
+        unix_now(),
+    ))
+    .into_response()
+}
+
 #[derive(Debug, Deserialize)]
 struct RevokeRequest {
     wavry_id: String,
@@ -784,6 +1013,49 @@ async fn ws_handler(
     ws.on_upgrade(move |socket| handle_socket(socket, state))
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Guests may only signal the host their invite names, and only to set up a
+/// session with it.
+fn guest_may_send(signal: &SignalMessage, host: &str) -> bool {
+    match signal {
+        SignalMessage::OFFER_RIFT {
+            target_username, ..
+        }
+        | SignalMessage::ANSWER_RIFT {
+            target_username, ..
+        }
+        | SignalMessage::OFFER {
+            target_username, ..
+        }
+        | SignalMessage::ANSWER {
+            target_username, ..
+        }
+        | SignalMessage::CANDIDATE {
+            target_username, ..
+        }
+        | SignalMessage::REQUEST_RELAY {
+            target_username, ..
+        } => target_username == host,
+        _ => false,
+    }
+}
+
+fn send_signal_error(tx: &mpsc::Sender<Message>, code: u16, message: &str) {
+    let _ = tx.try_send(Message::Text(
+        serde_json::to_string(&SignalMessage::ERROR {
+            code: Some(code),
+            message: message.into(),
+        })
+        .unwrap(),
+    ));
+}
+
 async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     let (mut sender, mut receiver) = socket.split();
     let (tx, mut rx) = mpsc::channel::<Message>(128);
@@ -798,6 +1070,8 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     });
 
     let mut my_username: Option<String> = None;
+    // Host named by the invite a guest connection bound with.
+    let mut invited_to: Option<String> = None;
 
     while let Some(Ok(msg)) = receiver.next().await {
         if let Message::Text(text) = msg {
@@ -806,12 +1080,74 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
                 Err(_) => continue,
             };
 
+            if let Some(host) = &invited_to {
+                if !guest_may_send(&signal, host) {
+                    send_signal_error(&tx_clone, 403, "Invite only allows signaling its host");
+                    continue;
+                }
+            }
+
             match signal {
                 SignalMessage::BIND { token } => {
                     let prefix: String = token.chars().take(8).collect();
                     let username = format!("user_{}", prefix);
                     my_username = Some(username.clone());
-                    state.peers.write().await.insert(username, tx_clone.clone());
+                    state
+                        .peers
+                        .write()
+                        .await
+                        .insert(username.clone(), tx_clone.clone());
+                    if state.preissued_lease_ttl.is_some() {
+                        tokio::spawn(preissue_lease(state.clone(), username, None));
+                    }
+                }
+                SignalMessage::BIND_INVITE { invite } => {
+                    if my_username.is_some() {
+                        send_signal_error(&tx_clone, 409, "Already bound");
+                        continue;
+                    }
+                    let claims = match verify_invite(&state.invite_secret, &invite, unix_now()) {
+                        Ok(claims) => claims,
+                        Err(err) => {
+                            info!("rejected invite: {}", err);
+                            send_signal_error(&tx_clone, 401, "Invalid or expired invite");
+                            continue;
+                        }
+                    };
+                    let guest = guest_username();
+                    info!(
+                        "bound guest {} invited to {} (invite {})",
+                        guest, claims.host, claims.id
+                    );
+                    state
+                        .peers
+                        .write()
+                        .await
+                        .insert(guest.clone(), tx_clone.clone());
+                    my_username = Some(guest);
+                    invited_to = Some(claims.host);
+                }
+                SignalMessage::CREATE_INVITE { ttl_secs } => {
+                    let Some(host) = &my_username else {
+                        send_signal_error(&tx_clone, 401, "Bind required before creating invites");
+                        continue;
+                    };
+                    let ttl = invite_ttl(ttl_secs);
+                    let now = unix_now();
+                    let token = mint_invite(&state.invite_secret, host, ttl, now);
+                    let url = state
+                        .public_url
+                        .as_deref()
+                        .map(|base| invite_url(base, &token));
+                    info!("minted invite to {} valid for {}s", host, ttl.as_secs());
+                    let _ = tx_clone.try_send(Message::Text(
+                        serde_json::to_string(&SignalMessage::INVITE {
+                            token,
+                            url,
+                            expires_at: now + ttl.as_secs(),
+                        })
+                        .unwrap(),
+                    ));
                 }
                 SignalMessage::REQUEST_RELAY {
                     target_username,
@@ -830,74 +1166,34 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
                             continue;
                         }
 
-                        let selected_relay = {
-                            let relays = state.relays.read().await;
-                            let reps = state.reputations.read().await;
-
-                            let candidates: Vec<RelayCandidate> = relays
-                                .iter()
-                                .filter_map(|(id, r)| {
-                                    if matches!(
-                                        r.state,
-                                        RelayState::Draining
-                                            | RelayState::Quarantined
-                                            | RelayState::Banned
-                                    ) {
-                                        return None;
-                                    }
-                                    let rep = reps.get(id).cloned().unwrap_or_default();
-
-                                    // Map legacy RelayReputation to new RelayMetrics
-                                    let metrics = RelayMetrics {
-                                        success_rate: rep.success_rate,
-                                        ..Default::default()
-                                    };
-
-                                    let age = Instant::now().saturating_duration_since(r.last_seen);
-                                    let seen_at = SystemTime::now()
-                                        .checked_sub(age)
-                                        .unwrap_or(SystemTime::UNIX_EPOCH);
-
-                                    Some(RelayCandidate {
-                                        _id: id.clone(),
-                                        endpoints: r.endpoints.clone(),
-                                        state: r.state.clone(),
-                                        metrics,
-                                        region: r.region.clone(),
-                                        asn: r.asn,
-                                        load_pct: r.load_pct,
-                                        last_seen: seen_at,
-                                    })
-                                })
-                                .collect();
-
-                            let filtered = selection::filter_by_geography(
-                                candidates,
-                                client_region.as_deref(),
-                                None,
-                                10,
-                            );
-
-                            selection::select_relay(&filtered).cloned()
+                        let preissued =
+                            take_preissued_lease(&state, src, client_region.as_deref()).await;
+                        let requester_lease = match preissued {
+                            Some(lease) => Some(lease),
+                            None => {
+                                mint_requester_lease(
+                                    &state,
+                                    src,
+                                    client_region.clone(),
+                                    state.lease_ttl,
+                                )
+                                .await
+                            }
                         };
 
-                        if let Some(relay) = selected_relay {
-                            let Some(addr) = relay.endpoints.first().cloned() else {
-                                warn!("selected relay {} has no endpoints", relay._id);
-                                continue;
-                            };
-                            let relay_id = relay._id;
-                            let session_id = Uuid::new_v4();
-                            let host_lease = generate_lease(
-                                src,
-                                session_id,
-                                "server",
-                                &relay_id,
-                                &state.signing_key_id,
-                                state.lease_ttl,
-                                &state.signing_key,
-                            )
-                            .unwrap();
+                        if let Some(PreissuedLease {
+                            relay_id,
+                            addr,
+                            session_id,
+                            token: host_lease,
+                            ..
+                        }) = requester_lease
+                        {
+                            let client_quota = state.usage.lock().unwrap().lease_quota(
+                                &target_username,
+                                state.quota_limits,
+                                chrono::Utc::now(),
+                            );
                             let client_lease = generate_lease(
                                 &target_username,
                                 session_id,
@@ -905,6 +1201,7 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
                                 &relay_id,
                                 &state.signing_key_id,
                                 state.lease_ttl,
+                                client_quota.as_ref(),
                                 &state.signing_key,
                             )
                             .unwrap();
@@ -931,6 +1228,46 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
                             )
                             .await;
                         }
+
+                        if state.preissued_lease_ttl.is_some() {
+                            tokio::spawn(preissue_lease(state.clone(), src.clone(), client_region));
+                        }
+                    }
+                }
+                SignalMessage::OFFER_RIFT {
+                    target_username,
+                    hello_base64,
+                    candidates,
+                } => {
+                    if let Some(src) = &my_username {
+                        relay_signal(
+                            &state,
+                            &target_username,
+                            SignalMessage::OFFER_RIFT {
+                                target_username: src.clone(),
+                                hello_base64,
+                                candidates,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                SignalMessage::ANSWER_RIFT {
+                    target_username,
+                    ack_base64,
+                    candidates,
+                } => {
+                    if let Some(src) = &my_username {
+                        relay_signal(
+                            &state,
+                            &target_username,
+                            SignalMessage::ANSWER_RIFT {
+                                target_username: src.clone(),
+                                ack_base64,
+                                candidates,
+                            },
+                        )
+                        .await;
                     }
                 }
                 SignalMessage::OFFER {
@@ -992,9 +1329,134 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
 
     if let Some(u) = my_username {
         state.peers.write().await.remove(&u);
+        state.lease_cache.lock().unwrap().remove(&u);
+    }
+}
+
+async fn select_relay_for_region(
+    state: &AppState,
+    client_region: Option<&str>,
+) -> Option<RelayCandidate> {
+    let relays = state.relays.read().await;
+    let reps = state.reputations.read().await;
+
+    let candidates: Vec<RelayCandidate> = relays
+        .iter()
+        .filter_map(|(id, r)| {
+            if matches!(
+                r.state,
+                RelayState::Draining | RelayState::Quarantined | RelayState::Banned
+            ) {
+                return None;
+            }
+            let rep = reps.get(id).cloned().unwrap_or_default();
+
+            // Map legacy RelayReputation to new RelayMetrics
+            let metrics = RelayMetrics {
+                success_rate: rep.success_rate,
+                ..Default::default()
+            };
+
+            let age = Instant::now().saturating_duration_since(r.last_seen);
+            let seen_at = SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+
+            Some(RelayCandidate {
+                _id: id.clone(),
+                endpoints: r.endpoints.clone(),
+                state: r.state.clone(),
+                metrics,
+                region: r.region.clone(),
+                asn: r.asn,
+                load_pct: r.load_pct,
+                last_seen: seen_at,
+            })
+        })
+        .collect();
+
+    let filtered = selection::filter_by_geography(candidates, client_region, None, 10);
+
+    selection::select_relay(&filtered).cloned()
+}
+
+/// Selects a relay and signs the requesting ("server") side of a new relay session.
+async fn mint_requester_lease(
+    state: &AppState,
+    wavry_id: &str,
+    client_region: Option<String>,
+    ttl: Duration,
+) -> Option<PreissuedLease> {
+    let relay = select_relay_for_region(state, client_region.as_deref()).await?;
+    let Some(addr) = relay.endpoints.first().cloned() else {
+        warn!("selected relay {} has no endpoints", relay._id);
+        return None;
+    };
+    let session_id = Uuid::new_v4();
+    let quota =
+        state
+            .usage
+            .lock()
+            .unwrap()
+            .lease_quota(wavry_id, state.quota_limits, chrono::Utc::now());
+    let token = match generate_lease(
+        wavry_id,
+        session_id,
+        "server",
+        &relay._id,
+        &state.signing_key_id,
+        ttl,
+        quota.as_ref(),
+        &state.signing_key,
+    ) {
+        Ok(token) => token,
+        Err(err) => {
+            warn!("failed to sign lease for {}: {}", wavry_id, err);
+            return None;
+        }
+    };
+    Some(PreissuedLease {
+        relay_id: relay._id,
+        addr,
+        session_id,
+        token,
+        region: client_region,
+        expires_at: Instant::now() + ttl,
+    })
+}
+
+/// Mints the user's next requester lease ahead of time.
+async fn preissue_lease(state: Arc<AppState>, wavry_id: String, client_region: Option<String>) {
+    let Some(ttl) = state.preissued_lease_ttl else {
+        return;
+    };
+    if let Some(lease) = mint_requester_lease(&state, &wavry_id, client_region, ttl).await {
+        // The user may have disconnected while the lease was being signed.
+        if state.peers.read().await.contains_key(&wavry_id) {
+            state.lease_cache.lock().unwrap().insert(&wavry_id, lease);
+        }
     }
 }
 
+/// Takes the user's pre-issued lease if its relay is still assignable.
+async fn take_preissued_lease(
+    state: &AppState,
+    wavry_id: &str,
+    client_region: Option<&str>,
+) -> Option<PreissuedLease> {
+    let now = Instant::now();
+    let lease = state
+        .lease_cache
+        .lock()
+        .unwrap()
+        .take(wavry_id, client_region, now)?;
+    let relays = state.relays.read().await;
+    relays
+        .get(&lease.relay_id)
+        .is_some_and(|relay| relay_is_assignable(relay, now))
+        .then_some(lease)
+}
+
 async fn relay_signal(state: &Arc<AppState>, target: &str, msg: SignalMessage) {
     let guard = state.peers.read().await;
     if let Some(tx) = guard.get(target) {
@@ -1056,6 +1518,12 @@ mod tests {
         let key_id = "kid-test";
         let relay_id = "relay-test";
         let session_id = Uuid::new_v4();
+        let quota = LeaseQuota {
+            daily_limit_bytes: Some(5_000_000_000),
+            monthly_limit_bytes: None,
+            daily_used_bytes: 1_234,
+            monthly_used_bytes: 5_678,
+        };
         let token = generate_lease(
             "user-a",
             session_id,
@@ -1063,6 +1531,7 @@ mod tests {
             relay_id,
             key_id,
             Duration::from_secs(300),
+            Some(&quota),
             &key,
         )
         .expect("generate lease");
@@ -1088,6 +1557,10 @@ mod tests {
         assert_eq!(payload.relay_id, relay_id);
         assert_eq!(payload.key_id, key_id);
         assert_eq!(payload.session_id, session_id);
+        assert_eq!(payload.daily_quota_bytes, Some(5_000_000_000));
+        assert_eq!(payload.monthly_quota_bytes, None);
+        assert_eq!(payload.daily_used_bytes, Some(1_234));
+        assert_eq!(payload.monthly_used_bytes, Some(5_678));
     }
 
     #[test]
@@ -1113,6 +1586,20 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn endpoint_ip_handles_both_families() {
+        assert_eq!(endpoint_ip("203.0.113.5:4000"), "203.0.113.5".parse().ok());
+        assert_eq!(
+            endpoint_ip("[2001:db8::5]:4000"),
+            "2001:db8::5".parse().ok()
+        );
+        assert_eq!(
+            endpoint_ip("[::ffff:203.0.113.5]:4000"),
+            "203.0.113.5".parse().ok()
+        );
+        assert_eq!(endpoint_ip("relay.example:4000"), None);
+    }
+
     #[test]
     fn fuzz_signal_message_json_parse_never_panics() {
         let mut seed = 0xBEEF_CAFE_1234_5678u64;
@@ -1125,6 +1612,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn guests_only_reach_their_host() {
+        let relay = |target: &str| SignalMessage::REQUEST_RELAY {
+            target_username: target.to_string(),
+            region: None,
+        };
+        assert!(guest_may_send(&relay("user_host"), "user_host"));
+        assert!(!guest_may_send(&relay("user_other"), "user_host"));
+        assert!(!guest_may_send(
+            &SignalMessage::CREATE_INVITE { ttl_secs: None },
+            "user_host"
+        ));
+        assert!(!guest_may_send(
+            &SignalMessage::BIND {
+                token: "t".to_string()
+            },
+            "user_host"
+        ));
+    }
+
     #[test]
     fn fuzz_mutated_signal_messages_never_panic() {
         let mut seed = 0x1234_5678_DEAD_BEEFu64;
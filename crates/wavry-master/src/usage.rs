@@ -0,0 +1,153 @@
+//! Per-user relay traffic totals
+//!
+//! Relays report the bytes they forwarded for each user. The master sums the
+//! reports per UTC day and month and signs the totals into the user's next
+//! leases together with the configured quotas, so whichever relay the user
+//! lands on can enforce them. Totals only live in memory and start over when
+//! the master restarts.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+/// Quotas applied to every user; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaLimits {
+    pub daily_bytes: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
+impl QuotaLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.daily_bytes.is_none() && self.monthly_bytes.is_none()
+    }
+}
+
+/// Quota claims for one lease.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaseQuota {
+    pub daily_limit_bytes: Option<u64>,
+    pub monthly_limit_bytes: Option<u64>,
+    pub daily_used_bytes: u64,
+    pub monthly_used_bytes: u64,
+}
+
+#[derive(Debug)]
+struct UserTotals {
+    day: NaiveDate,
+    daily: u64,
+    monthly: u64,
+}
+
+impl UserTotals {
+    /// Totals as of `today`, without whatever belongs to an earlier period.
+    fn current(&self, today: NaiveDate) -> (u64, u64) {
+        let daily = if self.day == today { self.daily } else { 0 };
+        let monthly = if same_month(self.day, today) {
+            self.monthly
+        } else {
+            0
+        };
+        (daily, monthly)
+    }
+}
+
+fn same_month(a: NaiveDate, b: NaiveDate) -> bool {
+    (a.year(), a.month()) == (b.year(), b.month())
+}
+
+#[derive(Debug, Default)]
+pub struct UsageTotals {
+    users: HashMap<String, UserTotals>,
+}
+
+impl UsageTotals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, wavry_id: &str, bytes: u64, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        let user = self
+            .users
+            .entry(wavry_id.to_string())
+            .or_insert(UserTotals {
+                day: today,
+                daily: 0,
+                monthly: 0,
+            });
+        let (daily, monthly) = user.current(today);
+        user.day = today;
+        user.daily = daily.saturating_add(bytes);
+        user.monthly = monthly.saturating_add(bytes);
+    }
+
+    /// Claims for a lease minted for `wavry_id` now, or `None` without quotas.
+    pub fn lease_quota(
+        &self,
+        wavry_id: &str,
+        limits: QuotaLimits,
+        now: DateTime<Utc>,
+    ) -> Option<LeaseQuota> {
+        if limits.is_unlimited() {
+            return None;
+        }
+        let (daily, monthly) = self
+            .users
+            .get(wavry_id)
+            .map_or((0, 0), |user| user.current(now.date_naive()));
+        Some(LeaseQuota {
+            daily_limit_bytes: limits.daily_bytes,
+            monthly_limit_bytes: limits.monthly_bytes,
+            daily_used_bytes: daily,
+            monthly_used_bytes: monthly,
+        })
+    }
+
+    /// Drops users with nothing left in the current month.
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        self.users.retain(|_, user| same_month(user.day, today));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn totals_follow_the_utc_day_and_month() {
+        let limits = QuotaLimits {
+            daily_bytes: Some(1_000),
+            monthly_bytes: None,
+        };
+        let mut totals = UsageTotals::new();
+        let day = Utc.with_ymd_and_hms(2026, 1, 30, 23, 0, 0).unwrap();
+        assert_eq!(
+            totals.lease_quota("user-a", QuotaLimits::default(), day),
+            None
+        );
+        totals.add("user-a", 300, day);
+        totals.add("user-a", 200, day);
+        let quota = totals.lease_quota("user-a", limits, day).unwrap();
+        assert_eq!(quota.daily_limit_bytes, Some(1_000));
+        assert_eq!(
+            (quota.daily_used_bytes, quota.monthly_used_bytes),
+            (500, 500)
+        );
+
+        let next_day = day + chrono::Duration::hours(2);
+        let quota = totals.lease_quota("user-a", limits, next_day).unwrap();
+        assert_eq!((quota.daily_used_bytes, quota.monthly_used_bytes), (0, 500));
+        totals.add("user-a", 100, next_day);
+        let quota = totals.lease_quota("user-a", limits, next_day).unwrap();
+        assert_eq!(
+            (quota.daily_used_bytes, quota.monthly_used_bytes),
+            (100, 600)
+        );
+
+        totals.prune(Utc.with_ymd_and_hms(2026, 2, 1, 0, 0, 0).unwrap());
+        assert!(totals.users.is_empty());
+    }
+}
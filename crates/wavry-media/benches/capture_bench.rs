@@ -1,7 +1,9 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
 #[cfg(target_os = "linux")]
-use wavry_media::{Codec, EncodeConfig, PipewireEncoder, Resolution};
+use wavry_media::{
+    CaptureMode, Codec, ContentType, EncodeConfig, EncoderTuning, PipewireEncoder, Resolution,
+};
 
 #[cfg(target_os = "linux")]
 fn bench_capture_init(c: &mut Criterion) {
@@ -23,6 +25,9 @@ fn bench_capture_init(c: &mut Criterion) {
                 display_id: None,
                 enable_10bit: false,
                 enable_hdr: false,
+                hide_cursor: false,
+                capture_mode: CaptureMode::Fixed,
+                tuning: EncoderTuning::for_codec(Codec::H264, ContentType::Game),
             };
             let _ = PipewireEncoder::new(config).await;
         })
@@ -1,8 +1,12 @@
+use crate::pacing::PresentStats;
 use crate::{Codec, DecodeConfig, Renderer};
 use anyhow::{anyhow, Result};
 use std::ffi::c_void;
 use std::ptr::NonNull;
 
+#[cfg(target_os = "android")]
+use crate::pacing::{PresentScheduler, Presentation, DEFAULT_REFRESH_HZ};
+
 #[cfg(target_os = "android")]
 use ndk::media::media_codec::{
     DequeuedInputBufferResult, DequeuedOutputBufferInfoResult, MediaCodec, MediaCodecDirection,
@@ -18,6 +22,8 @@ pub struct AndroidVideoRenderer {
     codec: MediaCodec,
     #[cfg(target_os = "android")]
     _native_window: NonNull<ANativeWindow>,
+    #[cfg(target_os = "android")]
+    pacing: PresentScheduler,
     #[cfg(not(target_os = "android"))]
     _dummy: (),
 }
@@ -69,6 +75,7 @@ impl AndroidVideoRenderer {
             Ok(Self {
                 codec,
                 _native_window: nw,
+                pacing: PresentScheduler::new(DEFAULT_REFRESH_HZ),
             })
         }
         #[cfg(not(target_os = "android"))]
@@ -78,6 +85,30 @@ impl AndroidVideoRenderer {
             Err(anyhow!("AndroidVideoRenderer only supported on Android"))
         }
     }
+
+    pub fn present_stats(&self) -> PresentStats {
+        #[cfg(target_os = "android")]
+        {
+            self.pacing.stats()
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            PresentStats::default()
+        }
+    }
+}
+
+/// CLOCK_MONOTONIC, the clock MediaCodec release timestamps are measured on.
+#[cfg(target_os = "android")]
+fn monotonic_us() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
 }
 
 impl Renderer for AndroidVideoRenderer {
@@ -122,8 +153,15 @@ impl Renderer for AndroidVideoRenderer {
                     .dequeue_output_buffer(std::time::Duration::from_micros(0))
                 {
                     Ok(DequeuedOutputBufferInfoResult::Buffer(buffer)) => {
-                        self.codec
-                            .release_output_buffer(buffer, true)
+                        // The presentation time is the host timestamp queued above.
+                        let timestamp_us = buffer.info().presentation_time_us().max(0) as u64;
+                        let released = match self.pacing.schedule(timestamp_us, monotonic_us()) {
+                            Presentation::Drop => self.codec.release_output_buffer(buffer, false),
+                            Presentation::At(slot_us) => self
+                                .codec
+                                .release_output_buffer_at_time(buffer, slot_us as i64 * 1_000),
+                        };
+                        released
                             .map_err(|e| anyhow!("Failed to release output buffer: {:?}", e))?;
                     }
                     Ok(DequeuedOutputBufferInfoResult::TryAgainLater) => break,
@@ -149,6 +187,13 @@ impl Renderer for AndroidVideoRenderer {
             Ok(())
         }
     }
+
+    fn set_display_refresh_hz(&mut self, refresh_hz: f32) {
+        #[cfg(target_os = "android")]
+        self.pacing.set_refresh_hz(refresh_hz);
+        #[cfg(not(target_os = "android"))]
+        let _ = refresh_hz;
+    }
 }
 
 unsafe impl Send for AndroidVideoRenderer {}
@@ -0,0 +1,286 @@
+//! Audio codec traits and their Opus implementation.
+//!
+//! Hosts and clients agree on an [`OpusConfig`] in Hello/HelloAck. With
+//! in-band FEC on, each packet also carries a low-bitrate copy of the frame
+//! before it, so [`OpusAudioDecoder`] can rebuild a single lost packet from
+//! the one that follows instead of concealing it.
+
+#[cfg(feature = "opus-support")]
+use anyhow::anyhow;
+use anyhow::Result;
+
+#[cfg(feature = "opus-support")]
+use opus::{Application, Channels, Decoder as OpusDecoder, Encoder as OpusEncoder};
+
+use super::{OPUS_BITRATE_BPS, OPUS_FRAME_MS, OPUS_SAMPLE_RATE};
+#[cfg(feature = "opus-support")]
+use super::{OPUS_MAX_FRAME_SAMPLES, OPUS_MAX_PACKET_BYTES};
+
+/// Frame durations Opus can encode, in microseconds.
+pub const OPUS_FRAME_DURATIONS_US: [u32; 6] = [2_500, 5_000, 10_000, 20_000, 40_000, 60_000];
+pub const OPUS_MIN_BITRATE_BPS: u32 = 6_000;
+pub const OPUS_MAX_BITRATE_BPS: u32 = 510_000;
+/// Longer gaps are skipped rather than concealed, so playback resyncs
+/// instead of filling with seconds of synthesized audio.
+const MAX_CONCEALED_FRAMES: u64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusConfig {
+    pub bitrate_bps: u32,
+    /// One of [`OPUS_FRAME_DURATIONS_US`].
+    pub frame_duration_us: u32,
+    pub inband_fec: bool,
+    /// Loss rate the encoder budgets FEC for; only used with `inband_fec`.
+    pub expected_loss_percent: u8,
+}
+
+impl Default for OpusConfig {
+    fn default() -> Self {
+        Self {
+            bitrate_bps: OPUS_BITRATE_BPS as u32,
+            frame_duration_us: OPUS_FRAME_MS * 1_000,
+            inband_fec: false,
+            expected_loss_percent: 0,
+        }
+    }
+}
+
+impl OpusConfig {
+    /// Clamps the bitrate to what Opus accepts and rounds the frame duration
+    /// up to the next one it supports.
+    pub fn normalized(self) -> Self {
+        let frame_duration_us = OPUS_FRAME_DURATIONS_US
+            .iter()
+            .copied()
+            .find(|&duration| duration >= self.frame_duration_us)
+            .unwrap_or(OPUS_FRAME_DURATIONS_US[OPUS_FRAME_DURATIONS_US.len() - 1]);
+        Self {
+            bitrate_bps: self
+                .bitrate_bps
+                .clamp(OPUS_MIN_BITRATE_BPS, OPUS_MAX_BITRATE_BPS),
+            frame_duration_us,
+            inband_fec: self.inband_fec,
+            expected_loss_percent: self.expected_loss_percent.min(100),
+        }
+    }
+
+    /// Samples per channel in one frame.
+    pub fn frame_samples(&self) -> usize {
+        (OPUS_SAMPLE_RATE as u64 * u64::from(self.frame_duration_us) / 1_000_000) as usize
+    }
+}
+
+pub trait AudioEncoder: Send {
+    /// Samples per channel that each call to `encode` takes.
+    fn frame_samples(&self) -> usize;
+    /// Encodes one interleaved frame.
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>>;
+    fn set_bitrate(&mut self, bitrate_bps: u32) -> Result<()>;
+}
+
+pub trait AudioDecoder: Send {
+    /// Decodes a packet to interleaved samples. A gap before `timestamp_us`
+    /// is filled first: from the packet's in-band FEC for the frame right
+    /// before it, and with loss concealment for anything earlier.
+    fn decode(&mut self, payload: &[u8], timestamp_us: u64) -> Result<Vec<f32>>;
+}
+
+/// Frames lost between the expected timestamp and the packet that arrived.
+fn missing_frames(expected_us: Option<u64>, timestamp_us: u64, frame_duration_us: u64) -> u64 {
+    let Some(expected_us) = expected_us else {
+        return 0;
+    };
+    if frame_duration_us == 0 || timestamp_us <= expected_us {
+        return 0;
+    }
+    let missing = (timestamp_us - expected_us + frame_duration_us / 2) / frame_duration_us;
+    if missing > MAX_CONCEALED_FRAMES {
+        0
+    } else {
+        missing
+    }
+}
+
+#[cfg(feature = "opus-support")]
+fn opus_channels(channels: usize) -> Result<Channels> {
+    match channels {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        _ => Err(anyhow!(
+            "{} channels need the multi-stream Opus encoder",
+            channels
+        )),
+    }
+}
+
+#[cfg(feature = "opus-support")]
+pub struct OpusAudioEncoder {
+    encoder: OpusEncoder,
+    channels: usize,
+    frame_samples: usize,
+}
+
+#[cfg(feature = "opus-support")]
+impl OpusAudioEncoder {
+    /// Mono or stereo encoder; `config` is normalized first.
+    pub fn new(channels: usize, config: OpusConfig) -> Result<Self> {
+        let config = config.normalized();
+        let mut encoder = OpusEncoder::new(
+            OPUS_SAMPLE_RATE,
+            opus_channels(channels)?,
+            Application::Audio,
+        )
+        .map_err(|e| anyhow!("Opus encoder init failed: {}", e))?;
+        encoder
+            .set_bitrate(opus::Bitrate::Bits(config.bitrate_bps as i32))
+            .map_err(|e| anyhow!("Opus bitrate set failed: {}", e))?;
+        encoder.set_complexity(5).ok();
+        encoder
+            .set_inband_fec(config.inband_fec)
+            .map_err(|e| anyhow!("Opus FEC set failed: {}", e))?;
+        if config.inband_fec {
+            encoder
+                .set_packet_loss_perc(i32::from(config.expected_loss_percent))
+                .map_err(|e| anyhow!("Opus loss estimate set failed: {}", e))?;
+        }
+        encoder.set_dtx(false).ok();
+        Ok(Self {
+            encoder,
+            channels,
+            frame_samples: config.frame_samples(),
+        })
+    }
+}
+
+#[cfg(feature = "opus-support")]
+impl AudioEncoder for OpusAudioEncoder {
+    fn frame_samples(&self) -> usize {
+        self.frame_samples
+    }
+
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>> {
+        if pcm.len() != self.frame_samples * self.channels {
+            return Err(anyhow!(
+                "Opus frame needs {} samples, got {}",
+                self.frame_samples * self.channels,
+                pcm.len()
+            ));
+        }
+        let mut out = vec![0u8; OPUS_MAX_PACKET_BYTES];
+        let len = self
+            .encoder
+            .encode(pcm, &mut out)
+            .map_err(|e| anyhow!("Opus encode failed: {}", e))?;
+        out.truncate(len);
+        Ok(out)
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u32) -> Result<()> {
+        let bitrate_bps = bitrate_bps.clamp(OPUS_MIN_BITRATE_BPS, OPUS_MAX_BITRATE_BPS);
+        self.encoder
+            .set_bitrate(opus::Bitrate::Bits(bitrate_bps as i32))
+            .map_err(|e| anyhow!("Opus bitrate set failed: {}", e))
+    }
+}
+
+#[cfg(feature = "opus-support")]
+pub struct OpusAudioDecoder {
+    decoder: OpusDecoder,
+    channels: usize,
+    scratch: Vec<f32>,
+    next_timestamp_us: Option<u64>,
+    /// Samples per channel in the last decoded packet, the size assumed for
+    /// frames that went missing after it.
+    last_frame_samples: usize,
+}
+
+#[cfg(feature = "opus-support")]
+impl OpusAudioDecoder {
+    pub fn new(channels: usize) -> Result<Self> {
+        let decoder = OpusDecoder::new(OPUS_SAMPLE_RATE, opus_channels(channels)?)
+            .map_err(|e| anyhow!("Opus decoder init failed: {}", e))?;
+        Ok(Self {
+            decoder,
+            channels,
+            scratch: vec![0.0; OPUS_MAX_FRAME_SAMPLES * channels],
+            next_timestamp_us: None,
+            last_frame_samples: 0,
+        })
+    }
+
+    fn decode_into(
+        &mut self,
+        payload: &[u8],
+        fec: bool,
+        len: usize,
+        out: &mut Vec<f32>,
+    ) -> Result<usize> {
+        let frames = self
+            .decoder
+            .decode_float(payload, &mut self.scratch[..len], fec)
+            .map_err(|e| anyhow!("Opus decode failed: {}", e))?;
+        out.extend_from_slice(&self.scratch[..frames * self.channels]);
+        Ok(frames)
+    }
+}
+
+#[cfg(feature = "opus-support")]
+impl AudioDecoder for OpusAudioDecoder {
+    fn decode(&mut self, payload: &[u8], timestamp_us: u64) -> Result<Vec<f32>> {
+        let mut out = Vec::new();
+        let frame_duration_us =
+            self.last_frame_samples as u64 * 1_000_000 / OPUS_SAMPLE_RATE as u64;
+        let missing = missing_frames(self.next_timestamp_us, timestamp_us, frame_duration_us);
+        if missing > 0 {
+            let len = self.last_frame_samples * self.channels;
+            for _ in 1..missing {
+                self.decode_into(&[], false, len, &mut out)?;
+            }
+            self.decode_into(payload, true, len, &mut out)?;
+        }
+        let len = self.scratch.len();
+        let frames = self.decode_into(payload, false, len, &mut out)?;
+        self.last_frame_samples = frames;
+        self.next_timestamp_us =
+            Some(timestamp_us + frames as u64 * 1_000_000 / OPUS_SAMPLE_RATE as u64);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_normalizes_to_opus_limits() {
+        let config = OpusConfig {
+            bitrate_bps: 1_000,
+            frame_duration_us: 7_000,
+            inband_fec: true,
+            expected_loss_percent: 150,
+        }
+        .normalized();
+        assert_eq!(config.bitrate_bps, OPUS_MIN_BITRATE_BPS);
+        assert_eq!(config.frame_duration_us, 10_000);
+        assert_eq!(config.expected_loss_percent, 100);
+        assert_eq!(config.frame_samples(), 480);
+
+        let long = OpusConfig {
+            frame_duration_us: 100_000,
+            ..OpusConfig::default()
+        };
+        assert_eq!(long.normalized().frame_duration_us, 60_000);
+        assert_eq!(OpusConfig::default().normalized(), OpusConfig::default());
+    }
+
+    #[test]
+    fn gaps_count_whole_frames_and_long_outages_resync() {
+        assert_eq!(missing_frames(None, 10_000, 5_000), 0);
+        assert_eq!(missing_frames(Some(10_000), 10_000, 5_000), 0);
+        // Timestamp jitter under half a frame is not loss.
+        assert_eq!(missing_frames(Some(10_000), 12_000, 5_000), 0);
+        assert_eq!(missing_frames(Some(10_000), 15_000, 5_000), 1);
+        assert_eq!(missing_frames(Some(10_000), 25_000, 5_000), 3);
+        assert_eq!(missing_frames(Some(10_000), 1_000_000, 5_000), 0);
+    }
+}
@@ -0,0 +1,111 @@
+//! Channel layouts for multi-channel and ambisonic audio.
+
+/// Channel layout of a session's audio stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioChannelLayout {
+    #[default]
+    Stereo,
+    /// FL FR FC LFE BL BR.
+    Surround51,
+    /// FL FR FC LFE BL BR SL SR.
+    Surround71,
+    /// First-order ambisonics in ACN order (W Y Z X) with SN3D weighting.
+    AmbisonicFoa,
+}
+
+/// Speaker direction as (azimuth, elevation) in degrees; azimuth is
+/// counter-clockwise from straight ahead, so positive is to the left.
+pub type SpeakerDirection = (f32, f32);
+
+const STEREO_SPEAKERS: [Option<SpeakerDirection>; 2] = [Some((30.0, 0.0)), Some((-30.0, 0.0))];
+const SURROUND_51_SPEAKERS: [Option<SpeakerDirection>; 6] = [
+    Some((30.0, 0.0)),
+    Some((-30.0, 0.0)),
+    Some((0.0, 0.0)),
+    None,
+    Some((110.0, 0.0)),
+    Some((-110.0, 0.0)),
+];
+const SURROUND_71_SPEAKERS: [Option<SpeakerDirection>; 8] = [
+    Some((30.0, 0.0)),
+    Some((-30.0, 0.0)),
+    Some((0.0, 0.0)),
+    None,
+    Some((150.0, 0.0)),
+    Some((-150.0, 0.0)),
+    Some((90.0, 0.0)),
+    Some((-90.0, 0.0)),
+];
+
+impl AudioChannelLayout {
+    pub const ALL: [Self; 4] = [
+        Self::Stereo,
+        Self::Surround51,
+        Self::Surround71,
+        Self::AmbisonicFoa,
+    ];
+
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Stereo => 2,
+            Self::Surround51 => 6,
+            Self::Surround71 => 8,
+            Self::AmbisonicFoa => 4,
+        }
+    }
+
+    pub fn is_ambisonic(self) -> bool {
+        matches!(self, Self::AmbisonicFoa)
+    }
+
+    /// Loudspeaker positions by channel; `None` marks the LFE channel.
+    /// Empty for ambisonic layouts, which carry a sound field instead.
+    pub fn speakers(self) -> &'static [Option<SpeakerDirection>] {
+        match self {
+            Self::Stereo => &STEREO_SPEAKERS,
+            Self::Surround51 => &SURROUND_51_SPEAKERS,
+            Self::Surround71 => &SURROUND_71_SPEAKERS,
+            Self::AmbisonicFoa => &[],
+        }
+    }
+
+    /// Layout the host captures from the desktop mix to produce this one.
+    pub fn capture_layout(self) -> Self {
+        match self {
+            Self::AmbisonicFoa => Self::Surround71,
+            layout => layout,
+        }
+    }
+}
+
+/// Unit vector for a speaker direction in OpenXR axes (-Z forward, +Y up,
+/// +X right).
+pub fn direction_vector((azimuth, elevation): SpeakerDirection) -> [f32; 3] {
+    let (az, el) = (azimuth.to_radians(), elevation.to_radians());
+    [-az.sin() * el.cos(), el.sin(), -az.cos() * el.cos()]
+}
+
+/// Pan interleaved speaker-layout samples into first-order ambisonics.
+/// LFE goes into the omnidirectional W channel.
+pub fn encode_foa(layout: AudioChannelLayout, input: &[f32]) -> Vec<f32> {
+    let speakers = layout.speakers();
+    if speakers.is_empty() {
+        return input.to_vec();
+    }
+    let mut out = Vec::with_capacity(input.len() / speakers.len() * 4);
+    for frame in input.chunks_exact(speakers.len()) {
+        let mut foa = [0.0f32; 4];
+        for (sample, speaker) in frame.iter().zip(speakers) {
+            foa[0] += sample;
+            if let Some(direction) = speaker {
+                let [x, y, z] = direction_vector(*direction);
+                // ACN order is W Y Z X in ambisonic axes: X forward, Y left, Z up.
+                foa[1] += sample * -x;
+                foa[2] += sample * y;
+                foa[3] += sample * -z;
+            }
+        }
+        out.extend_from_slice(&foa);
+    }
+    out
+}
@@ -16,4 +16,8 @@ pub(crate) fn opus_frame_duration_us() -> u64 {
     (OPUS_FRAME_SAMPLES as u64) * 1_000_000 / (OPUS_SAMPLE_RATE as u64)
 }
 
+pub mod codec;
+pub mod layout;
+pub mod multistream;
 pub mod renderer;
+pub mod spatial;
@@ -0,0 +1,201 @@
+//! Multi-channel Opus packets built from coupled stereo streams.
+//!
+//! Channels are paired in order (0+1, 2+3, ...) with a trailing mono stream
+//! for odd counts. Every stream except the last is prefixed with its length
+//! as a little-endian u16, so a stereo packet is a plain Opus packet.
+
+use anyhow::{anyhow, Result};
+
+#[cfg(feature = "opus-support")]
+use opus::{Application, Channels, Decoder as OpusDecoder, Encoder as OpusEncoder};
+
+#[cfg(feature = "opus-support")]
+use super::layout::AudioChannelLayout;
+#[cfg(feature = "opus-support")]
+use super::{OPUS_FRAME_SAMPLES, OPUS_MAX_FRAME_SAMPLES, OPUS_MAX_PACKET_BYTES, OPUS_SAMPLE_RATE};
+
+#[cfg(feature = "opus-support")]
+const COUPLED_STREAM_BITRATE_BPS: i32 = 96_000;
+#[cfg(feature = "opus-support")]
+const MONO_STREAM_BITRATE_BPS: i32 = 64_000;
+
+/// Channel count of each Opus stream carrying `channels` channels.
+pub fn stream_channels(channels: usize) -> Vec<usize> {
+    let mut streams = vec![2; channels / 2];
+    if channels % 2 == 1 {
+        streams.push(1);
+    }
+    streams
+}
+
+pub fn pack_streams(streams: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(streams.iter().map(|s| s.len() + 2).sum());
+    let Some((last, rest)) = streams.split_last() else {
+        return Ok(out);
+    };
+    for stream in rest {
+        let len = u16::try_from(stream.len()).map_err(|_| anyhow!("Opus stream too large"))?;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(stream);
+    }
+    out.extend_from_slice(last);
+    Ok(out)
+}
+
+pub fn unpack_streams(payload: &[u8], count: usize) -> Result<Vec<&[u8]>> {
+    let mut streams = Vec::with_capacity(count);
+    let mut rest = payload;
+    for _ in 1..count {
+        if rest.len() < 2 {
+            return Err(anyhow!("truncated multi-stream audio packet"));
+        }
+        let len = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+        if rest.len() < 2 + len {
+            return Err(anyhow!("truncated multi-stream audio packet"));
+        }
+        streams.push(&rest[2..2 + len]);
+        rest = &rest[2 + len..];
+    }
+    if count > 0 {
+        streams.push(rest);
+    }
+    Ok(streams)
+}
+
+#[cfg(feature = "opus-support")]
+pub struct MultichannelOpusEncoder {
+    layout: AudioChannelLayout,
+    streams: Vec<(OpusEncoder, usize)>,
+    scratch: Vec<i16>,
+}
+
+#[cfg(feature = "opus-support")]
+impl MultichannelOpusEncoder {
+    pub fn new(layout: AudioChannelLayout) -> Result<Self> {
+        let streams = stream_channels(layout.channels())
+            .into_iter()
+            .map(|channels| {
+                let (mode, bitrate) = if channels == 2 {
+                    (Channels::Stereo, COUPLED_STREAM_BITRATE_BPS)
+                } else {
+                    (Channels::Mono, MONO_STREAM_BITRATE_BPS)
+                };
+                let mut encoder = OpusEncoder::new(OPUS_SAMPLE_RATE, mode, Application::Audio)
+                    .map_err(|e| anyhow!("Opus encoder init failed: {}", e))?;
+                encoder
+                    .set_bitrate(opus::Bitrate::Bits(bitrate))
+                    .map_err(|e| anyhow!("Opus bitrate set failed: {}", e))?;
+                Ok((encoder, channels))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            layout,
+            streams,
+            scratch: Vec::with_capacity(OPUS_FRAME_SAMPLES * 2),
+        })
+    }
+
+    pub fn layout(&self) -> AudioChannelLayout {
+        self.layout
+    }
+
+    /// Encodes one interleaved frame of `OPUS_FRAME_SAMPLES` per channel.
+    pub fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>> {
+        let channels = self.layout.channels();
+        let mut packets = Vec::with_capacity(self.streams.len());
+        let mut first_channel = 0;
+        for (encoder, stream_channels) in &mut self.streams {
+            self.scratch.clear();
+            for frame in pcm.chunks_exact(channels) {
+                self.scratch
+                    .extend_from_slice(&frame[first_channel..first_channel + *stream_channels]);
+            }
+            first_channel += *stream_channels;
+            let mut out = vec![0u8; OPUS_MAX_PACKET_BYTES];
+            let len = encoder
+                .encode(&self.scratch, &mut out)
+                .map_err(|e| anyhow!("Opus encode failed: {}", e))?;
+            out.truncate(len);
+            packets.push(out);
+        }
+        pack_streams(&packets)
+    }
+}
+
+#[cfg(feature = "opus-support")]
+pub struct MultichannelOpusDecoder {
+    layout: AudioChannelLayout,
+    streams: Vec<(OpusDecoder, usize)>,
+    scratch: Vec<f32>,
+}
+
+#[cfg(feature = "opus-support")]
+impl MultichannelOpusDecoder {
+    pub fn new(layout: AudioChannelLayout) -> Result<Self> {
+        let streams = stream_channels(layout.channels())
+            .into_iter()
+            .map(|channels| {
+                let mode = if channels == 2 {
+                    Channels::Stereo
+                } else {
+                    Channels::Mono
+                };
+                let decoder = OpusDecoder::new(OPUS_SAMPLE_RATE, mode)
+                    .map_err(|e| anyhow!("Opus decoder init failed: {}", e))?;
+                Ok((decoder, channels))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            layout,
+            streams,
+            scratch: vec![0.0; OPUS_MAX_FRAME_SAMPLES * 2],
+        })
+    }
+
+    pub fn layout(&self) -> AudioChannelLayout {
+        self.layout
+    }
+
+    /// Decodes a packet into interleaved samples in this layout.
+    pub fn decode(&mut self, payload: &[u8]) -> Result<Vec<f32>> {
+        let channels = self.layout.channels();
+        let packets = unpack_streams(payload, self.streams.len())?;
+        let mut out = Vec::new();
+        let mut first_channel = 0;
+        for ((decoder, stream_channels), packet) in self.streams.iter_mut().zip(packets) {
+            let frames = decoder
+                .decode_float(packet, &mut self.scratch, false)
+                .map_err(|e| anyhow!("Opus decode failed: {}", e))?;
+            if out.is_empty() {
+                out.resize(frames * channels, 0.0);
+            }
+            let decoded = self.scratch.chunks_exact(*stream_channels).take(frames);
+            for (frame, samples) in out.chunks_exact_mut(channels).zip(decoded) {
+                frame[first_channel..first_channel + *stream_channels].copy_from_slice(samples);
+            }
+            first_channel += *stream_channels;
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_round_trip_and_stereo_stays_plain_opus() {
+        assert_eq!(stream_channels(6), vec![2, 2, 2]);
+        assert_eq!(stream_channels(3), vec![2, 1]);
+
+        let single = pack_streams(&[vec![1, 2, 3]]).unwrap();
+        assert_eq!(single, vec![1, 2, 3]);
+
+        let streams = vec![vec![1, 2], vec![], vec![3, 4, 5]];
+        let packed = pack_streams(&streams).unwrap();
+        let unpacked = unpack_streams(&packed, 3).unwrap();
+        assert_eq!(unpacked, vec![&[1u8, 2][..], &[][..], &[3u8, 4, 5][..]]);
+
+        assert!(unpack_streams(&packed[..3], 3).is_err());
+    }
+}
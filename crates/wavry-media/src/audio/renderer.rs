@@ -1,37 +1,92 @@
 use anyhow::{anyhow, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{BufferSize, SampleFormat, SampleRate, Stream, StreamConfig, SupportedBufferSize};
-#[cfg(feature = "opus-support")]
-use opus::{Channels, Decoder as OpusDecoder};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
 #[cfg(feature = "opus-support")]
-use super::OPUS_MAX_FRAME_SAMPLES;
-use super::{AUDIO_MAX_BUFFER_SAMPLES, OPUS_CHANNELS, OPUS_FRAME_SAMPLES, OPUS_SAMPLE_RATE};
+use super::codec::{AudioDecoder, OpusAudioDecoder};
+use super::layout::AudioChannelLayout;
+#[cfg(feature = "opus-support")]
+use super::multistream::MultichannelOpusDecoder;
+use super::spatial::{BinauralRenderer, HeadOrientation};
+use super::{
+    AUDIO_MAX_BUFFER_FRAMES, AUDIO_MAX_BUFFER_SAMPLES, OPUS_CHANNELS, OPUS_FRAME_SAMPLES,
+    OPUS_SAMPLE_RATE,
+};
 use crate::Renderer;
 
 pub struct CpalAudioRenderer {
     _stream: Stream,
     buffer: Arc<Mutex<VecDeque<f32>>>,
     #[cfg(feature = "opus-support")]
-    decoder: OpusDecoder,
-    channels: usize,
-    #[cfg(feature = "opus-support")]
-    decode_buf: Vec<i16>,
+    decoder: OpusAudioDecoder,
 }
 
 unsafe impl Send for CpalAudioRenderer {}
 
+/// Overrides which output device carries a client's microphone into the
+/// host, for loopback drivers not in [`VIRTUAL_MIC_OUTPUTS`].
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub const VIRTUAL_MIC_DEVICE_ENV: &str = "WAVRY_VIRTUAL_MIC_DEVICE";
+
+/// Playback ends of common loopback drivers. Audio written to them comes
+/// out of a paired recording device that host applications can pick as a
+/// microphone.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+const VIRTUAL_MIC_OUTPUTS: &[&str] = &["CABLE Input", "VB-Audio", "BlackHole", "Loopback"];
+
+/// Whether an output device named `name` feeds a virtual microphone.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn is_virtual_mic_output(name: &str, configured: Option<&str>) -> bool {
+    let name = name.to_ascii_lowercase();
+    match configured {
+        Some(configured) => name.contains(&configured.to_ascii_lowercase()),
+        None => VIRTUAL_MIC_OUTPUTS
+            .iter()
+            .any(|known| name.contains(&known.to_ascii_lowercase())),
+    }
+}
+
 impl CpalAudioRenderer {
     pub fn new() -> Result<Self> {
         let host = cpal::default_host();
         let device = host
             .default_output_device()
             .ok_or_else(|| anyhow!("No audio output device available"))?;
+        Self::with_device(&device)
+    }
 
-        let (config, sample_format) = select_output_config(&device)?;
-        let channels = config.channels as usize;
+    /// Plays into a loopback driver's output so the audio shows up as a
+    /// microphone on this machine. No driver is installed by Wavry; without
+    /// one this fails rather than playing the client's voice out loud.
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    pub fn new_virtual_microphone() -> Result<Self> {
+        let configured = std::env::var(VIRTUAL_MIC_DEVICE_ENV).ok();
+        let host = cpal::default_host();
+        let device = host
+            .output_devices()?
+            .find(|device| {
+                device
+                    .name()
+                    .map(|name| is_virtual_mic_output(&name, configured.as_deref()))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "no virtual microphone device found; install a loopback driver such as VB-CABLE or BlackHole, or set {}",
+                    VIRTUAL_MIC_DEVICE_ENV
+                )
+            })?;
+        log::info!(
+            "client microphone plays into {}",
+            device.name().unwrap_or_default()
+        );
+        Self::with_device(&device)
+    }
+
+    fn with_device(device: &cpal::Device) -> Result<Self> {
+        let (config, sample_format) = select_output_config(device)?;
 
         let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(
             AUDIO_MAX_BUFFER_SAMPLES,
@@ -43,72 +98,105 @@ impl CpalAudioRenderer {
         };
 
         let stream = match sample_format {
-            SampleFormat::F32 => build_stream_f32(&device, &config, buffer_clone, err_fn)?,
-            SampleFormat::I16 => build_stream_i16(&device, &config, buffer_clone, err_fn)?,
-            SampleFormat::U16 => build_stream_u16(&device, &config, buffer_clone, err_fn)?,
+            SampleFormat::F32 => build_stream_f32(device, &config, buffer_clone, err_fn)?,
+            SampleFormat::I16 => build_stream_i16(device, &config, buffer_clone, err_fn)?,
+            SampleFormat::U16 => build_stream_u16(device, &config, buffer_clone, err_fn)?,
             _ => return Err(anyhow!("Unsupported audio sample format")),
         };
 
         stream.play()?;
 
-        #[cfg(feature = "opus-support")]
-        {
-            let decoder = OpusDecoder::new(OPUS_SAMPLE_RATE, Channels::Stereo)
-                .map_err(|e| anyhow!("Opus decoder init failed: {}", e))?;
-
-            Ok(Self {
-                _stream: stream,
-                buffer,
-                decoder,
-                channels,
-                decode_buf: vec![0i16; OPUS_MAX_FRAME_SAMPLES * channels],
-            })
-        }
-        #[cfg(not(feature = "opus-support"))]
-        {
-            Ok(Self {
-                _stream: stream,
-                buffer,
-                channels,
-            })
-        }
+        Ok(Self {
+            _stream: stream,
+            buffer,
+            #[cfg(feature = "opus-support")]
+            decoder: OpusAudioDecoder::new(OPUS_CHANNELS)?,
+        })
     }
 
+    /// Decodes a packet for playback. The timestamp lets the decoder spot
+    /// lost packets and fill them in before this one.
     #[cfg(feature = "opus-support")]
-    pub fn push(&mut self, payload: &[u8]) -> Result<()> {
-        let decoded = self
-            .decoder
-            .decode(payload, &mut self.decode_buf, false)
-            .map_err(|e| anyhow!("Opus decode failed: {}", e))?;
-        let decoded_samples = decoded * self.channels;
-
-        let mut guard = match self.buffer.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
-        };
-
-        for sample in self.decode_buf.iter().take(decoded_samples) {
-            let sample = *sample as f32 / i16::MAX as f32;
-            guard.push_back(sample);
-        }
-
-        while guard.len() > AUDIO_MAX_BUFFER_SAMPLES {
-            guard.pop_front();
-        }
-
+    pub fn push(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        let decoded = self.decoder.decode(payload, timestamp_us)?;
+        push_samples(&self.buffer, &decoded);
         Ok(())
     }
 
     #[cfg(not(feature = "opus-support"))]
-    pub fn push(&mut self, _payload: &[u8]) -> Result<()> {
+    pub fn push(&mut self, _payload: &[u8], _timestamp_us: u64) -> Result<()> {
         // Opus disabled, no-op or implement alternate decoder
         Ok(())
     }
 }
 
 impl Renderer for CpalAudioRenderer {
-    fn render(&mut self, payload: &[u8], _timestamp_us: u64) -> Result<()> {
-        self.push(payload)
+    fn render(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        self.push(payload, timestamp_us)
+    }
+}
+
+/// Queues samples for the output stream, keeping at most a few packets'
+/// worth so latency stays bounded whatever frame size was negotiated.
+fn push_samples(buffer: &Mutex<VecDeque<f32>>, samples: &[f32]) {
+    let mut guard = match buffer.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    guard.extend(samples.iter().copied());
+    let limit = AUDIO_MAX_BUFFER_SAMPLES.max(samples.len() * AUDIO_MAX_BUFFER_FRAMES);
+    while guard.len() > limit {
+        guard.pop_front();
+    }
+}
+
+/// Head-tracked binaural playback of multi-channel and ambisonic streams.
+pub struct SpatialAudioRenderer {
+    output: CpalAudioRenderer,
+    head: HeadOrientation,
+    binaural: BinauralRenderer,
+    #[cfg(feature = "opus-support")]
+    decoder: MultichannelOpusDecoder,
+    mix: Vec<f32>,
+}
+
+unsafe impl Send for SpatialAudioRenderer {}
+
+impl SpatialAudioRenderer {
+    pub fn new(layout: AudioChannelLayout, head: HeadOrientation) -> Result<Self> {
+        Ok(Self {
+            output: CpalAudioRenderer::new()?,
+            head,
+            binaural: BinauralRenderer::new(layout, OPUS_SAMPLE_RATE),
+            #[cfg(feature = "opus-support")]
+            decoder: MultichannelOpusDecoder::new(layout)?,
+            mix: Vec::with_capacity(OPUS_FRAME_SAMPLES * OPUS_CHANNELS),
+        })
+    }
+
+    pub fn layout(&self) -> AudioChannelLayout {
+        self.binaural.layout()
+    }
+
+    /// A packet in another layout (a host falling back to stereo, say)
+    /// switches the decoder rather than failing.
+    #[cfg(feature = "opus-support")]
+    pub fn push(&mut self, payload: &[u8], layout: AudioChannelLayout) -> Result<()> {
+        if layout != self.decoder.layout() {
+            self.decoder = MultichannelOpusDecoder::new(layout)?;
+            self.binaural = BinauralRenderer::new(layout, OPUS_SAMPLE_RATE);
+        }
+        let decoded = self.decoder.decode(payload)?;
+        self.binaural.set_head_orientation(self.head.get());
+        self.mix.clear();
+        self.binaural.process(&decoded, &mut self.mix);
+        push_samples(&self.output.buffer, &self.mix);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "opus-support"))]
+    pub fn push(&mut self, _payload: &[u8], _layout: AudioChannelLayout) -> Result<()> {
+        Ok(())
     }
 }
 
@@ -225,3 +313,23 @@ fn f32_to_u16(value: f32) -> u16 {
     let scaled = value.clamp(-1.0, 1.0) * 0.5 + 0.5;
     (scaled * u16::MAX as f32) as u16
 }
+
+#[cfg(all(test, any(target_os = "windows", target_os = "macos")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_mic_output_matches_known_drivers() {
+        assert!(is_virtual_mic_output(
+            "CABLE Input (VB-Audio Virtual Cable)",
+            None
+        ));
+        assert!(is_virtual_mic_output("BlackHole 2ch", None));
+        assert!(!is_virtual_mic_output("Speakers (Realtek Audio)", None));
+        assert!(is_virtual_mic_output(
+            "Voicemeeter Input",
+            Some("voicemeeter")
+        ));
+        assert!(!is_virtual_mic_output("BlackHole 2ch", Some("Voicemeeter")));
+    }
+}
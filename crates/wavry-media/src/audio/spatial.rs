@@ -0,0 +1,348 @@
+//! Head-tracked binaural rendering of multi-channel and ambisonic audio.
+//!
+//! Each speaker channel (or each virtual speaker of an ambisonic decode) is
+//! a world-fixed source. Sources are turned into the listener's head frame
+//! and filtered with a spherical-head HRTF (Brown & Duda): an interaural
+//! delay plus a per-ear head-shadow shelf.
+
+use std::f32::consts::FRAC_PI_2;
+use std::sync::{Arc, Mutex};
+
+use super::layout::{direction_vector, AudioChannelLayout};
+
+const HEAD_RADIUS_M: f32 = 0.0875;
+const SPEED_OF_SOUND_MPS: f32 = 343.0;
+/// Covers the longest spherical-head delay (about 0.66 ms) at 96 kHz.
+const DELAY_LINE_LEN: usize = 128;
+/// Virtual speakers on the corners of a cube for the ambisonic decode.
+const FOA_DECODE_SPEAKERS: [(f32, f32); 8] = [
+    (45.0, 35.26),
+    (-45.0, 35.26),
+    (135.0, 35.26),
+    (-135.0, 35.26),
+    (45.0, -35.26),
+    (-45.0, -35.26),
+    (135.0, -35.26),
+    (-135.0, -35.26),
+];
+/// max-rE weighting of the first-order components.
+const FOA_DIRECTIONAL_GAIN: f32 = 1.732;
+const LFE_GAIN: f32 = 0.5;
+const EAR_AXES: [[f32; 3]; 2] = [[-1.0, 0.0, 0.0], [1.0, 0.0, 0.0]];
+
+/// Latest head orientation as an `[x, y, z, w]` quaternion, shared between
+/// the pose source and the audio path.
+#[derive(Debug, Clone)]
+pub struct HeadOrientation(Arc<Mutex<[f32; 4]>>);
+
+impl Default for HeadOrientation {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new([0.0, 0.0, 0.0, 1.0])))
+    }
+}
+
+impl HeadOrientation {
+    pub fn set(&self, orientation: [f32; 4]) {
+        if let Ok(mut guard) = self.0.lock() {
+            *guard = orientation;
+        }
+    }
+
+    pub fn get(&self) -> [f32; 4] {
+        self.0
+            .lock()
+            .map(|guard| *guard)
+            .unwrap_or([0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct EarFilter {
+    delay: f32,
+    b0: f32,
+    b1: f32,
+    a1: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl EarFilter {
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b1 * self.x1 - self.a1 * self.y1;
+        self.x1 = x;
+        self.y1 = y;
+        y
+    }
+}
+
+#[derive(Debug, Clone)]
+struct VirtualSource {
+    direction: [f32; 3],
+    delay_line: [f32; DELAY_LINE_LEN],
+    write_pos: usize,
+    ears: [EarFilter; 2],
+}
+
+impl VirtualSource {
+    fn new(direction: [f32; 3]) -> Self {
+        Self {
+            direction,
+            delay_line: [0.0; DELAY_LINE_LEN],
+            write_pos: 0,
+            ears: [EarFilter::default(); 2],
+        }
+    }
+}
+
+fn read_delayed(line: &[f32; DELAY_LINE_LEN], write_pos: usize, delay: f32) -> f32 {
+    let whole = delay.floor() as usize;
+    let frac = delay - whole as f32;
+    let at = |offset: usize| line[(write_pos + DELAY_LINE_LEN - offset) % DELAY_LINE_LEN];
+    at(whole) + (at(whole + 1) - at(whole)) * frac
+}
+
+/// Renders one layout's interleaved samples to binaural stereo.
+pub struct BinauralRenderer {
+    layout: AudioChannelLayout,
+    sample_rate: u32,
+    orientation: [f32; 4],
+    sources: Vec<VirtualSource>,
+    source_gain: f32,
+    source_input: Vec<f32>,
+}
+
+impl BinauralRenderer {
+    pub fn new(layout: AudioChannelLayout, sample_rate: u32) -> Self {
+        let directions: Vec<[f32; 3]> = if layout.is_ambisonic() {
+            FOA_DECODE_SPEAKERS
+                .iter()
+                .map(|speaker| direction_vector(*speaker))
+                .collect()
+        } else {
+            layout
+                .speakers()
+                .iter()
+                .flatten()
+                .map(|d| direction_vector(*d))
+                .collect()
+        };
+        // The ambisonic decode already splits the field across its speakers.
+        let source_gain = if layout.is_ambisonic() {
+            1.0
+        } else {
+            (2.0 / directions.len().max(2) as f32).sqrt()
+        };
+        let mut renderer = Self {
+            layout,
+            sample_rate: sample_rate.max(1),
+            orientation: [0.0, 0.0, 0.0, 1.0],
+            source_input: vec![0.0; directions.len()],
+            sources: directions.into_iter().map(VirtualSource::new).collect(),
+            source_gain,
+        };
+        let targets = renderer.ear_targets();
+        for (source, target) in renderer.sources.iter_mut().zip(targets) {
+            source.ears = target;
+        }
+        renderer
+    }
+
+    pub fn layout(&self) -> AudioChannelLayout {
+        self.layout
+    }
+
+    pub fn set_head_orientation(&mut self, orientation: [f32; 4]) {
+        let norm = orientation.iter().map(|c| c * c).sum::<f32>().sqrt();
+        if norm.is_finite() && norm > 1e-6 {
+            self.orientation = orientation.map(|c| c / norm);
+        }
+    }
+
+    /// Renders interleaved input in this layout, appending interleaved
+    /// stereo to `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        let channels = self.layout.channels();
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return;
+        }
+
+        // Delays glide to the new head pose across the block to avoid clicks.
+        let targets = self.ear_targets();
+        let mut delay_steps = Vec::with_capacity(self.sources.len());
+        for (source, target) in self.sources.iter_mut().zip(&targets) {
+            let mut steps = [0.0f32; 2];
+            for ((ear, target), step) in source.ears.iter_mut().zip(target).zip(&mut steps) {
+                *step = (target.delay - ear.delay) / frames as f32;
+                ear.b0 = target.b0;
+                ear.b1 = target.b1;
+                ear.a1 = target.a1;
+            }
+            delay_steps.push(steps);
+        }
+
+        let speakers = self.layout.speakers();
+        output.reserve(frames * 2);
+        for frame in input.chunks_exact(channels) {
+            let mut lfe = 0.0;
+            if self.layout.is_ambisonic() {
+                let (w, y, z, x) = (frame[0], frame[1], frame[2], frame[3]);
+                for (input, source) in self.source_input.iter_mut().zip(&self.sources) {
+                    let [dx, dy, dz] = source.direction;
+                    // OpenXR axes to ambisonic X forward, Y left, Z up.
+                    let directional = x * -dz + y * -dx + z * dy;
+                    *input =
+                        (w + FOA_DIRECTIONAL_GAIN * directional) / FOA_DECODE_SPEAKERS.len() as f32;
+                }
+            } else {
+                let mut next = 0;
+                for (sample, speaker) in frame.iter().zip(speakers) {
+                    if speaker.is_some() {
+                        self.source_input[next] = *sample;
+                        next += 1;
+                    } else {
+                        lfe += sample * LFE_GAIN;
+                    }
+                }
+            }
+
+            let mut out = [lfe; 2];
+            for ((source, input), steps) in self
+                .sources
+                .iter_mut()
+                .zip(&self.source_input)
+                .zip(&delay_steps)
+            {
+                source.write_pos = (source.write_pos + 1) % DELAY_LINE_LEN;
+                source.delay_line[source.write_pos] = *input * self.source_gain;
+                for ((ear, step), out) in source.ears.iter_mut().zip(steps).zip(&mut out) {
+                    ear.delay += step;
+                    let delayed = read_delayed(&source.delay_line, source.write_pos, ear.delay);
+                    *out += ear.process(delayed);
+                }
+            }
+            output.extend_from_slice(&out);
+        }
+    }
+
+    /// Delay and head-shadow coefficients of every source at the current
+    /// head orientation.
+    fn ear_targets(&self) -> Vec<[EarFilter; 2]> {
+        let inverse = [
+            -self.orientation[0],
+            -self.orientation[1],
+            -self.orientation[2],
+            self.orientation[3],
+        ];
+        let head_time = HEAD_RADIUS_M / SPEED_OF_SOUND_MPS;
+        let omega0 = SPEED_OF_SOUND_MPS / HEAD_RADIUS_M;
+        let k = self.sample_rate as f32 / omega0;
+        let max_delay = (DELAY_LINE_LEN - 2) as f32;
+
+        self.sources
+            .iter()
+            .map(|source| {
+                let local = rotate(inverse, source.direction);
+                EAR_AXES.map(|axis| {
+                    let cos_theta = (local[0] * axis[0] + local[1] * axis[1] + local[2] * axis[2])
+                        .clamp(-1.0, 1.0);
+                    let theta = cos_theta.acos();
+                    // Path length around the head, relative to its far side.
+                    let travel = if theta < FRAC_PI_2 {
+                        1.0 - cos_theta
+                    } else {
+                        1.0 + theta - FRAC_PI_2
+                    };
+                    let delay = (travel * head_time * self.sample_rate as f32).min(max_delay);
+                    // Head shadow: +6 dB treble facing the source, down to -20 dB behind.
+                    let alpha = 1.05 + 0.95 * (theta * 1.2).cos();
+                    EarFilter {
+                        delay,
+                        b0: (1.0 + alpha * k) / (1.0 + k),
+                        b1: (1.0 - alpha * k) / (1.0 + k),
+                        a1: (1.0 - k) / (1.0 + k),
+                        ..EarFilter::default()
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+fn rotate(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let u = [q[0], q[1], q[2]];
+    let s = q[3];
+    let dot = u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+    let uu = u[0] * u[0] + u[1] * u[1] + u[2] * u[2];
+    let cross = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    [0, 1, 2].map(|i| 2.0 * dot * u[i] + (s * s - uu) * v[i] + 2.0 * s * cross[i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_peak(output: &[f32], ear: usize) -> usize {
+        output
+            .chunks_exact(2)
+            .position(|frame| frame[ear].abs() > 0.05)
+            .expect("impulse never reached the ear")
+    }
+
+    fn impulse_on(layout: AudioChannelLayout, channel: usize) -> Vec<f32> {
+        let mut input = vec![0.0; layout.channels() * 64];
+        input[channel] = 1.0;
+        input
+    }
+
+    #[test]
+    fn turning_the_head_moves_sources_between_ears() {
+        let mut renderer = BinauralRenderer::new(AudioChannelLayout::Stereo, 48_000);
+        let mut output = Vec::new();
+        renderer.process(&impulse_on(AudioChannelLayout::Stereo, 0), &mut output);
+        // Front-left speaker reaches the left ear first.
+        assert!(first_peak(&output, 0) < first_peak(&output, 1));
+
+        // Turning 60 degrees left puts that speaker to the listener's right.
+        let half = 30f32.to_radians();
+        let mut renderer = BinauralRenderer::new(AudioChannelLayout::Stereo, 48_000);
+        renderer.set_head_orientation([0.0, half.sin(), 0.0, half.cos()]);
+        let mut gliding = Vec::new();
+        renderer.process(&impulse_on(AudioChannelLayout::Stereo, 0), &mut gliding);
+        let mut settled = Vec::new();
+        renderer.process(&impulse_on(AudioChannelLayout::Stereo, 0), &mut settled);
+        assert!(first_peak(&settled, 1) < first_peak(&settled, 0));
+    }
+
+    #[test]
+    fn ambisonic_field_follows_the_head() {
+        // Side-left speaker of a 7.1 mix, straight to the listener's left.
+        let mut speakers = [0.0; 8];
+        speakers[6] = 1.0;
+        let foa = crate::audio::layout::encode_foa(AudioChannelLayout::Surround71, &speakers);
+        assert!((foa[0] - 1.0).abs() < 1e-5 && (foa[1] - 1.0).abs() < 1e-5);
+        assert!(foa[3].abs() < 1e-5);
+
+        let mut input = vec![0.0; 4 * 64];
+        input[..4].copy_from_slice(&foa);
+        let mut renderer = BinauralRenderer::new(AudioChannelLayout::AmbisonicFoa, 48_000);
+        let mut output = Vec::new();
+        renderer.process(&input, &mut output);
+        let energy = |ear: usize| output.chunks_exact(2).map(|f| f[ear] * f[ear]).sum::<f32>();
+        assert!(energy(0) > energy(1) * 2.0);
+
+        // Facing the source, both ears hear it equally.
+        let half = 45f32.to_radians();
+        renderer.set_head_orientation([0.0, half.sin(), 0.0, half.cos()]);
+        let mut turned = Vec::new();
+        renderer.process(&[0.0; 4 * 64], &mut turned);
+        turned.clear();
+        renderer.process(&input, &mut turned);
+        let energy = |ear: usize| turned.chunks_exact(2).map(|f| f[ear] * f[ear]).sum::<f32>();
+        assert!((energy(0) - energy(1)).abs() < energy(0) * 0.05);
+    }
+}
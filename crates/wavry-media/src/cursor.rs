@@ -0,0 +1,105 @@
+//! Host pointer state, drawn by the client on top of decoded video.
+//!
+//! When the cursor channel is on, hosts leave the pointer out of captured
+//! frames and send it separately, so it tracks the mouse at input latency
+//! instead of frame latency.
+
+/// Cursor shapes the client can draw with its own themed image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemCursor {
+    Arrow,
+    IBeam,
+    Wait,
+    Crosshair,
+    Hand,
+    ResizeEw,
+    ResizeNs,
+    ResizeNwse,
+    ResizeNesw,
+    Move,
+    NotAllowed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorShape {
+    System(SystemCursor),
+    Bitmap {
+        width: u32,
+        height: u32,
+        hotspot_x: u32,
+        hotspot_y: u32,
+        /// Premultiplied-alpha BGRA, `width * height * 4` bytes.
+        bgra: Vec<u8>,
+    },
+}
+
+impl CursorShape {
+    /// A bitmap for renderers without their own cursor theme: the shape
+    /// itself, or a plain arrow standing in for any system cursor.
+    pub fn to_bitmap(&self) -> CursorShape {
+        match self {
+            CursorShape::Bitmap { .. } => self.clone(),
+            CursorShape::System(_) => arrow_bitmap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorState {
+    /// Hotspot position, normalized 0..1 to the captured display.
+    pub x: f32,
+    pub y: f32,
+    pub visible: bool,
+    /// Changes whenever `shape` does.
+    pub serial: u64,
+    pub shape: CursorShape,
+}
+
+const ARROW_WIDTH: u32 = 12;
+const ARROW_HEIGHT: u32 = 19;
+
+/// Black-outlined white arrow with its hotspot at the tip.
+fn arrow_bitmap() -> CursorShape {
+    let mut bgra = vec![0u8; (ARROW_WIDTH * ARROW_HEIGHT * 4) as usize];
+    for y in 0..ARROW_HEIGHT {
+        // The diagonal edge widens one pixel per row; the tail narrows back in.
+        let edge = if y < 12 { y } else { 24 - y };
+        for x in 0..=edge.min(ARROW_WIDTH - 1) {
+            let outline = x == 0 || x == edge || y == ARROW_HEIGHT - 1;
+            let value = if outline { 0 } else { 255 };
+            let offset = ((y * ARROW_WIDTH + x) * 4) as usize;
+            bgra[offset..offset + 4].copy_from_slice(&[value, value, value, 255]);
+        }
+    }
+    CursorShape::Bitmap {
+        width: ARROW_WIDTH,
+        height: ARROW_HEIGHT,
+        hotspot_x: 0,
+        hotspot_y: 0,
+        bgra,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_cursors_fall_back_to_an_opaque_arrow_tip() {
+        let CursorShape::Bitmap {
+            width,
+            height,
+            hotspot_x,
+            hotspot_y,
+            bgra,
+        } = CursorShape::System(SystemCursor::IBeam).to_bitmap()
+        else {
+            panic!("expected a bitmap");
+        };
+        assert_eq!(bgra.len(), (width * height * 4) as usize);
+        assert_eq!((hotspot_x, hotspot_y), (0, 0));
+        assert_eq!(bgra[3], 255);
+        // Right of the diagonal stays transparent.
+        assert_eq!(bgra[(width as usize - 1) * 4 + 3], 0);
+    }
+}
@@ -0,0 +1,178 @@
+//! Damage-driven capture: encode a frame only when the screen changed, and
+//! fall back to a slow keepalive while it sits idle.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How often an idle screen is still encoded in damage mode, so late joiners
+/// and loss recovery always have a recent frame to work from.
+pub const DAMAGE_KEEPALIVE_INTERVAL: Duration = Duration::from_millis(500);
+/// Window the encoded frame rate is measured over.
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// When the capturer hands frames to the encoder.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMode {
+    /// Every frame the capturer produces, up to the configured rate.
+    #[default]
+    Fixed,
+    /// Only frames whose content changed, plus a keepalive while idle.
+    Damage,
+}
+
+impl FromStr for CaptureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Self::Fixed),
+            "damage" | "vrr" => Ok(Self::Damage),
+            other => Err(format!(
+                "unknown capture mode '{}', expected fixed or damage",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CaptureMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fixed => "fixed",
+            Self::Damage => "damage",
+        })
+    }
+}
+
+/// Decides which captured frames reach the encoder.
+#[derive(Debug, Clone)]
+pub struct DamageGate {
+    mode: CaptureMode,
+    keepalive: Duration,
+    last_encoded: Option<Instant>,
+    skipped: u64,
+}
+
+impl DamageGate {
+    pub fn new(mode: CaptureMode) -> Self {
+        Self {
+            mode,
+            keepalive: DAMAGE_KEEPALIVE_INTERVAL,
+            last_encoded: None,
+            skipped: 0,
+        }
+    }
+
+    pub fn mode(&self) -> CaptureMode {
+        self.mode
+    }
+
+    /// Whether to encode a frame captured at `now`. `changed` is false when
+    /// the capturer knows the content matches the previous frame.
+    pub fn admit(&mut self, changed: bool, now: Instant) -> bool {
+        let admit = self.mode == CaptureMode::Fixed || changed || self.keepalive_due(now);
+        if admit {
+            self.last_encoded = Some(now);
+        } else {
+            self.skipped += 1;
+        }
+        admit
+    }
+
+    /// Whether an idle screen is owed a repeat of its last frame.
+    pub fn keepalive_due(&self, now: Instant) -> bool {
+        self.last_encoded
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.keepalive)
+    }
+
+    /// Frames left out because nothing changed.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+/// Counts encoded frames over a sliding one-second window.
+#[derive(Debug, Clone, Default)]
+pub struct FrameRateMeter {
+    window_start: Option<Instant>,
+    in_window: u32,
+    last_rate: u32,
+}
+
+impl FrameRateMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, now: Instant) {
+        self.roll(now);
+        self.in_window += 1;
+    }
+
+    /// Frames over the last full window. Drops to zero once frames stop.
+    pub fn fps(&mut self, now: Instant) -> u16 {
+        self.roll(now);
+        self.last_rate.min(u32::from(u16::MAX)) as u16
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let start = *self.window_start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed < FPS_WINDOW {
+            return;
+        }
+        // A window with no frames after the last full one reads as idle.
+        self.last_rate = if elapsed < FPS_WINDOW * 2 {
+            self.in_window
+        } else {
+            0
+        };
+        self.in_window = 0;
+        self.window_start = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_screens_drop_to_the_keepalive_rate() {
+        let start = Instant::now();
+        let mut gate = DamageGate::new(CaptureMode::Damage);
+        let admitted = (0..60)
+            .filter(|frame| {
+                let now = start + Duration::from_millis(frame * 1000 / 60);
+                gate.admit(*frame == 0, now)
+            })
+            .count();
+        assert_eq!(admitted, 2);
+        assert_eq!(gate.skipped(), 58);
+        assert!(gate.admit(true, start + Duration::from_millis(1010)));
+
+        let mut fixed = DamageGate::new(CaptureMode::Fixed);
+        assert!(fixed.admit(false, start));
+        assert!(fixed.admit(false, start));
+        assert_eq!(fixed.skipped(), 0);
+    }
+
+    #[test]
+    fn frame_rate_follows_the_last_full_window() {
+        let start = Instant::now();
+        let mut meter = FrameRateMeter::new();
+        for frame in 0..60u64 {
+            meter.record(start + Duration::from_micros(frame * 16_666));
+        }
+        assert_eq!(meter.fps(start + Duration::from_millis(999)), 0);
+        for frame in 0..2u64 {
+            meter.record(start + Duration::from_millis(1_000 + frame * 500));
+        }
+        assert_eq!(meter.fps(start + Duration::from_millis(1_500)), 60);
+        assert_eq!(meter.fps(start + Duration::from_millis(2_100)), 2);
+        assert_eq!(meter.fps(start + Duration::from_millis(5_000)), 0);
+    }
+}
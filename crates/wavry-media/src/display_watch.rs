@@ -0,0 +1,378 @@
+//! Notifications for displays being plugged in, removed or reconfigured.
+//!
+//! Each platform reports changes its own way: DRM connector status on Linux
+//! (which covers both X11 and the PipeWire-backed Wayland capture), display
+//! reconfiguration callbacks on macOS and `WM_DISPLAYCHANGE` on Windows. All
+//! of them land in a [`DisplayWatch`], which folds a burst of reports into a
+//! single change.
+
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::DisplayInfo;
+
+/// Quiet period that ends a burst of change reports. Plugging in a display
+/// usually reports its connector, then its mode, then the new arrangement.
+pub const DISPLAY_SETTLE: Duration = Duration::from_millis(750);
+
+/// Receives display configuration changes until dropped.
+pub struct DisplayWatch {
+    changes: mpsc::UnboundedReceiver<()>,
+    _source: Box<dyn Send>,
+}
+
+impl DisplayWatch {
+    #[cfg_attr(
+        not(any(target_os = "linux", target_os = "macos", target_os = "windows")),
+        allow(dead_code)
+    )]
+    fn new(changes: mpsc::UnboundedReceiver<()>, source: impl Send + 'static) -> Self {
+        Self {
+            changes,
+            _source: Box::new(source),
+        }
+    }
+
+    /// Resolves once the display layout changed and has been quiet for
+    /// [`DISPLAY_SETTLE`]. Returns `None` if the platform source stopped.
+    pub async fn changed(&mut self) -> Option<()> {
+        self.changes.recv().await?;
+        loop {
+            match time::timeout(DISPLAY_SETTLE, self.changes.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) | Err(_) => return Some(()),
+            }
+        }
+    }
+}
+
+/// Display a host should move capture to when `current` is no longer
+/// attached; `None` while it still is.
+pub fn replacement_display(displays: &[DisplayInfo], current: u32) -> Option<&DisplayInfo> {
+    if displays.iter().any(|d| d.id == current) {
+        return None;
+    }
+    displays.first()
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::watch;
+#[cfg(target_os = "macos")]
+pub(crate) use macos::watch;
+#[cfg(target_os = "windows")]
+pub(crate) use win32::watch;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Result};
+    use tokio::sync::mpsc;
+
+    use super::DisplayWatch;
+
+    const DRM_CLASS: &str = "/sys/class/drm";
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    struct StopOnDrop(Arc<AtomicBool>);
+
+    impl Drop for StopOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Status of every connector under `root`, e.g. `("card0-HDMI-A-1",
+    /// "connected")`, sorted by name.
+    pub(super) fn connector_states(root: &Path) -> Vec<(String, String)> {
+        let Ok(entries) = std::fs::read_dir(root) else {
+            return Vec::new();
+        };
+        let mut states: Vec<(String, String)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let status = std::fs::read_to_string(entry.path().join("status")).ok()?;
+                Some((
+                    entry.file_name().to_string_lossy().into_owned(),
+                    status.trim().to_string(),
+                ))
+            })
+            .collect();
+        states.sort();
+        states
+    }
+
+    /// Polls DRM connector status. Compositors re-announce PipeWire monitor
+    /// nodes after the same hot-plug, so one source serves X11 and Wayland.
+    pub(crate) fn watch() -> Result<DisplayWatch> {
+        let root = Path::new(DRM_CLASS);
+        let mut last = connector_states(root);
+        if last.is_empty() {
+            return Err(anyhow!("no DRM connectors under {}", DRM_CLASS));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::Builder::new()
+            .name("wavry-display-watch".into())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(POLL_INTERVAL);
+                    let states = connector_states(root);
+                    if states != last {
+                        log::info!("DRM connector status changed");
+                        last = states;
+                        if tx.send(()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            })?;
+        Ok(DisplayWatch::new(rx, StopOnDrop(stop)))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use anyhow::{anyhow, Result};
+    use tokio::sync::mpsc;
+
+    use super::DisplayWatch;
+
+    type ReconfigurationCallback =
+        unsafe extern "C" fn(display: u32, flags: u32, info: *mut c_void);
+
+    /// `kCGDisplayBeginConfigurationFlag`: a change is about to start.
+    const BEGIN_CONFIGURATION_FLAG: u32 = 1 << 0;
+    /// `kCFRunLoopRunFinished`: the run loop has no sources to wait on.
+    const RUN_LOOP_FINISHED: i32 = 1;
+
+    extern "C" {
+        fn CGDisplayRegisterReconfigurationCallback(
+            callback: ReconfigurationCallback,
+            info: *mut c_void,
+        ) -> i32;
+        fn CGDisplayRemoveReconfigurationCallback(
+            callback: ReconfigurationCallback,
+            info: *mut c_void,
+        ) -> i32;
+        static kCFRunLoopDefaultMode: *const c_void;
+        fn CFRunLoopRunInMode(mode: *const c_void, seconds: f64, return_after_source: u8) -> i32;
+    }
+
+    unsafe extern "C" fn on_reconfiguration(_display: u32, flags: u32, info: *mut c_void) {
+        if flags & BEGIN_CONFIGURATION_FLAG != 0 {
+            return;
+        }
+        let tx = &*(info as *const mpsc::UnboundedSender<()>);
+        let _ = tx.send(());
+    }
+
+    struct StopOnDrop(Arc<AtomicBool>);
+
+    impl Drop for StopOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Registers a display reconfiguration callback on a thread that keeps
+    /// its own run loop turning, since a headless host may never run the
+    /// main one.
+    pub(crate) fn watch() -> Result<DisplayWatch> {
+        let (tx, rx) = mpsc::unbounded_channel::<()>();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<()>>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::Builder::new()
+            .name("wavry-display-watch".into())
+            .spawn(move || unsafe {
+                let info = Box::into_raw(Box::new(tx)) as *mut c_void;
+                if CGDisplayRegisterReconfigurationCallback(on_reconfiguration, info) != 0 {
+                    drop(Box::from_raw(info as *mut mpsc::UnboundedSender<()>));
+                    let _ = ready_tx.send(Err(anyhow!(
+                        "CGDisplayRegisterReconfigurationCallback failed"
+                    )));
+                    return;
+                }
+                let _ = ready_tx.send(Ok(()));
+                while !thread_stop.load(Ordering::Relaxed) {
+                    if CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.5, 0) == RUN_LOOP_FINISHED {
+                        std::thread::sleep(std::time::Duration::from_millis(500));
+                    }
+                }
+                CGDisplayRemoveReconfigurationCallback(on_reconfiguration, info);
+                drop(Box::from_raw(info as *mut mpsc::UnboundedSender<()>));
+            })?;
+        ready_rx
+            .recv()
+            .map_err(|_| anyhow!("display watch thread exited"))??;
+        Ok(DisplayWatch::new(rx, StopOnDrop(stop)))
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use anyhow::{anyhow, Result};
+    use tokio::sync::mpsc;
+    use windows::core::w;
+    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, GetWindowLongPtrW,
+        PostMessageW, PostQuitMessage, RegisterClassW, SetWindowLongPtrW, GWLP_USERDATA, MSG,
+        WINDOW_EX_STYLE, WM_CLOSE, WM_DESTROY, WM_DISPLAYCHANGE, WNDCLASSW, WS_OVERLAPPED,
+    };
+
+    use super::DisplayWatch;
+
+    /// Hidden top-level window; message-only windows don't receive the
+    /// `WM_DISPLAYCHANGE` broadcast.
+    struct Window(isize);
+
+    impl Drop for Window {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = PostMessageW(Some(HWND(self.0 as *mut _)), WM_CLOSE, WPARAM(0), LPARAM(0));
+            }
+        }
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        let tx = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut mpsc::UnboundedSender<()>;
+        match msg {
+            WM_DISPLAYCHANGE if !tx.is_null() => {
+                let _ = (*tx).send(());
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                if !tx.is_null() {
+                    SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+                    drop(Box::from_raw(tx));
+                }
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+    }
+
+    pub(crate) fn watch() -> Result<DisplayWatch> {
+        let (tx, rx) = mpsc::unbounded_channel::<()>();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<isize>>();
+        std::thread::Builder::new()
+            .name("wavry-display-watch".into())
+            .spawn(move || unsafe {
+                let created = (|| -> Result<HWND> {
+                    let instance = GetModuleHandleW(None)?;
+                    let class = WNDCLASSW {
+                        lpfnWndProc: Some(window_proc),
+                        hInstance: instance.into(),
+                        lpszClassName: w!("WavryDisplayWatch"),
+                        ..Default::default()
+                    };
+                    // Fails harmlessly when a previous watch registered it.
+                    RegisterClassW(&class);
+                    Ok(CreateWindowExW(
+                        WINDOW_EX_STYLE::default(),
+                        w!("WavryDisplayWatch"),
+                        w!("Wavry display watch"),
+                        WS_OVERLAPPED,
+                        0,
+                        0,
+                        0,
+                        0,
+                        None,
+                        None,
+                        Some(instance.into()),
+                        None,
+                    )?)
+                })();
+                let hwnd = match created {
+                    Ok(hwnd) => hwnd,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(err));
+                        return;
+                    }
+                };
+                SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(Box::new(tx)) as isize);
+                let _ = ready_tx.send(Ok(hwnd.0 as isize));
+
+                let mut msg = MSG::default();
+                while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                    DispatchMessageW(&msg);
+                }
+            })?;
+        let hwnd = ready_rx
+            .recv()
+            .map_err(|_| anyhow!("display watch thread exited"))??;
+        Ok(DisplayWatch::new(rx, Window(hwnd)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(id: u32) -> DisplayInfo {
+        DisplayInfo {
+            id,
+            name: format!("Display {id}"),
+            resolution: crate::Resolution {
+                width: 1920,
+                height: 1080,
+            },
+        }
+    }
+
+    #[test]
+    fn replacement_display_keeps_attached_display() {
+        let displays = vec![display(0), display(1)];
+        assert!(replacement_display(&displays, 1).is_none());
+    }
+
+    #[test]
+    fn replacement_display_falls_back_to_first() {
+        let displays = vec![display(3), display(4)];
+        assert_eq!(replacement_display(&displays, 1).map(|d| d.id), Some(3));
+        assert!(replacement_display(&[], 1).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn connector_states_read_status_files_in_name_order() {
+        let root = std::env::temp_dir().join(format!("wavry-drm-{}", std::process::id()));
+        for (name, status) in [
+            ("card0-HDMI-A-1", "disconnected\n"),
+            ("card0-DP-1", "connected\n"),
+        ] {
+            std::fs::create_dir_all(root.join(name)).unwrap();
+            std::fs::write(root.join(name).join("status"), status).unwrap();
+        }
+        std::fs::create_dir_all(root.join("version")).unwrap();
+
+        assert_eq!(
+            linux::connector_states(&root),
+            vec![
+                ("card0-DP-1".to_string(), "connected".to_string()),
+                ("card0-HDMI-A-1".to_string(), "disconnected".to_string()),
+            ]
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{DecodeConfig, EncodeConfig, EncodedFrame};
+use crate::{DecodeConfig, EncodeConfig, EncodedFrame, EncoderStats, QpOffsetMap};
 use anyhow::Result;
 use std::time::{Duration, Instant};
 
@@ -6,6 +6,7 @@ pub struct DummyEncoder {
     start: Instant,
     seq: u64,
     fps: u16,
+    stats: EncoderStats,
 }
 
 impl DummyEncoder {
@@ -14,6 +15,7 @@ impl DummyEncoder {
             start: Instant::now(),
             seq: 0,
             fps: config.fps,
+            stats: EncoderStats::default(),
         })
     }
 
@@ -28,6 +30,7 @@ impl DummyEncoder {
 
         let timestamp_us = self.start.elapsed().as_micros() as u64;
         self.seq += 1;
+        self.stats.cpu_frames += 1;
 
         Ok(EncodedFrame {
             timestamp_us,
@@ -37,6 +40,18 @@ impl DummyEncoder {
             encode_duration_us: 0,
         })
     }
+
+    pub fn set_bitrate(&mut self, _bitrate_kbps: u32) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn set_qp_offset_map(&mut self, _map: Option<&QpOffsetMap>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn stats(&self) -> EncoderStats {
+        self.stats
+    }
 }
 
 pub struct DummyRenderer;
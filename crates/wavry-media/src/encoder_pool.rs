@@ -222,6 +222,61 @@ pub enum MemoryPressure {
     Low,      // <50% of max
 }
 
+/// Ring of GPU textures handed to a hardware encoder without a CPU copy.
+///
+/// Each slot records the fence value it was submitted with and is only
+/// handed out again once the encoder has completed that value, so a texture
+/// the encoder still reads is never overwritten.
+#[derive(Debug)]
+pub struct SharedTextureRing {
+    pending: Vec<Option<u64>>,
+    next: usize,
+}
+
+impl SharedTextureRing {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            pending: vec![None; slots.max(1)],
+            next: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Next free slot in ring order, releasing every slot whose fence value
+    /// is at or below `completed_fence`. `None` while all slots are in flight.
+    pub fn acquire(&mut self, completed_fence: u64) -> Option<usize> {
+        for slot in &mut self.pending {
+            if slot.is_some_and(|fence| fence <= completed_fence) {
+                *slot = None;
+            }
+        }
+        let slots = self.pending.len();
+        let index = (0..slots)
+            .map(|offset| (self.next + offset) % slots)
+            .find(|&index| self.pending[index].is_none())?;
+        self.next = (index + 1) % slots;
+        Some(index)
+    }
+
+    /// Marks `slot` busy until `fence_value` completes. Resubmitting a slot
+    /// keeps it busy until the later value.
+    pub fn submit(&mut self, slot: usize, fence_value: u64) {
+        let pending = &mut self.pending[slot];
+        *pending = Some(pending.map_or(fence_value, |fence| fence.max(fence_value)));
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.pending.iter().filter(|slot| slot.is_some()).count()
+    }
+}
+
 /// Pool of reusable encoders for adaptive bitrate scenarios.
 pub struct EncoderPool {
     config: EncoderPoolConfig,
@@ -593,4 +648,34 @@ mod tests {
         assert_eq!(stats.total_created, 1);
         assert_eq!(stats.reuses, 1);
     }
+
+    #[test]
+    fn shared_textures_are_reused_only_after_their_fence() {
+        let mut ring = SharedTextureRing::new(3);
+        let first = ring.acquire(0).unwrap();
+        ring.submit(first, 1);
+        let second = ring.acquire(0).unwrap();
+        ring.submit(second, 2);
+        let third = ring.acquire(0).unwrap();
+        ring.submit(third, 3);
+        assert_eq!((first, second, third), (0, 1, 2));
+        assert_eq!(ring.in_flight(), 3);
+        assert_eq!(ring.acquire(0), None);
+
+        assert_eq!(ring.acquire(1), Some(0));
+        ring.submit(0, 4);
+        // Slots come back in ring order even when several complete at once.
+        assert_eq!(ring.acquire(3), Some(1));
+        assert_eq!(ring.in_flight(), 1);
+    }
+
+    #[test]
+    fn resubmitted_texture_waits_for_the_later_fence() {
+        let mut ring = SharedTextureRing::new(1);
+        let slot = ring.acquire(0).unwrap();
+        ring.submit(slot, 5);
+        ring.submit(slot, 6);
+        assert_eq!(ring.acquire(5), None);
+        assert_eq!(ring.acquire(6), Some(slot));
+    }
 }
@@ -0,0 +1,209 @@
+//! Gaze-driven QP offset maps for foveated encoding.
+
+use crate::Resolution;
+
+/// Edge length of one map block in pixels (a CTU for HEVC/AV1, 4x4 H.264 MBs).
+pub const QP_MAP_BLOCK_SIZE: u32 = 64;
+/// Outer edge of the transition ring, as a multiple of the foveation radius.
+const TRANSITION_RING_SCALE: f32 = 2.0;
+const TRANSITION_QP_OFFSET: i8 = 4;
+const PERIPHERY_QP_OFFSET: i8 = 10;
+
+/// Where the viewer is looking, in normalized per-eye image coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoveationParams {
+    pub gaze_x: f32,
+    pub gaze_y: f32,
+    /// Full-quality radius as a fraction of the eye image width.
+    pub radius: f32,
+    /// The frame packs both eyes side by side; the gaze applies to each half.
+    pub side_by_side: bool,
+}
+
+/// Rectangle with a uniform QP offset, for ROI-based encoder APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QpRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub qp_offset: i8,
+}
+
+/// Per-block QP deltas relative to the rate controller's choice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QpOffsetMap {
+    pub width: u32,
+    pub height: u32,
+    pub block_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// Row-major, `columns * rows` entries.
+    pub offsets: Vec<i8>,
+}
+
+impl QpOffsetMap {
+    /// Full quality around the gaze, a transition ring, then a coarse periphery.
+    pub fn foveated(resolution: Resolution, params: &FoveationParams) -> Self {
+        let width = resolution.width as u32;
+        let height = resolution.height as u32;
+        let block_size = QP_MAP_BLOCK_SIZE;
+        let columns = width.div_ceil(block_size).max(1);
+        let rows = height.div_ceil(block_size).max(1);
+        let eye_width = if params.side_by_side {
+            (width / 2).max(1)
+        } else {
+            width.max(1)
+        } as f32;
+        let radius = params.radius.max(f32::EPSILON);
+
+        let mut offsets = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            let center_y = ((row * block_size + block_size / 2).min(height)) as f32;
+            // Distances are measured in eye widths so the fovea stays circular.
+            let dy = (center_y / eye_width) - params.gaze_y * height as f32 / eye_width;
+            for column in 0..columns {
+                let center_x = ((column * block_size + block_size / 2).min(width)) as f32;
+                let local_x = if params.side_by_side && center_x >= eye_width {
+                    center_x - eye_width
+                } else {
+                    center_x
+                };
+                let dx = local_x / eye_width - params.gaze_x;
+                let distance = (dx * dx + dy * dy).sqrt();
+                offsets.push(if distance <= radius {
+                    0
+                } else if distance <= radius * TRANSITION_RING_SCALE {
+                    TRANSITION_QP_OFFSET
+                } else {
+                    PERIPHERY_QP_OFFSET
+                });
+            }
+        }
+
+        Self {
+            width,
+            height,
+            block_size,
+            columns,
+            rows,
+            offsets,
+        }
+    }
+
+    pub fn offset_at(&self, column: u32, row: u32) -> Option<i8> {
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        self.offsets
+            .get((row * self.columns + column) as usize)
+            .copied()
+    }
+
+    /// Bounding rectangles per offset level, sharpest first so it takes
+    /// precedence where rectangles overlap. Levels split into separate
+    /// rectangles across columns where they are absent (e.g. between eyes).
+    pub fn regions(&self) -> Vec<QpRegion> {
+        let mut levels: Vec<i8> = self.offsets.clone();
+        levels.sort_unstable();
+        levels.dedup();
+
+        let mut regions = Vec::new();
+        for level in levels {
+            let mut span: Option<(u32, u32, u32, u32)> = None;
+            for column in 0..=self.columns {
+                let rows_with_level = (column < self.columns)
+                    .then(|| {
+                        let mut hits = (0..self.rows)
+                            .filter(|&row| self.offset_at(column, row) == Some(level));
+                        let first = hits.next()?;
+                        Some((first, hits.last().unwrap_or(first)))
+                    })
+                    .flatten();
+                match (rows_with_level, span.as_mut()) {
+                    (Some((top, bottom)), Some((_, end, span_top, span_bottom))) => {
+                        *end = column;
+                        *span_top = (*span_top).min(top);
+                        *span_bottom = (*span_bottom).max(bottom);
+                    }
+                    (Some((top, bottom)), None) => span = Some((column, column, top, bottom)),
+                    (None, _) => {
+                        if let Some((start, end, top, bottom)) = span.take() {
+                            regions.push(self.region(start, end, top, bottom, level));
+                        }
+                    }
+                }
+            }
+        }
+        regions
+    }
+
+    fn region(&self, start: u32, end: u32, top: u32, bottom: u32, qp_offset: i8) -> QpRegion {
+        let x = start * self.block_size;
+        let y = top * self.block_size;
+        QpRegion {
+            x,
+            y,
+            width: ((end + 1) * self.block_size).min(self.width) - x,
+            height: ((bottom + 1) * self.block_size).min(self.height) - y,
+            qp_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES_1080P: Resolution = Resolution {
+        width: 1920,
+        height: 1080,
+    };
+
+    #[test]
+    fn gaze_block_is_sharp_and_periphery_is_coarse() {
+        let map = QpOffsetMap::foveated(
+            RES_1080P,
+            &FoveationParams {
+                gaze_x: 0.5,
+                gaze_y: 0.5,
+                radius: 0.1,
+                side_by_side: false,
+            },
+        );
+        assert_eq!((map.columns, map.rows), (30, 17));
+        assert_eq!(map.offset_at(15, 8), Some(0));
+        assert_eq!(map.offset_at(0, 0), Some(PERIPHERY_QP_OFFSET));
+        assert_eq!(map.offset_at(30, 0), None);
+
+        let regions = map.regions();
+        assert_eq!(regions[0].qp_offset, 0);
+        let background = regions.last().unwrap();
+        assert_eq!(background.qp_offset, PERIPHERY_QP_OFFSET);
+        assert_eq!((background.width, background.height), (1920, 1080));
+    }
+
+    #[test]
+    fn side_by_side_frames_get_a_fovea_per_eye() {
+        let map = QpOffsetMap::foveated(
+            Resolution {
+                width: 3840,
+                height: 1920,
+            },
+            &FoveationParams {
+                gaze_x: 0.5,
+                gaze_y: 0.5,
+                radius: 0.05,
+                side_by_side: true,
+            },
+        );
+        let foveae: Vec<_> = map
+            .regions()
+            .into_iter()
+            .filter(|region| region.qp_offset == 0)
+            .collect();
+        assert_eq!(foveae.len(), 2);
+        assert!(foveae[0].x + foveae[0].width <= 1920);
+        assert!(foveae[1].x >= 1920);
+    }
+}
@@ -4,6 +4,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 #[cfg(unix)]
 use std::os::fd::OwnedFd;
+use wavry_common::error::ErrorCode;
 
 #[derive(Debug, thiserror::Error)]
 pub enum MediaError {
@@ -35,6 +36,22 @@ pub enum MediaError {
     Other(#[from] anyhow::Error),
 }
 
+impl From<&MediaError> for ErrorCode {
+    fn from(err: &MediaError) -> Self {
+        match err {
+            MediaError::ProtocolViolation(_) | MediaError::CompositorDisconnect(_) => {
+                ErrorCode::Compositor
+            }
+            MediaError::PortalUnavailable(_) => ErrorCode::PermissionDenied,
+            MediaError::StreamNodeLoss(_) => ErrorCode::CaptureLost,
+            MediaError::GStreamerError(_) => ErrorCode::MediaPipeline,
+            MediaError::HardwareFailure(_) => ErrorCode::EncoderFailed,
+            MediaError::Unsupported(_) => ErrorCode::PlatformUnsupported,
+            MediaError::PlatformError(_) | MediaError::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
+
 pub type MediaResult<T> = std::result::Result<T, MediaError>;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -66,6 +83,15 @@ pub enum FrameData {
     },
 }
 
+/// Region of a frame that changed since the previous frame, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
 #[derive(Debug)]
 pub struct RawFrame {
     pub width: u16,
@@ -73,6 +99,8 @@ pub struct RawFrame {
     pub format: FrameFormat,
     pub timestamp_us: u64,
     pub data: FrameData,
+    /// What changed since the previous frame; `None` when the capturer can't tell.
+    pub dirty_rects: Option<Vec<DirtyRect>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -84,6 +112,61 @@ pub struct EncodedFrame {
     pub encode_duration_us: u32,
 }
 
+/// How captured frames reach an encoder.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FramePath {
+    /// Copied into system memory and converted on the CPU.
+    #[default]
+    Cpu,
+    /// Imported from the capturer's GPU buffers and converted on the GPU.
+    ZeroCopy,
+}
+
+impl std::fmt::Display for FramePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FramePath::Cpu => "cpu",
+            FramePath::ZeroCopy => "zero-copy",
+        })
+    }
+}
+
+/// Counters an encoder keeps about the frames it takes in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncoderStats {
+    /// Path frames currently take.
+    pub frame_path: FramePath,
+    pub zero_copy_frames: u64,
+    pub cpu_frames: u64,
+    /// DRM format modifier of the imported buffers, once negotiated.
+    pub dmabuf_modifier: Option<u64>,
+    /// Smoothed time from a captured frame to the encoder's input. Without a
+    /// CPU copy this stays well under a millisecond.
+    pub upload_latency_us: u32,
+    /// Smoothed time the encoder takes from input to bitstream.
+    pub encode_latency_us: u32,
+    /// Frames encoded over the last second. In damage mode this falls to the
+    /// keepalive rate while the screen is idle.
+    pub fps: u16,
+    /// Captures left unencoded because nothing on screen changed.
+    pub unchanged_frames: u64,
+}
+
+impl EncoderStats {
+    /// Folds one frame's timings into the smoothed latencies.
+    pub fn record_latency(&mut self, upload_us: u32, encode_us: u32) {
+        let smooth = |average: u32, sample: u32| {
+            if average == 0 {
+                sample
+            } else {
+                (i64::from(average) + (i64::from(sample) - i64::from(average)) / 8) as u32
+            }
+        };
+        self.upload_latency_us = smooth(self.upload_latency_us, upload_us);
+        self.encode_latency_us = smooth(self.encode_latency_us, encode_us);
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Resolution {
     pub width: u16,
@@ -100,6 +183,10 @@ pub struct EncodeConfig {
     pub display_id: Option<u32>,
     pub enable_10bit: bool,
     pub enable_hdr: bool,
+    /// Leave the pointer out of captured frames; it is sent as a cursor update.
+    pub hide_cursor: bool,
+    pub capture_mode: CaptureMode,
+    pub tuning: EncoderTuning,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -149,6 +236,13 @@ pub trait CapabilityProbe: Send + Sync {
     fn supported_decoders(&self) -> Result<Vec<Codec>>;
     fn enumerate_displays(&self) -> Result<Vec<DisplayInfo>>;
 
+    /// Starts watching for displays being added, removed or reconfigured.
+    fn watch_displays(&self) -> Result<DisplayWatch> {
+        Err(anyhow::anyhow!(
+            "display change notifications are not supported on this platform"
+        ))
+    }
+
     fn encoder_capabilities(&self) -> Result<Vec<VideoCodecCapability>> {
         Ok(self
             .supported_encoders()?
@@ -169,6 +263,16 @@ pub trait CapabilityProbe: Send + Sync {
 
 pub trait Renderer: Send {
     fn render(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()>;
+
+    /// Draws the host pointer over the video. Renderers that can't composite
+    /// ignore it.
+    fn update_cursor(&mut self, _cursor: &CursorState) -> Result<()> {
+        Ok(())
+    }
+
+    /// Refresh rate of the display showing the video. Renderers that don't
+    /// pace presentation ignore it.
+    fn set_display_refresh_hz(&mut self, _refresh_hz: f32) {}
 }
 
 // Input Types abstraction (simplified for now)
@@ -251,25 +355,57 @@ pub use buffer_pool::{
     FrameBuffer, FrameBufferPool, FrameBufferPoolConfig, ReorderBuffer, ReorderError,
 };
 
+pub mod cursor;
+pub use cursor::{CursorShape, CursorState, SystemCursor};
+
 pub mod encoder_pool;
 pub use encoder_pool::{
     EncoderConfig, EncoderPool, EncoderPoolConfig, EncoderPoolStats, MemoryPressure, PooledEncoder,
-    ReferenceFrame, ReferenceFrameManager, StagingBuffer, StagingBufferPool,
+    ReferenceFrame, ReferenceFrameManager, SharedTextureRing, StagingBuffer, StagingBufferPool,
 };
 
+pub mod damage;
+pub use damage::{CaptureMode, DamageGate, FrameRateMeter, DAMAGE_KEEPALIVE_INTERVAL};
+
+pub mod display_watch;
+pub use display_watch::{replacement_display, DisplayWatch, DISPLAY_SETTLE};
+
+pub mod foveation;
+pub use foveation::{FoveationParams, QpOffsetMap, QpRegion, QP_MAP_BLOCK_SIZE};
+
+pub mod pacing;
+pub use pacing::{PresentScheduler, PresentStats, Presentation, VrFramePacer};
+
+mod mkv;
 pub mod recorder;
-pub use recorder::{Quality, RecorderConfig, VideoRecorder};
+pub use recorder::{Container, Quality, RecorderConfig, VideoRecorder};
+
+pub mod tuning;
+pub use tuning::{ContentType, EncoderTuning, RefreshMode};
 
 #[cfg(target_os = "linux")]
 mod linux;
+#[cfg(target_os = "linux")]
+mod x11_damage;
 
 mod audio;
+pub use audio::codec::{AudioDecoder, AudioEncoder, OpusConfig};
+#[cfg(feature = "opus-support")]
+pub use audio::codec::{OpusAudioDecoder, OpusAudioEncoder};
+pub use audio::layout::{encode_foa, AudioChannelLayout};
+#[cfg(feature = "opus-support")]
+pub use audio::multistream::{MultichannelOpusDecoder, MultichannelOpusEncoder};
+pub use audio::renderer::SpatialAudioRenderer;
+pub use audio::spatial::{BinauralRenderer, HeadOrientation};
 
 #[cfg(target_os = "linux")]
 pub use linux::{
     linux_runtime_diagnostics, GstAudioRenderer, GstVideoRenderer, LinuxProbe,
-    LinuxRuntimeDiagnostics, PipewireAudioCapturer, PipewireEncoder,
+    LinuxRuntimeDiagnostics, PipewireAudioCapturer, PipewireEncoder, PipewireVirtualMicrophone,
+    VIRTUAL_MIC_NODE_NAME,
 };
+#[cfg(target_os = "linux")]
+pub use x11_damage::{X11Damage, X11DamageWatch};
 
 mod dummy;
 pub use dummy::{DummyEncoder, DummyRenderer};
@@ -297,13 +433,15 @@ pub use mac_audio_capturer::{MacAudioCapturer, MacAudioRoute};
 #[cfg(target_os = "macos")]
 mod mac_audio_renderer;
 #[cfg(target_os = "macos")]
-pub use mac_audio_renderer::MacAudioRenderer;
+pub use mac_audio_renderer::{MacAudioRenderer, MacVirtualMicrophone};
 
 #[cfg(target_os = "windows")]
 mod windows;
+#[cfg(target_os = "windows")]
+pub mod windows_capture;
 
 #[cfg(target_os = "windows")]
 pub use windows::{
     WindowsAudioCapturer, WindowsAudioRenderer, WindowsEncoder, WindowsInputInjector, WindowsProbe,
-    WindowsRenderer,
+    WindowsRenderer, WindowsVirtualMicrophone,
 };
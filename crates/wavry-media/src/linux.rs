@@ -1,10 +1,13 @@
 use std::collections::BTreeSet;
+#[cfg(feature = "opus-support")]
+use std::collections::VecDeque;
 use std::env;
 use std::fs;
 use std::os::fd::{AsRawFd, OwnedFd};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Context, Result};
 use ashpd::desktop::{
@@ -14,12 +17,34 @@ use ashpd::desktop::{
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
 use std::future::Future;
 use tokio::time::{sleep, Duration};
 use x11rb::connection::Connection;
 use x11rb::protocol::randr::ConnectionExt as RandrExt;
 
-use crate::{Codec, DecodeConfig, EncodeConfig, EncodedFrame, MediaError, MediaResult, Renderer};
+use crate::audio::codec::OpusConfig;
+#[cfg(feature = "opus-support")]
+use crate::audio::multistream::MultichannelOpusEncoder;
+#[cfg(feature = "opus-support")]
+use crate::audio::renderer::f32_to_i16;
+use crate::audio::OPUS_SAMPLE_RATE;
+#[cfg(feature = "opus-support")]
+use crate::audio::{opus_frame_duration_us, AUDIO_MAX_BUFFER_FRAMES, OPUS_FRAME_SAMPLES};
+use crate::encode_foa;
+use crate::{
+    AudioChannelLayout, CaptureMode, Codec, ContentType, CursorShape, CursorState, DamageGate,
+    DecodeConfig, EncodeConfig, EncodedFrame, EncoderStats, EncoderTuning, FramePath,
+    FrameRateMeter, MediaError, MediaResult, PresentScheduler, PresentStats, Presentation,
+    QpOffsetMap, QpRegion, RefreshMode, Renderer, X11DamageWatch, DAMAGE_KEEPALIVE_INTERVAL,
+};
+
+/// Set to `0` to keep PipeWire capture on the CPU path even where the VA-API
+/// encoder could import its DMA-BUFs.
+const ZERO_COPY_ENV: &str = "WAVRY_LINUX_ZERO_COPY";
+/// How long a DMA-BUF pipeline gets to produce its first frame before the
+/// CPU path takes over.
+const ZERO_COPY_NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(3);
 
 fn element_available(name: &str) -> bool {
     gst::ElementFactory::find(name).is_some()
@@ -498,6 +523,28 @@ fn x11_monitor_crop(display_id: u32) -> Result<Option<(u32, u32, u32, u32)>> {
     Ok(Some((x, right, y, bottom)))
 }
 
+/// Fastest refresh rate among the active X11 monitors.
+fn x11_refresh_hz() -> Result<Option<f32>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+
+    let mut fastest: Option<f32> = None;
+    for crtc in &resources.crtcs {
+        let info = conn.randr_get_crtc_info(*crtc, 0)?.reply()?;
+        let Some(mode) = resources.modes.iter().find(|mode| mode.id == info.mode) else {
+            continue;
+        };
+        let dots = u32::from(mode.htotal) * u32::from(mode.vtotal);
+        if dots == 0 {
+            continue;
+        }
+        let refresh_hz = mode.dot_clock as f32 / dots as f32;
+        fastest = Some(fastest.map_or(refresh_hz, |fastest| fastest.max(refresh_hz)));
+    }
+    Ok(fastest)
+}
+
 async fn enumerate_wayland_displays_inner() -> Result<Vec<crate::DisplayInfo>> {
     let proxy = Screencast::new().await?;
     let session = proxy.create_session().await?;
@@ -668,55 +715,327 @@ fn select_encoder(codec: Codec, enable_10bit: bool) -> Result<(String, &'static
     Ok((encoder.to_string(), input_format))
 }
 
+/// VA post-processor that imports DMA-BUFs for `encoder_name` and converts
+/// them on the GPU, with the caps memory feature of the surfaces it hands the
+/// encoder. The two VA plugins don't share surfaces with each other.
+fn dmabuf_importer(encoder_name: &str) -> Option<(&'static str, &'static str)> {
+    if encoder_name.starts_with("vaapi") {
+        Some(("vaapipostproc", "memory:VASurface"))
+    } else if encoder_name.starts_with("va") {
+        Some(("vapostproc", "memory:VAMemory"))
+    } else {
+        None
+    }
+}
+
+fn zero_copy_enabled() -> bool {
+    !matches!(
+        env::var(ZERO_COPY_ENV).as_deref().map(str::trim),
+        Ok("0") | Ok("false") | Ok("off")
+    )
+}
+
+/// Modifier in a `drm-format` caps field such as `XR24:0x0100000000000001`.
+/// A bare fourcc names a linear buffer.
+fn drm_format_modifier(drm_format: &str) -> Option<u64> {
+    match drm_format.split_once(':') {
+        Some((_, modifier)) => {
+            let hex = modifier.strip_prefix("0x").unwrap_or(modifier);
+            u64::from_str_radix(hex, 16).ok()
+        }
+        None => (!drm_format.is_empty()).then_some(0),
+    }
+}
+
+/// Capture from a portal stream into `encoder_name`. With an `importer` the
+/// DMA-BUFs PipeWire hands out are imported as VA surfaces, so frames never
+/// leave the GPU; the buffer modifier is settled by caps negotiation between
+/// the compositor and the VA driver. Otherwise `videoconvert` maps each frame
+/// and converts it on the CPU.
+fn pipewire_pipeline(
+    fd: &OwnedFd,
+    node_id: u32,
+    importer: Option<(&str, &str)>,
+    input_format: &str,
+    config: &EncodeConfig,
+    encoder_name: &str,
+    parser: &str,
+) -> String {
+    let convert = match importer {
+        Some((postproc, memory)) => format!(
+            "video/x-raw(memory:DMABuf) ! {} ! video/x-raw({}),format={}",
+            postproc, memory, input_format
+        ),
+        None => format!("videoconvert ! video/x-raw,format={}", input_format),
+    };
+    format!(
+        "pipewiresrc name=capture fd={} path={} do-timestamp=true ! {},width={},height={},framerate={}/1 ! queue max-size-buffers=1 leaky=downstream ! {} name=encoder ! {} config-interval=-1 ! appsink name=sink max-buffers=1 drop=true sync=false",
+        fd.as_raw_fd(),
+        node_id,
+        convert,
+        config.resolution.width,
+        config.resolution.height,
+        config.fps,
+        encoder_name,
+        parser,
+    )
+}
+
+/// Parse `pipeline_str`, tune its encoder and start it.
+fn launch_pipeline(
+    pipeline_str: &str,
+    encoder_name: &str,
+    config: &EncodeConfig,
+    keyframe_interval_frames: u32,
+    roi_regions: &Arc<Mutex<Vec<QpRegion>>>,
+    damage: &Arc<Mutex<DamageFilter>>,
+) -> MediaResult<(gst::Pipeline, gst_app::AppSink, gst::Element)> {
+    let pipeline = gst::parse::launch(pipeline_str)
+        .map_err(|e| MediaError::GStreamerError(e.to_string()))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| MediaError::GStreamerError("failed to downcast pipeline".to_string()))?;
+
+    let appsink = pipeline
+        .by_name("sink")
+        .ok_or_else(|| MediaError::GStreamerError("appsink not found".to_string()))?
+        .downcast::<gst_app::AppSink>()
+        .map_err(|_| MediaError::GStreamerError("appsink type mismatch".to_string()))?;
+
+    let encoder_element = pipeline
+        .by_name("encoder")
+        .ok_or_else(|| MediaError::GStreamerError("encoder element not found".to_string()))?;
+
+    configure_low_latency_encoder(
+        &encoder_element,
+        encoder_name,
+        config,
+        keyframe_interval_frames,
+    )
+    .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
+
+    attach_roi_probe(&encoder_element, roi_regions.clone());
+
+    if config.capture_mode == CaptureMode::Damage {
+        if let Some(capture) = pipeline.by_name("capture") {
+            // pipewiresrc repeats its last buffer when the compositor goes
+            // quiet; the probe lets those through at the keepalive rate.
+            if capture.has_property("keepalive-time", None) {
+                capture.set_property_from_str(
+                    "keepalive-time",
+                    &DAMAGE_KEEPALIVE_INTERVAL.as_millis().to_string(),
+                );
+            }
+            attach_damage_probe(&capture, damage.clone());
+        }
+    }
+
+    if let Err(err) = pipeline.set_state(gst::State::Playing) {
+        let _ = pipeline.set_state(gst::State::Null);
+        return Err(MediaError::GStreamerError(err.to_string()));
+    }
+    Ok((pipeline, appsink, encoder_element))
+}
+
 fn configure_low_latency_encoder(
     encoder: &gst::Element,
     encoder_name: &str,
-    bitrate_kbps: u32,
+    config: &EncodeConfig,
     keyframe_interval_frames: u32,
-    enable_10bit: bool,
 ) -> Result<()> {
     fn set_if_exists<V: ToValue>(encoder: &gst::Element, name: &str, value: V) {
         if encoder.has_property(name, None) {
             encoder.set_property(name, &value);
         }
     }
+    // Enum and flags properties take their nick.
+    fn set_nick_if_exists(encoder: &gst::Element, name: &str, nick: &str) {
+        if encoder.has_property(name, None) {
+            encoder.set_property_from_str(name, nick);
+        }
+    }
 
-    set_if_exists(encoder, "bitrate", bitrate_kbps);
-    set_if_exists(encoder, "target-bitrate", bitrate_kbps);
+    let tuning = config.tuning;
+    set_if_exists(encoder, "bitrate", config.bitrate_kbps);
+    set_if_exists(encoder, "target-bitrate", config.bitrate_kbps);
     set_if_exists(encoder, "keyframe-period", keyframe_interval_frames);
     set_if_exists(encoder, "key-int-max", keyframe_interval_frames as i32);
 
-    if encoder_name.contains("x264") {
-        set_if_exists(encoder, "tune", "zerolatency");
-        set_if_exists(encoder, "speed-preset", "ultrafast");
-        set_if_exists(encoder, "bframes", 0i32);
-    } else if encoder_name.contains("x265") {
-        set_if_exists(encoder, "tune", "zerolatency");
-        set_if_exists(encoder, "speed-preset", "ultrafast");
-        set_if_exists(encoder, "bframes", 0i32);
-        if enable_10bit {
-            set_if_exists(encoder, "profile", "main10");
+    if encoder_name.contains("x264") || encoder_name.contains("x265") {
+        if tuning.low_latency {
+            set_nick_if_exists(encoder, "tune", "zerolatency");
+            set_nick_if_exists(encoder, "speed-preset", "ultrafast");
+        } else {
+            set_nick_if_exists(encoder, "speed-preset", "veryfast");
+        }
+        set_if_exists(encoder, "bframes", 0u32);
+        if encoder_name.contains("x264") {
+            set_if_exists(
+                encoder,
+                "intra-refresh",
+                tuning.refresh == RefreshMode::IntraRefresh,
+            );
+        }
+        let options = x26x_options(encoder_name, &tuning);
+        if !options.is_empty() {
+            set_if_exists(encoder, "option-string", options);
+        }
+        if encoder_name.contains("x265") && config.enable_10bit {
+            set_nick_if_exists(encoder, "profile", "main10");
         }
     } else if encoder_name.contains("svtav1") {
-        set_if_exists(encoder, "preset", 8i32);
-        set_if_exists(encoder, "tune", 0i32);
+        set_if_exists(
+            encoder,
+            "preset",
+            if tuning.low_latency { 8u32 } else { 6u32 },
+        );
+        set_if_exists(encoder, "parameters-string", svtav1_parameters(&tuning));
     } else if encoder_name.contains("vaapi") {
-        set_if_exists(encoder, "rate-control", "cbr");
-        set_if_exists(encoder, "max-bframes", 0i32);
+        set_nick_if_exists(encoder, "rate-control", "cbr");
+        set_if_exists(encoder, "max-bframes", 0u32);
         set_if_exists(encoder, "cabac", false);
-    } else if encoder_name.contains("nvh265") && enable_10bit {
-        set_if_exists(encoder, "profile", "main10");
+        if tuning.slices > 0 {
+            set_if_exists(encoder, "num-slices", u32::from(tuning.slices));
+        }
+    } else if encoder_name.starts_with("nv") {
+        set_if_exists(encoder, "zerolatency", tuning.low_latency);
+        if tuning.low_latency {
+            set_if_exists(encoder, "rc-lookahead", 0u32);
+        }
+        if encoder_name.contains("nvh265") && config.enable_10bit {
+            set_nick_if_exists(encoder, "profile", "main-10");
+        }
     }
 
     Ok(())
 }
 
+/// `option-string` for x264enc/x265enc: slice count and, for x265 (which
+/// has no property for it), intra refresh.
+fn x26x_options(encoder_name: &str, tuning: &EncoderTuning) -> String {
+    let mut options = Vec::new();
+    if tuning.slices > 0 {
+        options.push(format!("slices={}", tuning.slices));
+    }
+    if encoder_name.contains("x265") && tuning.refresh == RefreshMode::IntraRefresh {
+        options.push("intra-refresh=1".to_string());
+    }
+    options.join(":")
+}
+
+/// `parameters-string` for svtav1enc. SVT-AV1 has no intra refresh, so that
+/// mode falls back to keyframes.
+fn svtav1_parameters(tuning: &EncoderTuning) -> String {
+    let mut params = vec![
+        format!(
+            "scm={}",
+            match tuning.content {
+                ContentType::Desktop => 1,
+                ContentType::Game => 0,
+            }
+        ),
+        format!("tile-columns={}", tuning.tile_columns_log2()),
+    ];
+    if tuning.low_latency {
+        // Low-delay prediction: no frame waits on a later one.
+        params.push("pred-struct=1".to_string());
+    }
+    params.join(":")
+}
+
 pub struct PipewireEncoder {
     _fd: Option<OwnedFd>,
-    #[allow(dead_code)]
     pipeline: gst::Pipeline,
     appsink: gst_app::AppSink,
     encoder_element: gst::Element,
+    /// ROI rectangles attached to every raw frame entering the encoder.
+    roi_regions: Arc<Mutex<Vec<QpRegion>>>,
+    encoder_name: String,
+    config: EncodeConfig,
+    keyframe_interval_frames: u32,
+    /// CPU pipeline to rebuild with while the zero-copy one runs.
+    cpu_fallback: Option<String>,
+    /// First zero-copy frame, pulled to confirm DMA-BUF import works.
+    pending_sample: Option<gst::Sample>,
+    damage: Arc<Mutex<DamageFilter>>,
+    fps: FrameRateMeter,
+    stats: EncoderStats,
+}
+
+/// Damage-mode state shared with the capture pad probe.
+struct DamageFilter {
+    gate: DamageGate,
+    /// XDamage on the root window when capturing through `ximagesrc`.
+    x11: Option<X11DamageWatch>,
+}
+
+/// Holds back captures that repeat the previous picture until the keepalive
+/// is due. PipeWire only delivers a buffer when the compositor reports damage,
+/// and marks ones without new pixels (such as cursor-only updates) as gaps or
+/// leaves them empty. `ximagesrc` grabs at a fixed rate, so X11 capture asks
+/// XDamage whether anything was drawn since the last grab.
+fn attach_damage_probe(capture: &gst::Element, filter: Arc<Mutex<DamageFilter>>) {
+    let Some(pad) = capture.static_pad("src") else {
+        return;
+    };
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data else {
+            return gst::PadProbeReturn::Ok;
+        };
+        let Ok(mut filter) = filter.lock() else {
+            return gst::PadProbeReturn::Ok;
+        };
+        let mut changed = buffer.size() > 0
+            && !buffer
+                .flags()
+                .intersects(gst::BufferFlags::GAP | gst::BufferFlags::CORRUPTED);
+        if changed {
+            if let Some(x11) = filter.x11.as_mut() {
+                changed = x11.changed().unwrap_or_else(|err| {
+                    log::debug!("XDamage check failed: {}", err);
+                    true
+                });
+            }
+        }
+        if filter.gate.admit(changed, std::time::Instant::now()) {
+            gst::PadProbeReturn::Ok
+        } else {
+            gst::PadProbeReturn::Drop
+        }
+    });
+}
+
+/// Tag each raw buffer with ROI metas; VA-API encoders turn `delta-qp` into
+/// per-macroblock QP offsets, other encoders ignore the meta.
+fn attach_roi_probe(encoder: &gst::Element, regions: Arc<Mutex<Vec<QpRegion>>>) {
+    let Some(pad) = encoder.static_pad("sink") else {
+        return;
+    };
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        let Ok(regions) = regions.lock() else {
+            return gst::PadProbeReturn::Ok;
+        };
+        if regions.is_empty() {
+            return gst::PadProbeReturn::Ok;
+        }
+        if let Some(gst::PadProbeData::Buffer(ref mut buffer)) = info.data {
+            let buffer = buffer.make_mut();
+            for region in regions.iter() {
+                let mut meta = gst_video::VideoRegionOfInterestMeta::add(
+                    buffer,
+                    "foveation",
+                    (region.x, region.y, region.width, region.height),
+                );
+                for param in ["roi/vaapi", "roi/va"] {
+                    meta.add_param(
+                        gst::Structure::builder(param)
+                            .field("delta-qp", region.qp_offset as i32)
+                            .build(),
+                    );
+                }
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
 }
 
 impl PipewireEncoder {
@@ -747,22 +1066,37 @@ impl PipewireEncoder {
         // Try PipeWire portal first, fallback to X11 capture if available.
         let portal_stream = open_portal_stream(config.display_id).await;
 
-        let (pipeline_str, fd_opt) = match portal_stream {
+        let mut x11_damage = None;
+        let (pipeline_str, fd_opt, cpu_fallback) = match portal_stream {
             Ok((fd, node_id)) => {
                 require_elements(&["pipewiresrc"])
                     .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
-                let pipeline_str = format!(
-                    "pipewiresrc fd={} path={} do-timestamp=true ! videoconvert ! video/x-raw,format={},width={},height={},framerate={}/1 ! queue max-size-buffers=1 leaky=downstream ! {} name=encoder ! {} config-interval=-1 ! appsink name=sink max-buffers=1 drop=true sync=false",
-                    fd.as_raw_fd(),
+                let cpu_pipeline = pipewire_pipeline(
+                    &fd,
                     node_id,
+                    None,
                     input_format,
-                    config.resolution.width,
-                    config.resolution.height,
-                    config.fps,
-                    encoder_name,
+                    &config,
+                    &encoder_name,
                     parser,
                 );
-                (pipeline_str, Some(fd))
+                let importer = dmabuf_importer(&encoder_name)
+                    .filter(|(postproc, _)| zero_copy_enabled() && element_available(postproc));
+                match importer {
+                    Some(importer) => {
+                        let zero_copy_pipeline = pipewire_pipeline(
+                            &fd,
+                            node_id,
+                            Some(importer),
+                            input_format,
+                            &config,
+                            &encoder_name,
+                            parser,
+                        );
+                        (zero_copy_pipeline, Some(fd), Some(cpu_pipeline))
+                    }
+                    None => (cpu_pipeline, Some(fd), None),
+                }
             }
             Err(err) => {
                 if has_wayland_display() {
@@ -811,8 +1145,21 @@ impl PipewireEncoder {
                         String::new()
                     };
 
+                    // In damage mode ximagesrc copies only damaged areas, and the
+                    // capture probe skips grabs XDamage saw nothing drawn for.
+                    if config.capture_mode == CaptureMode::Damage {
+                        match X11DamageWatch::open() {
+                            Ok(watch) => x11_damage = Some(watch),
+                            Err(err) => {
+                                log::warn!("XDamage unavailable, encoding every X11 frame: {}", err)
+                            }
+                        }
+                    }
+
                     let pipeline_str = format!(
-                        "ximagesrc use-damage=0 ! videoconvert ! {}videoscale ! video/x-raw,format={},width={},height={},framerate={}/1 ! queue max-size-buffers=1 leaky=downstream ! {} name=encoder ! {} config-interval=-1 ! appsink name=sink max-buffers=1 drop=true sync=false",
+                        "ximagesrc name=capture use-damage={} show-pointer={} ! videoconvert ! {}videoscale ! video/x-raw,format={},width={},height={},framerate={}/1 ! queue max-size-buffers=1 leaky=downstream ! {} name=encoder ! {} config-interval=-1 ! appsink name=sink max-buffers=1 drop=true sync=false",
+                        x11_damage.is_some(),
+                        !config.hide_cursor,
                         crop_str,
                         input_format,
                         config.resolution.width,
@@ -821,47 +1168,156 @@ impl PipewireEncoder {
                         encoder_name,
                         parser,
                     );
-                    (pipeline_str, None)
+                    (pipeline_str, None, None)
                 } else {
                     return Err(MediaError::PlatformError(err.to_string()));
                 }
             }
         };
 
-        let pipeline = gst::parse::launch(&pipeline_str)
-            .map_err(|e| MediaError::GStreamerError(e.to_string()))?
-            .downcast::<gst::Pipeline>()
-            .map_err(|_| MediaError::GStreamerError("failed to downcast pipeline".to_string()))?;
-
-        let appsink = pipeline
-            .by_name("sink")
-            .ok_or_else(|| MediaError::GStreamerError("appsink not found".to_string()))?
-            .downcast::<gst_app::AppSink>()
-            .map_err(|_| MediaError::GStreamerError("appsink type mismatch".to_string()))?;
-
-        let encoder_element = pipeline
-            .by_name("encoder")
-            .ok_or_else(|| MediaError::GStreamerError("encoder element not found".to_string()))?;
-
-        configure_low_latency_encoder(
-            &encoder_element,
+        let roi_regions = Arc::new(Mutex::new(Vec::new()));
+        let damage = Arc::new(Mutex::new(DamageFilter {
+            gate: DamageGate::new(config.capture_mode),
+            x11: x11_damage,
+        }));
+        let launched = launch_pipeline(
+            &pipeline_str,
             &encoder_name,
-            config.bitrate_kbps,
+            &config,
             keyframe_interval_frames,
-            config.enable_10bit,
-        )
-        .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
-
-        pipeline
-            .set_state(gst::State::Playing)
-            .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
+            &roi_regions,
+            &damage,
+        );
+        let (launched, cpu_fallback) = match (launched, cpu_fallback) {
+            (Err(err), Some(cpu_pipeline)) => {
+                log::warn!(
+                    "DMA-BUF capture pipeline failed to start ({}); using the CPU path",
+                    err
+                );
+                let launched = launch_pipeline(
+                    &cpu_pipeline,
+                    &encoder_name,
+                    &config,
+                    keyframe_interval_frames,
+                    &roi_regions,
+                    &damage,
+                );
+                (launched, None)
+            }
+            other => other,
+        };
+        let (pipeline, appsink, encoder_element) = launched?;
 
-        Ok(Self {
+        let frame_path = if cpu_fallback.is_some() {
+            FramePath::ZeroCopy
+        } else {
+            FramePath::Cpu
+        };
+        let mut encoder = Self {
             _fd: fd_opt,
             pipeline,
             appsink,
             encoder_element,
-        })
+            roi_regions,
+            encoder_name,
+            config,
+            keyframe_interval_frames,
+            cpu_fallback,
+            pending_sample: None,
+            damage,
+            fps: FrameRateMeter::new(),
+            stats: EncoderStats {
+                frame_path,
+                ..EncoderStats::default()
+            },
+        };
+        if encoder.cpu_fallback.is_some() {
+            encoder.confirm_zero_copy().await?;
+        }
+        log::info!(
+            "{} takes frames via the {} path ({} capture)",
+            encoder.encoder_name,
+            encoder.stats.frame_path,
+            encoder.config.capture_mode
+        );
+        Ok(encoder)
+    }
+
+    /// Waits for the zero-copy pipeline's first frame and keeps it for
+    /// [`Self::next_frame`]. Rebuilds on the CPU path when caps or modifier
+    /// negotiation fails, or when nothing arrives in time.
+    async fn confirm_zero_copy(&mut self) -> MediaResult<()> {
+        let deadline = tokio::time::Instant::now() + ZERO_COPY_NEGOTIATION_TIMEOUT;
+        let failure = loop {
+            if let Some(sample) = self.appsink.try_pull_sample(gst::ClockTime::ZERO) {
+                self.pending_sample = Some(sample);
+                self.stats.dmabuf_modifier = self.negotiated_modifier();
+                if let Some(modifier) = self.stats.dmabuf_modifier {
+                    log::info!("importing DMA-BUFs with modifier {:#018x}", modifier);
+                }
+                return Ok(());
+            }
+            if let Err(err) = self.check_bus_errors() {
+                break err.to_string();
+            }
+            if tokio::time::Instant::now() >= deadline {
+                break "no frame arrived".to_string();
+            }
+            sleep(Duration::from_millis(20)).await;
+        };
+        self.fall_back_to_cpu(&failure)
+    }
+
+    /// Modifier PipeWire and the VA importer settled on. Only GStreamer 1.24
+    /// and later spell it out in the caps.
+    fn negotiated_modifier(&self) -> Option<u64> {
+        let caps = self
+            .pipeline
+            .by_name("capture")?
+            .static_pad("src")?
+            .current_caps()?;
+        let drm_format = caps.structure(0)?.get::<String>("drm-format").ok()?;
+        drm_format_modifier(&drm_format)
+    }
+
+    /// Replaces the zero-copy pipeline with the CPU one. Does nothing once
+    /// on the CPU path.
+    fn fall_back_to_cpu(&mut self, reason: &str) -> MediaResult<()> {
+        let Some(cpu_pipeline) = self.cpu_fallback.take() else {
+            return Ok(());
+        };
+        log::warn!(
+            "DMA-BUF zero-copy capture failed ({}); falling back to the CPU path",
+            reason
+        );
+        let _ = self.pipeline.set_state(gst::State::Null);
+        let (pipeline, appsink, encoder_element) = launch_pipeline(
+            &cpu_pipeline,
+            &self.encoder_name,
+            &self.config,
+            self.keyframe_interval_frames,
+            &self.roi_regions,
+            &self.damage,
+        )?;
+        self.pipeline = pipeline;
+        self.appsink = appsink;
+        self.encoder_element = encoder_element;
+        self.pending_sample = None;
+        self.stats.frame_path = FramePath::Cpu;
+        self.stats.dmabuf_modifier = None;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> EncoderStats {
+        let unchanged_frames = self
+            .damage
+            .lock()
+            .map(|filter| filter.gate.skipped())
+            .unwrap_or_default();
+        EncoderStats {
+            unchanged_frames,
+            ..self.stats
+        }
     }
 
     fn check_bus_errors(&self) -> MediaResult<()> {
@@ -895,15 +1351,35 @@ impl PipewireEncoder {
     }
 
     pub fn next_frame(&mut self) -> MediaResult<EncodedFrame> {
-        let sample = match self.appsink.pull_sample() {
-            Ok(s) => s,
-            Err(_) => {
-                self.check_bus_errors()?;
-                return Err(MediaError::GStreamerError(
-                    "failed to pull sample (no bus error found)".to_string(),
-                ));
-            }
+        let sample = match self.pending_sample.take() {
+            Some(sample) => sample,
+            None => match self.appsink.pull_sample() {
+                Ok(s) => s,
+                // Renegotiation, e.g. after the monitor changes mode, can
+                // land on a buffer the VA driver can't import.
+                Err(_) if self.cpu_fallback.is_some() => {
+                    let reason = match self.check_bus_errors() {
+                        Err(err) => err.to_string(),
+                        Ok(()) => "pipeline stopped".to_string(),
+                    };
+                    self.fall_back_to_cpu(&reason)?;
+                    return self.next_frame();
+                }
+                Err(_) => {
+                    self.check_bus_errors()?;
+                    return Err(MediaError::GStreamerError(
+                        "failed to pull sample (no bus error found)".to_string(),
+                    ));
+                }
+            },
         };
+        match self.stats.frame_path {
+            FramePath::ZeroCopy => self.stats.zero_copy_frames += 1,
+            FramePath::Cpu => self.stats.cpu_frames += 1,
+        }
+        let now = std::time::Instant::now();
+        self.fps.record(now);
+        self.stats.fps = self.fps.fps(now);
         let buffer = sample
             .buffer()
             .ok_or_else(|| MediaError::GStreamerError("missing buffer".to_string()))?;
@@ -924,6 +1400,8 @@ impl PipewireEncoder {
     /// Update encoder bitrate at runtime.
     /// VAAPI encoders support dynamic bitrate changes via the "bitrate" property.
     pub fn set_bitrate(&mut self, bitrate_kbps: u32) -> Result<()> {
+        // A CPU fallback pipeline starts at the current rate.
+        self.config.bitrate_kbps = bitrate_kbps;
         if self.encoder_element.has_property("bitrate", None) {
             self.encoder_element.set_property("bitrate", bitrate_kbps);
         } else if self.encoder_element.has_property("target-bitrate", None) {
@@ -933,12 +1411,95 @@ impl PipewireEncoder {
         log::debug!("Linux encoder bitrate updated to {} kbps", bitrate_kbps);
         Ok(())
     }
+
+    /// Apply (or clear with `None`) a QP offset map from the next frame on.
+    pub fn set_qp_offset_map(&mut self, map: Option<&QpOffsetMap>) -> Result<()> {
+        let regions = map.map(QpOffsetMap::regions).unwrap_or_default();
+        let mut slot = self
+            .roi_regions
+            .lock()
+            .map_err(|_| anyhow!("ROI state poisoned"))?;
+        *slot = regions;
+        Ok(())
+    }
 }
 
 pub struct GstVideoRenderer {
     #[allow(dead_code)]
     pipeline: gst::Pipeline,
     appsrc: gst_app::AppSrc,
+    cursor: Arc<Mutex<CursorOverlay>>,
+    pacing: Arc<Mutex<PresentScheduler>>,
+}
+
+/// Host pointer drawn by the `overlaycomposition` element on every frame.
+#[derive(Default)]
+struct CursorOverlay {
+    x: f32,
+    y: f32,
+    visible: bool,
+    serial: Option<u64>,
+    image: Option<CursorImage>,
+}
+
+struct CursorImage {
+    /// BGRA with a video meta, as overlay rectangles expect.
+    buffer: gst::Buffer,
+    width: u32,
+    height: u32,
+    hotspot_x: u32,
+    hotspot_y: u32,
+}
+
+impl CursorOverlay {
+    fn composition(&self, sample: &gst::Sample) -> Option<gst_video::VideoOverlayComposition> {
+        if !self.visible {
+            return None;
+        }
+        let image = self.image.as_ref()?;
+        let info = gst_video::VideoInfo::from_caps(sample.caps()?).ok()?;
+        let x = (self.x * info.width() as f32) as i32 - image.hotspot_x as i32;
+        let y = (self.y * info.height() as f32) as i32 - image.hotspot_y as i32;
+        let rect = gst_video::VideoOverlayRectangle::new_raw(
+            &image.buffer,
+            x,
+            y,
+            image.width,
+            image.height,
+            gst_video::VideoOverlayFormatFlags::PREMULTIPLIED_ALPHA,
+        );
+        gst_video::VideoOverlayComposition::new([&rect]).ok()
+    }
+}
+
+fn cursor_image(shape: &CursorShape) -> Result<CursorImage> {
+    let CursorShape::Bitmap {
+        width,
+        height,
+        hotspot_x,
+        hotspot_y,
+        bgra,
+    } = shape.to_bitmap()
+    else {
+        return Err(anyhow!("cursor shape has no bitmap"));
+    };
+    let mut buffer = gst::Buffer::from_mut_slice(bgra);
+    gst_video::VideoMeta::add(
+        buffer
+            .get_mut()
+            .ok_or_else(|| anyhow!("buffer mut failed"))?,
+        gst_video::VideoFrameFlags::empty(),
+        gst_video::VideoFormat::Bgra,
+        width,
+        height,
+    )?;
+    Ok(CursorImage {
+        buffer,
+        width,
+        height,
+        hotspot_x,
+        hotspot_y,
+    })
 }
 
 impl GstVideoRenderer {
@@ -950,12 +1511,13 @@ impl GstVideoRenderer {
             parser,
             "decodebin",
             "videoconvert",
+            "overlaycomposition",
             "autovideosink",
         ])?;
         require_decoder(config.codec)?;
 
         let pipeline_str = format!(
-            "appsrc name=src is-live=true format=time do-timestamp=true ! {} ! decodebin ! videoconvert ! autovideosink sync=false",
+            "appsrc name=src is-live=true format=time do-timestamp=true ! {} ! decodebin ! videoconvert ! overlaycomposition name=cursor ! videoconvert ! autovideosink name=videosink sync=false",
             parser
         );
         let pipeline = gst::parse::launch(&pipeline_str)?
@@ -971,9 +1533,45 @@ impl GstVideoRenderer {
         let caps = gst::Caps::from_str(caps_str)?;
         appsrc.set_caps(Some(&caps));
 
+        let cursor = Arc::new(Mutex::new(CursorOverlay::default()));
+        let draw_cursor = Arc::clone(&cursor);
+        pipeline
+            .by_name("cursor")
+            .ok_or_else(|| anyhow!("overlaycomposition not found"))?
+            .connect("draw", false, move |args| {
+                let sample = args[1].get::<gst::Sample>().ok()?;
+                let composition = draw_cursor
+                    .lock()
+                    .ok()
+                    .and_then(|cursor| cursor.composition(&sample));
+                Some(composition.to_value())
+            });
+
+        let refresh_hz = if has_x11_display() {
+            x11_refresh_hz().ok().flatten()
+        } else {
+            None
+        };
+        let pacing = Arc::new(Mutex::new(PresentScheduler::new(
+            refresh_hz.unwrap_or(crate::pacing::DEFAULT_REFRESH_HZ),
+        )));
+        attach_present_probe(&pipeline, Arc::clone(&pacing))?;
+
         pipeline.set_state(gst::State::Playing)?;
 
-        Ok(Self { pipeline, appsrc })
+        Ok(Self {
+            pipeline,
+            appsrc,
+            cursor,
+            pacing,
+        })
+    }
+
+    pub fn present_stats(&self) -> PresentStats {
+        self.pacing
+            .lock()
+            .map(|pacing| pacing.stats())
+            .unwrap_or_default()
     }
 
     pub fn push(&self, payload: &[u8], timestamp_us: u64) -> Result<()> {
@@ -992,10 +1590,69 @@ impl GstVideoRenderer {
     }
 }
 
+/// Holds each decoded frame in front of the sink until its scheduled refresh,
+/// or drops it. Buffers keep the host timestamps `push` gave them.
+fn attach_present_probe(
+    pipeline: &gst::Pipeline,
+    pacing: Arc<Mutex<PresentScheduler>>,
+) -> Result<()> {
+    let pad = pipeline
+        .by_name("videosink")
+        .and_then(|sink| sink.static_pad("sink"))
+        .ok_or_else(|| anyhow!("video sink pad not found"))?;
+    let epoch = std::time::Instant::now();
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data else {
+            return gst::PadProbeReturn::Ok;
+        };
+        let Some(pts) = buffer.pts() else {
+            return gst::PadProbeReturn::Ok;
+        };
+        let now_us = epoch.elapsed().as_micros() as u64;
+        let Ok(presentation) = pacing
+            .lock()
+            .map(|mut pacing| pacing.schedule(pts.useconds(), now_us))
+        else {
+            return gst::PadProbeReturn::Ok;
+        };
+        match presentation {
+            Presentation::Drop => gst::PadProbeReturn::Drop,
+            Presentation::At(slot_us) => {
+                if slot_us > now_us {
+                    std::thread::sleep(Duration::from_micros(slot_us - now_us));
+                }
+                gst::PadProbeReturn::Ok
+            }
+        }
+    });
+    Ok(())
+}
+
 impl Renderer for GstVideoRenderer {
     fn render(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
         self.push(payload, timestamp_us)
     }
+
+    fn set_display_refresh_hz(&mut self, refresh_hz: f32) {
+        if let Ok(mut pacing) = self.pacing.lock() {
+            pacing.set_refresh_hz(refresh_hz);
+        }
+    }
+
+    fn update_cursor(&mut self, cursor: &CursorState) -> Result<()> {
+        let mut overlay = self
+            .cursor
+            .lock()
+            .map_err(|_| anyhow!("cursor overlay lock poisoned"))?;
+        if overlay.serial != Some(cursor.serial) {
+            overlay.image = Some(cursor_image(&cursor.shape)?);
+            overlay.serial = Some(cursor.serial);
+        }
+        overlay.x = cursor.x;
+        overlay.y = cursor.y;
+        overlay.visible = cursor.visible;
+        Ok(())
+    }
 }
 
 pub struct PipewireAudioCapturer {
@@ -1003,6 +1660,18 @@ pub struct PipewireAudioCapturer {
     #[allow(dead_code)]
     pipeline: gst::Pipeline,
     appsink: gst_app::AppSink,
+    layout: AudioChannelLayout,
+    #[cfg(feature = "opus-support")]
+    multichannel: Option<MultichannelCapture>,
+}
+
+/// Multi-channel layouts pull raw PCM and encode it here: `opusenc` cannot
+/// produce the coupled-stream packets clients decode.
+#[cfg(feature = "opus-support")]
+struct MultichannelCapture {
+    encoder: MultichannelOpusEncoder,
+    pcm: VecDeque<i16>,
+    next_timestamp_us: u64,
 }
 
 impl PipewireAudioCapturer {
@@ -1011,19 +1680,75 @@ impl PipewireAudioCapturer {
     }
 
     pub async fn new_system_mix() -> MediaResult<Self> {
-        Self::new_with_route_linux(PipewireAudioRoute::SystemMix).await
+        Self::new_with_route_linux(PipewireAudioRoute::SystemMix, AudioChannelLayout::Stereo).await
     }
 
     pub async fn new_microphone() -> MediaResult<Self> {
-        Self::new_with_route_linux(PipewireAudioRoute::Microphone).await
+        Self::new_with_route_linux(PipewireAudioRoute::Microphone, AudioChannelLayout::Stereo).await
     }
 
     pub async fn new_application(app_name: String) -> MediaResult<Self> {
-        Self::new_with_route_linux(PipewireAudioRoute::Application(app_name)).await
+        Self::new_with_route_linux(
+            PipewireAudioRoute::Application(app_name),
+            AudioChannelLayout::Stereo,
+        )
+        .await
+    }
+
+    /// System mix captured in `layout`; ambisonic layouts are panned from a
+    /// 7.1 capture.
+    pub async fn new_system_mix_with_layout(layout: AudioChannelLayout) -> MediaResult<Self> {
+        Self::new_with_route_linux(PipewireAudioRoute::SystemMix, layout).await
+    }
+
+    pub fn layout(&self) -> AudioChannelLayout {
+        self.layout
+    }
+
+    /// Switches `opusenc` to the negotiated bitrate, frame size and FEC
+    /// setting. Multi-channel layouts keep their per-stream defaults.
+    pub fn set_opus_config(&mut self, config: OpusConfig) -> MediaResult<()> {
+        let Some(encoder) = self.pipeline.by_name("opus") else {
+            return Ok(());
+        };
+        let config = config.normalized();
+        let frame_size = match config.frame_duration_us {
+            2_500 => "2.5".to_string(),
+            duration => (duration / 1_000).to_string(),
+        };
+        encoder.set_property("bitrate", config.bitrate_bps as i32);
+        encoder.set_property_from_str("frame-size", &frame_size);
+        encoder.set_property("inband-fec", config.inband_fec);
+        if config.inband_fec {
+            encoder.set_property(
+                "packet-loss-percentage",
+                i32::from(config.expected_loss_percent),
+            );
+        }
+        Ok(())
     }
 
-    async fn new_with_route_linux(route: PipewireAudioRoute) -> MediaResult<Self> {
+    async fn new_with_route_linux(
+        route: PipewireAudioRoute,
+        layout: AudioChannelLayout,
+    ) -> MediaResult<Self> {
         gst::init().map_err(|e| MediaError::GStreamerError(e.to_string()))?;
+        #[cfg(feature = "opus-support")]
+        let multichannel = match layout {
+            AudioChannelLayout::Stereo => None,
+            layout => Some(MultichannelCapture {
+                encoder: MultichannelOpusEncoder::new(layout)?,
+                pcm: VecDeque::new(),
+                next_timestamp_us: 0,
+            }),
+        };
+        #[cfg(not(feature = "opus-support"))]
+        if layout != AudioChannelLayout::Stereo {
+            return Err(MediaError::Unsupported(
+                "multi-channel audio requires opus-support".to_string(),
+            ));
+        }
+        let tail = audio_pipeline_tail(layout);
         let (pipeline_str, fd_opt) = match route {
             PipewireAudioRoute::SystemMix => {
                 let portal = open_audio_portal_stream().await;
@@ -1038,9 +1763,10 @@ impl PipewireAudioCapturer {
                         ])
                         .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
                         let pipeline_str = format!(
-                            "pipewiresrc fd={} path={} do-timestamp=true ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false",
+                            "pipewiresrc fd={} path={} do-timestamp=true ! {}",
                             fd.as_raw_fd(),
-                            node_id
+                            node_id,
+                            tail
                         );
                         (pipeline_str, Some(fd))
                     }
@@ -1058,7 +1784,7 @@ impl PipewireAudioCapturer {
                                 "PipeWire audio portal failed, falling back to PulseAudio: {}",
                                 err
                             );
-                            let pipeline_str = "pulsesrc ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false".to_string();
+                            let pipeline_str = format!("pulsesrc ! {}", tail);
                             (pipeline_str, None)
                         } else {
                             return Err(MediaError::PortalUnavailable(format!(
@@ -1079,7 +1805,7 @@ impl PipewireAudioCapturer {
                         "appsink",
                     ])
                     .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
-                    let pipeline_str = "pulsesrc ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false".to_string();
+                    let pipeline_str = format!("pulsesrc ! {}", tail);
                     (pipeline_str, None)
                 } else if element_available("autoaudiosrc") {
                     require_elements(&[
@@ -1090,7 +1816,7 @@ impl PipewireAudioCapturer {
                         "appsink",
                     ])
                     .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
-                    let pipeline_str = "autoaudiosrc ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false".to_string();
+                    let pipeline_str = format!("autoaudiosrc ! {}", tail);
                     (pipeline_str, None)
                 } else {
                     return Err(MediaError::GStreamerError(
@@ -1111,10 +1837,8 @@ impl PipewireAudioCapturer {
                     match resolve_pulse_monitor_for_application(&app_name) {
                         Ok(source_name) => {
                             let escaped = gst_escape_property_value(&source_name);
-                            let pipeline_str = format!(
-                                "pulsesrc device=\"{}\" ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false",
-                                escaped
-                            );
+                            let pipeline_str =
+                                format!("pulsesrc device=\"{}\" ! {}", escaped, tail);
                             log::info!(
                                 "application audio route '{}' resolved to Pulse source '{}'",
                                 app_name,
@@ -1140,9 +1864,10 @@ impl PipewireAudioCapturer {
                                     ])
                                     .map_err(|e| MediaError::GStreamerError(e.to_string()))?;
                                     let pipeline_str = format!(
-                                        "pipewiresrc fd={} path={} do-timestamp=true ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false",
+                                        "pipewiresrc fd={} path={} do-timestamp=true ! {}",
                                         fd.as_raw_fd(),
-                                        node_id
+                                        node_id,
+                                        tail
                                     );
                                     (pipeline_str, Some(fd))
                                 }
@@ -1160,7 +1885,7 @@ impl PipewireAudioCapturer {
                                             "PipeWire audio portal failed, falling back to PulseAudio: {}",
                                             portal_err
                                         );
-                                        let pipeline_str = "pulsesrc ! audioconvert ! audioresample ! opusenc bitrate=128000 frame-size=5 ! appsink name=sink max-buffers=4 drop=true sync=false".to_string();
+                                        let pipeline_str = format!("pulsesrc ! {}", tail);
                                         (pipeline_str, None)
                                     } else {
                                         return Err(MediaError::PortalUnavailable(format!(
@@ -1199,44 +1924,115 @@ impl PipewireAudioCapturer {
             _fd: fd_opt,
             pipeline,
             appsink,
+            layout,
+            #[cfg(feature = "opus-support")]
+            multichannel,
         })
     }
 
     pub fn next_packet(&mut self) -> MediaResult<EncodedFrame> {
-        let sample = self.appsink.pull_sample().map_err(|_| {
-            // Check bus for specific errors if possible
-            let bus = self.pipeline.bus();
-            if let Some(bus) = bus {
-                if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
-                    if let gst::MessageView::Error(err) = msg.view() {
-                        return MediaError::StreamNodeLoss(format!(
-                            "Audio capture failed: {} ({})",
-                            err.error(),
-                            err.debug().unwrap_or_default()
-                        ));
-                    }
+        #[cfg(feature = "opus-support")]
+        if let Some(multichannel) = self.multichannel.as_mut() {
+            let capture_layout = self.layout.capture_layout();
+            let frame_len = OPUS_FRAME_SAMPLES * capture_layout.channels();
+            while multichannel.pcm.len() < frame_len {
+                let (data, pts) = pull_audio_buffer(&self.pipeline, &self.appsink)?;
+                if multichannel.pcm.is_empty() {
+                    multichannel.next_timestamp_us = pts;
                 }
+                multichannel.pcm.extend(
+                    data.chunks_exact(2)
+                        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])),
+                );
+                let excess = multichannel
+                    .pcm
+                    .len()
+                    .saturating_sub(AUDIO_MAX_BUFFER_FRAMES * frame_len);
+                let dropped = excess - excess % capture_layout.channels();
+                multichannel.pcm.drain(..dropped);
+                multichannel.next_timestamp_us += (dropped / capture_layout.channels()) as u64
+                    * 1_000_000
+                    / OPUS_SAMPLE_RATE as u64;
+            }
+
+            let mut frame: Vec<i16> = multichannel.pcm.drain(..frame_len).collect();
+            if self.layout.is_ambisonic() {
+                let speakers: Vec<f32> =
+                    frame.iter().map(|s| *s as f32 / i16::MAX as f32).collect();
+                frame = encode_foa(capture_layout, &speakers)
+                    .into_iter()
+                    .map(f32_to_i16)
+                    .collect();
             }
-            MediaError::GStreamerError("failed to pull audio sample".to_string())
-        })?;
-        let buffer = sample
-            .buffer()
-            .ok_or_else(|| MediaError::GStreamerError("missing audio buffer".to_string()))?;
-        let map = buffer
-            .map_readable()
-            .map_err(|_| MediaError::GStreamerError("audio buffer map failed".to_string()))?;
-        let pts = buffer.pts().map(|t| t.nseconds() / 1_000).unwrap_or(0);
 
+            let timestamp_us = multichannel.next_timestamp_us;
+            multichannel.next_timestamp_us += opus_frame_duration_us();
+            return Ok(EncodedFrame {
+                timestamp_us,
+                keyframe: true,
+                data: multichannel.encoder.encode(&frame)?,
+                capture_duration_us: 0,
+                encode_duration_us: 0,
+            });
+        }
+
+        let (data, pts) = pull_audio_buffer(&self.pipeline, &self.appsink)?;
         Ok(EncodedFrame {
             timestamp_us: pts,
             keyframe: true, // Audio packets are essentially all keyframes in Opus
-            data: map.as_slice().to_vec(),
+            data,
             capture_duration_us: 0,
             encode_duration_us: 0,
         })
     }
 }
 
+fn pull_audio_buffer(
+    pipeline: &gst::Pipeline,
+    appsink: &gst_app::AppSink,
+) -> MediaResult<(Vec<u8>, u64)> {
+    let sample = appsink.pull_sample().map_err(|_| {
+        // Check bus for specific errors if possible
+        let bus = pipeline.bus();
+        if let Some(bus) = bus {
+            if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+                if let gst::MessageView::Error(err) = msg.view() {
+                    return MediaError::StreamNodeLoss(format!(
+                        "Audio capture failed: {} ({})",
+                        err.error(),
+                        err.debug().unwrap_or_default()
+                    ));
+                }
+            }
+        }
+        MediaError::GStreamerError("failed to pull audio sample".to_string())
+    })?;
+    let buffer = sample
+        .buffer()
+        .ok_or_else(|| MediaError::GStreamerError("missing audio buffer".to_string()))?;
+    let map = buffer
+        .map_readable()
+        .map_err(|_| MediaError::GStreamerError("audio buffer map failed".to_string()))?;
+    let pts = buffer.pts().map(|t| t.nseconds() / 1_000).unwrap_or(0);
+    Ok((map.as_slice().to_vec(), pts))
+}
+
+fn audio_pipeline_tail(layout: AudioChannelLayout) -> String {
+    const SINK: &str = "appsink name=sink max-buffers=4 drop=true sync=false";
+    match layout {
+        AudioChannelLayout::Stereo => format!(
+            "audioconvert ! audioresample ! opusenc name=opus bitrate=128000 frame-size=5 ! {}",
+            SINK
+        ),
+        layout => format!(
+            "audioconvert ! audioresample ! audio/x-raw,format=S16LE,rate={},channels={},layout=interleaved ! {}",
+            OPUS_SAMPLE_RATE,
+            layout.capture_layout().channels(),
+            SINK
+        ),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum PipewireAudioRoute {
     SystemMix,
@@ -1393,7 +2189,7 @@ impl GstAudioRenderer {
             "audioresample",
             "autoaudiosink",
         ])?;
-        let pipeline_str = "appsrc name=src is-live=true format=time ! opusdec ! audioconvert ! audioresample ! autoaudiosink sync=false";
+        let pipeline_str = "appsrc name=src is-live=true format=time ! opusdec plc=true use-inband-fec=true ! audioconvert ! audioresample ! autoaudiosink sync=false";
         let pipeline = gst::parse::launch(pipeline_str)?
             .downcast::<gst::Pipeline>()
             .map_err(|_| anyhow!("failed to downcast audio pipeline"))?;
@@ -1436,6 +2232,80 @@ impl Drop for GstAudioRenderer {
     }
 }
 
+/// PipeWire node name of the virtual microphone carrying client voice.
+pub const VIRTUAL_MIC_NODE_NAME: &str = "wavry-microphone";
+
+/// Client microphone exposed as a PipeWire virtual source. It lives only as
+/// long as this value, so applications see "Wavry Microphone" while a
+/// client is sending voice.
+pub struct PipewireVirtualMicrophone {
+    appsrc: gst_app::AppSrc,
+    pipeline: gst::Pipeline,
+}
+
+impl PipewireVirtualMicrophone {
+    pub fn new() -> Result<Self> {
+        gst::init()?;
+        require_elements(&[
+            "appsrc",
+            "opusdec",
+            "audioconvert",
+            "audioresample",
+            "pipewiresink",
+        ])?;
+        let pipeline_str = "appsrc name=src is-live=true format=time ! opusdec plc=true use-inband-fec=true ! audioconvert ! audioresample ! pipewiresink name=sink mode=provide sync=false";
+        let pipeline = gst::parse::launch(pipeline_str)?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| anyhow!("failed to downcast virtual microphone pipeline"))?;
+
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("pipewiresink not found"))?;
+        let props = gst::Structure::builder("props")
+            .field("media.class", "Audio/Source/Virtual")
+            .field("node.name", VIRTUAL_MIC_NODE_NAME)
+            .field("node.description", "Wavry Microphone")
+            .build();
+        sink.set_property("stream-properties", props);
+
+        let appsrc = pipeline
+            .by_name("src")
+            .ok_or_else(|| anyhow!("appsrc not found"))?
+            .downcast::<gst_app::AppSrc>()
+            .map_err(|_| anyhow!("appsrc type mismatch"))?;
+
+        let caps = gst::Caps::builder("audio/x-opus")
+            .field("rate", 48_000i32)
+            .field("channels", 2i32)
+            .build();
+        appsrc.set_caps(Some(&caps));
+
+        pipeline.set_state(gst::State::Playing)?;
+        log::info!("client microphone available as {}", VIRTUAL_MIC_NODE_NAME);
+
+        Ok(Self { appsrc, pipeline })
+    }
+}
+
+impl Renderer for PipewireVirtualMicrophone {
+    fn render(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        let mut buffer = gst::Buffer::from_mut_slice(payload.to_vec());
+        if let Some(buffer_ref) = buffer.get_mut() {
+            buffer_ref.set_pts(gst::ClockTime::from_useconds(timestamp_us));
+        }
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|_| anyhow!("failed to push microphone buffer"))?;
+        Ok(())
+    }
+}
+
+impl Drop for PipewireVirtualMicrophone {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
 pub struct LinuxProbe;
 
 impl crate::CapabilityProbe for LinuxProbe {
@@ -1534,6 +2404,10 @@ impl crate::CapabilityProbe for LinuxProbe {
 
         Ok(displays)
     }
+
+    fn watch_displays(&self) -> Result<crate::DisplayWatch> {
+        crate::display_watch::watch()
+    }
 }
 
 fn decoder_available(codec: Codec) -> bool {
@@ -1723,9 +2597,68 @@ fn token_path() -> Option<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::{
-        backend_to_portal_descriptor, expected_portal_backends_from_desktop,
-        find_monitor_source_for_sink_from_sinks, find_sink_index_for_application_from_sink_inputs,
+        backend_to_portal_descriptor, dmabuf_importer, drm_format_modifier,
+        expected_portal_backends_from_desktop, find_monitor_source_for_sink_from_sinks,
+        find_sink_index_for_application_from_sink_inputs, svtav1_parameters, x26x_options,
     };
+    use crate::{Codec, ContentType, EncoderTuning, RefreshMode};
+
+    #[test]
+    fn svtav1_parameters_enable_screen_content_for_desktop() {
+        let desktop = EncoderTuning::for_codec(Codec::Av1, ContentType::Desktop);
+        assert_eq!(
+            svtav1_parameters(&desktop),
+            "scm=1:tile-columns=0:pred-struct=1"
+        );
+
+        let game = EncoderTuning {
+            low_latency: false,
+            ..EncoderTuning::for_codec(Codec::Av1, ContentType::Game)
+        };
+        assert_eq!(svtav1_parameters(&game), "scm=0:tile-columns=1");
+    }
+
+    #[test]
+    fn dmabuf_import_matches_the_encoder_plugin() {
+        assert_eq!(
+            dmabuf_importer("vaapih264enc"),
+            Some(("vaapipostproc", "memory:VASurface"))
+        );
+        assert_eq!(
+            dmabuf_importer("vah265enc"),
+            Some(("vapostproc", "memory:VAMemory"))
+        );
+        assert_eq!(dmabuf_importer("nvh264enc"), None);
+        assert_eq!(dmabuf_importer("x264enc"), None);
+    }
+
+    #[test]
+    fn drm_format_modifier_reads_explicit_and_linear_formats() {
+        assert_eq!(
+            drm_format_modifier("XR24:0x0100000000000001"),
+            Some(0x0100_0000_0000_0001)
+        );
+        assert_eq!(drm_format_modifier("NV12"), Some(0));
+        assert_eq!(drm_format_modifier("XR24:bogus"), None);
+        assert_eq!(drm_format_modifier(""), None);
+    }
+
+    #[test]
+    fn x26x_options_carry_slices_and_x265_intra_refresh() {
+        let tuning = EncoderTuning {
+            refresh: RefreshMode::IntraRefresh,
+            ..EncoderTuning::for_codec(Codec::Hevc, ContentType::Game)
+        };
+        assert_eq!(x26x_options("x265enc", &tuning), "slices=2:intra-refresh=1");
+        // x264enc takes intra refresh as a property instead.
+        assert_eq!(x26x_options("x264enc", &tuning), "slices=2");
+        let no_slices = EncoderTuning {
+            slices: 0,
+            refresh: RefreshMode::Idr,
+            ..tuning
+        };
+        assert_eq!(x26x_options("x264enc", &no_slices), "");
+    }
 
     #[test]
     fn find_sink_index_for_application_matches_binary() {
@@ -1821,6 +2754,9 @@ Sink #4
             display_id: None,
             enable_10bit: false,
             enable_hdr: false,
+            hide_cursor: false,
+            capture_mode: crate::CaptureMode::Fixed,
+            tuning: crate::EncoderTuning::for_codec(Codec::H264, crate::ContentType::Game),
         };
 
         let mut encoder = match super::PipewireEncoder::new(config).await {
@@ -45,12 +45,13 @@ use std::time::Instant;
 #[cfg(target_os = "macos")]
 use tokio::sync::oneshot;
 
+use crate::audio::codec::OpusConfig;
+#[cfg(feature = "opus-support")]
+use crate::audio::codec::{AudioEncoder, OpusAudioEncoder};
 use crate::audio::{
-    opus_frame_duration_us, AUDIO_MAX_BUFFER_SAMPLES, OPUS_CHANNELS, OPUS_FRAME_SAMPLES,
+    AUDIO_MAX_BUFFER_FRAMES, AUDIO_MAX_BUFFER_SAMPLES, OPUS_CHANNELS, OPUS_FRAME_SAMPLES,
     OPUS_SAMPLE_RATE,
 };
-#[cfg(feature = "opus-support")]
-use crate::audio::{OPUS_BITRATE_BPS, OPUS_MAX_PACKET_BYTES};
 use crate::EncodedFrame;
 
 #[cfg(target_os = "macos")]
@@ -61,10 +62,6 @@ pub enum MacAudioRoute {
     Application(String),
 }
 
-#[cfg(target_os = "macos")]
-#[cfg(feature = "opus-support")]
-use opus::{Application, Channels, Encoder as OpusEncoder};
-
 #[cfg(target_os = "macos")]
 struct AudioContext {
     // `tx` and `frame_duration_us` are only consumed inside the
@@ -75,9 +72,10 @@ struct AudioContext {
     tx: mpsc::Sender<EncodedFrame>,
     start_time: Instant,
     #[cfg(feature = "opus-support")]
-    encoder: OpusEncoder,
+    encoder: OpusAudioEncoder,
     pcm: VecDeque<i16>,
     next_timestamp_us: Option<u64>,
+    frame_samples: usize,
     #[cfg_attr(not(feature = "opus-support"), allow(dead_code))]
     frame_duration_us: u64,
     channels: usize,
@@ -85,6 +83,17 @@ struct AudioContext {
 
 #[cfg(target_os = "macos")]
 impl AudioContext {
+    fn set_opus_config(&mut self, config: OpusConfig) -> Result<()> {
+        let config = config.normalized();
+        #[cfg(feature = "opus-support")]
+        {
+            self.encoder = OpusAudioEncoder::new(self.channels, config)?;
+        }
+        self.frame_samples = config.frame_samples();
+        self.frame_duration_us = u64::from(config.frame_duration_us);
+        Ok(())
+    }
+
     fn ingest_chunk(&mut self, chunk: PcmChunk) {
         if self.next_timestamp_us.is_none() || self.pcm.is_empty() {
             self.next_timestamp_us = Some(chunk.timestamp_us);
@@ -92,8 +101,10 @@ impl AudioContext {
 
         self.pcm.extend(chunk.samples);
 
-        if self.pcm.len() > AUDIO_MAX_BUFFER_SAMPLES {
-            let drop = self.pcm.len() - AUDIO_MAX_BUFFER_SAMPLES;
+        let max_buffered = AUDIO_MAX_BUFFER_SAMPLES
+            .max(AUDIO_MAX_BUFFER_FRAMES * self.frame_samples * self.channels);
+        if self.pcm.len() > max_buffered {
+            let drop = self.pcm.len() - max_buffered;
             let aligned_drop = drop - (drop % self.channels.max(1));
             for _ in 0..aligned_drop {
                 self.pcm.pop_front();
@@ -107,18 +118,16 @@ impl AudioContext {
 
         #[cfg(feature = "opus-support")]
         {
-            let frame_len = OPUS_FRAME_SAMPLES * self.channels;
+            let frame_len = self.frame_samples * self.channels;
             while self.pcm.len() >= frame_len {
                 let frame: Vec<i16> = self.pcm.drain(..frame_len).collect();
-                let mut out = vec![0u8; OPUS_MAX_PACKET_BYTES];
-                let encoded = match self.encoder.encode(&frame, &mut out) {
-                    Ok(size) => size,
+                let out = match self.encoder.encode(&frame) {
+                    Ok(out) => out,
                     Err(err) => {
                         log::warn!("Opus encode error: {}", err);
                         break;
                     }
                 };
-                out.truncate(encoded);
 
                 let timestamp_us = self
                     .next_timestamp_us
@@ -149,20 +158,6 @@ struct PcmChunk {
     samples: Vec<i16>,
 }
 
-#[cfg(target_os = "macos")]
-#[cfg(feature = "opus-support")]
-fn create_opus_encoder() -> Result<OpusEncoder> {
-    let mut encoder = OpusEncoder::new(OPUS_SAMPLE_RATE, Channels::Stereo, Application::Audio)
-        .map_err(|e| anyhow!("Opus encoder init failed: {}", e))?;
-    encoder
-        .set_bitrate(opus::Bitrate::Bits(OPUS_BITRATE_BPS))
-        .map_err(|e| anyhow!("Opus bitrate set failed: {}", e))?;
-    encoder.set_complexity(5).ok();
-    encoder.set_inband_fec(false).ok();
-    encoder.set_dtx(false).ok();
-    Ok(encoder)
-}
-
 #[cfg(target_os = "macos")]
 define_class!(
     #[unsafe(super(NSObject))]
@@ -198,31 +193,8 @@ define_class!(
 
 #[cfg(target_os = "macos")]
 impl AudioHandler {
-    #[cfg(feature = "opus-support")]
-    fn new(tx: mpsc::Sender<EncodedFrame>, encoder: OpusEncoder) -> Retained<Self> {
-        let ivars = std::sync::Mutex::new(AudioContext {
-            tx,
-            start_time: Instant::now(),
-            encoder,
-            pcm: VecDeque::with_capacity(AUDIO_MAX_BUFFER_SAMPLES),
-            next_timestamp_us: None,
-            frame_duration_us: opus_frame_duration_us(),
-            channels: OPUS_CHANNELS,
-        });
-        let this = Self::alloc().set_ivars(ivars);
-        unsafe { msg_send![super(this), init] }
-    }
-
-    #[cfg(not(feature = "opus-support"))]
-    fn new(tx: mpsc::Sender<EncodedFrame>) -> Retained<Self> {
-        let ivars = std::sync::Mutex::new(AudioContext {
-            tx,
-            start_time: Instant::now(),
-            pcm: VecDeque::with_capacity(AUDIO_MAX_BUFFER_SAMPLES),
-            next_timestamp_us: None,
-            frame_duration_us: opus_frame_duration_us(),
-            channels: OPUS_CHANNELS,
-        });
+    fn new(context: AudioContext) -> Retained<Self> {
+        let ivars = std::sync::Mutex::new(context);
         let this = Self::alloc().set_ivars(ivars);
         unsafe { msg_send![super(this), init] }
     }
@@ -594,31 +566,18 @@ fn read_sample_i16(
 
 #[cfg(target_os = "macos")]
 fn build_audio_context(tx: mpsc::Sender<EncodedFrame>) -> Result<AudioContext> {
-    #[cfg(feature = "opus-support")]
-    {
-        let encoder = create_opus_encoder()?;
-        Ok(AudioContext {
-            tx,
-            start_time: Instant::now(),
-            encoder,
-            pcm: VecDeque::with_capacity(AUDIO_MAX_BUFFER_SAMPLES),
-            next_timestamp_us: None,
-            frame_duration_us: opus_frame_duration_us(),
-            channels: OPUS_CHANNELS,
-        })
-    }
-
-    #[cfg(not(feature = "opus-support"))]
-    {
-        Ok(AudioContext {
-            tx,
-            start_time: Instant::now(),
-            pcm: VecDeque::with_capacity(AUDIO_MAX_BUFFER_SAMPLES),
-            next_timestamp_us: None,
-            frame_duration_us: opus_frame_duration_us(),
-            channels: OPUS_CHANNELS,
-        })
-    }
+    let opus = OpusConfig::default();
+    Ok(AudioContext {
+        tx,
+        start_time: Instant::now(),
+        #[cfg(feature = "opus-support")]
+        encoder: OpusAudioEncoder::new(OPUS_CHANNELS, opus)?,
+        pcm: VecDeque::with_capacity(AUDIO_MAX_BUFFER_SAMPLES),
+        next_timestamp_us: None,
+        frame_samples: opus.frame_samples(),
+        frame_duration_us: u64::from(opus.frame_duration_us),
+        channels: OPUS_CHANNELS,
+    })
 }
 
 #[cfg(target_os = "macos")]
@@ -782,7 +741,9 @@ fn build_input_stream_u16(
 }
 
 #[cfg(target_os = "macos")]
-fn start_microphone_capture(tx: mpsc::Sender<EncodedFrame>) -> Result<Stream> {
+fn start_microphone_capture(
+    tx: mpsc::Sender<EncodedFrame>,
+) -> Result<(Stream, Arc<Mutex<AudioContext>>)> {
     let host = cpal::default_host();
     let device = host
         .default_input_device()
@@ -812,7 +773,7 @@ fn start_microphone_capture(tx: mpsc::Sender<EncodedFrame>) -> Result<Stream> {
     stream
         .play()
         .map_err(|e| anyhow!("failed to start microphone capture stream: {}", e))?;
-    Ok(stream)
+    Ok((stream, context))
 }
 
 pub struct MacAudioCapturer {
@@ -824,6 +785,8 @@ pub struct MacAudioCapturer {
     _queue: Option<DispatchRetained<dispatch2::DispatchQueue>>,
     #[cfg(target_os = "macos")]
     _cpal_stream: Option<Stream>,
+    #[cfg(target_os = "macos")]
+    mic_context: Option<Arc<Mutex<AudioContext>>>,
     rx: mpsc::Receiver<EncodedFrame>,
 }
 
@@ -842,25 +805,20 @@ impl MacAudioCapturer {
         match route {
             MacAudioRoute::Microphone => {
                 log::info!("starting macOS microphone capture route");
-                let stream = start_microphone_capture(tx)?;
+                let (stream, context) = start_microphone_capture(tx)?;
                 Ok(Self {
                     _stream: None,
                     _output_handler: None,
                     _queue: None,
                     _cpal_stream: Some(stream),
+                    mic_context: Some(context),
                     rx,
                 })
             }
             MacAudioRoute::SystemMix | MacAudioRoute::Application(_) => {
                 let content = get_shareable_content().await?.0;
 
-                #[cfg(feature = "opus-support")]
-                let output_handler = {
-                    let encoder = create_opus_encoder()?;
-                    AudioHandler::new(tx, encoder)
-                };
-                #[cfg(not(feature = "opus-support"))]
-                let output_handler = AudioHandler::new(tx);
+                let output_handler = AudioHandler::new(build_audio_context(tx)?);
 
                 let (stream, queue, rx_start) = setup_stream(content, &output_handler, &route)?;
 
@@ -873,6 +831,7 @@ impl MacAudioCapturer {
                     _output_handler: Some(output_handler),
                     _queue: Some(queue),
                     _cpal_stream: None,
+                    mic_context: None,
                     rx,
                 })
             }
@@ -884,6 +843,26 @@ impl MacAudioCapturer {
         Err(anyhow!("Not supported on this OS"))
     }
 
+    /// Switches to the negotiated bitrate, frame size and FEC setting.
+    #[cfg(target_os = "macos")]
+    pub fn set_opus_config(&mut self, config: OpusConfig) -> Result<()> {
+        let context = match (&self.mic_context, &self._output_handler) {
+            (Some(context), _) => context.as_ref(),
+            (None, Some(handler)) => handler.ivars(),
+            (None, None) => return Ok(()),
+        };
+        let mut guard = match context.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        guard.set_opus_config(config)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn set_opus_config(&mut self, _config: OpusConfig) -> Result<()> {
+        Ok(())
+    }
+
     pub fn next_packet(&mut self) -> Result<EncodedFrame> {
         self.rx
             .blocking_recv()
@@ -15,8 +15,8 @@ impl MacAudioRenderer {
         })
     }
 
-    pub fn push(&mut self, payload: &[u8]) -> Result<()> {
-        self.inner.push(payload)
+    pub fn push(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        self.inner.push(payload, timestamp_us)
     }
 }
 
@@ -25,3 +25,25 @@ impl Renderer for MacAudioRenderer {
         self.inner.render(payload, timestamp_us)
     }
 }
+
+/// Client microphone played into a loopback device such as BlackHole, whose
+/// input side host applications use as a microphone.
+pub struct MacVirtualMicrophone {
+    inner: CpalAudioRenderer,
+}
+
+unsafe impl Send for MacVirtualMicrophone {}
+
+impl MacVirtualMicrophone {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inner: CpalAudioRenderer::new_virtual_microphone()?,
+        })
+    }
+}
+
+impl Renderer for MacVirtualMicrophone {
+    fn render(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        self.inner.render(payload, timestamp_us)
+    }
+}
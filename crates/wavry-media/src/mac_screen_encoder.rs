@@ -4,7 +4,9 @@
     deprecated,
     clippy::arc_with_non_send_sync
 )]
-use crate::{Codec, EncodeConfig, EncodedFrame};
+use crate::{
+    CaptureMode, Codec, DamageGate, EncodeConfig, EncodedFrame, EncoderStats, FrameRateMeter,
+};
 use anyhow::{anyhow, Result};
 use tokio::sync::{mpsc, oneshot};
 
@@ -60,6 +62,7 @@ extern "C" {
     static kVTCompressionPropertyKey_ExpectedFrameRate: *const c_void;
     static kVTCompressionPropertyKey_DataRateLimits: *const c_void;
     static kVTCompressionPropertyKey_MaximizePowerEfficiency: *const c_void;
+    static kVTCompressionPropertyKey_PrioritizeEncodingSpeedOverQuality: *const c_void;
     static kVTCompressionPropertyKey_H264EntropyMode: *const c_void;
     static kVTCompressionPropertyKey_ColorPrimaries: *const c_void;
     static kVTCompressionPropertyKey_TransferFunction: *const c_void;
@@ -123,16 +126,33 @@ extern "C" {
     fn CFArrayGetValueAtIndex(array: *const c_void, idx: isize) -> *const c_void;
     fn CFDictionaryGetValue(dict: *const c_void, key: *const c_void) -> *const c_void;
     fn CFBooleanGetValue(boolean: *const c_void) -> bool;
+    fn CFNumberGetValue(number: *const c_void, the_type: i64, value_ptr: *mut c_void) -> bool;
+    fn CFRetain(cf: *const c_void) -> *const c_void;
 
     // Dictionary keys for sample buffer attachments
     static kCMSampleAttachmentKey_NotSync: *const c_void;
     static kCMSampleAttachmentKey_DependsOnOthers: *const c_void;
 }
 
+#[cfg(target_os = "macos")]
+#[link(name = "ScreenCaptureKit", kind = "framework")]
+extern "C" {
+    static SCStreamFrameInfoStatus: *const c_void;
+    static SCStreamFrameInfoDirtyRects: *const c_void;
+}
+
+// SCFrameStatus values
+#[cfg(target_os = "macos")]
+const SC_FRAME_STATUS_COMPLETE: i64 = 0;
+#[cfg(target_os = "macos")]
+const SC_FRAME_STATUS_IDLE: i64 = 1;
+
 // CFNumber types
 #[cfg(target_os = "macos")]
 const K_CFNUMBER_INT32_TYPE: i64 = 3;
 #[cfg(target_os = "macos")]
+const K_CFNUMBER_SINT64_TYPE: i64 = 4;
+#[cfg(target_os = "macos")]
 const K_CFNUMBER_FLOAT64_TYPE: i64 = 13;
 
 // Shared context for encoding
@@ -148,6 +168,54 @@ struct EncoderContext {
 struct OutputHandlerIvars {
     session_ptr: *mut c_void,
     start_time: std::time::Instant,
+    /// Set in damage capture mode.
+    damage: Option<std::sync::Mutex<DamageState>>,
+}
+
+/// Damage-mode state: which frames to encode, and the last picture to repeat
+/// while the screen is idle.
+#[cfg(target_os = "macos")]
+struct DamageState {
+    gate: DamageGate,
+    /// Retained CVPixelBuffer of the last encoded frame.
+    last_pixel_buffer: *mut c_void,
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for DamageState {
+    fn drop(&mut self) {
+        if !self.last_pixel_buffer.is_null() {
+            unsafe { CFRelease(self.last_pixel_buffer) };
+        }
+    }
+}
+
+/// SCFrameStatus of a captured sample, and how many dirty rects it reports.
+#[cfg(target_os = "macos")]
+fn frame_info(sample_buffer: &CMSampleBuffer) -> (Option<i64>, Option<isize>) {
+    let attachments =
+        unsafe { CMSampleBufferGetSampleAttachmentsArray(sample_buffer as *const _, false) };
+    if attachments.is_null() || unsafe { CFArrayGetCount(attachments) } == 0 {
+        return (None, None);
+    }
+    let info = unsafe { CFArrayGetValueAtIndex(attachments, 0) };
+    let status = unsafe { CFDictionaryGetValue(info, SCStreamFrameInfoStatus) };
+    let status = (!status.is_null())
+        .then(|| {
+            let mut value = 0i64;
+            unsafe {
+                CFNumberGetValue(
+                    status,
+                    K_CFNUMBER_SINT64_TYPE,
+                    &mut value as *mut i64 as *mut c_void,
+                )
+            }
+            .then_some(value)
+        })
+        .flatten();
+    let dirty = unsafe { CFDictionaryGetValue(info, SCStreamFrameInfoDirtyRects) };
+    let dirty = (!dirty.is_null()).then(|| unsafe { CFArrayGetCount(dirty) });
+    (status, dirty)
 }
 
 #[cfg(target_os = "macos")]
@@ -184,7 +252,42 @@ define_class!(
             }
 
             // Extract CVPixelBuffer from CMSampleBuffer
-            let pixel_buffer = unsafe { CMSampleBufferGetImageBuffer(sample_buffer as *const _) };
+            let mut pixel_buffer =
+                unsafe { CMSampleBufferGetImageBuffer(sample_buffer as *const _) };
+
+            // ScreenCaptureKit only sends new pictures when something changed,
+            // and idle-status samples without one in between. Complete frames
+            // with no dirty rects are skipped, and idle samples repeat the last
+            // picture at the keepalive rate.
+            let mut damage = ivars.damage.as_ref().and_then(|damage| damage.lock().ok());
+            if let Some(state) = damage.as_mut() {
+                let now = std::time::Instant::now();
+                let (status, dirty_rects) = frame_info(sample_buffer);
+                let complete = status.is_none_or(|status| status == SC_FRAME_STATUS_COMPLETE);
+                if complete && !pixel_buffer.is_null() {
+                    if !state
+                        .gate
+                        .admit(dirty_rects.is_none_or(|count| count > 0), now)
+                    {
+                        return;
+                    }
+                    unsafe {
+                        CFRetain(pixel_buffer);
+                        if !state.last_pixel_buffer.is_null() {
+                            CFRelease(state.last_pixel_buffer);
+                        }
+                    }
+                    state.last_pixel_buffer = pixel_buffer;
+                } else if status == Some(SC_FRAME_STATUS_IDLE)
+                    && !state.last_pixel_buffer.is_null()
+                    && state.gate.keepalive_due(now)
+                {
+                    state.gate.admit(false, now);
+                    pixel_buffer = state.last_pixel_buffer;
+                } else {
+                    return;
+                }
+            }
             if pixel_buffer.is_null() {
                 return;
             }
@@ -506,6 +609,18 @@ fn create_compression_session(
             kCFBooleanFalse,
         );
 
+        // VideoToolbox exposes no slice, intra-refresh or screen-content
+        // controls, so only the speed/quality trade-off of the tuning applies.
+        VTSessionSetProperty(
+            session,
+            kVTCompressionPropertyKey_PrioritizeEncodingSpeedOverQuality,
+            if config.tuning.low_latency {
+                kCFBooleanTrue
+            } else {
+                kCFBooleanFalse
+            },
+        );
+
         // H.264-only entropy setting for better quality/efficiency at the same bitrate.
         if config.codec == Codec::H264 {
             let cabac = b"CABAC\0";
@@ -532,10 +647,17 @@ fn create_compression_session(
 
 #[cfg(target_os = "macos")]
 impl OutputHandler {
-    fn new(session_ptr: *mut c_void) -> Retained<Self> {
+    fn new(session_ptr: *mut c_void, capture_mode: CaptureMode) -> Retained<Self> {
+        let damage = (capture_mode == CaptureMode::Damage).then(|| {
+            std::sync::Mutex::new(DamageState {
+                gate: DamageGate::new(capture_mode),
+                last_pixel_buffer: std::ptr::null_mut(),
+            })
+        });
         let ivars = OutputHandlerIvars {
             session_ptr,
             start_time: std::time::Instant::now(),
+            damage,
         };
         let this = Self::alloc().set_ivars(ivars);
         unsafe { msg_send![super(this), init] }
@@ -559,6 +681,8 @@ pub struct MacScreenEncoder {
     _queue: DispatchRetained<dispatch2::DispatchQueue>,
 
     rx: mpsc::Receiver<EncodedFrame>,
+    fps: FrameRateMeter,
+    stats: EncoderStats,
 }
 
 #[cfg(target_os = "macos")]
@@ -672,7 +796,11 @@ impl MacScreenEncoder {
                 stream_config.setPixelFormat(0x42475241); // 'BGRA'
             }
 
-            stream_config.setShowsCursor(true);
+            stream_config.setShowsCursor(!config.hide_cursor);
+            if config.capture_mode == CaptureMode::Damage {
+                // One surface stays held for keepalive repeats.
+                stream_config.setQueueDepth(4);
+            }
             stream_config.setMinimumFrameInterval(CMTime {
                 value: 1,
                 timescale: config.fps as i32,
@@ -742,7 +870,7 @@ impl MacScreenEncoder {
         let (session_ptr, encoder_context) = create_compression_session(config, tx)?;
         let send_session_ptr = SendPtr(session_ptr);
 
-        let output_handler = OutputHandler::new(send_session_ptr.0);
+        let output_handler = OutputHandler::new(send_session_ptr.0, config.capture_mode);
         let send_output_handler = SendRetained(output_handler);
 
         // 3. Setup Stream
@@ -782,20 +910,53 @@ impl MacScreenEncoder {
             encoder_context: Some(encoder_context),
             _queue: queue,
             rx,
+            fps: FrameRateMeter::new(),
+            stats: EncoderStats::default(),
         })
     }
 
     pub fn next_frame(&mut self) -> Result<EncodedFrame> {
-        self.rx
+        let frame = self
+            .rx
             .blocking_recv()
-            .ok_or_else(|| anyhow!("encoder stream closed"))
+            .ok_or_else(|| anyhow!("encoder stream closed"))?;
+        self.note_frame();
+        Ok(frame)
     }
 
     pub async fn next_frame_async(&mut self) -> Result<EncodedFrame> {
-        self.rx
+        let frame = self
+            .rx
             .recv()
             .await
-            .ok_or_else(|| anyhow!("encoder stream closed"))
+            .ok_or_else(|| anyhow!("encoder stream closed"))?;
+        self.note_frame();
+        Ok(frame)
+    }
+
+    fn note_frame(&mut self) {
+        let now = std::time::Instant::now();
+        self.fps.record(now);
+        self.stats.fps = self.fps.fps(now);
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn stats(&self) -> EncoderStats {
+        let unchanged_frames = self
+            .output_handler
+            .as_ref()
+            .and_then(|handler| handler.ivars().damage.as_ref())
+            .and_then(|damage| damage.lock().ok().map(|state| state.gate.skipped()))
+            .unwrap_or_default();
+        EncoderStats {
+            unchanged_frames,
+            ..self.stats
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn stats(&self) -> EncoderStats {
+        self.stats
     }
 
     #[cfg(target_os = "macos")]
@@ -931,6 +1092,10 @@ impl crate::CapabilityProbe for MacProbe {
         #[cfg(not(target_os = "macos"))]
         Ok(vec![])
     }
+
+    fn watch_displays(&self) -> Result<crate::DisplayWatch> {
+        crate::display_watch::watch()
+    }
 }
 
 #[cfg(all(test, target_os = "macos"))]
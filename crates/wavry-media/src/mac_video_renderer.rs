@@ -6,11 +6,12 @@
 use anyhow::{anyhow, Result};
 use core::ffi::c_int;
 use log::{debug, error, info, warn};
-use objc2::msg_send;
 use objc2::rc::Retained;
-use objc2::runtime::AnyObject;
+use objc2::runtime::{AnyClass, AnyObject};
+use objc2::{msg_send, sel};
 use objc2_core_media::{
-    CMBlockBuffer, CMSampleBuffer, CMTime, CMTimeFlags, CMVideoFormatDescription,
+    CMBlockBuffer, CMClock, CMSampleBuffer, CMTime, CMTimeFlags, CMTimebase,
+    CMVideoFormatDescription,
 };
 use objc2_core_video::{CVBuffer, CVImageBuffer};
 use objc2_video_toolbox::{
@@ -19,6 +20,9 @@ use objc2_video_toolbox::{
 };
 use std::ffi::c_void;
 use std::ptr::NonNull;
+use std::sync::Mutex;
+
+use crate::pacing::{PresentScheduler, PresentStats, Presentation, DEFAULT_REFRESH_HZ};
 
 type OSStatus = i32;
 
@@ -94,6 +98,16 @@ extern "C" {
         sample_buffer_out: *mut *mut CMSampleBuffer,
     ) -> OSStatus;
 
+    fn CMClockGetHostTimeClock() -> *mut CMClock;
+    fn CMClockGetTime(clock: *mut CMClock) -> CMTime;
+    fn CMTimebaseCreateWithSourceClock(
+        allocator: *const c_void,
+        source_clock: *mut CMClock,
+        timebase_out: *mut *mut CMTimebase,
+    ) -> OSStatus;
+    fn CMTimebaseSetTime(timebase: *mut CMTimebase, time: CMTime) -> OSStatus;
+    fn CMTimebaseSetRate(timebase: *mut CMTimebase, rate: f64) -> OSStatus;
+
     fn CFRelease(cf: *const c_void);
     fn VTDecompressionSessionInvalidate(session: *mut VTDecompressionSession);
     fn VTDecompressionSessionDecodeFrame(
@@ -109,9 +123,86 @@ extern "C" {
 const K_VT_DECODE_FRAME_ENABLE_ASYNC_DECOMPRESSION: u32 = 1 << 0;
 const K_VT_DECODE_FRAME_DO_NOT_OUTPUT_FRAME: u32 = 1 << 1;
 
+/// kCMTimeFlags_Valid
+const CM_TIME_VALID: CMTimeFlags = CMTimeFlags(1);
+
+#[repr(C)]
+struct CMSampleTimingInfo {
+    duration: CMTime,
+    presentation_time_stamp: CMTime,
+    decode_time_stamp: CMTime,
+}
+
+/// kCMTimeInvalid
+fn invalid_time() -> CMTime {
+    CMTime {
+        value: 0,
+        timescale: 0,
+        flags: CMTimeFlags(0),
+        epoch: 0,
+    }
+}
+
+fn micros_time(value_us: u64) -> CMTime {
+    CMTime {
+        value: value_us as i64,
+        timescale: 1_000_000,
+        flags: CM_TIME_VALID,
+        epoch: 0,
+    }
+}
+
+/// Host clock time, which the display layer's timebase follows.
+fn host_time_us() -> u64 {
+    let now = unsafe { CMClockGetTime(CMClockGetHostTimeClock()) };
+    if now.timescale <= 0 {
+        return 0;
+    }
+    (i128::from(now.value) * 1_000_000 / i128::from(now.timescale)) as u64
+}
+
+/// Refresh rate of the main screen, where macOS reports one (12.0 and later).
+fn main_screen_refresh_hz() -> Option<f32> {
+    let class = AnyClass::get(c"NSScreen")?;
+    unsafe {
+        let screen: *mut AnyObject = msg_send![class, mainScreen];
+        if screen.is_null() {
+            return None;
+        }
+        let known: bool = msg_send![screen, respondsToSelector: sel!(maximumFramesPerSecond)];
+        if !known {
+            return None;
+        }
+        let fps: isize = msg_send![screen, maximumFramesPerSecond];
+        (fps > 0).then_some(fps as f32)
+    }
+}
+
+/// Drives the layer from the host clock, so enqueued frames show at their
+/// presentation time instead of on arrival.
+fn attach_host_timebase(layer: &Retained<AnyObject>) -> Result<()> {
+    unsafe {
+        let clock = CMClockGetHostTimeClock();
+        let mut timebase: *mut CMTimebase = std::ptr::null_mut();
+        let status = CMTimebaseCreateWithSourceClock(std::ptr::null(), clock, &mut timebase);
+        if status != 0 || timebase.is_null() {
+            return Err(anyhow!(
+                "CMTimebaseCreateWithSourceClock failed: {}",
+                status
+            ));
+        }
+        CMTimebaseSetTime(timebase, CMClockGetTime(clock));
+        CMTimebaseSetRate(timebase, 1.0);
+        let _: () = msg_send![layer, setControlTimebase: timebase];
+        CFRelease(timebase as *const c_void);
+    }
+    Ok(())
+}
+
 /// Struct to pass context to the decompression callback
 struct DecoderContext {
     layer: Retained<AnyObject>,
+    pacing: Mutex<PresentScheduler>,
 }
 
 pub struct MacVideoRenderer {
@@ -164,30 +255,28 @@ unsafe extern "C-unwind" fn decompression_callback(
     // This is a simplified approach - directly enqueueing the pixel buffer
     // In practice, we'd create a proper CMSampleBuffer with timing info
 
-    // Create timing info for the sample
-    #[repr(C)]
-    struct CMSampleTimingInfo {
-        duration: CMTime,
-        presentation_time_stamp: CMTime,
-        decode_time_stamp: CMTime,
+    // The host timestamp set in decode_frame comes back here; swap it for
+    // the host-clock time the frame should go on screen.
+    let mut present_at = presentation_time_stamp;
+    if presentation_time_stamp.flags.0 & CM_TIME_VALID.0 != 0 {
+        let timestamp_us = presentation_time_stamp.value.max(0) as u64;
+        let now_us = host_time_us();
+        let presentation = match ctx.pacing.lock() {
+            Ok(mut pacing) => pacing.schedule(timestamp_us, now_us),
+            Err(_) => Presentation::At(now_us),
+        };
+        match presentation {
+            Presentation::Drop => return,
+            Presentation::At(slot_us) => present_at = micros_time(slot_us),
+        }
     }
 
     // We don't have duration info easily available here without tracking previous frames,
     // but for display it matters less. We can set invalid duration.
     let timing = CMSampleTimingInfo {
-        duration: CMTime {
-            value: 0,
-            timescale: 0,
-            flags: CMTimeFlags(0),
-            epoch: 0,
-        }, // kCMTimeInvalid
-        presentation_time_stamp,
-        decode_time_stamp: CMTime {
-            value: 0,
-            timescale: 0,
-            flags: CMTimeFlags(0),
-            epoch: 0,
-        }, // kCMTimeInvalid
+        duration: invalid_time(),
+        presentation_time_stamp: present_at,
+        decode_time_stamp: invalid_time(),
     };
 
     let mut sample_buffer: *mut CMSampleBuffer = std::ptr::null_mut();
@@ -231,7 +320,14 @@ impl MacVideoRenderer {
         let layer = unsafe { Retained::retain(layer_ptr as *mut AnyObject) }
             .ok_or(anyhow!("Failed to retain layer"))?;
 
-        let context = Box::new(DecoderContext { layer });
+        if let Err(err) = attach_host_timebase(&layer) {
+            warn!("frames will show on arrival: {}", err);
+        }
+        let refresh_hz = main_screen_refresh_hz().unwrap_or(DEFAULT_REFRESH_HZ);
+        let context = Box::new(DecoderContext {
+            layer,
+            pacing: Mutex::new(PresentScheduler::new(refresh_hz)),
+        });
         let context_ptr = Box::into_raw(context);
 
         info!("MacVideoRenderer created");
@@ -415,12 +511,11 @@ impl MacVideoRenderer {
             ));
         }
 
-        // Create timing info
-        let pts = CMTime {
-            value: timestamp_us as i64,
-            timescale: 1_000_000,  // microseconds
-            flags: CMTimeFlags(1), // kCMTimeFlags_Valid
-            epoch: 0,
+        // The host timestamp rides through the decoder to the output callback.
+        let timing = CMSampleTimingInfo {
+            duration: invalid_time(),
+            presentation_time_stamp: micros_time(timestamp_us),
+            decode_time_stamp: invalid_time(),
         };
 
         // Sample size
@@ -431,18 +526,18 @@ impl MacVideoRenderer {
 
         let status = unsafe {
             CMSampleBufferCreate(
-                std::ptr::null(),     // allocator
-                block_buffer,         // data buffer
-                true,                 // data is ready
-                std::ptr::null(),     // make data ready callback
-                std::ptr::null_mut(), // make data ready refcon
-                self.format_desc,     // format description
-                1,                    // num samples
-                0,                    // num sample timing entries (0 = no timing)
-                std::ptr::null(),     // sample timing array
-                1,                    // num sample size entries
-                &sample_size,         // sample size array
-                &mut sample_buffer,   // output
+                std::ptr::null(),                     // allocator
+                block_buffer,                         // data buffer
+                true,                                 // data is ready
+                std::ptr::null(),                     // make data ready callback
+                std::ptr::null_mut(),                 // make data ready refcon
+                self.format_desc,                     // format description
+                1,                                    // num samples
+                1,                                    // num sample timing entries
+                &timing as *const _ as *const c_void, // sample timing array
+                1,                                    // num sample size entries
+                &sample_size,                         // sample size array
+                &mut sample_buffer,                   // output
             )
         };
 
@@ -490,6 +585,17 @@ impl MacVideoRenderer {
     pub fn frames_decoded(&self) -> u64 {
         self.frames_decoded
     }
+
+    pub fn present_stats(&self) -> PresentStats {
+        if self.context.is_null() {
+            return PresentStats::default();
+        }
+        let ctx = unsafe { &*self.context };
+        ctx.pacing
+            .lock()
+            .map(|pacing| pacing.stats())
+            .unwrap_or_default()
+    }
 }
 
 impl crate::Renderer for MacVideoRenderer {
@@ -514,6 +620,16 @@ impl crate::Renderer for MacVideoRenderer {
 
         Ok(())
     }
+
+    fn set_display_refresh_hz(&mut self, refresh_hz: f32) {
+        if self.context.is_null() {
+            return;
+        }
+        let ctx = unsafe { &*self.context };
+        if let Ok(mut pacing) = ctx.pacing.lock() {
+            pacing.set_refresh_hz(refresh_hz);
+        }
+    }
 }
 
 impl MacVideoRenderer {
@@ -579,9 +695,10 @@ impl Drop for MacVideoRenderer {
                 let _ = Box::from_raw(self.context);
             }
         }
+        let present = self.present_stats();
         info!(
-            "MacVideoRenderer dropped (decoded {} frames)",
-            self.frames_decoded
+            "MacVideoRenderer dropped (decoded {} frames, presented {}, dropped {}, repeated {})",
+            self.frames_decoded, present.presented, present.dropped, present.repeated
         );
     }
 }
@@ -0,0 +1,323 @@
+//! Minimal Matroska muxer for session recordings.
+//!
+//! Files are written live-style: the segment and every cluster have unknown
+//! size, so nothing is patched afterwards and a recording cut short by a
+//! crash still plays up to its last complete block. Timestamps are in
+//! milliseconds.
+
+use std::io::{self, Write};
+
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const SEEK_PRE_ROLL: u32 = 0x56BB;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const CLUSTER: u32 = 0x1F43_B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+/// Block timestamps are signed 16-bit offsets from their cluster.
+const MAX_CLUSTER_SPAN_MS: i64 = i16::MAX as i64;
+/// Opus decoders need this much audio before a seek point to converge.
+const OPUS_SEEK_PRE_ROLL_NS: u64 = 80_000_000;
+
+#[derive(Debug, Clone)]
+pub struct MkvTrack {
+    pub number: u8,
+    pub codec_id: &'static str,
+    pub codec_private: Option<Vec<u8>>,
+    pub kind: MkvTrackKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MkvTrackKind {
+    Video { width: u16, height: u16 },
+    Audio { sample_rate: u32, channels: u8 },
+}
+
+pub struct MkvWriter<W: Write> {
+    out: W,
+    video_tracks: Vec<u8>,
+    cluster_start_ms: Option<u64>,
+    bytes_written: u64,
+}
+
+impl<W: Write> MkvWriter<W> {
+    pub fn new(out: W, tracks: &[MkvTrack]) -> io::Result<Self> {
+        let mut writer = Self {
+            out,
+            video_tracks: tracks
+                .iter()
+                .filter(|track| matches!(track.kind, MkvTrackKind::Video { .. }))
+                .map(|track| track.number)
+                .collect(),
+            cluster_start_ms: None,
+            bytes_written: 0,
+        };
+
+        let header = element(
+            EBML,
+            &[
+                uint(EBML_VERSION, 1),
+                uint(EBML_READ_VERSION, 1),
+                uint(EBML_MAX_ID_LENGTH, 4),
+                uint(EBML_MAX_SIZE_LENGTH, 8),
+                string(DOC_TYPE, "matroska"),
+                uint(DOC_TYPE_VERSION, 4),
+                uint(DOC_TYPE_READ_VERSION, 2),
+            ]
+            .concat(),
+        );
+        writer.write(&header)?;
+        writer.write(&[id_bytes(SEGMENT), UNKNOWN_SIZE.to_vec()].concat())?;
+
+        let app = concat!("wavry-media ", env!("CARGO_PKG_VERSION"));
+        let info = element(
+            INFO,
+            &[
+                uint(TIMESTAMP_SCALE, 1_000_000),
+                string(MUXING_APP, app),
+                string(WRITING_APP, app),
+            ]
+            .concat(),
+        );
+        writer.write(&info)?;
+
+        let entries: Vec<u8> = tracks.iter().flat_map(track_entry).collect();
+        writer.write(&element(TRACKS, &entries))?;
+        Ok(writer)
+    }
+
+    /// Appends one frame. Video keyframes open a new cluster so players can
+    /// seek to them.
+    pub fn write_block(
+        &mut self,
+        track: u8,
+        timestamp_ms: u64,
+        keyframe: bool,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let relative = self
+            .cluster_start_ms
+            .map(|start| timestamp_ms as i64 - start as i64);
+        let new_cluster = match relative {
+            None => true,
+            Some(relative) => {
+                !(i64::from(i16::MIN)..=MAX_CLUSTER_SPAN_MS).contains(&relative)
+                    || (keyframe && relative > 0 && self.video_tracks.contains(&track))
+            }
+        };
+        if new_cluster {
+            let cluster = [
+                id_bytes(CLUSTER),
+                UNKNOWN_SIZE.to_vec(),
+                uint(CLUSTER_TIMESTAMP, timestamp_ms),
+            ]
+            .concat();
+            self.write(&cluster)?;
+            self.cluster_start_ms = Some(timestamp_ms);
+        }
+        let relative = timestamp_ms as i64 - self.cluster_start_ms.unwrap_or(timestamp_ms) as i64;
+
+        let mut body = Vec::with_capacity(data.len() + 4);
+        body.push(0x80 | track);
+        body.extend_from_slice(&(relative as i16).to_be_bytes());
+        body.push(if keyframe { 0x80 } else { 0x00 });
+        body.extend_from_slice(data);
+        self.write(&element(SIMPLE_BLOCK, &body))
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.bytes_written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// `OpusHead` codec private data for mono or stereo streams.
+pub fn opus_head(channels: u8, sample_rate: u32) -> Vec<u8> {
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1);
+    head.push(channels);
+    head.extend_from_slice(&0u16.to_le_bytes());
+    head.extend_from_slice(&sample_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
+
+fn track_entry(track: &MkvTrack) -> Vec<u8> {
+    let mut body = [
+        uint(TRACK_NUMBER, u64::from(track.number)),
+        uint(TRACK_UID, u64::from(track.number)),
+        uint(
+            TRACK_TYPE,
+            match track.kind {
+                MkvTrackKind::Video { .. } => 1,
+                MkvTrackKind::Audio { .. } => 2,
+            },
+        ),
+        uint(FLAG_LACING, 0),
+        string(CODEC_ID, track.codec_id),
+    ]
+    .concat();
+    if let Some(private) = track.codec_private.as_ref() {
+        body.extend(element(CODEC_PRIVATE, private));
+    }
+    match track.kind {
+        MkvTrackKind::Video { width, height } => body.extend(element(
+            VIDEO,
+            &[
+                uint(PIXEL_WIDTH, u64::from(width)),
+                uint(PIXEL_HEIGHT, u64::from(height)),
+            ]
+            .concat(),
+        )),
+        MkvTrackKind::Audio {
+            sample_rate,
+            channels,
+        } => {
+            if track.codec_id == "A_OPUS" {
+                body.extend(uint(SEEK_PRE_ROLL, OPUS_SEEK_PRE_ROLL_NS));
+            }
+            body.extend(element(
+                AUDIO,
+                &[
+                    float(SAMPLING_FREQUENCY, f64::from(sample_rate)),
+                    uint(CHANNELS, u64::from(channels)),
+                ]
+                .concat(),
+            ));
+        }
+    }
+    element(TRACK_ENTRY, &body)
+}
+
+fn id_bytes(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(3);
+    bytes[skip..].to_vec()
+}
+
+/// Shortest EBML variable-length size; all-ones is reserved for "unknown".
+fn size_vint(size: u64) -> Vec<u8> {
+    let len = (1..=8)
+        .find(|&len| size < (1u64 << (7 * len)) - 1)
+        .unwrap_or(8);
+    let marked = size | (1u64 << (7 * len));
+    marked.to_be_bytes()[8 - len..].to_vec()
+}
+
+fn element(id: u32, body: &[u8]) -> Vec<u8> {
+    [id_bytes(id), size_vint(body.len() as u64), body.to_vec()].concat()
+}
+
+fn uint(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    element(id, &bytes[skip..])
+}
+
+fn float(id: u32, value: f64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+fn string(id: u32, value: &str) -> Vec<u8> {
+    element(id, value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_and_ids_use_shortest_encoding() {
+        assert_eq!(size_vint(0), vec![0x80]);
+        assert_eq!(size_vint(126), vec![0xFE]);
+        // 127 would be the reserved all-ones value in one byte.
+        assert_eq!(size_vint(127), vec![0x40, 0x7F]);
+        assert_eq!(id_bytes(SIMPLE_BLOCK), vec![0xA3]);
+        assert_eq!(id_bytes(CLUSTER), vec![0x1F, 0x43, 0xB6, 0x75]);
+        assert_eq!(uint(TRACK_NUMBER, 0), vec![0xD7, 0x81, 0x00]);
+    }
+
+    #[test]
+    fn keyframes_and_long_gaps_open_clusters() {
+        let tracks = [
+            MkvTrack {
+                number: 1,
+                codec_id: "V_AV1",
+                codec_private: None,
+                kind: MkvTrackKind::Video {
+                    width: 1280,
+                    height: 720,
+                },
+            },
+            MkvTrack {
+                number: 2,
+                codec_id: "A_OPUS",
+                codec_private: Some(opus_head(2, 48_000)),
+                kind: MkvTrackKind::Audio {
+                    sample_rate: 48_000,
+                    channels: 2,
+                },
+            },
+        ];
+        let mut writer = MkvWriter::new(Vec::new(), &tracks).unwrap();
+        let count_clusters = |bytes: &[u8]| {
+            bytes
+                .windows(4)
+                .filter(|w| *w == [0x1F, 0x43, 0xB6, 0x75])
+                .count()
+        };
+
+        writer.write_block(1, 0, true, b"key").unwrap();
+        writer.write_block(2, 5, true, b"opus").unwrap();
+        writer.write_block(1, 16, false, b"delta").unwrap();
+        assert_eq!(count_clusters(&writer.out), 1);
+
+        writer.write_block(1, 33, true, b"key").unwrap();
+        assert_eq!(count_clusters(&writer.out), 2);
+
+        writer.write_block(2, 60_000, true, b"opus").unwrap();
+        assert_eq!(count_clusters(&writer.out), 3);
+        assert_eq!(writer.bytes_written(), writer.out.len() as u64);
+
+        let bytes = writer.finish().unwrap();
+        assert!(bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]));
+    }
+}
@@ -0,0 +1,253 @@
+//! Frame scheduling against a display's refresh: encoder starts aligned to a
+//! VR headset's vsync, and presentation of decoded frames on clients.
+
+/// Fraction of each reported phase error corrected per report.
+const PHASE_GAIN: f32 = 0.5;
+/// Refresh rate assumed until a renderer learns the display's.
+pub const DEFAULT_REFRESH_HZ: f32 = 60.0;
+/// Longest a decoded frame is held to smooth out bursty delivery.
+const MAX_PLAYOUT_DELAY_US: u64 = 50_000;
+/// How far the transit baseline creeps up per frame, so it follows a path
+/// that got slower instead of clinging to an old minimum.
+const BASELINE_DRIFT_US: i64 = 20;
+/// Transit this far above the baseline means the host's clock restarted.
+const BASELINE_RESET_US: i64 = 1_000_000;
+/// Fraction of each frame's excess transit folded into the jitter estimate.
+const JITTER_GAIN: f32 = 1.0 / 16.0;
+
+/// Schedules frame starts on the headset's refresh grid, shifted by the phase
+/// error it reports so frames arrive just ahead of its compositor latch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VrFramePacer {
+    period_us: u64,
+    offset_us: u64,
+}
+
+impl VrFramePacer {
+    pub fn new(refresh_hz: f32) -> Self {
+        let mut pacer = Self {
+            period_us: 1_000_000 / 60,
+            offset_us: 0,
+        };
+        pacer.set_refresh_hz(refresh_hz);
+        pacer
+    }
+
+    pub fn period_us(&self) -> u64 {
+        self.period_us
+    }
+
+    /// Ignores rates outside 1-1000 Hz.
+    pub fn set_refresh_hz(&mut self, refresh_hz: f32) {
+        if !(1.0..=1_000.0).contains(&refresh_hz) {
+            return;
+        }
+        self.period_us = (1_000_000.0 / refresh_hz).round() as u64;
+        self.offset_us %= self.period_us;
+    }
+
+    /// Positive errors (frames arriving early) push frame starts later.
+    pub fn on_phase_error(&mut self, error_us: i64) {
+        let step = (error_us as f32 * PHASE_GAIN) as i64;
+        let period = self.period_us as i64;
+        self.offset_us = (self.offset_us as i64 + step).rem_euclid(period) as u64;
+    }
+
+    /// First slot at or after `now_us` on the shifted refresh grid.
+    pub fn next_slot_us(&self, now_us: u64) -> u64 {
+        let phase = (now_us + self.period_us - self.offset_us) % self.period_us;
+        if phase == 0 {
+            now_us
+        } else {
+            now_us + self.period_us - phase
+        }
+    }
+}
+
+/// What to do with a decoded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presentation {
+    /// Show it on the first refresh at or after this local time.
+    At(u64),
+    /// Skip it; a later frame replaces it.
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PresentStats {
+    pub presented: u64,
+    pub dropped: u64,
+    /// Refreshes that showed the previous frame again.
+    pub repeated: u64,
+    /// Current hold added on top of the fastest delivery seen.
+    pub playout_delay_us: u64,
+}
+
+/// Schedules decoded frames onto the display's refresh grid.
+///
+/// Each frame is due at its host timestamp plus the fastest transit seen and
+/// a playout delay sized to recent jitter, so frames that arrive in bursts
+/// still go out one refresh apart. Frames that would queue more than a
+/// refresh behind are dropped, though never two in a row.
+#[derive(Debug, Clone)]
+pub struct PresentScheduler {
+    period_us: u64,
+    baseline_us: Option<i64>,
+    jitter_us: f32,
+    last_slot_us: Option<u64>,
+    last_dropped: bool,
+    stats: PresentStats,
+}
+
+impl PresentScheduler {
+    pub fn new(refresh_hz: f32) -> Self {
+        let mut scheduler = Self {
+            period_us: (1_000_000.0 / DEFAULT_REFRESH_HZ) as u64,
+            baseline_us: None,
+            jitter_us: 0.0,
+            last_slot_us: None,
+            last_dropped: false,
+            stats: PresentStats::default(),
+        };
+        scheduler.set_refresh_hz(refresh_hz);
+        scheduler
+    }
+
+    pub fn period_us(&self) -> u64 {
+        self.period_us
+    }
+
+    /// Ignores rates outside 1-1000 Hz.
+    pub fn set_refresh_hz(&mut self, refresh_hz: f32) {
+        if (1.0..=1_000.0).contains(&refresh_hz) {
+            self.period_us = (1_000_000.0 / refresh_hz).round() as u64;
+        }
+    }
+
+    pub fn stats(&self) -> PresentStats {
+        self.stats
+    }
+
+    /// Decides when the frame stamped `timestamp_us` by the host goes on
+    /// screen, given the local time `now_us` it finished decoding.
+    pub fn schedule(&mut self, timestamp_us: u64, now_us: u64) -> Presentation {
+        let transit = now_us as i64 - timestamp_us as i64;
+        let baseline = match self.baseline_us {
+            Some(baseline) if transit - baseline < BASELINE_RESET_US => {
+                (baseline + BASELINE_DRIFT_US).min(transit)
+            }
+            _ => {
+                self.jitter_us = 0.0;
+                self.last_slot_us = None;
+                transit
+            }
+        };
+        self.baseline_us = Some(baseline);
+        let excess = (transit - baseline) as f32;
+        self.jitter_us += (excess - self.jitter_us) * JITTER_GAIN;
+        let delay = ((self.jitter_us * 2.0) as u64).min(MAX_PLAYOUT_DELAY_US);
+        self.stats.playout_delay_us = delay;
+
+        let due = (timestamp_us as i64 + baseline).max(0) as u64 + delay;
+        let mut slot = self.next_refresh_us(due.max(now_us));
+        if let Some(last) = self.last_slot_us {
+            // A burst lands several frames on one refresh; space them out.
+            slot = slot.max(last + self.period_us);
+        }
+        if slot > due + self.period_us && !self.last_dropped {
+            self.last_dropped = true;
+            self.stats.dropped += 1;
+            return Presentation::Drop;
+        }
+        if let Some(last) = self.last_slot_us {
+            self.stats.repeated += (slot - last) / self.period_us - 1;
+        }
+        self.last_slot_us = Some(slot);
+        self.last_dropped = false;
+        self.stats.presented += 1;
+        Presentation::At(slot)
+    }
+
+    fn next_refresh_us(&self, time_us: u64) -> u64 {
+        time_us.div_ceil(self.period_us) * self.period_us
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_error_shifts_the_slot_grid() {
+        let mut pacer = VrFramePacer::new(90.0);
+        assert_eq!(pacer.period_us(), 11_111);
+        assert_eq!(pacer.next_slot_us(0), 0);
+        assert_eq!(pacer.next_slot_us(1), 11_111);
+
+        pacer.on_phase_error(4_000);
+        assert_eq!(pacer.next_slot_us(1), 2_000);
+        assert_eq!(pacer.next_slot_us(2_001), 13_111);
+
+        // Late frames pull the grid earlier, wrapping around the period.
+        pacer.on_phase_error(-8_000);
+        assert_eq!(pacer.next_slot_us(0), 9_111);
+
+        pacer.set_refresh_hz(0.0);
+        assert_eq!(pacer.period_us(), 11_111);
+    }
+
+    #[test]
+    fn frames_arriving_in_pairs_go_out_one_refresh_apart() {
+        let mut scheduler = PresentScheduler::new(DEFAULT_REFRESH_HZ);
+        let period = scheduler.period_us();
+        // Every other frame is held up a refresh and lands with the next one.
+        let decisions: Vec<_> = (0..240u64)
+            .map(|frame| {
+                let timestamp = frame * period;
+                let held = if frame % 2 == 0 { period } else { 0 };
+                scheduler.schedule(timestamp, timestamp + 5_000 + held)
+            })
+            .collect();
+        let settled: Vec<u64> = decisions[120..]
+            .iter()
+            .map(|decision| match decision {
+                Presentation::At(slot) => *slot,
+                Presentation::Drop => panic!("settled frame dropped"),
+            })
+            .collect();
+        assert!(settled.windows(2).all(|pair| pair[1] - pair[0] == period));
+        assert!(scheduler.stats().playout_delay_us >= period / 2);
+    }
+
+    #[test]
+    fn frames_queued_behind_are_dropped_but_never_twice_in_a_row() {
+        let mut scheduler = PresentScheduler::new(DEFAULT_REFRESH_HZ);
+        let period = scheduler.period_us();
+        assert!(matches!(scheduler.schedule(0, 0), Presentation::At(_)));
+        // Four frames due within one refresh would queue far behind.
+        let decisions: Vec<_> = (1..=4)
+            .map(|frame| scheduler.schedule(frame * 1_000, period))
+            .collect();
+        assert_eq!(decisions[0], Presentation::At(period));
+        assert_eq!(decisions[1], Presentation::Drop);
+        assert_eq!(decisions[2], Presentation::At(2 * period));
+        assert_eq!(decisions[3], Presentation::Drop);
+        assert_eq!(scheduler.stats().dropped, 2);
+    }
+
+    #[test]
+    fn gaps_count_repeats_and_a_clock_restart_resets_the_baseline() {
+        let mut scheduler = PresentScheduler::new(DEFAULT_REFRESH_HZ);
+        let period = scheduler.period_us();
+        scheduler.schedule(0, 0);
+        scheduler.schedule(3 * period, 3 * period);
+        assert_eq!(scheduler.stats().repeated, 2);
+
+        // The host restarted its clock: timestamps drop back near zero.
+        let now = 10_000_000;
+        assert_eq!(
+            scheduler.schedule(0, now),
+            Presentation::At(now.div_ceil(period) * period)
+        );
+    }
+}
@@ -1,16 +1,27 @@
+//! Session recording to Matroska or MP4.
+//!
+//! A recording opens on a keyframe and rolls over to a new segment at the
+//! first keyframe past the size or duration limit, so every file plays on
+//! its own. Sample times come from the frames' own timestamps. Matroska also
+//! carries the Opus audio track; the MP4 muxer has no Opus support, so MP4
+//! recordings are video-only.
+
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::BufWriter;
-use std::path::PathBuf;
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
-use mp4::{
-    AacConfig, AudioObjectType, AvcConfig, ChannelConfig, HevcConfig, MediaConfig, Mp4Config,
-    Mp4Writer, SampleFreqIndex, TrackConfig,
-};
+use mp4::{AvcConfig, HevcConfig, MediaConfig, Mp4Config, Mp4Writer, TrackConfig};
 
+use crate::mkv::{opus_head, MkvTrack, MkvTrackKind, MkvWriter};
 use crate::{Codec, Resolution};
 
+const VIDEO_TRACK: u8 = 1;
+const AUDIO_TRACK: u8 = 2;
+const AUDIO_SAMPLE_RATE: u32 = 48_000;
+
 #[derive(Debug, Clone)]
 pub enum Quality {
     High,        // Preserve original bitrate
@@ -19,14 +30,35 @@ pub enum Quality {
     Custom(u32), // Specific bitrate in kbps
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Container {
+    #[default]
+    Mp4,
+    /// Records AV1 and Opus audio as well.
+    Matroska,
+}
+
+impl Container {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Matroska => "mkv",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RecorderConfig {
     pub enabled: bool,
     pub output_dir: PathBuf,
     pub filename_prefix: String,
+    /// Segment size limit; 0 disables size-based rotation.
     pub max_file_size_mb: u32,
+    /// Segment length limit; 0 disables time-based rotation.
+    pub max_segment_secs: u32,
     pub quality: Quality,
     pub split_on_codec_change: bool,
+    pub container: Container,
 }
 
 impl Default for RecorderConfig {
@@ -36,67 +68,49 @@ impl Default for RecorderConfig {
             output_dir: PathBuf::from("recordings"),
             filename_prefix: String::from("wavry-capture"),
             max_file_size_mb: 2048,
+            max_segment_secs: 0,
             quality: Quality::Standard,
             split_on_codec_change: true,
+            container: Container::Mp4,
         }
     }
 }
 
-pub struct VideoRecorder {
-    config: RecorderConfig,
-    writer: Option<Mp4Writer<BufWriter<File>>>,
-    video_track_id: u32,
-    audio_track_id: u32,
-    frame_count: u64,
-    audio_sample_count: u64,
-    start_time: Instant,
-    current_codec: Option<Codec>,
-    resolution: Option<Resolution>,
-    fps: u16,
-    /// Set when recording cannot proceed (e.g. unsupported codec) to avoid
-    /// re-attempting initialization and spamming warnings on every frame.
-    disabled: bool,
+/// Maps one track's timestamps onto the segment timeline. Video and audio
+/// may come from different clocks, so each track starts at the segment's
+/// wall-clock position when its first sample arrives.
+#[derive(Debug, Default)]
+struct TrackClock {
+    base: Option<(u64, u64)>,
 }
 
-impl VideoRecorder {
-    pub fn new(config: RecorderConfig) -> Result<Self> {
-        if !config.output_dir.exists() {
-            std::fs::create_dir_all(&config.output_dir)?;
-        }
-
-        Ok(Self {
-            config,
-            writer: None,
-            video_track_id: 0,
-            audio_track_id: 0,
-            frame_count: 0,
-            audio_sample_count: 0,
-            start_time: Instant::now(),
-            current_codec: None,
-            resolution: None,
-            fps: 60,
-            disabled: false,
-        })
+impl TrackClock {
+    /// `None` for samples older than the track's first one.
+    fn to_ms(&mut self, timestamp_us: u64, elapsed_ms: u64) -> Option<u64> {
+        let (base_us, offset_ms) = *self.base.get_or_insert((timestamp_us, elapsed_ms));
+        timestamp_us
+            .checked_sub(base_us)
+            .map(|delta_us| offset_ms + delta_us / 1_000)
     }
+}
 
-    pub fn is_initialized(&self) -> bool {
-        self.writer.is_some()
-    }
-
-    fn init_writer(&mut self, codec: Codec, res: Resolution, fps: u16) -> Result<()> {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        let filename = format!(
-            "{}-{}-{:?}x{:?}-{:?}.mp4",
-            self.config.filename_prefix, timestamp, res.width, res.height, codec
-        );
-        let path = self.config.output_dir.join(filename);
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
+struct Mp4Sink {
+    writer: Mp4Writer<BufWriter<File>>,
+    /// MP4 samples carry a duration, so each is held back until the next
+    /// one's timestamp is known.
+    pending: Option<mp4::Mp4Sample>,
+    frame_duration_ms: u32,
+    bytes: u64,
+}
 
+impl Mp4Sink {
+    fn open(
+        file: BufWriter<File>,
+        codec: Codec,
+        res: Resolution,
+        fps: u16,
+        keyframe: &[u8],
+    ) -> Result<Self> {
         let config = Mp4Config {
             major_brand: str::parse("isom").unwrap(),
             minor_version: 512,
@@ -108,65 +122,262 @@ impl VideoRecorder {
             ],
             timescale: 1000,
         };
+        let mut writer = Mp4Writer::write_start(file, &config)?;
 
-        let mut mp4_writer = Mp4Writer::write_start(writer, &config)?;
-
-        // Video Track
         let media_conf = match codec {
-            Codec::H264 => MediaConfig::AvcConfig(AvcConfig {
-                width: res.width,
-                height: res.height,
-                seq_param_set: vec![],
-                pic_param_set: vec![],
-            }),
+            Codec::H264 => {
+                let (sps, pps) = h264_parameter_sets(keyframe);
+                MediaConfig::AvcConfig(AvcConfig {
+                    width: res.width,
+                    height: res.height,
+                    seq_param_set: sps.map(<[u8]>::to_vec).unwrap_or_default(),
+                    pic_param_set: pps.map(<[u8]>::to_vec).unwrap_or_default(),
+                })
+            }
             Codec::Hevc => MediaConfig::HevcConfig(HevcConfig {
                 width: res.width,
                 height: res.height,
             }),
             Codec::Av1 => {
                 return Err(anyhow!(
-                    "AV1 recording is not yet supported by the MP4 muxer"
+                    "AV1 recording is not supported by the MP4 muxer; record to Matroska"
                 ));
             }
         };
-
-        let video_track_config = TrackConfig {
+        writer.add_track(&TrackConfig {
             track_type: mp4::TrackType::Video,
             timescale: 1000,
             language: String::from("und"),
             media_conf,
-        };
+        })?;
+
+        Ok(Self {
+            writer,
+            pending: None,
+            frame_duration_ms: 1000 / u32::from(fps.max(1)),
+            bytes: 0,
+        })
+    }
 
-        mp4_writer.add_track(&video_track_config)?;
-        self.video_track_id = 1;
+    fn write_video(&mut self, timestamp_ms: u64, keyframe: bool, data: Vec<u8>) -> Result<()> {
+        if let Some(mut previous) = self.pending.take() {
+            previous.duration = timestamp_ms.saturating_sub(previous.start_time).max(1) as u32;
+            self.writer
+                .write_sample(u32::from(VIDEO_TRACK), &previous)?;
+        }
+        self.bytes += data.len() as u64;
+        self.pending = Some(mp4::Mp4Sample {
+            start_time: timestamp_ms,
+            duration: self.frame_duration_ms,
+            rendering_offset: 0,
+            is_sync: keyframe,
+            bytes: data.into(),
+        });
+        Ok(())
+    }
 
-        // Audio Track (AAC)
-        let audio_track_config = TrackConfig {
-            track_type: mp4::TrackType::Audio,
-            timescale: 48000,
-            language: String::from("und"),
-            media_conf: MediaConfig::AacConfig(AacConfig {
-                bitrate: 128000,
-                profile: AudioObjectType::AacLowComplexity,
-                freq_index: SampleFreqIndex::Freq48000,
-                chan_conf: ChannelConfig::Stereo,
-            }),
-        };
+    fn finish(mut self) -> Result<()> {
+        if let Some(last) = self.pending.take() {
+            self.writer.write_sample(u32::from(VIDEO_TRACK), &last)?;
+        }
+        self.writer.write_end()?;
+        Ok(())
+    }
+}
 
-        mp4_writer.add_track(&audio_track_config)?;
-        self.audio_track_id = 2;
+enum Sink {
+    Mp4(Mp4Sink),
+    Matroska(MkvWriter<BufWriter<File>>),
+}
 
-        self.writer = Some(mp4_writer);
-        self.current_codec = Some(codec);
-        self.resolution = Some(res);
-        self.fps = fps;
-        self.start_time = Instant::now();
-        self.frame_count = 0;
-        self.audio_sample_count = 0;
+struct Segment {
+    sink: Sink,
+    path: PathBuf,
+    codec: Codec,
+    resolution: Resolution,
+    opened: Instant,
+    video_clock: TrackClock,
+    audio_clock: TrackClock,
+    has_audio: bool,
+}
 
+impl Segment {
+    fn bytes(&self) -> u64 {
+        match &self.sink {
+            Sink::Mp4(sink) => sink.bytes,
+            Sink::Matroska(writer) => writer.bytes_written(),
+        }
+    }
+
+    fn write_video(&mut self, data: &[u8], keyframe: bool, timestamp_us: u64) -> Result<()> {
+        let elapsed_ms = self.opened.elapsed().as_millis() as u64;
+        let Some(timestamp_ms) = self.video_clock.to_ms(timestamp_us, elapsed_ms) else {
+            return Ok(());
+        };
+        match &mut self.sink {
+            Sink::Mp4(sink) => {
+                let sample = length_prefixed(data).into_owned();
+                sink.write_video(timestamp_ms, keyframe, sample)
+            }
+            Sink::Matroska(writer) => {
+                let sample = match self.codec {
+                    Codec::Av1 => av1_sample(data),
+                    Codec::H264 | Codec::Hevc => length_prefixed(data),
+                };
+                Ok(writer.write_block(VIDEO_TRACK, timestamp_ms, keyframe, &sample)?)
+            }
+        }
+    }
+
+    fn write_audio(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        if !self.has_audio {
+            return Ok(());
+        }
+        let elapsed_ms = self.opened.elapsed().as_millis() as u64;
+        let Some(timestamp_ms) = self.audio_clock.to_ms(timestamp_us, elapsed_ms) else {
+            return Ok(());
+        };
+        if let Sink::Matroska(writer) = &mut self.sink {
+            writer.write_block(AUDIO_TRACK, timestamp_ms, true, payload)?;
+        }
         Ok(())
     }
 
+    fn finish(self) -> Result<()> {
+        match self.sink {
+            Sink::Mp4(sink) => sink.finish(),
+            Sink::Matroska(writer) => writer.finish().map(|_| ()).map_err(Into::into),
+        }
+    }
+}
+
+pub struct VideoRecorder {
+    config: RecorderConfig,
+    segment: Option<Segment>,
+    segment_index: u32,
+    audio_channels: u8,
+    /// Set when recording cannot proceed (e.g. unsupported codec) to avoid
+    /// re-attempting initialization and spamming warnings on every frame.
+    disabled: bool,
+}
+
+impl VideoRecorder {
+    pub fn new(config: RecorderConfig) -> Result<Self> {
+        if !config.output_dir.exists() {
+            std::fs::create_dir_all(&config.output_dir)?;
+        }
+
+        Ok(Self {
+            config,
+            segment: None,
+            segment_index: 0,
+            audio_channels: 2,
+            disabled: false,
+        })
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.segment.is_some()
+    }
+
+    /// File the current segment is being written to.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.segment.as_ref().map(|segment| segment.path.as_path())
+    }
+
+    /// Channel count of the Opus packets passed to `write_audio`, applied
+    /// from the next segment. Only mono and stereo are recorded.
+    pub fn set_audio_channels(&mut self, channels: u8) {
+        self.audio_channels = channels;
+    }
+
+    fn segment_full(&self, segment: &Segment) -> bool {
+        let max_bytes = u64::from(self.config.max_file_size_mb) * 1024 * 1024;
+        let max_age = Duration::from_secs(u64::from(self.config.max_segment_secs));
+        (max_bytes > 0 && segment.bytes() >= max_bytes)
+            || (!max_age.is_zero() && segment.opened.elapsed() >= max_age)
+    }
+
+    fn open_segment(
+        &mut self,
+        keyframe: &[u8],
+        codec: Codec,
+        res: Resolution,
+        fps: u16,
+    ) -> Result<Segment> {
+        let container = self.config.container;
+        if container == Container::Mp4 && codec == Codec::Av1 {
+            return Err(anyhow!(
+                "AV1 recording is not supported by the MP4 muxer; record to Matroska"
+            ));
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.segment_index += 1;
+        let filename = format!(
+            "{}-{}-{}x{}-{:?}-{:03}.{}",
+            self.config.filename_prefix,
+            timestamp,
+            res.width,
+            res.height,
+            codec,
+            self.segment_index,
+            container.extension()
+        );
+        let path = self.config.output_dir.join(filename);
+        let file = BufWriter::new(File::create(&path)?);
+
+        let has_audio = container == Container::Matroska && matches!(self.audio_channels, 1 | 2);
+        let sink = match container {
+            Container::Mp4 => Sink::Mp4(Mp4Sink::open(file, codec, res, fps, keyframe)?),
+            Container::Matroska => {
+                let mut tracks = vec![MkvTrack {
+                    number: VIDEO_TRACK,
+                    codec_id: match codec {
+                        Codec::H264 => "V_MPEG4/ISO/AVC",
+                        Codec::Hevc => "V_MPEGH/ISO/HEVC",
+                        Codec::Av1 => "V_AV1",
+                    },
+                    codec_private: match codec {
+                        Codec::H264 => h264_avcc(keyframe),
+                        Codec::Hevc => hevc_hvcc(keyframe),
+                        Codec::Av1 => av1_config(keyframe),
+                    },
+                    kind: MkvTrackKind::Video {
+                        width: res.width,
+                        height: res.height,
+                    },
+                }];
+                if has_audio {
+                    tracks.push(MkvTrack {
+                        number: AUDIO_TRACK,
+                        codec_id: "A_OPUS",
+                        codec_private: Some(opus_head(self.audio_channels, AUDIO_SAMPLE_RATE)),
+                        kind: MkvTrackKind::Audio {
+                            sample_rate: AUDIO_SAMPLE_RATE,
+                            channels: self.audio_channels,
+                        },
+                    });
+                }
+                Sink::Matroska(MkvWriter::new(file, &tracks)?)
+            }
+        };
+        log::info!("Recording to {}", path.display());
+
+        Ok(Segment {
+            sink,
+            path,
+            codec,
+            resolution: res,
+            opened: Instant::now(),
+            video_clock: TrackClock::default(),
+            audio_clock: TrackClock::default(),
+            has_audio,
+        })
+    }
+
     pub fn write_frame(
         &mut self,
         data: &[u8],
@@ -174,62 +385,56 @@ impl VideoRecorder {
         codec: Codec,
         res: Resolution,
         fps: u16,
+        timestamp_us: u64,
     ) -> Result<()> {
         if self.disabled {
             return Ok(());
         }
 
-        if self.writer.is_none()
-            || (self.config.split_on_codec_change
-                && (self.current_codec != Some(codec) || self.resolution != Some(res)))
-        {
-            if self.writer.is_some() {
-                self.finalize()?;
+        let format_changed = self
+            .segment
+            .as_ref()
+            .is_some_and(|segment| segment.codec != codec || segment.resolution != res);
+        let needs_segment = match self.segment.as_ref() {
+            None => true,
+            Some(segment) => {
+                (format_changed && self.config.split_on_codec_change) || self.segment_full(segment)
             }
-            if let Err(e) = self.init_writer(codec, res, fps) {
-                log::warn!("Recording disabled: {}", e);
-                self.disabled = true;
-                return Ok(());
+        };
+        if needs_segment && keyframe {
+            self.finalize()?;
+            match self.open_segment(data, codec, res, fps) {
+                Ok(segment) => self.segment = Some(segment),
+                Err(e) => {
+                    log::warn!("Recording disabled: {}", e);
+                    self.disabled = true;
+                    return Ok(());
+                }
             }
         }
 
-        if let Some(ref mut writer) = self.writer {
-            let duration = 1000 / self.fps as u64;
-            let sample = mp4::Mp4Sample {
-                start_time: self.frame_count * duration,
-                duration: duration as u32,
-                rendering_offset: 0,
-                is_sync: keyframe,
-                bytes: data.to_vec().into(),
-            };
-
-            writer.write_sample(self.video_track_id, &sample)?;
-            self.frame_count += 1;
+        match self.segment.as_mut() {
+            // Waiting for a keyframe to start on, or to switch format at.
+            None => Ok(()),
+            Some(_) if format_changed && self.config.split_on_codec_change && !keyframe => Ok(()),
+            Some(segment) => segment.write_video(data, keyframe, timestamp_us),
         }
-
-        Ok(())
     }
 
+    /// Records one Opus packet. Packets before the segment's first video
+    /// frame are dropped.
     pub fn write_audio(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
-        if let Some(ref mut writer) = self.writer {
-            let start_time = (timestamp_us * 48) / 1000;
-            let sample = mp4::Mp4Sample {
-                start_time,
-                duration: 960, // 20ms at 48kHz
-                rendering_offset: 0,
-                is_sync: true,
-                bytes: payload.to_vec().into(),
-            };
-
-            writer.write_sample(self.audio_track_id, &sample)?;
-            self.audio_sample_count += 1;
+        match self.segment.as_mut() {
+            Some(segment) => segment.write_audio(payload, timestamp_us),
+            None => Ok(()),
         }
-        Ok(())
     }
 
     pub fn finalize(&mut self) -> Result<()> {
-        if let Some(mut writer) = self.writer.take() {
-            writer.write_end()?;
+        if let Some(segment) = self.segment.take() {
+            let path = segment.path.clone();
+            segment.finish()?;
+            log::info!("Recording segment closed: {}", path.display());
         }
         Ok(())
     }
@@ -240,3 +445,275 @@ impl Drop for VideoRecorder {
         let _ = self.finalize();
     }
 }
+
+/// NAL units of an Annex-B access unit, without start codes. Empty when the
+/// data has no start codes.
+fn annexb_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                nals.push(trim_trailing_zeros(&data[start..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        nals.push(&data[start..]);
+    }
+    nals.retain(|nal| !nal.is_empty());
+    nals
+}
+
+fn trim_trailing_zeros(nal: &[u8]) -> &[u8] {
+    let end = nal.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &nal[..end]
+}
+
+/// Rewrites Annex-B as the 4-byte length-prefixed form both containers
+/// expect. Data without start codes is assumed to be length-prefixed already.
+fn length_prefixed(data: &[u8]) -> Cow<'_, [u8]> {
+    let nals = annexb_nals(data);
+    if nals.is_empty() {
+        return Cow::Borrowed(data);
+    }
+    let mut out = Vec::with_capacity(data.len() + nals.len());
+    for nal in nals {
+        out.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        out.extend_from_slice(nal);
+    }
+    Cow::Owned(out)
+}
+
+/// Strips emulation prevention bytes.
+fn rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &b in nal {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
+
+fn h264_parameter_sets(data: &[u8]) -> (Option<&[u8]>, Option<&[u8]>) {
+    let nals = annexb_nals(data);
+    let find = |kind: u8| nals.iter().copied().find(|nal| nal[0] & 0x1F == kind);
+    (find(7), find(8))
+}
+
+fn h264_avcc(keyframe: &[u8]) -> Option<Vec<u8>> {
+    let (Some(sps), Some(pps)) = h264_parameter_sets(keyframe) else {
+        return None;
+    };
+    if sps.len() < 4 {
+        return None;
+    }
+    let mut avcc = vec![1, sps[1], sps[2], sps[3], 0xFF, 0xE1];
+    avcc.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    avcc.extend_from_slice(sps);
+    avcc.push(1);
+    avcc.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    avcc.extend_from_slice(pps);
+    Some(avcc)
+}
+
+/// `hvcC` for an 8-bit 4:2:0 stream, with its VPS, SPS and PPS.
+fn hevc_hvcc(keyframe: &[u8]) -> Option<Vec<u8>> {
+    let nals = annexb_nals(keyframe);
+    let find = |kind: u8| {
+        nals.iter()
+            .copied()
+            .find(|nal| (nal[0] >> 1) & 0x3F == kind)
+    };
+    let (vps, sps, pps) = (find(32)?, find(33)?, find(34)?);
+    // After the 2-byte NAL header: one byte of ids, then 12 bytes of the
+    // general profile, tier and level.
+    let sps_rbsp = rbsp(sps);
+    let ptl = sps_rbsp.get(3..15)?;
+    let max_sub_layers = ((sps_rbsp[2] >> 1) & 0x07) + 1;
+    let temporal_id_nested = sps_rbsp[2] & 0x01;
+
+    let mut hvcc = vec![1];
+    hvcc.extend_from_slice(ptl);
+    hvcc.extend_from_slice(&[0xF0, 0x00, 0xFC, 0xFD, 0xF8, 0xF8, 0x00, 0x00]);
+    hvcc.push((max_sub_layers << 3) | (temporal_id_nested << 2) | 0x03);
+    hvcc.push(3);
+    for (kind, nal) in [(32u8, vps), (33, sps), (34, pps)] {
+        hvcc.push(0x80 | kind);
+        hvcc.extend_from_slice(&1u16.to_be_bytes());
+        hvcc.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        hvcc.extend_from_slice(nal);
+    }
+    Some(hvcc)
+}
+
+const AV1_OBU_SEQUENCE_HEADER: u8 = 1;
+const AV1_OBU_TEMPORAL_DELIMITER: u8 = 2;
+
+/// OBUs of a low-overhead AV1 temporal unit as `(type, header length, bytes)`.
+fn av1_obus(data: &[u8]) -> Vec<(u8, usize, &[u8])> {
+    let mut obus = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data[pos];
+        let kind = (header >> 3) & 0x0F;
+        let header_len = 1 + usize::from(header & 0x04 != 0);
+        if header & 0x02 == 0 {
+            obus.push((kind, header_len, &data[pos..]));
+            break;
+        }
+        let Some((size, size_len)) = data.get(pos + header_len..).and_then(leb128) else {
+            break;
+        };
+        let end = pos + header_len + size_len + size;
+        if end > data.len() {
+            break;
+        }
+        obus.push((kind, header_len + size_len, &data[pos..end]));
+        pos = end;
+    }
+    obus
+}
+
+fn leb128(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= usize::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Matroska AV1 blocks leave out temporal delimiters.
+fn av1_sample(data: &[u8]) -> Cow<'_, [u8]> {
+    let obus = av1_obus(data);
+    if !obus
+        .iter()
+        .any(|(kind, _, _)| *kind == AV1_OBU_TEMPORAL_DELIMITER)
+    {
+        return Cow::Borrowed(data);
+    }
+    Cow::Owned(
+        obus.into_iter()
+            .filter(|(kind, _, _)| *kind != AV1_OBU_TEMPORAL_DELIMITER)
+            .flat_map(|(_, _, obu)| obu.iter().copied())
+            .collect(),
+    )
+}
+
+/// `av1C` built from the keyframe's sequence header, assuming the 8-bit
+/// 4:2:0 output the encoders produce.
+fn av1_config(keyframe: &[u8]) -> Option<Vec<u8>> {
+    let (_, header_len, obu) = av1_obus(keyframe)
+        .into_iter()
+        .find(|(kind, _, _)| *kind == AV1_OBU_SEQUENCE_HEADER)?;
+    let mut bits = BitReader::new(&obu[header_len..]);
+    let profile = bits.read(3)? as u8;
+    let _still_picture = bits.read(1)?;
+    let (level, tier) = if bits.read(1)? == 1 {
+        (bits.read(5)? as u8, 0)
+    } else if bits.read(1)? == 1 {
+        // Timing info makes the operating point fields costly to reach;
+        // 31 means "no level constraint".
+        (31, 0)
+    } else {
+        let _initial_display_delay_present = bits.read(1)?;
+        let _operating_points = bits.read(5)?;
+        let _operating_point_idc = bits.read(12)?;
+        let level = bits.read(5)? as u8;
+        let tier = if level > 7 { bits.read(1)? as u8 } else { 0 };
+        (level, tier)
+    };
+
+    let mut config = vec![0x81, (profile << 5) | level, (tier << 7) | 0x0C, 0];
+    config.extend_from_slice(obu);
+    Some(config)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, bit: 0 }
+    }
+
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = *self.data.get(self.bit / 8)?;
+            value = (value << 1) | u32::from((byte >> (7 - self.bit % 8)) & 1);
+            self.bit += 1;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H264_KEYFRAME: &[u8] = &[
+        0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1F, 0xAC, // SPS
+        0, 0, 0, 1, 0x68, 0xEE, 0x3C, 0x80, // PPS
+        0, 0, 1, 0x65, 0x88, 0x00, 0x00, 0x03, 0x01, // IDR slice
+    ];
+
+    #[test]
+    fn annexb_becomes_length_prefixed_with_avcc() {
+        let nals = annexb_nals(H264_KEYFRAME);
+        assert_eq!(nals.len(), 3);
+        assert_eq!(nals[2], &[0x65, 0x88, 0x00, 0x00, 0x03, 0x01]);
+
+        let sample = length_prefixed(H264_KEYFRAME);
+        assert_eq!(&sample[..5], &[0, 0, 0, 5, 0x67]);
+        assert_eq!(sample.len(), 4 * 3 + 5 + 4 + 6);
+        // Already length-prefixed data passes through.
+        assert!(matches!(length_prefixed(&sample), Cow::Borrowed(_)));
+
+        let avcc = h264_avcc(H264_KEYFRAME).unwrap();
+        assert_eq!(&avcc[..6], &[1, 0x64, 0x00, 0x1F, 0xFF, 0xE1]);
+        assert!(h264_avcc(&H264_KEYFRAME[17..]).is_none());
+        assert_eq!(rbsp(&[0, 0, 3, 1, 0, 0, 3]), vec![0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn av1_temporal_delimiters_are_dropped_and_sequence_header_parsed() {
+        let temporal_delimiter = [0x12, 0x00];
+        // Profile 0, not reduced, no timing info, one operating point at level 8 tier 1.
+        let sequence_header = [0x0A, 0x04, 0x00, 0x00, 0x00, 0x44];
+        let frame = [0x32, 0x01, 0xAA];
+        let unit = [&temporal_delimiter[..], &sequence_header, &frame].concat();
+
+        let sample = av1_sample(&unit);
+        assert_eq!(&sample[..], &[&sequence_header[..], &frame].concat()[..]);
+
+        let config = av1_config(&unit).unwrap();
+        assert_eq!(config[0], 0x81);
+        assert_eq!(config[1], 8);
+        assert_eq!(config[2] >> 7, 1);
+        assert_eq!(&config[4..], &sequence_header);
+    }
+
+    #[test]
+    fn track_clocks_offset_late_tracks_and_drop_early_samples() {
+        let mut clock = TrackClock::default();
+        assert_eq!(clock.to_ms(5_000_000, 250), Some(250));
+        assert_eq!(clock.to_ms(5_020_000, 999), Some(270));
+        assert_eq!(clock.to_ms(4_000_000, 999), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Codec-specific encoder knobs that `EncodeConfig` leaves out.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Codec;
+
+/// What the host is streaming.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    /// Text and UI with large static areas. Turns on screen-content coding
+    /// tools (AV1 palette and intra block copy) where the encoder has them.
+    Desktop,
+    /// Games and video: motion-heavy and latency-critical.
+    #[default]
+    Game,
+}
+
+impl FromStr for ContentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "desktop" => Ok(Self::Desktop),
+            "game" => Ok(Self::Game),
+            other => Err(format!(
+                "unknown content type '{}', expected desktop or game",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Desktop => "desktop",
+            Self::Game => "game",
+        })
+    }
+}
+
+/// How the stream recovers after loss and lets a decoder join.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshMode {
+    /// Periodic and on-demand IDR frames.
+    #[default]
+    Idr,
+    /// A rolling wave of intra blocks spread over the keyframe interval,
+    /// which keeps frame sizes flat. Encoders without it fall back to IDR.
+    IntraRefresh,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EncoderTuning {
+    pub content: ContentType,
+    /// Fastest preset with no lookahead; off trades latency for quality.
+    pub low_latency: bool,
+    /// Slices per frame (tile columns for AV1); 0 leaves it to the encoder.
+    pub slices: u8,
+    pub refresh: RefreshMode,
+}
+
+impl EncoderTuning {
+    /// Defaults for streaming `content` with `codec`.
+    pub fn for_codec(codec: Codec, content: ContentType) -> Self {
+        // Slices let hardware and sliced-thread encoders work on a frame in
+        // parallel; AV1 gets the same from tiles, which only pay off once
+        // there is enough motion to spread.
+        let slices = match (codec, content) {
+            (Codec::H264, _) => 4,
+            (Codec::Hevc, _) => 2,
+            (Codec::Av1, ContentType::Game) => 2,
+            (Codec::Av1, ContentType::Desktop) => 0,
+        };
+        Self {
+            content,
+            low_latency: true,
+            slices,
+            refresh: RefreshMode::Idr,
+        }
+    }
+
+    /// Log2 of the AV1 tile-column count closest to `slices` without
+    /// exceeding it; AV1 allows up to 64 columns.
+    pub fn tile_columns_log2(&self) -> u32 {
+        match self.slices {
+            0 | 1 => 0,
+            n => u32::from(n).ilog2().min(6),
+        }
+    }
+}
+
+impl Default for EncoderTuning {
+    fn default() -> Self {
+        Self::for_codec(Codec::H264, ContentType::Game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_av1_defaults_leave_tiles_to_the_encoder() {
+        let desktop = EncoderTuning::for_codec(Codec::Av1, ContentType::Desktop);
+        assert_eq!(desktop.slices, 0);
+        assert_eq!(desktop.tile_columns_log2(), 0);
+
+        let game = EncoderTuning::for_codec(Codec::Av1, ContentType::Game);
+        assert_eq!(game.tile_columns_log2(), 1);
+        assert!(game.low_latency);
+        assert_eq!(game.refresh, RefreshMode::Idr);
+    }
+
+    #[test]
+    fn content_type_round_trips_through_strings() {
+        for content in [ContentType::Desktop, ContentType::Game] {
+            assert_eq!(content.to_string().parse::<ContentType>(), Ok(content));
+        }
+        assert!("movie".parse::<ContentType>().is_err());
+    }
+
+    #[test]
+    fn tile_columns_round_down_to_a_power_of_two() {
+        let tuning = |slices| EncoderTuning {
+            slices,
+            ..EncoderTuning::default()
+        };
+        assert_eq!(tuning(6).tile_columns_log2(), 2);
+        assert_eq!(tuning(255).tile_columns_log2(), 6);
+    }
+}
@@ -1,31 +1,30 @@
 // Windows implementation for wavry-media
 // Using Windows.Graphics.Capture (WGC) for high-performance screen capture.
 
-use crate::{Codec, EncodeConfig, EncodedFrame, Renderer};
+use crate::{
+    CaptureMode, Codec, DamageGate, EncodeConfig, EncodedFrame, EncoderStats, EncoderTuning,
+    FramePath, FrameRateMeter, QpOffsetMap, RefreshMode, Renderer, SharedTextureRing,
+};
 use anyhow::{anyhow, Context, Result};
 use libloading::Library;
-#[cfg(feature = "opus-support")]
-use opus::{Application, Channels, Encoder as OpusEncoder};
 use std::collections::VecDeque;
 use std::ffi::c_void;
 #[cfg(target_os = "windows")]
 use std::sync::{mpsc, Mutex};
 use std::time::{Duration, Instant};
 
-use crate::audio::renderer::f32_to_i16;
+use crate::audio::codec::OpusConfig;
 #[cfg(feature = "opus-support")]
-use crate::audio::OPUS_BITRATE_BPS;
+use crate::audio::codec::{AudioEncoder, OpusAudioEncoder};
+use crate::audio::renderer::f32_to_i16;
 use crate::audio::{
-    opus_frame_duration_us, AUDIO_MAX_BUFFER_SAMPLES, OPUS_CHANNELS, OPUS_FRAME_SAMPLES,
-    OPUS_MAX_PACKET_BYTES, OPUS_SAMPLE_RATE,
+    AUDIO_MAX_BUFFER_FRAMES, AUDIO_MAX_BUFFER_SAMPLES, OPUS_CHANNELS, OPUS_SAMPLE_RATE,
 };
 
 #[cfg(target_os = "windows")]
 use windows::{
     core::{implement, *},
     Graphics::Capture::*,
-    Graphics::DirectX::Direct3D11::IDirect3DDevice,
-    Graphics::DirectX::DirectXPixelFormat,
     Win32::Foundation::*,
     Win32::Graphics::Direct3D::*,
     Win32::Graphics::Direct3D11::*,
@@ -39,14 +38,15 @@ use windows::{
     Win32::System::Diagnostics::ToolHelp::*,
     Win32::System::Variant::*,
     Win32::System::WinRT::Direct3D11::IDirect3DDxgiInterfaceAccess,
-    Win32::System::WinRT::Graphics::Capture::IGraphicsCaptureItemInterop,
     Win32::UI::Input::KeyboardAndMouse::*,
-    Win32::UI::WindowsAndMessaging::GetDesktopWindow,
 };
 
 #[cfg(target_os = "windows")]
 use std::mem::ManuallyDrop;
 
+#[cfg(target_os = "windows")]
+use crate::windows_capture::{dirty_rects, frame_texture, WgcCapture};
+
 #[cfg(target_os = "windows")]
 const MF_BGR32: GUID = GUID::from_u128(0x00000016_0000_0010_8000_00aa00389b71); // MFVideoFormat_RGB32
 #[cfg(target_os = "windows")]
@@ -112,37 +112,68 @@ unsafe fn MFSetAttributeRatio(
     Ok(())
 }
 
+/// Set to 0 to read captured frames back to system memory instead of handing
+/// the encoder D3D11 textures.
 #[cfg(target_os = "windows")]
-extern "system" {
-    fn CreateDirect3D11DeviceFromDXGIDevice(
-        dxgidevice: *mut std::ffi::c_void,
-        graphicsdevice: *mut *mut std::ffi::c_void,
-    ) -> HRESULT;
-}
-
+const ZERO_COPY_ENV: &str = "WAVRY_WINDOWS_ZERO_COPY";
+/// Textures shared with the encoder. Hardware MFTs hold on to a few inputs
+/// while they work.
 #[cfg(target_os = "windows")]
-fn create_direct3d_device(device: &ID3D11Device) -> Result<IDirect3DDevice> {
-    let dxgi_device: IDXGIDevice = device.cast()?;
-    let mut inspectable = std::ptr::null_mut();
-    unsafe {
-        CreateDirect3D11DeviceFromDXGIDevice(dxgi_device.as_raw(), &mut inspectable).ok()?;
-        let device: IDirect3DDevice = std::mem::transmute_copy(&inspectable);
-        Ok(device)
-    }
-}
-
-/// Windows screen encoder using Media Foundation
+const SHARED_TEXTURES: usize = 4;
+/// One more than the encoder keeps, since a capture is held until the GPU has
+/// copied it.
+#[cfg(target_os = "windows")]
+const CAPTURE_POOL_BUFFERS: i32 = 3;
+/// How often to look for a new frame or encoder event while idle.
+#[cfg(target_os = "windows")]
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_millis(2);
+/// An idle screen still produces a frame this often in fixed capture mode, so
+/// the encoder keeps going. Damage mode repeats at the keepalive rate instead.
+#[cfg(target_os = "windows")]
+const CAPTURE_REPEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Windows screen encoder using Media Foundation.
+///
+/// Captured textures stay on the GPU: a video processor converts them to NV12
+/// (or they are copied as BGRA when the encoder takes RGB) into textures
+/// shared with the hardware encoder, and a D3D11 fence tells when each
+/// capture can go back to Windows.Graphics.Capture. Encoders without D3D11
+/// support, or `WAVRY_WINDOWS_ZERO_COPY=0`, get frames read back to system
+/// memory instead.
+///
+/// In damage capture mode the session reports dirty regions, and captures
+/// with none are skipped until the keepalive is due.
 #[allow(dead_code)]
 pub struct WindowsEncoder {
     config: EncodeConfig,
     device: ID3D11Device,
     context: ID3D11DeviceContext,
-    capture_item: GraphicsCaptureItem,
-    capture_session: GraphicsCaptureSession,
-    frame_pool: Direct3D11CaptureFramePool,
+    capture: WgcCapture,
     transform: IMFTransform,
+    /// Set for asynchronous MFTs, which hardware encoders are.
+    events: Option<IMFMediaEventGenerator>,
+    codec_api: Option<ICodecAPI>,
+    /// `None` when frames are read back to system memory.
+    shared: Option<SharedTextures>,
+    staging: Option<ID3D11Texture2D>,
+    output_provides_samples: bool,
+    output_buffer_size: u32,
+    /// Inputs the encoder asked for and has not been given yet.
+    input_requests: u32,
+    /// Frames handed to the encoder, oldest first.
+    in_flight: VecDeque<InFlightFrame>,
+    /// Latest input and its shared texture, repeated while the screen is idle.
+    last_input: Option<(IMFMediaBuffer, Option<usize>)>,
+    last_input_at: Instant,
+    damage: DamageGate,
+    fps: FrameRateMeter,
+    next_sequence: u64,
+    /// Sequence of the newest frame the encoder has returned.
+    completed_sequence: u64,
+    epoch: Instant,
     frame_width: u32,
     frame_height: u32,
+    stats: EncoderStats,
 }
 
 #[cfg(target_os = "windows")]
@@ -150,54 +181,67 @@ unsafe impl Send for WindowsEncoder {}
 #[cfg(target_os = "windows")]
 unsafe impl Sync for WindowsEncoder {}
 
+#[cfg(target_os = "windows")]
+struct InFlightFrame {
+    sequence: u64,
+    sample_time: i64,
+    submitted: Instant,
+    upload_us: u32,
+}
+
+/// Textures the encoder reads directly, and what fills them on the GPU.
+#[cfg(target_os = "windows")]
+struct SharedTextures {
+    textures: Vec<ID3D11Texture2D>,
+    /// Media buffers wrapping `textures`, one per slot.
+    buffers: Vec<IMFMediaBuffer>,
+    ring: SharedTextureRing,
+    context: ID3D11DeviceContext4,
+    fence: ID3D11Fence,
+    fence_value: u64,
+    /// Converts BGRA captures to NV12; `None` when the encoder takes BGRA.
+    converter: Option<Nv12Converter>,
+    /// Captures kept until the fence shows the GPU is done reading them.
+    held_captures: VecDeque<(u64, Direct3D11CaptureFrame)>,
+}
+
+#[cfg(target_os = "windows")]
+struct Nv12Converter {
+    video_device: ID3D11VideoDevice,
+    video_context: ID3D11VideoContext,
+    enumerator: ID3D11VideoProcessorEnumerator,
+    processor: ID3D11VideoProcessor,
+    output_views: Vec<ID3D11VideoProcessorOutputView>,
+}
+
 #[cfg(target_os = "windows")]
 impl WindowsEncoder {
     pub async fn new(config: EncodeConfig) -> Result<Self> {
         unsafe {
             MFStartup(MF_VERSION, MFSTARTUP_FULL).context("MFStartup failed")?;
 
-            let mut device = None;
-            let mut context = None;
-            D3D11CreateDevice(
-                None,
-                D3D_DRIVER_TYPE_HARDWARE,
-                HMODULE::default(),
-                D3D11_CREATE_DEVICE_BGRA_SUPPORT,
-                None,
-                D3D11_SDK_VERSION,
-                Some(&mut device),
-                None,
-                Some(&mut context),
-            )
-            .context("D3D11CreateDevice failed")?;
-
-            let device = device.ok_or_else(|| anyhow!("Failed to create D3D11 device"))?;
-            let context = context.ok_or_else(|| anyhow!("Failed to create D3D11 context"))?;
-
-            let interop: IGraphicsCaptureItemInterop =
-                windows::core::factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
-
-            let capture_item: GraphicsCaptureItem = if let Some(id) = config.display_id {
-                interop.CreateForMonitor(HMONITOR(id as _))?
-            } else {
-                interop.CreateForWindow(GetDesktopWindow())?
-            };
-
-            let item_size = capture_item.Size()?;
-            let frame_width = item_size.Width.max(1) as u32;
-            let frame_height = item_size.Height.max(1) as u32;
-
-            let winrt_device = create_direct3d_device(&device)?;
+            let (device, context) = create_encoder_device()?;
+            // Asynchronous MFTs use the immediate context from their own threads.
+            if let Ok(multithread) = device.cast::<ID3D11Multithread>() {
+                let _ = multithread.SetMultithreadProtected(true);
+            }
 
-            let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
-                &winrt_device,
-                DirectXPixelFormat::B8G8R8A8UIntNormalized,
-                2,
-                item_size,
-            )?;
+            let capture = WgcCapture::new(&device, config.display_id, CAPTURE_POOL_BUFFERS)?;
+            let item_size = capture.size();
+            // NV12 needs even dimensions.
+            let frame_width = item_size.Width.max(2) as u32 & !1;
+            let frame_height = item_size.Height.max(2) as u32 & !1;
 
-            let capture_session = frame_pool.CreateCaptureSession(&capture_item)?;
-            capture_session.StartCapture()?;
+            if config.hide_cursor {
+                if let Err(err) = capture.set_cursor_capture(false) {
+                    log::warn!("could not hide the cursor from capture: {:#}", err);
+                }
+            }
+            if config.capture_mode == CaptureMode::Damage {
+                capture.enable_dirty_regions();
+                capture.set_max_fps(config.fps);
+            }
+            capture.start()?;
 
             let mut activate_list: *mut Option<IMFActivate> = std::ptr::null_mut();
             let mut count = 0;
@@ -230,6 +274,35 @@ impl WindowsEncoder {
                 .unwrap();
 
             let transform: IMFTransform = activate.ActivateObject()?;
+            CoTaskMemFree(Some(activate_list as *const _));
+
+            let events = unlock_async_transform(&transform)?;
+            apply_codec_tuning(&transform, &config.tuning, frame_height);
+            let codec_api = transform.cast::<ICodecAPI>().ok();
+
+            // Hardware MFTs want the device manager before any media type.
+            let mut shared = if zero_copy_enabled() {
+                match SharedTextures::new(&device, &context, frame_width, frame_height, config.fps)
+                    .and_then(|shared| {
+                        attach_device_manager(&transform, &device)?;
+                        Ok(shared)
+                    }) {
+                    Ok(shared) => Some(shared),
+                    Err(err) => {
+                        log::warn!(
+                            "encoder cannot take D3D11 textures, reading frames back to system memory: {:#}",
+                            err
+                        );
+                        None
+                    }
+                }
+            } else {
+                log::info!(
+                    "{} is off; reading frames back to system memory",
+                    ZERO_COPY_ENV
+                );
+                None
+            };
 
             let output_media_type: IMFMediaType = MFCreateMediaType()?;
             output_media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
@@ -247,46 +320,800 @@ impl WindowsEncoder {
 
             transform.SetOutputType(0, Some(&output_media_type), 0)?;
 
-            let input_media_type: IMFMediaType = MFCreateMediaType()?;
-            input_media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
-            input_media_type.SetGUID(&MF_MT_SUBTYPE, &MF_BGR32)?;
-            MFSetAttributeSize(
-                &input_media_type,
-                &MF_MT_FRAME_SIZE,
-                frame_width,
-                frame_height,
-            )?;
-            MFSetAttributeRatio(&input_media_type, &MF_MT_FRAME_RATE, config.fps as u32, 1)?;
-            transform.SetInputType(0, Some(&input_media_type), 0)?;
-
-            let mut device_manager = None;
-            let mut reset_token = 0;
-            MFCreateDXGIDeviceManager(&mut reset_token, &mut device_manager)?;
-            let device_manager =
-                device_manager.ok_or_else(|| anyhow!("Failed to create MF device manager"))?;
-
-            device_manager.ResetDevice(&device, reset_token)?;
+            // Most hardware encoders only take NV12; some also take RGB.
+            let nv12 = shared
+                .as_ref()
+                .is_some_and(|shared| shared.converter.is_some())
+                && set_video_input_type(
+                    &transform,
+                    &MFVideoFormat_NV12,
+                    frame_width,
+                    frame_height,
+                    config.fps,
+                )
+                .is_ok();
+            if !nv12 {
+                set_video_input_type(&transform, &MF_BGR32, frame_width, frame_height, config.fps)
+                    .context("encoder accepts neither NV12 nor RGB32 input")?;
+            }
+            if let Some(shared) = &mut shared {
+                let format = if nv12 {
+                    DXGI_FORMAT_NV12
+                } else {
+                    DXGI_FORMAT_B8G8R8A8_UNORM
+                };
+                shared.allocate(&device, format, frame_width, frame_height)?;
+            }
 
-            transform.ProcessMessage(
-                MFT_MESSAGE_SET_D3D_MANAGER,
-                device_manager.as_raw() as usize,
-            )?;
+            let stream_info = transform.GetOutputStreamInfo(0)?;
+            let output_provides_samples = stream_info.dwFlags
+                & (MFT_OUTPUT_STREAM_PROVIDES_SAMPLES.0 | MFT_OUTPUT_STREAM_CAN_PROVIDE_SAMPLES.0)
+                    as u32
+                != 0;
+            transform.ProcessMessage(MFT_MESSAGE_NOTIFY_BEGIN_STREAMING, 0)?;
+            transform.ProcessMessage(MFT_MESSAGE_NOTIFY_START_OF_STREAM, 0)?;
 
-            CoTaskMemFree(Some(activate_list as *const _));
+            let frame_path = if shared.is_some() {
+                FramePath::ZeroCopy
+            } else {
+                FramePath::Cpu
+            };
+            log::info!(
+                "Windows encoder: {}x{} {} input, {} frames{}",
+                frame_width,
+                frame_height,
+                if nv12 { "NV12" } else { "RGB32" },
+                frame_path,
+                if events.is_some() { ", async MFT" } else { "" }
+            );
 
             Ok(Self {
                 config,
                 device,
                 context,
-                capture_item,
-                capture_session,
-                frame_pool,
+                capture,
                 transform,
+                events,
+                codec_api,
+                shared,
+                staging: None,
+                output_provides_samples,
+                output_buffer_size: stream_info.cbSize.max(frame_width * frame_height),
+                // Synchronous MFTs take input until they say otherwise.
+                input_requests: 1,
+                in_flight: VecDeque::new(),
+                last_input: None,
+                last_input_at: Instant::now(),
+                damage: DamageGate::new(config.capture_mode),
+                fps: FrameRateMeter::new(),
+                next_sequence: 1,
+                completed_sequence: 0,
+                epoch: Instant::now(),
                 frame_width,
                 frame_height,
+                stats: EncoderStats {
+                    frame_path,
+                    ..EncoderStats::default()
+                },
             })
         }
     }
+
+    pub fn next_frame(&mut self) -> Result<EncodedFrame> {
+        loop {
+            if let Some(frame) = unsafe { self.poll_output()? } {
+                return Ok(frame);
+            }
+            if self.input_requests > 0 && unsafe { self.submit_input()? } {
+                continue;
+            }
+            std::thread::sleep(CAPTURE_POLL_INTERVAL);
+        }
+    }
+
+    /// Update encoder bitrate at runtime through `ICodecAPI`.
+    pub fn set_bitrate(&mut self, bitrate_kbps: u32) -> Result<()> {
+        let codec_api = self
+            .codec_api
+            .as_ref()
+            .ok_or_else(|| anyhow!("encoder MFT has no ICodecAPI"))?;
+        unsafe {
+            codec_api.SetValue(
+                &CODECAPI_AVEncCommonMeanBitRate,
+                &codec_api_u32(bitrate_kbps * 1000),
+            )
+        }
+        .context("encoder rejected the bitrate change")?;
+        self.config.bitrate_kbps = bitrate_kbps;
+        log::debug!("Windows encoder bitrate updated to {} kbps", bitrate_kbps);
+        Ok(())
+    }
+
+    /// Media Foundation has no common QP map API, so only clearing succeeds.
+    pub fn set_qp_offset_map(&mut self, map: Option<&QpOffsetMap>) -> Result<()> {
+        match map {
+            Some(_) => Err(anyhow!(
+                "QP offset maps are unavailable through Media Foundation"
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stats(&self) -> EncoderStats {
+        EncoderStats {
+            unchanged_frames: self.damage.skipped(),
+            ..self.stats
+        }
+    }
+
+    /// Whether to encode the last input again because nothing new was
+    /// captured.
+    fn idle_repeat_due(&self, now: Instant) -> bool {
+        match self.config.capture_mode {
+            CaptureMode::Fixed => {
+                now.saturating_duration_since(self.last_input_at) >= CAPTURE_REPEAT_INTERVAL
+            }
+            CaptureMode::Damage => self.damage.keepalive_due(now),
+        }
+    }
+
+    /// Collects an encoded frame if one is ready, noting input requests on
+    /// the way.
+    unsafe fn poll_output(&mut self) -> Result<Option<EncodedFrame>> {
+        let Some(events) = self.events.clone() else {
+            let frame = self.process_output()?;
+            if frame.is_none() {
+                self.input_requests = 1;
+            }
+            return Ok(frame);
+        };
+        loop {
+            let event = match events.GetEvent(MF_EVENT_FLAG_NO_WAIT) {
+                Ok(event) => event,
+                Err(err) if err.code() == MF_E_NO_EVENTS_AVAILABLE => return Ok(None),
+                Err(err) => return Err(anyhow!("encoder event queue failed: {}", err)),
+            };
+            let kind = event.GetType()?;
+            if kind == METransformNeedInput.0 as u32 {
+                self.input_requests += 1;
+            } else if kind == METransformHaveOutput.0 as u32 {
+                return self.process_output();
+            }
+        }
+    }
+
+    unsafe fn process_output(&mut self) -> Result<Option<EncodedFrame>> {
+        let sample = if self.output_provides_samples {
+            None
+        } else {
+            let buffer = MFCreateMemoryBuffer(self.output_buffer_size)?;
+            let sample = MFCreateSample()?;
+            sample.AddBuffer(&buffer)?;
+            Some(sample)
+        };
+        let mut output = MFT_OUTPUT_DATA_BUFFER {
+            dwStreamID: 0,
+            pSample: ManuallyDrop::new(sample),
+            dwStatus: 0,
+            pEvents: ManuallyDrop::new(None),
+        };
+        let mut status = 0;
+        let result =
+            self.transform
+                .ProcessOutput(0, std::slice::from_mut(&mut output), &mut status);
+        let sample = ManuallyDrop::take(&mut output.pSample);
+        ManuallyDrop::drop(&mut output.pEvents);
+        match result {
+            Ok(()) => {}
+            Err(err) if err.code() == MF_E_TRANSFORM_NEED_MORE_INPUT => return Ok(None),
+            Err(err) if err.code() == MF_E_TRANSFORM_STREAM_CHANGE => {
+                // The encoder settled on new output details; take them as offered.
+                let output_type = self.transform.GetOutputAvailableType(0, 0)?;
+                self.transform.SetOutputType(0, Some(&output_type), 0)?;
+                return Ok(None);
+            }
+            Err(err) => return Err(anyhow!("encoder ProcessOutput failed: {:?}", err)),
+        }
+        let sample = sample.ok_or_else(|| anyhow!("encoder returned no output sample"))?;
+
+        let sample_time = sample.GetSampleTime().unwrap_or_default();
+        let keyframe = sample
+            .GetUINT32(&MFSampleExtension_CleanPoint)
+            .is_ok_and(|clean| clean != 0);
+        let buffer = sample.ConvertToContiguousBuffer()?;
+        let mut ptr = std::ptr::null_mut();
+        let mut len = 0;
+        buffer.Lock(&mut ptr, None, Some(&mut len))?;
+        let data = std::slice::from_raw_parts(ptr, len as usize).to_vec();
+        buffer.Unlock()?;
+
+        // Encoders may skip inputs, so everything up to this frame is done.
+        let now = Instant::now();
+        let mut durations = (0, 0);
+        while let Some(frame) = self.in_flight.front() {
+            if frame.sample_time > sample_time {
+                break;
+            }
+            self.completed_sequence = frame.sequence;
+            durations = (
+                frame.upload_us,
+                now.duration_since(frame.submitted).as_micros() as u32,
+            );
+            self.in_flight.pop_front();
+        }
+        self.stats.record_latency(durations.0, durations.1);
+        self.fps.record(now);
+        self.stats.fps = self.fps.fps(now);
+
+        Ok(Some(EncodedFrame {
+            timestamp_us: (sample_time / 10) as u64,
+            keyframe,
+            data,
+            capture_duration_us: durations.0,
+            encode_duration_us: durations.1,
+        }))
+    }
+
+    /// Hands the encoder the next captured frame, or the last one again while
+    /// the screen is idle. Returns false when there is nothing to send yet.
+    unsafe fn submit_input(&mut self) -> Result<bool> {
+        let started = Instant::now();
+        let mut slot = None;
+        if let Some(shared) = &mut self.shared {
+            shared.release_captures();
+            // Leave frames in the capture pool while the encoder is behind.
+            match shared.ring.acquire(self.completed_sequence) {
+                Some(free) => slot = Some(free),
+                None => return Ok(false),
+            }
+        }
+
+        let buffer = match self.capture.try_next_frame()? {
+            Some(capture) => {
+                // The capture goes straight back to the pool when skipped.
+                let changed = dirty_rects(&capture).is_none_or(|rects| !rects.is_empty());
+                if !self.damage.admit(changed, started) {
+                    return Ok(false);
+                }
+                match slot {
+                    Some(slot) => self.upload_shared(capture, slot)?,
+                    None => self.upload_system_memory(capture)?,
+                }
+            }
+            None => match &self.last_input {
+                Some((buffer, last_slot)) if self.idle_repeat_due(started) => {
+                    self.damage.admit(false, started);
+                    slot = *last_slot;
+                    buffer.clone()
+                }
+                _ => return Ok(false),
+            },
+        };
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        if let (Some(shared), Some(slot)) = (&mut self.shared, slot) {
+            shared.ring.submit(slot, sequence);
+        }
+        self.last_input = Some((buffer.clone(), slot));
+        self.last_input_at = Instant::now();
+
+        let sample_time = (self.epoch.elapsed().as_nanos() / 100) as i64;
+        let sample = MFCreateSample()?;
+        sample.AddBuffer(&buffer)?;
+        sample.SetSampleTime(sample_time)?;
+        sample.SetSampleDuration(10_000_000 / i64::from(self.config.fps.max(1)))?;
+        match self.transform.ProcessInput(0, &sample, 0) {
+            Ok(()) => {}
+            Err(err) if err.code() == MF_E_NOTACCEPTING => {
+                self.input_requests = 0;
+                return Ok(false);
+            }
+            Err(err) => return Err(anyhow!("encoder ProcessInput failed: {:?}", err)),
+        }
+        self.input_requests -= 1;
+
+        self.in_flight.push_back(InFlightFrame {
+            sequence,
+            sample_time,
+            submitted: Instant::now(),
+            upload_us: started.elapsed().as_micros() as u32,
+        });
+        match self.stats.frame_path {
+            FramePath::ZeroCopy => self.stats.zero_copy_frames += 1,
+            FramePath::Cpu => self.stats.cpu_frames += 1,
+        }
+        Ok(true)
+    }
+
+    /// Converts or copies the capture into shared texture `slot` on the GPU.
+    unsafe fn upload_shared(
+        &mut self,
+        capture: Direct3D11CaptureFrame,
+        slot: usize,
+    ) -> Result<IMFMediaBuffer> {
+        let texture = frame_texture(&capture)?;
+        let shared = self
+            .shared
+            .as_mut()
+            .ok_or_else(|| anyhow!("no shared textures"))?;
+        let target = &shared.textures[slot];
+        match &shared.converter {
+            Some(converter) => converter.convert(&texture, slot)?,
+            None => {
+                let mut desc = D3D11_TEXTURE2D_DESC::default();
+                texture.GetDesc(&mut desc);
+                let region = D3D11_BOX {
+                    left: 0,
+                    top: 0,
+                    front: 0,
+                    right: desc.Width.min(self.frame_width),
+                    bottom: desc.Height.min(self.frame_height),
+                    back: 1,
+                };
+                self.context
+                    .CopySubresourceRegion(target, 0, 0, 0, 0, &texture, 0, Some(&region));
+            }
+        }
+        shared.fence_value += 1;
+        shared
+            .context
+            .Signal(&shared.fence, shared.fence_value)
+            .context("failed to signal the capture fence")?;
+        shared
+            .held_captures
+            .push_back((shared.fence_value, capture));
+        Ok(shared.buffers[slot].clone())
+    }
+
+    /// Reads the capture back through a staging texture into a system
+    /// memory RGB32 buffer.
+    unsafe fn upload_system_memory(
+        &mut self,
+        capture: Direct3D11CaptureFrame,
+    ) -> Result<IMFMediaBuffer> {
+        let texture = frame_texture(&capture)?;
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut desc);
+        let staging = self.staging_texture(desc)?;
+        self.context.CopyResource(&staging, &texture);
+
+        let stride = self.frame_width as usize * 4;
+        let len = stride * self.frame_height as usize;
+        let buffer = MFCreateMemoryBuffer(len as u32)?;
+        let mut dst = std::ptr::null_mut();
+        buffer.Lock(&mut dst, None, None)?;
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        if let Err(err) = self
+            .context
+            .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+        {
+            let _ = buffer.Unlock();
+            return Err(anyhow!("failed to map the staging texture: {}", err));
+        }
+        let width = (desc.Width.min(self.frame_width) as usize) * 4;
+        let height = desc.Height.min(self.frame_height) as usize;
+        if width < stride || height < self.frame_height as usize {
+            std::ptr::write_bytes(dst, 0, len);
+        }
+        for row in 0..height {
+            std::ptr::copy_nonoverlapping(
+                (mapped.pData as *const u8).add(row * mapped.RowPitch as usize),
+                dst.add(row * stride),
+                width,
+            );
+        }
+        self.context.Unmap(&staging, 0);
+        buffer.Unlock()?;
+        buffer.SetCurrentLength(len as u32)?;
+        let _ = capture.Close();
+        Ok(buffer)
+    }
+
+    /// CPU-readable copy target, recreated whenever the captured size changes.
+    unsafe fn staging_texture(&mut self, source: D3D11_TEXTURE2D_DESC) -> Result<ID3D11Texture2D> {
+        if let Some(staging) = &self.staging {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            staging.GetDesc(&mut desc);
+            if desc.Width == source.Width && desc.Height == source.Height {
+                return Ok(staging.clone());
+            }
+        }
+        let desc = D3D11_TEXTURE2D_DESC {
+            MipLevels: 1,
+            ArraySize: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+            ..source
+        };
+        let mut staging = None;
+        self.device
+            .CreateTexture2D(&desc, None, Some(&mut staging))?;
+        let staging = staging.ok_or_else(|| anyhow!("CreateTexture2D returned no texture"))?;
+        self.staging = Some(staging.clone());
+        Ok(staging)
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for WindowsEncoder {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .transform
+                .ProcessMessage(MFT_MESSAGE_NOTIFY_END_STREAMING, 0);
+            if let Ok(shutdown) = self.transform.cast::<IMFShutdown>() {
+                let _ = shutdown.Shutdown();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl SharedTextures {
+    /// Sets up the fence and, where the driver has one, the NV12 video
+    /// processor. Textures come later in [`SharedTextures::allocate`], once
+    /// the encoder has picked its input format.
+    unsafe fn new(
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        width: u32,
+        height: u32,
+        fps: u16,
+    ) -> Result<Self> {
+        let device5: ID3D11Device5 = device
+            .cast()
+            .context("D3D11 fences need Windows 10 1703 or later")?;
+        let mut fence: Option<ID3D11Fence> = None;
+        device5
+            .CreateFence(0, D3D11_FENCE_FLAG_NONE, &mut fence)
+            .context("failed to create a D3D11 fence")?;
+        let fence = fence.ok_or_else(|| anyhow!("CreateFence returned no fence"))?;
+        let converter = match Nv12Converter::new(device, context, width, height, fps) {
+            Ok(converter) => Some(converter),
+            Err(err) => {
+                log::debug!("no NV12 video processor, encoding BGRA: {:#}", err);
+                None
+            }
+        };
+        Ok(Self {
+            textures: Vec::new(),
+            buffers: Vec::new(),
+            ring: SharedTextureRing::new(SHARED_TEXTURES),
+            context: context.cast()?,
+            fence,
+            fence_value: 0,
+            converter,
+            held_captures: VecDeque::new(),
+        })
+    }
+
+    unsafe fn allocate(
+        &mut self,
+        device: &ID3D11Device,
+        format: DXGI_FORMAT,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        if format != DXGI_FORMAT_NV12 {
+            self.converter = None;
+        }
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_RENDER_TARGET.0 as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+        for _ in 0..self.ring.len() {
+            let mut texture = None;
+            device
+                .CreateTexture2D(&desc, None, Some(&mut texture))
+                .context("failed to create a shared encoder texture")?;
+            let texture = texture.ok_or_else(|| anyhow!("CreateTexture2D returned no texture"))?;
+            let buffer = MFCreateDXGISurfaceBuffer(&ID3D11Texture2D::IID, &texture, 0, false)?;
+            let length = buffer.cast::<IMF2DBuffer>()?.GetContiguousLength()?;
+            buffer.SetCurrentLength(length)?;
+            if let Some(converter) = &mut self.converter {
+                converter.add_output(&texture)?;
+            }
+            self.textures.push(texture);
+            self.buffers.push(buffer);
+        }
+        Ok(())
+    }
+
+    /// Hands back captures the GPU has finished copying from.
+    unsafe fn release_captures(&mut self) {
+        let completed = self.fence.GetCompletedValue();
+        while let Some((fence_value, _)) = self.held_captures.front() {
+            if *fence_value > completed {
+                break;
+            }
+            if let Some((_, capture)) = self.held_captures.pop_front() {
+                let _ = capture.Close();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Nv12Converter {
+    unsafe fn new(
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        width: u32,
+        height: u32,
+        fps: u16,
+    ) -> Result<Self> {
+        let video_device: ID3D11VideoDevice = device.cast()?;
+        let video_context: ID3D11VideoContext = context.cast()?;
+        let frame_rate = DXGI_RATIONAL {
+            Numerator: u32::from(fps.max(1)),
+            Denominator: 1,
+        };
+        let content = D3D11_VIDEO_PROCESSOR_CONTENT_DESC {
+            InputFrameFormat: D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            InputFrameRate: frame_rate,
+            InputWidth: width,
+            InputHeight: height,
+            OutputFrameRate: frame_rate,
+            OutputWidth: width,
+            OutputHeight: height,
+            Usage: D3D11_VIDEO_USAGE_OPTIMAL_SPEED,
+        };
+        let enumerator = video_device.CreateVideoProcessorEnumerator(&content)?;
+        let support = enumerator.CheckVideoProcessorFormat(DXGI_FORMAT_NV12)?;
+        if support & D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_OUTPUT.0 as u32 == 0 {
+            return Err(anyhow!("video processor cannot output NV12"));
+        }
+        let processor = video_device.CreateVideoProcessor(&enumerator, 0)?;
+        // Screen content wants no denoising or edge enhancement.
+        video_context.VideoProcessorSetStreamAutoProcessingMode(&processor, 0, false);
+        video_context.VideoProcessorSetStreamFrameFormat(
+            &processor,
+            0,
+            D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+        );
+        Ok(Self {
+            video_device,
+            video_context,
+            enumerator,
+            processor,
+            output_views: Vec::new(),
+        })
+    }
+
+    unsafe fn add_output(&mut self, texture: &ID3D11Texture2D) -> Result<()> {
+        let desc = D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC {
+            ViewDimension: D3D11_VPOV_DIMENSION_TEXTURE2D,
+            Anonymous: D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC_0 {
+                Texture2D: D3D11_TEX2D_VPOV { MipSlice: 0 },
+            },
+        };
+        let mut view = None;
+        self.video_device.CreateVideoProcessorOutputView(
+            texture,
+            &self.enumerator,
+            &desc,
+            Some(&mut view),
+        )?;
+        self.output_views
+            .push(view.ok_or_else(|| anyhow!("no video processor output view"))?);
+        Ok(())
+    }
+
+    /// Scales and converts a BGRA capture into output `slot`.
+    unsafe fn convert(&self, source: &ID3D11Texture2D, slot: usize) -> Result<()> {
+        let desc = D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC {
+            FourCC: 0,
+            ViewDimension: D3D11_VPIV_DIMENSION_TEXTURE2D,
+            Anonymous: D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC_0 {
+                Texture2D: D3D11_TEX2D_VPIV {
+                    MipSlice: 0,
+                    ArraySlice: 0,
+                },
+            },
+        };
+        let mut input_view = None;
+        self.video_device.CreateVideoProcessorInputView(
+            source,
+            &self.enumerator,
+            &desc,
+            Some(&mut input_view),
+        )?;
+        let mut stream = D3D11_VIDEO_PROCESSOR_STREAM {
+            Enable: true.into(),
+            pInputSurface: ManuallyDrop::new(input_view),
+            ..Default::default()
+        };
+        let result = self.video_context.VideoProcessorBlt(
+            &self.processor,
+            &self.output_views[slot],
+            0,
+            std::slice::from_ref(&stream),
+        );
+        ManuallyDrop::drop(&mut stream.pInputSurface);
+        result.context("video processor conversion failed")
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn zero_copy_enabled() -> bool {
+    !matches!(
+        std::env::var(ZERO_COPY_ENV).as_deref().map(str::trim),
+        Ok("0") | Ok("false") | Ok("off")
+    )
+}
+
+/// A hardware device with video support where the driver allows it, which
+/// the video processor needs.
+#[cfg(target_os = "windows")]
+unsafe fn create_encoder_device() -> Result<(ID3D11Device, ID3D11DeviceContext)> {
+    let mut last_error = None;
+    for flags in [
+        D3D11_CREATE_DEVICE_BGRA_SUPPORT | D3D11_CREATE_DEVICE_VIDEO_SUPPORT,
+        D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+    ] {
+        let mut device = None;
+        let mut context = None;
+        match D3D11CreateDevice(
+            None,
+            D3D_DRIVER_TYPE_HARDWARE,
+            HMODULE::default(),
+            flags,
+            None,
+            D3D11_SDK_VERSION,
+            Some(&mut device),
+            None,
+            Some(&mut context),
+        ) {
+            Ok(()) => {
+                let device = device.ok_or_else(|| anyhow!("Failed to create D3D11 device"))?;
+                let context = context.ok_or_else(|| anyhow!("Failed to create D3D11 context"))?;
+                return Ok((device, context));
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(anyhow!("D3D11CreateDevice failed: {:?}", last_error))
+}
+
+/// Unlocks an asynchronous MFT and returns its event queue, or `None` for
+/// a synchronous one.
+#[cfg(target_os = "windows")]
+unsafe fn unlock_async_transform(
+    transform: &IMFTransform,
+) -> Result<Option<IMFMediaEventGenerator>> {
+    let Ok(attributes) = transform.GetAttributes() else {
+        return Ok(None);
+    };
+    if attributes.GetUINT32(&MF_TRANSFORM_ASYNC).unwrap_or(0) == 0 {
+        return Ok(None);
+    }
+    attributes
+        .SetUINT32(&MF_TRANSFORM_ASYNC_UNLOCK, 1)
+        .context("failed to unlock the asynchronous encoder")?;
+    Ok(Some(transform.cast()?))
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn attach_device_manager(transform: &IMFTransform, device: &ID3D11Device) -> Result<()> {
+    let mut device_manager = None;
+    let mut reset_token = 0;
+    MFCreateDXGIDeviceManager(&mut reset_token, &mut device_manager)?;
+    let device_manager =
+        device_manager.ok_or_else(|| anyhow!("Failed to create MF device manager"))?;
+    device_manager.ResetDevice(device, reset_token)?;
+    transform
+        .ProcessMessage(
+            MFT_MESSAGE_SET_D3D_MANAGER,
+            device_manager.as_raw() as usize,
+        )
+        .context("encoder MFT does not accept a D3D11 device")?;
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn set_video_input_type(
+    transform: &IMFTransform,
+    subtype: &GUID,
+    width: u32,
+    height: u32,
+    fps: u16,
+) -> Result<()> {
+    let input_media_type: IMFMediaType = MFCreateMediaType()?;
+    input_media_type.SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)?;
+    input_media_type.SetGUID(&MF_MT_SUBTYPE, subtype)?;
+    MFSetAttributeSize(&input_media_type, &MF_MT_FRAME_SIZE, width, height)?;
+    MFSetAttributeRatio(&input_media_type, &MF_MT_FRAME_RATE, fps as u32, 1)?;
+    input_media_type.SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)?;
+    if *subtype == MF_BGR32 {
+        // Rows are top-down, as read from the staging texture.
+        input_media_type.SetUINT32(&MF_MT_DEFAULT_STRIDE, width * 4)?;
+    }
+    transform.SetInputType(0, Some(&input_media_type), 0)?;
+    Ok(())
+}
+
+/// Applies `tuning` through `ICodecAPI` before the media types are set.
+/// Hardware MFTs differ in which properties they honour, so every setting
+/// is best effort.
+#[cfg(target_os = "windows")]
+unsafe fn apply_codec_tuning(transform: &IMFTransform, tuning: &EncoderTuning, frame_height: u32) {
+    let Ok(codec_api) = transform.cast::<ICodecAPI>() else {
+        log::debug!("encoder MFT has no ICodecAPI; keeping its default tuning");
+        return;
+    };
+
+    // 0 favours speed, 100 quality.
+    let quality_vs_speed: u32 = if tuning.low_latency { 0 } else { 50 };
+    let mut settings = vec![
+        (
+            CODECAPI_AVLowLatencyMode,
+            codec_api_bool(tuning.low_latency),
+        ),
+        (
+            CODECAPI_AVEncCommonQualityVsSpeed,
+            codec_api_u32(quality_vs_speed),
+        ),
+    ];
+    if tuning.slices > 0 {
+        // Mode 2 sizes slices in macroblock rows.
+        let mb_rows = frame_height.div_ceil(16);
+        settings.push((CODECAPI_AVEncSliceControlMode, codec_api_u32(2)));
+        settings.push((
+            CODECAPI_AVEncSliceControlSize,
+            codec_api_u32(mb_rows.div_ceil(u32::from(tuning.slices))),
+        ));
+    }
+    for (api, value) in &settings {
+        if let Err(err) = codec_api.SetValue(api, value) {
+            log::debug!("encoder ignored codec API setting {:?}: {}", api, err);
+        }
+    }
+
+    // Media Foundation has no common intra-refresh or screen-content switch.
+    if tuning.refresh == RefreshMode::IntraRefresh {
+        log::debug!("intra refresh is unavailable through Media Foundation; using IDR frames");
+    }
+}
+
+/// `ICodecAPI` takes its numeric settings as `VT_UI4` variants.
+#[cfg(target_os = "windows")]
+fn codec_api_u32(value: u32) -> VARIANT {
+    let mut variant = VARIANT::default();
+    unsafe {
+        let inner = &mut *variant.Anonymous.Anonymous;
+        inner.vt = VT_UI4;
+        inner.Anonymous.ulVal = value;
+    }
+    variant
+}
+
+#[cfg(target_os = "windows")]
+fn codec_api_bool(value: bool) -> VARIANT {
+    let mut variant = VARIANT::default();
+    unsafe {
+        let inner = &mut *variant.Anonymous.Anonymous;
+        inner.vt = VT_BOOL;
+        inner.Anonymous.boolVal = if value { VARIANT_TRUE } else { VARIANT_FALSE };
+    }
+    variant
 }
 
 /// Windows video renderer using D3D11
@@ -503,8 +1330,8 @@ impl WindowsAudioRenderer {
         })
     }
 
-    pub fn push(&mut self, payload: &[u8]) -> Result<()> {
-        self.inner.push(payload)
+    pub fn push(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        self.inner.push(payload, timestamp_us)
     }
 }
 
@@ -514,6 +1341,28 @@ impl Renderer for WindowsAudioRenderer {
     }
 }
 
+/// Client microphone played into a loopback driver such as VB-CABLE, whose
+/// recording end host applications use as a microphone.
+pub struct WindowsVirtualMicrophone {
+    inner: crate::audio::renderer::CpalAudioRenderer,
+}
+
+unsafe impl Send for WindowsVirtualMicrophone {}
+
+impl WindowsVirtualMicrophone {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inner: crate::audio::renderer::CpalAudioRenderer::new_virtual_microphone()?,
+        })
+    }
+}
+
+impl Renderer for WindowsVirtualMicrophone {
+    fn render(&mut self, payload: &[u8], timestamp_us: u64) -> Result<()> {
+        self.inner.render(payload, timestamp_us)
+    }
+}
+
 /// Windows audio capturer
 #[allow(dead_code)]
 pub struct WindowsAudioCapturer {
@@ -521,9 +1370,10 @@ pub struct WindowsAudioCapturer {
     capture_client: IAudioCaptureClient,
     format: WaveFormatGuard,
     #[cfg(feature = "opus-support")]
-    encoder: OpusEncoder,
+    encoder: OpusAudioEncoder,
     pcm: VecDeque<i16>,
     next_timestamp_us: Option<u64>,
+    frame_samples: usize,
     frame_duration_us: u64,
     channels: usize,
     sample_rate: u32,
@@ -812,8 +1662,9 @@ impl WindowsAudioCapturer {
             let input_sample_rate = (*format).nSamplesPerSec;
             let input_channels = (*format).nChannels as usize;
 
+            let opus = OpusConfig::default();
             #[cfg(feature = "opus-support")]
-            let encoder = create_opus_encoder()?;
+            let encoder = OpusAudioEncoder::new(OPUS_CHANNELS, opus)?;
 
             Ok(Self {
                 audio_client,
@@ -823,7 +1674,8 @@ impl WindowsAudioCapturer {
                 encoder,
                 pcm: VecDeque::with_capacity(AUDIO_MAX_BUFFER_SAMPLES),
                 next_timestamp_us: None,
-                frame_duration_us: opus_frame_duration_us(),
+                frame_samples: opus.frame_samples(),
+                frame_duration_us: u64::from(opus.frame_duration_us),
                 channels: OPUS_CHANNELS,
                 sample_rate: OPUS_SAMPLE_RATE,
                 input_channels,
@@ -835,29 +1687,33 @@ impl WindowsAudioCapturer {
         }
     }
 
+    /// Switches to the negotiated bitrate, frame size and FEC setting.
+    /// Buffered samples carry over into the new frame size.
+    pub fn set_opus_config(&mut self, config: OpusConfig) -> Result<()> {
+        let config = config.normalized();
+        #[cfg(feature = "opus-support")]
+        {
+            self.encoder = OpusAudioEncoder::new(self.channels, config)?;
+        }
+        self.frame_samples = config.frame_samples();
+        self.frame_duration_us = u64::from(config.frame_duration_us);
+        Ok(())
+    }
+
     pub fn next_frame(&mut self) -> Result<EncodedFrame> {
         self.capture_into_buffer()?;
 
-        let frame_len = OPUS_FRAME_SAMPLES * self.channels;
+        let frame_len = self.frame_samples * self.channels;
         if self.pcm.len() < frame_len {
             return Err(anyhow!("Not enough audio samples"));
         }
 
         let _frame: Vec<i16> = self.pcm.drain(..frame_len).collect();
-        let mut _out = vec![0u8; OPUS_MAX_PACKET_BYTES];
 
         #[cfg(feature = "opus-support")]
-        {
-            let encoded = self
-                .encoder
-                .encode(&_frame, &mut _out)
-                .map_err(|e| anyhow!("Opus encode failed: {}", e))?;
-            _out.truncate(encoded);
-        }
+        let data = self.encoder.encode(&_frame)?;
         #[cfg(not(feature = "opus-support"))]
-        {
-            return Err(anyhow!("Opus support not enabled"));
-        }
+        let data: Vec<u8> = return Err(anyhow!("Opus support not enabled"));
 
         let timestamp_us = self
             .next_timestamp_us
@@ -867,7 +1723,7 @@ impl WindowsAudioCapturer {
         Ok(EncodedFrame {
             timestamp_us,
             keyframe: true,
-            data: _out,
+            data,
             capture_duration_us: 0,
             encode_duration_us: 0,
         })
@@ -959,8 +1815,10 @@ impl WindowsAudioCapturer {
                         self.next_timestamp_us = Some(self.start_time.elapsed().as_micros() as u64);
                     }
 
-                    if self.pcm.len() > AUDIO_MAX_BUFFER_SAMPLES {
-                        let drop = self.pcm.len() - AUDIO_MAX_BUFFER_SAMPLES;
+                    let max_buffered = AUDIO_MAX_BUFFER_SAMPLES
+                        .max(AUDIO_MAX_BUFFER_FRAMES * self.frame_samples * self.channels);
+                    if self.pcm.len() > max_buffered {
+                        let drop = self.pcm.len() - max_buffered;
                         let aligned_drop = drop - (drop % self.channels.max(1));
                         for _ in 0..aligned_drop {
                             self.pcm.pop_front();
@@ -989,19 +1847,6 @@ enum WindowsAudioCaptureMode {
     Application(u32),
 }
 
-#[cfg(feature = "opus-support")]
-fn create_opus_encoder() -> Result<OpusEncoder> {
-    let mut encoder = OpusEncoder::new(OPUS_SAMPLE_RATE, Channels::Stereo, Application::Audio)
-        .map_err(|e| anyhow!("Opus encoder init failed: {}", e))?;
-    encoder
-        .set_bitrate(opus::Bitrate::Bits(OPUS_BITRATE_BPS))
-        .map_err(|e| anyhow!("Opus bitrate set failed: {}", e))?;
-    encoder.set_complexity(5).ok();
-    encoder.set_inband_fec(false).ok();
-    encoder.set_dtx(false).ok();
-    Ok(encoder)
-}
-
 enum PcmFormat {
     I16,
     I32,
@@ -1570,6 +2415,10 @@ impl crate::CapabilityProbe for WindowsProbe {
             Ok(displays)
         }
     }
+
+    fn watch_displays(&self) -> Result<crate::DisplayWatch> {
+        crate::display_watch::watch()
+    }
 }
 
 fn mf_subtype_for_codec(codec: Codec) -> GUID {
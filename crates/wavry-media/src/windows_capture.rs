@@ -0,0 +1,239 @@
+//! Windows.Graphics.Capture sessions, shared by the Windows encoder and
+//! `wavry_platform::WgcCapturer`.
+//!
+//! WGC only hands out a frame when the screen changed, so an idle desktop
+//! produces nothing to copy. On Windows 11 24H2 and later each frame also
+//! carries the regions that changed.
+
+use anyhow::{Context, Result};
+use windows::core::{Interface, HRESULT};
+use windows::Graphics::Capture::{
+    Direct3D11CaptureFrame, Direct3D11CaptureFramePool, GraphicsCaptureDirtyRegionMode,
+    GraphicsCaptureItem, GraphicsCaptureSession,
+};
+use windows::Graphics::DirectX::Direct3D11::IDirect3DDevice;
+use windows::Graphics::DirectX::DirectXPixelFormat;
+use windows::Graphics::SizeInt32;
+use windows::Win32::Graphics::Direct3D11::{ID3D11Device, ID3D11Texture2D};
+use windows::Win32::Graphics::Dxgi::IDXGIDevice;
+use windows::Win32::Graphics::Gdi::HMONITOR;
+use windows::Win32::System::WinRT::Direct3D11::IDirect3DDxgiInterfaceAccess;
+use windows::Win32::System::WinRT::Graphics::Capture::IGraphicsCaptureItemInterop;
+use windows::Win32::UI::WindowsAndMessaging::GetDesktopWindow;
+
+use crate::DirtyRect;
+
+const PIXEL_FORMAT: DirectXPixelFormat = DirectXPixelFormat::B8G8R8A8UIntNormalized;
+
+extern "system" {
+    fn CreateDirect3D11DeviceFromDXGIDevice(
+        dxgidevice: *mut std::ffi::c_void,
+        graphicsdevice: *mut *mut std::ffi::c_void,
+    ) -> HRESULT;
+}
+
+/// Captures one monitor, or the whole desktop, into a free-threaded frame
+/// pool on the caller's D3D11 device.
+pub struct WgcCapture {
+    winrt_device: IDirect3DDevice,
+    _item: GraphicsCaptureItem,
+    session: GraphicsCaptureSession,
+    frame_pool: Direct3D11CaptureFramePool,
+    pool_size: SizeInt32,
+    buffers: i32,
+}
+
+impl WgcCapture {
+    /// `display_id` is an HMONITOR, as in `EncodeConfig`. Capture starts
+    /// with [`Self::start`], once the session is configured.
+    pub fn new(device: &ID3D11Device, display_id: Option<u32>, buffers: i32) -> Result<Self> {
+        let winrt_device = create_direct3d_device(device)?;
+        let interop = windows::core::factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
+        let item: GraphicsCaptureItem = unsafe {
+            match display_id {
+                Some(id) => interop.CreateForMonitor(HMONITOR(id as _)),
+                None => interop.CreateForWindow(GetDesktopWindow()),
+            }
+        }
+        .context("failed to create a capture item")?;
+        let pool_size = item.Size()?;
+        let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
+            &winrt_device,
+            PIXEL_FORMAT,
+            buffers,
+            pool_size,
+        )?;
+        let session = frame_pool.CreateCaptureSession(&item)?;
+        // Missing on older builds, which keep the yellow capture border.
+        let _ = session.SetIsBorderRequired(false);
+        Ok(Self {
+            winrt_device,
+            _item: item,
+            session,
+            frame_pool,
+            pool_size,
+            buffers,
+        })
+    }
+
+    /// Size of the captured content, as of the latest frame.
+    pub fn size(&self) -> SizeInt32 {
+        self.pool_size
+    }
+
+    /// Shows or hides the cursor in captured frames.
+    pub fn set_cursor_capture(&self, enabled: bool) -> Result<()> {
+        self.session
+            .SetIsCursorCaptureEnabled(enabled)
+            .context("cursor capture toggle needs Windows 10 2004 or later")
+    }
+
+    /// Asks for the changed regions with each frame. Returns false on
+    /// Windows builds before 11 24H2, which don't report them.
+    pub fn enable_dirty_regions(&self) -> bool {
+        match self
+            .session
+            .SetDirtyRegionMode(GraphicsCaptureDirtyRegionMode::ReportOnly)
+        {
+            Ok(()) => true,
+            Err(err) => {
+                log::debug!("capture dirty regions unavailable: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Delivers no more than `fps` frames a second, where the Windows build
+    /// supports it.
+    pub fn set_max_fps(&self, fps: u16) {
+        let interval = windows::Foundation::TimeSpan {
+            Duration: 10_000_000 / i64::from(fps.max(1)),
+        };
+        if let Err(err) = self.session.SetMinUpdateInterval(interval) {
+            log::debug!("capture update interval unavailable: {}", err);
+        }
+    }
+
+    pub fn start(&self) -> Result<()> {
+        self.session.StartCapture()?;
+        Ok(())
+    }
+
+    /// The newest frame, or `None` when the screen has not changed since the
+    /// last one. The frame goes back to the pool when dropped or closed.
+    pub fn try_next_frame(&mut self) -> Result<Option<Direct3D11CaptureFrame>> {
+        let Ok(frame) = self.frame_pool.TryGetNextFrame() else {
+            return Ok(None);
+        };
+        let content_size = frame.ContentSize()?;
+        if content_size != self.pool_size {
+            // The pool keeps delivering the old size until it is recreated.
+            self.frame_pool.Recreate(
+                &self.winrt_device,
+                PIXEL_FORMAT,
+                self.buffers,
+                content_size,
+            )?;
+            self.pool_size = content_size;
+        }
+        Ok(Some(frame))
+    }
+}
+
+impl Drop for WgcCapture {
+    fn drop(&mut self) {
+        let _ = self.session.Close();
+        let _ = self.frame_pool.Close();
+    }
+}
+
+fn create_direct3d_device(device: &ID3D11Device) -> Result<IDirect3DDevice> {
+    let dxgi_device: IDXGIDevice = device.cast()?;
+    let mut inspectable = std::ptr::null_mut();
+    unsafe {
+        CreateDirect3D11DeviceFromDXGIDevice(dxgi_device.as_raw(), &mut inspectable).ok()?;
+        let device: IDirect3DDevice = std::mem::transmute_copy(&inspectable);
+        Ok(device)
+    }
+}
+
+/// The D3D11 texture behind a captured frame.
+pub fn frame_texture(frame: &Direct3D11CaptureFrame) -> Result<ID3D11Texture2D> {
+    let access: IDirect3DDxgiInterfaceAccess = frame.Surface()?.cast()?;
+    Ok(unsafe { access.GetInterface()? })
+}
+
+/// Regions of `frame` that changed, clipped to its content; `None` when this
+/// Windows build or session does not report them.
+pub fn dirty_rects(frame: &Direct3D11CaptureFrame) -> Option<Vec<DirtyRect>> {
+    let size = frame.ContentSize().ok()?;
+    let regions = frame.DirtyRegions().ok()?;
+    Some(
+        regions
+            .into_iter()
+            .filter_map(|r| {
+                dirty_rect(
+                    r.X,
+                    r.Y,
+                    r.Width,
+                    r.Height,
+                    size.Width as usize,
+                    size.Height as usize,
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Clips a WGC dirty region to the frame; empty regions are dropped.
+fn dirty_rect(
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    frame_width: usize,
+    frame_height: usize,
+) -> Option<DirtyRect> {
+    let left = x.clamp(0, frame_width as i32);
+    let top = y.clamp(0, frame_height as i32);
+    let right = x.saturating_add(width).clamp(0, frame_width as i32);
+    let bottom = y.saturating_add(height).clamp(0, frame_height as i32);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some(DirtyRect {
+        x: left as u16,
+        y: top as u16,
+        width: (right - left) as u16,
+        height: (bottom - top) as u16,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_regions_are_clipped_to_the_frame() {
+        assert_eq!(
+            dirty_rect(-10, 5, 30, 10, 1920, 1080),
+            Some(DirtyRect {
+                x: 0,
+                y: 5,
+                width: 20,
+                height: 10
+            })
+        );
+        assert_eq!(
+            dirty_rect(1900, 1070, 100, 100, 1920, 1080),
+            Some(DirtyRect {
+                x: 1900,
+                y: 1070,
+                width: 20,
+                height: 10
+            })
+        );
+        assert_eq!(dirty_rect(2000, 0, 10, 10, 1920, 1080), None);
+        assert_eq!(dirty_rect(0, 0, 0, 10, 1920, 1080), None);
+    }
+}
@@ -0,0 +1,113 @@
+//! XDamage change tracking for X11 capture.
+//!
+//! The X server tells us when anything was drawn into a window, so idle
+//! screens can be skipped without reading their pixels.
+
+use anyhow::{Context, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::damage::{self, ConnectionExt as DamageExt, ReportLevel};
+use x11rb::protocol::xproto::Window;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::NONE;
+
+/// XDamage object on one window, fed from the owning connection's events.
+pub struct X11Damage {
+    damage: damage::Damage,
+    damaged: bool,
+}
+
+impl X11Damage {
+    /// Starts tracking `window`. It counts as damaged until the first
+    /// [`Self::clear`].
+    pub fn new(conn: &impl Connection, window: Window) -> Result<Self> {
+        conn.damage_query_version(1, 1)?
+            .reply()
+            .context("XDamage extension unavailable")?;
+        let damage = conn.generate_id()?;
+        conn.damage_create(damage, window, ReportLevel::NON_EMPTY)?
+            .check()?;
+        Ok(Self {
+            damage,
+            damaged: true,
+        })
+    }
+
+    /// Notes `event` if it reports damage on this window. Returns whether it did.
+    pub fn observe(&mut self, event: &Event) -> bool {
+        match event {
+            Event::DamageNotify(ev) if ev.damage == self.damage => {
+                self.damaged = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forces the next check to report a change, e.g. after a resize.
+    pub fn mark(&mut self) {
+        self.damaged = true;
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        self.damaged
+    }
+
+    /// Resets the damage. Call it before copying the window, so anything
+    /// drawn during the copy shows up next time.
+    pub fn clear(&mut self, conn: &impl Connection) -> Result<()> {
+        conn.damage_subtract(self.damage, NONE, NONE)?;
+        self.damaged = false;
+        Ok(())
+    }
+
+    pub fn destroy(&self, conn: &impl Connection) {
+        let _ = conn.damage_destroy(self.damage);
+    }
+}
+
+/// Damage on the root window over a connection of its own, for capture
+/// that copies the screen elsewhere (such as `ximagesrc`).
+pub struct X11DamageWatch {
+    conn: RustConnection,
+    damage: X11Damage,
+    /// Damage seen by the previous check. A frame grabbed just before that
+    /// check may have missed it.
+    carried: bool,
+}
+
+impl X11DamageWatch {
+    pub fn open() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let damage = X11Damage::new(&conn, root)?;
+        conn.flush()?;
+        Ok(Self {
+            conn,
+            damage,
+            carried: false,
+        })
+    }
+
+    /// Whether the frame just grabbed may differ from the previous one.
+    pub fn changed(&mut self) -> Result<bool> {
+        while let Some(event) = self.conn.poll_for_event()? {
+            self.damage.observe(&event);
+        }
+        let damaged = self.damage.is_damaged();
+        if damaged {
+            self.damage.clear(&self.conn)?;
+            self.conn.flush()?;
+        }
+        let changed = damaged || self.carried;
+        self.carried = damaged;
+        Ok(changed)
+    }
+}
+
+impl Drop for X11DamageWatch {
+    fn drop(&mut self) {
+        self.damage.destroy(&self.conn);
+        let _ = self.conn.flush();
+    }
+}
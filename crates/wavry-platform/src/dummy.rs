@@ -1,4 +1,4 @@
-use crate::{FrameCapturer, InputInjector};
+use crate::{FrameCapturer, InputInjector, PointerMode};
 use anyhow::Result;
 use tracing::info;
 use wavry_media::RawFrame;
@@ -59,6 +59,31 @@ impl InputInjector for DummyInjector {
         );
         Ok(())
     }
+
+    fn touch_down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        info!("DummyInjector: Touch {} down at {}, {}", contact_id, x, y);
+        Ok(())
+    }
+
+    fn touch_move(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        info!("DummyInjector: Touch {} moved to {}, {}", contact_id, x, y);
+        Ok(())
+    }
+
+    fn touch_up(&mut self, contact_id: u32) -> Result<()> {
+        info!("DummyInjector: Touch {} up", contact_id);
+        Ok(())
+    }
+
+    fn set_pointer_mode(&mut self, mode: PointerMode) -> Result<()> {
+        info!("DummyInjector: Pointer mode {:?}", mode);
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        info!("DummyInjector: Text {:?}", text);
+        Ok(())
+    }
 }
 
 pub struct DummyCapturer;
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use anyhow::Result;
 
-use crate::InputInjector;
+use crate::{InputInjector, PointerMode};
 
 /// A single key remapping rule.
 #[derive(Debug, Clone)]
@@ -154,6 +154,26 @@ impl<I: InputInjector> InputInjector for MappedInjector<I> {
             .collect();
         self.inner.gamepad(gamepad_id, axes, &mapped_buttons)
     }
+
+    fn touch_down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        self.inner.touch_down(contact_id, x, y)
+    }
+
+    fn touch_move(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        self.inner.touch_move(contact_id, x, y)
+    }
+
+    fn touch_up(&mut self, contact_id: u32) -> Result<()> {
+        self.inner.touch_up(contact_id)
+    }
+
+    fn set_pointer_mode(&mut self, mode: PointerMode) -> Result<()> {
+        self.inner.set_pointer_mode(mode)
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.inner.text(text)
+    }
 }
 
 #[cfg(test)]
@@ -202,6 +222,21 @@ mod tests {
             }
             Ok(())
         }
+        fn touch_down(&mut self, _id: u32, _x: f32, _y: f32) -> Result<()> {
+            Ok(())
+        }
+        fn touch_move(&mut self, _id: u32, _x: f32, _y: f32) -> Result<()> {
+            Ok(())
+        }
+        fn touch_up(&mut self, _id: u32) -> Result<()> {
+            Ok(())
+        }
+        fn set_pointer_mode(&mut self, _mode: PointerMode) -> Result<()> {
+            Ok(())
+        }
+        fn text(&mut self, _text: &str) -> Result<()> {
+            Ok(())
+        }
     }
 
     #[test]
@@ -1,12 +1,29 @@
 #![allow(unsafe_code)]
 
 use anyhow::{bail, Result};
-use wavry_media::RawFrame;
+use wavry_media::{CursorState, RawFrame};
 
 pub trait FrameCapturer: Send {
     fn capture(&mut self) -> Result<RawFrame>;
 }
 
+/// Host pointer capture for the cursor channel.
+pub trait CursorCapturer: Send {
+    /// The current pointer, or `None` if nothing changed since the last poll.
+    fn poll(&mut self) -> Result<Option<CursorState>>;
+}
+
+/// How the host pointer follows the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PointerMode {
+    /// Normalized positions through `mouse_absolute`.
+    #[default]
+    Absolute,
+    /// Raw deltas through `mouse_motion`, for pointer-locked clients; the host
+    /// applies no pointer acceleration to them.
+    Relative,
+}
+
 pub trait InputInjector: Send {
     fn key(&mut self, keycode: u32, pressed: bool) -> Result<()>;
     fn mouse_button(&mut self, button: u8, pressed: bool) -> Result<()>;
@@ -19,6 +36,17 @@ pub trait InputInjector: Send {
         axes: &[(u32, f32)],
         buttons: &[(u32, bool)],
     ) -> Result<()>;
+    /// `x`/`y` are normalized to the captured display, as in `mouse_absolute`.
+    fn touch_down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()>;
+    fn touch_move(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()>;
+    fn touch_up(&mut self, contact_id: u32) -> Result<()>;
+    /// Switching to the current mode does nothing.
+    fn set_pointer_mode(&mut self, mode: PointerMode) -> Result<()>;
+    /// Types `text` as committed characters, independent of the client's
+    /// layout. Backends that can read the host layout type characters on
+    /// their own key, so games that read scan codes still see them; the rest
+    /// are injected as Unicode where the backend allows it.
+    fn text(&mut self, text: &str) -> Result<()>;
 }
 
 pub trait Clipboard: Send {
@@ -65,13 +93,33 @@ impl InputInjector for UnsupportedInjector {
     ) -> Result<()> {
         bail!("input injection is not implemented for this platform")
     }
+
+    fn touch_down(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        bail!("input injection is not implemented for this platform")
+    }
+
+    fn touch_move(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        bail!("input injection is not implemented for this platform")
+    }
+
+    fn touch_up(&mut self, _contact_id: u32) -> Result<()> {
+        bail!("input injection is not implemented for this platform")
+    }
+
+    fn set_pointer_mode(&mut self, _mode: PointerMode) -> Result<()> {
+        bail!("input injection is not implemented for this platform")
+    }
+
+    fn text(&mut self, _text: &str) -> Result<()> {
+        bail!("input injection is not implemented for this platform")
+    }
 }
 
 #[cfg(target_os = "linux")]
 mod linux;
 
 #[cfg(target_os = "linux")]
-pub use linux::{PipewireCapturer, UinputInjector};
+pub use linux::{PipewireCapturer, UinputInjector, X11Capturer, X11CursorCapturer};
 
 mod clipboard;
 pub use clipboard::ArboardClipboard;
@@ -82,8 +130,43 @@ mod windows_input_injector;
 #[cfg(target_os = "windows")]
 pub use windows_input_injector::WindowsInjector;
 
+#[cfg(target_os = "windows")]
+mod windows_capture;
+
+#[cfg(target_os = "windows")]
+pub use windows_capture::WgcCapturer;
+
+#[cfg(target_os = "windows")]
+mod windows_cursor;
+
+#[cfg(target_os = "windows")]
+pub use windows_cursor::WindowsCursorCapturer;
+
+#[cfg(target_os = "macos")]
+mod macos_input_injector;
+
+#[cfg(target_os = "macos")]
+pub use macos_input_injector::MacInjector;
+
 mod dummy;
 pub use dummy::{DummyCapturer, DummyInjector};
 
 mod input_map;
 pub use input_map::{ButtonRemap, InputMap, KeyRemap, MappedInjector};
+
+pub use rift_core::keymap::PhysicalKey;
+
+mod touch;
+pub use touch::MAX_TOUCH_CONTACTS;
+
+mod wake_lock;
+pub use wake_lock::WakeLock;
+
+mod session_lock;
+pub use session_lock::lock_session;
+
+mod thread_priority;
+pub use thread_priority::{ThreadPriority, ThreadTuning};
+
+mod port_mapping;
+pub use port_mapping::PortMapper;
@@ -0,0 +1,130 @@
+//! Virtual Xbox 360 pad on uinput, so Steam and SDL games on the host see a
+//! real controller instead of buttons on the keyboard/mouse device.
+
+use anyhow::Result;
+use evdev::{
+    uinput::VirtualDevice, uinput::VirtualDeviceBuilder, AbsInfo, AbsoluteAxisType, AttributeSet,
+    BusType, EventType, InputEvent, InputId, Key, UinputAbsSetup,
+};
+
+/// Matches the kernel's xpad driver, so controller databases pick the Xbox 360 mapping.
+const XBOX_360_NAME: &str = "Microsoft X-Box 360 pad";
+const XBOX_360_ID: (u16, u16, u16) = (0x045e, 0x028e, 0x0110);
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+const BUTTON_CODES: [u16; 11] = [
+    0x130, // BTN_SOUTH (A)
+    0x131, // BTN_EAST (B)
+    0x133, // BTN_X
+    0x134, // BTN_Y
+    0x136, // BTN_TL (LB)
+    0x137, // BTN_TR (RB)
+    0x13a, // BTN_SELECT (Back)
+    0x13b, // BTN_START
+    0x13c, // BTN_MODE (Guide)
+    0x13d, // BTN_THUMBL
+    0x13e, // BTN_THUMBR
+];
+
+/// Protocol axis to evdev axis and value: sticks 0-3, triggers 4-5, d-pad 6-7.
+fn xbox_axis(axis: u32, value: f32) -> Option<(u16, i32)> {
+    let stick = |v: f32| (v.clamp(-1.0, 1.0) * 32767.0) as i32;
+    let trigger = |v: f32| (v.clamp(0.0, 1.0) * 255.0) as i32;
+    let hat = |v: f32| v.round().clamp(-1.0, 1.0) as i32;
+    Some(match axis {
+        0 => (ABS_X, stick(value)),
+        1 => (ABS_Y, stick(value)),
+        2 => (ABS_RX, stick(value)),
+        3 => (ABS_RY, stick(value)),
+        4 => (ABS_Z, trigger(value)),
+        5 => (ABS_RZ, trigger(value)),
+        6 => (ABS_HAT0X, hat(value)),
+        7 => (ABS_HAT0Y, hat(value)),
+        _ => return None,
+    })
+}
+
+fn xbox_button(button: u32) -> Option<u16> {
+    BUTTON_CODES.get(button as usize).copied()
+}
+
+pub(super) struct VirtualGamepad {
+    device: VirtualDevice,
+}
+
+impl VirtualGamepad {
+    pub(super) fn new() -> Result<Self> {
+        let mut keys = AttributeSet::<Key>::new();
+        for code in BUTTON_CODES {
+            keys.insert(Key::new(code));
+        }
+        let stick = AbsInfo::new(0, -32768, 32767, 16, 128, 0);
+        let trigger = AbsInfo::new(0, 0, 255, 0, 0, 0);
+        let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+        let (vendor, product, version) = XBOX_360_ID;
+
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name(XBOX_360_NAME)
+            .input_id(InputId::new(BusType::BUS_USB, vendor, product, version))
+            .with_keys(&keys)?;
+        for (code, info) in [
+            (ABS_X, stick),
+            (ABS_Y, stick),
+            (ABS_RX, stick),
+            (ABS_RY, stick),
+            (ABS_Z, trigger),
+            (ABS_RZ, trigger),
+            (ABS_HAT0X, hat),
+            (ABS_HAT0Y, hat),
+        ] {
+            builder =
+                builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType(code), info))?;
+        }
+        Ok(Self {
+            device: builder.build()?,
+        })
+    }
+
+    /// Unknown axes and buttons are dropped; `emit` ends the batch with SYN_REPORT.
+    pub(super) fn apply(&mut self, axes: &[(u32, f32)], buttons: &[(u32, bool)]) -> Result<()> {
+        let axes = axes
+            .iter()
+            .filter_map(|&(axis, value)| xbox_axis(axis, value))
+            .map(|(code, value)| InputEvent::new(EventType::ABSOLUTE, code, value));
+        let buttons = buttons.iter().filter_map(|&(button, pressed)| {
+            xbox_button(button).map(|code| InputEvent::new(EventType::KEY, code, pressed as i32))
+        });
+        let events: Vec<InputEvent> = axes.chain(buttons).collect();
+        if !events.is_empty() {
+            self.device.emit(&events)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_input_maps_onto_xbox_360_codes() {
+        assert_eq!(xbox_axis(0, -1.5), Some((ABS_X, -32767)));
+        assert_eq!(xbox_axis(3, 0.5), Some((ABS_RY, 16383)));
+        assert_eq!(xbox_axis(5, 1.0), Some((ABS_RZ, 255)));
+        assert_eq!(xbox_axis(4, -0.3), Some((ABS_Z, 0)));
+        assert_eq!(xbox_axis(7, -0.9), Some((ABS_HAT0Y, -1)));
+        assert_eq!(xbox_axis(8, 1.0), None);
+
+        assert_eq!(xbox_button(0), Some(0x130));
+        assert_eq!(xbox_button(8), Some(0x13c));
+        assert_eq!(xbox_button(11), None);
+    }
+}
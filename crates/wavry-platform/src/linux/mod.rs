@@ -1,3 +1,5 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::fs;
 use std::future::Future;
 use std::os::fd::{AsRawFd, OwnedFd};
@@ -30,7 +32,17 @@ use x11rb::protocol::xtest::ConnectionExt as XTestExt;
 
 use wavry_media::{FrameData, FrameFormat, RawFrame};
 
-use crate::{FrameCapturer, InputInjector};
+use crate::{FrameCapturer, InputInjector, PhysicalKey, PointerMode};
+
+mod gamepad;
+mod touchscreen;
+mod x11_capture;
+mod x11_cursor;
+
+use gamepad::VirtualGamepad;
+use touchscreen::VirtualTouchscreen;
+pub use x11_capture::X11Capturer;
+pub use x11_cursor::X11CursorCapturer;
 
 fn element_available(name: &str) -> bool {
     gst::ElementFactory::find(name).is_some()
@@ -52,18 +64,16 @@ fn require_elements(names: &[&str]) -> Result<()> {
     }
 }
 
-pub struct PipewireCapturer {
-    _fd: Option<OwnedFd>,
-    #[allow(dead_code)]
-    pipeline: gst::Pipeline,
-    appsink: gst_app::AppSink,
+#[allow(clippy::large_enum_variant)]
+pub enum PipewireCapturer {
+    Gst(GstCapturer),
+    X11(X11Capturer),
 }
 
 impl PipewireCapturer {
     pub async fn new() -> Result<Self> {
         gst::init()?;
-        let portal = open_portal_stream().await;
-        let (pipeline_str, fd_opt) = match portal {
+        match open_portal_stream().await {
             Ok((fd, node_id)) => {
                 require_elements(&["pipewiresrc", "videoconvert", "appsink"])?;
                 let pipeline_str = format!(
@@ -71,24 +81,55 @@ impl PipewireCapturer {
                     fd.as_raw_fd(),
                     node_id
                 );
-                (pipeline_str, Some(fd))
+                Ok(PipewireCapturer::Gst(GstCapturer::launch(
+                    &pipeline_str,
+                    Some(fd),
+                )?))
             }
             Err(err) => {
-                if std::env::var_os("DISPLAY").is_some() {
-                    require_elements(&["ximagesrc", "videoconvert", "appsink"])?;
-                    tracing::warn!(
-                        "PipeWire portal failed, falling back to X11 capture: {}",
-                        err
-                    );
-                    let pipeline_str = "ximagesrc use-damage=0 ! videoconvert ! video/x-raw,format=RGBA ! appsink name=sink max-buffers=1 drop=true sync=false".to_string();
-                    (pipeline_str, None)
-                } else {
+                if std::env::var_os("DISPLAY").is_none() {
                     return Err(err);
                 }
+                tracing::warn!(
+                    "PipeWire portal failed, falling back to X11 capture: {}",
+                    err
+                );
+                match X11Capturer::new() {
+                    Ok(x11) => Ok(PipewireCapturer::X11(x11)),
+                    Err(shm_err) => {
+                        require_elements(&["ximagesrc", "videoconvert", "appsink"])?;
+                        tracing::warn!("XShm capture failed, using ximagesrc: {}", shm_err);
+                        let pipeline_str = "ximagesrc use-damage=0 ! videoconvert ! video/x-raw,format=RGBA ! appsink name=sink max-buffers=1 drop=true sync=false";
+                        Ok(PipewireCapturer::Gst(GstCapturer::launch(
+                            pipeline_str,
+                            None,
+                        )?))
+                    }
+                }
             }
-        };
+        }
+    }
+}
 
-        let pipeline = gst::parse::launch(&pipeline_str)?
+impl FrameCapturer for PipewireCapturer {
+    fn capture(&mut self) -> Result<RawFrame> {
+        match self {
+            PipewireCapturer::Gst(gst) => gst.capture(),
+            PipewireCapturer::X11(x11) => x11.capture(),
+        }
+    }
+}
+
+pub struct GstCapturer {
+    _fd: Option<OwnedFd>,
+    #[allow(dead_code)]
+    pipeline: gst::Pipeline,
+    appsink: gst_app::AppSink,
+}
+
+impl GstCapturer {
+    fn launch(pipeline_str: &str, fd: Option<OwnedFd>) -> Result<Self> {
+        let pipeline = gst::parse::launch(pipeline_str)?
             .downcast::<gst::Pipeline>()
             .map_err(|_| anyhow!("failed to downcast pipeline"))?;
         let appsink = pipeline
@@ -100,14 +141,14 @@ impl PipewireCapturer {
         pipeline.set_state(gst::State::Playing)?;
 
         Ok(Self {
-            _fd: fd_opt,
+            _fd: fd,
             pipeline,
             appsink,
         })
     }
 }
 
-impl FrameCapturer for PipewireCapturer {
+impl FrameCapturer for GstCapturer {
     fn capture(&mut self) -> Result<RawFrame> {
         let sample = self
             .appsink
@@ -130,6 +171,7 @@ impl FrameCapturer for PipewireCapturer {
                 bytes: map.as_slice().to_vec(),
                 stride: info.stride()[0] as u32,
             },
+            dirty_rects: None,
         })
     }
 }
@@ -217,10 +259,60 @@ impl InputInjector for UinputInjector {
             UinputInjector::X11(x11) => x11.gamepad(gamepad_id, axes, buttons),
         }
     }
+
+    fn touch_down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        match self {
+            UinputInjector::Uinput(inner) => inner.touch_down(contact_id, x, y),
+            UinputInjector::Portal(portal) => portal.touch_down(contact_id, x, y),
+            UinputInjector::X11(x11) => x11.touch_down(contact_id, x, y),
+        }
+    }
+
+    fn touch_move(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        match self {
+            UinputInjector::Uinput(inner) => inner.touch_move(contact_id, x, y),
+            UinputInjector::Portal(portal) => portal.touch_move(contact_id, x, y),
+            UinputInjector::X11(x11) => x11.touch_move(contact_id, x, y),
+        }
+    }
+
+    fn touch_up(&mut self, contact_id: u32) -> Result<()> {
+        match self {
+            UinputInjector::Uinput(inner) => inner.touch_up(contact_id),
+            UinputInjector::Portal(portal) => portal.touch_up(contact_id),
+            UinputInjector::X11(x11) => x11.touch_up(contact_id),
+        }
+    }
+
+    fn set_pointer_mode(&mut self, mode: PointerMode) -> Result<()> {
+        match self {
+            UinputInjector::Uinput(inner) => inner.set_pointer_mode(mode),
+            UinputInjector::Portal(portal) => portal.set_pointer_mode(mode),
+            UinputInjector::X11(x11) => x11.set_pointer_mode(mode),
+        }
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        match self {
+            UinputInjector::Uinput(inner) => inner.text(text),
+            UinputInjector::Portal(portal) => portal.text(text),
+            UinputInjector::X11(x11) => x11.text(text),
+        }
+    }
 }
 
 pub struct UinputInner {
     device: VirtualDevice,
+    /// Created on each pad's first event, keyed by protocol gamepad id.
+    gamepads: HashMap<u32, VirtualGamepad>,
+    /// Created on the first touch.
+    touchscreen: Option<VirtualTouchscreen>,
+    pointer_mode: PointerMode,
+    /// Relative-only mouse used in relative mode. The main device also has
+    /// absolute axes, which makes compositors treat its deltas as a tablet's
+    /// and accelerate them; a plain mouse keeps raw deltas for pointer-locked
+    /// games. Created on the first switch to relative mode.
+    raw_pointer: Option<VirtualDevice>,
 }
 
 impl UinputInner {
@@ -229,10 +321,6 @@ impl UinputInner {
         for code in 0u16..=255u16 {
             keys.insert(Key::new(code));
         }
-        // Add common gamepad buttons
-        for code in 0x130u16..=0x13Fu16 {
-            keys.insert(Key::new(code));
-        }
 
         let mut rel_axes = AttributeSet::<RelativeAxisType>::new();
         rel_axes.insert(RelativeAxisType::REL_X);
@@ -241,8 +329,6 @@ impl UinputInner {
         rel_axes.insert(RelativeAxisType::REL_HWHEEL);
 
         let abs_info = AbsInfo::new(0, 65535, 0, 0, 0, 0);
-        let gamepad_abs_info = AbsInfo::new(-32768, 32767, 0, 0, 0, 0);
-        let trigger_abs_info = AbsInfo::new(0, 255, 0, 0, 0, 0);
 
         let device = VirtualDeviceBuilder::new()?
             .name("wavry-uinput")
@@ -250,33 +336,42 @@ impl UinputInner {
             .with_relative_axes(&rel_axes)?
             .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, abs_info))?
             .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, abs_info))?
-            // Gamepad axes
-            .with_absolute_axis(&UinputAbsSetup::new(
-                AbsoluteAxisType::ABS_RX,
-                gamepad_abs_info,
-            ))?
-            .with_absolute_axis(&UinputAbsSetup::new(
-                AbsoluteAxisType::ABS_RY,
-                gamepad_abs_info,
-            ))?
-            .with_absolute_axis(&UinputAbsSetup::new(
-                AbsoluteAxisType::ABS_Z,
-                trigger_abs_info,
-            ))?
-            .with_absolute_axis(&UinputAbsSetup::new(
-                AbsoluteAxisType::ABS_RZ,
-                trigger_abs_info,
-            ))?
-            .with_absolute_axis(&UinputAbsSetup::new(
-                AbsoluteAxisType::ABS_HAT0X,
-                AbsInfo::new(-1, 1, 0, 0, 0, 0),
-            ))?
-            .with_absolute_axis(&UinputAbsSetup::new(
-                AbsoluteAxisType::ABS_HAT0Y,
-                AbsInfo::new(-1, 1, 0, 0, 0, 0),
-            ))?
             .build()?;
-        Ok(Self { device })
+        Ok(Self {
+            device,
+            gamepads: HashMap::new(),
+            touchscreen: None,
+            pointer_mode: PointerMode::Absolute,
+            raw_pointer: None,
+        })
+    }
+
+    fn raw_pointer() -> Result<VirtualDevice> {
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_LEFT);
+        keys.insert(Key::BTN_RIGHT);
+        keys.insert(Key::BTN_MIDDLE);
+
+        let mut rel_axes = AttributeSet::<RelativeAxisType>::new();
+        rel_axes.insert(RelativeAxisType::REL_X);
+        rel_axes.insert(RelativeAxisType::REL_Y);
+
+        Ok(VirtualDeviceBuilder::new()?
+            .name("wavry-raw-pointer")
+            .with_keys(&keys)?
+            .with_relative_axes(&rel_axes)?
+            .build()?)
+    }
+
+    fn touchscreen(&mut self) -> Result<&mut VirtualTouchscreen> {
+        let touchscreen = match self.touchscreen.take() {
+            Some(touchscreen) => touchscreen,
+            None => {
+                tracing::info!("creating virtual touchscreen");
+                VirtualTouchscreen::new()?
+            }
+        };
+        Ok(self.touchscreen.insert(touchscreen))
     }
 
     fn emit(&mut self, event: InputEvent) -> Result<()> {
@@ -318,11 +413,16 @@ impl InputInjector for UinputInner {
     }
 
     fn mouse_motion(&mut self, dx: i32, dy: i32) -> Result<()> {
-        self.device.emit(&[
+        let events = [
             InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, dx),
             InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_Y.0, dy),
-        ])?;
-        self.sync()
+            InputEvent::new(EventType::SYNCHRONIZATION, 0, 0),
+        ];
+        match self.raw_pointer.as_mut() {
+            Some(raw) if self.pointer_mode == PointerMode::Relative => raw.emit(&events)?,
+            _ => self.device.emit(&events)?,
+        }
+        Ok(())
     }
 
     fn mouse_absolute(&mut self, x: f32, y: f32) -> Result<()> {
@@ -362,67 +462,149 @@ impl InputInjector for UinputInner {
 
     fn gamepad(
         &mut self,
-        _gamepad_id: u32,
+        gamepad_id: u32,
         axes: &[(u32, f32)],
         buttons: &[(u32, bool)],
     ) -> Result<()> {
-        let mut events = Vec::new();
+        let pad = match self.gamepads.entry(gamepad_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let pad = VirtualGamepad::new()?;
+                tracing::info!("created virtual gamepad for pad {}", gamepad_id);
+                entry.insert(pad)
+            }
+        };
+        pad.apply(axes, buttons)
+    }
 
-        for &(axis, value) in axes {
-            let (code, val) = match axis {
-                0 => (AbsoluteAxisType::ABS_X.0, (value * 32767.0) as i32),
-                1 => (AbsoluteAxisType::ABS_Y.0, (value * 32767.0) as i32),
-                2 => (AbsoluteAxisType::ABS_RX.0, (value * 32767.0) as i32),
-                3 => (AbsoluteAxisType::ABS_RY.0, (value * 32767.0) as i32),
-                4 => (AbsoluteAxisType::ABS_Z.0, (value * 255.0) as i32),
-                5 => (AbsoluteAxisType::ABS_RZ.0, (value * 255.0) as i32),
-                6 => (AbsoluteAxisType::ABS_HAT0X.0, value as i32),
-                7 => (AbsoluteAxisType::ABS_HAT0Y.0, value as i32),
-                _ => continue,
-            };
-            events.push(InputEvent::new(EventType::ABSOLUTE, code, val));
-        }
-
-        for &(button, pressed) in buttons {
-            let code = match button {
-                0 => 0x130,  // BTN_SOUTH (A)
-                1 => 0x131,  // BTN_EAST (B)
-                2 => 0x133,  // BTN_WEST (X)
-                3 => 0x134,  // BTN_NORTH (Y)
-                4 => 0x136,  // BTN_TL (LB)
-                5 => 0x137,  // BTN_TR (RB)
-                6 => 0x13a,  // BTN_SELECT
-                7 => 0x13b,  // BTN_START
-                8 => 0x13c,  // BTN_MODE (Guide)
-                9 => 0x13d,  // BTN_THUMBL
-                10 => 0x13e, // BTN_THUMBR
-                _ => continue,
-            };
-            events.push(InputEvent::new(
-                EventType::KEY,
-                code,
-                if pressed { 1 } else { 0 },
-            ));
+    fn touch_down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        self.touchscreen()?.down(contact_id, x, y)
+    }
+
+    fn touch_move(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        self.touchscreen()?.motion(contact_id, x, y)
+    }
+
+    fn touch_up(&mut self, contact_id: u32) -> Result<()> {
+        match self.touchscreen.as_mut() {
+            Some(touchscreen) => touchscreen.up(contact_id),
+            None => Ok(()),
         }
+    }
 
-        if !events.is_empty() {
+    fn set_pointer_mode(&mut self, mode: PointerMode) -> Result<()> {
+        if mode == self.pointer_mode {
+            return Ok(());
+        }
+        if mode == PointerMode::Relative && self.raw_pointer.is_none() {
+            tracing::info!("creating raw relative pointer");
+            self.raw_pointer = Some(Self::raw_pointer()?);
+        }
+        self.pointer_mode = mode;
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        // A kernel keyboard only has keys, and which character a key gives
+        // is up to the host's layout; assume US.
+        let shift = PhysicalKey::ShiftLeft.evdev();
+        for c in text.chars() {
+            let Some((key, shifted)) = PhysicalKey::for_us_char(c) else {
+                tracing::debug!("no uinput key for {:?}", c);
+                continue;
+            };
+            let mut events = Vec::with_capacity(6);
+            if shifted {
+                events.push(InputEvent::new(EventType::KEY, shift, 1));
+            }
+            events.push(InputEvent::new(EventType::KEY, key.evdev(), 1));
+            events.push(InputEvent::new(EventType::SYNCHRONIZATION, 0, 0));
+            events.push(InputEvent::new(EventType::KEY, key.evdev(), 0));
+            if shifted {
+                events.push(InputEvent::new(EventType::KEY, shift, 0));
+            }
+            events.push(InputEvent::new(EventType::SYNCHRONIZATION, 0, 0));
             self.device.emit(&events)?;
-            self.sync()?;
         }
         Ok(())
     }
 }
 
+/// X keysym for `c`: Latin-1 characters are their own keysyms, everything
+/// else lives in the Unicode keysym range.
+fn char_keysym(c: char) -> u32 {
+    match c {
+        '\n' => 0xff0d, // XK_Return
+        '\t' => 0xff09, // XK_Tab
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u32,
+        _ => 0x0100_0000 | c as u32,
+    }
+}
+
+const XK_SHIFT_L: u32 = 0xffe1;
+/// Time for clients to read a character typed on the scratch key before the
+/// key is rebound to the next one.
+const X11_REMAP_SETTLE: Duration = Duration::from_millis(20);
+
 pub struct X11Injector {
     conn: x11rb::rust_connection::RustConnection,
     root: Window,
+    /// Unused keycode bound to characters the layout has no key for, with
+    /// the keysym it currently carries.
+    scratch: Option<(u8, u32)>,
 }
 
 impl X11Injector {
     fn new() -> Result<Self> {
         let (conn, screen_num) = x11rb::connect(None)?;
         let root = conn.setup().roots[screen_num].root;
-        Ok(Self { conn, root })
+        Ok(Self {
+            conn,
+            root,
+            scratch: None,
+        })
+    }
+
+    fn tap(&self, code: u8, shift: Option<u8>) -> Result<()> {
+        use x11rb::protocol::xproto::{KEY_PRESS_EVENT, KEY_RELEASE_EVENT};
+        let mut events = Vec::with_capacity(4);
+        if let Some(shift) = shift {
+            events.push((KEY_PRESS_EVENT, shift));
+        }
+        events.push((KEY_PRESS_EVENT, code));
+        events.push((KEY_RELEASE_EVENT, code));
+        if let Some(shift) = shift {
+            events.push((KEY_RELEASE_EVENT, shift));
+        }
+        for (event, code) in events {
+            self.conn
+                .xtest_fake_input(event, code, 0, self.root, 0, 0, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Binds the scratch keycode to `keysym` and types it.
+    fn tap_remapped(&mut self, keysym: u32, free_code: Option<u8>) -> Result<()> {
+        let code = match self.scratch {
+            Some((code, bound)) if bound == keysym => return self.tap(code, None),
+            Some((code, _)) => {
+                self.conn.flush()?;
+                thread::sleep(X11_REMAP_SETTLE);
+                code
+            }
+            None => match free_code {
+                Some(code) => code,
+                None => {
+                    tracing::debug!("no free X keycode for keysym {:#x}", keysym);
+                    return Ok(());
+                }
+            },
+        };
+        self.conn.change_keyboard_mapping(1, code, 1, &[keysym])?;
+        // Round trip so the new mapping is in place before the key goes down.
+        self.conn.get_input_focus()?.reply()?;
+        self.scratch = Some((code, keysym));
+        self.tap(code, None)
     }
 
     fn to_x_keycode(keycode: u32) -> Option<u8> {
@@ -593,10 +775,75 @@ impl InputInjector for X11Injector {
         // For now, provide a stub implementation
         Ok(())
     }
+
+    // XTest has no touch events; touches are dropped like gamepads.
+    fn touch_down(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn touch_move(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn touch_up(&mut self, _contact_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_pointer_mode(&mut self, _mode: PointerMode) -> Result<()> {
+        // XTest moves the cursor to positions we compute, so the server's
+        // acceleration never applies to relative motion.
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        let setup = self.conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = self
+            .conn
+            .get_keyboard_mapping(min, max - min + 1)?
+            .reply()?;
+        let per_code = usize::from(mapping.keysyms_per_keycode).max(1);
+        let codes = || mapping.keysyms.chunks(per_code).zip(min..=max);
+        // The first two keysyms of a keycode are its plain and shifted
+        // characters in the first group.
+        let lookup = |keysym: u32| {
+            codes().find_map(|(syms, code)| {
+                syms.iter()
+                    .take(2)
+                    .position(|&sym| sym == keysym)
+                    .map(|level| (code, level == 1))
+            })
+        };
+        let shift = lookup(XK_SHIFT_L).map(|(code, _)| code);
+        let free_code = codes()
+            .filter(|(syms, _)| syms.iter().all(|&sym| sym == 0))
+            .map(|(_, code)| code)
+            .last();
+
+        for c in text.chars() {
+            let keysym = char_keysym(c);
+            match lookup(keysym) {
+                Some((code, shifted)) => self.tap(code, shift.filter(|_| shifted))?,
+                None => self.tap_remapped(keysym, free_code)?,
+            }
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for X11Injector {
+    fn drop(&mut self) {
+        if let Some((code, _)) = self.scratch.take() {
+            let _ = self.conn.change_keyboard_mapping(1, code, 1, &[0]);
+            let _ = self.conn.flush();
+        }
+    }
 }
 
 enum PortalEvent {
     Key { keycode: u32, pressed: bool },
+    Keysym { keysym: u32, pressed: bool },
     Button { button: i32, pressed: bool },
     Motion { dx: f64, dy: f64 },
     MotionAbsolute { x: f64, y: f64 },
@@ -662,6 +909,16 @@ impl PortalInjector {
                                     .notify_keyboard_keycode(&session, keycode as i32, state)
                                     .await;
                             }
+                            PortalEvent::Keysym { keysym, pressed } => {
+                                let state = if pressed {
+                                    KeyState::Pressed
+                                } else {
+                                    KeyState::Released
+                                };
+                                let _ = proxy
+                                    .notify_keyboard_keysym(&session, keysym as i32, state)
+                                    .await;
+                            }
                             PortalEvent::Button { button, pressed } => {
                                 let state = if pressed {
                                     KeyState::Pressed
@@ -806,6 +1063,41 @@ impl InputInjector for PortalInjector {
         // For now, provide a stub implementation
         Ok(())
     }
+
+    // The session only requests pointer and keyboard devices.
+    fn touch_down(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn touch_move(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn touch_up(&mut self, _contact_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_pointer_mode(&mut self, _mode: PointerMode) -> Result<()> {
+        // The RemoteDesktop portal has no way to opt out of acceleration.
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        // The compositor finds a key for each keysym in the active layout,
+        // or binds a temporary one for characters the layout lacks.
+        for c in text.chars() {
+            let keysym = char_keysym(c);
+            self.send(PortalEvent::Keysym {
+                keysym,
+                pressed: true,
+            })?;
+            self.send(PortalEvent::Keysym {
+                keysym,
+                pressed: false,
+            })?;
+        }
+        Ok(())
+    }
 }
 
 fn is_wayland_session() -> bool {
@@ -0,0 +1,120 @@
+//! Virtual multi-touch screen on uinput, using the slotted (type B) protocol.
+
+use anyhow::Result;
+use evdev::{
+    uinput::VirtualDevice, uinput::VirtualDeviceBuilder, AbsInfo, AbsoluteAxisType, AttributeSet,
+    EventType, InputEvent, Key, PropType, UinputAbsSetup,
+};
+
+use crate::touch::{TouchSlots, MAX_TOUCH_CONTACTS};
+
+/// Positions span the whole device; the compositor maps it onto the output.
+const POSITION_MAX: i32 = 65535;
+
+fn position(value: f32) -> i32 {
+    (value.clamp(0.0, 1.0) * POSITION_MAX as f32) as i32
+}
+
+fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
+
+pub(super) struct VirtualTouchscreen {
+    device: VirtualDevice,
+    slots: TouchSlots,
+    next_tracking_id: i32,
+}
+
+impl VirtualTouchscreen {
+    pub(super) fn new() -> Result<Self> {
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_TOUCH);
+        let mut props = AttributeSet::<PropType>::new();
+        props.insert(PropType::DIRECT);
+        let position_info = AbsInfo::new(0, 0, POSITION_MAX, 0, 0, 0);
+
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name("wavry-touchscreen")
+            .with_keys(&keys)?
+            .with_properties(&props)?;
+        for (axis, info) in [
+            (AbsoluteAxisType::ABS_X, position_info),
+            (AbsoluteAxisType::ABS_Y, position_info),
+            (
+                AbsoluteAxisType::ABS_MT_SLOT,
+                AbsInfo::new(0, 0, MAX_TOUCH_CONTACTS as i32 - 1, 0, 0, 0),
+            ),
+            (
+                AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                AbsInfo::new(0, 0, u16::MAX as i32, 0, 0, 0),
+            ),
+            (AbsoluteAxisType::ABS_MT_POSITION_X, position_info),
+            (AbsoluteAxisType::ABS_MT_POSITION_Y, position_info),
+        ] {
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, info))?;
+        }
+        Ok(Self {
+            device: builder.build()?,
+            slots: TouchSlots::default(),
+            next_tracking_id: 0,
+        })
+    }
+
+    /// A down for a contact that is already down moves it instead.
+    pub(super) fn down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        if self.slots.get(contact_id).is_some() {
+            return self.motion(contact_id, x, y);
+        }
+        let first = self.slots.is_empty();
+        let Some(slot) = self.slots.down(contact_id) else {
+            return Ok(());
+        };
+        let tracking_id = self.next_tracking_id;
+        self.next_tracking_id = (self.next_tracking_id + 1) & i32::from(u16::MAX);
+
+        let (x, y) = (position(x), position(y));
+        let mut events = vec![
+            abs(AbsoluteAxisType::ABS_MT_SLOT, slot as i32),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, tracking_id),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, x),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y),
+            abs(AbsoluteAxisType::ABS_X, x),
+            abs(AbsoluteAxisType::ABS_Y, y),
+        ];
+        if first {
+            events.push(InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), 1));
+        }
+        self.device.emit(&events)?;
+        Ok(())
+    }
+
+    pub(super) fn motion(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        let Some(slot) = self.slots.get(contact_id) else {
+            return Ok(());
+        };
+        let (x, y) = (position(x), position(y));
+        self.device.emit(&[
+            abs(AbsoluteAxisType::ABS_MT_SLOT, slot as i32),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, x),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y),
+            abs(AbsoluteAxisType::ABS_X, x),
+            abs(AbsoluteAxisType::ABS_Y, y),
+        ])?;
+        Ok(())
+    }
+
+    pub(super) fn up(&mut self, contact_id: u32) -> Result<()> {
+        let Some(slot) = self.slots.up(contact_id) else {
+            return Ok(());
+        };
+        let mut events = vec![
+            abs(AbsoluteAxisType::ABS_MT_SLOT, slot as i32),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+        ];
+        if self.slots.is_empty() {
+            events.push(InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), 0));
+        }
+        self.device.emit(&events)?;
+        Ok(())
+    }
+}
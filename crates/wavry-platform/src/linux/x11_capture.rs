@@ -0,0 +1,359 @@
+//! X11 screen capture over MIT-SHM, for sessions without the ScreenCast portal.
+//!
+//! Captures the root window, or a single window through XComposite when
+//! `WAVRY_X11_WINDOW` names it. XDamage tells us when anything changed, so an
+//! idle desktop repeats the last frame instead of copying the screen again.
+
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::composite::{ConnectionExt as CompositeExt, Redirect};
+use x11rb::protocol::shm::{self, ConnectionExt as ShmExt};
+use x11rb::protocol::xfixes::ConnectionExt as XFixesExt;
+use x11rb::protocol::xproto::{
+    ChangeWindowAttributesAux, ConnectionExt as X11ConnectionExt, Drawable, EventMask, ImageFormat,
+    Pixmap, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use wavry_media::{FrameData, FrameFormat, RawFrame, X11Damage};
+
+use crate::FrameCapturer;
+
+/// How often to look for damage while the screen is idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+/// An idle screen still produces a frame this often, so the encoder keeps going.
+const REPEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// SysV shared memory segment attached to both us and the X server.
+struct ShmSegment {
+    conn_seg: shm::Seg,
+    addr: *mut u8,
+    len: usize,
+}
+
+impl ShmSegment {
+    fn new(conn: &RustConnection, len: usize) -> Result<Self> {
+        // SAFETY: plain SysV calls; the segment is marked for removal once the
+        // server has attached, so it cannot outlive both processes.
+        unsafe {
+            let id = libc::shmget(libc::IPC_PRIVATE, len, libc::IPC_CREAT | 0o600);
+            if id < 0 {
+                return Err(std::io::Error::last_os_error()).context("shmget failed");
+            }
+            let addr = libc::shmat(id, ptr::null(), 0);
+            if addr as isize == -1 {
+                let err = std::io::Error::last_os_error();
+                libc::shmctl(id, libc::IPC_RMID, ptr::null_mut());
+                return Err(err).context("shmat failed");
+            }
+            let conn_seg = conn.generate_id()?;
+            let attached = conn
+                .shm_attach(conn_seg, id as u32, false)
+                .map_err(anyhow::Error::from)
+                .and_then(|cookie| cookie.check().map_err(anyhow::Error::from));
+            libc::shmctl(id, libc::IPC_RMID, ptr::null_mut());
+            if let Err(err) = attached {
+                libc::shmdt(addr);
+                return Err(err.context("MIT-SHM attach failed"));
+            }
+            Ok(Self {
+                conn_seg,
+                addr: addr.cast(),
+                len,
+            })
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: `addr` maps `len` bytes until drop; the server only writes
+        // during a ShmGetImage we have already waited for.
+        unsafe { std::slice::from_raw_parts(self.addr, self.len) }
+    }
+
+    fn detach(self, conn: &RustConnection) {
+        let _ = conn.shm_detach(self.conn_seg);
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        // SAFETY: `addr` came from shmat and is detached exactly once.
+        unsafe {
+            libc::shmdt(self.addr.cast());
+        }
+    }
+}
+
+// The mapping is owned by this segment alone.
+unsafe impl Send for ShmSegment {}
+
+pub struct X11Capturer {
+    conn: RustConnection,
+    window: Window,
+    /// `window` itself, or its composite pixmap.
+    drawable: Drawable,
+    composite: bool,
+    pixmap: Option<Pixmap>,
+    width: u16,
+    height: u16,
+    red_first: bool,
+    shm: Option<ShmSegment>,
+    damage: X11Damage,
+    frame: Vec<u8>,
+    epoch: Instant,
+}
+
+impl X11Capturer {
+    pub fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        conn.shm_query_version()?
+            .reply()
+            .context("MIT-SHM extension unavailable")?;
+        conn.xfixes_query_version(5, 0)?
+            .reply()
+            .context("XFixes extension unavailable")?;
+
+        let (window, composite) = match target_window()? {
+            Some(window) => {
+                conn.composite_query_version(0, 4)?
+                    .reply()
+                    .context("XComposite extension unavailable")?;
+                conn.composite_redirect_window(window, Redirect::AUTOMATIC)?
+                    .check()?;
+                (window, true)
+            }
+            None => (root, false),
+        };
+        conn.change_window_attributes(
+            window,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+        )?
+        .check()?;
+
+        let visual = conn.get_window_attributes(window)?.reply()?.visual;
+        let red_first = conn.setup().roots[screen_num]
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| depth.visuals.iter())
+            .find(|v| v.visual_id == visual)
+            .map(|v| v.red_mask == 0xff)
+            .unwrap_or(false);
+
+        let damage = X11Damage::new(&conn, window)?;
+
+        let mut capturer = Self {
+            conn,
+            window,
+            drawable: window,
+            composite,
+            pixmap: None,
+            width: 0,
+            height: 0,
+            red_first,
+            shm: None,
+            damage,
+            frame: Vec::new(),
+            epoch: Instant::now(),
+        };
+        capturer.reconfigure()?;
+        tracing::info!(
+            "X11 capture of window {:#x} at {}x{}{}",
+            window,
+            capturer.width,
+            capturer.height,
+            if composite { " (composite)" } else { "" }
+        );
+        Ok(capturer)
+    }
+
+    /// Follows the window's size; a composite pixmap is replaced on every resize.
+    fn reconfigure(&mut self) -> Result<()> {
+        let geometry = self.conn.get_geometry(self.window)?.reply()?;
+        if self.composite {
+            if let Some(old) = self.pixmap.take() {
+                self.conn.free_pixmap(old)?;
+            }
+            let pixmap = self.conn.generate_id()?;
+            self.conn
+                .composite_name_window_pixmap(self.window, pixmap)?
+                .check()?;
+            self.pixmap = Some(pixmap);
+            self.drawable = pixmap;
+        }
+        if geometry.width == self.width && geometry.height == self.height && self.shm.is_some() {
+            return Ok(());
+        }
+
+        let bits_per_pixel = self
+            .conn
+            .setup()
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == geometry.depth)
+            .map(|format| format.bits_per_pixel);
+        if bits_per_pixel != Some(32) {
+            bail!(
+                "unsupported X11 depth {} ({:?} bpp)",
+                geometry.depth,
+                bits_per_pixel
+            );
+        }
+
+        if let Some(old) = self.shm.take() {
+            old.detach(&self.conn);
+        }
+        let len = geometry.width as usize * geometry.height as usize * 4;
+        self.shm = Some(ShmSegment::new(&self.conn, len)?);
+        self.width = geometry.width;
+        self.height = geometry.height;
+        self.frame.clear();
+        self.damage.mark();
+        Ok(())
+    }
+
+    fn drain_events(&mut self) -> Result<()> {
+        let mut resized = false;
+        while let Some(event) = self.conn.poll_for_event()? {
+            if self.damage.observe(&event) {
+                continue;
+            }
+            match event {
+                Event::ConfigureNotify(ev) if ev.window == self.window => {
+                    resized |= ev.width != self.width || ev.height != self.height;
+                }
+                _ => {}
+            }
+        }
+        if resized {
+            self.reconfigure()?;
+        }
+        Ok(())
+    }
+
+    fn grab(&mut self) -> Result<()> {
+        let shm = self
+            .shm
+            .as_ref()
+            .ok_or_else(|| anyhow!("X11 capture has no shared memory"))?;
+        // Clear the damage first so changes made during the copy show up next time.
+        self.damage.clear(&self.conn)?;
+        self.conn
+            .shm_get_image(
+                self.drawable,
+                0,
+                0,
+                self.width,
+                self.height,
+                !0,
+                ImageFormat::Z_PIXMAP.into(),
+                shm.conn_seg,
+                0,
+            )?
+            .reply()
+            .context("ShmGetImage failed")?;
+        x_pixels_to_rgba(shm.bytes(), self.red_first, &mut self.frame);
+        Ok(())
+    }
+}
+
+impl FrameCapturer for X11Capturer {
+    fn capture(&mut self) -> Result<RawFrame> {
+        let deadline = Instant::now() + REPEAT_INTERVAL;
+        loop {
+            self.drain_events()?;
+            if self.damage.is_damaged() || self.frame.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        // XDamage only says whether anything changed, not where.
+        let dirty_rects = if self.damage.is_damaged() || self.frame.is_empty() {
+            self.grab()?;
+            None
+        } else {
+            Some(Vec::new())
+        };
+
+        Ok(RawFrame {
+            width: self.width,
+            height: self.height,
+            format: FrameFormat::Rgba8,
+            timestamp_us: self.epoch.elapsed().as_micros() as u64,
+            data: FrameData::Cpu {
+                bytes: self.frame.clone(),
+                stride: self.width as u32 * 4,
+            },
+            dirty_rects,
+        })
+    }
+}
+
+impl Drop for X11Capturer {
+    fn drop(&mut self) {
+        self.damage.destroy(&self.conn);
+        if let Some(pixmap) = self.pixmap {
+            let _ = self.conn.free_pixmap(pixmap);
+        }
+        if self.composite {
+            let _ = self
+                .conn
+                .composite_unredirect_window(self.window, Redirect::AUTOMATIC);
+        }
+        if let Some(shm) = self.shm.take() {
+            shm.detach(&self.conn);
+        }
+        let _ = self.conn.flush();
+    }
+}
+
+/// Window named by `WAVRY_X11_WINDOW`, in hex (`0x3a00007`) or decimal.
+fn target_window() -> Result<Option<Window>> {
+    let Ok(value) = std::env::var("WAVRY_X11_WINDOW") else {
+        return Ok(None);
+    };
+    let value = value.trim();
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => Window::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed
+        .map(Some)
+        .map_err(|_| anyhow!("WAVRY_X11_WINDOW is not a window id: {}", value))
+}
+
+/// Converts 32-bit ZPixmap pixels (BGRX in memory, or RGBX when the visual's
+/// red mask is the low byte) into opaque RGBA.
+fn x_pixels_to_rgba(src: &[u8], red_first: bool, out: &mut Vec<u8>) {
+    out.clear();
+    out.reserve(src.len());
+    for px in src.chunks_exact(4) {
+        if red_first {
+            out.extend_from_slice(&[px[0], px[1], px[2], 0xff]);
+        } else {
+            out.extend_from_slice(&[px[2], px[1], px[0], 0xff]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x_pixels_become_opaque_rgba() {
+        let bgrx = [0x10, 0x20, 0x30, 0x00, 0xaa, 0xbb, 0xcc, 0x7f];
+        let mut out = Vec::new();
+        x_pixels_to_rgba(&bgrx, false, &mut out);
+        assert_eq!(out, [0x30, 0x20, 0x10, 0xff, 0xcc, 0xbb, 0xaa, 0xff]);
+
+        x_pixels_to_rgba(&bgrx[..4], true, &mut out);
+        assert_eq!(out, [0x10, 0x20, 0x30, 0xff]);
+    }
+}
@@ -0,0 +1,73 @@
+//! Pointer capture through XFixes, for hosts that leave the cursor out of video.
+
+use anyhow::{bail, Context, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::xfixes::{ConnectionExt as XFixesExt, GetCursorImageReply};
+use x11rb::protocol::xproto::{ConnectionExt as X11ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+
+use wavry_media::{CursorShape, CursorState};
+
+use crate::CursorCapturer;
+
+pub struct X11CursorCapturer {
+    conn: RustConnection,
+    root: Window,
+    last: Option<(i16, i16, u32)>,
+}
+
+impl X11CursorCapturer {
+    pub fn new() -> Result<Self> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            // Under Xwayland, XFixes only tracks the pointer over X11 windows.
+            bail!("X11 cursor capture is unavailable in Wayland sessions");
+        }
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        conn.xfixes_query_version(5, 0)?
+            .reply()
+            .context("XFixes extension unavailable")?;
+        Ok(Self {
+            conn,
+            root,
+            last: None,
+        })
+    }
+}
+
+impl CursorCapturer for X11CursorCapturer {
+    fn poll(&mut self) -> Result<Option<CursorState>> {
+        let image = self.conn.xfixes_get_cursor_image()?.reply()?;
+        let key = (image.x, image.y, image.cursor_serial);
+        if self.last == Some(key) {
+            return Ok(None);
+        }
+        self.last = Some(key);
+
+        let root = self.conn.get_geometry(self.root)?.reply()?;
+        Ok(Some(CursorState {
+            x: image.x as f32 / root.width.max(1) as f32,
+            y: image.y as f32 / root.height.max(1) as f32,
+            // XFixes reports hidden cursors as their last image.
+            visible: true,
+            serial: u64::from(image.cursor_serial),
+            shape: cursor_shape(image),
+        }))
+    }
+}
+
+/// XFixes pixels are premultiplied ARGB words, which are BGRA bytes in
+/// little-endian order.
+fn cursor_shape(image: GetCursorImageReply) -> CursorShape {
+    CursorShape::Bitmap {
+        width: u32::from(image.width),
+        height: u32::from(image.height),
+        hotspot_x: u32::from(image.xhot),
+        hotspot_y: u32::from(image.yhot),
+        bgra: image
+            .cursor_image
+            .iter()
+            .flat_map(|pixel| pixel.to_le_bytes())
+            .collect(),
+    }
+}
@@ -0,0 +1,518 @@
+use crate::{InputInjector, PhysicalKey, PointerMode};
+use anyhow::{bail, Result};
+use std::ffi::c_void;
+use std::time::{Duration, Instant};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CGSize {
+    width: f64,
+    height: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CGRect {
+    origin: CGPoint,
+    size: CGSize,
+}
+
+type CGEventRef = *mut c_void;
+type CGEventSourceRef = *mut c_void;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventSourceCreate(state_id: i32) -> CGEventSourceRef;
+    fn CGEventCreateMouseEvent(
+        source: CGEventSourceRef,
+        mouse_type: u32,
+        mouse_cursor_position: CGPoint,
+        mouse_button: u32,
+    ) -> CGEventRef;
+    fn CGEventCreateKeyboardEvent(
+        source: CGEventSourceRef,
+        keycode: u16,
+        keydown: bool,
+    ) -> CGEventRef;
+    fn CGEventCreateScrollWheelEvent2(
+        source: CGEventSourceRef,
+        units: u32,
+        wheel_count: u32,
+        wheel1: i32,
+        wheel2: i32,
+        wheel3: i32,
+    ) -> CGEventRef;
+    fn CGEventKeyboardSetUnicodeString(
+        event: CGEventRef,
+        string_length: usize,
+        unicode_string: *const u16,
+    );
+    fn CGEventSetType(event: CGEventRef, event_type: u32);
+    fn CGEventSetFlags(event: CGEventRef, flags: u64);
+    fn CGEventSetIntegerValueField(event: CGEventRef, field: u32, value: i64);
+    fn CGEventPost(tap: u32, event: CGEventRef);
+    fn CGMainDisplayID() -> u32;
+    fn CGDisplayBounds(display: u32) -> CGRect;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> u8;
+}
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn IsSecureEventInputEnabled() -> u8;
+}
+
+// CGEventType
+const K_CG_EVENT_LEFT_MOUSE_DOWN: u32 = 1;
+const K_CG_EVENT_LEFT_MOUSE_UP: u32 = 2;
+const K_CG_EVENT_RIGHT_MOUSE_DOWN: u32 = 3;
+const K_CG_EVENT_RIGHT_MOUSE_UP: u32 = 4;
+const K_CG_EVENT_MOUSE_MOVED: u32 = 5;
+const K_CG_EVENT_LEFT_MOUSE_DRAGGED: u32 = 6;
+const K_CG_EVENT_RIGHT_MOUSE_DRAGGED: u32 = 7;
+const K_CG_EVENT_FLAGS_CHANGED: u32 = 12;
+const K_CG_EVENT_OTHER_MOUSE_DOWN: u32 = 25;
+const K_CG_EVENT_OTHER_MOUSE_UP: u32 = 26;
+const K_CG_EVENT_OTHER_MOUSE_DRAGGED: u32 = 27;
+
+// CGEventField
+const K_CG_MOUSE_EVENT_CLICK_STATE: u32 = 1;
+const K_CG_MOUSE_EVENT_DELTA_X: u32 = 4;
+const K_CG_MOUSE_EVENT_DELTA_Y: u32 = 5;
+
+// CGEventFlags
+const K_CG_EVENT_FLAG_MASK_ALPHA_SHIFT: u64 = 0x0001_0000;
+const K_CG_EVENT_FLAG_MASK_SHIFT: u64 = 0x0002_0000;
+const K_CG_EVENT_FLAG_MASK_CONTROL: u64 = 0x0004_0000;
+const K_CG_EVENT_FLAG_MASK_ALTERNATE: u64 = 0x0008_0000;
+const K_CG_EVENT_FLAG_MASK_COMMAND: u64 = 0x0010_0000;
+const K_CG_EVENT_FLAG_MASK_SECONDARY_FN: u64 = 0x0080_0000;
+
+const K_CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE: i32 = 1;
+const K_CG_HID_EVENT_TAP: u32 = 0;
+const K_CG_SCROLL_EVENT_UNIT_PIXEL: u32 = 0;
+
+const CAPS_LOCK_KEYCODE: u32 = 0x39;
+
+/// Pixels per protocol wheel notch, about three lines as on Windows.
+const PIXELS_PER_NOTCH: f32 = 30.0;
+/// Presses closer than this, in time and points, count as one multi-click.
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+const DOUBLE_CLICK_DISTANCE: f64 = 4.0;
+
+/// Flag a held modifier key contributes, by CGKeyCode.
+fn modifier_flag(keycode: u32) -> Option<u64> {
+    Some(match keycode {
+        0x38 | 0x3C => K_CG_EVENT_FLAG_MASK_SHIFT,
+        0x3B | 0x3E => K_CG_EVENT_FLAG_MASK_CONTROL,
+        0x3A | 0x3D => K_CG_EVENT_FLAG_MASK_ALTERNATE,
+        0x37 | 0x36 => K_CG_EVENT_FLAG_MASK_COMMAND,
+        0x3F => K_CG_EVENT_FLAG_MASK_SECONDARY_FN,
+        CAPS_LOCK_KEYCODE => K_CG_EVENT_FLAG_MASK_ALPHA_SHIFT,
+        _ => return None,
+    })
+}
+
+/// Held modifier keys, so both shift keys can be down and key events carry
+/// the flags apps check for shortcuts.
+#[derive(Debug, Default)]
+struct Modifiers {
+    held: Vec<u32>,
+    caps_lock: bool,
+}
+
+impl Modifiers {
+    /// Returns false when `keycode` is not a modifier.
+    fn update(&mut self, keycode: u32, pressed: bool) -> bool {
+        if modifier_flag(keycode).is_none() {
+            return false;
+        }
+        if keycode == CAPS_LOCK_KEYCODE {
+            if pressed {
+                self.caps_lock = !self.caps_lock;
+            }
+        } else if pressed {
+            if !self.held.contains(&keycode) {
+                self.held.push(keycode);
+            }
+        } else {
+            self.held.retain(|&held| held != keycode);
+        }
+        true
+    }
+
+    fn flags(&self) -> u64 {
+        let held = self
+            .held
+            .iter()
+            .filter_map(|&keycode| modifier_flag(keycode))
+            .fold(0, |flags, flag| flags | flag);
+        if self.caps_lock {
+            held | K_CG_EVENT_FLAG_MASK_ALPHA_SHIFT
+        } else {
+            held
+        }
+    }
+}
+
+/// Protocol button (1 left, 2 middle, 3 right, 4 back, 5 forward) to
+/// CGMouseButton and its down, up and dragged event types.
+fn mouse_button_events(button: u8) -> Option<(u32, u32, u32, u32)> {
+    Some(match button {
+        1 => (
+            0,
+            K_CG_EVENT_LEFT_MOUSE_DOWN,
+            K_CG_EVENT_LEFT_MOUSE_UP,
+            K_CG_EVENT_LEFT_MOUSE_DRAGGED,
+        ),
+        3 => (
+            1,
+            K_CG_EVENT_RIGHT_MOUSE_DOWN,
+            K_CG_EVENT_RIGHT_MOUSE_UP,
+            K_CG_EVENT_RIGHT_MOUSE_DRAGGED,
+        ),
+        2 | 4 | 5 => (
+            match button {
+                2 => 2,
+                4 => 3,
+                _ => 4,
+            },
+            K_CG_EVENT_OTHER_MOUSE_DOWN,
+            K_CG_EVENT_OTHER_MOUSE_UP,
+            K_CG_EVENT_OTHER_MOUSE_DRAGGED,
+        ),
+        _ => return None,
+    })
+}
+
+/// Whole pixels to scroll now; the fraction carries into the next event so
+/// slow trackpad scrolling still moves.
+fn take_scroll_pixels(notches: f32, remainder: &mut f32) -> i32 {
+    let pixels = notches * PIXELS_PER_NOTCH + *remainder;
+    let whole = pixels.trunc();
+    *remainder = pixels - whole;
+    whole as i32
+}
+
+struct LastClick {
+    button: u8,
+    at: Instant,
+    position: CGPoint,
+    count: i64,
+}
+
+pub struct MacInjector {
+    source: CGEventSourceRef,
+    cursor: CGPoint,
+    modifiers: Modifiers,
+    /// Protocol buttons currently held, for dragged events.
+    buttons_down: Vec<u8>,
+    last_click: Option<LastClick>,
+    scroll_remainder: (f32, f32),
+    secure_input_warned: bool,
+}
+
+// The event source is only used from the owning injector.
+unsafe impl Send for MacInjector {}
+
+impl MacInjector {
+    pub fn new() -> Result<Self> {
+        let source = unsafe { CGEventSourceCreate(K_CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE) };
+        if source.is_null() {
+            bail!("failed to create a CGEventSource");
+        }
+        if unsafe { AXIsProcessTrusted() } == 0 {
+            tracing::warn!(
+                "Accessibility permission not granted; macOS will drop injected input until the \
+                 host is allowed under Privacy & Security > Accessibility"
+            );
+        }
+        let bounds = main_display_bounds();
+        Ok(Self {
+            source,
+            cursor: CGPoint {
+                x: bounds.origin.x + bounds.size.width / 2.0,
+                y: bounds.origin.y + bounds.size.height / 2.0,
+            },
+            modifiers: Modifiers::default(),
+            buttons_down: Vec::new(),
+            last_click: None,
+            scroll_remainder: (0.0, 0.0),
+            secure_input_warned: false,
+        })
+    }
+
+    fn post(&self, event: CGEventRef) {
+        if event.is_null() {
+            return;
+        }
+        unsafe {
+            CGEventSetFlags(event, self.modifiers.flags());
+            CGEventPost(K_CG_HID_EVENT_TAP, event);
+            CFRelease(event);
+        }
+    }
+
+    /// Password fields turn on secure input, which silently drops synthetic keys.
+    fn check_secure_input(&mut self) {
+        let secure = unsafe { IsSecureEventInputEnabled() } != 0;
+        if secure && !self.secure_input_warned {
+            tracing::warn!("macOS secure input is on; injected keys are dropped until it ends");
+        }
+        self.secure_input_warned = secure;
+    }
+
+    fn move_cursor(&mut self, position: CGPoint) {
+        let bounds = main_display_bounds();
+        let position = CGPoint {
+            x: position
+                .x
+                .clamp(bounds.origin.x, bounds.origin.x + bounds.size.width - 1.0),
+            y: position
+                .y
+                .clamp(bounds.origin.y, bounds.origin.y + bounds.size.height - 1.0),
+        };
+        let (delta_x, delta_y) = (position.x - self.cursor.x, position.y - self.cursor.y);
+        self.cursor = position;
+
+        // With a button held, apps expect dragged events rather than moves.
+        let (button, event_type) = match self
+            .buttons_down
+            .first()
+            .and_then(|&button| mouse_button_events(button))
+        {
+            Some((button, _, _, dragged)) => (button, dragged),
+            None => (0, K_CG_EVENT_MOUSE_MOVED),
+        };
+        unsafe {
+            let event = CGEventCreateMouseEvent(self.source, event_type, position, button);
+            if !event.is_null() {
+                CGEventSetIntegerValueField(event, K_CG_MOUSE_EVENT_DELTA_X, delta_x as i64);
+                CGEventSetIntegerValueField(event, K_CG_MOUSE_EVENT_DELTA_Y, delta_y as i64);
+            }
+            self.post(event);
+        }
+    }
+
+    fn click_count(&mut self, button: u8) -> i64 {
+        let now = Instant::now();
+        let count = match &self.last_click {
+            Some(last)
+                if last.button == button
+                    && now.duration_since(last.at) <= DOUBLE_CLICK_INTERVAL
+                    && (last.position.x - self.cursor.x).abs() <= DOUBLE_CLICK_DISTANCE
+                    && (last.position.y - self.cursor.y).abs() <= DOUBLE_CLICK_DISTANCE =>
+            {
+                last.count + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some(LastClick {
+            button,
+            at: now,
+            position: self.cursor,
+            count,
+        });
+        count
+    }
+}
+
+impl Drop for MacInjector {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.source) };
+    }
+}
+
+fn main_display_bounds() -> CGRect {
+    unsafe { CGDisplayBounds(CGMainDisplayID()) }
+}
+
+impl InputInjector for MacInjector {
+    fn key(&mut self, keycode: u32, pressed: bool) -> Result<()> {
+        let Some(keycode) = PhysicalKey::from_protocol(keycode).and_then(PhysicalKey::mac_keycode)
+        else {
+            tracing::debug!("no macOS key for keycode {}", keycode);
+            return Ok(());
+        };
+        self.check_secure_input();
+        let is_modifier = self.modifiers.update(u32::from(keycode), pressed);
+        unsafe {
+            let event = CGEventCreateKeyboardEvent(self.source, keycode, pressed);
+            if is_modifier && !event.is_null() {
+                CGEventSetType(event, K_CG_EVENT_FLAGS_CHANGED);
+            }
+            self.post(event);
+        }
+        Ok(())
+    }
+
+    fn mouse_button(&mut self, button: u8, pressed: bool) -> Result<()> {
+        let Some((cg_button, down, up, _)) = mouse_button_events(button) else {
+            return Ok(());
+        };
+        let click_count = if pressed {
+            if !self.buttons_down.contains(&button) {
+                self.buttons_down.push(button);
+            }
+            self.click_count(button)
+        } else {
+            self.buttons_down.retain(|&held| held != button);
+            self.last_click
+                .as_ref()
+                .filter(|last| last.button == button)
+                .map_or(1, |last| last.count)
+        };
+        let event_type = if pressed { down } else { up };
+        unsafe {
+            let event = CGEventCreateMouseEvent(self.source, event_type, self.cursor, cg_button);
+            if !event.is_null() {
+                CGEventSetIntegerValueField(event, K_CG_MOUSE_EVENT_CLICK_STATE, click_count);
+            }
+            self.post(event);
+        }
+        Ok(())
+    }
+
+    fn mouse_motion(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.move_cursor(CGPoint {
+            x: self.cursor.x + dx as f64,
+            y: self.cursor.y + dy as f64,
+        });
+        Ok(())
+    }
+
+    fn mouse_absolute(&mut self, x: f32, y: f32) -> Result<()> {
+        let bounds = main_display_bounds();
+        self.move_cursor(CGPoint {
+            x: bounds.origin.x + x.clamp(0.0, 1.0) as f64 * bounds.size.width,
+            y: bounds.origin.y + y.clamp(0.0, 1.0) as f64 * bounds.size.height,
+        });
+        Ok(())
+    }
+
+    fn scroll(&mut self, dx: f32, dy: f32) -> Result<()> {
+        let vertical = take_scroll_pixels(dy, &mut self.scroll_remainder.1);
+        // Positive dx scrolls right, while macOS' second wheel scrolls left.
+        let horizontal = take_scroll_pixels(-dx, &mut self.scroll_remainder.0);
+        if vertical == 0 && horizontal == 0 {
+            return Ok(());
+        }
+        unsafe {
+            let event = CGEventCreateScrollWheelEvent2(
+                self.source,
+                K_CG_SCROLL_EVENT_UNIT_PIXEL,
+                2,
+                vertical,
+                horizontal,
+                0,
+            );
+            self.post(event);
+        }
+        Ok(())
+    }
+
+    fn gamepad(
+        &mut self,
+        _gamepad_id: u32,
+        _axes: &[(u32, f32)],
+        _buttons: &[(u32, bool)],
+    ) -> Result<()> {
+        // macOS has no public API for virtual HID gamepads.
+        Ok(())
+    }
+
+    fn touch_down(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        // Nor for injecting touches; touch clients are expected to send pointer input.
+        Ok(())
+    }
+
+    fn touch_move(&mut self, _contact_id: u32, _x: f32, _y: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn touch_up(&mut self, _contact_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_pointer_mode(&mut self, _mode: PointerMode) -> Result<()> {
+        // Both modes post the cursor position we computed, so macOS applies no acceleration.
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.check_secure_input();
+        for c in text.chars() {
+            // Apps read the attached string rather than the keycode, so the
+            // host layout doesn't matter; Return and Tab go in as their keys.
+            let key = match c {
+                '\n' => PhysicalKey::Enter.mac_keycode(),
+                '\t' => PhysicalKey::Tab.mac_keycode(),
+                _ => None,
+            };
+            let mut units = [0u16; 2];
+            let units = c.encode_utf16(&mut units);
+            for keydown in [true, false] {
+                unsafe {
+                    let event = CGEventCreateKeyboardEvent(self.source, key.unwrap_or(0), keydown);
+                    if key.is_none() && !event.is_null() {
+                        CGEventKeyboardSetUnicodeString(event, units.len(), units.as_ptr());
+                    }
+                    self.post(event);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers_track_both_sides_and_caps_lock() {
+        let mut modifiers = Modifiers::default();
+        assert!(!modifiers.update(0x00, true));
+        assert!(modifiers.update(0x38, true));
+        assert!(modifiers.update(0x3C, true));
+        modifiers.update(0x38, false);
+        assert_eq!(modifiers.flags(), K_CG_EVENT_FLAG_MASK_SHIFT);
+        modifiers.update(0x3C, false);
+        assert_eq!(modifiers.flags(), 0);
+
+        modifiers.update(CAPS_LOCK_KEYCODE, true);
+        modifiers.update(CAPS_LOCK_KEYCODE, false);
+        modifiers.update(0x37, true);
+        assert_eq!(
+            modifiers.flags(),
+            K_CG_EVENT_FLAG_MASK_ALPHA_SHIFT | K_CG_EVENT_FLAG_MASK_COMMAND
+        );
+        modifiers.update(CAPS_LOCK_KEYCODE, true);
+        assert_eq!(modifiers.flags(), K_CG_EVENT_FLAG_MASK_COMMAND);
+    }
+
+    #[test]
+    fn scroll_carries_sub_pixel_remainder() {
+        let mut remainder = 0.0;
+        assert_eq!(take_scroll_pixels(0.02, &mut remainder), 0);
+        assert_eq!(take_scroll_pixels(0.02, &mut remainder), 1);
+        assert_eq!(take_scroll_pixels(-1.0, &mut remainder), -29);
+        assert_eq!(mouse_button_events(4).map(|events| events.0), Some(3));
+        assert_eq!(mouse_button_events(6), None);
+    }
+}
@@ -0,0 +1,702 @@
+//! Router port mapping, so clients outside the LAN can reach the host.
+//!
+//! Asks the default gateway over PCP (RFC 6887) first, falls back to NAT-PMP
+//! (RFC 6886) when the gateway answers with the older protocol, and then to a
+//! UPnP Internet Gateway Device found over SSDP. The mapping is renewed at
+//! half its lifetime while the host runs and removed again on shutdown; its
+//! external address is published for the host to advertise to clients.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::net::UdpSocket;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+/// PCP and NAT-PMP share the gateway port.
+const GATEWAY_PORT: u16 = 5351;
+const GATEWAY_RETRIES: u32 = 3;
+const GATEWAY_INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const PCP_VERSION: u8 = 2;
+const PCP_OPCODE_MAP: u8 = 1;
+const PCP_RESULT_UNSUPP_VERSION: u8 = 1;
+const PCP_MAP_LEN: usize = 60;
+const IPPROTO_UDP: u8 = 17;
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const SSDP_TIMEOUT: Duration = Duration::from_secs(2);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(3);
+/// Requested lease; renewed at half-life while hosting.
+const MAPPING_LIFETIME: Duration = Duration::from_secs(3600);
+const MIN_RENEW_INTERVAL: Duration = Duration::from_secs(30);
+/// How long shutdown waits for the router to drop the mapping.
+const REMOVE_TIMEOUT: Duration = Duration::from_secs(3);
+const MAPPING_DESCRIPTION: &str = "Wavry host";
+const IGD_SERVICE_TYPES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:",
+    "urn:schemas-upnp-org:service:WANPPPConnection:",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Gateway {
+    Pcp {
+        gateway: Ipv4Addr,
+        client_ip: Ipv4Addr,
+        /// Identifies the mapping in renewals and the final delete.
+        nonce: [u8; 12],
+    },
+    NatPmp(Ipv4Addr),
+    Upnp {
+        control_url: String,
+        service_type: String,
+        local_ip: Ipv4Addr,
+    },
+}
+
+/// An active UDP port mapping on the local router.
+#[derive(Debug, Clone)]
+struct PortMapping {
+    gateway: Gateway,
+    internal_port: u16,
+    external: SocketAddr,
+    lifetime: Duration,
+}
+
+impl PortMapping {
+    /// Maps `internal_port` using whichever protocol the router answers.
+    async fn create(internal_port: u16) -> Result<Self> {
+        let gateway_err = match default_gateway() {
+            Some(gateway) => match pcp_or_natpmp_map(gateway, internal_port).await {
+                Ok(mapping) => return Ok(mapping),
+                Err(err) => err,
+            },
+            None => anyhow!("no default gateway"),
+        };
+        upnp_map(internal_port).await.map_err(|upnp_err| {
+            anyhow!(
+                "PCP/NAT-PMP failed ({:#}); UPnP failed ({:#})",
+                gateway_err,
+                upnp_err
+            )
+        })
+    }
+
+    async fn renew(&mut self) -> Result<()> {
+        let renewed = match &self.gateway {
+            Gateway::Pcp {
+                gateway,
+                client_ip,
+                nonce,
+            } => pcp_map(
+                *gateway,
+                *client_ip,
+                *nonce,
+                self.internal_port,
+                self.external,
+            )
+            .await?
+            .ok_or_else(|| anyhow!("gateway no longer speaks PCP"))?,
+            Gateway::NatPmp(gateway) => {
+                natpmp_map(*gateway, self.internal_port, self.external.port()).await?
+            }
+            Gateway::Upnp { .. } => {
+                upnp_add(&self.gateway, self.internal_port, self.external.port()).await?;
+                return Ok(());
+            }
+        };
+        *self = renewed;
+        Ok(())
+    }
+
+    async fn remove(self) -> Result<()> {
+        match &self.gateway {
+            Gateway::Pcp {
+                gateway,
+                client_ip,
+                nonce,
+            } => {
+                let request = pcp_map_request(
+                    *client_ip,
+                    nonce,
+                    self.internal_port,
+                    SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+                    0,
+                );
+                gateway_request(*gateway, &request, 4).await.map(|_| ())
+            }
+            Gateway::NatPmp(gateway) => {
+                let request = natpmp_map_request(self.internal_port, 0, 0);
+                gateway_request(*gateway, &request, 16).await.map(|_| ())
+            }
+            Gateway::Upnp {
+                control_url,
+                service_type,
+                ..
+            } => {
+                let args = format!(
+                    "<NewRemoteHost></NewRemoteHost><NewExternalPort>{}</NewExternalPort><NewProtocol>UDP</NewProtocol>",
+                    self.external.port()
+                );
+                soap_call(control_url, service_type, "DeletePortMapping", &args)
+                    .await
+                    .map(|_| ())
+            }
+        }
+    }
+}
+
+/// Keeps the host's UDP port mapped on the router in the background.
+pub struct PortMapper {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl PortMapper {
+    /// Starts mapping `internal_port`. The receiver holds the external address
+    /// while a mapping is up.
+    pub fn spawn(internal_port: u16) -> (Self, watch::Receiver<Option<SocketAddr>>) {
+        let (external_tx, external_rx) = watch::channel(None);
+        let (stop, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(maintain(internal_port, external_tx, stop_rx));
+        (Self { stop, task }, external_rx)
+    }
+
+    /// Removes the mapping from the router.
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(());
+        if time::timeout(REMOVE_TIMEOUT * 2, self.task).await.is_err() {
+            warn!("timed out removing the router port mapping");
+        }
+    }
+}
+
+async fn maintain(
+    internal_port: u16,
+    external: watch::Sender<Option<SocketAddr>>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut mapping = tokio::select! {
+        _ = &mut stop => return,
+        mapping = PortMapping::create(internal_port) => match mapping {
+            Ok(mapping) => mapping,
+            Err(err) => {
+                info!("router port mapping unavailable: {:#}", err);
+                return;
+            }
+        },
+    };
+    info!(
+        "mapped UDP port {} to external {} for {}s",
+        internal_port,
+        mapping.external,
+        mapping.lifetime.as_secs()
+    );
+    external.send_replace(Some(mapping.external));
+
+    loop {
+        let renew_in = (mapping.lifetime / 2).max(MIN_RENEW_INTERVAL);
+        tokio::select! {
+            _ = &mut stop => break,
+            _ = time::sleep(renew_in) => {}
+        }
+        let renewed = match mapping.renew().await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                // A rebooted router forgets its mappings; start over.
+                debug!("port mapping renewal failed ({:#}); mapping again", err);
+                PortMapping::create(internal_port)
+                    .await
+                    .map(|fresh| mapping = fresh)
+            }
+        };
+        match renewed {
+            Ok(()) => {
+                if *external.borrow() != Some(mapping.external) {
+                    info!("router port mapping is now {}", mapping.external);
+                }
+                external.send_replace(Some(mapping.external));
+            }
+            Err(err) => {
+                warn!("lost the router port mapping: {:#}", err);
+                external.send_replace(None);
+            }
+        }
+    }
+
+    external.send_replace(None);
+    match time::timeout(REMOVE_TIMEOUT, mapping.remove()).await {
+        Ok(Ok(())) => debug!("removed router port mapping for UDP port {}", internal_port),
+        Ok(Err(err)) => warn!("failed to remove router port mapping: {:#}", err),
+        Err(_) => warn!("router did not confirm removing the port mapping"),
+    }
+}
+
+async fn gateway_request(gateway: Ipv4Addr, request: &[u8], min_len: usize) -> Result<Vec<u8>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let target = SocketAddrV4::new(gateway, GATEWAY_PORT);
+    let mut timeout = GATEWAY_INITIAL_TIMEOUT;
+    // Largest PCP message.
+    let mut buf = [0u8; 1100];
+    for _ in 0..GATEWAY_RETRIES {
+        socket.send_to(request, target).await?;
+        if let Ok(Ok((n, from))) = time::timeout(timeout, socket.recv_from(&mut buf)).await {
+            if from.ip() == gateway && n >= min_len {
+                return Ok(buf[..n].to_vec());
+            }
+        }
+        timeout *= 2;
+    }
+    Err(anyhow!("no answer from gateway {}", gateway))
+}
+
+async fn pcp_or_natpmp_map(gateway: Ipv4Addr, internal_port: u16) -> Result<PortMapping> {
+    let client_ip = local_ipv4(gateway).context("no local IPv4 route to the gateway")?;
+    let suggested = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), internal_port);
+    match pcp_map(gateway, client_ip, rand::random(), internal_port, suggested).await? {
+        Some(mapping) => Ok(mapping),
+        None => natpmp_map(gateway, internal_port, internal_port).await,
+    }
+}
+
+// PCP
+
+fn pcp_map_request(
+    client_ip: Ipv4Addr,
+    nonce: &[u8; 12],
+    internal_port: u16,
+    suggested_external: SocketAddr,
+    lifetime_secs: u32,
+) -> [u8; PCP_MAP_LEN] {
+    let suggested_ip = match suggested_external.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    let mut request = [0u8; PCP_MAP_LEN];
+    request[0] = PCP_VERSION;
+    request[1] = PCP_OPCODE_MAP;
+    request[4..8].copy_from_slice(&lifetime_secs.to_be_bytes());
+    request[8..24].copy_from_slice(&client_ip.to_ipv6_mapped().octets());
+    request[24..36].copy_from_slice(nonce);
+    request[36] = IPPROTO_UDP;
+    request[40..42].copy_from_slice(&internal_port.to_be_bytes());
+    request[42..44].copy_from_slice(&suggested_external.port().to_be_bytes());
+    request[44..60].copy_from_slice(&suggested_ip.octets());
+    request
+}
+
+/// Returns the assigned external address and granted lifetime in seconds,
+/// or `None` when the gateway only speaks NAT-PMP.
+fn parse_pcp_map_response(response: &[u8], nonce: &[u8; 12]) -> Result<Option<(SocketAddr, u32)>> {
+    // NAT-PMP gateways answer unknown versions with their own version 0.
+    if response.first() == Some(&0) {
+        return Ok(None);
+    }
+    if response.len() < 4 || response[0] != PCP_VERSION || response[1] != 0x80 | PCP_OPCODE_MAP {
+        bail!("malformed PCP response");
+    }
+    match response[3] {
+        0 => {}
+        PCP_RESULT_UNSUPP_VERSION => return Ok(None),
+        code => bail!("PCP result code {}", code),
+    }
+    if response.len() < PCP_MAP_LEN {
+        bail!("short PCP mapping response");
+    }
+    if response[24..36] != nonce[..] {
+        bail!("PCP response for another mapping");
+    }
+    let lifetime = u32::from_be_bytes([response[4], response[5], response[6], response[7]]);
+    let port = u16::from_be_bytes([response[42], response[43]]);
+    let mut ip = [0u8; 16];
+    ip.copy_from_slice(&response[44..60]);
+    let ip = Ipv6Addr::from(ip);
+    let ip = ip
+        .to_ipv4_mapped()
+        .map(IpAddr::V4)
+        .unwrap_or(IpAddr::V6(ip));
+    Ok(Some((SocketAddr::new(ip, port), lifetime)))
+}
+
+async fn pcp_map(
+    gateway: Ipv4Addr,
+    client_ip: Ipv4Addr,
+    nonce: [u8; 12],
+    internal_port: u16,
+    suggested_external: SocketAddr,
+) -> Result<Option<PortMapping>> {
+    let request = pcp_map_request(
+        client_ip,
+        &nonce,
+        internal_port,
+        suggested_external,
+        MAPPING_LIFETIME.as_secs() as u32,
+    );
+    let response = gateway_request(gateway, &request, 4).await?;
+    let Some((external, lifetime)) = parse_pcp_map_response(&response, &nonce)? else {
+        return Ok(None);
+    };
+    Ok(Some(PortMapping {
+        gateway: Gateway::Pcp {
+            gateway,
+            client_ip,
+            nonce,
+        },
+        internal_port,
+        external,
+        lifetime: Duration::from_secs(lifetime as u64),
+    }))
+}
+
+// NAT-PMP
+
+fn natpmp_map_request(internal_port: u16, external_port: u16, lifetime_secs: u32) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    request[1] = 1; // Map UDP
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime_secs.to_be_bytes());
+    request
+}
+
+fn natpmp_result(response: &[u8], opcode: u8) -> Result<()> {
+    if response.len() < 4 || response[0] != 0 || response[1] != 128 + opcode {
+        bail!("malformed NAT-PMP response");
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        code => bail!("NAT-PMP result code {}", code),
+    }
+}
+
+fn parse_natpmp_external_ip(response: &[u8]) -> Result<Ipv4Addr> {
+    natpmp_result(response, 0)?;
+    if response.len() < 12 {
+        bail!("short NAT-PMP address response");
+    }
+    Ok(Ipv4Addr::new(
+        response[8],
+        response[9],
+        response[10],
+        response[11],
+    ))
+}
+
+/// Returns the mapped external port and granted lifetime in seconds.
+fn parse_natpmp_map_response(response: &[u8]) -> Result<(u16, u32)> {
+    natpmp_result(response, 1)?;
+    if response.len() < 16 {
+        bail!("short NAT-PMP mapping response");
+    }
+    let external_port = u16::from_be_bytes([response[10], response[11]]);
+    let lifetime = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+    Ok((external_port, lifetime))
+}
+
+async fn natpmp_map(
+    gateway: Ipv4Addr,
+    internal_port: u16,
+    external_port: u16,
+) -> Result<PortMapping> {
+    let external_ip = parse_natpmp_external_ip(&gateway_request(gateway, &[0, 0], 12).await?)?;
+    let request = natpmp_map_request(
+        internal_port,
+        external_port,
+        MAPPING_LIFETIME.as_secs() as u32,
+    );
+    let (port, lifetime) =
+        parse_natpmp_map_response(&gateway_request(gateway, &request, 16).await?)?;
+    Ok(PortMapping {
+        gateway: Gateway::NatPmp(gateway),
+        internal_port,
+        external: SocketAddr::new(external_ip.into(), port),
+        lifetime: Duration::from_secs(lifetime as u64),
+    })
+}
+
+/// IPv4 default gateway from the kernel routing table.
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<Ipv4Addr> {
+    parse_proc_net_route(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+/// Without a routing table, assume the common `.1` router on the local /24.
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<Ipv4Addr> {
+    let [a, b, c, _] = local_ipv4(Ipv4Addr::new(192, 0, 2, 1))?.octets();
+    Some(Ipv4Addr::new(a, b, c, 1))
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_net_route(table: &str) -> Option<Ipv4Addr> {
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            return None;
+        }
+        // Stored as a little-endian hex u32.
+        let raw = u32::from_str_radix(fields[2], 16).ok()?;
+        (raw != 0).then(|| Ipv4Addr::from(raw.to_le_bytes()))
+    })
+}
+
+/// Local address the OS would use to reach `peer`; no packet is sent.
+fn local_ipv4(peer: Ipv4Addr) -> Option<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(SocketAddrV4::new(peer, 9)).ok()?;
+    match socket.local_addr().ok()? {
+        SocketAddr::V4(addr) if !addr.ip().is_unspecified() => Some(*addr.ip()),
+        _ => None,
+    }
+}
+
+// UPnP IGD
+
+fn ssdp_search_request() -> String {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n",
+        SSDP_ADDR
+    )
+}
+
+fn parse_ssdp_location(response: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("location")
+            .then(|| value.trim().to_string())
+    })
+}
+
+fn xml_tag<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].trim())
+}
+
+/// Finds the WAN connection service in a device description, returning
+/// `(service_type, control_url)`.
+fn find_wan_service(description: &str) -> Option<(String, String)> {
+    description.split("<service>").skip(1).find_map(|service| {
+        let service_type = xml_tag(service, "serviceType")?;
+        IGD_SERVICE_TYPES
+            .iter()
+            .any(|prefix| service_type.starts_with(prefix))
+            .then(|| {
+                xml_tag(service, "controlURL")
+                    .map(|url| (service_type.to_string(), url.to_string()))
+            })
+            .flatten()
+    })
+}
+
+fn soap_envelope(service_type: &str, action: &str, args: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\r\n<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body><u:{action} xmlns:u=\"{service_type}\">{args}</u:{action}></s:Body></s:Envelope>"
+    )
+}
+
+async fn soap_call(
+    control_url: &str,
+    service_type: &str,
+    action: &str,
+    args: &str,
+) -> Result<String> {
+    let res = reqwest::Client::new()
+        .post(control_url)
+        .timeout(HTTP_TIMEOUT)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header("SOAPAction", format!("\"{}#{}\"", service_type, action))
+        .body(soap_envelope(service_type, action, args))
+        .send()
+        .await?;
+    let status = res.status();
+    let body = res.text().await?;
+    if !status.is_success() {
+        let detail = xml_tag(&body, "errorDescription").unwrap_or("no detail");
+        bail!("{} failed with {}: {}", action, status, detail);
+    }
+    Ok(body)
+}
+
+async fn discover_igd() -> Result<Gateway> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket
+        .send_to(ssdp_search_request().as_bytes(), SSDP_ADDR)
+        .await?;
+
+    let mut buf = [0u8; 2048];
+    let (location, from) = time::timeout(SSDP_TIMEOUT, async {
+        loop {
+            let (n, from) = socket.recv_from(&mut buf).await?;
+            if let Some(location) = parse_ssdp_location(&String::from_utf8_lossy(&buf[..n])) {
+                return Ok::<_, std::io::Error>((location, from));
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("no UPnP gateway answered"))??;
+
+    let location_url = reqwest::Url::parse(&location)?;
+    let description = reqwest::Client::new()
+        .get(location_url.clone())
+        .timeout(HTTP_TIMEOUT)
+        .send()
+        .await?
+        .text()
+        .await?;
+    let (service_type, control_path) =
+        find_wan_service(&description).context("gateway has no WAN connection service")?;
+    let control_url = location_url.join(&control_path)?.to_string();
+
+    let IpAddr::V4(gateway_ip) = from.ip() else {
+        bail!("IPv6 UPnP gateways are not supported");
+    };
+    let local_ip = local_ipv4(gateway_ip).context("no local IPv4 route to the gateway")?;
+    Ok(Gateway::Upnp {
+        control_url,
+        service_type,
+        local_ip,
+    })
+}
+
+async fn upnp_add(gateway: &Gateway, internal_port: u16, external_port: u16) -> Result<()> {
+    let Gateway::Upnp {
+        control_url,
+        service_type,
+        local_ip,
+    } = gateway
+    else {
+        bail!("not a UPnP gateway");
+    };
+    let args = format!(
+        "<NewRemoteHost></NewRemoteHost><NewExternalPort>{}</NewExternalPort><NewProtocol>UDP</NewProtocol><NewInternalPort>{}</NewInternalPort><NewInternalClient>{}</NewInternalClient><NewEnabled>1</NewEnabled><NewPortMappingDescription>{}</NewPortMappingDescription><NewLeaseDuration>{}</NewLeaseDuration>",
+        external_port,
+        internal_port,
+        local_ip,
+        MAPPING_DESCRIPTION,
+        MAPPING_LIFETIME.as_secs()
+    );
+    soap_call(control_url, service_type, "AddPortMapping", &args)
+        .await
+        .map(|_| ())
+}
+
+async fn upnp_map(internal_port: u16) -> Result<PortMapping> {
+    let gateway = discover_igd().await?;
+    let Gateway::Upnp {
+        control_url,
+        service_type,
+        ..
+    } = &gateway
+    else {
+        unreachable!("discover_igd returns a UPnP gateway");
+    };
+    let body = soap_call(control_url, service_type, "GetExternalIPAddress", "").await?;
+    let external_ip: Ipv4Addr = xml_tag(&body, "NewExternalIPAddress")
+        .and_then(|ip| ip.parse().ok())
+        .context("gateway reported no external IPv4 address")?;
+
+    upnp_add(&gateway, internal_port, internal_port).await?;
+    Ok(PortMapping {
+        gateway,
+        internal_port,
+        external: SocketAddr::new(external_ip.into(), internal_port),
+        lifetime: MAPPING_LIFETIME,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcp_map_round_trip() {
+        let nonce = [7u8; 12];
+        let client_ip = Ipv4Addr::new(192, 168, 1, 20);
+        let suggested = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 40000);
+        let request = pcp_map_request(client_ip, &nonce, 40000, suggested, 3600);
+        assert_eq!(&request[..2], &[2, 1]);
+        assert_eq!(&request[8..24], &client_ip.to_ipv6_mapped().octets());
+        assert_eq!(request[36], IPPROTO_UDP);
+        assert_eq!(u16::from_be_bytes([request[40], request[41]]), 40000);
+
+        // Gateways echo the opcode payload with the assigned address filled in.
+        let mut response = request;
+        response[1] = 0x81;
+        response[4..8].copy_from_slice(&7200u32.to_be_bytes());
+        response[8..24].fill(0);
+        response[42..44].copy_from_slice(&40123u16.to_be_bytes());
+        response[44..60].copy_from_slice(&Ipv4Addr::new(203, 0, 113, 7).to_ipv6_mapped().octets());
+        assert_eq!(
+            parse_pcp_map_response(&response, &nonce).unwrap(),
+            Some(("203.0.113.7:40123".parse().unwrap(), 7200))
+        );
+        assert!(parse_pcp_map_response(&response, &[8u8; 12]).is_err());
+
+        response[3] = 2; // Not authorized
+        assert!(parse_pcp_map_response(&response, &nonce).is_err());
+    }
+
+    #[test]
+    fn natpmp_only_gateway_is_detected() {
+        // NAT-PMP's "unsupported version" answer to a PCP request.
+        let response = [0, 129, 0, 1, 0, 0, 0, 42];
+        assert_eq!(parse_pcp_map_response(&response, &[0u8; 12]).unwrap(), None);
+    }
+
+    #[test]
+    fn natpmp_map_round_trip() {
+        let request = natpmp_map_request(40000, 40000, 3600);
+        assert_eq!(&request[..2], &[0, 1]);
+        assert_eq!(u16::from_be_bytes([request[4], request[5]]), 40000);
+
+        let mut response = [0u8; 16];
+        response[1] = 129;
+        response[10..12].copy_from_slice(&40123u16.to_be_bytes());
+        response[12..16].copy_from_slice(&7200u32.to_be_bytes());
+        assert_eq!(parse_natpmp_map_response(&response).unwrap(), (40123, 7200));
+
+        response[3] = 3; // Network failure
+        assert!(parse_natpmp_map_response(&response).is_err());
+
+        let response = [0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7];
+        assert_eq!(
+            parse_natpmp_external_ip(&response).unwrap(),
+            Ipv4Addr::new(203, 0, 113, 7)
+        );
+    }
+
+    #[test]
+    fn default_route_parses_from_proc_table() {
+        let table = "Iface\tDestination\tGateway \tFlags\n\
+                     eth0\t0000A8C0\t00000000\t0001\n\
+                     eth0\t00000000\t0101A8C0\t0003\n";
+        assert_eq!(
+            parse_proc_net_route(table),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+    }
+
+    #[test]
+    fn wan_service_is_found_in_description() {
+        let response = "HTTP/1.1 200 OK\r\nlocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n";
+        assert_eq!(
+            parse_ssdp_location(response).as_deref(),
+            Some("http://192.168.1.1:5000/rootDesc.xml")
+        );
+
+        let description = "<root><service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType><controlURL>/ctl/L3F</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:2</serviceType><controlURL>/ctl/IPConn</controlURL></service></root>";
+        assert_eq!(
+            find_wan_service(description),
+            Some((
+                "urn:schemas-upnp-org:service:WANIPConnection:2".to_string(),
+                "/ctl/IPConn".to_string()
+            ))
+        );
+    }
+}
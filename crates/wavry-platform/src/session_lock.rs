@@ -0,0 +1,77 @@
+//! Locks the host's desktop session, so a machine left streaming is not left
+//! unlocked once its remote user goes away.
+//!
+//! Linux asks logind to lock the caller's session, Windows calls
+//! `LockWorkStation`, and macOS suspends the login session with `CGSession`,
+//! falling back to sleeping the display on releases that no longer ship it.
+
+use anyhow::Result;
+
+/// Locks the session this process runs in. Hosts running as a system service
+/// have no desktop session of their own and get an error instead.
+pub fn lock_session() -> Result<()> {
+    imp::lock_session()?;
+    tracing::info!("locked the host session");
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    use anyhow::{bail, Context};
+
+    let status = std::process::Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} {} exited with {}", program, args.join(" "), status);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::Result;
+
+    pub(super) fn lock_session() -> Result<()> {
+        super::run("loginctl", &["lock-session"])
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use anyhow::{Context, Result};
+    use windows::Win32::System::Shutdown::LockWorkStation;
+
+    pub(super) fn lock_session() -> Result<()> {
+        unsafe { LockWorkStation() }.context("LockWorkStation failed")
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use anyhow::Result;
+
+    const CGSESSION: &str =
+        "/System/Library/CoreServices/Menu Extras/User.menu/Contents/Resources/CGSession";
+
+    pub(super) fn lock_session() -> Result<()> {
+        match super::run(CGSESSION, &["-suspend"]) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                // Locks too when the user requires a password after sleep.
+                tracing::debug!("CGSession unavailable ({}); sleeping the display", err);
+                super::run("pmset", &["displaysleepnow"])
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod imp {
+    use anyhow::{bail, Result};
+
+    pub(super) fn lock_session() -> Result<()> {
+        bail!("locking the session is not supported on this platform")
+    }
+}
@@ -0,0 +1,199 @@
+//! Scheduling priority and core pinning for latency-critical host threads.
+//!
+//! Linux raises the thread's nice value or moves it to `SCHED_FIFO`, which
+//! needs `CAP_SYS_NICE` or an `rtprio` limit; Windows uses thread priority
+//! classes. Other platforms only accept the default tuning.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThreadPriority {
+    #[default]
+    Normal,
+    /// Above other desktop work, still time-shared.
+    High,
+    /// Fixed real-time priority; a busy thread can starve the rest of the host.
+    Realtime,
+}
+
+impl FromStr for ThreadPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "normal" => Ok(Self::Normal),
+            "high" => Ok(Self::High),
+            "realtime" | "rt" => Ok(Self::Realtime),
+            other => Err(format!(
+                "unknown thread priority '{}', expected normal, high or realtime",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ThreadPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal",
+            Self::High => "high",
+            Self::Realtime => "realtime",
+        })
+    }
+}
+
+/// Priority and optional CPU core for one thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadTuning {
+    pub priority: ThreadPriority,
+    pub core: Option<usize>,
+}
+
+impl ThreadTuning {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies to the calling thread. Default tuning leaves it untouched.
+    pub fn apply_current(&self) -> Result<()> {
+        if self.priority != ThreadPriority::Normal {
+            imp::set_priority(self.priority)?;
+        }
+        if let Some(core) = self.core {
+            imp::pin_to_core(core)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::{bail, Result};
+
+    use super::ThreadPriority;
+
+    const HIGH_NICE: libc::c_int = -10;
+    /// Low within the FIFO range so kernel and audio threads still win.
+    const REALTIME_FIFO_PRIORITY: libc::c_int = 10;
+
+    pub(super) fn set_priority(priority: ThreadPriority) -> Result<()> {
+        match priority {
+            ThreadPriority::Normal => Ok(()),
+            ThreadPriority::High => {
+                // Nice values are per thread on Linux when addressed by tid.
+                let tid = unsafe { libc::gettid() };
+                if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, HIGH_NICE) }
+                    != 0
+                {
+                    bail!("setpriority failed: {}", std::io::Error::last_os_error());
+                }
+                Ok(())
+            }
+            ThreadPriority::Realtime => {
+                let param = libc::sched_param {
+                    sched_priority: REALTIME_FIFO_PRIORITY,
+                };
+                let rc = unsafe {
+                    libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+                };
+                if rc != 0 {
+                    bail!(
+                        "SCHED_FIFO refused: {}",
+                        std::io::Error::from_raw_os_error(rc)
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) fn pin_to_core(core: usize) -> Result<()> {
+        if core >= libc::CPU_SETSIZE as usize {
+            bail!("core {} is out of range", core);
+        }
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(core, &mut set);
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                bail!(
+                    "sched_setaffinity failed: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use anyhow::{anyhow, bail, Result};
+    use windows::Win32::System::Threading::{
+        GetCurrentThread, SetThreadAffinityMask, SetThreadPriority, THREAD_PRIORITY_HIGHEST,
+        THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    use super::ThreadPriority;
+
+    pub(super) fn set_priority(priority: ThreadPriority) -> Result<()> {
+        let level = match priority {
+            ThreadPriority::Normal => return Ok(()),
+            ThreadPriority::High => THREAD_PRIORITY_HIGHEST,
+            ThreadPriority::Realtime => THREAD_PRIORITY_TIME_CRITICAL,
+        };
+        unsafe { SetThreadPriority(GetCurrentThread(), level) }
+            .map_err(|err| anyhow!("SetThreadPriority failed: {}", err))
+    }
+
+    pub(super) fn pin_to_core(core: usize) -> Result<()> {
+        if core >= usize::BITS as usize {
+            bail!("core {} is out of range", core);
+        }
+        let previous = unsafe { SetThreadAffinityMask(GetCurrentThread(), 1usize << core) };
+        if previous == 0 {
+            bail!(
+                "SetThreadAffinityMask failed: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod imp {
+    use anyhow::{bail, Result};
+
+    use super::ThreadPriority;
+
+    pub(super) fn set_priority(_priority: ThreadPriority) -> Result<()> {
+        bail!("thread priority is not implemented for this platform")
+    }
+
+    pub(super) fn pin_to_core(_core: usize) -> Result<()> {
+        bail!("core pinning is not implemented for this platform")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_parses_case_insensitively() {
+        assert_eq!(" High ".parse(), Ok(ThreadPriority::High));
+        assert_eq!("RT".parse(), Ok(ThreadPriority::Realtime));
+        assert!("urgent".parse::<ThreadPriority>().is_err());
+        assert_eq!(ThreadPriority::Realtime.to_string(), "realtime");
+    }
+
+    #[test]
+    fn default_tuning_is_a_no_op() {
+        let tuning = ThreadTuning::default();
+        assert!(tuning.is_default());
+        assert!(tuning.apply_current().is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+/// Simultaneous contacts an injector tracks; later touches are dropped.
+pub const MAX_TOUCH_CONTACTS: usize = 10;
+
+/// Maps a peer's contact IDs onto the fixed slots OS touch APIs expect.
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct TouchSlots {
+    slots: [Option<u32>; MAX_TOUCH_CONTACTS],
+}
+
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+impl TouchSlots {
+    pub(crate) fn get(&self, contact_id: u32) -> Option<usize> {
+        self.slots.iter().position(|slot| *slot == Some(contact_id))
+    }
+
+    /// Slot for a new contact, or the one it already holds.
+    pub(crate) fn down(&mut self, contact_id: u32) -> Option<usize> {
+        if let Some(slot) = self.get(contact_id) {
+            return Some(slot);
+        }
+        let slot = self.slots.iter().position(Option::is_none)?;
+        self.slots[slot] = Some(contact_id);
+        Some(slot)
+    }
+
+    pub(crate) fn up(&mut self, contact_id: u32) -> Option<usize> {
+        let slot = self.get(contact_id)?;
+        self.slots[slot] = None;
+        Some(slot)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contacts_reuse_freed_slots() {
+        let mut slots = TouchSlots::default();
+        assert_eq!(slots.down(42), Some(0));
+        assert_eq!(slots.down(7), Some(1));
+        assert_eq!(slots.down(42), Some(0));
+        assert_eq!(slots.up(42), Some(0));
+        assert_eq!(slots.get(42), None);
+        assert_eq!(slots.down(9), Some(0));
+        assert_eq!(slots.up(100), None);
+
+        for id in 10..18 {
+            assert!(slots.down(id).is_some());
+        }
+        assert_eq!(slots.down(99), None);
+
+        slots.up(9);
+        slots.up(7);
+        for id in 10..18 {
+            slots.up(id);
+        }
+        assert!(slots.is_empty());
+    }
+}
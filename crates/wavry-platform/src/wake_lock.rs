@@ -0,0 +1,285 @@
+//! Keeps the host's display awake while a session is streaming.
+//!
+//! Linux asks the desktop through the Inhibit portal and falls back to
+//! `systemd-inhibit`; Windows holds an execution state on a thread of its own,
+//! and macOS holds an IOPM assertion.
+
+use anyhow::Result;
+
+pub struct WakeLock {
+    reason: String,
+    active: bool,
+    inhibitor: Option<imp::Inhibitor>,
+}
+
+impl WakeLock {
+    /// `reason` is shown by desktops that list what is blocking sleep.
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            active: false,
+            inhibitor: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Blocks or allows idle blanking and sleep. Calls that keep the current
+    /// state do nothing, so a failed acquire is reported once per session.
+    pub fn set_active(&mut self, active: bool) -> Result<()> {
+        if active == self.active {
+            return Ok(());
+        }
+        self.active = active;
+        if !active {
+            if self.inhibitor.take().is_some() {
+                tracing::info!("released wake lock");
+            }
+            return Ok(());
+        }
+        self.inhibitor = Some(imp::Inhibitor::acquire(&self.reason)?);
+        tracing::info!("holding wake lock: {}", self.reason);
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc as std_mpsc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Context, Result};
+    use ashpd::desktop::inhibit::{InhibitFlags, InhibitProxy};
+    use ashpd::desktop::Request;
+    use tokio::runtime::Builder as RuntimeBuilder;
+    use tokio::sync::oneshot;
+
+    const PORTAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub(super) enum Inhibitor {
+        Portal {
+            release: Option<oneshot::Sender<()>>,
+            thread: Option<JoinHandle<()>>,
+        },
+        /// `systemd-inhibit` wrapping `cat`, which exits once its stdin closes.
+        Systemd(Child),
+    }
+
+    impl Inhibitor {
+        pub(super) fn acquire(reason: &str) -> Result<Self> {
+            match Self::portal(reason) {
+                Ok(inhibitor) => Ok(inhibitor),
+                Err(err) => {
+                    tracing::debug!(
+                        "inhibit portal unavailable ({}); using systemd-inhibit",
+                        err
+                    );
+                    Self::systemd(reason)
+                }
+            }
+        }
+
+        /// The portal keeps the inhibition while its request stays open, so a
+        /// thread owns the request until release.
+        fn portal(reason: &str) -> Result<Self> {
+            let (ready_tx, ready_rx) = std_mpsc::channel::<Result<()>>();
+            let (release_tx, release_rx) = oneshot::channel();
+            let reason = reason.to_string();
+            let thread = thread::spawn(move || {
+                let runtime = match RuntimeBuilder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(err.into()));
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    let request = match portal_inhibit(&reason).await {
+                        Ok(request) => request,
+                        Err(err) => {
+                            let _ = ready_tx.send(Err(err));
+                            return;
+                        }
+                    };
+                    let _ = ready_tx.send(Ok(()));
+                    let _ = release_rx.await;
+                    let _ = request.close().await;
+                });
+            });
+            ready_rx
+                .recv_timeout(PORTAL_TIMEOUT)
+                .map_err(|_| anyhow!("inhibit portal did not answer"))??;
+            Ok(Self::Portal {
+                release: Some(release_tx),
+                thread: Some(thread),
+            })
+        }
+
+        fn systemd(reason: &str) -> Result<Self> {
+            let mut child = Command::new("systemd-inhibit")
+                .arg("--what=idle:sleep")
+                .arg("--who=Wavry")
+                .arg(format!("--why={}", reason))
+                .arg("--mode=block")
+                .arg("cat")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .context("failed to run systemd-inhibit")?;
+            // Exits straight away when logind refuses the lock.
+            thread::sleep(Duration::from_millis(50));
+            if let Some(status) = child.try_wait()? {
+                return Err(anyhow!("systemd-inhibit exited with {}", status));
+            }
+            Ok(Self::Systemd(child))
+        }
+    }
+
+    async fn portal_inhibit(reason: &str) -> Result<Request<()>> {
+        let proxy = InhibitProxy::new().await?;
+        let request = proxy
+            .inhibit(None, InhibitFlags::Idle | InhibitFlags::Suspend, reason)
+            .await?;
+        Ok(request)
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            match self {
+                Self::Portal { release, thread } => {
+                    release.take();
+                    if let Some(thread) = thread.take() {
+                        let _ = thread.join();
+                    }
+                }
+                Self::Systemd(child) => {
+                    child.stdin.take();
+                    let _ = child.wait();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::sync::mpsc;
+    use std::thread::{self, JoinHandle};
+
+    use anyhow::{anyhow, Result};
+    use windows::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED,
+    };
+
+    /// The execution state belongs to the thread that set it, so a thread of
+    /// our own holds it instead of whichever runtime worker acquired the lock.
+    pub(super) struct Inhibitor {
+        release: Option<mpsc::Sender<()>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Inhibitor {
+        pub(super) fn acquire(_reason: &str) -> Result<Self> {
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let (release_tx, release_rx) = mpsc::channel::<()>();
+            let thread = thread::spawn(move || {
+                let previous = unsafe {
+                    SetThreadExecutionState(
+                        ES_CONTINUOUS | ES_SYSTEM_REQUIRED | ES_DISPLAY_REQUIRED,
+                    )
+                };
+                let _ = ready_tx.send(previous.0 != 0);
+                let _ = release_rx.recv();
+                unsafe {
+                    SetThreadExecutionState(ES_CONTINUOUS);
+                }
+            });
+            if !ready_rx.recv().unwrap_or(false) {
+                return Err(anyhow!("SetThreadExecutionState failed"));
+            }
+            Ok(Self {
+                release: Some(release_tx),
+                thread: Some(thread),
+            })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            self.release.take();
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use anyhow::{bail, Result};
+    use objc2_core_foundation::CFString;
+
+    const IOPM_ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: &CFString,
+            level: u32,
+            name: &CFString,
+            assertion_id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(assertion_id: u32) -> i32;
+    }
+
+    pub(super) struct Inhibitor {
+        assertion_id: u32,
+    }
+
+    impl Inhibitor {
+        pub(super) fn acquire(reason: &str) -> Result<Self> {
+            // Keeping the display awake also keeps the system from idle sleep.
+            let assertion_type = CFString::from_str("PreventUserIdleDisplaySleep");
+            let name = CFString::from_str(reason);
+            let mut assertion_id = 0;
+            let status = unsafe {
+                IOPMAssertionCreateWithName(
+                    &assertion_type,
+                    IOPM_ASSERTION_LEVEL_ON,
+                    &name,
+                    &mut assertion_id,
+                )
+            };
+            if status != 0 {
+                bail!("IOPMAssertionCreateWithName failed: {:#x}", status);
+            }
+            Ok(Self { assertion_id })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            unsafe {
+                IOPMAssertionRelease(self.assertion_id);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod imp {
+    use anyhow::Result;
+
+    pub(super) struct Inhibitor;
+
+    impl Inhibitor {
+        pub(super) fn acquire(_reason: &str) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Screen capture through Windows.Graphics.Capture, read back to the CPU.
+//!
+//! The WGC session is `wavry_media::windows_capture::WgcCapture`, the same one
+//! the Windows encoder uses; this only copies its frames into RGBA. WGC hands
+//! out a frame only when the screen changed, so an idle desktop repeats the
+//! last frame instead of copying it again.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use windows::Win32::Foundation::HMODULE;
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_HARDWARE;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_CPU_ACCESS_READ,
+    D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION,
+    D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_SAMPLE_DESC;
+
+use wavry_media::windows_capture::{dirty_rects, frame_texture, WgcCapture};
+use wavry_media::{FrameData, FrameFormat, RawFrame};
+
+use crate::FrameCapturer;
+
+/// How often to look for a new frame while the screen is idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+/// An idle screen still produces a frame this often, so the encoder keeps going.
+const REPEAT_INTERVAL: Duration = Duration::from_millis(100);
+const FRAME_POOL_BUFFERS: i32 = 2;
+
+pub struct WgcCapturer {
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    capture: WgcCapture,
+    staging: Option<ID3D11Texture2D>,
+    width: u16,
+    height: u16,
+    frame: Vec<u8>,
+    epoch: Instant,
+}
+
+// The D3D objects are free-threaded and only used by the owning capturer.
+unsafe impl Send for WgcCapturer {}
+
+impl WgcCapturer {
+    /// Captures the monitor `display_id` (an HMONITOR, as in `EncodeConfig`),
+    /// or the whole desktop.
+    pub fn new(display_id: Option<u32>) -> Result<Self> {
+        let (device, context) = create_d3d_device()?;
+        let capture = WgcCapture::new(&device, display_id, FRAME_POOL_BUFFERS)?;
+        let dirty_regions = capture.enable_dirty_regions();
+        capture.start()?;
+
+        let size = capture.size();
+        tracing::info!(
+            "WGC capture of display {:?} at {}x{}{}",
+            display_id,
+            size.Width,
+            size.Height,
+            if dirty_regions {
+                " (dirty regions)"
+            } else {
+                ""
+            }
+        );
+        Ok(Self {
+            device,
+            context,
+            capture,
+            staging: None,
+            width: 0,
+            height: 0,
+            frame: Vec::new(),
+            epoch: Instant::now(),
+        })
+    }
+
+    /// Shows or hides the cursor in captured frames.
+    pub fn set_capture_cursor(&mut self, enabled: bool) -> Result<()> {
+        self.capture.set_cursor_capture(enabled)
+    }
+
+    /// Copies `texture` into `self.frame`. Returns whether the size changed.
+    fn read_texture(&mut self, texture: &ID3D11Texture2D) -> Result<bool> {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+        let staging = self.staging_texture(desc)?;
+
+        let (width, height) = (desc.Width as usize, desc.Height as usize);
+        let resized = width as u16 != self.width || height as u16 != self.height;
+        unsafe {
+            self.context.CopyResource(&staging, texture);
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            self.context
+                .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+                .context("failed to map the staging texture")?;
+            let pitch = mapped.RowPitch as usize;
+            let src = std::slice::from_raw_parts(mapped.pData as *const u8, pitch * height);
+            bgra_rows_to_rgba(src, pitch, width, height, &mut self.frame);
+            self.context.Unmap(&staging, 0);
+        }
+        self.width = width as u16;
+        self.height = height as u16;
+        Ok(resized)
+    }
+
+    /// CPU-readable copy target, recreated whenever the captured size changes.
+    fn staging_texture(&mut self, source: D3D11_TEXTURE2D_DESC) -> Result<ID3D11Texture2D> {
+        if let Some(staging) = &self.staging {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { staging.GetDesc(&mut desc) };
+            if desc.Width == source.Width && desc.Height == source.Height {
+                return Ok(staging.clone());
+            }
+        }
+        let desc = D3D11_TEXTURE2D_DESC {
+            MipLevels: 1,
+            ArraySize: 1,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+            ..source
+        };
+        let mut staging = None;
+        unsafe {
+            self.device
+                .CreateTexture2D(&desc, None, Some(&mut staging))?
+        };
+        let staging = staging.ok_or_else(|| anyhow!("CreateTexture2D returned no texture"))?;
+        self.staging = Some(staging.clone());
+        Ok(staging)
+    }
+}
+
+impl FrameCapturer for WgcCapturer {
+    fn capture(&mut self) -> Result<RawFrame> {
+        let deadline = Instant::now() + REPEAT_INTERVAL;
+        let dirty_rects = loop {
+            if let Some(frame) = self.capture.try_next_frame()? {
+                let resized = self.read_texture(&frame_texture(&frame)?)?;
+                // After a resize the whole frame is new.
+                break if resized { None } else { dirty_rects(&frame) };
+            }
+            if !self.frame.is_empty() && Instant::now() >= deadline {
+                break Some(Vec::new());
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        Ok(RawFrame {
+            width: self.width,
+            height: self.height,
+            format: FrameFormat::Rgba8,
+            timestamp_us: self.epoch.elapsed().as_micros() as u64,
+            data: FrameData::Cpu {
+                bytes: self.frame.clone(),
+                stride: self.width as u32 * 4,
+            },
+            dirty_rects,
+        })
+    }
+}
+
+fn create_d3d_device() -> Result<(ID3D11Device, ID3D11DeviceContext)> {
+    let mut device = None;
+    let mut context = None;
+    unsafe {
+        D3D11CreateDevice(
+            None,
+            D3D_DRIVER_TYPE_HARDWARE,
+            HMODULE::default(),
+            D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+            None,
+            D3D11_SDK_VERSION,
+            Some(&mut device),
+            None,
+            Some(&mut context),
+        )
+        .context("D3D11CreateDevice failed")?;
+    }
+    Ok((
+        device.ok_or_else(|| anyhow!("D3D11CreateDevice returned no device"))?,
+        context.ok_or_else(|| anyhow!("D3D11CreateDevice returned no context"))?,
+    ))
+}
+
+/// Converts mapped BGRA rows, `pitch` bytes apart, into tightly packed opaque RGBA.
+fn bgra_rows_to_rgba(src: &[u8], pitch: usize, width: usize, height: usize, out: &mut Vec<u8>) {
+    out.clear();
+    out.reserve(width * height * 4);
+    for row in src.chunks(pitch).take(height) {
+        for px in row[..width * 4].chunks_exact(4) {
+            out.extend_from_slice(&[px[2], px[1], px[0], 0xff]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padded_bgra_rows_become_packed_rgba() {
+        let src = [
+            0x10, 0x20, 0x30, 0x00, 0xee, 0xee, 0xee, 0xee, //
+            0xaa, 0xbb, 0xcc, 0x7f, 0xee, 0xee, 0xee, 0xee,
+        ];
+        let mut out = Vec::new();
+        bgra_rows_to_rgba(&src, 8, 1, 2, &mut out);
+        assert_eq!(out, [0x30, 0x20, 0x10, 0xff, 0xcc, 0xbb, 0xaa, 0xff]);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::CursorCapturer;
+use anyhow::Result;
+use wavry_media::{CursorShape, CursorState, SystemCursor};
+use windows::core::PCWSTR;
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetCursorInfo, GetSystemMetrics, LoadCursorW, CURSORINFO, CURSOR_SHOWING, IDC_APPSTARTING,
+    IDC_ARROW, IDC_CROSS, IDC_HAND, IDC_IBEAM, IDC_NO, IDC_SIZEALL, IDC_SIZENESW, IDC_SIZENS,
+    IDC_SIZENWSE, IDC_SIZEWE, IDC_WAIT, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+    SM_YVIRTUALSCREEN,
+};
+
+/// Reports the pointer as the matching system cursor, so the client draws
+/// it from its own theme. Application cursors are reported as the arrow.
+pub struct WindowsCursorCapturer {
+    /// Shared system cursor handles, stored as integers to keep this `Send`.
+    system: Vec<(isize, SystemCursor)>,
+    last: Option<(i32, i32, bool, isize)>,
+}
+
+impl WindowsCursorCapturer {
+    pub fn new() -> Result<Self> {
+        let ids: [(PCWSTR, SystemCursor); 12] = [
+            (IDC_ARROW, SystemCursor::Arrow),
+            (IDC_IBEAM, SystemCursor::IBeam),
+            (IDC_WAIT, SystemCursor::Wait),
+            (IDC_APPSTARTING, SystemCursor::Wait),
+            (IDC_CROSS, SystemCursor::Crosshair),
+            (IDC_HAND, SystemCursor::Hand),
+            (IDC_SIZEWE, SystemCursor::ResizeEw),
+            (IDC_SIZENS, SystemCursor::ResizeNs),
+            (IDC_SIZENWSE, SystemCursor::ResizeNwse),
+            (IDC_SIZENESW, SystemCursor::ResizeNesw),
+            (IDC_SIZEALL, SystemCursor::Move),
+            (IDC_NO, SystemCursor::NotAllowed),
+        ];
+        let mut system = Vec::with_capacity(ids.len());
+        for (id, cursor) in ids {
+            let handle = unsafe { LoadCursorW(None, id)? };
+            system.push((handle.0 as isize, cursor));
+        }
+        Ok(Self { system, last: None })
+    }
+}
+
+impl CursorCapturer for WindowsCursorCapturer {
+    fn poll(&mut self) -> Result<Option<CursorState>> {
+        let mut info = CURSORINFO {
+            cbSize: std::mem::size_of::<CURSORINFO>() as u32,
+            ..Default::default()
+        };
+        unsafe { GetCursorInfo(&mut info)? };
+        let visible = info.flags.0 & CURSOR_SHOWING.0 != 0;
+        let handle = info.hCursor.0 as isize;
+        let key = (info.ptScreenPos.x, info.ptScreenPos.y, visible, handle);
+        if self.last == Some(key) {
+            return Ok(None);
+        }
+        self.last = Some(key);
+
+        let (left, top, width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            )
+        };
+        let system = self
+            .system
+            .iter()
+            .find(|(known, _)| *known == handle)
+            .map(|(_, cursor)| *cursor)
+            .unwrap_or(SystemCursor::Arrow);
+        Ok(Some(CursorState {
+            x: (info.ptScreenPos.x - left) as f32 / width.max(1) as f32,
+            y: (info.ptScreenPos.y - top) as f32 / height.max(1) as f32,
+            visible,
+            serial: handle as u64,
+            shape: CursorShape::System(system),
+        }))
+    }
+}
@@ -1,12 +1,230 @@
-use crate::InputInjector;
+use crate::touch::{TouchSlots, MAX_TOUCH_CONTACTS};
+use crate::{InputInjector, PhysicalKey, PointerMode};
 use anyhow::Result;
+use windows::Win32::Foundation::{POINT, RECT};
+use windows::Win32::UI::Controls::{POINTER_TOUCH_INFO, TOUCH_FLAG_NONE, TOUCH_MASK_CONTACTAREA};
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
+use windows::Win32::UI::Input::Pointer::{
+    InitializeTouchInjection, InjectTouchInput, POINTER_FLAGS, POINTER_FLAG_DOWN,
+    POINTER_FLAG_INCONTACT, POINTER_FLAG_INRANGE, POINTER_FLAG_UP, POINTER_FLAG_UPDATE,
+    POINTER_INFO, TOUCH_FEEDBACK_DEFAULT,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetSystemMetrics, SystemParametersInfoW, PT_TOUCH, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
+    SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN, SPI_GETMOUSE, SPI_GETMOUSESPEED, SPI_SETMOUSE,
+    SPI_SETMOUSESPEED, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+};
 
-pub struct WindowsInjector;
+/// The speed at which Windows moves the cursor one pixel per mickey.
+const UNSCALED_MOUSE_SPEED: u32 = 10;
+
+/// The user's pointer settings, put back when relative mode ends.
+#[derive(Debug, Clone, Copy)]
+struct MouseSettings {
+    /// Acceleration thresholds and "Enhance pointer precision".
+    params: [i32; 3],
+    speed: u32,
+}
+
+impl MouseSettings {
+    fn read() -> Result<Self> {
+        let mut params = [0i32; 3];
+        let mut speed = 0u32;
+        unsafe {
+            SystemParametersInfoW(
+                SPI_GETMOUSE,
+                0,
+                Some(params.as_mut_ptr().cast()),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )?;
+            SystemParametersInfoW(
+                SPI_GETMOUSESPEED,
+                0,
+                Some((&mut speed as *mut u32).cast()),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )?;
+        }
+        Ok(Self { params, speed })
+    }
+
+    /// Applies for this login session only; the saved profile is untouched.
+    fn apply(mut self) -> Result<()> {
+        unsafe {
+            SystemParametersInfoW(
+                SPI_SETMOUSE,
+                0,
+                Some(self.params.as_mut_ptr().cast()),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )?;
+            // SPI_SETMOUSESPEED takes the value itself in place of a pointer.
+            SystemParametersInfoW(
+                SPI_SETMOUSESPEED,
+                0,
+                Some(self.speed as usize as *mut _),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn unaccelerated(self) -> Self {
+        Self {
+            params: [self.params[0], self.params[1], 0],
+            speed: UNSCALED_MOUSE_SPEED,
+        }
+    }
+}
+
+pub struct WindowsInjector {
+    touch_ready: bool,
+    touch_slots: TouchSlots,
+    /// Every contact still down, indexed by slot; each injected frame carries all of them.
+    touch_points: [Option<POINT>; MAX_TOUCH_CONTACTS],
+    pointer_mode: PointerMode,
+    /// Set while relative mode has acceleration turned off.
+    saved_mouse: Option<MouseSettings>,
+}
 
 impl WindowsInjector {
     pub fn new() -> Self {
-        Self
+        Self {
+            touch_ready: false,
+            touch_slots: TouchSlots::default(),
+            touch_points: [None; MAX_TOUCH_CONTACTS],
+            pointer_mode: PointerMode::Absolute,
+            saved_mouse: None,
+        }
+    }
+
+    fn restore_mouse_settings(&mut self) -> Result<()> {
+        match self.saved_mouse.take() {
+            Some(saved) => saved.apply(),
+            None => Ok(()),
+        }
+    }
+
+    /// Injects one touch frame in which the contact in `changed` carries `flags`.
+    fn inject_touch(&mut self, changed: usize, flags: POINTER_FLAGS) -> Result<()> {
+        if !self.touch_ready {
+            unsafe {
+                InitializeTouchInjection(MAX_TOUCH_CONTACTS as u32, TOUCH_FEEDBACK_DEFAULT)?;
+            }
+            self.touch_ready = true;
+        }
+        let held = POINTER_FLAG_UPDATE | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT;
+        let contacts: Vec<POINTER_TOUCH_INFO> = self
+            .touch_points
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, point)| {
+                let flags = if slot == changed { flags } else { held };
+                point.map(|point| touch_contact(slot, point, flags))
+            })
+            .collect();
+        unsafe {
+            InjectTouchInput(&contacts)?;
+        }
+        Ok(())
+    }
+}
+
+/// Touch injection takes pixels on the virtual desktop, the same space
+/// `mouse_absolute` targets.
+fn touch_point(x: f32, y: f32) -> POINT {
+    let (left, top, width, height) = unsafe {
+        (
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        )
+    };
+    POINT {
+        x: left + (x.clamp(0.0, 1.0) * (width - 1).max(0) as f32) as i32,
+        y: top + (y.clamp(0.0, 1.0) * (height - 1).max(0) as f32) as i32,
+    }
+}
+
+fn keyboard_input(vk: u16, scan: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(vk),
+                wScan: scan,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+/// Press and release of the host-layout key that types `c` with at most
+/// Shift, sent with scan codes so games see it.
+fn layout_key_inputs(c: char) -> Option<Vec<INPUT>> {
+    // Windows types newlines with Enter, which VkKeyScan files under '\r'.
+    let c = if c == '\n' { '\r' } else { c };
+    let unit = u16::try_from(u32::from(c)).ok()?;
+    let scan = unsafe { VkKeyScanW(unit) };
+    // -1 means no key; AltGr and dead-key characters need more than Shift.
+    if scan == -1 || scan as u16 & 0xfe00 != 0 {
+        return None;
+    }
+    let vk = scan as u16 & 0xff;
+    let shifted = scan as u16 & 0x100 != 0;
+    // A dead key would start a composition instead of typing `c`.
+    if unsafe { MapVirtualKeyW(u32::from(vk), MAPVK_VK_TO_CHAR) } & 0x8000_0000 != 0 {
+        return None;
+    }
+    let key_scan = |vk: u16| unsafe { MapVirtualKeyW(u32::from(vk), MAPVK_VK_TO_VSC) } as u16;
+
+    let mut inputs = Vec::with_capacity(4);
+    if shifted {
+        inputs.push(keyboard_input(
+            VK_LSHIFT.0,
+            key_scan(VK_LSHIFT.0),
+            KEYBD_EVENT_FLAGS(0),
+        ));
+    }
+    inputs.push(keyboard_input(vk, key_scan(vk), KEYBD_EVENT_FLAGS(0)));
+    inputs.push(keyboard_input(vk, key_scan(vk), KEYEVENTF_KEYUP));
+    if shifted {
+        inputs.push(keyboard_input(
+            VK_LSHIFT.0,
+            key_scan(VK_LSHIFT.0),
+            KEYEVENTF_KEYUP,
+        ));
+    }
+    Some(inputs)
+}
+
+fn touch_contact(slot: usize, point: POINT, flags: POINTER_FLAGS) -> POINTER_TOUCH_INFO {
+    POINTER_TOUCH_INFO {
+        pointerInfo: POINTER_INFO {
+            pointerType: PT_TOUCH,
+            pointerId: slot as u32,
+            pointerFlags: flags,
+            ptPixelLocation: point,
+            ..Default::default()
+        },
+        touchFlags: TOUCH_FLAG_NONE,
+        touchMask: TOUCH_MASK_CONTACTAREA,
+        rcContact: RECT {
+            left: point.x - 2,
+            top: point.y - 2,
+            right: point.x + 2,
+            bottom: point.y + 2,
+        },
+        ..Default::default()
+    }
+}
+
+impl Drop for WindowsInjector {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_mouse_settings() {
+            tracing::warn!("failed to restore mouse settings: {}", err);
+        }
     }
 }
 
@@ -18,17 +236,29 @@ impl Default for WindowsInjector {
 
 impl InputInjector for WindowsInjector {
     fn key(&mut self, keycode: u32, pressed: bool) -> Result<()> {
+        let Some(key) = PhysicalKey::from_protocol(keycode) else {
+            tracing::debug!("dropping unknown keycode {}", keycode);
+            return Ok(());
+        };
+        let Some(vk) = key.windows_vk() else {
+            return Ok(());
+        };
+        let mut flags = if pressed {
+            KEYBD_EVENT_FLAGS(0)
+        } else {
+            KEYEVENTF_KEYUP
+        };
+        if key.is_windows_extended() {
+            flags |= KEYEVENTF_EXTENDEDKEY;
+        }
         let input = INPUT {
             r#type: INPUT_KEYBOARD,
             Anonymous: INPUT_0 {
                 ki: KEYBDINPUT {
-                    wVk: VIRTUAL_KEY(keycode as u16),
-                    wScan: 0,
-                    dwFlags: if pressed {
-                        KEYBD_EVENT_FLAGS(0)
-                    } else {
-                        KEYEVENTF_KEYUP
-                    },
+                    wVk: VIRTUAL_KEY(vk),
+                    // Games reading raw input look at the scan code, not the virtual key.
+                    wScan: unsafe { MapVirtualKeyW(u32::from(vk), MAPVK_VK_TO_VSC) } as u16,
+                    dwFlags: flags,
                     time: 0,
                     dwExtraInfo: 0,
                 },
@@ -173,4 +403,79 @@ impl InputInjector for WindowsInjector {
         // Future implementation: use XInput to inject gamepad input
         Ok(())
     }
+    fn touch_down(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        if self.touch_slots.get(contact_id).is_some() {
+            return self.touch_move(contact_id, x, y);
+        }
+        let Some(slot) = self.touch_slots.down(contact_id) else {
+            return Ok(());
+        };
+        self.touch_points[slot] = Some(touch_point(x, y));
+        self.inject_touch(
+            slot,
+            POINTER_FLAG_DOWN | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT,
+        )
+    }
+
+    fn touch_move(&mut self, contact_id: u32, x: f32, y: f32) -> Result<()> {
+        let Some(slot) = self.touch_slots.get(contact_id) else {
+            return Ok(());
+        };
+        self.touch_points[slot] = Some(touch_point(x, y));
+        self.inject_touch(
+            slot,
+            POINTER_FLAG_UPDATE | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT,
+        )
+    }
+
+    fn touch_up(&mut self, contact_id: u32) -> Result<()> {
+        let Some(slot) = self.touch_slots.get(contact_id) else {
+            return Ok(());
+        };
+        // The lifted contact goes out once more, then drops out of later frames.
+        let result = self.inject_touch(slot, POINTER_FLAG_UP);
+        self.touch_slots.up(contact_id);
+        self.touch_points[slot] = None;
+        result
+    }
+
+    fn set_pointer_mode(&mut self, mode: PointerMode) -> Result<()> {
+        if mode == self.pointer_mode {
+            return Ok(());
+        }
+        match mode {
+            // SendInput moves are scaled by the pointer speed and "Enhance
+            // pointer precision", so relative mode turns both off.
+            PointerMode::Relative => {
+                let saved = MouseSettings::read()?;
+                self.saved_mouse = Some(saved);
+                saved.unaccelerated().apply()?;
+            }
+            PointerMode::Absolute => self.restore_mouse_settings()?,
+        }
+        self.pointer_mode = mode;
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        let mut inputs = Vec::new();
+        for c in text.chars() {
+            if let Some(keys) = layout_key_inputs(c) {
+                inputs.extend(keys);
+                continue;
+            }
+            // KEYEVENTF_UNICODE arrives as VK_PACKET carrying one UTF-16 unit.
+            let mut units = [0u16; 2];
+            for &unit in c.encode_utf16(&mut units).iter() {
+                inputs.push(keyboard_input(0, unit, KEYEVENTF_UNICODE));
+                inputs.push(keyboard_input(0, unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP));
+            }
+        }
+        if !inputs.is_empty() {
+            unsafe {
+                SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+            }
+        }
+        Ok(())
+    }
 }
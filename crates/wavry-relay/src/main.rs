@@ -1,30 +1,47 @@
 #![forbid(unsafe_code)]
 
+#[cfg(feature = "quic")]
+mod quic;
 mod session;
+mod usage;
 
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::io::ErrorKind;
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
 use bytes::Bytes;
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 use rift_core::relay::{
     ForwardPayloadHeader, LeaseAckPayload, LeaseRejectPayload, LeaseRejectReason, RelayHeader,
     RelayPacketType, RELAY_HEADER_SIZE, RELAY_MAX_PACKET_SIZE,
 };
 use rift_core::PhysicalPacket;
 use serde::{Deserialize, Serialize};
-use session::{PeerRole, SessionError, SessionPool};
+use session::{shard_for_session, PeerRole, SessionError, SessionPool};
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::net::{TcpListener, UdpSocket};
 use tokio::sync::{mpsc, RwLock};
-use tracing::{debug, info, warn};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn, Instrument, Span};
+use usage::{QuotaGrant, UsageLedger};
 use uuid::Uuid;
-use wavry_common::protocol::{RelayHeartbeatRequest, RelayRegisterRequest, RelayRegisterResponse};
+use wavry_common::metrics::{self, Counter, Reporter, StatsdExporter};
+use wavry_common::protocol::{
+    RelayHeartbeatRequest, RelayRegisterRequest, RelayRegisterResponse, RelayUsageReport,
+};
+use wavry_common::{session_span, ConfigLoader, SessionSpanExt};
+
+/// Queue feeding received packets, with their source, to the worker that
+/// owns their session.
+type PacketQueue = mpsc::Sender<(Vec<u8>, SocketAddr)>;
 
 const DEFAULT_MAX_SESSIONS: usize = 100;
 /// Maximum number of distinct IPs tracked in the rate-limiter table.
@@ -41,16 +58,25 @@ const DEFAULT_PACKET_QUEUE_CAPACITY: usize = 2048;
 const DEFAULT_STATS_LOG_INTERVAL_SECS: u64 = 30;
 const DEFAULT_LOAD_SHED_THRESHOLD_PCT: u8 = 95;
 const DEFAULT_HEALTH_LISTEN: &str = "127.0.0.1:9091";
+const DEFAULT_WORKERS: usize = 1;
+const MAX_WORKERS: usize = 256;
 const MAX_CLOCK_SKEW_SECS: i64 = 30;
 const MAX_LEASE_HORIZON_SECS: i64 = 3600;
 const MAX_LEASE_TOKEN_BYTES: usize = 8192;
+const DEFAULT_USAGE_REPORT_INTERVAL_SECS: u64 = 60;
+/// How long the usage ledger remembers a user after their last traffic.
+const USAGE_RETENTION: Duration = Duration::from_secs(3600);
 
 #[derive(Parser, Debug)]
 #[command(name = "wavry-relay")]
 #[command(about = "Wavry relay node - forwards encrypted UDP traffic between peers")]
 struct Args {
-    /// UDP listen address (use :0 for random)
-    #[arg(long, env = "WAVRY_RELAY_LISTEN", default_value = "0.0.0.0:4000")]
+    /// TOML config file; its `[relay]` section fills any flag not given
+    #[arg(long, env = "WAVRY_CONFIG")]
+    config: Option<std::path::PathBuf>,
+
+    /// UDP listen address (use :0 for random); `[::]` serves IPv4 and IPv6
+    #[arg(long, env = "WAVRY_RELAY_LISTEN", default_value = "[::]:4000")]
     listen: SocketAddr,
 
     /// Master server URL
@@ -109,6 +135,14 @@ struct Args {
     )]
     packet_queue_capacity: usize,
 
+    /// How often per-user usage is reported to the master, in seconds
+    #[arg(
+        long,
+        env = "WAVRY_RELAY_USAGE_REPORT_INTERVAL_SECS",
+        default_value_t = DEFAULT_USAGE_REPORT_INTERVAL_SECS
+    )]
+    usage_report_interval_secs: u64,
+
     /// Session cleanup interval in seconds
     #[arg(long, default_value_t = DEFAULT_CLEANUP_INTERVAL_SECS)]
     cleanup_interval_secs: u64,
@@ -129,6 +163,10 @@ struct Args {
     #[arg(long, env = "WAVRY_RELAY_HEALTH_LISTEN", default_value = DEFAULT_HEALTH_LISTEN)]
     health_listen: SocketAddr,
 
+    /// statsd daemon (host:port) to push relay metrics to every stats interval
+    #[arg(long, env = "WAVRY_RELAY_STATSD_ADDR")]
+    statsd_addr: Option<String>,
+
     /// Geographic region (e.g. us-east-1)
     #[arg(long, env = "WAVRY_RELAY_REGION")]
     region: Option<String>,
@@ -140,6 +178,72 @@ struct Args {
     /// Maximum supported bitrate in kbps (minimum 10000)
     #[arg(long, env = "WAVRY_RELAY_MAX_BITRATE", default_value_t = 20_000)]
     max_bitrate_kbps: u32,
+
+    /// Forwarding workers, each with its own SO_REUSEPORT socket and session shard
+    #[arg(long, env = "WAVRY_RELAY_WORKERS", default_value_t = DEFAULT_WORKERS)]
+    workers: usize,
+
+    /// Also accept relay traffic over QUIC on this address (e.g. 0.0.0.0:443)
+    #[cfg(feature = "quic")]
+    #[arg(long, env = "WAVRY_RELAY_QUIC_LISTEN")]
+    quic_listen: Option<SocketAddr>,
+
+    /// PEM certificate chain for the QUIC listener (self-signed if omitted)
+    #[cfg(feature = "quic")]
+    #[arg(long, env = "WAVRY_RELAY_QUIC_CERT", requires = "quic_key")]
+    quic_cert: Option<std::path::PathBuf>,
+
+    /// PEM private key for the QUIC listener
+    #[cfg(feature = "quic")]
+    #[arg(long, env = "WAVRY_RELAY_QUIC_KEY", requires = "quic_cert")]
+    quic_key: Option<std::path::PathBuf>,
+}
+
+impl Args {
+    /// Parses the command line, then fills every flag left at its default
+    /// from the shared config (file, then `WAVRY_RELAY__*` variables).
+    fn load() -> Result<Self> {
+        let matches = Self::command().get_matches();
+        let mut args = Self::from_arg_matches(&matches)?;
+        args.apply_config(&matches)?;
+        Ok(args)
+    }
+
+    fn apply_config(&mut self, matches: &ArgMatches) -> Result<()> {
+        let mut loader = ConfigLoader::new();
+        if let Some(path) = &self.config {
+            loader = loader.file(path);
+        }
+        let relay = loader.load()?.relay;
+        let unset = |id: &str| {
+            matches
+                .value_source(id)
+                .is_none_or(|source| source == ValueSource::DefaultValue)
+        };
+        macro_rules! from_config {
+            ($($field:ident <- $key:ident),* $(,)?) => {
+                $(if unset(stringify!($field)) {
+                    self.$field = relay.$key;
+                })*
+            };
+        }
+        from_config!(
+            listen <- listen,
+            master_url <- master_url,
+            max_sessions <- max_sessions,
+            idle_timeout <- idle_timeout_secs,
+            master_public_key <- master_public_key,
+            allow_insecure_dev <- allow_insecure_dev,
+            ip_rate_limit_pps <- ip_rate_limit_pps,
+            packet_queue_capacity <- packet_queue_capacity,
+            lease_duration_secs <- lease_duration_secs,
+            load_shed_threshold_pct <- load_shed_threshold_pct,
+            health_listen <- health_listen,
+            region <- region,
+            max_bitrate_kbps <- max_bitrate_kbps,
+        );
+        Ok(())
+    }
 }
 
 fn env_bool(name: &str, default: bool) -> bool {
@@ -174,6 +278,8 @@ struct MasterRegistrationConfig {
     register_url: String,
     relay_id: String,
     endpoints: Vec<String>,
+    /// Address families the relay serves, `ipv4` and/or `ipv6`.
+    features: Vec<String>,
     region: Option<String>,
     asn: Option<u32>,
     max_sessions: usize,
@@ -195,7 +301,7 @@ async fn register_with_master(
             asn: config.asn,
             max_sessions: Some(config.max_sessions as u32),
             max_bitrate_kbps: Some(config.max_bitrate_kbps),
-            features: vec!["ipv4".into()],
+            features: config.features.clone(),
         };
         match with_master_auth(
             client.post(&config.register_url),
@@ -222,6 +328,46 @@ async fn register_with_master(
     }
 }
 
+/// Sends the usage ledger to the master every `interval`. Reports the master
+/// does not accept are kept and sent with the next one.
+async fn report_usage(
+    server: Arc<RelayServer>,
+    client: reqwest::Client,
+    usage_url: String,
+    master_auth_token: Option<String>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let entries = server.usage.write().await.take_unreported();
+        if entries.is_empty() {
+            continue;
+        }
+        let report = RelayUsageReport {
+            relay_id: server.relay_id.clone(),
+            entries,
+        };
+        let result = with_master_auth(client.post(&usage_url), master_auth_token.as_deref())
+            .json(&report)
+            .send()
+            .await;
+        match result {
+            Ok(resp) if resp.status().is_success() => {
+                debug!("reported usage for {} user(s)", report.entries.len());
+                continue;
+            }
+            Ok(resp) => warn!("relay usage report failed with status {}", resp.status()),
+            Err(err) => warn!("relay usage report request failed: {}", err),
+        }
+        server
+            .usage
+            .write()
+            .await
+            .restore_unreported(report.entries);
+    }
+}
+
 /// Per-source-IP packet rate limiter to prevent abuse.
 ///
 /// Uses a simple fixed-window algorithm with a 1-second window.
@@ -325,92 +471,92 @@ struct LeaseClaims {
     soft_limit_kbps: Option<u32>,
     #[serde(rename = "hlimit")]
     hard_limit_kbps: Option<u32>,
+    #[serde(rename = "dquota", default)]
+    daily_quota_bytes: Option<u64>,
+    #[serde(rename = "mquota", default)]
+    monthly_quota_bytes: Option<u64>,
+    #[serde(rename = "dused", default)]
+    daily_used_bytes: Option<u64>,
+    #[serde(rename = "mused", default)]
+    monthly_used_bytes: Option<u64>,
 }
 
-#[derive(Default)]
-struct RelayMetrics {
-    packets_rx: AtomicU64,
-    bytes_rx: AtomicU64,
-    packets_forwarded: AtomicU64,
-    bytes_forwarded: AtomicU64,
-    lease_present_packets: AtomicU64,
-    lease_renew_packets: AtomicU64,
-    dropped_packets: AtomicU64,
-    rate_limited_packets: AtomicU64,
-    identity_rate_limited_packets: AtomicU64,
-    invalid_packets: AtomicU64,
-    auth_reject_packets: AtomicU64,
-    session_not_found_packets: AtomicU64,
-    session_not_active_packets: AtomicU64,
-    unknown_peer_packets: AtomicU64,
-    replay_dropped_packets: AtomicU64,
-    backpressure_dropped_packets: AtomicU64,
-    session_full_rejects: AtomicU64,
-    wrong_relay_rejects: AtomicU64,
-    expired_lease_rejects: AtomicU64,
-    cleanup_expired_sessions: AtomicU64,
-    cleanup_idle_sessions: AtomicU64,
-    overload_shed_packets: AtomicU64,
-    nat_rebind_events: AtomicU64,
+impl LeaseClaims {
+    fn quota_grant(&self) -> QuotaGrant {
+        let issued_at = self
+            .issued_at
+            .as_deref()
+            .and_then(|raw| parse_claim_time(raw).ok())
+            .unwrap_or_else(chrono::Utc::now);
+        QuotaGrant {
+            daily_limit_bytes: self.daily_quota_bytes,
+            monthly_limit_bytes: self.monthly_quota_bytes,
+            daily_used_bytes: self.daily_used_bytes.unwrap_or(0),
+            monthly_used_bytes: self.monthly_used_bytes.unwrap_or(0),
+            issued_on: issued_at.date_naive(),
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct RelayMetricsSnapshot {
-    packets_rx: u64,
-    bytes_rx: u64,
-    packets_forwarded: u64,
-    bytes_forwarded: u64,
-    lease_present_packets: u64,
-    lease_renew_packets: u64,
-    dropped_packets: u64,
-    rate_limited_packets: u64,
-    identity_rate_limited_packets: u64,
-    invalid_packets: u64,
-    auth_reject_packets: u64,
-    session_not_found_packets: u64,
-    session_not_active_packets: u64,
-    unknown_peer_packets: u64,
-    replay_dropped_packets: u64,
-    backpressure_dropped_packets: u64,
-    session_full_rejects: u64,
-    wrong_relay_rejects: u64,
-    expired_lease_rejects: u64,
-    cleanup_expired_sessions: u64,
-    cleanup_idle_sessions: u64,
-    overload_shed_packets: u64,
-    nat_rebind_events: u64,
-}
+/// Declares the relay's counters. Each one is registered in the
+/// process-wide `wavry_common::metrics` registry as `wavry_relay.<field>`, so
+/// the statsd and Prometheus exporters see them, and the JSON endpoints read
+/// the same values back through [`RelayMetricsSnapshot`].
+macro_rules! relay_metrics {
+    ($($field:ident),* $(,)?) => {
+        struct RelayMetrics {
+            $($field: Counter,)*
+        }
 
-impl RelayMetrics {
-    fn snapshot(&self) -> RelayMetricsSnapshot {
-        RelayMetricsSnapshot {
-            packets_rx: self.packets_rx.load(Ordering::Relaxed),
-            bytes_rx: self.bytes_rx.load(Ordering::Relaxed),
-            packets_forwarded: self.packets_forwarded.load(Ordering::Relaxed),
-            bytes_forwarded: self.bytes_forwarded.load(Ordering::Relaxed),
-            lease_present_packets: self.lease_present_packets.load(Ordering::Relaxed),
-            lease_renew_packets: self.lease_renew_packets.load(Ordering::Relaxed),
-            dropped_packets: self.dropped_packets.load(Ordering::Relaxed),
-            rate_limited_packets: self.rate_limited_packets.load(Ordering::Relaxed),
-            identity_rate_limited_packets: self
-                .identity_rate_limited_packets
-                .load(Ordering::Relaxed),
-            invalid_packets: self.invalid_packets.load(Ordering::Relaxed),
-            auth_reject_packets: self.auth_reject_packets.load(Ordering::Relaxed),
-            session_not_found_packets: self.session_not_found_packets.load(Ordering::Relaxed),
-            session_not_active_packets: self.session_not_active_packets.load(Ordering::Relaxed),
-            unknown_peer_packets: self.unknown_peer_packets.load(Ordering::Relaxed),
-            replay_dropped_packets: self.replay_dropped_packets.load(Ordering::Relaxed),
-            backpressure_dropped_packets: self.backpressure_dropped_packets.load(Ordering::Relaxed),
-            session_full_rejects: self.session_full_rejects.load(Ordering::Relaxed),
-            wrong_relay_rejects: self.wrong_relay_rejects.load(Ordering::Relaxed),
-            expired_lease_rejects: self.expired_lease_rejects.load(Ordering::Relaxed),
-            cleanup_expired_sessions: self.cleanup_expired_sessions.load(Ordering::Relaxed),
-            cleanup_idle_sessions: self.cleanup_idle_sessions.load(Ordering::Relaxed),
-            overload_shed_packets: self.overload_shed_packets.load(Ordering::Relaxed),
-            nat_rebind_events: self.nat_rebind_events.load(Ordering::Relaxed),
+        impl Default for RelayMetrics {
+            fn default() -> Self {
+                Self {
+                    $($field: metrics::counter(concat!("wavry_relay.", stringify!($field))),)*
+                }
+            }
         }
-    }
+
+        #[derive(Debug, Serialize)]
+        struct RelayMetricsSnapshot {
+            $($field: u64,)*
+        }
+
+        impl RelayMetrics {
+            fn snapshot(&self) -> RelayMetricsSnapshot {
+                RelayMetricsSnapshot {
+                    $($field: self.$field.get(),)*
+                }
+            }
+        }
+    };
+}
+
+relay_metrics! {
+    packets_rx,
+    bytes_rx,
+    packets_forwarded,
+    bytes_forwarded,
+    lease_present_packets,
+    lease_renew_packets,
+    dropped_packets,
+    rate_limited_packets,
+    identity_rate_limited_packets,
+    invalid_packets,
+    auth_reject_packets,
+    session_not_found_packets,
+    session_not_active_packets,
+    unknown_peer_packets,
+    replay_dropped_packets,
+    backpressure_dropped_packets,
+    session_full_rejects,
+    wrong_relay_rejects,
+    expired_lease_rejects,
+    cleanup_expired_sessions,
+    cleanup_idle_sessions,
+    overload_shed_packets,
+    nat_rebind_events,
+    quota_exceeded_packets,
+    quic_connections,
 }
 
 /// The core relay server responsible for forwarding encrypted UDP packets between peers.
@@ -420,6 +566,7 @@ impl RelayMetrics {
 /// - Validates PASETO v4 session leases from the Master server
 /// - Maintains per-session state with replay protection
 /// - Enforces bandwidth limits and rate limiting
+/// - Accounts forwarded bytes per user and enforces quotas from leases
 /// - Provides load shedding when capacity is exceeded
 /// - Exports metrics for monitoring
 ///
@@ -431,12 +578,27 @@ impl RelayMetrics {
 /// # Load Management
 /// When active sessions exceed the configured threshold (default 95%), new session
 /// requests are rejected to maintain service quality for existing sessions.
+///
+/// # Workers
+/// Each worker owns one socket on the shared port and one session shard. The
+/// shards share one session counter, so `max_sessions` caps the whole relay.
+/// The
+/// kernel spreads flows across sockets by address, so a worker hands packets
+/// for sessions it does not own to the owning worker's queue; everything for
+/// a session is then handled, and sent, by that one worker.
+///
+/// With the `quic` feature, packets arriving over QUIC join the same queues
+/// and replies to QUIC peers go back over their connection.
 struct RelayServer {
     relay_id: String,
-    socket: UdpSocket,
-    sessions: RwLock<SessionPool>,
-    ip_limiter: RwLock<IpRateLimiter>,
+    sockets: Vec<UdpSocket>,
+    shards: Vec<RwLock<SessionPool>>,
+    /// Sessions across all shards.
+    session_count: Arc<AtomicUsize>,
+    /// Split by source IP so workers rarely contend on the same table.
+    ip_limiters: Vec<RwLock<IpRateLimiter>>,
     identity_limiter: RwLock<IdentityRateLimiter>,
+    usage: RwLock<UsageLedger>,
     max_sessions: usize,
     packet_queue_capacity: usize,
     load_shed_threshold_pct: u8,
@@ -448,13 +610,15 @@ struct RelayServer {
     expected_master_key_id: Option<String>,
     registered_with_master: AtomicBool,
     started_at: Instant,
+    #[cfg(feature = "quic")]
+    quic: Option<quic::QuicListener>,
 }
 
 impl RelayServer {
     #[allow(clippy::too_many_arguments)]
     async fn new(
         relay_id: String,
-        socket: UdpSocket,
+        sockets: Vec<UdpSocket>,
         max_sessions: usize,
         idle_timeout: Duration,
         lease_duration: Duration,
@@ -492,13 +656,28 @@ impl RelayServer {
             ));
         };
 
+        let workers = sockets.len().max(1);
+        let max_sessions = max_sessions.max(1);
+        let session_count = Arc::new(AtomicUsize::new(0));
         Ok(Self {
             relay_id,
-            socket,
-            sessions: RwLock::new(SessionPool::new(max_sessions, idle_timeout)),
-            ip_limiter: RwLock::new(IpRateLimiter::new(ip_rate_limit_pps.max(1))),
+            sockets,
+            shards: (0..workers)
+                .map(|_| {
+                    RwLock::new(SessionPool::with_shared_limit(
+                        max_sessions,
+                        idle_timeout,
+                        Arc::clone(&session_count),
+                    ))
+                })
+                .collect(),
+            session_count,
+            ip_limiters: (0..workers)
+                .map(|_| RwLock::new(IpRateLimiter::new(ip_rate_limit_pps.max(1))))
+                .collect(),
             identity_limiter: RwLock::new(IdentityRateLimiter::new(identity_rate_limit_pps.max(1))),
-            max_sessions: max_sessions.max(1),
+            usage: RwLock::new(UsageLedger::new()),
+            max_sessions,
             packet_queue_capacity: packet_queue_capacity.max(64),
             load_shed_threshold_pct: load_shed_threshold_pct.clamp(50, 100),
             lease_duration,
@@ -509,15 +688,55 @@ impl RelayServer {
             expected_master_key_id,
             registered_with_master: AtomicBool::new(true),
             started_at: Instant::now(),
+            #[cfg(feature = "quic")]
+            quic: None,
         })
     }
 
+    fn shard(&self, session_id: &Uuid) -> usize {
+        shard_for_session(session_id, self.shards.len())
+    }
+
+    fn socket_for(&self, session_id: &Uuid) -> &UdpSocket {
+        &self.sockets[self.shard(session_id)]
+    }
+
+    /// Sends to a peer over QUIC if that is how it connected, otherwise from
+    /// the session's UDP socket.
+    async fn send_to_peer(
+        &self,
+        session_id: &Uuid,
+        packet: &[u8],
+        dest: SocketAddr,
+    ) -> std::io::Result<()> {
+        #[cfg(feature = "quic")]
+        if let Some(quic) = self.quic.as_ref() {
+            if let Some(conn) = quic.connection(dest).await {
+                return quic::send(&conn, packet).await;
+            }
+        }
+        self.socket_for(session_id)
+            .send_to(packet, dest)
+            .await
+            .map(|_| ())
+    }
+
+    fn ip_limiter(&self, ip: std::net::IpAddr) -> &RwLock<IpRateLimiter> {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        &self.ip_limiters[hasher.finish() as usize % self.ip_limiters.len()]
+    }
+
     async fn active_session_count(&self) -> usize {
-        self.sessions.read().await.active_count().await
+        let mut count = 0;
+        for shard in &self.shards {
+            count += shard.read().await.active_count().await;
+        }
+        count
     }
 
-    async fn total_session_count(&self) -> usize {
-        self.sessions.read().await.len()
+    fn total_session_count(&self) -> usize {
+        self.session_count.load(Ordering::Acquire)
     }
 
     fn has_master_key(&self) -> bool {
@@ -531,35 +750,63 @@ impl RelayServer {
         if !self.registered_with_master.load(Ordering::Relaxed) {
             return false;
         }
-        let sessions = self.sessions.read().await;
-        let used = sessions.len();
-        let threshold = ((self.max_sessions as u64 * self.load_shed_threshold_pct as u64) / 100)
-            .max(1) as usize;
-        used < threshold
+        self.total_session_count() < self.shed_threshold()
     }
 
-    async fn run(&self) -> Result<()> {
-        let mut buf = vec![0u8; RELAY_MAX_PACKET_SIZE];
+    /// Session count past which new sessions are turned away.
+    fn shed_threshold(&self) -> usize {
+        ((self.max_sessions as u64 * self.load_shed_threshold_pct as u64) / 100).max(1) as usize
+    }
+
+    async fn run(self: Arc<Self>) -> Result<()> {
         let mut cleanup_interval = tokio::time::interval(self.cleanup_interval);
         let mut last_stats_log = std::time::Instant::now();
-        let (tx, mut rx) = mpsc::channel::<(Vec<u8>, SocketAddr)>(self.packet_queue_capacity);
+        let (queues, receivers): (Vec<_>, Vec<_>) = (0..self.sockets.len())
+            .map(|_| mpsc::channel::<(Vec<u8>, SocketAddr)>(self.packet_queue_capacity))
+            .unzip();
+        let queues = Arc::new(queues);
+
+        let mut workers = JoinSet::new();
+        for (worker, rx) in receivers.into_iter().enumerate() {
+            workers.spawn(Arc::clone(&self).run_worker(worker, rx, Arc::clone(&queues)));
+        }
+        #[cfg(feature = "quic")]
+        if self.quic.is_some() {
+            workers.spawn(Arc::clone(&self).run_quic(Arc::clone(&queues)));
+        }
 
         loop {
             tokio::select! {
-                result = self.socket.recv_from(&mut buf) => {
-                    let (len, src) = result?;
-                    let packet = &buf[..len];
-                    self.metrics.packets_rx.fetch_add(1, Ordering::Relaxed);
-                    self.metrics.bytes_rx.fetch_add(packet.len() as u64, Ordering::Relaxed);
-                    if tx.try_send((packet.to_vec(), src)).is_err() {
-                        self.metrics
-                            .dropped_packets
-                            .fetch_add(1, Ordering::Relaxed);
-                        self.metrics
-                            .backpressure_dropped_packets
-                            .fetch_add(1, Ordering::Relaxed);
+                Some(result) = workers.join_next() => {
+                    result??;
+                }
+                _ = cleanup_interval.tick() => {
+                    self.account_usage().await;
+                    self.cleanup().await;
+                    if last_stats_log.elapsed() >= self.stats_log_interval {
+                        self.log_metrics().await;
+                        last_stats_log = std::time::Instant::now();
                     }
                 }
+            }
+        }
+    }
+
+    /// Receives on this worker's socket and handles the packets queued for
+    /// its shard, whichever socket they arrived on.
+    async fn run_worker(
+        self: Arc<Self>,
+        worker: usize,
+        mut rx: mpsc::Receiver<(Vec<u8>, SocketAddr)>,
+        queues: Arc<Vec<PacketQueue>>,
+    ) -> Result<()> {
+        let mut buf = vec![0u8; RELAY_MAX_PACKET_SIZE];
+        loop {
+            tokio::select! {
+                result = self.sockets[worker].recv_from(&mut buf) => {
+                    let (len, src) = result?;
+                    self.enqueue_packet(&queues, buf[..len].to_vec(), src, worker);
+                }
                 maybe_packet = rx.recv() => {
                     if let Some((packet, src)) = maybe_packet {
                         if let Err(e) = self.handle_packet(&packet, src).await {
@@ -567,17 +814,31 @@ impl RelayServer {
                         }
                     }
                 }
-                _ = cleanup_interval.tick() => {
-                    self.cleanup().await;
-                    if last_stats_log.elapsed() >= self.stats_log_interval {
-                        self.log_metrics().await;
-                        last_stats_log = std::time::Instant::now();
-                    }
-                }
             }
         }
     }
 
+    /// Counts a received packet and queues it for the worker owning its
+    /// session, or for `fallback` when it has no readable header.
+    fn enqueue_packet(
+        &self,
+        queues: &[PacketQueue],
+        packet: Vec<u8>,
+        src: SocketAddr,
+        fallback: usize,
+    ) {
+        self.metrics.packets_rx.increment(1);
+        self.metrics.bytes_rx.increment(packet.len() as u64);
+        // Packets without a readable header are rejected by whoever receives them.
+        let owner = RelayHeader::decode(&packet)
+            .map(|header| self.shard(&header.session_id))
+            .unwrap_or(fallback);
+        if queues[owner].try_send((packet, src)).is_err() {
+            self.metrics.dropped_packets.increment(1);
+            self.metrics.backpressure_dropped_packets.increment(1);
+        }
+    }
+
     async fn handle_packet(&self, packet: &[u8], src: SocketAddr) -> Result<(), PacketError> {
         if packet.len() < RELAY_HEADER_SIZE || packet.len() > RELAY_MAX_PACKET_SIZE {
             return Err(PacketError::InvalidSize);
@@ -591,8 +852,10 @@ impl RelayServer {
         }
 
         {
-            let mut limiter = self.ip_limiter.write().await;
-            if !limiter.check(src.ip()) {
+            // IPv4 peers on a dual-stack socket arrive IPv4-mapped.
+            let ip = src.ip().to_canonical();
+            let mut limiter = self.ip_limiter(ip).write().await;
+            if !limiter.check(ip) {
                 if matches!(
                     header.packet_type,
                     RelayPacketType::LeasePresent | RelayPacketType::LeaseRenew
@@ -615,31 +878,32 @@ impl RelayServer {
         let payload = &packet[RELAY_HEADER_SIZE..];
         match header.packet_type {
             RelayPacketType::LeasePresent => {
-                self.metrics
-                    .lease_present_packets
-                    .fetch_add(1, Ordering::Relaxed);
-                self.handle_lease_present(&header, payload, src).await
+                self.metrics.lease_present_packets.increment(1);
+                self.handle_lease_present(&header, payload, src)
+                    .instrument(lease_span(header.session_id))
+                    .await
             }
             RelayPacketType::LeaseRenew => {
-                self.metrics
-                    .lease_renew_packets
-                    .fetch_add(1, Ordering::Relaxed);
-                self.handle_lease_renew(&header, src).await
+                self.metrics.lease_renew_packets.increment(1);
+                self.handle_lease_renew(&header, src)
+                    .instrument(lease_span(header.session_id))
+                    .await
             }
+            // Forwarding is the hot path and logs nothing per packet, so it stays unspanned.
             RelayPacketType::Forward => self.handle_forward(&header, payload, src).await,
             _ => Err(PacketError::UnexpectedType),
         }
     }
 
     async fn should_shed_new_session(&self, session_id: Uuid) -> bool {
-        let sessions = self.sessions.read().await;
-        if sessions.contains(&session_id) {
+        if self.shards[self.shard(&session_id)]
+            .read()
+            .await
+            .contains(&session_id)
+        {
             return false;
         }
-        let threshold = ((sessions.max_sessions() as u64 * self.load_shed_threshold_pct as u64)
-            / 100)
-            .max(1) as usize;
-        sessions.len() >= threshold
+        self.total_session_count() >= self.shed_threshold()
     }
 
     async fn handle_lease_present(
@@ -734,19 +998,30 @@ impl RelayServer {
         } else {
             format!("dev-peer-{}", src)
         };
+        Span::current().record_wavry_id(&wavry_id);
         {
             let mut limiter = self.identity_limiter.write().await;
             if !limiter.check(&wavry_id) {
-                self.metrics
-                    .identity_rate_limited_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.identity_rate_limited_packets.increment(1);
                 self.send_lease_reject(header.session_id, src, LeaseRejectReason::RateLimited)
                     .await;
                 return Err(PacketError::RateLimited);
             }
         }
+        if let Some(claims) = maybe_claims.as_ref() {
+            let exhausted = self.usage.write().await.apply_grant(
+                &wavry_id,
+                &claims.quota_grant(),
+                chrono::Utc::now(),
+            );
+            if exhausted {
+                self.send_lease_reject(header.session_id, src, LeaseRejectReason::QuotaExceeded)
+                    .await;
+                return Err(PacketError::QuotaExceeded);
+            }
+        }
         let session_lock = {
-            let mut sessions = self.sessions.write().await;
+            let mut sessions = self.shards[self.shard(&header.session_id)].write().await;
             match sessions.get_or_create(header.session_id, self.lease_duration) {
                 Ok(lock) => lock,
                 Err(SessionError::SessionFull) => {
@@ -771,6 +1046,10 @@ impl RelayServer {
                 .await;
             return Err(PacketError::SessionError);
         }
+        // The grant above just confirmed the user has quota left.
+        if let Some(peer) = session.get_peer_mut(peer_role) {
+            peer.quota_exhausted.store(false, Ordering::Relaxed);
+        }
         if let Some(claims) = maybe_claims {
             if let Some(soft) = claims.soft_limit_kbps {
                 session.soft_limit_kbps = soft.max(1_000);
@@ -798,7 +1077,7 @@ impl RelayServer {
         src: SocketAddr,
     ) -> Result<(), PacketError> {
         let session_lock = {
-            let sessions = self.sessions.read().await;
+            let sessions = self.shards[self.shard(&header.session_id)].read().await;
             match sessions.get(&header.session_id) {
                 Some(session) => session,
                 None => {
@@ -809,10 +1088,16 @@ impl RelayServer {
             }
         };
         let mut session = session_lock.write().await;
-        if session.identify_peer(src).is_none() {
+        let Some((_, sender, _)) = session.identify_peer(src) else {
             self.send_lease_reject(header.session_id, src, LeaseRejectReason::InvalidSignature)
                 .await;
             return Err(PacketError::UnknownPeer);
+        };
+        if sender.quota_exhausted.load(Ordering::Relaxed) {
+            drop(session);
+            self.send_lease_reject(header.session_id, src, LeaseRejectReason::QuotaExceeded)
+                .await;
+            return Err(PacketError::QuotaExceeded);
         }
         if let Err(err) = session.renew_lease(self.lease_duration) {
             match err {
@@ -841,7 +1126,7 @@ impl RelayServer {
         src: SocketAddr,
     ) -> Result<(), PacketError> {
         let session_lock = {
-            let sessions = self.sessions.read().await;
+            let sessions = self.shards[self.shard(&header.session_id)].read().await;
             sessions
                 .get(&header.session_id)
                 .ok_or(PacketError::SessionNotFound)?
@@ -855,6 +1140,9 @@ impl RelayServer {
         let dest_addr = dest.socket_addr;
         let sequence = extract_forward_sequence(payload)?;
         if let Some(sender) = session.get_peer_mut(sender_role) {
+            if sender.quota_exhausted.load(Ordering::Relaxed) {
+                return Err(PacketError::QuotaExceeded);
+            }
             if !sender.seq_window.check_and_update(sequence) {
                 return Err(PacketError::ReplayDetected(sequence));
             }
@@ -869,20 +1157,21 @@ impl RelayServer {
         if session.current_bps > (session.hard_limit_kbps as f32 * 1000.0) {
             return Err(PacketError::RateLimited);
         }
+        let forward_size = RELAY_HEADER_SIZE + payload.len();
         if let Some(sender) = session.get_peer_mut(sender_role) {
             if sender.socket_addr != src {
                 debug!(
                     "NAT rebinding detected for {:?}: {} -> {}",
                     sender_role, sender.socket_addr, src
                 );
-                self.metrics
-                    .nat_rebind_events
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.nat_rebind_events.increment(1);
                 sender.socket_addr = src;
             }
             sender.last_seen = now;
+            sender
+                .unaccounted_bytes
+                .fetch_add(forward_size as u64, Ordering::Relaxed);
         }
-        let forward_size = RELAY_HEADER_SIZE + payload.len();
         session.record_forward(forward_size);
         session.bytes_sent_window += forward_size as u64;
         let mut forward_buf = vec![0u8; RELAY_HEADER_SIZE + payload.len()];
@@ -891,13 +1180,12 @@ impl RelayServer {
             .map_err(|_| PacketError::InvalidHeader)?;
         forward_buf[RELAY_HEADER_SIZE..].copy_from_slice(payload);
         drop(session);
-        self.socket.send_to(&forward_buf, dest_addr).await?;
-        self.metrics
-            .packets_forwarded
-            .fetch_add(1, Ordering::Relaxed);
+        self.send_to_peer(&header.session_id, &forward_buf, dest_addr)
+            .await?;
+        self.metrics.packets_forwarded.increment(1);
         self.metrics
             .bytes_forwarded
-            .fetch_add(forward_buf.len() as u64, Ordering::Relaxed);
+            .increment(forward_buf.len() as u64);
         Ok(())
     }
 
@@ -926,7 +1214,7 @@ impl RelayServer {
         if payload.encode(&mut packet[RELAY_HEADER_SIZE..]).is_err() {
             return;
         }
-        let _ = self.socket.send_to(&packet, dest).await;
+        let _ = self.send_to_peer(&session_id, &packet, dest).await;
     }
 
     async fn send_lease_reject(
@@ -944,89 +1232,124 @@ impl RelayServer {
         if payload.encode(&mut packet[RELAY_HEADER_SIZE..]).is_err() {
             return;
         }
-        let _ = self.socket.send_to(&packet, dest).await;
+        let _ = self.send_to_peer(&session_id, &packet, dest).await;
+    }
+
+    /// Charges the bytes forwarded since the last tick to their senders' users
+    /// and stops forwarding for peers whose user has run out of quota.
+    ///
+    /// Per-peer counters are atomics drained under read locks, and the ledger
+    /// is only locked once to charge the per-user totals, so forwarding never
+    /// waits on the tick.
+    async fn account_usage(&self) {
+        let mut forwarded: HashMap<String, u64> = HashMap::new();
+        for shard in &self.shards {
+            for session_lock in shard.read().await.iter() {
+                let session = session_lock.read().await;
+                for peer in [session.client.as_ref(), session.server.as_ref()]
+                    .into_iter()
+                    .flatten()
+                {
+                    let bytes = peer.unaccounted_bytes.swap(0, Ordering::Relaxed);
+                    *forwarded.entry(peer.wavry_id.clone()).or_default() += bytes;
+                }
+            }
+        }
+
+        let now = chrono::Utc::now();
+        let mut exhausted_users = HashSet::new();
+        {
+            let mut usage = self.usage.write().await;
+            for (wavry_id, bytes) in forwarded {
+                if usage.record(&wavry_id, bytes, now) {
+                    exhausted_users.insert(wavry_id);
+                }
+            }
+        }
+
+        for shard in &self.shards {
+            for session_lock in shard.read().await.iter() {
+                let session = session_lock.read().await;
+                for peer in [session.client.as_ref(), session.server.as_ref()]
+                    .into_iter()
+                    .flatten()
+                {
+                    let exhausted = exhausted_users.contains(&peer.wavry_id);
+                    let was_exhausted = peer.quota_exhausted.swap(exhausted, Ordering::Relaxed);
+                    if exhausted && !was_exhausted {
+                        info!(
+                            "user {} is out of relay quota; dropping traffic for session {}",
+                            peer.wavry_id, session.session_id
+                        );
+                    }
+                }
+            }
+        }
     }
 
     async fn cleanup(&self) {
-        let mut sessions = self.sessions.write().await;
-        let cleanup = sessions.cleanup().await;
-        if cleanup.total_removed() > 0 {
-            self.metrics
-                .cleanup_expired_sessions
-                .fetch_add(cleanup.expired_sessions as u64, Ordering::Relaxed);
-            self.metrics
-                .cleanup_idle_sessions
-                .fetch_add(cleanup.idle_sessions as u64, Ordering::Relaxed);
-            debug!(
-                "relay cleanup removed expired={} idle={}",
-                cleanup.expired_sessions, cleanup.idle_sessions
-            );
+        for shard in &self.shards {
+            let cleanup = shard.write().await.cleanup().await;
+            if cleanup.total_removed() > 0 {
+                self.metrics
+                    .cleanup_expired_sessions
+                    .increment(cleanup.expired_sessions as u64);
+                self.metrics
+                    .cleanup_idle_sessions
+                    .increment(cleanup.idle_sessions as u64);
+                debug!(
+                    "relay cleanup removed expired={} idle={}",
+                    cleanup.expired_sessions, cleanup.idle_sessions
+                );
+            }
+        }
+        for limiter in &self.ip_limiters {
+            limiter.write().await.cleanup();
         }
-        let mut limiter = self.ip_limiter.write().await;
-        limiter.cleanup();
-        let mut identity_limiter = self.identity_limiter.write().await;
-        identity_limiter.cleanup();
+        self.identity_limiter.write().await.cleanup();
+        self.usage.write().await.cleanup(USAGE_RETENTION);
     }
 
     fn record_packet_error(&self, err: &PacketError, src: SocketAddr) {
-        self.metrics.dropped_packets.fetch_add(1, Ordering::Relaxed);
+        self.metrics.dropped_packets.increment(1);
         match err {
             PacketError::RateLimited => {
-                self.metrics
-                    .rate_limited_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.rate_limited_packets.increment(1);
             }
             PacketError::InvalidSignature => {
-                self.metrics
-                    .auth_reject_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.auth_reject_packets.increment(1);
                 warn!(
                     "Invalid lease signature from {}: Possible unauthorized access attempt",
                     src
                 );
             }
             PacketError::ExpiredLease => {
-                self.metrics
-                    .auth_reject_packets
-                    .fetch_add(1, Ordering::Relaxed);
-                self.metrics
-                    .expired_lease_rejects
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.auth_reject_packets.increment(1);
+                self.metrics.expired_lease_rejects.increment(1);
             }
             PacketError::SessionNotFound => {
-                self.metrics
-                    .session_not_found_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.session_not_found_packets.increment(1);
             }
             PacketError::SessionNotActive => {
-                self.metrics
-                    .session_not_active_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.session_not_active_packets.increment(1);
             }
             PacketError::UnknownPeer => {
-                self.metrics
-                    .unknown_peer_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.unknown_peer_packets.increment(1);
             }
             PacketError::ReplayDetected(_) => {
-                self.metrics
-                    .replay_dropped_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.replay_dropped_packets.increment(1);
             }
             PacketError::SessionFull => {
-                self.metrics
-                    .session_full_rejects
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.session_full_rejects.increment(1);
             }
             PacketError::WrongRelay => {
-                self.metrics
-                    .wrong_relay_rejects
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.wrong_relay_rejects.increment(1);
             }
             PacketError::Overloaded => {
-                self.metrics
-                    .overload_shed_packets
-                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics.overload_shed_packets.increment(1);
+            }
+            PacketError::QuotaExceeded => {
+                self.metrics.quota_exceeded_packets.increment(1);
             }
             PacketError::InvalidSize
             | PacketError::InvalidMagic
@@ -1036,7 +1359,7 @@ impl RelayServer {
             | PacketError::InvalidRole
             | PacketError::UnexpectedType
             | PacketError::KeyIdMismatch => {
-                self.metrics.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                self.metrics.invalid_packets.increment(1);
             }
             _ => {}
         }
@@ -1044,10 +1367,10 @@ impl RelayServer {
 
     async fn log_metrics(&self) {
         let active_sessions = self.active_session_count().await;
-        let total_sessions = self.total_session_count().await;
+        let total_sessions = self.total_session_count();
         let snapshot = self.metrics.snapshot();
         info!(
-            "relay metrics relay_id={} active_sessions={} total_sessions={} packets_rx={} bytes_rx={} forwarded_packets={} forwarded_bytes={} lease_present={} lease_renew={} dropped={} rate_limited={} identity_rate_limited={} invalid={} auth_rejects={} session_not_found={} session_not_active={} unknown_peer={} replay_drops={} backpressure_drops={} session_full={} wrong_relay={} expired_leases={} cleanup_expired={} cleanup_idle={} overload_shed={} nat_rebinds={}",
+            "relay metrics relay_id={} active_sessions={} total_sessions={} packets_rx={} bytes_rx={} forwarded_packets={} forwarded_bytes={} lease_present={} lease_renew={} dropped={} rate_limited={} identity_rate_limited={} invalid={} auth_rejects={} session_not_found={} session_not_active={} unknown_peer={} replay_drops={} backpressure_drops={} session_full={} wrong_relay={} expired_leases={} cleanup_expired={} cleanup_idle={} overload_shed={} nat_rebinds={} quota_exceeded={} usage_users={}",
             self.relay_id,
             active_sessions,
             total_sessions,
@@ -1073,7 +1396,9 @@ impl RelayServer {
             snapshot.cleanup_expired_sessions,
             snapshot.cleanup_idle_sessions,
             snapshot.overload_shed_packets,
-            snapshot.nat_rebind_events
+            snapshot.nat_rebind_events,
+            snapshot.quota_exceeded_packets,
+            self.usage.read().await.user_count()
         );
     }
 }
@@ -1116,6 +1441,8 @@ enum PacketError {
     ReplayDetected(u64),
     #[error("relay overloaded, shedding new session")]
     Overloaded,
+    #[error("user is out of relay quota")]
+    QuotaExceeded,
     #[error("session error")]
     SessionError,
     #[error("io error: {0}")]
@@ -1128,6 +1455,12 @@ struct ValidatedLease {
     peer_role: PeerRole,
 }
 
+fn lease_span(session_id: Uuid) -> Span {
+    let span = session_span("relay");
+    span.record_relay_session(session_id);
+    span
+}
+
 fn parse_claim_time(value: &str) -> Result<chrono::DateTime<chrono::Utc>, PacketError> {
     chrono::DateTime::parse_from_rfc3339(value)
         .map(|dt| dt.with_timezone(&chrono::Utc))
@@ -1241,13 +1574,14 @@ struct RelayStatusResponse {
     active_sessions: usize,
     total_sessions: usize,
     max_sessions: usize,
+    workers: usize,
     uptime_secs: u64,
     metrics: RelayMetricsSnapshot,
 }
 
 async fn relay_health(State(state): State<RelayHttpState>) -> impl IntoResponse {
     let active_sessions = state.server.active_session_count().await;
-    let total_sessions = state.server.total_session_count().await;
+    let total_sessions = state.server.total_session_count();
     let metrics = state.server.metrics.snapshot();
     let response = RelayStatusResponse {
         relay_id: state.server.relay_id.clone(),
@@ -1258,6 +1592,7 @@ async fn relay_health(State(state): State<RelayHttpState>) -> impl IntoResponse
         active_sessions,
         total_sessions,
         max_sessions: state.server.max_sessions,
+        workers: state.server.sockets.len(),
         uptime_secs: state.server.started_at.elapsed().as_secs(),
         metrics,
     };
@@ -1285,113 +1620,18 @@ async fn relay_metrics(State(state): State<RelayHttpState>) -> impl IntoResponse
 }
 
 async fn relay_metrics_prometheus(State(state): State<RelayHttpState>) -> impl IntoResponse {
-    let snapshot = state.server.metrics.snapshot();
-    let relay_id = &state.server.relay_id;
-    let active_sessions = state.server.active_session_count().await;
-
-    let prometheus_text = format!(
-        r#"# HELP wavry_relay_packets_rx Total packets received
-# TYPE wavry_relay_packets_rx counter
-wavry_relay_packets_rx{{relay_id="{relay_id}"}} {packets_rx}
-# HELP wavry_relay_bytes_rx Total bytes received
-# TYPE wavry_relay_bytes_rx counter
-wavry_relay_bytes_rx{{relay_id="{relay_id}"}} {bytes_rx}
-# HELP wavry_relay_packets_forwarded Total packets forwarded
-# TYPE wavry_relay_packets_forwarded counter
-wavry_relay_packets_forwarded{{relay_id="{relay_id}"}} {packets_forwarded}
-# HELP wavry_relay_bytes_forwarded Total bytes forwarded
-# TYPE wavry_relay_bytes_forwarded counter
-wavry_relay_bytes_forwarded{{relay_id="{relay_id}"}} {bytes_forwarded}
-# HELP wavry_relay_lease_present_packets Lease present packets received
-# TYPE wavry_relay_lease_present_packets counter
-wavry_relay_lease_present_packets{{relay_id="{relay_id}"}} {lease_present_packets}
-# HELP wavry_relay_lease_renew_packets Lease renew packets received
-# TYPE wavry_relay_lease_renew_packets counter
-wavry_relay_lease_renew_packets{{relay_id="{relay_id}"}} {lease_renew_packets}
-# HELP wavry_relay_dropped_packets Total packets dropped
-# TYPE wavry_relay_dropped_packets counter
-wavry_relay_dropped_packets{{relay_id="{relay_id}"}} {dropped_packets}
-# HELP wavry_relay_rate_limited_packets Packets dropped due to rate limiting
-# TYPE wavry_relay_rate_limited_packets counter
-wavry_relay_rate_limited_packets{{relay_id="{relay_id}"}} {rate_limited_packets}
-# HELP wavry_relay_identity_rate_limited_packets Lease packets dropped by identity rate limiting
-# TYPE wavry_relay_identity_rate_limited_packets counter
-wavry_relay_identity_rate_limited_packets{{relay_id="{relay_id}"}} {identity_rate_limited_packets}
-# HELP wavry_relay_invalid_packets Invalid packets received
-# TYPE wavry_relay_invalid_packets counter
-wavry_relay_invalid_packets{{relay_id="{relay_id}"}} {invalid_packets}
-# HELP wavry_relay_auth_reject_packets Packets rejected due to auth failure
-# TYPE wavry_relay_auth_reject_packets counter
-wavry_relay_auth_reject_packets{{relay_id="{relay_id}"}} {auth_reject_packets}
-# HELP wavry_relay_session_not_found_packets Packets for unknown sessions
-# TYPE wavry_relay_session_not_found_packets counter
-wavry_relay_session_not_found_packets{{relay_id="{relay_id}"}} {session_not_found_packets}
-# HELP wavry_relay_session_not_active_packets Packets for inactive sessions
-# TYPE wavry_relay_session_not_active_packets counter
-wavry_relay_session_not_active_packets{{relay_id="{relay_id}"}} {session_not_active_packets}
-# HELP wavry_relay_unknown_peer_packets Packets from unknown peers
-# TYPE wavry_relay_unknown_peer_packets counter
-wavry_relay_unknown_peer_packets{{relay_id="{relay_id}"}} {unknown_peer_packets}
-# HELP wavry_relay_replay_dropped_packets Packets dropped due to replay detection
-# TYPE wavry_relay_replay_dropped_packets counter
-wavry_relay_replay_dropped_packets{{relay_id="{relay_id}"}} {replay_dropped_packets}
-# HELP wavry_relay_backpressure_dropped_packets Packets dropped because inbound queue was full
-# TYPE wavry_relay_backpressure_dropped_packets counter
-wavry_relay_backpressure_dropped_packets{{relay_id="{relay_id}"}} {backpressure_dropped_packets}
-# HELP wavry_relay_session_full_rejects Session creations rejected (capacity)
-# TYPE wavry_relay_session_full_rejects counter
-wavry_relay_session_full_rejects{{relay_id="{relay_id}"}} {session_full_rejects}
-# HELP wavry_relay_wrong_relay_rejects Packets for wrong relay
-# TYPE wavry_relay_wrong_relay_rejects counter
-wavry_relay_wrong_relay_rejects{{relay_id="{relay_id}"}} {wrong_relay_rejects}
-# HELP wavry_relay_expired_lease_rejects Packets with expired leases
-# TYPE wavry_relay_expired_lease_rejects counter
-wavry_relay_expired_lease_rejects{{relay_id="{relay_id}"}} {expired_lease_rejects}
-# HELP wavry_relay_cleanup_expired_sessions Sessions cleaned up (expired)
-# TYPE wavry_relay_cleanup_expired_sessions counter
-wavry_relay_cleanup_expired_sessions{{relay_id="{relay_id}"}} {cleanup_expired_sessions}
-# HELP wavry_relay_cleanup_idle_sessions Sessions cleaned up (idle)
-# TYPE wavry_relay_cleanup_idle_sessions counter
-wavry_relay_cleanup_idle_sessions{{relay_id="{relay_id}"}} {cleanup_idle_sessions}
-# HELP wavry_relay_overload_shed_packets Packets shed due to overload
-# TYPE wavry_relay_overload_shed_packets counter
-wavry_relay_overload_shed_packets{{relay_id="{relay_id}"}} {overload_shed_packets}
-# HELP wavry_relay_nat_rebind_events NAT rebinding events
-# TYPE wavry_relay_nat_rebind_events counter
-wavry_relay_nat_rebind_events{{relay_id="{relay_id}"}} {nat_rebind_events}
-# HELP wavry_relay_active_sessions Current number of active sessions
-# TYPE wavry_relay_active_sessions gauge
-wavry_relay_active_sessions{{relay_id="{relay_id}"}} {active_sessions}
-# HELP wavry_relay_uptime_seconds Relay uptime in seconds
-# TYPE wavry_relay_uptime_seconds gauge
-wavry_relay_uptime_seconds{{relay_id="{relay_id}"}} {uptime_seconds}
-"#,
-        relay_id = relay_id,
-        packets_rx = snapshot.packets_rx,
-        bytes_rx = snapshot.bytes_rx,
-        packets_forwarded = snapshot.packets_forwarded,
-        bytes_forwarded = snapshot.bytes_forwarded,
-        lease_present_packets = snapshot.lease_present_packets,
-        lease_renew_packets = snapshot.lease_renew_packets,
-        dropped_packets = snapshot.dropped_packets,
-        rate_limited_packets = snapshot.rate_limited_packets,
-        identity_rate_limited_packets = snapshot.identity_rate_limited_packets,
-        invalid_packets = snapshot.invalid_packets,
-        auth_reject_packets = snapshot.auth_reject_packets,
-        session_not_found_packets = snapshot.session_not_found_packets,
-        session_not_active_packets = snapshot.session_not_active_packets,
-        unknown_peer_packets = snapshot.unknown_peer_packets,
-        replay_dropped_packets = snapshot.replay_dropped_packets,
-        backpressure_dropped_packets = snapshot.backpressure_dropped_packets,
-        session_full_rejects = snapshot.session_full_rejects,
-        wrong_relay_rejects = snapshot.wrong_relay_rejects,
-        expired_lease_rejects = snapshot.expired_lease_rejects,
-        cleanup_expired_sessions = snapshot.cleanup_expired_sessions,
-        cleanup_idle_sessions = snapshot.cleanup_idle_sessions,
-        overload_shed_packets = snapshot.overload_shed_packets,
-        nat_rebind_events = snapshot.nat_rebind_events,
-        active_sessions = active_sessions,
-        uptime_seconds = state.server.started_at.elapsed().as_secs(),
+    let server = &state.server;
+    wavry_common::gauge!(
+        "wavry_relay.active_sessions",
+        server.active_session_count().await as f64
+    );
+    wavry_common::gauge!(
+        "wavry_relay.uptime_seconds",
+        server.started_at.elapsed().as_secs() as f64
+    );
+    let prometheus_text = metrics::render_prometheus_with_labels(
+        &metrics::snapshot(),
+        &[("relay_id", server.relay_id.as_str())],
     );
 
     (
@@ -1427,10 +1667,79 @@ async fn serve_health_http(server: Arc<RelayServer>, listen: SocketAddr) -> Resu
     Ok(())
 }
 
+/// Binds one socket per worker on a single port. Falls back to a random port
+/// when the requested one is taken; extra workers then join that port.
+fn bind_worker_sockets(listen: SocketAddr, workers: usize) -> Result<Vec<UdpSocket>> {
+    let reuse_port = workers > 1;
+    let first = match bind_udp(listen, reuse_port) {
+        Ok(socket) => socket,
+        Err(err)
+            if listen.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                && !matches!(
+                    err.kind(),
+                    ErrorKind::AddrInUse | ErrorKind::PermissionDenied
+                ) =>
+        {
+            let fallback_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), listen.port());
+            warn!(
+                "IPv6 unavailable ({}); relay falling back to {}",
+                err, fallback_addr
+            );
+            return bind_worker_sockets(fallback_addr, workers);
+        }
+        Err(err) if err.kind() == ErrorKind::AddrInUse => {
+            let fallback_addr = SocketAddr::new(listen.ip(), 0);
+            warn!(
+                "relay bind {} is already in use, falling back to {}",
+                listen, fallback_addr
+            );
+            bind_udp(fallback_addr, reuse_port)?
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let bound_addr = first.local_addr()?;
+    let mut sockets = vec![first];
+    for _ in 1..workers {
+        sockets.push(bind_udp(bound_addr, true)?);
+    }
+    Ok(sockets)
+}
+
+fn bind_udp(addr: SocketAddr, reuse_port: bool) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        socket.set_only_v6(false)?;
+    }
+    #[cfg(unix)]
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    #[cfg(not(unix))]
+    let _ = reuse_port;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Address families a relay bound to `addr` serves, as registration features.
+fn address_family_features(addr: SocketAddr) -> Vec<String> {
+    match addr.ip() {
+        IpAddr::V4(_) => vec!["ipv4".into()],
+        IpAddr::V6(ip) if ip.is_unspecified() => vec!["ipv4".into(), "ipv6".into()],
+        IpAddr::V6(_) => vec!["ipv6".into()],
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
-    if !args.listen.ip().is_loopback() || !args.health_listen.ip().is_loopback() {
+    let args = Args::load()?;
+    #[cfg(feature = "quic")]
+    let public_quic = args
+        .quic_listen
+        .is_some_and(|addr| !addr.ip().is_loopback());
+    #[cfg(not(feature = "quic"))]
+    let public_quic = false;
+    if !args.listen.ip().is_loopback() || !args.health_listen.ip().is_loopback() || public_quic {
         if !env_bool("WAVRY_RELAY_ALLOW_PUBLIC_BIND", false) {
             return Err(anyhow::anyhow!(
                 "refusing non-loopback relay bind without WAVRY_RELAY_ALLOW_PUBLIC_BIND=1"
@@ -1443,23 +1752,28 @@ async fn main() -> Result<()> {
         }
     }
     let filter = format!("{},hyper=warn,tokio=warn", args.log_level);
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    let _log_handle =
+        wavry_common::init_logging(wavry_common::LogOptions::from_env(filter, "wavry-relay")?)?;
     info!("Starting wavry-relay v{}", env!("CARGO_PKG_VERSION"));
 
-    let socket = match UdpSocket::bind(args.listen).await {
-        Ok(socket) => socket,
-        Err(err) if err.kind() == ErrorKind::AddrInUse => {
-            let fallback_addr = SocketAddr::new(args.listen.ip(), 0);
-            warn!(
-                "relay bind {} is already in use, falling back to {}",
-                args.listen, fallback_addr
-            );
-            UdpSocket::bind(fallback_addr).await?
-        }
-        Err(err) => return Err(err.into()),
-    };
-    let bound_addr = socket.local_addr()?;
-    info!("Relay listening on {}", bound_addr);
+    let mut workers = args.workers.clamp(1, MAX_WORKERS);
+    if workers > 1 && !cfg!(unix) {
+        warn!("SO_REUSEPORT is unavailable on this platform; running a single relay worker");
+        workers = 1;
+    }
+    let sockets = bind_worker_sockets(args.listen, workers)?;
+    let bound_addr = sockets[0].local_addr()?;
+    info!(
+        "Relay listening on {} with {} worker(s)",
+        bound_addr, workers
+    );
+    #[cfg(feature = "quic")]
+    let quic_listener = args
+        .quic_listen
+        .map(|listen| {
+            quic::QuicListener::bind(listen, args.quic_cert.as_deref(), args.quic_key.as_deref())
+        })
+        .transpose()?;
 
     let relay_id = Uuid::new_v4().to_string();
     info!("Relay ID: {}", relay_id);
@@ -1470,6 +1784,7 @@ async fn main() -> Result<()> {
         register_url: format!("{}/v1/relays/register", args.master_url),
         relay_id: relay_id.clone(),
         endpoints: endpoints.clone(),
+        features: address_family_features(bound_addr),
         region: args.region.clone(),
         asn: args.asn,
         max_sessions: args.max_sessions,
@@ -1483,26 +1798,30 @@ async fn main() -> Result<()> {
         "Registered successfully. Heartbeat interval: {}ms",
         reg_data.heartbeat_interval_ms
     );
-    let server = Arc::new(
-        RelayServer::new(
-            relay_id.clone(),
-            socket,
-            args.max_sessions,
-            Duration::from_secs(args.idle_timeout),
-            Duration::from_secs(args.lease_duration_secs.max(1)),
-            Duration::from_secs(args.cleanup_interval_secs.max(1)),
-            Duration::from_secs(args.stats_log_interval_secs.max(5)),
-            args.load_shed_threshold_pct,
-            args.ip_rate_limit_pps.max(1),
-            args.identity_rate_limit_pps.max(1),
-            args.packet_queue_capacity.max(64),
-            args.master_public_key.as_deref(),
-            Some(&reg_data.master_public_key),
-            reg_data.master_key_id.clone(),
-            args.allow_insecure_dev,
-        )
-        .await?,
-    );
+    #[cfg_attr(not(feature = "quic"), allow(unused_mut))]
+    let mut server = RelayServer::new(
+        relay_id.clone(),
+        sockets,
+        args.max_sessions,
+        Duration::from_secs(args.idle_timeout),
+        Duration::from_secs(args.lease_duration_secs.max(1)),
+        Duration::from_secs(args.cleanup_interval_secs.max(1)),
+        Duration::from_secs(args.stats_log_interval_secs.max(5)),
+        args.load_shed_threshold_pct,
+        args.ip_rate_limit_pps.max(1),
+        args.identity_rate_limit_pps.max(1),
+        args.packet_queue_capacity.max(64),
+        args.master_public_key.as_deref(),
+        Some(&reg_data.master_public_key),
+        reg_data.master_key_id.clone(),
+        args.allow_insecure_dev,
+    )
+    .await?;
+    #[cfg(feature = "quic")]
+    {
+        server.quic = quic_listener;
+    }
+    let server = Arc::new(server);
 
     let health_server = server.clone();
     let health_listen = args.health_listen;
@@ -1604,6 +1923,22 @@ async fn main() -> Result<()> {
         }
     });
 
+    let _metrics_reporter = match &args.statsd_addr {
+        Some(addr) => Some(Reporter::start(
+            Duration::from_secs(args.stats_log_interval_secs.max(1)),
+            vec![Box::new(StatsdExporter::new(addr.as_str(), "")?)],
+        )),
+        None => None,
+    };
+
+    tokio::spawn(report_usage(
+        server.clone(),
+        reqwest::Client::new(),
+        format!("{}/v1/relays/usage", args.master_url),
+        args.master_auth_token.clone(),
+        Duration::from_secs(args.usage_report_interval_secs.max(10)),
+    ));
+
     // Setup graceful shutdown handler
     let shutdown_server = server.clone();
     tokio::spawn(async move {
@@ -1645,6 +1980,10 @@ mod tests {
             expiration: (now + chrono::Duration::minutes(5)).to_rfc3339(),
             soft_limit_kbps: Some(30_000),
             hard_limit_kbps: Some(60_000),
+            daily_quota_bytes: None,
+            monthly_quota_bytes: None,
+            daily_used_bytes: None,
+            monthly_used_bytes: None,
         }
     }
 
@@ -1710,6 +2049,33 @@ mod tests {
         assert!(matches!(err, PacketError::ExpiredLease));
     }
 
+    #[test]
+    fn lease_claims_carry_quota_grant() {
+        let session_id = Uuid::new_v4();
+        let mut claims = build_claims(session_id);
+        let json = serde_json::to_value(&claims).unwrap();
+        let decoded = decode_lease_claims_value(json).expect("claims without quota");
+        assert_eq!(decoded.quota_grant().daily_limit_bytes, None);
+
+        claims.daily_quota_bytes = Some(10_000_000_000);
+        claims.monthly_used_bytes = Some(42);
+        let json = serde_json::to_value(&claims).unwrap();
+        assert_eq!(json["dquota"], 10_000_000_000u64);
+        let grant = decode_lease_claims_value(json).unwrap().quota_grant();
+        assert_eq!(grant.daily_limit_bytes, Some(10_000_000_000));
+        assert_eq!(grant.monthly_limit_bytes, None);
+        assert_eq!(grant.monthly_used_bytes, 42);
+        assert_eq!(grant.issued_on, chrono::Utc::now().date_naive());
+    }
+
+    #[test]
+    fn registration_features_follow_the_bound_family() {
+        let features = |addr: &str| address_family_features(addr.parse().unwrap());
+        assert_eq!(features("0.0.0.0:4000"), vec!["ipv4"]);
+        assert_eq!(features("[::]:4000"), vec!["ipv4", "ipv6"]);
+        assert_eq!(features("[2001:db8::7]:4000"), vec!["ipv6"]);
+    }
+
     #[test]
     fn identity_rate_limiter_enforces_window() {
         let mut limiter = IdentityRateLimiter::new(2);
@@ -1723,4 +2089,26 @@ mod tests {
         assert!(limiter.check("user-1"));
         assert!(limiter.check("user-2"));
     }
+
+    #[test]
+    fn flags_given_win_over_the_config_file() {
+        let path = std::env::temp_dir().join(format!("wavry-relay-{}.toml", Uuid::new_v4()));
+        std::fs::write(&path, "[relay]\nmax_sessions = 9\nregion = \"eu\"\n").unwrap();
+
+        let matches = Args::command().get_matches_from([
+            "wavry-relay".as_ref(),
+            "--config".as_ref(),
+            path.as_os_str(),
+            "--max-sessions".as_ref(),
+            "5".as_ref(),
+        ]);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let applied = args.apply_config(&matches);
+        std::fs::remove_file(&path).unwrap();
+        applied.unwrap();
+
+        assert_eq!(args.max_sessions, 5);
+        assert_eq!(args.region.as_deref(), Some("eu"));
+        assert_eq!(args.idle_timeout, DEFAULT_IDLE_TIMEOUT_SECS);
+    }
 }
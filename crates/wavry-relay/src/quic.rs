@@ -0,0 +1,214 @@
+//! Optional QUIC listener for networks that block plain UDP.
+//!
+//! Relay packets are carried byte for byte as they would be over UDP: one
+//! QUIC datagram per packet, or a short unidirectional stream for the rare
+//! packet too big for a datagram on the current path. Lease checks,
+//! forwarding, rate limits and metrics are therefore shared with the UDP
+//! listener. The session pool knows a QUIC peer by its connection's remote
+//! address, and [`QuicListener`] maps that address back to the connection so
+//! replies leave over QUIC instead of the UDP socket.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use quinn::rustls::pki_types::pem::PemObject;
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rift_core::relay::RELAY_MAX_PACKET_SIZE;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::{PacketQueue, RelayServer};
+
+/// Matches the UDP session idle timeout closely enough that a silent QUIC
+/// peer is dropped around the time its session would be.
+const QUIC_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const QUIC_SELF_SIGNED_NAME: &str = "wavry-relay";
+
+pub struct QuicListener {
+    endpoint: quinn::Endpoint,
+    peers: RwLock<HashMap<SocketAddr, quinn::Connection>>,
+}
+
+impl QuicListener {
+    /// Binds the listener. Without a certificate and key a self-signed
+    /// certificate is generated; the relay protocol authenticates peers by
+    /// lease, so clients are expected not to verify it.
+    pub fn bind(listen: SocketAddr, cert: Option<&Path>, key: Option<&Path>) -> Result<Self> {
+        let (chain, key) = match (cert, key) {
+            (Some(cert), Some(key)) => load_identity(cert, key)?,
+            (None, None) => {
+                warn!("no QUIC certificate configured; using a self-signed certificate");
+                self_signed_identity()?
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "--quic-cert and --quic-key must be given together"
+                ))
+            }
+        };
+
+        let mut transport = quinn::TransportConfig::default();
+        transport.max_idle_timeout(Some(QUIC_IDLE_TIMEOUT.try_into()?));
+        transport.max_concurrent_bidi_streams(0u32.into());
+        let mut config = quinn::ServerConfig::with_single_cert(chain, key)?;
+        config.transport_config(Arc::new(transport));
+
+        let endpoint = quinn::Endpoint::server(config, listen)?;
+        info!("Relay QUIC listener on {}", endpoint.local_addr()?);
+        Ok(Self {
+            endpoint,
+            peers: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub async fn connection(&self, addr: SocketAddr) -> Option<quinn::Connection> {
+        self.peers.read().await.get(&addr).cloned()
+    }
+
+    /// Re-keys a connection whose remote address changed, so replies follow
+    /// the peer the same way the session pool does on NAT rebinding.
+    async fn rebind(&self, from: SocketAddr, to: SocketAddr, conn: &quinn::Connection) {
+        let mut peers = self.peers.write().await;
+        if peers
+            .get(&from)
+            .is_some_and(|known| known.stable_id() == conn.stable_id())
+        {
+            peers.remove(&from);
+        }
+        peers.insert(to, conn.clone());
+    }
+
+    async fn remove(&self, addr: SocketAddr, conn: &quinn::Connection) {
+        let mut peers = self.peers.write().await;
+        if peers
+            .get(&addr)
+            .is_some_and(|known| known.stable_id() == conn.stable_id())
+        {
+            peers.remove(&addr);
+        }
+    }
+}
+
+/// Sends one relay packet, as a datagram when it fits.
+pub async fn send(conn: &quinn::Connection, packet: &[u8]) -> io::Result<()> {
+    if conn
+        .max_datagram_size()
+        .is_some_and(|max| packet.len() <= max)
+    {
+        return conn
+            .send_datagram(Bytes::copy_from_slice(packet))
+            .map_err(io::Error::other);
+    }
+    let mut stream = conn.open_uni().await.map_err(io::Error::other)?;
+    stream.write_all(packet).await.map_err(io::Error::other)?;
+    stream.finish().map_err(io::Error::other)
+}
+
+fn load_identity(
+    cert: &Path,
+    key: &Path,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .with_context(|| format!("reading QUIC certificate {}", cert.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parsing QUIC certificate {}", cert.display()))?;
+    if chain.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no certificates found in {}",
+            cert.display()
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("reading QUIC private key {}", key.display()))?;
+    Ok((chain, key))
+}
+
+fn self_signed_identity() -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let certified = rcgen::generate_simple_self_signed(vec![QUIC_SELF_SIGNED_NAME.to_string()])?;
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    Ok((vec![certified.cert.der().clone()], key.into()))
+}
+
+impl RelayServer {
+    /// Accepts QUIC peers and feeds their packets into the same shard queues
+    /// as the UDP workers.
+    pub(crate) async fn run_quic(self: Arc<Self>, queues: Arc<Vec<PacketQueue>>) -> Result<()> {
+        let Some(quic) = self.quic.as_ref() else {
+            return Ok(());
+        };
+        while let Some(incoming) = quic.endpoint.accept().await {
+            let server = Arc::clone(&self);
+            let queues = Arc::clone(&queues);
+            tokio::spawn(async move {
+                match incoming.await {
+                    Ok(conn) => server.serve_quic_peer(conn, &queues).await,
+                    Err(e) => debug!("QUIC handshake failed: {}", e),
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn serve_quic_peer(&self, conn: quinn::Connection, queues: &[PacketQueue]) {
+        let Some(quic) = self.quic.as_ref() else {
+            return;
+        };
+        let mut remote = conn.remote_address();
+        quic.peers.write().await.insert(remote, conn.clone());
+        self.metrics.quic_connections.increment(1);
+
+        let reason = loop {
+            let packet = tokio::select! {
+                datagram = conn.read_datagram() => match datagram {
+                    Ok(datagram) => datagram.to_vec(),
+                    Err(e) => break e,
+                },
+                stream = conn.accept_uni() => match stream {
+                    Ok(mut stream) => match stream.read_to_end(RELAY_MAX_PACKET_SIZE).await {
+                        Ok(packet) => packet,
+                        Err(_) => {
+                            self.metrics.invalid_packets.increment(1);
+                            continue;
+                        }
+                    },
+                    Err(e) => break e,
+                },
+            };
+            let current = conn.remote_address();
+            if current != remote {
+                quic.rebind(remote, current, &conn).await;
+                remote = current;
+            }
+            self.enqueue_packet(queues, packet, remote, 0);
+        };
+        debug!("QUIC peer {} closed: {}", remote, reason);
+        quic.remove(remote, &conn).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn binds_with_a_self_signed_certificate() {
+        let (chain, _) = self_signed_identity().unwrap();
+        assert_eq!(chain.len(), 1);
+
+        let listener = QuicListener::bind("127.0.0.1:0".parse().unwrap(), None, None).unwrap();
+        assert!(listener.endpoint.local_addr().unwrap().port() != 0);
+        assert!(listener
+            .connection("127.0.0.1:1".parse().unwrap())
+            .await
+            .is_none());
+
+        let cert = Path::new("cert.pem");
+        assert!(QuicListener::bind("127.0.0.1:0".parse().unwrap(), Some(cert), None).is_err());
+    }
+}
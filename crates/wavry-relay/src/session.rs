@@ -8,12 +8,14 @@
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use rift_crypto::seq_window::SequenceWindow;
 use tokio::sync::RwLock;
 use uuid::Uuid;
+use wavry_common::error::ErrorCode;
 
 /// Session state machine states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,6 +49,11 @@ pub struct PeerState {
     pub last_seen: Instant,
     /// Sequence window for replay protection
     pub seq_window: SequenceWindow,
+    /// Bytes this peer sent that the usage ledger has not been charged yet.
+    /// Atomic so the accounting tick can drain it under a read lock.
+    pub unaccounted_bytes: AtomicU64,
+    /// Set while the peer's user is out of quota; its packets are dropped
+    pub quota_exhausted: AtomicBool,
 }
 
 impl PeerState {
@@ -56,6 +63,8 @@ impl PeerState {
             socket_addr,
             last_seen: Instant::now(),
             seq_window: SequenceWindow::new(),
+            unaccounted_bytes: AtomicU64::new(0),
+            quota_exhausted: AtomicBool::new(false),
         }
     }
 }
@@ -281,11 +290,29 @@ pub enum SessionError {
     InvalidLease,
 }
 
+impl From<&SessionError> for ErrorCode {
+    fn from(err: &SessionError) -> Self {
+        match err {
+            SessionError::LeaseExpired => ErrorCode::LeaseExpired,
+            SessionError::InvalidLease => ErrorCode::LeaseInvalid,
+            SessionError::ReplayDetected => ErrorCode::ReplayDetected,
+            SessionError::RateLimited => ErrorCode::RateLimited,
+            SessionError::SessionFull => ErrorCode::SessionFull,
+            SessionError::SessionNotFound => ErrorCode::NotFound,
+            SessionError::PeerAlreadyRegistered
+            | SessionError::SessionNotActive
+            | SessionError::UnknownPeer => ErrorCode::RelayRejected,
+        }
+    }
+}
+
 /// Session pool managing all active sessions
 #[derive(Debug)]
 pub struct SessionPool {
     sessions: HashMap<Uuid, Arc<RwLock<RelaySession>>>,
     max_sessions: usize,
+    /// Sessions held by this pool and any pool sharing its limit.
+    total_sessions: Arc<AtomicUsize>,
     session_idle_timeout: Duration,
 }
 
@@ -302,11 +329,17 @@ impl CleanupStats {
 }
 
 impl SessionPool {
-    /// Create a new session pool
-    pub fn new(max_sessions: usize, idle_timeout: Duration) -> Self {
+    /// Create a pool that counts its sessions into `total_sessions`, so
+    /// `max_sessions` caps every pool sharing the counter together.
+    pub fn with_shared_limit(
+        max_sessions: usize,
+        idle_timeout: Duration,
+        total_sessions: Arc<AtomicUsize>,
+    ) -> Self {
         Self {
             sessions: HashMap::new(),
             max_sessions,
+            total_sessions,
             session_idle_timeout: idle_timeout,
         }
     }
@@ -318,9 +351,12 @@ impl SessionPool {
         lease_duration: Duration,
     ) -> Result<Arc<RwLock<RelaySession>>, SessionError> {
         if !self.sessions.contains_key(&session_id) {
-            if self.sessions.len() >= self.max_sessions {
-                return Err(SessionError::SessionFull);
-            }
+            let max_sessions = self.max_sessions;
+            self.total_sessions
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |total| {
+                    (total < max_sessions).then_some(total + 1)
+                })
+                .map_err(|_| SessionError::SessionFull)?;
             let session = RelaySession::new(session_id, lease_duration);
             self.sessions
                 .insert(session_id, Arc::new(RwLock::new(session)));
@@ -337,7 +373,11 @@ impl SessionPool {
     /// Remove a session
     #[allow(dead_code)]
     pub fn remove(&mut self, session_id: &Uuid) -> Option<Arc<RwLock<RelaySession>>> {
-        self.sessions.remove(session_id)
+        let removed = self.sessions.remove(session_id);
+        if removed.is_some() {
+            self.total_sessions.fetch_sub(1, Ordering::AcqRel);
+        }
+        removed
     }
 
     /// Clean up expired and idle sessions
@@ -365,11 +405,8 @@ impl SessionPool {
         let expired_count = expired_ids.len();
         let idle_count = idle_ids.len();
 
-        for id in expired_ids {
-            self.sessions.remove(&id);
-        }
-        for id in idle_ids {
-            self.sessions.remove(&id);
+        for id in expired_ids.iter().chain(&idle_ids) {
+            self.remove(id);
         }
 
         CleanupStats {
@@ -378,16 +415,17 @@ impl SessionPool {
         }
     }
 
+    /// Iterate over all sessions
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<RwLock<RelaySession>>> {
+        self.sessions.values()
+    }
+
     /// Get session count
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.sessions.len()
     }
 
-    pub fn max_sessions(&self) -> usize {
-        self.max_sessions
-    }
-
     pub fn contains(&self, session_id: &Uuid) -> bool {
         self.sessions.contains_key(session_id)
     }
@@ -411,6 +449,26 @@ impl SessionPool {
     }
 }
 
+/// Worker shard that owns `session_id`.
+///
+/// Jump consistent hashing (Lamping & Veach), so changing the worker count
+/// only moves about `1/shards` of the sessions.
+pub fn shard_for_session(session_id: &Uuid, shards: usize) -> usize {
+    if shards <= 1 {
+        return 0;
+    }
+    let (high, low) = session_id.as_u64_pair();
+    let mut key = high ^ low;
+    let mut bucket: i64 = -1;
+    let mut next: i64 = 0;
+    while next < shards as i64 {
+        bucket = next;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    bucket as usize
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -424,6 +482,28 @@ mod tests {
         *seed
     }
 
+    #[test]
+    fn shard_for_session_is_stable_and_mostly_keeps_sessions_on_growth() {
+        let ids: Vec<Uuid> = (0..1_000u128)
+            .map(|n| Uuid::from_u128(n * 7919 + 1))
+            .collect();
+        let mut counts = [0usize; 4];
+        let mut moved = 0;
+        for id in &ids {
+            let shard = shard_for_session(id, 4);
+            assert_eq!(shard, shard_for_session(id, 4));
+            assert_eq!(shard_for_session(id, 1), 0);
+            counts[shard] += 1;
+            let grown = shard_for_session(id, 5);
+            if grown != shard {
+                assert_eq!(grown, 4, "growth only moves sessions to the new shard");
+                moved += 1;
+            }
+        }
+        assert!(counts.iter().all(|&count| count > 150));
+        assert!(moved < 300);
+    }
+
     #[test]
     fn register_peer_allows_nat_rebind_for_same_identity() {
         let session_id = Uuid::new_v4();
@@ -482,7 +562,7 @@ mod tests {
 
     #[tokio::test]
     async fn cleanup_reports_expired_and_idle_sessions() {
-        let mut pool = SessionPool::new(8, Duration::from_secs(5));
+        let mut pool = SessionPool::with_shared_limit(8, Duration::from_secs(5), Arc::default());
 
         let expired_id = Uuid::new_v4();
         let expired = pool
@@ -509,6 +589,35 @@ mod tests {
         assert!(pool.is_empty());
     }
 
+    #[tokio::test]
+    async fn shared_limit_caps_sessions_across_pools() {
+        let total = Arc::new(AtomicUsize::new(0));
+        let mut a = SessionPool::with_shared_limit(3, Duration::from_secs(5), total.clone());
+        let mut b = SessionPool::with_shared_limit(3, Duration::from_secs(5), total.clone());
+
+        let first = Uuid::new_v4();
+        a.get_or_create(first, Duration::from_secs(30)).unwrap();
+        a.get_or_create(Uuid::new_v4(), Duration::from_secs(30))
+            .unwrap();
+        b.get_or_create(Uuid::new_v4(), Duration::from_secs(30))
+            .unwrap();
+        assert!(matches!(
+            b.get_or_create(Uuid::new_v4(), Duration::from_secs(30)),
+            Err(SessionError::SessionFull)
+        ));
+        // Existing sessions are still found at the cap.
+        assert!(a.get_or_create(first, Duration::from_secs(30)).is_ok());
+        assert_eq!(total.load(Ordering::Acquire), 3);
+
+        a.remove(&first);
+        assert_eq!(total.load(Ordering::Acquire), 2);
+        b.get_or_create(Uuid::new_v4(), Duration::from_secs(30))
+            .unwrap();
+        // Removing an unknown session leaves the count alone.
+        a.remove(&Uuid::new_v4());
+        assert_eq!(total.load(Ordering::Acquire), 3);
+    }
+
     #[test]
     fn fuzz_session_state_transitions_never_panic() {
         let mut seed = 0xA1B2_C3D4_E5F6_1020u64;
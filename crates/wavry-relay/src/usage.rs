@@ -0,0 +1,219 @@
+//! Per-user traffic accounting across relay sessions.
+//!
+//! Session hard limits cap how fast one session may forward; this ledger caps
+//! how much one user may forward in a UTC day or month, summed over all of
+//! their sessions on this relay. The master pushes quotas down in lease claims
+//! together with the usage it has on record, and the relay reports what it
+//! forwarded back so the next lease, on any relay, starts from the right total.
+//!
+//! Bytes are charged to the peer that sent them.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use wavry_common::protocol::RelayUsageEntry;
+
+/// Quota carried in a lease's claims.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaGrant {
+    pub daily_limit_bytes: Option<u64>,
+    pub monthly_limit_bytes: Option<u64>,
+    /// Usage the master had on record when it signed the lease.
+    pub daily_used_bytes: u64,
+    pub monthly_used_bytes: u64,
+    /// UTC day the lease was signed; its usage totals only apply to that period.
+    pub issued_on: NaiveDate,
+}
+
+#[derive(Debug)]
+struct UserUsage {
+    day: NaiveDate,
+    daily_used: u64,
+    monthly_used: u64,
+    daily_limit: Option<u64>,
+    monthly_limit: Option<u64>,
+    /// Bytes counted here but not yet reported to the master.
+    unreported: u64,
+    last_activity: Instant,
+}
+
+impl UserUsage {
+    fn new(today: NaiveDate) -> Self {
+        Self {
+            day: today,
+            daily_used: 0,
+            monthly_used: 0,
+            daily_limit: None,
+            monthly_limit: None,
+            unreported: 0,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Starts a new day, and a new month if needed, once `today` moves on.
+    fn roll(&mut self, today: NaiveDate) {
+        if today == self.day {
+            return;
+        }
+        if !same_month(today, self.day) {
+            self.monthly_used = 0;
+        }
+        self.daily_used = 0;
+        self.day = today;
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.daily_limit
+            .is_some_and(|limit| self.daily_used >= limit)
+            || self
+                .monthly_limit
+                .is_some_and(|limit| self.monthly_used >= limit)
+    }
+}
+
+fn same_month(a: NaiveDate, b: NaiveDate) -> bool {
+    (a.year(), a.month()) == (b.year(), b.month())
+}
+
+/// Bytes forwarded per wavry_id in the current UTC day and month.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    users: HashMap<String, UserUsage>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn user(&mut self, wavry_id: &str, today: NaiveDate) -> &mut UserUsage {
+        let user = self
+            .users
+            .entry(wavry_id.to_string())
+            .or_insert_with(|| UserUsage::new(today));
+        user.roll(today);
+        user.last_activity = Instant::now();
+        user
+    }
+
+    /// Takes the quota from a freshly validated lease and returns whether the
+    /// user is already out of it. The master's totals lack whatever this relay
+    /// has not reported yet, so those bytes are added on top.
+    pub fn apply_grant(&mut self, wavry_id: &str, grant: &QuotaGrant, now: DateTime<Utc>) -> bool {
+        let today = now.date_naive();
+        let user = self.user(wavry_id, today);
+        user.daily_limit = grant.daily_limit_bytes;
+        user.monthly_limit = grant.monthly_limit_bytes;
+        if grant.issued_on == today {
+            user.daily_used = user
+                .daily_used
+                .max(grant.daily_used_bytes.saturating_add(user.unreported));
+        }
+        if same_month(grant.issued_on, today) {
+            user.monthly_used = user
+                .monthly_used
+                .max(grant.monthly_used_bytes.saturating_add(user.unreported));
+        }
+        user.is_exhausted()
+    }
+
+    /// Charges `bytes` to `wavry_id` and returns whether the user is now out
+    /// of quota.
+    pub fn record(&mut self, wavry_id: &str, bytes: u64, now: DateTime<Utc>) -> bool {
+        let user = self.user(wavry_id, now.date_naive());
+        user.daily_used = user.daily_used.saturating_add(bytes);
+        user.monthly_used = user.monthly_used.saturating_add(bytes);
+        user.unreported = user.unreported.saturating_add(bytes);
+        user.is_exhausted()
+    }
+
+    /// Takes every user's bytes since the last report.
+    pub fn take_unreported(&mut self) -> Vec<RelayUsageEntry> {
+        self.users
+            .iter_mut()
+            .filter(|(_, user)| user.unreported > 0)
+            .map(|(wavry_id, user)| RelayUsageEntry {
+                wavry_id: wavry_id.clone(),
+                bytes: std::mem::take(&mut user.unreported),
+            })
+            .collect()
+    }
+
+    /// Puts back a report the master did not accept, to be sent again.
+    pub fn restore_unreported(&mut self, entries: Vec<RelayUsageEntry>) {
+        let today = Utc::now().date_naive();
+        for entry in entries {
+            let user = self.user(&entry.wavry_id, today);
+            user.unreported = user.unreported.saturating_add(entry.bytes);
+        }
+    }
+
+    /// Forgets users without traffic for `retention` once the master has all
+    /// of their bytes; their next lease carries the totals again.
+    pub fn cleanup(&mut self, retention: Duration) {
+        let now = Instant::now();
+        self.users.retain(|_, user| {
+            user.unreported > 0 || now.duration_since(user.last_activity) < retention
+        });
+    }
+
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    fn grant(issued: DateTime<Utc>, daily_used: u64, monthly_used: u64) -> QuotaGrant {
+        QuotaGrant {
+            daily_limit_bytes: Some(1_000),
+            monthly_limit_bytes: Some(5_000),
+            daily_used_bytes: daily_used,
+            monthly_used_bytes: monthly_used,
+            issued_on: issued.date_naive(),
+        }
+    }
+
+    #[test]
+    fn quotas_sum_sessions_and_reset_with_the_period() {
+        let mut ledger = UsageLedger::new();
+        let day = at(2026, 3, 31);
+        assert!(!ledger.apply_grant("user-a", &grant(day, 400, 4_000), day));
+        assert!(!ledger.record("user-a", 300, day));
+        assert!(ledger.record("user-a", 300, day), "daily quota reached");
+        assert!(!ledger.record("user-b", 10_000, day), "no quota granted");
+
+        let next_day = at(2026, 4, 1);
+        assert!(!ledger.record("user-a", 100, next_day), "new day and month");
+        // A lease signed yesterday does not carry yesterday's totals over.
+        assert!(!ledger.apply_grant("user-a", &grant(day, 900, 4_900), next_day));
+        assert!(ledger.apply_grant("user-a", &grant(next_day, 950, 950), next_day));
+    }
+
+    #[test]
+    fn unreported_bytes_are_added_to_the_masters_totals() {
+        let mut ledger = UsageLedger::new();
+        let day = at(2026, 5, 10);
+        ledger.record("user-a", 600, day);
+        // The master has not heard about the 600 bytes yet.
+        assert!(ledger.apply_grant("user-a", &grant(day, 500, 500), day));
+
+        let report = ledger.take_unreported();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].bytes, 600);
+        assert!(ledger.take_unreported().is_empty());
+
+        ledger.restore_unreported(report);
+        assert_eq!(ledger.take_unreported()[0].bytes, 600);
+        ledger.cleanup(Duration::ZERO);
+        assert_eq!(ledger.user_count(), 0);
+    }
+}
@@ -0,0 +1,487 @@
+//! Client sessions: connect to a host and present its stream.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rift_core::ice::IceCandidate;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time;
+use wavry_client::lease::RelayLeaseSource;
+use wavry_client::{
+    run_client_with_shutdown, ClientConfig, ClientRuntimeStats, ClipboardSyncControl,
+    ClipboardSyncDirection, FileSendRequest, FileTransferCommand, FileTransferEvent,
+    JitterBufferConfig, MonitorSelection, RelayInfo, RendererFactory,
+};
+use wavry_media::{RecorderConfig, Resolution};
+
+use crate::event::{SessionEvent, SessionStats};
+
+/// How often the runtime counters are checked for connect/disconnect edges.
+const CONNECTION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct ClientSessionBuilder {
+    config: ClientConfig,
+    renderer_factory: Option<RendererFactory>,
+}
+
+impl ClientSessionBuilder {
+    pub fn new(client_name: impl Into<String>) -> Self {
+        Self {
+            config: ClientConfig {
+                connect_addr: None,
+                alternate_addrs: Vec::new(),
+                client_name: client_name.into(),
+                no_encrypt: false,
+                identity_key: None,
+                relay_info: None,
+                master_url: None,
+                max_resolution: None,
+                gamepad_enabled: true,
+                gamepad_deadzone: 0.1,
+                input_rate_hz: wavry_client::input::DEFAULT_INPUT_RATE_HZ,
+                vr_adapter: None,
+                runtime_stats: None,
+                recorder_config: None,
+                send_files: Vec::new(),
+                file_out_dir: PathBuf::from("received-files"),
+                file_max_bytes: rift_core::MAX_FILE_TRANSFER_BYTES,
+                file_command_bus: None,
+                file_send_bus: None,
+                file_event_bus: None,
+                clipboard_sync: None,
+                bandwidth_limit_bus: None,
+                host_recording_bus: None,
+                local_recording_bus: None,
+                pointer_mode_bus: None,
+                text_input_bus: None,
+                jitter_buffer: JitterBufferConfig::default(),
+                microphone: false,
+                known_hosts: None,
+                host_name: None,
+                strict_host_key: true,
+                socket: None,
+                remote_candidates: Vec::new(),
+                invite: None,
+                relay_lease_source: None,
+            },
+            renderer_factory: None,
+        }
+    }
+
+    /// Host address to try directly.
+    pub fn connect_addr(mut self, addr: SocketAddr) -> Self {
+        self.config.connect_addr = Some(addr);
+        self
+    }
+
+    /// Another address of the same host, e.g. its IPv6 address; raced
+    /// against `connect_addr`.
+    pub fn alternate_addr(mut self, addr: SocketAddr) -> Self {
+        self.config.alternate_addrs.push(addr);
+        self
+    }
+
+    /// Runs the session on `socket`, typically the one its candidates were
+    /// gathered on before signaling.
+    pub fn socket(mut self, socket: std::net::UdpSocket) -> Self {
+        self.config.socket = Some(Arc::new(socket));
+        self
+    }
+
+    /// The host's candidates from signaling, checked for connectivity before
+    /// the handshake.
+    pub fn remote_candidates(mut self, candidates: Vec<IceCandidate>) -> Self {
+        self.config.remote_candidates = candidates;
+        self
+    }
+
+    /// Invite link from a host; redeemed through its gateway when the
+    /// session starts, in place of `connect_addr`.
+    pub fn invite(mut self, link: impl Into<String>) -> Self {
+        self.config.invite = Some(link.into());
+        self
+    }
+
+    /// Relay to fall back to, or to use alone when there is no direct address.
+    pub fn relay(mut self, relay: RelayInfo) -> Self {
+        self.config.relay_info = Some(relay);
+        self
+    }
+
+    /// Signaling to ask for a new relay lease when the relay stops renewing
+    /// the current one.
+    pub fn relay_lease_source(mut self, source: RelayLeaseSource) -> Self {
+        self.config.relay_lease_source = Some(source);
+        self
+    }
+
+    pub fn identity_key(mut self, key: [u8; 32]) -> Self {
+        self.config.identity_key = Some(key);
+        self
+    }
+
+    /// Master server that receives session feedback for relayed sessions.
+    pub fn master_url(mut self, url: impl Into<String>) -> Self {
+        self.config.master_url = Some(url.into());
+        self
+    }
+
+    pub fn max_resolution(mut self, resolution: Resolution) -> Self {
+        self.config.max_resolution = Some(resolution);
+        self
+    }
+
+    pub fn gamepad(mut self, enabled: bool, deadzone: f32) -> Self {
+        self.config.gamepad_enabled = enabled;
+        self.config.gamepad_deadzone = rift_core::input::normalize_gamepad_deadzone(deadzone);
+        self
+    }
+
+    /// Input packets per second to hosts that take batches; `0` sends each
+    /// event on its own.
+    pub fn input_rate_hz(mut self, rate_hz: u32) -> Self {
+        self.config.input_rate_hz = rate_hz;
+        self
+    }
+
+    /// Local recording settings; see [`ClientSession::set_local_recording`].
+    pub fn recorder(mut self, config: RecorderConfig) -> Self {
+        self.config.recorder_config = Some(config);
+        self
+    }
+
+    pub fn file_out_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.file_out_dir = dir.into();
+        self
+    }
+
+    pub fn file_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.config.file_max_bytes = max_bytes;
+        self
+    }
+
+    /// Initial clipboard sync policy. Without one the session syncs both ways.
+    pub fn clipboard_sync(mut self, direction: ClipboardSyncDirection) -> Self {
+        self.config.clipboard_sync = Some(Arc::new(ClipboardSyncControl::new(direction)));
+        self
+    }
+
+    /// Playout delay for received video; adapts to jitter by default.
+    pub fn jitter_buffer(mut self, config: JitterBufferConfig) -> Self {
+        self.config.jitter_buffer = config;
+        self
+    }
+
+    /// Sends the local microphone to the host for in-game voice chat. Only
+    /// set this with the user's consent; the host must also allow it.
+    pub fn microphone(mut self, enabled: bool) -> Self {
+        self.config.microphone = enabled;
+        self
+    }
+
+    /// Pins the host's identity in this store on first connection and
+    /// checks it on later ones.
+    pub fn known_hosts(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.known_hosts = Some(path.into());
+        self
+    }
+
+    /// Name to pin the host under when connecting through a relay or by a
+    /// signaling username rather than a fixed address.
+    pub fn host_name(mut self, name: impl Into<String>) -> Self {
+        self.config.host_name = Some(name.into());
+        self
+    }
+
+    /// Whether a host whose identity changed is refused (the default) or
+    /// only warned about.
+    pub fn strict_host_key(mut self, strict: bool) -> Self {
+        self.config.strict_host_key = strict;
+        self
+    }
+
+    /// Shares existing counters instead of allocating fresh ones.
+    pub fn runtime_stats(mut self, stats: Arc<ClientRuntimeStats>) -> Self {
+        self.config.runtime_stats = Some(stats);
+        self
+    }
+
+    /// Presents decoded frames; without one the platform default is used.
+    pub fn renderer_factory(mut self, factory: RendererFactory) -> Self {
+        self.renderer_factory = Some(factory);
+        self
+    }
+
+    /// Spawns the session on the current Tokio runtime.
+    pub fn start(self) -> Result<ClientSession> {
+        let Self {
+            mut config,
+            renderer_factory,
+        } = self;
+        if config.connect_addr.is_none()
+            && config.remote_candidates.is_empty()
+            && config.relay_info.is_none()
+        {
+            return Err(anyhow!(
+                "no connection target: set a direct address, candidates or a relay"
+            ));
+        }
+
+        let runtime_stats = config
+            .runtime_stats
+            .get_or_insert_with(|| Arc::new(ClientRuntimeStats::default()))
+            .clone();
+        let clipboard_sync = config
+            .clipboard_sync
+            .get_or_insert_with(|| {
+                Arc::new(ClipboardSyncControl::new(
+                    ClipboardSyncDirection::Bidirectional,
+                ))
+            })
+            .clone();
+        let (file_command_tx, _) = broadcast::channel::<FileTransferCommand>(64);
+        config.file_command_bus = Some(file_command_tx.clone());
+        let (file_send_tx, _) = broadcast::channel::<FileSendRequest>(16);
+        config.file_send_bus = Some(file_send_tx.clone());
+        let (file_event_tx, _) = broadcast::channel::<FileTransferEvent>(256);
+        config.file_event_bus = Some(file_event_tx.clone());
+        let (bandwidth_limit_tx, _) = broadcast::channel::<u32>(8);
+        config.bandwidth_limit_bus = Some(bandwidth_limit_tx.clone());
+        let (host_recording_tx, _) = broadcast::channel::<bool>(8);
+        config.host_recording_bus = Some(host_recording_tx.clone());
+        let (local_recording_tx, _) = broadcast::channel::<bool>(8);
+        config.local_recording_bus = Some(local_recording_tx.clone());
+        let (pointer_mode_tx, _) = broadcast::channel::<bool>(8);
+        config.pointer_mode_bus = Some(pointer_mode_tx.clone());
+        let (text_input_tx, _) = broadcast::channel::<String>(64);
+        config.text_input_bus = Some(text_input_tx.clone());
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let (monitor_tx, monitor_rx) = mpsc::unbounded_channel::<MonitorSelection>();
+        let (events_tx, events_rx) = mpsc::unbounded_channel::<SessionEvent>();
+
+        let stats = runtime_stats.clone();
+        let ended = Arc::new(AtomicBool::new(false));
+        let ended_flag = ended.clone();
+        tokio::spawn(async move {
+            let client =
+                run_client_with_shutdown(config, renderer_factory, stop_rx, Some(monitor_rx));
+            tokio::pin!(client);
+            let mut poll = time::interval(CONNECTION_POLL_INTERVAL);
+            let mut connected = false;
+            let result = loop {
+                tokio::select! {
+                    result = &mut client => break result,
+                    _ = poll.tick() => {
+                        let now = stats.connected.load(Ordering::Relaxed);
+                        if now != connected {
+                            connected = now;
+                            let _ = events_tx.send(if now {
+                                SessionEvent::Connected
+                            } else {
+                                SessionEvent::Disconnected
+                            });
+                        }
+                    }
+                }
+            };
+            ended_flag.store(true, Ordering::Relaxed);
+            let _ = events_tx.send(SessionEvent::Ended {
+                error: result.err().map(|e| format!("{:#}", e)),
+            });
+        });
+
+        Ok(ClientSession {
+            stop_tx: Some(stop_tx),
+            events: Some(events_rx),
+            monitor_tx,
+            ended,
+            runtime_stats,
+            clipboard_sync,
+            file_command_tx,
+            file_send_tx,
+            file_event_tx,
+            bandwidth_limit_tx,
+            host_recording_tx,
+            local_recording_tx,
+            pointer_mode_tx,
+            text_input_tx,
+        })
+    }
+}
+
+/// Handle to a running client session. Dropping it leaves the session
+/// running; call [`ClientSession::stop`] to end it.
+pub struct ClientSession {
+    stop_tx: Option<oneshot::Sender<()>>,
+    events: Option<mpsc::UnboundedReceiver<SessionEvent>>,
+    monitor_tx: mpsc::UnboundedSender<MonitorSelection>,
+    ended: Arc<AtomicBool>,
+    runtime_stats: Arc<ClientRuntimeStats>,
+    clipboard_sync: Arc<ClipboardSyncControl>,
+    file_command_tx: broadcast::Sender<FileTransferCommand>,
+    file_send_tx: broadcast::Sender<FileSendRequest>,
+    file_event_tx: broadcast::Sender<FileTransferEvent>,
+    bandwidth_limit_tx: broadcast::Sender<u32>,
+    host_recording_tx: broadcast::Sender<bool>,
+    local_recording_tx: broadcast::Sender<bool>,
+    pointer_mode_tx: broadcast::Sender<bool>,
+    text_input_tx: broadcast::Sender<String>,
+}
+
+impl ClientSession {
+    pub fn builder(client_name: impl Into<String>) -> ClientSessionBuilder {
+        ClientSessionBuilder::new(client_name)
+    }
+
+    /// Lifecycle events; `None` once taken.
+    pub fn take_events(&mut self) -> Option<mpsc::UnboundedReceiver<SessionEvent>> {
+        self.events.take()
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            connected: !self.ended.load(Ordering::Relaxed)
+                && self.runtime_stats.connected.load(Ordering::Relaxed),
+            frames_decoded: self.runtime_stats.frames_decoded.load(Ordering::Relaxed),
+            latency: self.runtime_stats.latency(),
+            latency_summary: self.runtime_stats.latency_summary(),
+            jitter_buffer_delay_us: self
+                .runtime_stats
+                .jitter_buffer_delay_us
+                .load(Ordering::Relaxed),
+            late_frames_dropped: self
+                .runtime_stats
+                .late_frames_dropped
+                .load(Ordering::Relaxed),
+            ..SessionStats::default()
+        }
+    }
+
+    pub fn runtime_stats(&self) -> &Arc<ClientRuntimeStats> {
+        &self.runtime_stats
+    }
+
+    /// What the host lets this client do; controls for anything else would
+    /// be ignored by the host.
+    pub fn permissions(&self) -> rift_core::PermissionSet {
+        self.runtime_stats.permissions()
+    }
+
+    pub fn clipboard_sync(&self) -> &Arc<ClipboardSyncControl> {
+        &self.clipboard_sync
+    }
+
+    pub fn file_events(&self) -> broadcast::Receiver<FileTransferEvent> {
+        self.file_event_tx.subscribe()
+    }
+
+    /// Queues a local file for sending under `file_id`.
+    pub fn send_file(&self, file_id: u64, path: PathBuf) -> Result<()> {
+        if !self
+            .permissions()
+            .contains(rift_core::PermissionSet::FILE_TRANSFER)
+        {
+            return Err(anyhow!("the host does not allow file transfer"));
+        }
+        send(&self.file_send_tx, FileSendRequest { file_id, path })
+    }
+
+    pub fn file_command(&self, command: FileTransferCommand) -> Result<()> {
+        send(&self.file_command_tx, command)
+    }
+
+    /// Asks the host to keep its target bitrate at or below `kbps`.
+    pub fn set_bandwidth_limit(&self, kbps: u32) -> Result<()> {
+        send(&self.bandwidth_limit_tx, kbps)
+    }
+
+    /// Asks the host to start or stop recording the session on its side.
+    pub fn request_host_recording(&self, start: bool) -> Result<()> {
+        send(&self.host_recording_tx, start)
+    }
+
+    /// Starts or stops recording the received stream with the builder's
+    /// recorder settings.
+    pub fn set_local_recording(&self, enabled: bool) -> Result<()> {
+        send(&self.local_recording_tx, enabled)
+    }
+
+    /// Switches between raw relative mouse input for pointer-locked games
+    /// (`true`) and absolute pointer input.
+    pub fn set_relative_mouse(&self, enabled: bool) -> Result<()> {
+        send(&self.pointer_mode_tx, enabled)
+    }
+
+    /// Sends committed text, e.g. from an IME, for the host to type with its
+    /// own keyboard layout. Games should keep getting raw key events.
+    pub fn send_text(&self, text: impl Into<String>) -> Result<()> {
+        send(&self.text_input_tx, text.into())
+    }
+
+    /// Asks the host to capture another display.
+    pub fn select_monitor(&self, display_id: u32) -> Result<()> {
+        self.select_monitors(display_id, Vec::new())
+    }
+
+    /// Streams `additional` displays alongside `primary`, each to its own
+    /// renderer. The host caps how many it runs at once.
+    pub fn select_monitors(&self, primary: u32, additional: Vec<u32>) -> Result<()> {
+        self.monitor_tx
+            .send(MonitorSelection {
+                primary,
+                additional,
+            })
+            .map_err(|_| anyhow!("client session is no longer running"))
+    }
+
+    /// Returns false when the session was already stopped.
+    pub fn stop(&mut self) -> bool {
+        match self.stop_tx.take() {
+            Some(tx) => {
+                let _ = tx.send(());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn send<T>(tx: &broadcast::Sender<T>, value: T) -> Result<()> {
+    tx.send(value)
+        .map(|_| ())
+        .map_err(|_| anyhow!("client session is no longer running"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_requires_a_target() {
+        assert!(ClientSession::builder("test").start().is_err());
+
+        let builder = ClientSession::builder("test")
+            .connect_addr("192.0.2.1:5000".parse().unwrap())
+            .gamepad(true, 2.0)
+            .clipboard_sync(ClipboardSyncDirection::Disabled);
+        assert_eq!(builder.config.gamepad_deadzone, 0.95);
+        let builder = ClientSession::builder("test").gamepad(true, f32::NAN);
+        assert_eq!(
+            builder.config.gamepad_deadzone,
+            rift_core::input::DEFAULT_GAMEPAD_DEADZONE
+        );
+        assert_eq!(
+            builder
+                .config
+                .clipboard_sync
+                .as_ref()
+                .map(|control| control.direction()),
+            Some(ClipboardSyncDirection::Disabled)
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use std::net::SocketAddr;
+
+use rift_crypto::WavryId;
+use wavry_client::{LatencyBreakdown, LatencySummary};
+
+/// Lifecycle changes of a running session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// The handshake with the peer completed and media is flowing.
+    Connected,
+    /// The peer went silent; the session keeps waiting for it.
+    Disconnected,
+    /// The session loop exited. Always the last event.
+    Ended { error: Option<String> },
+    /// A device the host has no decision for asked to connect. Its Hello is
+    /// held until the decision arrives; hosts only.
+    PendingApproval {
+        wavry_id: WavryId,
+        client_name: String,
+        addr: SocketAddr,
+    },
+}
+
+/// Point-in-time counters of a session. Fields a role does not track stay
+/// zero: hosts never decode, clients never encode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    pub connected: bool,
+    pub fps: u32,
+    pub rtt_ms: u32,
+    pub bitrate_kbps: u32,
+    pub frames_encoded: u64,
+    pub frames_decoded: u64,
+    /// Breakdown for the most recently presented frame; clients only.
+    pub latency: Option<LatencyBreakdown>,
+    /// Per-stage percentiles over the last stats period; clients only.
+    pub latency_summary: Option<LatencySummary>,
+    /// Current playout delay of the jitter buffer; clients only.
+    pub jitter_buffer_delay_us: u64,
+    /// Frames dropped for arriving too late to be shown; clients only.
+    pub late_frames_dropped: u64,
+}
@@ -0,0 +1,539 @@
+//! Host sessions: capture this machine and stream it to a single client.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rift_core::cc::DeltaState;
+use rift_crypto::{AuthorizedClients, ClientDecision, WavryId};
+use tokio::sync::{mpsc, oneshot};
+use wavry_media::{CaptureMode, Codec, ContentType, EncodeConfig, EncoderTuning, Resolution};
+
+use crate::event::{SessionEvent, SessionStats};
+
+mod host_loop;
+mod source;
+
+pub use host_loop::HostLoop;
+pub use source::{AudioSource, ThreadedAudioSource, VideoSource};
+
+/// Counters a [`HostLoop`] updates while it runs.
+#[derive(Debug, Default)]
+pub struct HostCounters {
+    connected: AtomicBool,
+    fps: AtomicU32,
+    rtt_ms: AtomicU32,
+    bitrate_kbps: AtomicU32,
+    frames_encoded: AtomicU64,
+    target_bitrate_kbps: AtomicU32,
+    cc_state: Mutex<Option<DeltaState>>,
+}
+
+impl HostCounters {
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            connected: self.connected.load(Ordering::Relaxed),
+            fps: self.fps.load(Ordering::Relaxed),
+            rtt_ms: self.rtt_ms.load(Ordering::Relaxed),
+            bitrate_kbps: self.bitrate_kbps.load(Ordering::Relaxed),
+            frames_encoded: self.frames_encoded.load(Ordering::Relaxed),
+            ..SessionStats::default()
+        }
+    }
+
+    /// Bitrate congestion control currently asks the encoder for.
+    pub fn target_bitrate_kbps(&self) -> u32 {
+        self.target_bitrate_kbps.load(Ordering::Relaxed)
+    }
+
+    /// DELTA state after the latest congestion signal; `None` before one.
+    pub fn cc_state(&self) -> Option<DeltaState> {
+        *self.cc_state.lock().unwrap()
+    }
+}
+
+/// Host-side limits on a client's session. Everything is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionPolicy {
+    /// The session ends after this long, and the client is told not to
+    /// resume it.
+    pub max_session: Option<Duration>,
+    /// Streaming pauses after this long without client input and resumes
+    /// with the next input.
+    pub idle_timeout: Option<Duration>,
+    /// Lock this machine's desktop session when the client disconnects.
+    pub lock_on_disconnect: bool,
+}
+
+pub struct HostSessionBuilder {
+    port: u16,
+    config: EncodeConfig,
+    content: ContentType,
+    tuning: Option<EncoderTuning>,
+    allow_microphone: bool,
+    authorized: Option<AuthorizedClients>,
+    policy: SessionPolicy,
+}
+
+impl HostSessionBuilder {
+    /// Port 0 binds an ephemeral port; see [`HostSession::port`].
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            config: EncodeConfig {
+                codec: Codec::H264,
+                resolution: Resolution {
+                    width: 1920,
+                    height: 1080,
+                },
+                fps: 60,
+                bitrate_kbps: 8000,
+                keyframe_interval_ms: 2000,
+                display_id: None,
+                enable_10bit: false,
+                enable_hdr: false,
+                hide_cursor: false,
+                capture_mode: CaptureMode::Fixed,
+                tuning: EncoderTuning::default(),
+            },
+            content: ContentType::default(),
+            tuning: None,
+            allow_microphone: false,
+            authorized: None,
+            policy: SessionPolicy::default(),
+        }
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.config.codec = codec;
+        self
+    }
+
+    pub fn resolution(mut self, resolution: Resolution) -> Self {
+        self.config.resolution = resolution;
+        self
+    }
+
+    pub fn fps(mut self, fps: u16) -> Self {
+        self.config.fps = fps;
+        self
+    }
+
+    /// Starting bitrate; congestion control moves it from there.
+    pub fn bitrate_kbps(mut self, kbps: u32) -> Self {
+        self.config.bitrate_kbps = kbps;
+        self
+    }
+
+    pub fn keyframe_interval_ms(mut self, interval_ms: u32) -> Self {
+        self.config.keyframe_interval_ms = interval_ms;
+        self
+    }
+
+    /// Display to capture; the primary display when unset.
+    pub fn display_id(mut self, display_id: u32) -> Self {
+        self.config.display_id = Some(display_id);
+        self
+    }
+
+    /// Encode only when the screen changes, dropping to a keepalive rate
+    /// while it is idle.
+    pub fn capture_mode(mut self, mode: CaptureMode) -> Self {
+        self.config.capture_mode = mode;
+        self
+    }
+
+    /// What is being streamed; picks encoder tuning defaults for the codec.
+    pub fn content(mut self, content: ContentType) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Replaces the defaults `content` would pick.
+    pub fn tuning(mut self, tuning: EncoderTuning) -> Self {
+        self.tuning = Some(tuning);
+        self
+    }
+
+    /// Lets a client that asks play its microphone into a virtual input on
+    /// this machine, for voice chat in the streamed app.
+    pub fn allow_microphone(mut self, allow: bool) -> Self {
+        self.allow_microphone = allow;
+        self
+    }
+
+    /// Admits only devices allowed in `store`. Others raise
+    /// [`SessionEvent::PendingApproval`] and wait for
+    /// [`HostSession::decide`].
+    pub fn authorized_clients(mut self, store: AuthorizedClients) -> Self {
+        self.authorized = Some(store);
+        self
+    }
+
+    /// Time limits for the client's session, and whether to lock this
+    /// machine once it leaves.
+    pub fn session_policy(mut self, policy: SessionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    fn encode_config(&self) -> EncodeConfig {
+        EncodeConfig {
+            tuning: self
+                .tuning
+                .unwrap_or_else(|| EncoderTuning::for_codec(self.config.codec, self.content)),
+            ..self.config
+        }
+    }
+
+    /// Binds the socket and opens capture, then streams in the background on
+    /// the current Tokio runtime. Fails if either step fails.
+    pub async fn start(self) -> Result<HostSession> {
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let (init_tx, init_rx) = oneshot::channel::<Result<(u16, Arc<HostCounters>)>>();
+        let (events_tx, events_rx) = mpsc::unbounded_channel::<SessionEvent>();
+        let (decisions_tx, authorization) = match self.authorized {
+            Some(store) => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                (Some(tx), Some((store, rx)))
+            }
+            None => (None, None),
+        };
+
+        let config = self.encode_config();
+        tokio::spawn(async move {
+            let result = run_host(
+                self.port,
+                config,
+                self.allow_microphone,
+                authorization,
+                self.policy,
+                events_tx.clone(),
+                stop_rx,
+                init_tx,
+            )
+            .await;
+            let _ = events_tx.send(SessionEvent::Ended {
+                error: result.err().map(|e| format!("{:#}", e)),
+            });
+        });
+
+        let (port, counters) = init_rx
+            .await
+            .map_err(|_| anyhow!("host initialization channel closed"))??;
+        Ok(HostSession {
+            port,
+            stop_tx: Some(stop_tx),
+            events: Some(events_rx),
+            decisions_tx,
+            counters,
+        })
+    }
+}
+
+/// Handle to a running host session. Dropping it leaves the session
+/// running; call [`HostSession::stop`] to end it.
+pub struct HostSession {
+    port: u16,
+    stop_tx: Option<oneshot::Sender<()>>,
+    events: Option<mpsc::UnboundedReceiver<SessionEvent>>,
+    decisions_tx: Option<mpsc::UnboundedSender<(WavryId, ClientDecision)>>,
+    counters: Arc<HostCounters>,
+}
+
+impl HostSession {
+    pub fn builder(port: u16) -> HostSessionBuilder {
+        HostSessionBuilder::new(port)
+    }
+
+    /// UDP port the session is bound to.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Lifecycle events; `None` once taken.
+    pub fn take_events(&mut self) -> Option<mpsc::UnboundedReceiver<SessionEvent>> {
+        self.events.take()
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        self.counters.stats()
+    }
+
+    /// Answers a [`SessionEvent::PendingApproval`]; the decision is
+    /// remembered for later sessions. Returns false without
+    /// [`HostSessionBuilder::authorized_clients`] or once the session ended.
+    pub fn decide(&self, wavry_id: WavryId, decision: ClientDecision) -> bool {
+        self.decisions_tx
+            .as_ref()
+            .is_some_and(|tx| tx.send((wavry_id, decision)).is_ok())
+    }
+
+    /// Returns false when the session was already stopped.
+    pub fn stop(&mut self) -> bool {
+        match self.stop_tx.take() {
+            Some(tx) => {
+                let _ = tx.send(());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+async fn run_host(
+    port: u16,
+    config: EncodeConfig,
+    allow_microphone: bool,
+    authorization: Option<(
+        AuthorizedClients,
+        mpsc::UnboundedReceiver<(WavryId, ClientDecision)>,
+    )>,
+    policy: SessionPolicy,
+    events: mpsc::UnboundedSender<SessionEvent>,
+    mut stop_rx: oneshot::Receiver<()>,
+    init_tx: oneshot::Sender<Result<(u16, Arc<HostCounters>)>>,
+) -> Result<()> {
+    use wavry_media::CapabilityProbe;
+
+    let opened = async {
+        let socket = wavry_client::net::bind_udp(wavry_client::net::dual_stack_any(port))
+            .map_err(|e| anyhow!("Failed to bind UDP: {}", e))?;
+        let bound_port = socket.local_addr().map(|addr| addr.port()).unwrap_or(port);
+        let mut host_loop = open_host_loop(Arc::new(socket), config).await?;
+        if allow_microphone {
+            match open_virtual_microphone() {
+                Ok(sink) => host_loop = host_loop.microphone(sink),
+                Err(e) => log::warn!("Client microphone passthrough unavailable: {}", e),
+            }
+        }
+        if let Some((store, decisions)) = authorization {
+            host_loop = host_loop.authorization(store, decisions);
+        }
+        match wavry_platform::ArboardClipboard::new() {
+            Ok(clipboard) => host_loop = host_loop.clipboard(Box::new(clipboard)),
+            Err(e) => log::warn!("Clipboard sync unavailable: {}", e),
+        }
+        Ok::<_, anyhow::Error>((bound_port, host_loop))
+    }
+    .await;
+    let (bound_port, host_loop) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            let _ = init_tx.send(Err(anyhow!("{:#}", e)));
+            return Err(e);
+        }
+    };
+    log::info!(
+        "Host listening on UDP {} (requested port {})",
+        bound_port,
+        port
+    );
+
+    let mut host_loop = host_loop.session_policy(policy).events(events);
+    let counters = host_loop.counters();
+    let _ = init_tx.send(Ok((bound_port, counters.clone())));
+
+    let mut display_watch = match display_probe().watch_displays() {
+        Ok(watch) => Some(watch),
+        Err(e) => {
+            log::info!("Display hot-plug detection unavailable: {}", e);
+            None
+        }
+    };
+    let result = loop {
+        tokio::select! {
+            result = host_loop.run(&mut stop_rx) => break result,
+            Some(()) = async {
+                match display_watch.as_mut() {
+                    Some(watch) => watch.changed().await,
+                    None => None,
+                }
+            } => follow_display(&mut host_loop).await,
+        }
+    };
+    counters.connected.store(false, Ordering::Relaxed);
+    result
+}
+
+/// Moves capture to another display when the one being captured was
+/// unplugged.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+async fn follow_display(host_loop: &mut HostLoop<PlatformVideo, PlatformAudio>) {
+    use wavry_media::CapabilityProbe;
+
+    let displays = match tokio::task::spawn_blocking(|| display_probe().enumerate_displays()).await
+    {
+        Ok(Ok(displays)) => displays,
+        Ok(Err(e)) => {
+            log::warn!("Failed to list displays after a layout change: {}", e);
+            return;
+        }
+        Err(e) => {
+            log::warn!("Display enumeration task failed: {}", e);
+            return;
+        }
+    };
+    let mut config = *host_loop.config();
+    let Some(display) = config
+        .display_id
+        .and_then(|current| wavry_media::replacement_display(&displays, current))
+    else {
+        log::info!("Display layout changed: {} display(s)", displays.len());
+        return;
+    };
+    log::warn!(
+        "Captured display disappeared; switching capture to {} ({})",
+        display.id,
+        display.name
+    );
+    config.display_id = Some(display.id);
+    match open_video(config).await {
+        Ok(video) => host_loop.replace_video(video, config),
+        Err(e) => log::warn!("Failed to capture display {}: {:#}", display.id, e),
+    }
+}
+
+#[cfg(target_os = "macos")]
+type PlatformVideo = wavry_media::MacScreenEncoder;
+#[cfg(target_os = "macos")]
+type PlatformAudio = wavry_media::MacAudioCapturer;
+#[cfg(target_os = "linux")]
+type PlatformVideo = wavry_media::PipewireEncoder;
+#[cfg(target_os = "linux")]
+type PlatformAudio = ThreadedAudioSource;
+
+#[cfg(target_os = "macos")]
+fn display_probe() -> wavry_media::MacProbe {
+    wavry_media::MacProbe
+}
+
+#[cfg(target_os = "linux")]
+fn display_probe() -> wavry_media::LinuxProbe {
+    wavry_media::LinuxProbe
+}
+
+#[cfg(target_os = "macos")]
+async fn open_video(config: EncodeConfig) -> Result<PlatformVideo> {
+    wavry_media::MacScreenEncoder::new(config)
+        .await
+        .map_err(|e| anyhow!("Failed to create encoder: {}", e))
+}
+
+#[cfg(target_os = "linux")]
+async fn open_video(config: EncodeConfig) -> Result<PlatformVideo> {
+    wavry_media::PipewireEncoder::new(config)
+        .await
+        .map_err(|e| anyhow!("Failed to create encoder: {}", e))
+}
+
+#[cfg(target_os = "macos")]
+async fn open_host_loop(
+    socket: Arc<tokio::net::UdpSocket>,
+    config: EncodeConfig,
+) -> Result<HostLoop<PlatformVideo, PlatformAudio>> {
+    use wavry_media::MacAudioCapturer;
+
+    let host_loop = HostLoop::new(socket, config, open_video(config).await?);
+    // Audio is optional; stream video alone without it.
+    match MacAudioCapturer::new().await {
+        Ok(capturer) => Ok(host_loop.audio(capturer)),
+        Err(e) => {
+            log::warn!("Failed to create audio capturer: {}", e);
+            Ok(host_loop)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn open_host_loop(
+    socket: Arc<tokio::net::UdpSocket>,
+    config: EncodeConfig,
+) -> Result<HostLoop<PlatformVideo, PlatformAudio>> {
+    use wavry_media::PipewireAudioCapturer;
+
+    let host_loop = HostLoop::new(socket, config, open_video(config).await?);
+    // Audio is optional; stream video alone without it.
+    match PipewireAudioCapturer::new().await {
+        Ok(mut capturer) => {
+            let audio = ThreadedAudioSource::spawn(move || Ok(capturer.next_packet()?))?;
+            Ok(host_loop.audio(audio))
+        }
+        Err(e) => {
+            log::warn!("Failed to create audio capturer: {}", e);
+            Ok(host_loop)
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn open_virtual_microphone() -> Result<Box<dyn wavry_media::Renderer + Send>> {
+    Ok(Box::new(wavry_media::MacVirtualMicrophone::new()?))
+}
+
+#[cfg(target_os = "linux")]
+fn open_virtual_microphone() -> Result<Box<dyn wavry_media::Renderer + Send>> {
+    Ok(Box::new(wavry_media::PipewireVirtualMicrophone::new()?))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+async fn run_host(
+    _port: u16,
+    _config: EncodeConfig,
+    _allow_microphone: bool,
+    _authorization: Option<(
+        AuthorizedClients,
+        mpsc::UnboundedReceiver<(WavryId, ClientDecision)>,
+    )>,
+    _policy: SessionPolicy,
+    _events: mpsc::UnboundedSender<SessionEvent>,
+    _stop_rx: oneshot::Receiver<()>,
+    init_tx: oneshot::Sender<Result<(u16, Arc<HostCounters>)>>,
+) -> Result<()> {
+    let _ = init_tx.send(Err(anyhow!("Hosting is not supported on this platform")));
+    anyhow::bail!("Hosting is not supported on this platform");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_overrides_defaults() {
+        let builder = HostSession::builder(0)
+            .codec(Codec::Hevc)
+            .fps(120)
+            .display_id(2);
+        assert_eq!(builder.config.codec, Codec::Hevc);
+        assert_eq!(builder.config.fps, 120);
+        assert_eq!(builder.config.display_id, Some(2));
+        assert_eq!(builder.config.bitrate_kbps, 8000);
+    }
+
+    #[test]
+    fn tuning_follows_codec_and_content_unless_overridden() {
+        let builder = HostSession::builder(0)
+            .content(ContentType::Desktop)
+            .codec(Codec::Av1);
+        assert_eq!(
+            builder.encode_config().tuning,
+            EncoderTuning::for_codec(Codec::Av1, ContentType::Desktop)
+        );
+
+        let tuning = EncoderTuning {
+            slices: 8,
+            ..EncoderTuning::default()
+        };
+        let builder = builder.tuning(tuning);
+        assert_eq!(builder.encode_config().tuning, tuning);
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    #[tokio::test]
+    async fn start_reports_unsupported_platforms() {
+        let err = HostSession::builder(0).start().await.err().unwrap();
+        assert!(err.to_string().contains("not supported"));
+    }
+}
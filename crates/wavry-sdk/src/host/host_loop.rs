@@ -0,0 +1,1192 @@
+//! The host send/receive loop shared by every platform: a single client,
+//! Noise encryption, DELTA congestion control with bandwidth probing, FEC
+//! and NACK retransmission.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use rift_core::cc::{DeltaCC, DeltaConfig};
+use rift_core::compact::{self, CompactDecoder, CompactEncoder};
+use rift_core::feedback::SendTimes;
+use rift_core::probe::ProbeSender;
+use rift_core::{
+    chunk_video_payload, decode_msg, encode_msg, message_channel, Channel, Codec as RiftCodec,
+    CongestionControl as ProtoCongestion, ControlMessage as ProtoControl, FecBuilder, FecScheme,
+    Handshake, Hello as ProtoHello, HelloAck as ProtoHelloAck, Message as ProtoMessage,
+    PhysicalPacket, Pong as ProtoPong, Resolution as ProtoResolution, Role, RIFT_MAGIC,
+};
+use rift_crypto::connection::SecureServer;
+use rift_crypto::{AuthorizedClients, ClientDecision, SecureChannel, WavryId};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+use wavry_media::{Codec, EncodeConfig, EncodedFrame, Renderer};
+use wavry_platform::Clipboard;
+
+use super::source::{AudioSource, VideoSource};
+use super::{HostCounters, SessionPolicy};
+use crate::event::SessionEvent;
+
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_DATAGRAM_SIZE: usize = 1200;
+const NACK_HISTORY: usize = 512;
+const PACER_MIN_US: u64 = 20;
+const PACER_MAX_US: u64 = 500;
+const PACER_BASE_US: f64 = 30.0;
+/// How often an in-flight probe burst is topped up.
+const PROBE_TICK: Duration = Duration::from_millis(5);
+/// FEC ratio change that rebuilds the builder.
+const FEC_RATIO_STEP: f32 = 0.01;
+/// How often the session policy is checked.
+const POLICY_TICK: Duration = Duration::from_secs(1);
+/// How often this machine's clipboard is checked for a change to send.
+const CLIPBOARD_POLL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+struct SendHistory {
+    capacity: usize,
+    order: VecDeque<u64>,
+    packets: BTreeMap<u64, Bytes>,
+}
+
+impl SendHistory {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            packets: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, packet_id: u64, payload: Bytes) {
+        if !self.packets.contains_key(&packet_id) {
+            self.order.push_back(packet_id);
+        }
+        self.packets.insert(packet_id, payload);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.packets.remove(&oldest);
+            }
+        }
+    }
+
+    fn get(&self, packet_id: u64) -> Option<Bytes> {
+        self.packets.get(&packet_id).cloned()
+    }
+}
+
+#[derive(Debug)]
+struct Pacer {
+    next_send: time::Instant,
+    interval_us: u64,
+    rtt_smooth_us: f64,
+    rtt_min_us: u64,
+    jitter_smooth_us: f64,
+    last_packet_bytes: usize,
+}
+
+impl Pacer {
+    fn new() -> Self {
+        Self {
+            next_send: time::Instant::now(),
+            interval_us: PACER_BASE_US as u64,
+            rtt_smooth_us: 0.0,
+            rtt_min_us: u64::MAX,
+            jitter_smooth_us: 0.0,
+            last_packet_bytes: 1200,
+        }
+    }
+
+    fn on_stats(&mut self, rtt_us: u64, jitter_us: u32, bitrate_kbps: u32) {
+        if self.rtt_smooth_us == 0.0 {
+            self.rtt_smooth_us = rtt_us as f64;
+        } else {
+            self.rtt_smooth_us = 0.875 * self.rtt_smooth_us + 0.125 * (rtt_us as f64);
+        }
+        self.rtt_min_us = self.rtt_min_us.min(rtt_us);
+        if self.jitter_smooth_us == 0.0 {
+            self.jitter_smooth_us = jitter_us as f64;
+        } else {
+            self.jitter_smooth_us = 0.75 * self.jitter_smooth_us + 0.25 * (jitter_us as f64);
+        }
+        self.recompute_interval(bitrate_kbps);
+    }
+
+    fn note_packet_bytes(&mut self, bytes: usize, bitrate_kbps: u32) {
+        self.last_packet_bytes = bytes.max(1);
+        self.recompute_interval(bitrate_kbps);
+    }
+
+    fn recompute_interval(&mut self, bitrate_kbps: u32) {
+        let bitrate_factor = (20_000.0 / bitrate_kbps.max(1) as f64).clamp(0.5, 2.0);
+        let size_factor = (self.last_packet_bytes as f64 / 1200.0).clamp(0.5, 2.0);
+        let base_interval = PACER_BASE_US * bitrate_factor * size_factor;
+
+        let rtt_base = if self.rtt_min_us == u64::MAX {
+            self.rtt_smooth_us.max(1.0)
+        } else {
+            self.rtt_min_us as f64
+        };
+        let rtt_increase = ((self.rtt_smooth_us - rtt_base).max(0.0) / rtt_base).clamp(0.0, 2.0);
+        let jitter_norm = (self.jitter_smooth_us / 2000.0).clamp(0.0, 3.0);
+
+        let mut congestion = 1.0 + rtt_increase * 1.5 + jitter_norm * 0.5;
+        if rtt_increase < 0.02 && jitter_norm < 0.2 {
+            congestion *= 0.8;
+        }
+
+        let interval =
+            (base_interval * congestion).clamp(PACER_MIN_US as f64, PACER_MAX_US as f64) as u64;
+        self.interval_us = interval.max(PACER_MIN_US);
+    }
+
+    async fn wait(&mut self) {
+        let now = time::Instant::now();
+        if self.next_send <= now {
+            self.next_send = now;
+        }
+        let target = self.next_send;
+        self.next_send += Duration::from_micros(self.interval_us);
+        time::sleep_until(target).await;
+    }
+}
+
+struct PeerState {
+    session_alias: u32,
+    /// Noise session with the client over the host's shared socket.
+    channel: SecureChannel<Arc<UdpSocket>>,
+    handshake: Handshake,
+    frame_id: u64,
+    send_history: SendHistory,
+    pacer: Pacer,
+    fec_builder: FecBuilder,
+    fec_ratio: f32,
+    probe: Option<ProbeSender>,
+    /// Set once the HelloAck agreed on compact transport headers.
+    compact_tx: Option<CompactEncoder>,
+    compact_rx: CompactDecoder,
+    /// Set once the HelloAck agreed on transport-wide feedback.
+    send_times: Option<SendTimes>,
+    /// Both sides agreed in the HelloAck to microphone passthrough.
+    microphone: bool,
+    /// Ways the client's user lets the clipboard sync, from its Hello and
+    /// later `ClipboardSync` updates.
+    clipboard_sync: rift_core::ClipboardSync,
+    /// Video resumes at the next keyframe after an idle pause.
+    awaiting_keyframe: bool,
+}
+
+impl PeerState {
+    fn new(socket: Arc<UdpSocket>, peer: SocketAddr, fec_ratio: f32) -> Result<Self> {
+        let crypto = SecureServer::new().map_err(|e| anyhow!("crypto init failed: {}", e))?;
+        let session_alias = rand::random::<u32>().max(1);
+        let mut channel = SecureChannel::responder(socket, peer, crypto);
+        channel.set_session_alias(session_alias);
+        Ok(Self {
+            session_alias,
+            channel,
+            handshake: Handshake::new(Role::Host),
+            frame_id: 0,
+            send_history: SendHistory::new(NACK_HISTORY),
+            pacer: Pacer::new(),
+            fec_builder: FecBuilder::for_ratio(FecScheme::Xor, fec_ratio),
+            fec_ratio,
+            probe: None,
+            compact_tx: None,
+            compact_rx: CompactDecoder::default(),
+            send_times: None,
+            microphone: false,
+            clipboard_sync: rift_core::ClipboardSync::default(),
+            awaiting_keyframe: false,
+        })
+    }
+
+    /// Media may flow once both the Noise and RIFT handshakes are done.
+    fn is_ready(&self) -> bool {
+        self.channel.is_established()
+            && matches!(
+                self.handshake.state(),
+                rift_core::HandshakeState::Established { .. }
+            )
+    }
+
+    /// Rebuilds the FEC builder when the negotiated scheme or the congestion
+    /// controller's redundancy target changed.
+    fn update_fec(&mut self, scheme: FecScheme, ratio: f32) {
+        if scheme != self.fec_builder.scheme() || (ratio - self.fec_ratio).abs() > FEC_RATIO_STEP {
+            self.fec_builder = FecBuilder::for_ratio(scheme, ratio);
+            self.fec_ratio = ratio;
+        }
+    }
+}
+
+/// Lower the DeltaCC ceiling (and floor, if needed) to a user bandwidth limit.
+fn apply_bandwidth_limit(mut config: DeltaConfig, limit_kbps: Option<u32>) -> DeltaConfig {
+    if let Some(limit) = limit_kbps {
+        config.max_bitrate_kbps = config.max_bitrate_kbps.min(limit);
+        config.min_bitrate_kbps = config.min_bitrate_kbps.min(config.max_bitrate_kbps);
+    }
+    config
+}
+
+fn select_codec_for_hello(hello: &ProtoHello, encoder_codec: Codec) -> Option<RiftCodec> {
+    let desired = match encoder_codec {
+        Codec::Av1 => RiftCodec::Av1,
+        Codec::Hevc => RiftCodec::Hevc,
+        Codec::H264 => RiftCodec::H264,
+    };
+    if hello.supported_codecs.contains(&(desired as i32)) {
+        Some(desired)
+    } else {
+        None
+    }
+}
+
+fn stream_resolution_from_config(config: &EncodeConfig) -> ProtoResolution {
+    ProtoResolution {
+        width: config.resolution.width as u32,
+        height: config.resolution.height as u32,
+    }
+}
+
+fn media_msg(content: rift_core::media_message::Content) -> ProtoMessage {
+    ProtoMessage {
+        content: Some(rift_core::message::Content::Media(
+            rift_core::MediaMessage {
+                content: Some(content),
+            },
+        )),
+    }
+}
+
+fn control_msg(content: rift_core::control_message::Content) -> ProtoMessage {
+    ProtoMessage {
+        content: Some(rift_core::message::Content::Control(ProtoControl {
+            content: Some(content),
+        })),
+    }
+}
+
+/// Encrypts `plaintext` under the next packet id and returns both.
+fn seal(peer_state: &mut PeerState, plaintext: &[u8], channel: Channel) -> Result<(u64, Bytes)> {
+    let phys = peer_state.channel.seal(plaintext)?;
+    let packet_id = phys.packet_id;
+    let bytes = match peer_state.compact_tx.as_mut() {
+        Some(encoder) => encoder.encode(&phys, channel),
+        None => phys.encode(),
+    };
+    if let Some(send_times) = peer_state.send_times.as_mut() {
+        send_times.on_sent(packet_id, bytes.len(), Instant::now());
+    }
+    Ok((packet_id, bytes))
+}
+
+/// Sends `msg` and keeps it for NACK retransmission.
+async fn send_rift_msg(
+    socket: &UdpSocket,
+    peer_state: &mut PeerState,
+    peer: SocketAddr,
+    msg: ProtoMessage,
+) -> Result<u64> {
+    let (packet_id, bytes) = seal(peer_state, &encode_msg(&msg), message_channel(&msg))?;
+    peer_state.send_history.insert(packet_id, bytes.clone());
+    socket.send_to(&bytes, peer).await?;
+    Ok(packet_id)
+}
+
+/// Sends `msg` without keeping it; for FEC parity and probe padding, which
+/// are worthless once late.
+async fn send_unrecorded(
+    socket: &UdpSocket,
+    peer_state: &mut PeerState,
+    peer: SocketAddr,
+    msg: ProtoMessage,
+) -> Result<()> {
+    let (_, bytes) = seal(peer_state, &encode_msg(&msg), message_channel(&msg))?;
+    socket.send_to(&bytes, peer).await?;
+    Ok(())
+}
+
+async fn send_video_frame(
+    socket: &UdpSocket,
+    peer_state: &mut PeerState,
+    peer: SocketAddr,
+    frame: EncodedFrame,
+    bitrate_kbps: u32,
+) -> Result<()> {
+    let chunks = chunk_video_payload(
+        peer_state.frame_id,
+        frame.timestamp_us,
+        frame.keyframe,
+        &frame.data,
+        MAX_DATAGRAM_SIZE,
+        frame.capture_duration_us,
+        frame.encode_duration_us,
+    )
+    .map_err(|e| anyhow!("chunking error: {}", e))?;
+    peer_state.frame_id = peer_state.frame_id.wrapping_add(1);
+
+    for chunk in chunks {
+        let packet_bytes = chunk.payload.len() + 64;
+        let plaintext = encode_msg(&media_msg(rift_core::media_message::Content::Video(chunk)));
+        peer_state
+            .pacer
+            .note_packet_bytes(packet_bytes, bitrate_kbps);
+        peer_state.pacer.wait().await;
+
+        let (packet_id, bytes) = seal(peer_state, &plaintext, Channel::Media)?;
+        peer_state.send_history.insert(packet_id, bytes.clone());
+        socket.send_to(&bytes, peer).await?;
+
+        // The client rebuilds lost chunks from the plaintext it did receive.
+        for fec in peer_state.fec_builder.push(packet_id, &plaintext) {
+            send_unrecorded(
+                socket,
+                peer_state,
+                peer,
+                media_msg(rift_core::media_message::Content::Fec(fec)),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn send_audio_packet(
+    socket: &UdpSocket,
+    peer_state: &mut PeerState,
+    peer: SocketAddr,
+    packet: EncodedFrame,
+) -> Result<()> {
+    let msg = media_msg(rift_core::media_message::Content::Audio(
+        rift_core::AudioPacket {
+            timestamp_us: packet.timestamp_us,
+            payload: packet.data,
+            layout: rift_core::AudioLayout::AudioStereo as i32,
+            direction: rift_core::AudioDirection::AudioFromHost as i32,
+        },
+    ));
+    send_rift_msg(socket, peer_state, peer, msg).await?;
+    Ok(())
+}
+
+/// Streams a [`VideoSource`] and optional [`AudioSource`] to one client.
+///
+/// The loop outlives individual [`run`](Self::run) calls: after a capture
+/// error the caller can hand in a fresh source with
+/// [`replace_video`](Self::replace_video) and run again without dropping
+/// the connected client.
+pub struct HostLoop<V, A> {
+    socket: Arc<UdpSocket>,
+    config: EncodeConfig,
+    video: V,
+    audio: Option<A>,
+    microphone: Option<Box<dyn Renderer + Send>>,
+    clipboard: Option<Box<dyn Clipboard>>,
+    /// Clipboard text last sent or applied, so neither side echoes it back.
+    last_clipboard_text: Option<String>,
+    /// Remembered device decisions; `None` admits every client.
+    authorized: Option<AuthorizedClients>,
+    decisions_rx: Option<mpsc::UnboundedReceiver<(WavryId, ClientDecision)>>,
+    /// Device announced with [`SessionEvent::PendingApproval`], and its name.
+    pending_approval: Option<(WavryId, String)>,
+    counters: Arc<HostCounters>,
+    events: Option<mpsc::UnboundedSender<SessionEvent>>,
+    cc: DeltaCC,
+    cc_config: DeltaConfig,
+    bandwidth_limit: Option<u32>,
+    /// Ceiling the connected client asked for.
+    client_bitrate_cap: Option<u32>,
+    cc_config_rx: Option<mpsc::UnboundedReceiver<DeltaConfig>>,
+    bandwidth_limit_rx: Option<mpsc::UnboundedReceiver<u32>>,
+    fec_scheme: FecScheme,
+    policy: SessionPolicy,
+    client_addr: Option<SocketAddr>,
+    peer_state: Option<PeerState>,
+    last_packet_time: Instant,
+    /// When the connected client's Hello was accepted.
+    session_started: Option<Instant>,
+    last_input: Instant,
+    /// Streaming is paused until the client sends input again.
+    idle: bool,
+    fps_counter: u32,
+    bytes_sent: u64,
+    last_fps_time: Instant,
+}
+
+impl<V: VideoSource, A: AudioSource> HostLoop<V, A> {
+    /// `config` describes what `video` produces; it feeds the HelloAck and
+    /// the initial congestion-control target.
+    pub fn new(socket: Arc<UdpSocket>, config: EncodeConfig, video: V) -> Self {
+        let cc_config = DeltaConfig::default();
+        let cc = DeltaCC::new(cc_config.clone(), config.bitrate_kbps, config.fps as u32);
+        let counters = Arc::new(HostCounters::default());
+        counters
+            .target_bitrate_kbps
+            .store(cc.target_bitrate_kbps(), Ordering::Relaxed);
+        Self {
+            socket,
+            config,
+            video,
+            audio: None,
+            microphone: None,
+            clipboard: None,
+            last_clipboard_text: None,
+            authorized: None,
+            decisions_rx: None,
+            pending_approval: None,
+            counters,
+            events: None,
+            cc,
+            cc_config,
+            bandwidth_limit: None,
+            client_bitrate_cap: None,
+            cc_config_rx: None,
+            bandwidth_limit_rx: None,
+            fec_scheme: FecScheme::Xor,
+            policy: SessionPolicy::default(),
+            client_addr: None,
+            peer_state: None,
+            last_packet_time: Instant::now(),
+            session_started: None,
+            last_input: Instant::now(),
+            idle: false,
+            fps_counter: 0,
+            bytes_sent: 0,
+            last_fps_time: Instant::now(),
+        }
+    }
+
+    /// Without audio the session streams video alone.
+    pub fn audio(mut self, audio: A) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    /// Plays the client's microphone into `sink`, usually a virtual input.
+    /// Supplying one is the host's consent; the client must opt in as well.
+    pub fn microphone(mut self, sink: Box<dyn Renderer + Send>) -> Self {
+        self.microphone = Some(sink);
+        self
+    }
+
+    /// Syncs `clipboard` with the client, in the directions its user allows.
+    pub fn clipboard(mut self, mut clipboard: Box<dyn Clipboard>) -> Self {
+        self.last_clipboard_text = clipboard.get_text().ok().flatten();
+        self.clipboard = Some(clipboard);
+        self
+    }
+
+    /// Admits only devices allowed in `store`. Others are announced with
+    /// [`SessionEvent::PendingApproval`] and held until the user's decision
+    /// arrives on `decisions`; decisions are saved to `store`.
+    pub fn authorization(
+        mut self,
+        store: AuthorizedClients,
+        decisions: mpsc::UnboundedReceiver<(WavryId, ClientDecision)>,
+    ) -> Self {
+        self.authorized = Some(store);
+        self.decisions_rx = Some(decisions);
+        self
+    }
+
+    /// Limits on how long the client may stay and stay idle, and whether to
+    /// lock this machine once it leaves.
+    pub fn session_policy(mut self, policy: SessionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Shares existing counters instead of allocating fresh ones.
+    pub fn shared_counters(mut self, counters: Arc<HostCounters>) -> Self {
+        counters
+            .target_bitrate_kbps
+            .store(self.cc.target_bitrate_kbps(), Ordering::Relaxed);
+        self.counters = counters;
+        self
+    }
+
+    pub fn events(mut self, events: mpsc::UnboundedSender<SessionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn cc_config(mut self, config: DeltaConfig) -> Self {
+        self.cc_config = config;
+        self.rebuild_cc();
+        self
+    }
+
+    /// Tuning pushed while the session runs, e.g. from a settings panel.
+    pub fn cc_config_updates(mut self, updates: mpsc::UnboundedReceiver<DeltaConfig>) -> Self {
+        self.cc_config_rx = Some(updates);
+        self
+    }
+
+    /// User bandwidth caps pushed while the session runs.
+    pub fn bandwidth_limits(mut self, limits: mpsc::UnboundedReceiver<u32>) -> Self {
+        self.bandwidth_limit_rx = Some(limits);
+        self
+    }
+
+    pub fn counters(&self) -> Arc<HostCounters> {
+        self.counters.clone()
+    }
+
+    pub fn config(&self) -> &EncodeConfig {
+        &self.config
+    }
+
+    /// Swaps the capture source, e.g. after a capture error or a display
+    /// switch. The new encoder starts at the current congestion target.
+    pub fn replace_video(&mut self, video: V, config: EncodeConfig) {
+        self.video = video;
+        self.config = config;
+        let bitrate = self.cc.target_bitrate_kbps();
+        if let Err(e) = self.video.set_bitrate(bitrate) {
+            log::warn!("Failed to set encoder bitrate: {}", e);
+        }
+    }
+
+    /// Streams until `stop` fires (`Ok`) or the video source fails (`Err`).
+    ///
+    /// Cancelling the returned future may cut off the frame being sent; the
+    /// next keyframe repairs it.
+    pub async fn run(&mut self, stop: &mut oneshot::Receiver<()>) -> Result<()> {
+        let socket = self.socket.clone();
+        let mut probe_tick = time::interval(PROBE_TICK);
+        probe_tick.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        let mut policy_tick = time::interval(POLICY_TICK);
+        let mut clipboard_tick = time::interval(CLIPBOARD_POLL);
+
+        loop {
+            self.expire_idle_client();
+            let client_ready = self.peer_state.as_ref().is_some_and(PeerState::is_ready);
+
+            tokio::select! {
+                _ = &mut *stop => {
+                    log::info!("Host session stopped");
+                    self.counters.connected.store(false, Ordering::Relaxed);
+                    return Ok(());
+                }
+
+                // Control, keepalive and handshake traffic from the client.
+                res = async {
+                    let mut buf = [0u8; 2048];
+                    socket.recv_from(&mut buf).await.map(|(len, src)| (buf, len, src))
+                } => {
+                    let handled = match res {
+                        Ok((buf, len, src)) => self.handle_datagram(&buf[..len], src).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    if let Err(e) = handled {
+                        log::warn!("recv handler error: {}", e);
+                    }
+                }
+
+                Some(config) = recv_optional(&mut self.cc_config_rx) => {
+                    self.cc_config = config;
+                    self.rebuild_cc();
+                }
+
+                Some((wavry_id, decision)) = recv_optional(&mut self.decisions_rx) => {
+                    self.decide(wavry_id, decision);
+                }
+
+                Some(limit) = recv_optional(&mut self.bandwidth_limit_rx) => {
+                    log::info!("Host bandwidth limit set to {} kbps", limit);
+                    self.bandwidth_limit = Some(limit);
+                    self.rebuild_cc();
+                }
+
+                res = self.video.next_frame() => {
+                    self.send_frame(res?).await;
+                }
+
+                res = next_audio_packet(&mut self.audio) => {
+                    match res {
+                        Ok(packet) => self.send_audio(packet).await,
+                        Err(e) => {
+                            log::warn!("Audio capture failed, continuing without audio: {}", e);
+                            self.audio = None;
+                        }
+                    }
+                }
+
+                _ = probe_tick.tick(), if client_ready => {
+                    self.send_probes().await;
+                }
+
+                _ = policy_tick.tick(), if self.session_started.is_some() => {
+                    self.enforce_policy().await;
+                }
+
+                _ = clipboard_tick.tick(), if client_ready && self.clipboard.is_some() => {
+                    self.send_clipboard().await;
+                }
+            }
+        }
+    }
+
+    fn emit(&self, event: SessionEvent) {
+        if let Some(events) = self.events.as_ref() {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Saves the user's decision; a held client is answered when it resends
+    /// its Hello.
+    fn decide(&mut self, wavry_id: WavryId, decision: ClientDecision) {
+        let Some(store) = self.authorized.as_mut() else {
+            return;
+        };
+        let name = match self.pending_approval.take() {
+            Some((pending, name)) if pending == wavry_id => name,
+            other => {
+                self.pending_approval = other;
+                String::new()
+            }
+        };
+        log::info!("Device {} ({}): {}", wavry_id, name, decision);
+        if let Err(e) = store.decide(&wavry_id, decision, &name) {
+            log::warn!("Failed to save device decision: {:#}", e);
+        }
+    }
+
+    fn expire_idle_client(&mut self) {
+        if self.client_addr.is_some() && self.last_packet_time.elapsed() > CONNECTION_TIMEOUT {
+            log::warn!("Client timed out");
+            self.drop_client();
+        }
+    }
+
+    fn drop_client(&mut self) {
+        self.client_addr = None;
+        self.peer_state = None;
+        if self.client_bitrate_cap.take().is_some() {
+            self.rebuild_cc();
+        }
+        self.counters.connected.store(false, Ordering::Relaxed);
+        self.emit(SessionEvent::Disconnected);
+        // Only a session that got going leaves anything to lock.
+        if self.session_started.take().is_some() && self.policy.lock_on_disconnect {
+            if let Err(e) = wavry_platform::lock_session() {
+                log::warn!("Failed to lock the host session: {:#}", e);
+            }
+        }
+    }
+
+    /// Ends the session past its maximum duration and pauses streaming to a
+    /// client that stopped sending input.
+    async fn enforce_policy(&mut self) {
+        let Some(started) = self.session_started else {
+            return;
+        };
+        if self
+            .policy
+            .max_session
+            .is_some_and(|limit| started.elapsed() >= limit)
+        {
+            if let (Some(addr), Some(state)) = (self.client_addr, self.peer_state.as_mut()) {
+                let end = control_msg(rift_core::control_message::Content::SessionEnd(
+                    rift_core::SessionEnd {
+                        reason: rift_core::SessionEndReason::SessionEndTimeLimit as i32,
+                    },
+                ));
+                let _ = send_rift_msg(self.socket.as_ref(), state, addr, end).await;
+            }
+            log::info!("Session time limit reached; ending the session");
+            self.drop_client();
+            return;
+        }
+        let idle = self
+            .policy
+            .idle_timeout
+            .is_some_and(|timeout| self.last_input.elapsed() >= timeout);
+        if idle && !self.idle {
+            log::info!("No input from the client; pausing the stream");
+            self.idle = true;
+        }
+    }
+
+    /// Re-creates the congestion controller after its configuration or the
+    /// bandwidth limit changed, keeping the current target where it fits.
+    fn rebuild_cc(&mut self) {
+        let limit = self
+            .bandwidth_limit
+            .into_iter()
+            .chain(self.client_bitrate_cap)
+            .min();
+        let capped = apply_bandwidth_limit(self.cc_config.clone(), limit);
+        let bitrate = self.cc.target_bitrate_kbps().min(capped.max_bitrate_kbps);
+        self.cc = DeltaCC::new(capped, bitrate, self.cc.target_fps());
+        self.apply_target_bitrate(bitrate);
+    }
+
+    fn apply_target_bitrate(&mut self, bitrate: u32) {
+        if let Err(e) = self.video.set_bitrate(bitrate) {
+            log::warn!("Failed to set encoder bitrate: {}", e);
+        }
+        self.counters
+            .target_bitrate_kbps
+            .store(bitrate, Ordering::Relaxed);
+        *self.counters.cc_state.lock().unwrap() = Some(self.cc.state());
+    }
+
+    /// Feeds a congestion signal to DELTA and tells the encoder and client
+    /// when the target moved.
+    async fn on_cc_updated(&mut self, src: SocketAddr) {
+        let previous = self.counters.target_bitrate_kbps.load(Ordering::Relaxed);
+        let bitrate = self.cc.target_bitrate_kbps();
+        *self.counters.cc_state.lock().unwrap() = Some(self.cc.state());
+        if bitrate == previous {
+            return;
+        }
+        self.apply_target_bitrate(bitrate);
+
+        let target_fps = self.cc.target_fps();
+        if let Some(state) = self.peer_state.as_mut() {
+            let cc_msg = control_msg(rift_core::control_message::Content::Congestion(
+                ProtoCongestion {
+                    target_bitrate_kbps: bitrate,
+                    target_fps,
+                    max_bitrate_kbps: 0,
+                },
+            ));
+            let _ = send_rift_msg(self.socket.as_ref(), state, src, cc_msg).await;
+        }
+    }
+
+    async fn handle_datagram(&mut self, buf: &[u8], src: SocketAddr) -> Result<()> {
+        // Connectivity checks come from every candidate pair before the
+        // handshake, so they are answered without claiming the client slot.
+        if rift_core::ice::is_check(buf) {
+            if let Some(response) = rift_core::ice::check_response(buf, src) {
+                self.socket.send_to(&response, src).await?;
+            }
+            return Ok(());
+        }
+
+        if self.client_addr.is_some() && self.client_addr != Some(src) {
+            // Only one active client for now.
+            return Ok(());
+        }
+
+        if self.client_addr.is_none() {
+            self.client_addr = Some(src);
+            self.peer_state = Some(PeerState::new(
+                self.socket.clone(),
+                src,
+                self.cc.fec_ratio(),
+            )?);
+            log::info!("Client connected from {}", src);
+        }
+
+        self.last_packet_time = Instant::now();
+
+        if buf.len() < 2 || (buf[0..2] != RIFT_MAGIC && !compact::is_compact(buf)) {
+            return Ok(());
+        }
+
+        let decoded = match self.peer_state.as_ref() {
+            Some(state) => state.compact_rx.decode(Bytes::copy_from_slice(buf)),
+            None => PhysicalPacket::decode(Bytes::copy_from_slice(buf)),
+        };
+        let phys = match decoded {
+            Ok(p) => p,
+            Err(e) => {
+                log::warn!("RIFT decode error: {}", e);
+                return Ok(());
+            }
+        };
+
+        let socket = self.socket.clone();
+        let state = match self.peer_state.as_mut() {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        let was_established = state.channel.is_established();
+        let plaintext = match state.channel.handle_packet(&phys).await {
+            Ok(Some(plaintext)) => plaintext,
+            Ok(None) => {
+                if !was_established && state.channel.is_established() {
+                    log::info!("crypto established with {}", src);
+                }
+                return Ok(());
+            }
+            Err(e) if !was_established => return Err(anyhow!("crypto handshake error: {}", e)),
+            Err(e) => {
+                log::warn!("decrypt failed: {}", e);
+                return Ok(());
+            }
+        };
+        state.compact_rx.accept(phys.packet_id);
+        let msg = match decode_msg(&plaintext) {
+            Ok(m) => m,
+            Err(e) => {
+                log::warn!("RIFT proto decode error: {}", e);
+                return Ok(());
+            }
+        };
+
+        let ctrl = match msg.content {
+            Some(rift_core::message::Content::Control(ctrl)) => ctrl,
+            Some(rift_core::message::Content::Media(media)) => {
+                if let Some(rift_core::media_message::Content::Audio(packet)) = media.content {
+                    if state.microphone
+                        && packet.direction() == rift_core::AudioDirection::AudioFromClientMic
+                    {
+                        if let Some(sink) = self.microphone.as_mut() {
+                            if let Err(e) = sink.render(&packet.payload, packet.timestamp_us) {
+                                log::debug!("client microphone playback error: {}", e);
+                            }
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            Some(rift_core::message::Content::Input(_)) => {
+                self.last_input = Instant::now();
+                if self.idle {
+                    log::info!("Client input resumed the stream");
+                    self.idle = false;
+                    state.awaiting_keyframe = true;
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        match ctrl.content {
+            Some(rift_core::control_message::Content::Hello(hello)) => {
+                if !state.channel.is_established() {
+                    return Ok(());
+                }
+                // The client resends its Hello until answered.
+                if state.is_ready() {
+                    return Ok(());
+                }
+                let admitted = match self.authorized.as_ref() {
+                    None => true,
+                    Some(store) => {
+                        let identity = state.channel.remote_identity();
+                        match identity.map(|id| (id, store.decision(id))) {
+                            None => {
+                                log::warn!("Rejecting {}: client has no identity to approve", src);
+                                false
+                            }
+                            Some((_, Some(decision))) => decision == ClientDecision::Allow,
+                            Some((id, None)) => {
+                                if self.pending_approval.as_ref().map(|(p, _)| p) != Some(id) {
+                                    log::info!(
+                                        "Device {} ({}) awaits approval",
+                                        id,
+                                        hello.client_name
+                                    );
+                                    self.pending_approval =
+                                        Some((id.clone(), hello.client_name.clone()));
+                                    if let Some(events) = self.events.as_ref() {
+                                        let _ = events.send(SessionEvent::PendingApproval {
+                                            wavry_id: id.clone(),
+                                            client_name: hello.client_name.clone(),
+                                            addr: src,
+                                        });
+                                    }
+                                }
+                                return Ok(());
+                            }
+                        }
+                    }
+                };
+                let selected =
+                    select_codec_for_hello(&hello, self.config.codec).filter(|_| admitted);
+                let accepted = selected.is_some();
+                let fec_scheme = rift_core::fec::negotiate_scheme(&hello.fec_schemes);
+                let ack = ProtoHelloAck {
+                    accepted,
+                    selected_codec: selected.map(|c| c as i32).unwrap_or(0),
+                    stream_resolution: Some(stream_resolution_from_config(&self.config)),
+                    fps: self.config.fps as u32,
+                    initial_bitrate_kbps: self.cc.target_bitrate_kbps(),
+                    keyframe_interval_ms: self.config.keyframe_interval_ms,
+                    session_id: if accepted {
+                        rand::random::<[u8; 16]>().to_vec()
+                    } else {
+                        vec![0u8; 16]
+                    },
+                    session_alias: state.session_alias,
+                    public_addr: String::new(),
+                    candidate_addrs: vec![],
+                    stereo_mode: rift_core::StereoMode::StereoAuto as i32,
+                    audio_layout: rift_core::AudioLayout::AudioStereo as i32,
+                    fec_scheme: fec_scheme as i32,
+                    cursor_channel: false,
+                    audio_params: None,
+                    compact_header: accepted && hello.compact_header,
+                    transport_feedback: accepted && hello.transport_feedback,
+                    microphone: accepted && hello.microphone && self.microphone.is_some(),
+                    // Clients get full control; only wavry-server restricts them.
+                    permissions: 0,
+                    input_batch: accepted,
+                };
+
+                if accepted {
+                    if let Err(e) = state.handshake.on_receive_hello(&hello) {
+                        log::warn!("handshake error: {}", e);
+                    }
+                    if let Err(e) = state.handshake.on_send_hello_ack(&ack) {
+                        log::warn!("handshake ack error: {}", e);
+                    }
+                    self.fec_scheme = fec_scheme;
+                    state.update_fec(fec_scheme, self.cc.fec_ratio());
+                    self.counters.connected.store(true, Ordering::Relaxed);
+                }
+
+                let compact_header = ack.compact_header;
+                let transport_feedback = ack.transport_feedback;
+                let microphone = ack.microphone;
+                let ack_msg = control_msg(rift_core::control_message::Content::HelloAck(ack));
+                let _ = send_rift_msg(socket.as_ref(), state, src, ack_msg).await;
+                // The ack itself went out with a full header.
+                if compact_header {
+                    state.compact_tx = Some(CompactEncoder::default());
+                }
+                if transport_feedback && state.send_times.is_none() {
+                    state.send_times = Some(SendTimes::new(Instant::now()));
+                }
+                state.microphone = microphone;
+                if accepted {
+                    state.clipboard_sync = rift_core::ClipboardSync::negotiated(&hello);
+                    self.session_started = Some(Instant::now());
+                    self.last_input = Instant::now();
+                    self.idle = false;
+                    self.emit(SessionEvent::Connected);
+                }
+            }
+            Some(rift_core::control_message::Content::Ping(ping)) => {
+                let pong = control_msg(rift_core::control_message::Content::Pong(ProtoPong {
+                    timestamp_us: ping.timestamp_us,
+                }));
+                let _ = send_rift_msg(socket.as_ref(), state, src, pong).await;
+            }
+            Some(rift_core::control_message::Content::Stats(report)) => {
+                let loss_ratio = if report.received_packets > 0 {
+                    report.lost_packets as f32
+                        / (report.received_packets + report.lost_packets) as f32
+                } else {
+                    0.0
+                };
+                self.cc
+                    .on_rtt_sample(report.rtt_us, loss_ratio, report.jitter_us);
+                state.pacer.on_stats(
+                    report.rtt_us,
+                    report.jitter_us,
+                    self.cc.target_bitrate_kbps(),
+                );
+                self.counters
+                    .rtt_ms
+                    .store((report.rtt_us / 1000) as u32, Ordering::Relaxed);
+                self.on_cc_updated(src).await;
+            }
+            Some(rift_core::control_message::Content::Congestion(request))
+                if request.max_bitrate_kbps > 0 =>
+            {
+                log::info!("Client capped bitrate at {} kbps", request.max_bitrate_kbps);
+                self.client_bitrate_cap = Some(request.max_bitrate_kbps);
+                self.rebuild_cc();
+            }
+            Some(rift_core::control_message::Content::ProbeResult(result)) => {
+                self.cc.on_probe_result(&result);
+                self.on_cc_updated(src).await;
+            }
+            Some(rift_core::control_message::Content::TransportFeedback(feedback)) => {
+                let Some(send_times) = state.send_times.as_mut() else {
+                    return Ok(());
+                };
+                let packets = send_times.on_feedback(&feedback);
+                self.cc.on_transport_feedback(&packets, Instant::now());
+                self.on_cc_updated(src).await;
+            }
+            Some(rift_core::control_message::Content::Clipboard(clip)) => {
+                if clip.text.len() > rift_core::MAX_CLIPBOARD_TEXT_BYTES
+                    || !state.clipboard_sync.to_host
+                {
+                    return Ok(());
+                }
+                if let Some(clipboard) = self.clipboard.as_mut() {
+                    if let Err(e) = clipboard.set_text(clip.text.clone()) {
+                        log::debug!("clipboard write error: {}", e);
+                    }
+                    self.last_clipboard_text = Some(clip.text);
+                }
+            }
+            Some(rift_core::control_message::Content::ClipboardSync(sync)) => {
+                log::info!(
+                    "Client clipboard sync: to host {}, from host {}",
+                    sync.to_host,
+                    sync.from_host
+                );
+                state.clipboard_sync = sync;
+            }
+            Some(rift_core::control_message::Content::Nack(nack)) => {
+                for packet_id in nack.packet_ids {
+                    if let Some(payload) = state.send_history.get(packet_id) {
+                        let _ = socket.send_to(&payload, src).await;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn send_frame(&mut self, frame: EncodedFrame) {
+        let (Some(addr), Some(state)) = (self.client_addr, self.peer_state.as_mut()) else {
+            return;
+        };
+        if !state.is_ready() || self.idle {
+            return;
+        }
+        if state.awaiting_keyframe {
+            if !frame.keyframe {
+                return;
+            }
+            state.awaiting_keyframe = false;
+        }
+
+        let frame_bytes = frame.data.len();
+        let bitrate = self.cc.target_bitrate_kbps();
+        if let Err(e) = send_video_frame(self.socket.as_ref(), state, addr, frame, bitrate).await {
+            log::warn!("send frame error: {}", e);
+        }
+        state.update_fec(self.fec_scheme, self.cc.fec_ratio());
+
+        self.counters.frames_encoded.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent = self.bytes_sent.saturating_add(frame_bytes as u64);
+        self.fps_counter += 1;
+        if self.last_fps_time.elapsed() >= Duration::from_secs(1) {
+            self.counters.fps.store(self.fps_counter, Ordering::Relaxed);
+            self.counters
+                .bitrate_kbps
+                .store((self.bytes_sent * 8 / 1000) as u32, Ordering::Relaxed);
+            self.fps_counter = 0;
+            self.bytes_sent = 0;
+            self.last_fps_time = Instant::now();
+        }
+    }
+
+    async fn send_audio(&mut self, packet: EncodedFrame) {
+        let (Some(addr), Some(state)) = (self.client_addr, self.peer_state.as_mut()) else {
+            return;
+        };
+        if !state.is_ready() || self.idle {
+            return;
+        }
+        if let Err(e) = send_audio_packet(self.socket.as_ref(), state, addr, packet).await {
+            log::warn!("send audio packet error: {}", e);
+        }
+    }
+
+    /// Sends this machine's clipboard once it changes, if the client's user
+    /// lets it leave the host.
+    async fn send_clipboard(&mut self) {
+        let Some(clipboard) = self.clipboard.as_mut() else {
+            return;
+        };
+        let text = match clipboard.get_text() {
+            Ok(Some(text)) if self.last_clipboard_text.as_ref() != Some(&text) => text,
+            _ => return,
+        };
+        self.last_clipboard_text = Some(text.clone());
+        let (Some(addr), Some(state)) = (self.client_addr, self.peer_state.as_mut()) else {
+            return;
+        };
+        if !state.clipboard_sync.from_host || text.len() > rift_core::MAX_CLIPBOARD_TEXT_BYTES {
+            return;
+        }
+        let msg = control_msg(rift_core::control_message::Content::Clipboard(
+            rift_core::ClipboardMessage { text },
+        ));
+        if let Err(e) = send_rift_msg(self.socket.as_ref(), state, addr, msg).await {
+            log::debug!("clipboard send error: {}", e);
+        }
+    }
+
+    /// Starts a probe burst when DELTA asks for one and sends whatever part
+    /// of the current burst is due.
+    async fn send_probes(&mut self) {
+        let (Some(addr), Some(state)) = (self.client_addr, self.peer_state.as_mut()) else {
+            return;
+        };
+        let now = Instant::now();
+        if state.probe.is_none() {
+            state.probe = self
+                .cc
+                .next_probe(now)
+                .map(|burst| ProbeSender::new(burst, now));
+        }
+        let Some(sender) = state.probe.as_mut() else {
+            return;
+        };
+        let due = sender.poll(now);
+        if sender.is_done() {
+            state.probe = None;
+        }
+        for probe in due {
+            let msg = media_msg(rift_core::media_message::Content::Probe(probe));
+            if let Err(e) = send_unrecorded(self.socket.as_ref(), state, addr, msg).await {
+                log::debug!("send probe error: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+async fn next_audio_packet<A: AudioSource>(audio: &mut Option<A>) -> Result<EncodedFrame> {
+    match audio.as_mut() {
+        Some(audio) => audio.next_packet().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn recv_optional<T>(rx: &mut Option<mpsc::UnboundedReceiver<T>>) -> Option<T> {
+    match rx.as_mut() {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_bandwidth_limit_lowers_ceiling_and_floor() {
+        let base = DeltaConfig::default();
+        let capped = apply_bandwidth_limit(base.clone(), Some(1_500));
+        assert_eq!(capped.max_bitrate_kbps, 1_500);
+        assert_eq!(capped.min_bitrate_kbps, 1_500);
+
+        let uncapped = apply_bandwidth_limit(base.clone(), Some(1_000_000));
+        assert_eq!(uncapped.max_bitrate_kbps, base.max_bitrate_kbps);
+        assert_eq!(
+            apply_bandwidth_limit(base.clone(), None).min_bitrate_kbps,
+            base.min_bitrate_kbps
+        );
+    }
+
+    #[test]
+    fn send_history_evicts_oldest_packets() {
+        let mut history = SendHistory::new(2);
+        history.insert(1, Bytes::from_static(b"a"));
+        history.insert(2, Bytes::from_static(b"b"));
+        history.insert(3, Bytes::from_static(b"c"));
+        assert!(history.get(1).is_none());
+        assert_eq!(history.get(3), Some(Bytes::from_static(b"c")));
+    }
+}
@@ -0,0 +1,138 @@
+//! Capture sources a [`HostLoop`](super::HostLoop) streams from.
+
+use std::future::Future;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+use wavry_media::EncodedFrame;
+
+/// Packets buffered between a capture thread and the host loop.
+const THREADED_AUDIO_QUEUE: usize = 32;
+
+/// Encoded video for the host loop.
+pub trait VideoSource: Send {
+    /// Waits for the next encoded frame. An error ends the current run of
+    /// the host loop.
+    fn next_frame(&mut self) -> impl Future<Output = Result<EncodedFrame>> + Send;
+
+    /// Retargets the encoder; called whenever congestion control moves.
+    fn set_bitrate(&mut self, kbps: u32) -> Result<()>;
+}
+
+/// Encoded audio for the host loop.
+pub trait AudioSource: Send {
+    /// Waits for the next encoded packet. An error drops audio from the
+    /// session; video keeps flowing.
+    fn next_packet(&mut self) -> impl Future<Output = Result<EncodedFrame>> + Send;
+}
+
+/// Runs a blocking capture call on a dedicated thread so it never stalls
+/// the host loop. The thread exits after the first error or once the source
+/// is dropped.
+pub struct ThreadedAudioSource {
+    packets: mpsc::Receiver<Result<EncodedFrame>>,
+}
+
+impl ThreadedAudioSource {
+    pub fn spawn<F>(mut next_packet: F) -> Result<Self>
+    where
+        F: FnMut() -> Result<EncodedFrame> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(THREADED_AUDIO_QUEUE);
+        thread::Builder::new()
+            .name("wavry-audio-capture".into())
+            .spawn(move || loop {
+                let packet = next_packet();
+                let failed = packet.is_err();
+                if tx.blocking_send(packet).is_err() || failed {
+                    break;
+                }
+            })?;
+        Ok(Self { packets: rx })
+    }
+}
+
+impl AudioSource for ThreadedAudioSource {
+    async fn next_packet(&mut self) -> Result<EncodedFrame> {
+        self.packets
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(anyhow!("audio capture thread exited")))
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl VideoSource for wavry_media::MacScreenEncoder {
+    async fn next_frame(&mut self) -> Result<EncodedFrame> {
+        self.next_frame_async().await
+    }
+
+    fn set_bitrate(&mut self, kbps: u32) -> Result<()> {
+        wavry_media::MacScreenEncoder::set_bitrate(self, kbps)
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl AudioSource for wavry_media::MacAudioCapturer {
+    async fn next_packet(&mut self) -> Result<EncodedFrame> {
+        self.next_packet_async().await
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl VideoSource for wavry_media::PipewireEncoder {
+    async fn next_frame(&mut self) -> Result<EncodedFrame> {
+        // Pulling a sample blocks until the pipeline produces one; keep the
+        // wait off the runtime's other tasks.
+        tokio::task::block_in_place(|| wavry_media::PipewireEncoder::next_frame(self))
+            .map_err(Into::into)
+    }
+
+    fn set_bitrate(&mut self, kbps: u32) -> Result<()> {
+        wavry_media::PipewireEncoder::set_bitrate(self, kbps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(timestamp_us: u64) -> EncodedFrame {
+        EncodedFrame {
+            timestamp_us,
+            keyframe: false,
+            data: vec![0u8; 4],
+            capture_duration_us: 0,
+            encode_duration_us: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn threaded_audio_source_stops_after_an_error() {
+        let mut remaining = 2u64;
+        let mut source = ThreadedAudioSource::spawn(move || {
+            if remaining == 0 {
+                return Err(anyhow!("device lost"));
+            }
+            remaining -= 1;
+            Ok(packet(remaining))
+        })
+        .unwrap();
+
+        assert_eq!(source.next_packet().await.unwrap().timestamp_us, 1);
+        assert_eq!(source.next_packet().await.unwrap().timestamp_us, 0);
+        assert!(source
+            .next_packet()
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("device lost"));
+        assert!(source
+            .next_packet()
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("exited"));
+    }
+}
@@ -0,0 +1,18 @@
+//! Typed async API for embedding Wavry.
+//!
+//! Frontends build a [`HostSession`] or [`ClientSession`], start it on their
+//! Tokio runtime, and then drive it through the returned handle: lifecycle
+//! changes arrive as [`SessionEvent`]s on an mpsc channel, counters are read
+//! with `stats()`, and mid-session controls are plain method calls. The
+//! session loops themselves live here so the desktop app, the FFI layer and
+//! any other embedder run the same code. Embedders with their own capture
+//! pipeline can drive [`host::HostLoop`] directly with any
+//! [`host::VideoSource`] and [`host::AudioSource`].
+
+pub mod client;
+mod event;
+pub mod host;
+
+pub use client::{ClientSession, ClientSessionBuilder};
+pub use event::{SessionEvent, SessionStats};
+pub use host::{HostSession, HostSessionBuilder, SessionPolicy};
@@ -2,27 +2,39 @@ mod webrtc_bridge;
 
 mod host {
     use std::{
-        collections::{HashMap, VecDeque},
+        collections::{HashMap, HashSet, VecDeque},
         fmt,
-        net::SocketAddr,
+        io::ErrorKind,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
         path::PathBuf,
-        sync::Arc,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc, Mutex,
+        },
         time::Duration,
     };
 
     use anyhow::{anyhow, Result};
-    use clap::Parser;
+    use clap::parser::ValueSource;
+    use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
     use mdns_sd::{ServiceDaemon, ServiceInfo};
+    use rift_core::cc::{DeltaCC, DeltaConfig};
+    use rift_core::compact::{CompactDecoder, CompactEncoder};
+    use rift_core::probe::ProbeSender;
     use rift_core::{
-        chunk_video_payload, decode_msg, encode_msg, Codec as RiftCodec,
+        chunk_video_payload, decode_msg, encode_msg, message_channel,
+        AudioLayout as RiftAudioLayout, AudioParams as ProtoAudioParams, Codec as RiftCodec,
         ControlMessage as ProtoControl, FecBuilder, Handshake, HelloAck as ProtoHelloAck,
-        Message as ProtoMessage, PhysicalPacket, Resolution as ProtoResolution, Role, RIFT_VERSION,
+        Message as ProtoMessage, PermissionSet, PhysicalPacket, Resolution as ProtoResolution,
+        Role, StereoMode as RiftStereoMode, SystemCursor as RiftSystemCursor,
+        DEFAULT_FILE_CHUNK_BYTES, MAX_CURSOR_SIZE, MAX_FILE_TRANSFER_BYTES, RIFT_VERSION,
     };
     use rift_crypto::connection::SecureServer;
-    use wavry_common::file_transfer::{
-        FileOffer, IncomingFile, OutgoingFile, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_FILE_BYTES,
-    };
-    #[cfg(not(target_os = "linux"))]
+    use rift_crypto::identity::{IdentityKeypair, WavryId};
+    use rift_crypto::{AuthorizedClients, ClientDecision};
+    use wavry_common::file_transfer::{FileOffer, IncomingFile, OutgoingFile};
+    use wavry_common::{session_span, ConfigLoader, SessionSpanExt};
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
     use wavry_media::DummyEncoder as VideoEncoder;
     #[cfg(target_os = "linux")]
     use wavry_media::LinuxProbe;
@@ -39,23 +51,45 @@ mod host {
     #[cfg(target_os = "windows")]
     use wavry_media::WindowsAudioCapturer as AudioCapturer;
     #[cfg(target_os = "windows")]
+    use wavry_media::WindowsEncoder as VideoEncoder;
+    #[cfg(target_os = "windows")]
     use wavry_media::WindowsProbe;
     use wavry_media::{
-        CapabilityProbe, Codec, EncodeConfig, EncodedFrame, Quality, RecorderConfig,
-        Resolution as MediaResolution, VideoRecorder,
+        AudioChannelLayout, CapabilityProbe, CaptureMode, Codec, Container, ContentType,
+        CursorShape, CursorState, DisplayWatch, EncodeConfig, EncodedFrame, EncoderTuning,
+        FoveationParams, OpusConfig, QpOffsetMap, Quality, RecorderConfig, Renderer,
+        Resolution as MediaResolution, SystemCursor, VideoRecorder, VrFramePacer,
     };
 
     use bytes::Bytes;
-    use socket2::SockRef;
-    use tokio::{net::UdpSocket, sync::mpsc, time};
-    use tracing::{debug, error, info, warn};
-    #[cfg(not(target_os = "linux"))]
+    use socket2::{Domain, Protocol, SockRef, Socket, Type};
+    use tokio::{
+        net::UdpSocket,
+        sync::{mpsc, watch},
+        time,
+    };
+    use tracing::{debug, error, info, warn, Instrument, Span};
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
     use wavry_platform::DummyInjector as InjectorImpl;
+    #[cfg(target_os = "macos")]
+    use wavry_platform::MacInjector as InjectorImpl;
     #[cfg(target_os = "linux")]
     use wavry_platform::UinputInjector as InjectorImpl;
-    use wavry_platform::{ArboardClipboard, Clipboard, InputInjector};
+    #[cfg(target_os = "windows")]
+    use wavry_platform::WindowsCursorCapturer as CursorCapturerImpl;
+    #[cfg(target_os = "linux")]
+    use wavry_platform::X11CursorCapturer as CursorCapturerImpl;
+    use wavry_platform::{
+        ArboardClipboard, Clipboard, CursorCapturer, InputInjector, PointerMode, PortMapper,
+        ThreadPriority, ThreadTuning, WakeLock,
+    };
+    use wavry_vr::types::Pose as VrPose;
+    use wavry_vr_steamvr::{
+        controller_input_path, path_to_id, ButtonValue, ControllerInput, DeviceMotion,
+        DriverMessage, HostMessage, SteamVrBridge,
+    };
 
-    use crate::webrtc_bridge::WebRtcBridge;
+    use crate::webrtc_bridge::{ViewerMessage, WebRtcBridge};
 
     const MAX_DATAGRAM_SIZE: usize = 1200;
     const FEC_SHARD_COUNT: u32 = 8;
@@ -65,23 +99,47 @@ mod host {
     const PACER_MAX_US: u64 = 500;
     const PACER_BASE_US: f64 = 30.0;
     const NACK_HISTORY: usize = 512;
+    const MIN_PEER_BITRATE_KBPS: u32 = 1_000;
+    const MAX_PEER_BITRATE_KBPS: u32 = 100_000;
     const PEER_CLEANUP_INTERVAL_SECS: u64 = 2;
     const DEFAULT_RESOLUTION_WIDTH: u16 = 1280;
     const DEFAULT_RESOLUTION_HEIGHT: u16 = 720;
     const MIN_STREAM_DIMENSION: u32 = 320;
     const MAX_STREAM_DIMENSION: u32 = 8192;
+    const MIN_VR_FPS: u32 = 60;
+    const MAX_VR_FPS: u32 = 144;
     const FILE_TRANSFER_TICK_MS: u64 = 2;
     const FILE_TRANSFER_PROGRESS_CHUNK_INTERVAL: u32 = 64;
     const DEFAULT_FILE_TRANSFER_SHARE_PERCENT: f32 = 15.0;
     const DEFAULT_FILE_TRANSFER_MIN_KBPS: u32 = 256;
     const DEFAULT_FILE_TRANSFER_MAX_KBPS: u32 = 4096;
     const MAX_FILE_STATUS_MESSAGE_CHARS: usize = 512;
+    const CURSOR_POLL_MS: u64 = 4;
+    /// How often due probe packets are sent; bursts are a few ms apart.
+    const PROBE_TICK: Duration = Duration::from_millis(5);
+    /// Loss rate Opus budgets in-band FEC for when a client asks for it.
+    const AUDIO_FEC_LOSS_PERCENT: u8 = 10;
+    /// Cursor shapes ride on unreliable media packets, so they are resent
+    /// this often in case one was lost.
+    const CURSOR_SHAPE_REFRESH: Duration = Duration::from_secs(1);
+    /// Frames an encoder runs before its path and latencies are logged, long
+    /// enough for the smoothed numbers to settle.
+    const STATS_LOG_FRAMES: u64 = 300;
+    /// Devices announced for approval at once; the longest-waiting one is
+    /// forgotten to make room, and announced again when it next says Hello.
+    const MAX_PENDING_APPROVALS: usize = 32;
+    /// How long an announced device stays approvable by ID prefix.
+    const PENDING_APPROVAL_TTL: Duration = Duration::from_secs(300);
 
     #[derive(Parser, Debug)]
     #[command(name = "wavry-server")]
     struct Args {
-        /// UDP listen address (use :0 for random)
-        #[arg(long, env = "WAVRY_LISTEN_ADDR", default_value = "0.0.0.0:0")]
+        /// TOML config file; its `[server]` section fills any flag not given
+        #[arg(long, env = "WAVRY_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// UDP listen address (use :0 for random); `[::]` serves IPv4 and IPv6
+        #[arg(long, env = "WAVRY_LISTEN_ADDR", default_value = "[::]:0")]
         listen: SocketAddr,
 
         /// Disable encryption (for testing/debugging)
@@ -112,14 +170,32 @@ mod host {
         #[arg(long, env = "WAVRY_DISPLAY_ID")]
         display_id: Option<u32>,
 
+        /// What is being streamed, for encoder tuning: desktop or game
+        #[arg(long, env = "WAVRY_CONTENT", default_value = "game")]
+        content: ContentType,
+
+        /// When to encode: fixed (every captured frame) or damage (only when
+        /// the screen changes, with a slow keepalive while idle)
+        #[arg(long, env = "WAVRY_CAPTURE_MODE", default_value = "fixed")]
+        capture_mode: CaptureMode,
+
         /// Disable mDNS host advertisement
         #[arg(long, default_value_t = false)]
         disable_mdns: bool,
 
+        /// Don't ask the router (PCP, NAT-PMP or UPnP) to forward the listen port
+        #[arg(long, env = "WAVRY_DISABLE_PORT_MAPPING", default_value_t = false)]
+        disable_port_mapping: bool,
+
         /// Maximum number of tracked peer endpoints
         #[arg(long, default_value_t = 64)]
         max_peers: usize,
 
+        /// Maximum number of clients streamed to at once; later sessions share
+        /// the first session's encoder
+        #[arg(long, env = "WAVRY_MAX_STREAMS", default_value_t = 1)]
+        max_streams: usize,
+
         /// Drop peers that stay silent for this many seconds
         #[arg(long, default_value_t = 30)]
         peer_idle_timeout_secs: u64,
@@ -144,18 +220,34 @@ mod host {
         #[arg(long, env = "WAVRY_ENABLE_WEBRTC", default_value_t = false)]
         enable_webrtc: bool,
 
-        /// Enable local recording to MP4
+        /// Record the session from the start
         #[arg(long, env = "WAVRY_RECORD", default_value_t = false)]
         record: bool,
 
+        /// Let connected clients start and stop recording
+        #[arg(long, env = "WAVRY_RECORD_ON_REQUEST", default_value_t = false)]
+        record_on_request: bool,
+
         /// Directory to store recordings
         #[arg(long, env = "WAVRY_RECORD_DIR", default_value = "recordings")]
-        record_dir: String,
+        record_dir: PathBuf,
 
         /// Recording quality (high, standard, low)
         #[arg(long, env = "WAVRY_RECORD_QUALITY", default_value = "standard")]
         record_quality: String,
 
+        /// Recording container: mkv (video and audio) or mp4 (video only)
+        #[arg(long, env = "WAVRY_RECORD_FORMAT", default_value = "mkv")]
+        record_format: String,
+
+        /// Start a new recording file once the current one reaches this many MB (0 = no limit)
+        #[arg(long, env = "WAVRY_RECORD_SEGMENT_MB", default_value_t = 2048)]
+        record_segment_mb: u32,
+
+        /// Start a new recording file after this many seconds (0 = no limit)
+        #[arg(long, env = "WAVRY_RECORD_SEGMENT_SECS", default_value_t = 0)]
+        record_segment_secs: u32,
+
         /// Send file to client after session establishment (repeatable)
         #[arg(long = "send-file", value_name = "PATH")]
         send_files: Vec<PathBuf>,
@@ -168,7 +260,7 @@ mod host {
         #[arg(
             long,
             env = "WAVRY_FILE_MAX_BYTES",
-            default_value_t = DEFAULT_MAX_FILE_BYTES
+            default_value_t = MAX_FILE_TRANSFER_BYTES
         )]
         file_max_bytes: u64,
 
@@ -199,6 +291,117 @@ mod host {
         /// Audio source route (`system`, `microphone`, `app:<name>`, `disabled`)
         #[arg(long, env = "WAVRY_AUDIO_SOURCE", default_value = "system")]
         audio_source: String,
+
+        /// Let clients that ask send their microphone into a virtual input on this host
+        #[arg(long, env = "WAVRY_ALLOW_MICROPHONE", default_value_t = false)]
+        allow_microphone: bool,
+
+        /// Ed25519 key clients pin this host by (created if missing; defaults to the Wavry config dir)
+        #[arg(long, env = "WAVRY_HOST_IDENTITY_KEY")]
+        identity_key: Option<PathBuf>,
+
+        /// Remembered allow/deny decisions about client devices (defaults to the Wavry config dir)
+        #[arg(long, env = "WAVRY_AUTHORIZED_CLIENTS")]
+        authorized_clients: Option<PathBuf>,
+
+        /// Start sessions for any client without asking to approve new devices
+        #[arg(long, env = "WAVRY_ALLOW_ANY_CLIENT", default_value_t = false)]
+        allow_any_client: bool,
+
+        /// Admit this device's Wavry ID without asking (repeatable; comma-separated in the variable)
+        #[arg(
+            long = "allow-client",
+            value_name = "WAVRY_ID",
+            env = "WAVRY_ALLOWED_CLIENTS",
+            value_delimiter = ','
+        )]
+        allowed_clients: Vec<String>,
+
+        /// What clients may do besides watching: all, view-only, or a list of input, clipboard and files
+        #[arg(long, env = "WAVRY_PERMISSIONS", default_value = "all")]
+        permissions: PermissionSet,
+
+        /// End each session after this many seconds (0 = no limit)
+        #[arg(long, env = "WAVRY_MAX_SESSION_SECS", default_value_t = 0)]
+        max_session_secs: u64,
+
+        /// Pause streaming to a client that sent no input for this many seconds (0 = never)
+        #[arg(long, env = "WAVRY_IDLE_TIMEOUT_SECS", default_value_t = 0)]
+        idle_timeout_secs: u64,
+
+        /// Lock the host's desktop session when the last client disconnects
+        #[arg(long, env = "WAVRY_LOCK_ON_DISCONNECT", default_value_t = false)]
+        lock_on_disconnect: bool,
+
+        /// Stream SteamVR through the Wavry SteamVR driver instead of capturing the desktop
+        #[arg(long, env = "WAVRY_STEAMVR", default_value_t = false)]
+        steamvr: bool,
+
+        /// Loopback address the SteamVR driver connects to
+        #[arg(long, env = "WAVRY_STEAMVR_BRIDGE", default_value = wavry_vr_steamvr::DEFAULT_BRIDGE_ADDR)]
+        steamvr_bridge: SocketAddr,
+
+        /// Scheduling priority for the encode and send threads: normal, high or realtime
+        #[arg(long, env = "WAVRY_THREAD_PRIORITY", default_value = "normal")]
+        thread_priority: ThreadPriority,
+
+        /// Pin the capture/encode thread to this CPU core
+        #[arg(long, env = "WAVRY_ENCODE_CORE")]
+        encode_core: Option<usize>,
+
+        /// Pin the network send thread to this CPU core
+        #[arg(long, env = "WAVRY_SEND_CORE")]
+        send_core: Option<usize>,
+    }
+
+    impl Args {
+        /// Parses the command line, then fills every flag left at its default
+        /// from the shared config (file, then `WAVRY_SERVER__*` variables).
+        fn load() -> Result<Self> {
+            let matches = Self::command().get_matches();
+            let mut args = Self::from_arg_matches(&matches)?;
+            args.apply_config(&matches)?;
+            Ok(args)
+        }
+
+        fn apply_config(&mut self, matches: &ArgMatches) -> Result<()> {
+            let mut loader = ConfigLoader::new();
+            if let Some(path) = &self.config {
+                loader = loader.file(path);
+            }
+            let server = loader.load()?.server;
+            let unset = |id: &str| {
+                matches
+                    .value_source(id)
+                    .is_none_or(|source| source == ValueSource::DefaultValue)
+            };
+            macro_rules! from_config {
+                ($($field:ident),* $(,)?) => {
+                    $(if unset(stringify!($field)) {
+                        self.$field = server.$field;
+                    })*
+                };
+            }
+            from_config!(
+                listen,
+                no_encrypt,
+                width,
+                height,
+                fps,
+                bitrate_kbps,
+                keyframe_interval_ms,
+                display_id,
+                disable_mdns,
+                max_peers,
+                peer_idle_timeout_secs,
+                gateway_url,
+                enable_webrtc,
+                record,
+                record_dir,
+                audio_source,
+            );
+            Ok(())
+        }
     }
 
     #[derive(Clone, Copy, Debug)]
@@ -208,11 +411,33 @@ mod host {
         initial_bitrate_kbps: u32,
         keyframe_interval_ms: u32,
         max_peers: usize,
+        max_streams: usize,
         peer_idle_timeout: Duration,
         stats_log_interval: Duration,
         file_transfer_share_percent: f32,
         file_transfer_min_kbps: u32,
         file_transfer_max_kbps: u32,
+        /// Whether audio capture can follow a multi-channel session layout.
+        multichannel_audio: bool,
+        /// Video, tracking and controller input go through the SteamVR driver.
+        steamvr: bool,
+        /// A cursor capturer is running, so clients may draw the pointer themselves.
+        cursor_channel: bool,
+        /// Clients may play their microphone into a virtual input here.
+        allow_microphone: bool,
+        /// Granted to new sessions; `permissions <set>` on stdin changes it
+        /// for running ones too.
+        permissions: PermissionSet,
+        /// Sessions are ended once they have run this long.
+        max_session: Option<Duration>,
+        /// Streaming to a client pauses after this long without its input.
+        input_idle_timeout: Option<Duration>,
+        lock_on_disconnect: bool,
+        /// Router-mapped address clients outside the LAN reach this host on,
+        /// offered in HelloAcks.
+        external_addr: Option<SocketAddr>,
+        encode_thread: ThreadTuning,
+        send_thread: ThreadTuning,
     }
 
     fn env_bool(name: &str, default: bool) -> bool {
@@ -225,6 +450,217 @@ mod host {
         }
     }
 
+    /// Loads the host's long-term identity, creating it on first run. Clients
+    /// pin the resulting Wavry ID, so it must survive restarts.
+    fn load_or_create_host_identity(path: Option<PathBuf>) -> Result<IdentityKeypair> {
+        let path = match path {
+            Some(path) => path,
+            None => wavry_common::helpers::config_dir()
+                .ok_or_else(|| anyhow!("no config directory; pass --identity-key"))?
+                .join("host_identity.key"),
+        };
+        let private_path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("identity key path is not valid UTF-8"))?;
+
+        let identity = IdentityKeypair::load_or_generate(private_path)?;
+        info!(
+            "host identity: {} ({})",
+            identity.wavry_id(),
+            path.display()
+        );
+        Ok(identity)
+    }
+
+    enum Admission {
+        Allowed,
+        Denied,
+        /// Held without a HelloAck until the operator decides; the client
+        /// keeps resending its Hello meanwhile.
+        Pending,
+    }
+
+    /// Which client devices may start a session. Devices named with
+    /// --allow-client are admitted outright; other new ones are announced and
+    /// decided on from stdin with `allow <wavry-id>` or `deny <wavry-id>`.
+    struct ClientApprovals {
+        /// `None` admits every client (--allow-any-client or --no-encrypt).
+        store: Option<AuthorizedClients>,
+        /// Devices the operator allowed on the command line.
+        allowed: HashSet<WavryId>,
+        /// Devices waiting for a decision, with the name they connected as
+        /// and when they were announced.
+        pending: HashMap<WavryId, (String, time::Instant)>,
+    }
+
+    impl ClientApprovals {
+        fn open(args: &Args) -> Result<Self> {
+            let allowed = args
+                .allowed_clients
+                .iter()
+                .map(|id| WavryId::parse(id.trim()))
+                .collect::<Result<HashSet<_>, _>>()?;
+            let store = if args.no_encrypt || args.allow_any_client {
+                warn!("client approval disabled: any client that connects gets control");
+                None
+            } else {
+                let path = args
+                    .authorized_clients
+                    .clone()
+                    .or_else(AuthorizedClients::default_path)
+                    .ok_or_else(|| anyhow!("no config directory; pass --authorized-clients"))?;
+                let store = AuthorizedClients::open(&path)?;
+                info!(
+                    "client approval enabled ({} remembered devices in {}, {} allowed by flag)",
+                    store.clients().len(),
+                    path.display(),
+                    allowed.len()
+                );
+                Some(store)
+            };
+            Ok(Self {
+                store,
+                allowed,
+                pending: HashMap::new(),
+            })
+        }
+
+        fn admit(
+            &mut self,
+            peer: SocketAddr,
+            identity: Option<&WavryId>,
+            client_name: &str,
+        ) -> Admission {
+            let Some(store) = self.store.as_ref() else {
+                return Admission::Allowed;
+            };
+            let Some(identity) = identity else {
+                warn!("rejecting {}: client has no identity to approve", peer);
+                return Admission::Denied;
+            };
+            if self.allowed.contains(identity) {
+                return Admission::Allowed;
+            }
+            match store.decision(identity) {
+                Some(ClientDecision::Allow) => Admission::Allowed,
+                Some(ClientDecision::Deny) => {
+                    info!("rejecting {} ({}): device is denied", peer, identity);
+                    Admission::Denied
+                }
+                None => {
+                    let now = time::Instant::now();
+                    self.expire(now);
+                    if !self.pending.contains_key(identity) {
+                        if self.pending.len() >= MAX_PENDING_APPROVALS {
+                            let oldest = self
+                                .pending
+                                .iter()
+                                .min_by_key(|(_, (_, since))| *since)
+                                .map(|(id, _)| id.clone());
+                            if let Some(oldest) = oldest {
+                                self.pending.remove(&oldest);
+                            }
+                        }
+                        warn!(
+                            "pending approval: {:?} ({}) wants to connect from {}; type `allow {}` or `deny {}`",
+                            client_name, identity, peer, identity, identity
+                        );
+                        self.pending
+                            .insert(identity.clone(), (client_name.to_string(), now));
+                    }
+                    Admission::Pending
+                }
+            }
+        }
+
+        /// Forgets devices announced longer than [`PENDING_APPROVAL_TTL`] ago;
+        /// one still trying to connect is announced again.
+        fn expire(&mut self, now: time::Instant) {
+            self.pending.retain(|_, (_, since)| {
+                now.saturating_duration_since(*since) < PENDING_APPROVAL_TTL
+            });
+        }
+
+        /// Applies an operator command; a pending device may be named by any
+        /// unique prefix of its ID.
+        fn apply(&mut self, line: &str) -> Result<()> {
+            let Some(store) = self.store.as_mut() else {
+                return Ok(());
+            };
+            let mut words = line.split_whitespace();
+            let Some(command) = words.next() else {
+                return Ok(());
+            };
+            let Some(target) = words.next() else {
+                return Err(anyhow!("expected `allow <wavry-id>` or `deny <wavry-id>`"));
+            };
+            let decision = ClientDecision::parse(command)
+                .ok_or_else(|| anyhow!("unknown command {:?}", command))?;
+            self.expire(time::Instant::now());
+            let mut matches = self
+                .pending
+                .keys()
+                .filter(|id| id.as_str().starts_with(target));
+            let identity = match (matches.next(), matches.next()) {
+                (Some(id), None) => id.clone(),
+                (Some(_), Some(_)) => return Err(anyhow!("{:?} matches several devices", target)),
+                (None, _) => WavryId::parse(target)?,
+            };
+            let name = self
+                .pending
+                .remove(&identity)
+                .map(|(name, _)| name)
+                .unwrap_or_default();
+            store.decide(&identity, decision, &name)?;
+            info!("{} {} ({:?})", decision, identity, name);
+            Ok(())
+        }
+    }
+
+    /// Grants `permissions` to every running session and tells its client.
+    async fn update_permissions(
+        socket: &UdpSocket,
+        peers: &mut HashMap<SocketAddr, PeerState>,
+        sessions: &StreamSessions,
+        permissions: PermissionSet,
+    ) {
+        info!("client permissions: {}", permissions);
+        for &peer in &sessions.peers {
+            let Some(peer_state) = peers.get_mut(&peer) else {
+                continue;
+            };
+            peer_state.permissions = permissions;
+            let msg = ProtoMessage {
+                content: Some(rift_core::message::Content::Control(ProtoControl {
+                    content: Some(rift_core::control_message::Content::PermissionUpdate(
+                        rift_core::PermissionUpdate {
+                            permissions: permissions.to_wire(),
+                        },
+                    )),
+                })),
+            };
+            if let Err(err) = send_rift_msg(socket, peer_state, peer, msg).await {
+                debug!("failed to send permission update to {}: {}", peer, err);
+            }
+        }
+    }
+
+    /// Lines typed on stdin, read on a thread of their own.
+    fn spawn_stdin_lines() -> mpsc::UnboundedReceiver<String> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
     /// Crypto state for a peer
     enum CryptoState {
         /// No encryption (--no-encrypt mode)
@@ -246,11 +682,13 @@ mod host {
     }
 
     impl CryptoState {
-        fn new(disabled: bool) -> Self {
-            if disabled {
-                CryptoState::Disabled
-            } else {
-                CryptoState::Handshaking(SecureServer::new().expect("failed to create crypto"))
+        /// `None` disables encryption (--no-encrypt mode).
+        fn new(identity: Option<&IdentityKeypair>) -> Self {
+            match identity {
+                None => CryptoState::Disabled,
+                Some(identity) => CryptoState::Handshaking(
+                    SecureServer::with_identity(identity).expect("failed to create crypto"),
+                ),
             }
         }
 
@@ -265,17 +703,75 @@ mod host {
         pending_crypto_msg2: Option<Bytes>,
         session_id: Option<Vec<u8>>,
         session_alias: u32,
+        /// Highest `Resume` attempt accepted, so captured ones can't be replayed.
+        resume_attempt: u64,
         next_packet_id: u64,
         frame_id: u64,
         pacer: Pacer,
         send_history: SendHistory,
+        /// Bitrate the peer's stream is encoded and paced at: DELTA's target,
+        /// never above what the client asked for.
         target_bitrate_kbps: u32,
+        cc: DeltaCC,
+        /// Ceiling the client asked for with `CongestionControl.max_bitrate_kbps`.
+        bitrate_cap_kbps: Option<u32>,
+        /// Probe burst DELTA asked for that is still being sent.
+        probe: Option<ProbeSender>,
         skip_frames: u32,
         #[allow(dead_code)]
         fec_builder: FecBuilder,
         last_seen: time::Instant,
         last_stats_log: time::Instant,
         client_name: Option<String>,
+        /// Latest gaze from an eye-tracked headset, not yet handed to the encoder.
+        foveation: Option<FoveationParams>,
+        /// Codec and resolution agreed in the HelloAck, for later reconfiguration.
+        codec: Option<Codec>,
+        stream_resolution: Option<ProtoResolution>,
+        stereo_mode: RiftStereoMode,
+        audio_layout: RiftAudioLayout,
+        audio_opus: OpusConfig,
+        /// The client's display side changed; its new decoder needs a fresh encoder.
+        restart_encoder: bool,
+        /// Latest vsync phase report from a headset, not yet handed to the encoder.
+        vr_timing: Option<rift_core::VrTiming>,
+        /// Tracking and controller input not yet handed to the SteamVR driver.
+        steamvr_input: Vec<HostMessage>,
+        /// Joined a stream already in progress; video starts at the next keyframe.
+        awaiting_keyframe: bool,
+        /// Cursor shape last sent to this peer, and when, so it is only resent
+        /// after a change or once the refresh interval passes.
+        cursor_shape_sent: Option<(u64, time::Instant)>,
+        /// Recording start (`true`) or stop the client asked for, not yet applied.
+        recording_request: Option<bool>,
+        /// Set once the HelloAck agreed on compact transport headers.
+        compact_tx: Option<CompactEncoder>,
+        compact_rx: CompactDecoder,
+        /// Displays streamed to this peer besides the primary one, in
+        /// `SelectMonitor` order; the n-th rides `monitor_stream_id(n)`.
+        additional_monitors: Vec<u32>,
+        /// Next frame id per additional display. A display is missing until
+        /// its stream reaches a keyframe.
+        monitor_frame_ids: HashMap<u32, u64>,
+        /// Both sides agreed in the HelloAck to microphone passthrough.
+        microphone: bool,
+        /// What this client may do; nothing beyond watching until its Hello
+        /// is answered.
+        permissions: PermissionSet,
+        /// Ways the client's user lets the clipboard sync, from its Hello and
+        /// later `ClipboardSync` updates.
+        clipboard_sync: rift_core::ClipboardSync,
+        /// When the peer's Hello first started a session; a Resume keeps it.
+        session_started: Option<time::Instant>,
+        /// Latest input from the client, for the idle timeout.
+        last_input: time::Instant,
+        /// Streaming is paused until the client sends input again.
+        idle: bool,
+        /// Virtual input the client's microphone plays into, opened by its
+        /// first packet and closed with the session.
+        virtual_mic: Option<Box<dyn Renderer + Send>>,
+        /// `session` span this peer's packets are handled in.
+        span: Span,
     }
 
     #[derive(Debug, Clone)]
@@ -317,7 +813,12 @@ mod host {
             let mut outgoing = VecDeque::new();
             for path in send_files {
                 let file_id = random_file_id();
-                match OutgoingFile::from_path(path, file_id, DEFAULT_CHUNK_SIZE, max_file_bytes) {
+                match OutgoingFile::from_path(
+                    path,
+                    file_id,
+                    DEFAULT_FILE_CHUNK_BYTES,
+                    max_file_bytes,
+                ) {
                     Ok(file) => {
                         info!("queued file for transfer to client: {}", path.display());
                         outgoing.push_back(file);
@@ -379,15 +880,24 @@ mod host {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn ensure_encoder(
         frame_rx: &mut Option<mpsc::Receiver<FrameIn>>,
         selected_codec: &mut Option<Codec>,
         current_display_id: &mut Option<u32>,
+        current_fps: &mut Option<u16>,
+        current_hide_cursor: &mut Option<bool>,
         base: EncodeConfig,
         codec: Codec,
+        bitrate_target: &Arc<AtomicU32>,
+        foveation: &Arc<Mutex<Option<FoveationParams>>>,
+        pacing: &Arc<Mutex<Option<VrFramePacer>>>,
+        thread_tuning: ThreadTuning,
     ) -> Result<()> {
         if selected_codec == &Some(codec)
             && current_display_id == &base.display_id
+            && current_fps == &Some(base.fps)
+            && current_hide_cursor == &Some(base.hide_cursor)
             && frame_rx.is_some()
         {
             return Ok(());
@@ -395,20 +905,92 @@ mod host {
 
         let mut config = base;
         config.codec = codec;
+        config.tuning = EncoderTuning::for_codec(codec, base.tuning.content);
+        let rx = spawn_encoder(config, bitrate_target, foveation, pacing, thread_tuning).await?;
+
+        *frame_rx = Some(rx);
+        *selected_codec = Some(codec);
+        *current_display_id = base.display_id;
+        *current_fps = Some(base.fps);
+        *current_hide_cursor = Some(base.hide_cursor);
+        info!(
+            "Selected encoder codec: {:?}, display: {:?}",
+            codec, base.display_id
+        );
+        Ok(())
+    }
+
+    /// Opens an encoder for `config` on its own thread and returns the
+    /// channel its frames arrive on. The thread ends once the receiver is
+    /// dropped.
+    async fn spawn_encoder(
+        config: EncodeConfig,
+        bitrate_target: &Arc<AtomicU32>,
+        foveation: &Arc<Mutex<Option<FoveationParams>>>,
+        pacing: &Arc<Mutex<Option<VrFramePacer>>>,
+        thread_tuning: ThreadTuning,
+    ) -> Result<mpsc::Receiver<FrameIn>> {
         let encoder = VideoEncoder::new(config).await?;
         let (frame_tx, rx) = mpsc::channel::<FrameIn>(2);
+        let bitrate_target = Arc::clone(bitrate_target);
+        let foveation = Arc::clone(foveation);
+        let pacing = Arc::clone(pacing);
 
         std::thread::spawn(move || {
+            if let Err(err) = thread_tuning.apply_current() {
+                warn!(
+                    "encoder thread tuning ({:?}) failed: {}",
+                    thread_tuning, err
+                );
+            }
             let mut encoder = encoder;
+            let mut applied_bitrate_kbps = config.bitrate_kbps;
+            let epoch = std::time::Instant::now();
+            let mut frames = 0u64;
             loop {
+                let target = bitrate_target.load(Ordering::Relaxed);
+                if target != 0 && target != applied_bitrate_kbps {
+                    match encoder.set_bitrate(target) {
+                        Ok(()) => applied_bitrate_kbps = target,
+                        Err(err) => warn!("encoder bitrate update failed: {}", err),
+                    }
+                }
+                let gaze = foveation.lock().ok().and_then(|mut slot| slot.take());
+                if let Some(params) = gaze {
+                    let map = QpOffsetMap::foveated(config.resolution, &params);
+                    if let Err(err) = encoder.set_qp_offset_map(Some(&map)) {
+                        debug!("foveated encoding unavailable: {}", err);
+                    }
+                }
+                // Headsets start each frame on their vsync grid rather than ours.
+                let pacer = pacing.lock().ok().and_then(|slot| *slot);
+                if let Some(pacer) = pacer {
+                    let now_us = epoch.elapsed().as_micros() as u64;
+                    let slot_us = pacer.next_slot_us(now_us);
+                    if slot_us > now_us {
+                        std::thread::sleep(Duration::from_micros(slot_us - now_us));
+                    }
+                }
                 let start = std::time::Instant::now();
                 match encoder.next_frame() {
                     Ok(mut frame) => {
-                        let encode_duration = start.elapsed().as_micros() as u32;
-                        frame.encode_duration_us = encode_duration;
-                        // For backends where capture is combined with next_frame,
-                        // we can't easily separate them, so we just use the total time for now
-                        // or assume capture is fast and encode takes most of the time.
+                        // Backends that time their own stages fill these in;
+                        // otherwise capture and encode share one measurement.
+                        if frame.encode_duration_us == 0 {
+                            frame.encode_duration_us = start.elapsed().as_micros() as u32;
+                        }
+                        frames += 1;
+                        if frames == STATS_LOG_FRAMES {
+                            let stats = encoder.stats();
+                            info!(
+                                "encoder frames take the {} path (upload {} us, encode {} us, {} fps, {} unchanged skipped)",
+                                stats.frame_path,
+                                stats.upload_latency_us,
+                                stats.encode_latency_us,
+                                stats.fps,
+                                stats.unchanged_frames
+                            );
+                        }
                         if frame_tx.blocking_send(frame).is_err() {
                             break;
                         }
@@ -419,16 +1001,120 @@ mod host {
                     }
                 }
             }
+            let stats = encoder.stats();
+            info!(
+                "encoder stopped after {} zero-copy and {} CPU frames, {} unchanged skipped (upload {} us, encode {} us)",
+                stats.zero_copy_frames,
+                stats.cpu_frames,
+                stats.unchanged_frames,
+                stats.upload_latency_us,
+                stats.encode_latency_us
+            );
         });
 
-        *frame_rx = Some(rx);
-        *selected_codec = Some(codec);
-        *current_display_id = base.display_id;
-        info!(
-            "Selected encoder codec: {:?}, display: {:?}",
-            codec, base.display_id
-        );
-        Ok(())
+        Ok(rx)
+    }
+
+    /// Encoder for a display streamed alongside the primary one. Its frames
+    /// are forwarded into a channel shared by all such encoders, tagged with
+    /// the display id.
+    struct MonitorStream {
+        display_id: u32,
+        codec: Codec,
+        bitrate_target: Arc<AtomicU32>,
+        forwarder: tokio::task::JoinHandle<()>,
+    }
+
+    impl Drop for MonitorStream {
+        fn drop(&mut self) {
+            // Dropping the encoder's receiver stops its thread.
+            self.forwarder.abort();
+        }
+    }
+
+    /// Additional displays wanted by at least one session, in request order
+    /// and capped at `MAX_ADDITIONAL_MONITORS`. Per-eye sessions are left out
+    /// since their second stream is the right eye.
+    fn wanted_monitors(
+        sessions: &StreamSessions,
+        peers: &HashMap<SocketAddr, PeerState>,
+        primary_display: Option<u32>,
+    ) -> Vec<u32> {
+        let mut wanted = Vec::new();
+        for peer_state in sessions.peers.iter().filter_map(|peer| peers.get(peer)) {
+            if peer_state.stereo_mode == RiftStereoMode::StereoDualStream {
+                continue;
+            }
+            for &id in &peer_state.additional_monitors {
+                if Some(id) != primary_display && !wanted.contains(&id) {
+                    wanted.push(id);
+                }
+            }
+        }
+        wanted.truncate(rift_core::MAX_ADDITIONAL_MONITORS);
+        wanted
+    }
+
+    /// Starts and stops additional-display encoders to match `wanted`.
+    async fn sync_monitor_streams(
+        streams: &mut Vec<MonitorStream>,
+        wanted: &[u32],
+        frame_tx: &mpsc::Sender<(u32, FrameIn)>,
+        base: EncodeConfig,
+        codec: Option<Codec>,
+        thread_tuning: ThreadTuning,
+    ) {
+        let Some(codec) = codec else {
+            streams.clear();
+            return;
+        };
+        streams.retain(|stream| stream.codec == codec && wanted.contains(&stream.display_id));
+        for &display_id in wanted {
+            if streams.iter().any(|stream| stream.display_id == display_id) {
+                continue;
+            }
+            let mut config = base;
+            config.codec = codec;
+            config.tuning = EncoderTuning::for_codec(codec, base.tuning.content);
+            config.display_id = Some(display_id);
+            let bitrate_target = Arc::new(AtomicU32::new(0));
+            // Foveation and vsync pacing only apply to headsets, which stream one display.
+            let unused_foveation = Arc::new(Mutex::new(None));
+            let unused_pacing = Arc::new(Mutex::new(None));
+            let mut rx = match spawn_encoder(
+                config,
+                &bitrate_target,
+                &unused_foveation,
+                &unused_pacing,
+                thread_tuning,
+            )
+            .await
+            {
+                Ok(rx) => rx,
+                Err(err) => {
+                    warn!("encoder for display {} failed: {}", display_id, err);
+                    continue;
+                }
+            };
+            let frame_tx = frame_tx.clone();
+            let forwarder = tokio::spawn(async move {
+                while let Some(frame) = rx.recv().await {
+                    if frame_tx.send((display_id, frame)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            info!(
+                "Streaming additional display {} with {:?}",
+                display_id, codec
+            );
+            streams.push(MonitorStream {
+                display_id,
+                codec,
+                bitrate_target,
+                forwarder,
+            });
+        }
     }
 
     #[cfg(target_os = "linux")]
@@ -447,10 +1133,16 @@ mod host {
     }
 
     #[cfg(any(target_os = "linux", target_os = "macos"))]
-    async fn start_audio_capture(source: AudioRouteSource) -> Result<mpsc::Receiver<EncodedFrame>> {
+    async fn start_audio_capture(
+        source: AudioRouteSource,
+        layout: AudioChannelLayout,
+        opus: OpusConfig,
+    ) -> Result<mpsc::Receiver<EncodedFrame>> {
         let mut capturer = {
             #[cfg(target_os = "macos")]
             {
+                // macOS capture is stereo only.
+                let _ = layout;
                 match source {
                     AudioRouteSource::Disabled => return Err(anyhow!("audio source disabled")),
                     AudioRouteSource::SystemMix => {
@@ -469,7 +1161,9 @@ mod host {
             {
                 match source {
                     AudioRouteSource::Disabled => return Err(anyhow!("audio source disabled")),
-                    AudioRouteSource::SystemMix => AudioCapturer::new_system_mix().await?,
+                    AudioRouteSource::SystemMix => {
+                        AudioCapturer::new_system_mix_with_layout(layout).await?
+                    }
                     AudioRouteSource::Microphone => match AudioCapturer::new_microphone().await {
                         Ok(capturer) => capturer,
                         Err(err) => {
@@ -492,6 +1186,9 @@ mod host {
                 }
             }
         };
+        if let Err(err) = capturer.set_opus_config(opus) {
+            warn!("audio encoder settings not applied: {}", err);
+        }
         let (tx, rx) = mpsc::channel(16);
         std::thread::spawn(move || loop {
             match next_audio_packet(&mut capturer) {
@@ -510,7 +1207,11 @@ mod host {
     }
 
     #[cfg(target_os = "windows")]
-    async fn start_audio_capture(source: AudioRouteSource) -> Result<mpsc::Receiver<EncodedFrame>> {
+    async fn start_audio_capture(
+        source: AudioRouteSource,
+        _layout: AudioChannelLayout,
+        opus: OpusConfig,
+    ) -> Result<mpsc::Receiver<EncodedFrame>> {
         if matches!(source, AudioRouteSource::Disabled) {
             return Err(anyhow!("audio source disabled"));
         }
@@ -584,6 +1285,9 @@ mod host {
                     }
                 }
             };
+            if let Err(err) = capturer.set_opus_config(opus) {
+                warn!("audio encoder settings not applied: {}", err);
+            }
 
             loop {
                 match next_audio_packet(&mut capturer) {
@@ -606,6 +1310,8 @@ mod host {
     #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
     async fn start_audio_capture(
         _source: AudioRouteSource,
+        _layout: AudioChannelLayout,
+        _opus: OpusConfig,
     ) -> Result<mpsc::Receiver<EncodedFrame>> {
         Err(anyhow!("audio capture is not supported on this platform"))
     }
@@ -635,6 +1341,90 @@ mod host {
         }
     }
 
+    fn hello_supports_codec(hello: &rift_core::Hello, codec: Codec) -> bool {
+        let wanted = match codec {
+            Codec::Av1 => RiftCodec::Av1,
+            Codec::Hevc => RiftCodec::Hevc,
+            Codec::H264 => RiftCodec::H264,
+        };
+        hello.supported_codecs.contains(&(wanted as i32))
+    }
+
+    /// The host encodes a single stream, so stereo content goes out packed
+    /// side by side when the headset accepts it and the frame is wide enough.
+    fn choose_stereo_mode(
+        hello: &rift_core::Hello,
+        resolution: &ProtoResolution,
+    ) -> RiftStereoMode {
+        let offered = |mode: RiftStereoMode| hello.stereo_modes.contains(&(mode as i32));
+        if hello.stereo_modes.is_empty() {
+            RiftStereoMode::StereoAuto
+        } else if offered(RiftStereoMode::StereoSideBySide)
+            && resolution.width >= resolution.height * 2
+        {
+            RiftStereoMode::StereoSideBySide
+        } else if offered(RiftStereoMode::StereoMono) {
+            RiftStereoMode::StereoMono
+        } else {
+            RiftStereoMode::StereoAuto
+        }
+    }
+
+    /// First layout in the client's preference order that the host can capture.
+    fn choose_audio_layout(hello: &rift_core::Hello, multichannel: bool) -> RiftAudioLayout {
+        hello
+            .audio_layouts
+            .iter()
+            .filter_map(|layout| RiftAudioLayout::try_from(*layout).ok())
+            .find(|layout| multichannel || *layout == RiftAudioLayout::AudioStereo)
+            .unwrap_or(RiftAudioLayout::AudioStereo)
+    }
+
+    /// Host defaults overridden by whatever the client asked for; zero
+    /// fields leave the default in place.
+    fn choose_opus_config(hello: &rift_core::Hello) -> OpusConfig {
+        let mut config = OpusConfig::default();
+        if let Some(params) = hello.audio_params.as_ref() {
+            if params.bitrate_kbps > 0 {
+                config.bitrate_bps = params.bitrate_kbps.saturating_mul(1000);
+            }
+            if params.frame_duration_us > 0 {
+                config.frame_duration_us = params.frame_duration_us;
+            }
+            config.inband_fec = params.inband_fec;
+            if params.inband_fec {
+                config.expected_loss_percent = AUDIO_FEC_LOSS_PERCENT;
+            }
+        }
+        config.normalized()
+    }
+
+    fn opus_config_to_proto(config: OpusConfig) -> ProtoAudioParams {
+        ProtoAudioParams {
+            bitrate_kbps: config.bitrate_bps / 1000,
+            frame_duration_us: config.frame_duration_us,
+            inband_fec: config.inband_fec,
+        }
+    }
+
+    fn audio_layout_from_proto(layout: RiftAudioLayout) -> AudioChannelLayout {
+        match layout {
+            RiftAudioLayout::AudioStereo => AudioChannelLayout::Stereo,
+            RiftAudioLayout::AudioSurround51 => AudioChannelLayout::Surround51,
+            RiftAudioLayout::AudioSurround71 => AudioChannelLayout::Surround71,
+            RiftAudioLayout::AudioAmbisonicFoa => AudioChannelLayout::AmbisonicFoa,
+        }
+    }
+
+    /// Headsets stream at their display rate; flat clients get the configured rate.
+    fn choose_stream_fps(hello: &rift_core::Hello, default_fps: u32) -> u32 {
+        if hello.stereo_modes.is_empty() || hello.max_fps == 0 {
+            default_fps
+        } else {
+            hello.max_fps.clamp(MIN_VR_FPS, MAX_VR_FPS)
+        }
+    }
+
     fn filter_realtime_codecs(
         caps: Vec<wavry_media::VideoCodecCapability>,
         fallback: Vec<Codec>,
@@ -722,60 +1512,308 @@ mod host {
         vec![]
     }
 
+    /// Hot-plug notifications from the platform probe, where it has them.
+    fn watch_display_changes() -> Option<DisplayWatch> {
+        #[cfg(target_os = "linux")]
+        let probe = LinuxProbe;
+        #[cfg(target_os = "macos")]
+        let probe = MacProbe;
+        #[cfg(target_os = "windows")]
+        let probe = WindowsProbe;
+        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+        let probe = wavry_media::NullProbe;
+
+        match probe.watch_displays() {
+            Ok(watch) => Some(watch),
+            Err(err) => {
+                info!("display hot-plug detection unavailable: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Display to keep capturing after the layout changed: the current one if
+    /// it is still listed, otherwise the first that is.
+    fn surviving_display(current: Option<u32>, monitors: &[rift_core::MonitorInfo]) -> Option<u32> {
+        current
+            .filter(|id| monitors.iter().any(|monitor| monitor.id == *id))
+            .or_else(|| monitors.first().map(|monitor| monitor.id))
+    }
+
     impl PeerState {
-        fn new(no_encrypt: bool, initial_bitrate_kbps: u32) -> Self {
+        fn new(identity: Option<&IdentityKeypair>, initial_bitrate_kbps: u32) -> Self {
             let now = time::Instant::now();
+            let session_alias = rand::random::<u32>().max(1);
+            let span = session_span("server");
+            span.record_session_alias(session_alias);
             Self {
-                crypto: CryptoState::new(no_encrypt),
+                crypto: CryptoState::new(identity),
                 handshake: Handshake::new(Role::Host),
                 pending_crypto_msg2: None,
                 session_id: None,
-                session_alias: rand::random::<u32>().max(1),
+                session_alias,
+                resume_attempt: 0,
                 next_packet_id: 1,
                 frame_id: 0,
                 pacer: Pacer::new(),
                 send_history: SendHistory::new(NACK_HISTORY),
                 target_bitrate_kbps: initial_bitrate_kbps,
+                cc: peer_cc(initial_bitrate_kbps, None),
+                bitrate_cap_kbps: None,
+                probe: None,
                 skip_frames: 0,
                 fec_builder: FecBuilder::new(FEC_SHARD_COUNT).unwrap(),
                 last_seen: now,
                 last_stats_log: now,
                 client_name: None,
+                foveation: None,
+                codec: None,
+                stream_resolution: None,
+                stereo_mode: RiftStereoMode::StereoAuto,
+                audio_layout: RiftAudioLayout::AudioStereo,
+                audio_opus: OpusConfig::default(),
+                restart_encoder: false,
+                vr_timing: None,
+                steamvr_input: Vec::new(),
+                awaiting_keyframe: false,
+                cursor_shape_sent: None,
+                recording_request: None,
+                compact_tx: None,
+                compact_rx: CompactDecoder::default(),
+                additional_monitors: Vec::new(),
+                monitor_frame_ids: HashMap::new(),
+                microphone: false,
+                permissions: PermissionSet::VIEW,
+                clipboard_sync: rift_core::ClipboardSync::default(),
+                session_started: None,
+                last_input: now,
+                idle: false,
+                virtual_mic: None,
+                span,
             }
         }
+
+        /// Restarts congestion control at `bitrate_kbps` with no client cap.
+        fn reset_bitrate(&mut self, bitrate_kbps: u32) {
+            self.bitrate_cap_kbps = None;
+            self.probe = None;
+            self.cc = peer_cc(bitrate_kbps, None);
+            self.sync_target_bitrate();
+        }
+
+        /// Applies the client's bitrate ceiling, keeping the current target
+        /// where it still fits.
+        fn set_bitrate_cap(&mut self, cap_kbps: u32) {
+            let cap = cap_kbps.clamp(MIN_PEER_BITRATE_KBPS, MAX_PEER_BITRATE_KBPS);
+            self.bitrate_cap_kbps = Some(cap);
+            self.cc = peer_cc(self.cc.target_bitrate_kbps(), Some(cap));
+            self.sync_target_bitrate();
+        }
+
+        /// Takes DELTA's latest target, capped at the client's ceiling.
+        /// Returns whether it moved.
+        fn sync_target_bitrate(&mut self) -> bool {
+            let target = self
+                .bitrate_cap_kbps
+                .map_or(self.cc.target_bitrate_kbps(), |cap| {
+                    self.cc.target_bitrate_kbps().min(cap)
+                });
+            let moved = target != self.target_bitrate_kbps;
+            self.target_bitrate_kbps = target;
+            moved
+        }
     }
 
-    type FrameIn = EncodedFrame;
+    /// DELTA for one peer, bounded to the range the server streams at and to
+    /// the client's ceiling. The server picks frame rates itself, so only the
+    /// bitrate side of the controller is used.
+    fn peer_cc(bitrate_kbps: u32, cap_kbps: Option<u32>) -> DeltaCC {
+        let max_bitrate_kbps =
+            cap_kbps.map_or(MAX_PEER_BITRATE_KBPS, |cap| cap.min(MAX_PEER_BITRATE_KBPS));
+        let config = DeltaConfig {
+            min_bitrate_kbps: MIN_PEER_BITRATE_KBPS.min(max_bitrate_kbps),
+            max_bitrate_kbps,
+            ..DeltaConfig::default()
+        };
+        let bitrate_kbps = bitrate_kbps.clamp(config.min_bitrate_kbps, config.max_bitrate_kbps);
+        DeltaCC::new(config, bitrate_kbps, 0)
+    }
 
-    #[derive(Debug)]
-    struct SendHistory {
-        capacity: usize,
-        order: VecDeque<u64>,
-        packets: HashMap<u64, Bytes>,
+    /// Stream settings negotiated by the first session, offered unchanged to
+    /// peers that join while it is running.
+    #[derive(Debug, Clone, Copy)]
+    struct SharedStream {
+        codec: Codec,
+        resolution: ProtoResolution,
+        fps: u32,
+        stereo_mode: RiftStereoMode,
+        audio_layout: RiftAudioLayout,
+        audio_opus: OpusConfig,
+        /// The pointer is left out of video and sent as cursor updates.
+        cursor_channel: bool,
     }
 
-    impl SendHistory {
-        fn new(capacity: usize) -> Self {
-            Self {
-                capacity,
-                order: VecDeque::new(),
-                packets: HashMap::new(),
-            }
+    /// Peers currently receiving video, in the order they joined. The first
+    /// one drives encoder settings, VR pacing, SteamVR input and file transfer.
+    #[derive(Debug, Default)]
+    struct StreamSessions {
+        peers: Vec<SocketAddr>,
+        shared: Option<SharedStream>,
+    }
+
+    impl StreamSessions {
+        fn primary(&self) -> Option<SocketAddr> {
+            self.peers.first().copied()
         }
 
-        fn insert(&mut self, packet_id: u64, payload: Bytes) {
-            if !self.packets.contains_key(&packet_id) {
-                self.order.push_back(packet_id);
-            }
-            self.packets.insert(packet_id, payload);
-            while self.order.len() > self.capacity {
-                if let Some(oldest) = self.order.pop_front() {
-                    self.packets.remove(&oldest);
-                }
-            }
+        fn is_primary(&self, peer: SocketAddr) -> bool {
+            self.primary() == Some(peer)
         }
 
-        fn get(&self, packet_id: u64) -> Option<Bytes> {
+        fn contains(&self, peer: SocketAddr) -> bool {
+            self.peers.contains(&peer)
+        }
+
+        fn is_empty(&self) -> bool {
+            self.peers.is_empty()
+        }
+
+        /// Settings a Hello from `peer` must accept, if another session is live.
+        fn shared_for(&self, peer: SocketAddr) -> Option<SharedStream> {
+            self.shared
+                .filter(|_| self.peers.iter().any(|active| *active != peer))
+        }
+
+        fn remove(&mut self, peer: SocketAddr) -> bool {
+            let before = self.peers.len();
+            self.peers.retain(|active| *active != peer);
+            if self.peers.is_empty() {
+                self.shared = None;
+            }
+            self.peers.len() != before
+        }
+    }
+
+    /// Tees the encoded stream into a recorder while recording is on. Like a
+    /// peer joining mid-stream, a recording starts at the next keyframe.
+    struct RecordingController {
+        config: RecorderConfig,
+        /// Clients may start and stop recording.
+        remote_control: bool,
+        recorder: Option<VideoRecorder>,
+    }
+
+    impl RecordingController {
+        fn new(config: RecorderConfig, start: bool, remote_control: bool) -> Result<Self> {
+            let mut controller = Self {
+                config,
+                remote_control,
+                recorder: None,
+            };
+            if start {
+                controller.start()?;
+            }
+            Ok(controller)
+        }
+
+        fn is_recording(&self) -> bool {
+            self.recorder.is_some()
+        }
+
+        fn start(&mut self) -> Result<()> {
+            if self.recorder.is_none() {
+                self.recorder = Some(VideoRecorder::new(self.config.clone())?);
+                info!("recording to {}", self.config.output_dir.display());
+            }
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            if let Some(mut recorder) = self.recorder.take() {
+                recorder.finalize()?;
+                info!("recording stopped");
+            }
+            Ok(())
+        }
+
+        /// Applies a client's start or stop request and reports the outcome.
+        fn handle_request(&mut self, peer: SocketAddr, start: bool) -> rift_core::RecordingStatus {
+            let result = if !self.remote_control {
+                Err(anyhow!("host does not accept recording requests"))
+            } else if start {
+                self.start()
+            } else {
+                self.stop()
+            };
+            if let Err(err) = &result {
+                warn!("recording request from {} refused: {}", peer, err);
+            }
+            rift_core::RecordingStatus {
+                recording: self.is_recording(),
+                error: result.err().map(|err| err.to_string()).unwrap_or_default(),
+            }
+        }
+
+        fn on_video(&mut self, frame: &EncodedFrame, codec: Codec, config: &EncodeConfig) {
+            let Some(recorder) = self.recorder.as_mut() else {
+                return;
+            };
+            if let Err(err) = recorder.write_frame(
+                &frame.data,
+                frame.keyframe,
+                codec,
+                config.resolution,
+                config.fps,
+                frame.timestamp_us,
+            ) {
+                warn!("recording stopped after write error: {}", err);
+                self.recorder = None;
+            }
+        }
+
+        fn on_audio(&mut self, packet: &EncodedFrame, layout: RiftAudioLayout) {
+            let Some(recorder) = self.recorder.as_mut() else {
+                return;
+            };
+            recorder.set_audio_channels(audio_layout_from_proto(layout).channels() as u8);
+            if let Err(err) = recorder.write_audio(&packet.data, packet.timestamp_us) {
+                warn!("recording stopped after write error: {}", err);
+                self.recorder = None;
+            }
+        }
+    }
+
+    type FrameIn = EncodedFrame;
+
+    #[derive(Debug)]
+    struct SendHistory {
+        capacity: usize,
+        order: VecDeque<u64>,
+        packets: HashMap<u64, Bytes>,
+    }
+
+    impl SendHistory {
+        fn new(capacity: usize) -> Self {
+            Self {
+                capacity,
+                order: VecDeque::new(),
+                packets: HashMap::new(),
+            }
+        }
+
+        fn insert(&mut self, packet_id: u64, payload: Bytes) {
+            if !self.packets.contains_key(&packet_id) {
+                self.order.push_back(packet_id);
+            }
+            self.packets.insert(packet_id, payload);
+            while self.order.len() > self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.packets.remove(&oldest);
+                }
+            }
+        }
+
+        fn get(&self, packet_id: u64) -> Option<Bytes> {
             self.packets.get(&packet_id).cloned()
         }
     }
@@ -880,7 +1918,7 @@ mod host {
         fn capacity_for(rate_kbps: u32) -> f64 {
             // Keep up to 500ms of burst budget while still constraining sustained rate.
             let bytes_per_second = rate_kbps as f64 * 1000.0 / 8.0;
-            (bytes_per_second * 0.5).max((DEFAULT_CHUNK_SIZE as f64) * 4.0)
+            (bytes_per_second * 0.5).max((DEFAULT_FILE_CHUNK_BYTES as f64) * 4.0)
         }
 
         fn set_rate_kbps(&mut self, rate_kbps: u32) {
@@ -1069,21 +2107,21 @@ mod host {
     }
 
     pub async fn run() -> Result<()> {
-        let args = Args::parse();
+        let args = Args::load()?;
         tracing_subscriber::fmt().with_env_filter("info").init();
 
-        let runtime = validate_runtime_config(&args)?;
+        let mut runtime = validate_runtime_config(&args)?;
         if !args.listen.ip().is_loopback() && !env_bool("WAVRY_SERVER_ALLOW_PUBLIC_BIND", false) {
             return Err(anyhow!(
                 "refusing non-loopback server bind without WAVRY_SERVER_ALLOW_PUBLIC_BIND=1"
             ));
         }
 
-        let socket = UdpSocket::bind(args.listen).await?;
+        let socket = bind_udp(args.listen)?;
         let local_addr = socket.local_addr()?;
         info!("listening on {}", local_addr);
 
-        if let Err(e) = SockRef::from(&socket).set_tos_v4(DSCP_EF) {
+        if let Err(e) = set_tos(&socket, DSCP_EF) {
             debug!("failed to set DSCP/TOS: {}", e);
         }
 
@@ -1096,19 +2134,40 @@ mod host {
         } else {
             Some(advertise_mdns(local_addr)?)
         };
+        let mut port_mapping: Option<(PortMapper, watch::Receiver<Option<SocketAddr>>)> =
+            if args.disable_port_mapping || local_addr.ip().is_loopback() {
+                None
+            } else {
+                Some(PortMapper::spawn(local_addr.port()))
+            };
 
         let mut injector = InjectorImpl::new()?;
+        // SteamVR frames come from the headset compositor, which has no desktop pointer.
+        let mut cursor_capturer = if runtime.steamvr {
+            None
+        } else {
+            open_cursor_capturer()
+        };
+        runtime.cursor_channel = cursor_capturer.is_some();
         let mut clipboard = ArboardClipboard::new().ok();
         let mut last_clipboard_text = clipboard.as_mut().and_then(|c| c.get_text().ok()).flatten();
 
         let (webrtc_input_tx, mut webrtc_input_rx) =
             mpsc::unbounded_channel::<rift_core::input_message::Event>();
+        let (webrtc_control_tx, mut webrtc_control_rx) = mpsc::unbounded_channel::<ViewerMessage>();
+        let mut viewer_stats_logs: HashMap<String, time::Instant> = HashMap::new();
 
         let webrtc_bridge = if args.enable_webrtc {
             if let Some(token) = &args.session_token {
                 let bridge = Arc::new(
-                    WebRtcBridge::new(args.gateway_url.clone(), token.clone(), webrtc_input_tx)
-                        .await?,
+                    WebRtcBridge::new(
+                        args.gateway_url.clone(),
+                        token.clone(),
+                        webrtc_input_tx,
+                        webrtc_control_tx,
+                        runtime.initial_bitrate_kbps,
+                    )
+                    .await?,
                 );
                 let bridge_clone = Arc::clone(&bridge);
                 tokio::spawn(async move {
@@ -1134,35 +2193,68 @@ mod host {
             display_id: args.display_id,
             enable_10bit: false,
             enable_hdr: false,
+            hide_cursor: false,
+            capture_mode: args.capture_mode,
+            tuning: EncoderTuning::for_codec(Codec::H264, args.content),
         };
 
-        let mut recorder = if args.record {
-            let quality = match args.record_quality.to_lowercase().as_str() {
-                "high" => Quality::High,
-                "low" => Quality::Low,
-                _ => Quality::Standard,
-            };
-            Some(VideoRecorder::new(RecorderConfig {
+        // Live encoder bitrate override; 0 keeps the configured rate.
+        let encoder_bitrate_target = Arc::new(AtomicU32::new(0));
+        // Pending gaze for the encoder thread to turn into a QP offset map.
+        let encoder_foveation = Arc::new(Mutex::new(None));
+        // Vsync-aligned frame schedule, set once a headset reports its phase.
+        let encoder_pacing = Arc::new(Mutex::new(None));
+
+        // In SteamVR mode the driver encodes the compositor output, so its
+        // frames stand in for desktop capture.
+        let (steamvr, steamvr_frames, mut steamvr_haptics) = if runtime.steamvr {
+            let (bridge, events) = SteamVrBridge::listen(args.steamvr_bridge)?;
+            let (frames, haptics) = spawn_steamvr_relay(events);
+            (Some(bridge), Some(frames), Some(haptics))
+        } else {
+            (None, None, None)
+        };
+        let mut steamvr_bitrate_kbps = 0u32;
+
+        let quality = match args.record_quality.to_lowercase().as_str() {
+            "high" => Quality::High,
+            "low" => Quality::Low,
+            _ => Quality::Standard,
+        };
+        let container = match args.record_format.to_lowercase().as_str() {
+            "mp4" => Container::Mp4,
+            _ => Container::Matroska,
+        };
+        let mut recording = RecordingController::new(
+            RecorderConfig {
                 enabled: true,
-                output_dir: PathBuf::from(args.record_dir),
+                output_dir: args.record_dir,
+                max_file_size_mb: args.record_segment_mb,
+                max_segment_secs: args.record_segment_secs,
                 quality,
+                container,
                 ..Default::default()
-            })?)
-        } else {
-            None
-        };
+            },
+            args.record,
+            args.record_on_request,
+        )?;
 
         let audio_source = AudioRouteSource::parse(&args.audio_source);
-        let mut audio_rx = match start_audio_capture(audio_source.clone()).await {
-            Ok(rx) => {
-                info!("audio capture enabled ({:?})", audio_source);
-                Some(rx)
-            }
-            Err(err) => {
-                warn!("audio capture disabled: {}", err);
-                None
-            }
-        };
+        let mut audio_layout = RiftAudioLayout::AudioStereo;
+        let mut audio_opus = OpusConfig::default();
+        let mut audio_rx =
+            match start_audio_capture(audio_source.clone(), AudioChannelLayout::Stereo, audio_opus)
+                .await
+            {
+                Ok(rx) => {
+                    info!("audio capture enabled ({:?})", audio_source);
+                    Some(rx)
+                }
+                Err(err) => {
+                    warn!("audio capture disabled: {}", err);
+                    None
+                }
+            };
 
         let mut file_transfer = FileTransferState::new(
             &args.send_files,
@@ -1174,57 +2266,162 @@ mod host {
 
         let mut buf = vec![0u8; 64 * 1024];
         let mut peers: HashMap<SocketAddr, PeerState> = HashMap::new();
-        let mut active_peer: Option<SocketAddr> = None;
-        let mut frame_rx: Option<mpsc::Receiver<FrameIn>> = None;
+        let mut sessions = StreamSessions::default();
+        let mut frame_rx: Option<mpsc::Receiver<FrameIn>> = steamvr_frames;
         let mut selected_codec: Option<Codec> = None;
         let mut current_display_id: Option<u32> = None;
+        let mut current_fps: Option<u16> = None;
+        let mut current_hide_cursor: Option<bool> = None;
+        let (monitor_frame_tx, mut monitor_frame_rx) = mpsc::channel::<(u32, FrameIn)>(4);
+        let mut monitor_streams: Vec<MonitorStream> = Vec::new();
+        // SteamVR renders its own frames, so display changes don't concern it.
+        let mut display_watch = if steamvr.is_none() {
+            watch_display_changes()
+        } else {
+            None
+        };
         let local_supported = local_supported_encoders();
         info!("Local encoder candidates: {:?}", local_supported);
-        let no_encrypt = args.no_encrypt;
+        let host_identity = if args.no_encrypt {
+            None
+        } else {
+            Some(load_or_create_host_identity(args.identity_key.clone())?)
+        };
         let mut peer_cleanup_interval =
             time::interval(Duration::from_secs(PEER_CLEANUP_INTERVAL_SECS));
         let mut clipboard_poll_interval = time::interval(Duration::from_millis(500));
+        let mut cursor_poll_interval = time::interval(Duration::from_millis(CURSOR_POLL_MS));
+        cursor_poll_interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        let mut cursor: Option<CursorState> = None;
         let mut file_transfer_tick = time::interval(Duration::from_millis(FILE_TRANSFER_TICK_MS));
+        let mut probe_tick = time::interval(PROBE_TICK);
+        probe_tick.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
-        if args.enable_webrtc && selected_codec.is_none() {
+        if args.enable_webrtc && selected_codec.is_none() && steamvr.is_none() {
             ensure_encoder(
                 &mut frame_rx,
                 &mut selected_codec,
                 &mut current_display_id,
+                &mut current_fps,
+                &mut current_hide_cursor,
                 base_config,
                 Codec::H264,
+                &encoder_bitrate_target,
+                &encoder_foveation,
+                &encoder_pacing,
+                runtime.encode_thread,
             )
             .await?;
         }
 
+        // The main loop below drives every socket send on this thread.
+        if let Err(err) = runtime.send_thread.apply_current() {
+            warn!(
+                "send thread tuning ({:?}) failed: {}",
+                runtime.send_thread, err
+            );
+        }
+
+        let mut approvals = ClientApprovals::open(&args)?;
+        let mut operator_rx = spawn_stdin_lines();
+        info!("client permissions: {}", runtime.permissions);
+
+        let mut wake_lock = WakeLock::new("Streaming to a Wavry client");
+        let mut was_streaming = false;
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+
         loop {
+            let streaming = !sessions.is_empty();
+            if let Err(err) = wake_lock.set_active(streaming) {
+                warn!("Failed to keep the display awake: {}", err);
+            }
+            if was_streaming && !streaming && runtime.lock_on_disconnect {
+                if let Err(err) = wavry_platform::lock_session() {
+                    warn!("Failed to lock the host session: {:#}", err);
+                }
+            }
+            was_streaming = streaming;
             tokio::select! {
+                _ = &mut shutdown => {
+                    info!("shutting down");
+                    break;
+                }
+                Some(external) = async {
+                    let (_, external) = port_mapping.as_mut()?;
+                    external.changed().await.ok()?;
+                    let addr = *external.borrow();
+                    Some(addr)
+                } => {
+                    runtime.external_addr = external;
+                }
+                Some(line) = operator_rx.recv() => {
+                    let result = match line.trim().strip_prefix("permissions ") {
+                        Some(set) => match set.parse::<PermissionSet>() {
+                            Ok(permissions) => {
+                                runtime.permissions = permissions;
+                                update_permissions(&socket, &mut peers, &sessions, permissions).await;
+                                Ok(())
+                            }
+                            Err(err) => Err(anyhow!(err)),
+                        },
+                        None => approvals.apply(&line),
+                    };
+                    if let Err(err) = result {
+                        warn!("{}: {}", line.trim(), err);
+                    }
+                }
                 Some(event) = webrtc_input_rx.recv() => {
+                    if !runtime.permissions.contains(PermissionSet::INPUT) {
+                        continue;
+                    }
                     if let Err(e) = handle_input_event(&mut injector, event) {
                         warn!("WebRTC input injection failed: {}", e);
                     }
                 }
+                Some(message) = webrtc_control_rx.recv() => {
+                    handle_viewer_message(
+                        message,
+                        runtime,
+                        &mut viewer_stats_logs,
+                        &mut clipboard,
+                        &mut last_clipboard_text,
+                        &mut file_transfer,
+                    );
+                }
                 _ = peer_cleanup_interval.tick() => {
                     cleanup_inactive_peers(
                         &mut peers,
-                        &mut active_peer,
+                        &mut sessions,
                         runtime.peer_idle_timeout,
                     );
+                    enforce_session_limits(&socket, &mut peers, &mut sessions, runtime).await;
+                    let wanted = wanted_monitors(&sessions, &peers, base_config.display_id);
+                    monitor_streams.retain(|stream| wanted.contains(&stream.display_id));
                 }
                 _ = clipboard_poll_interval.tick() => {
                     if let Some(ref mut c) = clipboard {
                         if let Ok(Some(current_text)) = c.get_text() {
                             if Some(current_text.clone()) != last_clipboard_text {
                                 last_clipboard_text = Some(current_text.clone());
-                                if let Some(peer) = active_peer {
-                                    if let Some(peer_state) = peers.get_mut(&peer) {
-                                        let msg = ProtoMessage {
-                                            content: Some(rift_core::message::Content::Control(ProtoControl {
-                                                content: Some(rift_core::control_message::Content::Clipboard(
-                                                    rift_core::ClipboardMessage { text: current_text }
-                                                )),
-                                            })),
-                                        };
+                                let content = rift_core::message::Content::Control(ProtoControl {
+                                    content: Some(rift_core::control_message::Content::Clipboard(
+                                        rift_core::ClipboardMessage { text: current_text }
+                                    )),
+                                });
+                                if let Some(ref bridge) = webrtc_bridge {
+                                    if runtime.permissions.contains(PermissionSet::CLIPBOARD) {
+                                        bridge.send_to_viewers(content.clone());
+                                    }
+                                }
+                                for &peer in &sessions.peers {
+                                    let Some(peer_state) = peers.get_mut(&peer) else {
+                                        continue;
+                                    };
+                                    if peer_state.permissions.contains(PermissionSet::CLIPBOARD)
+                                        && peer_state.clipboard_sync.from_host
+                                    {
+                                        let msg = ProtoMessage { content: Some(content.clone()) };
                                         let _ = send_rift_msg(&socket, peer_state, peer, msg).await;
                                     }
                                 }
@@ -1232,8 +2429,52 @@ mod host {
                         }
                     }
                 }
+                _ = cursor_poll_interval.tick(), if base_config.hide_cursor && !sessions.is_empty() => {
+                    let Some(capturer) = cursor_capturer.as_mut() else {
+                        continue;
+                    };
+                    let moved = match capturer.poll() {
+                        Ok(Some(state)) => {
+                            cursor = Some(state);
+                            true
+                        }
+                        Ok(None) => false,
+                        Err(err) => {
+                            debug!("cursor capture failed: {}", err);
+                            false
+                        }
+                    };
+                    let Some(cursor) = cursor.as_ref() else {
+                        continue;
+                    };
+                    for &peer in &sessions.peers {
+                        let Some(peer_state) = peers.get_mut(&peer).filter(|state| !state.idle) else {
+                            continue;
+                        };
+                        let with_shape = peer_state.cursor_shape_sent.is_none_or(|(serial, sent_at)| {
+                            serial != cursor.serial || sent_at.elapsed() >= CURSOR_SHAPE_REFRESH
+                        });
+                        if !moved && !with_shape {
+                            continue;
+                        }
+                        if with_shape {
+                            peer_state.cursor_shape_sent = Some((cursor.serial, time::Instant::now()));
+                        }
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Media(rift_core::MediaMessage {
+                                content: Some(rift_core::media_message::Content::Cursor(
+                                    cursor_update(cursor, with_shape),
+                                )),
+                            })),
+                        };
+                        let _ = send_rift_msg(&socket, peer_state, peer, msg).await;
+                    }
+                }
+                _ = probe_tick.tick(), if !sessions.is_empty() => {
+                    send_probes(&socket, &mut peers, &sessions).await;
+                }
                 _ = file_transfer_tick.tick() => {
-                    if let Some(peer) = active_peer {
+                    if let Some(peer) = sessions.primary() {
                         if let Some(peer_state) = peers.get_mut(&peer) {
                             if let Err(err) = send_next_file_chunk(
                                 &socket,
@@ -1246,6 +2487,20 @@ mod host {
                                 warn!("file transfer send error: {}", err);
                             }
                         }
+                    } else if let Some(bridge) = webrtc_bridge.as_ref().filter(|b| b.has_viewers()) {
+                        let video_kbps = bridge
+                            .target_bitrate_kbps()
+                            .await
+                            .unwrap_or(runtime.initial_bitrate_kbps);
+                        if let Err(err) = send_next_file_chunk_to_viewers(
+                            bridge,
+                            runtime,
+                            video_kbps,
+                            &mut file_transfer_limiter,
+                            &mut file_transfer.outgoing,
+                        ) {
+                            warn!("browser file transfer send error: {}", err);
+                        }
                     }
                 }
                 Some(frame) = async {
@@ -1255,25 +2510,159 @@ mod host {
                         None
                     }
                 } => {
-                    if let Some(ref mut rec) = recorder {
-                        if let Some(codec) = selected_codec {
-                            let _ = rec.write_frame(&frame.data, frame.keyframe, codec, base_config.resolution, base_config.fps);
-                        }
+                    if let Some(codec) = selected_codec {
+                        recording.on_video(&frame, codec, &base_config);
                     }
 
+                    // Every session shares this encoder, so the slowest peer sets its rate.
+                    let mut encoder_kbps = sessions
+                        .peers
+                        .iter()
+                        .filter_map(|peer| peers.get(peer))
+                        .map(|peer_state| peer_state.target_bitrate_kbps)
+                        .min();
                     if let Some(ref bridge) = webrtc_bridge {
                         let _ = bridge.push_frame(frame.clone()).await;
+                        if let Some(kbps) = bridge.target_bitrate_kbps().await {
+                            encoder_kbps = Some(encoder_kbps.map_or(kbps, |native| native.min(kbps)));
+                        }
+                    }
+                    if let Some(kbps) = encoder_kbps {
+                        // Additional displays get an equal share of the link.
+                        let share = kbps / (1 + monitor_streams.len() as u32);
+                        encoder_bitrate_target.store(share, Ordering::Relaxed);
+                        for stream in &monitor_streams {
+                            stream.bitrate_target.store(share, Ordering::Relaxed);
+                        }
                     }
 
-                    if let Some(peer) = active_peer {
-                        if let Some(peer_state) = peers.get_mut(&peer) {
-                            if peer_state.skip_frames > 0 {
-                                peer_state.skip_frames = peer_state.skip_frames.saturating_sub(1);
+                    if let Some(peer_state) = sessions.primary().and_then(|peer| peers.get_mut(&peer)) {
+                        if let Some(params) = peer_state.foveation.take() {
+                            if let Ok(mut slot) = encoder_foveation.lock() {
+                                *slot = Some(params);
+                            }
+                        }
+                        if let Some(timing) = peer_state.vr_timing.take() {
+                            if let Ok(mut slot) = encoder_pacing.lock() {
+                                let pacer = slot.get_or_insert_with(|| VrFramePacer::new(timing.refresh_hz));
+                                pacer.set_refresh_hz(timing.refresh_hz);
+                                pacer.on_phase_error(timing.vsync_offset_us);
+                            }
+                        }
+                    }
+
+                    for &peer in &sessions.peers {
+                        let Some(peer_state) = peers.get_mut(&peer).filter(|state| !state.idle) else {
+                            continue;
+                        };
+                        if peer_state.skip_frames > 0 {
+                            peer_state.skip_frames = peer_state.skip_frames.saturating_sub(1);
+                            continue;
+                        }
+                        if peer_state.awaiting_keyframe {
+                            if !frame.keyframe {
                                 continue;
                             }
-                            let result = send_video_frame(&socket, peer, peer_state, frame).await;
-                            if let Err(err) = result {
-                                warn!("failed to send video frame to {}: {}", peer, err);
+                            peer_state.awaiting_keyframe = false;
+                        }
+                        let result = send_video_frame(&socket, peer, peer_state, frame.clone()).await;
+                        if let Err(err) = result {
+                            warn!("failed to send video frame to {}: {}", peer, err);
+                        }
+                    }
+                }
+                Some(()) = async {
+                    if let Some(watch) = display_watch.as_mut() {
+                        watch.changed().await
+                    } else {
+                        None
+                    }
+                } => {
+                    let monitors = tokio::task::spawn_blocking(get_monitor_list)
+                        .await
+                        .unwrap_or_default();
+                    let display = surviving_display(base_config.display_id, &monitors);
+                    info!(
+                        "display layout changed: {} displays, capturing {:?}",
+                        monitors.len(),
+                        display
+                    );
+                    if display.is_some() {
+                        base_config.display_id = display;
+                    }
+
+                    for &peer in &sessions.peers {
+                        let Some(peer_state) = peers.get_mut(&peer) else {
+                            continue;
+                        };
+                        peer_state
+                            .additional_monitors
+                            .retain(|id| monitors.iter().any(|monitor| monitor.id == *id));
+                        peer_state.monitor_frame_ids.clear();
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::MonitorList(
+                                    rift_core::MonitorList { monitors: monitors.clone() },
+                                )),
+                            })),
+                        };
+                        if let Err(err) = send_rift_msg(&socket, peer_state, peer, msg).await {
+                            debug!("failed to send monitor list to {}: {}", peer, err);
+                        }
+                    }
+
+                    // Ids can be reused for a different display after a change,
+                    // so every encoder restarts on the new layout.
+                    monitor_streams.clear();
+                    if let Some(codec) = selected_codec {
+                        frame_rx = None;
+                        if let Err(err) = ensure_encoder(&mut frame_rx, &mut selected_codec, &mut current_display_id, &mut current_fps, &mut current_hide_cursor, base_config, codec, &encoder_bitrate_target, &encoder_foveation, &encoder_pacing, runtime.encode_thread).await {
+                            warn!("encoder restart after display change failed: {}", err);
+                        }
+                        let wanted = wanted_monitors(&sessions, &peers, base_config.display_id);
+                        sync_monitor_streams(&mut monitor_streams, &wanted, &monitor_frame_tx, base_config, selected_codec, runtime.encode_thread).await;
+                    }
+                }
+                Some((display_id, frame)) = monitor_frame_rx.recv() => {
+                    for &peer in &sessions.peers {
+                        let Some(peer_state) = peers.get_mut(&peer).filter(|state| !state.idle) else {
+                            continue;
+                        };
+                        let Some(index) = peer_state
+                            .additional_monitors
+                            .iter()
+                            .position(|&id| id == display_id)
+                        else {
+                            continue;
+                        };
+                        if !frame.keyframe && !peer_state.monitor_frame_ids.contains_key(&display_id) {
+                            continue;
+                        }
+                        let frame_id = peer_state.monitor_frame_ids.entry(display_id).or_insert(0);
+                        let id = *frame_id;
+                        *frame_id = id.wrapping_add(1);
+                        let stream_id = rift_core::monitor_stream_id(index);
+                        if let Err(err) = send_stream_frame(&socket, peer, peer_state, frame.clone(), id, stream_id).await {
+                            warn!("failed to send display {} frame to {}: {}", display_id, peer, err);
+                        }
+                    }
+                }
+                Some(haptic) = async {
+                    if let Some(rx) = steamvr_haptics.as_mut() {
+                        rx.recv().await
+                    } else {
+                        None
+                    }
+                } => {
+                    if let Some(peer) = sessions.primary() {
+                        if let Some(peer_state) = peers.get_mut(&peer) {
+                            let msg = ProtoMessage {
+                                content: Some(rift_core::message::Content::Control(ProtoControl {
+                                    content: Some(rift_core::control_message::Content::Haptic(haptic)),
+                                })),
+                            };
+                            if let Err(err) = send_rift_msg(&socket, peer_state, peer, msg).await {
+                                debug!("failed to send haptics to {}: {}", peer, err);
                             }
                         }
                     }
@@ -1285,9 +2674,10 @@ mod host {
                         None
                     }
                 } => {
-                    if let Some(peer) = active_peer {
-                        if let Some(peer_state) = peers.get_mut(&peer) {
-                            if let Err(err) = send_audio_packet(&socket, peer, peer_state, audio_packet).await {
+                    recording.on_audio(&audio_packet, audio_layout);
+                    for &peer in &sessions.peers {
+                        if let Some(peer_state) = peers.get_mut(&peer).filter(|state| !state.idle) {
+                            if let Err(err) = send_audio_packet(&socket, peer, peer_state, audio_packet.clone(), audio_layout).await {
                                 debug!("failed to send audio packet to {}: {}", peer, err);
                             }
                         }
@@ -1297,6 +2687,26 @@ mod host {
                     let (len, peer) = recv?;
                     let raw = &buf[..len];
 
+                    // Connectivity checks from clients handed this host's
+                    // candidates over signaling; any other STUN is stray.
+                    if rift_core::ice::is_check(raw) {
+                        if let Some(response) = rift_core::ice::check_response(raw, peer) {
+                            let _ = socket.send_to(&response, peer).await;
+                        }
+                        continue;
+                    }
+
+                    if !peers.contains_key(&peer) {
+                        if let Some((old_addr, resume)) = open_resume(&mut peers, raw) {
+                            if let Err(err) =
+                                resume_session(&socket, &mut peers, &mut sessions, old_addr, peer, resume).await
+                            {
+                                debug!("resume from {} rejected: {}", peer, err);
+                            }
+                            continue;
+                        }
+                    }
+
                     if !peers.contains_key(&peer) && peers.len() >= runtime.max_peers {
                         warn!(
                             "dropping packet from {}: peer table full (max_peers={})",
@@ -1307,12 +2717,13 @@ mod host {
 
                     let peer_state = peers
                         .entry(peer)
-                        .or_insert_with(|| PeerState::new(no_encrypt, runtime.initial_bitrate_kbps));
+                        .or_insert_with(|| PeerState::new(host_identity.as_ref(), runtime.initial_bitrate_kbps));
+                    let span = peer_state.span.clone();
 
                     match handle_raw_packet(
                         &socket,
                         peer_state,
-                        &mut active_peer,
+                        &mut sessions,
                         peer,
                         raw,
                         &mut injector,
@@ -1322,14 +2733,49 @@ mod host {
                         &mut clipboard,
                         &mut last_clipboard_text,
                         &mut file_transfer,
+                        &mut approvals,
                     )
+                    .instrument(span)
                     .await
                     {
                         Ok(Some(codec)) => {
-                            if let Err(err) =
-                                ensure_encoder(&mut frame_rx, &mut selected_codec, &mut current_display_id, base_config, codec).await
+                            // A new session or display starts unpaced until the headset reports again.
+                            if let Ok(mut pacer) = encoder_pacing.lock() {
+                                *pacer = None;
+                            }
+                            let restart_encoder = std::mem::take(&mut peer_state.restart_encoder);
+                            if let Some(bridge) = steamvr.as_ref() {
+                                // The driver encodes with the codec from its own session settings.
+                                selected_codec = Some(codec);
+                                bridge.send(&HostMessage::Refresh { refresh_hz: f32::from(base_config.fps) });
+                                bridge.send(&HostMessage::RequestIdr);
+                            } else {
+                                if restart_encoder {
+                                    // A fresh encoder opens on a keyframe.
+                                    frame_rx = None;
+                                }
+                                if let Err(err) =
+                                    ensure_encoder(&mut frame_rx, &mut selected_codec, &mut current_display_id, &mut current_fps, &mut current_hide_cursor, base_config, codec, &encoder_bitrate_target, &encoder_foveation, &encoder_pacing, runtime.encode_thread).await
+                                {
+                                    warn!("encoder start failed: {}", err);
+                                }
+                            }
+                            if audio_rx.is_some()
+                                && (peer_state.audio_layout != audio_layout
+                                    || peer_state.audio_opus != audio_opus)
                             {
-                                warn!("encoder start failed: {}", err);
+                                let layout = audio_layout_from_proto(peer_state.audio_layout);
+                                let opus = peer_state.audio_opus;
+                                match start_audio_capture(audio_source.clone(), layout, opus).await {
+                                    Ok(rx) => {
+                                        info!("audio capture switched to {:?} ({:?})", layout, opus);
+                                        audio_rx = Some(rx);
+                                        audio_layout = peer_state.audio_layout;
+                                        audio_opus = opus;
+                                    }
+                                    // Packets keep their layout tag, so the client follows along.
+                                    Err(err) => warn!("{:?} audio capture failed: {}", layout, err),
+                                }
                             }
                         }
                         Ok(None) => {}
@@ -1337,38 +2783,164 @@ mod host {
                             debug!("packet from {} dropped: {}", peer, e);
                         }
                     }
+                    if steamvr.is_none() {
+                        let wanted = wanted_monitors(&sessions, &peers, base_config.display_id);
+                        sync_monitor_streams(&mut monitor_streams, &wanted, &monitor_frame_tx, base_config, selected_codec, runtime.encode_thread).await;
+                    }
+                    let Some(peer_state) = peers.get_mut(&peer) else {
+                        continue;
+                    };
+                    if let Some(start) = peer_state.recording_request.take() {
+                        let status = recording.handle_request(peer, start);
+                        let msg = ProtoMessage {
+                            content: Some(rift_core::message::Content::Control(ProtoControl {
+                                content: Some(rift_core::control_message::Content::RecordingStatus(status)),
+                            })),
+                        };
+                        if let Err(err) = send_rift_msg(&socket, peer_state, peer, msg).await {
+                            debug!("failed to send recording status to {}: {}", peer, err);
+                        }
+                    }
+                    match steamvr.as_ref() {
+                        Some(bridge) if sessions.is_primary(peer) => forward_to_steamvr(
+                            bridge,
+                            peer_state,
+                            &mut steamvr_bitrate_kbps,
+                            base_config.fps,
+                        ),
+                        _ => peer_state.steamvr_input.clear(),
+                    }
                 }
             }
         }
+
+        if let Some((mapper, _)) = port_mapping {
+            mapper.shutdown().await;
+        }
+        Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
-    async fn handle_raw_packet(
-        socket: &UdpSocket,
-        peer_state: &mut PeerState,
-        active_peer: &mut Option<SocketAddr>,
-        peer: SocketAddr,
-        raw: &[u8],
-        injector: &mut InjectorImpl,
+    /// Resolves on Ctrl-C, or on SIGTERM where there is one.
+    async fn shutdown_signal() {
+        let ctrl_c = async {
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                warn!("cannot listen for Ctrl-C: {}", err);
+                std::future::pending::<()>().await;
+            }
+        };
+        #[cfg(unix)]
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = ctrl_c => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => ctrl_c.await,
+        }
+        #[cfg(not(unix))]
+        ctrl_c.await;
+    }
+
+    /// Stats, clipboard pastes and download acknowledgements from browser viewers.
+    fn handle_viewer_message(
+        message: ViewerMessage,
         runtime: HostRuntimeConfig,
-        local_supported: &[Codec],
-        base_config: &mut EncodeConfig,
+        stats_logs: &mut HashMap<String, time::Instant>,
         clipboard: &mut Option<ArboardClipboard>,
         last_clipboard_text: &mut Option<String>,
         file_transfer: &mut FileTransferState,
-    ) -> Result<Option<Codec>> {
-        peer_state.last_seen = time::Instant::now();
-        let phys = PhysicalPacket::decode(Bytes::copy_from_slice(raw))
-            .map_err(|e| anyhow!("RIFT decode error: {}", e))?;
-
-        match &mut peer_state.crypto {
-            CryptoState::Disabled => {
-                let msg =
-                    decode_msg(&phys.payload).map_err(|e| anyhow!("Proto decode error: {}", e))?;
+    ) {
+        let rift_core::message::Content::Control(ProtoControl {
+            content: Some(control),
+        }) = message.content
+        else {
+            debug!("ignoring browser file upload; web uploads are not supported");
+            return;
+        };
+        match control {
+            rift_core::control_message::Content::Stats(report) => {
+                let due = stats_logs
+                    .get(&message.peer_id)
+                    .is_none_or(|last| last.elapsed() >= runtime.stats_log_interval);
+                if due {
+                    let total = report.received_packets.saturating_add(report.lost_packets);
+                    let loss_percent = if total == 0 {
+                        0.0
+                    } else {
+                        (report.lost_packets as f64 * 100.0) / total as f64
+                    };
+                    info!(
+                        "stats from web viewer {}: rtt={}ms jitter={}us loss={:.2}%",
+                        message.peer_id,
+                        report.rtt_us / 1000,
+                        report.jitter_us,
+                        loss_percent
+                    );
+                    // Forget viewers that stopped reporting.
+                    stats_logs.retain(|_, last| last.elapsed() < runtime.stats_log_interval * 4);
+                    stats_logs.insert(message.peer_id, time::Instant::now());
+                }
+            }
+            rift_core::control_message::Content::Clipboard(_)
+                if !runtime.permissions.contains(PermissionSet::CLIPBOARD) =>
+            {
+                debug!("dropping clipboard update from browser: not permitted");
+            }
+            rift_core::control_message::Content::Clipboard(clip) => {
+                debug!("Received clipboard update from browser");
+                if let Some(ref mut c) = clipboard {
+                    let _ = c.set_text(clip.text.clone());
+                    *last_clipboard_text = Some(clip.text);
+                }
+            }
+            rift_core::control_message::Content::FileStatus(status) => {
+                let status_name = rift_core::file_status::Status::try_from(status.status)
+                    .map(|s| format!("{:?}", s))
+                    .unwrap_or_else(|_| format!("UNKNOWN({})", status.status));
+                info!(
+                    "browser file transfer status file_id={} status={} message={}",
+                    status.file_id,
+                    status_name,
+                    sanitize_file_status_message(&status.message)
+                );
+                apply_file_status_to_outgoing(&mut file_transfer.outgoing, &status);
+            }
+            _ => debug!("ignoring browser file upload; web uploads are not supported"),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_raw_packet(
+        socket: &UdpSocket,
+        peer_state: &mut PeerState,
+        sessions: &mut StreamSessions,
+        peer: SocketAddr,
+        raw: &[u8],
+        injector: &mut InjectorImpl,
+        runtime: HostRuntimeConfig,
+        local_supported: &[Codec],
+        base_config: &mut EncodeConfig,
+        clipboard: &mut Option<ArboardClipboard>,
+        last_clipboard_text: &mut Option<String>,
+        file_transfer: &mut FileTransferState,
+        approvals: &mut ClientApprovals,
+    ) -> Result<Option<Codec>> {
+        peer_state.last_seen = time::Instant::now();
+        let phys = peer_state
+            .compact_rx
+            .decode(Bytes::copy_from_slice(raw))
+            .map_err(|e| anyhow!("RIFT decode error: {}", e))?;
+
+        match &mut peer_state.crypto {
+            CryptoState::Disabled => {
+                let msg =
+                    decode_msg(&phys.payload).map_err(|e| anyhow!("Proto decode error: {}", e))?;
+                peer_state.compact_rx.accept(phys.packet_id);
                 handle_rift_msg(
                     socket,
                     peer_state,
-                    active_peer,
+                    sessions,
                     peer,
                     msg,
                     injector,
@@ -1378,6 +2950,7 @@ mod host {
                     clipboard,
                     last_clipboard_text,
                     file_transfer,
+                    approvals,
                 )
                 .await
             }
@@ -1432,13 +3005,14 @@ mod host {
                 let plaintext = server
                     .decrypt(phys.packet_id, &phys.payload)
                     .map_err(|e| anyhow!("Decrypt failed: {}", e))?;
+                peer_state.compact_rx.accept(phys.packet_id);
 
                 let msg =
                     decode_msg(&plaintext).map_err(|e| anyhow!("Proto decode error: {}", e))?;
                 handle_rift_msg(
                     socket,
                     peer_state,
-                    active_peer,
+                    sessions,
                     peer,
                     msg,
                     injector,
@@ -1448,6 +3022,7 @@ mod host {
                     clipboard,
                     last_clipboard_text,
                     file_transfer,
+                    approvals,
                 )
                 .await
             }
@@ -1458,7 +3033,7 @@ mod host {
     async fn handle_rift_msg(
         socket: &UdpSocket,
         peer_state: &mut PeerState,
-        active_peer: &mut Option<SocketAddr>,
+        sessions: &mut StreamSessions,
         peer: SocketAddr,
         msg: ProtoMessage,
         injector: &mut InjectorImpl,
@@ -1468,12 +3043,18 @@ mod host {
         clipboard: &mut Option<ArboardClipboard>,
         last_clipboard_text: &mut Option<String>,
         file_transfer: &mut FileTransferState,
+        approvals: &mut ClientApprovals,
     ) -> Result<Option<Codec>> {
         use rift_core::message::Content;
 
         let content = msg
             .content
             .ok_or_else(|| anyhow!("empty message content"))?;
+        let required = required_permission(&content);
+        if !peer_state.permissions.contains(required) {
+            debug!("dropping message from {}: {} not permitted", peer, required);
+            return Ok(None);
+        }
         match content {
             Content::Control(ctrl) => {
                 let ctrl_content = ctrl
@@ -1484,33 +3065,32 @@ mod host {
                         if !peer_state.crypto.is_established() {
                             return Err(anyhow!("crypto required before RIFT hello"));
                         }
+                        let identity = match &peer_state.crypto {
+                            CryptoState::Established(server) => server.remote_identity(),
+                            _ => None,
+                        };
+                        match approvals.admit(peer, identity, &hello.client_name) {
+                            Admission::Allowed => {}
+                            Admission::Denied => {
+                                return reject_hello(socket, peer_state, peer).await
+                            }
+                            Admission::Pending => return Ok(None),
+                        }
 
-                        if active_peer.is_some() && *active_peer != Some(peer) {
-                            let ack = ProtoHelloAck {
-                                accepted: false,
-                                selected_codec: 0,
-                                stream_resolution: None,
-                                fps: 0,
-                                initial_bitrate_kbps: 0,
-                                keyframe_interval_ms: 0,
-                                session_id: UNASSIGNED_SESSION_ID.to_vec(),
-                                session_alias: 0,
-                                public_addr: String::new(),
-                            };
-                            send_rift_msg(
-                                socket,
-                                peer_state,
-                                peer,
-                                ProtoMessage {
-                                    content: Some(Content::Control(ProtoControl {
-                                        content: Some(
-                                            rift_core::control_message::Content::HelloAck(ack),
-                                        ),
-                                    })),
-                                },
-                            )
-                            .await?;
-                            return Ok(None);
+                        if !sessions.contains(peer) && sessions.peers.len() >= runtime.max_streams {
+                            return reject_hello(socket, peer_state, peer).await;
+                        }
+                        // The encoder is already running for other sessions, so
+                        // a joining peer gets its settings rather than new ones.
+                        let shared = sessions.shared_for(peer);
+                        if let Some(shared) = shared {
+                            if !hello_supports_codec(&hello, shared.codec) {
+                                warn!(
+                                    "rejecting {}: shared stream codec {:?} unsupported by client",
+                                    peer, shared.codec
+                                );
+                                return reject_hello(socket, peer_state, peer).await;
+                            }
                         }
 
                         info!(
@@ -1526,16 +3106,52 @@ mod host {
                             .map_err(|e| anyhow!("Handshake error: {}", e))?;
 
                         let session_id = rand::random::<[u8; 16]>().to_vec();
+                        peer_state.span.record_session_id(&session_id);
                         peer_state.session_id = Some(session_id.clone());
                         peer_state.frame_id = 0;
                         peer_state.client_name = Some(hello.client_name.clone());
-                        peer_state.target_bitrate_kbps = runtime.initial_bitrate_kbps;
-
-                        let desired_codec = choose_codec_for_hello(&hello, local_supported);
-                        let stream_resolution = normalize_stream_resolution(
-                            hello.max_resolution,
-                            runtime.default_resolution,
-                        );
+                        peer_state.reset_bitrate(runtime.initial_bitrate_kbps);
+
+                        let stream = match shared {
+                            Some(shared) => shared,
+                            None => {
+                                let resolution = normalize_stream_resolution(
+                                    hello.max_resolution,
+                                    runtime.default_resolution,
+                                );
+                                let fps = choose_stream_fps(&hello, runtime.fps);
+                                let cursor_channel = hello.cursor_channel && runtime.cursor_channel;
+                                base_config.fps = fps as u16;
+                                base_config.hide_cursor = cursor_channel;
+                                SharedStream {
+                                    codec: choose_codec_for_hello(&hello, local_supported),
+                                    resolution,
+                                    fps,
+                                    stereo_mode: choose_stereo_mode(&hello, &resolution),
+                                    audio_layout: choose_audio_layout(
+                                        &hello,
+                                        runtime.multichannel_audio,
+                                    ),
+                                    audio_opus: choose_opus_config(&hello),
+                                    cursor_channel,
+                                }
+                            }
+                        };
+                        let desired_codec = stream.codec;
+                        let stream_resolution = stream.resolution;
+                        let fps = stream.fps;
+                        peer_state.codec = Some(desired_codec);
+                        peer_state.stream_resolution = Some(stream_resolution);
+                        peer_state.stereo_mode = stream.stereo_mode;
+                        peer_state.audio_layout = stream.audio_layout;
+                        peer_state.audio_opus = stream.audio_opus;
+                        peer_state.vr_timing = None;
+                        peer_state.microphone = hello.microphone && runtime.allow_microphone;
+                        if hello.microphone && !runtime.allow_microphone {
+                            info!("{} offered its microphone; passthrough not allowed", peer);
+                        }
+                        peer_state.permissions = runtime.permissions;
+                        peer_state.clipboard_sync = rift_core::ClipboardSync::negotiated(&hello);
                         let ack = ProtoHelloAck {
                             accepted: true,
                             selected_codec: match desired_codec {
@@ -1544,20 +3160,45 @@ mod host {
                                 Codec::H264 => RiftCodec::H264 as i32,
                             },
                             stream_resolution: Some(stream_resolution),
-                            fps: runtime.fps,
+                            fps,
                             initial_bitrate_kbps: runtime.initial_bitrate_kbps,
                             keyframe_interval_ms: runtime.keyframe_interval_ms,
                             session_id: session_id.clone(),
                             session_alias: peer_state.session_alias,
-                            public_addr: String::new(),
+                            public_addr: runtime
+                                .external_addr
+                                .map(|addr| addr.to_string())
+                                .unwrap_or_default(),
+                            candidate_addrs: vec![],
+                            stereo_mode: peer_state.stereo_mode as i32,
+                            audio_layout: peer_state.audio_layout as i32,
+                            fec_scheme: rift_core::fec::negotiate_scheme(&hello.fec_schemes) as i32,
+                            cursor_channel: stream.cursor_channel,
+                            audio_params: Some(opus_config_to_proto(stream.audio_opus)),
+                            compact_header: hello.compact_header,
+                            transport_feedback: false,
+                            microphone: peer_state.microphone,
+                            permissions: peer_state.permissions.to_wire(),
+                            input_batch: true,
                         };
+                        peer_state.cursor_shape_sent = None;
 
                         peer_state
                             .handshake
                             .on_send_hello_ack(&ack)
                             .map_err(|e| anyhow!("Handshake error: {}", e))?;
-                        *active_peer = Some(peer);
+                        if !sessions.contains(peer) {
+                            sessions.peers.push(peer);
+                            peer_state.session_started = Some(time::Instant::now());
+                            peer_state.last_input = time::Instant::now();
+                            peer_state.idle = false;
+                        }
+                        peer_state.awaiting_keyframe = shared.is_some();
+                        if shared.is_none() {
+                            sessions.shared = Some(stream);
+                        }
 
+                        let compact_header = ack.compact_header;
                         send_rift_msg(
                             socket,
                             peer_state,
@@ -1571,6 +3212,10 @@ mod host {
                             },
                         )
                         .await?;
+                        // The ack itself went out with a full header.
+                        if compact_header {
+                            peer_state.compact_tx = Some(CompactEncoder::default());
+                        }
 
                         // Send monitor list for discovery
                         let monitors = get_monitor_list();
@@ -1596,8 +3241,68 @@ mod host {
                             stream_resolution.height,
                             hex::encode(&session_id)
                         );
+                        if shared.is_some() {
+                            info!(
+                                "{} joined the shared stream ({} of {} sessions)",
+                                peer,
+                                sessions.peers.len(),
+                                runtime.max_streams
+                            );
+                            return Ok(None);
+                        }
                         return Ok(Some(desired_codec));
                     }
+                    rift_core::control_message::Content::StreamReconfigure(request) => {
+                        // Other sessions decode the same stream and can't follow a
+                        // layout change, so only a lone primary may reconfigure.
+                        if !sessions.is_primary(peer) || sessions.peers.len() > 1 {
+                            debug!("ignoring stream reconfigure from {}", peer);
+                            return Ok(None);
+                        }
+                        let (Some(codec), Some(resolution)) =
+                            (peer_state.codec, peer_state.stream_resolution)
+                        else {
+                            return Ok(None);
+                        };
+                        let offer = rift_core::Hello {
+                            stereo_modes: request.stereo_modes,
+                            max_fps: request.max_fps,
+                            ..Default::default()
+                        };
+                        peer_state.stereo_mode = choose_stereo_mode(&offer, &resolution);
+                        peer_state.vr_timing = None;
+                        peer_state.restart_encoder = true;
+                        let fps = choose_stream_fps(&offer, runtime.fps);
+                        base_config.fps = fps as u16;
+                        if let Some(shared) = sessions.shared.as_mut() {
+                            shared.stereo_mode = peer_state.stereo_mode;
+                            shared.fps = fps;
+                        }
+                        info!(
+                            "peer {} reconfigured VR stream: {:?} at {} fps",
+                            peer, peer_state.stereo_mode, fps
+                        );
+                        let reply = rift_core::StreamReconfigured {
+                            stereo_mode: peer_state.stereo_mode as i32,
+                            fps,
+                        };
+                        send_rift_msg(
+                            socket,
+                            peer_state,
+                            peer,
+                            ProtoMessage {
+                                content: Some(Content::Control(ProtoControl {
+                                    content: Some(
+                                        rift_core::control_message::Content::StreamReconfigured(
+                                            reply,
+                                        ),
+                                    ),
+                                })),
+                            },
+                        )
+                        .await?;
+                        return Ok(Some(codec));
+                    }
                     rift_core::control_message::Content::Ping(ping) => {
                         let pong = rift_core::Pong {
                             timestamp_us: ping.timestamp_us,
@@ -1631,8 +3336,31 @@ mod host {
                                 report.received_packets,
                                 report.lost_packets
                             );
+                            if let Some(total) =
+                                report.latency.as_ref().and_then(|l| l.total.as_ref())
+                            {
+                                info!(
+                                    "latency from {}: p50={}us p95={}us max={}us",
+                                    peer, total.p50_us, total.p95_us, total.max_us
+                                );
+                            }
                             peer_state.last_stats_log = time::Instant::now();
                         }
+                        let loss_ratio = if report.received_packets > 0 {
+                            report.lost_packets as f32
+                                / report.received_packets.saturating_add(report.lost_packets) as f32
+                        } else {
+                            0.0
+                        };
+                        peer_state
+                            .cc
+                            .on_rtt_sample(report.rtt_us, loss_ratio, report.jitter_us);
+                        if peer_state.sync_target_bitrate() {
+                            debug!(
+                                "peer {} congestion target update: {} kbps",
+                                peer, peer_state.target_bitrate_kbps
+                            );
+                        }
                         peer_state.pacer.on_stats(
                             report.rtt_us,
                             report.jitter_us,
@@ -1640,13 +3368,32 @@ mod host {
                         );
                     }
                     rift_core::control_message::Content::Congestion(cc) => {
-                        let requested = cc.target_bitrate_kbps.clamp(1_000, 100_000);
-                        if requested != peer_state.target_bitrate_kbps {
+                        // The client only sets a ceiling; the target stays DELTA's.
+                        if cc.max_bitrate_kbps > 0 {
+                            info!(
+                                "peer {} capped bitrate at {} kbps",
+                                peer, cc.max_bitrate_kbps
+                            );
+                            peer_state.set_bitrate_cap(cc.max_bitrate_kbps);
+                        }
+                    }
+                    rift_core::control_message::Content::Resume(resume) => {
+                        // Resumed from the address the session is already on.
+                        match accept_resume(peer_state, &resume) {
+                            Ok(()) => {
+                                send_rift_msg(socket, peer_state, peer, resume_ack(resume.attempt))
+                                    .await?;
+                            }
+                            Err(err) => debug!("resume from {} rejected: {}", peer, err),
+                        }
+                    }
+                    rift_core::control_message::Content::ProbeResult(result) => {
+                        peer_state.cc.on_probe_result(&result);
+                        if peer_state.sync_target_bitrate() {
                             debug!(
-                                "peer {} congestion target update: {} -> {} kbps",
-                                peer, peer_state.target_bitrate_kbps, requested
+                                "peer {} probe moved the target to {} kbps",
+                                peer, peer_state.target_bitrate_kbps
                             );
-                            peer_state.target_bitrate_kbps = requested;
                         }
                     }
                     rift_core::control_message::Content::Nack(nack) => {
@@ -1657,6 +3404,22 @@ mod host {
                             }
                         }
                     }
+                    rift_core::control_message::Content::RecordingControl(control) => {
+                        if sessions.contains(peer) {
+                            peer_state.recording_request = Some(control.start);
+                        }
+                    }
+                    rift_core::control_message::Content::PointerMode(mode) => {
+                        if sessions.contains(peer) {
+                            let mode = if mode.relative {
+                                PointerMode::Relative
+                            } else {
+                                PointerMode::Absolute
+                            };
+                            debug!("peer {} pointer mode {:?}", peer, mode);
+                            injector.set_pointer_mode(mode)?;
+                        }
+                    }
                     rift_core::control_message::Content::EncoderControl(ctrl) => {
                         if ctrl.skip_frames > 0 {
                             peer_state.skip_frames =
@@ -1664,20 +3427,65 @@ mod host {
                         }
                     }
                     rift_core::control_message::Content::PoseUpdate(pose) => {
-                        let _ = pose;
+                        if runtime.steamvr {
+                            peer_state
+                                .steamvr_input
+                                .extend(steamvr_head_tracking(&pose));
+                        }
                     }
                     rift_core::control_message::Content::HandPoseUpdate(hand_pose) => {
-                        let _ = hand_pose;
+                        if runtime.steamvr {
+                            peer_state
+                                .steamvr_input
+                                .extend(steamvr_controller_motion(&hand_pose));
+                        }
+                    }
+                    rift_core::control_message::Content::VrTiming(timing) => {
+                        if timing.refresh_hz.is_finite() && timing.refresh_hz > 0.0 {
+                            peer_state.vr_timing = Some(timing);
+                        }
+                    }
+                    rift_core::control_message::Content::Foveation(update) => {
+                        let finite = [update.gaze_x, update.gaze_y, update.radius]
+                            .iter()
+                            .all(|v| v.is_finite());
+                        if finite {
+                            let resolution = base_config.resolution;
+                            peer_state.foveation = Some(FoveationParams {
+                                gaze_x: update.gaze_x.clamp(0.0, 1.0),
+                                gaze_y: update.gaze_y.clamp(0.0, 1.0),
+                                radius: update.radius.clamp(0.02, 1.0),
+                                side_by_side: match peer_state.stereo_mode {
+                                    RiftStereoMode::StereoSideBySide => true,
+                                    // Same packing rule the VR client uses for its eye layout.
+                                    RiftStereoMode::StereoAuto => {
+                                        resolution.width as u32 >= resolution.height as u32 * 2
+                                    }
+                                    _ => false,
+                                },
+                            });
+                        }
                     }
-                    rift_core::control_message::Content::VrTiming(_timing) => {}
                     rift_core::control_message::Content::SelectMonitor(select) => {
-                        info!("Client selected monitor: {}", select.monitor_id);
+                        info!(
+                            "Client selected monitor: {} (additional: {:?})",
+                            select.monitor_id, select.additional_monitor_ids
+                        );
                         base_config.display_id = Some(select.monitor_id);
+                        let mut additional = select.additional_monitor_ids;
+                        additional.retain(|&id| id != select.monitor_id);
+                        additional.truncate(rift_core::MAX_ADDITIONAL_MONITORS);
+                        peer_state
+                            .monitor_frame_ids
+                            .retain(|id, _| additional.contains(id));
+                        peer_state.additional_monitors = additional;
                         return Ok(Some(base_config.codec));
                     }
                     rift_core::control_message::Content::Clipboard(clip) => {
                         if clip.text.len() > rift_core::MAX_CLIPBOARD_TEXT_BYTES {
                             warn!("Received clipboard message exceeds size limit ({} bytes), ignoring", clip.text.len());
+                        } else if !peer_state.clipboard_sync.to_host {
+                            debug!("Ignoring clipboard update from {}: client opted out", peer);
                         } else {
                             debug!("Received clipboard update from client");
                             if let Some(ref mut c) = clipboard {
@@ -1686,6 +3494,13 @@ mod host {
                             }
                         }
                     }
+                    rift_core::control_message::Content::ClipboardSync(sync) => {
+                        info!(
+                            "{} clipboard sync: to host {}, from host {}",
+                            peer, sync.to_host, sync.from_host
+                        );
+                        peer_state.clipboard_sync = sync;
+                    }
                     rift_core::control_message::Content::FileHeader(header) => {
                         let file_id = header.file_id;
                         match offer_from_proto(header, file_transfer.max_file_bytes) {
@@ -1812,12 +3627,30 @@ mod host {
                 }
             }
             Content::Input(input_msg) => {
-                if let Some(event) = input_msg.event {
-                    handle_input_event(injector, event)?;
+                peer_state.last_input = time::Instant::now();
+                if peer_state.idle {
+                    // Video picks up at the next keyframe, as for a joining peer.
+                    info!("{} sent input; resuming the stream", peer);
+                    peer_state.idle = false;
+                    peer_state.awaiting_keyframe = true;
+                    peer_state.monitor_frame_ids.clear();
+                }
+                for event in rift_core::input::unbundle_input(input_msg) {
+                    match event {
+                        // Headset controllers arrive as gamepads 0 (left) and 1 (right).
+                        rift_core::input_message::Event::Gamepad(gamepad)
+                            if runtime.steamvr && gamepad.gamepad_id < 2 =>
+                        {
+                            peer_state
+                                .steamvr_input
+                                .extend(steamvr_controller_input(&gamepad));
+                        }
+                        event => handle_input_event(injector, event)?,
+                    }
                 }
             }
-            Content::Media(media) => {
-                if let Some(rift_core::media_message::Content::FileChunk(chunk)) = media.content {
+            Content::Media(media) => match media.content {
+                Some(rift_core::media_message::Content::FileChunk(chunk)) => {
                     handle_incoming_file_chunk(
                         socket,
                         peer_state,
@@ -1827,20 +3660,61 @@ mod host {
                     )
                     .await?;
                 }
-            }
+                Some(rift_core::media_message::Content::Audio(packet))
+                    if packet.direction() == rift_core::AudioDirection::AudioFromClientMic =>
+                {
+                    play_client_microphone(peer_state, &packet);
+                }
+                _ => {}
+            },
         }
         Ok(None)
     }
 
+    /// Permission a client message needs; watching covers everything else.
+    fn required_permission(content: &rift_core::message::Content) -> PermissionSet {
+        use rift_core::control_message::Content as Control;
+        use rift_core::media_message::Content as Media;
+        use rift_core::message::Content;
+
+        match content {
+            Content::Input(_) => PermissionSet::INPUT,
+            Content::Control(ProtoControl {
+                content: Some(control),
+            }) => match control {
+                Control::PointerMode(_) => PermissionSet::INPUT,
+                Control::Clipboard(_) => PermissionSet::CLIPBOARD,
+                Control::FileHeader(_) => PermissionSet::FILE_TRANSFER,
+                _ => PermissionSet::VIEW,
+            },
+            Content::Media(rift_core::MediaMessage {
+                content: Some(Media::FileChunk(_)),
+            }) => PermissionSet::FILE_TRANSFER,
+            _ => PermissionSet::VIEW,
+        }
+    }
+
     fn handle_input_event(
         injector: &mut InjectorImpl,
         event: rift_core::input_message::Event,
     ) -> Result<()> {
         use rift_core::input_message::Event;
+        let Some(event) = rift_core::input::sanitize_input_event(event) else {
+            debug!("Dropped malformed input event");
+            return Ok(());
+        };
         match event {
             Event::Key(k) => injector.key(k.keycode, k.pressed)?,
+            Event::Text(t) => injector.text(&t.text)?,
             Event::MouseButton(m) => injector.mouse_button(m.button as u8, m.pressed)?,
-            Event::MouseMove(m) => injector.mouse_absolute(m.x, m.y)?,
+            Event::MouseMove(m) => {
+                injector.set_pointer_mode(PointerMode::Absolute)?;
+                injector.mouse_absolute(m.x, m.y)?;
+            }
+            Event::MouseRelative(m) => {
+                injector.set_pointer_mode(PointerMode::Relative)?;
+                injector.mouse_motion(m.dx, m.dy)?;
+            }
             Event::Scroll(s) => {
                 injector.scroll(s.dx, s.dy)?;
                 debug!("Scroll event injected: dx={}, dy={}", s.dx, s.dy);
@@ -1852,10 +3726,201 @@ mod host {
                 injector.gamepad(g.gamepad_id, &axes, &buttons)?;
                 debug!("Gamepad event injected for ID {}", g.gamepad_id);
             }
+            Event::Touch(t) => match rift_core::TouchPhase::try_from(t.phase) {
+                Ok(rift_core::TouchPhase::Down) => injector.touch_down(t.contact_id, t.x, t.y)?,
+                Ok(rift_core::TouchPhase::Move) => injector.touch_move(t.contact_id, t.x, t.y)?,
+                Ok(rift_core::TouchPhase::Up) => injector.touch_up(t.contact_id)?,
+                Err(_) => {}
+            },
+            // Already unbundled; sanitizing drops nested batches.
+            Event::Batch(_) => {}
         }
         Ok(())
     }
 
+    fn all_finite(values: &[f32]) -> bool {
+        values.iter().all(|v| v.is_finite())
+    }
+
+    /// Client pose timestamps key the driver's frames, so a frame's timestamp
+    /// is that of the pose it was rendered at.
+    fn steamvr_head_tracking(pose: &rift_core::PoseUpdate) -> Option<HostMessage> {
+        let position = [pose.position_x, pose.position_y, pose.position_z];
+        let orientation = [
+            pose.orientation_x,
+            pose.orientation_y,
+            pose.orientation_z,
+            pose.orientation_w,
+        ];
+        if !all_finite(&position) || !all_finite(&orientation) {
+            return None;
+        }
+        Some(HostMessage::Tracking {
+            timestamp_ns: pose.timestamp_us.saturating_mul(1_000),
+            head: DeviceMotion {
+                pose: VrPose {
+                    position,
+                    orientation,
+                },
+                ..Default::default()
+            },
+        })
+    }
+
+    fn steamvr_controller_motion(hand: &rift_core::HandPoseUpdate) -> Option<HostMessage> {
+        let position = [hand.position_x, hand.position_y, hand.position_z];
+        let orientation = [
+            hand.orientation_x,
+            hand.orientation_y,
+            hand.orientation_z,
+            hand.orientation_w,
+        ];
+        let linear_velocity = [
+            hand.linear_velocity_x,
+            hand.linear_velocity_y,
+            hand.linear_velocity_z,
+        ];
+        let angular_velocity = [
+            hand.angular_velocity_x,
+            hand.angular_velocity_y,
+            hand.angular_velocity_z,
+        ];
+        let finite = [
+            position.as_slice(),
+            &orientation,
+            &linear_velocity,
+            &angular_velocity,
+        ]
+        .iter()
+        .all(|values| all_finite(values));
+        if hand.hand_id > 1 || !finite {
+            return None;
+        }
+        Some(HostMessage::Controller {
+            hand: hand.hand_id,
+            motion: DeviceMotion {
+                pose: VrPose {
+                    position,
+                    orientation,
+                },
+                linear_velocity,
+                angular_velocity,
+            },
+        })
+    }
+
+    fn steamvr_controller_input(gamepad: &rift_core::GamepadMessage) -> Vec<HostMessage> {
+        let axes = gamepad
+            .axes
+            .iter()
+            .filter(|a| a.value.is_finite())
+            .filter_map(|a| {
+                let path =
+                    controller_input_path(gamepad.gamepad_id, ControllerInput::Axis(a.axis))?;
+                // Sticks are two-sided; trigger and grip run 0..1.
+                let value = if a.axis < 2 {
+                    a.value.clamp(-1.0, 1.0)
+                } else {
+                    a.value.clamp(0.0, 1.0)
+                };
+                Some((path, ButtonValue::Scalar(value)))
+            });
+        let buttons = gamepad.buttons.iter().filter_map(|b| {
+            let path =
+                controller_input_path(gamepad.gamepad_id, ControllerInput::Button(b.button))?;
+            Some((path, ButtonValue::Binary(b.pressed)))
+        });
+        axes.chain(buttons)
+            .map(|(path, value)| HostMessage::Button {
+                path_id: path_to_id(&path),
+                value,
+            })
+            .collect()
+    }
+
+    /// Hands tracking and input queued by `handle_rift_msg` to the driver, and
+    /// follows the peer's congestion-controlled bitrate.
+    fn forward_to_steamvr(
+        bridge: &SteamVrBridge,
+        peer_state: &mut PeerState,
+        bitrate_kbps: &mut u32,
+        fps: u16,
+    ) {
+        for msg in peer_state.steamvr_input.drain(..) {
+            bridge.send(&msg);
+        }
+        if *bitrate_kbps != peer_state.target_bitrate_kbps {
+            *bitrate_kbps = peer_state.target_bitrate_kbps;
+            bridge.send(&HostMessage::EncoderParams {
+                bitrate_bps: u64::from(*bitrate_kbps) * 1_000,
+                framerate: f32::from(fps),
+            });
+        }
+    }
+
+    /// Splits driver output into encoded frames and controller haptics.
+    fn spawn_steamvr_relay(
+        events: std::sync::mpsc::Receiver<DriverMessage>,
+    ) -> (
+        mpsc::Receiver<FrameIn>,
+        mpsc::UnboundedReceiver<rift_core::HapticFeedback>,
+    ) {
+        let (frame_tx, frame_rx) = mpsc::channel::<FrameIn>(8);
+        let (haptic_tx, haptic_rx) = mpsc::unbounded_channel();
+        let left_hand = path_to_id(wavry_vr_steamvr::protocol::LEFT_HAND_PATH);
+        let right_hand = path_to_id(wavry_vr_steamvr::protocol::RIGHT_HAND_PATH);
+        std::thread::spawn(move || {
+            for event in events {
+                match event {
+                    DriverMessage::VideoConfig { codec, .. } => {
+                        info!("SteamVR driver encoding {:?}", codec);
+                    }
+                    DriverMessage::Video {
+                        timestamp_ns,
+                        keyframe,
+                        data,
+                    } => {
+                        let frame = EncodedFrame {
+                            timestamp_us: timestamp_ns / 1_000,
+                            keyframe,
+                            data,
+                            capture_duration_us: 0,
+                            encode_duration_us: 0,
+                        };
+                        if frame_tx.blocking_send(frame).is_err() {
+                            break;
+                        }
+                    }
+                    DriverMessage::Haptics {
+                        device_id,
+                        duration_s,
+                        frequency,
+                        amplitude,
+                    } => {
+                        let controller_id = if device_id == left_hand {
+                            0
+                        } else if device_id == right_hand {
+                            1
+                        } else {
+                            continue;
+                        };
+                        let haptic = rift_core::HapticFeedback {
+                            controller_id,
+                            amplitude: amplitude.clamp(0.0, 1.0),
+                            frequency_hz: frequency.max(0.0),
+                            duration_us: (duration_s.max(0.0) * 1_000_000.0) as u64,
+                        };
+                        if haptic_tx.send(haptic).is_err() {
+                            break;
+                        }
+                    }
+                    DriverMessage::Present { .. } => {}
+                }
+            }
+        });
+        (frame_rx, haptic_rx)
+    }
+
     fn validate_runtime_config(args: &Args) -> Result<HostRuntimeConfig> {
         if args.width < MIN_STREAM_DIMENSION || args.width > MAX_STREAM_DIMENSION {
             return Err(anyhow!(
@@ -1890,6 +3955,9 @@ mod host {
         if args.max_peers == 0 {
             return Err(anyhow!("--max-peers must be at least 1"));
         }
+        if args.max_streams == 0 || args.max_streams > args.max_peers {
+            return Err(anyhow!("--max-streams must be between 1 and --max-peers"));
+        }
         if args.peer_idle_timeout_secs == 0 {
             return Err(anyhow!("--peer-idle-timeout-secs must be at least 1"));
         }
@@ -1909,6 +3977,19 @@ mod host {
                 "--file-transfer-min-kbps must be <= --file-transfer-max-kbps"
             ));
         }
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        for (flag, core) in [
+            ("--encode-core", args.encode_core),
+            ("--send-core", args.send_core),
+        ] {
+            if core.is_some_and(|core| core >= cores) {
+                return Err(anyhow!(
+                    "{} must be below the {} available cores",
+                    flag,
+                    cores
+                ));
+            }
+        }
 
         Ok(HostRuntimeConfig {
             default_resolution: MediaResolution {
@@ -1919,11 +4000,36 @@ mod host {
             initial_bitrate_kbps: args.bitrate_kbps,
             keyframe_interval_ms: args.keyframe_interval_ms,
             max_peers: args.max_peers,
+            max_streams: args.max_streams,
             peer_idle_timeout: Duration::from_secs(args.peer_idle_timeout_secs),
             stats_log_interval: Duration::from_secs(args.stats_log_interval_secs),
             file_transfer_share_percent: args.file_transfer_share_percent,
             file_transfer_min_kbps: args.file_transfer_min_kbps,
             file_transfer_max_kbps: args.file_transfer_max_kbps,
+            // Only the Linux system mix can be captured beyond stereo.
+            multichannel_audio: cfg!(target_os = "linux")
+                && matches!(
+                    AudioRouteSource::parse(&args.audio_source),
+                    AudioRouteSource::SystemMix
+                ),
+            steamvr: args.steamvr,
+            cursor_channel: false,
+            allow_microphone: args.allow_microphone,
+            permissions: args.permissions,
+            max_session: (args.max_session_secs > 0)
+                .then(|| Duration::from_secs(args.max_session_secs)),
+            input_idle_timeout: (args.idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(args.idle_timeout_secs)),
+            lock_on_disconnect: args.lock_on_disconnect,
+            external_addr: None,
+            encode_thread: ThreadTuning {
+                priority: args.thread_priority,
+                core: args.encode_core,
+            },
+            send_thread: ThreadTuning {
+                priority: args.thread_priority,
+                core: args.send_core,
+            },
         })
     }
 
@@ -1945,89 +4051,410 @@ mod host {
 
     fn cleanup_inactive_peers(
         peers: &mut HashMap<SocketAddr, PeerState>,
-        active_peer: &mut Option<SocketAddr>,
+        sessions: &mut StreamSessions,
         idle_timeout: Duration,
     ) {
         let now = time::Instant::now();
         let mut removed = 0usize;
-        let mut removed_active_peer = false;
+        let mut removed_sessions = 0usize;
         peers.retain(|addr, state| {
             let stale = now.duration_since(state.last_seen) > idle_timeout;
             if stale {
                 removed += 1;
-                if Some(*addr) == *active_peer {
-                    removed_active_peer = true;
+                if sessions.remove(*addr) {
+                    removed_sessions += 1;
                 }
-                warn!(
-                    "dropping stale peer {} after {:?} of inactivity",
-                    addr,
-                    now.duration_since(state.last_seen)
-                );
+                state.span.in_scope(|| {
+                    warn!(
+                        "dropping stale peer {} after {:?} of inactivity",
+                        addr,
+                        now.duration_since(state.last_seen)
+                    )
+                });
             }
             !stale
         });
-        if removed_active_peer {
-            *active_peer = None;
-            info!("active peer expired; host is ready for new clients");
+        if removed_sessions > 0 {
+            if sessions.is_empty() {
+                info!("active peer expired; host is ready for new clients");
+            } else {
+                info!(
+                    "{} session(s) expired; {} still streaming",
+                    removed_sessions,
+                    sessions.peers.len()
+                );
+            }
         }
         if removed > 0 {
             debug!("peer cleanup removed {} stale peer(s)", removed);
         }
     }
 
-    async fn send_rift_msg(
-        socket: &UdpSocket,
-        peer_state: &mut PeerState,
-        peer: SocketAddr,
-        msg: ProtoMessage,
-    ) -> Result<()> {
-        let plaintext = encode_msg(&msg);
-        let packet_id = peer_state.next_packet_id;
-        peer_state.next_packet_id = peer_state.next_packet_id.wrapping_add(1);
-
-        let payload = match &mut peer_state.crypto {
-            CryptoState::Disabled => plaintext,
-            CryptoState::Established(server) => server
-                .encrypt(packet_id, &plaintext)
-                .map_err(|e| anyhow!("Encrypt failed: {}", e))?,
-            _ => return Err(anyhow!("cannot send RIFT msg during handshake")),
-        };
+    /// What the host's session policy asks of a running session.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum SessionLimit {
+        Within,
+        /// No input for the idle timeout; pause streaming until there is.
+        Idle,
+        /// Ran for the maximum session duration; end it.
+        Expired,
+    }
 
-        let phys = PhysicalPacket {
-            version: RIFT_VERSION,
-            session_id: None,
-            session_alias: Some(peer_state.session_alias),
-            packet_id,
-            payload: Bytes::copy_from_slice(&payload),
+    fn session_limit(
+        peer_state: &PeerState,
+        max_session: Option<Duration>,
+        input_idle_timeout: Option<Duration>,
+        now: time::Instant,
+    ) -> SessionLimit {
+        let Some(started) = peer_state.session_started else {
+            return SessionLimit::Within;
         };
-
-        let bytes = phys.encode();
-        peer_state.send_history.insert(packet_id, bytes.clone());
-        socket.send_to(&bytes, peer).await?;
-        Ok(())
+        if max_session.is_some_and(|limit| now.duration_since(started) >= limit) {
+            return SessionLimit::Expired;
+        }
+        // Clients that may not send input can't show they are still there.
+        let idle = input_idle_timeout
+            .is_some_and(|timeout| now.duration_since(peer_state.last_input) >= timeout);
+        if idle && peer_state.permissions.contains(PermissionSet::INPUT) {
+            SessionLimit::Idle
+        } else {
+            SessionLimit::Within
+        }
     }
 
-    async fn send_video_frame(
+    /// Pauses idle sessions and ends those past the maximum duration, telling
+    /// their clients so they don't try to resume.
+    async fn enforce_session_limits(
         socket: &UdpSocket,
-        peer: SocketAddr,
-        peer_state: &mut PeerState,
-        frame: EncodedFrame,
-    ) -> Result<()> {
-        let chunks = chunk_video_payload(
-            peer_state.frame_id,
-            frame.timestamp_us,
-            frame.keyframe,
-            &frame.data,
-            MAX_DATAGRAM_SIZE,
-            frame.capture_duration_us,
-            frame.encode_duration_us,
-        )
-        .map_err(|e| anyhow!("Chunking error: {}", e))?;
-        peer_state.frame_id = peer_state.frame_id.wrapping_add(1);
-
-        for chunk in chunks {
-            let packet_bytes = chunk.payload.len() + 64;
-            let msg = ProtoMessage {
+        peers: &mut HashMap<SocketAddr, PeerState>,
+        sessions: &mut StreamSessions,
+        runtime: HostRuntimeConfig,
+    ) {
+        let now = time::Instant::now();
+        for peer in sessions.peers.clone() {
+            let Some(peer_state) = peers.get_mut(&peer) else {
+                continue;
+            };
+            match session_limit(
+                peer_state,
+                runtime.max_session,
+                runtime.input_idle_timeout,
+                now,
+            ) {
+                SessionLimit::Within => {}
+                SessionLimit::Idle => {
+                    if !peer_state.idle {
+                        info!("no input from {}; pausing the stream", peer);
+                        peer_state.idle = true;
+                    }
+                }
+                SessionLimit::Expired => {
+                    let msg = ProtoMessage {
+                        content: Some(rift_core::message::Content::Control(ProtoControl {
+                            content: Some(rift_core::control_message::Content::SessionEnd(
+                                rift_core::SessionEnd {
+                                    reason: rift_core::SessionEndReason::SessionEndTimeLimit as i32,
+                                },
+                            )),
+                        })),
+                    };
+                    if let Err(err) = send_rift_msg(socket, peer_state, peer, msg).await {
+                        debug!("failed to send session end to {}: {}", peer, err);
+                    }
+                    peer_state
+                        .span
+                        .in_scope(|| info!("ending session with {}: time limit reached", peer));
+                    sessions.remove(peer);
+                    peers.remove(&peer);
+                }
+            }
+        }
+    }
+
+    /// Starts a probe burst for each streaming peer whose DELTA asks for one
+    /// and sends whatever part of its current burst is due.
+    async fn send_probes(
+        socket: &UdpSocket,
+        peers: &mut HashMap<SocketAddr, PeerState>,
+        sessions: &StreamSessions,
+    ) {
+        let now = std::time::Instant::now();
+        for &peer in &sessions.peers {
+            let Some(peer_state) = peers.get_mut(&peer).filter(|state| !state.idle) else {
+                continue;
+            };
+            if peer_state.probe.is_none() {
+                peer_state.probe = peer_state
+                    .cc
+                    .next_probe(now)
+                    .map(|burst| ProbeSender::new(burst, now));
+            }
+            let Some(sender) = peer_state.probe.as_mut() else {
+                continue;
+            };
+            let due = sender.poll(now);
+            if sender.is_done() {
+                peer_state.probe = None;
+            }
+            for probe in due {
+                let msg = ProtoMessage {
+                    content: Some(rift_core::message::Content::Media(
+                        rift_core::MediaMessage {
+                            content: Some(rift_core::media_message::Content::Probe(probe)),
+                        },
+                    )),
+                };
+                if let Err(err) = send_unrecorded(socket, peer_state, peer, msg).await {
+                    debug!("send probe to {} failed: {}", peer, err);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// A `Resume` from an address the host doesn't know yet: a transport
+    /// packet under a live session's alias that decrypts with that session's
+    /// keys. Returns the address the session is bound to and the message.
+    fn open_resume(
+        peers: &mut HashMap<SocketAddr, PeerState>,
+        raw: &[u8],
+    ) -> Option<(SocketAddr, rift_core::Resume)> {
+        let phys = PhysicalPacket::decode(Bytes::copy_from_slice(raw)).ok()?;
+        if phys.session_id.is_some() {
+            return None;
+        }
+        let alias = phys.session_alias?;
+        let (addr, state) = peers
+            .iter_mut()
+            .find(|(_, state)| state.session_alias == alias && state.session_id.is_some())?;
+        let CryptoState::Established(server) = &mut state.crypto else {
+            return None;
+        };
+        let plaintext = server.decrypt(phys.packet_id, &phys.payload).ok()?;
+        state.compact_rx.accept(phys.packet_id);
+        match decode_msg(&plaintext).ok()?.content? {
+            rift_core::message::Content::Control(ProtoControl {
+                content: Some(rift_core::control_message::Content::Resume(resume)),
+            }) => Some((*addr, resume)),
+            _ => None,
+        }
+    }
+
+    /// Checks a `Resume` that decrypted under this peer's keys against its
+    /// ticket. Attempts must increase, so a replayed one is refused.
+    fn accept_resume(peer_state: &mut PeerState, resume: &rift_core::Resume) -> Result<()> {
+        let session_id = peer_state
+            .session_id
+            .as_deref()
+            .and_then(|id| <[u8; 16]>::try_from(id).ok())
+            .map(u128::from_be_bytes)
+            .ok_or_else(|| anyhow!("session has no id yet"))?;
+        let CryptoState::Established(server) = &peer_state.crypto else {
+            return Err(anyhow!("resumption requires an encrypted session"));
+        };
+        let ticket = server
+            .resume_ticket()
+            .ok_or_else(|| anyhow!("session has no resume ticket"))?;
+        if resume.session_alias != peer_state.session_alias
+            || resume.attempt <= peer_state.resume_attempt
+            || !ticket.verify(
+                session_id,
+                resume.session_alias,
+                resume.attempt,
+                &resume.proof,
+            )
+        {
+            return Err(anyhow!("invalid or replayed resume attempt"));
+        }
+        peer_state.resume_attempt = resume.attempt;
+        peer_state.last_seen = time::Instant::now();
+        Ok(())
+    }
+
+    fn resume_ack(attempt: u64) -> ProtoMessage {
+        ProtoMessage {
+            content: Some(rift_core::message::Content::Control(ProtoControl {
+                content: Some(rift_core::control_message::Content::ResumeAck(
+                    rift_core::ResumeAck { attempt },
+                )),
+            })),
+        }
+    }
+
+    /// Re-binds a live session at `old_addr` to the address its `Resume` came
+    /// from. Keys and packet counters carry over, so the stream continues
+    /// where it left off.
+    async fn resume_session(
+        socket: &UdpSocket,
+        peers: &mut HashMap<SocketAddr, PeerState>,
+        sessions: &mut StreamSessions,
+        old_addr: SocketAddr,
+        peer: SocketAddr,
+        resume: rift_core::Resume,
+    ) -> Result<()> {
+        let state = peers
+            .get_mut(&old_addr)
+            .ok_or_else(|| anyhow!("session vanished"))?;
+        accept_resume(state, &resume)?;
+
+        let state = peers
+            .remove(&old_addr)
+            .ok_or_else(|| anyhow!("session vanished"))?;
+        state
+            .span
+            .in_scope(|| info!("session moved from {} to {}", old_addr, peer));
+        peers.insert(peer, state);
+        for active in sessions.peers.iter_mut() {
+            if *active == old_addr {
+                *active = peer;
+            }
+        }
+
+        let state = peers
+            .get_mut(&peer)
+            .ok_or_else(|| anyhow!("session vanished"))?;
+        send_rift_msg(socket, state, peer, resume_ack(resume.attempt)).await
+    }
+
+    /// Declines a Hello, e.g. when every stream slot is taken.
+    async fn reject_hello(
+        socket: &UdpSocket,
+        peer_state: &mut PeerState,
+        peer: SocketAddr,
+    ) -> Result<Option<Codec>> {
+        let ack = ProtoHelloAck {
+            accepted: false,
+            selected_codec: 0,
+            stream_resolution: None,
+            fps: 0,
+            initial_bitrate_kbps: 0,
+            keyframe_interval_ms: 0,
+            session_id: UNASSIGNED_SESSION_ID.to_vec(),
+            session_alias: 0,
+            public_addr: String::new(),
+            candidate_addrs: vec![],
+            stereo_mode: RiftStereoMode::StereoAuto as i32,
+            audio_layout: RiftAudioLayout::AudioStereo as i32,
+            fec_scheme: 0,
+            cursor_channel: false,
+            audio_params: None,
+            compact_header: false,
+            transport_feedback: false,
+            microphone: false,
+            permissions: 0,
+            input_batch: false,
+        };
+        send_rift_msg(
+            socket,
+            peer_state,
+            peer,
+            ProtoMessage {
+                content: Some(rift_core::message::Content::Control(ProtoControl {
+                    content: Some(rift_core::control_message::Content::HelloAck(ack)),
+                })),
+            },
+        )
+        .await?;
+        Ok(None)
+    }
+
+    async fn send_rift_msg(
+        socket: &UdpSocket,
+        peer_state: &mut PeerState,
+        peer: SocketAddr,
+        msg: ProtoMessage,
+    ) -> Result<()> {
+        let (packet_id, bytes) = seal_rift_msg(peer_state, &msg)?;
+        peer_state.send_history.insert(packet_id, bytes.clone());
+        socket.send_to(&bytes, peer).await?;
+        Ok(())
+    }
+
+    /// Sends `msg` without keeping it for retransmission; probe padding is
+    /// worthless once late.
+    async fn send_unrecorded(
+        socket: &UdpSocket,
+        peer_state: &mut PeerState,
+        peer: SocketAddr,
+        msg: ProtoMessage,
+    ) -> Result<()> {
+        let (_, bytes) = seal_rift_msg(peer_state, &msg)?;
+        socket.send_to(&bytes, peer).await?;
+        Ok(())
+    }
+
+    fn seal_rift_msg(peer_state: &mut PeerState, msg: &ProtoMessage) -> Result<(u64, Bytes)> {
+        let plaintext = encode_msg(msg);
+        let packet_id = peer_state.next_packet_id;
+        peer_state.next_packet_id = peer_state.next_packet_id.wrapping_add(1);
+
+        let payload = match &mut peer_state.crypto {
+            CryptoState::Disabled => plaintext,
+            CryptoState::Established(server) => server
+                .encrypt(packet_id, &plaintext)
+                .map_err(|e| anyhow!("Encrypt failed: {}", e))?,
+            _ => return Err(anyhow!("cannot send RIFT msg during handshake")),
+        };
+
+        let phys = PhysicalPacket {
+            version: RIFT_VERSION,
+            session_id: None,
+            session_alias: Some(peer_state.session_alias),
+            packet_id,
+            payload: Bytes::copy_from_slice(&payload),
+        };
+
+        let bytes = match peer_state.compact_tx.as_mut() {
+            Some(encoder) => encoder.encode(&phys, message_channel(msg)),
+            None => phys.encode(),
+        };
+        Ok((packet_id, bytes))
+    }
+
+    async fn send_video_frame(
+        socket: &UdpSocket,
+        peer: SocketAddr,
+        peer_state: &mut PeerState,
+        frame: EncodedFrame,
+    ) -> Result<()> {
+        let frame_id = peer_state.frame_id;
+        peer_state.frame_id = peer_state.frame_id.wrapping_add(1);
+        send_stream_frame(
+            socket,
+            peer,
+            peer_state,
+            frame,
+            frame_id,
+            rift_core::VIDEO_STREAM_PRIMARY,
+        )
+        .await
+    }
+
+    async fn send_stream_frame(
+        socket: &UdpSocket,
+        peer: SocketAddr,
+        peer_state: &mut PeerState,
+        frame: EncodedFrame,
+        frame_id: u64,
+        stream_id: u32,
+    ) -> Result<()> {
+        let mut chunks = chunk_video_payload(
+            frame_id,
+            frame.timestamp_us,
+            frame.keyframe,
+            &frame.data,
+            MAX_DATAGRAM_SIZE,
+            frame.capture_duration_us,
+            frame.encode_duration_us,
+        )
+        .map_err(|e| anyhow!("Chunking error: {}", e))?;
+        for chunk in chunks.iter_mut() {
+            chunk.stream_id = stream_id;
+        }
+
+        for chunk in chunks {
+            let packet_bytes = chunk.payload.len() + 64;
+            let msg = ProtoMessage {
                 content: Some(rift_core::message::Content::Media(
                     rift_core::MediaMessage {
                         content: Some(rift_core::media_message::Content::Video(chunk)),
@@ -2048,6 +4475,7 @@ mod host {
         peer: SocketAddr,
         peer_state: &mut PeerState,
         packet: EncodedFrame,
+        layout: RiftAudioLayout,
     ) -> Result<()> {
         let msg = ProtoMessage {
             content: Some(rift_core::message::Content::Media(
@@ -2056,6 +4484,8 @@ mod host {
                         rift_core::AudioPacket {
                             timestamp_us: packet.timestamp_us,
                             payload: packet.data,
+                            layout: layout as i32,
+                            direction: rift_core::AudioDirection::AudioFromHost as i32,
                         },
                     )),
                 },
@@ -2064,6 +4494,124 @@ mod host {
         send_rift_msg(socket, peer_state, peer, msg).await
     }
 
+    /// Plays a client's microphone into the host's virtual input, if it was
+    /// agreed for this session. A device that fails to open turns
+    /// passthrough off rather than being retried on every packet.
+    fn play_client_microphone(peer_state: &mut PeerState, packet: &rift_core::AudioPacket) {
+        if !peer_state.microphone {
+            return;
+        }
+        if peer_state.virtual_mic.is_none() {
+            match open_virtual_microphone() {
+                Ok(mic) => peer_state.virtual_mic = Some(mic),
+                Err(err) => {
+                    warn!("client microphone passthrough unavailable: {}", err);
+                    peer_state.microphone = false;
+                    return;
+                }
+            }
+        }
+        if let Some(mic) = peer_state.virtual_mic.as_mut() {
+            if let Err(err) = mic.render(&packet.payload, packet.timestamp_us) {
+                debug!("client microphone playback error: {}", err);
+            }
+        }
+    }
+
+    fn open_virtual_microphone() -> Result<Box<dyn Renderer + Send>> {
+        #[cfg(target_os = "linux")]
+        {
+            Ok(Box::new(wavry_media::PipewireVirtualMicrophone::new()?))
+        }
+        #[cfg(target_os = "windows")]
+        {
+            Ok(Box::new(wavry_media::WindowsVirtualMicrophone::new()?))
+        }
+        #[cfg(target_os = "macos")]
+        {
+            Ok(Box::new(wavry_media::MacVirtualMicrophone::new()?))
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+        {
+            Err(anyhow!("not supported on this platform"))
+        }
+    }
+
+    /// Pointer capture for the cursor channel, where the platform has one.
+    fn open_cursor_capturer() -> Option<Box<dyn CursorCapturer>> {
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        let capturer = CursorCapturerImpl::new().map(|c| Box::new(c) as Box<dyn CursorCapturer>);
+        #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+        let capturer: Result<Box<dyn CursorCapturer>> =
+            Err(anyhow!("not supported on this platform"));
+        match capturer {
+            Ok(capturer) => {
+                info!("cursor channel available");
+                Some(capturer)
+            }
+            Err(err) => {
+                info!("cursor channel unavailable: {}", err);
+                None
+            }
+        }
+    }
+
+    fn system_cursor_to_proto(cursor: SystemCursor) -> RiftSystemCursor {
+        match cursor {
+            SystemCursor::Arrow => RiftSystemCursor::Arrow,
+            SystemCursor::IBeam => RiftSystemCursor::Ibeam,
+            SystemCursor::Wait => RiftSystemCursor::Wait,
+            SystemCursor::Crosshair => RiftSystemCursor::Crosshair,
+            SystemCursor::Hand => RiftSystemCursor::Hand,
+            SystemCursor::ResizeEw => RiftSystemCursor::ResizeEw,
+            SystemCursor::ResizeNs => RiftSystemCursor::ResizeNs,
+            SystemCursor::ResizeNwse => RiftSystemCursor::ResizeNwse,
+            SystemCursor::ResizeNesw => RiftSystemCursor::ResizeNesw,
+            SystemCursor::Move => RiftSystemCursor::Move,
+            SystemCursor::NotAllowed => RiftSystemCursor::NotAllowed,
+        }
+    }
+
+    /// Position update, with the shape attached when `with_shape`. Bitmaps
+    /// larger than `MAX_CURSOR_SIZE` are sent as the arrow.
+    fn cursor_update(cursor: &CursorState, with_shape: bool) -> rift_core::CursorUpdate {
+        let mut update = rift_core::CursorUpdate {
+            x: cursor.x,
+            y: cursor.y,
+            visible: cursor.visible,
+            shape_serial: cursor.serial,
+            ..Default::default()
+        };
+        if !with_shape {
+            return update;
+        }
+        match &cursor.shape {
+            CursorShape::System(system) => {
+                update.system_cursor = system_cursor_to_proto(*system) as i32;
+            }
+            CursorShape::Bitmap {
+                width,
+                height,
+                hotspot_x,
+                hotspot_y,
+                bgra,
+            } if (1..=MAX_CURSOR_SIZE).contains(width)
+                && (1..=MAX_CURSOR_SIZE).contains(height)
+                && bgra.len() == (width * height * 4) as usize =>
+            {
+                update.width = *width;
+                update.height = *height;
+                update.hotspot_x = *hotspot_x;
+                update.hotspot_y = *hotspot_y;
+                update.bgra = bgra.clone();
+            }
+            CursorShape::Bitmap { .. } => {
+                update.system_cursor = RiftSystemCursor::Arrow as i32;
+            }
+        }
+        update
+    }
+
     async fn send_next_file_chunk(
         socket: &UdpSocket,
         runtime: HostRuntimeConfig,
@@ -2072,72 +4620,114 @@ mod host {
         limiter: &mut FileTransferLimiter,
         outgoing: &mut VecDeque<OutgoingFile>,
     ) -> Result<()> {
-        if !rotate_to_next_ready_transfer(outgoing) {
+        let budget_kbps = file_transfer_budget_kbps(runtime, peer_state.target_bitrate_kbps);
+        let Some(msg) = next_file_transfer_message(budget_kbps, limiter, outgoing)? else {
             return Ok(());
+        };
+        let header_file_id = file_header_id(&msg);
+        if let Err(err) = send_rift_msg(socket, peer_state, peer, msg).await {
+            requeue_file_header(outgoing, header_file_id);
+            return Err(err);
         }
+        Ok(())
+    }
 
-        let mut progressed = false;
-        let file_budget_kbps = file_transfer_budget_kbps(runtime, peer_state.target_bitrate_kbps);
-        limiter.set_rate_kbps(file_budget_kbps);
-
-        {
-            let front = outgoing
-                .front_mut()
-                .expect("rotate helper guaranteed front");
-
-            if !front.header_sent() {
-                let header = offer_to_proto(front.offer());
-                let msg = ProtoMessage {
-                    content: Some(rift_core::message::Content::Control(ProtoControl {
-                        content: Some(rift_core::control_message::Content::FileHeader(header)),
-                    })),
-                };
-                send_rift_msg(socket, peer_state, peer, msg).await?;
-                front.mark_header_sent();
-                info!(
-                    "started sending file {} to client ({} bytes)",
-                    front.offer().filename,
-                    front.offer().file_size
-                );
-                progressed = true;
-            } else {
-                let current_chunk = front.next_chunk_index();
-                match front.next_chunk()? {
-                    Some(chunk) => {
-                        if !limiter.try_take(chunk.payload.len()) {
-                            front.set_next_chunk(current_chunk)?;
-                        } else {
-                            let msg = ProtoMessage {
-                                content: Some(rift_core::message::Content::Media(
-                                    rift_core::MediaMessage {
-                                        content: Some(
-                                            rift_core::media_message::Content::FileChunk(
-                                                rift_core::FileChunk {
-                                                    file_id: chunk.file_id,
-                                                    chunk_index: chunk.chunk_index,
-                                                    payload: chunk.payload,
-                                                },
-                                            ),
-                                        ),
-                                    },
-                                )),
-                            };
-                            send_rift_msg(socket, peer_state, peer, msg).await?;
-                            progressed = true;
-                        }
-                    }
-                    None => {
-                        // completion is finalized after remote FileStatus::Complete
-                    }
-                }
+    /// Browsers receive files over their control data channel, paced by the video budget.
+    fn send_next_file_chunk_to_viewers(
+        bridge: &WebRtcBridge,
+        runtime: HostRuntimeConfig,
+        video_bitrate_kbps: u32,
+        limiter: &mut FileTransferLimiter,
+        outgoing: &mut VecDeque<OutgoingFile>,
+    ) -> Result<()> {
+        let budget_kbps = file_transfer_budget_kbps(runtime, video_bitrate_kbps);
+        let Some(msg) = next_file_transfer_message(budget_kbps, limiter, outgoing)? else {
+            return Ok(());
+        };
+        let header_file_id = file_header_id(&msg);
+        if let Some(content) = msg.content {
+            if !bridge.send_to_viewers(content) {
+                requeue_file_header(outgoing, header_file_id);
             }
         }
+        Ok(())
+    }
+
+    /// Next header or chunk for the first ready transfer, if the budget allows one.
+    fn next_file_transfer_message(
+        budget_kbps: u32,
+        limiter: &mut FileTransferLimiter,
+        outgoing: &mut VecDeque<OutgoingFile>,
+    ) -> Result<Option<ProtoMessage>> {
+        if !rotate_to_next_ready_transfer(outgoing) {
+            return Ok(None);
+        }
+        limiter.set_rate_kbps(budget_kbps);
+
+        let front = outgoing
+            .front_mut()
+            .expect("rotate helper guaranteed front");
+        let msg = if !front.header_sent() {
+            let header = offer_to_proto(front.offer());
+            front.mark_header_sent();
+            info!(
+                "started sending file {} to client ({} bytes)",
+                front.offer().filename,
+                front.offer().file_size
+            );
+            ProtoMessage {
+                content: Some(rift_core::message::Content::Control(ProtoControl {
+                    content: Some(rift_core::control_message::Content::FileHeader(header)),
+                })),
+            }
+        } else {
+            let current_chunk = front.next_chunk_index();
+            // completion is finalized after remote FileStatus::Complete
+            let Some(chunk) = front.next_chunk()? else {
+                return Ok(None);
+            };
+            if !limiter.try_take(chunk.payload.len()) {
+                front.set_next_chunk(current_chunk)?;
+                return Ok(None);
+            }
+            ProtoMessage {
+                content: Some(rift_core::message::Content::Media(
+                    rift_core::MediaMessage {
+                        content: Some(rift_core::media_message::Content::FileChunk(
+                            rift_core::FileChunk {
+                                file_id: chunk.file_id,
+                                chunk_index: chunk.chunk_index,
+                                payload: chunk.payload,
+                            },
+                        )),
+                    },
+                )),
+            }
+        };
 
-        if progressed && outgoing.len() > 1 {
+        if outgoing.len() > 1 {
             outgoing.rotate_left(1);
         }
+        Ok(Some(msg))
+    }
 
-        Ok(())
+    fn file_header_id(msg: &ProtoMessage) -> Option<u64> {
+        match &msg.content {
+            Some(rift_core::message::Content::Control(ProtoControl {
+                content: Some(rift_core::control_message::Content::FileHeader(header)),
+            })) => Some(header.file_id),
+            _ => None,
+        }
+    }
+
+    /// A header that never left the host must be offered again on the next tick.
+    fn requeue_file_header(outgoing: &mut VecDeque<OutgoingFile>, file_id: Option<u64>) {
+        let Some(file_id) = file_id else {
+            return;
+        };
+        if let Some(file) = outgoing.iter_mut().find(|f| f.offer().file_id == file_id) {
+            file.reset_header();
+        }
     }
 
     async fn handle_incoming_file_chunk(
@@ -2239,13 +4829,82 @@ mod host {
         Ok(())
     }
 
+    /// Binds the host socket. `[::]` also takes IPv4 peers, which then show
+    /// up as IPv4-mapped addresses, and becomes `0.0.0.0` on machines
+    /// without IPv6.
+    fn bind_udp(listen: SocketAddr) -> std::io::Result<UdpSocket> {
+        fn bind(addr: SocketAddr) -> std::io::Result<Socket> {
+            let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+            if addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+                socket.set_only_v6(false)?;
+            }
+            socket.set_nonblocking(true)?;
+            socket.bind(&addr.into())?;
+            Ok(socket)
+        }
+
+        let socket = match bind(listen) {
+            Err(err)
+                if listen.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                    && !matches!(
+                        err.kind(),
+                        ErrorKind::AddrInUse | ErrorKind::PermissionDenied
+                    ) =>
+            {
+                warn!("IPv6 unavailable ({}); listening on IPv4 only", err);
+                bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), listen.port()))?
+            }
+            result => result?,
+        };
+        UdpSocket::from_std(socket.into())
+    }
+
+    fn set_tos(socket: &UdpSocket, tos: u32) -> std::io::Result<()> {
+        let sock = SockRef::from(socket);
+        if socket.local_addr()?.is_ipv4() {
+            return sock.set_tos_v4(tos);
+        }
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        sock.set_tclass_v6(tos)?;
+        if let Err(e) = sock.set_tos_v4(tos) {
+            debug!("no IPv4 TOS on dual-stack socket: {}", e);
+        }
+        Ok(())
+    }
+
+    /// The machine name as a DNS label, so each host on the LAN is listed
+    /// under its own name.
+    fn mdns_host_label(hostname: &str) -> String {
+        let label: String = hostname
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .take(63)
+            .collect();
+        let label = label.trim_matches('-');
+        if label.is_empty() {
+            "wavry-host".to_string()
+        } else {
+            label.to_string()
+        }
+    }
+
     fn advertise_mdns(listen_addr: SocketAddr) -> Result<ServiceDaemon> {
         let mdns = ServiceDaemon::new()?;
+        let name = mdns_host_label(&gethostname::gethostname().to_string_lossy());
+        // With no specific address, every interface's addresses are published.
+        let addrs = if listen_addr.ip().is_unspecified() {
+            String::new()
+        } else {
+            listen_addr.ip().to_string()
+        };
         let service_info = ServiceInfo::new(
             "_wavry._udp.local.",
-            "wavry-host",
-            "wavry.local.",
-            listen_addr.ip().to_string(),
+            &name,
+            &format!("{}.local.", name),
+            addrs,
             listen_addr.port(),
             &[("v", "1")][..],
         )?
@@ -2270,6 +4929,151 @@ mod host {
             path
         }
 
+        #[test]
+        fn choose_stereo_mode_packs_wide_frames_for_stereo_clients() {
+            let wide = ProtoResolution {
+                width: 3840,
+                height: 1920,
+            };
+            let flat = ProtoResolution {
+                width: 1920,
+                height: 1080,
+            };
+            let mut hello = rift_core::Hello::default();
+            assert_eq!(
+                choose_stereo_mode(&hello, &wide),
+                RiftStereoMode::StereoAuto
+            );
+            hello.stereo_modes = vec![
+                RiftStereoMode::StereoSideBySide as i32,
+                RiftStereoMode::StereoMono as i32,
+            ];
+            assert_eq!(
+                choose_stereo_mode(&hello, &wide),
+                RiftStereoMode::StereoSideBySide
+            );
+            assert_eq!(
+                choose_stereo_mode(&hello, &flat),
+                RiftStereoMode::StereoMono
+            );
+        }
+
+        #[test]
+        fn choose_stream_fps_follows_headset_refresh() {
+            let mut hello = rift_core::Hello {
+                max_fps: 120,
+                ..Default::default()
+            };
+            assert_eq!(choose_stream_fps(&hello, 60), 60);
+            hello.stereo_modes = vec![RiftStereoMode::StereoSideBySide as i32];
+            assert_eq!(choose_stream_fps(&hello, 60), 120);
+            hello.max_fps = 240;
+            assert_eq!(choose_stream_fps(&hello, 60), MAX_VR_FPS);
+            hello.max_fps = 0;
+            assert_eq!(choose_stream_fps(&hello, 60), 60);
+        }
+
+        #[test]
+        fn choose_audio_layout_takes_first_capturable_preference() {
+            let mut hello = rift_core::Hello::default();
+            assert_eq!(
+                choose_audio_layout(&hello, true),
+                RiftAudioLayout::AudioStereo
+            );
+            hello.audio_layouts = vec![
+                RiftAudioLayout::AudioSurround71 as i32,
+                RiftAudioLayout::AudioAmbisonicFoa as i32,
+                RiftAudioLayout::AudioStereo as i32,
+            ];
+            assert_eq!(
+                choose_audio_layout(&hello, true),
+                RiftAudioLayout::AudioSurround71
+            );
+            assert_eq!(
+                choose_audio_layout(&hello, false),
+                RiftAudioLayout::AudioStereo
+            );
+        }
+
+        #[test]
+        fn choose_opus_config_applies_client_preferences_within_limits() {
+            let mut hello = rift_core::Hello::default();
+            assert_eq!(choose_opus_config(&hello), OpusConfig::default());
+
+            hello.audio_params = Some(ProtoAudioParams {
+                bitrate_kbps: 0,
+                frame_duration_us: 15_000,
+                inband_fec: true,
+            });
+            let config = choose_opus_config(&hello);
+            assert_eq!(config.bitrate_bps, OpusConfig::default().bitrate_bps);
+            assert_eq!(config.frame_duration_us, 20_000);
+            assert!(config.inband_fec);
+            assert_eq!(config.expected_loss_percent, AUDIO_FEC_LOSS_PERCENT);
+
+            hello.audio_params = Some(ProtoAudioParams {
+                bitrate_kbps: 4_000,
+                frame_duration_us: 0,
+                inband_fec: false,
+            });
+            let ack = opus_config_to_proto(choose_opus_config(&hello));
+            assert_eq!(ack.bitrate_kbps, 510);
+            assert_eq!(ack.frame_duration_us, 5_000);
+            assert!(!ack.inband_fec);
+        }
+
+        #[test]
+        fn steamvr_input_maps_controllers_and_rejects_bad_poses() {
+            let gamepad = rift_core::GamepadMessage {
+                gamepad_id: 1,
+                axes: vec![
+                    rift_core::GamepadAxis {
+                        axis: 2,
+                        value: 1.5,
+                    },
+                    rift_core::GamepadAxis {
+                        axis: 9,
+                        value: 0.5,
+                    },
+                ],
+                buttons: vec![rift_core::GamepadButton {
+                    button: 0,
+                    pressed: true,
+                }],
+            };
+            assert_eq!(
+                steamvr_controller_input(&gamepad),
+                vec![
+                    HostMessage::Button {
+                        path_id: path_to_id("/user/hand/right/input/trigger/value"),
+                        value: ButtonValue::Scalar(1.0),
+                    },
+                    HostMessage::Button {
+                        path_id: path_to_id("/user/hand/right/input/a/click"),
+                        value: ButtonValue::Binary(true),
+                    },
+                ]
+            );
+
+            let pose = rift_core::PoseUpdate {
+                timestamp_us: 7,
+                orientation_w: 1.0,
+                ..Default::default()
+            };
+            assert!(matches!(
+                steamvr_head_tracking(&pose),
+                Some(HostMessage::Tracking {
+                    timestamp_ns: 7_000,
+                    ..
+                })
+            ));
+            let broken = rift_core::PoseUpdate {
+                position_x: f32::NAN,
+                ..Default::default()
+            };
+            assert_eq!(steamvr_head_tracking(&broken), None);
+        }
+
         #[test]
         fn normalize_stream_resolution_clamps_bounds() {
             let fallback = MediaResolution {
@@ -2287,6 +5091,285 @@ mod host {
             assert_eq!(out.height, MAX_STREAM_DIMENSION);
         }
 
+        #[test]
+        fn stream_sessions_share_settings_and_promote_next_peer() {
+            let first: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+            let second: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+            let mut sessions = StreamSessions::default();
+            sessions.peers.push(first);
+            sessions.shared = Some(SharedStream {
+                codec: Codec::Hevc,
+                resolution: ProtoResolution {
+                    width: 1920,
+                    height: 1080,
+                },
+                fps: 60,
+                stereo_mode: RiftStereoMode::StereoAuto,
+                audio_layout: RiftAudioLayout::AudioStereo,
+                audio_opus: OpusConfig::default(),
+                cursor_channel: false,
+            });
+
+            // A lone primary renegotiates; anyone else joins its stream.
+            assert!(sessions.shared_for(first).is_none());
+            assert_eq!(
+                sessions.shared_for(second).map(|s| s.codec),
+                Some(Codec::Hevc)
+            );
+
+            sessions.peers.push(second);
+            assert!(sessions.shared_for(first).is_some());
+            assert!(sessions.remove(first));
+            assert!(sessions.is_primary(second));
+            assert!(sessions.shared.is_some());
+
+            assert!(sessions.remove(second));
+            assert!(sessions.is_empty());
+            assert!(sessions.shared.is_none());
+        }
+
+        #[test]
+        fn display_changes_keep_the_current_display_while_it_is_listed() {
+            let monitor = |id| rift_core::MonitorInfo {
+                id,
+                name: format!("Display {}", id),
+                width: 1920,
+                height: 1080,
+            };
+            let monitors = [monitor(3), monitor(7)];
+            assert_eq!(surviving_display(Some(7), &monitors), Some(7));
+            assert_eq!(surviving_display(Some(5), &monitors), Some(3));
+            assert_eq!(surviving_display(None, &monitors), Some(3));
+            assert_eq!(surviving_display(Some(7), &[]), None);
+        }
+
+        #[test]
+        fn wanted_monitors_merge_flat_sessions_without_the_primary() {
+            let flat: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+            let headset: SocketAddr = "127.0.0.1:4001".parse().unwrap();
+            let other: SocketAddr = "127.0.0.1:4002".parse().unwrap();
+            let mut sessions = StreamSessions::default();
+            let mut peers = HashMap::new();
+            for (peer, stereo_mode, additional) in [
+                (flat, RiftStereoMode::StereoMono, vec![2, 1, 3]),
+                (headset, RiftStereoMode::StereoDualStream, vec![5]),
+                (other, RiftStereoMode::StereoAuto, vec![3, 4, 6]),
+            ] {
+                let mut state = PeerState::new(None, 8_000);
+                state.stereo_mode = stereo_mode;
+                state.additional_monitors = additional;
+                peers.insert(peer, state);
+                sessions.peers.push(peer);
+            }
+
+            assert_eq!(wanted_monitors(&sessions, &peers, Some(1)), vec![2, 3, 4]);
+            assert!(sessions.remove(flat));
+            assert_eq!(wanted_monitors(&sessions, &peers, Some(1)), vec![3, 4, 6]);
+        }
+
+        #[test]
+        fn new_devices_wait_for_an_operator_decision() {
+            let peer: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+            let laptop = IdentityKeypair::generate().wavry_id();
+            let stranger = IdentityKeypair::generate().wavry_id();
+            let mut approvals = ClientApprovals {
+                store: Some(AuthorizedClients::in_memory()),
+                allowed: HashSet::new(),
+                pending: HashMap::new(),
+            };
+
+            assert!(matches!(
+                approvals.admit(peer, None, "anonymous"),
+                Admission::Denied
+            ));
+            assert!(matches!(
+                approvals.admit(peer, Some(&laptop), "laptop"),
+                Admission::Pending
+            ));
+            assert!(matches!(
+                approvals.admit(peer, Some(&stranger), "stranger"),
+                Admission::Pending
+            ));
+            // Resent hellos don't announce the device twice.
+            assert!(matches!(
+                approvals.admit(peer, Some(&laptop), "laptop"),
+                Admission::Pending
+            ));
+            assert_eq!(approvals.pending.len(), 2);
+
+            approvals
+                .apply(&format!("allow {}", &laptop.as_str()[..12]))
+                .unwrap();
+            approvals
+                .apply(&format!("deny {}", stranger.as_str()))
+                .unwrap();
+            assert!(approvals.apply("allow not-a-device").is_err());
+            assert!(approvals.pending.is_empty());
+            assert!(matches!(
+                approvals.admit(peer, Some(&laptop), "laptop"),
+                Admission::Allowed
+            ));
+            assert!(matches!(
+                approvals.admit(peer, Some(&stranger), "stranger"),
+                Admission::Denied
+            ));
+        }
+
+        #[test]
+        fn pending_approvals_are_capped_and_expire() {
+            let peer: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+            let trusted = IdentityKeypair::generate().wavry_id();
+            let mut approvals = ClientApprovals {
+                store: Some(AuthorizedClients::in_memory()),
+                allowed: HashSet::from([trusted.clone()]),
+                pending: HashMap::new(),
+            };
+            assert!(matches!(
+                approvals.admit(peer, Some(&trusted), "server"),
+                Admission::Allowed
+            ));
+
+            let first = IdentityKeypair::generate().wavry_id();
+            approvals.admit(peer, Some(&first), "first");
+            for _ in 0..MAX_PENDING_APPROVALS {
+                let id = IdentityKeypair::generate().wavry_id();
+                approvals.admit(peer, Some(&id), "flood");
+            }
+            assert_eq!(approvals.pending.len(), MAX_PENDING_APPROVALS);
+            assert!(!approvals.pending.contains_key(&first));
+
+            approvals.expire(time::Instant::now() + PENDING_APPROVAL_TTL);
+            assert!(approvals.pending.is_empty());
+        }
+
+        #[test]
+        fn client_messages_need_the_matching_permission() {
+            use rift_core::control_message::Content as Control;
+            use rift_core::message::Content;
+
+            let control = |content| {
+                Content::Control(ProtoControl {
+                    content: Some(content),
+                })
+            };
+            let key = Content::Input(rift_core::InputMessage {
+                event: Some(rift_core::input_message::Event::Key(rift_core::Key {
+                    keycode: 30,
+                    pressed: true,
+                })),
+                ..Default::default()
+            });
+            let clipboard = control(Control::Clipboard(rift_core::ClipboardMessage {
+                text: "secret".into(),
+            }));
+            let chunk = Content::Media(rift_core::MediaMessage {
+                content: Some(rift_core::media_message::Content::FileChunk(
+                    rift_core::FileChunk::default(),
+                )),
+            });
+            let hello = control(Control::Hello(rift_core::Hello::default()));
+
+            let view_only = PermissionSet::VIEW;
+            assert!(view_only.contains(required_permission(&hello)));
+            assert!(!view_only.contains(required_permission(&key)));
+            assert!(!view_only.contains(required_permission(&clipboard)));
+            assert!(!view_only.contains(required_permission(&chunk)));
+
+            let input_only: PermissionSet = "input".parse().unwrap();
+            assert!(input_only.contains(required_permission(&key)));
+            assert!(!input_only.contains(required_permission(&clipboard)));
+            assert!(PermissionSet::all().contains(required_permission(&chunk)));
+        }
+
+        #[test]
+        fn session_limits_pause_idle_input_and_end_long_sessions() {
+            let mut state = PeerState::new(None, 8_000);
+            let started = time::Instant::now();
+            let hour = Duration::from_secs(3600);
+            let idle = Some(Duration::from_secs(300));
+            let later = |secs| started + Duration::from_secs(secs);
+
+            // Nothing applies before a Hello starts the session.
+            assert_eq!(
+                session_limit(&state, Some(hour), idle, later(7200)),
+                SessionLimit::Within
+            );
+
+            state.session_started = Some(started);
+            state.last_input = started;
+            state.permissions = PermissionSet::all();
+            assert_eq!(
+                session_limit(&state, Some(hour), idle, later(299)),
+                SessionLimit::Within
+            );
+            assert_eq!(
+                session_limit(&state, Some(hour), idle, later(300)),
+                SessionLimit::Idle
+            );
+            assert_eq!(
+                session_limit(&state, None, None, later(300)),
+                SessionLimit::Within
+            );
+            assert_eq!(
+                session_limit(&state, Some(hour), idle, later(3600)),
+                SessionLimit::Expired
+            );
+
+            state.permissions = PermissionSet::VIEW;
+            assert_eq!(
+                session_limit(&state, Some(hour), idle, later(600)),
+                SessionLimit::Within
+            );
+        }
+
+        #[test]
+        fn hello_supports_codec_matches_advertised_codecs() {
+            let hello = rift_core::Hello {
+                supported_codecs: vec![RiftCodec::H264 as i32, RiftCodec::Hevc as i32],
+                ..Default::default()
+            };
+            assert!(hello_supports_codec(&hello, Codec::Hevc));
+            assert!(!hello_supports_codec(&hello, Codec::Av1));
+        }
+
+        #[test]
+        fn cursor_update_attaches_shape_on_request() {
+            let cursor = CursorState {
+                x: 0.5,
+                y: 0.25,
+                visible: true,
+                serial: 7,
+                shape: CursorShape::Bitmap {
+                    width: 2,
+                    height: 2,
+                    hotspot_x: 1,
+                    hotspot_y: 0,
+                    bgra: vec![255; 16],
+                },
+            };
+            let update = cursor_update(&cursor, false);
+            assert_eq!(update.shape_serial, 7);
+            assert!(update.bgra.is_empty());
+
+            let update = cursor_update(&cursor, true);
+            assert_eq!((update.width, update.height, update.hotspot_x), (2, 2, 1));
+            assert_eq!(update.bgra.len(), 16);
+
+            let oversized = CursorState {
+                shape: CursorShape::Bitmap {
+                    width: MAX_CURSOR_SIZE + 1,
+                    height: 1,
+                    hotspot_x: 0,
+                    hotspot_y: 0,
+                    bgra: vec![0; (MAX_CURSOR_SIZE as usize + 1) * 4],
+                },
+                ..cursor
+            };
+            let update = cursor_update(&oversized, true);
+            assert_eq!(update.system_cursor(), RiftSystemCursor::Arrow);
+            assert!(update.bgra.is_empty());
+        }
+
         #[test]
         fn rotate_to_next_ready_transfer_skips_paused_and_finished() {
             let dir = temp_dir("transfer-rotate");
@@ -2297,18 +5380,18 @@ mod host {
             fs::write(&file_b, vec![2u8; 1500]).expect("write b");
             fs::write(&file_c, vec![3u8; 1500]).expect("write c");
 
-            let mut a = OutgoingFile::from_path(&file_a, 11, 300, DEFAULT_MAX_FILE_BYTES)
+            let mut a = OutgoingFile::from_path(&file_a, 11, 300, MAX_FILE_TRANSFER_BYTES)
                 .expect("create outgoing a");
             a.mark_header_sent();
             a.pause();
 
-            let mut b = OutgoingFile::from_path(&file_b, 22, 300, DEFAULT_MAX_FILE_BYTES)
+            let mut b = OutgoingFile::from_path(&file_b, 22, 300, MAX_FILE_TRANSFER_BYTES)
                 .expect("create outgoing b");
             b.mark_header_sent();
             b.set_next_chunk(b.offer().total_chunks)
                 .expect("set b finished");
 
-            let c = OutgoingFile::from_path(&file_c, 33, 300, DEFAULT_MAX_FILE_BYTES)
+            let c = OutgoingFile::from_path(&file_c, 33, 300, MAX_FILE_TRANSFER_BYTES)
                 .expect("create outgoing c");
 
             let mut queue = VecDeque::from([a, b, c]);
@@ -2329,6 +5412,55 @@ mod host {
             assert!(!sanitized.contains('\r'));
             assert!(sanitized.len() <= MAX_FILE_STATUS_MESSAGE_CHARS);
         }
+
+        #[test]
+        fn client_cap_bounds_the_congestion_target() {
+            let mut state = PeerState::new(None, 20_000);
+            state.set_bitrate_cap(8_000);
+            assert_eq!(state.target_bitrate_kbps, 8_000);
+            for _ in 0..50 {
+                state.cc.on_rtt_sample(10_000, 0.0, 100);
+                state.sync_target_bitrate();
+            }
+            assert!(state.target_bitrate_kbps <= 8_000);
+
+            state.set_bitrate_cap(10);
+            assert_eq!(state.target_bitrate_kbps, MIN_PEER_BITRATE_KBPS);
+
+            state.reset_bitrate(20_000);
+            assert_eq!(state.bitrate_cap_kbps, None);
+            assert_eq!(state.target_bitrate_kbps, 20_000);
+        }
+
+        #[test]
+        fn mdns_label_is_the_short_machine_name() {
+            assert_eq!(mdns_host_label("Living Room PC.lan"), "Living-Room-PC");
+            assert_eq!(mdns_host_label("gaming-rig"), "gaming-rig");
+            assert_eq!(mdns_host_label(".."), "wavry-host");
+        }
+
+        #[test]
+        fn recording_requests_need_remote_control() {
+            let dir = temp_dir("recording");
+            let config = RecorderConfig {
+                output_dir: dir.clone(),
+                ..Default::default()
+            };
+            let peer: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+            let mut host_only = RecordingController::new(config.clone(), false, false).unwrap();
+            let status = host_only.handle_request(peer, true);
+            assert!(!status.recording);
+            assert!(!status.error.is_empty());
+
+            let mut remote = RecordingController::new(config, false, true).unwrap();
+            assert!(remote.handle_request(peer, true).recording);
+            let status = remote.handle_request(peer, false);
+            assert!(!status.recording);
+            assert!(status.error.is_empty());
+
+            fs::remove_dir_all(dir).ok();
+        }
     }
 }
 
@@ -5,31 +5,39 @@ use sha2::{Digest, Sha256};
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::net::TcpStream;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use tokio_tungstenite::{
     connect_async, tungstenite::protocol::Message as WsMessage, MaybeTlsStream, WebSocketStream,
 };
 use tracing::{debug, error, info, warn};
-use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264};
-use webrtc::api::APIBuilder;
-use webrtc::ice_transport::ice_server;
-use webrtc::media::Sample;
-use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
-use webrtc::peer_connection::RTCPeerConnection;
-use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
-use webrtc::track::track_local::TrackLocal;
+use wavry_web::{
+    ControlMessage, ControlStreamFrame, InputDatagram, InputTranslator, LocalIceCandidate,
+    WebControlResponse, WebRtcHost, WebRtcPeer, WebRtcSignaling, WebRtcStartParams, WebVideoCodec,
+    CONTROL_CHANNEL_LABEL, INPUT_PROTOCOL_VERSION, WEB_STATS_INTERVAL,
+};
+use webrtc::data_channel::RTCDataChannel;
 
 use wavry_common::protocol::SignalMessage;
 use wavry_media::EncodedFrame;
 
 const SIGNALING_TLS_PINS_ENV: &str = "WAVRY_SIGNALING_TLS_PINS_SHA256";
+/// Control frames buffered per browser before a slow data channel starts dropping them.
+const CONTROL_BROADCAST_CAPACITY: usize = 256;
 
 pub struct WebRtcBridge {
     gateway_url: String,
     session_token: String,
-    video_track: Arc<TrackLocalStaticSample>,
-    peer_connection: Arc<Mutex<Option<RTCPeerConnection>>>,
+    host: WebRtcHost,
+    local_candidates: Mutex<Option<mpsc::UnboundedReceiver<LocalIceCandidate>>>,
     input_tx: mpsc::UnboundedSender<rift_core::input_message::Event>,
+    control_tx: mpsc::UnboundedSender<ViewerMessage>,
+    viewer_tx: broadcast::Sender<String>,
+}
+
+/// A RIFT message on behalf of one browser viewer.
+pub struct ViewerMessage {
+    pub peer_id: String,
+    pub content: rift_core::message::Content,
 }
 
 fn env_bool(name: &str, default: bool) -> bool {
@@ -179,26 +187,21 @@ impl WebRtcBridge {
         gateway_url: String,
         session_token: String,
         input_tx: mpsc::UnboundedSender<rift_core::input_message::Event>,
+        control_tx: mpsc::UnboundedSender<ViewerMessage>,
+        initial_bitrate_kbps: u32,
     ) -> Result<Self> {
-        let mut m = MediaEngine::default();
-        m.register_default_codecs()?;
-        let _api = APIBuilder::new().with_media_engine(m).build();
-
-        let video_track = Arc::new(TrackLocalStaticSample::new(
-            webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability {
-                mime_type: MIME_TYPE_H264.to_string(),
-                ..Default::default()
-            },
-            "video".to_string(),
-            "webrtc-rs".to_string(),
-        ));
+        let (host, local_candidates) =
+            WebRtcHost::new(Vec::new(), WebVideoCodec::H264, initial_bitrate_kbps);
+        let (viewer_tx, _) = broadcast::channel(CONTROL_BROADCAST_CAPACITY);
 
         Ok(Self {
             gateway_url,
             session_token,
-            video_track,
-            peer_connection: Arc::new(Mutex::new(None)),
+            host,
+            local_candidates: Mutex::new(Some(local_candidates)),
             input_tx,
+            control_tx,
+            viewer_tx,
         })
     }
 
@@ -222,6 +225,22 @@ impl WebRtcBridge {
         let (mut write, mut read) = ws_stream.split();
         let (signal_tx, mut signal_rx) = mpsc::channel::<SignalMessage>(32);
 
+        // Relay locally gathered ICE candidates to the browser they belong to.
+        if let Some(mut local_candidates) = self.local_candidates.lock().await.take() {
+            let candidate_tx = signal_tx.clone();
+            tokio::spawn(async move {
+                while let Some(local) = local_candidates.recv().await {
+                    let signal = SignalMessage::CANDIDATE {
+                        target_username: local.peer_id,
+                        candidate: local.candidate,
+                    };
+                    if candidate_tx.send(signal).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
         // Task to send signals back to gateway
         tokio::spawn(async move {
             while let Some(signal) = signal_rx.recv().await {
@@ -271,8 +290,16 @@ impl WebRtcBridge {
             } => {
                 info!("Received WebRTC offer from {}", target_username);
                 let answer_sdp = self
-                    .create_answer(sdp, target_username.clone(), tx.clone())
+                    .host
+                    .on_offer(WebRtcStartParams {
+                        peer_id: target_username.clone(),
+                        session_token: self.session_token.clone(),
+                        offer_sdp: sdp,
+                    })
                     .await?;
+                if let Some(peer) = self.host.peer(&target_username).await {
+                    self.attach_data_channels(&peer);
+                }
                 tx.send(SignalMessage::ANSWER {
                     target_username,
                     sdp: answer_sdp,
@@ -280,16 +307,26 @@ impl WebRtcBridge {
                 })
                 .await?;
             }
+            SignalMessage::ANSWER {
+                target_username,
+                sdp,
+                ..
+            } => {
+                if let Err(e) = self.host.on_answer(&target_username, sdp).await {
+                    warn!("Ignoring WebRTC answer from {}: {}", target_username, e);
+                }
+            }
             SignalMessage::CANDIDATE {
                 target_username,
                 candidate,
             } => {
                 debug!("Received ICE candidate from {}", target_username);
-                let pc_guard = self.peer_connection.lock().await;
-                if let Some(pc) = &*pc_guard {
-                    if let Ok(ice_candidate) = serde_json::from_str(&candidate) {
-                        pc.add_ice_candidate(ice_candidate).await?;
-                    }
+                if let Err(e) = self
+                    .host
+                    .on_ice_candidate(&target_username, candidate)
+                    .await
+                {
+                    warn!("Ignoring ICE candidate from {}: {}", target_username, e);
                 }
             }
             _ => {}
@@ -297,96 +334,183 @@ impl WebRtcBridge {
         Ok(())
     }
 
-    async fn create_answer(
-        &self,
-        offer_sdp: String,
-        target_username: String,
-        tx: mpsc::Sender<SignalMessage>,
-    ) -> Result<String> {
-        let mut m = MediaEngine::default();
-        m.register_default_codecs()?;
-        let api = APIBuilder::new().with_media_engine(m).build();
-
-        let config = webrtc::peer_connection::configuration::RTCConfiguration {
-            ice_servers: vec![ice_server::RTCIceServer {
-                urls: vec!["stun:stun.l.google.com:19302".to_string()],
-                ..Default::default()
-            }],
-            ..Default::default()
-        };
-
-        let pc = api.new_peer_connection(config).await?;
-
-        let track = Arc::clone(&self.video_track);
-        pc.add_track(track as Arc<dyn TrackLocal + Send + Sync>)
-            .await?;
-
+    fn attach_data_channels(&self, peer: &Arc<WebRtcPeer>) {
         let input_tx = self.input_tx.clone();
-        pc.on_data_channel(Box::new(move |d| {
+        let control_tx = self.control_tx.clone();
+        let viewer_tx = self.viewer_tx.clone();
+        let channel_peer = peer.clone();
+        let translator = Arc::new(std::sync::Mutex::new(InputTranslator::default()));
+        peer.connection().on_data_channel(Box::new(move |d| {
             let input_tx = input_tx.clone();
+            let control_tx = control_tx.clone();
+            let viewer_tx = viewer_tx.clone();
+            let peer = channel_peer.clone();
+            let translator = translator.clone();
             Box::pin(async move {
-                if d.label() == "input" {
+                if d.label() == CONTROL_CHANNEL_LABEL {
+                    attach_control_channel(d, peer, control_tx, viewer_tx);
+                } else if d.label() == "input" {
                     info!("WebRTC input data channel opened");
                     d.on_message(Box::new(move |msg| {
                         let input_tx = input_tx.clone();
+                        let translator = translator.clone();
                         Box::pin(async move {
-                            if let Ok(input_msg) = rift_core::InputMessage::decode(msg.data) {
-                                if let Some(event) = input_msg.event {
-                                    let _ = input_tx.send(event);
-                                }
+                            // Browsers send compact input datagrams; native peers send protobuf.
+                            let input_msg = if msg.data.first() == Some(&INPUT_PROTOCOL_VERSION) {
+                                InputDatagram::decode(msg.data).and_then(|datagram| {
+                                    translator.lock().ok()?.translate_datagram(&datagram)
+                                })
+                            } else {
+                                rift_core::InputMessage::decode(msg.data).ok()
+                            };
+                            if let Some(event) = input_msg.and_then(|m| m.event) {
+                                let _ = input_tx.send(event);
                             }
                         })
                     }));
                 }
             })
         }));
+    }
 
-        pc.on_ice_candidate(Box::new(move |c| {
-            let tx = tx.clone();
-            let target = target_username.clone();
-            Box::pin(async move {
-                if let Some(candidate) = c {
-                    let _ = tx
-                        .send(SignalMessage::CANDIDATE {
-                            target_username: target,
-                            candidate: candidate.to_json().unwrap().candidate,
-                        })
-                        .await;
-                }
-            })
-        }));
-
-        pc.set_remote_description(RTCSessionDescription::offer(offer_sdp).unwrap())
-            .await?;
-
-        let answer = pc.create_answer(None).await?;
-        pc.set_local_description(answer.clone()).await?;
+    /// Whether any browser has an open control channel.
+    pub fn has_viewers(&self) -> bool {
+        self.viewer_tx.receiver_count() > 0
+    }
 
-        let mut pc_guard = self.peer_connection.lock().await;
-        *pc_guard = Some(pc);
+    /// Forward a host clipboard or file-transfer message to every browser.
+    /// Returns false when no browser received it.
+    pub fn send_to_viewers(&self, content: rift_core::message::Content) -> bool {
+        let Some(response) = wavry_web::rift_to_response(content) else {
+            return false;
+        };
+        match serde_json::to_string(&ControlStreamFrame::Response(response)) {
+            Ok(text) => self.viewer_tx.send(text).is_ok(),
+            Err(e) => {
+                warn!("failed to encode browser control frame: {}", e);
+                false
+            }
+        }
+    }
 
-        Ok(answer.sdp)
+    /// Bitrate the connected browsers can sustain, from RTCP feedback.
+    pub async fn target_bitrate_kbps(&self) -> Option<u32> {
+        self.host.target_bitrate_kbps().await
     }
 
     pub async fn push_frame(&self, frame: EncodedFrame) -> Result<()> {
         // Only push if we have an active connection
-        let pc_guard = self.peer_connection.lock().await;
-        if pc_guard.is_none() {
+        if !self.host.has_peers().await {
             return Ok(());
         }
 
-        self.video_track
-            .write_sample(&Sample {
-                data: frame.data.into(),
-                duration: std::time::Duration::from_micros(16666), // 60fps approx
-                ..Default::default()
-            })
+        self.host
+            .write_frame(&frame.data, frame.timestamp_us)
             .await?;
 
         Ok(())
     }
 }
 
+/// JSON control frames: clipboard, file transfer and stats in both directions.
+fn attach_control_channel(
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<WebRtcPeer>,
+    control_tx: mpsc::UnboundedSender<ViewerMessage>,
+    viewer_tx: broadcast::Sender<String>,
+) {
+    info!("WebRTC control data channel opened for {}", peer.peer_id);
+    let inbound_peer = peer.clone();
+    let reply = channel.clone();
+    channel.on_message(Box::new(move |msg| {
+        let peer = inbound_peer.clone();
+        let control_tx = control_tx.clone();
+        let reply = reply.clone();
+        Box::pin(async move {
+            let frame = match serde_json::from_slice::<ControlStreamFrame>(&msg.data) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    debug!("invalid control frame from {}: {}", peer.peer_id, e);
+                    return;
+                }
+            };
+            let content = match frame {
+                ControlStreamFrame::Stats(report) => {
+                    peer.on_stats_report(&report);
+                    let period_ms = WEB_STATS_INTERVAL.as_millis() as u32;
+                    Some(rift_core::message::Content::Control(
+                        rift_core::ControlMessage {
+                            content: Some(rift_core::control_message::Content::Stats(
+                                report.to_rift(period_ms),
+                            )),
+                        },
+                    ))
+                }
+                ControlStreamFrame::Control(ControlMessage::StatsRequest) => {
+                    if let Some(report) = peer.stats_report() {
+                        let response =
+                            ControlStreamFrame::Response(WebControlResponse::Stats(report));
+                        let _ = send_frame(&reply, &response).await;
+                    }
+                    None
+                }
+                ControlStreamFrame::Control(control) => wavry_web::control_to_rift(control),
+                ControlStreamFrame::Response(_) => None,
+            };
+            if let Some(content) = content {
+                let _ = control_tx.send(ViewerMessage {
+                    peer_id: peer.peer_id.clone(),
+                    content,
+                });
+            }
+        })
+    }));
+
+    let outbound = channel.clone();
+    channel.on_open(Box::new(move || {
+        let viewer_rx = viewer_tx.subscribe();
+        Box::pin(async move {
+            tokio::spawn(forward_to_viewer(outbound, peer, viewer_rx));
+        })
+    }));
+}
+
+/// Relay host broadcasts and report this browser's stats until the channel closes.
+async fn forward_to_viewer(
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<WebRtcPeer>,
+    mut viewer_rx: broadcast::Receiver<String>,
+) {
+    let mut stats_tick = tokio::time::interval(WEB_STATS_INTERVAL);
+    loop {
+        tokio::select! {
+            frame = viewer_rx.recv() => match frame {
+                Ok(text) => {
+                    if channel.send_text(text).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("control channel to {} dropped {} frames", peer.peer_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = stats_tick.tick() => {
+                if let Some(report) = peer.stats_report() {
+                    if send_frame(&channel, &ControlStreamFrame::Stats(report)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send_frame(channel: &RTCDataChannel, frame: &ControlStreamFrame) -> Result<()> {
+    channel.send_text(serde_json::to_string(frame)?).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::{normalize_fingerprint, parse_tls_pin_set};
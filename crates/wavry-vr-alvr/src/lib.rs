@@ -10,7 +10,9 @@ pub use vendor::AlvrAdapter;
 mod stub {
     use std::sync::Arc;
 
-    use wavry_vr::types::{Pose, StreamConfig, VideoFrame};
+    use wavry_vr::types::{
+        HapticFeedback, OverlayConfig, Pose, StereoMode, StreamConfig, VideoFrame,
+    };
     use wavry_vr::{VrAdapter, VrAdapterCallbacks, VrError, VrResult};
 
     pub struct AlvrAdapter {
@@ -21,6 +23,12 @@ mod stub {
         pub fn new() -> Self {
             Self { _callback: None }
         }
+
+        pub fn with_overlay(self, _overlay: OverlayConfig) -> Self {
+            self
+        }
+
+        pub fn set_overlay(&mut self, _overlay: OverlayConfig) {}
     }
 
     impl Default for AlvrAdapter {
@@ -51,8 +59,22 @@ mod stub {
             ))
         }
 
+        fn submit_haptic(&mut self, _haptic: HapticFeedback) -> VrResult<()> {
+            Err(VrError::Unavailable(
+                "ALVR adapter not enabled. Build with feature 'alvr'.".to_string(),
+            ))
+        }
+
         fn configure_stream(&mut self, _config: StreamConfig) {}
 
+        fn stereo_modes(&self) -> Vec<StereoMode> {
+            Vec::new()
+        }
+
+        fn refresh_hz(&self) -> Option<u32> {
+            None
+        }
+
         fn on_network_stats(&mut self, _stats: wavry_vr::types::NetworkStats) {}
 
         fn on_encoder_control(&mut self, _control: wavry_vr::types::EncoderControl) {}
@@ -11,8 +11,11 @@ use std::sync::Arc;
 use std::thread::JoinHandle;
 
 use glam::{Quat, Vec3};
-use wavry_vr::types::{EncoderControl, NetworkStats, Pose, StreamConfig, VideoFrame};
-use wavry_vr::{VrAdapter, VrAdapterCallbacks, VrError, VrResult};
+use wavry_vr::types::{
+    EncoderControl, Eye, HapticFeedback, NetworkStats, OverlayConfig, Pose, StereoMode,
+    StreamConfig, VideoFrame,
+};
+use wavry_vr::{PosePredictor, VrAdapter, VrAdapterCallbacks, VrError, VrResult};
 use wavry_vr_openxr::{spawn_runtime, SharedState};
 
 // Minimal ALVR primitives (vendored) for compatibility with ALVR types.
@@ -23,6 +26,12 @@ mod alvr_primitives;
 pub struct AlvrAdapter {
     state: Option<Arc<SharedState>>,
     runtime: Option<JoinHandle<()>>,
+    pose_predictor: PosePredictor,
+    prediction_latency_us: u64,
+    config: Option<StreamConfig>,
+    /// Decoders cannot start mid-GOP, so frames wait for a keyframe after (re)configuration.
+    awaiting_keyframe: bool,
+    overlay: Option<OverlayConfig>,
 }
 
 impl AlvrAdapter {
@@ -30,8 +39,38 @@ impl AlvrAdapter {
         Self {
             state: None,
             runtime: None,
+            pose_predictor: PosePredictor::default(),
+            prediction_latency_us: 0,
+            config: None,
+            awaiting_keyframe: true,
+            overlay: None,
         }
     }
+
+    /// Show the stream as a floating screen inside whatever VR app is running.
+    pub fn with_overlay(mut self, overlay: OverlayConfig) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    /// Move or resize the overlay screen. Has no effect outside overlay mode.
+    pub fn set_overlay(&mut self, overlay: OverlayConfig) {
+        if self.overlay.is_none() {
+            return;
+        }
+        self.overlay = Some(overlay);
+        if let Some(state) = self.state.as_ref() {
+            if let Ok(mut slot) = state.overlay.lock() {
+                *slot = Some(overlay);
+            }
+        }
+    }
+
+    fn running_state(&self) -> VrResult<&Arc<SharedState>> {
+        self.state
+            .as_ref()
+            .ok_or_else(|| VrError::Adapter("adapter not started".to_string()))
+    }
 }
 
 impl Default for AlvrAdapter {
@@ -42,15 +81,29 @@ impl Default for AlvrAdapter {
 
 impl VrAdapter for AlvrAdapter {
     fn start(&mut self, cb: Arc<dyn VrAdapterCallbacks>) -> VrResult<()> {
+        if self.runtime.is_some() {
+            return Err(VrError::Adapter("adapter already started".to_string()));
+        }
         let state = Arc::new(SharedState::new(cb));
+        state
+            .prediction_latency_us
+            .store(self.prediction_latency_us, Ordering::Relaxed);
+        if let Ok(mut cfg) = state.stream_config.lock() {
+            *cfg = self.config;
+        }
+        if let Ok(mut overlay) = state.overlay.lock() {
+            *overlay = self.overlay;
+        }
         let runtime = spawn_runtime(state.clone())?;
         self.state = Some(state);
         self.runtime = Some(runtime);
+        self.awaiting_keyframe = true;
+        self.pose_predictor.reset();
         Ok(())
     }
 
     fn stop(&mut self) {
-        if let Some(state) = self.state.as_ref() {
+        if let Some(state) = self.state.take() {
             state.stop.store(true, Ordering::Relaxed);
         }
         if let Some(handle) = self.runtime.take() {
@@ -59,18 +112,28 @@ impl VrAdapter for AlvrAdapter {
     }
 
     fn submit_video(&mut self, frame: VideoFrame) -> VrResult<()> {
-        if let Some(state) = self.state.as_ref() {
-            if let Ok(mut slot) = state.latest_frame.lock() {
-                *slot = Some(frame);
+        let state = self.running_state()?;
+        // The compositor presents one decoded stream; a second eye stream is never negotiated.
+        if frame.eye == Some(Eye::Right) {
+            return Ok(());
+        }
+        if self.awaiting_keyframe {
+            if !frame.keyframe {
+                return Ok(());
             }
-            Ok(())
-        } else {
-            Err(VrError::Adapter("adapter not started".to_string()))
+            self.awaiting_keyframe = false;
         }
+        state.submit_frame(frame);
+        Ok(())
     }
 
-    fn submit_pose(&mut self, pose: Pose, _timestamp_us: u64) -> VrResult<()> {
+    fn submit_pose(&mut self, pose: Pose, timestamp_us: u64) -> VrResult<()> {
         // Pose submission hook for server-side OpenVR integration.
+        self.pose_predictor.observe(pose, timestamp_us);
+        let pose = self
+            .pose_predictor
+            .predict(timestamp_us + self.prediction_latency_us)
+            .unwrap_or(pose);
         let _alvr_pose = alvr_primitives::Pose {
             orientation: Quat::from_xyzw(
                 pose.orientation[0],
@@ -83,7 +146,24 @@ impl VrAdapter for AlvrAdapter {
         Ok(())
     }
 
+    fn submit_haptic(&mut self, haptic: HapticFeedback) -> VrResult<()> {
+        self.running_state()?.queue_haptic(haptic);
+        Ok(())
+    }
+
     fn configure_stream(&mut self, config: StreamConfig) {
+        let changed = self.config.is_none_or(|current| {
+            current.codec != config.codec
+                || current.width != config.width
+                || current.height != config.height
+        });
+        if changed {
+            self.awaiting_keyframe = true;
+        }
+        if config.stereo == StereoMode::DualStream {
+            log::warn!("ALVR adapter presents only the left eye of dual-stream video");
+        }
+        self.config = Some(config);
         if let Some(state) = self.state.as_ref() {
             if let Ok(mut cfg) = state.stream_config.lock() {
                 *cfg = Some(config);
@@ -91,11 +171,35 @@ impl VrAdapter for AlvrAdapter {
         }
     }
 
+    fn stereo_modes(&self) -> Vec<StereoMode> {
+        // A virtual screen shows a flat desktop.
+        if self.overlay.is_some() {
+            return vec![StereoMode::Mono];
+        }
+        // The OpenXR compositor decodes a single stream and splits packed frames.
+        vec![StereoMode::SideBySide, StereoMode::Mono]
+    }
+
+    fn refresh_hz(&self) -> Option<u32> {
+        let state = self.state.as_ref()?;
+        match state.refresh_hz.load(Ordering::Relaxed) {
+            0 => None,
+            hz => Some(hz),
+        }
+    }
+
     fn on_network_stats(&mut self, stats: NetworkStats) {
-        let _ = stats;
+        // A pose travels one way and its frame comes back, so predict a full round trip.
+        self.prediction_latency_us = stats.rtt_us + stats.jitter_us as u64;
+        if let Some(state) = self.state.as_ref() {
+            state
+                .prediction_latency_us
+                .store(self.prediction_latency_us, Ordering::Relaxed);
+        }
     }
 
     fn on_encoder_control(&mut self, control: EncoderControl) {
+        // The host skips encoding for these frames; nothing is pending locally.
         let _ = control;
     }
 }
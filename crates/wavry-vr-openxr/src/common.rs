@@ -1,11 +1,18 @@
 use openxr as xr;
 use std::time::{Duration, Instant};
-use wavry_vr::types::{GamepadAxis, GamepadButton, GamepadInput, HandPose, Pose, StreamConfig};
-use wavry_vr::{VrError, VrResult};
+use wavry_vr::types::{
+    EyeView, FoveationHint, GamepadAxis, GamepadButton, GamepadInput, HandPose, HapticFeedback,
+    OverlayConfig, Pose, StereoMode, StreamConfig, VrTiming,
+};
+use wavry_vr::{relative_pose, reproject_view, PhaseEstimator, VrError, VrResult};
 
 pub const INPUT_SEND_INTERVAL: Duration = Duration::from_millis(20);
 pub const AXIS_EPS: f32 = 0.01;
 pub const STICK_DEADZONE: f32 = 0.05;
+/// Full-quality radius around the gaze point, as a fraction of the eye width.
+pub const FOVEATION_RADIUS: f32 = 0.12;
+/// Minimum spacing of timing reports that only carry new reprojection or phase data.
+pub const TIMING_SEND_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Clone, Copy, Default)]
 pub struct GamepadSnapshot {
@@ -23,14 +30,29 @@ pub struct InputActions {
     pub stick: xr::Action<xr::Vector2f>,
     pub primary: xr::Action<bool>,
     pub secondary: xr::Action<bool>,
+    pub haptic: xr::Action<xr::Haptic>,
     pub left: xr::Path,
     pub right: xr::Path,
     pub last_sent: [GamepadSnapshot; 2],
     pub last_sent_at: [Instant; 2],
+    pub eye_gaze: Option<EyeGaze>,
+}
+
+/// XR_EXT_eye_gaze_interaction pose, located relative to the head.
+pub struct EyeGaze {
+    pub action: xr::Action<xr::Posef>,
+    pub space: xr::Space,
+    pub view_space: xr::Space,
 }
 
 impl InputActions {
-    pub fn new<G>(instance: &xr::Instance, session: &xr::Session<G>) -> VrResult<Self> {
+    /// `eye_gaze` requests gaze tracking; the runtime must have enabled
+    /// XR_EXT_eye_gaze_interaction on the instance.
+    pub fn new<G: xr::Graphics>(
+        instance: &xr::Instance,
+        session: &xr::Session<G>,
+        eye_gaze: bool,
+    ) -> VrResult<Self> {
         let action_set = instance
             .create_action_set("wavry", "Wavry", 0)
             .map_err(|e| VrError::Adapter(format!("OpenXR action set: {e:?}")))?;
@@ -64,6 +86,9 @@ impl InputActions {
         let secondary = action_set
             .create_action("secondary", "Secondary", &subaction_paths)
             .map_err(|e| VrError::Adapter(format!("OpenXR action secondary: {e:?}")))?;
+        let haptic = action_set
+            .create_action("haptic", "Haptic", &subaction_paths)
+            .map_err(|e| VrError::Adapter(format!("OpenXR action haptic: {e:?}")))?;
 
         let profile_paths = [
             "/interaction_profiles/khr/simple_controller",
@@ -77,7 +102,7 @@ impl InputActions {
             let profile_path = instance
                 .string_to_path(profile)
                 .map_err(|e| VrError::Adapter(format!("OpenXR profile path: {e:?}")))?;
-            let bindings = Self::bindings_for_profile(
+            let mut bindings = Self::bindings_for_profile(
                 instance,
                 profile,
                 &trigger,
@@ -88,6 +113,14 @@ impl InputActions {
                 &primary,
                 &secondary,
             )?;
+            // Every supported profile exposes a haptic output on both hands.
+            for hand in ["left", "right"] {
+                if let Ok(path) =
+                    instance.string_to_path(&format!("/user/hand/{hand}/output/haptic"))
+                {
+                    bindings.push(xr::Binding::new(&haptic, path));
+                }
+            }
             if let Err(err) = instance.suggest_interaction_profile_bindings(profile_path, &bindings)
             {
                 eprintln!(
@@ -97,10 +130,30 @@ impl InputActions {
             }
         }
 
+        let gaze_action = if eye_gaze {
+            Self::create_gaze_action(instance, &action_set)
+        } else {
+            None
+        };
+
         session
             .attach_action_sets(&[&action_set])
             .map_err(|e| VrError::Adapter(format!("OpenXR attach actions: {e:?}")))?;
 
+        let eye_gaze = gaze_action.and_then(|action| {
+            let space = action
+                .create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY)
+                .ok()?;
+            let view_space = session
+                .create_reference_space(xr::ReferenceSpaceType::VIEW, xr::Posef::IDENTITY)
+                .ok()?;
+            Some(EyeGaze {
+                action,
+                space,
+                view_space,
+            })
+        });
+
         Ok(Self {
             action_set,
             trigger,
@@ -110,10 +163,90 @@ impl InputActions {
             stick,
             primary,
             secondary,
+            haptic,
             left,
             right,
             last_sent: [GamepadSnapshot::default(), GamepadSnapshot::default()],
             last_sent_at: [Instant::now(), Instant::now()],
+            eye_gaze,
+        })
+    }
+
+    fn create_gaze_action(
+        instance: &xr::Instance,
+        action_set: &xr::ActionSet,
+    ) -> Option<xr::Action<xr::Posef>> {
+        let action = action_set
+            .create_action::<xr::Posef>("eye_gaze", "Eye Gaze", &[])
+            .ok()?;
+        let profile = instance
+            .string_to_path("/interaction_profiles/ext/eye_gaze_interaction")
+            .ok()?;
+        let gaze_path = instance
+            .string_to_path("/user/eyes_ext/input/gaze_ext/pose")
+            .ok()?;
+        if let Err(err) = instance
+            .suggest_interaction_profile_bindings(profile, &[xr::Binding::new(&action, gaze_path)])
+        {
+            eprintln!("OpenXR eye gaze binding rejected: {:?}", err);
+            return None;
+        }
+        Some(action)
+    }
+
+    /// Play a vibration on the left (0) or right (1) controller.
+    pub fn apply_haptic<G>(
+        &self,
+        session: &xr::Session<G>,
+        haptic: HapticFeedback,
+    ) -> VrResult<()> {
+        let hand = match haptic.controller_id {
+            0 => self.left,
+            1 => self.right,
+            other => {
+                return Err(VrError::Adapter(format!(
+                    "unknown haptic controller id {other}"
+                )))
+            }
+        };
+        // A zero duration maps to XR_MIN_HAPTIC_DURATION (-1) and a zero
+        // frequency is XR_FREQUENCY_UNSPECIFIED.
+        let duration_ns = if haptic.duration_us == 0 {
+            -1
+        } else {
+            haptic
+                .duration_us
+                .saturating_mul(1_000)
+                .min(i64::MAX as u64) as i64
+        };
+        let vibration = xr::HapticVibration::new()
+            .amplitude(haptic.amplitude.clamp(0.0, 1.0))
+            .frequency(haptic.frequency_hz.max(0.0))
+            .duration(xr::Duration::from_nanos(duration_ns));
+        self.haptic
+            .apply_feedback(session, hand, &vibration)
+            .map_err(|e| VrError::Adapter(format!("OpenXR haptic: {e:?}")))
+    }
+
+    /// Gaze point for foveated encoding. Call after [`Self::poll`], which syncs actions.
+    pub fn poll_foveation(
+        &self,
+        time: xr::Time,
+        fov: xr::Fovf,
+        timestamp_us: u64,
+    ) -> Option<FoveationHint> {
+        let gaze = self.eye_gaze.as_ref()?;
+        let location = gaze.space.locate(&gaze.view_space, time).ok()?;
+        if !location
+            .location_flags
+            .contains(xr::SpaceLocationFlags::ORIENTATION_TRACKED)
+        {
+            return None;
+        }
+        Some(FoveationHint {
+            timestamp_us,
+            gaze: gaze_to_image(to_pose(location.pose).orientation, fov)?,
+            radius: FOVEATION_RADIUS,
         })
     }
 
@@ -367,6 +500,78 @@ impl InputActions {
     }
 }
 
+/// Per-frame timing: submits each image at the head pose it was rendered with,
+/// so the compositor reprojects repeated or late frames instead of pinning them
+/// to the head, and measures the vsync phase of frame arrivals for the host.
+#[derive(Default)]
+pub struct FrameTiming {
+    render_pose: Option<Pose>,
+    late_latch: Option<Pose>,
+    phase: PhaseEstimator,
+    period_us: u64,
+    last_refresh_hz: Option<f32>,
+    last_sent: Option<Instant>,
+}
+
+impl FrameTiming {
+    /// A new image was decoded. Without a host-reported view it is latched to
+    /// the head pose it is first displayed at.
+    pub fn new_image(&mut self, view: Option<EyeView>) {
+        self.render_pose = view.map(|view| view.pose);
+    }
+
+    /// A new frame was taken `age_us` after it arrived, right after the frame wait.
+    pub fn observe_frame_age(&mut self, age_us: u64) {
+        self.phase.observe(age_us, self.period_us);
+    }
+
+    /// Projection layer pose for `views[index]`; `views[0]` is the head reference.
+    pub fn layer_pose(&mut self, views: &[xr::View], index: usize) -> xr::Posef {
+        let display = to_pose(views[0].pose);
+        let render = *self.render_pose.get_or_insert(display);
+        self.late_latch = Some(relative_pose(&render, &display));
+        to_xr_pose(reproject_view(
+            &render,
+            &display,
+            &to_pose(views[index].pose),
+        ))
+    }
+
+    /// Timing report, sent when the refresh rate changes and otherwise at most
+    /// every [`TIMING_SEND_INTERVAL`] once reprojection or phase data is available.
+    pub fn timing(&mut self, frame_state: &xr::FrameState, now: Instant) -> Option<VrTiming> {
+        let period_ns = frame_state.predicted_display_period.as_nanos();
+        if period_ns <= 0 {
+            return None;
+        }
+        let refresh_hz = 1_000_000_000.0 / period_ns as f32;
+        if self.period_us != (period_ns / 1_000) as u64 {
+            self.period_us = (period_ns / 1_000) as u64;
+            self.phase.reset();
+        }
+        let changed = self
+            .last_refresh_hz
+            .is_none_or(|prev| (prev - refresh_hz).abs() > 0.1);
+        let due = (self.late_latch.is_some() || self.phase.error_us() != 0)
+            && self
+                .last_sent
+                .is_none_or(|sent| now.duration_since(sent) >= TIMING_SEND_INTERVAL);
+        if !changed && !due {
+            return None;
+        }
+        self.last_refresh_hz = Some(refresh_hz);
+        self.last_sent = Some(now);
+        Some(VrTiming {
+            refresh_hz,
+            vsync_offset_us: self.phase.error_us(),
+            predicted_display_time_us: (frame_state.predicted_display_time.as_nanos() / 1_000)
+                as u64,
+            render_pose: self.render_pose,
+            late_latch: self.late_latch,
+        })
+    }
+}
+
 pub struct EyeLayout {
     pub eye_width: u32,
     pub eye_height: u32,
@@ -376,7 +581,11 @@ pub struct EyeLayout {
 pub fn eye_layout(cfg: StreamConfig) -> EyeLayout {
     let width = cfg.width as u32;
     let height = cfg.height as u32;
-    let is_sbs = width >= height * 2 && width.is_multiple_of(2);
+    let is_sbs = match cfg.stereo {
+        StereoMode::SideBySide => width.is_multiple_of(2),
+        StereoMode::Auto => width >= height * 2 && width.is_multiple_of(2),
+        StereoMode::Mono | StereoMode::DualStream => false,
+    };
     let eye_width = if is_sbs { width / 2 } else { width };
     EyeLayout {
         eye_width,
@@ -385,6 +594,206 @@ pub fn eye_layout(cfg: StreamConfig) -> EyeLayout {
     }
 }
 
+/// Shape and placement of the overlay screen in the LOCAL reference space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScreenGeometry {
+    Quad {
+        pose: xr::Posef,
+        size: xr::Extent2Df,
+    },
+    /// XR_KHR_composition_layer_cylinder; `pose` is the cylinder axis.
+    Cylinder {
+        pose: xr::Posef,
+        radius: f32,
+        central_angle: f32,
+        aspect_ratio: f32,
+    },
+}
+
+/// Lay out the overlay screen in front of the viewer for a `width`x`height`
+/// image. Curvature falls back to a flat quad when `cylinder` is unsupported.
+pub fn screen_geometry(
+    overlay: OverlayConfig,
+    width: u32,
+    height: u32,
+    cylinder: bool,
+) -> ScreenGeometry {
+    let screen_width = overlay.width_m.max(0.1);
+    let distance = overlay.distance_m.max(0.25);
+    let curvature = overlay.curvature.clamp(0.0, 1.0);
+    let aspect_ratio = if height == 0 {
+        16.0 / 9.0
+    } else {
+        width.max(1) as f32 / height as f32
+    };
+    let pose_at = |z: f32| xr::Posef {
+        position: xr::Vector3f { x: 0.0, y: 0.0, z },
+        ..xr::Posef::IDENTITY
+    };
+
+    if !cylinder || curvature <= f32::EPSILON {
+        return ScreenGeometry::Quad {
+            pose: pose_at(-distance),
+            size: xr::Extent2Df {
+                width: screen_width,
+                height: screen_width / aspect_ratio,
+            },
+        };
+    }
+
+    // Keep the arc length at `screen_width`; full curvature puts the axis at the viewer.
+    let radius = distance / curvature;
+    let central_angle = (screen_width / radius).min(std::f32::consts::TAU);
+    ScreenGeometry::Cylinder {
+        pose: pose_at(radius - distance),
+        radius,
+        central_angle,
+        aspect_ratio,
+    }
+}
+
+/// XR_EXTX_overlay chain entry placing our layers above the main app's.
+pub fn overlay_session_info() -> xr::sys::SessionCreateInfoOverlayEXTX {
+    xr::sys::SessionCreateInfoOverlayEXTX {
+        ty: xr::sys::SessionCreateInfoOverlayEXTX::TYPE,
+        next: std::ptr::null(),
+        create_flags: xr::sys::OverlaySessionCreateFlagsEXTX::EMPTY,
+        session_layers_placement: 1,
+    }
+}
+
+/// Raw `xrCreateSession`, for chains such as XR_EXTX_overlay that
+/// `Instance::create_session` cannot express.
+///
+/// # Safety
+/// `binding` must point to a valid graphics binding for `G`, and every
+/// structure chained from it must outlive this call.
+pub unsafe fn create_session_raw<G: xr::Graphics>(
+    instance: &xr::Instance,
+    system: xr::SystemId,
+    binding: *const std::ffi::c_void,
+) -> VrResult<(xr::Session<G>, xr::FrameWaiter, xr::FrameStream<G>)> {
+    let info = xr::sys::SessionCreateInfo {
+        ty: xr::sys::SessionCreateInfo::TYPE,
+        next: binding,
+        create_flags: xr::sys::SessionCreateFlags::EMPTY,
+        system_id: system,
+    };
+    let mut handle = xr::sys::Session::NULL;
+    let result = (instance.fp().create_session)(instance.as_raw(), &info, &mut handle);
+    if result.into_raw() < 0 {
+        return Err(VrError::Adapter(format!(
+            "OpenXR create_session: {result:?}"
+        )));
+    }
+    Ok(xr::Session::from_raw(
+        instance.clone(),
+        handle,
+        Box::new(()),
+    ))
+}
+
+/// End the frame with the overlay screen as its only layer.
+pub fn end_frame_with_screen<G: xr::Graphics>(
+    frame_stream: &mut xr::FrameStream<G>,
+    display_time: xr::Time,
+    space: &xr::Space,
+    geometry: ScreenGeometry,
+    sub_image: xr::SwapchainSubImage<'_, G>,
+) -> VrResult<()> {
+    let result = match geometry {
+        ScreenGeometry::Quad { pose, size } => {
+            let layer = xr::CompositionLayerQuad::new()
+                .space(space)
+                .eye_visibility(xr::EyeVisibility::BOTH)
+                .sub_image(sub_image)
+                .pose(pose)
+                .size(size);
+            let layers: [&xr::CompositionLayerBase<G>; 1] = [&layer];
+            frame_stream.end(display_time, xr::EnvironmentBlendMode::OPAQUE, &layers)
+        }
+        ScreenGeometry::Cylinder {
+            pose,
+            radius,
+            central_angle,
+            aspect_ratio,
+        } => {
+            let layer = xr::CompositionLayerCylinderKHR::new()
+                .space(space)
+                .eye_visibility(xr::EyeVisibility::BOTH)
+                .sub_image(sub_image)
+                .pose(pose)
+                .radius(radius)
+                .central_angle(central_angle)
+                .aspect_ratio(aspect_ratio);
+            let layers: [&xr::CompositionLayerBase<G>; 1] = [&layer];
+            frame_stream.end(display_time, xr::EnvironmentBlendMode::OPAQUE, &layers)
+        }
+    };
+    result.map_err(|e| VrError::Adapter(format!("OpenXR end: {e:?}")))
+}
+
+/// Project a view-space gaze orientation onto normalized eye image coordinates.
+pub fn gaze_to_image(orientation: [f32; 4], fov: xr::Fovf) -> Option<[f32; 2]> {
+    let [x, y, z, w] = orientation;
+    // The gaze looks down -Z; rotate that axis by the orientation.
+    let dir_x = -2.0 * (x * z + w * y);
+    let dir_y = -2.0 * (y * z - w * x);
+    let dir_z = -(1.0 - 2.0 * (x * x + y * y));
+    if dir_z >= -f32::EPSILON {
+        return None;
+    }
+    let tan_x = dir_x / -dir_z;
+    let tan_y = dir_y / -dir_z;
+    let (left, right) = (fov.angle_left.tan(), fov.angle_right.tan());
+    let (up, down) = (fov.angle_up.tan(), fov.angle_down.tan());
+    if right - left <= f32::EPSILON || up - down <= f32::EPSILON {
+        return None;
+    }
+    Some([
+        ((tan_x - left) / (right - left)).clamp(0.0, 1.0),
+        ((up - tan_y) / (up - down)).clamp(0.0, 1.0),
+    ])
+}
+
+/// Convert an NV12 decoder output (Y plane, then interleaved UV) to RGBA with
+/// BT.709 limited-range coefficients. Returns `false` if `src` is too short.
+pub fn nv12_to_rgba(
+    src: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    slice_height: u32,
+    out: &mut Vec<u8>,
+) -> bool {
+    let (width, height) = (width as usize, height as usize);
+    if width == 0 || height == 0 {
+        return false;
+    }
+    let stride = (stride as usize).max(width);
+    let uv_offset = stride * (slice_height as usize).max(height);
+    let uv_rows = height.div_ceil(2);
+    if src.len() < uv_offset + stride * (uv_rows - 1) + width.div_ceil(2) * 2 {
+        return false;
+    }
+    out.resize(width * height * 4, 0);
+    for row in 0..height {
+        let y_row = &src[row * stride..];
+        let uv_row = &src[uv_offset + (row / 2) * stride..];
+        let dst = &mut out[row * width * 4..(row + 1) * width * 4];
+        for (col, pixel) in dst.chunks_exact_mut(4).enumerate() {
+            let c = 298 * (y_row[col] as i32 - 16);
+            let d = uv_row[col & !1] as i32 - 128;
+            let e = uv_row[(col & !1) + 1] as i32 - 128;
+            pixel[0] = ((c + 459 * e + 128) >> 8).clamp(0, 255) as u8;
+            pixel[1] = ((c - 55 * d - 136 * e + 128) >> 8).clamp(0, 255) as u8;
+            pixel[2] = ((c + 541 * d + 128) >> 8).clamp(0, 255) as u8;
+            pixel[3] = 255;
+        }
+    }
+    true
+}
+
 pub fn to_pose(pose: xr::Posef) -> Pose {
     Pose {
         position: [pose.position.x, pose.position.y, pose.position.z],
@@ -397,6 +806,22 @@ pub fn to_pose(pose: xr::Posef) -> Pose {
     }
 }
 
+pub fn to_xr_pose(pose: Pose) -> xr::Posef {
+    xr::Posef {
+        orientation: xr::Quaternionf {
+            x: pose.orientation[0],
+            y: pose.orientation[1],
+            z: pose.orientation[2],
+            w: pose.orientation[3],
+        },
+        position: xr::Vector3f {
+            x: pose.position[0],
+            y: pose.position[1],
+            z: pose.position[2],
+        },
+    }
+}
+
 pub struct HandTrackingState {
     pub left: xr::HandTracker,
     pub right: xr::HandTracker,
@@ -482,3 +907,55 @@ fn hand_pose_from_joints(
         angular_velocity,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_geometry_keeps_width_along_the_arc() {
+        let overlay = OverlayConfig {
+            width_m: 3.2,
+            distance_m: 2.0,
+            curvature: 1.0,
+        };
+        match screen_geometry(overlay, 1920, 1080, true) {
+            ScreenGeometry::Cylinder {
+                pose,
+                radius,
+                central_angle,
+                aspect_ratio,
+            } => {
+                assert_eq!(radius, 2.0);
+                assert!((radius * central_angle - 3.2).abs() < 1e-5);
+                assert!((aspect_ratio - 16.0 / 9.0).abs() < 1e-5);
+                assert_eq!(pose.position.z, 0.0);
+            }
+            other => panic!("expected cylinder, got {other:?}"),
+        }
+
+        // Without the cylinder extension the same screen is a flat quad.
+        match screen_geometry(overlay, 1920, 1080, false) {
+            ScreenGeometry::Quad { pose, size } => {
+                assert_eq!(pose.position.z, -2.0);
+                assert_eq!(size.width, 3.2);
+                assert!((size.height - 1.8).abs() < 1e-5);
+            }
+            other => panic!("expected quad, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn nv12_to_rgba_honours_stride_and_levels() {
+        // 2x2 frame with a stride of 4 and a padded slice height of 3.
+        let mut src = vec![0u8; 4 * 3 + 4];
+        src[..2].copy_from_slice(&[16, 235]);
+        src[4..6].copy_from_slice(&[16, 235]);
+        src[12..14].copy_from_slice(&[128, 128]);
+        let mut out = Vec::new();
+        assert!(nv12_to_rgba(&src, 2, 2, 4, 3, &mut out));
+        assert_eq!(&out[..8], &[0, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(out.len(), 16);
+        assert!(!nv12_to_rgba(&src[..10], 2, 2, 4, 3, &mut out));
+    }
+}
@@ -1,8 +1,12 @@
-use std::sync::{atomic::AtomicBool, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
+use std::time::Instant;
 
-use wavry_vr::types::{StreamConfig, VideoFrame};
-use wavry_vr::{VrAdapterCallbacks, VrResult};
+use wavry_vr::types::{
+    HandPose, HapticFeedback, OverlayConfig, Pose, PoseVelocity, StreamConfig, VideoFrame, VrTiming,
+};
+use wavry_vr::{PosePredictor, VrAdapterCallbacks, VrResult};
 
 pub mod common;
 
@@ -15,11 +19,30 @@ pub mod windows;
 #[cfg(target_os = "android")]
 pub mod android;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod vulkan;
+
+/// Haptic events queued for the runtime thread beyond this are dropped.
+const MAX_PENDING_HAPTICS: usize = 32;
+
 pub struct SharedState {
     pub callbacks: Arc<dyn VrAdapterCallbacks>,
     pub latest_frame: Mutex<Option<VideoFrame>>,
     pub stream_config: Mutex<Option<StreamConfig>>,
     pub stop: AtomicBool,
+    pub pending_haptics: Mutex<Vec<HapticFeedback>>,
+    /// Show the stream as a floating screen over another VR app. Whether the
+    /// runtime uses overlay mode is fixed at spawn; the placement is read per frame.
+    pub overlay: Mutex<Option<OverlayConfig>>,
+    /// Time from pose sampling until the matching streamed frame is shown.
+    pub prediction_latency_us: AtomicU64,
+    /// Display refresh rate last reported by the runtime, rounded; 0 until known.
+    pub refresh_hz: AtomicU32,
+    epoch: Instant,
+    /// When `latest_frame` was submitted, in microseconds since `epoch`.
+    frame_submitted_us: AtomicU64,
+    head_predictor: Mutex<PosePredictor>,
+    hand_predictors: Mutex<[PosePredictor; 2]>,
 }
 
 impl SharedState {
@@ -29,11 +52,90 @@ impl SharedState {
             latest_frame: Mutex::new(None),
             stream_config: Mutex::new(None),
             stop: AtomicBool::new(false),
+            pending_haptics: Mutex::new(Vec::new()),
+            overlay: Mutex::new(None),
+            prediction_latency_us: AtomicU64::new(0),
+            refresh_hz: AtomicU32::new(0),
+            epoch: Instant::now(),
+            frame_submitted_us: AtomicU64::new(0),
+            head_predictor: Mutex::new(PosePredictor::default()),
+            hand_predictors: Mutex::new([PosePredictor::default(), PosePredictor::default()]),
+        }
+    }
+
+    /// Extrapolate a head pose sampled at `display_time_us` to when the frame
+    /// rendered from it will reach the display. Returns the pose and that time.
+    pub fn predict_head_pose(&self, pose: Pose, display_time_us: u64) -> (Pose, u64) {
+        let target_us = display_time_us + self.prediction_latency_us.load(Ordering::Relaxed);
+        let Ok(mut predictor) = self.head_predictor.lock() else {
+            return (pose, display_time_us);
+        };
+        predictor.observe(pose, display_time_us);
+        match predictor.predict(target_us) {
+            Some(predicted) => (predicted, target_us),
+            None => (pose, display_time_us),
+        }
+    }
+
+    /// Hand counterpart of [`Self::predict_head_pose`], using runtime velocities.
+    pub fn predict_hand_pose(&self, hand: HandPose, display_time_us: u64) -> (HandPose, u64) {
+        let target_us = display_time_us + self.prediction_latency_us.load(Ordering::Relaxed);
+        let Ok(mut predictors) = self.hand_predictors.lock() else {
+            return (hand, display_time_us);
+        };
+        let Some(predictor) = predictors.get_mut(hand.hand_id as usize) else {
+            return (hand, display_time_us);
+        };
+        let velocity = PoseVelocity {
+            linear: hand.linear_velocity,
+            angular: hand.angular_velocity,
+        };
+        predictor.observe_with_velocity(hand.pose, velocity, display_time_us);
+        match predictor.predict(target_us) {
+            Some(pose) => (HandPose { pose, ..hand }, target_us),
+            None => (hand, display_time_us),
+        }
+    }
+
+    pub fn overlay(&self) -> Option<OverlayConfig> {
+        self.overlay.lock().ok().and_then(|overlay| *overlay)
+    }
+
+    pub fn submit_frame(&self, frame: VideoFrame) {
+        if let Ok(mut slot) = self.latest_frame.lock() {
+            *slot = Some(frame);
+            self.frame_submitted_us
+                .store(self.epoch.elapsed().as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// The newest frame, with how long it waited since [`Self::submit_frame`].
+    pub fn take_latest_frame(&self) -> Option<(VideoFrame, u64)> {
+        let frame = self.latest_frame.lock().ok()?.take()?;
+        let submitted_us = self.frame_submitted_us.load(Ordering::Relaxed);
+        let age_us = (self.epoch.elapsed().as_micros() as u64).saturating_sub(submitted_us);
+        Some((frame, age_us))
+    }
+
+    pub fn report_timing(&self, timing: VrTiming) {
+        self.refresh_hz
+            .store(timing.refresh_hz.round() as u32, Ordering::Relaxed);
+        self.callbacks.on_vr_timing(timing);
+    }
+
+    pub fn queue_haptic(&self, haptic: HapticFeedback) {
+        if let Ok(mut pending) = self.pending_haptics.lock() {
+            if pending.len() < MAX_PENDING_HAPTICS {
+                pending.push(haptic);
+            }
         }
     }
 
-    pub fn take_latest_frame(&self) -> Option<VideoFrame> {
-        self.latest_frame.lock().ok()?.take()
+    pub fn take_pending_haptics(&self) -> Vec<HapticFeedback> {
+        self.pending_haptics
+            .lock()
+            .map(|mut pending| std::mem::take(&mut *pending))
+            .unwrap_or_default()
     }
 }
 
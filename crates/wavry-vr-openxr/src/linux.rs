@@ -6,10 +6,10 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use ash::vk;
 use ash::vk::Handle;
-use ash::{vk, Entry as VkEntry};
 use glow::HasContext;
 use openxr as xr;
 use x11::{glx, xlib};
@@ -18,15 +18,17 @@ use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
 
-use wavry_vr::types::{StreamConfig, VideoCodec, VrTiming};
+use wavry_vr::types::{StreamConfig, VideoCodec};
 use wavry_vr::{VrError, VrResult};
 
-use crate::common::{eye_layout, to_pose, HandTrackingState, InputActions};
+use crate::common::{
+    create_session_raw, end_frame_with_screen, eye_layout, overlay_session_info, screen_geometry,
+    to_pose, FrameTiming, HandTrackingState, InputActions,
+};
+use crate::vulkan::{choose_vk_swapchain_format, VulkanContext};
 use crate::SharedState;
 
 const VIEW_COUNT: usize = 2;
-const VULKAN_FENCE_SKIP_LIMIT: u32 = 2;
-const VULKAN_FENCE_WAIT_TIMEOUT_NS: u64 = 1_000_000;
 
 struct GlxContext {
     display: *mut xlib::Display,
@@ -264,442 +266,6 @@ impl GstDecoder {
     }
 }
 
-struct VulkanContext {
-    instance: ash::Instance,
-    device: ash::Device,
-    physical_device: vk::PhysicalDevice,
-    queue: vk::Queue,
-    queue_family_index: u32,
-    command_pool: vk::CommandPool,
-    command_buffer: vk::CommandBuffer,
-    staging_buffer: vk::Buffer,
-    staging_memory: vk::DeviceMemory,
-    staging_size: vk::DeviceSize,
-    upload_fence: vk::Fence,
-    fence_in_use: bool,
-    fence_skip_count: u32,
-}
-
-impl VulkanContext {
-    fn new(xr_instance: &xr::Instance, system: xr::SystemId) -> VrResult<Self> {
-        let entry = unsafe { VkEntry::load() }
-            .map_err(|e| VrError::Adapter(format!("Vulkan entry load failed: {e}")))?;
-
-        let reqs = xr_instance
-            .graphics_requirements::<xr::Vulkan>(system)
-            .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan requirements: {e:?}")))?;
-        let api_version = vk::make_api_version(
-            0,
-            reqs.min_api_version_supported.major() as u32,
-            reqs.min_api_version_supported.minor() as u32,
-            reqs.min_api_version_supported.patch(),
-        );
-
-        let instance_exts = xr_instance
-            .vulkan_legacy_instance_extensions(system)
-            .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan instance extensions: {e:?}")))?;
-        let instance_exts = parse_extension_list(&instance_exts);
-        let instance_ext_ptrs: Vec<*const i8> = instance_exts.iter().map(|s| s.as_ptr()).collect();
-
-        let app_name = CString::new("Wavry").unwrap();
-        let engine_name = CString::new("Wavry").unwrap();
-        let app_info = vk::ApplicationInfo::builder()
-            .application_name(&app_name)
-            .engine_name(&engine_name)
-            .api_version(api_version);
-
-        let create_info = vk::InstanceCreateInfo::builder()
-            .application_info(&app_info)
-            .enabled_extension_names(&instance_ext_ptrs);
-
-        let instance = unsafe {
-            entry
-                .create_instance(&create_info, None)
-                .map_err(|e| VrError::Adapter(format!("Vulkan instance create failed: {e}")))?
-        };
-
-        let physical_device = unsafe {
-            xr_instance.vulkan_graphics_device(system, instance.handle().as_raw() as *const _)
-        }
-        .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan graphics device: {e:?}")))?;
-        let physical_device = vk::PhysicalDevice::from_raw(physical_device as u64);
-
-        let queue_family_index = find_graphics_queue_family(&instance, physical_device)?;
-
-        let device_exts = xr_instance
-            .vulkan_legacy_device_extensions(system)
-            .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan device extensions: {e:?}")))?;
-        let device_exts = parse_extension_list(&device_exts);
-        let device_ext_ptrs: Vec<*const i8> = device_exts.iter().map(|s| s.as_ptr()).collect();
-
-        let priorities = [1.0f32];
-        let queue_info = vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(queue_family_index)
-            .queue_priorities(&priorities);
-
-        let device_create = vk::DeviceCreateInfo::builder()
-            .queue_create_infos(std::slice::from_ref(&queue_info))
-            .enabled_extension_names(&device_ext_ptrs);
-
-        let device = unsafe {
-            instance
-                .create_device(physical_device, &device_create, None)
-                .map_err(|e| VrError::Adapter(format!("Vulkan device create failed: {e}")))?
-        };
-
-        let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
-
-        let command_pool_info = vk::CommandPoolCreateInfo::builder()
-            .queue_family_index(queue_family_index)
-            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
-        let command_pool = unsafe {
-            device
-                .create_command_pool(&command_pool_info, None)
-                .map_err(|e| VrError::Adapter(format!("Vulkan command pool create failed: {e}")))?
-        };
-
-        let command_buffer_info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(command_pool)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(1);
-        let command_buffer = unsafe {
-            device
-                .allocate_command_buffers(&command_buffer_info)
-                .map_err(|e| VrError::Adapter(format!("Vulkan command buffer alloc failed: {e}")))?
-        }[0];
-
-        let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
-        let upload_fence = unsafe {
-            device
-                .create_fence(&fence_info, None)
-                .map_err(|e| VrError::Adapter(format!("Vulkan fence create failed: {e}")))?
-        };
-
-        Ok(Self {
-            instance,
-            device,
-            physical_device,
-            queue,
-            queue_family_index,
-            command_pool,
-            command_buffer,
-            staging_buffer: vk::Buffer::null(),
-            staging_memory: vk::DeviceMemory::null(),
-            staging_size: 0,
-            upload_fence,
-            fence_in_use: false,
-            fence_skip_count: 0,
-        })
-    }
-
-    fn ensure_staging(&mut self, size: vk::DeviceSize) -> VrResult<()> {
-        if self.staging_size >= size && self.staging_buffer != vk::Buffer::null() {
-            return Ok(());
-        }
-
-        unsafe {
-            if self.staging_buffer != vk::Buffer::null() {
-                self.device.destroy_buffer(self.staging_buffer, None);
-                self.device.free_memory(self.staging_memory, None);
-                self.staging_buffer = vk::Buffer::null();
-                self.staging_memory = vk::DeviceMemory::null();
-                self.staging_size = 0;
-            }
-        }
-
-        let buffer_info = vk::BufferCreateInfo::builder()
-            .size(size)
-            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-        let staging_buffer = unsafe {
-            self.device.create_buffer(&buffer_info, None).map_err(|e| {
-                VrError::Adapter(format!("Vulkan staging buffer create failed: {e}"))
-            })?
-        };
-        let req = unsafe { self.device.get_buffer_memory_requirements(staging_buffer) };
-        let memory_type_index = find_memory_type(
-            &self.instance,
-            self.physical_device,
-            req.memory_type_bits,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        )?
-        .ok_or_else(|| VrError::Adapter("No suitable Vulkan memory type".to_string()))?;
-
-        let alloc_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(req.size)
-            .memory_type_index(memory_type_index);
-        let staging_memory = unsafe {
-            self.device
-                .allocate_memory(&alloc_info, None)
-                .map_err(|e| VrError::Adapter(format!("Vulkan memory alloc failed: {e}")))?
-        };
-        unsafe {
-            self.device
-                .bind_buffer_memory(staging_buffer, staging_memory, 0)
-                .map_err(|e| VrError::Adapter(format!("Vulkan bind memory failed: {e}")))?;
-        }
-
-        self.staging_buffer = staging_buffer;
-        self.staging_memory = staging_memory;
-        self.staging_size = size;
-        Ok(())
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    fn upload_rgba_region(
-        &mut self,
-        image: vk::Image,
-        old_layout: vk::ImageLayout,
-        src_width: u32,
-        src_height: u32,
-        src_offset_x: u32,
-        copy_width: u32,
-        copy_height: u32,
-        data: &[u8],
-    ) -> VrResult<vk::ImageLayout> {
-        let size = (copy_width as vk::DeviceSize) * (copy_height as vk::DeviceSize) * 4;
-        if size == 0 {
-            return Ok(old_layout);
-        }
-
-        if self.fence_in_use {
-            let ready = unsafe { self.device.get_fence_status(self.upload_fence) }
-                .map_err(|e| VrError::Adapter(format!("Vulkan fence status failed: {e}")))?;
-            if !ready {
-                self.fence_skip_count = self.fence_skip_count.saturating_add(1);
-                if self.fence_skip_count < VULKAN_FENCE_SKIP_LIMIT {
-                    return Ok(old_layout);
-                }
-                let wait_result = unsafe {
-                    self.device.wait_for_fences(
-                        &[self.upload_fence],
-                        true,
-                        VULKAN_FENCE_WAIT_TIMEOUT_NS,
-                    )
-                };
-                match wait_result {
-                    Ok(()) => {
-                        unsafe {
-                            self.device
-                                .reset_fences(&[self.upload_fence])
-                                .map_err(|e| {
-                                    VrError::Adapter(format!("Vulkan fence reset failed: {e}"))
-                                })?;
-                        }
-                        self.fence_in_use = false;
-                        self.fence_skip_count = 0;
-                    }
-                    Err(vk::Result::TIMEOUT) => {
-                        return Ok(old_layout);
-                    }
-                    Err(err) => {
-                        return Err(VrError::Adapter(format!("Vulkan fence wait failed: {err}")));
-                    }
-                }
-            }
-            unsafe {
-                self.device
-                    .reset_fences(&[self.upload_fence])
-                    .map_err(|e| VrError::Adapter(format!("Vulkan fence reset failed: {e}")))?;
-            }
-            self.fence_in_use = false;
-            self.fence_skip_count = 0;
-        } else {
-            unsafe {
-                self.device
-                    .reset_fences(&[self.upload_fence])
-                    .map_err(|e| VrError::Adapter(format!("Vulkan fence reset failed: {e}")))?;
-            }
-            self.fence_skip_count = 0;
-        }
-
-        self.ensure_staging(size)?;
-
-        unsafe {
-            let ptr = self
-                .device
-                .map_memory(self.staging_memory, 0, size, vk::MemoryMapFlags::empty())
-                .map_err(|e| VrError::Adapter(format!("Vulkan map memory failed: {e}")))?;
-            let dst = ptr.cast::<u8>();
-            if src_width == copy_width && src_height == copy_height && src_offset_x == 0 {
-                std::ptr::copy_nonoverlapping(data.as_ptr(), dst, size as usize);
-            } else {
-                let src_stride = (src_width * 4) as usize;
-                let dst_stride = (copy_width * 4) as usize;
-                for row in 0..copy_height as usize {
-                    let src_index = row * src_stride + (src_offset_x as usize * 4);
-                    let dst_index = row * dst_stride;
-                    let src_ptr = data.as_ptr().add(src_index);
-                    let dst_ptr = dst.add(dst_index);
-                    std::ptr::copy_nonoverlapping(src_ptr, dst_ptr, dst_stride);
-                }
-            }
-            self.device.unmap_memory(self.staging_memory);
-
-            self.device
-                .reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())
-                .map_err(|e| {
-                    VrError::Adapter(format!("Vulkan reset command buffer failed: {e}"))
-                })?;
-
-            let begin_info = vk::CommandBufferBeginInfo::builder()
-                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-            self.device
-                .begin_command_buffer(self.command_buffer, &begin_info)
-                .map_err(|e| {
-                    VrError::Adapter(format!("Vulkan begin command buffer failed: {e}"))
-                })?;
-
-            let subresource_range = vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
-                base_mip_level: 0,
-                level_count: 1,
-                base_array_layer: 0,
-                layer_count: 1,
-            };
-
-            let to_transfer = vk::ImageMemoryBarrier::builder()
-                .old_layout(old_layout)
-                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                .src_access_mask(vk::AccessFlags::empty())
-                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                .image(image)
-                .subresource_range(subresource_range);
-
-            self.device.cmd_pipeline_barrier(
-                self.command_buffer,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::TRANSFER,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                std::slice::from_ref(&to_transfer),
-            );
-
-            let region = vk::BufferImageCopy::builder()
-                .image_subresource(vk::ImageSubresourceLayers {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: 0,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                })
-                .image_extent(vk::Extent3D {
-                    width: copy_width,
-                    height: copy_height,
-                    depth: 1,
-                });
-
-            self.device.cmd_copy_buffer_to_image(
-                self.command_buffer,
-                self.staging_buffer,
-                image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                std::slice::from_ref(&region),
-            );
-
-            let to_color = vk::ImageMemoryBarrier::builder()
-                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                .dst_access_mask(
-                    vk::AccessFlags::COLOR_ATTACHMENT_READ
-                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                )
-                .image(image)
-                .subresource_range(subresource_range);
-
-            self.device.cmd_pipeline_barrier(
-                self.command_buffer,
-                vk::PipelineStageFlags::TRANSFER,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                std::slice::from_ref(&to_color),
-            );
-
-            self.device
-                .end_command_buffer(self.command_buffer)
-                .map_err(|e| VrError::Adapter(format!("Vulkan end command buffer failed: {e}")))?;
-
-            let submit_info = vk::SubmitInfo::builder()
-                .command_buffers(std::slice::from_ref(&self.command_buffer));
-            self.device
-                .queue_submit(
-                    self.queue,
-                    std::slice::from_ref(&submit_info),
-                    self.upload_fence,
-                )
-                .map_err(|e| VrError::Adapter(format!("Vulkan queue submit failed: {e}")))?;
-            self.fence_in_use = true;
-            self.fence_skip_count = 0;
-        }
-
-        Ok(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-    }
-}
-
-impl Drop for VulkanContext {
-    fn drop(&mut self) {
-        unsafe {
-            let _ = self.device.device_wait_idle();
-            if self.staging_buffer != vk::Buffer::null() {
-                self.device.destroy_buffer(self.staging_buffer, None);
-            }
-            if self.staging_memory != vk::DeviceMemory::null() {
-                self.device.free_memory(self.staging_memory, None);
-            }
-            if self.command_pool != vk::CommandPool::null() {
-                self.device.destroy_command_pool(self.command_pool, None);
-            }
-            if self.upload_fence != vk::Fence::null() {
-                self.device.destroy_fence(self.upload_fence, None);
-            }
-            self.device.destroy_device(None);
-            self.instance.destroy_instance(None);
-        }
-    }
-}
-
-fn parse_extension_list(list: &str) -> Vec<CString> {
-    list.split_whitespace()
-        .filter(|s| !s.is_empty())
-        .map(|s| CString::new(s).unwrap())
-        .collect()
-}
-
-fn find_graphics_queue_family(
-    instance: &ash::Instance,
-    physical_device: vk::PhysicalDevice,
-) -> VrResult<u32> {
-    let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-    families
-        .iter()
-        .enumerate()
-        .find(|(_, family)| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
-        .map(|(idx, _)| idx as u32)
-        .ok_or_else(|| VrError::Adapter("No Vulkan graphics queue family".to_string()))
-}
-
-fn find_memory_type(
-    instance: &ash::Instance,
-    physical_device: vk::PhysicalDevice,
-    type_bits: u32,
-    properties: vk::MemoryPropertyFlags,
-) -> VrResult<Option<u32>> {
-    let mem = unsafe { instance.get_physical_device_memory_properties(physical_device) };
-    for i in 0..mem.memory_type_count {
-        let is_type = (type_bits & (1 << i)) != 0;
-        let has_props = mem.memory_types[i as usize]
-            .property_flags
-            .contains(properties);
-        if is_type && has_props {
-            return Ok(Some(i));
-        }
-    }
-    Ok(None)
-}
-
 pub fn spawn(state: Arc<SharedState>) -> VrResult<JoinHandle<()>> {
     thread::Builder::new()
         .name("wavry-pcvr-linux".to_string())
@@ -737,43 +303,6 @@ fn choose_gl_swapchain_format(formats: &[u32]) -> (u32, &'static str, bool) {
     (fallback, name, srgb)
 }
 
-fn describe_vk_swapchain_format(format: u32) -> (&'static str, bool) {
-    if format == vk::Format::R8G8B8A8_UNORM.as_raw() as u32 {
-        ("VK_FORMAT_R8G8B8A8_UNORM", false)
-    } else if format == vk::Format::B8G8R8A8_UNORM.as_raw() as u32 {
-        ("VK_FORMAT_B8G8R8A8_UNORM", false)
-    } else if format == vk::Format::R8G8B8A8_SRGB.as_raw() as u32 {
-        ("VK_FORMAT_R8G8B8A8_SRGB", true)
-    } else if format == vk::Format::B8G8R8A8_SRGB.as_raw() as u32 {
-        ("VK_FORMAT_B8G8R8A8_SRGB", true)
-    } else {
-        ("UNKNOWN_VK_FORMAT", false)
-    }
-}
-
-fn choose_vk_swapchain_format(formats: &[u32]) -> (u32, &'static str, bool) {
-    let preferred = [
-        vk::Format::R8G8B8A8_UNORM.as_raw() as u32,
-        vk::Format::B8G8R8A8_UNORM.as_raw() as u32,
-        vk::Format::R8G8B8A8_SRGB.as_raw() as u32,
-        vk::Format::B8G8R8A8_SRGB.as_raw() as u32,
-    ];
-    if let Some(format) = preferred
-        .iter()
-        .copied()
-        .find(|&fmt| formats.contains(&fmt))
-    {
-        let (name, srgb) = describe_vk_swapchain_format(format);
-        return (format, name, srgb);
-    }
-    let fallback = formats
-        .first()
-        .copied()
-        .unwrap_or(vk::Format::R8G8B8A8_UNORM.as_raw() as u32);
-    let (name, srgb) = describe_vk_swapchain_format(fallback);
-    (fallback, name, srgb)
-}
-
 fn log_swapchain_validation_u32(
     instance: &xr::Instance,
     backend: &str,
@@ -799,7 +328,8 @@ fn log_swapchain_validation_u32(
 }
 
 fn run(state: Arc<SharedState>) -> VrResult<()> {
-    let use_vulkan = env::var("WAVRY_USE_VULKAN").is_ok();
+    // Overlay sessions are only wired up for the Vulkan path.
+    let use_vulkan = env::var("WAVRY_USE_VULKAN").is_ok() || state.overlay().is_some();
     if use_vulkan {
         run_vulkan(state)
     } else {
@@ -834,6 +364,9 @@ fn run_glx(state: Arc<SharedState>) -> VrResult<()> {
     if available_exts.ext_hand_tracking {
         exts.ext_hand_tracking = true;
     }
+    if available_exts.ext_eye_gaze_interaction {
+        exts.ext_eye_gaze_interaction = true;
+    }
 
     let app_info = xr::ApplicationInfo {
         application_name: "Wavry",
@@ -863,7 +396,8 @@ fn run_glx(state: Arc<SharedState>) -> VrResult<()> {
             .map_err(|e| VrError::Adapter(format!("OpenXR create_session: {e:?}")))?
     };
     wavry_vr::set_pcvr_status("PCVR: Linux X11 OpenGL runtime active".to_string());
-    let mut input_actions = InputActions::new(&instance, &session).ok();
+    let mut input_actions =
+        InputActions::new(&instance, &session, available_exts.ext_eye_gaze_interaction).ok();
     let hand_tracking = if available_exts.ext_hand_tracking {
         HandTrackingState::new(&session).ok()
     } else {
@@ -895,7 +429,7 @@ fn run_glx(state: Arc<SharedState>) -> VrResult<()> {
     let mut swapchains: Option<[xr::Swapchain<xr::OpenGL>; VIEW_COUNT]> = None;
     let mut swapchain_images: Option<[Vec<u32>; VIEW_COUNT]> = None;
     let mut last_decoded: Option<DecodedFrame> = None;
-    let mut last_refresh_hz: Option<f32> = None;
+    let mut frame_timing = FrameTiming::default();
 
     loop {
         while let Some(event) = instance
@@ -956,33 +490,39 @@ fn run_glx(state: Arc<SharedState>) -> VrResult<()> {
         if !views.is_empty() {
             let pose = to_pose(views[0].pose);
             let timestamp_us = (frame_state.predicted_display_time.as_nanos() / 1_000) as u64;
-            state.callbacks.on_pose_update(pose, timestamp_us);
+            let (predicted, predicted_us) = state.predict_head_pose(pose, timestamp_us);
+            state.callbacks.on_pose_update(predicted, predicted_us);
             if let Some(actions) = input_actions.as_mut() {
                 if let Ok(inputs) = actions.poll(&session, timestamp_us) {
                     for input in inputs {
                         state.callbacks.on_gamepad_input(input);
                     }
                 }
+                if let Some(hint) = actions.poll_foveation(
+                    frame_state.predicted_display_time,
+                    views[0].fov,
+                    timestamp_us,
+                ) {
+                    state.callbacks.on_foveation_update(hint);
+                }
+                for haptic in state.take_pending_haptics() {
+                    if let Err(err) = actions.apply_haptic(&session, haptic) {
+                        eprintln!("OpenXR haptic feedback failed: {:?}", err);
+                    }
+                }
             }
             if let Some(tracking) = hand_tracking.as_ref() {
                 for hand_pose in tracking.poll(&reference_space, frame_state.predicted_display_time)
                 {
-                    state.callbacks.on_hand_pose_update(hand_pose, timestamp_us);
+                    let (predicted, predicted_us) =
+                        state.predict_hand_pose(hand_pose, timestamp_us);
+                    state.callbacks.on_hand_pose_update(predicted, predicted_us);
                 }
             }
         }
 
-        let period_ns = frame_state.predicted_display_period.as_nanos();
-        if period_ns > 0 {
-            let refresh_hz = 1_000_000_000.0 / period_ns as f32;
-            let send = last_refresh_hz.is_none_or(|prev| (prev - refresh_hz).abs() > 0.1);
-            if send {
-                state.callbacks.on_vr_timing(VrTiming {
-                    refresh_hz,
-                    vsync_offset_us: 0,
-                });
-                last_refresh_hz = Some(refresh_hz);
-            }
+        if let Some(timing) = frame_timing.timing(&frame_state, Instant::now()) {
+            state.report_timing(timing);
         }
 
         if decoder.is_none() {
@@ -991,10 +531,12 @@ fn run_glx(state: Arc<SharedState>) -> VrResult<()> {
             }
         }
 
-        if let Some(frame) = state.take_latest_frame() {
+        if let Some((frame, age_us)) = state.take_latest_frame() {
+            frame_timing.observe_frame_age(age_us);
             if let Some(decoder) = decoder.as_ref() {
                 if let Some(decoded) = decoder.decode(&frame.data, frame.timestamp_us)? {
                     last_decoded = Some(decoded);
+                    frame_timing.new_image(frame.view);
                 }
             }
         }
@@ -1156,7 +698,7 @@ fn run_glx(state: Arc<SharedState>) -> VrResult<()> {
                             })
                         };
                         layer_views[i] = xr::CompositionLayerProjectionView::new()
-                            .pose(views[i].pose)
+                            .pose(frame_timing.layer_pose(&views, i))
                             .fov(views[i].fov)
                             .sub_image(sub_image);
                     }
@@ -1206,6 +748,17 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
     if available_exts.ext_hand_tracking {
         exts.ext_hand_tracking = true;
     }
+    if available_exts.ext_eye_gaze_interaction {
+        exts.ext_eye_gaze_interaction = true;
+    }
+    let overlay = state.overlay();
+    if overlay.is_some() {
+        exts.extx_overlay = available_exts.extx_overlay;
+        exts.khr_composition_layer_cylinder = available_exts.khr_composition_layer_cylinder;
+        if !exts.extx_overlay {
+            eprintln!("OpenXR XR_EXTX_overlay unavailable; showing the screen in its own session");
+        }
+    }
 
     let app_info = xr::ApplicationInfo {
         application_name: "Wavry",
@@ -1231,13 +784,32 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
         queue_index: 0,
     };
 
-    let (session, mut frame_waiter, mut frame_stream) = unsafe {
-        instance
-            .create_session::<xr::Vulkan>(system, &create_info)
-            .map_err(|e| VrError::Adapter(format!("OpenXR create_session: {e:?}")))?
+    let (session, mut frame_waiter, mut frame_stream) = if exts.extx_overlay {
+        let overlay_info = overlay_session_info();
+        let binding = xr::sys::GraphicsBindingVulkanKHR {
+            ty: xr::sys::GraphicsBindingVulkanKHR::TYPE,
+            next: &overlay_info as *const _ as *const _,
+            instance: create_info.instance,
+            physical_device: create_info.physical_device,
+            device: create_info.device,
+            queue_family_index: create_info.queue_family_index,
+            queue_index: create_info.queue_index,
+        };
+        unsafe { create_session_raw::<xr::Vulkan>(&instance, system, &binding as *const _ as _)? }
+    } else {
+        unsafe {
+            instance
+                .create_session::<xr::Vulkan>(system, &create_info)
+                .map_err(|e| VrError::Adapter(format!("OpenXR create_session: {e:?}")))?
+        }
     };
-    wavry_vr::set_pcvr_status("PCVR: Linux Wayland Vulkan runtime active".to_string());
-    let mut input_actions = InputActions::new(&instance, &session).ok();
+    if overlay.is_some() {
+        wavry_vr::set_pcvr_status("PCVR: Linux Vulkan overlay screen active".to_string());
+    } else {
+        wavry_vr::set_pcvr_status("PCVR: Linux Wayland Vulkan runtime active".to_string());
+    }
+    let mut input_actions =
+        InputActions::new(&instance, &session, available_exts.ext_eye_gaze_interaction).ok();
     let hand_tracking = if available_exts.ext_hand_tracking {
         HandTrackingState::new(&session).ok()
     } else {
@@ -1270,7 +842,7 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
     let mut swapchain_images: Option<[Vec<vk::Image>; VIEW_COUNT]> = None;
     let mut image_layouts: Option<[Vec<vk::ImageLayout>; VIEW_COUNT]> = None;
     let mut last_decoded: Option<DecodedFrame> = None;
-    let mut last_refresh_hz: Option<f32> = None;
+    let mut frame_timing = FrameTiming::default();
 
     loop {
         while let Some(event) = instance
@@ -1331,33 +903,39 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
         if !views.is_empty() {
             let pose = to_pose(views[0].pose);
             let timestamp_us = (frame_state.predicted_display_time.as_nanos() / 1_000) as u64;
-            state.callbacks.on_pose_update(pose, timestamp_us);
+            let (predicted, predicted_us) = state.predict_head_pose(pose, timestamp_us);
+            state.callbacks.on_pose_update(predicted, predicted_us);
             if let Some(actions) = input_actions.as_mut() {
                 if let Ok(inputs) = actions.poll(&session, timestamp_us) {
                     for input in inputs {
                         state.callbacks.on_gamepad_input(input);
                     }
                 }
+                if let Some(hint) = actions.poll_foveation(
+                    frame_state.predicted_display_time,
+                    views[0].fov,
+                    timestamp_us,
+                ) {
+                    state.callbacks.on_foveation_update(hint);
+                }
+                for haptic in state.take_pending_haptics() {
+                    if let Err(err) = actions.apply_haptic(&session, haptic) {
+                        eprintln!("OpenXR haptic feedback failed: {:?}", err);
+                    }
+                }
             }
             if let Some(tracking) = hand_tracking.as_ref() {
                 for hand_pose in tracking.poll(&reference_space, frame_state.predicted_display_time)
                 {
-                    state.callbacks.on_hand_pose_update(hand_pose, timestamp_us);
+                    let (predicted, predicted_us) =
+                        state.predict_hand_pose(hand_pose, timestamp_us);
+                    state.callbacks.on_hand_pose_update(predicted, predicted_us);
                 }
             }
         }
 
-        let period_ns = frame_state.predicted_display_period.as_nanos();
-        if period_ns > 0 {
-            let refresh_hz = 1_000_000_000.0 / period_ns as f32;
-            let send = last_refresh_hz.is_none_or(|prev| (prev - refresh_hz).abs() > 0.1);
-            if send {
-                state.callbacks.on_vr_timing(VrTiming {
-                    refresh_hz,
-                    vsync_offset_us: 0,
-                });
-                last_refresh_hz = Some(refresh_hz);
-            }
+        if let Some(timing) = frame_timing.timing(&frame_state, Instant::now()) {
+            state.report_timing(timing);
         }
 
         if decoder.is_none() {
@@ -1366,10 +944,12 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
             }
         }
 
-        if let Some(frame) = state.take_latest_frame() {
+        if let Some((frame, age_us)) = state.take_latest_frame() {
+            frame_timing.observe_frame_age(age_us);
             if let Some(decoder) = decoder.as_ref() {
                 if let Some(decoded) = decoder.decode(&frame.data, frame.timestamp_us)? {
                     last_decoded = Some(decoded);
+                    frame_timing.new_image(frame.view);
                 }
             }
         }
@@ -1446,8 +1026,10 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
                     xr::CompositionLayerProjectionView::new(),
                     xr::CompositionLayerProjectionView::new(),
                 ];
+                // The overlay screen shows one image to both eyes.
+                let view_count = if overlay.is_some() { 1 } else { VIEW_COUNT };
 
-                for i in 0..VIEW_COUNT {
+                for i in 0..view_count {
                     let image_index = swapchains[i]
                         .acquire_image()
                         .map_err(|e| VrError::Adapter(format!("OpenXR acquire: {e:?}")))?;
@@ -1503,24 +1085,50 @@ fn run_vulkan(state: Arc<SharedState>) -> VrResult<()> {
                             })
                         };
                         layer_views[i] = xr::CompositionLayerProjectionView::new()
-                            .pose(views[i].pose)
+                            .pose(frame_timing.layer_pose(&views, i))
                             .fov(views[i].fov)
                             .sub_image(sub_image);
                     }
                 }
 
-                let layer = xr::CompositionLayerProjection::new()
-                    .space(&reference_space)
-                    .views(&layer_views);
-                let layers: [&xr::CompositionLayerBase<xr::Vulkan>; 1] = [&layer];
-
-                frame_stream
-                    .end(
+                if let Some(initial) = overlay {
+                    let geometry = screen_geometry(
+                        state.overlay().unwrap_or(initial),
+                        width.max(0) as u32,
+                        height.max(0) as u32,
+                        exts.khr_composition_layer_cylinder,
+                    );
+                    let sub_image = unsafe {
+                        xr::SwapchainSubImage::from_raw(xr::sys::SwapchainSubImage {
+                            swapchain: swapchains[0].as_raw(),
+                            image_rect: xr::Rect2Di {
+                                offset: xr::Offset2Di { x: 0, y: 0 },
+                                extent: xr::Extent2Di { width, height },
+                            },
+                            image_array_index: 0,
+                        })
+                    };
+                    end_frame_with_screen(
+                        &mut frame_stream,
                         frame_state.predicted_display_time,
-                        xr::EnvironmentBlendMode::OPAQUE,
-                        &layers,
-                    )
-                    .map_err(|e| VrError::Adapter(format!("OpenXR end: {e:?}")))?;
+                        &reference_space,
+                        geometry,
+                        sub_image,
+                    )?;
+                } else {
+                    let layer = xr::CompositionLayerProjection::new()
+                        .space(&reference_space)
+                        .views(&layer_views);
+                    let layers: [&xr::CompositionLayerBase<xr::Vulkan>; 1] = [&layer];
+
+                    frame_stream
+                        .end(
+                            frame_state.predicted_display_time,
+                            xr::EnvironmentBlendMode::OPAQUE,
+                            &layers,
+                        )
+                        .map_err(|e| VrError::Adapter(format!("OpenXR end: {e:?}")))?;
+                }
             }
         } else {
             frame_stream
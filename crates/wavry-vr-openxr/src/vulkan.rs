@@ -0,0 +1,488 @@
+//! Vulkan device shared by the Linux and Android OpenXR runtimes, with a
+//! staging-buffer path for uploading decoded RGBA frames into swapchains.
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+use ash::vk::{self, Handle};
+use ash::Entry as VkEntry;
+use openxr as xr;
+
+use wavry_vr::{VrError, VrResult};
+
+const VULKAN_FENCE_SKIP_LIMIT: u32 = 2;
+const VULKAN_FENCE_WAIT_TIMEOUT_NS: u64 = 1_000_000;
+
+pub(crate) struct VulkanContext {
+    pub(crate) instance: ash::Instance,
+    pub(crate) device: ash::Device,
+    pub(crate) physical_device: vk::PhysicalDevice,
+    queue: vk::Queue,
+    pub(crate) queue_family_index: u32,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    staging_buffer: vk::Buffer,
+    staging_memory: vk::DeviceMemory,
+    staging_size: vk::DeviceSize,
+    upload_fence: vk::Fence,
+    fence_in_use: bool,
+    fence_skip_count: u32,
+}
+
+impl VulkanContext {
+    pub(crate) fn new(xr_instance: &xr::Instance, system: xr::SystemId) -> VrResult<Self> {
+        let entry = unsafe { VkEntry::load() }
+            .map_err(|e| VrError::Adapter(format!("Vulkan entry load failed: {e}")))?;
+
+        let reqs = xr_instance
+            .graphics_requirements::<xr::Vulkan>(system)
+            .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan requirements: {e:?}")))?;
+        let api_version = vk::make_api_version(
+            0,
+            reqs.min_api_version_supported.major() as u32,
+            reqs.min_api_version_supported.minor() as u32,
+            reqs.min_api_version_supported.patch(),
+        );
+
+        let instance_exts = xr_instance
+            .vulkan_legacy_instance_extensions(system)
+            .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan instance extensions: {e:?}")))?;
+        let instance_exts = parse_extension_list(&instance_exts);
+        let instance_ext_ptrs: Vec<*const c_char> =
+            instance_exts.iter().map(|s| s.as_ptr()).collect();
+
+        let app_name = CString::new("Wavry").unwrap();
+        let engine_name = CString::new("Wavry").unwrap();
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&app_name)
+            .engine_name(&engine_name)
+            .api_version(api_version);
+
+        let create_info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_extension_names(&instance_ext_ptrs);
+
+        let instance = unsafe {
+            entry
+                .create_instance(&create_info, None)
+                .map_err(|e| VrError::Adapter(format!("Vulkan instance create failed: {e}")))?
+        };
+
+        let physical_device = unsafe {
+            xr_instance.vulkan_graphics_device(system, instance.handle().as_raw() as *const _)
+        }
+        .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan graphics device: {e:?}")))?;
+        let physical_device = vk::PhysicalDevice::from_raw(physical_device as u64);
+
+        let queue_family_index = find_graphics_queue_family(&instance, physical_device)?;
+
+        let device_exts = xr_instance
+            .vulkan_legacy_device_extensions(system)
+            .map_err(|e| VrError::Adapter(format!("OpenXR Vulkan device extensions: {e:?}")))?;
+        let device_exts = parse_extension_list(&device_exts);
+        let device_ext_ptrs: Vec<*const c_char> = device_exts.iter().map(|s| s.as_ptr()).collect();
+
+        let priorities = [1.0f32];
+        let queue_info = vk::DeviceQueueCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .queue_priorities(&priorities);
+
+        let device_create = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(std::slice::from_ref(&queue_info))
+            .enabled_extension_names(&device_ext_ptrs);
+
+        let device = unsafe {
+            instance
+                .create_device(physical_device, &device_create, None)
+                .map_err(|e| VrError::Adapter(format!("Vulkan device create failed: {e}")))?
+        };
+
+        let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+
+        let command_pool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+        let command_pool = unsafe {
+            device
+                .create_command_pool(&command_pool_info, None)
+                .map_err(|e| VrError::Adapter(format!("Vulkan command pool create failed: {e}")))?
+        };
+
+        let command_buffer_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer = unsafe {
+            device
+                .allocate_command_buffers(&command_buffer_info)
+                .map_err(|e| VrError::Adapter(format!("Vulkan command buffer alloc failed: {e}")))?
+        }[0];
+
+        let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+        let upload_fence = unsafe {
+            device
+                .create_fence(&fence_info, None)
+                .map_err(|e| VrError::Adapter(format!("Vulkan fence create failed: {e}")))?
+        };
+
+        Ok(Self {
+            instance,
+            device,
+            physical_device,
+            queue,
+            queue_family_index,
+            command_pool,
+            command_buffer,
+            staging_buffer: vk::Buffer::null(),
+            staging_memory: vk::DeviceMemory::null(),
+            staging_size: 0,
+            upload_fence,
+            fence_in_use: false,
+            fence_skip_count: 0,
+        })
+    }
+
+    fn ensure_staging(&mut self, size: vk::DeviceSize) -> VrResult<()> {
+        if self.staging_size >= size && self.staging_buffer != vk::Buffer::null() {
+            return Ok(());
+        }
+
+        unsafe {
+            if self.staging_buffer != vk::Buffer::null() {
+                self.device.destroy_buffer(self.staging_buffer, None);
+                self.device.free_memory(self.staging_memory, None);
+                self.staging_buffer = vk::Buffer::null();
+                self.staging_memory = vk::DeviceMemory::null();
+                self.staging_size = 0;
+            }
+        }
+
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let staging_buffer = unsafe {
+            self.device.create_buffer(&buffer_info, None).map_err(|e| {
+                VrError::Adapter(format!("Vulkan staging buffer create failed: {e}"))
+            })?
+        };
+        let req = unsafe { self.device.get_buffer_memory_requirements(staging_buffer) };
+        let memory_type_index = find_memory_type(
+            &self.instance,
+            self.physical_device,
+            req.memory_type_bits,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?
+        .ok_or_else(|| VrError::Adapter("No suitable Vulkan memory type".to_string()))?;
+
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(req.size)
+            .memory_type_index(memory_type_index);
+        let staging_memory = unsafe {
+            self.device
+                .allocate_memory(&alloc_info, None)
+                .map_err(|e| VrError::Adapter(format!("Vulkan memory alloc failed: {e}")))?
+        };
+        unsafe {
+            self.device
+                .bind_buffer_memory(staging_buffer, staging_memory, 0)
+                .map_err(|e| VrError::Adapter(format!("Vulkan bind memory failed: {e}")))?;
+        }
+
+        self.staging_buffer = staging_buffer;
+        self.staging_memory = staging_memory;
+        self.staging_size = size;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn upload_rgba_region(
+        &mut self,
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        src_width: u32,
+        src_height: u32,
+        src_offset_x: u32,
+        copy_width: u32,
+        copy_height: u32,
+        data: &[u8],
+    ) -> VrResult<vk::ImageLayout> {
+        let size = (copy_width as vk::DeviceSize) * (copy_height as vk::DeviceSize) * 4;
+        if size == 0 {
+            return Ok(old_layout);
+        }
+
+        if self.fence_in_use {
+            let ready = unsafe { self.device.get_fence_status(self.upload_fence) }
+                .map_err(|e| VrError::Adapter(format!("Vulkan fence status failed: {e}")))?;
+            if !ready {
+                self.fence_skip_count = self.fence_skip_count.saturating_add(1);
+                if self.fence_skip_count < VULKAN_FENCE_SKIP_LIMIT {
+                    return Ok(old_layout);
+                }
+                let wait_result = unsafe {
+                    self.device.wait_for_fences(
+                        &[self.upload_fence],
+                        true,
+                        VULKAN_FENCE_WAIT_TIMEOUT_NS,
+                    )
+                };
+                match wait_result {
+                    Ok(()) => {
+                        unsafe {
+                            self.device
+                                .reset_fences(&[self.upload_fence])
+                                .map_err(|e| {
+                                    VrError::Adapter(format!("Vulkan fence reset failed: {e}"))
+                                })?;
+                        }
+                        self.fence_in_use = false;
+                        self.fence_skip_count = 0;
+                    }
+                    Err(vk::Result::TIMEOUT) => {
+                        return Ok(old_layout);
+                    }
+                    Err(err) => {
+                        return Err(VrError::Adapter(format!("Vulkan fence wait failed: {err}")));
+                    }
+                }
+            }
+            unsafe {
+                self.device
+                    .reset_fences(&[self.upload_fence])
+                    .map_err(|e| VrError::Adapter(format!("Vulkan fence reset failed: {e}")))?;
+            }
+            self.fence_in_use = false;
+            self.fence_skip_count = 0;
+        } else {
+            unsafe {
+                self.device
+                    .reset_fences(&[self.upload_fence])
+                    .map_err(|e| VrError::Adapter(format!("Vulkan fence reset failed: {e}")))?;
+            }
+            self.fence_skip_count = 0;
+        }
+
+        self.ensure_staging(size)?;
+
+        unsafe {
+            let ptr = self
+                .device
+                .map_memory(self.staging_memory, 0, size, vk::MemoryMapFlags::empty())
+                .map_err(|e| VrError::Adapter(format!("Vulkan map memory failed: {e}")))?;
+            let dst = ptr.cast::<u8>();
+            if src_width == copy_width && src_height == copy_height && src_offset_x == 0 {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), dst, size as usize);
+            } else {
+                let src_stride = (src_width * 4) as usize;
+                let dst_stride = (copy_width * 4) as usize;
+                for row in 0..copy_height as usize {
+                    let src_index = row * src_stride + (src_offset_x as usize * 4);
+                    let dst_index = row * dst_stride;
+                    let src_ptr = data.as_ptr().add(src_index);
+                    let dst_ptr = dst.add(dst_index);
+                    std::ptr::copy_nonoverlapping(src_ptr, dst_ptr, dst_stride);
+                }
+            }
+            self.device.unmap_memory(self.staging_memory);
+
+            self.device
+                .reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(|e| {
+                    VrError::Adapter(format!("Vulkan reset command buffer failed: {e}"))
+                })?;
+
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            self.device
+                .begin_command_buffer(self.command_buffer, &begin_info)
+                .map_err(|e| {
+                    VrError::Adapter(format!("Vulkan begin command buffer failed: {e}"))
+                })?;
+
+            let subresource_range = vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            };
+
+            let to_transfer = vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .image(image)
+                .subresource_range(subresource_range);
+
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                std::slice::from_ref(&to_transfer),
+            );
+
+            let region = vk::BufferImageCopy::builder()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .image_extent(vk::Extent3D {
+                    width: copy_width,
+                    height: copy_height,
+                    depth: 1,
+                });
+
+            self.device.cmd_copy_buffer_to_image(
+                self.command_buffer,
+                self.staging_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                std::slice::from_ref(&region),
+            );
+
+            let to_color = vk::ImageMemoryBarrier::builder()
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                )
+                .image(image)
+                .subresource_range(subresource_range);
+
+            self.device.cmd_pipeline_barrier(
+                self.command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                std::slice::from_ref(&to_color),
+            );
+
+            self.device
+                .end_command_buffer(self.command_buffer)
+                .map_err(|e| VrError::Adapter(format!("Vulkan end command buffer failed: {e}")))?;
+
+            let submit_info = vk::SubmitInfo::builder()
+                .command_buffers(std::slice::from_ref(&self.command_buffer));
+            self.device
+                .queue_submit(
+                    self.queue,
+                    std::slice::from_ref(&submit_info),
+                    self.upload_fence,
+                )
+                .map_err(|e| VrError::Adapter(format!("Vulkan queue submit failed: {e}")))?;
+            self.fence_in_use = true;
+            self.fence_skip_count = 0;
+        }
+
+        Ok(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+    }
+}
+
+impl Drop for VulkanContext {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            if self.staging_buffer != vk::Buffer::null() {
+                self.device.destroy_buffer(self.staging_buffer, None);
+            }
+            if self.staging_memory != vk::DeviceMemory::null() {
+                self.device.free_memory(self.staging_memory, None);
+            }
+            if self.command_pool != vk::CommandPool::null() {
+                self.device.destroy_command_pool(self.command_pool, None);
+            }
+            if self.upload_fence != vk::Fence::null() {
+                self.device.destroy_fence(self.upload_fence, None);
+            }
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+fn parse_extension_list(list: &str) -> Vec<CString> {
+    list.split_whitespace()
+        .filter(|s| !s.is_empty())
+        .map(|s| CString::new(s).unwrap())
+        .collect()
+}
+
+fn find_graphics_queue_family(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> VrResult<u32> {
+    let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    families
+        .iter()
+        .enumerate()
+        .find(|(_, family)| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+        .map(|(idx, _)| idx as u32)
+        .ok_or_else(|| VrError::Adapter("No Vulkan graphics queue family".to_string()))
+}
+
+fn find_memory_type(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    type_bits: u32,
+    properties: vk::MemoryPropertyFlags,
+) -> VrResult<Option<u32>> {
+    let mem = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    for i in 0..mem.memory_type_count {
+        let is_type = (type_bits & (1 << i)) != 0;
+        let has_props = mem.memory_types[i as usize]
+            .property_flags
+            .contains(properties);
+        if is_type && has_props {
+            return Ok(Some(i));
+        }
+    }
+    Ok(None)
+}
+
+fn describe_vk_swapchain_format(format: u32) -> (&'static str, bool) {
+    if format == vk::Format::R8G8B8A8_UNORM.as_raw() as u32 {
+        ("VK_FORMAT_R8G8B8A8_UNORM", false)
+    } else if format == vk::Format::B8G8R8A8_UNORM.as_raw() as u32 {
+        ("VK_FORMAT_B8G8R8A8_UNORM", false)
+    } else if format == vk::Format::R8G8B8A8_SRGB.as_raw() as u32 {
+        ("VK_FORMAT_R8G8B8A8_SRGB", true)
+    } else if format == vk::Format::B8G8R8A8_SRGB.as_raw() as u32 {
+        ("VK_FORMAT_B8G8R8A8_SRGB", true)
+    } else {
+        ("UNKNOWN_VK_FORMAT", false)
+    }
+}
+
+pub(crate) fn choose_vk_swapchain_format(formats: &[u32]) -> (u32, &'static str, bool) {
+    let preferred = [
+        vk::Format::R8G8B8A8_UNORM.as_raw() as u32,
+        vk::Format::B8G8R8A8_UNORM.as_raw() as u32,
+        vk::Format::R8G8B8A8_SRGB.as_raw() as u32,
+        vk::Format::B8G8R8A8_SRGB.as_raw() as u32,
+    ];
+    if let Some(format) = preferred
+        .iter()
+        .copied()
+        .find(|&fmt| formats.contains(&fmt))
+    {
+        let (name, srgb) = describe_vk_swapchain_format(format);
+        return (format, name, srgb);
+    }
+    let fallback = formats
+        .first()
+        .copied()
+        .unwrap_or(vk::Format::R8G8B8A8_UNORM.as_raw() as u32);
+    let (name, srgb) = describe_vk_swapchain_format(fallback);
+    (fallback, name, srgb)
+}
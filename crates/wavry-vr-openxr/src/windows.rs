@@ -3,10 +3,10 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use openxr as xr;
-use wavry_vr::types::{VideoCodec, VrTiming};
+use wavry_vr::types::VideoCodec;
 use wavry_vr::{VrError, VrResult};
 
 use windows::core::Interface;
@@ -19,7 +19,10 @@ use windows::Win32::System::Com::{
     CoInitializeEx, CoTaskMemFree, CoUninitialize, COINIT_MULTITHREADED,
 };
 
-use crate::common::{eye_layout, to_pose, HandTrackingState, InputActions};
+use crate::common::{
+    create_session_raw, end_frame_with_screen, eye_layout, overlay_session_info, screen_geometry,
+    to_pose, FrameTiming, HandTrackingState, InputActions,
+};
 use crate::SharedState;
 
 const VIEW_COUNT: usize = 2;
@@ -296,6 +299,17 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
     if available_exts.ext_hand_tracking {
         exts.ext_hand_tracking = true;
     }
+    if available_exts.ext_eye_gaze_interaction {
+        exts.ext_eye_gaze_interaction = true;
+    }
+    let overlay = state.overlay();
+    if overlay.is_some() {
+        exts.extx_overlay = available_exts.extx_overlay;
+        exts.khr_composition_layer_cylinder = available_exts.khr_composition_layer_cylinder;
+        if !exts.extx_overlay {
+            eprintln!("OpenXR XR_EXTX_overlay unavailable; showing the screen in its own session");
+        }
+    }
 
     let app_info = xr::ApplicationInfo {
         application_name: "Wavry",
@@ -317,13 +331,28 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
         },
     };
 
-    let (session, mut frame_waiter, mut frame_stream) = unsafe {
-        instance
-            .create_session::<xr::D3D11>(system, &create_info)
-            .map_err(|e| VrError::Adapter(format!("OpenXR create_session: {e:?}")))?
+    let (session, mut frame_waiter, mut frame_stream) = if exts.extx_overlay {
+        let overlay_info = overlay_session_info();
+        let binding = xr::sys::GraphicsBindingD3D11KHR {
+            ty: xr::sys::GraphicsBindingD3D11KHR::TYPE,
+            next: &overlay_info as *const _ as *const _,
+            device: create_info.device as _,
+        };
+        unsafe { create_session_raw::<xr::D3D11>(&instance, system, &binding as *const _ as _)? }
+    } else {
+        unsafe {
+            instance
+                .create_session::<xr::D3D11>(system, &create_info)
+                .map_err(|e| VrError::Adapter(format!("OpenXR create_session: {e:?}")))?
+        }
     };
-    wavry_vr::set_pcvr_status("PCVR: Windows D3D11 runtime active".to_string());
-    let mut input_actions = InputActions::new(&instance, &session).ok();
+    if overlay.is_some() {
+        wavry_vr::set_pcvr_status("PCVR: Windows D3D11 overlay screen active".to_string());
+    } else {
+        wavry_vr::set_pcvr_status("PCVR: Windows D3D11 runtime active".to_string());
+    }
+    let mut input_actions =
+        InputActions::new(&instance, &session, available_exts.ext_eye_gaze_interaction).ok();
     let hand_tracking = if available_exts.ext_hand_tracking {
         HandTrackingState::new(&session).ok()
     } else {
@@ -340,7 +369,7 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
     let mut swapchains: Option<[xr::Swapchain<xr::D3D11>; VIEW_COUNT]> = None;
     let mut swapchain_images: Option<[Vec<ID3D11Texture2D>; VIEW_COUNT]> = None;
     let mut last_texture: Option<ID3D11Texture2D> = None;
-    let mut last_refresh_hz: Option<f32> = None;
+    let mut frame_timing = FrameTiming::default();
 
     loop {
         while let Some(event) = instance
@@ -403,33 +432,39 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
         if !views.is_empty() {
             let pose = to_pose(views[0].pose);
             let timestamp_us = (frame_state.predicted_display_time.as_nanos() / 1_000) as u64;
-            state.callbacks.on_pose_update(pose, timestamp_us);
+            let (predicted, predicted_us) = state.predict_head_pose(pose, timestamp_us);
+            state.callbacks.on_pose_update(predicted, predicted_us);
             if let Some(actions) = input_actions.as_mut() {
                 if let Ok(inputs) = actions.poll(&session, timestamp_us) {
                     for input in inputs {
                         state.callbacks.on_gamepad_input(input);
                     }
                 }
+                if let Some(hint) = actions.poll_foveation(
+                    frame_state.predicted_display_time,
+                    views[0].fov,
+                    timestamp_us,
+                ) {
+                    state.callbacks.on_foveation_update(hint);
+                }
+                for haptic in state.take_pending_haptics() {
+                    if let Err(err) = actions.apply_haptic(&session, haptic) {
+                        eprintln!("OpenXR haptic feedback failed: {:?}", err);
+                    }
+                }
             }
             if let Some(tracking) = hand_tracking.as_ref() {
                 for hand_pose in tracking.poll(&reference_space, frame_state.predicted_display_time)
                 {
-                    state.callbacks.on_hand_pose_update(hand_pose, timestamp_us);
+                    let (predicted, predicted_us) =
+                        state.predict_hand_pose(hand_pose, timestamp_us);
+                    state.callbacks.on_hand_pose_update(predicted, predicted_us);
                 }
             }
         }
 
-        let period_ns = frame_state.predicted_display_period.as_nanos();
-        if period_ns > 0 {
-            let refresh_hz = 1_000_000_000.0 / period_ns as f32;
-            let send = last_refresh_hz.is_none_or(|prev| (prev - refresh_hz).abs() > 0.1);
-            if send {
-                state.callbacks.on_vr_timing(VrTiming {
-                    refresh_hz,
-                    vsync_offset_us: 0,
-                });
-                last_refresh_hz = Some(refresh_hz);
-            }
+        if let Some(timing) = frame_timing.timing(&frame_state, Instant::now()) {
+            state.report_timing(timing);
         }
 
         if decoder.is_none() {
@@ -438,10 +473,12 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
             }
         }
 
-        if let Some(frame) = state.take_latest_frame() {
+        if let Some((frame, age_us)) = state.take_latest_frame() {
+            frame_timing.observe_frame_age(age_us);
             if let Some(decoder) = decoder.as_ref() {
                 if let Some(texture) = decoder.decode(&frame.data, frame.timestamp_us)? {
                     last_texture = Some(texture);
+                    frame_timing.new_image(frame.view);
                 }
             }
         }
@@ -513,8 +550,10 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
                     xr::CompositionLayerProjectionView::new(),
                     xr::CompositionLayerProjectionView::new(),
                 ];
+                // The overlay screen shows one image to both eyes.
+                let view_count = if overlay.is_some() { 1 } else { VIEW_COUNT };
 
-                for i in 0..VIEW_COUNT {
+                for i in 0..view_count {
                     let image_index = swapchains[i]
                         .acquire_image()
                         .map_err(|e| VrError::Adapter(format!("OpenXR acquire: {e:?}")))?;
@@ -567,24 +606,50 @@ fn run(state: Arc<SharedState>) -> VrResult<()> {
                             })
                         };
                         layer_views[i] = xr::CompositionLayerProjectionView::new()
-                            .pose(views[i].pose)
+                            .pose(frame_timing.layer_pose(&views, i))
                             .fov(views[i].fov)
                             .sub_image(sub_image);
                     }
                 }
 
-                let layer = xr::CompositionLayerProjection::new()
-                    .space(&reference_space)
-                    .views(&layer_views);
-                let layers: [&xr::CompositionLayerBase<xr::D3D11>; 1] = [&layer];
-
-                frame_stream
-                    .end(
+                if let Some(initial) = overlay {
+                    let geometry = screen_geometry(
+                        state.overlay().unwrap_or(initial),
+                        width.max(0) as u32,
+                        height.max(0) as u32,
+                        exts.khr_composition_layer_cylinder,
+                    );
+                    let sub_image = unsafe {
+                        xr::SwapchainSubImage::from_raw(xr::sys::SwapchainSubImage {
+                            swapchain: swapchains[0].as_raw(),
+                            image_rect: xr::Rect2Di {
+                                offset: xr::Offset2Di { x: 0, y: 0 },
+                                extent: xr::Extent2Di { width, height },
+                            },
+                            image_array_index: 0,
+                        })
+                    };
+                    end_frame_with_screen(
+                        &mut frame_stream,
                         frame_state.predicted_display_time,
-                        xr::EnvironmentBlendMode::OPAQUE,
-                        &layers,
-                    )
-                    .map_err(|e| VrError::Adapter(format!("OpenXR end: {e:?}")))?;
+                        &reference_space,
+                        geometry,
+                        sub_image,
+                    )?;
+                } else {
+                    let layer = xr::CompositionLayerProjection::new()
+                        .space(&reference_space)
+                        .views(&layer_views);
+                    let layers: [&xr::CompositionLayerBase<xr::D3D11>; 1] = [&layer];
+
+                    frame_stream
+                        .end(
+                            frame_state.predicted_display_time,
+                            xr::EnvironmentBlendMode::OPAQUE,
+                            &layers,
+                        )
+                        .map_err(|e| VrError::Adapter(format!("OpenXR end: {e:?}")))?;
+                }
             }
         } else {
             frame_stream
@@ -0,0 +1,109 @@
+// Derived from ALVR (MIT)
+// Original copyright preserved
+
+//! Compiles the vendored ALVR OpenVR driver when the `steamvr` feature is on.
+//!
+//! The OpenVR SDK is not vendored: point `OPENVR_SDK_DIR` at a checkout that
+//! has `headers/openvr_driver.h`. Linux also needs `FFMPEG_DIR` (an FFmpeg
+//! build with `include/` and `lib/`), Windows needs `VPL_DIR` for oneVPL.
+
+fn main() {
+    #[cfg(feature = "steamvr")]
+    steamvr::build();
+}
+
+#[cfg(feature = "steamvr")]
+mod steamvr {
+    use std::env;
+    use std::path::{Path, PathBuf};
+
+    const DRIVER_DIR: &str = "../../third_party/alvr/alvr/server_openvr";
+
+    fn required_dir(var: &str) -> PathBuf {
+        println!("cargo:rerun-if-env-changed={var}");
+        let dir = env::var_os(var)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| panic!("feature `steamvr` needs {var} to be set"));
+        assert!(dir.exists(), "{var} points at a missing directory");
+        dir
+    }
+
+    fn collect_sources(dir: &Path, skip: &[&str], out: &mut Vec<PathBuf>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            if skip.iter().any(|s| name == *s) {
+                continue;
+            }
+            if path.is_dir() {
+                collect_sources(&path, skip, out);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext == "c" || ext == "cpp")
+            {
+                out.push(path);
+            }
+        }
+    }
+
+    pub fn build() {
+        let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+        let cpp = Path::new(DRIVER_DIR).join("cpp");
+        println!("cargo:rerun-if-changed={}", cpp.display());
+
+        let platform = match target_os.as_str() {
+            "windows" => "win32",
+            "linux" => "linux",
+            other => panic!("the SteamVR driver does not support {other}"),
+        };
+        let mut skip = vec!["tools", "platform"];
+        if target_os == "linux" {
+            skip.push("amf");
+        }
+        let mut sources = Vec::new();
+        collect_sources(&cpp, &skip, &mut sources);
+        collect_sources(&cpp.join("platform").join(platform), &[], &mut sources);
+
+        let openvr = required_dir("OPENVR_SDK_DIR");
+        let mut build = cc::Build::new();
+        build
+            .cpp(true)
+            .std("c++17")
+            .files(&sources)
+            .include(openvr.join("headers"))
+            .include(&cpp);
+
+        if target_os == "windows" {
+            let vpl = required_dir("VPL_DIR");
+            build
+                .debug(false)
+                .flag("/permissive-")
+                .define("NOMINMAX", None)
+                .define("_WINSOCKAPI_", None)
+                .define("_MBCS", None)
+                .define("_MT", None)
+                .define("ONEVPL_EXPERIMENTAL", None)
+                .include(vpl.join("include"));
+            println!(
+                "cargo:rustc-link-search=native={}",
+                vpl.join("lib").display()
+            );
+            println!("cargo:rustc-link-lib=vpl");
+        } else {
+            let ffmpeg = required_dir("FFMPEG_DIR");
+            build.include(ffmpeg.join("include"));
+            println!(
+                "cargo:rustc-link-search=native={}",
+                ffmpeg.join("lib").display()
+            );
+            for lib in ["avutil", "avfilter", "avcodec"] {
+                println!("cargo:rustc-link-lib={lib}");
+            }
+        }
+
+        build.compile("wavry_steamvr_driver");
+    }
+}
@@ -0,0 +1,662 @@
+// Derived from ALVR (MIT)
+// Original copyright preserved
+
+//! OpenVR driver entry point.
+//!
+//! vrserver loads this library and calls `HmdDriverFactory`. The vendored ALVR
+//! C++ driver registers the headset and controllers and encodes the composited
+//! frames; this module replaces ALVR's server core with the loopback bridge to
+//! the Wavry host.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io::{BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info, warn};
+use wavry_vr::types::VideoCodec;
+use wavry_vr::DEFAULT_REFRESH_HZ;
+
+use crate::protocol::{
+    controller_input_path, path_to_id, ButtonValue, ControllerInput, DeviceMotion, DriverMessage,
+    HostMessage, DEFAULT_BRIDGE_ADDR, HEAD_PATH, LEFT_HAND_PATH, RIGHT_HAND_PATH,
+};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const BUTTON_TYPE_BINARY: u32 = 0;
+const BUTTON_TYPE_SCALAR: u32 = 1;
+const PROPERTY_TYPE_FLOAT: u32 = 1;
+const PROPERTY_TYPE_STRING: u32 = 6;
+// vr::ETrackedDeviceProperty values from openvr_driver.h.
+const PROP_TRACKING_SYSTEM_NAME: u32 = 1000;
+const PROP_MODEL_NUMBER: u32 = 1001;
+const PROP_MANUFACTURER_NAME: u32 = 1005;
+const PROP_DISPLAY_FREQUENCY: u32 = 2002;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FfiQuat {
+    x: f32,
+    y: f32,
+    z: f32,
+    w: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FfiPose {
+    orientation: FfiQuat,
+    position: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FfiDeviceMotion {
+    device_id: u64,
+    pose: FfiPose,
+    linear_velocity: [f32; 3],
+    angular_velocity: [f32; 3],
+}
+
+#[repr(C)]
+struct FfiHandData {
+    controller_motion: *const FfiDeviceMotion,
+    hand_skeleton: *const c_void,
+    is_hand_tracker: bool,
+    predict_hand_skeleton: bool,
+}
+
+#[repr(C)]
+union FfiButtonScalar {
+    binary: u32,
+    scalar: f32,
+}
+
+#[repr(C)]
+struct FfiButtonValue {
+    type_: u32,
+    value: FfiButtonScalar,
+}
+
+// The C union also holds u64 and double members, hence the alignment.
+#[repr(C, align(8))]
+union FfiOpenvrPropertyValue {
+    float_: f32,
+    string: [c_char; 256],
+}
+
+#[repr(C)]
+struct FfiOpenvrProperty {
+    key: u32,
+    type_: u32,
+    value: FfiOpenvrPropertyValue,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FfiDynamicEncoderParams {
+    updated: u32,
+    bitrate_bps: u64,
+    framerate: f32,
+}
+
+type LogFn = extern "C" fn(*const c_char);
+
+#[allow(non_upper_case_globals)]
+extern "C" {
+    static mut FRAME_RENDER_VS_CSO_PTR: *const u8;
+    static mut FRAME_RENDER_VS_CSO_LEN: u32;
+    static mut FRAME_RENDER_PS_CSO_PTR: *const u8;
+    static mut FRAME_RENDER_PS_CSO_LEN: u32;
+    static mut QUAD_SHADER_CSO_PTR: *const u8;
+    static mut QUAD_SHADER_CSO_LEN: u32;
+    static mut COMPRESS_AXIS_ALIGNED_CSO_PTR: *const u8;
+    static mut COMPRESS_AXIS_ALIGNED_CSO_LEN: u32;
+    static mut COLOR_CORRECTION_CSO_PTR: *const u8;
+    static mut COLOR_CORRECTION_CSO_LEN: u32;
+    static mut RGBTOYUV420_CSO_PTR: *const u8;
+    static mut RGBTOYUV420_CSO_LEN: u32;
+    static mut QUAD_SHADER_COMP_SPV_PTR: *const u8;
+    static mut QUAD_SHADER_COMP_SPV_LEN: u32;
+    static mut COLOR_SHADER_COMP_SPV_PTR: *const u8;
+    static mut COLOR_SHADER_COMP_SPV_LEN: u32;
+    static mut FFR_SHADER_COMP_SPV_PTR: *const u8;
+    static mut FFR_SHADER_COMP_SPV_LEN: u32;
+    static mut RGBTOYUV420_SHADER_COMP_SPV_PTR: *const u8;
+    static mut RGBTOYUV420_SHADER_COMP_SPV_LEN: u32;
+
+    static mut g_sessionPath: *const c_char;
+    static mut g_driverRootDir: *const c_char;
+
+    static mut LogError: Option<LogFn>;
+    static mut LogWarn: Option<LogFn>;
+    static mut LogInfo: Option<LogFn>;
+    static mut LogDebug: Option<LogFn>;
+    static mut LogEncoder: Option<LogFn>;
+    static mut LogPeriodically: Option<extern "C" fn(*const c_char, *const c_char)>;
+    static mut DriverReadyIdle: Option<extern "C" fn(bool)>;
+    static mut SetVideoConfigNals: Option<extern "C" fn(*const u8, i32, i32)>;
+    static mut VideoSend: Option<extern "C" fn(u64, *mut u8, i32, bool)>;
+    static mut HapticsSend: Option<extern "C" fn(u64, f32, f32, f32)>;
+    static mut ShutdownRuntime: Option<extern "C" fn()>;
+    static mut PathStringToHash: Option<extern "C" fn(*const c_char) -> u64>;
+    static mut ReportPresent: Option<extern "C" fn(u64, u64)>;
+    static mut ReportComposed: Option<extern "C" fn(u64, u64)>;
+    static mut GetDynamicEncoderParams: Option<extern "C" fn() -> FfiDynamicEncoderParams>;
+    static mut GetSerialNumber: Option<extern "C" fn(u64, *mut c_char) -> u64>;
+    static mut SetOpenvrProps: Option<unsafe extern "C" fn(*mut c_void, u64)>;
+    static mut RegisterButtons: Option<unsafe extern "C" fn(*mut c_void, u64)>;
+    static mut WaitForVSync: Option<extern "C" fn()>;
+
+    fn CppInit(early_hmd_initialization: bool);
+    fn CppOpenvrEntryPoint(interface_name: *const c_char, return_code: *mut i32) -> *mut c_void;
+    fn InitializeStreaming() -> bool;
+    fn DeinitializeStreaming();
+    fn RequestIDR();
+    fn SetTracking(
+        target_timestamp_ns: u64,
+        controller_pose_time_offset_s: f32,
+        head_motion: FfiDeviceMotion,
+        left_hand_data: FfiHandData,
+        right_hand_data: FfiHandData,
+        body_tracker_motions: *const FfiDeviceMotion,
+        body_tracker_motion_count: i32,
+    );
+    fn SetOpenvrProperty(instance_ptr: *mut c_void, prop: FfiOpenvrProperty);
+    fn RegisterButton(instance_ptr: *mut c_void, button_id: u64);
+    fn SetButton(button_id: u64, value: FfiButtonValue);
+    fn InitOpenvrClient();
+    fn ShutdownOpenvrClient();
+    fn SetChaperoneArea(area_width: f32, area_height: f32);
+}
+
+static BRIDGE: Mutex<Option<BufWriter<TcpStream>>> = Mutex::new(None);
+static CONTROLLERS: Mutex<[Option<DeviceMotion>; 2]> = Mutex::new([None; 2]);
+static ENCODER_PARAMS: Mutex<Option<(u64, f32)>> = Mutex::new(None);
+static REFRESH_HZ_BITS: AtomicU32 = AtomicU32::new(0);
+static LAST_VSYNC: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn send(msg: DriverMessage) {
+    let Ok(mut bridge) = BRIDGE.lock() else {
+        return;
+    };
+    let Some(writer) = bridge.as_mut() else {
+        return;
+    };
+    if let Err(err) = msg.write_to(writer).and_then(|_| writer.flush()) {
+        warn!("Wavry host write failed: {}", err);
+        *bridge = None;
+    }
+}
+
+fn bridge_addr() -> String {
+    std::env::var("WAVRY_STEAMVR_BRIDGE").unwrap_or_else(|_| DEFAULT_BRIDGE_ADDR.to_string())
+}
+
+fn refresh_hz() -> f32 {
+    match f32::from_bits(REFRESH_HZ_BITS.load(Ordering::Relaxed)) {
+        hz if hz > 0.0 => hz,
+        _ => DEFAULT_REFRESH_HZ as f32,
+    }
+}
+
+fn to_ffi_motion(device_id: u64, motion: DeviceMotion) -> FfiDeviceMotion {
+    let [x, y, z, w] = motion.pose.orientation;
+    FfiDeviceMotion {
+        device_id,
+        pose: FfiPose {
+            orientation: FfiQuat { x, y, z, w },
+            position: motion.pose.position,
+        },
+        linear_velocity: motion.linear_velocity,
+        angular_velocity: motion.angular_velocity,
+    }
+}
+
+fn hand_data(motion: &Option<FfiDeviceMotion>) -> FfiHandData {
+    FfiHandData {
+        controller_motion: motion.as_ref().map_or(ptr::null(), |m| m as *const _),
+        hand_skeleton: ptr::null(),
+        is_hand_tracker: false,
+        predict_hand_skeleton: false,
+    }
+}
+
+fn handle_host_message(msg: HostMessage) {
+    match msg {
+        HostMessage::Refresh { refresh_hz } => {
+            REFRESH_HZ_BITS.store(refresh_hz.to_bits(), Ordering::Relaxed);
+        }
+        HostMessage::Tracking { timestamp_ns, head } => {
+            let controllers = CONTROLLERS.lock().map(|c| *c).unwrap_or_default();
+            let left = controllers[0].map(|m| to_ffi_motion(path_to_id(LEFT_HAND_PATH), m));
+            let right = controllers[1].map(|m| to_ffi_motion(path_to_id(RIGHT_HAND_PATH), m));
+            unsafe {
+                SetTracking(
+                    timestamp_ns,
+                    0.0,
+                    to_ffi_motion(path_to_id(HEAD_PATH), head),
+                    hand_data(&left),
+                    hand_data(&right),
+                    ptr::null(),
+                    0,
+                )
+            };
+        }
+        HostMessage::Controller { hand, motion } => {
+            if let Ok(mut controllers) = CONTROLLERS.lock() {
+                if let Some(slot) = controllers.get_mut(hand as usize) {
+                    *slot = Some(motion);
+                }
+            }
+        }
+        HostMessage::Button { path_id, value } => {
+            let value = match value {
+                ButtonValue::Binary(pressed) => FfiButtonValue {
+                    type_: BUTTON_TYPE_BINARY,
+                    value: FfiButtonScalar {
+                        binary: pressed as u32,
+                    },
+                },
+                ButtonValue::Scalar(scalar) => FfiButtonValue {
+                    type_: BUTTON_TYPE_SCALAR,
+                    value: FfiButtonScalar { scalar },
+                },
+            };
+            unsafe { SetButton(path_id, value) };
+        }
+        HostMessage::EncoderParams {
+            bitrate_bps,
+            framerate,
+        } => {
+            if let Ok(mut params) = ENCODER_PARAMS.lock() {
+                *params = Some((bitrate_bps, framerate));
+            }
+        }
+        HostMessage::RequestIdr => unsafe { RequestIDR() },
+    }
+}
+
+fn bridge_loop() {
+    let addr = bridge_addr();
+    loop {
+        let stream = match TcpStream::connect(&addr) {
+            Ok(stream) => stream,
+            Err(_) => {
+                thread::sleep(RECONNECT_INTERVAL);
+                continue;
+            }
+        };
+        let reader = stream
+            .set_nodelay(true)
+            .and_then(|_| stream.try_clone())
+            .map(BufReader::new);
+        let mut reader = match reader {
+            Ok(reader) => reader,
+            Err(err) => {
+                warn!("Wavry host connection setup failed: {}", err);
+                thread::sleep(RECONNECT_INTERVAL);
+                continue;
+            }
+        };
+        if let Ok(mut bridge) = BRIDGE.lock() {
+            *bridge = Some(BufWriter::new(stream));
+        }
+        info!("Connected to Wavry host at {}", addr);
+
+        if unsafe { InitializeStreaming() } {
+            loop {
+                match HostMessage::read_from(&mut reader) {
+                    Ok(Some(msg)) => handle_host_message(msg),
+                    Ok(None) => break,
+                    Err(err) => {
+                        warn!("Wavry host read failed: {}", err);
+                        break;
+                    }
+                }
+            }
+            unsafe { DeinitializeStreaming() };
+        } else {
+            error!("SteamVR driver failed to start streaming");
+        }
+
+        if let Ok(mut bridge) = BRIDGE.lock() {
+            *bridge = None;
+        }
+        info!("Disconnected from Wavry host");
+        thread::sleep(RECONNECT_INTERVAL);
+    }
+}
+
+fn log_message(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+extern "C" fn log_error(msg: *const c_char) {
+    error!("{}", log_message(msg));
+}
+
+extern "C" fn log_warn(msg: *const c_char) {
+    warn!("{}", log_message(msg));
+}
+
+extern "C" fn log_info(msg: *const c_char) {
+    info!("{}", log_message(msg));
+}
+
+extern "C" fn log_debug(msg: *const c_char) {
+    debug!("{}", log_message(msg));
+}
+
+extern "C" fn log_periodically(tag: *const c_char, msg: *const c_char) {
+    debug!("[{}] {}", log_message(tag), log_message(msg));
+}
+
+extern "C" fn path_string_to_hash(path: *const c_char) -> u64 {
+    path_to_id(&log_message(path))
+}
+
+extern "C" fn get_serial_number(device_id: u64, out: *mut c_char) -> u64 {
+    let serial = if device_id == path_to_id(HEAD_PATH) {
+        "WAVRY-HMD".to_string()
+    } else if device_id == path_to_id(LEFT_HAND_PATH) {
+        "WAVRY-CTRL-L".to_string()
+    } else if device_id == path_to_id(RIGHT_HAND_PATH) {
+        "WAVRY-CTRL-R".to_string()
+    } else {
+        format!("WAVRY-{device_id:016X}")
+    };
+    let serial = CString::new(serial).unwrap_or_default();
+    let bytes = serial.as_bytes_with_nul();
+    // The C++ side first asks for the length with a null buffer, then copies.
+    if !out.is_null() {
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr().cast(), out, bytes.len()) };
+    }
+    bytes.len() as u64
+}
+
+fn string_prop(key: u32, value: &str) -> FfiOpenvrProperty {
+    let mut string = [0 as c_char; 256];
+    for (dst, src) in string.iter_mut().zip(value.bytes().take(255)) {
+        *dst = src as c_char;
+    }
+    FfiOpenvrProperty {
+        key,
+        type_: PROPERTY_TYPE_STRING,
+        value: FfiOpenvrPropertyValue { string },
+    }
+}
+
+/// # Safety
+/// `instance_ptr` is a valid pointer to a `TrackedDevice` instance.
+unsafe extern "C" fn set_device_openvr_props(instance_ptr: *mut c_void, device_id: u64) {
+    let model = if device_id == path_to_id(HEAD_PATH) {
+        "Wavry HMD"
+    } else {
+        "Wavry Controller"
+    };
+    for prop in [
+        string_prop(PROP_TRACKING_SYSTEM_NAME, "wavry"),
+        string_prop(PROP_MANUFACTURER_NAME, "Wavry"),
+        string_prop(PROP_MODEL_NUMBER, model),
+    ] {
+        unsafe { SetOpenvrProperty(instance_ptr, prop) };
+    }
+    if device_id == path_to_id(HEAD_PATH) {
+        let prop = FfiOpenvrProperty {
+            key: PROP_DISPLAY_FREQUENCY,
+            type_: PROPERTY_TYPE_FLOAT,
+            value: FfiOpenvrPropertyValue {
+                float_: refresh_hz(),
+            },
+        };
+        unsafe { SetOpenvrProperty(instance_ptr, prop) };
+    }
+}
+
+/// # Safety
+/// `instance_ptr` is a valid pointer to a `Controller` instance.
+unsafe extern "C" fn register_buttons(instance_ptr: *mut c_void, device_id: u64) {
+    let hand = if device_id == path_to_id(LEFT_HAND_PATH) {
+        0
+    } else if device_id == path_to_id(RIGHT_HAND_PATH) {
+        1
+    } else {
+        return;
+    };
+    let inputs = (0..4)
+        .map(ControllerInput::Axis)
+        .chain((0..2).map(ControllerInput::Button));
+    for input in inputs {
+        if let Some(path) = controller_input_path(hand, input) {
+            unsafe { RegisterButton(instance_ptr, path_to_id(&path)) };
+        }
+    }
+}
+
+extern "C" fn driver_ready_idle(set_default_chaperone: bool) {
+    thread::spawn(move || {
+        unsafe { InitOpenvrClient() };
+        if set_default_chaperone {
+            // Must not run on the vrserver thread that called us.
+            unsafe { SetChaperoneArea(2.0, 2.0) };
+        }
+    });
+}
+
+extern "C" fn set_video_config_nals(buffer: *const u8, len: i32, codec: i32) {
+    if buffer.is_null() || len <= 0 {
+        return;
+    }
+    let codec = match codec {
+        0 => VideoCodec::H264,
+        1 => VideoCodec::Hevc,
+        _ => VideoCodec::Av1,
+    };
+    let nals = unsafe { std::slice::from_raw_parts(buffer, len as usize) }.to_vec();
+    send(DriverMessage::VideoConfig { codec, nals });
+}
+
+extern "C" fn video_send(timestamp_ns: u64, buffer: *mut u8, len: i32, is_idr: bool) {
+    if buffer.is_null() || len <= 0 {
+        return;
+    }
+    let data = unsafe { std::slice::from_raw_parts(buffer, len as usize) }.to_vec();
+    send(DriverMessage::Video {
+        timestamp_ns,
+        keyframe: is_idr,
+        data,
+    });
+}
+
+extern "C" fn haptics_send(device_id: u64, duration_s: f32, frequency: f32, amplitude: f32) {
+    send(DriverMessage::Haptics {
+        device_id,
+        duration_s,
+        frequency,
+        amplitude,
+    });
+}
+
+extern "C" fn report_present(timestamp_ns: u64, offset_ns: u64) {
+    send(DriverMessage::Present {
+        timestamp_ns,
+        offset_ns,
+    });
+}
+
+extern "C" fn report_composed(_timestamp_ns: u64, _offset_ns: u64) {}
+
+extern "C" fn get_dynamic_encoder_params() -> FfiDynamicEncoderParams {
+    match ENCODER_PARAMS.lock().ok().and_then(|mut p| p.take()) {
+        Some((bitrate_bps, framerate)) => FfiDynamicEncoderParams {
+            updated: 1,
+            bitrate_bps,
+            framerate,
+        },
+        None => FfiDynamicEncoderParams::default(),
+    }
+}
+
+/// Paces SteamVR's compositor to the headset refresh rate.
+extern "C" fn wait_for_vsync() {
+    let interval = Duration::from_secs_f32(1.0 / refresh_hz());
+    let now = Instant::now();
+    // Don't hold the lock while sleeping.
+    let target = {
+        let Ok(mut last) = LAST_VSYNC.lock() else {
+            return;
+        };
+        let next = last.map_or(now, |last| last + interval);
+        // After a stall, restart the grid instead of bursting to catch up.
+        let next = if next + interval < now { now } else { next };
+        *last = Some(next);
+        next
+    };
+    if target > now {
+        thread::sleep(target - now);
+    }
+}
+
+extern "C" fn shutdown_runtime() {
+    if let Ok(mut bridge) = BRIDGE.lock() {
+        *bridge = None;
+    }
+    unsafe { ShutdownOpenvrClient() };
+}
+
+macro_rules! set_shader {
+    ($ptr:ident, $len:ident, $path:literal) => {{
+        static BYTES: &[u8] = include_bytes!($path);
+        $ptr = BYTES.as_ptr();
+        $len = BYTES.len() as u32;
+    }};
+}
+
+fn initialize_shaders() {
+    unsafe {
+        set_shader!(
+            FRAME_RENDER_VS_CSO_PTR,
+            FRAME_RENDER_VS_CSO_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/win32/FrameRenderVS.cso"
+        );
+        set_shader!(
+            FRAME_RENDER_PS_CSO_PTR,
+            FRAME_RENDER_PS_CSO_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/win32/FrameRenderPS.cso"
+        );
+        set_shader!(
+            QUAD_SHADER_CSO_PTR,
+            QUAD_SHADER_CSO_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/win32/QuadVertexShader.cso"
+        );
+        set_shader!(
+            COMPRESS_AXIS_ALIGNED_CSO_PTR,
+            COMPRESS_AXIS_ALIGNED_CSO_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/win32/CompressAxisAlignedPixelShader.cso"
+        );
+        set_shader!(
+            COLOR_CORRECTION_CSO_PTR,
+            COLOR_CORRECTION_CSO_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/win32/ColorCorrectionPixelShader.cso"
+        );
+        set_shader!(
+            RGBTOYUV420_CSO_PTR,
+            RGBTOYUV420_CSO_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/win32/rgbtoyuv420.cso"
+        );
+        set_shader!(
+            QUAD_SHADER_COMP_SPV_PTR,
+            QUAD_SHADER_COMP_SPV_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/linux/shader/quad.comp.spv"
+        );
+        set_shader!(
+            COLOR_SHADER_COMP_SPV_PTR,
+            COLOR_SHADER_COMP_SPV_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/linux/shader/color.comp.spv"
+        );
+        set_shader!(
+            FFR_SHADER_COMP_SPV_PTR,
+            FFR_SHADER_COMP_SPV_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/linux/shader/ffr.comp.spv"
+        );
+        set_shader!(
+            RGBTOYUV420_SHADER_COMP_SPV_PTR,
+            RGBTOYUV420_SHADER_COMP_SPV_LEN,
+            "../../../third_party/alvr/alvr/server_openvr/cpp/platform/linux/shader/rgbtoyuv420.comp.spv"
+        );
+    }
+}
+
+/// Directory holding `driver.vrdrivermanifest`; the session file the C++
+/// driver reads its display and encoder settings from lives next to it.
+fn driver_root_dir() -> PathBuf {
+    std::env::var_os("WAVRY_STEAMVR_DRIVER_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+}
+
+fn leak_c_string(path: PathBuf) -> *const c_char {
+    CString::new(path.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// The SteamVR/OpenVR entry point.
+///
+/// # Safety
+/// Called by vrserver with a valid interface name and return code pointer.
+#[no_mangle]
+pub unsafe extern "C" fn HmdDriverFactory(
+    interface_name: *const c_char,
+    return_code: *mut i32,
+) -> *mut c_void {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let root = driver_root_dir();
+        unsafe {
+            g_sessionPath = leak_c_string(root.join("session.json"));
+            g_driverRootDir = leak_c_string(root);
+
+            initialize_shaders();
+
+            LogError = Some(log_error);
+            LogWarn = Some(log_warn);
+            LogInfo = Some(log_info);
+            LogDebug = Some(log_debug);
+            LogEncoder = Some(log_debug);
+            LogPeriodically = Some(log_periodically);
+            PathStringToHash = Some(path_string_to_hash);
+            GetSerialNumber = Some(get_serial_number);
+            SetOpenvrProps = Some(set_device_openvr_props);
+            RegisterButtons = Some(register_buttons);
+            DriverReadyIdle = Some(driver_ready_idle);
+            HapticsSend = Some(haptics_send);
+            SetVideoConfigNals = Some(set_video_config_nals);
+            VideoSend = Some(video_send);
+            GetDynamicEncoderParams = Some(get_dynamic_encoder_params);
+            ReportComposed = Some(report_composed);
+            ReportPresent = Some(report_present);
+            WaitForVSync = Some(wait_for_vsync);
+            ShutdownRuntime = Some(shutdown_runtime);
+
+            // The headset is registered before the host connects so SteamVR
+            // starts with a display instead of waiting for one.
+            CppInit(true);
+        }
+
+        thread::spawn(bridge_loop);
+    });
+
+    unsafe { CppOpenvrEntryPoint(interface_name, return_code) }
+}
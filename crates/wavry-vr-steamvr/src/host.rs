@@ -0,0 +1,246 @@
+//! Host side of the driver bridge, owned by wavry-server.
+
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use log::{debug, info, warn};
+
+use crate::protocol::{DriverMessage, HostMessage};
+
+struct DriverConnection {
+    id: u64,
+    writer: BufWriter<TcpStream>,
+}
+
+#[derive(Default)]
+struct Shared {
+    driver: Mutex<Option<DriverConnection>>,
+    /// Replayed to a driver that connects after the headset reported its rate.
+    refresh: Mutex<Option<HostMessage>>,
+    next_id: AtomicU64,
+}
+
+/// Accepts the SteamVR driver and relays tracking to it and frames from it.
+pub struct SteamVrBridge {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+}
+
+impl SteamVrBridge {
+    /// Listens for the driver on `addr`. Driver messages arrive on the returned
+    /// channel, with codec parameter sets already in front of each keyframe.
+    pub fn listen(addr: SocketAddr) -> io::Result<(Self, mpsc::Receiver<DriverMessage>)> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared::default());
+        let (tx, rx) = mpsc::channel();
+
+        let accept_shared = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("steamvr-bridge".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(err) = accept(&accept_shared, stream, tx.clone()) {
+                                warn!("SteamVR driver connection failed: {}", err);
+                            }
+                        }
+                        Err(err) => warn!("SteamVR bridge accept failed: {}", err),
+                    }
+                }
+            })?;
+
+        info!("SteamVR bridge listening on {}", local_addr);
+        Ok((Self { shared, local_addr }, rx))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared
+            .driver
+            .lock()
+            .map(|driver| driver.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Messages sent while no driver is connected are dropped.
+    pub fn send(&self, msg: &HostMessage) {
+        if matches!(msg, HostMessage::Refresh { .. }) {
+            if let Ok(mut refresh) = self.shared.refresh.lock() {
+                *refresh = Some(msg.clone());
+            }
+        }
+        let Ok(mut driver) = self.shared.driver.lock() else {
+            return;
+        };
+        let Some(conn) = driver.as_mut() else {
+            return;
+        };
+        let result = msg
+            .write_to(&mut conn.writer)
+            .and_then(|_| conn.writer.flush());
+        if let Err(err) = result {
+            warn!("SteamVR driver write failed, dropping connection: {}", err);
+            *driver = None;
+        }
+    }
+}
+
+fn accept(
+    shared: &Arc<Shared>,
+    stream: TcpStream,
+    tx: mpsc::Sender<DriverMessage>,
+) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+    let mut conn = DriverConnection {
+        id,
+        writer: BufWriter::new(stream),
+    };
+    if let Some(refresh) = shared.refresh.lock().ok().and_then(|r| r.clone()) {
+        refresh.write_to(&mut conn.writer)?;
+    }
+    HostMessage::RequestIdr.write_to(&mut conn.writer)?;
+    conn.writer.flush()?;
+
+    // A restarted vrserver replaces the previous driver.
+    if let Ok(mut driver) = shared.driver.lock() {
+        *driver = Some(conn);
+    }
+    info!("SteamVR driver connected from {}", peer);
+
+    let shared = Arc::clone(shared);
+    thread::Builder::new()
+        .name("steamvr-driver-rx".into())
+        .spawn(move || {
+            read_driver(reader, tx);
+            if let Ok(mut driver) = shared.driver.lock() {
+                if driver.as_ref().is_some_and(|conn| conn.id == id) {
+                    *driver = None;
+                }
+            }
+            info!("SteamVR driver {} disconnected", peer);
+        })?;
+    Ok(())
+}
+
+fn read_driver(mut reader: BufReader<TcpStream>, tx: mpsc::Sender<DriverMessage>) {
+    let mut config_nals = Vec::new();
+    loop {
+        let msg = match DriverMessage::read_from(&mut reader) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return,
+            Err(err) => {
+                debug!("SteamVR driver read failed: {}", err);
+                return;
+            }
+        };
+        let msg = match msg {
+            DriverMessage::VideoConfig { codec, nals } => {
+                config_nals = nals.clone();
+                DriverMessage::VideoConfig { codec, nals }
+            }
+            DriverMessage::Video {
+                timestamp_ns,
+                keyframe: true,
+                data,
+            } if !config_nals.is_empty() => {
+                let mut with_config = Vec::with_capacity(config_nals.len() + data.len());
+                with_config.extend_from_slice(&config_nals);
+                with_config.extend_from_slice(&data);
+                DriverMessage::Video {
+                    timestamp_ns,
+                    keyframe: true,
+                    data: with_config,
+                }
+            }
+            msg => msg,
+        };
+        if tx.send(msg).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use wavry_vr::types::VideoCodec;
+
+    #[test]
+    fn bridge_relays_both_directions_and_prefixes_keyframes() {
+        let (bridge, rx) = SteamVrBridge::listen("127.0.0.1:0".parse().unwrap()).unwrap();
+        bridge.send(&HostMessage::Refresh { refresh_hz: 120.0 });
+
+        let mut driver = TcpStream::connect(bridge.local_addr()).unwrap();
+        driver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(
+            HostMessage::read_from(&mut driver).unwrap(),
+            Some(HostMessage::Refresh { refresh_hz: 120.0 })
+        );
+        assert_eq!(
+            HostMessage::read_from(&mut driver).unwrap(),
+            Some(HostMessage::RequestIdr)
+        );
+
+        for msg in [
+            DriverMessage::VideoConfig {
+                codec: VideoCodec::H264,
+                nals: vec![0, 0, 1, 0x67],
+            },
+            DriverMessage::Video {
+                timestamp_ns: 5,
+                keyframe: true,
+                data: vec![0, 0, 1, 0x65],
+            },
+            DriverMessage::Video {
+                timestamp_ns: 6,
+                keyframe: false,
+                data: vec![0, 0, 1, 0x41],
+            },
+        ] {
+            msg.write_to(&mut driver).unwrap();
+        }
+
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(
+            rx.recv_timeout(timeout).unwrap(),
+            DriverMessage::VideoConfig { .. }
+        ));
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            DriverMessage::Video {
+                timestamp_ns: 5,
+                keyframe: true,
+                data: vec![0, 0, 1, 0x67, 0, 0, 1, 0x65],
+            }
+        );
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            DriverMessage::Video {
+                timestamp_ns: 6,
+                keyframe: false,
+                data: vec![0, 0, 1, 0x41],
+            }
+        );
+
+        assert!(bridge.is_connected());
+        bridge.send(&HostMessage::RequestIdr);
+        assert_eq!(
+            HostMessage::read_from(&mut driver).unwrap(),
+            Some(HostMessage::RequestIdr)
+        );
+    }
+}
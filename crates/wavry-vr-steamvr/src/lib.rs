@@ -0,0 +1,18 @@
+//! SteamVR driver that presents a Wavry client's headset and controllers to
+//! SteamVR on the host and hands the composited, encoded frames back to
+//! wavry-server.
+//!
+//! Without the `steamvr` feature only the host side of the bridge is built.
+
+#![allow(unsafe_code)]
+
+#[cfg(feature = "steamvr")]
+mod driver;
+mod host;
+pub mod protocol;
+
+pub use host::SteamVrBridge;
+pub use protocol::{
+    controller_input_path, path_to_id, ButtonValue, ControllerInput, DeviceMotion, DriverMessage,
+    HostMessage, DEFAULT_BRIDGE_ADDR,
+};
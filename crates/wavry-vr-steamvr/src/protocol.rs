@@ -0,0 +1,500 @@
+//! Messages between the SteamVR driver and the Wavry host.
+//!
+//! The driver runs inside vrserver and connects to the host over loopback TCP.
+//! Each message is a one-byte tag, a little-endian u32 body length and the body.
+
+use std::io::{self, Read, Write};
+
+use wavry_vr::types::{Pose, VideoCodec};
+
+pub const DEFAULT_BRIDGE_ADDR: &str = "127.0.0.1:9944";
+pub const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+pub const HEAD_PATH: &str = "/user/head";
+pub const LEFT_HAND_PATH: &str = "/user/hand/left";
+pub const RIGHT_HAND_PATH: &str = "/user/hand/right";
+
+/// Device and input ids the driver registers with SteamVR (FNV-1a of the path).
+pub fn path_to_id(path: &str) -> u64 {
+    path.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Input path of a controller button or axis as reported by the client's
+/// gamepad mapping (stick x/y, trigger, grip; primary, secondary).
+pub fn controller_input_path(hand: u32, input: ControllerInput) -> Option<String> {
+    let (root, primary, secondary) = match hand {
+        0 => (LEFT_HAND_PATH, "x", "y"),
+        1 => (RIGHT_HAND_PATH, "a", "b"),
+        _ => return None,
+    };
+    let component = match input {
+        ControllerInput::Axis(0) => "thumbstick/x".to_string(),
+        ControllerInput::Axis(1) => "thumbstick/y".to_string(),
+        ControllerInput::Axis(2) => "trigger/value".to_string(),
+        ControllerInput::Axis(3) => "squeeze/value".to_string(),
+        ControllerInput::Button(0) => format!("{primary}/click"),
+        ControllerInput::Button(1) => format!("{secondary}/click"),
+        _ => return None,
+    };
+    Some(format!("{root}/input/{component}"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerInput {
+    Axis(u32),
+    Button(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeviceMotion {
+    pub pose: Pose,
+    pub linear_velocity: [f32; 3],
+    pub angular_velocity: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtonValue {
+    Binary(bool),
+    Scalar(f32),
+}
+
+/// Host → driver.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostMessage {
+    /// Headset refresh rate the driver paces vsync to.
+    Refresh {
+        refresh_hz: f32,
+    },
+    /// Head pose sampled at `timestamp_ns`; the driver submits tracking on each one.
+    Tracking {
+        timestamp_ns: u64,
+        head: DeviceMotion,
+    },
+    /// Latest controller pose, `hand` 0 = left, 1 = right.
+    Controller {
+        hand: u32,
+        motion: DeviceMotion,
+    },
+    Button {
+        path_id: u64,
+        value: ButtonValue,
+    },
+    EncoderParams {
+        bitrate_bps: u64,
+        framerate: f32,
+    },
+    RequestIdr,
+}
+
+/// Driver → host.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriverMessage {
+    /// Parameter sets to put in front of every keyframe.
+    VideoConfig {
+        codec: VideoCodec,
+        nals: Vec<u8>,
+    },
+    /// Encoded composited frame for the tracking sample at `timestamp_ns`.
+    Video {
+        timestamp_ns: u64,
+        keyframe: bool,
+        data: Vec<u8>,
+    },
+    Haptics {
+        device_id: u64,
+        duration_s: f32,
+        frequency: f32,
+        amplitude: f32,
+    },
+    Present {
+        timestamp_ns: u64,
+        offset_ns: u64,
+    },
+}
+
+impl HostMessage {
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut body = Vec::new();
+        let tag = match self {
+            Self::Refresh { refresh_hz } => {
+                put_f32(&mut body, *refresh_hz);
+                1
+            }
+            Self::Tracking { timestamp_ns, head } => {
+                put_u64(&mut body, *timestamp_ns);
+                put_motion(&mut body, head);
+                2
+            }
+            Self::Controller { hand, motion } => {
+                put_u32(&mut body, *hand);
+                put_motion(&mut body, motion);
+                3
+            }
+            Self::Button { path_id, value } => {
+                put_u64(&mut body, *path_id);
+                match value {
+                    ButtonValue::Binary(pressed) => {
+                        body.push(0);
+                        put_f32(&mut body, if *pressed { 1.0 } else { 0.0 });
+                    }
+                    ButtonValue::Scalar(value) => {
+                        body.push(1);
+                        put_f32(&mut body, *value);
+                    }
+                }
+                4
+            }
+            Self::EncoderParams {
+                bitrate_bps,
+                framerate,
+            } => {
+                put_u64(&mut body, *bitrate_bps);
+                put_f32(&mut body, *framerate);
+                5
+            }
+            Self::RequestIdr => 6,
+        };
+        write_frame(w, tag, &body)
+    }
+
+    /// `Ok(None)` when the peer closed the connection.
+    pub fn read_from(r: &mut impl Read) -> io::Result<Option<Self>> {
+        let Some((tag, body)) = read_frame(r)? else {
+            return Ok(None);
+        };
+        let mut body = Body(&body);
+        let msg = match tag {
+            1 => Self::Refresh {
+                refresh_hz: body.f32()?,
+            },
+            2 => Self::Tracking {
+                timestamp_ns: body.u64()?,
+                head: body.motion()?,
+            },
+            3 => Self::Controller {
+                hand: body.u32()?,
+                motion: body.motion()?,
+            },
+            4 => {
+                let path_id = body.u64()?;
+                let kind = body.u8()?;
+                let value = body.f32()?;
+                Self::Button {
+                    path_id,
+                    value: if kind == 0 {
+                        ButtonValue::Binary(value != 0.0)
+                    } else {
+                        ButtonValue::Scalar(value)
+                    },
+                }
+            }
+            5 => Self::EncoderParams {
+                bitrate_bps: body.u64()?,
+                framerate: body.f32()?,
+            },
+            6 => Self::RequestIdr,
+            _ => return Err(invalid(format!("unknown host message tag {tag}"))),
+        };
+        Ok(Some(msg))
+    }
+}
+
+impl DriverMessage {
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut body = Vec::new();
+        let tag = match self {
+            Self::VideoConfig { codec, nals } => {
+                body.push(match codec {
+                    VideoCodec::H264 => 0,
+                    VideoCodec::Hevc => 1,
+                    VideoCodec::Av1 => 2,
+                });
+                body.extend_from_slice(nals);
+                1
+            }
+            Self::Video {
+                timestamp_ns,
+                keyframe,
+                data,
+            } => {
+                put_u64(&mut body, *timestamp_ns);
+                body.push(*keyframe as u8);
+                body.extend_from_slice(data);
+                2
+            }
+            Self::Haptics {
+                device_id,
+                duration_s,
+                frequency,
+                amplitude,
+            } => {
+                put_u64(&mut body, *device_id);
+                put_f32(&mut body, *duration_s);
+                put_f32(&mut body, *frequency);
+                put_f32(&mut body, *amplitude);
+                3
+            }
+            Self::Present {
+                timestamp_ns,
+                offset_ns,
+            } => {
+                put_u64(&mut body, *timestamp_ns);
+                put_u64(&mut body, *offset_ns);
+                4
+            }
+        };
+        write_frame(w, tag, &body)
+    }
+
+    /// `Ok(None)` when the peer closed the connection.
+    pub fn read_from(r: &mut impl Read) -> io::Result<Option<Self>> {
+        let Some((tag, body)) = read_frame(r)? else {
+            return Ok(None);
+        };
+        let mut body = Body(&body);
+        let msg = match tag {
+            1 => Self::VideoConfig {
+                codec: match body.u8()? {
+                    0 => VideoCodec::H264,
+                    1 => VideoCodec::Hevc,
+                    2 => VideoCodec::Av1,
+                    other => return Err(invalid(format!("unknown codec {other}"))),
+                },
+                nals: body.rest(),
+            },
+            2 => Self::Video {
+                timestamp_ns: body.u64()?,
+                keyframe: body.u8()? != 0,
+                data: body.rest(),
+            },
+            3 => Self::Haptics {
+                device_id: body.u64()?,
+                duration_s: body.f32()?,
+                frequency: body.f32()?,
+                amplitude: body.f32()?,
+            },
+            4 => Self::Present {
+                timestamp_ns: body.u64()?,
+                offset_ns: body.u64()?,
+            },
+            _ => return Err(invalid(format!("unknown driver message tag {tag}"))),
+        };
+        Ok(Some(msg))
+    }
+}
+
+fn write_frame(w: &mut impl Write, tag: u8, body: &[u8]) -> io::Result<()> {
+    if body.len() > MAX_MESSAGE_BYTES {
+        return Err(invalid(format!(
+            "message of {} bytes too large",
+            body.len()
+        )));
+    }
+    let mut header = [0u8; 5];
+    header[0] = tag;
+    header[1..].copy_from_slice(&(body.len() as u32).to_le_bytes());
+    w.write_all(&header)?;
+    w.write_all(body)
+}
+
+fn read_frame(r: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut header = [0u8; 5];
+    match r.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_MESSAGE_BYTES {
+        return Err(invalid(format!("message of {len} bytes too large")));
+    }
+    let mut body = vec![0u8; len];
+    r.read_exact(&mut body)?;
+    Ok(Some((header[0], body)))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, v: f32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_motion(out: &mut Vec<u8>, motion: &DeviceMotion) {
+    let pose = &motion.pose;
+    for v in pose
+        .position
+        .iter()
+        .chain(&pose.orientation)
+        .chain(&motion.linear_velocity)
+        .chain(&motion.angular_velocity)
+    {
+        put_f32(out, *v);
+    }
+}
+
+struct Body<'a>(&'a [u8]);
+
+impl Body<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(invalid("truncated bridge message".to_string()));
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().expect("split at N"))
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    fn vec3(&mut self) -> io::Result<[f32; 3]> {
+        Ok([self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    fn motion(&mut self) -> io::Result<DeviceMotion> {
+        let position = self.vec3()?;
+        let orientation = [self.f32()?, self.f32()?, self.f32()?, self.f32()?];
+        Ok(DeviceMotion {
+            pose: Pose {
+                position,
+                orientation,
+            },
+            linear_velocity: self.vec3()?,
+            angular_velocity: self.vec3()?,
+        })
+    }
+
+    fn rest(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip_over_a_stream() {
+        let motion = DeviceMotion {
+            pose: Pose {
+                position: [0.1, 1.6, -0.2],
+                orientation: [0.0, 0.6, 0.0, 0.8],
+            },
+            linear_velocity: [0.5, 0.0, 0.0],
+            angular_velocity: [0.0, 1.0, 0.0],
+        };
+        let host = vec![
+            HostMessage::Refresh { refresh_hz: 90.0 },
+            HostMessage::Tracking {
+                timestamp_ns: 42,
+                head: motion,
+            },
+            HostMessage::Controller { hand: 1, motion },
+            HostMessage::Button {
+                path_id: path_to_id("/user/hand/right/input/a/click"),
+                value: ButtonValue::Binary(true),
+            },
+            HostMessage::Button {
+                path_id: 7,
+                value: ButtonValue::Scalar(0.25),
+            },
+            HostMessage::EncoderParams {
+                bitrate_bps: 40_000_000,
+                framerate: 72.0,
+            },
+            HostMessage::RequestIdr,
+        ];
+        let driver = vec![
+            DriverMessage::VideoConfig {
+                codec: VideoCodec::Hevc,
+                nals: vec![0, 0, 0, 1, 0x40],
+            },
+            DriverMessage::Video {
+                timestamp_ns: 42,
+                keyframe: true,
+                data: vec![1, 2, 3],
+            },
+            DriverMessage::Haptics {
+                device_id: path_to_id(LEFT_HAND_PATH),
+                duration_s: 0.1,
+                frequency: 160.0,
+                amplitude: 0.5,
+            },
+            DriverMessage::Present {
+                timestamp_ns: 42,
+                offset_ns: 3_000_000,
+            },
+        ];
+
+        let mut wire = Vec::new();
+        for msg in &host {
+            msg.write_to(&mut wire).unwrap();
+        }
+        let mut reader = wire.as_slice();
+        for msg in &host {
+            assert_eq!(
+                HostMessage::read_from(&mut reader).unwrap().as_ref(),
+                Some(msg)
+            );
+        }
+        assert_eq!(HostMessage::read_from(&mut reader).unwrap(), None);
+
+        let mut wire = Vec::new();
+        for msg in &driver {
+            msg.write_to(&mut wire).unwrap();
+        }
+        let mut reader = wire.as_slice();
+        for msg in &driver {
+            assert_eq!(
+                DriverMessage::read_from(&mut reader).unwrap().as_ref(),
+                Some(msg)
+            );
+        }
+
+        // A message cut off mid-body is an error, not a clean close.
+        let mut truncated = &wire[..wire.len() - 1];
+        for _ in 0..driver.len() - 1 {
+            DriverMessage::read_from(&mut truncated).unwrap();
+        }
+        assert!(DriverMessage::read_from(&mut truncated).is_err());
+    }
+
+    #[test]
+    fn controller_inputs_map_to_touch_paths() {
+        assert_eq!(
+            controller_input_path(0, ControllerInput::Button(0)).as_deref(),
+            Some("/user/hand/left/input/x/click")
+        );
+        assert_eq!(
+            controller_input_path(1, ControllerInput::Axis(2)).as_deref(),
+            Some("/user/hand/right/input/trigger/value")
+        );
+        assert_eq!(controller_input_path(2, ControllerInput::Axis(0)), None);
+        assert_ne!(path_to_id(LEFT_HAND_PATH), path_to_id(RIGHT_HAND_PATH));
+    }
+}
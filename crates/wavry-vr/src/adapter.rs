@@ -2,8 +2,8 @@ use std::sync::Arc;
 
 use crate::{
     types::{
-        EncoderControl, GamepadInput, HandPose, NetworkStats, Pose, StreamConfig, VideoFrame,
-        VrTiming,
+        EncoderControl, FoveationHint, GamepadInput, HandPose, HapticFeedback, NetworkStats, Pose,
+        StereoMode, StreamConfig, VideoFrame, VrTiming,
     },
     VrResult,
 };
@@ -15,6 +15,11 @@ pub trait VrAdapterCallbacks: Send + Sync {
     fn on_hand_pose_update(&self, hand_pose: HandPose, timestamp_us: u64);
     fn on_vr_timing(&self, timing: VrTiming);
     fn on_gamepad_input(&self, input: GamepadInput);
+    fn on_foveation_update(&self, hint: FoveationHint);
+    fn on_haptic_feedback(&self, haptic: HapticFeedback);
+    /// The display side changed mid-session; the host should pick a new
+    /// stereo layout and frame rate and restart its encoder.
+    fn on_renegotiate(&self, _stereo_modes: Vec<StereoMode>, _refresh_hz: Option<u32>) {}
 }
 
 pub trait VrAdapter: Send {
@@ -24,7 +29,12 @@ pub trait VrAdapter: Send {
     // Wavry -> ALVR (frame submission)
     fn submit_video(&mut self, frame: VideoFrame) -> VrResult<()>;
     fn submit_pose(&mut self, pose: Pose, timestamp_us: u64) -> VrResult<()>;
+    fn submit_haptic(&mut self, haptic: HapticFeedback) -> VrResult<()>;
     fn configure_stream(&mut self, config: StreamConfig);
+    /// Stereo layouts the display side can present, most preferred first.
+    fn stereo_modes(&self) -> Vec<StereoMode>;
+    /// Display refresh rate, once the runtime has reported it.
+    fn refresh_hz(&self) -> Option<u32>;
 
     // Wavry -> ALVR (transport/encoder signals)
     fn on_network_stats(&mut self, stats: NetworkStats);
@@ -1,17 +1,27 @@
 #![forbid(unsafe_code)]
 
 pub mod adapter;
+pub mod manager;
+pub mod pacing;
+pub mod prediction;
 pub mod status;
 pub mod types;
 
 pub use adapter::{VrAdapter, VrAdapterCallbacks};
+pub use manager::VrAdapterManager;
+pub use pacing::{PhaseEstimator, DEFAULT_REFRESH_HZ, PACING_MARGIN_US};
+pub use prediction::{
+    extrapolate_pose, relative_pose, reproject_view, PosePredictor, PredictionConfig,
+};
 pub use status::{pcvr_status, set_pcvr_status};
 pub use types::{
-    EncoderControl, GamepadAxis, GamepadButton, GamepadInput, NetworkStats, Pose, PoseVelocity,
-    StreamConfig, VideoCodec, VideoFrame, VrTiming,
+    EncoderControl, Eye, EyeView, Fov, FoveationHint, GamepadAxis, GamepadButton, GamepadInput,
+    HapticFeedback, NetworkStats, OverlayConfig, Pose, PoseVelocity, StereoMode, StreamConfig,
+    VideoCodec, VideoFrame, VrTiming,
 };
 
 use thiserror::Error;
+use wavry_common::error::ErrorCode;
 
 #[derive(Debug, Error)]
 pub enum VrError {
@@ -21,4 +31,13 @@ pub enum VrError {
     Adapter(String),
 }
 
+impl From<&VrError> for ErrorCode {
+    fn from(err: &VrError) -> Self {
+        match err {
+            VrError::Unavailable(_) => ErrorCode::DeviceUnavailable,
+            VrError::Adapter(_) => ErrorCode::Internal,
+        }
+    }
+}
+
 pub type VrResult<T> = Result<T, VrError>;
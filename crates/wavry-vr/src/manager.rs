@@ -0,0 +1,285 @@
+//! Switching VR adapters during a live session.
+//!
+//! The manager takes the adapter's place in the client. Swapping in another
+//! adapter, or restarting the current one after the headset reconnects, keeps
+//! the callbacks and the negotiated stream, then asks the host to renegotiate
+//! so its encoder restarts on a keyframe the new decoder can start from.
+
+use std::sync::Arc;
+
+use crate::{
+    adapter::{VrAdapter, VrAdapterCallbacks},
+    types::{
+        EncoderControl, HapticFeedback, NetworkStats, Pose, StereoMode, StreamConfig, VideoFrame,
+    },
+    VrResult,
+};
+
+pub struct VrAdapterManager {
+    adapter: Box<dyn VrAdapter>,
+    /// Set while the session is running.
+    callbacks: Option<Arc<dyn VrAdapterCallbacks>>,
+    stream: Option<StreamConfig>,
+}
+
+impl VrAdapterManager {
+    pub fn new(adapter: Box<dyn VrAdapter>) -> Self {
+        Self {
+            adapter,
+            callbacks: None,
+            stream: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.callbacks.is_some()
+    }
+
+    /// Replaces the active adapter. If `adapter` fails to start, the previous
+    /// one is restarted and kept.
+    pub fn swap(&mut self, mut adapter: Box<dyn VrAdapter>) -> VrResult<()> {
+        let Some(cb) = self.callbacks.clone() else {
+            self.adapter = adapter;
+            return Ok(());
+        };
+        self.adapter.stop();
+        if let Err(err) = adapter.start(Arc::clone(&cb)) {
+            if self.adapter.start(Arc::clone(&cb)).is_ok() {
+                self.resume(&cb);
+            } else {
+                self.callbacks = None;
+            }
+            return Err(err);
+        }
+        self.adapter = adapter;
+        self.resume(&cb);
+        Ok(())
+    }
+
+    /// Stops and starts the current adapter, e.g. after the headset reconnected.
+    pub fn restart(&mut self) -> VrResult<()> {
+        let Some(cb) = self.callbacks.clone() else {
+            return Ok(());
+        };
+        self.adapter.stop();
+        if let Err(err) = self.adapter.start(Arc::clone(&cb)) {
+            self.callbacks = None;
+            return Err(err);
+        }
+        self.resume(&cb);
+        Ok(())
+    }
+
+    /// The old stream keeps the new adapter showing frames until the host answers.
+    fn resume(&mut self, cb: &Arc<dyn VrAdapterCallbacks>) {
+        if let Some(config) = self.stream {
+            self.adapter.configure_stream(config);
+        }
+        cb.on_renegotiate(self.adapter.stereo_modes(), self.adapter.refresh_hz());
+    }
+}
+
+impl VrAdapter for VrAdapterManager {
+    fn start(&mut self, cb: Arc<dyn VrAdapterCallbacks>) -> VrResult<()> {
+        self.adapter.start(Arc::clone(&cb))?;
+        self.callbacks = Some(cb);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.adapter.stop();
+        self.callbacks = None;
+        self.stream = None;
+    }
+
+    fn submit_video(&mut self, frame: VideoFrame) -> VrResult<()> {
+        self.adapter.submit_video(frame)
+    }
+
+    fn submit_pose(&mut self, pose: Pose, timestamp_us: u64) -> VrResult<()> {
+        self.adapter.submit_pose(pose, timestamp_us)
+    }
+
+    fn submit_haptic(&mut self, haptic: HapticFeedback) -> VrResult<()> {
+        self.adapter.submit_haptic(haptic)
+    }
+
+    fn configure_stream(&mut self, config: StreamConfig) {
+        self.stream = Some(config);
+        self.adapter.configure_stream(config);
+    }
+
+    fn stereo_modes(&self) -> Vec<StereoMode> {
+        self.adapter.stereo_modes()
+    }
+
+    fn refresh_hz(&self) -> Option<u32> {
+        self.adapter.refresh_hz()
+    }
+
+    fn on_network_stats(&mut self, stats: NetworkStats) {
+        self.adapter.on_network_stats(stats);
+    }
+
+    fn on_encoder_control(&mut self, control: EncoderControl) {
+        self.adapter.on_encoder_control(control);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FoveationHint, GamepadInput, HandPose, VideoCodec, VrTiming};
+    use crate::VrError;
+    use std::sync::Mutex;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct FakeAdapter {
+        name: &'static str,
+        fail_start: bool,
+        modes: Vec<StereoMode>,
+        log: Log,
+    }
+
+    impl FakeAdapter {
+        fn boxed(name: &'static str, modes: Vec<StereoMode>, log: &Log) -> Box<Self> {
+            Box::new(Self {
+                name,
+                fail_start: false,
+                modes,
+                log: Arc::clone(log),
+            })
+        }
+
+        fn record(&self, event: impl std::fmt::Display) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", self.name, event));
+        }
+    }
+
+    impl VrAdapter for FakeAdapter {
+        fn start(&mut self, _cb: Arc<dyn VrAdapterCallbacks>) -> VrResult<()> {
+            if self.fail_start {
+                return Err(VrError::Unavailable("no headset".into()));
+            }
+            self.record("start");
+            Ok(())
+        }
+        fn stop(&mut self) {
+            self.record("stop");
+        }
+        fn submit_video(&mut self, _frame: VideoFrame) -> VrResult<()> {
+            Ok(())
+        }
+        fn submit_pose(&mut self, _pose: Pose, _timestamp_us: u64) -> VrResult<()> {
+            Ok(())
+        }
+        fn submit_haptic(&mut self, _haptic: HapticFeedback) -> VrResult<()> {
+            Ok(())
+        }
+        fn configure_stream(&mut self, config: StreamConfig) {
+            self.record(format_args!("configure {}x{}", config.width, config.height));
+        }
+        fn stereo_modes(&self) -> Vec<StereoMode> {
+            self.modes.clone()
+        }
+        fn refresh_hz(&self) -> Option<u32> {
+            Some(72)
+        }
+        fn on_network_stats(&mut self, _stats: NetworkStats) {}
+        fn on_encoder_control(&mut self, _control: EncoderControl) {}
+    }
+
+    struct FakeCallbacks {
+        log: Log,
+    }
+
+    impl VrAdapterCallbacks for FakeCallbacks {
+        fn on_video_frame(&self, _frame: VideoFrame, _timestamp_us: u64, _frame_id: u64) {}
+        fn on_pose_update(&self, _pose: Pose, _timestamp_us: u64) {}
+        fn on_hand_pose_update(&self, _hand_pose: HandPose, _timestamp_us: u64) {}
+        fn on_vr_timing(&self, _timing: VrTiming) {}
+        fn on_gamepad_input(&self, _input: GamepadInput) {}
+        fn on_foveation_update(&self, _hint: FoveationHint) {}
+        fn on_haptic_feedback(&self, _haptic: HapticFeedback) {}
+        fn on_renegotiate(&self, stereo_modes: Vec<StereoMode>, refresh_hz: Option<u32>) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("renegotiate {:?} {:?}", stereo_modes, refresh_hz));
+        }
+    }
+
+    fn running_manager(log: &Log) -> VrAdapterManager {
+        let mut manager =
+            VrAdapterManager::new(FakeAdapter::boxed("alvr", vec![StereoMode::Mono], log));
+        manager
+            .start(Arc::new(FakeCallbacks {
+                log: Arc::clone(log),
+            }))
+            .unwrap();
+        manager.configure_stream(StreamConfig {
+            codec: VideoCodec::Hevc,
+            width: 3840,
+            height: 1920,
+            stereo: StereoMode::Mono,
+            refresh_hz: 90,
+        });
+        log.lock().unwrap().clear();
+        manager
+    }
+
+    #[test]
+    fn swap_moves_stream_to_new_adapter_and_renegotiates() {
+        let log = Log::default();
+        let mut manager = running_manager(&log);
+
+        let openxr = FakeAdapter::boxed("openxr", vec![StereoMode::SideBySide], &log);
+        manager.swap(openxr).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "alvr stop",
+                "openxr start",
+                "openxr configure 3840x1920",
+                "renegotiate [SideBySide] Some(72)",
+            ]
+        );
+
+        log.lock().unwrap().clear();
+        manager.restart().unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "openxr stop",
+                "openxr start",
+                "openxr configure 3840x1920",
+                "renegotiate [SideBySide] Some(72)",
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_swap_restores_previous_adapter() {
+        let log = Log::default();
+        let mut manager = running_manager(&log);
+
+        let mut broken = FakeAdapter::boxed("openxr", vec![StereoMode::SideBySide], &log);
+        broken.fail_start = true;
+        assert!(manager.swap(broken).is_err());
+        assert!(manager.is_running());
+        assert_eq!(manager.stereo_modes(), [StereoMode::Mono]);
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "alvr stop",
+                "alvr start",
+                "alvr configure 3840x1920",
+                "renegotiate [Mono] Some(72)",
+            ]
+        );
+    }
+}
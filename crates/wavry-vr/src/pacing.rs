@@ -0,0 +1,75 @@
+//! Headset-side vsync phase measurement for VR frame pacing.
+//!
+//! The headset reports how far frame arrivals sit from its compositor's latch
+//! point in [`VrTiming::vsync_offset_us`](crate::types::VrTiming), and the host
+//! shifts its encoder schedule until frames land just ahead of vsync.
+
+/// Refresh rate assumed until the runtime reports the real one.
+pub const DEFAULT_REFRESH_HZ: u32 = 90;
+/// How long before the latch point a frame should ideally arrive.
+pub const PACING_MARGIN_US: u64 = 2_000;
+const PHASE_SMOOTHING: f32 = 0.2;
+
+/// Smoothed phase error of frame arrivals relative to the compositor latch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseEstimator {
+    error_us: Option<f32>,
+}
+
+impl PhaseEstimator {
+    /// `age_us` is how long a new frame waited before the compositor took it.
+    pub fn observe(&mut self, age_us: u64, period_us: u64) {
+        if period_us == 0 {
+            return;
+        }
+        let margin = PACING_MARGIN_US.min(period_us / 4);
+        let error = wrap_phase(age_us as i64 - margin as i64, period_us) as f32;
+        self.error_us = Some(match self.error_us {
+            Some(prev) => prev + (error - prev) * PHASE_SMOOTHING,
+            None => error,
+        });
+    }
+
+    /// Positive when frames arrive earlier than needed and the host should
+    /// start them later; negative when they just miss the latch.
+    pub fn error_us(&self) -> i64 {
+        self.error_us.map_or(0, |error| error.round() as i64)
+    }
+
+    pub fn reset(&mut self) {
+        self.error_us = None;
+    }
+}
+
+/// Map a phase difference into `(-period/2, period/2]`.
+fn wrap_phase(error_us: i64, period_us: u64) -> i64 {
+    let period = period_us as i64;
+    let wrapped = error_us.rem_euclid(period);
+    if wrapped > period / 2 {
+        wrapped - period
+    } else {
+        wrapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn early_and_late_arrivals_have_opposite_signs() {
+        let period_us = 11_111;
+
+        let mut early = PhaseEstimator::default();
+        early.observe(6_000, period_us);
+        assert_eq!(early.error_us(), 4_000);
+
+        // Missing the latch by 1 ms means waiting almost a full period.
+        let mut late = PhaseEstimator::default();
+        late.observe(period_us - 1_000, period_us);
+        assert_eq!(late.error_us(), -3_000);
+
+        late.observe(PACING_MARGIN_US, period_us);
+        assert_eq!(late.error_us(), -2_400);
+    }
+}
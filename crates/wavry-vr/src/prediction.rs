@@ -0,0 +1,366 @@
+//! Head/controller pose extrapolation to hide streaming latency.
+
+use crate::types::{Pose, PoseVelocity};
+
+/// Tuning for [`PosePredictor`].
+#[derive(Debug, Clone, Copy)]
+pub struct PredictionConfig {
+    /// Longest extrapolation; beyond this errors grow faster than latency hurts.
+    pub max_horizon_us: u64,
+    /// Weight of the newest velocity estimate (1.0 disables smoothing).
+    pub smoothing: f32,
+    /// Include the linear acceleration term in position extrapolation.
+    pub use_acceleration: bool,
+}
+
+impl Default for PredictionConfig {
+    fn default() -> Self {
+        Self {
+            max_horizon_us: 100_000,
+            smoothing: 0.5,
+            use_acceleration: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    pose: Pose,
+    timestamp_us: u64,
+}
+
+/// Velocity/acceleration model of one tracked device.
+#[derive(Debug, Clone)]
+pub struct PosePredictor {
+    config: PredictionConfig,
+    last: Option<Sample>,
+    velocity: Option<PoseVelocity>,
+    linear_acceleration: [f32; 3],
+}
+
+impl PosePredictor {
+    pub fn new(config: PredictionConfig) -> Self {
+        Self {
+            config,
+            last: None,
+            velocity: None,
+            linear_acceleration: [0.0; 3],
+        }
+    }
+
+    pub fn config(&self) -> PredictionConfig {
+        self.config
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.velocity = None;
+        self.linear_acceleration = [0.0; 3];
+    }
+
+    /// Smoothed velocity estimate, zero until two samples have been seen.
+    pub fn velocity(&self) -> PoseVelocity {
+        self.velocity.unwrap_or_default()
+    }
+
+    /// Record a pose sample and derive velocity from the previous one.
+    pub fn observe(&mut self, pose: Pose, timestamp_us: u64) {
+        let measured = match self.last {
+            Some(last) if timestamp_us > last.timestamp_us => {
+                let dt = (timestamp_us - last.timestamp_us) as f32 / 1_000_000.0;
+                Some(PoseVelocity {
+                    linear: scale(sub(pose.position, last.pose.position), 1.0 / dt),
+                    angular: angular_velocity(last.pose.orientation, pose.orientation, dt),
+                })
+            }
+            Some(last) if timestamp_us < last.timestamp_us => return,
+            _ => None,
+        };
+        self.record(pose, measured, timestamp_us);
+    }
+
+    /// Record a pose together with a runtime-reported velocity.
+    pub fn observe_with_velocity(&mut self, pose: Pose, velocity: PoseVelocity, timestamp_us: u64) {
+        if self
+            .last
+            .is_some_and(|last| timestamp_us < last.timestamp_us)
+        {
+            return;
+        }
+        self.record(pose, Some(velocity), timestamp_us);
+    }
+
+    fn record(&mut self, pose: Pose, measured: Option<PoseVelocity>, timestamp_us: u64) {
+        if let (Some(measured), Some(last)) = (measured, self.last) {
+            let alpha = self.config.smoothing.clamp(0.0, 1.0);
+            match self.velocity {
+                Some(previous) if timestamp_us > last.timestamp_us => {
+                    let dt = (timestamp_us - last.timestamp_us) as f32 / 1_000_000.0;
+                    let velocity = PoseVelocity {
+                        linear: lerp(previous.linear, measured.linear, alpha),
+                        angular: lerp(previous.angular, measured.angular, alpha),
+                    };
+                    let acceleration = scale(sub(velocity.linear, previous.linear), 1.0 / dt);
+                    self.linear_acceleration = lerp(self.linear_acceleration, acceleration, alpha);
+                    self.velocity = Some(velocity);
+                }
+                Some(_) => {}
+                None => self.velocity = Some(measured),
+            }
+        } else if measured.is_some() {
+            self.velocity = measured;
+        }
+        self.last = Some(Sample { pose, timestamp_us });
+    }
+
+    /// Extrapolate the latest pose to `display_time_us`, capped at the horizon.
+    pub fn predict(&self, display_time_us: u64) -> Option<Pose> {
+        let last = self.last?;
+        let ahead_us = display_time_us
+            .saturating_sub(last.timestamp_us)
+            .min(self.config.max_horizon_us);
+        let acceleration = if self.config.use_acceleration {
+            self.linear_acceleration
+        } else {
+            [0.0; 3]
+        };
+        Some(extrapolate_pose(
+            &last.pose,
+            &self.velocity(),
+            acceleration,
+            ahead_us as f32 / 1_000_000.0,
+        ))
+    }
+}
+
+impl Default for PosePredictor {
+    fn default() -> Self {
+        Self::new(PredictionConfig::default())
+    }
+}
+
+/// Constant-acceleration position and constant-rate rotation over `dt_s`.
+/// Angular velocity is expressed in the reference space, as OpenXR reports it.
+pub fn extrapolate_pose(
+    pose: &Pose,
+    velocity: &PoseVelocity,
+    linear_acceleration: [f32; 3],
+    dt_s: f32,
+) -> Pose {
+    let mut position = pose.position;
+    for (axis, p) in position.iter_mut().enumerate() {
+        *p += velocity.linear[axis] * dt_s + 0.5 * linear_acceleration[axis] * dt_s * dt_s;
+    }
+    let rotation = scale(velocity.angular, dt_s);
+    let angle = norm(rotation);
+    let orientation = if angle < 1e-6 {
+        pose.orientation
+    } else {
+        let axis = scale(rotation, 1.0 / angle);
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        let delta = [axis[0] * sin, axis[1] * sin, axis[2] * sin, cos];
+        normalize_quat(quat_mul(delta, pose.orientation))
+    };
+    Pose {
+        position,
+        orientation,
+    }
+}
+
+/// `to` expressed in the frame of `from`: the late-latch delta when `from` is
+/// the pose an image was rendered with and `to` the pose it is displayed at.
+pub fn relative_pose(from: &Pose, to: &Pose) -> Pose {
+    let inverse = quat_conjugate(from.orientation);
+    Pose {
+        position: rotate(inverse, sub(to.position, from.position)),
+        orientation: normalize_quat(quat_mul(inverse, to.orientation)),
+    }
+}
+
+/// Where to submit `view` (a display-time eye pose) so the compositor warps an
+/// image rendered at `render_head` to the viewer's `display_head`.
+pub fn reproject_view(render_head: &Pose, display_head: &Pose, view: &Pose) -> Pose {
+    let offset = relative_pose(display_head, view);
+    let rotated = rotate(render_head.orientation, offset.position);
+    Pose {
+        position: [
+            render_head.position[0] + rotated[0],
+            render_head.position[1] + rotated[1],
+            render_head.position[2] + rotated[2],
+        ],
+        orientation: normalize_quat(quat_mul(render_head.orientation, offset.orientation)),
+    }
+}
+
+/// Reference-space angular velocity rotating `from` into `to` over `dt_s`.
+fn angular_velocity(from: [f32; 4], to: [f32; 4], dt_s: f32) -> [f32; 3] {
+    let mut delta = quat_mul(to, quat_conjugate(from));
+    if delta[3] < 0.0 {
+        delta = delta.map(|c| -c);
+    }
+    let sin_half = norm([delta[0], delta[1], delta[2]]);
+    if sin_half < 1e-6 {
+        return [0.0; 3];
+    }
+    let angle = 2.0 * sin_half.atan2(delta[3]);
+    scale([delta[0], delta[1], delta[2]], angle / (sin_half * dt_s))
+}
+
+fn quat_mul(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    let [ax, ay, az, aw] = a;
+    let [bx, by, bz, bw] = b;
+    [
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz,
+    ]
+}
+
+fn quat_conjugate(q: [f32; 4]) -> [f32; 4] {
+    [-q[0], -q[1], -q[2], q[3]]
+}
+
+fn normalize_quat(q: [f32; 4]) -> [f32; 4] {
+    let len = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if len < 1e-6 {
+        [0.0, 0.0, 0.0, 1.0]
+    } else {
+        q.map(|c| c / len)
+    }
+}
+
+fn rotate(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let r = quat_mul(quat_mul(q, [v[0], v[1], v[2], 0.0]), quat_conjugate(q));
+    [r[0], r[1], r[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(v: [f32; 3], s: f32) -> [f32; 3] {
+    v.map(|c| c * s)
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn norm(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaw(angle: f32) -> [f32; 4] {
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        [0.0, sin, 0.0, cos]
+    }
+
+    #[test]
+    fn linear_motion_is_extrapolated_and_capped() {
+        let mut predictor = PosePredictor::new(PredictionConfig {
+            smoothing: 1.0,
+            use_acceleration: false,
+            ..PredictionConfig::default()
+        });
+        for step in 0..3u64 {
+            predictor.observe(
+                Pose {
+                    position: [step as f32 * 0.01, 0.0, 0.0],
+                    orientation: [0.0, 0.0, 0.0, 1.0],
+                },
+                step * 10_000,
+            );
+        }
+        // 1 m/s, 20 ms past the last sample at x = 0.02.
+        let predicted = predictor.predict(40_000).unwrap();
+        assert!((predicted.position[0] - 0.04).abs() < 1e-4);
+
+        let capped = predictor.predict(10_000_000).unwrap();
+        assert!((capped.position[0] - 0.12).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rotation_rate_is_recovered_from_orientation_deltas() {
+        let mut predictor = PosePredictor::new(PredictionConfig {
+            smoothing: 1.0,
+            ..PredictionConfig::default()
+        });
+        predictor.observe(
+            Pose {
+                position: [0.0; 3],
+                orientation: yaw(0.0),
+            },
+            0,
+        );
+        predictor.observe(
+            Pose {
+                position: [0.0; 3],
+                orientation: yaw(0.1),
+            },
+            100_000,
+        );
+        assert!((predictor.velocity().angular[1] - 1.0).abs() < 1e-3);
+
+        let predicted = predictor.predict(200_000).unwrap();
+        let expected = yaw(0.2);
+        for (got, want) in predicted.orientation.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-3, "{predicted:?}");
+        }
+    }
+
+    #[test]
+    fn reprojection_moves_the_eye_with_the_render_pose() {
+        let render = Pose {
+            position: [0.0, 1.6, 0.0],
+            orientation: yaw(0.0),
+        };
+        let display = Pose {
+            position: [0.0, 1.6, 0.0],
+            orientation: yaw(0.2),
+        };
+        let latch = relative_pose(&render, &display);
+        let expected = yaw(0.2);
+        for (got, want) in latch.orientation.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-5, "{latch:?}");
+        }
+
+        // The left eye sits 32 mm to the left of the displayed head.
+        let eye = Pose {
+            position: [-0.032 * 0.2f32.cos(), 1.6, 0.032 * 0.2f32.sin()],
+            orientation: yaw(0.2),
+        };
+        let submitted = reproject_view(&render, &display, &eye);
+        assert!(
+            (submitted.position[0] + 0.032).abs() < 1e-5,
+            "{submitted:?}"
+        );
+        assert!(submitted.position[2].abs() < 1e-5, "{submitted:?}");
+        for (got, want) in submitted.orientation.iter().zip(yaw(0.0)) {
+            assert!((got - want).abs() < 1e-5, "{submitted:?}");
+        }
+    }
+
+    #[test]
+    fn stale_samples_are_ignored() {
+        let mut predictor = PosePredictor::default();
+        assert!(predictor.predict(0).is_none());
+        predictor.observe(Pose::default(), 20_000);
+        predictor.observe(
+            Pose {
+                position: [1.0, 0.0, 0.0],
+                orientation: [0.0, 0.0, 0.0, 1.0],
+            },
+            10_000,
+        );
+        assert_eq!(predictor.predict(20_000).unwrap().position, [0.0; 3]);
+    }
+}
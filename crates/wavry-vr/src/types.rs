@@ -7,14 +7,54 @@ pub enum VideoCodec {
     Av1,
 }
 
+/// How a stream carries both eyes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StereoMode {
+    /// Not negotiated; side-by-side is inferred from a 2:1 or wider frame.
+    #[default]
+    Auto,
+    Mono,
+    /// Both eyes packed left|right into one frame.
+    SideBySide,
+    /// One stream per eye; frames carry [`VideoFrame::eye`].
+    DualStream,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// Field of view half-angles in radians (left/down negative), as OpenXR reports them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Fov {
+    pub angle_left: f32,
+    pub angle_right: f32,
+    pub angle_up: f32,
+    pub angle_down: f32,
+}
+
+/// Pose and field of view an eye image was rendered with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EyeView {
+    pub pose: Pose,
+    pub fov: Fov,
+}
+
+/// `width`/`height` are per stream: the packed frame for side-by-side, one eye
+/// for dual-stream.
 #[derive(Debug, Clone, Copy)]
 pub struct StreamConfig {
     pub codec: VideoCodec,
     pub width: u16,
     pub height: u16,
+    pub stereo: StereoMode,
+    /// Negotiated frame rate; 0 when the host did not state one.
+    pub refresh_hz: u32,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Pose {
     pub position: [f32; 3],
     pub orientation: [f32; 4],
@@ -34,10 +74,55 @@ pub struct HandPose {
     pub angular_velocity: [f32; 3],
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Eye-tracked gaze for foveated encoding. `gaze` is in normalized per-eye
+/// image coordinates (0,0 top-left); `radius` is a fraction of the eye width.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoveationHint {
+    pub timestamp_us: u64,
+    pub gaze: [f32; 2],
+    pub radius: f32,
+}
+
+/// Controller vibration. `frequency_hz` of 0 leaves the choice to the runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HapticFeedback {
+    pub controller_id: u32, // 0 = left, 1 = right
+    pub amplitude: f32,
+    pub frequency_hz: f32,
+    pub duration_us: u64,
+}
+
+/// Floating virtual screen for viewing a flat stream inside another VR app.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayConfig {
+    /// Screen width in meters; the height follows the stream's aspect ratio.
+    pub width_m: f32,
+    /// Distance from the viewer to the center of the screen in meters.
+    pub distance_m: f32,
+    /// 0.0 is flat; 1.0 bends the screen around the viewer at `distance_m`.
+    pub curvature: f32,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            width_m: 2.0,
+            distance_m: 2.0,
+            curvature: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
 pub struct VrTiming {
     pub refresh_hz: f32,
     pub vsync_offset_us: i64,
+    /// When the runtime expects the frame being composed to reach the display.
+    pub predicted_display_time_us: u64,
+    /// Head pose the displayed image was rendered with, if known.
+    pub render_pose: Option<Pose>,
+    /// Head motion from `render_pose` to the display pose, corrected by reprojection.
+    pub late_latch: Option<Pose>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -78,4 +163,8 @@ pub struct VideoFrame {
     pub frame_id: u64,
     pub keyframe: bool,
     pub data: Bytes,
+    /// Which eye this frame shows under [`StereoMode::DualStream`].
+    pub eye: Option<Eye>,
+    /// Render view reported by the host, if any.
+    pub view: Option<EyeView>,
 }
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, bail, Result};
+use pasetors::claims::{Claims, ClaimsValidationRules};
+use pasetors::keys::{AsymmetricKeyPair, AsymmetricPublicKey, AsymmetricSecretKey, Generate};
+use pasetors::token::{Public, UntrustedToken};
+use pasetors::{public, version4::V4};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// Audience every web session token is minted for.
+pub const WEB_TOKEN_AUDIENCE: &str = "wavry-web";
+/// Tokens only need to survive the browser's connect round trip.
+pub const DEFAULT_WEB_TOKEN_TTL: Duration = Duration::from_secs(60);
+/// Query parameter carrying the token on the WebTransport CONNECT URL.
+pub const WEB_TOKEN_QUERY_PARAM: &str = "token";
+
+/// Claims carried by a verified web session token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSessionClaims {
+    pub username: String,
+    /// SHA-256 of the gateway session token the browser was issued for.
+    pub session_binding: String,
+}
+
+impl WebSessionClaims {
+    /// Whether `session_token` is the gateway session this token was minted for.
+    pub fn is_bound_to(&self, session_token: &str) -> bool {
+        self.session_binding == session_binding(session_token)
+    }
+}
+
+/// Hex SHA-256 of a gateway session token; the raw token never enters a URL.
+pub fn session_binding(session_token: &str) -> String {
+    hex::encode(Sha256::digest(session_token.as_bytes()))
+}
+
+/// Mints short-lived PASETO v4 tokens that let a browser open a web session.
+pub struct WebTokenSigner {
+    secret: AsymmetricSecretKey<V4>,
+    public: AsymmetricPublicKey<V4>,
+}
+
+impl WebTokenSigner {
+    /// Load a signer from the 64-byte Ed25519 secret key (seed followed by public key).
+    pub fn from_secret_bytes(bytes: &[u8]) -> Result<Self> {
+        let secret = AsymmetricSecretKey::<V4>::from(bytes)
+            .map_err(|e| anyhow!("invalid web token key: {e}"))?;
+        let public = AsymmetricPublicKey::<V4>::from(&bytes[32..])
+            .map_err(|e| anyhow!("invalid web token key: {e}"))?;
+        Ok(Self { secret, public })
+    }
+
+    /// Ephemeral signer, for deployments where minting and verification share a process.
+    pub fn generate() -> Result<Self> {
+        let pair = AsymmetricKeyPair::<V4>::generate()
+            .map_err(|e| anyhow!("failed to generate web token key: {e}"))?;
+        Ok(Self {
+            secret: pair.secret,
+            public: pair.public,
+        })
+    }
+
+    pub fn verifier(&self) -> WebTokenVerifier {
+        WebTokenVerifier::new(self.public.clone())
+    }
+
+    pub fn mint(&self, username: &str, session_token: &str, ttl: Duration) -> Result<String> {
+        let mut claims = Claims::new().map_err(|e| anyhow!("pasetors error: {e}"))?;
+        claims
+            .set_expires_in(&ttl)
+            .map_err(|e| anyhow!("pasetors error: {e}"))?;
+        claims
+            .audience(WEB_TOKEN_AUDIENCE)
+            .map_err(|e| anyhow!("pasetors error: {e}"))?;
+        claims
+            .subject(username)
+            .map_err(|e| anyhow!("pasetors error: {e}"))?;
+        claims
+            .add_additional("sid", session_binding(session_token))
+            .map_err(|e| anyhow!("pasetors error: {e}"))?;
+        public::sign(&self.secret, &claims, None, None).map_err(|e| anyhow!("pasetors error: {e}"))
+    }
+}
+
+/// Checks web session tokens against the gateway's public key.
+#[derive(Clone)]
+pub struct WebTokenVerifier {
+    key: AsymmetricPublicKey<V4>,
+}
+
+impl WebTokenVerifier {
+    pub fn new(key: AsymmetricPublicKey<V4>) -> Self {
+        Self { key }
+    }
+
+    pub fn from_public_bytes(bytes: &[u8]) -> Result<Self> {
+        let key = AsymmetricPublicKey::<V4>::from(bytes)
+            .map_err(|e| anyhow!("invalid web token public key: {e}"))?;
+        Ok(Self::new(key))
+    }
+
+    /// Verify signature, audience and expiry, returning the bound claims.
+    pub fn verify(&self, token: &str) -> Result<WebSessionClaims> {
+        let untrusted = UntrustedToken::<Public, V4>::try_from(token)
+            .map_err(|_| anyhow!("malformed web token"))?;
+        let mut rules = ClaimsValidationRules::new();
+        rules.validate_audience_with(WEB_TOKEN_AUDIENCE);
+        let trusted = public::verify(&self.key, &untrusted, &rules, None, None)
+            .map_err(|_| anyhow!("web token rejected"))?;
+        let claims = trusted
+            .payload_claims()
+            .ok_or_else(|| anyhow!("web token has no claims"))?;
+
+        let username = claim_str(claims, "sub")?;
+        let session_binding = claim_str(claims, "sid")?;
+        if username.is_empty() || session_binding.is_empty() {
+            bail!("web token missing subject or session binding");
+        }
+        Ok(WebSessionClaims {
+            username,
+            session_binding,
+        })
+    }
+
+    /// Pull the token out of a CONNECT path such as `/?token=v4.public...`.
+    pub fn verify_path(&self, path: &str) -> Result<WebSessionClaims> {
+        let token = token_from_path(path).ok_or_else(|| anyhow!("web token missing"))?;
+        self.verify(token)
+    }
+}
+
+fn claim_str(claims: &Claims, name: &str) -> Result<String> {
+    claims
+        .get_claim(name)
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("web token missing `{name}` claim"))
+}
+
+/// PASETO tokens are URL-safe base64 with dots, so no percent-decoding is needed.
+pub fn token_from_path(path: &str) -> Option<&str> {
+    let (_, query) = path.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == WEB_TOKEN_QUERY_PARAM)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minted_token_verifies_and_binds_session() {
+        let signer = WebTokenSigner::generate().unwrap();
+        let token = signer
+            .mint("alice", "session-abc", DEFAULT_WEB_TOKEN_TTL)
+            .unwrap();
+
+        let claims = signer
+            .verifier()
+            .verify_path(&format!("/?room=1&token={token}"))
+            .unwrap();
+        assert_eq!(claims.username, "alice");
+        assert!(claims.is_bound_to("session-abc"));
+        assert!(!claims.is_bound_to("session-xyz"));
+    }
+
+    #[test]
+    fn rejects_foreign_signer_and_tampering() {
+        let signer = WebTokenSigner::generate().unwrap();
+        let other = WebTokenSigner::generate().unwrap();
+        let token = signer.mint("alice", "s", DEFAULT_WEB_TOKEN_TTL).unwrap();
+
+        assert!(other.verifier().verify(&token).is_err());
+        let mut tampered = token.clone();
+        tampered.pop();
+        tampered.push(if token.ends_with('A') { 'B' } else { 'A' });
+        assert!(signer.verifier().verify(&tampered).is_err());
+        assert!(signer.verifier().verify_path("/").is_err());
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let signer = WebTokenSigner::generate().unwrap();
+        let token = signer.mint("alice", "s", Duration::ZERO).unwrap();
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(signer.verifier().verify(&token).is_err());
+    }
+}
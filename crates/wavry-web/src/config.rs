@@ -1,3 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::webrtc::DEFAULT_STUN_SERVER;
+
+/// One entry of a browser `RTCConfiguration.iceServers` list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebIceServer {
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct WebGatewayConfig {
     pub public_base_url: String,
@@ -6,6 +20,11 @@ pub struct WebGatewayConfig {
     pub webtransport_url: String,
     pub webtransport_bind_addr: String,
     pub webrtc_signaling_url: String,
+    pub stun_urls: Vec<String>,
+    /// TURN relays offered to browsers; only advertised alongside credentials.
+    pub turn_urls: Vec<String>,
+    /// Master endpoint minting ephemeral TURN credentials.
+    pub turn_credentials_url: String,
 }
 
 impl WebGatewayConfig {
@@ -18,6 +37,11 @@ impl WebGatewayConfig {
         let webtransport_url = format!("https://app.{base}/wt");
         let webtransport_bind_addr = "0.0.0.0:4444".to_string();
         let webrtc_signaling_url = format!("https://app.{base}/webrtc");
+        let turn_urls = vec![
+            format!("turn:turn.{base}:3478"),
+            format!("turns:turn.{base}:5349"),
+        ];
+        let turn_credentials_url = format!("{auth_base_url}/v1/turn/credentials");
         Self {
             public_base_url,
             auth_base_url,
@@ -25,7 +49,33 @@ impl WebGatewayConfig {
             webtransport_url,
             webtransport_bind_addr,
             webrtc_signaling_url,
+            stun_urls: vec![DEFAULT_STUN_SERVER.to_string()],
+            turn_urls,
+            turn_credentials_url,
+        }
+    }
+
+    /// ICE servers for a browser. `turn_credentials` is the `(username, credential)`
+    /// pair minted by the master; without it TURN entries are left out.
+    pub fn ice_servers(&self, turn_credentials: Option<(&str, &str)>) -> Vec<WebIceServer> {
+        let mut servers = Vec::new();
+        if !self.stun_urls.is_empty() {
+            servers.push(WebIceServer {
+                urls: self.stun_urls.clone(),
+                username: None,
+                credential: None,
+            });
         }
+        if let Some((username, credential)) = turn_credentials {
+            if !self.turn_urls.is_empty() {
+                servers.push(WebIceServer {
+                    urls: self.turn_urls.clone(),
+                    username: Some(username.to_string()),
+                    credential: Some(credential.to_string()),
+                });
+            }
+        }
+        servers
     }
 }
 
@@ -34,3 +84,28 @@ impl Default for WebGatewayConfig {
         Self::from_domain("wavry.dev")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turn_servers_require_credentials() {
+        let config = WebGatewayConfig::from_domain("example.com");
+        assert_eq!(
+            config.turn_credentials_url,
+            "https://auth.example.com/v1/turn/credentials"
+        );
+
+        let stun_only = config.ice_servers(None);
+        assert_eq!(stun_only.len(), 1);
+        assert_eq!(stun_only[0].username, None);
+
+        let servers = config.ice_servers(Some(("1700003600:alice", "secret")));
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[1].urls[0], "turn:turn.example.com:3478");
+        assert_eq!(servers[1].credential.as_deref(), Some("secret"));
+        let json = serde_json::to_string(&stun_only[0]).unwrap();
+        assert!(!json.contains("username"), "{json}");
+    }
+}
@@ -0,0 +1,139 @@
+use crate::protocol::StatsReport;
+use rift_core::cc::{DeltaCC, DeltaConfig, DeltaState};
+
+/// Loss above which the sender backs off regardless of delay (GCC's 10% rule).
+const HIGH_LOSS: f32 = 0.10;
+/// Loss below which a previous loss-based cap is relaxed.
+const LOW_LOSS: f32 = 0.02;
+const LOSS_CAP_RECOVERY: f32 = 1.08;
+
+/// Adapts a browser viewer's bitrate from WebRTC/WebTransport feedback.
+///
+/// Delay and jitter drive the same DELTA controller native sessions use; REMB
+/// and heavy loss add caps on top, since browsers report loss far more
+/// directly than queueing delay.
+pub struct WebCongestionController {
+    cc: DeltaCC,
+    min_bitrate_kbps: u32,
+    last_rtt_us: Option<u64>,
+    remb_cap_kbps: Option<u32>,
+    loss_cap_kbps: Option<u32>,
+}
+
+impl WebCongestionController {
+    pub fn new(initial_bitrate_kbps: u32, initial_fps: u32) -> Self {
+        Self::with_config(DeltaConfig::default(), initial_bitrate_kbps, initial_fps)
+    }
+
+    pub fn with_config(config: DeltaConfig, initial_bitrate_kbps: u32, initial_fps: u32) -> Self {
+        let min_bitrate_kbps = config.min_bitrate_kbps;
+        Self {
+            cc: DeltaCC::new(config, initial_bitrate_kbps, initial_fps),
+            min_bitrate_kbps,
+            last_rtt_us: None,
+            remb_cap_kbps: None,
+            loss_cap_kbps: None,
+        }
+    }
+
+    /// Feed an RTCP receiver report. Reports without an RTT (no sender report
+    /// echoed yet) reuse the last known RTT so loss still counts.
+    pub fn on_receiver_report(&mut self, rtt_ms: Option<u32>, fraction_lost: f32, jitter_ms: f32) {
+        if let Some(rtt_ms) = rtt_ms {
+            self.last_rtt_us = Some(rtt_ms as u64 * 1000);
+        }
+        let Some(rtt_us) = self.last_rtt_us else {
+            self.on_loss(fraction_lost);
+            return;
+        };
+        let jitter_us = (jitter_ms.max(0.0) * 1000.0) as u32;
+        self.cc
+            .on_rtt_sample(rtt_us, fraction_lost.clamp(0.0, 1.0), jitter_us);
+        self.on_loss(fraction_lost);
+    }
+
+    /// Apply a REMB estimate from the browser as an upper bound.
+    pub fn on_remb(&mut self, bitrate_bps: f32) {
+        if bitrate_bps.is_finite() && bitrate_bps > 0.0 {
+            self.remb_cap_kbps = Some(((bitrate_bps / 1000.0) as u32).max(self.min_bitrate_kbps));
+        }
+    }
+
+    /// Stats a WebTransport client measured itself (datagram RTT and loss).
+    pub fn on_stats_report(&mut self, report: &StatsReport) {
+        self.on_receiver_report(Some(report.rtt_ms), report.packet_loss, report.jitter_ms);
+    }
+
+    fn on_loss(&mut self, fraction_lost: f32) {
+        if !fraction_lost.is_finite() {
+            return;
+        }
+        let current = self.target_bitrate_kbps();
+        if fraction_lost > HIGH_LOSS {
+            let reduced = (current as f32 * (1.0 - 0.5 * fraction_lost.min(1.0))) as u32;
+            self.loss_cap_kbps = Some(reduced.max(self.min_bitrate_kbps));
+        } else if fraction_lost < LOW_LOSS {
+            self.loss_cap_kbps = self
+                .loss_cap_kbps
+                .map(|cap| (cap as f32 * LOSS_CAP_RECOVERY) as u32)
+                .filter(|cap| *cap < self.cc.target_bitrate_kbps());
+        }
+    }
+
+    pub fn target_bitrate_kbps(&self) -> u32 {
+        [self.remb_cap_kbps, self.loss_cap_kbps]
+            .into_iter()
+            .flatten()
+            .fold(self.cc.target_bitrate_kbps(), u32::min)
+    }
+
+    pub fn target_fps(&self) -> u32 {
+        self.cc.target_fps()
+    }
+
+    pub fn state(&self) -> DeltaState {
+        self.cc.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remb_caps_target_bitrate() {
+        let mut controller = WebCongestionController::new(8_000, 60);
+        controller.on_remb(3_000_000.0);
+        assert_eq!(controller.target_bitrate_kbps(), 3_000);
+        controller.on_remb(f32::NAN);
+        assert_eq!(controller.target_bitrate_kbps(), 3_000);
+    }
+
+    #[test]
+    fn heavy_loss_backs_off_then_recovers() {
+        let mut controller = WebCongestionController::new(10_000, 60);
+        controller.on_receiver_report(Some(30), 0.3, 2.0);
+        let backed_off = controller.target_bitrate_kbps();
+        assert!(backed_off < 10_000, "target {backed_off}");
+
+        for _ in 0..50 {
+            controller.on_receiver_report(None, 0.0, 2.0);
+        }
+        assert!(controller.target_bitrate_kbps() > backed_off);
+    }
+
+    #[test]
+    fn webtransport_stats_feed_delta() {
+        let mut controller = WebCongestionController::new(5_000, 60);
+        controller.on_stats_report(&StatsReport {
+            rtt_ms: 20,
+            jitter_ms: 1.0,
+            packet_loss: 0.0,
+            bitrate_kbps: 5_000,
+            encoder_delay_ms: 0.0,
+            decoder_delay_ms: None,
+        });
+        assert_eq!(controller.state(), DeltaState::Stable);
+        assert!(controller.target_bitrate_kbps() >= 5_000);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::protocol::{ControlMessage, InputDatagram};
+use rift_core::input::{apply_gamepad_deadzone, sanitize_input_event};
+use rift_core::input_message::Event;
+use rift_core::keymap::PhysicalKey;
+use rift_core::{GamepadAxis, GamepadButton, GamepadMessage, InputMessage};
+use std::collections::HashMap;
+
+/// Browser wheel events report ~100 px per notch.
+const WHEEL_PIXELS_PER_NOTCH: f32 = 100.0;
+const GAMEPAD_BUTTON_COUNT: u32 = 16;
+const DEFAULT_GAMEPAD_DEADZONE: f32 = 0.1;
+
+/// Translates browser input into RIFT input messages for the host injector.
+///
+/// Pointer-locked browsers only report relative motion, so the translator keeps
+/// a normalized cursor position scaled by the viewport the client last reported.
+#[derive(Debug, Clone)]
+pub struct InputTranslator {
+    width: u16,
+    height: u16,
+    cursor: (f32, f32),
+    gamepad_deadzone: f32,
+    gamepad_buttons: HashMap<u8, u16>,
+}
+
+impl Default for InputTranslator {
+    fn default() -> Self {
+        Self::new(1920, 1080)
+    }
+}
+
+impl InputTranslator {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+            cursor: (0.5, 0.5),
+            gamepad_deadzone: DEFAULT_GAMEPAD_DEADZONE,
+            gamepad_buttons: HashMap::new(),
+        }
+    }
+
+    pub fn set_viewport(&mut self, width: u16, height: u16) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+    }
+
+    pub fn set_gamepad_deadzone(&mut self, deadzone: f32) {
+        self.gamepad_deadzone = deadzone;
+    }
+
+    pub fn translate_datagram(&mut self, datagram: &InputDatagram) -> Option<InputMessage> {
+        let (timestamp_us, event) = match *datagram {
+            InputDatagram::MouseMove {
+                dx,
+                dy,
+                timestamp_us,
+            } => {
+                let x = (self.cursor.0 + dx as f32 / self.width as f32).clamp(0.0, 1.0);
+                let y = (self.cursor.1 + dy as f32 / self.height as f32).clamp(0.0, 1.0);
+                self.cursor = (x, y);
+                (
+                    timestamp_us,
+                    Event::MouseMove(rift_core::MouseMove { x, y }),
+                )
+            }
+            InputDatagram::Scroll {
+                dx,
+                dy,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                // DOM deltaY is positive when scrolling down; RIFT uses wheel-up positive.
+                Event::Scroll(rift_core::Scroll {
+                    dx: dx as f32 / WHEEL_PIXELS_PER_NOTCH,
+                    dy: -(dy as f32) / WHEEL_PIXELS_PER_NOTCH,
+                }),
+            ),
+            InputDatagram::Analog {
+                axis,
+                value,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::Gamepad(GamepadMessage {
+                    gamepad_id: 0,
+                    axes: vec![GamepadAxis {
+                        axis: axis as u32,
+                        value: apply_gamepad_deadzone(value, self.gamepad_deadzone),
+                    }],
+                    buttons: Vec::new(),
+                }),
+            ),
+            InputDatagram::Gamepad {
+                gamepad_id,
+                buttons,
+                axes,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::Gamepad(self.gamepad_snapshot(gamepad_id, buttons, axes)),
+            ),
+        };
+        sanitized(timestamp_us, event)
+    }
+
+    /// Translate input carried on the reliable control stream. `Resize`
+    /// updates the viewport used for relative motion and yields no event.
+    pub fn translate_control(&mut self, message: &ControlMessage) -> Option<InputMessage> {
+        let (timestamp_us, event) = match *message {
+            // Keys the protocol has no code for are dropped.
+            ControlMessage::Key {
+                ref code,
+                pressed,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::Key(rift_core::Key {
+                    keycode: PhysicalKey::from_dom_code(code)?.protocol(),
+                    pressed,
+                }),
+            ),
+            // DOM buttons are 0-based (left, middle, right); RIFT numbers them from 1.
+            ControlMessage::MouseButton {
+                button,
+                pressed,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::MouseButton(rift_core::MouseButton {
+                    button: button as u32 + 1,
+                    pressed,
+                }),
+            ),
+            ControlMessage::Text {
+                ref text,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::Text(rift_core::TextInput { text: text.clone() }),
+            ),
+            ControlMessage::GamepadButton {
+                gamepad_id,
+                button,
+                pressed,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::Gamepad(GamepadMessage {
+                    gamepad_id: gamepad_id as u32,
+                    axes: Vec::new(),
+                    buttons: vec![GamepadButton {
+                        button: button as u32,
+                        pressed,
+                    }],
+                }),
+            ),
+            ControlMessage::GamepadAxis {
+                gamepad_id,
+                axis,
+                value,
+                timestamp_us,
+            } => (
+                timestamp_us,
+                Event::Gamepad(GamepadMessage {
+                    gamepad_id: gamepad_id as u32,
+                    axes: vec![GamepadAxis {
+                        axis: axis as u32,
+                        value: apply_gamepad_deadzone(value, self.gamepad_deadzone),
+                    }],
+                    buttons: Vec::new(),
+                }),
+            ),
+            ControlMessage::Resize { width, height } => {
+                self.set_viewport(width, height);
+                return None;
+            }
+            _ => return None,
+        };
+        sanitized(timestamp_us, event)
+    }
+
+    /// Browsers poll full gamepad state; only buttons that changed are forwarded.
+    fn gamepad_snapshot(&mut self, gamepad_id: u8, buttons: u16, axes: [i16; 4]) -> GamepadMessage {
+        let previous = self.gamepad_buttons.insert(gamepad_id, buttons);
+        let changed = previous.map_or(buttons, |prev| prev ^ buttons);
+        let buttons = (0..GAMEPAD_BUTTON_COUNT)
+            .filter(|bit| changed & (1 << bit) != 0)
+            .map(|bit| GamepadButton {
+                button: bit,
+                pressed: buttons & (1 << bit) != 0,
+            })
+            .collect();
+        let axes = axes
+            .iter()
+            .enumerate()
+            .map(|(axis, &raw)| {
+                let value = raw as f32 / i16::MAX as f32;
+                GamepadAxis {
+                    axis: axis as u32,
+                    value: apply_gamepad_deadzone(value, self.gamepad_deadzone),
+                }
+            })
+            .collect();
+        GamepadMessage {
+            gamepad_id: gamepad_id as u32,
+            axes,
+            buttons,
+        }
+    }
+}
+
+fn sanitized(timestamp_us: u64, event: Event) -> Option<InputMessage> {
+    sanitize_input_event(event).map(|event| InputMessage {
+        timestamp_us,
+        event: Some(event),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_motion_accumulates_into_normalized_cursor() {
+        let mut translator = InputTranslator::new(1000, 500);
+        let msg = translator
+            .translate_datagram(&InputDatagram::MouseMove {
+                dx: 100,
+                dy: -50,
+                timestamp_us: 7,
+            })
+            .unwrap();
+        assert_eq!(msg.timestamp_us, 7);
+        assert_eq!(
+            msg.event,
+            Some(Event::MouseMove(rift_core::MouseMove { x: 0.6, y: 0.4 }))
+        );
+
+        let msg = translator
+            .translate_datagram(&InputDatagram::MouseMove {
+                dx: i16::MAX,
+                dy: 0,
+                timestamp_us: 8,
+            })
+            .unwrap();
+        assert_eq!(
+            msg.event,
+            Some(Event::MouseMove(rift_core::MouseMove { x: 1.0, y: 0.4 }))
+        );
+    }
+
+    #[test]
+    fn keys_are_sent_as_evdev_codes() {
+        let mut translator = InputTranslator::default();
+        let key = |code: &str| ControlMessage::Key {
+            code: code.into(),
+            pressed: true,
+            timestamp_us: 3,
+        };
+        let msg = translator.translate_control(&key("KeyA")).unwrap();
+        assert_eq!(
+            msg.event,
+            Some(Event::Key(rift_core::Key {
+                keycode: 30,
+                pressed: true
+            }))
+        );
+        let msg = translator.translate_control(&key("ArrowUp")).unwrap();
+        assert_eq!(
+            msg.event,
+            Some(Event::Key(rift_core::Key {
+                keycode: 103,
+                pressed: true
+            }))
+        );
+        assert!(translator.translate_control(&key("Unidentified")).is_none());
+    }
+
+    #[test]
+    fn gamepad_snapshots_forward_only_changed_buttons() {
+        let mut translator = InputTranslator::default();
+        let first = translator
+            .translate_datagram(&InputDatagram::Gamepad {
+                gamepad_id: 1,
+                buttons: 0b01,
+                axes: [i16::MAX, 0, 0, 0],
+                timestamp_us: 1,
+            })
+            .unwrap();
+        let Some(Event::Gamepad(first)) = first.event else {
+            panic!("expected gamepad event");
+        };
+        assert_eq!(first.buttons.len(), 1);
+        assert_eq!(first.axes[0].value, 1.0);
+
+        let second = translator
+            .translate_datagram(&InputDatagram::Gamepad {
+                gamepad_id: 1,
+                buttons: 0b10,
+                axes: [0; 4],
+                timestamp_us: 2,
+            })
+            .unwrap();
+        let Some(Event::Gamepad(second)) = second.event else {
+            panic!("expected gamepad event");
+        };
+        let changed: Vec<(u32, bool)> = second
+            .buttons
+            .iter()
+            .map(|b| (b.button, b.pressed))
+            .collect();
+        assert_eq!(changed, vec![(0, false), (1, true)]);
+    }
+
+    #[test]
+    fn control_messages_map_to_rift_events() {
+        let mut translator = InputTranslator::default();
+        let button = translator
+            .translate_control(&ControlMessage::MouseButton {
+                button: 2,
+                pressed: true,
+                timestamp_us: 3,
+            })
+            .unwrap();
+        assert_eq!(
+            button.event,
+            Some(Event::MouseButton(rift_core::MouseButton {
+                button: 3,
+                pressed: true
+            }))
+        );
+
+        let text = translator
+            .translate_control(&ControlMessage::Text {
+                text: "ß\u{8}".to_string(),
+                timestamp_us: 4,
+            })
+            .unwrap();
+        assert_eq!(
+            text.event,
+            Some(Event::Text(rift_core::TextInput {
+                text: "ß".to_string()
+            }))
+        );
+
+        assert!(translator
+            .translate_control(&ControlMessage::GamepadAxis {
+                gamepad_id: 0,
+                axis: 0,
+                value: f32::NAN,
+                timestamp_us: 4,
+            })
+            .is_none());
+        assert!(translator
+            .translate_control(&ControlMessage::Resize {
+                width: 800,
+                height: 600
+            })
+            .is_none());
+    }
+}
@@ -1,19 +1,44 @@
 //! Web client transport layer: WebTransport (control/input) + WebRTC (media).
 //!
-//! This crate intentionally avoids binding to a specific WebTransport/WebRTC runtime.
-//! It provides protocol types and server integration points.
+//! Protocol types and server integration points are always available; the
+//! `webtransport-runtime` feature adds a QUIC-backed WebTransport server and
+//! `webrtc-runtime` adds peer connections for browser media.
 
+mod auth;
 mod config;
+mod congestion;
+mod input;
+#[cfg(feature = "webrtc-runtime")]
+mod media;
 mod protocol;
+mod stats;
+mod transfer;
 mod webrtc;
 mod webtransport;
 
-pub use config::WebGatewayConfig;
+pub use auth::{
+    session_binding, token_from_path, WebSessionClaims, WebTokenSigner, WebTokenVerifier,
+    DEFAULT_WEB_TOKEN_TTL, WEB_TOKEN_AUDIENCE, WEB_TOKEN_QUERY_PARAM,
+};
+pub use config::{WebGatewayConfig, WebIceServer};
+pub use congestion::WebCongestionController;
+pub use input::InputTranslator;
+#[cfg(feature = "webrtc-runtime")]
+pub use media::{ReceiverFeedback, RtpVideoSender, WebVideoCodec};
 pub use protocol::{
     ControlMessage, ControlStreamFrame, InputDatagram, StatsReport, WebClientCapabilities,
-    WebControlResponse,
+    WebControlResponse, INPUT_PROTOCOL_VERSION,
+};
+pub use stats::WEB_STATS_INTERVAL;
+pub use transfer::{
+    control_to_rift, rift_to_response, WebFileChunk, WebFileHeader, WebFileState, WebFileStatus,
+    CONTROL_CHANNEL_LABEL,
+};
+#[cfg(feature = "webrtc-runtime")]
+pub use webrtc::{parse_ice_candidate, WebRtcHost};
+pub use webrtc::{
+    LocalIceCandidate, WebRtcPeer, WebRtcSignaling, WebRtcStartParams, DEFAULT_STUN_SERVER,
 };
-pub use webrtc::{WebRtcPeer, WebRtcSignaling, WebRtcStartParams};
 pub use webtransport::{WebTransportServer, WebTransportSession, WebTransportSessionHandler};
 
 /// High-level skeleton for a unified host gateway.
@@ -22,37 +47,41 @@ pub use webtransport::{WebTransportServer, WebTransportSession, WebTransportSess
 /// alongside it, using shared control-plane state.
 pub struct WebGateway {
     config: WebGatewayConfig,
+    token_verifier: Option<WebTokenVerifier>,
 }
 
 impl WebGateway {
     pub fn new(config: WebGatewayConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            token_verifier: None,
+        }
     }
 
-    /// Start WebTransport control plane and WebRTC signaling.
+    /// Only admit browsers presenting a token signed by the matching gateway key.
+    pub fn with_token_verifier(mut self, verifier: WebTokenVerifier) -> Self {
+        self.token_verifier = Some(verifier);
+        self
+    }
+
+    /// Start the WebTransport control plane, dispatching sessions to `handler`.
     ///
     /// The native RIFT server remains in its own binary/crate and is not modified here.
-    pub async fn start(self) -> anyhow::Result<()> {
+    pub async fn start(self, handler: impl WebTransportSessionHandler) -> anyhow::Result<()> {
         #[cfg(feature = "webtransport-runtime")]
         {
-            struct NoopHandler;
-            impl WebTransportSessionHandler for NoopHandler {
-                fn on_session_started(&self, _session: WebTransportSession) {}
-
-                fn on_input_datagram(&self, _session_id: &str, _datagram: InputDatagram) {}
-
-                fn on_control_frame(&self, _session_id: &str, _frame: ControlStreamFrame) {}
+            let mut wt = WebTransportServer::bind(&self.config.webtransport_bind_addr).await?;
+            if let Some(verifier) = self.token_verifier {
+                wt = wt.with_token_verifier(verifier);
             }
-
-            let wt = WebTransportServer::bind(&self.config.webtransport_bind_addr).await?;
-            wt.run(NoopHandler).await
+            wt.run(handler).await
         }
 
         #[cfg(not(feature = "webtransport-runtime"))]
         {
-            let _ = self.config;
+            let _ = (self.config, self.token_verifier, handler);
             Err(anyhow::anyhow!(
-                "WebGateway::start is a skeleton; enable `webtransport-runtime` or integrate your own runtime"
+                "WebGateway::start requires the `webtransport-runtime` feature"
             ))
         }
     }
@@ -0,0 +1,201 @@
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use webrtc::api::media_engine::{MIME_TYPE_AV1, MIME_TYPE_H264};
+use webrtc::rtcp::reception_report::ReceptionReport;
+use webrtc::rtp::codecs::{av1::Av1Payloader, h264::H264Payloader};
+use webrtc::rtp::packetizer::{new_packetizer, Packetizer, Payloader};
+use webrtc::rtp::sequence::new_random_sequencer;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+use webrtc::track::track_local::TrackLocalWriter;
+
+/// RTP clock rate for video payloads.
+pub const VIDEO_CLOCK_RATE: u32 = 90_000;
+/// Keeps packets under typical tunnel MTUs once SRTP and TURN overhead is added.
+const RTP_MTU: usize = 1200;
+/// Seconds between the NTP (1900) and Unix (1970) epochs.
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Codecs the host encoder can hand to browsers without transcoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebVideoCodec {
+    H264,
+    Av1,
+}
+
+impl WebVideoCodec {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            WebVideoCodec::H264 => MIME_TYPE_H264,
+            WebVideoCodec::Av1 => MIME_TYPE_AV1,
+        }
+    }
+
+    fn payloader(self) -> Box<dyn Payloader + Send + Sync> {
+        match self {
+            WebVideoCodec::H264 => Box::<H264Payloader>::default(),
+            WebVideoCodec::Av1 => Box::<Av1Payloader>::default(),
+        }
+    }
+}
+
+/// Packetizes encoder output (Annex-B H264 or AV1 OBUs) onto an RTP track.
+///
+/// The track can be bound to many peer connections; each binding rewrites the
+/// SSRC and payload type it negotiated, and the interceptors attached to each
+/// connection emit sender reports for it.
+pub struct RtpVideoSender {
+    codec: WebVideoCodec,
+    track: Arc<TrackLocalStaticRTP>,
+    packetizer: Box<dyn Packetizer + Send + Sync>,
+    last_timestamp_us: Option<u64>,
+}
+
+impl RtpVideoSender {
+    pub fn new(codec: WebVideoCodec) -> Self {
+        let track = Arc::new(TrackLocalStaticRTP::new(
+            RTCRtpCodecCapability {
+                mime_type: codec.mime_type().to_string(),
+                clock_rate: VIDEO_CLOCK_RATE,
+                ..Default::default()
+            },
+            "video".to_string(),
+            "wavry".to_string(),
+        ));
+        // Payload type and SSRC are placeholders; the track rewrites them per binding.
+        let packetizer = Box::new(new_packetizer(
+            RTP_MTU,
+            0,
+            0,
+            codec.payloader(),
+            Box::new(new_random_sequencer()),
+            VIDEO_CLOCK_RATE,
+        ));
+        Self {
+            codec,
+            track,
+            packetizer,
+            last_timestamp_us: None,
+        }
+    }
+
+    pub fn codec(&self) -> WebVideoCodec {
+        self.codec
+    }
+
+    pub fn track(&self) -> &Arc<TrackLocalStaticRTP> {
+        &self.track
+    }
+
+    /// Send one encoded frame; returns the number of RTP packets written.
+    pub async fn write_frame(&mut self, data: &[u8], timestamp_us: u64) -> anyhow::Result<usize> {
+        if let Some(last) = self.last_timestamp_us {
+            self.packetizer
+                .skip_samples(rtp_ticks_between(last, timestamp_us));
+        }
+        self.last_timestamp_us = Some(timestamp_us);
+
+        let packets = self
+            .packetizer
+            .packetize(&Bytes::copy_from_slice(data), 0)?;
+        for packet in &packets {
+            self.track.write_rtp(packet).await?;
+        }
+        Ok(packets.len())
+    }
+}
+
+/// RTP ticks elapsed between two capture timestamps; clock steps backwards count as zero.
+pub fn rtp_ticks_between(previous_us: u64, current_us: u64) -> u32 {
+    let elapsed_us = current_us.saturating_sub(previous_us);
+    (elapsed_us * VIDEO_CLOCK_RATE as u64 / 1_000_000).min(u32::MAX as u64) as u32
+}
+
+/// Latest receiver-report view of a browser's incoming video.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ReceiverFeedback {
+    /// Fraction of packets lost since the previous report, 0.0..=1.0.
+    pub fraction_lost: f32,
+    pub total_lost: u32,
+    pub jitter_ms: f32,
+    /// Round trip derived from LSR/DLSR; `None` until a sender report was echoed.
+    pub rtt_ms: Option<u32>,
+}
+
+impl ReceiverFeedback {
+    pub fn from_report(report: &ReceptionReport, now: SystemTime) -> Self {
+        Self {
+            fraction_lost: report.fraction_lost as f32 / 256.0,
+            total_lost: report.total_lost,
+            jitter_ms: report.jitter as f32 * 1000.0 / VIDEO_CLOCK_RATE as f32,
+            rtt_ms: rtt_from_report(report.last_sender_report, report.delay, now),
+        }
+    }
+}
+
+/// Middle 32 bits of the NTP timestamp for `now`, as used by LSR/DLSR.
+pub fn compact_ntp(now: SystemTime) -> u32 {
+    let since_unix = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_unix.as_secs() + NTP_UNIX_OFFSET_SECS;
+    let frac = ((since_unix.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    (((secs & 0xFFFF) << 16) | (frac >> 16)) as u32
+}
+
+fn rtt_from_report(last_sender_report: u32, delay: u32, now: SystemTime) -> Option<u32> {
+    if last_sender_report == 0 {
+        return None;
+    }
+    let rtt = compact_ntp(now)
+        .wrapping_sub(last_sender_report)
+        .wrapping_sub(delay);
+    // Compact NTP is 1/65536 s; anything over a minute means the report was bogus.
+    let rtt_ms = (rtt as u64 * 1000) >> 16;
+    (rtt_ms < 60_000).then_some(rtt_ms as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn rtp_ticks_follow_capture_clock() {
+        assert_eq!(rtp_ticks_between(0, 16_667), 1500);
+        assert_eq!(rtp_ticks_between(1_000_000, 2_000_000), VIDEO_CLOCK_RATE);
+        assert_eq!(rtp_ticks_between(5_000, 1_000), 0);
+    }
+
+    #[test]
+    fn receiver_feedback_derives_rtt_from_lsr_and_dlsr() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let sent = now - Duration::from_millis(150);
+        let report = ReceptionReport {
+            fraction_lost: 64,
+            jitter: 900,
+            last_sender_report: compact_ntp(sent),
+            // The browser held the report for 50 ms before answering.
+            delay: 50 * 65_536 / 1000,
+            ..Default::default()
+        };
+
+        let feedback = ReceiverFeedback::from_report(&report, now);
+        assert_eq!(feedback.fraction_lost, 0.25);
+        assert_eq!(feedback.jitter_ms, 10.0);
+        let rtt = feedback.rtt_ms.unwrap();
+        assert!((99..=101).contains(&rtt), "rtt {rtt}");
+
+        let no_sr = ReceptionReport::default();
+        assert_eq!(ReceiverFeedback::from_report(&no_sr, now).rtt_ms, None);
+    }
+
+    #[tokio::test]
+    async fn write_frame_packetizes_h264_access_unit() {
+        let mut sender = RtpVideoSender::new(WebVideoCodec::H264);
+        let mut frame = vec![0, 0, 0, 1, 0x65];
+        frame.resize(3005, 0xAB);
+        // Unbound tracks accept writes and drop them.
+        let packets = sender.write_frame(&frame, 0).await.unwrap();
+        assert!(packets >= 3, "expected FU-A fragmentation, got {packets}");
+    }
+}
@@ -1,8 +1,14 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 
+use crate::config::WebIceServer;
+use crate::transfer::{WebFileChunk, WebFileHeader, WebFileStatus};
+
 pub const INPUT_PROTOCOL_VERSION: u8 = 1;
 
+/// Largest control frame accepted on a WebTransport stream.
+pub const MAX_CONTROL_FRAME_LEN: usize = 64 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebClientCapabilities {
     pub max_width: u16,
@@ -31,8 +37,9 @@ pub enum ControlMessage {
         bitrate_kbps: u32,
         fps: u16,
     },
+    /// `code` is the browser's `KeyboardEvent.code`, naming the physical key.
     Key {
-        keycode: u32,
+        code: String,
         pressed: bool,
         timestamp_us: u64,
     },
@@ -41,6 +48,11 @@ pub enum ControlMessage {
         pressed: bool,
         timestamp_us: u64,
     },
+    /// Committed text from `input`/`compositionend` events.
+    Text {
+        text: String,
+        timestamp_us: u64,
+    },
     GamepadButton {
         gamepad_id: u8,
         button: u16,
@@ -66,6 +78,12 @@ pub enum ControlMessage {
         candidate: String,
     },
     StatsRequest,
+    Clipboard {
+        text: String,
+    },
+    FileHeader(WebFileHeader),
+    FileChunk(WebFileChunk),
+    FileStatus(WebFileStatus),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -90,6 +108,16 @@ pub enum WebControlResponse {
         candidate: String,
     },
     Stats(StatsReport),
+    Clipboard {
+        text: String,
+    },
+    FileHeader(WebFileHeader),
+    FileChunk(WebFileChunk),
+    FileStatus(WebFileStatus),
+    /// Sent after `Connect`; TURN entries carry short-lived credentials.
+    IceServers {
+        ice_servers: Vec<WebIceServer>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -261,10 +289,146 @@ impl InputDatagram {
     }
 }
 
+/// A frame on the control stream. On the wire the payload sits under a key
+/// named after the frame type, e.g. `{"type":"control","control":{...}}`,
+/// since the messages carry a `type` tag of their own.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[serde(from = "WireFrame", into = "WireFrame")]
 pub enum ControlStreamFrame {
     Control(ControlMessage),
     Stats(StatsReport),
     Response(WebControlResponse),
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireFrame {
+    Control { control: ControlMessage },
+    Stats { stats: StatsReport },
+    Response { response: WebControlResponse },
+}
+
+impl From<WireFrame> for ControlStreamFrame {
+    fn from(frame: WireFrame) -> Self {
+        match frame {
+            WireFrame::Control { control } => Self::Control(control),
+            WireFrame::Stats { stats } => Self::Stats(stats),
+            WireFrame::Response { response } => Self::Response(response),
+        }
+    }
+}
+
+impl From<ControlStreamFrame> for WireFrame {
+    fn from(frame: ControlStreamFrame) -> Self {
+        match frame {
+            ControlStreamFrame::Control(control) => Self::Control { control },
+            ControlStreamFrame::Stats(stats) => Self::Stats { stats },
+            ControlStreamFrame::Response(response) => Self::Response { response },
+        }
+    }
+}
+
+impl ControlStreamFrame {
+    /// Encode for a long-lived stream: big-endian `u32` length, then JSON.
+    pub fn encode_framed(&self) -> anyhow::Result<Bytes> {
+        let json = serde_json::to_vec(self)?;
+        if json.len() > MAX_CONTROL_FRAME_LEN {
+            anyhow::bail!("control frame too large: {} bytes", json.len());
+        }
+        let mut buf = BytesMut::with_capacity(4 + json.len());
+        buf.put_u32(json.len() as u32);
+        buf.put_slice(&json);
+        Ok(buf.freeze())
+    }
+
+    /// Pop one complete frame from `buf`, leaving partial data in place.
+    pub fn decode_framed(buf: &mut BytesMut) -> anyhow::Result<Option<Self>> {
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        if len > MAX_CONTROL_FRAME_LEN {
+            anyhow::bail!("control frame too large: {} bytes", len);
+        }
+        if buf.len() < 4 + len {
+            return Ok(None);
+        }
+        buf.advance(4);
+        let payload = buf.split_to(len);
+        Ok(Some(serde_json::from_slice(&payload)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framed_control_frames_round_trip_across_partial_reads() {
+        let frame = ControlStreamFrame::Control(ControlMessage::Resize {
+            width: 1280,
+            height: 720,
+        });
+        let encoded = frame.encode_framed().unwrap();
+
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&encoded[..3]);
+        assert!(ControlStreamFrame::decode_framed(&mut buf)
+            .unwrap()
+            .is_none());
+        buf.extend_from_slice(&encoded[3..]);
+        buf.extend_from_slice(&encoded);
+
+        for _ in 0..2 {
+            let decoded = ControlStreamFrame::decode_framed(&mut buf)
+                .unwrap()
+                .unwrap();
+            assert!(matches!(
+                decoded,
+                ControlStreamFrame::Control(ControlMessage::Resize {
+                    width: 1280,
+                    height: 720
+                })
+            ));
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn control_frames_nest_the_message_under_its_frame_type() {
+        let json = r#"{"type":"control","control":{"type":"resize","width":640,"height":480}}"#;
+        let frame: ControlStreamFrame = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            frame,
+            ControlStreamFrame::Control(ControlMessage::Resize {
+                width: 640,
+                height: 480
+            })
+        ));
+        assert_eq!(serde_json::to_string(&frame).unwrap(), json);
+    }
+
+    #[test]
+    fn oversized_frame_length_is_rejected() {
+        let mut buf = BytesMut::new();
+        buf.put_u32(MAX_CONTROL_FRAME_LEN as u32 + 1);
+        assert!(ControlStreamFrame::decode_framed(&mut buf).is_err());
+    }
+
+    #[test]
+    fn input_datagram_round_trip() {
+        let datagram = InputDatagram::Gamepad {
+            gamepad_id: 1,
+            buttons: 0b101,
+            axes: [1, -2, 3, -4],
+            timestamp_us: 42,
+        };
+        match InputDatagram::decode(datagram.encode()) {
+            Some(InputDatagram::Gamepad { buttons, axes, .. }) => {
+                assert_eq!(buttons, 0b101);
+                assert_eq!(axes, [1, -2, 3, -4]);
+            }
+            other => panic!("unexpected datagram: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::protocol::StatsReport;
+use std::time::Duration;
+
+#[cfg(feature = "webrtc-runtime")]
+use crate::media::ReceiverFeedback;
+
+/// How often hosts and browsers exchange stats, matching native clients.
+pub const WEB_STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// Browsers report loss as a fraction; RIFT counters are rebuilt against this
+/// many packets so loss percentages survive the round trip.
+const SYNTHETIC_PACKETS_PER_REPORT: u32 = 10_000;
+
+impl StatsReport {
+    /// Express a browser report as the `StatsReport` a native client would send.
+    pub fn to_rift(&self, period_ms: u32) -> rift_core::StatsReport {
+        let loss = if self.packet_loss.is_finite() {
+            self.packet_loss.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let lost_packets = (loss * SYNTHETIC_PACKETS_PER_REPORT as f32).round() as u32;
+        rift_core::StatsReport {
+            period_ms,
+            received_packets: SYNTHETIC_PACKETS_PER_REPORT - lost_packets,
+            lost_packets,
+            rtt_us: self.rtt_ms as u64 * 1000,
+            jitter_us: (self.jitter_ms.max(0.0) * 1000.0) as u32,
+            latency: None,
+        }
+    }
+
+    /// Build a browser-facing report from RIFT counters and the host's send rate.
+    pub fn from_rift(report: &rift_core::StatsReport, bitrate_kbps: u32) -> Self {
+        let total = report.received_packets.saturating_add(report.lost_packets);
+        let packet_loss = if total == 0 {
+            0.0
+        } else {
+            report.lost_packets as f32 / total as f32
+        };
+        Self {
+            rtt_ms: (report.rtt_us / 1000).min(u32::MAX as u64) as u32,
+            jitter_ms: report.jitter_us as f32 / 1000.0,
+            packet_loss,
+            bitrate_kbps,
+            encoder_delay_ms: 0.0,
+            decoder_delay_ms: None,
+        }
+    }
+
+    /// Host-side view of a browser from its latest RTCP receiver report.
+    #[cfg(feature = "webrtc-runtime")]
+    pub fn from_feedback(feedback: &ReceiverFeedback, bitrate_kbps: u32) -> Self {
+        Self {
+            rtt_ms: feedback.rtt_ms.unwrap_or(0),
+            jitter_ms: feedback.jitter_ms,
+            packet_loss: feedback.fraction_lost,
+            bitrate_kbps,
+            encoder_delay_ms: 0.0,
+            decoder_delay_ms: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browser_report_round_trips_through_rift_counters() {
+        let report = StatsReport {
+            rtt_ms: 42,
+            jitter_ms: 3.5,
+            packet_loss: 0.025,
+            bitrate_kbps: 8_000,
+            encoder_delay_ms: 0.0,
+            decoder_delay_ms: Some(4.0),
+        };
+        let rift = report.to_rift(1000);
+        assert_eq!(rift.rtt_us, 42_000);
+        assert_eq!(rift.jitter_us, 3_500);
+        assert_eq!(rift.lost_packets, 250);
+        assert_eq!(rift.received_packets + rift.lost_packets, 10_000);
+
+        let back = StatsReport::from_rift(&rift, 8_000);
+        assert_eq!(back.rtt_ms, 42);
+        assert!((back.packet_loss - 0.025).abs() < 1e-6);
+        assert_eq!(back.jitter_ms, 3.5);
+    }
+
+    #[test]
+    fn invalid_loss_is_treated_as_none() {
+        let report = StatsReport {
+            rtt_ms: 0,
+            jitter_ms: -1.0,
+            packet_loss: f32::NAN,
+            bitrate_kbps: 0,
+            encoder_delay_ms: 0.0,
+            decoder_delay_ms: None,
+        };
+        let rift = report.to_rift(1000);
+        assert_eq!(rift.lost_packets, 0);
+        assert_eq!(rift.jitter_us, 0);
+        assert_eq!(
+            StatsReport::from_rift(&rift_core::StatsReport::default(), 0).packet_loss,
+            0.0
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::protocol::{ControlMessage, WebControlResponse};
+use rift_core::{control_message, file_status, media_message, message, MAX_CLIPBOARD_TEXT_BYTES};
+use serde::{Deserialize, Serialize};
+
+/// Label of the reliable, ordered data channel carrying JSON control frames.
+pub const CONTROL_CHANNEL_LABEL: &str = "control";
+
+/// Mirrors `rift_core::FileHeader`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebFileHeader {
+    #[serde(with = "file_id_string")]
+    pub file_id: u64,
+    pub filename: String,
+    pub file_size: u64,
+    pub checksum_sha256: String,
+    pub chunk_size: u32,
+    pub total_chunks: u32,
+}
+
+/// Mirrors `rift_core::FileChunk`; the payload travels as base64.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebFileChunk {
+    #[serde(with = "file_id_string")]
+    pub file_id: u64,
+    pub chunk_index: u32,
+    #[serde(with = "base64_payload")]
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebFileState {
+    Pending,
+    InProgress,
+    Complete,
+    Error,
+}
+
+/// Mirrors `rift_core::FileStatus`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebFileStatus {
+    #[serde(with = "file_id_string")]
+    pub file_id: u64,
+    pub status: WebFileState,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl From<rift_core::FileHeader> for WebFileHeader {
+    fn from(header: rift_core::FileHeader) -> Self {
+        Self {
+            file_id: header.file_id,
+            filename: header.filename,
+            file_size: header.file_size,
+            checksum_sha256: header.checksum_sha256,
+            chunk_size: header.chunk_size,
+            total_chunks: header.total_chunks,
+        }
+    }
+}
+
+impl From<WebFileHeader> for rift_core::FileHeader {
+    fn from(header: WebFileHeader) -> Self {
+        Self {
+            file_id: header.file_id,
+            filename: header.filename,
+            file_size: header.file_size,
+            checksum_sha256: header.checksum_sha256,
+            chunk_size: header.chunk_size,
+            total_chunks: header.total_chunks,
+        }
+    }
+}
+
+impl From<rift_core::FileChunk> for WebFileChunk {
+    fn from(chunk: rift_core::FileChunk) -> Self {
+        Self {
+            file_id: chunk.file_id,
+            chunk_index: chunk.chunk_index,
+            payload: chunk.payload,
+        }
+    }
+}
+
+impl From<WebFileChunk> for rift_core::FileChunk {
+    fn from(chunk: WebFileChunk) -> Self {
+        Self {
+            file_id: chunk.file_id,
+            chunk_index: chunk.chunk_index,
+            payload: chunk.payload,
+        }
+    }
+}
+
+impl From<file_status::Status> for WebFileState {
+    fn from(status: file_status::Status) -> Self {
+        match status {
+            file_status::Status::Pending => WebFileState::Pending,
+            file_status::Status::InProgress => WebFileState::InProgress,
+            file_status::Status::Complete => WebFileState::Complete,
+            file_status::Status::Error => WebFileState::Error,
+        }
+    }
+}
+
+impl From<WebFileState> for file_status::Status {
+    fn from(state: WebFileState) -> Self {
+        match state {
+            WebFileState::Pending => file_status::Status::Pending,
+            WebFileState::InProgress => file_status::Status::InProgress,
+            WebFileState::Complete => file_status::Status::Complete,
+            WebFileState::Error => file_status::Status::Error,
+        }
+    }
+}
+
+impl WebFileStatus {
+    /// `None` when the RIFT status carries an enum value this build does not know.
+    pub fn from_rift(status: rift_core::FileStatus) -> Option<Self> {
+        Some(Self {
+            file_id: status.file_id,
+            status: file_status::Status::try_from(status.status).ok()?.into(),
+            message: status.message,
+        })
+    }
+}
+
+impl From<WebFileStatus> for rift_core::FileStatus {
+    fn from(status: WebFileStatus) -> Self {
+        Self {
+            file_id: status.file_id,
+            status: file_status::Status::from(status.status) as i32,
+            message: status.message,
+        }
+    }
+}
+
+/// Map a browser clipboard/file message onto the RIFT message a native client
+/// would have sent. Non-transfer messages and oversized clipboard text yield `None`.
+pub fn control_to_rift(msg: ControlMessage) -> Option<message::Content> {
+    let control = match msg {
+        ControlMessage::Clipboard { text } if text.len() <= MAX_CLIPBOARD_TEXT_BYTES => {
+            control_message::Content::Clipboard(rift_core::ClipboardMessage { text })
+        }
+        ControlMessage::FileHeader(header) => control_message::Content::FileHeader(header.into()),
+        ControlMessage::FileStatus(status) => control_message::Content::FileStatus(status.into()),
+        ControlMessage::FileChunk(chunk) => {
+            return Some(message::Content::Media(rift_core::MediaMessage {
+                content: Some(media_message::Content::FileChunk(chunk.into())),
+            }))
+        }
+        _ => return None,
+    };
+    Some(message::Content::Control(rift_core::ControlMessage {
+        content: Some(control),
+    }))
+}
+
+/// Map a host clipboard/file message onto the response sent to browsers.
+pub fn rift_to_response(content: message::Content) -> Option<WebControlResponse> {
+    match content {
+        message::Content::Control(rift_core::ControlMessage {
+            content: Some(control),
+        }) => match control {
+            control_message::Content::Clipboard(clip) => (clip.text.len()
+                <= MAX_CLIPBOARD_TEXT_BYTES)
+                .then_some(WebControlResponse::Clipboard { text: clip.text }),
+            control_message::Content::FileHeader(header) => {
+                Some(WebControlResponse::FileHeader(header.into()))
+            }
+            control_message::Content::FileStatus(status) => {
+                WebFileStatus::from_rift(status).map(WebControlResponse::FileStatus)
+            }
+            _ => None,
+        },
+        message::Content::Media(rift_core::MediaMessage {
+            content: Some(media_message::Content::FileChunk(chunk)),
+        }) => Some(WebControlResponse::FileChunk(chunk.into())),
+        _ => None,
+    }
+}
+
+/// JavaScript numbers lose precision above 2^53, so file ids travel as strings.
+mod file_id_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(id)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+mod base64_payload {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(payload: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(payload))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ControlStreamFrame;
+
+    #[test]
+    fn file_chunk_json_keeps_large_ids_and_binary_payload() {
+        let chunk = WebFileChunk {
+            file_id: u64::MAX - 1,
+            chunk_index: 3,
+            payload: vec![0, 255, 7],
+        };
+        let json = serde_json::to_string(&ControlMessage::FileChunk(chunk.clone())).unwrap();
+        assert!(
+            json.contains("\"file_id\":\"18446744073709551614\""),
+            "{json}"
+        );
+        assert!(json.contains("\"payload\":\"AP8H\""), "{json}");
+
+        let decoded: ControlMessage = serde_json::from_str(&json).unwrap();
+        assert!(matches!(decoded, ControlMessage::FileChunk(c) if c == chunk));
+    }
+
+    #[test]
+    fn browser_transfer_messages_map_to_rift() {
+        let status = ControlMessage::FileStatus(WebFileStatus {
+            file_id: 9,
+            status: WebFileState::Complete,
+            message: String::new(),
+        });
+        let Some(message::Content::Control(rift_core::ControlMessage {
+            content: Some(control_message::Content::FileStatus(status)),
+        })) = control_to_rift(status)
+        else {
+            panic!("expected file status");
+        };
+        assert_eq!(status.status, file_status::Status::Complete as i32);
+
+        let oversized = ControlMessage::Clipboard {
+            text: "x".repeat(MAX_CLIPBOARD_TEXT_BYTES + 1),
+        };
+        assert!(control_to_rift(oversized).is_none());
+        assert!(control_to_rift(ControlMessage::StatsRequest).is_none());
+    }
+
+    #[test]
+    fn host_file_chunk_becomes_response_frame() {
+        let content = message::Content::Media(rift_core::MediaMessage {
+            content: Some(media_message::Content::FileChunk(rift_core::FileChunk {
+                file_id: 1,
+                chunk_index: 0,
+                payload: b"hi".to_vec(),
+            })),
+        });
+        let response = rift_to_response(content).unwrap();
+        let frame = ControlStreamFrame::Response(response);
+        let json = serde_json::to_string(&frame).unwrap();
+        let back: ControlStreamFrame = serde_json::from_str(&json).unwrap();
+        assert!(matches!(
+            back,
+            ControlStreamFrame::Response(WebControlResponse::FileChunk(c)) if c.payload == b"hi"
+        ));
+    }
+}
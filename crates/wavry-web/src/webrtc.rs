@@ -1,20 +1,471 @@
 use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+#[cfg(feature = "webrtc-runtime")]
+use anyhow::anyhow;
+#[cfg(feature = "webrtc-runtime")]
+use std::collections::HashMap;
+#[cfg(feature = "webrtc-runtime")]
+use std::sync::Arc;
+#[cfg(feature = "webrtc-runtime")]
+use tokio::sync::{mpsc, Mutex};
+#[cfg(feature = "webrtc-runtime")]
+use webrtc::{
+    api::{interceptor_registry::register_default_interceptors, media_engine::MediaEngine},
+    ice_transport::{ice_candidate::RTCIceCandidateInit, ice_server::RTCIceServer},
+    interceptor::registry::Registry,
+    peer_connection::{
+        configuration::RTCConfiguration, sdp::session_description::RTCSessionDescription,
+        RTCPeerConnection,
+    },
+    rtcp::{
+        payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate,
+        receiver_report::ReceiverReport,
+    },
+    track::track_local::{track_local_static_rtp::TrackLocalStaticRTP, TrackLocal},
+};
+
+#[cfg(feature = "webrtc-runtime")]
+use crate::congestion::WebCongestionController;
+#[cfg(feature = "webrtc-runtime")]
+use crate::media::{ReceiverFeedback, RtpVideoSender, WebVideoCodec};
+#[cfg(feature = "webrtc-runtime")]
+use crate::protocol::StatsReport;
+
+/// Public STUN server used when no ICE servers are configured.
+pub const DEFAULT_STUN_SERVER: &str = "stun:stun.l.google.com:19302";
+#[cfg(feature = "webrtc-runtime")]
+const DEFAULT_WEB_FPS: u32 = 60;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebRtcStartParams {
+    pub peer_id: String,
     pub session_token: String,
     pub offer_sdp: String,
 }
 
+/// Parse a remote ICE candidate, accepting either the browser's JSON
+/// `RTCIceCandidateInit` or a bare `candidate:` line.
+#[cfg(feature = "webrtc-runtime")]
+pub fn parse_ice_candidate(candidate: &str) -> anyhow::Result<RTCIceCandidateInit> {
+    let trimmed = candidate.trim();
+    if trimmed.starts_with('{') {
+        return Ok(serde_json::from_str(trimmed)?);
+    }
+    if trimmed.is_empty() {
+        return Err(anyhow!("empty ICE candidate"));
+    }
+    Ok(RTCIceCandidateInit {
+        candidate: trimmed.to_string(),
+        ..Default::default()
+    })
+}
+
+/// One browser peer connection. DTLS and SRTP keying are negotiated by the
+/// underlying stack once ICE connects.
+#[cfg(feature = "webrtc-runtime")]
+pub struct WebRtcPeer {
+    pub peer_id: String,
+    connection: Arc<RTCPeerConnection>,
+    feedback: Arc<std::sync::Mutex<PeerFeedback>>,
+}
+
+#[cfg(feature = "webrtc-runtime")]
+struct PeerFeedback {
+    latest: Option<ReceiverFeedback>,
+    congestion: WebCongestionController,
+}
+
+#[cfg(feature = "webrtc-runtime")]
+impl PeerFeedback {
+    fn on_rtcp(&mut self, packets: &[Box<dyn webrtc::rtcp::packet::Packet + Send + Sync>]) {
+        let now = std::time::SystemTime::now();
+        for packet in packets {
+            let packet = packet.as_any();
+            if let Some(rr) = packet.downcast_ref::<ReceiverReport>() {
+                for report in &rr.reports {
+                    let feedback = ReceiverFeedback::from_report(report, now);
+                    self.congestion.on_receiver_report(
+                        feedback.rtt_ms,
+                        feedback.fraction_lost,
+                        feedback.jitter_ms,
+                    );
+                    self.latest = Some(feedback);
+                }
+            } else if let Some(remb) = packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+                self.congestion.on_remb(remb.bitrate);
+            }
+        }
+    }
+}
+
 /// Skeleton for WebRTC signaling integration.
+#[cfg(not(feature = "webrtc-runtime"))]
 #[derive(Debug)]
 pub struct WebRtcPeer {
     pub peer_id: String,
 }
 
+#[cfg(feature = "webrtc-runtime")]
+impl std::fmt::Debug for WebRtcPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebRtcPeer")
+            .field("peer_id", &self.peer_id)
+            .field("state", &self.connection.connection_state())
+            .finish()
+    }
+}
+
+#[cfg(feature = "webrtc-runtime")]
+impl WebRtcPeer {
+    /// Create a peer connection sending `video_track` to the browser.
+    pub async fn new(
+        peer_id: String,
+        ice_servers: &[String],
+        video_track: Arc<TrackLocalStaticRTP>,
+        initial_bitrate_kbps: u32,
+    ) -> anyhow::Result<Self> {
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs()?;
+        let registry = register_default_interceptors(Registry::new(), &mut media_engine)?;
+        let api = webrtc::api::APIBuilder::new()
+            .with_media_engine(media_engine)
+            .with_interceptor_registry(registry)
+            .build();
+
+        let urls = if ice_servers.is_empty() {
+            vec![DEFAULT_STUN_SERVER.to_string()]
+        } else {
+            ice_servers.to_vec()
+        };
+        let config = RTCConfiguration {
+            ice_servers: vec![RTCIceServer {
+                urls,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let connection = Arc::new(api.new_peer_connection(config).await?);
+
+        let sender = connection
+            .add_track(video_track as Arc<dyn TrackLocal + Send + Sync>)
+            .await?;
+        // RTCP has to be drained for the interceptors (NACK, reports) to run;
+        // receiver reports and REMB drive this peer's bitrate target.
+        let feedback = Arc::new(std::sync::Mutex::new(PeerFeedback {
+            latest: None,
+            congestion: WebCongestionController::new(initial_bitrate_kbps, DEFAULT_WEB_FPS),
+        }));
+        let report_sink = feedback.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 1500];
+            while let Ok((packets, _)) = sender.read(&mut buf).await {
+                if let Ok(mut feedback) = report_sink.lock() {
+                    feedback.on_rtcp(&packets);
+                }
+            }
+        });
+
+        let state_peer_id = peer_id.clone();
+        connection.on_peer_connection_state_change(Box::new(move |state| {
+            tracing::debug!("WebRTC peer {} state: {}", state_peer_id, state);
+            Box::pin(async {})
+        }));
+
+        Ok(Self {
+            peer_id,
+            connection,
+            feedback,
+        })
+    }
+
+    /// Most recent RTCP receiver report from the browser, if any arrived yet.
+    pub fn receiver_feedback(&self) -> Option<ReceiverFeedback> {
+        self.feedback.lock().ok().and_then(|f| f.latest)
+    }
+
+    /// Bitrate this browser can currently sustain, per DELTA and its REMB.
+    pub fn target_bitrate_kbps(&self) -> Option<u32> {
+        self.feedback
+            .lock()
+            .ok()
+            .map(|f| f.congestion.target_bitrate_kbps())
+    }
+
+    /// Stats the browser measured itself and sent over its control channel.
+    pub fn on_stats_report(&self, report: &StatsReport) {
+        if let Ok(mut feedback) = self.feedback.lock() {
+            feedback.congestion.on_stats_report(report);
+        }
+    }
+
+    /// Host-side stats for this browser, once a receiver report has arrived.
+    pub fn stats_report(&self) -> Option<StatsReport> {
+        let feedback = self.feedback.lock().ok()?;
+        let latest = feedback.latest.as_ref()?;
+        Some(StatsReport::from_feedback(
+            latest,
+            feedback.congestion.target_bitrate_kbps(),
+        ))
+    }
+
+    /// Forward locally gathered ICE candidates as JSON `RTCIceCandidateInit`.
+    pub fn on_local_candidate(&self, callback: impl Fn(String) + Send + Sync + 'static) {
+        let callback = Arc::new(callback);
+        self.connection.on_ice_candidate(Box::new(move |candidate| {
+            let callback = callback.clone();
+            Box::pin(async move {
+                let Some(candidate) = candidate else { return };
+                match candidate
+                    .to_json()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|init| Ok(serde_json::to_string(&init)?))
+                {
+                    Ok(json) => callback(json),
+                    Err(e) => tracing::warn!("failed to serialize ICE candidate: {}", e),
+                }
+            })
+        }));
+    }
+
+    /// Apply a browser offer and return the SDP answer.
+    pub async fn accept_offer(&self, offer_sdp: String) -> anyhow::Result<String> {
+        self.connection
+            .set_remote_description(RTCSessionDescription::offer(offer_sdp)?)
+            .await?;
+        let answer = self.connection.create_answer(None).await?;
+        self.connection
+            .set_local_description(answer.clone())
+            .await?;
+        Ok(answer.sdp)
+    }
+
+    /// Create a host-initiated offer.
+    pub async fn create_offer(&self) -> anyhow::Result<String> {
+        let offer = self.connection.create_offer(None).await?;
+        self.connection.set_local_description(offer.clone()).await?;
+        Ok(offer.sdp)
+    }
+
+    /// Apply the browser's answer to an offer from [`Self::create_offer`].
+    pub async fn apply_answer(&self, answer_sdp: String) -> anyhow::Result<()> {
+        self.connection
+            .set_remote_description(RTCSessionDescription::answer(answer_sdp)?)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn add_remote_candidate(&self, candidate: &str) -> anyhow::Result<()> {
+        self.connection
+            .add_ice_candidate(parse_ice_candidate(candidate)?)
+            .await?;
+        Ok(())
+    }
+
+    pub fn connection(&self) -> &Arc<RTCPeerConnection> {
+        &self.connection
+    }
+
+    pub async fn close(&self) -> anyhow::Result<()> {
+        self.connection.close().await?;
+        Ok(())
+    }
+}
+
 /// Signaling interface between browser and host.
 pub trait WebRtcSignaling: Send + Sync + 'static {
-    fn on_offer(&self, params: WebRtcStartParams) -> anyhow::Result<String>;
-    fn on_answer(&self, peer_id: &str, sdp: String) -> anyhow::Result<()>;
-    fn on_ice_candidate(&self, peer_id: &str, candidate: String);
+    /// Handle a browser offer and return the SDP answer.
+    fn on_offer(
+        &self,
+        params: WebRtcStartParams,
+    ) -> impl Future<Output = anyhow::Result<String>> + Send;
+    fn on_answer(
+        &self,
+        peer_id: &str,
+        sdp: String,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+    fn on_ice_candidate(
+        &self,
+        peer_id: &str,
+        candidate: String,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Local ICE candidate that must be relayed to the browser identified by `peer_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalIceCandidate {
+    pub peer_id: String,
+    pub candidate: String,
+}
+
+/// Host-side [`WebRtcSignaling`] implementation owning one peer per browser.
+///
+/// All peers share a single video track, so each encoded frame is packetized once.
+#[cfg(feature = "webrtc-runtime")]
+pub struct WebRtcHost {
+    ice_servers: Vec<String>,
+    initial_bitrate_kbps: u32,
+    video: Mutex<RtpVideoSender>,
+    video_track: Arc<TrackLocalStaticRTP>,
+    peers: Mutex<HashMap<String, Arc<WebRtcPeer>>>,
+    candidate_tx: mpsc::UnboundedSender<LocalIceCandidate>,
+}
+
+#[cfg(feature = "webrtc-runtime")]
+impl WebRtcHost {
+    /// Local candidates are delivered on the returned receiver.
+    pub fn new(
+        ice_servers: Vec<String>,
+        codec: WebVideoCodec,
+        initial_bitrate_kbps: u32,
+    ) -> (Self, mpsc::UnboundedReceiver<LocalIceCandidate>) {
+        let video = RtpVideoSender::new(codec);
+        let video_track = video.track().clone();
+        let (candidate_tx, candidate_rx) = mpsc::unbounded_channel();
+        (
+            Self {
+                ice_servers,
+                initial_bitrate_kbps,
+                video: Mutex::new(video),
+                video_track,
+                peers: Mutex::new(HashMap::new()),
+                candidate_tx,
+            },
+            candidate_rx,
+        )
+    }
+
+    /// Packetize one encoded frame for every connected browser.
+    pub async fn write_frame(&self, data: &[u8], timestamp_us: u64) -> anyhow::Result<usize> {
+        self.video
+            .lock()
+            .await
+            .write_frame(data, timestamp_us)
+            .await
+    }
+
+    pub async fn peer(&self, peer_id: &str) -> Option<Arc<WebRtcPeer>> {
+        self.peers.lock().await.get(peer_id).cloned()
+    }
+
+    /// Shared-track bitrate: the lowest target among connected browsers.
+    pub async fn target_bitrate_kbps(&self) -> Option<u32> {
+        self.peers
+            .lock()
+            .await
+            .values()
+            .filter_map(|peer| peer.target_bitrate_kbps())
+            .min()
+    }
+
+    pub async fn has_peers(&self) -> bool {
+        !self.peers.lock().await.is_empty()
+    }
+
+    pub async fn remove_peer(&self, peer_id: &str) {
+        let peer = self.peers.lock().await.remove(peer_id);
+        if let Some(peer) = peer {
+            if let Err(e) = peer.close().await {
+                tracing::debug!("closing WebRTC peer {} failed: {}", peer_id, e);
+            }
+        }
+    }
+
+    async fn known_peer(&self, peer_id: &str) -> anyhow::Result<Arc<WebRtcPeer>> {
+        self.peer(peer_id)
+            .await
+            .ok_or_else(|| anyhow!("unknown WebRTC peer {}", peer_id))
+    }
+}
+
+#[cfg(feature = "webrtc-runtime")]
+impl WebRtcSignaling for WebRtcHost {
+    async fn on_offer(&self, params: WebRtcStartParams) -> anyhow::Result<String> {
+        // A renegotiating browser replaces its previous connection.
+        self.remove_peer(&params.peer_id).await;
+
+        let peer = WebRtcPeer::new(
+            params.peer_id.clone(),
+            &self.ice_servers,
+            self.video_track.clone(),
+            self.initial_bitrate_kbps,
+        )
+        .await?;
+        let candidate_tx = self.candidate_tx.clone();
+        let peer_id = params.peer_id.clone();
+        peer.on_local_candidate(move |candidate| {
+            let _ = candidate_tx.send(LocalIceCandidate {
+                peer_id: peer_id.clone(),
+                candidate,
+            });
+        });
+
+        let answer = peer.accept_offer(params.offer_sdp).await?;
+        self.peers
+            .lock()
+            .await
+            .insert(params.peer_id, Arc::new(peer));
+        Ok(answer)
+    }
+
+    async fn on_answer(&self, peer_id: &str, sdp: String) -> anyhow::Result<()> {
+        self.known_peer(peer_id).await?.apply_answer(sdp).await
+    }
+
+    async fn on_ice_candidate(&self, peer_id: &str, candidate: String) -> anyhow::Result<()> {
+        self.known_peer(peer_id)
+            .await?
+            .add_remote_candidate(&candidate)
+            .await
+    }
+}
+
+#[cfg(all(test, feature = "webrtc-runtime"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ice_candidate_accepts_json_and_raw_lines() {
+        let raw = "candidate:1 1 udp 2122260223 192.168.1.2 50000 typ host";
+        assert_eq!(parse_ice_candidate(raw).unwrap().candidate, raw);
+
+        let json = format!(r#"{{"candidate":"{raw}","sdpMid":"0","sdpMLineIndex":0}}"#);
+        let parsed = parse_ice_candidate(&json).unwrap();
+        assert_eq!(parsed.candidate, raw);
+        assert_eq!(parsed.sdp_mid.as_deref(), Some("0"));
+
+        assert!(parse_ice_candidate("  ").is_err());
+    }
+
+    #[tokio::test]
+    async fn host_answers_offer_from_another_peer() {
+        let (host, _candidates) = WebRtcHost::new(Vec::new(), WebVideoCodec::H264, 8_000);
+
+        let browser = WebRtcPeer::new(
+            "browser".to_string(),
+            &[],
+            RtpVideoSender::new(WebVideoCodec::H264).track().clone(),
+            8_000,
+        )
+        .await
+        .unwrap();
+        let offer = browser.create_offer().await.unwrap();
+
+        let answer = host
+            .on_offer(WebRtcStartParams {
+                peer_id: "browser".to_string(),
+                session_token: "token".to_string(),
+                offer_sdp: offer,
+            })
+            .await
+            .unwrap();
+        assert!(answer.contains("m=video"));
+        browser.apply_answer(answer).await.unwrap();
+
+        assert!(host.peer("browser").await.is_some());
+        assert_eq!(host.target_bitrate_kbps().await, Some(8_000));
+        assert!(host.on_answer("missing", String::new()).await.is_err());
+        host.remove_peer("browser").await;
+        assert!(!host.has_peers().await);
+    }
 }
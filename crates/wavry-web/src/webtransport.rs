@@ -1,23 +1,63 @@
+use crate::auth::{WebSessionClaims, WebTokenVerifier};
 use crate::protocol::{ControlStreamFrame, InputDatagram};
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use std::sync::Arc;
 
+#[cfg(feature = "webtransport-runtime")]
+use crate::protocol::MAX_CONTROL_FRAME_LEN;
+#[cfg(not(feature = "webtransport-runtime"))]
+use anyhow::anyhow;
+#[cfg(feature = "webtransport-runtime")]
+use bytes::BytesMut;
 #[cfg(feature = "webtransport-runtime")]
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 #[cfg(feature = "webtransport-runtime")]
 use tokio::sync::mpsc;
 
-/// Skeleton for a WebTransport server implementation.
+/// Outbound control frames queued per session before senders back off.
+#[cfg(feature = "webtransport-runtime")]
+const SESSION_CHANNEL_CAPACITY: usize = 100;
+
+/// WebTransport server accepting browser sessions.
+///
+/// With `webtransport-runtime` enabled this binds a QUIC/HTTP3 endpoint using the
+/// certificate and key from `WAVRY_WT_CERT`/`WAVRY_WT_KEY` (default `cert.pem`/`key.pem`).
+/// Once a token verifier is set, sessions whose CONNECT URL lacks a valid
+/// gateway-minted `?token=` are refused with 403 before any stream is opened.
 pub struct WebTransportServer {
     #[cfg_attr(not(feature = "webtransport-runtime"), allow(dead_code))]
     bind_addr: String,
+    #[cfg_attr(not(feature = "webtransport-runtime"), allow(dead_code))]
+    token_verifier: Option<WebTokenVerifier>,
+    #[cfg(feature = "webtransport-runtime")]
+    endpoint: wtransport::Endpoint<wtransport::endpoint::endpoint_side::Server>,
 }
 
 impl WebTransportServer {
     pub async fn bind(addr: &str) -> Result<Self> {
-        Ok(Self {
-            bind_addr: addr.to_string(),
-        })
+        #[cfg(feature = "webtransport-runtime")]
+        {
+            let endpoint = bind_endpoint(addr).await?;
+            Ok(Self {
+                bind_addr: addr.to_string(),
+                token_verifier: None,
+                endpoint,
+            })
+        }
+
+        #[cfg(not(feature = "webtransport-runtime"))]
+        {
+            Ok(Self {
+                bind_addr: addr.to_string(),
+                token_verifier: None,
+            })
+        }
+    }
+
+    /// Require a valid web session token on every incoming session.
+    pub fn with_token_verifier(mut self, verifier: WebTokenVerifier) -> Self {
+        self.token_verifier = Some(verifier);
+        self
     }
 
     pub async fn run(self, handler: impl WebTransportSessionHandler) -> Result<()> {
@@ -25,14 +65,28 @@ impl WebTransportServer {
 
         #[cfg(feature = "webtransport-runtime")]
         {
-            return run_real_runtime(&self.bind_addr, handler).await;
+            tracing::info!("WebTransport (QUIC) server listening on {}", self.bind_addr);
+            if self.token_verifier.is_none() {
+                tracing::warn!("WebTransport sessions are not token-authenticated");
+            }
+            loop {
+                let incoming_session = self.endpoint.accept().await;
+                let handler = handler.clone();
+                let verifier = self.token_verifier.clone();
+
+                tokio::spawn(async move {
+                    if let Err(e) = handle_session(incoming_session, handler, verifier).await {
+                        tracing::error!("WebTransport session error: {}", e);
+                    }
+                });
+            }
         }
 
         #[cfg(not(feature = "webtransport-runtime"))]
         {
             let _ = handler;
             Err(anyhow!(
-                "WebTransportServer::run is a skeleton; enable feature `webtransport-runtime` for runtime binding"
+                "WebTransportServer::run requires the `webtransport-runtime` feature"
             ))
         }
     }
@@ -42,6 +96,8 @@ impl WebTransportServer {
 #[derive(Debug)]
 pub struct WebTransportSession {
     pub session_id: String,
+    /// Verified token claims; `None` only when the server runs without a verifier.
+    pub claims: Option<WebSessionClaims>,
     #[cfg(feature = "webtransport-runtime")]
     pub tx: mpsc::Sender<ControlStreamFrame>,
 }
@@ -51,141 +107,217 @@ pub trait WebTransportSessionHandler: Send + Sync + 'static {
     fn on_session_started(&self, session: WebTransportSession);
     fn on_input_datagram(&self, session_id: &str, datagram: InputDatagram);
     fn on_control_frame(&self, session_id: &str, frame: ControlStreamFrame);
+
+    /// Called once after the session's transport is gone.
+    fn on_session_closed(&self, _session_id: &str) {}
 }
 
 #[cfg(feature = "webtransport-runtime")]
-async fn run_real_runtime(
+async fn bind_endpoint(
     bind_addr: &str,
-    handler: Arc<dyn WebTransportSessionHandler>,
-) -> Result<()> {
+) -> Result<wtransport::Endpoint<wtransport::endpoint::endpoint_side::Server>> {
     use std::net::SocketAddr;
-    use wtransport::Endpoint;
-    use wtransport::ServerConfig;
+    use wtransport::{Endpoint, Identity, ServerConfig};
 
     let addr: SocketAddr = bind_addr.parse()?;
 
     let cert_path = std::env::var("WAVRY_WT_CERT").unwrap_or_else(|_| "cert.pem".to_string());
     let key_path = std::env::var("WAVRY_WT_KEY").unwrap_or_else(|_| "key.pem".to_string());
-
-    let identity = wtransport::Identity::load_pemfiles(
+    let identity = Identity::load_pemfiles(
         std::path::Path::new(&cert_path),
         std::path::Path::new(&key_path),
     )
-    .await?;
+    .await
+    .map_err(|e| anyhow::anyhow!("failed to load WebTransport identity {cert_path}: {e}"))?;
 
     let config = ServerConfig::builder()
         .with_bind_address(addr)
         .with_identity(identity)
         .build();
 
-    let endpoint = Endpoint::server(config)?;
-    tracing::info!("WebTransport (QUIC) server listening on {}", bind_addr);
-
-    loop {
-        let incoming_session = endpoint.accept().await;
-        let handler = handler.clone();
-
-        tokio::spawn(async move {
-            if let Err(e) = handle_session(incoming_session, handler).await {
-                tracing::error!("WebTransport session error: {}", e);
-            }
-        });
-    }
+    Ok(Endpoint::server(config)?)
 }
 
 #[cfg(feature = "webtransport-runtime")]
 async fn handle_session(
     incoming_session: wtransport::endpoint::IncomingSession,
     handler: Arc<dyn WebTransportSessionHandler>,
+    verifier: Option<WebTokenVerifier>,
 ) -> Result<()> {
     let session_request = incoming_session.await?;
+    let claims = match verifier {
+        Some(verifier) => match verifier.verify_path(session_request.path()) {
+            Ok(claims) => Some(claims),
+            Err(e) => {
+                tracing::warn!(
+                    "rejecting WebTransport session from {}: {}",
+                    session_request.remote_address(),
+                    e
+                );
+                session_request.forbidden().await;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
     let connection = session_request.accept().await?;
     let session_id = connection.remote_address().to_string();
     tracing::info!("Accepted WebTransport session from {}", session_id);
 
     let connection = Arc::new(connection);
-    let (tx, mut rx) = mpsc::channel::<ControlStreamFrame>(100);
+    let (tx, mut rx) = mpsc::channel::<ControlStreamFrame>(SESSION_CHANNEL_CAPACITY);
 
     handler.on_session_started(WebTransportSession {
         session_id: session_id.clone(),
+        claims,
         tx,
     });
 
-    let h1 = handler.clone();
-    let sid1 = session_id.clone();
-    let c1 = connection.clone();
-    let datagram_task = tokio::spawn(async move {
-        loop {
-            match c1.receive_datagram().await {
-                Ok(data) => {
-                    let bytes = bytes::Bytes::copy_from_slice(&data);
-                    if let Some(datagram) = InputDatagram::decode(bytes) {
-                        h1.on_input_datagram(&sid1, datagram);
-                    }
+    let datagram_task = {
+        let handler = handler.clone();
+        let session_id = session_id.clone();
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            while let Ok(data) = connection.receive_datagram().await {
+                let bytes = bytes::Bytes::copy_from_slice(&data);
+                match InputDatagram::decode(bytes) {
+                    Some(datagram) => handler.on_input_datagram(&session_id, datagram),
+                    None => tracing::debug!("dropping malformed datagram from {}", session_id),
                 }
-                Err(_) => break,
             }
-        }
-    });
+        })
+    };
 
-    let h2 = handler.clone();
-    let sid2 = session_id.clone();
-    let c2 = connection.clone();
-    let stream_task = tokio::spawn(async move {
-        loop {
-            tokio::select! {
-                stream = c2.accept_bi() => {
-                    match stream {
-                        Ok((_send, mut recv)) => {
-                            let h = h2.clone();
-                            let sid = sid2.clone();
-                            tokio::spawn(async move {
-                                let mut buf = Vec::new();
-                                if recv.read_to_end(&mut buf).await.is_ok() {
-                                    if let Ok(frame) = serde_json::from_slice::<ControlStreamFrame>(&buf) {
-                                        h.on_control_frame(&sid, frame);
-                                    }
-                                }
-                            });
-                        }
-                        Err(_) => break,
+    // The latest bidirectional stream the browser opens is the control channel;
+    // outbound frames go there, or on fresh uni streams until one exists.
+    let latest_control = Arc::new(std::sync::Mutex::new(None::<wtransport::SendStream>));
+    let stream_task = {
+        let handler = handler.clone();
+        let session_id = session_id.clone();
+        let connection = connection.clone();
+        let latest_control = latest_control.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    stream = connection.accept_bi() => {
+                        let Ok((send, recv)) = stream else { break };
+                        latest_control
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .replace(send);
+                        tokio::spawn(read_framed_stream(recv, handler.clone(), session_id.clone()));
                     }
-                }
-                stream = c2.accept_uni() => {
-                    match stream {
-                        Ok(mut recv) => {
-                            let h = h2.clone();
-                            let sid = sid2.clone();
-                            tokio::spawn(async move {
-                                let mut buf = Vec::new();
-                                if recv.read_to_end(&mut buf).await.is_ok() {
-                                    if let Ok(frame) = serde_json::from_slice::<ControlStreamFrame>(&buf) {
-                                        h.on_control_frame(&sid, frame);
-                                    }
-                                }
-                            });
-                        }
-                        Err(_) => break,
+                    stream = connection.accept_uni() => {
+                        let Ok(recv) = stream else { break };
+                        let handler = handler.clone();
+                        let session_id = session_id.clone();
+                        tokio::spawn(async move {
+                            // One frame per uni stream, bounded like a framed one.
+                            let mut buf = Vec::new();
+                            let mut limited = recv.take(MAX_CONTROL_FRAME_LEN as u64 + 1);
+                            if limited.read_to_end(&mut buf).await.is_err() {
+                                return;
+                            }
+                            if buf.len() > MAX_CONTROL_FRAME_LEN {
+                                tracing::warn!(
+                                    "dropping oversized control stream from {}",
+                                    session_id
+                                );
+                                return;
+                            }
+                            match serde_json::from_slice::<ControlStreamFrame>(&buf) {
+                                Ok(frame) => handler.on_control_frame(&session_id, frame),
+                                Err(e) => tracing::debug!(
+                                    "invalid control frame from {}: {}",
+                                    session_id,
+                                    e
+                                ),
+                            }
+                        });
                     }
                 }
-                Some(frame) = rx.recv() => {
-                    if let Ok(mut stream) = c2.open_uni().await {
-                        if let Ok(json) = serde_json::to_vec(&frame) {
-                            let _ = stream.write_all(&json).await;
-                        }
-                    }
+            }
+        })
+    };
+
+    let writer_task = {
+        let connection = connection.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let mut control: Option<wtransport::SendStream> = None;
+            while let Some(frame) = rx.recv().await {
+                let latest = latest_control
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .take();
+                if latest.is_some() {
+                    control = latest;
+                }
+                let result = match control.as_mut() {
+                    Some(stream) => write_framed(stream, &frame).await,
+                    None => write_uni(&connection, &frame).await,
+                };
+                if let Err(e) = result {
+                    tracing::debug!("failed to send control frame to {}: {}", session_id, e);
+                    control = None;
                 }
             }
-        }
-    });
+        })
+    };
 
     tokio::select! {
-        _ = datagram_task => {}
-        _ = stream_task => {}
         _ = connection.closed() => {
             tracing::info!("WebTransport session {} closed", session_id);
         }
+        _ = datagram_task => {}
+        _ = stream_task => {}
+    }
+    writer_task.abort();
+    handler.on_session_closed(&session_id);
+
+    Ok(())
+}
+
+#[cfg(feature = "webtransport-runtime")]
+async fn read_framed_stream(
+    mut recv: wtransport::RecvStream,
+    handler: Arc<dyn WebTransportSessionHandler>,
+    session_id: String,
+) {
+    let mut buf = BytesMut::with_capacity(4096);
+    loop {
+        loop {
+            match ControlStreamFrame::decode_framed(&mut buf) {
+                Ok(Some(frame)) => handler.on_control_frame(&session_id, frame),
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::warn!("closing control stream from {}: {}", session_id, e);
+                    return;
+                }
+            }
+        }
+        match recv.read_buf(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
     }
+}
 
+#[cfg(feature = "webtransport-runtime")]
+async fn write_framed(
+    stream: &mut wtransport::SendStream,
+    frame: &ControlStreamFrame,
+) -> Result<()> {
+    let bytes = frame.encode_framed()?;
+    stream.write_all(&bytes).await?;
+    Ok(())
+}
+
+#[cfg(feature = "webtransport-runtime")]
+async fn write_uni(connection: &wtransport::Connection, frame: &ControlStreamFrame) -> Result<()> {
+    let json = serde_json::to_vec(frame)?;
+    let mut stream = connection.open_uni().await?.await?;
+    stream.write_all(&json).await?;
+    stream.finish().await?;
     Ok(())
 }